@@ -0,0 +1,153 @@
+use ring::rand::{SecureRandom, SystemRandom};
+use std::{fmt, str::FromStr};
+
+/// Distribution from which the amounts and the data sizes of the packets are drawn
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Distribution {
+    /// Always the same value: `fixed:<value>`
+    Fixed(u64),
+    /// Any value between the bounds (inclusive) with the same probability: `uniform:<min>-<max>`
+    Uniform(u64, u64),
+    /// Exponentially distributed values with the given mean, so that most are small and
+    /// a few are large: `exponential:<mean>`
+    Exponential(f64),
+}
+
+impl Distribution {
+    /// Draws a value, capped at `max`
+    pub fn sample(&self, rng: &SystemRandom, max: u64) -> u64 {
+        let value = match *self {
+            Distribution::Fixed(value) => value,
+            Distribution::Uniform(min, max) => {
+                let range = max - min;
+                if range == u64::MAX {
+                    random_u64(rng)
+                } else {
+                    min + random_u64(rng) % (range + 1)
+                }
+            }
+            Distribution::Exponential(mean) => {
+                // Inverse transform sampling, with 1 - u in (0, 1] so that the log is finite
+                let u = random_fraction(rng);
+                (-mean * (1.0 - u).ln()).round() as u64
+            }
+        };
+        value.min(max)
+    }
+}
+
+impl FromStr for Distribution {
+    type Err = String;
+
+    fn from_str(src: &str) -> Result<Self, Self::Err> {
+        let (kind, params) = match src.find(':') {
+            Some(index) => (&src[..index], &src[index + 1..]),
+            // A plain number is a fixed value
+            None => ("fixed", src),
+        };
+        let number = |value: &str| {
+            value
+                .trim()
+                .parse::<u64>()
+                .map_err(|_| format!("`{}` is not a valid number in `{}`", value, src))
+        };
+        match kind {
+            "fixed" => Ok(Distribution::Fixed(number(params)?)),
+            "uniform" => {
+                let index = params
+                    .find('-')
+                    .ok_or_else(|| format!("expected `uniform:<min>-<max>`, got `{}`", src))?;
+                let (min, max) = (number(&params[..index])?, number(&params[index + 1..])?);
+                if min > max {
+                    return Err(format!("the minimum of `{}` exceeds its maximum", src));
+                }
+                Ok(Distribution::Uniform(min, max))
+            }
+            "exponential" => {
+                let mean = params
+                    .trim()
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|mean| mean.is_finite() && *mean >= 0.0)
+                    .ok_or_else(|| format!("`{}` is not a valid mean in `{}`", params, src))?;
+                Ok(Distribution::Exponential(mean))
+            }
+            _ => Err(format!(
+                "unknown distribution `{}` (expected fixed, uniform or exponential)",
+                kind
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Distribution {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Distribution::Fixed(value) => write!(f, "fixed:{}", value),
+            Distribution::Uniform(min, max) => write!(f, "uniform:{}-{}", min, max),
+            Distribution::Exponential(mean) => write!(f, "exponential:{}", mean),
+        }
+    }
+}
+
+pub fn random_u64(rng: &SystemRandom) -> u64 {
+    let mut bytes = [0; 8];
+    rng.fill(&mut bytes)
+        .expect("Failed to read from the system's randomness");
+    u64::from_be_bytes(bytes)
+}
+
+/// Returns a random number in [0, 1)
+fn random_fraction(rng: &SystemRandom) -> f64 {
+    // The 53 bits of precision of a double
+    (random_u64(rng) >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_distributions() {
+        assert_eq!(
+            Distribution::from_str("fixed:100"),
+            Ok(Distribution::Fixed(100))
+        );
+        assert_eq!(Distribution::from_str("42"), Ok(Distribution::Fixed(42)));
+        assert_eq!(
+            Distribution::from_str("uniform:10-1000"),
+            Ok(Distribution::Uniform(10, 1000))
+        );
+        assert_eq!(
+            Distribution::from_str("exponential:250.5"),
+            Ok(Distribution::Exponential(250.5))
+        );
+        assert!(Distribution::from_str("uniform:1000-10").is_err());
+        assert!(Distribution::from_str("uniform:10").is_err());
+        assert!(Distribution::from_str("exponential:-1").is_err());
+        assert!(Distribution::from_str("normal:5").is_err());
+        assert!(Distribution::from_str("fixed:abc").is_err());
+    }
+
+    #[test]
+    fn samples_within_bounds() {
+        let rng = SystemRandom::new();
+        for _ in 0..1000 {
+            let value = Distribution::Uniform(10, 20).sample(&rng, u64::MAX);
+            assert!((10..=20).contains(&value));
+        }
+        assert_eq!(Distribution::Fixed(500).sample(&rng, 100), 100);
+        assert!(Distribution::Uniform(0, u64::MAX).sample(&rng, 5) <= 5);
+    }
+
+    #[test]
+    fn exponential_has_its_mean() {
+        let rng = SystemRandom::new();
+        let samples = 20_000;
+        let sum: u64 = (0..samples)
+            .map(|_| Distribution::Exponential(100.0).sample(&rng, u64::MAX))
+            .sum();
+        let mean = sum as f64 / samples as f64;
+        assert!(mean > 90.0 && mean < 110.0, "mean was {}", mean);
+    }
+}
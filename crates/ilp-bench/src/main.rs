@@ -0,0 +1,236 @@
+mod distribution;
+mod receiver;
+mod report;
+mod workload;
+
+use clap::{crate_version, App, AppSettings, Arg, ArgMatches};
+use std::{process::exit, str::FromStr, time::Duration};
+use workload::{Config, Error};
+
+fn app<'a, 'b>() -> App<'a, 'b> {
+    App::new("ilp-bench")
+        .about("Sends payment workloads through Interledger.rs nodes and reports their throughput, latencies and rejects")
+        .version(crate_version!())
+        .setting(AppSettings::AllowNegativeNumbers)
+        .after_help("DISTRIBUTIONS:\n    fixed:<value>          always the same value (or just <value>)\n    uniform:<min>-<max>    any value between min and max\n    exponential:<mean>     mostly small values, a few large ones")
+        .args(&[
+            Arg::with_name("node")
+                .long("node")
+                .default_value("http://localhost:7770")
+                .help("API of the node the packets are sent to"),
+            Arg::with_name("receiver_node")
+                .long("receiver-node")
+                .takes_value(true)
+                .help("API of the node which delivers the packets to the receiver, to benchmark a pair of peered nodes [default: the --node]"),
+            Arg::with_name("admin_token")
+                .long("admin-token")
+                .env("ILP_BENCH_ADMIN_TOKEN")
+                .takes_value(true)
+                .help("Admin token of the nodes, with which the sender and receiver accounts are created if they do not exist"),
+            Arg::with_name("sender")
+                .long("sender")
+                .default_value("bench_sender:bench_sender_token")
+                .help("Username and ILP-over-HTTP token of the account which sends the packets, as <username>:<token>"),
+            Arg::with_name("receiver")
+                .long("receiver")
+                .default_value("bench_receiver:bench_receiver_token")
+                .help("Username and ILP-over-HTTP token of the account which receives the packets, as <username>:<token>"),
+            Arg::with_name("asset_code")
+                .long("asset-code")
+                .default_value("XYZ")
+                .help("Asset code of the accounts which are created"),
+            Arg::with_name("asset_scale")
+                .long("asset-scale")
+                .default_value("9")
+                .help("Asset scale of the accounts which are created"),
+            Arg::with_name("listen")
+                .long("listen")
+                .default_value("127.0.0.1:3030")
+                .help("Address on which the receiver accepts the packets"),
+            Arg::with_name("receiver_url")
+                .long("receiver-url")
+                .takes_value(true)
+                .help("URL at which the node reaches the receiver [default: http://<listen>/ilp]"),
+            Arg::with_name("destination")
+                .long("destination")
+                .takes_value(true)
+                .help("ILP address the packets are sent to instead of the receiver, which they are only fulfilled by if something there knows their fulfillments"),
+            Arg::with_name("concurrency")
+                .long("concurrency")
+                .short("c")
+                .default_value("10")
+                .help("Number of packets in flight at the same time"),
+            Arg::with_name("duration")
+                .long("duration")
+                .short("d")
+                .default_value("10")
+                .help("How long to send packets for, in seconds"),
+            Arg::with_name("rate")
+                .long("rate")
+                .takes_value(true)
+                .help("Packets sent per second [default: as many as the concurrency allows]"),
+            Arg::with_name("rate_start")
+                .long("rate-start")
+                .takes_value(true)
+                .requires("rate")
+                .help("Packets sent per second at first, which rise or fall to the --rate over the --ramp [default: the --rate]"),
+            Arg::with_name("ramp")
+                .long("ramp")
+                .default_value("0")
+                .help("Seconds over which the rate goes from --rate-start to --rate"),
+            Arg::with_name("amount")
+                .long("amount")
+                .default_value("fixed:1000")
+                .help("Distribution of the amounts of the packets"),
+            Arg::with_name("data_size")
+                .long("data-size")
+                .default_value("fixed:32")
+                .help("Distribution of the sizes of the data of the packets, in bytes (at most 32767)"),
+            Arg::with_name("expiry")
+                .long("expiry")
+                .default_value("30000")
+                .help("Time until the packets expire, in milliseconds"),
+            Arg::with_name("json")
+                .long("json")
+                .help("Prints the report as JSON"),
+        ])
+}
+
+fn parse<T: FromStr>(matches: &ArgMatches, name: &str) -> Result<Option<T>, Error>
+where
+    T::Err: ToString,
+{
+    matches
+        .value_of(name)
+        .map(|value| {
+            value.parse().map_err(|err: T::Err| {
+                Error::Config(format!(
+                    "invalid --{}: {}",
+                    name.replace('_', "-"),
+                    err.to_string()
+                ))
+            })
+        })
+        .transpose()
+}
+
+fn seconds(matches: &ArgMatches, name: &str) -> Result<Duration, Error> {
+    let seconds: f64 = parse(matches, name)?.unwrap_or_default();
+    if seconds.is_finite() && seconds >= 0.0 {
+        Ok(Duration::from_secs_f64(seconds))
+    } else {
+        Err(Error::Config(format!("invalid --{}: {}", name, seconds)))
+    }
+}
+
+fn config(matches: &ArgMatches) -> Result<Config, Error> {
+    // The arguments with a default value are always present
+    let node = matches.value_of("node").unwrap().to_string();
+    let listen = parse(matches, "listen")?.unwrap();
+    Ok(Config {
+        receiver_node: matches
+            .value_of("receiver_node")
+            .map(String::from)
+            .unwrap_or_else(|| node.clone()),
+        node,
+        admin_token: matches.value_of("admin_token").map(String::from),
+        sender: parse(matches, "sender")?.unwrap(),
+        receiver: parse(matches, "receiver")?.unwrap(),
+        asset_code: matches.value_of("asset_code").unwrap().to_string(),
+        asset_scale: parse(matches, "asset_scale")?.unwrap(),
+        receiver_url: matches
+            .value_of("receiver_url")
+            .map(String::from)
+            .unwrap_or_else(|| format!("http://{}/ilp", listen)),
+        listen,
+        destination: parse(matches, "destination")?,
+        concurrency: parse(matches, "concurrency")?.unwrap(),
+        duration: seconds(matches, "duration")?,
+        rate: parse(matches, "rate")?,
+        rate_start: parse(matches, "rate_start")?,
+        ramp: seconds(matches, "ramp")?,
+        amount: parse(matches, "amount")?.unwrap(),
+        data_size: parse(matches, "data_size")?.unwrap(),
+        expiry: Duration::from_millis(parse(matches, "expiry")?.unwrap()),
+    })
+}
+
+#[tokio::main]
+async fn main() {
+    let matches = app().get_matches();
+    let result = match config(&matches) {
+        Ok(config) => workload::run(config).await,
+        Err(err) => Err(err),
+    };
+    match result {
+        Ok(report) => {
+            if matches.is_present("json") {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&report).expect("The report is serializable")
+                );
+            } else {
+                print!("{}", report);
+            }
+        }
+        Err(err) => {
+            eprintln!("ilp-bench error: {}", err);
+            exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use distribution::Distribution;
+
+    fn parse_config(args: &str) -> Result<Config, Error> {
+        let matches = app().get_matches_from_safe(args.split(' ')).unwrap();
+        config(&matches)
+    }
+
+    #[test]
+    fn parses_defaults() {
+        let config = parse_config("ilp-bench").unwrap();
+        assert_eq!(config.node, "http://localhost:7770");
+        assert_eq!(config.receiver_node, config.node);
+        assert_eq!(config.sender.username, "bench_sender");
+        assert_eq!(config.receiver_url, "http://127.0.0.1:3030/ilp");
+        assert_eq!(config.concurrency, 10);
+        assert_eq!(config.duration, Duration::from_secs(10));
+        assert_eq!(config.rate, None);
+        assert_eq!(config.amount, Distribution::Fixed(1000));
+        assert_eq!(config.expiry, Duration::from_secs(30));
+    }
+
+    #[test]
+    fn parses_workloads() {
+        let config = parse_config("ilp-bench --node http://a:7770 --receiver-node http://b:7770 --admin-token admin -c 50 -d 2.5 --rate 1000 --rate-start 10 --ramp 1 --amount uniform:1-100 --data-size exponential:512 --destination example.sink").unwrap();
+        assert_eq!(config.receiver_node, "http://b:7770");
+        assert_eq!(config.admin_token.as_deref(), Some("admin"));
+        assert_eq!(config.concurrency, 50);
+        assert_eq!(config.duration, Duration::from_millis(2500));
+        assert_eq!(config.rate, Some(1000.0));
+        assert_eq!(config.rate_start, Some(10.0));
+        assert_eq!(config.ramp, Duration::from_secs(1));
+        assert_eq!(config.amount, Distribution::Uniform(1, 100));
+        assert_eq!(config.data_size, Distribution::Exponential(512.0));
+        assert_eq!(
+            config.destination.as_ref().unwrap().to_string(),
+            "example.sink"
+        );
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn rejects_invalid_arguments() {
+        assert!(parse_config("ilp-bench --amount normal:5").is_err());
+        assert!(parse_config("ilp-bench --sender alice").is_err());
+        assert!(parse_config("ilp-bench -d -1").is_err());
+        let config = parse_config("ilp-bench -c 0").unwrap();
+        assert!(config.validate().is_err());
+        let config = parse_config("ilp-bench --ramp 5").unwrap();
+        assert!(config.validate().is_err());
+    }
+}
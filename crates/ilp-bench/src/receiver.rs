@@ -0,0 +1,112 @@
+use bytes::{Bytes, BytesMut};
+use interledger_packet::{FulfillBuilder, Prepare};
+use ring::{digest, hmac};
+use std::{convert::TryFrom, sync::Arc};
+use warp::{http::Response, http::StatusCode, Filter, Rejection, Reply};
+
+/// Derives the fulfillments of the packets from their data, so that the receiver can
+/// fulfill the packets of the workload without keeping track of them
+#[derive(Clone)]
+pub struct Fulfiller(Arc<hmac::Key>);
+
+impl Fulfiller {
+    pub fn new(secret: &[u8]) -> Self {
+        Fulfiller(Arc::new(hmac::Key::new(hmac::HMAC_SHA256, secret)))
+    }
+
+    pub fn fulfillment(&self, data: &[u8]) -> [u8; 32] {
+        let mut fulfillment = [0; 32];
+        fulfillment.copy_from_slice(hmac::sign(&self.0, data).as_ref());
+        fulfillment
+    }
+
+    pub fn condition(&self, data: &[u8]) -> [u8; 32] {
+        let mut condition = [0; 32];
+        condition
+            .copy_from_slice(digest::digest(&digest::SHA256, &self.fulfillment(data)).as_ref());
+        condition
+    }
+}
+
+/// ILP-over-HTTP endpoint of the receiving account, which fulfills every Prepare packet
+/// sent by the node with the expected token
+pub fn receiver_filter(
+    fulfiller: Fulfiller,
+    token: String,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let authorization = format!("Bearer {}", token);
+    warp::post()
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::body::bytes())
+        .map(move |header: Option<String>, body: Bytes| {
+            if header.as_ref() != Some(&authorization) {
+                return Response::builder()
+                    .status(StatusCode::UNAUTHORIZED)
+                    .body(Vec::new());
+            }
+            let prepare = match Prepare::try_from(BytesMut::from(body.as_ref())) {
+                Ok(prepare) => prepare,
+                Err(_) => {
+                    return Response::builder()
+                        .status(StatusCode::BAD_REQUEST)
+                        .body(Vec::new())
+                }
+            };
+            let fulfill = FulfillBuilder {
+                fulfillment: &fulfiller.fulfillment(prepare.data()),
+                data: &[],
+            }
+            .build();
+            Response::builder()
+                .header("content-type", "application/octet-stream")
+                .body(fulfill.as_ref().to_vec())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use interledger_packet::{Address, Packet, PrepareBuilder};
+    use std::{
+        str::FromStr,
+        time::{Duration, SystemTime},
+    };
+
+    #[tokio::test]
+    async fn fulfills_prepare_packets() {
+        let fulfiller = Fulfiller::new(b"secret");
+        let filter = receiver_filter(fulfiller.clone(), "token".to_string());
+        let prepare = PrepareBuilder {
+            amount: 10,
+            expires_at: SystemTime::now() + Duration::from_secs(30),
+            execution_condition: &fulfiller.condition(b"data"),
+            destination: Address::from_str("example.receiver").unwrap(),
+            data: b"data",
+        }
+        .build();
+
+        let response = warp::test::request()
+            .method("POST")
+            .header("authorization", "Bearer token")
+            .body(prepare.as_ref())
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let fulfill = match Packet::try_from(BytesMut::from(response.body().as_ref())) {
+            Ok(Packet::Fulfill(fulfill)) => fulfill,
+            other => panic!("Expected a fulfill, got {:?}", other),
+        };
+        assert_eq!(
+            digest::digest(&digest::SHA256, fulfill.fulfillment()).as_ref(),
+            prepare.execution_condition()
+        );
+
+        let response = warp::test::request()
+            .method("POST")
+            .header("authorization", "Bearer wrong")
+            .body(prepare.as_ref())
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+}
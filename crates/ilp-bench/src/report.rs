@@ -0,0 +1,222 @@
+use hdrhistogram::Histogram;
+use serde::Serialize;
+use std::{collections::BTreeMap, fmt, time::Duration};
+
+/// Longest latency recorded precisely, in microseconds. Longer ones are recorded as it.
+const MAX_LATENCY: u64 = 3_600_000_000;
+
+/// What became of a packet sent by the workload
+#[derive(Clone, Debug, PartialEq)]
+pub enum Outcome {
+    Fulfilled,
+    /// Rejected with the given error code
+    Rejected(String),
+    /// No Fulfill or Reject came back, for the given reason (such as an HTTP error status)
+    Failed(String),
+}
+
+/// Counts of the packets sent by a worker, which are merged into the report at the end
+pub struct Stats {
+    sent: u64,
+    fulfilled: u64,
+    amount_fulfilled: u64,
+    bytes_sent: u64,
+    rejects: BTreeMap<String, u64>,
+    errors: BTreeMap<String, u64>,
+    /// Latencies of the packets which got a response, in microseconds
+    latency: Histogram<u64>,
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Stats {
+            sent: 0,
+            fulfilled: 0,
+            amount_fulfilled: 0,
+            bytes_sent: 0,
+            rejects: BTreeMap::new(),
+            errors: BTreeMap::new(),
+            latency: Histogram::new_with_bounds(1, MAX_LATENCY, 3)
+                .expect("The bounds of the histogram are valid"),
+        }
+    }
+
+    pub fn record(&mut self, amount: u64, size: usize, outcome: Outcome, latency: Duration) {
+        self.sent += 1;
+        self.bytes_sent += size as u64;
+        match outcome {
+            Outcome::Fulfilled => {
+                self.fulfilled += 1;
+                self.amount_fulfilled = self.amount_fulfilled.saturating_add(amount);
+            }
+            Outcome::Rejected(code) => *self.rejects.entry(code).or_insert(0) += 1,
+            Outcome::Failed(reason) => {
+                *self.errors.entry(reason).or_insert(0) += 1;
+                // The packets which failed have no meaningful latency
+                return;
+            }
+        }
+        let micros = (latency.as_micros() as u64).clamp(1, MAX_LATENCY);
+        self.latency.saturating_record(micros);
+    }
+
+    pub fn merge(&mut self, other: Stats) {
+        self.sent += other.sent;
+        self.fulfilled += other.fulfilled;
+        self.amount_fulfilled = self.amount_fulfilled.saturating_add(other.amount_fulfilled);
+        self.bytes_sent += other.bytes_sent;
+        for (code, count) in other.rejects {
+            *self.rejects.entry(code).or_insert(0) += count;
+        }
+        for (reason, count) in other.errors {
+            *self.errors.entry(reason).or_insert(0) += count;
+        }
+        // Both histograms have the same bounds, so adding them cannot fail
+        let _ = self.latency.add(&other.latency);
+    }
+
+    /// Summarizes the packets sent over the given time
+    pub fn report(&self, elapsed: Duration) -> Report {
+        let seconds = elapsed.as_secs_f64().max(f64::EPSILON);
+        let quantile = |quantile: f64| self.latency.value_at_quantile(quantile) as f64 / 1000.0;
+        Report {
+            duration: elapsed.as_secs_f64(),
+            sent: self.sent,
+            fulfilled: self.fulfilled,
+            rejected: self.rejects.values().sum(),
+            failed: self.errors.values().sum(),
+            packets_per_second: self.sent as f64 / seconds,
+            fulfilled_per_second: self.fulfilled as f64 / seconds,
+            amount_fulfilled: self.amount_fulfilled,
+            bytes_sent: self.bytes_sent,
+            latency: Latency {
+                min: self.latency.min() as f64 / 1000.0,
+                mean: self.latency.mean() / 1000.0,
+                p50: quantile(0.5),
+                p90: quantile(0.9),
+                p99: quantile(0.99),
+                p999: quantile(0.999),
+                max: self.latency.max() as f64 / 1000.0,
+            },
+            rejects: self.rejects.clone(),
+            errors: self.errors.clone(),
+        }
+    }
+}
+
+/// Latencies of the packets until their Fulfill or Reject came back, in milliseconds
+#[derive(Clone, Debug, Serialize)]
+pub struct Latency {
+    pub min: f64,
+    pub mean: f64,
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+    pub p999: f64,
+    pub max: f64,
+}
+
+/// The results of a workload
+#[derive(Clone, Debug, Serialize)]
+pub struct Report {
+    /// How long the packets were sent for, in seconds
+    pub duration: f64,
+    pub sent: u64,
+    pub fulfilled: u64,
+    pub rejected: u64,
+    /// Number of packets to which no Fulfill or Reject came back
+    pub failed: u64,
+    pub packets_per_second: f64,
+    pub fulfilled_per_second: f64,
+    /// Sum of the amounts of the fulfilled packets
+    pub amount_fulfilled: u64,
+    /// Sum of the sizes of the data of the packets
+    pub bytes_sent: u64,
+    pub latency: Latency,
+    /// Number of rejected packets by error code
+    pub rejects: BTreeMap<String, u64>,
+    /// Number of failed packets by reason
+    pub errors: BTreeMap<String, u64>,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Duration:    {:.2}s", self.duration)?;
+        writeln!(
+            f,
+            "Packets:     {} sent, {} fulfilled, {} rejected, {} failed",
+            self.sent, self.fulfilled, self.rejected, self.failed
+        )?;
+        writeln!(
+            f,
+            "Throughput:  {:.1} packets/s sent, {:.1} packets/s fulfilled",
+            self.packets_per_second, self.fulfilled_per_second
+        )?;
+        writeln!(
+            f,
+            "Amount:      {} fulfilled, {} bytes of data sent",
+            self.amount_fulfilled, self.bytes_sent
+        )?;
+        let latency = &self.latency;
+        writeln!(
+            f,
+            "Latency (ms): min {:.2}, mean {:.2}, p50 {:.2}, p90 {:.2}, p99 {:.2}, p99.9 {:.2}, max {:.2}",
+            latency.min, latency.mean, latency.p50, latency.p90, latency.p99, latency.p999, latency.max
+        )?;
+        if !self.rejects.is_empty() {
+            writeln!(f, "Rejects:")?;
+            for (code, count) in &self.rejects {
+                writeln!(f, "  {}: {}", code, count)?;
+            }
+        }
+        if !self.errors.is_empty() {
+            writeln!(f, "Errors:")?;
+            for (reason, count) in &self.errors {
+                writeln!(f, "  {}: {}", reason, count)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merges_stats_into_a_report() {
+        let mut stats = Stats::new();
+        for millis in 1..=100 {
+            stats.record(10, 32, Outcome::Fulfilled, Duration::from_millis(millis));
+        }
+        let mut other = Stats::new();
+        other.record(
+            10,
+            32,
+            Outcome::Rejected("T04".to_string()),
+            Duration::from_millis(50),
+        );
+        other.record(
+            10,
+            32,
+            Outcome::Failed("HTTP 401 Unauthorized".to_string()),
+            Duration::from_secs(30),
+        );
+        stats.merge(other);
+
+        let report = stats.report(Duration::from_secs(2));
+        assert_eq!(report.sent, 102);
+        assert_eq!(report.fulfilled, 100);
+        assert_eq!(report.rejected, 1);
+        assert_eq!(report.failed, 1);
+        assert_eq!(report.amount_fulfilled, 1000);
+        assert_eq!(report.bytes_sent, 102 * 32);
+        assert_eq!(report.packets_per_second, 51.0);
+        assert_eq!(report.rejects["T04"], 1);
+        assert_eq!(report.errors["HTTP 401 Unauthorized"], 1);
+        // The failed packet's latency is not recorded
+        assert!(report.latency.max < 101.0);
+        assert!((report.latency.p50 - 50.0).abs() < 1.0);
+        assert!((report.latency.p99 - 99.0).abs() < 1.0);
+    }
+}
@@ -0,0 +1,509 @@
+use crate::distribution::Distribution;
+use crate::receiver::{receiver_filter, Fulfiller};
+use crate::report::{Outcome, Report, Stats};
+use bytes::BytesMut;
+use futures::future::join_all;
+use interledger_packet::{Address, Packet, PrepareBuilder};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde_json::{json, Value};
+use std::{
+    convert::TryFrom,
+    net::SocketAddr,
+    str::FromStr,
+    sync::atomic::{AtomicU64, Ordering},
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+use thiserror::Error;
+use tokio::{sync::oneshot, time::delay_for};
+
+/// Largest data an ILP packet can carry
+const MAX_DATA_SIZE: u64 = 32_767;
+/// How long to wait for the node to route the packets to the receiver before starting
+const ROUTE_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("{0}")]
+    Config(String),
+    #[error("could not listen on {0}")]
+    Listen(SocketAddr),
+    #[error("request to {url} failed: {source}")]
+    Http {
+        url: String,
+        #[source]
+        source: reqwest::Error,
+    },
+    #[error("unexpected response from {url}: {status}: {body}")]
+    Api {
+        url: String,
+        status: reqwest::StatusCode,
+        body: String,
+    },
+    #[error("the packets sent to {destination} are not fulfilled: {outcome:?}")]
+    Unreachable {
+        destination: Address,
+        outcome: Outcome,
+    },
+}
+
+/// Username and ILP-over-HTTP token of an account, given as `<username>:<token>`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Credentials {
+    pub username: String,
+    pub token: String,
+}
+
+impl FromStr for Credentials {
+    type Err = String;
+
+    fn from_str(src: &str) -> Result<Self, Self::Err> {
+        match src.find(':') {
+            Some(index) if index > 0 && index < src.len() - 1 => Ok(Credentials {
+                username: src[..index].to_string(),
+                token: src[index + 1..].to_string(),
+            }),
+            _ => Err(format!("expected `<username>:<token>`, got `{}`", src)),
+        }
+    }
+}
+
+/// The workload and the accounts it is run with
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// API of the node the packets are sent to
+    pub node: String,
+    /// API of the node which forwards the packets to the receiver. It is the same
+    /// as `node`, unless the packets go through a pair of nodes.
+    pub receiver_node: String,
+    /// Admin token of the nodes, with which the accounts are created if it is set
+    pub admin_token: Option<String>,
+    pub sender: Credentials,
+    pub receiver: Credentials,
+    pub asset_code: String,
+    pub asset_scale: u8,
+    /// Address on which the receiver accepts the packets forwarded by the node
+    pub listen: SocketAddr,
+    /// URL at which the node reaches the receiver
+    pub receiver_url: String,
+    /// Address the packets are sent to instead of the receiver. The packets are only
+    /// fulfilled if something at this address fulfills them.
+    pub destination: Option<Address>,
+    /// Number of packets in flight at the same time
+    pub concurrency: usize,
+    pub duration: Duration,
+    /// Rate, in packets per second, at which the packets are sent. They are sent as fast
+    /// as the concurrency allows if it is not set.
+    pub rate: Option<f64>,
+    /// Rate at which the packets are sent at first, which rises (or falls) to `rate`
+    /// over the `ramp`
+    pub rate_start: Option<f64>,
+    pub ramp: Duration,
+    pub amount: Distribution,
+    pub data_size: Distribution,
+    /// Time until the packets expire
+    pub expiry: Duration,
+}
+
+impl Config {
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.concurrency == 0 {
+            return Err(Error::Config(
+                "the concurrency must be at least 1".to_string(),
+            ));
+        }
+        for rate in self.rate.iter().chain(self.rate_start.iter()) {
+            if !rate.is_finite() || *rate < 0.0 {
+                return Err(Error::Config(format!("`{}` is not a valid rate", rate)));
+            }
+        }
+        if self.rate == Some(0.0) {
+            return Err(Error::Config("the rate must be above 0".to_string()));
+        }
+        if self.rate.is_none() && (self.rate_start.is_some() || self.ramp > Duration::from_secs(0))
+        {
+            return Err(Error::Config(
+                "the rate must be set to ramp it up or down".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Times at which the packets are sent to follow the rate, which changes linearly
+/// from the start rate to the final one over the ramp
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Schedule {
+    pub start_rate: f64,
+    pub rate: f64,
+    pub ramp: Duration,
+}
+
+impl Schedule {
+    /// Returns when the `n`th packet (counting from 0) is due, since the start
+    pub fn send_time(&self, n: u64) -> Duration {
+        let n = n as f64;
+        let ramp = self.ramp.as_secs_f64();
+        // Number of packets sent by the end of the ramp, at its average rate
+        let ramp_packets = (self.start_rate + self.rate) / 2.0 * ramp;
+        let seconds = if n >= ramp_packets {
+            ramp + (n - ramp_packets) / self.rate
+        } else {
+            // Solves n = start_rate * t + acceleration * t^2 / 2
+            let acceleration = (self.rate - self.start_rate) / ramp;
+            if acceleration.abs() < f64::EPSILON {
+                n / self.start_rate
+            } else {
+                let discriminant =
+                    (self.start_rate * self.start_rate + 2.0 * acceleration * n).max(0.0);
+                (discriminant.sqrt() - self.start_rate) / acceleration
+            }
+        };
+        Duration::from_secs_f64(seconds.max(0.0))
+    }
+}
+
+fn api_url(node: &str, path: &str) -> String {
+    format!("{}/{}", node.trim_end_matches('/'), path)
+}
+
+async fn check_response(url: String, response: reqwest::Response) -> Result<Value, Error> {
+    let status = response.status();
+    let body = response.text().await.map_err(|source| Error::Http {
+        url: url.clone(),
+        source,
+    })?;
+    if status.is_success() {
+        Ok(serde_json::from_str(&body).unwrap_or(Value::Null))
+    } else {
+        Err(Error::Api { url, status, body })
+    }
+}
+
+/// Creates an account via the admin API, unless it already exists
+async fn create_account(
+    client: &reqwest::Client,
+    node: &str,
+    admin_token: &str,
+    account: Value,
+) -> Result<(), Error> {
+    let url = api_url(node, "accounts");
+    let response = client
+        .post(&url)
+        .bearer_auth(admin_token)
+        .json(&account)
+        .send()
+        .await
+        .map_err(|source| Error::Http {
+            url: url.clone(),
+            source,
+        })?;
+    if response.status() == reqwest::StatusCode::CONFLICT {
+        eprintln!(
+            "Using the existing account {} of {}",
+            account["username"], node
+        );
+        return Ok(());
+    }
+    check_response(url, response).await.map(|_| ())
+}
+
+async fn fetch_address(
+    client: &reqwest::Client,
+    node: &str,
+    account: &Credentials,
+) -> Result<Address, Error> {
+    let url = api_url(node, &format!("accounts/{}", account.username));
+    let response = client
+        .get(&url)
+        .bearer_auth(&account.token)
+        .send()
+        .await
+        .map_err(|source| Error::Http {
+            url: url.clone(),
+            source,
+        })?;
+    let details = check_response(url.clone(), response).await?;
+    details["ilp_address"]
+        .as_str()
+        .and_then(|address| Address::from_str(address).ok())
+        .ok_or_else(|| Error::Api {
+            url,
+            status: reqwest::StatusCode::OK,
+            body: "the account has no ILP address".to_string(),
+        })
+}
+
+/// Sends the packets of the workload to the node, as the sending account
+#[derive(Clone)]
+struct Sender {
+    client: reqwest::Client,
+    /// ILP-over-HTTP endpoint of the sending account
+    url: String,
+    token: String,
+    destination: Address,
+    expiry: Duration,
+    fulfiller: Fulfiller,
+    rng: SystemRandom,
+}
+
+impl Sender {
+    async fn send(&self, amount: u64, data_size: usize) -> Outcome {
+        let mut data = vec![0; data_size];
+        self.rng
+            .fill(&mut data)
+            .expect("Failed to read from the system's randomness");
+        let prepare = PrepareBuilder {
+            amount,
+            expires_at: SystemTime::now() + self.expiry,
+            execution_condition: &self.fulfiller.condition(&data),
+            destination: self.destination.clone(),
+            data: &data,
+        }
+        .build();
+        let response = self
+            .client
+            .post(&self.url)
+            .bearer_auth(&self.token)
+            .header("content-type", "application/octet-stream")
+            .body(BytesMut::from(prepare).freeze())
+            .send()
+            .await;
+        let response = match response {
+            Ok(response) => response,
+            Err(err) if err.is_timeout() => return Outcome::Failed("timeout".to_string()),
+            Err(_) => return Outcome::Failed("connection error".to_string()),
+        };
+        let status = response.status();
+        if !status.is_success() {
+            return Outcome::Failed(format!("HTTP {}", status));
+        }
+        match response.bytes().await {
+            Ok(body) => match Packet::try_from(BytesMut::from(body.as_ref())) {
+                Ok(Packet::Fulfill(_)) => Outcome::Fulfilled,
+                Ok(Packet::Reject(reject)) => Outcome::Rejected(reject.code().to_string()),
+                _ => Outcome::Failed("invalid response".to_string()),
+            },
+            Err(_) => Outcome::Failed("connection error".to_string()),
+        }
+    }
+
+    /// Waits until the packets are fulfilled, which they are not until the nodes
+    /// exchanged their routes
+    async fn wait_for_route(&self) -> Result<(), Error> {
+        let started_at = Instant::now();
+        loop {
+            let outcome = self.send(1, 0).await;
+            if outcome == Outcome::Fulfilled {
+                return Ok(());
+            }
+            if started_at.elapsed() > ROUTE_TIMEOUT {
+                return Err(Error::Unreachable {
+                    destination: self.destination.clone(),
+                    outcome,
+                });
+            }
+            delay_for(Duration::from_millis(200)).await;
+        }
+    }
+}
+
+/// Sets up the accounts and the receiver, runs the workload and returns its results
+pub async fn run(config: Config) -> Result<Report, Error> {
+    config.validate()?;
+    let client = reqwest::Client::builder()
+        .timeout(config.expiry + Duration::from_secs(5))
+        .build()
+        .map_err(|source| Error::Http {
+            url: config.node.clone(),
+            source,
+        })?;
+    let rng = SystemRandom::new();
+    let mut secret = [0; 32];
+    rng.fill(&mut secret)
+        .expect("Failed to read from the system's randomness");
+    let fulfiller = Fulfiller::new(&secret);
+
+    if let Some(ref admin_token) = config.admin_token {
+        create_account(
+            &client,
+            &config.node,
+            admin_token,
+            json!({
+                "username": config.sender.username,
+                "asset_code": config.asset_code,
+                "asset_scale": config.asset_scale,
+                "ilp_over_http_incoming_token": config.sender.token,
+            }),
+        )
+        .await?;
+    }
+
+    let mut shutdown = None;
+    let destination = match config.destination {
+        Some(ref destination) => destination.clone(),
+        None => {
+            let (shutdown_sender, shutdown_receiver) = oneshot::channel::<()>();
+            let (_, server) = warp::serve(receiver_filter(
+                fulfiller.clone(),
+                config.receiver.token.clone(),
+            ))
+            .try_bind_with_graceful_shutdown(config.listen, async {
+                shutdown_receiver.await.ok();
+            })
+            .map_err(|_| Error::Listen(config.listen))?;
+            tokio::spawn(server);
+            shutdown = Some(shutdown_sender);
+
+            if let Some(ref admin_token) = config.admin_token {
+                create_account(
+                    &client,
+                    &config.receiver_node,
+                    admin_token,
+                    json!({
+                        "username": config.receiver.username,
+                        "asset_code": config.asset_code,
+                        "asset_scale": config.asset_scale,
+                        "ilp_over_http_url": config.receiver_url,
+                        "ilp_over_http_incoming_token": config.receiver.token,
+                        "ilp_over_http_outgoing_token": config.receiver.token,
+                        "routing_relation": "Child",
+                    }),
+                )
+                .await?;
+            }
+            fetch_address(&client, &config.receiver_node, &config.receiver).await?
+        }
+    };
+
+    let sender = Sender {
+        client,
+        url: api_url(
+            &config.node,
+            &format!("accounts/{}/ilp", config.sender.username),
+        ),
+        token: config.sender.token.clone(),
+        destination,
+        expiry: config.expiry,
+        fulfiller,
+        rng,
+    };
+    if shutdown.is_some() {
+        sender.wait_for_route().await?;
+    }
+
+    let schedule = config.rate.map(|rate| Schedule {
+        start_rate: config.rate_start.unwrap_or(rate),
+        rate,
+        ramp: config.ramp,
+    });
+    eprintln!(
+        "Sending packets to {} for {}s with {} in flight at most (amounts: {}, data sizes: {})",
+        sender.destination,
+        config.duration.as_secs_f64(),
+        config.concurrency,
+        config.amount,
+        config.data_size,
+    );
+    let started_at = Instant::now();
+    let counter = Arc::new(AtomicU64::new(0));
+    let workers = (0..config.concurrency).map(|_| {
+        let sender = sender.clone();
+        let counter = counter.clone();
+        let config = config.clone();
+        tokio::spawn(async move {
+            let mut stats = Stats::new();
+            loop {
+                let now = started_at.elapsed();
+                if now >= config.duration {
+                    break;
+                }
+                if let Some(schedule) = schedule {
+                    let due = schedule.send_time(counter.fetch_add(1, Ordering::SeqCst));
+                    if due >= config.duration {
+                        break;
+                    }
+                    if due > now {
+                        delay_for(due - now).await;
+                    }
+                }
+                let amount = config.amount.sample(&sender.rng, u64::MAX);
+                let data_size = config.data_size.sample(&sender.rng, MAX_DATA_SIZE) as usize;
+                let sent_at = Instant::now();
+                let outcome = sender.send(amount, data_size).await;
+                stats.record(amount, data_size, outcome, sent_at.elapsed());
+            }
+            stats
+        })
+    });
+
+    let mut stats = Stats::new();
+    for worker in join_all(workers).await {
+        // The workers do not panic, unless the system's randomness is unavailable
+        stats.merge(worker.expect("A worker panicked"));
+    }
+    let report = stats.report(started_at.elapsed());
+    if let Some(shutdown) = shutdown {
+        let _ = shutdown.send(());
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_credentials() {
+        assert_eq!(
+            Credentials::from_str("alice:secret:token"),
+            Ok(Credentials {
+                username: "alice".to_string(),
+                token: "secret:token".to_string(),
+            })
+        );
+        assert!(Credentials::from_str("alice").is_err());
+        assert!(Credentials::from_str(":token").is_err());
+        assert!(Credentials::from_str("alice:").is_err());
+    }
+
+    #[test]
+    fn schedules_at_a_constant_rate() {
+        let schedule = Schedule {
+            start_rate: 100.0,
+            rate: 100.0,
+            ramp: Duration::from_secs(0),
+        };
+        assert_eq!(schedule.send_time(0), Duration::from_secs(0));
+        assert_eq!(schedule.send_time(50), Duration::from_millis(500));
+        assert_eq!(schedule.send_time(300), Duration::from_secs(3));
+    }
+
+    #[test]
+    fn schedules_along_the_ramp() {
+        // From 0 to 100 packets per second over 10 seconds: 500 packets during the ramp
+        let schedule = Schedule {
+            start_rate: 0.0,
+            rate: 100.0,
+            ramp: Duration::from_secs(10),
+        };
+        let seconds = |n| schedule.send_time(n).as_secs_f64();
+        assert_eq!(seconds(0), 0.0);
+        // n = 5 t^2
+        assert!((seconds(125) - 5.0).abs() < 1e-6);
+        assert!((seconds(500) - 10.0).abs() < 1e-6);
+        assert!((seconds(600) - 11.0).abs() < 1e-6);
+
+        // From 100 down to 50 packets per second over 2 seconds: 150 packets during the ramp
+        let schedule = Schedule {
+            start_rate: 100.0,
+            rate: 50.0,
+            ramp: Duration::from_secs(2),
+        };
+        let seconds = |n| schedule.send_time(n).as_secs_f64();
+        // n = 100 t - 12.5 t^2
+        assert!((seconds(87) - 1.0).abs() < 0.01);
+        assert!((seconds(150) - 2.0).abs() < 1e-6);
+        assert!((seconds(200) - 3.0).abs() < 1e-6);
+    }
+}
@@ -3,10 +3,15 @@ use reqwest::{
     self,
     blocking::{Client, Response},
 };
+use serde_json::json;
 use std::collections::HashMap;
+use std::io::{self, Read};
 use tungstenite::{connect, handshake::client::Request};
 use url::Url;
 
+/// Version of the node's API which the CLI calls
+const API_VERSION: &str = "v1";
+
 #[non_exhaustive]
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
@@ -28,6 +33,8 @@ pub enum Error {
     WebsocketErr(#[from] tungstenite::error::Error),
     #[error("HTTP error: {0}")]
     HttpErr(#[from] http::Error),
+    #[error("Error reading file: {0}")]
+    IoErr(#[from] io::Error),
 }
 
 pub fn run(matches: &ArgMatches) -> Result<Response, Error> {
@@ -36,6 +43,24 @@ pub fn run(matches: &ArgMatches) -> Result<Response, Error> {
         url: matches.value_of("node_url").unwrap(), // infallible unwrap
     };
 
+    // Warn about what the node may not support, according to what it advertises. The node is
+    // only asked for the commands which need some of its features, and the command is still
+    // sent if it cannot be asked.
+    let required_features = required_features(matches);
+    if !required_features.is_empty() {
+        match client.get_status() {
+            Ok(status) => {
+                for warning in compatibility_warnings(&status, &required_features) {
+                    eprintln!("ilp-cli warning: {}", warning);
+                }
+            }
+            Err(err) => eprintln!(
+                "ilp-cli warning: could not check the features the node supports: {}",
+                err
+            ),
+        }
+    }
+
     // Dispatch based on parsed input
     match matches.subcommand() {
         ("accounts", Some(accounts_matches)) => match accounts_matches.subcommand() {
@@ -51,22 +76,65 @@ pub fn run(matches: &ArgMatches) -> Result<Response, Error> {
             ("update-settings", Some(submatches)) => client.put_account_settings(submatches),
             _ => Err(Error::UsageErr("ilp-cli help accounts")),
         },
+        ("account-templates", Some(templates_matches)) => match templates_matches.subcommand() {
+            ("delete", Some(submatches)) => client.delete_account_template(submatches),
+            ("list", Some(submatches)) => client.get_account_templates(submatches),
+            ("set", Some(submatches)) => client.put_account_template(submatches),
+            _ => Err(Error::UsageErr("ilp-cli help account-templates")),
+        },
+        ("assets", Some(assets_matches)) => match assets_matches.subcommand() {
+            ("delete", Some(submatches)) => client.delete_asset(submatches),
+            ("list", Some(submatches)) => client.get_assets(submatches),
+            ("set", Some(submatches)) => client.put_asset(submatches),
+            _ => Err(Error::UsageErr("ilp-cli help assets")),
+        },
         ("pay", Some(pay_matches)) => client.post_account_payments(pay_matches),
+        ("ping", Some(ping_matches)) => client.post_account_ping(ping_matches),
         ("rates", Some(rates_matches)) => match rates_matches.subcommand() {
             ("list", Some(submatches)) => client.get_rates(submatches),
             ("set-all", Some(submatches)) => client.put_rates(submatches),
+            ("pin", Some(submatches)) => client.put_rate_pin(submatches),
+            ("unpin", Some(submatches)) => client.delete_rate_pin(submatches),
+            ("list-pins", Some(submatches)) => client.get_rate_pins(submatches),
             _ => Err(Error::UsageErr("ilp-cli help rates")),
         },
         ("routes", Some(routes_matches)) => match routes_matches.subcommand() {
+            ("export", Some(submatches)) => client.get_routes_export(submatches),
+            ("import", Some(submatches)) => client.put_routes_import(submatches),
             ("list", Some(submatches)) => client.get_routes(submatches),
             ("set", Some(submatches)) => client.put_route_static(submatches),
             ("set-all", Some(submatches)) => client.put_routes_static(submatches),
             _ => Err(Error::UsageErr("ilp-cli help routes")),
         },
         ("settlement-engines", Some(settlement_matches)) => match settlement_matches.subcommand() {
+            ("delete", Some(submatches)) => client.delete_settlement_engine(submatches),
+            ("list", Some(submatches)) => client.get_settlement_engines(submatches),
+            ("reconciliation", Some(submatches)) => {
+                client.get_settlement_reconciliation(submatches)
+            }
+            ("set", Some(submatches)) => client.put_settlement_engine(submatches),
             ("set-all", Some(submatches)) => client.put_settlement_engines(submatches),
             _ => Err(Error::UsageErr("ilp-cli help settlement-engines")),
         },
+        ("balances", Some(balances_matches)) => match balances_matches.subcommand() {
+            ("recover", Some(submatches)) => client.post_balance_recover(submatches),
+            ("snapshot", Some(submatches)) => client.post_balances_snapshot(submatches),
+            ("verify", Some(submatches)) => client.get_balances_verify(submatches),
+            _ => Err(Error::UsageErr("ilp-cli help balances")),
+        },
+        ("settings", Some(settings_matches)) => match settings_matches.subcommand() {
+            ("get", Some(submatches)) => client.get_settings(submatches),
+            ("set", Some(submatches)) => client.put_settings(submatches),
+            _ => Err(Error::UsageErr("ilp-cli help settings")),
+        },
+        ("peering", Some(peering_matches)) => match peering_matches.subcommand() {
+            ("approve", Some(submatches)) => client.post_peering_approve(submatches),
+            ("list", Some(submatches)) => client.get_peering_requests(submatches),
+            ("list-sent", Some(submatches)) => client.get_peering_proposals(submatches),
+            ("reject", Some(submatches)) => client.delete_peering_request(submatches),
+            ("request", Some(submatches)) => client.post_peering_proposal(submatches),
+            _ => Err(Error::UsageErr("ilp-cli help peering")),
+        },
         ("status", Some(status_matches)) => client.get_root(status_matches),
         ("logs", Some(log_level)) => client.put_tracing_level(log_level),
         ("testnet", Some(testnet_matches)) => match testnet_matches.subcommand() {
@@ -100,9 +168,13 @@ impl NodeClient<'_> {
 
     // POST /accounts
     fn post_accounts(&self, matches: &ArgMatches) -> Result<Response, Error> {
-        let (auth, args) = extract_args(matches);
+        let (auth, mut args) = extract_args(matches);
+        let mut url = Url::parse(&format!("{}/accounts/", self.url))?;
+        if let Some(template) = args.remove("template") {
+            url.query_pairs_mut().append_pair("template", template);
+        }
         self.client
-            .post(&format!("{}/accounts/", self.url))
+            .post(url.as_str())
             .bearer_auth(auth)
             .json(&args)
             .send()
@@ -233,6 +305,18 @@ impl NodeClient<'_> {
             .map_err(Error::SendErr)
     }
 
+    // POST /accounts/:username/ping
+    fn post_account_ping(&self, matches: &ArgMatches) -> Result<Response, Error> {
+        let (auth, mut args) = extract_args(matches);
+        let user = args.remove("username").unwrap(); // infallible unwrap
+        self.client
+            .post(&format!("{}/accounts/{}/ping", self.url, user))
+            .bearer_auth(auth)
+            .json(&args)
+            .send()
+            .map_err(Error::SendErr)
+    }
+
     // GET /rates
     fn get_rates(&self, _matches: &ArgMatches) -> Result<Response, Error> {
         self.client
@@ -252,6 +336,37 @@ impl NodeClient<'_> {
             .map_err(Error::SendErr)
     }
 
+    // PUT /rates/:asset_code/pin
+    fn put_rate_pin(&self, matches: &ArgMatches) -> Result<Response, Error> {
+        let (auth, args) = extract_args(matches);
+        self.client
+            .put(&format!("{}/rates/{}/pin", self.url, args["asset_code"]))
+            .bearer_auth(auth)
+            .json(&json!({ "rate": args["rate"] }))
+            .send()
+            .map_err(Error::SendErr)
+    }
+
+    // DELETE /rates/:asset_code/pin
+    fn delete_rate_pin(&self, matches: &ArgMatches) -> Result<Response, Error> {
+        let (auth, args) = extract_args(matches);
+        self.client
+            .delete(&format!("{}/rates/{}/pin", self.url, args["asset_code"]))
+            .bearer_auth(auth)
+            .send()
+            .map_err(Error::SendErr)
+    }
+
+    // GET /rates/pins
+    fn get_rate_pins(&self, matches: &ArgMatches) -> Result<Response, Error> {
+        let (auth, _) = extract_args(matches);
+        self.client
+            .get(&format!("{}/rates/pins", self.url))
+            .bearer_auth(auth)
+            .send()
+            .map_err(Error::SendErr)
+    }
+
     // GET /routes
     fn get_routes(&self, _matches: &ArgMatches) -> Result<Response, Error> {
         self.client
@@ -260,6 +375,37 @@ impl NodeClient<'_> {
             .map_err(Error::SendErr)
     }
 
+    // GET /routes/export
+    fn get_routes_export(&self, matches: &ArgMatches) -> Result<Response, Error> {
+        let (auth, args) = extract_args(matches);
+        self.client
+            .get(&format!("{}/routes/export", self.url))
+            .bearer_auth(auth)
+            .query(&[("format", args["format"])])
+            .send()
+            .map_err(Error::SendErr)
+    }
+
+    // PUT /routes/import
+    fn put_routes_import(&self, matches: &ArgMatches) -> Result<Response, Error> {
+        let (auth, args) = extract_args(matches);
+        let routes = match args["file"] {
+            "-" => {
+                let mut routes = String::new();
+                io::stdin().read_to_string(&mut routes)?;
+                routes
+            }
+            path => std::fs::read_to_string(path)?,
+        };
+        self.client
+            .put(&format!("{}/routes/import", self.url))
+            .bearer_auth(auth)
+            .query(&[("format", args["format"])])
+            .body(routes)
+            .send()
+            .map_err(Error::SendErr)
+    }
+
     // PUT /routes/static/:prefix
     fn put_route_static(&self, matches: &ArgMatches) -> Result<Response, Error> {
         let (auth, args) = extract_args(matches);
@@ -282,6 +428,42 @@ impl NodeClient<'_> {
             .map_err(Error::SendErr)
     }
 
+    // GET /settlement/engines
+    fn get_settlement_engines(&self, matches: &ArgMatches) -> Result<Response, Error> {
+        let (auth, _) = extract_args(matches);
+        self.client
+            .get(&format!("{}/settlement/engines", self.url))
+            .bearer_auth(auth)
+            .send()
+            .map_err(Error::SendErr)
+    }
+
+    // PUT /settlement/engines, with the engine of a single asset code
+    fn put_settlement_engine(&self, matches: &ArgMatches) -> Result<Response, Error> {
+        let (auth, args) = extract_args(matches);
+        let mut engines = HashMap::new();
+        engines.insert(args["asset_code"], args["url"]);
+        self.client
+            .put(&format!("{}/settlement/engines", self.url))
+            .bearer_auth(auth)
+            .json(&engines)
+            .send()
+            .map_err(Error::SendErr)
+    }
+
+    // DELETE /settlement/engines/:asset_code
+    fn delete_settlement_engine(&self, matches: &ArgMatches) -> Result<Response, Error> {
+        let (auth, args) = extract_args(matches);
+        self.client
+            .delete(&format!(
+                "{}/settlement/engines/{}",
+                self.url, args["asset_code"]
+            ))
+            .bearer_auth(auth)
+            .send()
+            .map_err(Error::SendErr)
+    }
+
     // PUT /settlement/engines
     fn put_settlement_engines(&self, matches: &ArgMatches) -> Result<Response, Error> {
         let (auth, engine_pairs) = unflatten_pairs(matches);
@@ -293,6 +475,198 @@ impl NodeClient<'_> {
             .map_err(Error::SendErr)
     }
 
+    // POST /balances/:username/recover
+    fn post_balance_recover(&self, matches: &ArgMatches) -> Result<Response, Error> {
+        let (auth, args) = extract_args(matches);
+        self.client
+            .post(&format!(
+                "{}/balances/{}/recover",
+                self.url, args["username"]
+            ))
+            .bearer_auth(auth)
+            .send()
+            .map_err(Error::SendErr)
+    }
+
+    // POST /balances/snapshot
+    fn post_balances_snapshot(&self, matches: &ArgMatches) -> Result<Response, Error> {
+        let (auth, _) = extract_args(matches);
+        self.client
+            .post(&format!("{}/balances/snapshot", self.url))
+            .bearer_auth(auth)
+            .send()
+            .map_err(Error::SendErr)
+    }
+
+    // GET /settlements/reconciliation
+    fn get_settlement_reconciliation(&self, matches: &ArgMatches) -> Result<Response, Error> {
+        let (auth, _) = extract_args(matches);
+        self.client
+            .get(&format!("{}/settlements/reconciliation", self.url))
+            .bearer_auth(auth)
+            .send()
+            .map_err(Error::SendErr)
+    }
+
+    // GET /balances/verify
+    fn get_balances_verify(&self, matches: &ArgMatches) -> Result<Response, Error> {
+        let (auth, _) = extract_args(matches);
+        self.client
+            .get(&format!("{}/balances/verify", self.url))
+            .bearer_auth(auth)
+            .send()
+            .map_err(Error::SendErr)
+    }
+
+    // GET /settings
+    fn get_settings(&self, matches: &ArgMatches) -> Result<Response, Error> {
+        let (auth, _) = extract_args(matches);
+        self.client
+            .get(&format!("{}/settings", self.url))
+            .bearer_auth(auth)
+            .send()
+            .map_err(Error::SendErr)
+    }
+
+    // PUT /settings
+    fn put_settings(&self, matches: &ArgMatches) -> Result<Response, Error> {
+        let (auth, args) = extract_args(matches);
+        self.client
+            .put(&format!("{}/settings", self.url))
+            .bearer_auth(auth)
+            .json(&args)
+            .send()
+            .map_err(Error::SendErr)
+    }
+
+    // POST /peering/requests/:id/approve
+    fn post_peering_approve(&self, matches: &ArgMatches) -> Result<Response, Error> {
+        let (auth, args) = extract_args(matches);
+        self.client
+            .post(&format!(
+                "{}/peering/requests/{}/approve",
+                self.url, args["id"]
+            ))
+            .bearer_auth(auth)
+            .send()
+            .map_err(Error::SendErr)
+    }
+
+    // GET /peering/requests
+    fn get_peering_requests(&self, matches: &ArgMatches) -> Result<Response, Error> {
+        let (auth, _) = extract_args(matches);
+        self.client
+            .get(&format!("{}/peering/requests", self.url))
+            .bearer_auth(auth)
+            .send()
+            .map_err(Error::SendErr)
+    }
+
+    // GET /peering/proposals
+    fn get_peering_proposals(&self, matches: &ArgMatches) -> Result<Response, Error> {
+        let (auth, _) = extract_args(matches);
+        self.client
+            .get(&format!("{}/peering/proposals", self.url))
+            .bearer_auth(auth)
+            .send()
+            .map_err(Error::SendErr)
+    }
+
+    // DELETE /peering/requests/:id
+    fn delete_peering_request(&self, matches: &ArgMatches) -> Result<Response, Error> {
+        let (auth, args) = extract_args(matches);
+        self.client
+            .delete(&format!("{}/peering/requests/{}", self.url, args["id"]))
+            .bearer_auth(auth)
+            .send()
+            .map_err(Error::SendErr)
+    }
+
+    // POST /peering/proposals
+    fn post_peering_proposal(&self, matches: &ArgMatches) -> Result<Response, Error> {
+        let (auth, args) = extract_args(matches);
+        self.client
+            .post(&format!("{}/peering/proposals", self.url))
+            .bearer_auth(auth)
+            .json(&args)
+            .send()
+            .map_err(Error::SendErr)
+    }
+
+    // GET /assets
+    fn get_assets(&self, matches: &ArgMatches) -> Result<Response, Error> {
+        let (auth, _) = extract_args(matches);
+        self.client
+            .get(&format!("{}/assets", self.url))
+            .bearer_auth(auth)
+            .send()
+            .map_err(Error::SendErr)
+    }
+
+    // PUT /assets
+    fn put_asset(&self, matches: &ArgMatches) -> Result<Response, Error> {
+        let (auth, args) = extract_args(matches);
+        self.client
+            .put(&format!("{}/assets", self.url))
+            .bearer_auth(auth)
+            .json(&args)
+            .send()
+            .map_err(Error::SendErr)
+    }
+
+    // GET /account-templates
+    fn get_account_templates(&self, matches: &ArgMatches) -> Result<Response, Error> {
+        let (auth, _) = extract_args(matches);
+        self.client
+            .get(&format!("{}/account-templates", self.url))
+            .bearer_auth(auth)
+            .send()
+            .map_err(Error::SendErr)
+    }
+
+    // PUT /account-templates
+    fn put_account_template(&self, matches: &ArgMatches) -> Result<Response, Error> {
+        let (auth, field_pairs) = unflatten_pairs(matches);
+        // The values which are not JSON (such as the asset codes) are sent as strings
+        let fields: HashMap<_, _> = field_pairs
+            .into_iter()
+            .map(|(field, value)| {
+                let value = serde_json::from_str(value)
+                    .unwrap_or_else(|_| serde_json::Value::String(value.to_string()));
+                (field, value)
+            })
+            .collect();
+        self.client
+            .put(&format!("{}/account-templates", self.url))
+            .bearer_auth(auth)
+            .json(&json!({
+                "name": matches.value_of("name").unwrap(), // infallible unwrap
+                "fields": fields,
+            }))
+            .send()
+            .map_err(Error::SendErr)
+    }
+
+    // DELETE /account-templates/:name
+    fn delete_account_template(&self, matches: &ArgMatches) -> Result<Response, Error> {
+        let (auth, args) = extract_args(matches);
+        self.client
+            .delete(&format!("{}/account-templates/{}", self.url, args["name"]))
+            .bearer_auth(auth)
+            .send()
+            .map_err(Error::SendErr)
+    }
+
+    // DELETE /assets/:code
+    fn delete_asset(&self, matches: &ArgMatches) -> Result<Response, Error> {
+        let (auth, args) = extract_args(matches);
+        self.client
+            .delete(&format!("{}/assets/{}", self.url, args["code"]))
+            .bearer_auth(auth)
+            .send()
+            .map_err(Error::SendErr)
+    }
+
     // PUT /tracing-level
     fn put_tracing_level(&self, matches: &ArgMatches) -> Result<Response, Error> {
         let (auth, args) = extract_args(matches);
@@ -312,6 +686,11 @@ impl NodeClient<'_> {
             .map_err(Error::SendErr)
     }
 
+    /// Returns what the node advertises, which is nothing if it is too old to advertise it
+    fn get_status(&self) -> Result<NodeStatus, Error> {
+        Ok(self.get_root(&ArgMatches::default())?.json()?)
+    }
+
     /*
     {"http_endpoint": "https://rs3.xpring.dev/ilp", // ilp_over_http_url
     "passkey": "b0i3q9tbvfgek",  // ilp_over_http_outgoing_token = username:passkey
@@ -365,6 +744,59 @@ impl NodeClient<'_> {
     }
 }
 
+/// The features advertised by a node, and the versions of the API it serves
+#[derive(Debug, Default, serde::Deserialize)]
+struct NodeStatus {
+    #[serde(default)]
+    features: Vec<String>,
+    #[serde(default)]
+    api_versions: Vec<String>,
+}
+
+/// The features of the node which the command needs
+fn required_features(matches: &ArgMatches) -> Vec<&'static str> {
+    let mut features = Vec::new();
+    match matches.subcommand() {
+        ("settlement-engines", _) => features.push("settlement"),
+        ("accounts", Some(accounts_matches)) => match accounts_matches.subcommand() {
+            ("create", Some(submatches)) | ("update", Some(submatches)) => {
+                if submatches.is_present("settlement_engine_url") {
+                    features.push("settlement");
+                }
+                if submatches.is_present("ilp_over_http_incoming_token") {
+                    features.push("http");
+                }
+                if submatches.is_present("ilp_over_btp_incoming_token") {
+                    features.push("btp");
+                }
+            }
+            _ => {}
+        },
+        _ => {}
+    }
+    features
+}
+
+fn compatibility_warnings(status: &NodeStatus, required_features: &[&str]) -> Vec<String> {
+    let mut warnings = Vec::new();
+    if !status.api_versions.is_empty() && !status.api_versions.iter().any(|v| v == API_VERSION) {
+        warnings.push(format!(
+            "the node serves the API versions {}, but ilp-cli uses {}",
+            status.api_versions.join(", "),
+            API_VERSION
+        ));
+    }
+    for feature in required_features {
+        if !status.features.iter().any(|f| f == feature) {
+            warnings.push(format!(
+                "the node does not advertise the {} feature, which this command needs",
+                feature
+            ));
+        }
+    }
+    warnings
+}
+
 // This function takes the map of arguments parsed by Clap
 // and extracts the values for each argument.
 fn extract_args<'a>(matches: &'a ArgMatches) -> (&'a str, HashMap<&'a str, &'a str>) {
@@ -401,3 +833,54 @@ struct XpringResponse {
     username: String,
     payment_pointer: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+
+    fn status(features: &[&str], api_versions: &[&str]) -> NodeStatus {
+        NodeStatus {
+            features: features.iter().map(|f| f.to_string()).collect(),
+            api_versions: api_versions.iter().map(|v| v.to_string()).collect(),
+        }
+    }
+
+    fn required_features_of(command: &str) -> Vec<&'static str> {
+        let matches = parser::build()
+            .get_matches_from_safe(command.split(' '))
+            .unwrap();
+        required_features(&matches)
+    }
+
+    #[test]
+    fn finds_the_features_the_commands_need() {
+        assert_eq!(
+            required_features_of("ilp-cli settlement-engines list --auth foo"),
+            vec!["settlement"]
+        );
+        assert_eq!(
+            required_features_of("ilp-cli accounts create alice --auth foo --asset-code XYZ --asset-scale 6 --ilp-over-btp-incoming-token bar --settlement-engine-url baz"),
+            vec!["settlement", "btp"]
+        );
+        assert!(required_features_of("ilp-cli accounts list --auth foo").is_empty());
+    }
+
+    #[test]
+    fn warns_about_missing_features_and_api_versions() {
+        let node = status(&["btp", "http", "spsp"], &["v1"]);
+        assert!(compatibility_warnings(&node, &["http"]).is_empty());
+        assert_eq!(
+            compatibility_warnings(&node, &["settlement"]),
+            vec!["the node does not advertise the settlement feature, which this command needs"]
+        );
+
+        let node = status(&["settlement"], &["v2"]);
+        assert_eq!(
+            compatibility_warnings(&node, &["settlement"]),
+            vec!["the node serves the API versions v2, but ilp-cli uses v1"]
+        );
+        // Nodes which do not advertise their API versions are assumed to serve this one
+        assert!(compatibility_warnings(&status(&[], &[]), &[]).is_empty());
+    }
+}
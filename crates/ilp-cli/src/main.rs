@@ -75,8 +75,9 @@ mod interface_tests {
     #[test]
     fn accounts_create() {
         should_parse(&[
-            "ilp-cli accounts create alice --auth foo --asset-code XYZ --asset-scale 6 --ilp-address bar --max-packet-amount 100 --min-balance 0 --ilp-over-http-url qux --ilp-over-http-incoming-token baz --ilp-over-http-outgoing-token qaz --ilp-over-btp-url spam --ilp-over-btp-outgoing-token ham --ilp-over-btp-incoming-token eggs --settle-threshold 0 --settle-to 0 --routing-relation foobar --round-trip-time 1000 --amount-per-minute-limit 42 --packets-per-minute-limit 4 --settlement-engine-url if_you_can_read_this_congratulations_youve_scrolled_too_far_right", // maximal
+            "ilp-cli accounts create alice --auth foo --asset-code XYZ --asset-scale 6 --ilp-address bar --max-packet-amount 100 --max-packet-size 4096 --min-balance 0 --ilp-over-http-url qux --ilp-over-http-incoming-token baz --ilp-over-http-outgoing-token qaz --ilp-over-btp-url spam --ilp-over-btp-outgoing-token ham --ilp-over-btp-incoming-token eggs --settle-threshold 0 --settle-to 0 --routing-relation foobar --round-trip-time 1000 --amount-per-minute-limit 42 --packets-per-minute-limit 4 --settlement-engine-url if_you_can_read_this_congratulations_youve_scrolled_too_far_right", // maximal
             "ilp-cli accounts create alice --auth foo --asset-code ABC --asset-scale 3 --min-balance -1000 --settle-threshold -10", // negative numbers
+            "ilp-cli accounts create alice --auth foo --template peer-xrp --ilp-over-http-url qux", // template
         ]);
     }
 
@@ -84,7 +85,7 @@ mod interface_tests {
     fn accounts_update() {
         should_parse(&[
             "ilp-cli accounts update alice --auth foo --asset-code ABC --asset-scale 9", // minimal
-            "ilp-cli accounts update alice --auth foo --asset-code XYZ --asset-scale 6 --ilp-address bar --max-packet-amount 100 --min-balance 0 --ilp-over-http-url qux --ilp-over-http-incoming-token baz --ilp-over-http-outgoing-token qaz --ilp-over-btp-url spam --ilp-over-btp-outgoing-token ham --ilp-over-btp-incoming-token eggs --settle-threshold 0 --settle-to 0 --routing-relation foobar --round-trip-time 1000 --amount-per-minute-limit 42 --packets-per-minute-limit 4 --settlement-engine-url if_you_can_read_this_congratulations_youve_scrolled_too_far_right", // maximal
+            "ilp-cli accounts update alice --auth foo --asset-code XYZ --asset-scale 6 --ilp-address bar --max-packet-amount 100 --max-packet-size 4096 --min-balance 0 --ilp-over-http-url qux --ilp-over-http-incoming-token baz --ilp-over-http-outgoing-token qaz --ilp-over-btp-url spam --ilp-over-btp-outgoing-token ham --ilp-over-btp-incoming-token eggs --settle-threshold 0 --settle-to 0 --routing-relation foobar --round-trip-time 1000 --amount-per-minute-limit 42 --packets-per-minute-limit 4 --settlement-engine-url if_you_can_read_this_congratulations_youve_scrolled_too_far_right", // maximal
         ]);
     }
 
@@ -128,7 +129,7 @@ mod interface_tests {
     fn accounts_update_settings() {
         should_parse(&[
             "ilp-cli accounts update-settings alice --auth foo", // minimal
-            "ilp-cli accounts update-settings alice --auth foo --ilp-over-http-incoming-token bar --ilp-over-btp-incoming-token qux --ilp-over-http-outgoing-token baz --ilp-over-btp-outgoing-token qaz --ilp-over-http-url spam --ilp-over-btp-url eggs --settle-threshold 0 --settle-to 0", // maximal
+            "ilp-cli accounts update-settings alice --auth foo --ilp-over-http-incoming-token bar --ilp-over-btp-incoming-token qux --ilp-over-http-outgoing-token baz --ilp-over-btp-outgoing-token qaz --ilp-over-http-url spam --ilp-over-btp-url eggs --settle-threshold 0 --settle-to 0 --packets-per-second-limit 10 --packets-per-minute-limit 100 --amount-per-minute-limit 1000 --max-packet-amount 500 --max-packet-size 4096", // maximal
             "ilp-cli accounts update-settings alice --auth foo --settle-threshold -1000 --settle-to -10", // negative numbers
         ]);
     }
@@ -137,6 +138,16 @@ mod interface_tests {
     fn pay() {
         should_parse(&[
             "ilp-cli pay alice --auth foo --amount 500 --to bar", // minimal
+            "ilp-cli pay alice --auth foo --amount 500 --to bar --memo INV-42", // maximal
+            "ilp-cli pay alice --auth foo --destination-amount 500 --to bar", // fixed delivery
+        ]);
+    }
+
+    #[test]
+    fn ping() {
+        should_parse(&[
+            "ilp-cli ping alice --auth foo example.bob", // minimal
+            "ilp-cli ping alice --auth foo example.bob --timeout 5000", // maximal
         ]);
     }
 
@@ -157,6 +168,43 @@ mod interface_tests {
         ]);
     }
 
+    #[test]
+    fn rates_pin() {
+        should_parse(&[
+            "ilp-cli rates pin XYZ --rate 0.5 --auth foo", // minimal
+        ]);
+    }
+
+    #[test]
+    fn rates_unpin() {
+        should_parse(&[
+            "ilp-cli rates unpin XYZ --auth foo", // minimal
+        ]);
+    }
+
+    #[test]
+    fn rates_list_pins() {
+        should_parse(&[
+            "ilp-cli rates list-pins --auth foo", // minimal
+        ]);
+    }
+
+    #[test]
+    fn routes_export() {
+        should_parse(&[
+            "ilp-cli routes export --auth foo",              // minimal
+            "ilp-cli routes export --auth foo --format csv", // csv
+        ]);
+    }
+
+    #[test]
+    fn routes_import() {
+        should_parse(&[
+            "ilp-cli routes import routes.json --auth foo", // minimal
+            "ilp-cli routes import routes.csv --auth foo --format csv", // csv
+        ]);
+    }
+
     #[test]
     fn routes_list() {
         should_parse(&[
@@ -181,6 +229,78 @@ mod interface_tests {
         ])
     }
 
+    #[test]
+    fn account_templates_delete() {
+        should_parse(&[
+            "ilp-cli account-templates delete peer-xrp --auth foo", // minimal
+        ]);
+    }
+
+    #[test]
+    fn account_templates_list() {
+        should_parse(&[
+            "ilp-cli account-templates list --auth foo", // minimal
+        ]);
+    }
+
+    #[test]
+    fn account_templates_set() {
+        should_parse(&[
+            "ilp-cli account-templates set peer-xrp --auth foo", // minimal
+            "ilp-cli account-templates set peer-xrp --auth foo --field asset_code XRP --field asset_scale 9 --field routing_relation Peer --field route_full_table true", // many
+        ]);
+    }
+
+    #[test]
+    fn assets_delete() {
+        should_parse(&[
+            "ilp-cli assets delete USD --auth foo", // minimal
+        ]);
+    }
+
+    #[test]
+    fn assets_list() {
+        should_parse(&[
+            "ilp-cli assets list --auth foo", // minimal
+        ]);
+    }
+
+    #[test]
+    fn assets_set() {
+        should_parse(&[
+            "ilp-cli assets set USD --auth foo --max-scale 9 --display-scale 2", // minimal
+            "ilp-cli assets set USD --auth foo --min-scale 2 --max-scale 9 --symbol $ --display-scale 2 --rounding down", // maximal
+        ]);
+    }
+
+    #[test]
+    fn settlement_engines_delete() {
+        should_parse(&[
+            "ilp-cli settlement-engines delete ABC --auth foo", // minimal
+        ]);
+    }
+
+    #[test]
+    fn settlement_engines_list() {
+        should_parse(&[
+            "ilp-cli settlement-engines list --auth foo", // minimal
+        ]);
+    }
+
+    #[test]
+    fn settlement_engines_reconciliation() {
+        should_parse(&[
+            "ilp-cli settlement-engines reconciliation --auth foo", // minimal
+        ]);
+    }
+
+    #[test]
+    fn settlement_engines_set() {
+        should_parse(&[
+            "ilp-cli settlement-engines set ABC --auth foo --url http://localhost:3000", // minimal
+        ]);
+    }
+
     #[test]
     fn settlement_engines_set_all() {
         should_parse(&[
@@ -191,6 +311,54 @@ mod interface_tests {
         ]);
     }
 
+    #[test]
+    fn balances_recover() {
+        should_parse(&[
+            "ilp-cli balances recover alice --auth foo", // minimal
+        ]);
+    }
+
+    #[test]
+    fn balances_snapshot() {
+        should_parse(&[
+            "ilp-cli balances snapshot --auth foo", // minimal
+        ]);
+    }
+
+    #[test]
+    fn balances_verify() {
+        should_parse(&[
+            "ilp-cli balances verify --auth foo", // minimal
+        ]);
+    }
+
+    #[test]
+    fn settings_get() {
+        should_parse(&[
+            "ilp-cli settings get --auth foo", // minimal
+        ]);
+    }
+
+    #[test]
+    fn settings_set() {
+        should_parse(&[
+            "ilp-cli settings set --auth foo", // minimal
+            "ilp-cli settings set --auth foo --route-broadcast-interval 5000 --exchange-rate-poll-interval 1000 --max-packet-amount 100 --max-packet-size 4096", // maximal
+        ]);
+    }
+
+    #[test]
+    fn peering() {
+        should_parse(&[
+            "ilp-cli peering list --auth foo", // minimal
+            "ilp-cli peering list-sent --auth foo", // minimal
+            "ilp-cli peering approve abc --auth foo", // minimal
+            "ilp-cli peering reject abc --auth foo", // minimal
+            "ilp-cli peering request http://peer.example --auth foo --username bob --asset-code XRP --asset-scale 9", // minimal
+            "ilp-cli peering request http://peer.example --auth foo --username bob --peer-username alice --asset-code XRP --asset-scale 9", // maximal
+        ]);
+    }
+
     #[test]
     fn status() {
         should_parse(&[
@@ -215,8 +383,10 @@ mod interface_tests {
             match parser_result {
                 Err(e) => panic!("Failed to parse command `{}`: {}", example, e),
                 Ok(matches) => match run(&matches) {
-                    // Because these are interface tests, not integration tests, network errors are expected
+                    // Because these are interface tests, not integration tests, network errors
+                    // (and missing files) are expected
                     Ok(_)
+                    | Err(Error::IoErr(_))
                     | Err(Error::SendErr(_))
                     | Err(Error::WebsocketErr(_))
                     | Err(Error::TestnetErr(_)) => (),
@@ -12,10 +12,48 @@ pub fn build<'a, 'b>() -> App<'a, 'b> {
             accounts_update(),
             accounts_update_settings(),
         ]),
+        account_templates().subcommands(vec![
+            account_templates_delete(),
+            account_templates_list(),
+            account_templates_set(),
+        ]),
+        assets().subcommands(vec![assets_delete(), assets_list(), assets_set()]),
         pay(),
-        rates().subcommands(vec![rates_list(), rates_set_all()]),
-        routes().subcommands(vec![routes_list(), routes_set(), routes_set_all()]),
-        settlement_engines().subcommands(vec![settlement_engines_set_all()]),
+        ping(),
+        rates().subcommands(vec![
+            rates_list(),
+            rates_set_all(),
+            rates_pin(),
+            rates_unpin(),
+            rates_list_pins(),
+        ]),
+        routes().subcommands(vec![
+            routes_export(),
+            routes_import(),
+            routes_list(),
+            routes_set(),
+            routes_set_all(),
+        ]),
+        settlement_engines().subcommands(vec![
+            settlement_engines_delete(),
+            settlement_engines_list(),
+            settlement_engines_reconciliation(),
+            settlement_engines_set(),
+            settlement_engines_set_all(),
+        ]),
+        balances().subcommands(vec![
+            balances_recover(),
+            balances_snapshot(),
+            balances_verify(),
+        ]),
+        settings().subcommands(vec![settings_get(), settings_set()]),
+        peering().subcommands(vec![
+            peering_approve(),
+            peering_list(),
+            peering_list_sent(),
+            peering_reject(),
+            peering_request(),
+        ]),
         status(),
         logs(),
         testnet().subcommands(vec![testnet_setup()]),
@@ -87,15 +125,19 @@ fn accounts_create<'a, 'b>() -> App<'a, 'b> {
                 .takes_value(true)
                 .required(true)
                 .help("The username of the account"),
+            Arg::with_name("template")
+                .long("template")
+                .takes_value(true)
+                .help("The name of the account template setting the details which are not given"),
             Arg::with_name("asset_code")
                 .long("asset-code")
                 .takes_value(true)
-                .required(true)
+                .required_unless("template")
                 .help("The code of the asset associated with this account"),
             Arg::with_name("asset_scale")
                 .long("asset-scale")
                 .takes_value(true)
-                .required(true)
+                .required_unless("template")
                 .help("The scale of the asset associated with this account"),
             // TODO: when we have a glossary of HTTP API options, add their descriptions to these
             Arg::with_name("ilp_address")
@@ -104,9 +146,18 @@ fn accounts_create<'a, 'b>() -> App<'a, 'b> {
             Arg::with_name("max_packet_amount")
                 .long("max-packet-amount")
                 .takes_value(true),
+            Arg::with_name("max_packet_size")
+                .long("max-packet-size")
+                .takes_value(true),
             Arg::with_name("min_balance")
                 .long("min-balance")
                 .takes_value(true),
+            Arg::with_name("payable_limit")
+                .long("payable-limit")
+                .takes_value(true),
+            Arg::with_name("receivable_limit")
+                .long("receivable-limit")
+                .takes_value(true),
             Arg::with_name("ilp_over_http_url")
                 .long("ilp-over-http-url")
                 .takes_value(true),
@@ -137,15 +188,37 @@ fn accounts_create<'a, 'b>() -> App<'a, 'b> {
             Arg::with_name("round_trip_time")
                 .long("round-trip-time")
                 .takes_value(true),
+            Arg::with_name("route_broadcast_interval")
+                .long("route-broadcast-interval")
+                .takes_value(true),
+            Arg::with_name("route_expiry")
+                .long("route-expiry")
+                .takes_value(true),
             Arg::with_name("amount_per_minute_limit")
                 .long("amount-per-minute-limit")
                 .takes_value(true),
+            Arg::with_name("packets_per_second_limit")
+                .long("packets-per-second-limit")
+                .takes_value(true),
             Arg::with_name("packets_per_minute_limit")
                 .long("packets-per-minute-limit")
                 .takes_value(true),
+            Arg::with_name("spread").long("spread").takes_value(true),
+            Arg::with_name("percentage_fee")
+                .long("percentage-fee")
+                .takes_value(true),
+            Arg::with_name("fixed_fee")
+                .long("fixed-fee")
+                .takes_value(true),
             Arg::with_name("settlement_engine_url")
                 .long("settlement-engine-url")
                 .takes_value(true),
+            Arg::with_name("liquidity_notification_url")
+                .long("liquidity-notification-url")
+                .takes_value(true),
+            Arg::with_name("balance_notification_url")
+                .long("balance-notification-url")
+                .takes_value(true),
         ])
 }
 
@@ -175,9 +248,18 @@ fn accounts_update<'a, 'b>() -> App<'a, 'b> {
             Arg::with_name("max_packet_amount")
                 .long("max-packet-amount")
                 .takes_value(true),
+            Arg::with_name("max_packet_size")
+                .long("max-packet-size")
+                .takes_value(true),
             Arg::with_name("min_balance")
                 .long("min-balance")
                 .takes_value(true),
+            Arg::with_name("payable_limit")
+                .long("payable-limit")
+                .takes_value(true),
+            Arg::with_name("receivable_limit")
+                .long("receivable-limit")
+                .takes_value(true),
             Arg::with_name("ilp_over_http_url")
                 .long("ilp-over-http-url")
                 .takes_value(true),
@@ -208,15 +290,37 @@ fn accounts_update<'a, 'b>() -> App<'a, 'b> {
             Arg::with_name("round_trip_time")
                 .long("round-trip-time")
                 .takes_value(true),
+            Arg::with_name("route_broadcast_interval")
+                .long("route-broadcast-interval")
+                .takes_value(true),
+            Arg::with_name("route_expiry")
+                .long("route-expiry")
+                .takes_value(true),
             Arg::with_name("amount_per_minute_limit")
                 .long("amount-per-minute-limit")
                 .takes_value(true),
+            Arg::with_name("packets_per_second_limit")
+                .long("packets-per-second-limit")
+                .takes_value(true),
             Arg::with_name("packets_per_minute_limit")
                 .long("packets-per-minute-limit")
                 .takes_value(true),
+            Arg::with_name("spread").long("spread").takes_value(true),
+            Arg::with_name("percentage_fee")
+                .long("percentage-fee")
+                .takes_value(true),
+            Arg::with_name("fixed_fee")
+                .long("fixed-fee")
+                .takes_value(true),
             Arg::with_name("settlement_engine_url")
                 .long("settlement-engine-url")
                 .takes_value(true),
+            Arg::with_name("liquidity_notification_url")
+                .long("liquidity-notification-url")
+                .takes_value(true),
+            Arg::with_name("balance_notification_url")
+                .long("balance-notification-url")
+                .takes_value(true),
         ])
 }
 
@@ -296,6 +400,21 @@ fn accounts_update_settings<'a, 'b>() -> App<'a, 'b> {
             Arg::with_name("settle_to")
                 .long("settle-to")
                 .takes_value(true),
+            Arg::with_name("packets_per_second_limit")
+                .long("packets-per-second-limit")
+                .takes_value(true),
+            Arg::with_name("packets_per_minute_limit")
+                .long("packets-per-minute-limit")
+                .takes_value(true),
+            Arg::with_name("amount_per_minute_limit")
+                .long("amount-per-minute-limit")
+                .takes_value(true),
+            Arg::with_name("max_packet_amount")
+                .long("max-packet-amount")
+                .takes_value(true),
+            Arg::with_name("max_packet_size")
+                .long("max-packet-size")
+                .takes_value(true),
         ])
 }
 
@@ -312,13 +431,43 @@ fn pay<'a, 'b>() -> App<'a, 'b> {
             Arg::with_name("source_amount")
                 .long("amount")
                 .takes_value(true)
-                .required(true)
+                .required_unless("destination_amount")
+                .conflicts_with("destination_amount")
                 .help("The amount to transfer from the sender to the receiver, denominated in units of the sender's assets"),
+            Arg::with_name("destination_amount")
+                .long("destination-amount")
+                .takes_value(true)
+                .help("The exact amount to deliver to the receiver, denominated in units of the receiver's assets, instead of a source amount"),
             Arg::with_name("receiver")
                 .long("to")
                 .takes_value(true)
                 .required(true)
                 .help("The Payment Pointer or SPSP address of the account receiving the payment"),
+            Arg::with_name("memo")
+                .long("memo")
+                .takes_value(true)
+                .help("A reference of the payment, such as an invoice number, which is sent to the receiver"),
+        ])
+}
+
+fn ping<'a, 'b>() -> App<'a, 'b> {
+    AuthorizedSubCommand::with_name("ping")
+        .about("Send an echo packet from an account on this node and measure its round trip time")
+        .args(&[
+            Arg::with_name("username")
+                .index(1)
+                .takes_value(true)
+                .required(true)
+                .help("The username of the account on this node sending the echo packet"),
+            Arg::with_name("destination")
+                .index(2)
+                .takes_value(true)
+                .required(true)
+                .help("The ILP address to ping"),
+            Arg::with_name("timeout")
+                .long("timeout")
+                .takes_value(true)
+                .help("How long to wait for the echo response, in milliseconds. Defaults to 10000"),
         ])
 }
 
@@ -342,10 +491,74 @@ fn rates_set_all<'a, 'b>() -> App<'a, 'b> {
         )
 }
 
+fn rates_pin<'a, 'b>() -> App<'a, 'b> {
+    AuthorizedSubCommand::with_name("pin")
+        .about("Pin the exchange rate of an asset, overriding the rate polled from the provider until it is unpinned")
+        .args(&[
+            Arg::with_name("asset_code")
+                .index(1)
+                .takes_value(true)
+                .required(true)
+                .help("The asset code whose rate to pin"),
+            Arg::with_name("rate")
+                .long("rate")
+                .takes_value(true)
+                .required(true)
+                .help("The exchange rate of the asset"),
+        ])
+}
+
+fn rates_unpin<'a, 'b>() -> App<'a, 'b> {
+    AuthorizedSubCommand::with_name("unpin")
+        .about("Unpin the exchange rate of an asset; the rate polled from the provider applies again on the next poll")
+        .arg(
+            Arg::with_name("asset_code")
+                .index(1)
+                .takes_value(true)
+                .required(true)
+                .help("The asset code whose rate to unpin"),
+        )
+}
+
+fn rates_list_pins<'a, 'b>() -> App<'a, 'b> {
+    AuthorizedSubCommand::with_name("list-pins").about("List the pinned exchange rates")
+}
+
 fn routes<'a, 'b>() -> App<'a, 'b> {
     SubCommand::with_name("routes").about("Operations for interacting with the routing table")
 }
 
+fn routes_export<'a, 'b>() -> App<'a, 'b> {
+    AuthorizedSubCommand::with_name("export")
+        .about("Export this node's routing table, telling which routes are static, local or learned from peers")
+        .arg(
+            Arg::with_name("format")
+                .long("format")
+                .takes_value(true)
+                .possible_values(&["json", "csv"])
+                .default_value("json")
+                .help("The format of the exported routes"),
+        )
+}
+
+fn routes_import<'a, 'b>() -> App<'a, 'b> {
+    AuthorizedSubCommand::with_name("import")
+        .about("Overwrite the static routes of this node with the static routes of an export; the local and learned routes of the export are ignored")
+        .args(&[
+            Arg::with_name("file")
+                .index(1)
+                .takes_value(true)
+                .required(true)
+                .help("The file of the export, or - to read it from the standard input"),
+            Arg::with_name("format")
+                .long("format")
+                .takes_value(true)
+                .possible_values(&["json", "csv"])
+                .default_value("json")
+                .help("The format of the export"),
+        ])
+}
+
 fn routes_list<'a, 'b>() -> App<'a, 'b> {
     SubCommand::with_name("list").about("View this node's routing table")
 }
@@ -379,14 +592,147 @@ fn routes_set_all<'a, 'b>() -> App<'a, 'b> {
         )
 }
 
+fn account_templates<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("account-templates")
+        .about("Interact with the templates which accounts can be created with")
+}
+
+fn account_templates_delete<'a, 'b>() -> App<'a, 'b> {
+    AuthorizedSubCommand::with_name("delete")
+        .about("Remove an account template; the accounts created with it are kept")
+        .arg(
+            Arg::with_name("name")
+                .index(1)
+                .takes_value(true)
+                .required(true)
+                .help("The name of the template to remove"),
+        )
+}
+
+fn account_templates_list<'a, 'b>() -> App<'a, 'b> {
+    AuthorizedSubCommand::with_name("list").about("List the account templates")
+}
+
+fn account_templates_set<'a, 'b>() -> App<'a, 'b> {
+    AuthorizedSubCommand::with_name("set")
+        .about("Save an account template, or replace the one with the same name")
+        .args(&[
+            Arg::with_name("name")
+                .index(1)
+                .takes_value(true)
+                .required(true)
+                .help("The name of the template, such as peer-xrp"),
+            Arg::with_name("halve")
+                .long("field")
+                .number_of_values(2)
+                .multiple(true)
+                .help("A space-separated pair of an account detail and its value, such as settle_threshold 1000; may appear multiple times"),
+        ])
+}
+
+fn assets<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("assets")
+        .about("Interact with the registry of the assets which accounts can be denominated in")
+}
+
+fn assets_delete<'a, 'b>() -> App<'a, 'b> {
+    AuthorizedSubCommand::with_name("delete")
+        .about("Remove an asset from the registry; its accounts are kept")
+        .arg(
+            Arg::with_name("code")
+                .index(1)
+                .takes_value(true)
+                .required(true)
+                .help("The code of the asset to remove"),
+        )
+}
+
+fn assets_list<'a, 'b>() -> App<'a, 'b> {
+    AuthorizedSubCommand::with_name("list").about("List the registered assets")
+}
+
+fn assets_set<'a, 'b>() -> App<'a, 'b> {
+    AuthorizedSubCommand::with_name("set")
+        .about("Register an asset, or replace the one with the same code")
+        .args(&[
+            Arg::with_name("code")
+                .index(1)
+                .takes_value(true)
+                .required(true)
+                .help("The code of the asset"),
+            Arg::with_name("min_scale")
+                .long("min-scale")
+                .takes_value(true)
+                .help("The smallest asset scale of the accounts denominated in the asset (defaults to 0)"),
+            Arg::with_name("max_scale")
+                .long("max-scale")
+                .takes_value(true)
+                .required(true)
+                .help("The largest asset scale of the accounts denominated in the asset"),
+            Arg::with_name("symbol")
+                .long("symbol")
+                .takes_value(true)
+                .help("The symbol the amounts are displayed with, such as $ (defaults to the code after the amount)"),
+            Arg::with_name("display_scale")
+                .long("display-scale")
+                .takes_value(true)
+                .required(true)
+                .help("The number of decimals the amounts are displayed with"),
+            Arg::with_name("rounding")
+                .long("rounding")
+                .takes_value(true)
+                .possible_values(&["half_up", "down", "up"])
+                .help("How the amounts are rounded to the displayed decimals (defaults to half_up)"),
+        ])
+}
+
 fn settlement_engines<'a, 'b>() -> App<'a, 'b> {
     SubCommand::with_name("settlement-engines")
         .about("Interact with the settlement engine configurations")
 }
 
+fn settlement_engines_delete<'a, 'b>() -> App<'a, 'b> {
+    AuthorizedSubCommand::with_name("delete")
+        .about("Remove the default settlement engine of an asset code; the accounts with an engine of their own keep it")
+        .arg(
+            Arg::with_name("asset_code")
+                .index(1)
+                .takes_value(true)
+                .required(true)
+                .help("The asset code whose default settlement engine to remove"),
+        )
+}
+
+fn settlement_engines_list<'a, 'b>() -> App<'a, 'b> {
+    AuthorizedSubCommand::with_name("list")
+        .about("List the default settlement engines, which the accounts without an engine of their own use")
+}
+
+fn settlement_engines_reconciliation<'a, 'b>() -> App<'a, 'b> {
+    AuthorizedSubCommand::with_name("reconciliation")
+        .about("Show the totals settled with the accounts according to the node and to their settlement engines, as of the latest reconciliation")
+}
+
+fn settlement_engines_set<'a, 'b>() -> App<'a, 'b> {
+    AuthorizedSubCommand::with_name("set")
+        .about("Configure the default settlement engine of a single asset code")
+        .args(&[
+            Arg::with_name("asset_code")
+                .index(1)
+                .takes_value(true)
+                .required(true)
+                .help("The asset code to configure"),
+            Arg::with_name("url")
+                .long("url")
+                .takes_value(true)
+                .required(true)
+                .help("The URL of the settlement engine of the accounts denominated in the asset"),
+        ])
+}
+
 fn settlement_engines_set_all<'a, 'b>() -> App<'a, 'b> {
     AuthorizedSubCommand::with_name("set-all")
-        .about("Configure the default settlement engines for given asset codes")
+        .about("Configure the default settlement engines for given asset codes; the ones of the other asset codes are kept")
         .arg(
             Arg::with_name("halve")
                 .long("pair")
@@ -396,6 +742,134 @@ fn settlement_engines_set_all<'a, 'b>() -> App<'a, 'b> {
         )
 }
 
+fn balances<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("balances")
+        .about("Audit the account balances against their balance journals")
+}
+
+fn balances_recover<'a, 'b>() -> App<'a, 'b> {
+    AuthorizedSubCommand::with_name("recover")
+        .about("Overwrite an account's balance with the one replayed from its balance journal")
+        .arg(
+            Arg::with_name("username")
+                .index(1)
+                .takes_value(true)
+                .required(true)
+                .help("The username of the account whose balance to recover"),
+        )
+}
+
+fn balances_snapshot<'a, 'b>() -> App<'a, 'b> {
+    AuthorizedSubCommand::with_name("snapshot").about("Snapshot the balances of all accounts")
+}
+
+fn balances_verify<'a, 'b>() -> App<'a, 'b> {
+    AuthorizedSubCommand::with_name("verify")
+        .about("Compare the balances of all accounts to the ones replayed from their journals")
+}
+
+fn settings<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("settings")
+        .about("Interact with the node settings which can be changed at runtime")
+}
+
+fn settings_get<'a, 'b>() -> App<'a, 'b> {
+    AuthorizedSubCommand::with_name("get").about("View the runtime settings stored for this node")
+}
+
+fn settings_set<'a, 'b>() -> App<'a, 'b> {
+    AuthorizedSubCommand::with_name("set")
+        .about("Overwrite the runtime settings of this node; settings which are left out fall back to the node's configuration")
+        .args(&[
+            Arg::with_name("route_broadcast_interval")
+                .long("route-broadcast-interval")
+                .takes_value(true)
+                .help("Interval, in milliseconds, on which routes are broadcast to peers"),
+            Arg::with_name("exchange_rate_poll_interval")
+                .long("exchange-rate-poll-interval")
+                .takes_value(true)
+                .help("Interval, in milliseconds, on which the exchange rate provider is polled"),
+            Arg::with_name("max_packet_amount")
+                .long("max-packet-amount")
+                .takes_value(true)
+                .help("The max amount per packet which is forwarded for any account"),
+            Arg::with_name("max_packet_size")
+                .long("max-packet-size")
+                .takes_value(true)
+                .help("The max size, in bytes, of the packets which are accepted from any account"),
+        ])
+}
+
+fn peering<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("peering")
+        .about("Request to peer with other nodes, and answer their requests")
+}
+
+fn peering_approve<'a, 'b>() -> App<'a, 'b> {
+    AuthorizedSubCommand::with_name("approve")
+        .about("Approve a peering request: create the requester's account and send it the parameters of this node's account")
+        .arg(
+            Arg::with_name("id")
+                .index(1)
+                .takes_value(true)
+                .required(true)
+                .help("The id of the peering request to approve"),
+        )
+}
+
+fn peering_list<'a, 'b>() -> App<'a, 'b> {
+    AuthorizedSubCommand::with_name("list")
+        .about("List the peering requests received from other nodes which await approval")
+}
+
+fn peering_list_sent<'a, 'b>() -> App<'a, 'b> {
+    AuthorizedSubCommand::with_name("list-sent")
+        .about("List the peering requests sent to other nodes which were not answered yet")
+}
+
+fn peering_reject<'a, 'b>() -> App<'a, 'b> {
+    AuthorizedSubCommand::with_name("reject")
+        .about("Reject a peering request")
+        .arg(
+            Arg::with_name("id")
+                .index(1)
+                .takes_value(true)
+                .required(true)
+                .help("The id of the peering request to reject"),
+        )
+}
+
+fn peering_request<'a, 'b>() -> App<'a, 'b> {
+    AuthorizedSubCommand::with_name("request")
+        .about("Send a peering request to another node; the peer's account is created once its admin approves it")
+        .args(&[
+            Arg::with_name("peer_url")
+                .index(1)
+                .takes_value(true)
+                .required(true)
+                .help("The base URL of the API of the node to peer with"),
+            Arg::with_name("username")
+                .long("username")
+                .takes_value(true)
+                .required(true)
+                .help("The username of the account created on this node for the peer"),
+            Arg::with_name("peer_username")
+                .long("peer-username")
+                .takes_value(true)
+                .help("The username proposed for this node's account on the peer (defaults to the last segment of this node's ILP address)"),
+            Arg::with_name("asset_code")
+                .long("asset-code")
+                .takes_value(true)
+                .required(true)
+                .help("The code of the asset of the accounts"),
+            Arg::with_name("asset_scale")
+                .long("asset-scale")
+                .takes_value(true)
+                .required(true)
+                .help("The scale of the asset of the accounts"),
+        ])
+}
+
 fn logs<'a, 'b>() -> App<'a, 'b> {
     AuthorizedSubCommand::with_name("logs")
         .about("Modify the logging level of the server")
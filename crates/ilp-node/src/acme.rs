@@ -0,0 +1,1157 @@
+use crate::shutdown::{bind_listener, Shutdown};
+use crate::tls::{load_certs, load_private_key, CertificateResolver};
+use reqwest::{
+    header::{CONTENT_TYPE, LOCATION},
+    Client, Response,
+};
+use ring::{
+    digest::{digest, SHA256},
+    rand::SystemRandom,
+    signature::{
+        EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_ASN1_SIGNING, ECDSA_P256_SHA256_FIXED_SIGNING,
+    },
+};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    fs, io,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    str,
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::time::delay_for;
+use tracing::{debug, error, info, warn};
+use warp::{http::StatusCode, Filter};
+
+/// How often the node checks whether the certificate is due for renewal
+const RENEWAL_CHECK_INTERVAL: Duration = Duration::from_secs(12 * 60 * 60);
+/// Bounds of the delay before the next attempt to issue a certificate after
+/// a failure, which doubles after each consecutive failure
+const MIN_RETRY_DELAY: Duration = Duration::from_secs(60);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60 * 60);
+/// How often and how many times an order or an authorization is fetched while it is pending
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+const POLL_ATTEMPTS: usize = 30;
+
+const ACCOUNT_KEY_FILE: &str = "account-key.pem";
+const CERT_FILE: &str = "cert.pem";
+const KEY_FILE: &str = "key.pem";
+
+/// Configuration of the certificate which is issued and renewed with ACME (RFC 8555).
+/// The certificate authority validates that the node controls the domains with
+/// http-01 challenges, so the node must be reachable on port 80 of each domain.
+/// By configuring it, the terms of service of the certificate authority are agreed to.
+#[derive(Deserialize, Clone, Debug)]
+pub struct AcmeConfig {
+    /// Domains the certificate is issued for
+    #[serde(deserialize_with = "crate::node::deserialize_string_list")]
+    pub domains: Vec<String>,
+    /// Email address the certificate authority may contact about the certificate
+    pub contact_email: Option<String>,
+    /// URL of the directory of the ACME server.
+    /// Defaults to the production environment of Let's Encrypt.
+    #[serde(default = "AcmeConfig::default_directory_url")]
+    pub directory_url: String,
+    /// Directory in which the account key, the certificate and its key are kept, so
+    /// that the certificate is not issued again each time the node starts. Defaults to `acme`.
+    #[serde(default = "AcmeConfig::default_cache_dir")]
+    pub cache_dir: String,
+    /// IP address and port on which the http-01 challenges are answered.
+    /// Defaults to 0.0.0.0:80.
+    #[serde(default = "AcmeConfig::default_challenge_bind_address")]
+    pub challenge_bind_address: SocketAddr,
+    /// Time, in seconds, before the certificate expires at which it is renewed.
+    /// Defaults to 2592000 (30 days).
+    #[serde(default = "AcmeConfig::default_renew_before")]
+    pub renew_before: u64,
+}
+
+impl AcmeConfig {
+    fn default_directory_url() -> String {
+        "https://acme-v02.api.letsencrypt.org/directory".to_string()
+    }
+
+    fn default_cache_dir() -> String {
+        "acme".to_string()
+    }
+
+    fn default_challenge_bind_address() -> SocketAddr {
+        SocketAddr::from(([0, 0, 0, 0], 80))
+    }
+
+    fn default_renew_before() -> u64 {
+        30 * 24 * 60 * 60
+    }
+}
+
+/// Key authorizations of the pending http-01 challenges, by token
+type Challenges = Arc<RwLock<HashMap<String, String>>>;
+
+/// Installs the cached certificate in the resolver, starts answering the http-01
+/// challenges and spawns the task which issues the certificate and renews it
+/// before it expires. Both stop once the shutdown starts.
+pub(crate) fn spawn_certificate_manager(
+    config: AcmeConfig,
+    resolver: CertificateResolver,
+    shutdown: &Shutdown,
+    reuse_port: bool,
+) -> io::Result<()> {
+    fs::create_dir_all(&config.cache_dir)?;
+    let cache_dir = PathBuf::from(&config.cache_dir);
+    let mut expires_at = match load_cached_certificate(&cache_dir, &resolver) {
+        Ok(expires_at) => {
+            info!(target: "interledger-node", "Loaded the cached certificate for {}", config.domains.join(", "));
+            Some(expires_at)
+        }
+        Err(err) => {
+            debug!(target: "interledger-node", "No cached certificate: {}", err);
+            None
+        }
+    };
+
+    let challenges = Challenges::default();
+    let tokens = challenges.clone();
+    let challenge_api = warp::get()
+        .and(warp::path!(".well-known" / "acme-challenge" / String))
+        .map(
+            move |token: String| match tokens.read().unwrap().get(&token) {
+                Some(key_authorization) => {
+                    warp::reply::with_status(key_authorization.clone(), StatusCode::OK)
+                }
+                None => warp::reply::with_status(String::new(), StatusCode::NOT_FOUND),
+            },
+        );
+    let listener = bind_listener(config.challenge_bind_address, reuse_port)?;
+    info!(target: "interledger-node", "Answering the ACME challenges on: {}", config.challenge_bind_address);
+    shutdown.spawn_server(
+        warp::serve(challenge_api)
+            .serve_incoming_with_graceful_shutdown(listener, shutdown.signal()),
+    );
+
+    let mut stopped = Box::pin(shutdown.signal());
+    tokio::spawn(async move {
+        let renew_before = Duration::from_secs(config.renew_before);
+        let mut client = None;
+        let mut retry_delay = MIN_RETRY_DELAY;
+        loop {
+            let renew_in = expires_at
+                .and_then(|expires_at: SystemTime| expires_at.checked_sub(renew_before))
+                .and_then(|renew_at| renew_at.duration_since(SystemTime::now()).ok());
+            let delay = match renew_in {
+                // Checking again regularly guards against changes of the system clock
+                Some(renew_in) => renew_in.min(RENEWAL_CHECK_INTERVAL),
+                None => match issue_certificate(&config, &mut client, &challenges).await {
+                    Ok((certs, key)) => {
+                        match save_certificate(&cache_dir, &certs, &key)
+                            .and_then(|_| load_cached_certificate(&cache_dir, &resolver))
+                        {
+                            Ok(new_expires_at) => {
+                                info!(target: "interledger-node", "Issued the certificate for {}", config.domains.join(", "));
+                                expires_at = Some(new_expires_at);
+                                retry_delay = MIN_RETRY_DELAY;
+                                // Even if the certificate is due for renewal already (because
+                                // it is valid for less than renew_before), it is not issued
+                                // again before the next check
+                                RENEWAL_CHECK_INTERVAL
+                            }
+                            Err(err) => {
+                                error!(target: "interledger-node", "Error installing the issued certificate: {}", err);
+                                retry_delay
+                            }
+                        }
+                    }
+                    Err(err) => {
+                        warn!(target: "interledger-node", "Error issuing the certificate for {}, retrying in {}s: {}", config.domains.join(", "), retry_delay.as_secs(), err);
+                        // A new client fetches the directory and a nonce again
+                        client = None;
+                        let delay = retry_delay;
+                        retry_delay = (retry_delay * 2).min(MAX_RETRY_DELAY);
+                        delay
+                    }
+                },
+            };
+            tokio::select! {
+                _ = delay_for(delay) => {},
+                _ = &mut stopped => return,
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Installs the certificate of the cache in the resolver and returns when it expires
+fn load_cached_certificate(
+    cache_dir: &Path,
+    resolver: &CertificateResolver,
+) -> io::Result<SystemTime> {
+    let certs = load_certs(&cache_dir.join(CERT_FILE).to_string_lossy())?;
+    let key = load_private_key(&cache_dir.join(KEY_FILE).to_string_lossy())?;
+    let expires_at = not_after(&certs[0].0).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Unable to read when the certificate expires",
+        )
+    })?;
+    resolver.set(certs, &key)?;
+    Ok(expires_at)
+}
+
+/// Writes the certificate chain and its key to the cache. Each file is written
+/// under a temporary name first, so that it is either replaced or left as it was.
+fn save_certificate(cache_dir: &Path, certs: &str, key: &[u8]) -> io::Result<()> {
+    write_private(
+        &cache_dir.join(KEY_FILE),
+        pem("PRIVATE KEY", key).as_bytes(),
+    )?;
+    write_private(&cache_dir.join(CERT_FILE), certs.as_bytes())
+}
+
+fn write_private(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let temporary = path.with_extension("tmp");
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    io::Write::write_all(&mut options.open(&temporary)?, contents)?;
+    fs::rename(temporary, path)
+}
+
+/// Issues a certificate for the configured domains, and returns its
+/// chain (as PEM) and its key (as PKCS#8)
+async fn issue_certificate(
+    config: &AcmeConfig,
+    client: &mut Option<AcmeClient>,
+    challenges: &Challenges,
+) -> Result<(String, Vec<u8>), String> {
+    if client.is_none() {
+        let account_key = account_key(Path::new(&config.cache_dir))
+            .map_err(|err| format!("Error loading the account key: {}", err))?;
+        let mut new_client = AcmeClient::connect(&config.directory_url, account_key).await?;
+        new_client.register(config.contact_email.as_deref()).await?;
+        *client = Some(new_client);
+    }
+    let client = client.as_mut().unwrap();
+
+    let (order_url, order) = client.new_order(&config.domains).await?;
+    for authorization in order.authorizations.iter() {
+        client.authorize(authorization, challenges).await?;
+    }
+
+    let (csr, key) = certificate_request(&config.domains, &client.rng)?;
+    client
+        .post(&order.finalize, Some(json!({ "csr": base64url(&csr) })))
+        .await?;
+    let order: Order = client.poll(&order_url).await?;
+    let certificate_url = match (order.status.as_str(), order.certificate) {
+        ("valid", Some(url)) => url,
+        (status, _) => return Err(format!("The order is {} instead of valid", status)),
+    };
+    let certs = client
+        .post(&certificate_url, None)
+        .await?
+        .text()
+        .await
+        .map_err(|err| format!("Error downloading the certificate: {}", err))?;
+    Ok((certs, key))
+}
+
+/// Loads the key of the ACME account from the cache, or generates it
+fn account_key(cache_dir: &Path) -> io::Result<Vec<u8>> {
+    let path = cache_dir.join(ACCOUNT_KEY_FILE);
+    if path.exists() {
+        return load_private_key(&path.to_string_lossy()).map(|key| key.0);
+    }
+    let key = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &SystemRandom::new())
+        .map_err(|_| io::Error::new(io::ErrorKind::Other, "Unable to generate a key"))?;
+    write_private(&path, pem("PRIVATE KEY", key.as_ref()).as_bytes())?;
+    Ok(key.as_ref().to_vec())
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Directory {
+    new_nonce: String,
+    new_account: String,
+    new_order: String,
+}
+
+#[derive(Deserialize)]
+struct Order {
+    status: String,
+    #[serde(default)]
+    authorizations: Vec<String>,
+    finalize: String,
+    certificate: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Authorization {
+    status: String,
+    identifier: Identifier,
+    challenges: Vec<Challenge>,
+}
+
+#[derive(Deserialize)]
+struct Identifier {
+    value: String,
+}
+
+#[derive(Deserialize)]
+struct Challenge {
+    #[serde(rename = "type")]
+    kind: String,
+    url: String,
+    token: String,
+}
+
+/// Client of an ACME server, which signs its requests with the key of the account
+struct AcmeClient {
+    client: Client,
+    directory: Directory,
+    key: EcdsaKeyPair,
+    rng: SystemRandom,
+    /// The nonce returned by the last response, which the next request uses
+    nonce: Option<String>,
+    account_url: Option<String>,
+}
+
+impl AcmeClient {
+    async fn connect(directory_url: &str, account_key: Vec<u8>) -> Result<Self, String> {
+        let key = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &account_key)
+            .map_err(|err| format!("The account key is not a P-256 key: {}", err))?;
+        let client = Client::new();
+        let directory = client
+            .get(directory_url)
+            .send()
+            .await
+            .and_then(Response::error_for_status)
+            .map_err(|err| format!("Error fetching the ACME directory: {}", err))?
+            .json()
+            .await
+            .map_err(|err| format!("Invalid ACME directory: {}", err))?;
+        Ok(AcmeClient {
+            client,
+            directory,
+            key,
+            rng: SystemRandom::new(),
+            nonce: None,
+            account_url: None,
+        })
+    }
+
+    /// Creates the account of the key, or looks it up if it already exists
+    async fn register(&mut self, contact_email: Option<&str>) -> Result<(), String> {
+        let mut account = json!({ "termsOfServiceAgreed": true });
+        if let Some(email) = contact_email {
+            account["contact"] = json!([format!("mailto:{}", email)]);
+        }
+        let url = self.directory.new_account.clone();
+        let response = self.post(&url, Some(account)).await?;
+        self.account_url = Some(location(&response)?);
+        Ok(())
+    }
+
+    async fn new_order(&mut self, domains: &[String]) -> Result<(String, Order), String> {
+        let identifiers: Vec<Value> = domains
+            .iter()
+            .map(|domain| json!({ "type": "dns", "value": domain }))
+            .collect();
+        let url = self.directory.new_order.clone();
+        let response = self
+            .post(&url, Some(json!({ "identifiers": identifiers })))
+            .await?;
+        let order_url = location(&response)?;
+        let order = response
+            .json()
+            .await
+            .map_err(|err| format!("Invalid order: {}", err))?;
+        Ok((order_url, order))
+    }
+
+    /// Answers the http-01 challenge of the authorization, unless it is already valid
+    async fn authorize(&mut self, url: &str, challenges: &Challenges) -> Result<(), String> {
+        let authorization: Authorization = self.fetch(url).await?;
+        if authorization.status == "valid" {
+            return Ok(());
+        }
+        let domain = authorization.identifier.value;
+        let challenge = authorization
+            .challenges
+            .into_iter()
+            .find(|challenge| challenge.kind == "http-01")
+            .ok_or_else(|| format!("No http-01 challenge was offered for {}", domain))?;
+
+        let key_authorization = format!("{}.{}", challenge.token, self.thumbprint());
+        challenges
+            .write()
+            .unwrap()
+            .insert(challenge.token.clone(), key_authorization);
+        let result = match self.post(&challenge.url, Some(json!({}))).await {
+            Ok(_) => self.poll::<Authorization>(url).await,
+            Err(err) => Err(err),
+        };
+        challenges.write().unwrap().remove(&challenge.token);
+
+        match result?.status.as_str() {
+            "valid" => Ok(()),
+            status => Err(format!("The authorization of {} is {}", domain, status)),
+        }
+    }
+
+    /// Fetches the order or the authorization until it is no longer pending
+    async fn poll<T: DeserializeOwned>(&mut self, url: &str) -> Result<T, String> {
+        for _ in 0..POLL_ATTEMPTS {
+            let object: Value = self.fetch(url).await?;
+            match object["status"].as_str() {
+                Some("pending") | Some("processing") => delay_for(POLL_INTERVAL).await,
+                _ => {
+                    return serde_json::from_value(object)
+                        .map_err(|err| format!("Invalid response from {}: {}", url, err))
+                }
+            }
+        }
+        Err(format!("{} is still pending", url))
+    }
+
+    async fn fetch<T: DeserializeOwned>(&mut self, url: &str) -> Result<T, String> {
+        self.post(url, None)
+            .await?
+            .json()
+            .await
+            .map_err(|err| format!("Invalid response from {}: {}", url, err))
+    }
+
+    /// Sends a request signed with the account key (with an empty payload, it is a
+    /// POST-as-GET request). It is retried once if the server rejects the nonce.
+    async fn post(&mut self, url: &str, payload: Option<Value>) -> Result<Response, String> {
+        let payload = payload
+            .map(|payload| base64url(payload.to_string().as_bytes()))
+            .unwrap_or_default();
+        let mut retried = false;
+        loop {
+            let nonce = match self.nonce.take() {
+                Some(nonce) => nonce,
+                None => self.new_nonce().await?,
+            };
+            let body = self.sign(url, &nonce, &payload)?;
+
+            let response = self
+                .client
+                .post(url)
+                .header(CONTENT_TYPE, "application/jose+json")
+                .body(body.to_string())
+                .send()
+                .await
+                .map_err(|err| format!("Error sending the request to {}: {}", url, err))?;
+            self.nonce = response
+                .headers()
+                .get("Replay-Nonce")
+                .and_then(|nonce| nonce.to_str().ok())
+                .map(String::from);
+            if response.status().is_success() {
+                return Ok(response);
+            }
+
+            let status = response.status();
+            let problem: Value = response.json().await.unwrap_or_default();
+            if problem["type"] == "urn:ietf:params:acme:error:badNonce" && !retried {
+                retried = true;
+                continue;
+            }
+            return Err(format!(
+                "{} from {}: {}",
+                status,
+                url,
+                problem["detail"].as_str().unwrap_or_default()
+            ));
+        }
+    }
+
+    /// Returns the JWS (in the flattened JSON serialization) of the base64url-encoded
+    /// payload, which identifies the account by its URL once it is registered
+    fn sign(&self, url: &str, nonce: &str, payload: &str) -> Result<Value, String> {
+        let mut protected = json!({ "alg": "ES256", "nonce": nonce, "url": url });
+        match self.account_url {
+            Some(ref account_url) => protected["kid"] = json!(account_url),
+            None => protected["jwk"] = self.jwk(),
+        }
+        let protected = base64url(protected.to_string().as_bytes());
+        let signature = self
+            .key
+            .sign(&self.rng, format!("{}.{}", protected, payload).as_bytes())
+            .map_err(|_| "Error signing the request".to_string())?;
+        Ok(json!({
+            "protected": protected,
+            "payload": payload,
+            "signature": base64url(signature.as_ref()),
+        }))
+    }
+
+    async fn new_nonce(&self) -> Result<String, String> {
+        self.client
+            .head(&self.directory.new_nonce)
+            .send()
+            .await
+            .map_err(|err| format!("Error fetching a nonce: {}", err))?
+            .headers()
+            .get("Replay-Nonce")
+            .and_then(|nonce| nonce.to_str().ok())
+            .map(String::from)
+            .ok_or_else(|| "The ACME server did not return a nonce".to_string())
+    }
+
+    /// The public key of the account as a JWK, with its members in lexicographic
+    /// order (as the thumbprint requires)
+    fn jwk(&self) -> Value {
+        // The public key is an uncompressed point: 0x04, then the x and y coordinates
+        let point = self.key.public_key().as_ref();
+        json!({
+            "crv": "P-256",
+            "kty": "EC",
+            "x": base64url(&point[1..33]),
+            "y": base64url(&point[33..]),
+        })
+    }
+
+    /// JWK thumbprint (RFC 7638) of the account key
+    fn thumbprint(&self) -> String {
+        jwk_thumbprint(&self.jwk())
+    }
+}
+
+/// JWK thumbprint (RFC 7638) of the key, whose required members must be the only ones.
+/// The members of JSON objects are serialized in lexicographic order, without whitespace.
+fn jwk_thumbprint(jwk: &Value) -> String {
+    base64url(digest(&SHA256, jwk.to_string().as_bytes()).as_ref())
+}
+
+fn location(response: &Response) -> Result<String, String> {
+    response
+        .headers()
+        .get(LOCATION)
+        .and_then(|location| location.to_str().ok())
+        .map(String::from)
+        .ok_or_else(|| format!("No location returned by {}", response.url()))
+}
+
+fn base64url(bytes: &[u8]) -> String {
+    base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+}
+
+fn pem(label: &str, der: &[u8]) -> String {
+    let encoded = base64::encode(der);
+    let mut pem = format!("-----BEGIN {}-----\n", label);
+    for line in encoded.as_bytes().chunks(64) {
+        pem.push_str(str::from_utf8(line).unwrap());
+        pem.push('\n');
+    }
+    pem.push_str(&format!("-----END {}-----\n", label));
+    pem
+}
+
+// DER encodings of the object identifiers used in the certificate signing request
+const OID_COMMON_NAME: &[u8] = &[0x06, 0x03, 0x55, 0x04, 0x03];
+const OID_EC_PUBLIC_KEY: &[u8] = &[0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01];
+const OID_PRIME256V1: &[u8] = &[0x06, 0x08, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07];
+const OID_ECDSA_WITH_SHA256: &[u8] = &[0x06, 0x08, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x02];
+const OID_EXTENSION_REQUEST: &[u8] = &[
+    0x06, 0x09, 0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x09, 0x0e,
+];
+const OID_SUBJECT_ALT_NAME: &[u8] = &[0x06, 0x03, 0x55, 0x1d, 0x11];
+
+const INTEGER: u8 = 0x02;
+const BIT_STRING: u8 = 0x03;
+const OCTET_STRING: u8 = 0x04;
+const UTF8_STRING: u8 = 0x0c;
+const UTC_TIME: u8 = 0x17;
+const GENERALIZED_TIME: u8 = 0x18;
+const SEQUENCE: u8 = 0x30;
+const SET: u8 = 0x31;
+const CONTEXT_0: u8 = 0xa0;
+const DNS_NAME: u8 = 0x82;
+
+/// Generates a P-256 key, and returns a PKCS#10 certificate signing request for the
+/// domains signed with it, and the key (as PKCS#8)
+fn certificate_request(
+    domains: &[String],
+    rng: &SystemRandom,
+) -> Result<(Vec<u8>, Vec<u8>), String> {
+    let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, rng)
+        .map_err(|_| "Unable to generate the key of the certificate".to_string())?;
+    let key = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref())
+        .map_err(|err| format!("Invalid key of the certificate: {}", err))?;
+
+    // The common name is limited to 64 characters, the domains are in the
+    // subject alternative names anyway
+    let subject = match domains.first() {
+        Some(domain) if domain.len() <= 64 => der(
+            SEQUENCE,
+            &[&der(
+                SET,
+                &[&der(
+                    SEQUENCE,
+                    &[OID_COMMON_NAME, &der(UTF8_STRING, &[domain.as_bytes()])],
+                )],
+            )],
+        ),
+        _ => der(SEQUENCE, &[]),
+    };
+    let public_key = der(
+        SEQUENCE,
+        &[
+            &der(SEQUENCE, &[OID_EC_PUBLIC_KEY, OID_PRIME256V1]),
+            &der(BIT_STRING, &[&[0], key.public_key().as_ref()]),
+        ],
+    );
+    let names: Vec<Vec<u8>> = domains
+        .iter()
+        .map(|domain| der(DNS_NAME, &[domain.as_bytes()]))
+        .collect();
+    let names: Vec<&[u8]> = names.iter().map(Vec::as_slice).collect();
+    let extensions = der(
+        SEQUENCE,
+        &[&der(
+            SEQUENCE,
+            &[
+                OID_SUBJECT_ALT_NAME,
+                &der(OCTET_STRING, &[&der(SEQUENCE, &names)]),
+            ],
+        )],
+    );
+    let attributes = der(
+        CONTEXT_0,
+        &[&der(
+            SEQUENCE,
+            &[OID_EXTENSION_REQUEST, &der(SET, &[&extensions])],
+        )],
+    );
+    let info = der(
+        SEQUENCE,
+        &[&der(INTEGER, &[&[0]]), &subject, &public_key, &attributes],
+    );
+
+    let signature = key
+        .sign(rng, &info)
+        .map_err(|_| "Error signing the certificate signing request".to_string())?;
+    let csr = der(
+        SEQUENCE,
+        &[
+            &info,
+            &der(SEQUENCE, &[OID_ECDSA_WITH_SHA256]),
+            &der(BIT_STRING, &[&[0], signature.as_ref()]),
+        ],
+    );
+    Ok((csr, pkcs8.as_ref().to_vec()))
+}
+
+/// Encodes a DER element with the concatenated contents
+fn der(tag: u8, contents: &[&[u8]]) -> Vec<u8> {
+    let contents = contents.concat();
+    let mut element = vec![tag];
+    if contents.len() < 0x80 {
+        element.push(contents.len() as u8);
+    } else {
+        let len = contents.len().to_be_bytes();
+        let len = &len[len.iter().take_while(|byte| **byte == 0).count()..];
+        element.push(0x80 | len.len() as u8);
+        element.extend_from_slice(len);
+    }
+    element.extend_from_slice(&contents);
+    element
+}
+
+/// Splits the DER element at the start of the input into its tag, its contents
+/// and the rest of the input
+fn der_element(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = input.split_first()?;
+    let (&len, rest) = rest.split_first()?;
+    let (len, rest) = if len < 0x80 {
+        (len as usize, rest)
+    } else {
+        let octets = (len & 0x7f) as usize;
+        if octets == 0 || octets > 4 || rest.len() < octets {
+            return None;
+        }
+        let (len, rest) = rest.split_at(octets);
+        let len = len
+            .iter()
+            .fold(0usize, |len, byte| (len << 8) | *byte as usize);
+        (len, rest)
+    };
+    if rest.len() < len {
+        return None;
+    }
+    let (contents, rest) = rest.split_at(len);
+    Some((tag, contents, rest))
+}
+
+/// Reads the end of the validity period of a DER-encoded X.509 certificate
+fn not_after(certificate: &[u8]) -> Option<SystemTime> {
+    let (_, certificate, _) = der_element(certificate)?;
+    let (_, mut tbs_certificate, _) = der_element(certificate)?;
+    // Skip the version (which is optional), the serial number,
+    // the signature algorithm and the issuer
+    if tbs_certificate.first() == Some(&CONTEXT_0) {
+        tbs_certificate = der_element(tbs_certificate)?.2;
+    }
+    for _ in 0..3 {
+        tbs_certificate = der_element(tbs_certificate)?.2;
+    }
+    let (_, validity, _) = der_element(tbs_certificate)?;
+    let (_, _, validity) = der_element(validity)?;
+    let (tag, time, _) = der_element(validity)?;
+
+    let time = str::from_utf8(time).ok()?;
+    let (year, time) = match tag {
+        UTC_TIME => {
+            let year: u64 = time.get(..2)?.parse().ok()?;
+            (
+                if year < 50 { 2000 + year } else { 1900 + year },
+                &time[2..],
+            )
+        }
+        GENERALIZED_TIME => (time.get(..4)?.parse().ok()?, &time[4..]),
+        _ => return None,
+    };
+    let field = |index: usize| -> Option<u64> { time.get(index * 2..index * 2 + 2)?.parse().ok() };
+    let days = days_since_epoch(year, field(0)?, field(1)?);
+    let seconds = days * 86_400 + field(2)? * 3600 + field(3)? * 60 + field(4)?;
+    Some(UNIX_EPOCH + Duration::from_secs(seconds))
+}
+
+/// Days from 1970-01-01 to the date (from 1970 on) in the Gregorian calendar
+fn days_since_epoch(year: u64, month: u64, day: u64) -> u64 {
+    // Counting from March makes the leap day the last day of the year
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes05::Bytes;
+    use ring::signature::{UnparsedPublicKey, ECDSA_P256_SHA256_ASN1, ECDSA_P256_SHA256_FIXED};
+    use std::collections::HashSet;
+    use std::sync::Mutex;
+    use tokio::net::TcpListener;
+    use warp::{filters::path::FullPath, http::Method};
+
+    const NODE_CERT: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/settlement-tls/node.pem"
+    );
+
+    fn decode(encoded: &str) -> Vec<u8> {
+        base64::decode_config(encoded, base64::URL_SAFE_NO_PAD).unwrap()
+    }
+
+    fn test_client(account_url: Option<&str>) -> AcmeClient {
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+        AcmeClient {
+            client: Client::new(),
+            directory: Directory {
+                new_nonce: "https://ca.example/new-nonce".to_string(),
+                new_account: "https://ca.example/new-account".to_string(),
+                new_order: "https://ca.example/new-order".to_string(),
+            },
+            key: EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref())
+                .unwrap(),
+            rng,
+            nonce: None,
+            account_url: account_url.map(String::from),
+        }
+    }
+
+    /// Returns the public key of the JWK, as an uncompressed point
+    fn jwk_public_key(jwk: &Value) -> Vec<u8> {
+        let mut point = vec![4];
+        point.extend(decode(jwk["x"].as_str().unwrap()));
+        point.extend(decode(jwk["y"].as_str().unwrap()));
+        point
+    }
+
+    /// Returns the protected header and the payload of the JWS, if it is signed by the key
+    fn verify_jws(jws: &Value, public_key: &[u8]) -> Option<(Value, Vec<u8>)> {
+        let protected = jws["protected"].as_str()?;
+        let payload = jws["payload"].as_str()?;
+        let signature = decode(jws["signature"].as_str()?);
+        UnparsedPublicKey::new(&ECDSA_P256_SHA256_FIXED, public_key)
+            .verify(format!("{}.{}", protected, payload).as_bytes(), &signature)
+            .ok()?;
+        let protected = serde_json::from_slice(&decode(protected)).ok()?;
+        Some((protected, decode(payload)))
+    }
+
+    #[test]
+    fn computes_jwk_thumbprints() {
+        // The example of RFC 7638, section 3.1
+        let jwk = json!({
+            "e": "AQAB",
+            "kty": "RSA",
+            "n": "0vx7agoebGcQSuuPiLJXZptN9nndrQmbXEps2aiAFbWhM78LhWx4cbbfAAtVT86zwu1RK7aPFFxuhDR1L6tSoc_BJECPebWKRXjBZCiFV4n3oknjhMstn64tZ_2W-5JsGY4Hc5n9yBXArwl93lqt7_RN5w6Cf0h4QyQ5v-65YGjQR0_FDW2QvzqY368QQMicAtaSqzs8KJZgnYb9c7d0zgdAZHzu6qMQvRL5hajrn1n91CbOpbISD08qNLyrdkt-bFTWhAI4vMQFh6WeZu0fM4lFd2NcRwr3XPksINHaQ-G_xBniIqbw0Ls1jF44-csFCur-kEgU8awapJzKnqDKgw",
+        });
+        assert_eq!(
+            jwk_thumbprint(&jwk),
+            "NzbLsXh8uDCcd-6MNwXF4W_7noWXFZAfHkxZsRGC9Xs"
+        );
+    }
+
+    #[test]
+    fn signs_requests_with_the_account_key() {
+        let mut client = test_client(None);
+        let public_key = client.key.public_key().as_ref().to_vec();
+        assert_eq!(jwk_public_key(&client.jwk()), public_key);
+
+        let payload = base64url(br#"{"termsOfServiceAgreed":true}"#);
+        let jws = client
+            .sign("https://ca.example/new-account", "nonce", &payload)
+            .unwrap();
+        let (protected, signed_payload) = verify_jws(&jws, &public_key).unwrap();
+        assert_eq!(signed_payload, br#"{"termsOfServiceAgreed":true}"#);
+        assert_eq!(
+            protected,
+            json!({
+                "alg": "ES256",
+                "nonce": "nonce",
+                "url": "https://ca.example/new-account",
+                "jwk": client.jwk(),
+            })
+        );
+
+        // Once the account is registered, it is identified by its URL
+        client.account_url = Some("https://ca.example/account/1".to_string());
+        let jws = client
+            .sign("https://ca.example/new-order", "other nonce", "")
+            .unwrap();
+        let (protected, _) = verify_jws(&jws, &public_key).unwrap();
+        assert_eq!(protected["kid"], "https://ca.example/account/1");
+        assert!(protected.get("jwk").is_none());
+
+        // Signatures by other keys are told apart
+        let other = test_client(None);
+        assert!(verify_jws(&jws, other.key.public_key().as_ref()).is_none());
+    }
+
+    #[test]
+    fn encodes_and_reads_der_elements() {
+        assert_eq!(der(OCTET_STRING, &[&[1, 2], &[3]]), vec![4, 3, 1, 2, 3]);
+        for &(len, ref header) in &[
+            (127, vec![4, 127]),
+            (200, vec![4, 0x81, 200]),
+            (300, vec![4, 0x82, 1, 44]),
+        ] {
+            let contents = vec![7; len];
+            let element = der(OCTET_STRING, &[&contents]);
+            assert_eq!(&element[..header.len()], header.as_slice());
+            let mut input = element.clone();
+            input.push(0xff);
+            assert_eq!(
+                der_element(&input),
+                Some((OCTET_STRING, contents.as_slice(), &[0xff][..]))
+            );
+        }
+
+        // Truncated elements and indefinite lengths are not read
+        assert_eq!(der_element(&[4, 3, 1, 2]), None);
+        assert_eq!(der_element(&[4, 0x82, 1]), None);
+        assert_eq!(der_element(&[4, 0x80]), None);
+    }
+
+    #[test]
+    fn reads_when_certificates_expire() {
+        assert_eq!(days_since_epoch(1970, 1, 1), 0);
+        assert_eq!(days_since_epoch(2000, 3, 1), 11017);
+        assert_eq!(days_since_epoch(2024, 2, 29), 19782);
+
+        // Valid until 2126-09-21T23:34:08Z, which is encoded as a GeneralizedTime
+        let certs = load_certs(NODE_CERT).unwrap();
+        assert_eq!(
+            not_after(&certs[0].0),
+            Some(UNIX_EPOCH + Duration::from_secs(4_945_707_248))
+        );
+        assert_eq!(not_after(&certs[0].0[..100]), None);
+    }
+
+    #[test]
+    fn requests_certificates_signed_by_their_key() {
+        let domains = vec!["example.com".to_string(), "www.example.com".to_string()];
+        let (csr, key) = certificate_request(&domains, &SystemRandom::new()).unwrap();
+        let key = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &key).unwrap();
+
+        let (tag, request, rest) = der_element(&csr).unwrap();
+        assert_eq!((tag, rest), (SEQUENCE, &[][..]));
+        let (_, info_contents, rest) = der_element(request).unwrap();
+        let info = &request[..request.len() - rest.len()];
+        let (_, algorithm, rest) = der_element(rest).unwrap();
+        assert_eq!(algorithm, OID_ECDSA_WITH_SHA256);
+        let (tag, signature, _) = der_element(rest).unwrap();
+        assert_eq!((tag, signature[0]), (BIT_STRING, 0));
+        UnparsedPublicKey::new(&ECDSA_P256_SHA256_ASN1, key.public_key().as_ref())
+            .verify(info, &signature[1..])
+            .unwrap();
+
+        // The version, the subject (whose common name is the first domain), the public
+        // key and the extension request with the subject alternative names
+        let (tag, version, rest) = der_element(info_contents).unwrap();
+        assert_eq!((tag, version), (INTEGER, &[0][..]));
+        let (_, subject, rest) = der_element(rest).unwrap();
+        assert!(subject.ends_with(b"example.com"));
+        let (_, public_key, rest) = der_element(rest).unwrap();
+        let (_, _, public_key) = der_element(public_key).unwrap();
+        let (_, public_key, _) = der_element(public_key).unwrap();
+        assert_eq!(&public_key[1..], key.public_key().as_ref());
+        let (tag, attributes, rest) = der_element(rest).unwrap();
+        assert_eq!((tag, rest), (CONTEXT_0, &[][..]));
+        for domain in &domains {
+            let name = der(DNS_NAME, &[domain.as_bytes()]);
+            assert!(attributes.windows(name.len()).any(|window| window == name));
+        }
+    }
+
+    /// State of a mocked ACME server, which checks the requests like a certificate
+    /// authority would and issues the certificate of the fixtures
+    struct MockServer {
+        base_url: String,
+        challenges: Challenges,
+        nonces: HashSet<String>,
+        next_nonce: u64,
+        account_key: Option<Vec<u8>>,
+        bad_nonce_sent: bool,
+        challenge_answered: bool,
+        csr: Option<Vec<u8>>,
+    }
+
+    impl MockServer {
+        fn nonce(&mut self) -> String {
+            self.next_nonce += 1;
+            let nonce = format!("nonce-{}", self.next_nonce);
+            self.nonces.insert(nonce.clone());
+            nonce
+        }
+
+        fn url(&self, path: &str) -> String {
+            format!("{}{}", self.base_url, path)
+        }
+
+        fn handle(&mut self, method: Method, path: &str, body: &[u8]) -> (StatusCode, Value) {
+            if method == Method::GET && path == "/directory" {
+                return (
+                    StatusCode::OK,
+                    json!({
+                        "newNonce": self.url("/new-nonce"),
+                        "newAccount": self.url("/new-account"),
+                        "newOrder": self.url("/new-order"),
+                    }),
+                );
+            }
+            if method == Method::HEAD && path == "/new-nonce" {
+                return (StatusCode::OK, Value::Null);
+            }
+            match self.verify(path, body) {
+                Ok(payload) => self.respond(path, payload),
+                Err(problem) => (StatusCode::BAD_REQUEST, problem),
+            }
+        }
+
+        /// Checks the nonce, the URL and the signature of the request, and returns its payload
+        fn verify(&mut self, path: &str, body: &[u8]) -> Result<Vec<u8>, Value> {
+            let malformed = |detail: &str| json!({ "type": "urn:ietf:params:acme:error:malformed", "detail": detail });
+            let jws: Value = serde_json::from_slice(body).map_err(|_| malformed("not a JWS"))?;
+            let header: Value = jws["protected"]
+                .as_str()
+                .and_then(|protected| serde_json::from_slice(&decode(protected)).ok())
+                .ok_or_else(|| malformed("invalid protected header"))?;
+            let nonce = header["nonce"].as_str().unwrap_or_default();
+            if !self.nonces.remove(nonce) {
+                return Err(malformed("nonce reused"));
+            }
+            if header["url"] != self.url(path) {
+                return Err(malformed("wrong url"));
+            }
+            let public_key = if path == "/new-account" {
+                jwk_public_key(&header["jwk"])
+            } else if header["kid"] == self.url("/account/1") {
+                self.account_key.clone().unwrap()
+            } else {
+                return Err(malformed("unknown account"));
+            };
+            let (_, payload) =
+                verify_jws(&jws, &public_key).ok_or_else(|| malformed("invalid signature"))?;
+            // The first nonce is rejected, as if it had expired
+            if !self.bad_nonce_sent {
+                self.bad_nonce_sent = true;
+                return Err(json!({ "type": "urn:ietf:params:acme:error:badNonce" }));
+            }
+            if path == "/new-account" {
+                self.account_key = Some(public_key);
+            }
+            Ok(payload)
+        }
+
+        fn respond(&mut self, path: &str, payload: Vec<u8>) -> (StatusCode, Value) {
+            let payload: Value = serde_json::from_slice(&payload).unwrap_or_default();
+            let base_url = self.base_url.clone();
+            let order = |status: &str| {
+                json!({
+                    "status": status,
+                    "authorizations": [format!("{}/authorization/1", base_url)],
+                    "finalize": format!("{}/finalize/1", base_url),
+                })
+            };
+            match path {
+                "/new-account" => {
+                    assert_eq!(payload["termsOfServiceAgreed"], true);
+                    assert_eq!(payload["contact"], json!(["mailto:admin@example.com"]));
+                    (StatusCode::CREATED, json!({ "status": "valid" }))
+                }
+                "/new-order" => {
+                    assert_eq!(
+                        payload["identifiers"],
+                        json!([{ "type": "dns", "value": "example.com" }])
+                    );
+                    (StatusCode::CREATED, order("pending"))
+                }
+                "/authorization/1" => {
+                    let status = if self.challenge_answered {
+                        "valid"
+                    } else {
+                        "pending"
+                    };
+                    (
+                        StatusCode::OK,
+                        json!({
+                            "status": status,
+                            "identifier": { "type": "dns", "value": "example.com" },
+                            "challenges": [
+                                { "type": "dns-01", "url": self.url("/challenge/2"), "token": "dns" },
+                                { "type": "http-01", "url": self.url("/challenge/1"), "token": "token" },
+                            ],
+                        }),
+                    )
+                }
+                "/challenge/1" => {
+                    // The node serves the key authorization while the challenge is validated
+                    let thumbprint = jwk_thumbprint(&json!({
+                        "crv": "P-256",
+                        "kty": "EC",
+                        "x": base64url(&self.account_key.as_ref().unwrap()[1..33]),
+                        "y": base64url(&self.account_key.as_ref().unwrap()[33..]),
+                    }));
+                    assert_eq!(
+                        self.challenges.read().unwrap().get("token"),
+                        Some(&format!("token.{}", thumbprint))
+                    );
+                    self.challenge_answered = true;
+                    (StatusCode::OK, json!({ "status": "processing" }))
+                }
+                "/finalize/1" => {
+                    self.csr = Some(decode(payload["csr"].as_str().unwrap()));
+                    (StatusCode::OK, order("processing"))
+                }
+                "/order/1" => {
+                    let mut order = order("valid");
+                    order["certificate"] = json!(self.url("/certificate/1"));
+                    (StatusCode::OK, order)
+                }
+                "/certificate/1" => (
+                    StatusCode::OK,
+                    Value::String(fs::read_to_string(NODE_CERT).unwrap()),
+                ),
+                _ => (StatusCode::NOT_FOUND, Value::Null),
+            }
+        }
+    }
+
+    async fn serve_mock_server(challenges: Challenges) -> Arc<Mutex<MockServer>> {
+        let mut listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let server = Arc::new(Mutex::new(MockServer {
+            base_url,
+            challenges,
+            nonces: HashSet::new(),
+            next_nonce: 0,
+            account_key: None,
+            bad_nonce_sent: false,
+            challenge_answered: false,
+            csr: None,
+        }));
+        let server_clone = server.clone();
+        let routes = warp::method()
+            .and(warp::path::full())
+            .and(warp::body::bytes())
+            .map(move |method: Method, path: FullPath, body: Bytes| {
+                let mut server = server_clone.lock().unwrap();
+                let (status, body) = server.handle(method, path.as_str(), &body);
+                let mut response = warp::http::Response::builder()
+                    .status(status)
+                    .header("Replay-Nonce", server.nonce());
+                if status == StatusCode::CREATED {
+                    let location = match path.as_str() {
+                        "/new-account" => server.url("/account/1"),
+                        _ => server.url("/order/1"),
+                    };
+                    response = response.header(LOCATION, location);
+                }
+                let body = match body {
+                    Value::Null => String::new(),
+                    Value::String(text) => text,
+                    body => {
+                        response = response.header(CONTENT_TYPE, "application/json");
+                        body.to_string()
+                    }
+                };
+                response.body(body).unwrap()
+            });
+        tokio::spawn(async move {
+            warp::serve(routes)
+                .serve_incoming(listener.incoming())
+                .await
+        });
+        server
+    }
+
+    #[tokio::test]
+    async fn issues_certificates() {
+        let challenges = Challenges::default();
+        let server = serve_mock_server(challenges.clone()).await;
+        let cache_dir = std::env::temp_dir().join(format!("ilp-node-acme-{}", std::process::id()));
+        fs::create_dir_all(&cache_dir).unwrap();
+        let config = AcmeConfig {
+            domains: vec!["example.com".to_string()],
+            contact_email: Some("admin@example.com".to_string()),
+            directory_url: server.lock().unwrap().url("/directory"),
+            cache_dir: cache_dir.to_string_lossy().to_string(),
+            challenge_bind_address: AcmeConfig::default_challenge_bind_address(),
+            renew_before: AcmeConfig::default_renew_before(),
+        };
+
+        let mut client = None;
+        let result = issue_certificate(&config, &mut client, &challenges).await;
+        let account_key_saved = cache_dir.join(ACCOUNT_KEY_FILE).exists();
+        fs::remove_dir_all(&cache_dir).unwrap();
+
+        let (certs, key) = result.unwrap();
+        assert_eq!(certs, fs::read_to_string(NODE_CERT).unwrap());
+        assert!(account_key_saved);
+        // The challenge is no longer served once it was validated
+        assert!(challenges.read().unwrap().is_empty());
+        // The request was for the key which was returned
+        let key = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &key).unwrap();
+        let csr = server.lock().unwrap().csr.clone().unwrap();
+        let public_key = key.public_key().as_ref();
+        assert!(csr
+            .windows(public_key.len())
+            .any(|window| window == public_key));
+    }
+}
@@ -0,0 +1,841 @@
+use interledger::{
+    api::NodeStore,
+    packet::{Address, Reject},
+    service::{
+        Account, IlpResult, IncomingRequest, IncomingService, OutgoingRequest, OutgoingService,
+    },
+    service_util::{AccountReconciliation, BalanceStore, SettlementReconciliation},
+};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::mpsc::UnboundedReceiver;
+use tracing::{debug, warn};
+use url::Url;
+use uuid::Uuid;
+
+/// How long the node waits for the webhook to accept an alert
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Configuration for the alerts sent when the node crosses an operational threshold.
+#[derive(Deserialize, Clone)]
+pub struct AlertingConfig {
+    /// URL to which the alerts are POSTed as JSON. The message of the alert is in the
+    /// `text` field, so Slack incoming webhooks can be used as is.
+    pub webhook_url: Url,
+    /// Interval, in milliseconds, at which the rules are evaluated. The packets and
+    /// settlements are counted per interval. Defaults to 60000ms (1 minute).
+    #[serde(default = "AlertingConfig::default_evaluation_interval")]
+    pub evaluation_interval: u64,
+    /// The rules which fire an alert when their condition is met
+    #[serde(default)]
+    pub rules: Vec<AlertRule>,
+}
+
+impl AlertingConfig {
+    fn default_evaluation_interval() -> u64 {
+        60_000
+    }
+}
+
+/// A condition which is checked for each account
+#[derive(Deserialize, Clone)]
+pub struct AlertRule {
+    #[serde(flatten)]
+    pub condition: AlertCondition,
+    /// Usernames of the accounts the rule applies to. Defaults to all accounts.
+    #[serde(default)]
+    pub accounts: Vec<String>,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AlertCondition {
+    /// The balance of the account is below `min_balance` or above `max_balance`
+    Balance {
+        min_balance: Option<i64>,
+        max_balance: Option<i64>,
+    },
+    /// At least `threshold` outgoing settlements to the account failed in one interval
+    SettlementFailures {
+        #[serde(default = "default_settlement_failure_threshold")]
+        threshold: u64,
+    },
+    /// All the packets forwarded to the account for `duration` milliseconds were
+    /// rejected by this node, because it could not reach the account's peer
+    PeerUnreachable {
+        #[serde(default = "default_unreachable_duration")]
+        duration: u64,
+    },
+    /// At least the `threshold` share (between 0 and 1) of the packets received from
+    /// the account in one interval were rejected, with at least `min_packets` packets
+    RejectRate {
+        threshold: f64,
+        #[serde(default = "default_min_packets")]
+        min_packets: u64,
+    },
+    /// The totals settled with the account have differed from the totals of its settlement
+    /// engine for `duration` milliseconds, according to the settlement reconciliation
+    SettlementDiscrepancy {
+        #[serde(default = "default_discrepancy_duration")]
+        duration: u64,
+    },
+}
+
+fn default_settlement_failure_threshold() -> u64 {
+    1
+}
+
+fn default_unreachable_duration() -> u64 {
+    300_000
+}
+
+fn default_min_packets() -> u64 {
+    100
+}
+
+fn default_discrepancy_duration() -> u64 {
+    600_000
+}
+
+impl AlertCondition {
+    fn name(&self) -> &'static str {
+        match self {
+            AlertCondition::Balance { .. } => "balance",
+            AlertCondition::SettlementFailures { .. } => "settlement_failures",
+            AlertCondition::PeerUnreachable { .. } => "peer_unreachable",
+            AlertCondition::RejectRate { .. } => "reject_rate",
+            AlertCondition::SettlementDiscrepancy { .. } => "settlement_discrepancy",
+        }
+    }
+}
+
+#[derive(Default)]
+struct PacketCounts {
+    packets: u64,
+    rejects: u64,
+}
+
+/// What the node observed since the rules were last evaluated
+#[derive(Default)]
+struct Observations {
+    incoming_packets: HashMap<Uuid, PacketCounts>,
+    settlement_failures: HashMap<Uuid, u64>,
+    /// When the node started failing to reach each peer
+    unreachable_since: HashMap<Uuid, Instant>,
+    /// The accounts whose settlement totals differed at the latest reconciliation
+    settlement_discrepancies: HashMap<Uuid, AccountReconciliation>,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum AlertStatus {
+    Firing,
+    Resolved,
+}
+
+#[derive(Serialize)]
+struct AlertNotification<'a> {
+    text: String,
+    status: AlertStatus,
+    alert: &'static str,
+    account: &'a str,
+    node: String,
+    message: &'a str,
+    timestamp: u64,
+}
+
+/// An alert which started firing or was resolved when the rules were evaluated
+struct AlertChange<'a, A> {
+    condition: &'a AlertCondition,
+    account: &'a A,
+    status: AlertStatus,
+    message: String,
+}
+
+/// Records the packets, rejects and settlement failures of the accounts and
+/// notifies the webhook when an alert fires and when it is resolved
+#[derive(Clone)]
+pub struct AlertMonitor {
+    ilp_address: Address,
+    observations: Arc<Mutex<Observations>>,
+    settlement_reconciliation: SettlementReconciliation,
+}
+
+impl AlertMonitor {
+    pub fn new(ilp_address: Address, settlement_reconciliation: SettlementReconciliation) -> Self {
+        AlertMonitor {
+            ilp_address,
+            observations: Arc::new(Mutex::new(Observations::default())),
+            settlement_reconciliation,
+        }
+    }
+
+    /// Counts the packets received from the account and the ones which were rejected
+    pub fn record_incoming(&self, account_id: Uuid, result: &IlpResult) {
+        let mut observations = self.observations.lock().unwrap();
+        let counts = observations.incoming_packets.entry(account_id).or_default();
+        counts.packets += 1;
+        if result.is_err() {
+            counts.rejects += 1;
+        }
+    }
+
+    /// Tracks whether the peer of the account is reachable. The packets which this node
+    /// rejects itself after trying to forward them (because it could not connect to the
+    /// peer, or the peer did not respond in time) mean that the peer is unreachable, while
+    /// any response of the peer means it is reachable.
+    pub fn record_outgoing(&self, account_id: Uuid, result: &IlpResult) {
+        let reached = match result {
+            Ok(_) => true,
+            Err(reject) => !self.triggered_by_this_node(reject),
+        };
+        let mut observations = self.observations.lock().unwrap();
+        if reached {
+            observations.unreachable_since.remove(&account_id);
+        } else {
+            observations
+                .unreachable_since
+                .entry(account_id)
+                .or_insert_with(Instant::now);
+        }
+    }
+
+    pub fn record_settlement_failure(&self, account_id: Uuid) {
+        *self
+            .observations
+            .lock()
+            .unwrap()
+            .settlement_failures
+            .entry(account_id)
+            .or_default() += 1;
+    }
+
+    fn triggered_by_this_node(&self, reject: &Reject) -> bool {
+        reject.triggered_by().as_ref() == Some(&self.ilp_address)
+    }
+
+    /// Spawns a task which records the settlement failures sent by the `BalanceService`
+    pub fn spawn_settlement_failures(&self, mut settlement_failures: UnboundedReceiver<Uuid>) {
+        let monitor = self.clone();
+        tokio::spawn(async move {
+            while let Some(account_id) = settlement_failures.recv().await {
+                monitor.record_settlement_failure(account_id);
+            }
+        });
+    }
+
+    /// Spawns a task which evaluates the rules on the configured interval. An alert is
+    /// sent once when its condition starts being met, and a resolution once it stops.
+    pub fn spawn_alerting<S, A>(&self, store: S, config: AlertingConfig)
+    where
+        S: NodeStore<Account = A> + BalanceStore + Clone + Send + Sync + 'static,
+        A: Account + Send + Sync + 'static,
+    {
+        let monitor = self.clone();
+        tokio::spawn(async move {
+            let client = Client::new();
+            // The messages of the alerts which are firing, by rule and account
+            let mut firing: HashMap<(usize, Uuid), String> = HashMap::new();
+            let mut interval =
+                tokio::time::interval(Duration::from_millis(config.evaluation_interval));
+            // The first tick completes immediately
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                let accounts = match store.get_all_accounts().await {
+                    Ok(accounts) => accounts,
+                    Err(err) => {
+                        warn!(target: "interledger-node", "Error loading the accounts to evaluate the alerting rules: {}", err);
+                        continue;
+                    }
+                };
+                let changes = monitor
+                    .evaluate_rules(&config.rules, &accounts, &store, &mut firing)
+                    .await;
+                for change in changes {
+                    monitor
+                        .notify(
+                            &client,
+                            &config.webhook_url,
+                            change.condition,
+                            change.account,
+                            change.status,
+                            &change.message,
+                        )
+                        .await;
+                }
+            }
+        });
+    }
+
+    /// Evaluates the rules for the accounts with what the node observed since the last
+    /// evaluation. Returns the alerts which started or stopped firing, so that each alert
+    /// is only sent once while its condition is met, and resolved once it is not anymore.
+    /// `firing` holds the messages of the alerts which are firing, by rule and account.
+    async fn evaluate_rules<'a, S, A>(
+        &self,
+        rules: &'a [AlertRule],
+        accounts: &'a [A],
+        store: &S,
+        firing: &mut HashMap<(usize, Uuid), String>,
+    ) -> Vec<AlertChange<'a, A>>
+    where
+        S: BalanceStore,
+        A: Account,
+    {
+        let observations = self.take_observations();
+        let mut seen = HashSet::with_capacity(firing.len());
+        let mut changes = Vec::new();
+
+        for (index, rule) in rules.iter().enumerate() {
+            for account in accounts.iter().filter(|account| {
+                rule.accounts.is_empty() || rule.accounts.contains(&account.username().to_string())
+            }) {
+                let key = (index, account.id());
+                seen.insert(key);
+                let alert = evaluate(&rule.condition, account, store, &observations).await;
+                match (alert, firing.get(&key)) {
+                    (Some(message), None) => {
+                        firing.insert(key, message.clone());
+                        changes.push(AlertChange {
+                            condition: &rule.condition,
+                            account,
+                            status: AlertStatus::Firing,
+                            message,
+                        });
+                    }
+                    (None, Some(_)) => {
+                        let message = firing.remove(&key).unwrap_or_default();
+                        changes.push(AlertChange {
+                            condition: &rule.condition,
+                            account,
+                            status: AlertStatus::Resolved,
+                            message,
+                        });
+                    }
+                    // Already notified, or nothing to notify
+                    _ => {}
+                }
+            }
+        }
+        // Forget the alerts of the deleted accounts
+        firing.retain(|key, _| seen.contains(key));
+        changes
+    }
+
+    /// Returns the packets and settlement failures counted since the last call, when
+    /// the peers became unreachable (which is tracked across intervals) and the
+    /// discrepancies found by the latest settlement reconciliation
+    fn take_observations(&self) -> Observations {
+        let settlement_discrepancies = self
+            .settlement_reconciliation
+            .report()
+            .accounts
+            .into_iter()
+            .filter(|account| account.discrepancy_since.is_some())
+            .map(|account| (account.account_id, account))
+            .collect();
+        let mut observations = self.observations.lock().unwrap();
+        Observations {
+            incoming_packets: std::mem::take(&mut observations.incoming_packets),
+            settlement_failures: std::mem::take(&mut observations.settlement_failures),
+            unreachable_since: observations.unreachable_since.clone(),
+            settlement_discrepancies,
+        }
+    }
+
+    async fn notify<A: Account>(
+        &self,
+        client: &Client,
+        webhook_url: &Url,
+        condition: &AlertCondition,
+        account: &A,
+        status: AlertStatus,
+        message: &str,
+    ) {
+        let prefix = match status {
+            AlertStatus::Firing => "FIRING",
+            AlertStatus::Resolved => "RESOLVED",
+        };
+        let username = account.username().to_string();
+        let notification = AlertNotification {
+            text: format!("[{}] {} ({})", prefix, message, self.ilp_address),
+            status,
+            alert: condition.name(),
+            account: &username,
+            node: self.ilp_address.to_string(),
+            message,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|time| time.as_secs())
+                .unwrap_or_default(),
+        };
+        debug!(target: "interledger-node", "{}", notification.text);
+        let result = client
+            .post(webhook_url.as_str())
+            .timeout(WEBHOOK_TIMEOUT)
+            .json(&notification)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        if let Err(err) = result {
+            warn!(target: "interledger-node", "Error sending alert to the webhook: {}", err);
+        }
+    }
+}
+
+/// Returns the message of the alert if the account meets the rule's condition
+async fn evaluate<S, A>(
+    condition: &AlertCondition,
+    account: &A,
+    store: &S,
+    observations: &Observations,
+) -> Option<String>
+where
+    S: BalanceStore,
+    A: Account,
+{
+    let username = account.username();
+    match *condition {
+        AlertCondition::Balance {
+            min_balance,
+            max_balance,
+        } => {
+            let balance = match store.get_balance(account.id()).await {
+                Ok(balance) => balance,
+                Err(err) => {
+                    warn!(target: "interledger-node", "Error loading the balance of account {}: {}", username, err);
+                    return None;
+                }
+            };
+            match (min_balance, max_balance) {
+                (Some(min), _) if balance < min => Some(format!(
+                    "Balance of account {} is {}, below the limit of {}",
+                    username, balance, min
+                )),
+                (_, Some(max)) if balance > max => Some(format!(
+                    "Balance of account {} is {}, above the limit of {}",
+                    username, balance, max
+                )),
+                _ => None,
+            }
+        }
+        AlertCondition::SettlementFailures { threshold } => {
+            let failures = observations
+                .settlement_failures
+                .get(&account.id())
+                .cloned()
+                .unwrap_or_default();
+            if failures >= threshold {
+                Some(format!(
+                    "{} outgoing settlements to account {} failed",
+                    failures, username
+                ))
+            } else {
+                None
+            }
+        }
+        AlertCondition::PeerUnreachable { duration } => {
+            let since = observations.unreachable_since.get(&account.id())?;
+            if since.elapsed() >= Duration::from_millis(duration) {
+                Some(format!(
+                    "Account {} has been unreachable for {} seconds",
+                    username,
+                    since.elapsed().as_secs()
+                ))
+            } else {
+                None
+            }
+        }
+        AlertCondition::RejectRate {
+            threshold,
+            min_packets,
+        } => {
+            let counts = observations.incoming_packets.get(&account.id())?;
+            let rate = counts.rejects as f64 / counts.packets as f64;
+            if counts.packets >= min_packets && rate >= threshold {
+                Some(format!(
+                    "{} of the {} packets received from account {} were rejected",
+                    counts.rejects, counts.packets, username
+                ))
+            } else {
+                None
+            }
+        }
+        AlertCondition::SettlementDiscrepancy { duration } => {
+            let discrepancy = observations.settlement_discrepancies.get(&account.id())?;
+            let since = UNIX_EPOCH + Duration::from_millis(discrepancy.discrepancy_since?);
+            let elapsed = SystemTime::now().duration_since(since).unwrap_or_default();
+            if elapsed >= Duration::from_millis(duration) {
+                Some(format!(
+                    "Settlements of account {} differ from its engine's: the node sent {} and received {}, the engine sent {} and received {} (scale {})",
+                    username,
+                    discrepancy.node_sent,
+                    discrepancy.node_received,
+                    discrepancy.engine_sent.unwrap_or_default(),
+                    discrepancy.engine_received.unwrap_or_default(),
+                    discrepancy.asset_scale,
+                ))
+            } else {
+                None
+            }
+        }
+    }
+}
+
+/// Counts the packets received from each account for the reject rate rules
+pub fn incoming_alerts<A: Account + 'static>(
+    monitor: Option<AlertMonitor>,
+) -> impl Fn(
+    IncomingRequest<A>,
+    Box<dyn IncomingService<A> + Send>,
+) -> futures::future::BoxFuture<'static, IlpResult>
+       + Clone
+       + Send
+       + Sync {
+    move |request, mut next| {
+        let monitor = monitor.clone();
+        Box::pin(async move {
+            let account_id = request.from.id();
+            let result = next.handle_request(request).await;
+            if let Some(monitor) = monitor {
+                monitor.record_incoming(account_id, &result);
+            }
+            result
+        })
+    }
+}
+
+/// Tracks whether the peers are reachable for the peer unreachable rules
+pub fn outgoing_alerts<A: Account + 'static>(
+    monitor: Option<AlertMonitor>,
+) -> impl Fn(
+    OutgoingRequest<A>,
+    Box<dyn OutgoingService<A> + Send>,
+) -> futures::future::BoxFuture<'static, IlpResult>
+       + Clone
+       + Send
+       + Sync {
+    move |request, mut next| {
+        let monitor = monitor.clone();
+        Box::pin(async move {
+            let account_id = request.to.id();
+            let result = next.send_request(request).await;
+            if let Some(monitor) = monitor {
+                monitor.record_outgoing(account_id, &result);
+            }
+            result
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use interledger::{
+        errors::BalanceStoreError,
+        packet::{ErrorCode, FulfillBuilder, RejectBuilder},
+        service::Username,
+        service_util::Escrow,
+    };
+    use mockito::Matcher;
+    use serde_json::json;
+    use std::str::FromStr;
+
+    #[derive(Clone, Debug)]
+    struct TestAccount(Uuid, Username);
+
+    impl Account for TestAccount {
+        fn id(&self) -> Uuid {
+            self.0
+        }
+
+        fn username(&self) -> &Username {
+            &self.1
+        }
+
+        fn ilp_address(&self) -> &Address {
+            unimplemented!()
+        }
+
+        fn asset_scale(&self) -> u8 {
+            9
+        }
+
+        fn asset_code(&self) -> &str {
+            "XYZ"
+        }
+    }
+
+    fn account(username: &str) -> TestAccount {
+        TestAccount(Uuid::new_v4(), Username::from_str(username).unwrap())
+    }
+
+    /// Store in which every account has the same balance
+    #[derive(Clone)]
+    struct TestStore(Arc<Mutex<i64>>);
+
+    #[async_trait]
+    impl BalanceStore for TestStore {
+        async fn get_balance(&self, _: Uuid) -> Result<i64, BalanceStoreError> {
+            Ok(*self.0.lock().unwrap())
+        }
+
+        async fn get_in_flight_amount(&self, _: Uuid) -> Result<u64, BalanceStoreError> {
+            unimplemented!()
+        }
+
+        async fn update_balances_for_prepare(&self, _: &Escrow) -> Result<(), BalanceStoreError> {
+            unimplemented!()
+        }
+
+        async fn update_balances_for_fulfill(
+            &self,
+            _: &Escrow,
+        ) -> Result<(i64, u64), BalanceStoreError> {
+            unimplemented!()
+        }
+
+        async fn update_balances_for_reject(&self, _: &Escrow) -> Result<(), BalanceStoreError> {
+            unimplemented!()
+        }
+
+        async fn update_balances_for_settle_to(
+            &self,
+            _: Uuid,
+        ) -> Result<(i64, u64), BalanceStoreError> {
+            unimplemented!()
+        }
+
+        async fn release_expired_escrows(&self) -> Result<usize, BalanceStoreError> {
+            unimplemented!()
+        }
+    }
+
+    fn monitor() -> AlertMonitor {
+        AlertMonitor::new(
+            Address::from_str("example.node").unwrap(),
+            SettlementReconciliation::default(),
+        )
+    }
+
+    fn fulfill() -> IlpResult {
+        Ok(FulfillBuilder {
+            fulfillment: &[0; 32],
+            data: &[],
+        }
+        .build())
+    }
+
+    fn reject(triggered_by: &str) -> IlpResult {
+        Err(RejectBuilder {
+            code: ErrorCode::T01_PEER_UNREACHABLE,
+            message: &[],
+            triggered_by: Some(&Address::from_str(triggered_by).unwrap()),
+            data: &[],
+        }
+        .build())
+    }
+
+    async fn evaluate_condition(
+        monitor: &AlertMonitor,
+        condition: AlertCondition,
+        account: &TestAccount,
+        balance: i64,
+    ) -> Option<String> {
+        let observations = monitor.take_observations();
+        let store = TestStore(Arc::new(Mutex::new(balance)));
+        evaluate(&condition, account, &store, &observations).await
+    }
+
+    #[tokio::test]
+    async fn evaluates_balance_rules() {
+        let monitor = monitor();
+        let alice = account("alice");
+        let condition = || AlertCondition::Balance {
+            min_balance: Some(-100),
+            max_balance: Some(100),
+        };
+        assert_eq!(
+            evaluate_condition(&monitor, condition(), &alice, -101).await,
+            Some("Balance of account alice is -101, below the limit of -100".to_owned())
+        );
+        assert_eq!(
+            evaluate_condition(&monitor, condition(), &alice, 101).await,
+            Some("Balance of account alice is 101, above the limit of 100".to_owned())
+        );
+        assert_eq!(
+            evaluate_condition(&monitor, condition(), &alice, 100).await,
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn evaluates_reject_rate_rules() {
+        let monitor = monitor();
+        let alice = account("alice");
+        let condition = |min_packets| AlertCondition::RejectRate {
+            threshold: 0.5,
+            min_packets,
+        };
+        let record = || {
+            for i in 0..10 {
+                let result = if i < 5 {
+                    reject("example.other")
+                } else {
+                    fulfill()
+                };
+                monitor.record_incoming(alice.id(), &result);
+            }
+        };
+
+        record();
+        assert_eq!(
+            evaluate_condition(&monitor, condition(10), &alice, 0).await,
+            Some("5 of the 10 packets received from account alice were rejected".to_owned())
+        );
+        // Too few packets to tell
+        record();
+        assert_eq!(
+            evaluate_condition(&monitor, condition(11), &alice, 0).await,
+            None
+        );
+        // The packets are counted per interval
+        assert_eq!(
+            evaluate_condition(&monitor, condition(10), &alice, 0).await,
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn evaluates_settlement_failure_rules() {
+        let monitor = monitor();
+        let alice = account("alice");
+        let condition = || AlertCondition::SettlementFailures { threshold: 2 };
+        monitor.record_settlement_failure(alice.id());
+        assert_eq!(
+            evaluate_condition(&monitor, condition(), &alice, 0).await,
+            None
+        );
+        monitor.record_settlement_failure(alice.id());
+        monitor.record_settlement_failure(alice.id());
+        assert_eq!(
+            evaluate_condition(&monitor, condition(), &alice, 0).await,
+            Some("2 outgoing settlements to account alice failed".to_owned())
+        );
+    }
+
+    #[tokio::test]
+    async fn tracks_unreachable_peers() {
+        let monitor = monitor();
+        let alice = account("alice");
+        let condition = |duration| AlertCondition::PeerUnreachable { duration };
+
+        // Rejects of the peer (or of nodes after it) mean the peer was reached
+        monitor.record_outgoing(alice.id(), &reject("example.other"));
+        assert_eq!(
+            evaluate_condition(&monitor, condition(0), &alice, 0).await,
+            None
+        );
+
+        monitor.record_outgoing(alice.id(), &reject("example.node"));
+        assert!(evaluate_condition(&monitor, condition(0), &alice, 0)
+            .await
+            .unwrap()
+            .starts_with("Account alice has been unreachable for"));
+        assert_eq!(
+            evaluate_condition(&monitor, condition(60_000), &alice, 0).await,
+            None
+        );
+
+        monitor.record_outgoing(alice.id(), &fulfill());
+        assert_eq!(
+            evaluate_condition(&monitor, condition(0), &alice, 0).await,
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn notifies_alerts_once_until_resolved() {
+        let monitor = monitor();
+        let alice = account("alice");
+        let bob = account("bob");
+        let accounts = vec![alice.clone(), bob];
+        let rules = vec![AlertRule {
+            condition: AlertCondition::Balance {
+                min_balance: Some(0),
+                max_balance: None,
+            },
+            accounts: vec!["alice".to_owned()],
+        }];
+        let store = TestStore(Arc::new(Mutex::new(-1)));
+        let mut firing = HashMap::new();
+
+        // Only the accounts of the rule are evaluated
+        let changes = monitor
+            .evaluate_rules(&rules, &accounts, &store, &mut firing)
+            .await;
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].account.id(), alice.id());
+        assert_eq!(changes[0].status, AlertStatus::Firing);
+
+        // Still firing, which was already notified
+        let changes = monitor
+            .evaluate_rules(&rules, &accounts, &store, &mut firing)
+            .await;
+        assert!(changes.is_empty());
+
+        *store.0.lock().unwrap() = 0;
+        let changes = monitor
+            .evaluate_rules(&rules, &accounts, &store, &mut firing)
+            .await;
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].status, AlertStatus::Resolved);
+        assert_eq!(
+            changes[0].message,
+            "Balance of account alice is -1, below the limit of 0"
+        );
+
+        let changes = monitor
+            .evaluate_rules(&rules, &accounts, &store, &mut firing)
+            .await;
+        assert!(changes.is_empty());
+        assert!(firing.is_empty());
+    }
+
+    #[tokio::test]
+    async fn posts_alerts_to_the_webhook() {
+        let webhook = mockito::mock("POST", "/alerts")
+            .match_body(Matcher::PartialJson(json!({
+                "text": "[FIRING] Balance is too low (example.node)",
+                "status": "firing",
+                "alert": "balance",
+                "account": "alice",
+                "node": "example.node",
+                "message": "Balance is too low",
+            })))
+            .with_status(200)
+            .create();
+        let webhook_url = Url::parse(&format!("{}/alerts", mockito::server_url())).unwrap();
+        monitor()
+            .notify(
+                &Client::new(),
+                &webhook_url,
+                &AlertCondition::Balance {
+                    min_balance: Some(0),
+                    max_balance: None,
+                },
+                &account("alice"),
+                AlertStatus::Firing,
+                "Balance is too low",
+            )
+            .await;
+        webhook.assert();
+    }
+}
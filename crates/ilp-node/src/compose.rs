@@ -0,0 +1,606 @@
+//! `ilp-node generate-compose`, which writes a docker-compose file for a network of nodes
+//! along with the configuration of each node and the accounts they have with each other.
+//!
+//! The generated configurations and accounts are parsed with the node's own configuration
+//! schema and the `POST /accounts` request body before anything is written, so that the
+//! topologies fail to generate rather than drift from what the node accepts.
+
+use crate::config_check::parse_config;
+use crate::init::{quote, random_token, write_secret_file};
+use clap::{App, Arg};
+use config::{Config, File, FileFormat};
+use interledger::api::AccountDetails;
+use serde_json::json;
+use std::{ffi::OsString, fs, iter, path::Path};
+
+const DEFAULT_OUTPUT_DIR: &str = "ilp-compose";
+const NODE_IMAGE: &str = "interledgerrs/ilp-node:latest";
+const REDIS_IMAGE: &str = "redis:5-alpine";
+const GANACHE_IMAGE: &str = "trufflesuite/ganache-cli:latest";
+const ETHEREUM_ENGINE_IMAGE: &str = "interledgerrs/ilp-settlement-ethereum:latest";
+/// The port of the API and ILP over HTTP in the containers of the nodes
+const HTTP_PORT: u16 = 7770;
+const SETTLEMENT_API_PORT: u16 = 7771;
+const ENGINE_PORT: u16 = 3000;
+/// The port on the host of the first node's API, the ones of the next nodes being
+/// 1000 apart (7770, 8770, 9770...)
+const FIRST_PUBLISHED_PORT: u16 = 7770;
+
+/// A network of nodes, which are connected in a chain
+struct Topology {
+    name: &'static str,
+    about: &'static str,
+    nodes: &'static [&'static str],
+    asset_code: &'static str,
+    /// Whether the peers settle with Ethereum settlement engines (on a local ganache
+    /// testnet) rather than not at all
+    settlement: bool,
+}
+
+static TOPOLOGIES: &[Topology] = &[
+    Topology {
+        name: "two-peers",
+        about: "Alice and Bob, who peer directly with each other",
+        nodes: &["alice", "bob"],
+        asset_code: "ABC",
+        settlement: false,
+    },
+    Topology {
+        name: "three-node-chain",
+        about: "A sender (Alice), a connector (Bob) and a receiver (Charlie)",
+        nodes: &["alice", "bob", "charlie"],
+        asset_code: "ABC",
+        settlement: false,
+    },
+    Topology {
+        name: "multi-hop-settlement",
+        about:
+            "Four nodes in a chain, each pair of peers settling in ETH on a local ganache testnet",
+        nodes: &["alice", "bob", "charlie", "dave"],
+        asset_code: "ETH",
+        settlement: true,
+    },
+];
+
+fn generate_compose_app<'a, 'b>() -> App<'a, 'b> {
+    App::new("ilp-node generate-compose")
+        .about("Generates a docker-compose file for a network of nodes, the configuration of each node and the accounts the nodes have with each other, along with a script which creates the accounts once the nodes are running.")
+        .args(&[
+            Arg::with_name("topology")
+                .long("topology")
+                .takes_value(true)
+                .required(true)
+                .possible_values(&["two-peers", "three-node-chain", "multi-hop-settlement"])
+                .help("Topology of the network: two-peers (Alice and Bob, who peer directly with each other), three-node-chain (a sender, a connector and a receiver) or multi-hop-settlement (four nodes in a chain, each pair of peers settling in ETH on a local ganache testnet)."),
+            Arg::with_name("output")
+                .long("output")
+                .takes_value(true)
+                .help("Directory to write the files to. Defaults to ilp-compose."),
+            Arg::with_name("force")
+                .long("force")
+                .help("Overwrite the files if they exist."),
+        ])
+}
+
+/// The account of a peer (or of the node's own user) on a node
+struct PeerAccount {
+    username: &'static str,
+    /// Set for the accounts of the peers, and not for the node's own user
+    peer: Option<Peer>,
+    incoming_token: String,
+}
+
+struct Peer {
+    outgoing_token: String,
+    /// Whether the account settles with the node's settlement engine
+    settlement: bool,
+}
+
+struct Node {
+    name: &'static str,
+    asset_code: &'static str,
+    published_port: u16,
+    secret_seed: String,
+    admin_auth_token: String,
+    /// The key of the node's Ethereum account, when the topology settles
+    ethereum_key: Option<String>,
+    accounts: Vec<PeerAccount>,
+}
+
+impl Topology {
+    fn nodes(&self) -> Result<Vec<Node>, String> {
+        let mut nodes = Vec::with_capacity(self.nodes.len());
+        for (index, name) in self.nodes.iter().enumerate() {
+            nodes.push(Node {
+                name,
+                asset_code: self.asset_code,
+                published_port: FIRST_PUBLISHED_PORT + 1000 * index as u16,
+                secret_seed: random_token()?,
+                admin_auth_token: random_token()?,
+                ethereum_key: if self.settlement {
+                    Some(random_token()?)
+                } else {
+                    None
+                },
+                accounts: vec![PeerAccount {
+                    username: name,
+                    peer: None,
+                    incoming_token: random_token()?,
+                }],
+            });
+        }
+        // Each node peers with the next one in the chain, the tokens which one of them
+        // sends being the ones the other one expects
+        for index in 1..nodes.len() {
+            let (left, right) = (self.nodes[index - 1], self.nodes[index]);
+            let (left_token, right_token) = (random_token()?, random_token()?);
+            nodes[index - 1].accounts.push(PeerAccount {
+                username: right,
+                peer: Some(Peer {
+                    outgoing_token: left_token.clone(),
+                    settlement: self.settlement,
+                }),
+                incoming_token: right_token.clone(),
+            });
+            nodes[index].accounts.push(PeerAccount {
+                username: left,
+                peer: Some(Peer {
+                    outgoing_token: right_token,
+                    settlement: self.settlement,
+                }),
+                incoming_token: left_token,
+            });
+        }
+        Ok(nodes)
+    }
+}
+
+impl Node {
+    fn config(&self) -> String {
+        let config = [
+            ("ilp_address", format!("example.{}", self.name)),
+            ("secret_seed", self.secret_seed.clone()),
+            ("admin_auth_token", self.admin_auth_token.clone()),
+            (
+                "database_url",
+                format!("redis://redis-{}:6379/0", self.name),
+            ),
+            ("http_bind_address", format!("0.0.0.0:{}", HTTP_PORT)),
+            (
+                "settlement_api_bind_address",
+                format!("0.0.0.0:{}", SETTLEMENT_API_PORT),
+            ),
+        ];
+        let config: String = config
+            .iter()
+            .map(|(key, value)| format!("{}: {}\n", key, quote(value)))
+            .collect();
+        format!("# Generated by `ilp-node generate-compose`\n{}", config)
+    }
+
+    fn account_json(&self, account: &PeerAccount) -> String {
+        let mut body = json!({
+            "username": account.username,
+            "asset_code": self.asset_code,
+            "asset_scale": 9,
+            "ilp_over_http_incoming_token": account.incoming_token,
+        });
+        match account.peer {
+            None => {
+                body["ilp_address"] = json!(format!("example.{}.{}", self.name, account.username));
+            }
+            Some(ref peer) => {
+                body["ilp_address"] = json!(format!("example.{}", account.username));
+                body["routing_relation"] = json!("Peer");
+                body["ilp_over_http_url"] = json!(format!(
+                    "http://{}:{}/accounts/{}/ilp",
+                    account.username, HTTP_PORT, self.name
+                ));
+                body["ilp_over_http_outgoing_token"] = json!(peer.outgoing_token);
+                if peer.settlement {
+                    body["settlement_engine_url"] =
+                        json!(format!("http://engine-{}:{}", self.name, ENGINE_PORT));
+                    body["settle_threshold"] = json!(1_000_000);
+                    body["settle_to"] = json!(0);
+                    body["min_balance"] = json!(-10_000_000);
+                }
+            }
+        }
+        serde_json::to_string_pretty(&body).unwrap_or_default() + "\n"
+    }
+}
+
+/// Checks the configuration and the accounts of the node with the schemas the node parses
+/// them with
+fn check_node(node: &Node) -> Result<(), String> {
+    let mut config = Config::new();
+    config
+        .merge(File::from_str(&node.config(), FileFormat::Yaml))
+        .map_err(|err| format!("Invalid configuration of {}: {}", node.name, err))?;
+    let parsed = parse_config(config)
+        .map_err(|issue| format!("Invalid configuration of {}: {}", node.name, issue))?;
+    if let Some(issue) = parsed.validate().into_iter().next() {
+        return Err(format!("Invalid configuration of {}: {}", node.name, issue));
+    }
+    for account in node.accounts.iter() {
+        serde_json::from_str::<AccountDetails>(&node.account_json(account)).map_err(|err| {
+            format!(
+                "Invalid account {} of {}: {}",
+                account.username, node.name, err
+            )
+        })?;
+    }
+    Ok(())
+}
+
+fn docker_compose(topology: &Topology, nodes: &[Node]) -> String {
+    let mut services = Vec::new();
+    for node in nodes.iter() {
+        services.push(format!(
+            "  redis-{name}:\n    image: {redis}\n",
+            name = node.name,
+            redis = REDIS_IMAGE,
+        ));
+        services.push(format!(
+            "  {name}:\n    image: {image}\n    command: [\"/config/{name}.yml\"]\n    volumes:\n      - ./{name}.yml:/config/{name}.yml:ro\n    ports:\n      - \"{port}:{http_port}\"\n    depends_on:\n      - redis-{name}\n",
+            name = node.name,
+            image = NODE_IMAGE,
+            port = node.published_port,
+            http_port = HTTP_PORT,
+        ));
+        if let Some(ref key) = node.ethereum_key {
+            services.push(format!(
+                "  engine-{name}:\n    image: {image}\n    command:\n      - --private_key={key}\n      - --confirmations=0\n      - --poll_frequency=1000\n      - --ethereum_url=http://ganache:8545\n      - --connector_url=http://{name}:{settlement_port}\n      - --redis_url=redis://redis-{name}:6379/1\n      - --asset_scale=9\n      - --settlement_api_bind_address=0.0.0.0:{engine_port}\n    depends_on:\n      - ganache\n      - redis-{name}\n",
+                name = node.name,
+                image = ETHEREUM_ENGINE_IMAGE,
+                key = key,
+                settlement_port = SETTLEMENT_API_PORT,
+                engine_port = ENGINE_PORT,
+            ));
+        }
+    }
+    if topology.settlement {
+        // Funds the Ethereum account of each node with 1000 ETH
+        let accounts: String = nodes
+            .iter()
+            .filter_map(|node| node.ethereum_key.as_ref())
+            .map(|key| format!("      - --account=0x{},1000000000000000000000\n", key))
+            .collect();
+        services.push(format!(
+            "  ganache:\n    image: {}\n    command:\n      - --networkId=1\n{}",
+            GANACHE_IMAGE, accounts
+        ));
+    }
+    format!(
+        "# Generated by `ilp-node generate-compose --topology {}`\n# {}\nversion: \"3\"\n\nservices:\n{}",
+        topology.name,
+        topology.about,
+        services.join("\n")
+    )
+}
+
+/// The script which creates the accounts via the API of each node
+fn setup_script(nodes: &[Node]) -> String {
+    let mut script = String::from(
+        "#!/bin/sh\n# Generated by `ilp-node generate-compose`. Creates the accounts once the nodes are running.\nset -e\ncd \"$(dirname \"$0\")\"\n\n",
+    );
+    for node in nodes.iter() {
+        let url = format!("http://localhost:{}", node.published_port);
+        script.push_str(&format!(
+            "until curl -s -o /dev/null {}; do sleep 1; done\n",
+            url
+        ));
+        for account in node.accounts.iter() {
+            script.push_str(&format!(
+                "curl -s -f -X POST -H \"Authorization: Bearer {}\" -H \"Content-Type: application/json\" -d @accounts/{}/{}.json {}/accounts > /dev/null\n",
+                node.admin_auth_token, node.name, account.username, url
+            ));
+        }
+        script.push_str(&format!(
+            "echo \"Created the accounts of {} ({})\"\n\n",
+            node.name, url
+        ));
+    }
+    script
+}
+
+/// Runs `ilp-node generate-compose` with the arguments following `generate-compose`
+pub fn run_generate_compose<I>(args: I) -> Result<(), String>
+where
+    I: Iterator<Item = OsString>,
+{
+    let matches = generate_compose_app()
+        .get_matches_from(iter::once(OsString::from("ilp-node generate-compose")).chain(args));
+    let topology_name = matches.value_of("topology").unwrap_or_default();
+    let topology = TOPOLOGIES
+        .iter()
+        .find(|topology| topology.name == topology_name)
+        .ok_or_else(|| format!("Unknown topology {}", topology_name))?;
+    let output = Path::new(matches.value_of("output").unwrap_or(DEFAULT_OUTPUT_DIR));
+    let force = matches.is_present("force");
+
+    let nodes = topology.nodes()?;
+    for node in nodes.iter() {
+        check_node(node)?;
+    }
+
+    let mut files = vec![
+        (
+            output.join("docker-compose.yml"),
+            docker_compose(topology, &nodes),
+        ),
+        (output.join("setup.sh"), setup_script(&nodes)),
+    ];
+    for node in nodes.iter() {
+        files.push((output.join(format!("{}.yml", node.name)), node.config()));
+        for account in node.accounts.iter() {
+            files.push((
+                output
+                    .join("accounts")
+                    .join(node.name)
+                    .join(format!("{}.json", account.username)),
+                node.account_json(account),
+            ));
+        }
+    }
+    if !force {
+        if let Some((path, _)) = files.iter().find(|(path, _)| path.exists()) {
+            return Err(format!(
+                "{} already exists, use --force to overwrite it",
+                path.display()
+            ));
+        }
+    }
+    for node in nodes.iter() {
+        let directory = output.join("accounts").join(node.name);
+        fs::create_dir_all(&directory)
+            .map_err(|err| format!("Error creating {}: {}", directory.display(), err))?;
+    }
+    // All of the files contain secrets, the docker-compose file the keys of the
+    // Ethereum accounts when the topology settles
+    for (path, contents) in files.iter() {
+        write_secret_file(&path.to_string_lossy(), contents, force)?;
+    }
+
+    println!(
+        "Wrote the {} topology ({}) to {}. Start it with:",
+        topology.name,
+        topology.about,
+        output.display()
+    );
+    println!(
+        "  docker-compose -f {}/docker-compose.yml up -d",
+        output.display()
+    );
+    println!(
+        "and then create the accounts with: sh {}/setup.sh",
+        output.display()
+    );
+    for node in nodes.iter() {
+        println!(
+            "{}: http://localhost:{}, admin auth token: {}",
+            node.name, node.published_port, node.admin_auth_token
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use secrecy::ExposeSecret;
+    use std::{env, path::PathBuf};
+
+    fn topology(name: &str) -> &'static Topology {
+        TOPOLOGIES
+            .iter()
+            .find(|topology| topology.name == name)
+            .unwrap()
+    }
+
+    fn account<'a>(node: &'a Node, username: &str) -> &'a PeerAccount {
+        node.accounts
+            .iter()
+            .find(|account| account.username == username)
+            .unwrap()
+    }
+
+    fn parse_account(node: &Node, username: &str) -> AccountDetails {
+        serde_json::from_str(&node.account_json(account(node, username))).unwrap()
+    }
+
+    /// Runs `ilp-node generate-compose` in a new directory, which it returns
+    fn generate(topology: &str, args: &[&str]) -> (PathBuf, Result<(), String>) {
+        let dir = env::temp_dir().join(format!(
+            "ilp-node-compose-{}-{}",
+            topology,
+            std::process::id()
+        ));
+        let output = dir.to_string_lossy().into_owned();
+        let args = ["--topology", topology, "--output", &output]
+            .iter()
+            .chain(args)
+            .map(OsString::from)
+            .collect::<Vec<_>>();
+        let result = run_generate_compose(args.into_iter());
+        (dir, result)
+    }
+
+    #[test]
+    fn generates_valid_configs_and_accounts() {
+        for topology in TOPOLOGIES {
+            let nodes = topology.nodes().unwrap();
+            assert_eq!(nodes.len(), topology.nodes.len());
+            for node in nodes.iter() {
+                check_node(node).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn peers_each_node_with_its_neighbours() {
+        let nodes = topology("three-node-chain").nodes().unwrap();
+        let usernames = |node: &Node| -> Vec<&str> {
+            node.accounts
+                .iter()
+                .map(|account| account.username)
+                .collect()
+        };
+        assert_eq!(usernames(&nodes[0]), vec!["alice", "bob"]);
+        assert_eq!(usernames(&nodes[1]), vec!["bob", "alice", "charlie"]);
+        assert_eq!(usernames(&nodes[2]), vec!["charlie", "bob"]);
+
+        for pair in nodes.windows(2) {
+            let (left, right) = (&pair[0], &pair[1]);
+            let left_account = parse_account(left, right.name);
+            let right_account = parse_account(right, left.name);
+            // The token one node sends is the one the other expects
+            assert_eq!(
+                left_account
+                    .ilp_over_http_outgoing_token
+                    .unwrap()
+                    .expose_secret(),
+                right_account
+                    .ilp_over_http_incoming_token
+                    .unwrap()
+                    .expose_secret()
+            );
+            assert_eq!(
+                right_account
+                    .ilp_over_http_outgoing_token
+                    .unwrap()
+                    .expose_secret(),
+                left_account
+                    .ilp_over_http_incoming_token
+                    .unwrap()
+                    .expose_secret()
+            );
+            assert_eq!(
+                left_account.ilp_over_http_url.unwrap(),
+                format!("http://{}:7770/accounts/{}/ilp", right.name, left.name)
+            );
+            assert_eq!(
+                left_account.ilp_address.unwrap().to_string(),
+                format!("example.{}", right.name)
+            );
+            assert_eq!(left_account.routing_relation.as_deref(), Some("Peer"));
+            assert_eq!(left_account.settlement_engine_url, None);
+        }
+
+        // The node's own user is a child of the node
+        let user = parse_account(&nodes[1], "bob");
+        assert_eq!(user.ilp_address.unwrap().to_string(), "example.bob.bob");
+        assert!(user.ilp_over_http_outgoing_token.is_none());
+        assert_eq!(user.routing_relation, None);
+    }
+
+    #[test]
+    fn settles_with_ethereum_engines() {
+        let topology = topology("multi-hop-settlement");
+        let nodes = topology.nodes().unwrap();
+        let account = parse_account(&nodes[1], "charlie");
+        assert_eq!(
+            account.settlement_engine_url.unwrap().as_str(),
+            "http://engine-bob:3000"
+        );
+        assert_eq!(account.settle_threshold, Some(1_000_000));
+
+        let compose = docker_compose(topology, &nodes);
+        let services = FileFormat::Yaml.parse(None, &compose).unwrap()["services"]
+            .clone()
+            .into_table()
+            .unwrap();
+        let mut names: Vec<&str> = services.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        assert_eq!(
+            names,
+            vec![
+                "alice",
+                "bob",
+                "charlie",
+                "dave",
+                "engine-alice",
+                "engine-bob",
+                "engine-charlie",
+                "engine-dave",
+                "ganache",
+                "redis-alice",
+                "redis-bob",
+                "redis-charlie",
+                "redis-dave",
+            ]
+        );
+        // Every engine's account is funded on the testnet
+        for node in nodes.iter() {
+            let key = node.ethereum_key.as_ref().unwrap();
+            assert!(compose.contains(&format!("--private_key={}", key)));
+            assert!(compose.contains(&format!("--account=0x{},", key)));
+        }
+    }
+
+    #[test]
+    fn writes_the_files_of_the_topology() {
+        let (dir, result) = generate("two-peers", &[]);
+        result.unwrap();
+        let read = |path: &str| fs::read_to_string(dir.join(path)).unwrap();
+
+        let compose = FileFormat::Yaml
+            .parse(None, &read("docker-compose.yml"))
+            .unwrap();
+        let services = compose["services"].clone().into_table().unwrap();
+        assert_eq!(services.len(), 4);
+        let alice = services["alice"].clone().into_table().unwrap();
+        assert_eq!(alice["image"].clone().into_str().unwrap(), NODE_IMAGE);
+
+        // The configs are the ones the node parses
+        for name in &["alice", "bob"] {
+            let mut config = Config::new();
+            config
+                .merge(File::from_str(
+                    &read(&format!("{}.yml", name)),
+                    FileFormat::Yaml,
+                ))
+                .unwrap();
+            let node = parse_config(config).unwrap();
+            assert_eq!(
+                node.ilp_address.unwrap().to_string(),
+                format!("example.{}", name)
+            );
+        }
+
+        // The setup script creates every account with the admin token of its node
+        let script = read("setup.sh");
+        let alice_token = Config::new()
+            .merge(File::from_str(&read("alice.yml"), FileFormat::Yaml))
+            .unwrap()
+            .get_str("admin_auth_token")
+            .unwrap();
+        for (node, account) in &[
+            ("alice", "alice"),
+            ("alice", "bob"),
+            ("bob", "bob"),
+            ("bob", "alice"),
+        ] {
+            let path = format!("accounts/{}/{}.json", node, account);
+            serde_json::from_str::<AccountDetails>(&read(&path)).unwrap();
+            assert!(script.contains(&format!("-d @{}", path)));
+        }
+        assert!(script.contains(&format!(
+            "Bearer {}\" -H \"Content-Type: application/json\" -d @accounts/alice/bob.json http://localhost:7770/accounts",
+            alice_token
+        )));
+        assert!(script.contains("http://localhost:8770/accounts"));
+
+        // The files are only overwritten if forced
+        let config = read("alice.yml");
+        let (_, result) = generate("two-peers", &[]);
+        assert!(result
+            .unwrap_err()
+            .ends_with("already exists, use --force to overwrite it"));
+        assert_eq!(read("alice.yml"), config);
+        let (_, result) = generate("two-peers", &["--force"]);
+        result.unwrap();
+        assert_ne!(read("alice.yml"), config);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
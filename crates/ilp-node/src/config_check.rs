@@ -0,0 +1,472 @@
+//! Validation of the node's configuration.
+//!
+//! The configuration, merged from the environment, stdin, the config file and the command
+//! line, is parsed into an [`InterledgerNode`](../struct.InterledgerNode.html) while
+//! tracking the key each value came from, so that errors point at the offending key.
+//! The parsed settings are then checked for values the node cannot run with, and (with
+//! `--validate-config`) for the reachability of the database and the settlement engines.
+
+use crate::node::InterledgerNode;
+use config::{Config, Source};
+use serde::{
+    de::{value, Deserializer, Error as DeserializeError, Visitor},
+    forward_to_deserialize_any, Deserialize,
+};
+use std::{collections::HashSet, fmt, net::SocketAddr, time::Duration};
+use tokio::{net::TcpStream, time::timeout};
+use url::Url;
+use warp::http::HeaderValue;
+
+#[cfg(feature = "redis")]
+use crate::redis_store::redis_settlement_engines;
+
+/// How long the reachability checks wait for the database or a settlement engine
+const REACHABILITY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Keys which are accepted, even though they are not fields of the configuration
+static IGNORED_KEYS: &[&str] = &[
+    // `ILP_ADDRESS` is resolved to the `ilp_address`
+    "address",
+    // temporary alias of the database_url, for backwards compatibility
+    "redis_url",
+    // resolved before the configuration is parsed
+    "secrets",
+    // flags which select what the node does
+    "rotate_encryption_key",
+    "validate_config",
+];
+
+/// A problem with the configuration
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigIssue {
+    /// The offending key, with the keys of its parents separated by `.`
+    pub key: String,
+    /// What is wrong with the value of the key
+    pub message: String,
+}
+
+impl ConfigIssue {
+    pub fn new(key: impl Into<String>, message: impl Into<String>) -> Self {
+        ConfigIssue {
+            key: key.into(),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ConfigIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "`{}`: {}", self.key, self.message)
+    }
+}
+
+/// Parses the configuration, pointing at the key of the value which could not be parsed
+pub fn parse_config(config: Config) -> Result<InterledgerNode, ConfigIssue> {
+    serde_path_to_error::deserialize(config).map_err(|err| {
+        let path = err.path().to_string();
+        let message = err.inner().to_string();
+        // Missing fields are reported at the struct which is missing them
+        let missing_field = message
+            .strip_prefix("missing field `")
+            .and_then(|rest| rest.split('`').next());
+        let key = match (path.as_str(), missing_field) {
+            (".", Some(field)) => field.to_string(),
+            (".", None) => String::new(),
+            (path, Some(field)) => format!("{}.{}", path, field),
+            (path, None) => path.to_string(),
+        };
+        ConfigIssue::new(key, message)
+    })
+}
+
+/// Returns the top-level keys of the configuration, as defined by the fields of
+/// [`InterledgerNode`](../struct.InterledgerNode.html) (including the ones of the
+/// enabled features)
+pub fn config_keys() -> Vec<&'static str> {
+    let mut keys = Vec::new();
+    let _ = InterledgerNode::deserialize(FieldNames(&mut keys));
+    // Newer versions of serde also list the aliases of the fields
+    keys.retain(|key| !IGNORED_KEYS.contains(key));
+    keys
+}
+
+/// Returns the top-level keys which are not set, so that the node uses their defaults
+pub fn defaulted_keys(config: &Config) -> Vec<&'static str> {
+    let set: HashSet<String> = config
+        .collect()
+        .map(|table| table.into_keys().collect())
+        .unwrap_or_default();
+    config_keys()
+        .into_iter()
+        .filter(|key| !set.contains(*key))
+        .collect()
+}
+
+/// Returns a warning for each top-level key which is not a part of the configuration,
+/// suggesting the closest known key
+pub fn unknown_keys(config: &Config) -> Vec<ConfigIssue> {
+    let known = config_keys();
+    let mut keys: Vec<String> = match config.collect() {
+        Ok(table) => table.into_keys().collect(),
+        Err(_) => return Vec::new(),
+    };
+    keys.sort();
+    keys.into_iter()
+        .filter(|key| {
+            !known
+                .iter()
+                .chain(IGNORED_KEYS.iter())
+                .any(|known| known == key)
+        })
+        .map(|key| {
+            let suggestion = known
+                .iter()
+                .map(|known| (edit_distance(&key, known), known))
+                .filter(|(distance, _)| *distance <= 3)
+                .min();
+            let message = match suggestion {
+                Some((_, known)) => format!("unknown key, did you mean `{}`?", known),
+                None => "unknown key, it is ignored".to_string(),
+            };
+            ConfigIssue::new(key, message)
+        })
+        .collect()
+}
+
+impl InterledgerNode {
+    /// Checks the settings for values which the node cannot run with
+    pub fn validate(&self) -> Vec<ConfigIssue> {
+        let mut issues = Vec::new();
+
+        if self.admin_auth_token.is_empty() {
+            issues.push(ConfigIssue::new("admin_auth_token", "must not be empty"));
+        }
+        match Url::parse(&self.database_url) {
+            Ok(url) => {
+                if !is_supported_database(&url) {
+                    issues.push(ConfigIssue::new(
+                        "database_url",
+                        format!("unsupported data source scheme: {}", url.scheme()),
+                    ));
+                }
+            }
+            Err(err) => issues.push(ConfigIssue::new(
+                "database_url",
+                format!("`{}` is not a valid URL: {}", self.database_url, err),
+            )),
+        }
+        for url in self.read_replica_urls.iter() {
+            if let Err(err) = Url::parse(url) {
+                issues.push(ConfigIssue::new(
+                    "read_replica_urls",
+                    format!("`{}` is not a valid URL: {}", url, err),
+                ));
+            }
+        }
+        if let Some(ref sunset) = self.api_sunset {
+            if !sunset.ends_with(" GMT") || HeaderValue::from_str(sunset).is_err() {
+                issues.push(ConfigIssue::new(
+                    "api_sunset",
+                    format!(
+                        "`{}` is not an HTTP date, such as `Sat, 31 Dec 2022 23:59:59 GMT`",
+                        sunset
+                    ),
+                ));
+            }
+        }
+        if self.http_bind_address == self.settlement_api_bind_address {
+            issues.push(ConfigIssue::new(
+                "settlement_api_bind_address",
+                format!(
+                    "{} is already the http_bind_address",
+                    self.settlement_api_bind_address
+                ),
+            ));
+        }
+        if let Some(ref tls) = self.http_tls {
+            if let Err(err) = tls.server_config() {
+                issues.push(ConfigIssue::new("http_tls", err.to_string()));
+            }
+            #[cfg(feature = "acme")]
+            {
+                if let Some(ref acme) = tls.acme {
+                    if acme.challenge_bind_address == self.http_bind_address
+                        || acme.challenge_bind_address == self.settlement_api_bind_address
+                    {
+                        issues.push(ConfigIssue::new(
+                            "http_tls.acme.challenge_bind_address",
+                            format!(
+                                "{} is already used by another API",
+                                acme.challenge_bind_address
+                            ),
+                        ));
+                    }
+                }
+            }
+        }
+        let mut bind_addresses = vec![
+            ("http_bind_address", self.http_bind_address),
+            (
+                "settlement_api_bind_address",
+                self.settlement_api_bind_address,
+            ),
+        ];
+        let listeners = [
+            ("listeners.api", &self.listeners.api),
+            ("listeners.ilp_over_http", &self.listeners.ilp_over_http),
+            ("listeners.btp", &self.listeners.btp),
+            ("listeners.spsp", &self.listeners.spsp),
+        ];
+        if let Some(grpc_bind_address) = self.grpc_bind_address {
+            if let Some((other, _)) = bind_addresses
+                .iter()
+                .find(|(_, address)| *address == grpc_bind_address)
+            {
+                issues.push(ConfigIssue::new(
+                    "grpc_bind_address",
+                    format!("{} is already used by {}", grpc_bind_address, other),
+                ));
+            }
+            bind_addresses.push(("grpc_bind_address", grpc_bind_address));
+        }
+        for (key, listener) in listeners.iter() {
+            if let Some(listener) = listener {
+                if let Some((other, _)) = bind_addresses
+                    .iter()
+                    .find(|(_, address)| *address == listener.bind_address)
+                {
+                    issues.push(ConfigIssue::new(
+                        format!("{}.bind_address", key),
+                        format!("{} is already used by {}", listener.bind_address, other),
+                    ));
+                }
+                bind_addresses.push((*key, listener.bind_address));
+                if let Err(err) = listener.server_config() {
+                    issues.push(ConfigIssue::new(format!("{}.tls", key), err.to_string()));
+                }
+                if listener.requests_per_minute == Some(0) {
+                    issues.push(ConfigIssue::new(
+                        format!("{}.requests_per_minute", key),
+                        "must be greater than 0 (leave it unset for no limit)",
+                    ));
+                }
+            }
+        }
+        if let Some(ref tls) = self.settlement_tls {
+            if let Err(err) = tls.client_config().and_then(|_| tls.server_config()) {
+                issues.push(ConfigIssue::new("settlement_tls", err.to_string()));
+            }
+        }
+        if self.settlement_replay_window.is_some() && self.settlement_engine_secrets.is_empty() {
+            issues.push(ConfigIssue::new(
+                "settlement_engine_secrets",
+                "must have the secrets of the settlement engines, which sign their calls when settlement_replay_window is set",
+            ));
+        }
+        if let Some(ref key_management) = self.key_management {
+            if let Err(err) = key_management.build() {
+                issues.push(ConfigIssue::new("key_management", err.to_string()));
+            }
+        }
+        if let Some(ref previous_key_management) = self.previous_key_management {
+            if self.key_management.is_none() {
+                issues.push(ConfigIssue::new(
+                    "previous_key_management",
+                    "can only be used together with key_management",
+                ));
+            } else if let Err(err) = previous_key_management.build() {
+                issues.push(ConfigIssue::new("previous_key_management", err.to_string()));
+            }
+        }
+
+        // Intervals of 0 would make the node spin (or panic when creating the interval), and
+        // Redis rejects the expiries of 0 set with the TTLs and the nonces of a replay window
+        // of 0 (or deletes the keys right away when compacting)
+        let intervals = [
+            ("route_broadcast_interval", self.route_broadcast_interval),
+            ("settings_poll_interval", self.settings_poll_interval),
+            ("balance_snapshot_interval", self.balance_snapshot_interval),
+            ("escrow_sweep_interval", self.escrow_sweep_interval),
+            (
+                "settlement_reconciliation_interval",
+                self.settlement_reconciliation_interval,
+            ),
+            ("compaction_interval", self.compaction_interval),
+            ("idempotency_ttl", self.idempotency_ttl),
+            ("uncredited_settlement_ttl", self.uncredited_settlement_ttl),
+            ("settlement_replay_window", self.settlement_replay_window),
+            (
+                "exchange_rate.poll_interval",
+                Some(self.exchange_rate.poll_interval),
+            ),
+            (
+                "latency_probes.interval",
+                self.latency_probes.as_ref().map(|probes| probes.interval),
+            ),
+            (
+                "balance_notifications.debounce",
+                self.balance_notifications
+                    .as_ref()
+                    .map(|notifications| notifications.debounce),
+            ),
+        ];
+        for (key, interval) in intervals.iter() {
+            if *interval == Some(0) {
+                issues.push(ConfigIssue::new(*key, "must be greater than 0"));
+            }
+        }
+
+        if !is_fraction(self.exchange_rate.spread) {
+            issues.push(ConfigIssue::new(
+                "exchange_rate.spread",
+                "must be at least 0 and less than 1",
+            ));
+        }
+        if let Some(percentage_fee) = self.exchange_rate.percentage_fee {
+            if !is_fraction(percentage_fee) {
+                issues.push(ConfigIssue::new(
+                    "exchange_rate.percentage_fee",
+                    "must be at least 0 and less than 1",
+                ));
+            }
+        }
+        if let Some(max_deviation) = self.exchange_rate.max_deviation {
+            if !(max_deviation > 0.0 && max_deviation.is_finite()) {
+                issues.push(ConfigIssue::new(
+                    "exchange_rate.max_deviation",
+                    "must be greater than 0",
+                ));
+            }
+        }
+
+        if self.expiry.max_duration == 0 {
+            issues.push(ConfigIssue::new(
+                "expiry.max_duration",
+                "must be greater than 0",
+            ));
+        } else if self.expiry.min_window >= self.expiry.max_duration {
+            issues.push(ConfigIssue::new(
+                "expiry.min_window",
+                format!(
+                    "must be less than the expiry.max_duration ({}ms), otherwise every packet is rejected",
+                    self.expiry.max_duration
+                ),
+            ));
+        }
+
+        issues
+    }
+
+    /// Checks that the database, and the settlement engines of the accounts in it,
+    /// can be connected to. This does not modify the database.
+    pub async fn check_reachability(&self) -> Vec<ConfigIssue> {
+        let database_url = match Url::parse(&self.database_url) {
+            Ok(url) => url,
+            // The validation already reported the URL
+            Err(_) => return Vec::new(),
+        };
+
+        let engines = match database_url.scheme() {
+            #[cfg(feature = "redis")]
+            "redis" | "redis+unix" => timeout(REACHABILITY_TIMEOUT, redis_settlement_engines(self))
+                .await
+                .unwrap_or_else(|_| Err("the database did not respond in time".to_string())),
+            // The validation already reported the scheme
+            _ => Ok(Vec::new()),
+        };
+        match engines {
+            Ok(engines) => check_settlement_engines(engines).await,
+            Err(message) => vec![ConfigIssue::new("database_url", message)],
+        }
+    }
+}
+
+/// Connects to each of the settlement engines (given with the username of an account
+/// which uses it)
+async fn check_settlement_engines(engines: Vec<(String, Url)>) -> Vec<ConfigIssue> {
+    let mut checked = HashSet::new();
+    let mut issues = Vec::new();
+    for (username, url) in engines {
+        if !checked.insert(url.clone()) {
+            continue;
+        }
+        if let Err(err) = connect(&url).await {
+            issues.push(ConfigIssue::new(
+                format!("accounts.{}.settlement_engine_url", username),
+                format!(
+                    "cannot connect to the settlement engine at {}: {}",
+                    url, err
+                ),
+            ));
+        }
+    }
+    issues
+}
+
+async fn connect(url: &Url) -> Result<(), String> {
+    let addresses: Vec<SocketAddr> = url.socket_addrs(|| None).map_err(|err| err.to_string())?;
+    let mut error = "the host could not be resolved".to_string();
+    for address in addresses {
+        match timeout(REACHABILITY_TIMEOUT, TcpStream::connect(address)).await {
+            Ok(Ok(_)) => return Ok(()),
+            Ok(Err(err)) => error = err.to_string(),
+            Err(_) => error = "the connection timed out".to_string(),
+        }
+    }
+    Err(error)
+}
+
+fn is_supported_database(url: &Url) -> bool {
+    match url.scheme() {
+        #[cfg(feature = "redis")]
+        "redis" | "redis+unix" => true,
+        _ => false,
+    }
+}
+
+fn is_fraction(value: f64) -> bool {
+    (0.0..1.0).contains(&value)
+}
+
+/// Number of single character insertions, deletions and substitutions which turn
+/// one string into the other
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + if a_char == *b_char { 0 } else { 1 };
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Deserializer which only records the names of the fields of the struct
+/// it deserializes, and then fails
+struct FieldNames<'a>(&'a mut Vec<&'static str>);
+
+impl<'de, 'a> Deserializer<'de> for FieldNames<'a> {
+    type Error = value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+        Err(value::Error::custom("only structs have field names"))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.0.extend_from_slice(fields);
+        Err(value::Error::custom("only the field names are recorded"))
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str string bytes byte_buf option
+        unit unit_struct newtype_struct seq tuple tuple_struct map enum identifier ignored_any
+    }
+}
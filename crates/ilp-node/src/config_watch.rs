@@ -0,0 +1,250 @@
+use config::{Config, ConfigError};
+use interledger::{
+    api::{NodeStore, RuntimeSettings},
+    ccp::DEFAULT_BROADCAST_INTERVAL,
+};
+use serde::Deserialize;
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::sync::watch;
+use tracing::{error, info, warn};
+
+/// Interval, in milliseconds, on which the store is checked for changed runtime settings
+/// if none is configured
+pub const DEFAULT_SETTINGS_POLL_INTERVAL: u64 = 10_000;
+
+/// Sending halves of the settings which the running services re-read when they change
+pub(crate) struct ReloadableSettings {
+    route_broadcast_interval: watch::Sender<Duration>,
+    exchange_rate_poll_interval: watch::Sender<Duration>,
+    max_packet_amount: Arc<AtomicU64>,
+    max_packet_size: Arc<AtomicU64>,
+    /// The values which were last applied to the services
+    applied: RuntimeSettings,
+}
+
+/// Receiving halves of the reloadable settings, to be passed to the services
+pub(crate) struct SettingsReceivers {
+    pub route_broadcast_interval: watch::Receiver<Duration>,
+    pub exchange_rate_poll_interval: watch::Receiver<Duration>,
+    pub max_packet_amount: Arc<AtomicU64>,
+    pub max_packet_size: Arc<AtomicU64>,
+}
+
+impl ReloadableSettings {
+    /// Creates the channels for the settings, initialized with the provided values
+    pub fn new(settings: RuntimeSettings) -> (Self, SettingsReceivers) {
+        let applied = with_defaults(settings);
+        let (route_broadcast_interval, route_broadcast_interval_receiver) = watch::channel(
+            Duration::from_millis(applied.route_broadcast_interval.unwrap()),
+        );
+        let (exchange_rate_poll_interval, exchange_rate_poll_interval_receiver) = watch::channel(
+            Duration::from_millis(applied.exchange_rate_poll_interval.unwrap()),
+        );
+        let max_packet_amount = Arc::new(AtomicU64::new(applied.max_packet_amount.unwrap()));
+        let max_packet_size = Arc::new(AtomicU64::new(applied.max_packet_size.unwrap()));
+
+        let receivers = SettingsReceivers {
+            route_broadcast_interval: route_broadcast_interval_receiver,
+            exchange_rate_poll_interval: exchange_rate_poll_interval_receiver,
+            max_packet_amount: max_packet_amount.clone(),
+            max_packet_size: max_packet_size.clone(),
+        };
+        let settings = ReloadableSettings {
+            route_broadcast_interval,
+            exchange_rate_poll_interval,
+            max_packet_amount,
+            max_packet_size,
+            applied,
+        };
+        (settings, receivers)
+    }
+
+    /// Passes the settings which differ from the applied ones on to the services.
+    /// Intervals of 0 are ignored because they cannot be scheduled.
+    fn apply(&mut self, settings: RuntimeSettings) {
+        let settings = with_defaults(settings);
+
+        if settings.route_broadcast_interval != self.applied.route_broadcast_interval {
+            let ms = settings.route_broadcast_interval.unwrap();
+            if ms == 0 {
+                warn!(target: "interledger-node", "Ignoring route_broadcast_interval of 0ms");
+            } else {
+                info!(target: "interledger-node", "Changing route_broadcast_interval to {}ms", ms);
+                // The receiver is dropped if the route manager is not running
+                let _ = self
+                    .route_broadcast_interval
+                    .broadcast(Duration::from_millis(ms));
+                self.applied.route_broadcast_interval = Some(ms);
+            }
+        }
+
+        if settings.exchange_rate_poll_interval != self.applied.exchange_rate_poll_interval {
+            let ms = settings.exchange_rate_poll_interval.unwrap();
+            if ms == 0 {
+                warn!(target: "interledger-node", "Ignoring exchange_rate.poll_interval of 0ms");
+            } else {
+                info!(target: "interledger-node", "Changing exchange_rate.poll_interval to {}ms", ms);
+                // The receiver is dropped if no exchange rate provider is configured
+                let _ = self
+                    .exchange_rate_poll_interval
+                    .broadcast(Duration::from_millis(ms));
+                self.applied.exchange_rate_poll_interval = Some(ms);
+            }
+        }
+
+        if settings.max_packet_amount != self.applied.max_packet_amount {
+            let amount = settings.max_packet_amount.unwrap();
+            info!(target: "interledger-node", "Changing max_packet_amount to {}", amount);
+            self.max_packet_amount.store(amount, Ordering::Relaxed);
+            self.applied.max_packet_amount = Some(amount);
+        }
+
+        if settings.max_packet_size != self.applied.max_packet_size {
+            let size = settings.max_packet_size.unwrap();
+            info!(target: "interledger-node", "Changing max_packet_size to {} bytes", size);
+            self.max_packet_size.store(size, Ordering::Relaxed);
+            self.applied.max_packet_size = Some(size);
+        }
+    }
+}
+
+/// Fills in the values the services use when a setting is not configured
+fn with_defaults(settings: RuntimeSettings) -> RuntimeSettings {
+    settings.or(RuntimeSettings {
+        route_broadcast_interval: Some(DEFAULT_BROADCAST_INTERVAL),
+        exchange_rate_poll_interval: Some(60_000),
+        max_packet_amount: Some(u64::MAX),
+        max_packet_size: Some(u64::MAX),
+    })
+}
+
+/// Spawns a task which applies changes to the runtime settings in the store and, when the
+/// node receives a SIGHUP, to the reloadable settings in its config file.
+///
+/// Settings from the store take precedence. Settings which are removed from the store fall
+/// back to the configured values. When the config file is reloaded, the values it contains
+/// replace the configured ones, while values missing from it keep their previous values.
+pub(crate) fn spawn_settings_watcher<S: NodeStore>(
+    store: S,
+    mut settings: ReloadableSettings,
+    mut configured: RuntimeSettings,
+    config_file: Option<String>,
+    poll_interval: Duration,
+) {
+    tokio::spawn(async move {
+        let mut poll = tokio::time::interval(poll_interval);
+        let mut reload_signal = ReloadSignal::new();
+        let mut overrides = RuntimeSettings::default();
+        loop {
+            let changed = tokio::select! {
+                _ = poll.tick() => match store.get_runtime_settings().await {
+                    Ok(stored) => {
+                        let changed = stored != overrides;
+                        overrides = stored;
+                        changed
+                    }
+                    Err(err) => {
+                        warn!(target: "interledger-node", "Error loading runtime settings from the store: {}", err);
+                        false
+                    }
+                },
+                _ = reload_signal.recv() => match config_file {
+                    Some(ref path) => match read_config_file(path) {
+                        Ok(file_settings) => {
+                            info!(target: "interledger-node", "Reloaded settings from config file: {}", path);
+                            configured = file_settings.or(configured);
+                            true
+                        }
+                        Err(err) => {
+                            error!(target: "interledger-node", "Error reloading config file {}: {:?}", path, err);
+                            false
+                        }
+                    },
+                    None => {
+                        warn!(target: "interledger-node", "Not reloading settings because the node was not started with a config file");
+                        false
+                    }
+                },
+            };
+            if changed {
+                settings.apply(overrides.clone().or(configured.clone()));
+            }
+        }
+    });
+}
+
+/// The subset of the node's configuration which can be reloaded from the config file
+#[derive(Deserialize, Default)]
+struct ReloadableConfig {
+    route_broadcast_interval: Option<u64>,
+    max_packet_amount: Option<u64>,
+    max_packet_size: Option<u64>,
+    #[serde(default)]
+    exchange_rate: ReloadableExchangeRateConfig,
+}
+
+#[derive(Deserialize, Default)]
+struct ReloadableExchangeRateConfig {
+    poll_interval: Option<u64>,
+}
+
+fn read_config_file(path: &str) -> Result<RuntimeSettings, ConfigError> {
+    let mut config = Config::new();
+    config.merge(config::File::with_name(path))?;
+    let config: ReloadableConfig = config.try_into()?;
+    Ok(RuntimeSettings {
+        route_broadcast_interval: config.route_broadcast_interval,
+        exchange_rate_poll_interval: config.exchange_rate.poll_interval,
+        max_packet_amount: config.max_packet_amount,
+        max_packet_size: config.max_packet_size,
+    })
+}
+
+/// Resolves whenever the node is asked to reload its config file, which happens on SIGHUP
+#[cfg(unix)]
+struct ReloadSignal {
+    hangup: Option<tokio::signal::unix::Signal>,
+}
+
+#[cfg(unix)]
+impl ReloadSignal {
+    fn new() -> Self {
+        use tokio::signal::unix::{signal, SignalKind};
+        let hangup = signal(SignalKind::hangup())
+            .map_err(|err| {
+                warn!(target: "interledger-node", "Unable to listen for SIGHUP, the config file will not be reloaded: {}", err)
+            })
+            .ok();
+        ReloadSignal { hangup }
+    }
+
+    async fn recv(&mut self) {
+        if let Some(ref mut hangup) = self.hangup {
+            if hangup.recv().await.is_some() {
+                return;
+            }
+        }
+        futures::future::pending().await
+    }
+}
+
+/// Platforms without signals never reload the config file
+#[cfg(not(unix))]
+struct ReloadSignal;
+
+#[cfg(not(unix))]
+impl ReloadSignal {
+    fn new() -> Self {
+        ReloadSignal
+    }
+
+    async fn recv(&mut self) {
+        futures::future::pending().await
+    }
+}
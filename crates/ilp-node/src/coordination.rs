@@ -0,0 +1,388 @@
+use interledger::{
+    btp::{connect_to_service_account, BtpOutgoingService, BtpStore},
+    errors::AccountStoreError,
+    service::{Account as AccountTrait, AccountStore, LeaseStore, OutgoingService},
+    service_util::{send_settlement, Scheduler},
+    settlement::core::{
+        types::{SettlementQueueStore, SettlementStore},
+        SettlementClient,
+    },
+    store::account::Account,
+};
+use serde::Deserialize;
+use std::{
+    collections::HashSet,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+#[cfg(feature = "balance-tracking")]
+use tokio::sync::mpsc::{self, UnboundedSender};
+use tokio::time::Instant;
+use tracing::{debug, error, info, warn};
+use uuid::Uuid;
+
+/// Name of the lease held by the node which runs the singleton tasks
+/// (broadcasting routes and polling the exchange rate provider)
+static LEADER_LEASE: &str = "leader";
+
+/// How often the leading control node checks for settlements queued by the workers
+const SETTLEMENT_QUEUE_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// What a node does among the nodes sharing the store
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ClusterRole {
+    /// Forwards packets, sends the settlements they trigger, and may be elected leader
+    #[default]
+    All,
+    /// Forwards packets without keeping any state of its own: it never becomes the leader,
+    /// and queues the settlements triggered by the packets it forwards in the store
+    Worker,
+    /// May be elected leader, and while it is, it sends the settlements queued by the workers
+    Control,
+}
+
+/// Configuration for running multiple nodes against the same store.
+#[derive(Deserialize, Clone)]
+pub struct ClusterConfig {
+    /// Unique identifier of this node among the nodes sharing the store.
+    /// Defaults to a random identifier, generated on every start.
+    #[serde(default)]
+    pub node_id: Option<String>,
+    /// Time, in milliseconds, after which the leases held by a node expire if it stops
+    /// renewing them (for example because it crashed). Leases are renewed three times
+    /// per this interval. Defaults to 10000ms (10 seconds).
+    #[serde(default = "ClusterConfig::default_lease_ttl")]
+    pub lease_ttl: u64,
+    /// What this node does among the nodes sharing the store. Defaults to `all`.
+    #[serde(default)]
+    pub role: ClusterRole,
+}
+
+impl ClusterConfig {
+    fn default_lease_ttl() -> u64 {
+        10_000
+    }
+}
+
+fn btp_lease(account_id: Uuid) -> String {
+    format!("btp_outgoing:{}", account_id)
+}
+
+/// Coordinates the nodes sharing a store using leases: only the leader runs the
+/// singleton tasks, and each outgoing BTP connection is owned by a single node.
+#[derive(Clone)]
+pub(crate) struct Coordinator {
+    node_id: Arc<String>,
+    lease_ttl: u64,
+    role: ClusterRole,
+}
+
+impl Coordinator {
+    pub fn new(config: &ClusterConfig) -> Self {
+        let node_id = config
+            .node_id
+            .clone()
+            .unwrap_or_else(|| Uuid::new_v4().to_string());
+        info!(target: "interledger-node", "Coordinating with the other nodes using the store as node {} (role: {:?})", node_id, config.role);
+        Coordinator {
+            node_id: Arc::new(node_id),
+            lease_ttl: config.lease_ttl,
+            role: config.role,
+        }
+    }
+
+    /// Whether the settlements triggered by the packets this node
+    /// forwards are queued in the store rather than sent by this node
+    #[cfg(feature = "balance-tracking")]
+    pub fn queues_settlements(&self) -> bool {
+        self.role == ClusterRole::Worker
+    }
+
+    fn renew_interval(&self) -> Duration {
+        Duration::from_millis((self.lease_ttl / 3).max(1))
+    }
+
+    /// Tries to acquire the leader lease, and then spawns a task which keeps renewing (or
+    /// trying to acquire) it. The returned flag is set while this node is the leader. It is
+    /// cleared as soon as a renewal fails, so that the node stops acting as the leader
+    /// before its lease expires.
+    /// Workers never take part in the election.
+    pub async fn start_leader_election<S>(&self, store: S) -> Arc<AtomicBool>
+    where
+        S: LeaseStore + Send + Sync + 'static,
+    {
+        let is_leader = Arc::new(AtomicBool::new(false));
+        if self.role == ClusterRole::Worker {
+            return is_leader;
+        }
+        // Elect a leader before the singleton tasks start, so that they run right away
+        self.renew_leadership(&store, &is_leader).await;
+
+        let coordinator = self.clone();
+        let is_leader_clone = is_leader.clone();
+        tokio::spawn(async move {
+            let period = coordinator.renew_interval();
+            let mut interval = tokio::time::interval_at(Instant::now() + period, period);
+            loop {
+                interval.tick().await;
+                coordinator.renew_leadership(&store, &is_leader_clone).await;
+            }
+        });
+        is_leader
+    }
+
+    async fn renew_leadership<S: LeaseStore>(&self, store: &S, is_leader: &AtomicBool) {
+        let leader = store
+            .acquire_lease(LEADER_LEASE, &self.node_id, self.lease_ttl)
+            .await
+            .unwrap_or_else(|err| {
+                warn!(target: "interledger-node", "Error renewing the leader lease: {}", err);
+                false
+            });
+        if is_leader.swap(leader, Ordering::Relaxed) != leader {
+            if leader {
+                info!(target: "interledger-node", "Node {} is now the leader, it broadcasts routes and polls the exchange rate provider", self.node_id);
+            } else {
+                info!(target: "interledger-node", "Node {} is no longer the leader", self.node_id);
+            }
+        }
+    }
+
+    /// Releases the leader lease and the leases of the outgoing BTP connections held by this
+    /// node, so that the other nodes take over without waiting for them to expire. This is
+    /// done as the node stops, so the renewal tasks have no chance to acquire them again.
+    pub async fn release_leases<S>(&self, store: &S)
+    where
+        S: LeaseStore + BtpStore<Account = Account>,
+    {
+        let mut leases = vec![LEADER_LEASE.to_string()];
+        match store.get_btp_outgoing_accounts().await {
+            Ok(accounts) => leases.extend(accounts.iter().map(|account| btp_lease(account.id()))),
+            Err(err) => {
+                warn!(target: "interledger-node", "Error loading the accounts with outgoing BTP connections: {}", err)
+            }
+        }
+        // Leases held by other nodes are left as they are
+        for lease in leases.iter() {
+            if let Err(err) = store.release_lease(lease, &self.node_id).await {
+                warn!(target: "interledger-node", "Error releasing the lease {}: {}", lease, err);
+            }
+        }
+        info!(target: "interledger-node", "Node {} released its leases", self.node_id);
+    }
+
+    /// Returns a channel for the settlements to queue in the store, and spawns a task which
+    /// queues them. The settlements which cannot be queued are refunded to the accounts.
+    #[cfg(feature = "balance-tracking")]
+    pub fn spawn_settlement_queue<S>(&self, store: S) -> UnboundedSender<(Uuid, u64)>
+    where
+        S: SettlementQueueStore + SettlementStore + Send + Sync + 'static,
+    {
+        let (sender, mut receiver) = mpsc::unbounded_channel::<(Uuid, u64)>();
+        tokio::spawn(async move {
+            while let Some((account_id, amount)) = receiver.recv().await {
+                if let Err(err) = store.queue_settlement(account_id, amount).await {
+                    error!(target: "interledger-node", "Error queueing the settlement of {} to account {}, refunding it: {}", amount, account_id, err);
+                    if let Err(err) = store.refund_settlement(account_id, amount).await {
+                        error!(target: "interledger-node", "Error refunding the settlement of {} to account {}: {}", amount, account_id, err);
+                    }
+                }
+            }
+        });
+        sender
+    }
+
+    /// Schedules the task which sends the settlements queued by the workers to the settlement
+    /// engines of the accounts, while this node is the leader. Only control nodes send them.
+    pub fn schedule_settlement_sender<S>(
+        &self,
+        scheduler: &Scheduler,
+        store: S,
+        settlement_client: SettlementClient,
+        is_leader: Arc<AtomicBool>,
+    ) where
+        S: SettlementQueueStore
+            + SettlementStore<Account = Account>
+            + AccountStore<Account = Account>
+            + Clone
+            + Send
+            + Sync
+            + 'static,
+    {
+        if self.role != ClusterRole::Control {
+            return;
+        }
+        scheduler
+            .task("settlement_queue", SETTLEMENT_QUEUE_POLL_INTERVAL)
+            .only_while(is_leader.clone())
+            .spawn(move || {
+                send_queued_settlements(store.clone(), settlement_client.clone(), is_leader.clone())
+            });
+    }
+
+    /// Returns the accounts whose outgoing BTP connection this node
+    /// may open, because it acquired their connection lease
+    pub async fn claim_btp_accounts<S>(&self, store: &S, accounts: Vec<Account>) -> Vec<Account>
+    where
+        S: LeaseStore,
+    {
+        let mut claimed = Vec::with_capacity(accounts.len());
+        for account in accounts {
+            match store
+                .acquire_lease(&btp_lease(account.id()), &self.node_id, self.lease_ttl)
+                .await
+            {
+                Ok(true) => claimed.push(account),
+                Ok(false) => {
+                    debug!(target: "interledger-node", "Not connecting to account {} via BTP because another node owns the connection", account.username())
+                }
+                Err(err) => {
+                    error!(target: "interledger-node", "Error acquiring the BTP connection lease of account {}: {}", account.username(), err)
+                }
+            }
+        }
+        claimed
+    }
+
+    /// Spawns a task which renews the leases of the outgoing BTP connections owned by this
+    /// node, takes over the connections whose owner stopped renewing their lease, and closes
+    /// the connections which are owned by another node. Each account's outgoing BTP
+    /// connection is thereby kept open by exactly one of the nodes sharing the store.
+    pub fn spawn_btp_ownership<S, O>(
+        &self,
+        store: S,
+        owned: Vec<Uuid>,
+        btp: BtpOutgoingService<O, Account>,
+    ) where
+        S: LeaseStore + BtpStore<Account = Account> + Send + Sync + 'static,
+        O: OutgoingService<Account> + Clone + Send + Sync + 'static,
+    {
+        let coordinator = self.clone();
+        let mut owned: HashSet<Uuid> = owned.into_iter().collect();
+        tokio::spawn(async move {
+            let period = coordinator.renew_interval();
+            let mut interval = tokio::time::interval_at(Instant::now() + period, period);
+            loop {
+                interval.tick().await;
+                match store.get_btp_outgoing_accounts().await {
+                    Ok(accounts) => {
+                        owned = coordinator
+                            .renew_btp_ownership(&store, accounts, &owned, &btp)
+                            .await;
+                    }
+                    Err(err) => {
+                        warn!(target: "interledger-node", "Error loading the accounts with outgoing BTP connections: {}", err)
+                    }
+                }
+            }
+        });
+    }
+
+    /// Returns the ids of the accounts whose outgoing BTP connection is now owned by this node
+    async fn renew_btp_ownership<S, O>(
+        &self,
+        store: &S,
+        accounts: Vec<Account>,
+        owned: &HashSet<Uuid>,
+        btp: &BtpOutgoingService<O, Account>,
+    ) -> HashSet<Uuid>
+    where
+        S: LeaseStore,
+        O: OutgoingService<Account> + Clone + Send + Sync + 'static,
+    {
+        let mut still_owned = HashSet::with_capacity(owned.len());
+        for account in accounts {
+            let id = account.id();
+            let lease = btp_lease(id);
+            match store
+                .acquire_lease(&lease, &self.node_id, self.lease_ttl)
+                .await
+            {
+                Ok(true) if owned.contains(&id) => {
+                    still_owned.insert(id);
+                }
+                Ok(true) => {
+                    let username = account.username().clone();
+                    match connect_to_service_account(account, false, btp.clone()).await {
+                        Ok(_) => {
+                            info!(target: "interledger-node", "Took over the outgoing BTP connection of account {}", username);
+                            still_owned.insert(id);
+                        }
+                        Err(err) => {
+                            warn!(target: "interledger-node", "Error connecting to account {} via BTP: {}", username, err);
+                            // Let another node try to connect
+                            let _ = store.release_lease(&lease, &self.node_id).await;
+                        }
+                    }
+                }
+                Ok(false) => {
+                    if owned.contains(&id) {
+                        info!(target: "interledger-node", "Another node took over the outgoing BTP connection of account {}", account.username());
+                    }
+                    // This also closes connections opened via the API on this node
+                    btp.close_connection(&id);
+                }
+                Err(err) => {
+                    warn!(target: "interledger-node", "Error renewing the BTP connection lease of account {}: {}", account.username(), err);
+                    // Keep the connection while the store is unreachable
+                    if owned.contains(&id) {
+                        still_owned.insert(id);
+                    }
+                }
+            }
+        }
+        still_owned
+    }
+}
+
+/// Sends the settlements queued by the workers until the queue is empty
+/// (or this node is not the leader anymore)
+async fn send_queued_settlements<S>(
+    store: S,
+    settlement_client: SettlementClient,
+    is_leader: Arc<AtomicBool>,
+) -> Result<(), String>
+where
+    S: SettlementQueueStore + SettlementStore<Account = Account> + AccountStore<Account = Account>,
+{
+    while is_leader.load(Ordering::Relaxed) {
+        let (account_id, amount) = match store.take_queued_settlement().await {
+            Ok(Some(settlement)) => settlement,
+            Ok(None) => break,
+            Err(err) => {
+                return Err(format!(
+                    "Error taking the settlements queued by the workers: {}",
+                    err
+                ))
+            }
+        };
+        let account = match store.get_accounts(vec![account_id]).await {
+            Ok(mut accounts) => accounts.pop(),
+            // The account was deleted after the settlement was queued
+            Err(AccountStoreError::WrongLength { .. }) => {
+                warn!(target: "interledger-node", "Dropping the settlement of {} queued for account {}, which no longer exists", amount, account_id);
+                continue;
+            }
+            Err(err) => {
+                warn!(target: "interledger-node", "Error loading account {}, queueing its settlement again: {}", account_id, err);
+                if let Err(err) = store.queue_settlement(account_id, amount).await {
+                    error!(target: "interledger-node", "Error queueing the settlement of {} to account {} again: {}", amount, account_id, err);
+                }
+                return Err(format!("Error loading account {}: {}", account_id, err));
+            }
+        };
+        if let Some(account) = account {
+            if send_settlement(&store, &settlement_client, &account, amount)
+                .await
+                .is_err()
+            {
+                warn!(target: "interledger-node", "The settlement engine of account {} did not accept the settlement of {}, it was refunded", account.username(), amount);
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,93 @@
+//! Configuration of the node via environment variables.
+//!
+//! Every setting can be set with an environment variable named after its key, in capitals,
+//! prefixed with `ILP_` and with the keys of nested settings separated by `__` (two
+//! underscores): `ILP_EXCHANGE_RATE__SPREAD` sets `exchange_rate.spread`. The settings which
+//! are lists, or tables which are easier to write in one go (such as `alerting.rules`), are
+//! set with a JSON value, which is any value starting with `[` or `{`.
+
+use config::{Config, ConfigError, FileFormat, Source, Value};
+use std::collections::HashMap;
+
+/// Prefix of the environment variables of the node's settings
+pub const ENV_PREFIX: &str = "ILP_";
+/// Separator of the keys of nested settings in the names of the environment variables
+const SEPARATOR: &str = "__";
+
+/// Returns the configuration set by the environment variables with the `ILP_` prefix
+pub fn config_from_env<I>(vars: I) -> Result<Config, ConfigError>
+where
+    I: IntoIterator<Item = (String, String)>,
+{
+    let mut config = Config::new();
+    for (name, value) in vars {
+        if name.len() <= ENV_PREFIX.len()
+            || !name[..ENV_PREFIX.len()].eq_ignore_ascii_case(ENV_PREFIX)
+        {
+            continue;
+        }
+        let key = name[ENV_PREFIX.len()..]
+            .to_lowercase()
+            .replace(SEPARATOR, ".");
+        let trimmed = value.trim_start();
+        if trimmed.starts_with('[') || trimmed.starts_with('{') {
+            config.set(&key, parse_json(&name, &value)?)?;
+        } else {
+            config.set(&key, value)?;
+        }
+    }
+
+    // `ILP_ADDRESS` is resolved to the `ilp_address`
+    if let Ok(value) = config.get_str("address") {
+        config.set("ilp_address", value)?;
+    }
+    Ok(config)
+}
+
+fn parse_json(name: &str, json: &str) -> Result<Value, ConfigError> {
+    FileFormat::Json
+        .parse(None, &format!("{{\"value\": {}}}", json))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .ok_or_else(|| ConfigError::Message(format!("{} is not valid JSON", name)))
+}
+
+/// Returns the environment variables which set the same configuration, sorted by name.
+/// This is the inverse of [`config_from_env`](./fn.config_from_env.html).
+pub fn env_vars(config: &Config) -> Result<Vec<(String, String)>, ConfigError> {
+    let mut vars = Vec::new();
+    flatten(&mut vars, None, config.collect()?)?;
+    vars.sort();
+    Ok(vars)
+}
+
+/// Returns the name of the environment variable of the key (with its parents separated by `.`)
+pub fn env_var_name(key: &str) -> String {
+    format!(
+        "{}{}",
+        ENV_PREFIX,
+        key.to_uppercase().replace('.', SEPARATOR)
+    )
+}
+
+fn flatten(
+    vars: &mut Vec<(String, String)>,
+    parent: Option<&str>,
+    table: HashMap<String, Value>,
+) -> Result<(), ConfigError> {
+    for (key, value) in table {
+        let key = match parent {
+            Some(parent) => format!("{}.{}", parent, key),
+            None => key,
+        };
+        if let Ok(table) = value.clone().into_table() {
+            flatten(vars, Some(&key), table)?;
+        } else if let Ok(array) = value.clone().into_array() {
+            let json: serde_json::Value = Value::from(array).try_into()?;
+            vars.push((env_var_name(&key), json.to_string()));
+        } else if let Ok(value) = value.into_str() {
+            vars.push((env_var_name(&key), value));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,48 @@
+use futures::future::BoxFuture;
+use interledger::packet::Address;
+use std::{future::Future, sync::Arc};
+
+type StartedHook = Arc<dyn Fn(&Address) + Send + Sync>;
+type StoppedHook = Arc<dyn Fn() -> BoxFuture<'static, ()> + Send + Sync>;
+
+/// Callbacks of the programs which embed the node, which are called as the node starts and stops
+#[derive(Clone, Default)]
+pub struct Hooks {
+    started: Vec<StartedHook>,
+    stopped: Vec<StoppedHook>,
+}
+
+impl Hooks {
+    /// Adds a callback which is called with the ILP address of the node once it
+    /// accepts connections and packets
+    pub fn on_started<F>(&mut self, hook: F) -> &mut Self
+    where
+        F: Fn(&Address) + Send + Sync + 'static,
+    {
+        self.started.push(Arc::new(hook));
+        self
+    }
+
+    /// Adds a callback which runs once the node stopped accepting connections and packets,
+    /// and the in-flight requests completed. The node waits for it (until the shutdown
+    /// deadline passes) before it exits.
+    pub fn on_stopped<F, R>(&mut self, hook: F) -> &mut Self
+    where
+        F: Fn() -> R + Send + Sync + 'static,
+        R: Future<Output = ()> + Send + 'static,
+    {
+        self.stopped
+            .push(Arc::new(move || Box::pin(hook()) as BoxFuture<'static, ()>));
+        self
+    }
+
+    pub(crate) fn started(&self, ilp_address: &Address) {
+        for hook in self.started.iter() {
+            hook(ilp_address);
+        }
+    }
+
+    pub(crate) fn stopped(&self) -> impl Iterator<Item = BoxFuture<'static, ()>> + '_ {
+        self.stopped.iter().map(|hook| hook())
+    }
+}
@@ -0,0 +1,609 @@
+use clap::{App, Arg, ArgMatches};
+use interledger::{packet::Address, service::Username};
+use ring::rand::{SecureRandom, SystemRandom};
+use std::{
+    ffi::OsString,
+    fs::{self, OpenOptions},
+    io::{self, BufRead, Write},
+    iter,
+    net::SocketAddr,
+    path::Path,
+    str::FromStr,
+};
+use url::Url;
+
+const DEFAULT_CONFIG_PATH: &str = "config.yml";
+const DEFAULT_ILP_ADDRESS: &str = "private.node";
+const DEFAULT_DATABASE_URL: &str = "redis://127.0.0.1:6379";
+const DEFAULT_HTTP_BIND_ADDRESS: &str = "127.0.0.1:7770";
+
+fn init_app<'a, 'b>() -> App<'a, 'b> {
+    App::new("ilp-node init")
+        .about("Generates the configuration of a new node, with a random secret seed and admin token, and optionally the account of its first peer. The values which are not passed as arguments are asked for, unless --non_interactive is set (or stdin is not a terminal), in which case their defaults are used.")
+        .args(&[
+            Arg::with_name("output")
+                .long("output")
+                .takes_value(true)
+                .help("Path of the config file to write. Defaults to config.yml."),
+            Arg::with_name("force")
+                .long("force")
+                .help("Overwrite the config file (and the account of the first peer) if it exists."),
+            Arg::with_name("non_interactive")
+                .long("non_interactive")
+                .help("Do not ask for the values which are not passed as arguments, and use their defaults."),
+            Arg::with_name("ilp_address")
+                .long("ilp_address")
+                .takes_value(true)
+                .conflicts_with("parent")
+                .help("ILP address of the node. Defaults to private.node."),
+            Arg::with_name("parent")
+                .long("parent")
+                .help("The node gets its ILP address from its first peer, which is its parent, via IL-DCP."),
+            Arg::with_name("database_url")
+                .long("database_url")
+                .takes_value(true)
+                .help("URL of the Redis database. Defaults to redis://127.0.0.1:6379."),
+            Arg::with_name("http_bind_address")
+                .long("http_bind_address")
+                .takes_value(true)
+                .help("IP address and port the HTTP API listens on. Defaults to 127.0.0.1:7770."),
+            Arg::with_name("peer.username")
+                .long("peer.username")
+                .takes_value(true)
+                .help("Username of the account of the first peer. The account is only set up if this or --parent is set."),
+            Arg::with_name("peer.asset_code")
+                .long("peer.asset_code")
+                .takes_value(true)
+                .help("Asset code of the account of the first peer."),
+            Arg::with_name("peer.asset_scale")
+                .long("peer.asset_scale")
+                .takes_value(true)
+                .help("Asset scale of the account of the first peer. Defaults to 9."),
+            Arg::with_name("peer.routing_relation")
+                .long("peer.routing_relation")
+                .takes_value(true)
+                .possible_values(&["Peer", "Child"])
+                .help("Routing relation of the first peer, unless it is the parent. Defaults to Peer."),
+            Arg::with_name("peer.ilp_over_http_url")
+                .long("peer.ilp_over_http_url")
+                .takes_value(true)
+                .help("URL of the ILP over HTTP endpoint of the first peer."),
+            Arg::with_name("peer.ilp_over_http_outgoing_token")
+                .long("peer.ilp_over_http_outgoing_token")
+                .takes_value(true)
+                .help("Token with which the node authenticates to the first peer, which the peer's operator provides."),
+        ])
+}
+
+/// The account of the first peer, which is written as the body of a `POST /accounts` request
+struct Peer {
+    username: String,
+    asset_code: String,
+    asset_scale: u8,
+    routing_relation: &'static str,
+    ilp_over_http_url: String,
+    ilp_over_http_incoming_token: String,
+    ilp_over_http_outgoing_token: String,
+}
+
+/// Asks for the values which are not passed as arguments
+struct Wizard<'a> {
+    matches: ArgMatches<'a>,
+    interactive: bool,
+}
+
+impl<'a> Wizard<'a> {
+    fn prompt(&self, question: &str) -> Result<String, String> {
+        print!("{}: ", question);
+        io::stdout().flush().map_err(|err| err.to_string())?;
+        let mut answer = String::new();
+        io::stdin()
+            .lock()
+            .read_line(&mut answer)
+            .map_err(|err| format!("Error reading the answer: {}", err))?;
+        Ok(answer.trim().to_string())
+    }
+
+    /// Returns the value of the argument, or the answer to the question, or the default
+    fn ask(&self, name: &str, question: &str, default: Option<&str>) -> Result<String, String> {
+        if let Some(value) = self.matches.value_of(name) {
+            return Ok(value.to_string());
+        }
+        let answer = if self.interactive {
+            match default {
+                Some(default) => self.prompt(&format!("{} [{}]", question, default))?,
+                None => self.prompt(question)?,
+            }
+        } else {
+            String::new()
+        };
+        match (answer.is_empty(), default) {
+            (false, _) => Ok(answer),
+            (true, Some(default)) => Ok(default.to_string()),
+            (true, None) => Err(format!("--{} must be set", name)),
+        }
+    }
+
+    /// Returns whether the flag is set, or the answer to the question
+    fn confirm(&self, name: &str, question: &str) -> Result<bool, String> {
+        if self.matches.is_present(name) {
+            return Ok(true);
+        }
+        if !self.interactive {
+            return Ok(false);
+        }
+        let answer = self.prompt(&format!("{} [y/N]", question))?;
+        Ok(answer.eq_ignore_ascii_case("y") || answer.eq_ignore_ascii_case("yes"))
+    }
+}
+
+/// Runs `ilp-node init` with the arguments following `init`
+pub fn run_init<I>(args: I, stdin_is_tty: bool) -> Result<(), String>
+where
+    I: Iterator<Item = OsString>,
+{
+    let matches =
+        init_app().get_matches_from(iter::once(OsString::from("ilp-node init")).chain(args));
+    let output = matches
+        .value_of("output")
+        .unwrap_or(DEFAULT_CONFIG_PATH)
+        .to_string();
+    let force = matches.is_present("force");
+    let interactive = stdin_is_tty && !matches.is_present("non_interactive");
+    let wizard = Wizard {
+        matches,
+        interactive,
+    };
+    if !force && Path::new(&output).exists() {
+        return Err(format!(
+            "{} already exists, use --force to overwrite it",
+            output
+        ));
+    }
+
+    let parent = !wizard.matches.is_present("ilp_address")
+        && wizard.confirm(
+            "parent",
+            "Does the node get its ILP address from a parent node (via IL-DCP)?",
+        )?;
+    let ilp_address = if parent {
+        None
+    } else {
+        let ilp_address = wizard.ask(
+            "ilp_address",
+            "ILP address of the node",
+            Some(DEFAULT_ILP_ADDRESS),
+        )?;
+        Address::from_str(&ilp_address)
+            .map_err(|err| format!("Invalid ILP address {}: {}", ilp_address, err))?;
+        Some(ilp_address)
+    };
+    let database_url = wizard.ask(
+        "database_url",
+        "URL of the Redis database",
+        Some(DEFAULT_DATABASE_URL),
+    )?;
+    Url::parse(&database_url)
+        .map_err(|err| format!("Invalid database URL {}: {}", database_url, err))?;
+    let http_bind_address = wizard.ask(
+        "http_bind_address",
+        "IP address and port the HTTP API listens on",
+        Some(DEFAULT_HTTP_BIND_ADDRESS),
+    )?;
+    SocketAddr::from_str(&http_bind_address)
+        .map_err(|err| format!("Invalid bind address {}: {}", http_bind_address, err))?;
+
+    let peer = if parent
+        || wizard.matches.is_present("peer.username")
+        || wizard.confirm("peer", "Set up the account of a first peer?")?
+    {
+        Some(ask_peer(&wizard, parent)?)
+    } else {
+        None
+    };
+
+    let admin_auth_token = random_token()?;
+    let mut config = vec![
+        ("secret_seed", random_token()?),
+        ("admin_auth_token", admin_auth_token.clone()),
+    ];
+    if let Some(ilp_address) = ilp_address {
+        config.push(("ilp_address", ilp_address));
+    }
+    config.push(("database_url", database_url));
+    config.push(("http_bind_address", http_bind_address.clone()));
+    let config: String = config
+        .iter()
+        .map(|(key, value)| format!("{}: {}\n", key, quote(value)))
+        .collect();
+    write_secret_file(
+        &output,
+        format!("# Generated by `ilp-node init`\n{}", config),
+        force,
+    )?;
+    println!("Wrote the configuration of the node to {}", output);
+    println!("Admin auth token: {}", admin_auth_token);
+
+    if let Some(peer) = peer {
+        let account_path = Path::new(&output)
+            .with_file_name(format!("{}.json", peer.username))
+            .to_string_lossy()
+            .into_owned();
+        write_secret_file(&account_path, peer.to_json(), force)?;
+        println!(
+            "Wrote the account of the first peer to {}. Start the node with `ilp-node {}`, and then register the account with:",
+            account_path, output
+        );
+        println!(
+            "  curl -X POST -H \"Authorization: Bearer {}\" -H \"Content-Type: application/json\" -d @{} http://{}/accounts",
+            admin_auth_token, account_path, http_bind_address
+        );
+        if parent {
+            println!(
+                "The node gets its ILP address from the parent once the account is registered."
+            );
+        }
+        println!(
+            "The peer authenticates to the node with the token: {}",
+            peer.ilp_over_http_incoming_token
+        );
+    } else {
+        println!("Start the node with `ilp-node {}`", output);
+    }
+    Ok(())
+}
+
+fn ask_peer(wizard: &Wizard, parent: bool) -> Result<Peer, String> {
+    let username = wizard.ask(
+        "peer.username",
+        "Username of the peer's account",
+        Some(if parent { "parent" } else { "peer" }),
+    )?;
+    Username::from_str(&username)
+        .map_err(|err| format!("Invalid username {}: {}", username, err))?;
+    let asset_code = wizard.ask("peer.asset_code", "Asset code of the account", None)?;
+    let asset_scale = wizard.ask("peer.asset_scale", "Asset scale of the account", Some("9"))?;
+    let asset_scale = u8::from_str(&asset_scale)
+        .map_err(|err| format!("Invalid asset scale {}: {}", asset_scale, err))?;
+    let routing_relation = if parent {
+        "Parent"
+    } else {
+        match wizard
+            .ask(
+                "peer.routing_relation",
+                "Routing relation of the peer (Peer or Child)",
+                Some("Peer"),
+            )?
+            .as_str()
+        {
+            "Peer" => "Peer",
+            "Child" => "Child",
+            other => return Err(format!("Invalid routing relation {}", other)),
+        }
+    };
+    let ilp_over_http_url = wizard.ask(
+        "peer.ilp_over_http_url",
+        "URL of the peer's ILP over HTTP endpoint",
+        None,
+    )?;
+    Url::parse(&ilp_over_http_url)
+        .map_err(|err| format!("Invalid URL {}: {}", ilp_over_http_url, err))?;
+    let ilp_over_http_outgoing_token = wizard.ask(
+        "peer.ilp_over_http_outgoing_token",
+        "Token with which the node authenticates to the peer",
+        None,
+    )?;
+    Ok(Peer {
+        username,
+        asset_code,
+        asset_scale,
+        routing_relation,
+        ilp_over_http_url,
+        ilp_over_http_incoming_token: random_token()?,
+        ilp_over_http_outgoing_token,
+    })
+}
+
+impl Peer {
+    fn to_json(&self) -> String {
+        format!(
+            "{{\n  \"username\": {},\n  \"asset_code\": {},\n  \"asset_scale\": {},\n  \"routing_relation\": {},\n  \"ilp_over_http_url\": {},\n  \"ilp_over_http_incoming_token\": {},\n  \"ilp_over_http_outgoing_token\": {}\n}}\n",
+            quote(&self.username),
+            quote(&self.asset_code),
+            self.asset_scale,
+            quote(self.routing_relation),
+            quote(&self.ilp_over_http_url),
+            quote(&self.ilp_over_http_incoming_token),
+            quote(&self.ilp_over_http_outgoing_token),
+        )
+    }
+}
+
+/// Returns 32 random bytes, hex-encoded
+pub fn random_token() -> Result<String, String> {
+    let mut bytes = [0; 32];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .map_err(|_| "Error generating random bytes".to_string())?;
+    Ok(hex::encode(bytes))
+}
+
+/// Quotes the string, which is then valid both in JSON and in YAML
+pub fn quote(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Writes the file, which only its owner can read since it contains secrets
+pub fn write_secret_file(
+    path: &str,
+    contents: impl AsRef<[u8]>,
+    overwrite: bool,
+) -> Result<(), String> {
+    let mut options = OpenOptions::new();
+    options.write(true);
+    if overwrite {
+        options.create(true).truncate(true);
+    } else {
+        options.create_new(true);
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(path)
+        .map_err(|err| format!("Error creating {}: {}", path, err))?;
+    file.write_all(contents.as_ref())
+        .map_err(|err| format!("Error writing {}: {}", path, err))?;
+    // The mode only applies to new files
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))
+            .map_err(|err| format!("Error restricting the permissions of {}: {}", path, err))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::InterledgerNode;
+    use config::Config;
+    use interledger::api::AccountDetails;
+    use secrecy::ExposeSecret;
+    use std::{env, path::PathBuf};
+
+    /// Runs `ilp-node init` non-interactively in a new directory, and returns the
+    /// directory with the result
+    fn init(name: &str, args: &[&str]) -> (PathBuf, Result<(), String>) {
+        let dir = env::temp_dir().join(format!("ilp-node-init-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("config.yml").to_string_lossy().into_owned();
+        let args = ["--output", &output]
+            .iter()
+            .chain(args)
+            .map(OsString::from)
+            .collect::<Vec<_>>();
+        let result = run_init(args.into_iter(), false);
+        (dir, result)
+    }
+
+    fn load_node(dir: &Path) -> InterledgerNode {
+        let mut config = Config::new();
+        crate::merge_config_file(&dir.join("config.yml").to_string_lossy(), &mut config).unwrap();
+        config.try_into().unwrap()
+    }
+
+    fn load_account(path: PathBuf) -> AccountDetails {
+        serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    #[cfg(unix)]
+    fn mode(path: PathBuf) -> u32 {
+        use std::os::unix::fs::PermissionsExt;
+        fs::metadata(path).unwrap().permissions().mode() & 0o777
+    }
+
+    #[test]
+    fn writes_the_config_of_a_node() {
+        let (dir, result) = init(
+            "node",
+            &[
+                "--ilp_address",
+                "example.node",
+                "--http_bind_address",
+                "127.0.0.1:8770",
+            ],
+        );
+        result.unwrap();
+        let node = load_node(&dir);
+        assert_eq!(
+            node.ilp_address,
+            Some(Address::from_str("example.node").unwrap())
+        );
+        assert_eq!(node.database_url, DEFAULT_DATABASE_URL);
+        assert_eq!(node.http_bind_address, "127.0.0.1:8770".parse().unwrap());
+        assert_eq!(node.admin_auth_token.len(), 64);
+        assert_ne!(hex::encode(node.secret_seed), node.admin_auth_token);
+        // Without a peer, only the config is written
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        #[cfg(unix)]
+        assert_eq!(mode(dir.join("config.yml")), 0o600);
+
+        // The secrets are generated again for every node
+        let (other_dir, result) = init("other-node", &[]);
+        result.unwrap();
+        let other_node = load_node(&other_dir);
+        assert_eq!(
+            other_node.ilp_address,
+            Some(Address::from_str(DEFAULT_ILP_ADDRESS).unwrap())
+        );
+        assert_ne!(other_node.secret_seed, node.secret_seed);
+        assert_ne!(other_node.admin_auth_token, node.admin_auth_token);
+        fs::remove_dir_all(dir).unwrap();
+        fs::remove_dir_all(other_dir).unwrap();
+    }
+
+    #[test]
+    fn writes_the_account_of_the_parent() {
+        let (dir, result) = init(
+            "parent",
+            &[
+                "--parent",
+                "--peer.asset_code",
+                "XRP",
+                "--peer.ilp_over_http_url",
+                "https://parent.example/accounts/child/ilp",
+                "--peer.ilp_over_http_outgoing_token",
+                "token with \"quotes\"",
+            ],
+        );
+        result.unwrap();
+        // The node gets its address from the parent
+        assert_eq!(load_node(&dir).ilp_address, None);
+        let account = load_account(dir.join("parent.json"));
+        assert_eq!(account.username.as_ref(), "parent");
+        assert_eq!(account.asset_code, "XRP");
+        assert_eq!(account.asset_scale, 9);
+        assert_eq!(account.routing_relation.as_deref(), Some("Parent"));
+        assert_eq!(
+            account.ilp_over_http_url.as_deref(),
+            Some("https://parent.example/accounts/child/ilp")
+        );
+        assert_eq!(
+            account
+                .ilp_over_http_outgoing_token
+                .as_ref()
+                .map(|token| token.expose_secret().as_str()),
+            Some("token with \"quotes\"")
+        );
+        assert_eq!(
+            account
+                .ilp_over_http_incoming_token
+                .as_ref()
+                .map(|token| token.expose_secret().len()),
+            Some(64)
+        );
+        #[cfg(unix)]
+        assert_eq!(mode(dir.join("parent.json")), 0o600);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn writes_the_account_of_a_peer() {
+        let (dir, result) = init(
+            "peer",
+            &[
+                "--peer.username",
+                "alice",
+                "--peer.asset_code",
+                "ABC",
+                "--peer.asset_scale",
+                "6",
+                "--peer.routing_relation",
+                "Child",
+                "--peer.ilp_over_http_url",
+                "http://localhost:8770/accounts/node/ilp",
+                "--peer.ilp_over_http_outgoing_token",
+                "token",
+            ],
+        );
+        result.unwrap();
+        assert!(load_node(&dir).ilp_address.is_some());
+        let account = load_account(dir.join("alice.json"));
+        assert_eq!(account.username.as_ref(), "alice");
+        assert_eq!(account.asset_scale, 6);
+        assert_eq!(account.routing_relation.as_deref(), Some("Child"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_invalid_or_missing_values() {
+        for (name, args, error) in &[
+            (
+                "address",
+                vec!["--ilp_address", "not an address"],
+                "Invalid ILP address not an address",
+            ),
+            (
+                "database",
+                vec!["--database_url", "not a url"],
+                "Invalid database URL not a url",
+            ),
+            (
+                "bind",
+                vec!["--http_bind_address", "localhost"],
+                "Invalid bind address localhost",
+            ),
+            ("asset", vec!["--parent"], "--peer.asset_code must be set"),
+            (
+                "scale",
+                vec![
+                    "--parent",
+                    "--peer.asset_code",
+                    "XRP",
+                    "--peer.asset_scale",
+                    "256",
+                ],
+                "Invalid asset scale 256",
+            ),
+        ] {
+            let (dir, result) = init(name, args);
+            assert!(result.unwrap_err().starts_with(error));
+            // Nothing is written unless all values are valid
+            assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+            fs::remove_dir_all(dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn only_overwrites_the_config_if_forced() {
+        let (dir, result) = init("force", &[]);
+        result.unwrap();
+        let output = dir.join("config.yml").to_string_lossy().into_owned();
+        let config = fs::read_to_string(&output).unwrap();
+
+        let result = run_init(
+            vec![OsString::from("--output"), OsString::from(&output)].into_iter(),
+            false,
+        );
+        assert!(result
+            .unwrap_err()
+            .ends_with("already exists, use --force to overwrite it"));
+        assert_eq!(fs::read_to_string(&output).unwrap(), config);
+
+        let args = vec!["--output", &output, "--force"];
+        run_init(args.into_iter().map(OsString::from), false).unwrap();
+        assert_ne!(fs::read_to_string(&output).unwrap(), config);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn quotes_strings_for_json_and_yaml() {
+        let value = "a \"quoted\" \\ value\nwith: yaml # syntax";
+        let quoted = quote(value);
+        assert_eq!(
+            quoted,
+            "\"a \\\"quoted\\\" \\\\ value\\u000awith: yaml # syntax\""
+        );
+        assert_eq!(serde_json::from_str::<String>(&quoted).unwrap(), value);
+        let yaml = config::FileFormat::Yaml
+            .parse(None, &format!("key: {}", quoted))
+            .unwrap();
+        assert_eq!(yaml["key"].clone().into_str().unwrap(), value);
+    }
+}
@@ -1,17 +1,22 @@
 use interledger::{
+    api::NodeStore,
     ccp::CcpRoutingAccount,
+    packet::{ErrorClass, Reject},
+    router::RouterStore,
     service::{
         Account, IlpResult, IncomingRequest, IncomingService, OutgoingRequest, OutgoingService,
     },
+    service_util::{BalanceStore, Scheduler},
 };
-use metrics::{self, labels, recorder, Key};
-use std::time::Instant;
+use metrics::{self, labels, recorder, Key, Label};
+use std::time::{Duration, Instant};
 
 pub async fn incoming_metrics<A: Account + CcpRoutingAccount>(
     request: IncomingRequest<A>,
     mut next: Box<dyn IncomingService<A> + Send>,
 ) -> IlpResult {
     let labels = labels!(
+        "from_account" => request.from.username().to_string(),
         "from_asset_code" => request.from.asset_code().to_string(),
         "from_routing_relation" => request.from.routing_relation().to_string(),
     );
@@ -22,16 +27,15 @@ pub async fn incoming_metrics<A: Account + CcpRoutingAccount>(
     let start_time = Instant::now();
 
     let result = next.handle_request(request).await;
-    if result.is_ok() {
-        recorder().increment_counter(
+    match result {
+        Ok(_) => recorder().increment_counter(
             Key::from_name_and_labels("requests.incoming.fulfill", labels.clone()),
             1,
-        );
-    } else {
-        recorder().increment_counter(
-            Key::from_name_and_labels("requests.incoming.reject", labels.clone()),
+        ),
+        Err(ref reject) => recorder().increment_counter(
+            Key::from_name_and_labels("requests.incoming.reject", reject_labels(&labels, reject)),
             1,
-        );
+        ),
     }
 
     recorder().record_histogram(
@@ -46,6 +50,8 @@ pub async fn outgoing_metrics<A: Account + CcpRoutingAccount>(
     mut next: Box<dyn OutgoingService<A> + Send>,
 ) -> IlpResult {
     let labels = labels!(
+        "from_account" => request.from.username().to_string(),
+        "to_account" => request.to.username().to_string(),
         "from_asset_code" => request.from.asset_code().to_string(),
         "to_asset_code" => request.to.asset_code().to_string(),
         "from_routing_relation" => request.from.routing_relation().to_string(),
@@ -61,16 +67,15 @@ pub async fn outgoing_metrics<A: Account + CcpRoutingAccount>(
     let start_time = Instant::now();
 
     let result = next.send_request(request).await;
-    if result.is_ok() {
-        recorder().increment_counter(
+    match result {
+        Ok(_) => recorder().increment_counter(
             Key::from_name_and_labels("requests.outgoing.fulfill", labels.clone()),
             1,
-        );
-    } else {
-        recorder().increment_counter(
-            Key::from_name_and_labels("requests.outgoing.reject", labels.clone()),
+        ),
+        Err(ref reject) => recorder().increment_counter(
+            Key::from_name_and_labels("requests.outgoing.reject", reject_labels(&labels, reject)),
             1,
-        );
+        ),
     }
 
     recorder().record_histogram(
@@ -80,3 +85,286 @@ pub async fn outgoing_metrics<A: Account + CcpRoutingAccount>(
 
     result
 }
+
+/// Adds the code and the class (final, temporary or relative) of the reject to the labels
+fn reject_labels(labels: &[Label], reject: &Reject) -> Vec<Label> {
+    let code = reject.code();
+    let class = match code.class() {
+        ErrorClass::Final => "final",
+        ErrorClass::Temporary => "temporary",
+        ErrorClass::Relative => "relative",
+        ErrorClass::Unknown => "unknown",
+    };
+    let mut labels = labels.to_vec();
+    labels.push(Label::new("error_class", class));
+    labels.push(Label::new("error_code", code.to_string()));
+    labels
+}
+
+/// Schedules the task which records, every `interval`, the balance and the amount in flight
+/// of each account in the `account.balance` and `account.in_flight` gauges (labelled with the
+/// account's username and asset code), and the number of routes in the `routes` gauge
+pub fn schedule_store_metrics<S, A>(scheduler: &Scheduler, store: S, interval: Duration)
+where
+    S: NodeStore<Account = A> + BalanceStore + RouterStore + Clone + Send + Sync + 'static,
+    A: Account + Send + Sync + 'static,
+{
+    scheduler
+        .task("store_metrics", interval)
+        .spawn(move || record_store_metrics(store.clone()));
+}
+
+async fn record_store_metrics<S, A>(store: S) -> Result<(), String>
+where
+    S: NodeStore<Account = A> + BalanceStore + RouterStore,
+    A: Account,
+{
+    recorder().update_gauge(Key::from_name("routes"), store.routing_table().len() as i64);
+
+    let accounts = store.get_all_accounts().await.map_err(|err| {
+        format!(
+            "Error loading the accounts to record their balances: {}",
+            err
+        )
+    })?;
+    for account in accounts {
+        let balance = store.get_balance(account.id()).await;
+        let in_flight = store.get_in_flight_amount(account.id()).await;
+        let labels = labels!(
+            "account" => account.username().to_string(),
+            "asset_code" => account.asset_code().to_string(),
+        );
+        if let Ok(balance) = balance {
+            recorder().update_gauge(
+                Key::from_name_and_labels("account.balance", labels.clone()),
+                balance,
+            );
+        }
+        if let Ok(in_flight) = in_flight {
+            recorder().update_gauge(
+                Key::from_name_and_labels("account.in_flight", labels),
+                in_flight as i64,
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use interledger::{
+        ccp::RoutingRelation,
+        packet::{Address, ErrorCode, FulfillBuilder, PrepareBuilder, RejectBuilder},
+        service::{incoming_service_fn, outgoing_service_fn, Username},
+    };
+    use metrics::Recorder;
+    use once_cell::sync::Lazy;
+    use std::{
+        str::FromStr,
+        sync::{Mutex, Once},
+        time::SystemTime,
+    };
+    use uuid::Uuid;
+
+    /// The name and the labels of a recorded metric
+    type Recorded = (String, Vec<(String, String)>);
+
+    /// The metrics recorded by all the tests
+    static RECORDED: Lazy<Mutex<Vec<Recorded>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+    struct TestRecorder;
+
+    impl TestRecorder {
+        fn record(&self, key: Key) {
+            let (name, labels) = key.into_parts();
+            let labels = labels
+                .into_iter()
+                .map(|label| (label.key().to_owned(), label.value().to_owned()))
+                .collect();
+            RECORDED.lock().unwrap().push((name.to_string(), labels));
+        }
+    }
+
+    impl Recorder for TestRecorder {
+        fn increment_counter(&self, key: Key, _: u64) {
+            self.record(key)
+        }
+
+        fn update_gauge(&self, key: Key, _: i64) {
+            self.record(key)
+        }
+
+        fn record_histogram(&self, key: Key, _: u64) {
+            self.record(key)
+        }
+    }
+
+    static RECORDER: TestRecorder = TestRecorder;
+    static INSTALL_RECORDER: Once = Once::new();
+
+    /// Returns the metrics recorded for packets from the account (the tests use different
+    /// accounts, since they share the recorder)
+    fn recorded_from(username: &str) -> Vec<Recorded> {
+        RECORDED
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, labels)| {
+                labels
+                    .iter()
+                    .any(|(key, value)| key == "from_account" && value == username)
+            })
+            .cloned()
+            .collect()
+    }
+
+    fn label<'a>(labels: &'a [(String, String)], key: &str) -> Option<&'a str> {
+        labels
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    #[derive(Clone, Debug)]
+    struct TestAccount(Username);
+
+    impl Account for TestAccount {
+        fn id(&self) -> Uuid {
+            Uuid::nil()
+        }
+
+        fn username(&self) -> &Username {
+            &self.0
+        }
+
+        fn ilp_address(&self) -> &Address {
+            unimplemented!()
+        }
+
+        fn asset_scale(&self) -> u8 {
+            9
+        }
+
+        fn asset_code(&self) -> &str {
+            "XYZ"
+        }
+    }
+
+    impl CcpRoutingAccount for TestAccount {
+        fn routing_relation(&self) -> RoutingRelation {
+            RoutingRelation::Peer
+        }
+    }
+
+    fn account(username: &str) -> TestAccount {
+        TestAccount(Username::from_str(username).unwrap())
+    }
+
+    fn prepare() -> interledger::packet::Prepare {
+        PrepareBuilder {
+            destination: Address::from_str("example.destination").unwrap(),
+            amount: 100,
+            expires_at: SystemTime::now(),
+            execution_condition: &[0; 32],
+            data: &[],
+        }
+        .build()
+    }
+
+    #[tokio::test]
+    async fn records_incoming_fulfills() {
+        INSTALL_RECORDER.call_once(|| metrics::set_recorder(&RECORDER).unwrap());
+        let next = incoming_service_fn(|_| {
+            Ok(FulfillBuilder {
+                fulfillment: &[0; 32],
+                data: &[],
+            }
+            .build())
+        });
+        let request = IncomingRequest {
+            from: account("incoming_alice"),
+            prepare: prepare(),
+        };
+        incoming_metrics(request, Box::new(next)).await.unwrap();
+
+        let recorded = recorded_from("incoming_alice");
+        let names: Vec<&str> = recorded.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "requests.incoming.prepare",
+                "requests.incoming.fulfill",
+                "requests.incoming.duration"
+            ]
+        );
+        let labels = &recorded[0].1;
+        assert_eq!(label(labels, "from_asset_code"), Some("XYZ"));
+        assert_eq!(label(labels, "from_routing_relation"), Some("Peer"));
+        assert_eq!(label(labels, "error_code"), None);
+    }
+
+    #[tokio::test]
+    async fn labels_outgoing_rejects_with_their_code_and_class() {
+        INSTALL_RECORDER.call_once(|| metrics::set_recorder(&RECORDER).unwrap());
+        let next = outgoing_service_fn(|_| {
+            Err(RejectBuilder {
+                code: ErrorCode::T04_INSUFFICIENT_LIQUIDITY,
+                message: &[],
+                triggered_by: None,
+                data: &[],
+            }
+            .build())
+        });
+        let request = OutgoingRequest {
+            from: account("outgoing_alice"),
+            to: account("outgoing_bob"),
+            original_amount: 100,
+            prepare: prepare(),
+        };
+        outgoing_metrics(request, Box::new(next)).await.unwrap_err();
+
+        let recorded = recorded_from("outgoing_alice");
+        let names: Vec<&str> = recorded.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "requests.outgoing.prepare",
+                "requests.outgoing.reject",
+                "requests.outgoing.duration"
+            ]
+        );
+        let labels = &recorded[1].1;
+        assert_eq!(label(labels, "to_account"), Some("outgoing_bob"));
+        assert_eq!(label(labels, "error_code"), Some("T04"));
+        assert_eq!(label(labels, "error_class"), Some("temporary"));
+        // The duration is not split by the outcome of the packet
+        assert_eq!(label(&recorded[2].1, "error_code"), None);
+    }
+
+    #[test]
+    fn labels_the_class_of_rejects() {
+        let reject = |code| {
+            RejectBuilder {
+                code,
+                message: &[],
+                triggered_by: None,
+                data: &[],
+            }
+            .build()
+        };
+        let classes: Vec<String> = vec![
+            ErrorCode::F02_UNREACHABLE,
+            ErrorCode::T00_INTERNAL_ERROR,
+            ErrorCode::R00_TRANSFER_TIMED_OUT,
+            ErrorCode::new(*b"???"),
+        ]
+        .into_iter()
+        .map(|code| {
+            let labels = reject_labels(&[], &reject(code));
+            labels[0].value().to_owned()
+        })
+        .collect();
+        assert_eq!(classes, vec!["final", "temporary", "relative", "unknown"]);
+    }
+}
@@ -6,5 +6,8 @@ pub mod trace;
 #[cfg(feature = "monitoring")]
 pub mod prometheus;
 
+#[cfg(feature = "opentelemetry")]
+pub mod opentelemetry;
+
 #[cfg(feature = "google-pubsub")]
 pub mod google_pubsub;
@@ -0,0 +1,73 @@
+use opentelemetry_crate::{
+    sdk::{
+        self,
+        trace::{Sampler, Tracer},
+        Resource,
+    },
+    trace::TraceError,
+    KeyValue,
+};
+use opentelemetry_otlp::Uninstall;
+use serde::Deserialize;
+use tracing::Subscriber;
+use tracing_opentelemetry::OpenTelemetryLayer;
+use tracing_subscriber::registry::LookupSpan;
+
+/// Configuration for exporting the traces of the packets via
+/// [OpenTelemetry](https://opentelemetry.io) (OTLP), for example to Jaeger or Tempo.
+#[derive(Deserialize, Clone)]
+pub struct OpenTelemetryConfig {
+    /// URL of the OTLP collector which the traces are exported to.
+    /// Defaults to http://localhost:4317.
+    #[serde(default = "OpenTelemetryConfig::default_endpoint")]
+    pub endpoint: String,
+    /// Ratio, between 0 and 1, of the packets whose traces are exported. The spans of
+    /// the services follow the decision taken for the packet's root span.
+    /// Defaults to 1 (every packet).
+    #[serde(default = "OpenTelemetryConfig::default_sampling_ratio")]
+    pub sampling_ratio: f64,
+    /// Name of the node in the exported traces. Defaults to "ilp-node".
+    #[serde(default = "OpenTelemetryConfig::default_service_name")]
+    pub service_name: String,
+}
+
+impl OpenTelemetryConfig {
+    fn default_endpoint() -> String {
+        "http://localhost:4317".to_owned()
+    }
+
+    fn default_sampling_ratio() -> f64 {
+        1.0
+    }
+
+    fn default_service_name() -> String {
+        "ilp-node".to_owned()
+    }
+}
+
+/// Returns a layer which exports the spans to the configured OTLP collector. The
+/// returned guard flushes the remaining spans when it is dropped, so it must be
+/// kept alive while the node runs.
+pub fn otlp_layer<S>(
+    config: &OpenTelemetryConfig,
+) -> Result<(OpenTelemetryLayer<S, Tracer>, Uninstall), TraceError>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    let sampler = Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(config.sampling_ratio)));
+    let (tracer, uninstall) = opentelemetry_otlp::new_pipeline()
+        .with_endpoint(&config.endpoint)
+        .with_trace_config(
+            sdk::trace::config()
+                .with_default_sampler(sampler)
+                .with_resource(Resource::new(vec![KeyValue::new(
+                    "service.name",
+                    config.service_name.clone(),
+                )])),
+        )
+        .install()?;
+    Ok((
+        tracing_opentelemetry::layer().with_tracer(tracer),
+        uninstall,
+    ))
+}
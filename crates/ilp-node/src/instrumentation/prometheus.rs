@@ -22,6 +22,10 @@ pub struct PrometheusConfig {
     /// 1 second of histogram data points every second. Defaults to 10000ms (10 seconds).
     #[serde(default = "PrometheusConfig::default_histogram_granularity")]
     pub histogram_granularity: u64,
+    /// Interval, in milliseconds, at which the node records the balances of the accounts
+    /// and the size of the routing table. Defaults to 10000ms (10 seconds).
+    #[serde(default = "PrometheusConfig::default_gauge_interval")]
+    pub gauge_interval: u64,
 }
 
 impl PrometheusConfig {
@@ -32,6 +36,10 @@ impl PrometheusConfig {
     fn default_histogram_granularity() -> u64 {
         10_000
     }
+
+    fn default_gauge_interval() -> u64 {
+        10_000
+    }
 }
 
 /// Starts a Prometheus metrics server that will listen on the configured address.
@@ -1,3 +1,4 @@
+use async_trait::async_trait;
 use interledger::{
     ccp::{CcpRoutingAccount, RoutingRelation},
     packet::{ErrorCode, Fulfill, Reject},
@@ -6,10 +7,20 @@ use interledger::{
     },
 };
 use std::str;
-use tracing::{debug_span, error_span, info, info_span};
+use tracing::{debug_span, error_span, field, info, info_span, Span};
 use tracing_futures::Instrument;
+use tracing_subscriber::filter::{Directive, EnvFilter, ParseError};
 use uuid::Uuid;
 
+/// The spans of the requests, and the fields which contain the ids of the accounts
+/// which sent or received them
+const ACCOUNT_SPANS: [(&str, &str); 4] = [
+    ("incoming", "from.id"),
+    ("forwarding", "to.id"),
+    ("outgoing", "from.id"),
+    ("outgoing", "to.id"),
+];
+
 /// Add tracing context for the incoming request.
 /// This adds minimal information for the ERROR log
 /// level and more information for the DEBUG level.
@@ -20,6 +31,9 @@ pub async fn trace_incoming<A: Account>(
     let request_span = error_span!(target: "interledger-node",
         "incoming",
         request.id = %Uuid::new_v4(),
+        // The condition is the same on every hop, so it correlates
+        // the traces of a packet across the nodes it went through
+        prepare.condition = %hex::encode(request.prepare.execution_condition()),
         prepare.destination = %request.prepare.destination(),
         prepare.amount = request.prepare.amount(),
         from.id = %request.from.id()
@@ -86,6 +100,7 @@ pub async fn trace_outgoing<A: Account + CcpRoutingAccount>(
     let request_span = error_span!(target: "interledger-node",
         "outgoing",
         request.id = %Uuid::new_v4(),
+        prepare.condition = %hex::encode(request.prepare.execution_condition()),
         prepare.destination = %request.prepare.destination(),
         from.id = %request.from.id(),
         to.id = %request.to.id(),
@@ -120,6 +135,105 @@ pub async fn trace_outgoing<A: Account + CcpRoutingAccount>(
     trace_response(result)
 }
 
+/// Wraps the incoming service in a span named after it, in which the code of the reject
+/// is recorded if the packet is rejected. The (exported) traces of a packet thereby show
+/// which service of the chain rejected it.
+pub fn incoming_span<I>(service: I, name: &'static str) -> SpanService<I> {
+    SpanService {
+        name,
+        next: service,
+    }
+}
+
+/// Wraps the outgoing service in a span named after it, like
+/// [`incoming_span`](./fn.incoming_span.html)
+pub fn outgoing_span<O>(service: O, name: &'static str) -> SpanService<O> {
+    SpanService {
+        name,
+        next: service,
+    }
+}
+
+/// Calls the next service in a span named after it.
+/// (This is a struct rather than a closure wrapping the service, since the closure's type would
+/// contain the type of the service a second time and thereby double its size at every span)
+#[derive(Clone)]
+pub struct SpanService<S> {
+    name: &'static str,
+    next: S,
+}
+
+#[async_trait]
+impl<I, A> IncomingService<A> for SpanService<I>
+where
+    I: IncomingService<A> + Send + Sync + 'static,
+    A: Account + Sync + 'static,
+{
+    async fn handle_request(&mut self, request: IncomingRequest<A>) -> IlpResult {
+        let span = service_span(self.name);
+        let result = self
+            .next
+            .handle_request(request)
+            .instrument(span.clone())
+            .await;
+        record_reject(&span, &result);
+        result
+    }
+}
+
+#[async_trait]
+impl<O, A> OutgoingService<A> for SpanService<O>
+where
+    O: OutgoingService<A> + Send + Sync + 'static,
+    A: Account + Sync + 'static,
+{
+    async fn send_request(&mut self, request: OutgoingRequest<A>) -> IlpResult {
+        let span = service_span(self.name);
+        let result = self
+            .next
+            .send_request(request)
+            .instrument(span.clone())
+            .await;
+        record_reject(&span, &result);
+        result
+    }
+}
+
+fn service_span(name: &'static str) -> Span {
+    // `otel.name` is used as the name of the span when it is exported via OpenTelemetry
+    debug_span!(target: "interledger-node",
+        "service",
+        otel.name = name,
+        reject.code = field::Empty,
+    )
+}
+
+fn record_reject(span: &Span, result: &IlpResult) {
+    if let Err(ref reject) = result {
+        span.record("reject.code", &field::display(reject.code()));
+    }
+}
+
+/// Returns the filter directives which set the log level of the
+/// requests sent by or to the account, such as `[incoming{from.id=<id>}]=trace`
+pub fn account_log_directives(account_id: Uuid, level: &str) -> Result<Vec<Directive>, ParseError> {
+    ACCOUNT_SPANS
+        .iter()
+        .map(|(span, field)| format!("[{}{{{}={}}}]={}", span, field, account_id, level).parse())
+        .collect()
+}
+
+/// Returns the filter made of the directives, without those of the account
+/// (which were added by [`account_log_directives`](./fn.account_log_directives.html))
+pub fn without_account_directives(directives: &str, account_id: Uuid) -> EnvFilter {
+    let account_id = account_id.to_string();
+    let directives: Vec<&str> = directives
+        .split(',')
+        .filter(|directive| !directive.contains(&account_id))
+        .collect();
+    EnvFilter::new(directives.join(","))
+}
+
 /// Log whether the response was a Fulfill or Reject
 fn trace_response(result: Result<Fulfill, Reject>) -> Result<Fulfill, Reject> {
     match result {
@@ -150,3 +264,48 @@ fn trace_response(result: Result<Fulfill, Reject>) -> Result<Fulfill, Reject> {
 
     result
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_account_log_directives() {
+        let id = Uuid::new_v4();
+        let directives: Vec<String> = account_log_directives(id, "trace")
+            .unwrap()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            directives,
+            vec![
+                format!("[incoming{{from.id={}}}]=trace", id),
+                format!("[forwarding{{to.id={}}}]=trace", id),
+                format!("[outgoing{{from.id={}}}]=trace", id),
+                format!("[outgoing{{to.id={}}}]=trace", id),
+            ]
+        );
+        assert!(account_log_directives(id, "loud").is_err());
+    }
+
+    #[test]
+    fn removes_only_the_directives_of_the_account() {
+        let alice = Uuid::new_v4();
+        let bob = Uuid::new_v4();
+        let mut directives = vec!["info".to_owned()];
+        for id in &[alice, bob] {
+            directives.extend(
+                account_log_directives(*id, "debug")
+                    .unwrap()
+                    .iter()
+                    .map(ToString::to_string),
+            );
+        }
+
+        let filter = without_account_directives(&directives.join(","), alice).to_string();
+        assert!(!filter.contains(&alice.to_string()));
+        assert_eq!(filter.matches(&bob.to_string()).count(), 4);
+        assert!(filter.split(',').any(|directive| directive == "info"));
+    }
+}
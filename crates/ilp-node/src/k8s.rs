@@ -0,0 +1,304 @@
+//! `ilp-node generate-k8s`, which writes the Kubernetes manifests (a Deployment, a Service
+//! and a Secret) of a node from its configuration file.
+//!
+//! The node in the Deployment is configured with environment variables only (see
+//! [`env_config`](../env_config/index.html)), the settings with secrets being set from the
+//! Secret, so that no config file or chart needs to be maintained alongside the manifests.
+
+use crate::config_check::parse_config;
+use crate::env_config::{env_var_name, env_vars};
+use crate::init::{quote, write_secret_file};
+use crate::secrets::resolve_secrets;
+use clap::{App, Arg};
+use config::{Config, File};
+use std::{
+    ffi::OsString,
+    iter,
+    net::{Ipv4Addr, SocketAddr},
+    str::FromStr,
+};
+
+const DEFAULT_NAME: &str = "ilp-node";
+const DEFAULT_IMAGE: &str = "interledgerrs/ilp-node:latest";
+/// The settings whose values are written to the Secret rather than to the Deployment, by
+/// the words of their keys. The URLs are included since they may have credentials in them.
+const SECRET_WORDS: &[&str] = &["secret", "token", "password", "key", "url"];
+/// The settings with the addresses the node listens on, and the names of their ports
+const PORTS: &[(&str, &str)] = &[
+    ("http_bind_address", "http"),
+    ("settlement_api_bind_address", "settlement"),
+    ("listeners.api.bind_address", "api"),
+    ("listeners.ilp_over_http.bind_address", "ilp-over-http"),
+    ("listeners.btp.bind_address", "btp"),
+    ("listeners.spsp.bind_address", "spsp"),
+    ("grpc_bind_address", "grpc"),
+    ("prometheus.bind_address", "metrics"),
+    ("http_tls.acme.challenge_bind_address", "acme"),
+];
+
+fn generate_k8s_app<'a, 'b>() -> App<'a, 'b> {
+    App::new("ilp-node generate-k8s")
+        .about("Generates the Kubernetes manifests of a node (a Deployment, a Service and a Secret) from its configuration file. The node is configured with ILP_ environment variables in the Deployment, the settings with secrets (tokens, keys, passwords and URLs) being set from the Secret. The addresses the node listens on are bound to all interfaces, so that the Service can reach them.")
+        .args(&[
+            Arg::with_name("config")
+                .takes_value(true)
+                .index(1)
+                .required(true)
+                .help("Name of config file (in JSON, YAML, or TOML format)"),
+            Arg::with_name("name")
+                .long("name")
+                .takes_value(true)
+                .help("Name of the Deployment, the Service and the Secret. Defaults to ilp-node."),
+            Arg::with_name("namespace")
+                .long("namespace")
+                .takes_value(true)
+                .help("Namespace of the manifests. If it is not set, they are applied to the namespace of the kubectl context."),
+            Arg::with_name("image")
+                .long("image")
+                .takes_value(true)
+                .help("Image of the node. Defaults to interledgerrs/ilp-node:latest."),
+            Arg::with_name("output")
+                .long("output")
+                .takes_value(true)
+                .help("Path of the file to write the manifests to. If it is not set, they are printed."),
+            Arg::with_name("force")
+                .long("force")
+                .help("Overwrite the output file if it exists."),
+        ])
+}
+
+/// Whether the Kubernetes object name is a valid DNS label
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 63
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+        && !name.starts_with('-')
+        && !name.ends_with('-')
+}
+
+fn is_secret(name: &str) -> bool {
+    let key = name.to_lowercase();
+    SECRET_WORDS.iter().any(|word| key.contains(word))
+}
+
+/// The manifests of the node
+struct Manifests<'a> {
+    name: &'a str,
+    namespace: Option<&'a str>,
+    image: &'a str,
+    /// The environment variables which are set in the Deployment
+    env: Vec<(String, String)>,
+    /// The environment variables which are set from the Secret
+    secret_env: Vec<(String, String)>,
+    /// The names and the numbers of the ports the node listens on
+    ports: Vec<(&'static str, u16)>,
+    /// The name of the port of the API, and whether it is served over TLS
+    api_port: &'static str,
+    api_tls: bool,
+}
+
+impl<'a> Manifests<'a> {
+    fn metadata(&self, indent: &str) -> String {
+        let mut metadata = format!(
+            "{indent}name: {name}\n{indent}labels:\n{indent}  app.kubernetes.io/name: ilp-node\n{indent}  app.kubernetes.io/instance: {name}\n",
+            indent = indent,
+            name = self.name
+        );
+        if let Some(namespace) = self.namespace {
+            metadata.push_str(&format!("{}namespace: {}\n", indent, namespace));
+        }
+        metadata
+    }
+
+    fn secret(&self) -> String {
+        let data: String = self
+            .secret_env
+            .iter()
+            .map(|(name, value)| format!("  {}: {}\n", name, quote(value)))
+            .collect();
+        format!(
+            "apiVersion: v1\nkind: Secret\nmetadata:\n{}type: Opaque\nstringData:\n{}",
+            self.metadata("  "),
+            data
+        )
+    }
+
+    fn service(&self) -> String {
+        let ports: String = self
+            .ports
+            .iter()
+            .map(|(name, port)| {
+                format!(
+                    "    - name: {}\n      port: {}\n      targetPort: {}\n",
+                    name, port, name
+                )
+            })
+            .collect();
+        format!(
+            "apiVersion: v1\nkind: Service\nmetadata:\n{}spec:\n  selector:\n    app.kubernetes.io/instance: {}\n  ports:\n{}",
+            self.metadata("  "),
+            self.name,
+            ports
+        )
+    }
+
+    fn deployment(&self) -> String {
+        let env: String = self
+            .env
+            .iter()
+            .map(|(name, value)| {
+                format!(
+                    "            - name: {}\n              value: {}\n",
+                    name,
+                    quote(value)
+                )
+            })
+            .collect();
+        let ports: String = self
+            .ports
+            .iter()
+            .map(|(name, port)| {
+                format!(
+                    "            - name: {}\n              containerPort: {}\n",
+                    name, port
+                )
+            })
+            .collect();
+        // The root of the API answers with the status of the node, without authentication
+        let probe = format!(
+            "            httpGet:\n              path: /\n              port: {}\n              scheme: {}\n",
+            self.api_port,
+            if self.api_tls { "HTTPS" } else { "HTTP" }
+        );
+        format!(
+            "apiVersion: apps/v1\nkind: Deployment\nmetadata:\n{metadata}spec:\n  replicas: 1\n  selector:\n    matchLabels:\n      app.kubernetes.io/instance: {name}\n  template:\n    metadata:\n      labels:\n        app.kubernetes.io/name: ilp-node\n        app.kubernetes.io/instance: {name}\n    spec:\n      containers:\n        - name: ilp-node\n          image: {image}\n          env:\n{env}          envFrom:\n            - secretRef:\n                name: {name}\n          ports:\n{ports}          readinessProbe:\n{probe}          livenessProbe:\n{probe}            initialDelaySeconds: 10\n",
+            metadata = self.metadata("  "),
+            name = self.name,
+            image = self.image,
+            env = if env.is_empty() { "            []\n".to_string() } else { env },
+            ports = ports,
+            probe = probe,
+        )
+    }
+}
+
+/// Binds the address to all interfaces if it is bound to the loopback interface, which
+/// cannot be reached from outside of the pod
+fn unspecified_if_loopback(address: SocketAddr) -> SocketAddr {
+    if address.ip().is_loopback() {
+        SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), address.port())
+    } else {
+        address
+    }
+}
+
+/// Runs `ilp-node generate-k8s` with the arguments following `generate-k8s`
+pub async fn run_generate_k8s<I>(args: I) -> Result<(), String>
+where
+    I: Iterator<Item = OsString>,
+{
+    let matches = generate_k8s_app()
+        .get_matches_from(iter::once(OsString::from("ilp-node generate-k8s")).chain(args));
+    let config_path = matches.value_of("config").unwrap_or_default();
+    let name = matches.value_of("name").unwrap_or(DEFAULT_NAME);
+    let namespace = matches.value_of("namespace");
+    let image = matches.value_of("image").unwrap_or(DEFAULT_IMAGE);
+    for name in iter::once(name).chain(namespace) {
+        if !is_valid_name(name) {
+            return Err(format!(
+                "Invalid name {}, which must consist of at most 63 lowercase letters, digits or '-'",
+                name
+            ));
+        }
+    }
+
+    let mut config = Config::new();
+    config
+        .merge(File::with_name(config_path))
+        .map_err(|err| format!("Error reading {}: {}", config_path, err))?;
+    // The references to secrets are written as they are, and only resolved to validate
+    // the configuration
+    let resolved = resolve_secrets(&config).await?;
+    let node =
+        parse_config(resolved).map_err(|issue| format!("Invalid configuration: {}", issue))?;
+    if let Some(issue) = node.validate().into_iter().next() {
+        return Err(format!("Invalid configuration: {}", issue));
+    }
+    // The addresses with defaults are always set, since their defaults are the loopback
+    // interface
+    for (key, address) in [
+        ("http_bind_address", node.http_bind_address),
+        (
+            "settlement_api_bind_address",
+            node.settlement_api_bind_address,
+        ),
+    ]
+    .iter()
+    {
+        config
+            .set(key, address.to_string())
+            .map_err(|err| err.to_string())?;
+    }
+
+    let mut manifests = Manifests {
+        name,
+        namespace,
+        image,
+        env: Vec::new(),
+        secret_env: Vec::new(),
+        ports: Vec::new(),
+        api_port: "http",
+        api_tls: node.http_tls.is_some(),
+    };
+    let vars = env_vars(&config).map_err(|err| err.to_string())?;
+    for (var, value) in vars {
+        let port = PORTS
+            .iter()
+            .find(|(key, _)| env_var_name(key) == var)
+            .map(|(_, port)| port);
+        let value = match (port, SocketAddr::from_str(&value)) {
+            (Some(port), Ok(address)) => {
+                let address = unspecified_if_loopback(address);
+                if !manifests
+                    .ports
+                    .iter()
+                    .any(|(_, number)| *number == address.port())
+                {
+                    manifests.ports.push((port, address.port()));
+                }
+                address.to_string()
+            }
+            _ => value,
+        };
+        if is_secret(&var) {
+            manifests.secret_env.push((var, value));
+        } else {
+            manifests.env.push((var, value));
+        }
+    }
+    if let Some(ref api) = node.listeners.api {
+        manifests.api_port = "api";
+        manifests.api_tls = api.tls.is_some();
+    }
+
+    let documents = format!(
+        "# Generated by `ilp-node generate-k8s` from {}\n{}---\n{}---\n{}",
+        config_path,
+        manifests.secret(),
+        manifests.service(),
+        manifests.deployment()
+    );
+    match matches.value_of("output") {
+        Some(output) => {
+            write_secret_file(output, &documents, matches.is_present("force"))?;
+            eprintln!(
+                "Wrote the manifests of {} to {}. Apply them with `kubectl apply -f {}`",
+                name, output, output
+            );
+        }
+        None => print!("{}", documents),
+    }
+    Ok(())
+}
@@ -1,8 +1,40 @@
+//! # ilp-node
+//!
+//! The Interledger.rs node as a library, so that programs can embed a connector rather than
+//! running the `ilp-node` binary. [`InterledgerNode::new`](./struct.InterledgerNode.html#method.new)
+//! returns the configuration of a node with the default options, to which custom services
+//! ([`Middlewares`](./struct.Middlewares.html)) and callbacks ([`Hooks`](./struct.Hooks.html))
+//! can be added before it is started with `run`, or on a custom store with `serve_with_store`.
+
 #![type_length_limit = "10000000"]
+#![recursion_limit = "256"]
+#[cfg(feature = "acme")]
+mod acme;
+#[cfg(feature = "alerting")]
+mod alerting;
+pub mod config_check;
+mod config_watch;
+mod coordination;
+pub mod env_config;
+mod hooks;
 mod instrumentation;
+mod listeners;
+mod middleware;
 mod node;
+pub mod secrets;
+mod shutdown;
+#[cfg(feature = "telemetry")]
+mod telemetry;
+mod tls;
 
 #[cfg(feature = "redis")]
 mod redis_store;
 
+pub use hooks::Hooks;
+#[cfg(feature = "opentelemetry")]
+pub use instrumentation::opentelemetry::{otlp_layer, OpenTelemetryConfig};
+pub use listeners::{ListenerConfig, ListenersConfig, Subsystem};
+pub use middleware::Middlewares;
 pub use node::*;
+pub use shutdown::{shutdown_signal, Shutdown, ShutdownConfig};
+pub use tls::HttpTlsConfig;
@@ -0,0 +1,268 @@
+use crate::shutdown::{bind_listener, Shutdown};
+use crate::tls::{tls_incoming, HttpTlsConfig};
+use futures::{Future, Stream};
+use hyper::{
+    server::accept,
+    service::{make_service_fn, service_fn, Service},
+    Body, Request, Server,
+};
+use interledger::{
+    api::RequestLimiter,
+    errors::{default_rejection_handler, ApiError},
+};
+use serde::Deserialize;
+use std::{
+    convert::Infallible,
+    io,
+    net::{IpAddr, SocketAddr},
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpStream,
+};
+use tokio_rustls::{rustls::ServerConfig, server::TlsStream};
+use tracing::{debug, error, info};
+use warp::{
+    filters::BoxedFilter,
+    http::{header::RETRY_AFTER, HeaderValue},
+    Filter, Rejection, Reply,
+};
+
+/// Addresses on which the subsystems of the HTTP API are served apart, so that they can
+/// be firewalled separately (for example, to only expose the admin API internally).
+/// The subsystems which are not configured are served together on the `http_bind_address`.
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct ListenersConfig {
+    /// The accounts, settings and payments API of the administrators and account holders
+    pub api: Option<ListenerConfig>,
+    /// ILP over HTTP, with which the peers send packets
+    pub ilp_over_http: Option<ListenerConfig>,
+    /// BTP, with which the peers and clients connect over WebSockets
+    pub btp: Option<ListenerConfig>,
+    /// The SPSP endpoints, which the payment pointers of the accounts resolve to
+    pub spsp: Option<ListenerConfig>,
+    /// The subsystems which are neither served nor advertised, for the nodes which only
+    /// have some roles (for example, a receiver which does not accept BTP connections)
+    #[serde(default)]
+    pub disabled: Vec<Subsystem>,
+}
+
+impl ListenersConfig {
+    pub(crate) fn is_enabled(&self, subsystem: Subsystem) -> bool {
+        !self.disabled.contains(&subsystem)
+    }
+}
+
+/// A subsystem of the node which can be disabled
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Subsystem {
+    /// ILP over HTTP
+    IlpOverHttp,
+    Btp,
+    Spsp,
+    /// The settlement API, on which the settlement engines call the node
+    Settlement,
+}
+
+impl Subsystem {
+    /// The name of the subsystem in the features advertised on `GET /`
+    pub(crate) fn feature(self) -> &'static str {
+        match self {
+            Subsystem::IlpOverHttp => "http",
+            Subsystem::Btp => "btp",
+            Subsystem::Spsp => "spsp",
+            Subsystem::Settlement => "settlement",
+        }
+    }
+}
+
+/// A listener of its own for a subsystem
+#[derive(Deserialize, Clone, Debug)]
+pub struct ListenerConfig {
+    /// IP address and port to listen on
+    pub bind_address: SocketAddr,
+    /// TLS termination of the listener, which is independent of the `http_tls`.
+    /// Only certificates from files are supported, ACME is only supported for the `http_tls`.
+    #[serde(default)]
+    pub tls: Option<HttpTlsConfig>,
+    /// The max number of requests each client IP address may send to the listener per
+    /// minute, above which they are rejected with 429 Too Many Requests. There is no limit
+    /// if it is not set. Behind a reverse proxy, all the requests come from its address.
+    #[serde(default)]
+    pub requests_per_minute: Option<u32>,
+}
+
+impl ListenerConfig {
+    /// Returns the TLS configuration of the listener, if it is served over TLS
+    pub(crate) fn server_config(&self) -> io::Result<Option<ServerConfig>> {
+        let tls = match self.tls {
+            Some(ref tls) => tls,
+            None => return Ok(None),
+        };
+        #[cfg(feature = "acme")]
+        {
+            if tls.acme.is_some() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "ACME is only supported for the http_tls",
+                ));
+            }
+        }
+        tls.server_config().map(|(config, _)| Some(config))
+    }
+}
+
+/// The routes of a subsystem, with their replies boxed so that
+/// the routes of several subsystems can be combined at runtime
+pub(crate) type Routes = BoxedFilter<(Box<dyn Reply>,)>;
+
+pub(crate) fn boxed_routes<F, R>(filter: F) -> Routes
+where
+    F: Filter<Extract = (R,), Error = Rejection> + Clone + Send + Sync + 'static,
+    R: Reply + 'static,
+{
+    filter
+        .map(|reply| Box::new(reply) as Box<dyn Reply>)
+        .boxed()
+}
+
+/// Splits the subsystems into the ones which are served on a listener of their own, and
+/// the routes of the others combined (in the same order), which are served on the
+/// `http_bind_address`. If all the subsystems have their own listener, there are none.
+pub(crate) fn split_subsystems(
+    subsystems: Vec<(&'static str, Option<ListenerConfig>, Routes)>,
+) -> (Option<Routes>, Vec<(&'static str, ListenerConfig, Routes)>) {
+    let mut combined: Option<Routes> = None;
+    let mut separate = Vec::new();
+    for (name, listener, routes) in subsystems {
+        match listener {
+            Some(listener) => separate.push((name, listener, routes)),
+            None => {
+                combined = Some(match combined {
+                    Some(combined) => combined.or(routes).unify().boxed(),
+                    None => routes,
+                })
+            }
+        }
+    }
+    (combined, separate)
+}
+
+/// Serves the routes on the address (over TLS, with a TLS configuration) until the
+/// shutdown starts, limiting the requests of each client IP address if there is a
+/// limit. Connections which are open then are completed.
+pub(crate) fn serve_routes(
+    name: &str,
+    routes: Routes,
+    bind_address: SocketAddr,
+    tls_config: Option<ServerConfig>,
+    requests_per_minute: Option<u32>,
+    shutdown: &Shutdown,
+    reuse_port: bool,
+) -> Result<(), ()> {
+    let routes = routes
+        .recover(default_rejection_handler)
+        .with(warp::log("interledger-api"))
+        .boxed();
+    let listener = bind_listener(bind_address, reuse_port).map_err(|err| {
+        error!(target: "interledger-node", "Error binding the {} to {}: {}", name, bind_address, err)
+    })?;
+    let limiter = requests_per_minute
+        .map(|requests| RequestLimiter::per_key(requests, Duration::from_secs(60)));
+    if let Some(tls_config) = tls_config {
+        let incoming = tls_incoming(listener, tls_config, shutdown.signal());
+        info!(target: "interledger-node", "Interledger.rs node {} listening with TLS on: {}", name, bind_address);
+        match limiter {
+            Some(limiter) => {
+                shutdown.spawn_server(serve_limited(incoming, routes, limiter, shutdown.signal()))
+            }
+            None => shutdown.spawn_server(
+                warp::serve(routes)
+                    .serve_incoming_with_graceful_shutdown(incoming, shutdown.signal()),
+            ),
+        }
+    } else {
+        info!(target: "interledger-node", "Interledger.rs node {} listening on: {}", name, bind_address);
+        match limiter {
+            Some(limiter) => {
+                shutdown.spawn_server(serve_limited(listener, routes, limiter, shutdown.signal()))
+            }
+            None => shutdown.spawn_server(
+                warp::serve(routes)
+                    .serve_incoming_with_graceful_shutdown(listener, shutdown.signal()),
+            ),
+        }
+    }
+    Ok(())
+}
+
+/// Connections whose client address is known, which warp does not pass to the
+/// routes of the connections it did not accept itself
+trait PeerAddr {
+    fn peer_addr(&self) -> io::Result<SocketAddr>;
+}
+
+impl PeerAddr for TcpStream {
+    fn peer_addr(&self) -> io::Result<SocketAddr> {
+        TcpStream::peer_addr(self)
+    }
+}
+
+impl PeerAddr for TlsStream<TcpStream> {
+    fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.get_ref().0.peer_addr()
+    }
+}
+
+/// Serves the routes on the connections, rejecting the requests of the clients
+/// which sent more than their requests of the minute
+async fn serve_limited<I, C>(
+    incoming: I,
+    routes: BoxedFilter<(impl Reply + 'static,)>,
+    limiter: RequestLimiter<IpAddr>,
+    signal: impl Future<Output = ()>,
+) where
+    I: Stream<Item = io::Result<C>> + Send + 'static,
+    C: PeerAddr + AsyncRead + AsyncWrite + Send + Unpin + 'static,
+{
+    let service = warp::service(routes);
+    let make_service = make_service_fn(move |connection: &C| {
+        let client = connection.peer_addr().ok().map(|address| address.ip());
+        let service = service.clone();
+        let limiter = limiter.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request: Request<Body>| {
+                let mut service = service.clone();
+                let allowed = match client {
+                    Some(client) => limiter.check(client, Instant::now()),
+                    None => Ok(()),
+                };
+                async move {
+                    match allowed {
+                        Ok(()) => service.call(request).await,
+                        Err(retry_after) => {
+                            debug!(target: "interledger-node", "Rejecting a request of {:?}, which sent too many requests", client);
+                            let mut response = ApiError::too_many_requests()
+                                .detail("too many requests, try again later")
+                                .into_response();
+                            response.headers_mut().insert(
+                                RETRY_AFTER,
+                                HeaderValue::from(retry_after.as_secs().max(1)),
+                            );
+                            Ok(response)
+                        }
+                    }
+                }
+            }))
+        }
+    });
+    if let Err(err) = Server::builder(accept::from_stream(incoming))
+        .serve(make_service)
+        .with_graceful_shutdown(signal)
+        .await
+    {
+        error!(target: "interledger-node", "Server error: {}", err);
+    }
+}
@@ -1,6 +1,30 @@
 #![type_length_limit = "10000000"]
+#![recursion_limit = "256"]
+#[cfg(feature = "acme")]
+mod acme;
+#[cfg(feature = "alerting")]
+mod alerting;
+mod compose;
+mod config_check;
+mod config_watch;
+mod coordination;
+mod env_config;
+mod hooks;
+mod init;
 mod instrumentation;
+mod k8s;
+mod listeners;
+mod middleware;
+#[cfg(feature = "redis")]
+mod migrate;
 pub mod node;
+mod secrets;
+mod shutdown;
+#[cfg(feature = "redis")]
+mod snapshot;
+#[cfg(feature = "telemetry")]
+mod telemetry;
+mod tls;
 
 use cfg_if::cfg_if;
 
@@ -9,8 +33,21 @@ cfg_if! {
         use tracing_subscriber::{
             filter::EnvFilter,
             fmt::{time::ChronoUtc, Subscriber},
+            registry::LookupSpan,
+            util::SubscriberInitExt,
         };
-        use node::LogWriter;
+        use node::{FilterHandle, InterledgerNode, LogFormat, LogWriter};
+    }
+}
+
+cfg_if! {
+    if #[cfg(feature = "opentelemetry")] {
+        use instrumentation::opentelemetry::otlp_layer;
+        use tracing_subscriber::layer::SubscriberExt;
+        type TracingGuard = Option<opentelemetry_otlp::Uninstall>;
+    } else if #[cfg(feature = "monitoring")] {
+        /// Nothing needs to be flushed when the spans are not exported
+        struct TracingGuard;
     }
 }
 
@@ -21,15 +58,40 @@ use clap::{crate_version, App, Arg, ArgMatches};
 use config::{Config, Source};
 use config::{ConfigError, FileFormat, Value};
 use libc::{c_int, isatty};
-use node::InterledgerNode;
+use shutdown::{shutdown_signal, Shutdown};
 use std::{
     ffi::{OsStr, OsString},
     io::Read,
+    time::Duration,
     vec::Vec,
 };
 
 #[tokio::main]
 async fn main() {
+    // The subcommands which generate files, rather than run the node
+    let args = std::env::args_os().skip(2);
+    let generated = match std::env::args_os()
+        .nth(1)
+        .as_ref()
+        .and_then(|arg| arg.to_str())
+    {
+        Some("init") => Some(init::run_init(args, is_fd_tty(0))),
+        Some("generate-compose") => Some(compose::run_generate_compose(args)),
+        Some("generate-k8s") => Some(k8s::run_generate_k8s(args).await),
+        #[cfg(feature = "redis")]
+        Some("migrate-store") => Some(migrate::run_migrate_store(args).await),
+        #[cfg(feature = "redis")]
+        Some("snapshot") => Some(snapshot::run_snapshot(args).await),
+        _ => None,
+    };
+    if let Some(result) = generated {
+        if let Err(error) = result {
+            eprintln!("Error: {}", error);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     // The naming convention of arguments
     //
     // - URL vs URI
@@ -72,6 +134,18 @@ async fn main() {
             .takes_value(true)
             .required(true)
             .help("HTTP Authorization token for the node admin (sent as a Bearer token)"),
+        Arg::with_name("jwt_auth.jwks_url")
+            .long("jwt_auth.jwks_url")
+            .takes_value(true)
+            .help("URL of the JWKS of the identity provider whose JWTs are accepted by the API, in addition to the static tokens. Setting jwt_auth requires its issuer and audience as well."),
+        Arg::with_name("jwt_auth.issuer")
+            .long("jwt_auth.issuer")
+            .takes_value(true)
+            .help("Expected issuer (iss claim) of the JWTs accepted by the API"),
+        Arg::with_name("jwt_auth.audience")
+            .long("jwt_auth.audience")
+            .takes_value(true)
+            .help("Audience (aud claim) the JWTs accepted by the API must be issued for"),
         Arg::with_name("database_url")
             .long("database_url")
             // temporary alias for backwards compatibility
@@ -79,27 +153,175 @@ async fn main() {
             .takes_value(true)
             .default_value("redis://127.0.0.1:6379")
             .help("Redis URI (for example, \"redis://127.0.0.1:6379\" or \"unix:/tmp/redis.sock\")"),
+        Arg::with_name("read_replica_urls")
+            .long("read_replica_urls")
+            .takes_value(true)
+            .help("Comma-separated URIs of read-only replicas of the database. Account lookups and route table loads are spread over the replicas, while all writes go to the database_url."),
+        Arg::with_name("read_replica_max_staleness")
+            .long("read_replica_max_staleness")
+            .takes_value(true)
+            .help("Max lag, defined in milliseconds, of a read replica behind the database for it to be read from. Defaults to 1000ms (1 second)."),
         Arg::with_name("http_bind_address")
             .long("http_bind_address")
             .takes_value(true)
             .help("IP address and port to listen for HTTP connections. This is used for both the API and ILP over HTTP packets. ILP over HTTP is a means to transfer ILP packets instead of BTP connections"),
+        Arg::with_name("http_tls.cert")
+            .long("http_tls.cert")
+            .takes_value(true)
+            .help("Path of the PEM file with the certificate chain the HTTP API is served over TLS with"),
+        Arg::with_name("http_tls.key")
+            .long("http_tls.key")
+            .takes_value(true)
+            .help("Path of the PEM file with the private key (PKCS#8 or RSA) of the HTTP API's certificate"),
+        Arg::with_name("http_tls.acme.domains")
+            .long("http_tls.acme.domains")
+            .takes_value(true)
+            .help("Comma-separated domains for which a certificate is issued and renewed with ACME, to serve the HTTP API over TLS with. Requires the acme feature."),
+        Arg::with_name("http_tls.acme.contact_email")
+            .long("http_tls.acme.contact_email")
+            .takes_value(true)
+            .help("Email address the ACME certificate authority may contact about the certificate"),
+        Arg::with_name("http_tls.acme.directory_url")
+            .long("http_tls.acme.directory_url")
+            .takes_value(true)
+            .help("URL of the directory of the ACME server. Defaults to the production environment of Let's Encrypt."),
+        Arg::with_name("http_tls.acme.cache_dir")
+            .long("http_tls.acme.cache_dir")
+            .takes_value(true)
+            .help("Directory in which the ACME account key, the certificate and its key are kept. Defaults to acme."),
+        Arg::with_name("http_tls.acme.challenge_bind_address")
+            .long("http_tls.acme.challenge_bind_address")
+            .takes_value(true)
+            .help("IP address and port on which the ACME http-01 challenges are answered. Defaults to 0.0.0.0:80."),
+        Arg::with_name("http_tls.acme.renew_before")
+            .long("http_tls.acme.renew_before")
+            .takes_value(true)
+            .help("Time, in seconds, before the certificate expires at which it is renewed. Defaults to 2592000 (30 days)."),
+        Arg::with_name("listeners.api.bind_address")
+            .long("listeners.api.bind_address")
+            .takes_value(true)
+            .help("IP address and port to serve the node's accounts, settings and payments API on, instead of the http_bind_address. The TLS settings of the listeners can only be set in the config file."),
+        Arg::with_name("listeners.ilp_over_http.bind_address")
+            .long("listeners.ilp_over_http.bind_address")
+            .takes_value(true)
+            .help("IP address and port to serve ILP over HTTP on, instead of the http_bind_address"),
+        Arg::with_name("listeners.btp.bind_address")
+            .long("listeners.btp.bind_address")
+            .takes_value(true)
+            .help("IP address and port to accept BTP connections on, instead of the http_bind_address"),
+        Arg::with_name("listeners.spsp.bind_address")
+            .long("listeners.spsp.bind_address")
+            .takes_value(true)
+            .help("IP address and port to serve the SPSP endpoints (which the payment pointers resolve to) on, instead of the http_bind_address"),
+        Arg::with_name("listeners.spsp.requests_per_minute")
+            .long("listeners.spsp.requests_per_minute")
+            .takes_value(true)
+            .help("Max number of requests each client IP address may send to the SPSP listener per minute. Unlimited if not set. The limits of the other listeners can only be set in the config file."),
+        Arg::with_name("grpc_bind_address")
+            .long("grpc_bind_address")
+            .takes_value(true)
+            .help("IP address and port to serve the admin API over gRPC on (HTTP/2 without TLS). It is not served if this is not set."),
         Arg::with_name("settlement_api_bind_address")
             .long("settlement_api_bind_address")
             .takes_value(true)
             .help("IP address and port to listen for the Settlement Engine API"),
+        Arg::with_name("settlement_tls.ca_cert")
+            .long("settlement_tls.ca_cert")
+            .takes_value(true)
+            .help("Path of the PEM file with the CA certificates which sign the certificates of the settlement engines. Setting settlement_tls enables mutual TLS between the node and its settlement engines."),
+        Arg::with_name("settlement_tls.cert")
+            .long("settlement_tls.cert")
+            .takes_value(true)
+            .help("Path of the PEM file with the certificate chain the node presents to the settlement engines and serves the Settlement Engine API with"),
+        Arg::with_name("settlement_tls.key")
+            .long("settlement_tls.key")
+            .takes_value(true)
+            .help("Path of the PEM file with the private key (PKCS#8 or RSA) of the node's certificate"),
+        Arg::with_name("settlement_replay_window")
+            .long("settlement_replay_window")
+            .takes_value(true)
+            .help("Time, defined in seconds, outside of which the calls of the settlement engines are rejected as stale. If this is set, the engines must send the Request-Timestamp, Request-Nonce and Request-Signature headers, signed with their secret from settlement_engine_secrets (set in the config file), and calls reusing a nonce are rejected as replayed."),
         Arg::with_name("default_spsp_account")
             .long("default_spsp_account")
             .takes_value(true)
             .help("When SPSP payments are sent to the root domain, the payment pointer is resolved to <domain>/.well-known/pay. This value determines which account those payments will be sent to."),
+        Arg::with_name("stream_receipts")
+            .long("stream_receipts")
+            .takes_value(true)
+            .possible_values(&["true", "false"])
+            .help("Whether the node issues STREAM receipts of the amounts received via the payment pointers it hosts, and verifies them for the websites paid via those pointers (see POST /receipts/verify). Defaults to false."),
+        Arg::with_name("graphql")
+            .long("graphql")
+            .takes_value(true)
+            .possible_values(&["true", "false"])
+            .help("Whether the admin can query the node's accounts, balances, settlements, routes, rates and recent payments via /graphql. Defaults to false."),
         Arg::with_name("route_broadcast_interval")
             .long("route_broadcast_interval")
             .takes_value(true)
             .help("Interval, defined in milliseconds, on which the node will broadcast routing information to other nodes using CCP. Defaults to 30000ms (30 seconds)."),
+        Arg::with_name("max_packet_amount")
+            .long("max_packet_amount")
+            .takes_value(true)
+            .help("The max amount per packet which the node forwards for any account, on top of each account's own max_packet_amount. If this is not set, only the accounts' limits apply."),
+        Arg::with_name("max_packet_size")
+            .long("max_packet_size")
+            .takes_value(true)
+            .help("The max size, defined in bytes, of the prepare packets which the node accepts from any account, on top of each account's own max_packet_size. Packets over BTP and HTTP can never be larger than 40000 bytes. Larger packets are rejected with F01 errors whose data holds the size of the packet and the maximum."),
+        Arg::with_name("packet_deduplication_memory")
+            .long("packet_deduplication_memory")
+            .takes_value(true)
+            .help("Max memory, defined in bytes, of the cache of recently forwarded prepare packets. Duplicates of those packets (with the same condition, destination, amount and expiry) are answered with the previous response instead of being forwarded again, which defends against routing loops. Defaults to 0, which disables the deduplication."),
+        Arg::with_name("settings_poll_interval")
+            .long("settings_poll_interval")
+            .takes_value(true)
+            .help("Interval, defined in milliseconds, on which the node will check the store for changes to its runtime settings (route_broadcast_interval, exchange_rate.poll_interval, max_packet_amount and max_packet_size, set via the /settings API). Defaults to 10000ms (10 seconds)."),
+        Arg::with_name("balance_snapshot_interval")
+            .long("balance_snapshot_interval")
+            .takes_value(true)
+            .help("Interval, defined in milliseconds, on which the store will snapshot the balances of all accounts. If this is not set, snapshots are only taken via the API."),
+        Arg::with_name("escrow_sweep_interval")
+            .long("escrow_sweep_interval")
+            .takes_value(true)
+            .help("Interval, defined in milliseconds, on which the store will release the escrowed amounts of packets which expired without being fulfilled or rejected. Defaults to 10000ms (10 seconds)."),
+        Arg::with_name("settlement_reconciliation_interval")
+            .long("settlement_reconciliation_interval")
+            .takes_value(true)
+            .help("Interval, defined in milliseconds, on which the totals settled with the accounts which have a settlement engine are compared with the totals of their engines. The latest report is returned by GET /settlements/reconciliation. If this is not set, the settlements are not reconciled."),
+        Arg::with_name("idempotency_ttl")
+            .long("idempotency_ttl")
+            .takes_value(true)
+            .help("Time, defined in seconds, after which idempotency records of the settlement API expire. Defaults to 86400 seconds (24 hours)."),
+        Arg::with_name("uncredited_settlement_ttl")
+            .long("uncredited_settlement_ttl")
+            .takes_value(true)
+            .help("Time, defined in seconds, after the last change to an account's uncredited settlement amount after which it expires. If this is not set, uncredited amounts never expire."),
+        Arg::with_name("compaction_interval")
+            .long("compaction_interval")
+            .takes_value(true)
+            .help("Interval, defined in milliseconds, on which the store will apply the configured TTLs to records written without one and delete data left behind by deleted accounts. If this is not set, the store is not compacted."),
+        Arg::with_name("audit_log_retention")
+            .long("audit_log_retention")
+            .takes_value(true)
+            .help("Time, defined in seconds, for which the entries of the audit log of the admin API (see GET /audit) are kept. Older entries are deleted when new ones are recorded and when the store is compacted. If this is not set, they are kept forever."),
+        Arg::with_name("key_management.provider")
+            .long("key_management.provider")
+            .takes_value(true)
+            .help("Key manager used for the envelope encryption of the account secrets. Currently `local` can be configured from the command line, while `aws_kms` and `vault` (which need the respective feature flags) must be configured via a config file or stdin. If this is not set, the secrets are encrypted with a key derived from the secret_seed."),
+        Arg::with_name("key_management.key_file")
+            .long("key_management.key_file")
+            .takes_value(true)
+            .help("Path to the file containing the hex-encoded 256-bit key encryption key of the `local` key manager. You can generate a random key by running `openssl rand -hex 32`"),
+        Arg::with_name("rotate_encryption_key")
+            .long("rotate_encryption_key")
+            .help("Re-encrypt the account secrets with a newly generated data encryption key, wrapped by the configured key manager, and exit. The node must not be running while the key is rotated."),
+        Arg::with_name("validate_config")
+            .long("validate-config")
+            .help("Check the configuration, and whether the database and the settlement engines of its accounts are reachable, and exit without starting the node. Every problem which was found is printed, and the exit code is non-zero if the configuration is invalid."),
         Arg::with_name("exchange_rate.provider")
             .long("exchange_rate.provider")
             .takes_value(true)
-            .help("Exchange rate API to poll for exchange rates. If this is not set, the node will not poll for rates and will instead use the rates set via the HTTP API. \
-                Note that CryptoCompare can also be used when the node is configured via a config file or stdin, because an API key must be provided to use that service."),
+            .help("Exchange rate API to poll for exchange rates: CoinCap, CoinGecko, Kraken or Bitstamp. If this is not set, the node will not poll for rates and will instead use the rates set via the HTTP API. \
+                Note that CryptoCompare, Http and Median can also be used when the node is configured via a config file or stdin, because they take additional settings."),
         Arg::with_name("exchange_rate.poll_interval")
             .long("exchange_rate.poll_interval")
             .default_value("60000")
@@ -113,6 +335,62 @@ async fn main() {
                 For example, take an incoming packet with an amount of 100. If the \
                 exchange rate is 1:0.5 and the spread is 0.01, the amount on the \
                     outgoing packet would be 198 (instead of 200 without the spread)."),
+        Arg::with_name("exchange_rate.percentage_fee")
+            .long("exchange_rate.percentage_fee")
+            .takes_value(true)
+            .help("Fee, as a fraction of the converted amount, deducted from each packet. The spread and this fee can be overridden for asset pairs (via the /fees API) and for accounts (via their spread, percentage_fee and fixed_fee settings)."),
+        Arg::with_name("exchange_rate.max_age")
+            .long("exchange_rate.max_age")
+            .takes_value(true)
+            .help("Maximum age, in seconds, of a polled rate. While the provider fails, or stops returning an asset, its last known good rate is used until it is older than this, after which it is removed."),
+        Arg::with_name("exchange_rate.max_deviation")
+            .long("exchange_rate.max_deviation")
+            .takes_value(true)
+            .help("Maximum change, as a fraction of the previous rate, which is applied without operator confirmation. New rates which deviate more are rejected and the previous rate is kept, until the new rate is set via the HTTP API."),
+        Arg::with_name("expiry.min_window")
+            .long("expiry.min_window")
+            .takes_value(true)
+            .help("Minimum time, in milliseconds, an incoming packet must have left before it expires. Packets expiring sooner are rejected with R02. Defaults to 0."),
+        Arg::with_name("expiry.max_clock_skew")
+            .long("expiry.max_clock_skew")
+            .takes_value(true)
+            .help("Maximum difference, in milliseconds, between the clocks of the node and its peers which is tolerated when checking whether incoming packets expired. Defaults to 0."),
+        Arg::with_name("expiry.hop_reduction")
+            .long("expiry.hop_reduction")
+            .takes_value(true)
+            .help("Time, in milliseconds, subtracted from the expiry of each forwarded packet, on top of the round trip times of the incoming and outgoing accounts. Defaults to 0."),
+        Arg::with_name("expiry.max_duration")
+            .long("expiry.max_duration")
+            .takes_value(true)
+            .help("Maximum time, in milliseconds, until a forwarded packet expires. Packets which expire later are shortened to this. Defaults to 30000ms (30 seconds)."),
+        Arg::with_name("cluster.node_id")
+            .long("cluster.node_id")
+            .takes_value(true)
+            .help("Unique identifier of this node among the nodes sharing the store. Setting any cluster option makes the node coordinate with the other nodes using the store. Defaults to a random identifier."),
+        Arg::with_name("cluster.lease_ttl")
+            .long("cluster.lease_ttl")
+            .takes_value(true)
+            .help("Time, in milliseconds, after which the leases held by a node (to broadcast routes, poll exchange rates and own outgoing BTP connections) expire if it stops renewing them. Defaults to 10000ms (10 seconds)."),
+        Arg::with_name("cluster.role")
+            .long("cluster.role")
+            .takes_value(true)
+            .possible_values(&["all", "worker", "control"])
+            .help("What this node does among the nodes sharing the store: `worker` nodes only forward packets and queue the settlements they trigger in the store, `control` nodes may be elected leader to broadcast routes, poll exchange rates and send the queued settlements, and `all` nodes do both. Defaults to `all`."),
+        Arg::with_name("shutdown.deadline")
+            .long("shutdown.deadline")
+            .takes_value(true)
+            .help("Time, in milliseconds, the node waits for the in-flight requests to complete when it receives a SIGTERM or SIGINT, before it exits anyway. Defaults to 30000ms (30 seconds)."),
+        Arg::with_name("shutdown.reuse_port")
+            .long("shutdown.reuse_port")
+            .help("Bind the HTTP API and the settlement API with SO_REUSEPORT, so that the node replacing this one during a restart can listen on the same addresses before this one stops. Only supported on Unix."),
+        Arg::with_name("alerting.webhook_url")
+            .long("alerting.webhook_url")
+            .takes_value(true)
+            .help("URL to which the alerts are POSTed as JSON (compatible with Slack incoming webhooks). The alerting rules can only be set in the config file."),
+        Arg::with_name("alerting.evaluation_interval")
+            .long("alerting.evaluation_interval")
+            .takes_value(true)
+            .help("Interval, in milliseconds, at which the alerting rules are evaluated. Defaults to 60000ms (1 minute)."),
         Arg::with_name("prometheus.bind_address")
             .long("prometheus.bind_address")
             .takes_value(true)
@@ -129,9 +407,20 @@ async fn main() {
                 old data. For example, a value of 1000ms (1 second) would mean that the \
                 node forgets the oldest 1 second of histogram data points every second. \
                 Defaults to 10000ms (10 seconds)."),
+        Arg::with_name("prometheus.gauge_interval")
+            .long("prometheus.gauge_interval")
+            .takes_value(true)
+            .help("Interval, in milliseconds, at which the node records the balances of the \
+                accounts and the size of the routing table. Defaults to 10000ms (10 seconds)."),
         ]);
 
-    let mut config = get_env_config("ilp");
+    let mut config = match env_config::config_from_env(std::env::vars()) {
+        Ok(config) => config,
+        Err(error) => {
+            output_config_error(error, None);
+            return;
+        }
+    };
     if let Ok((path, config_file)) = precheck_arguments(app.clone()) {
         if !is_fd_tty(0) {
             if let Err(error) = merge_std_in(&mut config) {
@@ -147,24 +436,78 @@ async fn main() {
         }
         set_app_env(&config, &mut app, &path, path.len());
     }
+    let validate_config = std::env::args_os().any(|arg| arg == "--validate-config");
+    if validate_config {
+        // Missing settings are reported along with the other problems
+        reset_required(&mut app);
+    }
     let matches = app.get_matches();
     merge_args(&mut config, &matches);
 
+    let config = match secrets::resolve_secrets(&config).await {
+        Ok(config) => config,
+        Err(error) => {
+            println!("Error: {}", error);
+            std::process::exit(1);
+        }
+    };
+    for warning in config_check::unknown_keys(&config) {
+        println!("Warning: {}", warning);
+    }
+    let defaulted_keys = config_check::defaulted_keys(&config);
+    let node = match config_check::parse_config(config) {
+        Ok(node) => node,
+        Err(issue) => {
+            println!("Error: {}", issue);
+            std::process::exit(1);
+        }
+    };
+    let mut issues = node.validate();
+    if validate_config {
+        issues.extend(node.check_reachability().await);
+    }
+    for issue in issues.iter() {
+        println!("Error: {}", issue);
+    }
+    if !issues.is_empty() {
+        std::process::exit(1);
+    }
+    if validate_config {
+        if !defaulted_keys.is_empty() {
+            println!("Using the defaults of: {}", defaulted_keys.join(", "));
+        }
+        println!("The configuration is valid");
+        return;
+    }
+
     cfg_if! {
         if #[cfg(feature = "monitoring")] {
             let mut log_writer = LogWriter::default();
 
             let (nb_log_writer, _guard) = tracing_appender::non_blocking(log_writer.clone());
 
-            let tracing_builder = Subscriber::builder()
-                .with_timer(ChronoUtc::rfc3339())
-                .with_env_filter(EnvFilter::from_default_env())
-                .with_writer(nb_log_writer)
-                .with_filter_reloading();
-
-            log_writer.handle = Some(tracing_builder.reload_handle());
-
-            let _ = tracing_builder.try_init();
+            // The guard flushes the spans which were not exported yet when the node stops
+            let _tracing_guard = match node.log_format {
+                LogFormat::Text => {
+                    let tracing_builder = Subscriber::builder()
+                        .with_timer(ChronoUtc::rfc3339())
+                        .with_env_filter(EnvFilter::from_default_env())
+                        .with_writer(nb_log_writer)
+                        .with_filter_reloading();
+                    log_writer.handle = Some(FilterHandle::Text(tracing_builder.reload_handle()));
+                    init_tracing(tracing_builder.finish(), &node)
+                }
+                LogFormat::Json => {
+                    let tracing_builder = Subscriber::builder()
+                        .json()
+                        .with_timer(ChronoUtc::rfc3339())
+                        .with_env_filter(EnvFilter::from_default_env())
+                        .with_writer(nb_log_writer)
+                        .with_filter_reloading();
+                    log_writer.handle = Some(FilterHandle::Json(tracing_builder.reload_handle()));
+                    init_tracing(tracing_builder.finish(), &node)
+                }
+            };
 
             let log_writer = Some(log_writer);
         } else {
@@ -172,14 +515,52 @@ async fn main() {
         }
     }
 
-    let node = config
-        .try_into::<InterledgerNode>()
-        .expect("Could not parse provided configuration options into an Interledger Node config");
-    node.serve(log_writer.clone()).await.unwrap();
+    if matches.is_present("rotate_encryption_key") {
+        if node.rotate_encryption_key().await.is_err() {
+            std::process::exit(1);
+        }
+        return;
+    }
+    let shutdown = Shutdown::default();
+    let shutdown_deadline = Duration::from_millis(node.shutdown.deadline);
+    node.serve_with_shutdown(log_writer.clone(), shutdown.clone())
+        .await
+        .unwrap();
 
-    // Add a future which is always pending. This will ensure main does not exist
-    // TODO: Is there a better way of doing this?
-    futures::future::pending().await
+    // Run until the node is asked to stop. Returning from main afterwards
+    // flushes the logs and the traces which were not written yet.
+    shutdown_signal().await;
+    tracing::info!(target: "interledger-node", "Shutting down, waiting up to {}ms for the in-flight requests", shutdown_deadline.as_millis());
+    shutdown.shut_down(shutdown_deadline).await;
+}
+
+/// Installs the subscriber as the global default, adding the layer
+/// which exports the spans via OpenTelemetry if it is configured
+#[cfg(feature = "monitoring")]
+fn init_tracing<S>(subscriber: S, node: &InterledgerNode) -> TracingGuard
+where
+    S: tracing::Subscriber + for<'span> LookupSpan<'span> + Send + Sync + 'static,
+{
+    cfg_if! {
+        if #[cfg(feature = "opentelemetry")] {
+            let (otel_layer, guard) = match node.opentelemetry {
+                Some(ref config) => match otlp_layer(config) {
+                    Ok((layer, guard)) => (Some(layer), Some(guard)),
+                    Err(err) => {
+                        eprintln!("Error setting up the OpenTelemetry exporter: {}", err);
+                        (None, None)
+                    }
+                },
+                None => (None, None),
+            };
+            let _ = subscriber.with(otel_layer).try_init();
+            guard
+        } else {
+            let _ = node;
+            let _ = subscriber.try_init();
+            TracingGuard
+        }
+    }
 }
 
 fn output_config_error(error: ConfigError, config_path: Option<&str>) {
@@ -272,23 +653,6 @@ fn merge_args(config: &mut Config, matches: &ArgMatches) {
     }
 }
 
-// retrieve Config from a certain prefix
-// if the prefix is `ilp`, `address` is resolved to `ilp_address`
-fn get_env_config(prefix: &str) -> Config {
-    let mut config = Config::new();
-    config
-        .merge(config::Environment::with_prefix(prefix).separator("__"))
-        .unwrap();
-
-    if prefix.to_lowercase() == "ilp" {
-        if let Ok(value) = config.get_str("address") {
-            config.set("ilp_address", value).unwrap();
-        }
-    }
-
-    config
-}
-
 // This sets the Config values which contains environment variables, config file settings, and STDIN
 // settings, into each option's env value which is used when Parser parses the arguments. If this
 // value is set, the Parser reads the value from it and doesn't warn even if the argument is not
@@ -0,0 +1,147 @@
+use async_trait::async_trait;
+use futures::future::BoxFuture;
+use interledger::{
+    service::{IlpResult, IncomingRequest, IncomingService, OutgoingRequest, OutgoingService},
+    store::account::Account,
+};
+use std::{future::Future, sync::Arc};
+
+type IncomingMiddleware = Arc<
+    dyn Fn(
+            IncomingRequest<Account>,
+            Box<dyn IncomingService<Account> + Send>,
+        ) -> BoxFuture<'static, IlpResult>
+        + Send
+        + Sync,
+>;
+type OutgoingMiddleware = Arc<
+    dyn Fn(
+            OutgoingRequest<Account>,
+            Box<dyn OutgoingService<Account> + Send>,
+        ) -> BoxFuture<'static, IlpResult>
+        + Send
+        + Sync,
+>;
+
+/// Custom services which the node inserts into its chains of services, so that packets can
+/// be filtered or modified by business rules without forking the node. Each middleware is
+/// called with the request and the next service, like the functions passed to
+/// [`IncomingService::wrap`](../interledger/service/trait.IncomingService.html#method.wrap),
+/// and may either reply right away or pass the (modified) request on.
+///
+/// The middlewares are called in the order in which they were added:
+/// - the incoming ones with the packets the node forwards, after they were validated,
+///   rate limited, and checked against the blocklist and the accounts' packet filters,
+///   right before they are routed
+/// - the outgoing ones with the packets the node sends (including the ones it forwards),
+///   once their amount is converted to the receiving account's asset, before it is
+///   deducted from the balances
+#[derive(Clone, Default)]
+pub struct Middlewares {
+    incoming: Vec<IncomingMiddleware>,
+    outgoing: Vec<OutgoingMiddleware>,
+}
+
+impl Middlewares {
+    /// Adds a middleware to the incoming packets
+    pub fn incoming<F, R>(&mut self, middleware: F) -> &mut Self
+    where
+        F: Fn(IncomingRequest<Account>, Box<dyn IncomingService<Account> + Send>) -> R
+            + Send
+            + Sync
+            + 'static,
+        R: Future<Output = IlpResult> + Send + 'static,
+    {
+        self.incoming.push(Arc::new(move |request, next| {
+            Box::pin(middleware(request, next)) as BoxFuture<'static, IlpResult>
+        }));
+        self
+    }
+
+    /// Adds a middleware to the outgoing packets
+    pub fn outgoing<F, R>(&mut self, middleware: F) -> &mut Self
+    where
+        F: Fn(OutgoingRequest<Account>, Box<dyn OutgoingService<Account> + Send>) -> R
+            + Send
+            + Sync
+            + 'static,
+        R: Future<Output = IlpResult> + Send + 'static,
+    {
+        self.outgoing.push(Arc::new(move |request, next| {
+            Box::pin(middleware(request, next)) as BoxFuture<'static, IlpResult>
+        }));
+        self
+    }
+
+    /// Inserts the incoming middlewares before the service
+    pub(crate) fn wrap_incoming<I>(&self, next: I) -> MiddlewareService<IncomingMiddleware, I>
+    where
+        I: IncomingService<Account> + Clone + Send + Sync + 'static,
+    {
+        MiddlewareService::new(self.incoming.clone(), next)
+    }
+
+    /// Inserts the outgoing middlewares before the service
+    pub(crate) fn wrap_outgoing<O>(&self, next: O) -> MiddlewareService<OutgoingMiddleware, O>
+    where
+        O: OutgoingService<Account> + Clone + Send + Sync + 'static,
+    {
+        MiddlewareService::new(self.outgoing.clone(), next)
+    }
+}
+
+/// Calls the middlewares one after the other, and then the next service
+#[derive(Clone)]
+pub(crate) struct MiddlewareService<M, S> {
+    middlewares: Arc<Vec<M>>,
+    index: usize,
+    next: S,
+}
+
+impl<M, S> MiddlewareService<M, S> {
+    fn new(middlewares: Vec<M>, next: S) -> Self {
+        MiddlewareService {
+            middlewares: Arc::new(middlewares),
+            index: 0,
+            next,
+        }
+    }
+
+    /// Returns the service which calls the middlewares after the current one
+    fn remaining(&self) -> Self
+    where
+        S: Clone,
+    {
+        MiddlewareService {
+            middlewares: self.middlewares.clone(),
+            index: self.index + 1,
+            next: self.next.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl<I> IncomingService<Account> for MiddlewareService<IncomingMiddleware, I>
+where
+    I: IncomingService<Account> + Clone + Send + Sync + 'static,
+{
+    async fn handle_request(&mut self, request: IncomingRequest<Account>) -> IlpResult {
+        match self.middlewares.get(self.index).cloned() {
+            Some(middleware) => middleware(request, Box::new(self.remaining())).await,
+            None => self.next.handle_request(request).await,
+        }
+    }
+}
+
+#[async_trait]
+impl<O> OutgoingService<Account> for MiddlewareService<OutgoingMiddleware, O>
+where
+    O: OutgoingService<Account> + Clone + Send + Sync + 'static,
+{
+    async fn send_request(&mut self, request: OutgoingRequest<Account>) -> IlpResult {
+        match self.middlewares.get(self.index).cloned() {
+            Some(middleware) => middleware(request, Box::new(self.remaining())).await,
+            None => self.next.send_request(request).await,
+        }
+    }
+}
@@ -0,0 +1,430 @@
+//! `ilp-node migrate-store`, which copies the state of a node (its accounts, balances,
+//! routes, rates, settlement state and everything else in its store) from one Redis store
+//! to another, so that operators can move a node to another Redis server without recreating
+//! its accounts. Redis is the only store backend, so there is no other backend to copy to.
+//!
+//! The values of the keys are read and written with the commands of their types rather than
+//! with `DUMP` and `RESTORE`, whose serialization depends on the version of the server, so
+//! that the servers can run different versions of Redis. The node keeps decrypting its
+//! tokens with the same `secret_seed`. After the copy, the values of every key of the target
+//! are compared with the source. With `--sync`, the copy is then repeated until the command
+//! is interrupted. The node is not written to both stores, so it must be stopped before
+//! the last pass: the writes it makes after that pass are not copied.
+
+use crate::shutdown::shutdown_signal;
+use clap::{App, Arg};
+use redis_crate::{aio::Connection, cmd, Client, IntoConnectionInfo, Value};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    ffi::OsString,
+    iter,
+    time::Duration,
+};
+use tokio::time::delay_for;
+
+const DEFAULT_BATCH_SIZE: usize = 1000;
+const DEFAULT_SYNC_INTERVAL: u64 = 1000;
+
+fn migrate_store_app<'a, 'b>() -> App<'a, 'b> {
+    App::new("ilp-node migrate-store")
+        .about("Copies the state of a node from one Redis store to another and checks that the copy matches. Redis is the only store backend, so both stores must be Redis databases (for example to move a node to another Redis server). The node must be stopped before the last pass of the copy, since its writes are not mirrored to the target as it makes them.")
+        .args(&[
+            Arg::with_name("from")
+                .long("from")
+                .takes_value(true)
+                .required(true)
+                .help("URL of the store to copy, which is the node's database_url (redis:// or redis+unix://)."),
+            Arg::with_name("to")
+                .long("to")
+                .takes_value(true)
+                .required(true)
+                .help("URL of the store to copy to, which the node then uses as its database_url."),
+            Arg::with_name("overwrite")
+                .long("overwrite")
+                .help("Copy to a store which is not empty. Its keys which are not in the source are deleted."),
+            Arg::with_name("sync")
+                .long("sync")
+                .help("After the copy, keep copying the keys which changed in the source to the target until the command is interrupted (with Ctrl-C or SIGTERM), then do a last pass and check the copy again. Stop the node before interrupting the command, and restart it with the new database_url once the command exits: the writes the node makes after the last pass are lost."),
+            Arg::with_name("sync_interval")
+                .long("sync_interval")
+                .takes_value(true)
+                .help("Milliseconds between the passes of --sync. Defaults to 1000."),
+            Arg::with_name("batch_size")
+                .long("batch_size")
+                .takes_value(true)
+                .help("Number of keys copied per round trip. Defaults to 1000."),
+        ])
+}
+
+/// The content of a key, in a form which does not depend on how the store encodes it, so
+/// that the keys of two stores can be compared, and copied between versions of Redis
+#[derive(Debug, PartialEq)]
+enum Content {
+    Missing,
+    String(Vec<u8>),
+    List(Vec<Vec<u8>>),
+    Set(BTreeSet<Vec<u8>>),
+    Hash(BTreeMap<Vec<u8>, Vec<u8>>),
+    SortedSet(BTreeMap<Vec<u8>, f64>),
+}
+
+impl Content {
+    /// Adds the commands which replace the key of the target with this content, and its
+    /// time to live in milliseconds (0 if it does not expire), to the pipeline
+    fn write(&self, pipe: &mut redis_crate::Pipeline, key: &[u8], ttl: u64) {
+        pipe.cmd("DEL").arg(key).ignore();
+        match self {
+            Content::Missing => return,
+            Content::String(value) => {
+                pipe.cmd("SET").arg(key).arg(value.as_slice()).ignore();
+            }
+            // The collections are never empty, since Redis removes the empty ones
+            Content::List(items) => {
+                pipe.cmd("RPUSH").arg(key).arg(items).ignore();
+            }
+            Content::Set(members) => {
+                pipe.cmd("SADD")
+                    .arg(key)
+                    .arg(members.iter().collect::<Vec<_>>())
+                    .ignore();
+            }
+            Content::Hash(fields) => {
+                let command = pipe.cmd("HSET").arg(key);
+                for (field, value) in fields {
+                    command.arg(field.as_slice()).arg(value.as_slice());
+                }
+                command.ignore();
+            }
+            Content::SortedSet(members) => {
+                let command = pipe.cmd("ZADD").arg(key);
+                for (member, score) in members {
+                    command.arg(*score).arg(member.as_slice());
+                }
+                command.ignore();
+            }
+        }
+        if ttl > 0 {
+            pipe.cmd("PEXPIRE").arg(key).arg(ttl).ignore();
+        }
+    }
+}
+
+pub struct Store {
+    pub url: String,
+    pub connection: Connection,
+}
+
+impl Store {
+    pub async fn connect(url: &str) -> Result<Self, String> {
+        let scheme = url.split("://").next().unwrap_or_default();
+        if !["redis", "redis+unix", "unix"].contains(&scheme) {
+            return Err(format!(
+                "Unsupported store {}: Redis is the only store backend",
+                url
+            ));
+        }
+        let connection_info = url
+            .into_connection_info()
+            .map_err(|err| format!("Invalid Redis URL {}: {}", url, err))?;
+        let connection = Client::open(connection_info)
+            .map_err(|err| format!("Invalid Redis URL {}: {}", url, err))?
+            .get_async_connection()
+            .await
+            .map_err(|err| format!("Cannot connect to {}: {}", url, err))?;
+        Ok(Store {
+            url: url.to_string(),
+            connection,
+        })
+    }
+
+    pub fn error(&self, action: &str, err: redis_crate::RedisError) -> String {
+        format!("Error {} {}: {}", action, self.url, err)
+    }
+
+    pub async fn keys(&mut self, batch_size: usize) -> Result<HashSet<Vec<u8>>, String> {
+        let mut keys = HashSet::new();
+        let mut cursor = 0u64;
+        loop {
+            let (next, batch): (u64, Vec<Vec<u8>>) = cmd("SCAN")
+                .arg(cursor)
+                .arg("COUNT")
+                .arg(batch_size)
+                .query_async(&mut self.connection)
+                .await
+                .map_err(|err| self.error("listing the keys of", err))?;
+            keys.extend(batch);
+            if next == 0 {
+                return Ok(keys);
+            }
+            cursor = next;
+        }
+    }
+
+    /// Returns the serialized values of the keys, and their time to live in milliseconds
+    /// (0 if they do not expire)
+    pub async fn dump(&mut self, keys: &[Vec<u8>]) -> Result<Vec<(Option<Vec<u8>>, u64)>, String> {
+        let mut pipe = redis_crate::pipe();
+        for key in keys {
+            pipe.cmd("DUMP")
+                .arg(key.as_slice())
+                .cmd("PTTL")
+                .arg(key.as_slice());
+        }
+        let values: Vec<(Option<Vec<u8>>, i64)> = pipe
+            .query_async(&mut self.connection)
+            .await
+            .map_err(|err| self.error("reading", err))?;
+        Ok(values
+            .into_iter()
+            .map(|(value, ttl)| (value, if ttl > 0 { ttl as u64 } else { 0 }))
+            .collect())
+    }
+
+    /// Returns the content of the keys, and their time to live in milliseconds (0 if they
+    /// do not expire)
+    async fn contents(&mut self, keys: &[Vec<u8>]) -> Result<Vec<(Content, u64)>, String> {
+        let mut pipe = redis_crate::pipe();
+        for key in keys {
+            pipe.cmd("TYPE")
+                .arg(key.as_slice())
+                .cmd("PTTL")
+                .arg(key.as_slice());
+        }
+        let kinds: Vec<(String, i64)> = pipe
+            .query_async(&mut self.connection)
+            .await
+            .map_err(|err| self.error("reading", err))?;
+
+        let mut pipe = redis_crate::pipe();
+        let mut reads = 0;
+        for (key, (kind, _)) in keys.iter().zip(kinds.iter()) {
+            match kind.as_str() {
+                "none" => continue,
+                "string" => pipe.cmd("GET").arg(key.as_slice()),
+                "list" => pipe.cmd("LRANGE").arg(key.as_slice()).arg(0).arg(-1),
+                "set" => pipe.cmd("SMEMBERS").arg(key.as_slice()),
+                "hash" => pipe.cmd("HGETALL").arg(key.as_slice()),
+                "zset" => pipe
+                    .cmd("ZRANGE")
+                    .arg(key.as_slice())
+                    .arg(0)
+                    .arg(-1)
+                    .arg("WITHSCORES"),
+                // The store does not use the other types (such as streams)
+                kind => {
+                    return Err(format!(
+                        "Cannot copy {} of {}: keys of type {} are not supported",
+                        String::from_utf8_lossy(key),
+                        self.url,
+                        kind
+                    ))
+                }
+            };
+            reads += 1;
+        }
+        let values: Vec<Value> = if reads > 0 {
+            pipe.query_async(&mut self.connection)
+                .await
+                .map_err(|err| self.error("reading", err))?
+        } else {
+            Vec::new()
+        };
+        let mut values = values.into_iter();
+
+        let mut contents = Vec::with_capacity(keys.len());
+        for (kind, ttl) in kinds {
+            let ttl = if ttl > 0 { ttl as u64 } else { 0 };
+            if kind == "none" {
+                contents.push((Content::Missing, ttl));
+                continue;
+            }
+            let value = values.next().unwrap_or(Value::Nil);
+            // The key was removed between the two round trips
+            if value == Value::Nil || value == Value::Bulk(Vec::new()) {
+                contents.push((Content::Missing, 0));
+                continue;
+            }
+            let content = match kind.as_str() {
+                "string" => redis_crate::from_redis_value(&value).map(Content::String),
+                "list" => redis_crate::from_redis_value(&value).map(Content::List),
+                "set" => redis_crate::from_redis_value(&value).map(Content::Set),
+                "hash" => redis_crate::from_redis_value(&value).map(Content::Hash),
+                _ => redis_crate::from_redis_value(&value).map(Content::SortedSet),
+            }
+            .map_err(|err| self.error("reading", err))?;
+            contents.push((content, ttl));
+        }
+        Ok(contents)
+    }
+}
+
+/// Copies the keys of the source which differ in the target, and deletes the keys of the
+/// target which are not in the source. Returns the number of keys written and deleted.
+async fn copy_pass(
+    source: &mut Store,
+    target: &mut Store,
+    batch_size: usize,
+) -> Result<(usize, usize), String> {
+    let source_keys = source.keys(batch_size).await?;
+    let keys: Vec<Vec<u8>> = source_keys.iter().cloned().collect();
+    let mut written = 0;
+    for batch in keys.chunks(batch_size) {
+        let contents = source.contents(batch).await?;
+        let copies = target.contents(batch).await?;
+        let mut pipe = redis_crate::pipe();
+        pipe.atomic();
+        let mut changed = 0;
+        for ((key, (content, ttl)), (copy, _)) in batch.iter().zip(contents).zip(copies) {
+            if content == copy {
+                continue;
+            }
+            changed += 1;
+            // A key which was removed since it was listed is deleted
+            content.write(&mut pipe, key, ttl);
+        }
+        if changed > 0 {
+            pipe.query_async::<_, ()>(&mut target.connection)
+                .await
+                .map_err(|err| target.error("writing to", err))?;
+            written += changed;
+        }
+    }
+
+    let stale: Vec<Vec<u8>> = target
+        .keys(batch_size)
+        .await?
+        .into_iter()
+        .filter(|key| !source_keys.contains(key))
+        .collect();
+    for batch in stale.chunks(batch_size) {
+        cmd("DEL")
+            .arg(batch)
+            .query_async::<_, ()>(&mut target.connection)
+            .await
+            .map_err(|err| target.error("writing to", err))?;
+    }
+    Ok((written, stale.len()))
+}
+
+/// Compares the content of every key of the source with the target, ignoring how each
+/// server encodes them. Returns the number of keys which were compared.
+async fn check_copy(
+    source: &mut Store,
+    target: &mut Store,
+    batch_size: usize,
+) -> Result<usize, String> {
+    let source_keys = source.keys(batch_size).await?;
+    let target_keys = target.keys(batch_size).await?;
+    if let Some(key) = target_keys.difference(&source_keys).next() {
+        return Err(format!(
+            "The copy does not match: {} is not in {}",
+            String::from_utf8_lossy(key),
+            source.url
+        ));
+    }
+    let keys: Vec<Vec<u8>> = source_keys.iter().cloned().collect();
+    for batch in keys.chunks(batch_size) {
+        let contents = source.contents(batch).await?;
+        let copies = target.contents(batch).await?;
+        for ((key, (content, _)), (copy, _)) in batch.iter().zip(contents).zip(copies) {
+            if content != copy {
+                return Err(format!(
+                    "The copy does not match: {} differs in {}",
+                    String::from_utf8_lossy(key),
+                    target.url
+                ));
+            }
+        }
+    }
+    Ok(source_keys.len())
+}
+
+/// Counts the accounts, routes and rates of the store, which are reported after the copy
+pub async fn summary(store: &mut Store) -> Result<String, String> {
+    let (accounts, routes, rates): (usize, usize, usize) = redis_crate::pipe()
+        .cmd("SCARD")
+        .arg("accounts")
+        .cmd("HLEN")
+        .arg("routes:current")
+        .cmd("HLEN")
+        .arg("rates:current")
+        .query_async(&mut store.connection)
+        .await
+        .map_err(|err| store.error("reading", err))?;
+    Ok(format!(
+        "{} accounts, {} routes and {} exchange rates",
+        accounts, routes, rates
+    ))
+}
+
+pub async fn run_migrate_store<I>(args: I) -> Result<(), String>
+where
+    I: Iterator<Item = OsString>,
+{
+    let matches = migrate_store_app()
+        .get_matches_from(iter::once(OsString::from("ilp-node migrate-store")).chain(args));
+    let from = matches.value_of("from").unwrap_or_default();
+    let to = matches.value_of("to").unwrap_or_default();
+    if from == to {
+        return Err("The source and the target are the same store".to_string());
+    }
+    let batch_size = match matches.value_of("batch_size") {
+        Some(size) => size
+            .parse::<usize>()
+            .ok()
+            .filter(|size| *size > 0)
+            .ok_or_else(|| format!("Invalid batch size {}", size))?,
+        None => DEFAULT_BATCH_SIZE,
+    };
+    let sync_interval = match matches.value_of("sync_interval") {
+        Some(interval) => interval
+            .parse::<u64>()
+            .map_err(|_| format!("Invalid sync interval {}", interval))?,
+        None => DEFAULT_SYNC_INTERVAL,
+    };
+    let overwrite = matches.is_present("overwrite");
+
+    let mut source = Store::connect(from).await?;
+    let mut target = Store::connect(to).await?;
+    if !overwrite && !target.keys(batch_size).await?.is_empty() {
+        return Err(format!(
+            "{} is not empty. Pass --overwrite to replace its content.",
+            to
+        ));
+    }
+    let (written, deleted) = copy_pass(&mut source, &mut target, batch_size).await?;
+    println!(
+        "Copied {} keys to {} (deleted {} keys which were not in {})",
+        written, to, deleted, from
+    );
+
+    if matches.is_present("sync") {
+        println!("Mirroring the changes to {} until interrupted", to);
+        let interrupted = shutdown_signal();
+        futures::pin_mut!(interrupted);
+        loop {
+            tokio::select! {
+                _ = &mut interrupted => break,
+                _ = delay_for(Duration::from_millis(sync_interval)) => {}
+            }
+            let (written, deleted) = copy_pass(&mut source, &mut target, batch_size).await?;
+            if written + deleted > 0 {
+                println!("Mirrored {} changed and {} deleted keys", written, deleted);
+            }
+        }
+        let (written, deleted) = copy_pass(&mut source, &mut target, batch_size).await?;
+        println!(
+            "Last pass: mirrored {} changed and {} deleted keys",
+            written, deleted
+        );
+    }
+
+    let checked = check_copy(&mut source, &mut target, batch_size).await?;
+    println!(
+        "Checked the {} keys of {}, which has {}",
+        checked,
+        to,
+        summary(&mut target).await?
+    );
+    Ok(())
+}
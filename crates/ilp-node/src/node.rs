@@ -1,91 +1,150 @@
 use cfg_if::cfg_if;
 
+#[cfg(feature = "acme")]
+use crate::acme::spawn_certificate_manager;
+#[cfg(feature = "alerting")]
+use crate::alerting::{incoming_alerts, outgoing_alerts, AlertMonitor, AlertingConfig};
 #[cfg(feature = "google-pubsub")]
 use crate::instrumentation::google_pubsub::{create_google_pubsub_wrapper, PubsubConfig};
+#[cfg(feature = "opentelemetry")]
+use crate::instrumentation::opentelemetry::OpenTelemetryConfig;
+#[cfg(feature = "telemetry")]
+use crate::telemetry::{telemetry_api, Telemetry, TelemetryConfig};
+#[cfg(any(feature = "monitoring", feature = "telemetry"))]
+use interledger::api::admin_only;
+#[cfg(feature = "fault-injection")]
+use interledger::service_util::{FaultInjectionService, FaultInjector};
 
 cfg_if! {
     if #[cfg(feature = "monitoring")] {
         use interledger::errors::ApiError;
-        use secrecy::{ExposeSecret, SecretString};
         use tracing::debug_span;
         use tracing_appender::non_blocking::NonBlocking;
         use tracing_futures::Instrument;
         use tracing_subscriber::{
             filter::EnvFilter,
             fmt::{format, time::ChronoUtc, Formatter},
-            reload::Handle,
+            reload::{self, Handle},
         };
         use crate::instrumentation::{
-            metrics::{incoming_metrics, outgoing_metrics},
+            metrics::{incoming_metrics, outgoing_metrics, schedule_store_metrics},
             prometheus::{serve_prometheus, PrometheusConfig},
-            trace::{trace_forwarding, trace_incoming, trace_outgoing},
+            trace::{
+                account_log_directives, incoming_span, outgoing_span, trace_forwarding,
+                trace_incoming, trace_outgoing, without_account_directives,
+            },
         };
-        use interledger::service::IncomingService;
-        use futures::FutureExt;
-        use std::{io::{self, Stdout}, sync::Arc};
+        use std::io::{self, Stdout};
     }
 }
 
-#[cfg(any(feature = "monitoring", feature = "google-pubsub"))]
+#[cfg(any(
+    feature = "monitoring",
+    feature = "google-pubsub",
+    feature = "alerting"
+))]
 use interledger::service::OutgoingService;
 
 use bytes::Bytes;
 use futures::TryFutureExt;
 use hex::FromHex;
 use interledger::{
-    api::{NodeApi, NodeStore},
+    api::{
+        execute_scheduled_payments, fail_interrupted_payments, versioned_api, AccountTemplateStore,
+        AssetStore, AuditLogStore, ChildProvisioning, JwtAuth, JwtConfig, NodeApi, NodeStore,
+        OutgoingPaymentStore, PaymentPointerStore, PeeringStore, PullPaymentStore,
+        ReceiptVerifierStore, RuntimeSettings, ScheduledPaymentStore,
+    },
     btp::{btp_service_as_filter, connect_client, BtpOutgoingService, BtpStore},
-    ccp::{CcpRouteManagerBuilder, CcpRoutingAccount, CcpRoutingStore, RoutingRelation},
-    errors::*,
+    ccp::{
+        AddressRangeService, CcpRouteManagerBuilder, CcpRoutingAccount, CcpRoutingStore,
+        RoutingRelation,
+    },
     http::{HttpClientService, HttpServer as IlpOverHttpServer, HttpStore},
     ildcp::IldcpService,
     packet::Address,
     packet::{ErrorCode, RejectBuilder},
-    rates::{ExchangeRateFetcher, ExchangeRateStore},
+    rates::{ExchangeRateFetcher, ExchangeRateStore, PinnedRateStore, RateGuards},
     router::{Router, RouterStore},
     service::{
-        outgoing_service_fn, Account as AccountTrait, AccountStore, AddressStore, OutgoingRequest,
-        Username,
+        clock, outgoing_service_fn, Account as AccountTrait, AccountStore, AddressStore,
+        BlocklistConfig, IncomingService, LeaseStore, OutgoingRequest, PacketFilterService,
+        PeerBlocklist, PeerBlocklistService, TrustedProxies, Username,
     },
     service_util::{
-        BalanceStore, EchoService, ExchangeRateService, ExpiryShortenerService,
-        MaxPacketAmountService, RateLimitService, RateLimitStore, ValidatorService,
+        BalanceJournalStore, BalanceNotificationConfig, BalanceNotifications, BalanceStore,
+        DeduplicationService, EchoPings, EchoService, ExchangeRateService, ExpiryShortenerService,
+        FairQueueConfig, FairQueueService, FeePolicy, FeePolicyStore, LatencyProbeConfig,
+        LiquidityStore, MaxPacketAmountService, NodeStats, PacketSizeService, PacketTraceService,
+        PacketTraces, PeerLatencies, RateLimitService, RateLimitStore, RejectRedactionConfig,
+        RejectRedactionService, Scheduler, SchedulerConfig, SettlementReconciliation, StatsService,
+        TransferLimitService, TransferLimitStore, ValidatorService, DEFAULT_MAX_EXPIRY_DURATION,
     },
     settlement::{
         api::{create_settlements_filter, SettlementMessageService},
         core::{
             idempotency::IdempotentStore,
-            types::{LeftoversStore, SettlementStore},
+            replay_protection::{NonceStore, ReplayProtection},
+            types::{LeftoversStore, SettlementQueueStore, SettlementStore, SettlementTotalsStore},
+            SettlementClient, SettlementTlsConfig,
         },
     },
-    store::account::Account,
-    stream::{StreamNotificationsStore, StreamReceiverService},
+    store::{account::Account, kms::KeyManagerConfig},
+    stream::{
+        MaxPacketAmountStore, StreamConnections, StreamNotificationsStore, StreamReceiptStore,
+        StreamReceiverService, UnreachableDestinationStore,
+    },
 };
 use num_bigint::BigUint;
 use once_cell::sync::Lazy;
-use serde::{de::Error as DeserializeError, Deserialize, Deserializer};
+use secrecy::{ExposeSecret, SecretString};
+use serde::{
+    de::{
+        value::{Error as ValueError, MapDeserializer},
+        Error as DeserializeError,
+    },
+    Deserialize, Deserializer,
+};
 use std::{
     convert::TryFrom,
+    future::Future,
     net::SocketAddr,
     str::{self, FromStr},
-    time::Duration,
+    sync::{atomic::AtomicBool, Arc},
+    time::{Duration, Instant, UNIX_EPOCH},
 };
-use tokio::spawn;
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, trace, warn};
 use url::Url;
 use uuid::Uuid;
-use warp::{self, Filter};
+use warp::{self, http::HeaderValue, Filter};
 
+use crate::config_watch::{
+    spawn_settings_watcher, ReloadableSettings, DEFAULT_SETTINGS_POLL_INTERVAL,
+};
+use crate::coordination::{ClusterConfig, Coordinator};
+use crate::hooks::Hooks;
+use crate::listeners::{boxed_routes, serve_routes, split_subsystems, ListenersConfig, Subsystem};
+use crate::middleware::Middlewares;
 #[cfg(feature = "redis")]
 use crate::redis_store::*;
+use crate::shutdown::{bind_listener, incoming_shutdown, Shutdown, ShutdownConfig};
+use crate::tls::{tls_incoming, HttpTlsConfig};
 #[cfg(feature = "balance-tracking")]
-use interledger::service_util::BalanceService;
+use interledger::service_util::{BalanceNotifier, BalanceService, LiquidityNotifier};
 
 #[doc(hidden)]
-pub use interledger::rates::ExchangeRateProvider;
+pub use interledger::rates::ExchangeRateProviderConfig;
 
 static DEFAULT_ILP_ADDRESS: Lazy<Address> = Lazy::new(|| Address::from_str("local.host").unwrap());
 
+/// How often the scheduled payments which are due are sent
+const SCHEDULED_PAYMENTS_INTERVAL: Duration = Duration::from_secs(5);
+/// How often the payments interrupted by a node stopping are failed
+const OUTGOING_PAYMENTS_INTERVAL: Duration = Duration::from_secs(30);
+/// How often the peers are notified of the packets blocked by their receivable limits
+#[cfg(feature = "balance-tracking")]
+const LIQUIDITY_NOTIFICATION_INTERVAL: Duration = Duration::from_secs(60);
+
 fn default_settlement_api_bind_address() -> SocketAddr {
     SocketAddr::from(([127, 0, 0, 1], 7771))
 }
@@ -141,8 +200,32 @@ where
     }
 }
 
+/// Accepts either a list of strings or a single comma-separated string
+/// (as passed on the command line or via an environment variable)
+pub(crate) fn deserialize_string_list<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StringList {
+        List(Vec<String>),
+        Joined(String),
+    }
+
+    Ok(match StringList::deserialize(deserializer)? {
+        StringList::List(list) => list,
+        StringList::Joined(joined) => joined
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(String::from)
+            .collect(),
+    })
+}
+
 /// Configuration for calculating exchange rates between various pairs.
-#[derive(Deserialize, Clone, Default)]
+#[derive(Deserialize, Clone)]
 pub struct ExchangeRateConfig {
     /// Interval, defined in milliseconds, on which the node will poll the exchange rate provider.
     /// Defaults to 60000ms (60 seconds).
@@ -155,10 +238,16 @@ pub struct ExchangeRateConfig {
     /// API to poll for exchange rates. Currently the supported options are:
     /// - [CoinCap](https://docs.coincap.io)
     /// - [CryptoCompare](https://cryptocompare.com) (note this requires an API key)
+    /// - [CoinGecko](https://www.coingecko.com/en/api)
+    /// - [Kraken](https://www.kraken.com/features/api)
+    /// - [Bitstamp](https://www.bitstamp.net/api/)
+    /// - Http: any API returning JSON, with the rates located via JSONPath expressions
+    /// - Median: the median of the rates returned by a list of the above providers
+    ///
     /// If this value is not set, the node will not poll for exchange rates and will
     /// instead use the rates configured via the HTTP API.
     #[serde(default)]
-    pub provider: Option<ExchangeRateProvider>,
+    pub provider: Option<ExchangeRateProviderConfig>,
     /// Spread, as a fraction, to add on top of the exchange rate.
     /// This amount is kept as the node operator's profit, or may cover
     /// fluctuations in exchange rates.
@@ -167,6 +256,24 @@ pub struct ExchangeRateConfig {
     /// outgoing packet would be 198 (instead of 200 without the spread).
     #[serde(default)]
     pub spread: f64,
+    /// Fee, as a fraction of the converted amount, deducted from each packet.
+    /// The spread and this fee can be overridden for asset pairs (via the
+    /// `/fees` API) and for accounts (via their `spread`, `percentage_fee`
+    /// and `fixed_fee` settings).
+    #[serde(default)]
+    pub percentage_fee: Option<f64>,
+    /// Maximum age, in seconds, of a polled rate. While the provider fails, or stops
+    /// returning an asset, its last known good rate is used until it is older than this,
+    /// after which it is removed. If this is not set, rates are kept while polling fails
+    /// (up to `poll_failure_tolerance` times) and removed as soon as the provider stops
+    /// returning them.
+    #[serde(default)]
+    pub max_age: Option<u64>,
+    /// Maximum change, as a fraction of the previous rate, which is applied without operator
+    /// confirmation. New rates which deviate more are rejected and the previous rate is kept,
+    /// until the operator confirms the new rate by setting it via the HTTP API.
+    #[serde(default)]
+    pub max_deviation: Option<f64>,
 }
 
 impl ExchangeRateConfig {
@@ -178,6 +285,66 @@ impl ExchangeRateConfig {
     }
 }
 
+impl Default for ExchangeRateConfig {
+    fn default() -> Self {
+        ExchangeRateConfig {
+            poll_interval: ExchangeRateConfig::default_poll_interval(),
+            poll_failure_tolerance: ExchangeRateConfig::default_poll_failure_tolerance(),
+            provider: None,
+            spread: 0.0,
+            percentage_fee: None,
+            max_age: None,
+            max_deviation: None,
+        }
+    }
+}
+
+/// Configuration for the checks and adjustments of the expiries of packets
+#[derive(Deserialize, Clone)]
+pub struct ExpiryConfig {
+    /// Minimum time, in milliseconds, an incoming packet must have left before it expires.
+    /// Packets expiring sooner are rejected with `R02: Insufficient Timeout`. Defaults to 0.
+    #[serde(default)]
+    pub min_window: u32,
+    /// Maximum difference, in milliseconds, between the clocks of the node and its peers
+    /// which is tolerated when checking whether incoming packets expired. Defaults to 0.
+    #[serde(default)]
+    pub max_clock_skew: u32,
+    /// Time, in milliseconds, subtracted from the expiry of each forwarded packet, on top
+    /// of the round trip times of the incoming and outgoing accounts. Defaults to 0.
+    #[serde(default)]
+    pub hop_reduction: u32,
+    /// Maximum time, in milliseconds, until a forwarded packet expires. Packets which
+    /// expire later are shortened to this. Defaults to 30000ms (30 seconds).
+    #[serde(default = "ExpiryConfig::default_max_duration")]
+    pub max_duration: u32,
+}
+
+impl ExpiryConfig {
+    fn default_max_duration() -> u32 {
+        DEFAULT_MAX_EXPIRY_DURATION
+    }
+}
+
+impl Default for ExpiryConfig {
+    fn default() -> Self {
+        ExpiryConfig {
+            min_window: 0,
+            max_clock_skew: 0,
+            hop_reduction: 0,
+            max_duration: DEFAULT_MAX_EXPIRY_DURATION,
+        }
+    }
+}
+
+/// The secret a settlement engine signs its calls to the Settlement Engine API with
+#[derive(Deserialize, Clone)]
+pub struct SettlementEngineSecret {
+    /// URL of the engine, as set on its accounts
+    pub url: Url,
+    pub secret: SecretString,
+}
+
 /// An all-in-one Interledger node that includes sender and receiver functionality,
 /// a connector, and a management API.
 /// Will connect to the database at the given URL; see the crate features defined in
@@ -193,6 +360,10 @@ pub struct InterledgerNode {
     pub secret_seed: [u8; 32],
     /// HTTP Authorization token for the node admin (sent as a Bearer token)
     pub admin_auth_token: String,
+    /// Accept the JWTs issued by an identity provider for the admin and the accounts,
+    /// validated against the keys of its JWKS, in addition to the static tokens
+    #[serde(default)]
+    pub jwt_auth: Option<JwtConfig>,
     /// Data store URI (for example, "redis://127.0.0.1:6379" or "redis+unix:/tmp/redis.sock")
     #[serde(
         default = "default_database_url",
@@ -200,35 +371,340 @@ pub struct InterledgerNode {
         alias = "redis_url"
     )]
     pub database_url: String,
+    /// URIs of read-only replicas of the database. Account lookups and route table loads
+    /// are spread over the replicas, while all writes go to the `database_url`.
+    #[serde(default, deserialize_with = "deserialize_string_list")]
+    pub read_replica_urls: Vec<String>,
+    /// Max lag, defined in milliseconds, of a read replica behind the database for it to be
+    /// read from. Defaults to 1000ms (1 second).
+    pub read_replica_max_staleness: Option<u64>,
     /// IP address and port to listen for HTTP connections
     /// This is used for both the API and ILP over HTTP packets
     #[serde(default = "default_http_bind_address")]
     pub http_bind_address: SocketAddr,
+    /// TLS termination of the HTTP API, with a configured certificate or one issued and
+    /// renewed with ACME. If this is not set, the HTTP API is served without TLS (which
+    /// requires a reverse proxy terminating TLS in front of the node in production).
+    #[serde(default)]
+    pub http_tls: Option<HttpTlsConfig>,
+    /// Addresses on which the HTTP API, ILP over HTTP, BTP and the SPSP endpoints are
+    /// served apart, each with its own TLS configuration. The subsystems which are not
+    /// configured are served together on the `http_bind_address`.
+    #[serde(default)]
+    pub listeners: ListenersConfig,
+    /// IP address and port to serve the admin API over gRPC on (HTTP/2 without TLS).
+    /// It is not served if this is not set.
+    #[serde(default)]
+    pub grpc_bind_address: Option<SocketAddr>,
+    /// URL at which other nodes reach the node's API, such as `https://node.example.com`.
+    /// It enables the peering requests, with which the accounts of two nodes are created
+    /// on both sides once the admin of the node receiving the request approves it.
+    #[serde(default)]
+    pub public_url: Option<Url>,
+    /// Lets wallets create their own `Child` account under the node, by signing a challenge
+    /// issued by the node with their Ed25519 key. The accounts are created in the configured
+    /// asset and with its constrained defaults. It is disabled if this is not set.
+    #[serde(default)]
+    pub child_provisioning: Option<ChildProvisioning>,
     /// IP address and port to listen for the Settlement Engine API
     #[serde(default = "default_settlement_api_bind_address")]
     pub settlement_api_bind_address: SocketAddr,
+    /// Mutual TLS between the node and its settlement engines. If this is set, the node calls
+    /// the engines with its client certificate and only trusts the engine certificates signed
+    /// by the configured CA, and the settlement API is served over TLS and requires the
+    /// engines to present a client certificate signed by that CA.
+    #[serde(default)]
+    pub settlement_tls: Option<SettlementTlsConfig>,
+    /// Time, defined in seconds, outside of which the calls of the settlement engines are
+    /// rejected as stale. If this is set, the engines must send a timestamp and a nonce
+    /// with each call, signed with their secret from `settlement_engine_secrets`, and calls
+    /// reusing a nonce are rejected as replayed.
+    pub settlement_replay_window: Option<u64>,
+    /// The secrets the settlement engines sign their calls with, if `settlement_replay_window`
+    /// is set. Each call must be signed with the secret of the engine of its account.
+    #[serde(default)]
+    pub settlement_engine_secrets: Vec<SettlementEngineSecret>,
     /// When SPSP payments are sent to the root domain, the payment pointer is resolved
     /// to <domain>/.well-known/pay. This value determines which account those payments
     /// will be sent to.
     #[serde(default, deserialize_with = "deserialize_optional_username")]
     pub default_spsp_account: Option<Username>,
+    /// Whether the node issues STREAM receipts of the amounts received via the payment
+    /// pointers it hosts, and verifies them for the websites paid via those pointers
+    /// (see `POST /receipts/verify`). Defaults to false.
+    #[serde(default)]
+    pub stream_receipts: bool,
+    /// Whether the admin can query the node's accounts, balances, settlements, routes,
+    /// rates and recent payments via `/graphql`. Defaults to false.
+    #[serde(default)]
+    pub graphql: bool,
+    /// The date after which the unversioned paths of the API (which predate the `/v1`
+    /// prefix) may be removed, as an HTTP date such as `Sat, 31 Dec 2022 23:59:59 GMT`.
+    /// It is sent in the `Sunset` header of their responses.
+    #[serde(default)]
+    pub api_sunset: Option<String>,
     /// Interval, defined in milliseconds, on which the node will broadcast routing
     /// information to other nodes using CCP. Defaults to 30000ms (30 seconds).
     pub route_broadcast_interval: Option<u64>,
+    /// The max amount per packet which the node forwards for any account, on top of each
+    /// account's own max_packet_amount. If this is not set, only the accounts' limits apply.
+    pub max_packet_amount: Option<u64>,
+    /// The max size, defined in bytes, of the prepare packets which the node accepts from any
+    /// account, on top of each account's own max_packet_size. Packets received over BTP and
+    /// HTTP can never be larger than 40000 bytes.
+    pub max_packet_size: Option<u64>,
+    /// Max memory, defined in bytes, of the cache of recently forwarded prepare packets
+    /// which duplicates are answered from instead of being forwarded again.
+    /// Defaults to 0, which disables the deduplication.
+    #[serde(default)]
+    pub packet_deduplication_memory: usize,
+    /// Configuration of the per-account queues of the incoming packets, which share the
+    /// processing of the node between the peers when it is busy. If `max_concurrent` is
+    /// not set, the packets are processed as soon as they are received.
+    #[serde(default)]
+    pub fair_queuing: FairQueueConfig,
+    /// Policies, per error class, with which the rejects sent back to the senders of
+    /// packets are sanitized, so that they do not leak internal details such as store errors
+    /// or the addresses of the peers. The rejects of the classes without a policy are sent as is.
+    #[serde(default)]
+    pub reject_redaction: RejectRedactionConfig,
+    /// Interval, defined in milliseconds, on which the node will check the store for changes
+    /// to its runtime settings (see the `/settings` API). Defaults to 10000ms (10 seconds).
+    pub settings_poll_interval: Option<u64>,
+    /// Path of the config file the node was started with. When the node receives a SIGHUP,
+    /// the settings which can be changed at runtime are reloaded from this file.
+    #[serde(default, rename = "config")]
+    pub config_file: Option<String>,
+    /// Interval, defined in milliseconds, on which the store will snapshot the balances
+    /// of all accounts. Balances are verified by replaying the balance journal on top of
+    /// the latest snapshot. If this is not set, snapshots are only taken via the API.
+    pub balance_snapshot_interval: Option<u64>,
+    /// Interval, defined in milliseconds, on which the store will release the escrowed
+    /// amounts of packets which expired without being fulfilled or rejected (for example
+    /// because a node crashed while they were in flight). Defaults to 10000ms (10 seconds).
+    pub escrow_sweep_interval: Option<u64>,
+    /// Interval, defined in milliseconds, on which the totals settled with the accounts which
+    /// have a settlement engine are compared with the totals of their engines. If this is not
+    /// set, the settlements are not reconciled.
+    pub settlement_reconciliation_interval: Option<u64>,
+    /// Time, defined in seconds, after which idempotency records of the settlement API
+    /// expire. Defaults to 86400 seconds (24 hours).
+    pub idempotency_ttl: Option<u64>,
+    /// Time, defined in seconds, after the last change to an account's uncredited settlement
+    /// amount after which it expires. If this is not set, uncredited amounts never expire.
+    pub uncredited_settlement_ttl: Option<u64>,
+    /// Interval, defined in milliseconds, on which the store will apply the configured TTLs
+    /// to records written without one and delete data left behind by deleted accounts.
+    /// If this is not set, the store is not compacted.
+    pub compaction_interval: Option<u64>,
+    /// Time, defined in seconds, for which the entries of the audit log of the admin API
+    /// are kept. If this is not set, they are kept forever.
+    pub audit_log_retention: Option<u64>,
+    /// Key manager used for the envelope encryption of the account secrets in the store.
+    /// If it is not set, the secrets are encrypted with a key derived from the `secret_seed`.
+    #[serde(default)]
+    pub key_management: Option<KeyManagerConfig>,
+    /// Key manager which wrapped the current data encryption key. This is only used when
+    /// rotating the encryption key, to switch from one key manager to another.
+    #[serde(default)]
+    pub previous_key_management: Option<KeyManagerConfig>,
     #[serde(default)]
     /// Configuration for calculating exchange rates between various pairs.
     pub exchange_rate: ExchangeRateConfig,
+    /// Configuration for the checks and adjustments of the expiries of packets
+    #[serde(default)]
+    pub expiry: ExpiryConfig,
+    /// Thresholds of the misbehavior (malformed packets, authentication failures and
+    /// rejected packets) after which a peer is quarantined. If no threshold is set, peers
+    /// are only quarantined via the `/blocklist` API.
+    #[serde(default)]
+    pub blocklist: BlocklistConfig,
+    /// The reverse proxies in front of the node, whose header gives the address of the
+    /// clients which is checked against the IP allowlists of the accounts
+    #[serde(default)]
+    pub trusted_proxies: TrustedProxies,
+    /// Configuration of the echo requests with which the node probes its peers and parents,
+    /// to keep statistics of their round trip times and jitter (see
+    /// `GET /accounts/:username/stats`). If it is not set, the accounts are not probed.
+    #[serde(default)]
+    pub latency_probes: Option<LatencyProbeConfig>,
+    /// Thresholds of the balances of the accounts whose crossings are POSTed to the accounts'
+    /// balance notification URLs and sent to the API's WebSocket subscribers (see
+    /// `/accounts/:username/balance/notifications`). If it is not set, the balances are
+    /// not checked.
+    #[serde(default)]
+    pub balance_notifications: Option<BalanceNotificationConfig>,
+    /// Configuration for running multiple nodes against the same store. If it is set, only the
+    /// elected leader broadcasts routes and polls the exchange rate provider, and each outgoing
+    /// BTP connection is opened by a single node. If it is not set, the node assumes that it is
+    /// the only one using the store.
+    #[serde(default)]
+    pub cluster: Option<ClusterConfig>,
+    /// Configuration of how the node stops when it receives a SIGTERM, and of the
+    /// addresses being reusable by the node replacing it during a restart
+    #[serde(default)]
+    pub shutdown: ShutdownConfig,
+    /// Configuration of the scheduler of the periodic tasks (such as the route broadcasts,
+    /// the polling of the exchange rates and the maintenance of the store): the jitter of
+    /// their runs, and which of them only run when they are triggered via the API
+    #[serde(default)]
+    pub scheduler: SchedulerConfig,
+    /// Configuration for the alerts POSTed to a webhook when an account crosses an
+    /// operational threshold. If this configuration is not provided, no alerts are sent.
+    /// Needs the feature flag "alerting" to be enabled
+    #[cfg(feature = "alerting")]
+    #[serde(default)]
+    pub alerting: Option<AlertingConfig>,
+    /// Configuration for sharing anonymized health metrics (the version, uptime and order of
+    /// magnitude of the packets of the node) with a collector of the network's statistics.
+    /// If this configuration is not provided, nothing is shared.
+    /// Needs the feature flag "telemetry" to be enabled
+    #[cfg(feature = "telemetry")]
+    #[serde(default)]
+    pub telemetry: Option<TelemetryConfig>,
     /// Configuration for [Prometheus](https://prometheus.io) metrics collection.
     /// If this configuration is not provided, the node will not collect metrics.
     /// Needs the feature flag "monitoring" to be enabled
     #[cfg(feature = "monitoring")]
     #[serde(default)]
     pub prometheus: Option<PrometheusConfig>,
+    /// Format of the logs: `text` (human readable lines, the default) or `json` (one JSON
+    /// object per line, including the fields of the spans in which the log was recorded)
+    #[cfg(feature = "monitoring")]
+    #[serde(default)]
+    pub log_format: LogFormat,
+    /// Configuration for exporting the traces of the packets via OpenTelemetry (OTLP).
+    /// If this configuration is not provided, the traces are only logged.
+    /// Needs the feature flag "opentelemetry" to be enabled
+    #[cfg(feature = "opentelemetry")]
+    #[serde(default)]
+    pub opentelemetry: Option<OpenTelemetryConfig>,
     #[cfg(feature = "google-pubsub")]
     pub google_pubsub: Option<PubsubConfig>,
+    /// Custom services which are inserted into the chains of services of the node.
+    /// They cannot be configured, they are added by the programs which embed the node.
+    #[serde(skip)]
+    pub middlewares: Middlewares,
+    /// Callbacks which are called as the node starts and stops.
+    /// They cannot be configured, they are added by the programs which embed the node.
+    #[serde(skip)]
+    pub hooks: Hooks,
+}
+
+/// The traits a store must implement for the node to run on it
+pub trait InterledgerStore:
+    NodeStore<Account = Account>
+    + AddressStore
+    + BtpStore<Account = Account>
+    + HttpStore<Account = Account>
+    + StreamNotificationsStore<Account = Account>
+    + MaxPacketAmountStore
+    + UnreachableDestinationStore
+    + BalanceStore
+    + ExchangeRateStore
+    + PinnedRateStore
+    + FeePolicyStore
+    + BalanceJournalStore
+    + LiquidityStore
+    + TransferLimitStore
+    + SettlementStore<Account = Account>
+    + SettlementQueueStore
+    + SettlementTotalsStore
+    + RouterStore<Account = Account>
+    + CcpRoutingStore<Account = Account>
+    + RateLimitStore<Account = Account>
+    + LeftoversStore<AccountId = Uuid, AssetType = BigUint>
+    + IdempotentStore
+    + NonceStore
+    + AccountStore<Account = Account>
+    + LeaseStore
+    + AssetStore
+    + AccountTemplateStore
+    + AuditLogStore
+    + PaymentPointerStore
+    + PullPaymentStore
+    + ScheduledPaymentStore
+    + OutgoingPaymentStore
+    + PeeringStore
+    + ReceiptVerifierStore
+    + StreamReceiptStore
+    + Clone
+    + Send
+    + Sync
+    + 'static
+{
+}
+
+impl<S> InterledgerStore for S where
+    S: NodeStore<Account = Account>
+        + AddressStore
+        + BtpStore<Account = Account>
+        + HttpStore<Account = Account>
+        + StreamNotificationsStore<Account = Account>
+        + MaxPacketAmountStore
+        + UnreachableDestinationStore
+        + BalanceStore
+        + ExchangeRateStore
+        + PinnedRateStore
+        + FeePolicyStore
+        + BalanceJournalStore
+        + LiquidityStore
+        + TransferLimitStore
+        + SettlementStore<Account = Account>
+        + SettlementQueueStore
+        + SettlementTotalsStore
+        + RouterStore<Account = Account>
+        + CcpRoutingStore<Account = Account>
+        + RateLimitStore<Account = Account>
+        + LeftoversStore<AccountId = Uuid, AssetType = BigUint>
+        + IdempotentStore
+        + NonceStore
+        + AccountStore<Account = Account>
+        + LeaseStore
+        + AssetStore
+        + AccountTemplateStore
+        + AuditLogStore
+        + PaymentPointerStore
+        + PullPaymentStore
+        + ScheduledPaymentStore
+        + OutgoingPaymentStore
+        + PeeringStore
+        + ReceiptVerifierStore
+        + StreamReceiptStore
+        + Clone
+        + Send
+        + Sync
+        + 'static
+{
 }
 
 impl InterledgerNode {
+    /// Returns the configuration of a node with the defaults of all the other options,
+    /// which can be changed (and to which middlewares and hooks can be added) before
+    /// the node is started, for example with [`run`](#method.run).
+    pub fn new(secret_seed: [u8; 32], admin_auth_token: impl Into<String>) -> Self {
+        // The options are deserialized so that their defaults are the same as in the config files
+        let options = vec![
+            ("secret_seed", "00".repeat(32)),
+            ("admin_auth_token", admin_auth_token.into()),
+        ];
+        let mut node = InterledgerNode::deserialize(MapDeserializer::<_, ValueError>::new(
+            options.into_iter(),
+        ))
+        .expect("All the other options have defaults");
+        node.secret_seed = secret_seed;
+        node
+    }
+
+    /// Starts the node, and returns the handle with which it is stopped once it accepts
+    /// connections and packets. This is how programs embedding the node run it: the node
+    /// logs with their tracing subscriber.
+    pub async fn run(self) -> Result<Shutdown, ()> {
+        let shutdown = Shutdown::default();
+        self.serve_with_shutdown(None, shutdown.clone()).await?;
+        Ok(shutdown)
+    }
+
     /// Returns a future that runs the Interledger.rs Node.
     ///
     /// If the Prometheus configuration was provided, it will
@@ -236,32 +712,137 @@ impl InterledgerNode {
     // TODO when a BTP connection is made, insert a outgoing HTTP entry into the Store to tell other
     // connector instances to forward packets for that account to us
     pub async fn serve(self, log_writer: Option<LogWriter>) -> Result<(), ()> {
+        self.serve_with_shutdown(log_writer, Shutdown::default())
+            .await
+    }
+
+    /// Returns a future that runs the Interledger.rs Node, which stops accepting
+    /// connections and packets once the shutdown starts.
+    pub async fn serve_with_shutdown(
+        self,
+        log_writer: Option<LogWriter>,
+        shutdown: Shutdown,
+    ) -> Result<(), ()> {
+        // The uptime advertised by the API is measured from here
+        let started_at = Instant::now();
+        self.clone()
+            .with_prometheus(self.serve_node(log_writer, shutdown, started_at))
+            .await
+    }
+
+    /// Returns a future that runs the Interledger.rs Node on the provided store, rather
+    /// than on the one the `database_url` points to, so that programs embedding the
+    /// node can run it on a store of their own.
+    pub async fn serve_with_store<S: InterledgerStore>(
+        self,
+        store: S,
+        log_writer: Option<LogWriter>,
+        shutdown: Shutdown,
+    ) -> Result<(), ()> {
+        let started_at = Instant::now();
+        let ilp_address = self.node_ilp_address();
+        let scheduler = Scheduler::new(self.scheduler.clone());
+        self.clone()
+            .with_prometheus(self.chain_services(
+                store,
+                ilp_address,
+                log_writer,
+                shutdown,
+                scheduler,
+                started_at,
+            ))
+            .await
+    }
+
+    /// Runs the node along with the Prometheus metrics server, if it is configured
+    async fn with_prometheus<F>(self, serve: F) -> Result<(), ()>
+    where
+        F: Future<Output = Result<(), ()>>,
+    {
         cfg_if! {
             if #[cfg(feature = "monitoring")] {
-                let f = futures::future::join(serve_prometheus(self.clone()), self.serve_node(log_writer)).then(
-                    |r| async move {
-                        if r.0.is_ok() || r.1.is_ok() {
-                            Ok(())
-                        } else {
-                            Err(())
-                        }
-                    },
-                );
+                let (prometheus, node) = futures::future::join(serve_prometheus(self), serve).await;
+                if prometheus.is_ok() || node.is_ok() {
+                    Ok(())
+                } else {
+                    Err(())
+                }
             } else {
-                let f = self.serve_node(log_writer);
+                serve.await
             }
         }
-
-        f.await
     }
 
-    async fn serve_node(self, log_writer: Option<LogWriter>) -> Result<(), ()> {
-        let ilp_address = if let Some(address) = &self.ilp_address {
+    fn node_ilp_address(&self) -> Address {
+        if let Some(address) = &self.ilp_address {
             address.clone()
         } else {
             DEFAULT_ILP_ADDRESS.clone()
+        }
+    }
+
+    /// The features advertised by `GET /`: the subsystems which are not disabled, and the
+    /// optional APIs and protocols which are configured
+    pub fn node_features(&self) -> Vec<String> {
+        let mut features: Vec<&str> = [
+            Subsystem::Btp,
+            Subsystem::IlpOverHttp,
+            Subsystem::Settlement,
+            Subsystem::Spsp,
+        ]
+        .iter()
+        .filter(|subsystem| self.listeners.is_enabled(**subsystem))
+        .map(|subsystem| subsystem.feature())
+        .collect();
+        if self.stream_receipts {
+            features.push("receipts");
+        }
+        if self.graphql {
+            features.push("graphql");
+        }
+        if self.grpc_bind_address.is_some() {
+            features.push("grpc");
+        }
+        if self.child_provisioning.is_some() {
+            features.push("provisioning");
+        }
+        features.into_iter().map(String::from).collect()
+    }
+
+    /// Re-encrypts the account secrets in the store with a newly generated data encryption
+    /// key, wrapped by the configured key manager.
+    ///
+    /// The node must not be running while the key is rotated.
+    pub async fn rotate_encryption_key(self) -> Result<(), ()> {
+        let database_url = match Url::parse(&self.database_url) {
+            Ok(url) => url,
+            Err(e) => {
+                error!(
+                    "The string '{}' could not be parsed as a URL: {}",
+                    &self.database_url, e
+                );
+                return Err(());
+            }
         };
 
+        match database_url.scheme() {
+            #[cfg(feature = "redis")]
+            "redis" | "redis+unix" => rotate_redis_encryption_key(self).await,
+            other => {
+                error!("unsupported data source scheme: {}", other);
+                Err(())
+            }
+        }
+    }
+
+    async fn serve_node(
+        self,
+        log_writer: Option<LogWriter>,
+        shutdown: Shutdown,
+        started_at: Instant,
+    ) -> Result<(), ()> {
+        let ilp_address = self.node_ilp_address();
+
         // TODO: store a Url directly in InterledgerNode rather than a String?
         let database_url = match Url::parse(&self.database_url) {
             Ok(url) => url,
@@ -276,7 +857,9 @@ impl InterledgerNode {
 
         match database_url.scheme() {
             #[cfg(feature = "redis")]
-            "redis" | "redis+unix" => serve_redis_node(self, ilp_address, log_writer).await,
+            "redis" | "redis+unix" => {
+                serve_redis_node(self, ilp_address, log_writer, shutdown, started_at).await
+            }
             other => {
                 error!("unsupported data source scheme: {}", other);
                 Err(())
@@ -285,34 +868,15 @@ impl InterledgerNode {
     }
 
     #[allow(clippy::cognitive_complexity)]
-    pub(crate) async fn chain_services<S>(
+    pub(crate) async fn chain_services<S: InterledgerStore>(
         self,
         store: S,
         ilp_address: Address,
         _log_writer: Option<LogWriter>,
-    ) -> Result<(), ()>
-    where
-        S: NodeStore<Account = Account>
-            + AddressStore
-            + BtpStore<Account = Account>
-            + HttpStore<Account = Account>
-            + StreamNotificationsStore<Account = Account>
-            + BalanceStore
-            + SettlementStore<Account = Account>
-            + ExchangeRateStore
-            + BalanceStore
-            + SettlementStore<Account = Account>
-            + RouterStore<Account = Account>
-            + CcpRoutingStore<Account = Account>
-            + RateLimitStore<Account = Account>
-            + LeftoversStore<AccountId = Uuid, AssetType = BigUint>
-            + IdempotentStore
-            + AccountStore<Account = Account>
-            + Clone
-            + Send
-            + Sync
-            + 'static,
-    {
+        shutdown: Shutdown,
+        scheduler: Scheduler,
+        started_at: Instant,
+    ) -> Result<(), ()> {
         debug!(target: "interledger-node",
             "Starting Interledger node with ILP address: {}",
             ilp_address
@@ -321,23 +885,149 @@ impl InterledgerNode {
         let secret_seed = Bytes::from(&self.secret_seed[..]);
         let http_bind_address = self.http_bind_address;
         let settlement_api_bind_address = self.settlement_api_bind_address;
+        let settlement_replay_protection = self.settlement_replay_window.map(|window| {
+            ReplayProtection::new(
+                Duration::from_secs(window),
+                self.settlement_engine_secrets
+                    .iter()
+                    .map(|engine| (engine.url.clone(), engine.secret.expose_secret().clone())),
+            )
+        });
+        let reuse_port = self.shutdown.reuse_port;
+        let balance_snapshot_interval = self.balance_snapshot_interval;
+        let settlement_reconciliation_interval = self.settlement_reconciliation_interval;
+        let settlement_reconciliation = SettlementReconciliation::default();
+        // All the calls to the settlement engines share this client, so that they
+        // authenticate to the engines with the node's certificate
+        let (settlement_client, settlement_tls_config) = match self.settlement_tls {
+            Some(ref tls) => {
+                let client = SettlementClient::with_tls(tls).map_err(|err| {
+                    error!(target: "interledger-node", "Error configuring TLS for the settlement engines: {}", err)
+                })?;
+                let server_config = tls.server_config().map_err(|err| {
+                    error!(target: "interledger-node", "Error configuring TLS for the settlement API: {}", err)
+                })?;
+                (client, Some(server_config))
+            }
+            None => (SettlementClient::default(), None),
+        };
+        let http_tls_config = match self.http_tls {
+            Some(ref tls) => Some(tls.server_config().map_err(|err| {
+                error!(target: "interledger-node", "Error configuring TLS for the HTTP API: {}", err)
+            })?),
+            None => None,
+        };
+        #[cfg(feature = "acme")]
+        let acme = self.http_tls.as_ref().and_then(|tls| tls.acme.clone());
+        let listeners = self.listeners.clone();
+        let node_features = self.node_features();
+        let grpc_bind_address = self.grpc_bind_address;
         let ilp_address_clone = ilp_address.clone();
         let ilp_address_clone2 = ilp_address.clone();
         let admin_auth_token = self.admin_auth_token.clone();
+        // The JWTs are validated with the same keys by all the admin routes
+        let jwt_auth = self.jwt_auth.clone().map(|jwt_config| {
+            info!(target: "interledger-node", "Accepting the API tokens issued by {}", jwt_config.issuer);
+            JwtAuth::new(jwt_config)
+        });
+        let public_url = self.public_url.clone();
+        let child_provisioning = self.child_provisioning.clone();
         let default_spsp_account = self.default_spsp_account.clone();
-        let route_broadcast_interval = self.route_broadcast_interval;
+        let stream_receipts = self.stream_receipts;
+        let graphql = self.graphql;
+        let latency_probes = self.latency_probes.clone();
+        #[cfg(feature = "balance-tracking")]
+        let balance_notification_config = self.balance_notifications.clone();
+        let balance_notifications = BalanceNotifications::default();
+        let api_sunset = self
+            .api_sunset
+            .as_ref()
+            .and_then(|sunset| HeaderValue::from_str(sunset).ok());
+        let packet_deduplication_memory = self.packet_deduplication_memory;
+        let fair_queuing = self.fair_queuing.clone();
+        let reject_redaction = self.reject_redaction.clone();
         let exchange_rate_provider = self.exchange_rate.provider.clone();
-        let exchange_rate_poll_interval = self.exchange_rate.poll_interval;
         let exchange_rate_poll_failure_tolerance = self.exchange_rate.poll_failure_tolerance;
-        let exchange_rate_spread = self.exchange_rate.spread;
+        let fee_policy = FeePolicy {
+            spread: Some(self.exchange_rate.spread),
+            percentage_fee: self.exchange_rate.percentage_fee,
+            fixed_fee: None,
+        };
+        let expiry = self.expiry.clone();
+        let middlewares = self.middlewares.clone();
+        let hooks = self.hooks.clone();
+        for hook in hooks.stopped() {
+            shutdown.on_shutdown(hook);
+        }
+        let blocklist = PeerBlocklist::new(self.blocklist);
+        let trusted_proxies = self.trusted_proxies.clone();
+        let exchange_rate_guards = RateGuards {
+            max_age: self.exchange_rate.max_age,
+            max_deviation: self.exchange_rate.max_deviation,
+        };
+        let config_file = self.config_file.clone();
+        let settings_poll_interval = self
+            .settings_poll_interval
+            .unwrap_or(DEFAULT_SETTINGS_POLL_INTERVAL);
+        // The services are started with the configured values, which the settings watcher
+        // replaces with the runtime settings from the store once it polls them
+        let configured_settings = RuntimeSettings {
+            route_broadcast_interval: self.route_broadcast_interval,
+            exchange_rate_poll_interval: Some(self.exchange_rate.poll_interval),
+            max_packet_amount: self.max_packet_amount,
+            max_packet_size: self.max_packet_size,
+        };
+        let (reloadable_settings, settings_receivers) =
+            ReloadableSettings::new(configured_settings.clone());
         #[cfg(feature = "google-pubsub")]
         let google_pubsub = self.google_pubsub.clone();
+        #[cfg(feature = "monitoring")]
+        let prometheus = self.prometheus.clone();
+        #[cfg(feature = "alerting")]
+        let alerting = self.alerting.clone();
+        #[cfg(feature = "telemetry")]
+        let telemetry_config = self.telemetry.clone();
+        #[cfg(feature = "alerting")]
+        let alert_monitor = alerting
+            .as_ref()
+            .map(|_| AlertMonitor::new(ilp_address.clone(), settlement_reconciliation.clone()));
 
         let btp_accounts = store
             .get_btp_outgoing_accounts()
             .map_err(|_| error!(target: "interledger-node", "Error getting accounts"))
             .await?;
 
+        // When sharing the store with other nodes, the singleton tasks only run on the leader
+        // and the outgoing BTP connections are only opened by the node which owns them
+        let coordinator = self.cluster.as_ref().map(Coordinator::new);
+        let (is_leader, btp_accounts) = match coordinator {
+            Some(ref coordinator) => (
+                coordinator.start_leader_election(store.clone()).await,
+                coordinator.claim_btp_accounts(&store, btp_accounts).await,
+            ),
+            None => (Arc::new(AtomicBool::new(true)), btp_accounts),
+        };
+        if let Some(ref coordinator) = coordinator {
+            coordinator.schedule_settlement_sender(
+                &scheduler,
+                store.clone(),
+                settlement_client.clone(),
+                is_leader.clone(),
+            );
+        }
+        let owned_btp_accounts: Vec<Uuid> =
+            btp_accounts.iter().map(|account| account.id()).collect();
+        // The accounts of the peers' other currencies share the connections to their servers
+        let mut btp_sub_accounts = Vec::new();
+        for account in btp_accounts.iter() {
+            btp_sub_accounts.extend(
+                store
+                    .get_sub_accounts(account)
+                    .map_err(|_| error!(target: "interledger-node", "Error getting accounts"))
+                    .await?,
+            );
+        }
+
         let outgoing_service = outgoing_service_fn(move |request: OutgoingRequest<Account>| {
             // Don't log anything for failed route updates sent to child accounts
             // because there's a good chance they'll be offline
@@ -372,6 +1062,16 @@ impl InterledgerNode {
         )
         .map_err(|err| error!("{}", err))
         .await?;
+        for sub_account in btp_sub_accounts {
+            btp_client_service.add_sub_account(sub_account);
+        }
+        if let Some(ref coordinator) = coordinator {
+            coordinator.spawn_btp_ownership(
+                store.clone(),
+                owned_btp_accounts,
+                btp_client_service.clone(),
+            );
+        }
         let btp_server_service =
             BtpOutgoingService::new(ilp_address_clone2, btp_client_service.clone());
         let btp_server_service_clone = btp_server_service.clone();
@@ -381,6 +1081,21 @@ impl InterledgerNode {
         // service to others like the router and then call handle_incoming on it to set up the incoming handler
         let outgoing_service = btp_server_service.clone();
         let outgoing_service = HttpClientService::new(store.clone(), outgoing_service);
+        let outgoing_service = outgoing_span(outgoing_service, "http_btp_client");
+        // The faults are injected where the packets leave and enter the node, as if they
+        // were lost, held up, duplicated or mangled on the way to and from the peers
+        #[cfg(feature = "fault-injection")]
+        let fault_injector = FaultInjector::default();
+        #[cfg(feature = "fault-injection")]
+        let outgoing_service =
+            FaultInjectionService::new(fault_injector.clone(), store.clone(), outgoing_service);
+        // The packets of the accounts in trace mode are recorded as they are exchanged with
+        // the peers, as they are received from and sent to them
+        let packet_traces = PacketTraces::default();
+        let outgoing_service = PacketTraceService::new(packet_traces.clone(), outgoing_service);
+        // The packets rejected by this node here mean that it could not reach the peer
+        #[cfg(feature = "alerting")]
+        let outgoing_service = outgoing_service.wrap(outgoing_alerts(alert_monitor.clone()));
 
         #[cfg(feature = "monitoring")]
         let outgoing_service = outgoing_service.wrap(outgoing_metrics);
@@ -388,13 +1103,71 @@ impl InterledgerNode {
         // Note: the expiry shortener must come after the Validator so that the expiry duration
         // is shortened before we check whether there is enough time left
         let outgoing_service = ValidatorService::outgoing(store.clone(), outgoing_service);
-        let outgoing_service = ExpiryShortenerService::new(outgoing_service);
-        let outgoing_service =
-            StreamReceiverService::new(secret_seed.clone(), store.clone(), outgoing_service);
+        let outgoing_service = outgoing_span(outgoing_service, "outgoing_validator");
+        let outgoing_service = PacketSizeService::new(store.clone(), outgoing_service);
+        let outgoing_service = outgoing_span(outgoing_service, "packet_size");
+        let mut outgoing_service = ExpiryShortenerService::new(outgoing_service);
+        outgoing_service
+            .max_expiry_duration(expiry.max_duration)
+            .hop_reduction(expiry.hop_reduction);
+        let stream_connections = StreamConnections::default();
+        let mut outgoing_service =
+            StreamReceiverService::new(secret_seed.clone(), store.clone(), outgoing_service)
+                .with_connections(stream_connections.clone());
+        if stream_receipts {
+            outgoing_service = outgoing_service.with_receipts();
+        }
         #[cfg(feature = "balance-tracking")]
-        let outgoing_service = BalanceService::new(store.clone(), outgoing_service);
+        let outgoing_service = {
+            let mut balance_service = BalanceService::new(store.clone(), outgoing_service);
+            balance_service.settlement_client(settlement_client.clone());
+            // Workers hand the settlements over to the control nodes through the store
+            if let Some(ref coordinator) = coordinator {
+                if coordinator.queues_settlements() {
+                    balance_service
+                        .queue_settlements(coordinator.spawn_settlement_queue(store.clone()));
+                }
+            }
+            let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+            balance_service.notify_liquidity_blocks(sender);
+            LiquidityNotifier::new(ilp_address.clone(), LIQUIDITY_NOTIFICATION_INTERVAL)
+                .spawn(receiver);
+            if let Some(config) = balance_notification_config {
+                let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+                balance_service.notify_balance_changes(sender);
+                BalanceNotifier::new(
+                    ilp_address.clone(),
+                    store.clone(),
+                    config,
+                    balance_notifications.clone(),
+                )
+                .spawn(receiver);
+            }
+            #[cfg(feature = "alerting")]
+            {
+                if let Some(ref monitor) = alert_monitor {
+                    let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+                    balance_service.notify_settlement_failures(sender);
+                    monitor.spawn_settlement_failures(receiver);
+                }
+            }
+            balance_service
+        };
+        #[cfg(feature = "balance-tracking")]
+        let outgoing_service = outgoing_span(outgoing_service, "balance");
+        // The packets exceeding the transfer limits of the accounts are rejected before
+        // they change the balances
+        let outgoing_service = TransferLimitService::new(store.clone(), outgoing_service);
+        let outgoing_service = outgoing_span(outgoing_service, "transfer_limits");
+        // The custom middlewares see the converted amounts, and the packets they
+        // reject do not change the balances
+        let outgoing_service = middlewares.wrap_outgoing(outgoing_service);
+        let outgoing_service = outgoing_span(outgoing_service, "middlewares");
+        let node_stats = NodeStats::default();
         let outgoing_service =
-            ExchangeRateService::new(exchange_rate_spread, store.clone(), outgoing_service);
+            ExchangeRateService::with_fee_policy(fee_policy, store.clone(), outgoing_service)
+                .with_stats(node_stats.clone());
+        let outgoing_service = outgoing_span(outgoing_service, "exchange_rate");
 
         #[cfg(feature = "google-pubsub")]
         let outgoing_service = outgoing_service.wrap(create_google_pubsub_wrapper(google_pubsub));
@@ -409,9 +1182,28 @@ impl InterledgerNode {
                 let outgoing_service_fwd = outgoing_service.clone();
             }
         }
+        // Only the forwarded packets are deduplicated, since routing loops and peers
+        // retrying packets only concern those
+        let outgoing_service_fwd =
+            DeduplicationService::new(packet_deduplication_memory, outgoing_service_fwd);
+        let outgoing_service_fwd = outgoing_span(outgoing_service_fwd, "deduplication");
+        // The Reject packets of the next hops are attributed to them if their routing
+        // relation does not allow them to be triggered where they claim to be
+        let outgoing_service_fwd = AddressRangeService::new(store.clone(), outgoing_service_fwd);
+        let outgoing_service_fwd = outgoing_span(outgoing_service_fwd, "address_range");
 
         // Set up the Router and Routing Manager
         let incoming_service = Router::new(store.clone(), outgoing_service_fwd);
+        let incoming_service = incoming_span(incoming_service, "router");
+        let incoming_service = middlewares.wrap_incoming(incoming_service);
+        let incoming_service = incoming_span(incoming_service, "middlewares");
+        // The accounts' packet filters only apply to the packets which are forwarded
+        // (not to those handled by the node itself, such as CCP and ILDCP requests)
+        let incoming_service = PacketFilterService::new(store.clone(), incoming_service);
+        let incoming_service = incoming_span(incoming_service, "packet_filter");
+        // The peer. and local. packets which were not handled by the node are not forwarded
+        let incoming_service = AddressRangeService::new(store.clone(), incoming_service);
+        let incoming_service = incoming_span(incoming_service, "address_range");
 
         // Add tracing to track the outgoing request details
         #[cfg(feature = "monitoring")]
@@ -424,17 +1216,93 @@ impl InterledgerNode {
             incoming_service,
         );
         ccp_builder.ilp_address(ilp_address.clone());
-        if let Some(ms) = route_broadcast_interval {
-            ccp_builder.broadcast_interval(ms);
+        ccp_builder.without_broadcast_interval();
+        // The nodes sharing the store save the routes they learn from their peers, and the
+        // leader merges them into the routing table which it sets and broadcasts
+        if coordinator.is_some() {
+            ccp_builder.broadcasting(is_leader.clone()).share_routes();
+        }
+        let peer_latencies = PeerLatencies::default();
+        if latency_probes
+            .as_ref()
+            .map_or(false, |config| config.route_tiebreaker)
+        {
+            let peer_latencies = peer_latencies.clone();
+            ccp_builder.route_latency(move |account_id| peer_latencies.round_trip_time(account_id));
         }
+        // The probes are sent straight to the peers, like the CCP messages
+        let outgoing_service_probes = outgoing_service.clone();
 
-        let incoming_service = ccp_builder.to_service();
-        let incoming_service = EchoService::new(store.clone(), incoming_service);
-        let incoming_service = SettlementMessageService::new(incoming_service);
+        let ccp_manager = ccp_builder.to_service();
+        // Request the routes of the peers once, and then broadcast ours on the interval
+        let ccp_manager_clone = ccp_manager.clone();
+        tokio::spawn(async move { ccp_manager_clone.request_all_routes().await });
+        let ccp_manager_clone = ccp_manager.clone();
+        let route_broadcast_interval = *settings_receivers.route_broadcast_interval.borrow();
+        scheduler
+            .task("route_broadcast", route_broadcast_interval)
+            .interval_updates(settings_receivers.route_broadcast_interval)
+            .only_while(is_leader.clone())
+            .spawn(move || {
+                let ccp_manager = ccp_manager_clone.clone();
+                async move { ccp_manager.broadcast().await.map_err(|err| err.to_string()) }
+            });
+        let incoming_service = ccp_manager;
+        let echo_pings = EchoPings::default();
+        let incoming_service =
+            EchoService::with_pings(store.clone(), echo_pings.clone(), incoming_service);
+        let mut incoming_service = SettlementMessageService::new(incoming_service);
+        incoming_service.settlement_client(settlement_client.clone());
         let incoming_service = IldcpService::new(incoming_service);
-        let incoming_service = MaxPacketAmountService::new(store.clone(), incoming_service);
-        let incoming_service = ValidatorService::incoming(store.clone(), incoming_service);
+        let incoming_service = MaxPacketAmountService::with_node_limit(
+            store.clone(),
+            settings_receivers.max_packet_amount,
+            incoming_service,
+        );
+        let incoming_service = PacketSizeService::with_node_limit(
+            store.clone(),
+            settings_receivers.max_packet_size,
+            incoming_service,
+        );
+        let mut incoming_service = ValidatorService::incoming(store.clone(), incoming_service);
+        incoming_service
+            .min_expiry_window(expiry.min_window)
+            .max_clock_skew(expiry.max_clock_skew);
+        let incoming_service = incoming_span(incoming_service, "incoming_validator");
+        // Only the packets within the rate limits wait in the queues
+        let incoming_service = FairQueueService::new(fair_queuing, store.clone(), incoming_service);
+        let incoming_service = incoming_span(incoming_service, "fair_queue");
         let incoming_service = RateLimitService::new(store.clone(), incoming_service);
+        let incoming_service = incoming_span(incoming_service, "rate_limit");
+        // Refuses the packets of the quarantined peers, and quarantines the peers
+        // whose packets are rejected too often
+        let incoming_service =
+            PeerBlocklistService::new(store.clone(), blocklist.clone(), incoming_service);
+        let incoming_service = incoming_span(incoming_service, "blocklist");
+        let incoming_service = PacketTraceService::new(packet_traces.clone(), incoming_service);
+        #[cfg(feature = "fault-injection")]
+        let incoming_service =
+            FaultInjectionService::new(fault_injector.clone(), store.clone(), incoming_service);
+        // Count all the packets received, including the ones rejected by the services above
+        let incoming_service = StatsService::new(node_stats.clone(), incoming_service);
+        #[cfg(feature = "telemetry")]
+        let telemetry = telemetry_config
+            .map(|config| Telemetry::new(config, secret_seed.as_ref(), node_stats.clone()));
+        #[cfg(feature = "telemetry")]
+        let telemetry_routes = telemetry_api(
+            telemetry.clone(),
+            admin_only(&admin_auth_token, jwt_auth.clone()),
+        );
+        #[cfg(feature = "alerting")]
+        let incoming_service = incoming_service.wrap(incoming_alerts(alert_monitor.clone()));
+        // The rejects are redacted once they were traced, counted and checked for alerts,
+        // which need their details
+        let incoming_service =
+            RejectRedactionService::new(reject_redaction, store.clone(), incoming_service);
+        // Once the node is stopping, new packets are rejected while the ones
+        // in flight are waited for
+        let incoming_service =
+            incoming_service.wrap(incoming_shutdown(shutdown.clone(), ilp_address.clone()));
 
         // Add tracing to track the incoming request details
         #[cfg(feature = "monitoring")]
@@ -480,6 +1348,9 @@ impl InterledgerNode {
             }
         }
 
+        // Send the scheduled payments the same way as the payments sent via the API
+        let incoming_service_scheduled = incoming_service_api.clone();
+
         // Node HTTP API
         let mut api = NodeApi::new(
             bytes05::Bytes::copy_from_slice(secret_seed.as_ref()),
@@ -492,7 +1363,37 @@ impl InterledgerNode {
         if let Some(username) = default_spsp_account {
             api.default_spsp_account(username);
         }
+        if stream_receipts {
+            api.receipt_verifier();
+        }
+        if graphql {
+            api.graphql();
+        }
         api.node_version(env!("CARGO_PKG_VERSION").to_string());
+        api.node_features(node_features);
+        api.started_at(started_at);
+        api.fee_policy(fee_policy);
+        api.echo_pings(echo_pings.clone());
+        api.peer_latencies(peer_latencies.clone());
+        api.packet_traces(packet_traces);
+        api.balance_notifications(balance_notifications);
+        api.stream_connections(stream_connections);
+        #[cfg(feature = "fault-injection")]
+        api.fault_injector(fault_injector);
+        api.node_stats(node_stats);
+        api.blocklist(blocklist.clone());
+        api.scheduler(scheduler.clone());
+        api.settlement_reconciliation(settlement_reconciliation.clone());
+        api.settlement_client(settlement_client.clone());
+        if let Some(public_url) = public_url {
+            api.public_url(public_url);
+        }
+        if let Some(child_provisioning) = child_provisioning {
+            api.child_provisioning(child_provisioning);
+        }
+        if let Some(jwt_auth) = jwt_auth.clone() {
+            api.jwt_auth(jwt_auth);
+        }
 
         cfg_if! {
             if #[cfg(feature = "monitoring")] {
@@ -507,41 +1408,37 @@ impl InterledgerNode {
             }
         }
 
-        // add an API of ILP over HTTP and add rejection handler
-        let api = api
-            .into_warp_filter()
-            .or(IlpOverHttpServer::new(incoming_service_http, store.clone()).as_filter())
-            .or(btp_service_as_filter(
-                btp_server_service_clone,
-                store.clone(),
-            ));
+        let grpc_api = grpc_bind_address.map(|_| api.grpc_api());
+
+        // The subsystems of the HTTP API, which can be served on addresses of their own
+        let (api, spsp_api) = api.into_warp_filters();
+        let ilp_over_http_api = IlpOverHttpServer::new(incoming_service_http, store.clone())
+            .blocklist(blocklist.clone())
+            .trusted_proxies(trusted_proxies.clone())
+            .as_filter();
+        let btp_api = btp_service_as_filter(
+            btp_server_service_clone,
+            store.clone(),
+            blocklist,
+            trusted_proxies,
+        );
 
         // If monitoring is enabled, run a tracing subscriber
         // and expose a new endpoint at /tracing-level which allows
         // changing the tracing level by administrators
         cfg_if! {
             if #[cfg(feature = "monitoring")] {
-                let admin_only = warp::header::<SecretString>("authorization")
-                    .and_then(move |authorization: SecretString| {
-                        let admin_auth_header = format!("Bearer {}", self.admin_auth_token.clone());
-                        async move {
-                            if authorization.expose_secret() == &admin_auth_header {
-                                Ok::<(), warp::Rejection>(())
-                            } else {
-                                Err(warp::Rejection::from(ApiError::unauthorized()))
-                            }
-                        }
-                    })
-                    .untuple_one()
-                    .boxed();
+                let admin_only = admin_only(&self.admin_auth_token, jwt_auth.clone()).boxed();
 
                 let api = {
                     let tracing_handle = _log_writer.and_then(|al| al.handle);
 
+                    let account_tracing_handle = tracing_handle.clone();
+                    let reset_tracing_handle = tracing_handle.clone();
                     let adjust_tracing = warp::put()
                         .and(warp::path("tracing-level"))
                         .and(warp::path::end())
-                        .and(admin_only)
+                        .and(admin_only.clone())
                         .and(warp::body::bytes())
                         .and_then(
                             move |new_level_input: bytes05::Bytes| {
@@ -557,7 +1454,7 @@ impl InterledgerNode {
                                             ApiError::bad_request().detail("could not parse body as log level")
                                         })?;
 
-                                    let curr_env = handle.with_current(|env| env.to_string()).unwrap();
+                                    let curr_env = handle.directives();
                                     let new_env = curr_env.parse::<EnvFilter>().unwrap().add_directive(new_level);
 
                                     handle.reload(new_env).map_err(|err| {
@@ -573,50 +1470,459 @@ impl InterledgerNode {
                             },
                         );
 
+                    // Overrides the log level of the requests sent by or to an account
+                    let store_clone = store.clone();
+                    let admin_only_clone = admin_only.clone();
+                    let adjust_account_tracing = warp::put()
+                        .and(warp::path("tracing-level"))
+                        .and(warp::path("accounts"))
+                        .and(warp::path::param::<Username>())
+                        .and(warp::path::end())
+                        .and(admin_only_clone)
+                        .and(warp::body::bytes())
+                        .and_then(
+                            move |username: Username, new_level_input: bytes05::Bytes| {
+                                let handle = account_tracing_handle.clone().unwrap();
+                                let store = store_clone.clone();
+                                async move {
+                                    let new_level_str = std::str::from_utf8(new_level_input.as_ref()).map_err(|_| {
+                                        ApiError::bad_request().detail("invalid utf-8 body provided")
+                                    })?;
+                                    let account_id = store.get_account_id_from_username(&username).await?;
+                                    let directives = account_log_directives(account_id, new_level_str)
+                                        .map_err(|_| {
+                                            ApiError::bad_request().detail("could not parse body as log level")
+                                        })?;
+
+                                    // Replace the previous override of the account
+                                    let new_env = directives.into_iter().fold(
+                                        without_account_directives(&handle.directives(), account_id),
+                                        |env, directive| env.add_directive(directive),
+                                    );
+                                    handle.reload(new_env).map_err(|err| {
+                                        ApiError::internal_server_error()
+                                            .detail(format!("could not apply new log level: {}", err))
+                                    })?;
+                                    debug!(target: "interledger-node", "Logging level of account {} adjusted to {}", username, new_level_str);
+                                    Ok::<String, warp::Rejection>(format!(
+                                        "Logging level of account {} changed to: {}",
+                                        username, new_level_str
+                                    ))
+                                }
+                            },
+                        );
+
+                    let store_clone = store.clone();
+                    let reset_account_tracing = warp::delete()
+                        .and(warp::path("tracing-level"))
+                        .and(warp::path("accounts"))
+                        .and(warp::path::param::<Username>())
+                        .and(warp::path::end())
+                        .and(admin_only)
+                        .and_then(move |username: Username| {
+                            let handle = reset_tracing_handle.clone().unwrap();
+                            let store = store_clone.clone();
+                            async move {
+                                let account_id = store.get_account_id_from_username(&username).await?;
+                                handle
+                                    .reload(without_account_directives(&handle.directives(), account_id))
+                                    .map_err(|err| {
+                                        ApiError::internal_server_error()
+                                            .detail(format!("could not reset log level: {}", err))
+                                    })?;
+                                debug!(target: "interledger-node", "Logging level of account {} reset", username);
+                                Ok::<String, warp::Rejection>(format!(
+                                    "Logging level of account {} reset",
+                                    username
+                                ))
+                            }
+                        });
+
                     api.or(adjust_tracing)
+                        .or(adjust_account_tracing)
+                        .or(reset_account_tracing)
                 };
             }
         }
 
-        let api = api
-            .recover(default_rejection_handler)
-            .with(warp::log("interledger-api"))
-            .boxed();
+        #[cfg(feature = "telemetry")]
+        let api = api.or(telemetry_routes);
+        let api = versioned_api(api.boxed(), api_sunset);
+
+        // The payment pointers hosted on arbitrary paths are looked up last,
+        // so that they do not take precedence over the other routes
+        let ilp_over_http_api = boxed_routes(ilp_over_http_api);
+        let subsystems = vec![
+            (None, "node API", listeners.api.clone(), boxed_routes(api)),
+            (
+                Some(Subsystem::IlpOverHttp),
+                "ILP over HTTP API",
+                listeners.ilp_over_http.clone(),
+                ilp_over_http_api,
+            ),
+            (
+                Some(Subsystem::Btp),
+                "BTP API",
+                listeners.btp.clone(),
+                boxed_routes(btp_api),
+            ),
+            (
+                Some(Subsystem::Spsp),
+                "SPSP API",
+                listeners.spsp.clone(),
+                boxed_routes(spsp_api),
+            ),
+        ]
+        .into_iter()
+        .filter(|(subsystem, name, _, _)| match subsystem {
+            Some(subsystem) if !listeners.is_enabled(*subsystem) => {
+                info!(target: "interledger-node", "The {} is disabled", name);
+                false
+            }
+            _ => true,
+        })
+        .map(|(_, name, listener, routes)| (name, listener, routes))
+        .collect();
+        let (combined, separate) = split_subsystems(subsystems);
+        for (name, listener, routes) in separate {
+            let tls_config = listener.server_config().map_err(|err| {
+                error!(target: "interledger-node", "Error configuring TLS for the {}: {}", name, err)
+            })?;
+            serve_routes(
+                name,
+                routes,
+                listener.bind_address,
+                tls_config,
+                listener.requests_per_minute,
+                &shutdown,
+                reuse_port,
+            )?;
+        }
+        match combined {
+            Some(routes) => {
+                let tls_config = match http_tls_config {
+                    Some((tls_config, resolver)) => {
+                        #[cfg(feature = "acme")]
+                        {
+                            if let Some(acme) = acme {
+                                spawn_certificate_manager(acme, resolver, &shutdown, reuse_port)
+                                    .map_err(|err| {
+                                        error!(target: "interledger-node", "Error starting the ACME certificate manager: {}", err)
+                                    })?;
+                            }
+                        }
+                        #[cfg(not(feature = "acme"))]
+                        let _ = resolver;
+                        Some(tls_config)
+                    }
+                    None => None,
+                };
+                serve_routes(
+                    "HTTP API",
+                    routes,
+                    http_bind_address,
+                    tls_config,
+                    None,
+                    &shutdown,
+                    reuse_port,
+                )?;
+            }
+            None => {
+                info!(target: "interledger-node", "All the subsystems of the HTTP API have their own listeners, nothing listens on: {}", http_bind_address)
+            }
+        }
 
-        info!(target: "interledger-node", "Interledger.rs node HTTP API listening on: {}", http_bind_address);
-        spawn(warp::serve(api).bind(http_bind_address));
+        // gRPC API
+        if let (Some(grpc_api), Some(grpc_bind_address)) = (grpc_api, grpc_bind_address) {
+            let listener = bind_listener(grpc_bind_address, reuse_port).map_err(|err| {
+                error!(target: "interledger-node", "Error binding the gRPC API to {}: {}", grpc_bind_address, err)
+            })?;
+            info!(target: "interledger-node", "gRPC API listening on: {}", grpc_bind_address);
+            shutdown
+                .spawn_server(grpc_api.serve_with_graceful_shutdown(listener, shutdown.signal()));
+        }
 
         // Settlement API
-        let settlement_api = create_settlements_filter(store.clone(), outgoing_service.clone());
-        info!(target: "interledger-node", "Settlement API listening on: {}", settlement_api_bind_address);
-        spawn(warp::serve(settlement_api).bind(settlement_api_bind_address));
+        if listeners.is_enabled(Subsystem::Settlement) {
+            let settlement_api = create_settlements_filter(
+                store.clone(),
+                outgoing_service.clone(),
+                settlement_replay_protection,
+            );
+            let listener = bind_listener(settlement_api_bind_address, reuse_port).map_err(|err| {
+                error!(target: "interledger-node", "Error binding the settlement API to {}: {}", settlement_api_bind_address, err)
+            })?;
+            // The calls of the settlement engines which are in progress once the
+            // node is stopping are completed, but new ones are not accepted
+            if let Some(tls_config) = settlement_tls_config {
+                let incoming = tls_incoming(listener, tls_config, shutdown.signal());
+                info!(target: "interledger-node", "Settlement API listening with mutual TLS on: {}", settlement_api_bind_address);
+                shutdown.spawn_server(
+                    warp::serve(settlement_api)
+                        .serve_incoming_with_graceful_shutdown(incoming, shutdown.signal()),
+                );
+            } else {
+                info!(target: "interledger-node", "Settlement API listening on: {}", settlement_api_bind_address);
+                shutdown.spawn_server(
+                    warp::serve(settlement_api)
+                        .serve_incoming_with_graceful_shutdown(listener, shutdown.signal()),
+                );
+            }
+        } else {
+            info!(target: "interledger-node", "The settlement API is disabled");
+        }
+
+        // Snapshot the balances once no more packets are processed, so that the next
+        // verification of the journals starts from the balances the node stopped with
+        if balance_snapshot_interval.is_some() {
+            let store = store.clone();
+            shutdown.on_shutdown(async move {
+                match store.snapshot_balances().await {
+                    Ok(accounts) => {
+                        info!(target: "interledger-node", "Snapshotted the balances of {} accounts", accounts)
+                    }
+                    Err(err) => {
+                        error!(target: "interledger-node", "Error snapshotting the balances: {}", err)
+                    }
+                }
+            });
+        }
+        // Let another node take over the leases of this one right away
+        if let Some(coordinator) = coordinator {
+            let store = store.clone();
+            shutdown.on_shutdown(async move { coordinator.release_leases(&store).await });
+        }
+
+        {
+            let store = store.clone();
+            scheduler
+                .task("scheduled_payments", SCHEDULED_PAYMENTS_INTERVAL)
+                .only_while(is_leader.clone())
+                .spawn(move || {
+                    let incoming_service = incoming_service_scheduled.clone();
+                    let store = store.clone();
+                    let now = clock::now()
+                        .duration_since(UNIX_EPOCH)
+                        .expect("Time went backwards")
+                        .as_secs();
+                    async move {
+                        execute_scheduled_payments(incoming_service, store, now)
+                            .await
+                            .map(|executed| {
+                                if executed > 0 {
+                                    debug!(target: "interledger-node", "Sent {} scheduled payments", executed)
+                                }
+                            })
+                            .map_err(|err| err.to_string())
+                    }
+                });
+        }
+
+        {
+            let store = store.clone();
+            scheduler
+                .task("outgoing_payments", OUTGOING_PAYMENTS_INTERVAL)
+                .only_while(is_leader.clone())
+                .spawn(move || {
+                    let store = store.clone();
+                    let now = clock::now()
+                        .duration_since(UNIX_EPOCH)
+                        .expect("Time went backwards")
+                        .as_secs();
+                    async move {
+                        fail_interrupted_payments(store, now)
+                            .await
+                            .map(|failed| {
+                                if failed > 0 {
+                                    info!(target: "interledger-node", "Failed {} payments interrupted by a node stopping", failed)
+                                }
+                            })
+                            .map_err(|err| err.to_string())
+                    }
+                });
+        }
+
+        if let Some(config) = latency_probes {
+            let store = store.clone();
+            scheduler
+                .task("latency_probes", Duration::from_millis(config.interval))
+                .spawn(move || {
+                    let store = store.clone();
+                    let echo_pings = echo_pings.clone();
+                    let peer_latencies = peer_latencies.clone();
+                    let outgoing_service = outgoing_service_probes.clone();
+                    let timeout = Duration::from_millis(config.timeout);
+                    async move {
+                        let accounts: Vec<Account> = store
+                            .get_all_accounts()
+                            .await
+                            .map_err(|err| err.to_string())?
+                            .into_iter()
+                            .filter(|account| {
+                                account.routing_relation() == RoutingRelation::Peer
+                                    || account.routing_relation() == RoutingRelation::Parent
+                            })
+                            .collect();
+                        let probed = accounts.len();
+                        let answered = peer_latencies
+                            .probe(
+                                &echo_pings,
+                                outgoing_service,
+                                accounts,
+                                &store.get_ilp_address(),
+                                timeout,
+                            )
+                            .await;
+                        trace!(target: "interledger-node", "{} of {} latency probes were answered", answered, probed);
+                        Ok(())
+                    }
+                });
+        }
+
+        // The engines are only queried by one of the nodes sharing the store
+        if let Some(interval) = settlement_reconciliation_interval {
+            let store = store.clone();
+            let settlement_client = settlement_client.clone();
+            scheduler
+                .task("settlement_reconciliation", Duration::from_millis(interval))
+                .delay_first_run()
+                .only_while(is_leader.clone())
+                .spawn(move || {
+                    let store = store.clone();
+                    let settlement_client = settlement_client.clone();
+                    let settlement_reconciliation = settlement_reconciliation.clone();
+                    async move {
+                        let accounts = store
+                            .get_all_accounts()
+                            .await
+                            .map_err(|err| err.to_string())?;
+                        let discrepancies = settlement_reconciliation
+                            .reconcile(&store, &settlement_client, accounts)
+                            .await;
+                        if discrepancies > 0 {
+                            warn!(target: "interledger-node", "The settlement totals of {} accounts differ from their engines'", discrepancies)
+                        }
+                        Ok(())
+                    }
+                });
+        }
 
         // Exchange Rate Polling
         if let Some(provider) = exchange_rate_provider {
             let exchange_rate_fetcher = ExchangeRateFetcher::new(
-                provider,
+                provider.build(),
                 exchange_rate_poll_failure_tolerance,
                 store.clone(),
-            );
-            exchange_rate_fetcher
-                .spawn_interval(Duration::from_millis(exchange_rate_poll_interval));
+            )
+            .guards(exchange_rate_guards);
+            let exchange_rate_poll_interval =
+                *settings_receivers.exchange_rate_poll_interval.borrow();
+            scheduler
+                .task("exchange_rate_poll", exchange_rate_poll_interval)
+                .interval_updates(settings_receivers.exchange_rate_poll_interval)
+                .only_while(is_leader)
+                .spawn(move || {
+                    let exchange_rate_fetcher = exchange_rate_fetcher.clone();
+                    async move {
+                        exchange_rate_fetcher
+                            .update_rates()
+                            .await
+                            .map_err(|_| "Error updating the exchange rates".to_string())
+                    }
+                });
         } else {
             debug!(target: "interledger-node", "Not using exchange rate provider. Rates must be set via the HTTP API");
         }
 
+        // Record the balances and the size of the routing table
+        #[cfg(feature = "monitoring")]
+        {
+            if let Some(prometheus) = prometheus {
+                schedule_store_metrics(
+                    &scheduler,
+                    store.clone(),
+                    Duration::from_millis(prometheus.gauge_interval),
+                );
+            }
+        }
+
+        #[cfg(feature = "alerting")]
+        {
+            if let (Some(alerting), Some(monitor)) = (alerting, alert_monitor) {
+                monitor.spawn_alerting(store.clone(), alerting);
+            }
+        }
+
+        #[cfg(feature = "telemetry")]
+        {
+            if let Some(telemetry) = telemetry {
+                info!(target: "interledger-node", "Sharing the health metrics of the node with the collector");
+                telemetry.schedule(&scheduler);
+            }
+        }
+
+        spawn_settings_watcher(
+            store,
+            reloadable_settings,
+            configured_settings,
+            config_file,
+            Duration::from_millis(settings_poll_interval),
+        );
+
+        hooks.started(&ilp_address);
         Ok(())
     }
 }
 
 cfg_if! {
     if #[cfg(feature = "monitoring")] {
-        type TracingSubscriber =
+        type TextSubscriber =
             Formatter<format::DefaultFields, format::Format<format::Full, ChronoUtc>, NonBlocking>;
+        type JsonSubscriber =
+            Formatter<format::JsonFields, format::Format<format::Json, ChronoUtc>, NonBlocking>;
+
+        /// Format of the node's logs
+        #[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+        #[serde(rename_all = "snake_case")]
+        pub enum LogFormat {
+            /// Human readable lines
+            #[default]
+            Text,
+            /// One JSON object per line, including the fields of the spans in which the
+            /// log was recorded (such as the ids of the accounts and the reject code)
+            Json,
+        }
+
+        /// Handle to the (reloadable) filter of the tracing subscriber,
+        /// whose type depends on the format of the logs
+        #[derive(Clone)]
+        pub enum FilterHandle {
+            Text(Handle<EnvFilter, TextSubscriber>),
+            Json(Handle<EnvFilter, JsonSubscriber>),
+        }
+
+        impl FilterHandle {
+            /// Returns the directives of the current filter
+            fn directives(&self) -> String {
+                match self {
+                    FilterHandle::Text(handle) => handle.with_current(|env| env.to_string()),
+                    FilterHandle::Json(handle) => handle.with_current(|env| env.to_string()),
+                }
+                .unwrap_or_default()
+            }
+
+            fn reload(&self, env: EnvFilter) -> Result<(), reload::Error> {
+                match self {
+                    FilterHandle::Text(handle) => handle.reload(env),
+                    FilterHandle::Json(handle) => handle.reload(env),
+                }
+            }
+        }
 
         #[derive(Clone)]
         pub struct LogWriter {
             stdout:     Arc<Stdout>,
-            pub handle: Option<Handle<EnvFilter, TracingSubscriber>>,
+            pub handle: Option<FilterHandle>,
         }
 
         impl Default for LogWriter {
@@ -640,5 +1946,14 @@ cfg_if! {
     } else {
         #[derive(Clone)]
         pub struct LogWriter;
+
+        // The services are only wrapped in spans when monitoring is enabled
+        fn incoming_span<I>(service: I, _name: &'static str) -> I {
+            service
+        }
+
+        fn outgoing_span<O>(service: O, _name: &'static str) -> O {
+            service
+        }
     }
 }
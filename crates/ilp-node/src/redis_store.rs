@@ -1,16 +1,24 @@
 #![cfg(feature = "redis")]
 
 use crate::node::{InterledgerNode, LogWriter};
+use crate::shutdown::Shutdown;
 use futures::TryFutureExt;
-pub use interledger::{
-    api::{AccountDetails, NodeStore},
-    packet::Address,
-    service::Account,
-    store::redis::RedisStoreBuilder,
+pub use interledger::{packet::Address, store::redis::RedisStoreBuilder};
+use interledger::{
+    service_util::{BalanceJournalStore, BalanceStore, Scheduler},
+    store::{
+        kms::{KeyManager, KeyManagerConfig},
+        redis::{RedisStore, DEFAULT_ESCROW_SWEEP_INTERVAL},
+    },
 };
-pub use redis_crate::{ConnectionInfo, IntoConnectionInfo};
+pub use redis_crate::IntoConnectionInfo;
 use ring::hmac;
-use tracing::error;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tracing::{error, info, trace, warn};
+use url::Url;
 
 static REDIS_SECRET_GENERATION_STRING: &str = "ilp_redis_secret";
 
@@ -25,16 +33,194 @@ pub async fn serve_redis_node(
     node: InterledgerNode,
     ilp_address: Address,
     log_writer: Option<LogWriter>,
+    shutdown: Shutdown,
+    started_at: Instant,
 ) -> Result<(), ()> {
     let redis_connection_info = node.database_url.clone().into_connection_info().unwrap();
     let redis_addr = redis_connection_info.addr.clone();
     let redis_secret = generate_redis_secret(&node.secret_seed);
-    let store = RedisStoreBuilder::new(redis_connection_info, redis_secret)
-        .node_ilp_address(ilp_address.clone())
+    let mut builder = RedisStoreBuilder::new(redis_connection_info, redis_secret);
+    builder.node_ilp_address(ilp_address.clone());
+    if let Some(ref key_management) = node.key_management {
+        builder.key_manager(build_key_manager(key_management)?);
+    }
+    // The maintenance of the store runs on the node's scheduler
+    builder.without_maintenance_intervals();
+    if let Some(idempotency_ttl) = node.idempotency_ttl {
+        builder.idempotency_ttl(idempotency_ttl);
+    }
+    if let Some(uncredited_settlement_ttl) = node.uncredited_settlement_ttl {
+        builder.uncredited_settlement_ttl(uncredited_settlement_ttl);
+    }
+    if let Some(audit_log_retention) = node.audit_log_retention {
+        builder.audit_log_retention(audit_log_retention);
+    }
+    if !node.read_replica_urls.is_empty() {
+        let read_replicas = node
+            .read_replica_urls
+            .iter()
+            .map(|url| {
+                url.as_str().into_connection_info().map_err(|err| {
+                    error!(target: "interledger-node", "Invalid read replica URL {}: {:?}", url, err)
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        builder.read_replicas(read_replicas);
+    }
+    if let Some(read_replica_max_staleness) = node.read_replica_max_staleness {
+        builder.max_replica_staleness(read_replica_max_staleness);
+    }
+    let store = builder
         .connect()
         .map_err(move |err| error!(target: "interledger-node", "Error connecting to Redis: {:?} {:?}", redis_addr, err))
         .await?;
-    node.chain_services(store, ilp_address, log_writer).await
+    let scheduler = Scheduler::new(node.scheduler.clone());
+    schedule_redis_maintenance(&node, &store, &scheduler);
+    node.chain_services(
+        store,
+        ilp_address,
+        log_writer,
+        shutdown,
+        scheduler,
+        started_at,
+    )
+    .await
+}
+
+/// Schedules the balance snapshots, the release of the escrows of expired packets
+/// and the compaction of the store, on their configured intervals
+fn schedule_redis_maintenance(node: &InterledgerNode, store: &RedisStore, scheduler: &Scheduler) {
+    if let Some(balance_snapshot_interval) = node.balance_snapshot_interval {
+        let store = store.clone();
+        scheduler
+            .task(
+                "balance_snapshot",
+                Duration::from_millis(balance_snapshot_interval),
+            )
+            .spawn(move || {
+                let store = store.clone();
+                async move {
+                    let snapshotted = store
+                        .snapshot_balances()
+                        .await
+                        .map_err(|err| format!("Error snapshotting balances: {}", err))?;
+                    trace!(target: "interledger-node", "Snapshotted balances of {} accounts", snapshotted);
+                    Ok(())
+                }
+            });
+    }
+
+    // The first sweep waits for an interval so that packets which were in flight
+    // while the node restarted can still be fulfilled or rejected
+    let escrow_sweep_interval = node
+        .escrow_sweep_interval
+        .unwrap_or(DEFAULT_ESCROW_SWEEP_INTERVAL)
+        .max(1);
+    let store_clone = store.clone();
+    scheduler
+        .task(
+            "escrow_sweep",
+            Duration::from_millis(escrow_sweep_interval),
+        )
+        .delay_first_run()
+        .spawn(move || {
+            let store = store_clone.clone();
+            async move {
+                let released = store
+                    .release_expired_escrows()
+                    .await
+                    .map_err(|err| format!("Error releasing expired escrows: {}", err))?;
+                if released > 0 {
+                    warn!(target: "interledger-node", "Released the escrows of {} packets which expired without being fulfilled or rejected", released);
+                }
+                Ok(())
+            }
+        });
+
+    if let Some(compaction_interval) = node.compaction_interval {
+        let store = store.clone();
+        scheduler
+            .task(
+                "store_compaction",
+                Duration::from_millis(compaction_interval),
+            )
+            .spawn(move || {
+                let store = store.clone();
+                async move {
+                    store
+                        .compact()
+                        .await
+                        .map_err(|err| format!("Error compacting the store: {}", err))?;
+                    Ok(())
+                }
+            });
+    }
+}
+
+pub async fn rotate_redis_encryption_key(node: InterledgerNode) -> Result<(), ()> {
+    let redis_connection_info = node.database_url.clone().into_connection_info().unwrap();
+    let redis_secret = generate_redis_secret(&node.secret_seed);
+    let mut builder = RedisStoreBuilder::new(redis_connection_info, redis_secret);
+    match node.key_management {
+        Some(ref key_management) => {
+            builder.key_manager(build_key_manager(key_management)?);
+        }
+        None => {
+            error!(target: "interledger-node", "key_management must be configured to rotate the encryption key");
+            return Err(());
+        }
+    }
+    if let Some(ref previous_key_management) = node.previous_key_management {
+        builder.previous_key_manager(build_key_manager(previous_key_management)?);
+    }
+    let reencrypted = builder.rotate_encryption_key().await?;
+    info!(target: "interledger-node", "Re-encrypted the secrets of {} accounts", reencrypted);
+    Ok(())
+}
+
+/// Returns the settlement engines of the accounts in the Redis store, with the usernames
+/// of the accounts, so that it can be checked whether they are reachable. The accounts are
+/// read directly rather than by connecting the store, because connecting the store may
+/// migrate the data in it.
+pub async fn redis_settlement_engines(
+    node: &InterledgerNode,
+) -> Result<Vec<(String, Url)>, String> {
+    let redis_connection_info = node
+        .database_url
+        .as_str()
+        .into_connection_info()
+        .map_err(|err| format!("invalid Redis URL: {}", err))?;
+    let mut connection = redis_crate::Client::open(redis_connection_info)
+        .map_err(|err| format!("invalid Redis URL: {}", err))?
+        .get_async_connection()
+        .await
+        .map_err(|err| format!("cannot connect to Redis: {}", err))?;
+    let account_ids: Vec<String> = redis_crate::cmd("SMEMBERS")
+        .arg("accounts")
+        .query_async(&mut connection)
+        .await
+        .map_err(|err| format!("cannot load the accounts: {}", err))?;
+
+    let mut engines = Vec::new();
+    for id in account_ids {
+        let (username, url): (Option<String>, Option<String>) = redis_crate::cmd("HMGET")
+            .arg(format!("accounts:{}", id))
+            .arg("username")
+            .arg("settlement_engine_url")
+            .query_async(&mut connection)
+            .await
+            .map_err(|err| format!("cannot load the account {}: {}", id, err))?;
+        if let (Some(username), Some(Ok(url))) = (username, url.as_deref().map(Url::parse)) {
+            engines.push((username, url));
+        }
+    }
+    Ok(engines)
+}
+
+fn build_key_manager(config: &KeyManagerConfig) -> Result<Arc<dyn KeyManager>, ()> {
+    config.build().map_err(
+        |err| error!(target: "interledger-node", "Error creating the key manager: {}", err),
+    )
 }
 
 pub fn generate_redis_secret(secret_seed: &[u8; 32]) -> [u8; 32] {
@@ -0,0 +1,452 @@
+//! Resolution of the secrets referenced by the node's configuration.
+//!
+//! Rather than in plaintext, any string of the configuration (such as the
+//! `admin_auth_token`, the `database_url` including the Redis password, or the
+//! credentials of a key manager) may be set to a reference which is resolved at startup:
+//!
+//! - `env:NAME` is replaced with the value of the environment variable `NAME`
+//! - `file:/path/to/file` is replaced with the contents of the file, without trailing newlines
+//! - `vault:<mount>/<path>#<field>` is replaced with the field of a secret of the KV version 2
+//!   secrets engine of [HashiCorp Vault](https://www.vaultproject.io/docs/secrets/kv/kv-v2).
+//!   This needs the feature flag "vault" and the `secrets.vault` configuration.
+//!
+//! Other schemes can be added by registering a [`SecretsResolver`](./trait.SecretsResolver.html).
+
+use async_trait::async_trait;
+use config::{Config, Value};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    sync::Arc,
+};
+
+#[cfg(feature = "vault")]
+use reqwest::Client;
+#[cfg(feature = "vault")]
+use secrecy::{ExposeSecret, SecretString};
+#[cfg(feature = "vault")]
+use serde::Deserialize;
+#[cfg(feature = "vault")]
+use url::Url;
+
+/// Resolves the references of one scheme (the part of the reference before the first `:`)
+#[async_trait]
+pub trait SecretsResolver: Send + Sync {
+    /// Returns the secret identified by the part of the reference after the scheme
+    async fn resolve(&self, path: &str) -> Result<String, String>;
+}
+
+/// The resolvers of the schemes which may be used in the configuration
+pub struct SecretsResolvers {
+    resolvers: HashMap<String, Arc<dyn SecretsResolver>>,
+}
+
+impl Default for SecretsResolvers {
+    /// Resolves `env:` and `file:` references. `vault:` references are rejected
+    /// until a resolver is registered for them.
+    fn default() -> Self {
+        let mut resolvers = SecretsResolvers {
+            resolvers: HashMap::new(),
+        };
+        resolvers.register("env", Arc::new(EnvResolver));
+        resolvers.register("file", Arc::new(FileResolver));
+        resolvers.register("vault", Arc::new(UnconfiguredVaultResolver));
+        resolvers
+    }
+}
+
+impl SecretsResolvers {
+    /// Uses the resolver for the references of the scheme, replacing the previous one
+    pub fn register(&mut self, scheme: &str, resolver: Arc<dyn SecretsResolver>) {
+        self.resolvers.insert(scheme.to_string(), resolver);
+    }
+
+    /// Replaces all references found in the value (and the tables and arrays it contains)
+    /// with the secrets they refer to
+    pub async fn resolve(&self, value: &mut Value) -> Result<(), String> {
+        let mut references = HashSet::new();
+        self.collect_references(value, &mut references);
+
+        let mut secrets = HashMap::with_capacity(references.len());
+        for reference in references {
+            let (scheme, path) = self.parse_reference(&reference).unwrap();
+            let secret = self.resolvers[scheme]
+                .resolve(path)
+                .await
+                .map_err(|err| format!("Error resolving secret {}: {}", reference, err))?;
+            secrets.insert(reference, secret);
+        }
+
+        replace_references(value, &secrets);
+        Ok(())
+    }
+
+    /// Splits the string into its scheme and path if it is a reference to a secret
+    fn parse_reference<'a>(&self, value: &'a str) -> Option<(&'a str, &'a str)> {
+        let index = value.find(':')?;
+        let (scheme, path) = (&value[..index], &value[index + 1..]);
+        if self.resolvers.contains_key(scheme) {
+            Some((scheme, path))
+        } else {
+            None
+        }
+    }
+
+    fn collect_references(&self, value: &Value, references: &mut HashSet<String>) {
+        match kind(value) {
+            Kind::Table(table) => table
+                .values()
+                .for_each(|value| self.collect_references(value, references)),
+            Kind::Array(array) => array
+                .iter()
+                .for_each(|value| self.collect_references(value, references)),
+            Kind::String(string) => {
+                if self.parse_reference(&string).is_some() {
+                    references.insert(string);
+                }
+            }
+        }
+    }
+}
+
+fn replace_references(value: &mut Value, secrets: &HashMap<String, String>) {
+    match kind(value) {
+        Kind::Table(mut table) => {
+            table
+                .values_mut()
+                .for_each(|value| replace_references(value, secrets));
+            *value = Value::new(None, table);
+        }
+        Kind::Array(mut array) => {
+            array
+                .iter_mut()
+                .for_each(|value| replace_references(value, secrets));
+            *value = Value::new(None, array);
+        }
+        Kind::String(string) => {
+            if let Some(secret) = secrets.get(&string) {
+                *value = Value::new(None, secret.clone());
+            }
+        }
+    }
+}
+
+/// The config crate does not expose the kinds of its values, so they are told apart
+/// by the conversions which succeed. Numbers and booleans are converted to strings,
+/// which never are references.
+enum Kind {
+    Table(HashMap<String, Value>),
+    Array(Vec<Value>),
+    String(String),
+}
+
+fn kind(value: &Value) -> Kind {
+    if let Ok(table) = value.clone().into_table() {
+        Kind::Table(table)
+    } else if let Ok(array) = value.clone().into_array() {
+        Kind::Array(array)
+    } else {
+        // Nil is the only kind which cannot be converted to a string
+        Kind::String(value.clone().into_str().unwrap_or_default())
+    }
+}
+
+/// Returns the configuration with all references to secrets replaced by the secrets.
+/// The `secrets` table configures the resolvers, so its own references may only use
+/// the `env:` and `file:` schemes.
+pub async fn resolve_secrets(config: &Config) -> Result<Config, String> {
+    let resolvers = SecretsResolvers::default();
+    #[cfg(feature = "vault")]
+    let resolvers = register_vault_resolver(resolvers, config).await?;
+
+    let mut value = config.cache.clone();
+    resolvers.resolve(&mut value).await?;
+    let mut resolved = Config::new();
+    for (key, value) in value.into_table().unwrap_or_default() {
+        resolved
+            .set(&key, value)
+            .map_err(|err| format!("Error setting {}: {}", key, err))?;
+    }
+    Ok(resolved)
+}
+
+/// Registers the resolver of `vault:` references if the `secrets.vault` table is configured
+#[cfg(feature = "vault")]
+async fn register_vault_resolver(
+    mut resolvers: SecretsResolvers,
+    config: &Config,
+) -> Result<SecretsResolvers, String> {
+    if let Ok(mut vault_config) = config.get::<Value>("secrets.vault") {
+        resolvers.resolve(&mut vault_config).await?;
+        let vault_config: VaultSecretsConfig = vault_config
+            .try_into()
+            .map_err(|err| format!("Invalid secrets.vault configuration: {}", err))?;
+        resolvers.register("vault", Arc::new(VaultResolver::new(vault_config)));
+    }
+    Ok(resolvers)
+}
+
+/// Resolves `env:NAME` references to the value of the environment variable
+struct EnvResolver;
+
+#[async_trait]
+impl SecretsResolver for EnvResolver {
+    async fn resolve(&self, name: &str) -> Result<String, String> {
+        std::env::var(name).map_err(|err| format!("{} ({})", err, name))
+    }
+}
+
+/// Resolves `file:PATH` references to the contents of the file
+struct FileResolver;
+
+#[async_trait]
+impl SecretsResolver for FileResolver {
+    async fn resolve(&self, path: &str) -> Result<String, String> {
+        let contents = fs::read_to_string(path).map_err(|err| err.to_string())?;
+        // Files written by editors and `echo` usually end with a newline
+        Ok(contents.trim_end_matches(&['\r', '\n'][..]).to_string())
+    }
+}
+
+/// Rejects `vault:` references, so that they are not used as plaintext
+/// when the node cannot resolve them
+struct UnconfiguredVaultResolver;
+
+#[async_trait]
+impl SecretsResolver for UnconfiguredVaultResolver {
+    async fn resolve(&self, _path: &str) -> Result<String, String> {
+        Err(
+            "vault references need the feature flag \"vault\" and the secrets.vault configuration"
+                .to_string(),
+        )
+    }
+}
+
+/// Configuration of the Vault server the `vault:` references are read from
+#[cfg(feature = "vault")]
+#[derive(Deserialize)]
+struct VaultSecretsConfig {
+    /// Address of the Vault server, such as `https://vault.example.com:8200`
+    url: Url,
+    token: SecretString,
+}
+
+/// Resolves `vault:<mount>/<path>#<field>` references to the field of a secret
+/// of a KV version 2 secrets engine
+#[cfg(feature = "vault")]
+struct VaultResolver {
+    client: Client,
+    config: VaultSecretsConfig,
+}
+
+#[cfg(feature = "vault")]
+#[derive(Deserialize)]
+struct VaultResponse {
+    data: VaultSecret,
+}
+
+#[cfg(feature = "vault")]
+#[derive(Deserialize)]
+struct VaultSecret {
+    data: HashMap<String, String>,
+}
+
+#[cfg(feature = "vault")]
+impl VaultResolver {
+    fn new(config: VaultSecretsConfig) -> Self {
+        VaultResolver {
+            client: Client::new(),
+            config,
+        }
+    }
+}
+
+#[cfg(feature = "vault")]
+#[async_trait]
+impl SecretsResolver for VaultResolver {
+    async fn resolve(&self, path: &str) -> Result<String, String> {
+        let invalid_path = || "expected a reference like vault:secret/ilp#field".to_string();
+        let index = path.rfind('#').ok_or_else(invalid_path)?;
+        let (path, field) = (&path[..index], &path[index + 1..]);
+        let index = path.find('/').ok_or_else(invalid_path)?;
+        let (mount, path) = (&path[..index], &path[index + 1..]);
+
+        let url = self
+            .config
+            .url
+            .join(&format!("v1/{}/data/{}", mount, path))
+            .map_err(|err| err.to_string())?;
+        let response = self
+            .client
+            .get(url)
+            .header("X-Vault-Token", self.config.token.expose_secret().as_str())
+            .send()
+            .await
+            .map_err(|err| err.to_string())?;
+        if !response.status().is_success() {
+            return Err(format!("Vault responded with {}", response.status()));
+        }
+        let mut response: VaultResponse = response.json().await.map_err(|err| err.to_string())?;
+        response
+            .data
+            .data
+            .remove(field)
+            .ok_or_else(|| format!("the secret has no field {}", field))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, path::PathBuf};
+
+    fn temp_file(name: &str, contents: &str) -> PathBuf {
+        let path =
+            env::temp_dir().join(format!("ilp-node-secrets-{}-{}", name, std::process::id()));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    struct StaticResolver;
+
+    #[async_trait]
+    impl SecretsResolver for StaticResolver {
+        async fn resolve(&self, path: &str) -> Result<String, String> {
+            Ok(format!("static {}", path))
+        }
+    }
+
+    #[tokio::test]
+    async fn resolves_env_and_file_references() {
+        env::set_var("ILP_SECRETS_TEST_TOKEN", "admin token");
+        let path = temp_file("database-url", "redis://:password@127.0.0.1:6379\n\n");
+        let mut config = Config::new();
+        config
+            .set("admin_auth_token", "env:ILP_SECRETS_TEST_TOKEN")
+            .unwrap();
+        config
+            .set("store.database_url", format!("file:{}", path.display()))
+            .unwrap();
+        config
+            .set("tokens", vec!["env:ILP_SECRETS_TEST_TOKEN", "plaintext"])
+            .unwrap();
+        config.set("http_bind_address", "127.0.0.1:7770").unwrap();
+        config.set("exchange_rate.poll_interval", 60_000).unwrap();
+
+        let resolved = resolve_secrets(&config).await.unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(resolved.get_str("admin_auth_token").unwrap(), "admin token");
+        assert_eq!(
+            resolved.get_str("store.database_url").unwrap(),
+            "redis://:password@127.0.0.1:6379"
+        );
+        assert_eq!(
+            resolved.get::<Vec<String>>("tokens").unwrap(),
+            vec!["admin token", "plaintext"]
+        );
+        assert_eq!(
+            resolved.get_str("http_bind_address").unwrap(),
+            "127.0.0.1:7770"
+        );
+        assert_eq!(
+            resolved.get_int("exchange_rate.poll_interval").unwrap(),
+            60_000
+        );
+    }
+
+    #[tokio::test]
+    async fn leaves_strings_of_unknown_schemes() {
+        let mut config = Config::new();
+        config
+            .set("database_url", "redis://127.0.0.1:6379")
+            .unwrap();
+        config.set("ilp_address", "example.node").unwrap();
+
+        let resolved = resolve_secrets(&config).await.unwrap();
+        assert_eq!(
+            resolved.get_str("database_url").unwrap(),
+            "redis://127.0.0.1:6379"
+        );
+        assert_eq!(resolved.get_str("ilp_address").unwrap(), "example.node");
+    }
+
+    #[tokio::test]
+    async fn uses_registered_resolvers() {
+        let mut resolvers = SecretsResolvers::default();
+        resolvers.register("static", Arc::new(StaticResolver));
+        let mut value = Value::new(None, "static:secret");
+
+        resolvers.resolve(&mut value).await.unwrap();
+        assert_eq!(value.into_str().unwrap(), "static secret");
+    }
+
+    #[tokio::test]
+    async fn reports_missing_environment_variables() {
+        let mut config = Config::new();
+        config
+            .set("admin_auth_token", "env:ILP_SECRETS_TEST_MISSING")
+            .unwrap();
+
+        let err = resolve_secrets(&config).await.unwrap_err();
+        assert!(err.starts_with("Error resolving secret env:ILP_SECRETS_TEST_MISSING:"));
+    }
+
+    #[tokio::test]
+    async fn reports_missing_files() {
+        let path = env::temp_dir().join("ilp-node-secrets-missing");
+        let mut config = Config::new();
+        config
+            .set("admin_auth_token", format!("file:{}", path.display()))
+            .unwrap();
+
+        let err = resolve_secrets(&config).await.unwrap_err();
+        assert!(err.starts_with(&format!("Error resolving secret file:{}:", path.display())));
+    }
+
+    #[tokio::test]
+    async fn rejects_vault_references_without_configuration() {
+        let mut config = Config::new();
+        config
+            .set("admin_auth_token", "vault:secret/ilp#admin_auth_token")
+            .unwrap();
+
+        let err = resolve_secrets(&config).await.unwrap_err();
+        assert!(err.contains("the secrets.vault configuration"));
+    }
+
+    #[cfg(feature = "vault")]
+    #[tokio::test]
+    async fn resolves_vault_references() {
+        let m = mockito::mock("GET", "/v1/secret/data/ilp")
+            .match_header("X-Vault-Token", "vault token")
+            .with_body(r#"{"data":{"data":{"admin_auth_token":"admin token"}}}"#)
+            .create();
+        env::set_var("ILP_SECRETS_TEST_VAULT_TOKEN", "vault token");
+        let mut config = Config::new();
+        config
+            .set("secrets.vault.url", mockito::server_url())
+            .unwrap();
+        config
+            .set("secrets.vault.token", "env:ILP_SECRETS_TEST_VAULT_TOKEN")
+            .unwrap();
+        config
+            .set("admin_auth_token", "vault:secret/ilp#admin_auth_token")
+            .unwrap();
+        config
+            .set("other_token", "vault:secret/ilp#missing")
+            .unwrap();
+
+        let err = resolve_secrets(&config).await.unwrap_err();
+        assert!(err.ends_with("the secret has no field missing"));
+
+        config.set("other_token", "vault:secret/ilp").unwrap();
+        let err = resolve_secrets(&config).await.unwrap_err();
+        assert!(err.ends_with("expected a reference like vault:secret/ilp#field"));
+
+        config.set("other_token", "plaintext").unwrap();
+        let resolved = resolve_secrets(&config).await.unwrap();
+        assert_eq!(resolved.get_str("admin_auth_token").unwrap(), "admin token");
+        m.expect_at_least(1).assert();
+    }
+}
@@ -0,0 +1,241 @@
+use futures::future::{join_all, BoxFuture};
+use interledger::{
+    packet::{Address, ErrorCode, RejectBuilder},
+    service::{Account, IlpResult, IncomingRequest, IncomingService},
+};
+use serde::Deserialize;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::{
+    future::Future,
+    io, mem,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+use tokio::{
+    net::TcpListener,
+    sync::watch,
+    task::JoinHandle,
+    time::{delay_for, timeout_at, Instant},
+};
+use tracing::{info, warn};
+
+/// How often the node checks whether the in-flight requests completed while it drains them
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Configuration of how the node stops
+#[derive(Deserialize, Clone, Debug)]
+pub struct ShutdownConfig {
+    /// Time, in milliseconds, the node waits for the requests which are in flight when it
+    /// is asked to stop, and for its cleanup, before it exits anyway.
+    /// Defaults to 30000ms (30 seconds).
+    #[serde(default = "ShutdownConfig::default_deadline")]
+    pub deadline: u64,
+    /// Whether the HTTP API and the settlement API are bound with `SO_REUSEPORT`, so that
+    /// the node replacing this one during a restart can listen on the same addresses
+    /// before this one stops. Only supported on Unix.
+    #[serde(default)]
+    pub reuse_port: bool,
+}
+
+impl ShutdownConfig {
+    fn default_deadline() -> u64 {
+        30_000
+    }
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> Self {
+        ShutdownConfig {
+            deadline: ShutdownConfig::default_deadline(),
+            reuse_port: false,
+        }
+    }
+}
+
+/// Coordinates the shutdown of the node: once it starts, the servers stop accepting
+/// connections, new packets are rejected, and the node waits for the servers and the
+/// in-flight requests to complete before it runs the cleanup hooks.
+#[derive(Clone)]
+pub struct Shutdown {
+    sender: Arc<watch::Sender<bool>>,
+    receiver: watch::Receiver<bool>,
+    stopping: Arc<AtomicBool>,
+    in_flight: Arc<AtomicUsize>,
+    servers: Arc<Mutex<Vec<JoinHandle<()>>>>,
+    hooks: Arc<Mutex<Vec<BoxFuture<'static, ()>>>>,
+}
+
+/// Marks a request as in flight until it is dropped
+pub struct InFlight(Arc<AtomicUsize>);
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        let (sender, receiver) = watch::channel(false);
+        Shutdown {
+            sender: Arc::new(sender),
+            receiver,
+            stopping: Arc::new(AtomicBool::new(false)),
+            in_flight: Arc::new(AtomicUsize::new(0)),
+            servers: Arc::new(Mutex::new(Vec::new())),
+            hooks: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+}
+
+impl Shutdown {
+    /// Returns a future which resolves once the shutdown started
+    pub fn signal(&self) -> impl Future<Output = ()> + Send + 'static {
+        let mut receiver = self.receiver.clone();
+        async move {
+            while let Some(stopping) = receiver.recv().await {
+                if stopping {
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Marks a request as in flight, unless the shutdown started
+    pub fn track(&self) -> Option<InFlight> {
+        // Counting the request before checking the flag ensures that
+        // it is either rejected or waited for
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        let in_flight = InFlight(self.in_flight.clone());
+        if self.stopping.load(Ordering::SeqCst) {
+            None
+        } else {
+            Some(in_flight)
+        }
+    }
+
+    /// Spawns a server, which must stop accepting connections once the shutdown
+    /// starts. The node waits for it to complete before it exits.
+    pub fn spawn_server<F>(&self, server: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let handle = tokio::spawn(server);
+        self.servers.lock().unwrap().push(handle);
+    }
+
+    /// Adds a hook which runs once the servers and the in-flight requests completed
+    pub fn on_shutdown<F>(&self, hook: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.hooks.lock().unwrap().push(Box::pin(hook));
+    }
+
+    /// Starts the shutdown, and waits for the servers and the in-flight requests to
+    /// complete and then for the hooks to run. Returns false if they did not before
+    /// the deadline passed.
+    pub async fn shut_down(&self, deadline: Duration) -> bool {
+        self.stopping.store(true, Ordering::SeqCst);
+        let _ = self.sender.broadcast(true);
+        let deadline = Instant::now() + deadline;
+
+        let servers = mem::take(&mut *self.servers.lock().unwrap());
+        let in_flight = self.in_flight.clone();
+        let drained = timeout_at(deadline, async move {
+            join_all(servers).await;
+            while in_flight.load(Ordering::SeqCst) > 0 {
+                delay_for(DRAIN_POLL_INTERVAL).await;
+            }
+        })
+        .await
+        .is_ok();
+        if drained {
+            info!(target: "interledger-node", "Drained the in-flight requests");
+        } else {
+            warn!(target: "interledger-node", "Stopping with {} requests still in flight, because the shutdown deadline passed", self.in_flight.load(Ordering::SeqCst));
+        }
+
+        let hooks = mem::take(&mut *self.hooks.lock().unwrap());
+        let cleaned_up = timeout_at(deadline, join_all(hooks)).await.is_ok();
+        if !cleaned_up {
+            warn!(target: "interledger-node", "Stopping before the cleanup completed, because the shutdown deadline passed");
+        }
+        drained && cleaned_up
+    }
+}
+
+/// Rejects the packets received after the shutdown started, and tracks the
+/// others until they are fulfilled or rejected
+pub fn incoming_shutdown<A: Account + 'static>(
+    shutdown: Shutdown,
+    ilp_address: Address,
+) -> impl Fn(IncomingRequest<A>, Box<dyn IncomingService<A> + Send>) -> BoxFuture<'static, IlpResult>
+       + Clone
+       + Send
+       + Sync {
+    move |request, mut next| {
+        let in_flight = shutdown.track();
+        let ilp_address = ilp_address.clone();
+        Box::pin(async move {
+            match in_flight {
+                Some(_in_flight) => next.handle_request(request).await,
+                None => Err(RejectBuilder {
+                    code: ErrorCode::T00_INTERNAL_ERROR,
+                    message: b"The node is shutting down",
+                    triggered_by: Some(&ilp_address),
+                    data: &[],
+                }
+                .build()),
+            }
+        })
+    }
+}
+
+/// Resolves once the node is asked to stop, which happens on SIGTERM or SIGINT (Ctrl-C)
+pub async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = terminate.recv() => {},
+                    _ = tokio::signal::ctrl_c() => {},
+                }
+                return;
+            }
+            Err(err) => {
+                warn!(target: "interledger-node", "Unable to listen for SIGTERM, the node only stops gracefully on SIGINT: {}", err)
+            }
+        }
+    }
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+/// Binds a listener to the address. With `reuse_port`, other processes (such as the
+/// node replacing this one during a restart) may listen on the same address as well.
+pub(crate) fn bind_listener(address: SocketAddr, reuse_port: bool) -> io::Result<TcpListener> {
+    let domain = if address.is_ipv4() {
+        Domain::ipv4()
+    } else {
+        Domain::ipv6()
+    };
+    let socket = Socket::new(domain, Type::stream(), Some(Protocol::tcp()))?;
+    socket.set_reuse_address(true)?;
+    if reuse_port {
+        #[cfg(unix)]
+        socket.set_reuse_port(true)?;
+        #[cfg(not(unix))]
+        warn!(target: "interledger-node", "SO_REUSEPORT is not supported on this platform, binding {} without it", address);
+    }
+    socket.bind(&address.into())?;
+    socket.listen(1024)?;
+    let listener = socket.into_tcp_listener();
+    listener.set_nonblocking(true)?;
+    TcpListener::from_std(listener)
+}
@@ -0,0 +1,422 @@
+//! `ilp-node snapshot create` and `ilp-node snapshot restore`, which back up the state of a
+//! node (its accounts with their secrets, balances, routes, settlement progress and
+//! everything else in its store) to an encrypted file, and restore it.
+//!
+//! The keys are read in batches with `SCAN`, so that the store keeps serving other clients
+//! while the snapshot is taken, which means that the node must be stopped for the snapshot
+//! to be consistent. It is encrypted with a key derived from the node's `secret_seed`, which
+//! the node needs anyway to decrypt the tokens of the restored accounts, and its integrity
+//! is checked (by the authentication tag of the encryption) before anything is restored.
+
+use crate::config_check::parse_config;
+use crate::init::write_secret_file;
+use crate::migrate::{summary, Store};
+use crate::node::InterledgerNode;
+use crate::secrets::resolve_secrets;
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use config::{Config, File};
+use ring::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN},
+    hmac,
+    rand::{SecureRandom, SystemRandom},
+};
+use std::{
+    convert::TryInto,
+    ffi::OsString,
+    fs, iter,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Prefix of the snapshot files, which is also authenticated by their encryption
+const MAGIC: &[u8; 8] = b"ILPSNAP1";
+static SNAPSHOT_KEY_GENERATION_STRING: &[u8] = b"ilp_snapshot_key";
+
+/// Number of keys read or restored per round trip
+const BATCH_SIZE: usize = 1000;
+
+fn snapshot_app<'a, 'b>() -> App<'a, 'b> {
+    let config = Arg::with_name("config")
+        .takes_value(true)
+        .index(1)
+        .required(true)
+        .help("Name of the config file of the node (in JSON, YAML, or TOML format), whose database_url is the store to back up or restore to, and whose secret_seed encrypts the snapshot.");
+    App::new("ilp-node snapshot")
+        .about("Backs up the state of a node to an encrypted file, or restores it.")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommands(vec![
+            SubCommand::with_name("create")
+                .about("Writes an encrypted snapshot of the node's store. Stop the node first, as the keys are read in batches and a running node would change them in between.")
+                .args(&[
+                    config.clone(),
+                    Arg::with_name("output")
+                        .long("output")
+                        .takes_value(true)
+                        .required(true)
+                        .help("File to write the snapshot to."),
+                    Arg::with_name("force")
+                        .long("force")
+                        .help("Overwrite the file if it exists."),
+                ]),
+            SubCommand::with_name("restore")
+                .about("Checks the integrity of a snapshot and restores it to the node's store. The node must be stopped.")
+                .args(&[
+                    config,
+                    Arg::with_name("input")
+                        .long("input")
+                        .takes_value(true)
+                        .required(true)
+                        .help("File to read the snapshot from."),
+                    Arg::with_name("overwrite")
+                        .long("overwrite")
+                        .help("Restore to a store which is not empty. Its keys which are not in the snapshot are deleted."),
+                ]),
+        ])
+}
+
+/// A key of the store, serialized with `DUMP`
+#[derive(Debug, PartialEq)]
+struct Entry {
+    key: Vec<u8>,
+    value: Vec<u8>,
+    /// Milliseconds before the key expires, or 0 if it does not expire
+    ttl: u64,
+}
+
+/// The keys of a store at the time the snapshot was taken
+#[derive(Debug, PartialEq)]
+struct Snapshot {
+    /// When the snapshot was taken, in milliseconds since the UNIX epoch
+    created_at: u64,
+    entries: Vec<Entry>,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+fn snapshot_key(secret_seed: &[u8; 32]) -> LessSafeKey {
+    let key = hmac::sign(
+        &hmac::Key::new(hmac::HMAC_SHA256, secret_seed),
+        SNAPSHOT_KEY_GENERATION_STRING,
+    );
+    LessSafeKey::new(
+        UnboundKey::new(&AES_256_GCM, key.as_ref()).expect("the key has the length of AES-256"),
+    )
+}
+
+impl Snapshot {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&self.created_at.to_be_bytes());
+        bytes.extend_from_slice(&(self.entries.len() as u32).to_be_bytes());
+        for entry in self.entries.iter() {
+            bytes.extend_from_slice(&(entry.key.len() as u32).to_be_bytes());
+            bytes.extend_from_slice(&entry.key);
+            bytes.extend_from_slice(&(entry.value.len() as u32).to_be_bytes());
+            bytes.extend_from_slice(&entry.value);
+            bytes.extend_from_slice(&entry.ttl.to_be_bytes());
+        }
+        bytes
+    }
+
+    fn from_bytes(mut bytes: &[u8]) -> Result<Self, String> {
+        fn take<'a>(bytes: &mut &'a [u8], length: usize) -> Result<&'a [u8], String> {
+            if bytes.len() < length {
+                return Err("The snapshot is truncated".to_string());
+            }
+            let (taken, rest) = bytes.split_at(length);
+            *bytes = rest;
+            Ok(taken)
+        }
+        fn take_u32(bytes: &mut &[u8]) -> Result<u32, String> {
+            Ok(u32::from_be_bytes(take(bytes, 4)?.try_into().unwrap()))
+        }
+        fn take_u64(bytes: &mut &[u8]) -> Result<u64, String> {
+            Ok(u64::from_be_bytes(take(bytes, 8)?.try_into().unwrap()))
+        }
+
+        let created_at = take_u64(&mut bytes)?;
+        let count = take_u32(&mut bytes)?;
+        let mut entries = Vec::new();
+        for _ in 0..count {
+            let length = take_u32(&mut bytes)? as usize;
+            let key = take(&mut bytes, length)?.to_vec();
+            let length = take_u32(&mut bytes)? as usize;
+            let value = take(&mut bytes, length)?.to_vec();
+            let ttl = take_u64(&mut bytes)?;
+            entries.push(Entry { key, value, ttl });
+        }
+        if !bytes.is_empty() {
+            return Err("The snapshot has trailing data".to_string());
+        }
+        Ok(Snapshot {
+            created_at,
+            entries,
+        })
+    }
+
+    fn encrypt(&self, secret_seed: &[u8; 32]) -> Result<Vec<u8>, String> {
+        let mut nonce = [0; NONCE_LEN];
+        SystemRandom::new()
+            .fill(&mut nonce)
+            .map_err(|_| "Error generating random bytes".to_string())?;
+        let mut sealed = self.to_bytes();
+        snapshot_key(secret_seed)
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(MAGIC),
+                &mut sealed,
+            )
+            .map_err(|_| "Error encrypting the snapshot".to_string())?;
+        Ok(MAGIC
+            .iter()
+            .chain(nonce.iter())
+            .chain(sealed.iter())
+            .cloned()
+            .collect())
+    }
+
+    fn decrypt(file: &[u8], secret_seed: &[u8; 32]) -> Result<Self, String> {
+        if file.len() < MAGIC.len() + NONCE_LEN + AES_256_GCM.tag_len()
+            || &file[..MAGIC.len()] != MAGIC
+        {
+            return Err("The file is not a snapshot".to_string());
+        }
+        let (nonce, sealed) = file[MAGIC.len()..].split_at(NONCE_LEN);
+        let mut sealed = sealed.to_vec();
+        let bytes = snapshot_key(secret_seed)
+            .open_in_place(
+                Nonce::try_assume_unique_for_key(nonce).map_err(|_| "Invalid nonce".to_string())?,
+                Aad::from(MAGIC),
+                &mut sealed,
+            )
+            .map_err(|_| {
+                "The snapshot is corrupted, or was taken by a node with another secret_seed"
+                    .to_string()
+            })?;
+        Snapshot::from_bytes(bytes)
+    }
+}
+
+async fn load_node(matches: &ArgMatches<'_>) -> Result<InterledgerNode, String> {
+    let config_path = matches.value_of("config").unwrap_or_default();
+    let mut config = Config::new();
+    config
+        .merge(File::with_name(config_path))
+        .map_err(|err| format!("Error reading {}: {}", config_path, err))?;
+    let resolved = resolve_secrets(&config).await?;
+    parse_config(resolved).map_err(|issue| format!("Invalid configuration: {}", issue))
+}
+
+async fn create_snapshot(matches: &ArgMatches<'_>) -> Result<(), String> {
+    let node = load_node(matches).await?;
+    let output = matches.value_of("output").unwrap_or_default();
+    let mut store = Store::connect(node.database_url.as_str()).await?;
+
+    let created_at = now();
+    let keys: Vec<Vec<u8>> = store.keys(BATCH_SIZE).await?.into_iter().collect();
+    let mut entries = Vec::with_capacity(keys.len());
+    for batch in keys.chunks(BATCH_SIZE) {
+        let values = store.dump(batch).await?;
+        // The keys which were removed since they were listed are left out
+        entries.extend(batch.iter().zip(values).filter_map(|(key, (value, ttl))| {
+            value.map(|value| Entry {
+                key: key.clone(),
+                value,
+                ttl,
+            })
+        }));
+    }
+    let snapshot = Snapshot {
+        created_at,
+        entries,
+    };
+    write_secret_file(
+        output,
+        snapshot.encrypt(&node.secret_seed)?,
+        matches.is_present("force"),
+    )?;
+    let summary = summary(&mut store).await?;
+    println!(
+        "Wrote a snapshot of the {} keys of {}, which has {}, to {}",
+        snapshot.entries.len(),
+        store.url,
+        summary,
+        output
+    );
+    Ok(())
+}
+
+async fn restore_snapshot(matches: &ArgMatches<'_>) -> Result<(), String> {
+    let node = load_node(matches).await?;
+    let input = matches.value_of("input").unwrap_or_default();
+    let file = fs::read(input).map_err(|err| format!("Error reading {}: {}", input, err))?;
+    let snapshot = Snapshot::decrypt(&file, &node.secret_seed)?;
+
+    let mut store = Store::connect(node.database_url.as_str()).await?;
+    let existing = store.keys(BATCH_SIZE).await?;
+    if !existing.is_empty() && !matches.is_present("overwrite") {
+        return Err(format!(
+            "{} is not empty. Pass --overwrite to replace its content.",
+            store.url
+        ));
+    }
+
+    // The keys which would have expired since the snapshot was taken are left out
+    let elapsed = now().saturating_sub(snapshot.created_at);
+    let entries: Vec<&Entry> = snapshot
+        .entries
+        .iter()
+        .filter(|entry| entry.ttl == 0 || entry.ttl > elapsed)
+        .collect();
+    let mut pipe = redis_crate::pipe();
+    pipe.atomic();
+    for key in existing.iter() {
+        pipe.cmd("DEL").arg(key.as_slice()).ignore();
+    }
+    for entry in entries.iter() {
+        let ttl = if entry.ttl == 0 {
+            0
+        } else {
+            entry.ttl - elapsed
+        };
+        pipe.cmd("RESTORE")
+            .arg(entry.key.as_slice())
+            .arg(ttl)
+            .arg(entry.value.as_slice())
+            .arg("REPLACE")
+            .ignore();
+    }
+    pipe.query_async::<_, ()>(&mut store.connection)
+        .await
+        .map_err(|err| store.error("restoring the snapshot to", err))?;
+
+    let restored = store.keys(BATCH_SIZE).await?;
+    if let Some(entry) = entries.iter().find(|entry| !restored.contains(&entry.key)) {
+        return Err(format!(
+            "{} was not restored",
+            String::from_utf8_lossy(&entry.key)
+        ));
+    }
+    let summary = summary(&mut store).await?;
+    println!(
+        "Restored {} keys to {}, which has {} ({} keys had expired since the snapshot was taken)",
+        entries.len(),
+        store.url,
+        summary,
+        snapshot.entries.len() - entries.len()
+    );
+    Ok(())
+}
+
+pub async fn run_snapshot<I>(args: I) -> Result<(), String>
+where
+    I: Iterator<Item = OsString>,
+{
+    let matches = snapshot_app()
+        .get_matches_from(iter::once(OsString::from("ilp-node snapshot")).chain(args));
+    match matches.subcommand() {
+        ("create", Some(matches)) => create_snapshot(matches).await,
+        ("restore", Some(matches)) => restore_snapshot(matches).await,
+        _ => unreachable!("a subcommand is required"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot() -> Snapshot {
+        Snapshot {
+            created_at: 1_589_000_000_000,
+            entries: vec![
+                Entry {
+                    key: b"accounts".to_vec(),
+                    value: vec![2, 1, 0, 255],
+                    ttl: 0,
+                },
+                Entry {
+                    key: b"idempotency-key:b8a4a0c1".to_vec(),
+                    value: Vec::new(),
+                    ttl: 3_600_000,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn round_trips_the_bytes() {
+        let snapshot = snapshot();
+        assert_eq!(
+            Snapshot::from_bytes(&snapshot.to_bytes()).unwrap(),
+            snapshot
+        );
+        let empty = Snapshot {
+            created_at: 0,
+            entries: Vec::new(),
+        };
+        assert_eq!(Snapshot::from_bytes(&empty.to_bytes()).unwrap(), empty);
+    }
+
+    #[test]
+    fn rejects_truncated_or_trailing_bytes() {
+        let bytes = snapshot().to_bytes();
+        for length in [0, 11, bytes.len() - 1].iter() {
+            assert_eq!(
+                Snapshot::from_bytes(&bytes[..*length]).unwrap_err(),
+                "The snapshot is truncated"
+            );
+        }
+        let mut trailing = bytes;
+        trailing.push(0);
+        assert_eq!(
+            Snapshot::from_bytes(&trailing).unwrap_err(),
+            "The snapshot has trailing data"
+        );
+    }
+
+    #[test]
+    fn round_trips_the_encryption() {
+        let snapshot = snapshot();
+        let file = snapshot.encrypt(&[7; 32]).unwrap();
+        assert!(file.starts_with(MAGIC));
+        assert_eq!(Snapshot::decrypt(&file, &[7; 32]).unwrap(), snapshot);
+        // A random nonce is used for every snapshot
+        assert_ne!(snapshot.encrypt(&[7; 32]).unwrap(), file);
+    }
+
+    #[test]
+    fn rejects_tampered_snapshots() {
+        let file = snapshot().encrypt(&[7; 32]).unwrap();
+        let corrupted =
+            "The snapshot is corrupted, or was taken by a node with another secret_seed";
+        // Every byte after the prefix is authenticated: the nonce, the content and the tag
+        for position in MAGIC.len()..file.len() {
+            let mut tampered = file.clone();
+            tampered[position] ^= 1;
+            assert_eq!(
+                Snapshot::decrypt(&tampered, &[7; 32]).unwrap_err(),
+                corrupted
+            );
+        }
+        assert_eq!(
+            Snapshot::decrypt(&file[..file.len() - 1], &[7; 32]).unwrap_err(),
+            corrupted
+        );
+        assert_eq!(Snapshot::decrypt(&file, &[8; 32]).unwrap_err(), corrupted);
+
+        let mut prefix = file.clone();
+        prefix[0] ^= 1;
+        assert_eq!(
+            Snapshot::decrypt(&prefix, &[7; 32]).unwrap_err(),
+            "The file is not a snapshot"
+        );
+        assert_eq!(
+            Snapshot::decrypt(&file[..MAGIC.len() + NONCE_LEN], &[7; 32]).unwrap_err(),
+            "The file is not a snapshot"
+        );
+    }
+}
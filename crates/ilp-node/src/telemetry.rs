@@ -0,0 +1,344 @@
+use interledger::{
+    errors::ApiError,
+    service_util::{NodeStats, Scheduler},
+};
+use reqwest::Client;
+use ring::hmac;
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::{Arc, RwLock},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tracing::{debug, warn};
+use url::Url;
+use warp::{Filter, Rejection};
+
+/// Label of the HMAC of the secret seed from which the anonymous identifier of the node is
+/// derived, so that the collector can tell the reports of a node apart across restarts
+/// without learning its address or secret
+static TELEMETRY_ID_LABEL: &[u8] = b"ilp_node_telemetry_id";
+/// Packet counts at or above this are all reported in the same bucket
+const MAX_VOLUME_BUCKET: u64 = 1_000_000;
+/// Requests to the collector which take longer are abandoned until the next report,
+/// so that an unresponsive collector does not hold the telemetry task
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Configuration for sharing anonymized health metrics of the node with a collector, so that
+/// the operators of a network can tell how healthy it is. Nothing is shared without it.
+#[derive(Deserialize, Clone)]
+pub struct TelemetryConfig {
+    /// URL to which the reports are POSTed as JSON
+    pub collector_url: Url,
+    /// URL from which the aggregated statistics of the network are fetched after
+    /// each report. If it is not set, only the reports are sent.
+    #[serde(default)]
+    pub network_stats_url: Option<Url>,
+    /// Interval, in milliseconds, at which the reports are sent. Defaults to 3600000ms (1 hour).
+    #[serde(default = "TelemetryConfig::default_interval")]
+    pub interval: u64,
+}
+
+impl TelemetryConfig {
+    fn default_interval() -> u64 {
+        3_600_000
+    }
+}
+
+/// The health metrics shared with the collector. The node is identified by an opaque
+/// identifier, and its packets are only reported in orders of magnitude.
+#[derive(Serialize, Clone)]
+pub struct TelemetryReport {
+    node_id: String,
+    version: String,
+    /// Time since the node started, in whole hours
+    uptime_hours: u64,
+    /// Number of Prepare packets received during the last 24 hours, and of the ones which
+    /// were fulfilled and rejected, as ranges such as `100-999`
+    packets: String,
+    fulfilled: String,
+    rejected: String,
+    timestamp: u64,
+}
+
+/// What the node last shared and fetched, returned by `GET /telemetry`
+#[derive(Serialize, Default)]
+struct TelemetryState {
+    last_report: Option<TelemetryReport>,
+    /// When the last report was accepted by the collector, in milliseconds since the UNIX epoch
+    last_published: Option<u64>,
+    last_error: Option<String>,
+    /// The aggregated statistics of the network, as returned by the collector
+    network: Option<serde_json::Value>,
+}
+
+/// Periodically publishes the health metrics of the node and fetches the ones of the network
+#[derive(Clone)]
+pub struct Telemetry {
+    config: TelemetryConfig,
+    node_id: String,
+    version: String,
+    started: Instant,
+    stats: NodeStats,
+    client: Client,
+    state: Arc<RwLock<TelemetryState>>,
+}
+
+impl Telemetry {
+    pub fn new(config: TelemetryConfig, secret_seed: &[u8], stats: NodeStats) -> Self {
+        let key = hmac::Key::new(hmac::HMAC_SHA256, secret_seed);
+        let node_id = hex::encode(&hmac::sign(&key, TELEMETRY_ID_LABEL).as_ref()[..16]);
+        Telemetry {
+            config,
+            node_id,
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            started: Instant::now(),
+            stats,
+            client: Client::new(),
+            state: Arc::new(RwLock::new(TelemetryState::default())),
+        }
+    }
+
+    /// Adds the task which publishes the reports (and fetches the network statistics)
+    /// to the scheduler
+    pub fn schedule(&self, scheduler: &Scheduler) {
+        let telemetry = self.clone();
+        scheduler
+            .task("telemetry", Duration::from_millis(self.config.interval))
+            .spawn(move || {
+                let telemetry = telemetry.clone();
+                async move { telemetry.publish().await }
+            });
+    }
+
+    fn report(&self) -> TelemetryReport {
+        let packets = self.stats.overview().packets;
+        TelemetryReport {
+            node_id: self.node_id.clone(),
+            version: self.version.clone(),
+            uptime_hours: self.started.elapsed().as_secs() / 3600,
+            packets: volume_bucket(packets.prepare),
+            fulfilled: volume_bucket(packets.fulfill),
+            rejected: volume_bucket(packets.reject),
+            timestamp: now_millis(),
+        }
+    }
+
+    async fn publish(&self) -> Result<(), String> {
+        let report = self.report();
+        let result = self
+            .client
+            .post(self.config.collector_url.as_str())
+            .timeout(REQUEST_TIMEOUT)
+            .json(&report)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|err| format!("Error sending the telemetry report: {}", err));
+        {
+            let mut state = self.state.write().unwrap();
+            state.last_report = Some(report);
+            match result {
+                Ok(_) => {
+                    state.last_published = Some(now_millis());
+                    state.last_error = None;
+                }
+                Err(ref err) => state.last_error = Some(err.clone()),
+            }
+        }
+        result?;
+        debug!(target: "interledger-node", "Sent the telemetry report to {}", self.config.collector_url);
+
+        if let Some(ref url) = self.config.network_stats_url {
+            match self.fetch_network_stats(url).await {
+                Ok(network) => self.state.write().unwrap().network = Some(network),
+                Err(err) => {
+                    warn!(target: "interledger-node", "Error fetching the network statistics: {}", err);
+                    self.state.write().unwrap().last_error =
+                        Some(format!("Error fetching the network statistics: {}", err));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn fetch_network_stats(&self, url: &Url) -> Result<serde_json::Value, reqwest::Error> {
+        self.client
+            .get(url.as_str())
+            .timeout(REQUEST_TIMEOUT)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+    }
+}
+
+/// Returns the order of magnitude of the count, such as `100-999`
+fn volume_bucket(count: u64) -> String {
+    if count == 0 {
+        return "0".to_string();
+    }
+    if count >= MAX_VOLUME_BUCKET {
+        return format!("{}+", MAX_VOLUME_BUCKET);
+    }
+    let mut lower = 1;
+    while lower * 10 <= count {
+        lower *= 10;
+    }
+    format!("{}-{}", lower, lower * 10 - 1)
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// `GET /telemetry`, which returns the last report shared by the node and the last
+/// statistics of the network it fetched. Only the administrator can call it.
+pub fn telemetry_api(
+    telemetry: Option<Telemetry>,
+    admin_only: impl Filter<Extract = (), Error = Rejection> + Clone + Send + Sync + 'static,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = Rejection> + Clone {
+    warp::get()
+        .and(warp::path("telemetry"))
+        .and(warp::path::end())
+        .and(admin_only)
+        .and_then(move || {
+            let telemetry = telemetry.clone();
+            async move {
+                let telemetry = telemetry.ok_or_else(|| {
+                    ApiError::not_found().detail("telemetry sharing is not enabled")
+                })?;
+                let state = telemetry.state.read().unwrap();
+                Ok::<_, Rejection>(warp::reply::json(&*state))
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::Matcher;
+    use serde_json::json;
+
+    fn telemetry(collector_path: &str, network_stats_path: Option<&str>) -> Telemetry {
+        let url = |path: &str| Url::parse(&format!("{}{}", mockito::server_url(), path)).unwrap();
+        Telemetry::new(
+            TelemetryConfig {
+                collector_url: url(collector_path),
+                network_stats_url: network_stats_path.map(url),
+                interval: TelemetryConfig::default_interval(),
+            },
+            &[0; 32],
+            NodeStats::default(),
+        )
+    }
+
+    #[test]
+    fn reports_packets_in_orders_of_magnitude() {
+        for &(count, bucket) in &[
+            (0, "0"),
+            (1, "1-9"),
+            (9, "1-9"),
+            (10, "10-99"),
+            (999, "100-999"),
+            (1000, "1000-9999"),
+            (999_999, "100000-999999"),
+            (1_000_000, "1000000+"),
+            (u64::MAX, "1000000+"),
+        ] {
+            assert_eq!(volume_bucket(count), bucket);
+        }
+    }
+
+    #[test]
+    fn derives_an_opaque_node_id_from_the_secret_seed() {
+        let stats = NodeStats::default;
+        let config = || TelemetryConfig {
+            collector_url: Url::parse("https://collector.example").unwrap(),
+            network_stats_url: None,
+            interval: TelemetryConfig::default_interval(),
+        };
+        let node_id = Telemetry::new(config(), &[1; 32], stats()).node_id;
+        assert_eq!(node_id.len(), 32);
+        assert!(!node_id.contains(&hex::encode([1; 16])));
+        // It is the same across restarts, but differs between nodes
+        assert_eq!(Telemetry::new(config(), &[1; 32], stats()).node_id, node_id);
+        assert_ne!(Telemetry::new(config(), &[2; 32], stats()).node_id, node_id);
+    }
+
+    #[test]
+    fn reports_no_identifying_fields() {
+        let report = serde_json::to_value(telemetry("/reports", None).report()).unwrap();
+        let mut fields: Vec<&str> = report
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect();
+        fields.sort_unstable();
+        assert_eq!(
+            fields,
+            vec![
+                "fulfilled",
+                "node_id",
+                "packets",
+                "rejected",
+                "timestamp",
+                "uptime_hours",
+                "version",
+            ]
+        );
+        assert_eq!(report["packets"], "0");
+        assert_eq!(report["uptime_hours"], 0);
+    }
+
+    #[tokio::test]
+    async fn publishes_reports_and_fetches_network_stats() {
+        let telemetry = telemetry("/reports", Some("/network"));
+        let collector = mockito::mock("POST", "/reports")
+            .match_body(Matcher::PartialJson(json!({
+                "node_id": telemetry.node_id,
+                "version": env!("CARGO_PKG_VERSION"),
+                "packets": "0",
+            })))
+            .create();
+        let network = mockito::mock("GET", "/network")
+            .with_header("Content-Type", "application/json")
+            .with_body(r#"{"nodes":"10-99"}"#)
+            .create();
+
+        telemetry.publish().await.unwrap();
+        collector.assert();
+        network.assert();
+        let state = telemetry.state.read().unwrap();
+        assert!(state.last_report.is_some());
+        assert!(state.last_published.is_some());
+        assert_eq!(state.last_error, None);
+        assert_eq!(state.network, Some(json!({ "nodes": "10-99" })));
+    }
+
+    #[tokio::test]
+    async fn records_errors_of_the_collector() {
+        let telemetry = telemetry("/failing-reports", Some("/unused-network"));
+        let collector = mockito::mock("POST", "/failing-reports")
+            .with_status(500)
+            .create();
+        let network = mockito::mock("GET", "/unused-network").expect(0).create();
+
+        assert!(telemetry.publish().await.is_err());
+        collector.assert();
+        // The statistics of the network are not fetched if the report was not accepted
+        network.assert();
+        let state = telemetry.state.read().unwrap();
+        assert!(state.last_report.is_some());
+        assert_eq!(state.last_published, None);
+        assert!(state
+            .last_error
+            .as_ref()
+            .unwrap()
+            .starts_with("Error sending the telemetry report"));
+    }
+}
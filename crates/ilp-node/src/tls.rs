@@ -0,0 +1,247 @@
+#[cfg(feature = "acme")]
+use crate::acme::AcmeConfig;
+use futures::channel::mpsc::{self, UnboundedReceiver};
+use serde::Deserialize;
+use std::{
+    fs::File,
+    future::Future,
+    io::{self, BufReader},
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::{
+    rustls::{
+        internal::pemfile,
+        sign::{self, CertifiedKey},
+        Certificate, ClientHello, NoClientAuth, PrivateKey, ResolvesServerCert, ServerConfig,
+    },
+    server::TlsStream,
+    TlsAcceptor,
+};
+use tracing::{debug, warn};
+
+/// TLS termination of the HTTP API, which also carries the ILP over HTTP, BTP and SPSP
+/// requests. The certificate is either read from the configured files or issued and
+/// renewed automatically with ACME.
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct HttpTlsConfig {
+    /// Path of the PEM file with the certificate chain to serve
+    pub cert: Option<String>,
+    /// Path of the PEM file with the private key (PKCS#8 or RSA) of the certificate
+    pub key: Option<String>,
+    /// Issue and renew the certificate with an ACME certificate authority (such as Let's
+    /// Encrypt) instead. Needs the feature flag "acme" to be enabled
+    #[cfg(feature = "acme")]
+    pub acme: Option<AcmeConfig>,
+}
+
+impl HttpTlsConfig {
+    /// Returns the TLS configuration of the HTTP API and the resolver through which its
+    /// certificate can be replaced. With ACME, the resolver has no certificate yet.
+    pub(crate) fn server_config(&self) -> io::Result<(ServerConfig, CertificateResolver)> {
+        let resolver = CertificateResolver::default();
+        match (self.cert.as_ref(), self.key.as_ref(), self.acme_domains()) {
+            (Some(cert), Some(key), None) => {
+                resolver.set(load_certs(cert)?, &load_private_key(key)?)?
+            }
+            (None, None, Some(0)) => {
+                return Err(invalid_data("No domain to issue the certificate for"))
+            }
+            (None, None, Some(_)) => {}
+            (None, None, None) => {
+                return Err(invalid_data(
+                    "Either the certificate and its key or ACME must be configured",
+                ))
+            }
+            (_, _, Some(_)) => {
+                return Err(invalid_data(
+                    "The certificate and its key cannot be configured together with ACME",
+                ))
+            }
+            _ => {
+                return Err(invalid_data(
+                    "The certificate and its key must be configured together",
+                ))
+            }
+        }
+
+        let mut config = ServerConfig::new(NoClientAuth::new());
+        config.cert_resolver = Arc::new(resolver.clone());
+        config.set_protocols(&[b"http/1.1".to_vec()]);
+        Ok((config, resolver))
+    }
+
+    #[cfg(feature = "acme")]
+    fn acme_domains(&self) -> Option<usize> {
+        self.acme.as_ref().map(|acme| acme.domains.len())
+    }
+
+    #[cfg(not(feature = "acme"))]
+    fn acme_domains(&self) -> Option<usize> {
+        None
+    }
+}
+
+/// Serves the certificate which was set last, so that a renewed certificate is
+/// used for the new connections without restarting the node
+#[derive(Clone, Default)]
+pub(crate) struct CertificateResolver(Arc<RwLock<Option<CertifiedKey>>>);
+
+impl CertificateResolver {
+    pub(crate) fn set(&self, certs: Vec<Certificate>, key: &PrivateKey) -> io::Result<()> {
+        let key = sign::any_supported_type(key)
+            .map_err(|_| invalid_data("Unsupported type of private key"))?;
+        *self.0.write().unwrap() = Some(CertifiedKey::new(certs, Arc::new(key)));
+        Ok(())
+    }
+}
+
+impl ResolvesServerCert for CertificateResolver {
+    fn resolve(&self, _client_hello: ClientHello) -> Option<CertifiedKey> {
+        self.0.read().unwrap().clone()
+    }
+}
+
+/// Reads the certificate chain from a PEM file
+pub(crate) fn load_certs(path: &str) -> io::Result<Vec<Certificate>> {
+    let certs = pemfile::certs(&mut open(path)?)
+        .map_err(|_| invalid_data(format!("Invalid certificate in {}", path)))?;
+    if certs.is_empty() {
+        return Err(invalid_data(format!("No certificate found in {}", path)));
+    }
+    Ok(certs)
+}
+
+/// Reads the first private key (PKCS#8 or RSA) from a PEM file
+pub(crate) fn load_private_key(path: &str) -> io::Result<PrivateKey> {
+    let invalid_key = || invalid_data(format!("Invalid private key in {}", path));
+    let mut keys = pemfile::pkcs8_private_keys(&mut open(path)?).map_err(|_| invalid_key())?;
+    if keys.is_empty() {
+        keys = pemfile::rsa_private_keys(&mut open(path)?).map_err(|_| invalid_key())?;
+    }
+    keys.into_iter()
+        .next()
+        .ok_or_else(|| invalid_data(format!("No private key found in {}", path)))
+}
+
+fn open(path: &str) -> io::Result<BufReader<File>> {
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|err| io::Error::new(err.kind(), format!("Error opening {}: {}", path, err)))
+}
+
+fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+/// Accepts the connections of the listener and returns the stream of the connections
+/// which completed the TLS handshake (with a client certificate, if the configuration
+/// requires it), so that they can be served with `warp::serve(..).serve_incoming(..)`.
+/// The handshakes run concurrently, so a slow client does not hold back the others.
+/// The listener is closed once the shutdown signal resolves.
+pub(crate) fn tls_incoming(
+    mut listener: TcpListener,
+    config: ServerConfig,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> UnboundedReceiver<io::Result<TlsStream<TcpStream>>> {
+    let acceptor = TlsAcceptor::from(Arc::new(config));
+    let (sender, receiver) = mpsc::unbounded();
+    tokio::spawn(async move {
+        let mut shutdown = Box::pin(shutdown);
+        while !sender.is_closed() {
+            let accepted = tokio::select! {
+                accepted = listener.accept() => accepted,
+                _ = &mut shutdown => break,
+            };
+            let (socket, peer) = match accepted {
+                Ok(connection) => connection,
+                Err(err) => {
+                    warn!(target: "interledger-node", "Error accepting a TLS connection: {}", err);
+                    // Don't spin if the node ran out of file descriptors
+                    tokio::time::delay_for(Duration::from_millis(100)).await;
+                    continue;
+                }
+            };
+            let acceptor = acceptor.clone();
+            let sender = sender.clone();
+            tokio::spawn(async move {
+                match acceptor.accept(socket).await {
+                    Ok(stream) => {
+                        let _ = sender.unbounded_send(Ok(stream));
+                    }
+                    Err(err) => {
+                        debug!(target: "interledger-node", "TLS handshake with {} failed: {}", peer, err)
+                    }
+                }
+            });
+        }
+    });
+    receiver
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use interledger::settlement::core::{SettlementClient, SettlementTlsConfig};
+    use url::Url;
+    use uuid::Uuid;
+    use warp::Filter;
+
+    /// The certificates of the node and of its settlement engine, both signed by the same CA
+    fn settlement_tls_config(name: &str) -> SettlementTlsConfig {
+        let fixture = |file: &str| {
+            format!(
+                "{}/tests/fixtures/settlement-tls/{}",
+                env!("CARGO_MANIFEST_DIR"),
+                file
+            )
+        };
+        SettlementTlsConfig {
+            ca_cert: fixture("ca.pem"),
+            cert: fixture(&format!("{}.pem", name)),
+            key: fixture(&format!("{}-key.pem", name)),
+        }
+    }
+
+    /// Serves an engine which accepts the account creation requests over mutual TLS
+    async fn engine_url() -> Url {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let config = settlement_tls_config("engine").server_config().unwrap();
+        let incoming = tls_incoming(listener, config, futures::future::pending());
+        let accounts = warp::post()
+            .and(warp::path("accounts"))
+            .map(|| warp::reply::json(&serde_json::json!({})));
+        tokio::spawn(warp::serve(accounts).serve_incoming(incoming));
+        Url::parse(&format!("https://localhost:{}", port)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn settlement_client_authenticates_with_the_node_certificate() {
+        let url = engine_url().await;
+        let client = SettlementClient::with_tls(&settlement_tls_config("node")).unwrap();
+        let response = client
+            .create_engine_account(Uuid::new_v4(), url)
+            .await
+            .unwrap();
+        assert!(response.status().is_success());
+    }
+
+    #[tokio::test]
+    async fn engine_rejects_clients_without_certificate() {
+        let url = engine_url().await;
+        // The client trusts the engine, but does not authenticate itself
+        let mut config = tokio_rustls::rustls::ClientConfig::new();
+        config
+            .root_store
+            .add_pem_file(&mut open(&settlement_tls_config("node").ca_cert).unwrap())
+            .unwrap();
+        let client = reqwest::Client::builder()
+            .use_preconfigured_tls(config)
+            .build()
+            .unwrap();
+        let result = client.post(url.join("accounts").unwrap()).send().await;
+        assert!(result.is_err());
+    }
+}
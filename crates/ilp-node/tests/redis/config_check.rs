@@ -0,0 +1,188 @@
+use crate::redis_helpers::*;
+use crate::test_helpers::*;
+use config::{Config, Value};
+use ilp_node::{
+    config_check::{config_keys, defaulted_keys, parse_config, unknown_keys, ConfigIssue},
+    InterledgerNode,
+};
+use redis_crate::AsyncCommands;
+use serde_json::{self, json};
+
+fn minimal_config() -> Config {
+    let mut config = Config::new();
+    config
+        .set("admin_auth_token", "admin")
+        .unwrap()
+        .set("secret_seed", random_secret())
+        .unwrap();
+    config
+}
+
+#[test]
+fn points_at_the_offending_key() {
+    let mut config = minimal_config();
+    config.set("exchange_rate.spread", "a lot").unwrap();
+    let issue = parse_config(config).err().unwrap();
+    assert_eq!(issue.key, "exchange_rate.spread");
+
+    let mut config = Config::new();
+    config.set("admin_auth_token", "admin").unwrap();
+    let issue = parse_config(config).err().unwrap();
+    assert_eq!(issue.key, "secret_seed");
+    assert_eq!(issue.message, "missing field `secret_seed`");
+}
+
+#[test]
+fn suggests_known_keys() {
+    let mut config = minimal_config();
+    config
+        .set("route_broadcast_intreval", 1000)
+        .unwrap()
+        .set("address", "example.node")
+        .unwrap()
+        .set("unrelated_setting", Value::new(None, true))
+        .unwrap();
+    assert_eq!(
+        unknown_keys(&config),
+        vec![
+            ConfigIssue::new(
+                "route_broadcast_intreval",
+                "unknown key, did you mean `route_broadcast_interval`?"
+            ),
+            ConfigIssue::new("unrelated_setting", "unknown key, it is ignored"),
+        ]
+    );
+
+    let defaults = defaulted_keys(&config);
+    assert!(defaults.contains(&"database_url"));
+    assert!(!defaults.contains(&"secret_seed"));
+}
+
+#[test]
+fn reports_invalid_settings() {
+    let node: InterledgerNode = serde_json::from_value(json!({
+        "admin_auth_token": "admin",
+        "secret_seed": random_secret(),
+        "database_url": "postgres://localhost",
+        "grpc_bind_address": "127.0.0.1:7771",
+        "route_broadcast_interval": 0,
+        "settlement_replay_window": 0,
+        "idempotency_ttl": 0,
+        "uncredited_settlement_ttl": 0,
+        "exchange_rate": {
+            "spread": 1.5,
+        },
+        "expiry": {
+            "min_window": 30000,
+        },
+        "balance_notifications": {
+            "debounce": 0,
+        },
+    }))
+    .unwrap();
+    let keys: Vec<String> = node.validate().into_iter().map(|issue| issue.key).collect();
+    assert_eq!(
+        keys,
+        vec![
+            "database_url",
+            "grpc_bind_address",
+            "settlement_engine_secrets",
+            "route_broadcast_interval",
+            "idempotency_ttl",
+            "uncredited_settlement_ttl",
+            "settlement_replay_window",
+            "balance_notifications.debounce",
+            "exchange_rate.spread",
+            "expiry.min_window"
+        ]
+    );
+}
+
+#[test]
+fn documents_every_key() {
+    let docs = include_str!("../../../../docs/configuration.md");
+    for key in config_keys() {
+        assert!(
+            docs.contains(&format!("\n- {}\n", key)) || docs.contains(&format!("\n- [{}]", key)),
+            "docs/configuration.md does not document `{}`",
+            key
+        );
+    }
+}
+
+#[tokio::test]
+async fn checks_reachability() {
+    let context = TestContext::new();
+    let database_url = connection_info_to_string(context.get_client_connection_info());
+    let mut connection = context.async_connection().await.unwrap();
+    let engine_port = get_open_port(None);
+    let _: () = connection
+        .sadd("accounts", "00000000-0000-0000-0000-000000000000")
+        .await
+        .unwrap();
+    let _: () = connection
+        .hset_multiple(
+            "accounts:00000000-0000-0000-0000-000000000000",
+            &[
+                ("username", "alice".to_string()),
+                (
+                    "settlement_engine_url",
+                    format!("http://127.0.0.1:{}", engine_port),
+                ),
+            ],
+        )
+        .await
+        .unwrap();
+
+    let node: InterledgerNode = serde_json::from_value(json!({
+        "admin_auth_token": "admin",
+        "secret_seed": random_secret(),
+        "database_url": database_url,
+    }))
+    .unwrap();
+    let issues = node.check_reachability().await;
+    assert_eq!(issues.len(), 1);
+    assert_eq!(issues[0].key, "accounts.alice.settlement_engine_url");
+
+    let node: InterledgerNode = serde_json::from_value(json!({
+        "admin_auth_token": "admin",
+        "secret_seed": random_secret(),
+        "database_url": format!("redis://127.0.0.1:{}", get_open_port(None)),
+    }))
+    .unwrap();
+    let issues = node.check_reachability().await;
+    assert_eq!(issues.len(), 1);
+    assert_eq!(issues[0].key, "database_url");
+}
+
+#[test]
+fn reports_invalid_http_tls() {
+    let node: InterledgerNode = serde_json::from_value(json!({
+        "admin_auth_token": "admin",
+        "secret_seed": random_secret(),
+        "http_tls": {
+            "cert": "/nonexistent/node.pem",
+        },
+    }))
+    .unwrap();
+    let issues = node.validate();
+    assert_eq!(issues.len(), 1);
+    assert_eq!(issues[0].key, "http_tls");
+    assert_eq!(
+        issues[0].message,
+        "The certificate and its key must be configured together"
+    );
+
+    let node: InterledgerNode = serde_json::from_value(json!({
+        "admin_auth_token": "admin",
+        "secret_seed": random_secret(),
+        "http_tls": {
+            "cert": "/nonexistent/node.pem",
+            "key": "/nonexistent/node-key.pem",
+        },
+    }))
+    .unwrap();
+    let issues = node.validate();
+    assert_eq!(issues.len(), 1);
+    assert!(issues[0].message.contains("/nonexistent/node.pem"));
+}
@@ -0,0 +1,54 @@
+use crate::redis_helpers::*;
+use ilp_node::InterledgerNode;
+use interledger::packet::Address;
+use reqwest::Client;
+use ring::rand::{SecureRandom, SystemRandom};
+use std::{
+    net::SocketAddr,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+#[tokio::test]
+async fn runs_an_embedded_node() {
+    let context = TestContext::new();
+    let http_port = get_open_port(None);
+    let mut secret_seed = [0; 32];
+    SystemRandom::new().fill(&mut secret_seed).unwrap();
+    let mut node = InterledgerNode::new(secret_seed, "admin");
+    node.database_url = connection_info_to_string(context.get_client_connection_info());
+    node.http_bind_address = SocketAddr::from(([127, 0, 0, 1], http_port));
+    node.settlement_api_bind_address = SocketAddr::from(([127, 0, 0, 1], get_open_port(None)));
+    node.ilp_address = Some(Address::from_str("example.embedded").unwrap());
+
+    let started = Arc::new(Mutex::new(None));
+    let stopped = Arc::new(AtomicBool::new(false));
+    let started_clone = started.clone();
+    let stopped_clone = stopped.clone();
+    node.hooks
+        .on_started(move |ilp_address| {
+            *started_clone.lock().unwrap() = Some(ilp_address.to_string());
+        })
+        .on_stopped(move || {
+            let stopped = stopped_clone.clone();
+            async move { stopped.store(true, Ordering::SeqCst) }
+        });
+    let shutdown = node.run().await.unwrap();
+    assert_eq!(started.lock().unwrap().as_deref(), Some("example.embedded"));
+
+    let accounts = Client::new()
+        .get(&format!("http://localhost:{}/accounts", http_port))
+        .bearer_auth("admin")
+        .send()
+        .await
+        .unwrap();
+    assert!(accounts.status().is_success());
+
+    assert!(!stopped.load(Ordering::SeqCst));
+    assert!(shutdown.shut_down(Duration::from_secs(5)).await);
+    assert!(stopped.load(Ordering::SeqCst));
+}
@@ -0,0 +1,82 @@
+use crate::test_helpers::random_secret;
+use config::{Config, File, FileFormat};
+use ilp_node::{
+    config_check::parse_config,
+    env_config::{config_from_env, env_vars},
+};
+
+fn vars(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+    vars.iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect()
+}
+
+#[test]
+fn sets_nested_and_structured_settings() {
+    let secret = random_secret();
+    let config = config_from_env(vars(&[
+        ("ILP_SECRET_SEED", &secret),
+        ("ILP_ADMIN_AUTH_TOKEN", "admin"),
+        ("ILP_ADDRESS", "example.node"),
+        ("ILP_EXCHANGE_RATE__SPREAD", "0.01"),
+        ("ILP_HTTP_BIND_ADDRESS", "0.0.0.0:7770"),
+        (
+            "ILP_READ_REPLICA_URLS",
+            r#"["redis://replica1:6379", "redis://replica2:6379"]"#,
+        ),
+        ("HOME", "/root"),
+    ]))
+    .unwrap();
+    let node = parse_config(config).unwrap_or_else(|issue| panic!("{}", issue));
+    assert_eq!(node.ilp_address.unwrap().to_string(), "example.node");
+    assert_eq!(node.admin_auth_token, "admin");
+    assert!((node.exchange_rate.spread - 0.01).abs() < f64::EPSILON);
+    assert_eq!(node.http_bind_address.to_string(), "0.0.0.0:7770");
+    assert_eq!(
+        node.read_replica_urls,
+        vec!["redis://replica1:6379", "redis://replica2:6379"]
+    );
+}
+
+#[test]
+fn rejects_invalid_json() {
+    let error = config_from_env(vars(&[("ILP_READ_REPLICA_URLS", "[\"redis://")]))
+        .err()
+        .unwrap();
+    assert_eq!(error.to_string(), "ILP_READ_REPLICA_URLS is not valid JSON");
+}
+
+#[test]
+fn env_vars_round_trip() {
+    let mut config = Config::new();
+    config
+        .merge(File::from_str(
+            "admin_auth_token: admin\nexchange_rate:\n  spread: 0.01\n  provider: CoinCap\nread_replica_urls: [\"redis://replica1:6379\"]\nlisteners:\n  btp:\n    bind_address: 127.0.0.1:7772\n",
+            FileFormat::Yaml,
+        ))
+        .unwrap();
+    let vars = env_vars(&config).unwrap();
+    assert_eq!(
+        vars,
+        vec![
+            ("ILP_ADMIN_AUTH_TOKEN".to_string(), "admin".to_string()),
+            (
+                "ILP_EXCHANGE_RATE__PROVIDER".to_string(),
+                "CoinCap".to_string()
+            ),
+            ("ILP_EXCHANGE_RATE__SPREAD".to_string(), "0.01".to_string()),
+            (
+                "ILP_LISTENERS__BTP__BIND_ADDRESS".to_string(),
+                "127.0.0.1:7772".to_string()
+            ),
+            (
+                "ILP_READ_REPLICA_URLS".to_string(),
+                r#"["redis://replica1:6379"]"#.to_string()
+            ),
+        ]
+    );
+    assert_eq!(
+        env_vars(&config_from_env(vars.clone()).unwrap()).unwrap(),
+        vars
+    );
+}
@@ -0,0 +1,221 @@
+use crate::redis_helpers::*;
+use crate::test_helpers::*;
+use ilp_node::{InterledgerNode, Shutdown};
+use reqwest::{Client, StatusCode};
+use serde_json::{self, json};
+use std::time::Duration;
+
+#[tokio::test]
+async fn serves_the_subsystems_apart() {
+    let context = TestContext::new();
+    let database_url = connection_info_to_string(context.get_client_connection_info());
+    let http_port = get_open_port(None);
+    let api_port = get_open_port(None);
+    let node: InterledgerNode = serde_json::from_value(json!({
+        "ilp_address": "example.node",
+        "admin_auth_token": "admin",
+        "database_url": database_url,
+        "http_bind_address": format!("127.0.0.1:{}", http_port),
+        "settlement_api_bind_address": format!("127.0.0.1:{}", get_open_port(None)),
+        "secret_seed": random_secret(),
+        "listeners": {
+            "api": {
+                "bind_address": format!("127.0.0.1:{}", api_port),
+            },
+        },
+    }))
+    .unwrap();
+    let shutdown = Shutdown::default();
+    node.serve_with_shutdown(None, shutdown.clone())
+        .await
+        .unwrap();
+
+    let client = Client::new();
+    let get_accounts = |port: u16| {
+        client
+            .get(&format!("http://localhost:{}/accounts", port))
+            .bearer_auth("admin")
+            .send()
+    };
+    // The API is only served on its own listener
+    assert_eq!(
+        get_accounts(api_port).await.unwrap().status(),
+        StatusCode::OK
+    );
+    assert!(get_accounts(http_port)
+        .await
+        .unwrap()
+        .status()
+        .is_client_error());
+
+    // While the other subsystems are still served on the http_bind_address
+    create_account_on_node(
+        api_port,
+        json!({
+            "username": "alice",
+            "asset_code": "XYZ",
+            "asset_scale": 9,
+        }),
+        "admin",
+    )
+    .await
+    .unwrap();
+    let spsp = client
+        .get(&format!(
+            "http://localhost:{}/accounts/alice/spsp",
+            http_port
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(spsp.status(), StatusCode::OK);
+    let spsp = client
+        .get(&format!(
+            "http://localhost:{}/accounts/alice/spsp",
+            api_port
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert!(spsp.status().is_client_error());
+
+    assert!(shutdown.shut_down(Duration::from_secs(5)).await);
+}
+
+#[tokio::test]
+async fn limits_the_requests_to_the_spsp_listener() {
+    let context = TestContext::new();
+    let database_url = connection_info_to_string(context.get_client_connection_info());
+    let http_port = get_open_port(None);
+    let spsp_port = get_open_port(None);
+    let node: InterledgerNode = serde_json::from_value(json!({
+        "ilp_address": "example.node",
+        "admin_auth_token": "admin",
+        "database_url": database_url,
+        "http_bind_address": format!("127.0.0.1:{}", http_port),
+        "settlement_api_bind_address": format!("127.0.0.1:{}", get_open_port(None)),
+        "secret_seed": random_secret(),
+        "listeners": {
+            "spsp": {
+                "bind_address": format!("127.0.0.1:{}", spsp_port),
+                "requests_per_minute": 2,
+            },
+        },
+    }))
+    .unwrap();
+    let shutdown = Shutdown::default();
+    node.serve_with_shutdown(None, shutdown.clone())
+        .await
+        .unwrap();
+    create_account_on_node(
+        http_port,
+        json!({
+            "username": "alice",
+            "asset_code": "XYZ",
+            "asset_scale": 9,
+        }),
+        "admin",
+    )
+    .await
+    .unwrap();
+
+    let client = Client::new();
+    let get = |port: u16, path: &str| {
+        client
+            .get(&format!("http://localhost:{}{}", port, path))
+            .bearer_auth("admin")
+            .send()
+    };
+    // The admin API is not served on the public listener
+    assert!(get(spsp_port, "/accounts")
+        .await
+        .unwrap()
+        .status()
+        .is_client_error());
+    assert_eq!(
+        get(spsp_port, "/accounts/alice/spsp")
+            .await
+            .unwrap()
+            .status(),
+        StatusCode::OK
+    );
+    // The client sent its 2 requests of the minute
+    let limited = get(spsp_port, "/accounts/alice/spsp").await.unwrap();
+    assert_eq!(limited.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert!(limited.headers().contains_key("retry-after"));
+    // While the other listeners are not limited
+    for _ in 0..3 {
+        assert_eq!(
+            get(http_port, "/accounts").await.unwrap().status(),
+            StatusCode::OK
+        );
+    }
+
+    assert!(shutdown.shut_down(Duration::from_secs(5)).await);
+}
+
+#[tokio::test]
+async fn does_not_serve_or_advertise_the_disabled_subsystems() {
+    let context = TestContext::new();
+    let database_url = connection_info_to_string(context.get_client_connection_info());
+    let http_port = get_open_port(None);
+    let settlement_port = get_open_port(None);
+    let node: InterledgerNode = serde_json::from_value(json!({
+        "ilp_address": "example.node",
+        "admin_auth_token": "admin",
+        "database_url": database_url,
+        "http_bind_address": format!("127.0.0.1:{}", http_port),
+        "settlement_api_bind_address": format!("127.0.0.1:{}", settlement_port),
+        "secret_seed": random_secret(),
+        "stream_receipts": true,
+        "listeners": {
+            "disabled": ["btp", "settlement"],
+        },
+    }))
+    .unwrap();
+    assert_eq!(node.node_features(), vec!["http", "spsp", "receipts"]);
+    let shutdown = Shutdown::default();
+    node.serve_with_shutdown(None, shutdown.clone())
+        .await
+        .unwrap();
+
+    let client = Client::new();
+    let status: serde_json::Value = client
+        .get(&format!("http://localhost:{}/", http_port))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(status["features"], json!(["http", "spsp", "receipts"]));
+
+    create_account_on_node(
+        http_port,
+        json!({
+            "username": "alice",
+            "asset_code": "XYZ",
+            "asset_scale": 9,
+            "ilp_over_btp_incoming_token": "token",
+        }),
+        "admin",
+    )
+    .await
+    .unwrap();
+    let btp = client
+        .get(&format!(
+            "http://localhost:{}/accounts/alice/ilp/btp",
+            http_port
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert!(btp.status().is_client_error());
+    assert!(client
+        .get(&format!("http://localhost:{}/", settlement_port))
+        .send()
+        .await
+        .is_err());
+
+    assert!(shutdown.shut_down(Duration::from_secs(5)).await);
+}
@@ -0,0 +1,67 @@
+use crate::redis_helpers::*;
+use crate::test_helpers::*;
+use ilp_node::InterledgerNode;
+use serde_json::{self, json};
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+#[tokio::test]
+async fn calls_the_custom_middlewares() {
+    let context = TestContext::new();
+    let node_http = get_open_port(None);
+    let mut node: InterledgerNode = serde_json::from_value(json!({
+        "ilp_address": "example.node",
+        "admin_auth_token": "admin",
+        "database_url": connection_info_to_string(context.get_client_connection_info()),
+        "http_bind_address": format!("127.0.0.1:{}", node_http),
+        "settlement_api_bind_address": format!("127.0.0.1:{}", get_open_port(None)),
+        "secret_seed": random_secret(),
+    }))
+    .unwrap();
+    let incoming = Arc::new(AtomicUsize::new(0));
+    let outgoing = Arc::new(AtomicUsize::new(0));
+    let incoming_clone = incoming.clone();
+    let outgoing_clone = outgoing.clone();
+    node.middlewares
+        .incoming(move |request, mut next| {
+            incoming_clone.fetch_add(1, Ordering::SeqCst);
+            async move { next.handle_request(request).await }
+        })
+        .outgoing(move |request, mut next| {
+            outgoing_clone.fetch_add(1, Ordering::SeqCst);
+            async move { next.send_request(request).await }
+        });
+    node.serve(None).await.unwrap();
+
+    for username in &["alice", "bob"] {
+        create_account_on_node(
+            node_http,
+            json!({
+                "username": username,
+                "asset_code": "XYZ",
+                "asset_scale": 9,
+                "ilp_over_http_incoming_token": "default account holder",
+            }),
+            "admin",
+        )
+        .await
+        .unwrap();
+    }
+
+    let delivery = send_money_to_username(
+        node_http,
+        node_http,
+        1000,
+        "bob",
+        "alice",
+        "default account holder",
+    )
+    .await
+    .unwrap();
+    assert_eq!(delivery.delivered_amount, 1000);
+    // The packets of the payment were passed through both middlewares
+    assert!(incoming.load(Ordering::SeqCst) > 0);
+    assert!(outgoing.load(Ordering::SeqCst) > 0);
+}
@@ -0,0 +1,178 @@
+use crate::redis_helpers::*;
+use redis_crate::{self as redis, Connection};
+use std::collections::BTreeMap;
+use std::process::{Command, Output};
+
+/// The fixture of a node's store, with keys of every type and one which expires
+const FIXTURE: &str = include_str!("../fixtures/migrate/store.redis");
+
+fn load_fixture(connection: &mut Connection) {
+    for line in FIXTURE.lines() {
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut args = line.split_whitespace();
+        let mut command = redis::cmd(args.next().unwrap());
+        for arg in args {
+            command.arg(arg);
+        }
+        command.execute(connection);
+    }
+}
+
+/// The value of every key of the store, read with the commands of its type so that it does
+/// not depend on how the server encodes it
+fn contents(connection: &mut Connection) -> BTreeMap<String, Vec<String>> {
+    let keys: Vec<String> = redis::cmd("KEYS").arg("*").query(connection).unwrap();
+    keys.into_iter()
+        .map(|key| {
+            let kind: String = redis::cmd("TYPE").arg(&key).query(connection).unwrap();
+            let mut command = match kind.as_str() {
+                "string" => redis::cmd("GET"),
+                "list" => redis::cmd("LRANGE"),
+                "set" => redis::cmd("SMEMBERS"),
+                "hash" => redis::cmd("HGETALL"),
+                "zset" => redis::cmd("ZRANGE"),
+                kind => panic!("Unexpected key type {}", kind),
+            };
+            command.arg(&key);
+            match kind.as_str() {
+                "list" => {
+                    command.arg(0).arg(-1);
+                }
+                "zset" => {
+                    command.arg(0).arg(-1).arg("WITHSCORES");
+                }
+                _ => {}
+            }
+            let mut value: Vec<String> = match kind.as_str() {
+                "string" => vec![command.query(connection).unwrap()],
+                _ => command.query(connection).unwrap(),
+            };
+            // The members of sets and the fields of hashes are not returned in any order
+            if kind == "set" {
+                value.sort();
+            } else if kind == "hash" {
+                let mut fields: Vec<Vec<String>> = value.chunks(2).map(|f| f.to_vec()).collect();
+                fields.sort();
+                value = fields.concat();
+            }
+            value.insert(0, kind);
+            (key, value)
+        })
+        .collect()
+}
+
+fn url(context: &TestContext) -> String {
+    connection_info_to_string(context.get_client_connection_info())
+}
+
+fn migrate_store(from: &str, to: &str, extra: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_ilp-node"))
+        .args(["migrate-store", "--from", from, "--to", to])
+        .args(extra)
+        .output()
+        .unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    assert!(
+        output.status.success(),
+        "migrate-store failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+#[test]
+fn copies_the_fixture_store() {
+    let source = TestContext::new();
+    let target = TestContext::new();
+    load_fixture(&mut source.connection());
+    let expected = contents(&mut source.connection());
+
+    // A small batch size, so that the keys are copied in several round trips
+    let output = migrate_store(&url(&source), &url(&target), &["--batch_size", "4"]);
+    let stdout = stdout(&output);
+    assert!(stdout.contains(&format!(
+        "Copied {} keys to {} (deleted 0 keys",
+        expected.len(),
+        url(&target)
+    )));
+    assert!(stdout.contains(&format!(
+        "Checked the {} keys of {}, which has 2 accounts, 2 routes and 3 exchange rates",
+        expected.len(),
+        url(&target)
+    )));
+
+    let mut connection = target.connection();
+    assert_eq!(contents(&mut connection), expected);
+    let ttl: i64 = redis::cmd("PTTL")
+        .arg("idempotency-key:b8a4a0c1")
+        .query(&mut connection)
+        .unwrap();
+    assert!(ttl > 0 && ttl <= 3_600_000);
+    let persistent: i64 = redis::cmd("PTTL")
+        .arg("accounts:6f9a2c4e-1b7d-4f0a-9a53-2d8c1e0b7a11")
+        .query(&mut connection)
+        .unwrap();
+    assert_eq!(persistent, -1);
+}
+
+#[test]
+fn re_running_the_migration_is_idempotent() {
+    let source = TestContext::new();
+    let target = TestContext::new();
+    load_fixture(&mut source.connection());
+    let expected = contents(&mut source.connection());
+    stdout(&migrate_store(&url(&source), &url(&target), &[]));
+
+    // The target is not empty anymore
+    let output = migrate_store(&url(&source), &url(&target), &[]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("is not empty"));
+    assert_eq!(contents(&mut target.connection()), expected);
+
+    // Nothing differs, so nothing is written again
+    let stdout = stdout(&migrate_store(
+        &url(&source),
+        &url(&target),
+        &["--overwrite"],
+    ));
+    assert!(stdout.contains(&format!(
+        "Copied 0 keys to {} (deleted 0 keys",
+        url(&target)
+    )));
+    assert_eq!(contents(&mut target.connection()), expected);
+}
+
+#[test]
+fn overwriting_replaces_the_changed_and_stale_keys() {
+    let source = TestContext::new();
+    let target = TestContext::new();
+    load_fixture(&mut source.connection());
+    let expected = contents(&mut source.connection());
+    stdout(&migrate_store(&url(&source), &url(&target), &[]));
+
+    let mut connection = target.connection();
+    redis::cmd("HSET")
+        .arg("rates:current")
+        .arg("USD")
+        .arg("0.5")
+        .execute(&mut connection);
+    redis::cmd("SET")
+        .arg("stale")
+        .arg("value")
+        .execute(&mut connection);
+
+    let stdout = stdout(&migrate_store(
+        &url(&source),
+        &url(&target),
+        &["--overwrite"],
+    ));
+    assert!(stdout.contains(&format!(
+        "Copied 1 keys to {} (deleted 1 keys",
+        url(&target)
+    )));
+    assert_eq!(contents(&mut connection), expected);
+}
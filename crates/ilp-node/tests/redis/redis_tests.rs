@@ -1,7 +1,15 @@
 #![type_length_limit = "10000000"]
+#![recursion_limit = "256"]
 mod btp;
+mod config_check;
+mod embedding;
+mod env_config;
 mod exchange_rates;
+mod listeners;
+mod middleware;
+mod migrate;
 mod payments_incoming;
+mod shutdown;
 mod three_nodes;
 
 // Only run prometheus tests if the monitoring feature is turned on
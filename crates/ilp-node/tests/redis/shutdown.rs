@@ -0,0 +1,72 @@
+use crate::redis_helpers::*;
+use crate::test_helpers::*;
+use ilp_node::{InterledgerNode, Shutdown};
+use reqwest::Client;
+use serde_json::{self, json};
+use std::time::Duration;
+
+fn node_config(database_url: &str, http_port: u16, settlement_port: u16) -> InterledgerNode {
+    serde_json::from_value(json!({
+        "ilp_address": "example.node",
+        "admin_auth_token": "admin",
+        "database_url": database_url,
+        "http_bind_address": format!("127.0.0.1:{}", http_port),
+        "settlement_api_bind_address": format!("127.0.0.1:{}", settlement_port),
+        "secret_seed": random_secret(),
+        "route_broadcast_interval": 200,
+        "shutdown": {
+            "reuse_port": true,
+        },
+    }))
+    .unwrap()
+}
+
+#[tokio::test]
+async fn stops_accepting_connections() {
+    let context = TestContext::new();
+    let database_url = connection_info_to_string(context.get_client_connection_info());
+    let http_port = get_open_port(None);
+    let shutdown = Shutdown::default();
+    node_config(&database_url, http_port, get_open_port(None))
+        .serve_with_shutdown(None, shutdown.clone())
+        .await
+        .unwrap();
+
+    let client = Client::new();
+    let url = format!("http://localhost:{}/", http_port);
+    assert!(client.get(&url).send().await.is_ok());
+
+    assert!(shutdown.shut_down(Duration::from_secs(5)).await);
+    assert!(client.get(&url).send().await.is_err());
+}
+
+#[tokio::test]
+async fn restarts_on_the_same_addresses() {
+    let context = TestContext::new();
+    let database_url = connection_info_to_string(context.get_client_connection_info());
+    let http_port = get_open_port(None);
+    let settlement_port = get_open_port(None);
+
+    let old_node = Shutdown::default();
+    node_config(&database_url, http_port, settlement_port)
+        .serve_with_shutdown(None, old_node.clone())
+        .await
+        .unwrap();
+    // The new node listens on the same addresses while the old one is still running
+    let new_node = Shutdown::default();
+    node_config(&database_url, http_port, settlement_port)
+        .serve_with_shutdown(None, new_node.clone())
+        .await
+        .unwrap();
+
+    assert!(old_node.shut_down(Duration::from_secs(5)).await);
+    let client = Client::new();
+    for _ in 0..10 {
+        client
+            .get(&format!("http://localhost:{}/", http_port))
+            .send()
+            .await
+            .unwrap();
+    }
+    assert!(new_node.shut_down(Duration::from_secs(5)).await);
+}
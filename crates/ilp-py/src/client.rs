@@ -0,0 +1,180 @@
+use reqwest::blocking::{Client, RequestBuilder};
+use reqwest::{header::CONTENT_TYPE, Method};
+use std::net::TcpStream;
+use tungstenite::{client::AutoStream, connect, handshake::client::Request, WebSocket};
+use url::Url;
+
+#[allow(clippy::enum_variant_names)]
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Error sending HTTP request: {0}")]
+    SendErr(#[from] reqwest::Error),
+    #[error("Node responded with status {status}: {body}")]
+    StatusErr { status: u16, body: String },
+    #[error("Invalid protocol in URL: {0}")]
+    ProtocolErr(String),
+    #[error("Error altering URL scheme")]
+    SchemeErr(()),
+    #[error("Error parsing URL: {0}")]
+    UrlErr(#[from] url::ParseError),
+    #[error("WebSocket error: {0}")]
+    WebsocketErr(#[from] tungstenite::error::Error),
+    #[error("HTTP error: {0}")]
+    HttpErr(#[from] http::Error),
+}
+
+/// The body of a request to the node
+pub enum Body {
+    Empty,
+    Json(String),
+    Text(String),
+}
+
+/// A client of a node's HTTP API, which sends the requests of the CLI's commands
+/// with the token of an admin or an account
+#[derive(Clone, Debug)]
+pub struct NodeClient {
+    client: Client,
+    url: String,
+    auth_token: Option<String>,
+}
+
+impl NodeClient {
+    pub fn new(url: &str, auth_token: Option<String>) -> Self {
+        NodeClient {
+            client: Client::new(),
+            url: url.trim_end_matches('/').to_string(),
+            auth_token,
+        }
+    }
+
+    /// Sends the request and returns the body of its successful response
+    pub fn request(&self, method: Method, path: &str, body: Body) -> Result<String, Error> {
+        let mut request = self.authorize(
+            self.client
+                .request(method, &format!("{}{}", self.url, path)),
+        );
+        request = match body {
+            Body::Empty => request,
+            Body::Json(json) => request.header(CONTENT_TYPE, "application/json").body(json),
+            Body::Text(text) => request.body(text),
+        };
+        let response = request.send()?;
+        let status = response.status();
+        let body = response.text()?;
+        if status.is_success() {
+            Ok(body)
+        } else {
+            Err(Error::StatusErr {
+                status: status.as_u16(),
+                body,
+            })
+        }
+    }
+
+    /// Opens the WebSocket of the path, on which the node sends its notifications
+    pub fn subscribe(&self, path: &str) -> Result<WebSocket<AutoStream>, Error> {
+        let mut url = Url::parse(&format!("{}{}", self.url, path))?;
+        let scheme = match url.scheme() {
+            "http" => Ok("ws"),
+            "https" => Ok("wss"),
+            s => Err(Error::ProtocolErr(format!(
+                "{} (only HTTP and HTTPS are supported)",
+                s
+            ))),
+        }?;
+        url.set_scheme(scheme).map_err(Error::SchemeErr)?;
+
+        let mut request = Request::builder().uri(url.into_string());
+        if let Some(auth_token) = &self.auth_token {
+            request = request.header("Authorization", format!("Bearer {}", auth_token));
+        }
+        let (socket, _) = connect(request.body(())?)?;
+        Ok(socket)
+    }
+
+    fn authorize(&self, request: RequestBuilder) -> RequestBuilder {
+        match &self.auth_token {
+            Some(auth_token) => request.bearer_auth(auth_token),
+            None => request,
+        }
+    }
+}
+
+/// Stops the reads of the socket from blocking for longer than the timeout, so that
+/// the readers can handle signals such as interrupts in between
+pub fn set_read_timeout(
+    socket: &WebSocket<AutoStream>,
+    timeout: std::time::Duration,
+) -> std::io::Result<()> {
+    let stream: &TcpStream = match socket.get_ref() {
+        tungstenite::stream::Stream::Plain(stream) => stream,
+        tungstenite::stream::Stream::Tls(stream) => stream.get_ref(),
+    };
+    stream.set_read_timeout(Some(timeout))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+    use std::sync::mpsc;
+    use std::thread;
+    use warp::{http::StatusCode, Filter};
+
+    /// Serves `GET /accounts` to the admin, on a runtime of its own since the client blocks
+    fn serve() -> SocketAddr {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let mut runtime = tokio::runtime::Runtime::new().unwrap();
+            runtime.block_on(async move {
+                let accounts = warp::get()
+                    .and(warp::path("accounts"))
+                    .and(warp::header::optional::<String>("authorization"))
+                    .map(|authorization: Option<String>| {
+                        if authorization.as_deref() == Some("Bearer admin") {
+                            warp::reply::with_status("[]", StatusCode::OK)
+                        } else {
+                            warp::reply::with_status("Unauthorized", StatusCode::UNAUTHORIZED)
+                        }
+                    });
+                let (addr, server) = warp::serve(accounts).bind_ephemeral(([127, 0, 0, 1], 0));
+                sender.send(addr).unwrap();
+                server.await
+            })
+        });
+        receiver.recv().unwrap()
+    }
+
+    #[test]
+    fn sends_requests_with_the_token() {
+        let addr = serve();
+        let client = NodeClient::new(&format!("http://{}/", addr), Some("admin".to_string()));
+        let body = client
+            .request(Method::GET, "/accounts", Body::Empty)
+            .unwrap();
+        assert_eq!(body, "[]");
+    }
+
+    #[test]
+    fn returns_error_responses() {
+        let addr = serve();
+        let client = NodeClient::new(&format!("http://{}", addr), None);
+        match client.request(Method::GET, "/accounts", Body::Empty) {
+            Err(Error::StatusErr { status, body }) => {
+                assert_eq!(status, 401);
+                assert_eq!(body, "Unauthorized");
+            }
+            result => panic!("Expected an error response, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn only_subscribes_over_http() {
+        let client = NodeClient::new("ftp://localhost:7770", None);
+        assert!(matches!(
+            client.subscribe("/payments/incoming"),
+            Err(Error::ProtocolErr(_))
+        ));
+    }
+}
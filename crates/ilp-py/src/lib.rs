@@ -0,0 +1,286 @@
+//! # ilp-py
+//!
+//! Python bindings of the HTTP API of Interledger.rs nodes, which send the same requests as the
+//! commands of `ilp-cli` so that nodes can be managed from Python scripts:
+//!
+//! ```python
+//! import ilp
+//!
+//! node = ilp.NodeClient("http://localhost:7770", "admin-token")
+//! node.create_account({"username": "alice", "asset_code": "XRP", "asset_scale": 9})
+//! for payment in node.incoming_payments("alice"):
+//!     print(payment["amount"])
+//! ```
+//!
+//! The bodies of the requests and responses are converted from and to Python objects as JSON.
+//! Error responses raise `ilp.NodeError`.
+
+mod client;
+
+use client::{set_read_timeout, Body, Error, NodeClient as HttpClient};
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
+use pyo3::prelude::*;
+use pyo3::types::PyString;
+use reqwest::Method;
+use serde_json::json;
+use std::io::ErrorKind;
+use std::time::Duration;
+use tungstenite::{client::AutoStream, Message, WebSocket};
+
+create_exception!(
+    ilp,
+    NodeError,
+    PyException,
+    "Raised when a request to the node fails or is refused"
+);
+
+/// How often the readers of notifications check for signals, such as interrupts
+const SIGNAL_CHECK_INTERVAL: Duration = Duration::from_millis(200);
+
+fn node_error(error: Error) -> PyErr {
+    NodeError::new_err(error.to_string())
+}
+
+fn to_json(value: &Bound<'_, PyAny>) -> PyResult<Body> {
+    let json = value
+        .py()
+        .import("json")?
+        .call_method1("dumps", (value,))?
+        .extract()?;
+    Ok(Body::Json(json))
+}
+
+/// Returns the JSON of the body as a Python object, the other bodies as strings, and
+/// empty bodies as `None`
+fn from_json(py: Python<'_>, body: String) -> PyResult<PyObject> {
+    if body.is_empty() {
+        return Ok(py.None());
+    }
+    match py.import("json")?.call_method1("loads", (&body,)) {
+        Ok(value) => Ok(value.unbind()),
+        Err(_) => Ok(PyString::new(py, &body).into_any().unbind()),
+    }
+}
+
+/// A client of a node's HTTP API. The token is the admin's token, or the token of an
+/// account for the requests made on behalf of the account.
+#[pyclass(module = "ilp")]
+struct NodeClient {
+    client: HttpClient,
+}
+
+impl NodeClient {
+    fn send(&self, py: Python<'_>, method: Method, path: &str, body: Body) -> PyResult<PyObject> {
+        let response = py
+            .allow_threads(|| self.client.request(method, path, body))
+            .map_err(node_error)?;
+        from_json(py, response)
+    }
+
+    fn subscribe(&self, py: Python<'_>, path: &str) -> PyResult<Notifications> {
+        let socket = py
+            .allow_threads(|| self.client.subscribe(path))
+            .map_err(node_error)?;
+        set_read_timeout(&socket, SIGNAL_CHECK_INTERVAL)
+            .map_err(|err| NodeError::new_err(err.to_string()))?;
+        Ok(Notifications {
+            socket: Some(socket),
+        })
+    }
+}
+
+#[pymethods]
+impl NodeClient {
+    #[new]
+    #[pyo3(signature = (url = "http://localhost:7770", auth_token = None))]
+    fn new(url: &str, auth_token: Option<String>) -> Self {
+        NodeClient {
+            client: HttpClient::new(url, auth_token),
+        }
+    }
+
+    /// GET / (the status of the node)
+    fn status(&self, py: Python<'_>) -> PyResult<PyObject> {
+        self.send(py, Method::GET, "/", Body::Empty)
+    }
+
+    /// POST /accounts
+    fn create_account(&self, py: Python<'_>, details: &Bound<'_, PyAny>) -> PyResult<PyObject> {
+        self.send(py, Method::POST, "/accounts", to_json(details)?)
+    }
+
+    /// GET /accounts
+    fn list_accounts(&self, py: Python<'_>) -> PyResult<PyObject> {
+        self.send(py, Method::GET, "/accounts", Body::Empty)
+    }
+
+    /// GET /accounts/:username
+    fn get_account(&self, py: Python<'_>, username: &str) -> PyResult<PyObject> {
+        let path = format!("/accounts/{}", username);
+        self.send(py, Method::GET, &path, Body::Empty)
+    }
+
+    /// PUT /accounts/:username
+    fn update_account(
+        &self,
+        py: Python<'_>,
+        username: &str,
+        details: &Bound<'_, PyAny>,
+    ) -> PyResult<PyObject> {
+        let path = format!("/accounts/{}", username);
+        self.send(py, Method::PUT, &path, to_json(details)?)
+    }
+
+    /// PUT /accounts/:username/settings
+    fn update_account_settings(
+        &self,
+        py: Python<'_>,
+        username: &str,
+        settings: &Bound<'_, PyAny>,
+    ) -> PyResult<PyObject> {
+        let path = format!("/accounts/{}/settings", username);
+        self.send(py, Method::PUT, &path, to_json(settings)?)
+    }
+
+    /// DELETE /accounts/:username
+    fn delete_account(&self, py: Python<'_>, username: &str) -> PyResult<PyObject> {
+        let path = format!("/accounts/{}", username);
+        self.send(py, Method::DELETE, &path, Body::Empty)
+    }
+
+    /// GET /accounts/:username/balance
+    fn get_balance(&self, py: Python<'_>, username: &str) -> PyResult<PyObject> {
+        let path = format!("/accounts/{}/balance", username);
+        self.send(py, Method::GET, &path, Body::Empty)
+    }
+
+    /// POST /accounts/:username/payments, with the token of the account
+    fn pay(
+        &self,
+        py: Python<'_>,
+        username: &str,
+        receiver: &str,
+        source_amount: u64,
+    ) -> PyResult<PyObject> {
+        let path = format!("/accounts/{}/payments", username);
+        let body = json!({
+            "receiver": receiver,
+            "source_amount": source_amount,
+        });
+        self.send(py, Method::POST, &path, Body::Json(body.to_string()))
+    }
+
+    /// POST /accounts/:username/payments of a fixed destination amount, with the token
+    /// of the account
+    fn deliver(
+        &self,
+        py: Python<'_>,
+        username: &str,
+        receiver: &str,
+        destination_amount: u64,
+    ) -> PyResult<PyObject> {
+        let path = format!("/accounts/{}/payments", username);
+        let body = json!({
+            "receiver": receiver,
+            "destination_amount": destination_amount,
+        });
+        self.send(py, Method::POST, &path, Body::Json(body.to_string()))
+    }
+
+    /// GET /rates
+    fn get_rates(&self, py: Python<'_>) -> PyResult<PyObject> {
+        self.send(py, Method::GET, "/rates", Body::Empty)
+    }
+
+    /// PUT /rates, with a dict of the rates by asset code
+    fn set_rates(&self, py: Python<'_>, rates: &Bound<'_, PyAny>) -> PyResult<PyObject> {
+        self.send(py, Method::PUT, "/rates", to_json(rates)?)
+    }
+
+    /// GET /routes
+    fn get_routes(&self, py: Python<'_>) -> PyResult<PyObject> {
+        self.send(py, Method::GET, "/routes", Body::Empty)
+    }
+
+    /// PUT /routes/static/:prefix, routing the prefix to the account with the username
+    fn set_route(&self, py: Python<'_>, prefix: &str, destination: &str) -> PyResult<PyObject> {
+        let path = format!("/routes/static/{}", prefix);
+        self.send(py, Method::PUT, &path, Body::Text(destination.to_string()))
+    }
+
+    /// PUT /routes/static, with a dict of the usernames by prefix
+    fn set_routes(&self, py: Python<'_>, routes: &Bound<'_, PyAny>) -> PyResult<PyObject> {
+        self.send(py, Method::PUT, "/routes/static", to_json(routes)?)
+    }
+
+    /// WebSocket /accounts/:username/payments/incoming, or /payments/incoming for the
+    /// payments received by all the accounts. Returns an iterator of the payments.
+    #[pyo3(signature = (username = None))]
+    fn incoming_payments(&self, py: Python<'_>, username: Option<&str>) -> PyResult<Notifications> {
+        let path = match username {
+            Some(username) => format!("/accounts/{}/payments/incoming", username),
+            None => "/payments/incoming".to_string(),
+        };
+        self.subscribe(py, &path)
+    }
+}
+
+/// An iterator of the notifications sent by the node on a WebSocket, until the node
+/// closes it or `close` is called
+#[pyclass(module = "ilp")]
+struct Notifications {
+    socket: Option<WebSocket<AutoStream>>,
+}
+
+#[pymethods]
+impl Notifications {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self, py: Python<'_>) -> PyResult<Option<PyObject>> {
+        loop {
+            let socket = match self.socket.as_mut() {
+                Some(socket) => socket,
+                None => return Ok(None),
+            };
+            match py.allow_threads(|| socket.read_message()) {
+                Ok(Message::Text(text)) => return from_json(py, text).map(Some),
+                Ok(Message::Close(_)) => {
+                    self.socket = None;
+                    return Ok(None);
+                }
+                Ok(_) => {}
+                Err(tungstenite::Error::Io(err))
+                    if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) =>
+                {
+                    py.check_signals()?
+                }
+                Err(tungstenite::Error::ConnectionClosed) => {
+                    self.socket = None;
+                    return Ok(None);
+                }
+                Err(err) => {
+                    self.socket = None;
+                    return Err(node_error(err.into()));
+                }
+            }
+        }
+    }
+
+    /// Closes the WebSocket, which ends the iteration
+    fn close(&mut self) {
+        if let Some(mut socket) = self.socket.take() {
+            let _ = socket.close(None);
+        }
+    }
+}
+
+#[pymodule]
+fn ilp(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<NodeClient>()?;
+    m.add_class::<Notifications>()?;
+    m.add("NodeError", m.py().get_type::<NodeError>())?;
+    Ok(())
+}
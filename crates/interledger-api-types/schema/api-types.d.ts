@@ -0,0 +1,153 @@
+// Generated from the JSON Schema of the interledger-api-types crate, do not edit
+
+/** The details of an account, with which it is created or updated */
+export interface AccountDetails {
+  /** The maximum amount the account can send per minute */
+  amount_per_minute_limit?: number | null;
+  /** The account's currency */
+  asset_code: string;
+  /** The account's asset scale */
+  asset_scale: number;
+  /** URL to which the notifications of the account's balance crossing the thresholds configured on the node are POSTed as JSON */
+  balance_notification_url?: string | null;
+  /** Fee, in the outgoing account's units, charged on each packet sent by the account */
+  fixed_fee?: number | null;
+  /** The account's Interledger Protocol address. If none is provided, the node should generate one */
+  ilp_address?: string | null;
+  /** The account's incoming ILP over BTP token. This must match the ILP over BTP outgoing token on the peer's node if exchanging packets with that peer. */
+  ilp_over_btp_incoming_token?: string | null;
+  /** Whether the peer speaks the BTP dialect of the legacy JavaScript plugins (`ilp-plugin-btp` and `ilp-plugin-mini-accounts`), for peering with the JavaScript connector stack */
+  ilp_over_btp_legacy?: boolean;
+  /** The account's outgoing ILP over BTP token. This must match the ILP over BTP incoming token on the peer's node if exchanging packets with that peer. */
+  ilp_over_btp_outgoing_token?: string | null;
+  /** The account's ILP over BTP URL (this is where packets are sent over WebSockets from your node) */
+  ilp_over_btp_url?: string | null;
+  /** The dialect of ILP over HTTP spoken by the account's peer: `rfc` (the default), or `java` or `java_jwt` for the Java connector with its `SIMPLE` or `JWT_HS_256` authentication. It sets the headers of the requests sent to the peer */
+  ilp_over_http_dialect?: "rfc" | "java" | "java_jwt";
+  /** The account's API and incoming ILP over HTTP token. This must match the ILP over HTTP outgoing token on the peer's node if receiving packets from that peer */
+  ilp_over_http_incoming_token?: string | null;
+  /** The account's outgoing ILP over HTTP token This must match the ILP over HTTP incoming token on the peer's node if sending packets to that peer */
+  ilp_over_http_outgoing_token?: string | null;
+  /** The Ed25519 key (in base64, as a 32 byte seed or a PKCS#8 document) with which the ILP over HTTP requests sent to the peer are signed with HTTP message signatures */
+  ilp_over_http_signing_key?: string | null;
+  /** The account's ILP over HTTP URL (this is where packets are sent over HTTP from your node) */
+  ilp_over_http_url?: string | null;
+  /** The Ed25519 public key (in base64) of the peer. The ILP over HTTP requests it signs with the matching key are accepted without the incoming token */
+  ilp_over_http_verification_key?: string | null;
+  /** Networks (in CIDR notation) the account's incoming ILP-over-HTTP requests and BTP connections must come from. Any address is allowed if it is empty */
+  ip_allowlist?: Array<string>;
+  /** URL to which the account's peer is notified, as JSON, of the packets it sent which were rejected because it owes the node more than its receivable limit, with the amount it must settle for them to go through. Notified at most once a minute */
+  liquidity_notification_url?: string | null;
+  /** The max amount per packet which can be routed for this account */
+  max_packet_amount?: number;
+  /** The max size, in bytes, of the ILP packets sent to or by this account */
+  max_packet_size?: number | null;
+  /** The minimum balance this account can have (consider this as a credit/trust limit). Superseded by the receivable limit, which takes precedence over it */
+  min_balance?: number | null;
+  /** Filters applied, in order, to the packets sent by the account before they are forwarded */
+  packet_filters?: Array<PacketFilter>;
+  /** The limit of packets the account can send per minute */
+  packets_per_minute_limit?: number | null;
+  /** The limit of packets the account can send per second */
+  packets_per_second_limit?: number | null;
+  /** The max amount the node may owe this account. Packets which would make the node owe more (counting the packets to the account which are still in flight) are rejected with T04 errors */
+  payable_limit?: number | null;
+  /** Fee, as a fraction of the converted amount, charged on the packets sent by the account */
+  percentage_fee?: number | null;
+  /** The max amount this account may owe the node. Packets from the account which would make it owe more are rejected with T04 errors */
+  receivable_limit?: number | null;
+  /** The round trip time of the account (should be set depending on how well the network connectivity of the account and the node is) */
+  round_trip_time?: number | null;
+  /** Interval, defined in milliseconds, on which the node broadcasts its routes to the account, for peers which should receive slower updates than the others. The broadcasts still happen on the node's route broadcast interval, so a shorter interval has no effect */
+  route_broadcast_interval?: number | null;
+  /** How long, in milliseconds, the account should keep the routes the node broadcasts to it unless they are broadcast again. Defaults to 30 seconds */
+  route_expiry?: number | null;
+  /** Whether each route broadcast to the account carries the whole routing table, rather than the changes since the previous broadcast */
+  route_full_table?: boolean;
+  /** The routing relation of the account */
+  routing_relation?: string | null;
+  /** The threshold after which the balance service will trigger a settlement */
+  settle_threshold?: number | null;
+  /** The amount which the balance service will attempt to settle down to */
+  settle_to?: number | null;
+  /** The account's settlement engine URL. If a global engine url is configured for the account's asset code,  that will be used instead (even if the account is configured with a specific one) */
+  settlement_engine_url?: string | null;
+  /** Spread, as a fraction, subtracted from the exchange rate of the packets sent by the account (overrides the spread of the asset pair and of the node) */
+  spread?: number | null;
+  /** The username of another account of the same peer, in another currency, whose BTP connection this account shares instead of opening one of its own. The packets of the account keep their own balance and settlement. */
+  transport_account?: string | null;
+  /** The account's username */
+  username: string;
+}
+
+/** AccountSettings is a subset of the user parameters defined in AccountDetails. Its purpose is to allow a user to modify certain of their parameters which they may want to re-configure in the future, such as their tokens (which act as passwords), their settlement frequency preferences, or their HTTP/BTP endpoints, since they may change their network configuration. */
+export interface AccountSettings {
+  /** The maximum amount the account can send per minute */
+  amount_per_minute_limit?: number | null;
+  /** The account's incoming ILP over BTP token. */
+  ilp_over_btp_incoming_token?: string | null;
+  /** The account's outgoing ILP over BTP token. This must match the ILP over BTP incoming token on the peer's node if exchanging packets with that peer. */
+  ilp_over_btp_outgoing_token?: string | null;
+  /** The account's ILP over BTP URL (this is where packets are sent over WebSockets from your node) */
+  ilp_over_btp_url?: string | null;
+  /** The account's incoming ILP over HTTP token. */
+  ilp_over_http_incoming_token?: string | null;
+  /** The account's outgoing ILP over HTTP token */
+  ilp_over_http_outgoing_token?: string | null;
+  /** The account's ILP over HTTP URL (this is where packets are sent over HTTP from your node) */
+  ilp_over_http_url?: string | null;
+  /** The max amount per packet which can be routed for this account. Packets above it are rejected with F08 errors which carry the maximum, so that senders can size their packets accordingly. Only the admin can change it. */
+  max_packet_amount?: number | null;
+  /** The max size, in bytes, of the ILP packets sent to or by this account. Packets above it are rejected with F01 errors which carry the maximum. Only the admin can change it. */
+  max_packet_size?: number | null;
+  /** The limit of packets the account can send per minute */
+  packets_per_minute_limit?: number | null;
+  /** The limit of packets the account can send per second. Only the admin can change the rate limits. */
+  packets_per_second_limit?: number | null;
+  /** The threshold after which the balance service will trigger a settlement */
+  settle_threshold?: number | null;
+  /** The amount which the balance service will attempt to settle down to. Note that this is intentionally an unsigned integer because users should not be able to set the settle_to value to be negative (meaning the node would pre-fund with the user) */
+  settle_to?: number | null;
+}
+
+/** The exchange rates of the assets, as the price of one unit in the node's reference asset */
+export type ExchangeRates = { [key: string]: number };
+
+/** A restriction on the packets an account may send through the node */
+export type PacketFilter = { addresses: Array<string>; type: "allow_addresses"; } | { addresses: Array<string>; type: "deny_addresses"; } | { prefixes: Array<string>; type: "allow_prefixes"; } | { prefixes: Array<string>; type: "deny_prefixes"; } | { amount: number; type: "max_amount"; } | { end: string; start: string; type: "time_window"; };
+
+/** Node settings which can be changed while the node is running. Fields which are not set fall back to the values the node was configured with. */
+export interface RuntimeSettings {
+  /** Interval, defined in milliseconds, on which the node will poll the exchange rate provider */
+  exchange_rate_poll_interval?: number | null;
+  /** The max amount per packet which is forwarded for any account, on top of each account's own max_packet_amount */
+  max_packet_amount?: number | null;
+  /** The max size, in bytes, of the ILP packets which are accepted from any account, on top of each account's own max_packet_size */
+  max_packet_size?: number | null;
+  /** Interval, defined in milliseconds, on which the node will broadcast routing information to other nodes using CCP */
+  route_broadcast_interval?: number | null;
+}
+
+/** An SPSP payment sent from an account (the body of `POST /accounts/:username/payments`) */
+export interface SpspPayRequest {
+  /** The amount to deliver, in the units of the receiver. The payment then sends what the amount costs at the exchange rate of the path, within the slippage, and fails if it cannot deliver all of it */
+  destination_amount?: number | null;
+  /** A reference of the payment, such as the number of the invoice it pays, which is sent to the receiver and included in its payment notifications (up to 256 bytes) */
+  memo?: string | null;
+  /** URL to which the payment, with its outcome, is POSTed as JSON once it is finished */
+  notification_url?: string | null;
+  /** The payment pointer or SPSP URL of the receiver */
+  receiver: string;
+  /** The max slippage of the exchange rate, as a fraction. Defaults to 0.015 */
+  slippage?: number;
+  /** The amount to send, in the units of the sending account. Either this or the destination amount must be set */
+  source_amount?: number | null;
+}
+
+/** A payment pulled by an account (the body of `POST /accounts/:username/pulls`) */
+export interface SpspPullRequest {
+  /** The amount to pull, in the units of the pull pointer */
+  amount: number;
+  /** The pull pointer which the payment is pulled from */
+  pull_pointer: string;
+}
@@ -0,0 +1,493 @@
+//! # interledger-api-types
+//!
+//! The request and response types of the node's HTTP API, shared by the node and its clients.
+//!
+//! The types describe themselves with a [JSON Schema](./fn.json_schema.html), from which
+//! [TypeScript definitions](./fn.typescript.html) are derived. Both are published in the
+//! `schema` directory of this crate, and a test fails if they do not match the types, so that
+//! the SDKs generated from them stay in lockstep with the node. Regenerate them by running
+//! the tests with `UPDATE_SCHEMA=1`.
+//!
+//! The schema describes the values as the node serializes them. In the requests, the
+//! integers and the exchange rates may also be sent as strings.
+
+use serde::{de, Deserialize, Serialize};
+use serde_json::Value;
+use std::{collections::HashMap, fmt::Display, str::FromStr};
+
+pub mod schema;
+
+pub use schemars::JsonSchema;
+
+use interledger_packet::Address;
+use interledger_service::{HttpDialect, IpNetwork, PacketFilter, Username};
+use secrecy::SecretString;
+
+// This enum and the following functions are used to allow clients to send either
+// numbers or strings and have them be properly deserialized into the appropriate
+// integer type.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum NumOrStr<T> {
+    Num(T),
+    Str(String),
+}
+
+pub fn number_or_string<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: de::Deserializer<'de>,
+    T: FromStr + Deserialize<'de>,
+    <T as FromStr>::Err: Display,
+{
+    match NumOrStr::deserialize(deserializer)? {
+        NumOrStr::Num(n) => Ok(n),
+        NumOrStr::Str(s) => T::from_str(&s).map_err(de::Error::custom),
+    }
+}
+
+pub fn optional_number_or_string<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: de::Deserializer<'de>,
+    T: FromStr + Deserialize<'de>,
+    <T as FromStr>::Err: Display,
+{
+    match NumOrStr::deserialize(deserializer)? {
+        NumOrStr::Num(n) => Ok(Some(n)),
+        NumOrStr::Str(s) => T::from_str(&s).map_err(de::Error::custom).map(Some),
+    }
+}
+
+pub fn map_of_number_or_string<'de, D>(deserializer: D) -> Result<HashMap<String, f64>, D::Error>
+where
+    D: de::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    struct Wrapper(#[serde(deserialize_with = "number_or_string")] f64);
+
+    let v = HashMap::<String, Wrapper>::deserialize(deserializer)?;
+    Ok(v.into_iter().map(|(k, Wrapper(v))| (k, v)).collect())
+}
+
+/// The max slippage of the exchange rate of the payments, as a fraction, if none is requested
+pub const fn get_default_max_slippage() -> f64 {
+    0.015
+}
+
+/// The exchange rates of the assets, as the price of one unit in the node's reference asset
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ExchangeRates(
+    #[serde(deserialize_with = "map_of_number_or_string")] pub HashMap<String, f64>,
+);
+
+/// Node settings which can be changed while the node is running.
+/// Fields which are not set fall back to the values the node was configured with.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct RuntimeSettings {
+    /// Interval, defined in milliseconds, on which the node will broadcast routing
+    /// information to other nodes using CCP
+    #[serde(
+        default,
+        deserialize_with = "optional_number_or_string",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub route_broadcast_interval: Option<u64>,
+    /// Interval, defined in milliseconds, on which the node will poll the exchange rate provider
+    #[serde(
+        default,
+        deserialize_with = "optional_number_or_string",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub exchange_rate_poll_interval: Option<u64>,
+    /// The max amount per packet which is forwarded for any account,
+    /// on top of each account's own max_packet_amount
+    #[serde(
+        default,
+        deserialize_with = "optional_number_or_string",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub max_packet_amount: Option<u64>,
+    /// The max size, in bytes, of the ILP packets which are accepted from any account,
+    /// on top of each account's own max_packet_size
+    #[serde(
+        default,
+        deserialize_with = "optional_number_or_string",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub max_packet_size: Option<u64>,
+}
+
+/// AccountSettings is a subset of the user parameters defined in
+/// AccountDetails. Its purpose is to allow a user to modify certain of their
+/// parameters which they may want to re-configure in the future, such as their
+/// tokens (which act as passwords), their settlement frequency preferences, or
+/// their HTTP/BTP endpoints, since they may change their network configuration.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct AccountSettings {
+    /// The account's incoming ILP over HTTP token.
+    #[schemars(with = "Option<schema::SecretStringSchema>")]
+    pub ilp_over_http_incoming_token: Option<SecretString>,
+    /// The account's incoming ILP over BTP token.
+    #[schemars(with = "Option<schema::SecretStringSchema>")]
+    pub ilp_over_btp_incoming_token: Option<SecretString>,
+    /// The account's outgoing ILP over HTTP token
+    #[schemars(with = "Option<schema::SecretStringSchema>")]
+    pub ilp_over_http_outgoing_token: Option<SecretString>,
+    /// The account's outgoing ILP over BTP token.
+    /// This must match the ILP over BTP incoming token on the peer's node if exchanging
+    /// packets with that peer.
+    #[schemars(with = "Option<schema::SecretStringSchema>")]
+    pub ilp_over_btp_outgoing_token: Option<SecretString>,
+    /// The account's ILP over HTTP URL (this is where packets are sent over HTTP from your node)
+    pub ilp_over_http_url: Option<String>,
+    /// The account's ILP over BTP URL (this is where packets are sent over WebSockets from your node)
+    pub ilp_over_btp_url: Option<String>,
+    /// The threshold after which the balance service will trigger a settlement
+    #[serde(default, deserialize_with = "optional_number_or_string")]
+    pub settle_threshold: Option<i64>,
+    /// The amount which the balance service will attempt to settle down to.
+    /// Note that this is intentionally an unsigned integer because users should
+    /// not be able to set the settle_to value to be negative (meaning the node
+    /// would pre-fund with the user)
+    #[serde(default, deserialize_with = "optional_number_or_string")]
+    pub settle_to: Option<u64>,
+    /// The limit of packets the account can send per second.
+    /// Only the admin can change the rate limits.
+    #[serde(default, deserialize_with = "optional_number_or_string")]
+    pub packets_per_second_limit: Option<u32>,
+    /// The limit of packets the account can send per minute
+    #[serde(default, deserialize_with = "optional_number_or_string")]
+    pub packets_per_minute_limit: Option<u32>,
+    /// The maximum amount the account can send per minute
+    #[serde(default, deserialize_with = "optional_number_or_string")]
+    pub amount_per_minute_limit: Option<u64>,
+    /// The max amount per packet which can be routed for this account. Packets above it
+    /// are rejected with F08 errors which carry the maximum, so that senders can size
+    /// their packets accordingly. Only the admin can change it.
+    #[serde(default, deserialize_with = "optional_number_or_string")]
+    pub max_packet_amount: Option<u64>,
+    /// The max size, in bytes, of the ILP packets sent to or by this account. Packets
+    /// above it are rejected with F01 errors which carry the maximum. Only the admin can
+    /// change it.
+    #[serde(default, deserialize_with = "optional_number_or_string")]
+    pub max_packet_size: Option<u64>,
+}
+
+/// The details of an account, with which it is created or updated
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct AccountDetails {
+    /// The account's Interledger Protocol address.
+    /// If none is provided, the node should generate one
+    #[schemars(with = "Option<schema::AddressSchema>")]
+    pub ilp_address: Option<Address>,
+    /// The account's username
+    #[schemars(with = "schema::UsernameSchema")]
+    pub username: Username,
+    /// The account's currency
+    pub asset_code: String,
+    /// The account's asset scale
+    #[serde(deserialize_with = "number_or_string")]
+    pub asset_scale: u8,
+    /// The max amount per packet which can be routed for this account
+    #[serde(default = "u64::max_value", deserialize_with = "number_or_string")]
+    pub max_packet_amount: u64,
+    /// The max size, in bytes, of the ILP packets sent to or by this account
+    #[serde(default, deserialize_with = "optional_number_or_string")]
+    pub max_packet_size: Option<u64>,
+    /// The minimum balance this account can have (consider this as a credit/trust limit).
+    /// Superseded by the receivable limit, which takes precedence over it
+    #[serde(default, deserialize_with = "optional_number_or_string")]
+    pub min_balance: Option<i64>,
+    /// The max amount the node may owe this account. Packets which would make the node
+    /// owe more (counting the packets to the account which are still in flight) are
+    /// rejected with T04 errors
+    #[serde(default, deserialize_with = "optional_number_or_string")]
+    pub payable_limit: Option<u64>,
+    /// The max amount this account may owe the node. Packets from the account which
+    /// would make it owe more are rejected with T04 errors
+    #[serde(default, deserialize_with = "optional_number_or_string")]
+    pub receivable_limit: Option<u64>,
+    /// The account's ILP over HTTP URL (this is where packets are sent over HTTP from your node)
+    pub ilp_over_http_url: Option<String>,
+    /// The account's API and incoming ILP over HTTP token.
+    /// This must match the ILP over HTTP outgoing token on the peer's node if receiving
+    /// packets from that peer
+    // TODO: The incoming token is used for both ILP over HTTP, and for authorizing actions from the HTTP API.
+    // Should we add 1 more token, for more granular permissioning?
+    #[schemars(with = "Option<schema::SecretStringSchema>")]
+    pub ilp_over_http_incoming_token: Option<SecretString>,
+    /// The account's outgoing ILP over HTTP token
+    /// This must match the ILP over HTTP incoming token on the peer's node if sending
+    /// packets to that peer
+    #[schemars(with = "Option<schema::SecretStringSchema>")]
+    pub ilp_over_http_outgoing_token: Option<SecretString>,
+    /// The dialect of ILP over HTTP spoken by the account's peer: `rfc` (the default), or
+    /// `java` or `java_jwt` for the Java connector with its `SIMPLE` or `JWT_HS_256`
+    /// authentication. It sets the headers of the requests sent to the peer
+    #[serde(default)]
+    #[schemars(with = "schema::HttpDialectSchema")]
+    pub ilp_over_http_dialect: HttpDialect,
+    /// The Ed25519 key (in base64, as a 32 byte seed or a PKCS#8 document) with which the
+    /// ILP over HTTP requests sent to the peer are signed with HTTP message signatures
+    #[schemars(with = "Option<schema::SecretStringSchema>")]
+    pub ilp_over_http_signing_key: Option<SecretString>,
+    /// The Ed25519 public key (in base64) of the peer. The ILP over HTTP requests it signs
+    /// with the matching key are accepted without the incoming token
+    pub ilp_over_http_verification_key: Option<String>,
+    /// The account's ILP over BTP URL (this is where packets are sent over WebSockets from your node)
+    pub ilp_over_btp_url: Option<String>,
+    /// The account's outgoing ILP over BTP token.
+    /// This must match the ILP over BTP incoming token on the peer's node if exchanging
+    /// packets with that peer.
+    #[schemars(with = "Option<schema::SecretStringSchema>")]
+    pub ilp_over_btp_outgoing_token: Option<SecretString>,
+    /// The account's incoming ILP over BTP token.
+    /// This must match the ILP over BTP outgoing token on the peer's node if exchanging
+    /// packets with that peer.
+    #[schemars(with = "Option<schema::SecretStringSchema>")]
+    pub ilp_over_btp_incoming_token: Option<SecretString>,
+    /// Whether the peer speaks the BTP dialect of the legacy JavaScript plugins
+    /// (`ilp-plugin-btp` and `ilp-plugin-mini-accounts`), for peering with the JavaScript
+    /// connector stack
+    #[serde(default)]
+    pub ilp_over_btp_legacy: bool,
+    /// The username of another account of the same peer, in another currency, whose
+    /// BTP connection this account shares instead of opening one of its own. The packets
+    /// of the account keep their own balance and settlement.
+    #[serde(default)]
+    #[schemars(with = "Option<schema::UsernameSchema>")]
+    pub transport_account: Option<Username>,
+    /// The threshold after which the balance service will trigger a settlement
+    #[serde(default, deserialize_with = "optional_number_or_string")]
+    pub settle_threshold: Option<i64>,
+    /// The amount which the balance service will attempt to settle down to
+    #[serde(default, deserialize_with = "optional_number_or_string")]
+    pub settle_to: Option<i64>,
+    /// The routing relation of the account
+    pub routing_relation: Option<String>,
+    /// The round trip time of the account (should be set depending on how
+    /// well the network connectivity of the account and the node is)
+    #[serde(default, deserialize_with = "optional_number_or_string")]
+    pub round_trip_time: Option<u32>,
+    /// Interval, defined in milliseconds, on which the node broadcasts its routes to the
+    /// account, for peers which should receive slower updates than the others. The
+    /// broadcasts still happen on the node's route broadcast interval, so a shorter
+    /// interval has no effect
+    #[serde(default, deserialize_with = "optional_number_or_string")]
+    pub route_broadcast_interval: Option<u64>,
+    /// How long, in milliseconds, the account should keep the routes the node broadcasts
+    /// to it unless they are broadcast again. Defaults to 30 seconds
+    #[serde(default, deserialize_with = "optional_number_or_string")]
+    pub route_expiry: Option<u32>,
+    /// Whether each route broadcast to the account carries the whole routing table,
+    /// rather than the changes since the previous broadcast
+    #[serde(default)]
+    pub route_full_table: bool,
+    /// The maximum amount the account can send per minute
+    #[serde(default, deserialize_with = "optional_number_or_string")]
+    pub amount_per_minute_limit: Option<u64>,
+    /// The limit of packets the account can send per second
+    #[serde(default, deserialize_with = "optional_number_or_string")]
+    pub packets_per_second_limit: Option<u32>,
+    /// The limit of packets the account can send per minute
+    #[serde(default, deserialize_with = "optional_number_or_string")]
+    pub packets_per_minute_limit: Option<u32>,
+    /// The account's settlement engine URL. If a global engine url is configured
+    /// for the account's asset code,  that will be used instead (even if the account is
+    /// configured with a specific one)
+    pub settlement_engine_url: Option<String>,
+    /// URL to which the account's peer is notified, as JSON, of the packets it sent which
+    /// were rejected because it owes the node more than its receivable limit, with the
+    /// amount it must settle for them to go through. Notified at most once a minute
+    #[serde(default)]
+    pub liquidity_notification_url: Option<String>,
+    /// URL to which the notifications of the account's balance crossing the thresholds
+    /// configured on the node are POSTed as JSON
+    #[serde(default)]
+    pub balance_notification_url: Option<String>,
+    /// Spread, as a fraction, subtracted from the exchange rate of the packets sent by
+    /// the account (overrides the spread of the asset pair and of the node)
+    #[serde(default, deserialize_with = "optional_number_or_string")]
+    pub spread: Option<f64>,
+    /// Fee, as a fraction of the converted amount, charged on the packets sent by the account
+    #[serde(default, deserialize_with = "optional_number_or_string")]
+    pub percentage_fee: Option<f64>,
+    /// Fee, in the outgoing account's units, charged on each packet sent by the account
+    #[serde(default, deserialize_with = "optional_number_or_string")]
+    pub fixed_fee: Option<u64>,
+    /// Filters applied, in order, to the packets sent by the account before they are forwarded
+    #[serde(default)]
+    #[schemars(with = "Vec<schema::PacketFilterSchema>")]
+    pub packet_filters: Vec<PacketFilter>,
+    /// Networks (in CIDR notation) the account's incoming ILP-over-HTTP requests and
+    /// BTP connections must come from. Any address is allowed if it is empty
+    #[serde(default)]
+    #[schemars(with = "Vec<schema::IpNetworkSchema>")]
+    pub ip_allowlist: Vec<IpNetwork>,
+}
+
+/// An SPSP payment sent from an account (the body of `POST /accounts/:username/payments`)
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct SpspPayRequest {
+    /// The payment pointer or SPSP URL of the receiver
+    pub receiver: String,
+    /// The amount to send, in the units of the sending account. Either this or the
+    /// destination amount must be set
+    #[serde(default, deserialize_with = "optional_number_or_string")]
+    pub source_amount: Option<u64>,
+    /// The amount to deliver, in the units of the receiver. The payment then sends what
+    /// the amount costs at the exchange rate of the path, within the slippage, and fails
+    /// if it cannot deliver all of it
+    #[serde(default, deserialize_with = "optional_number_or_string")]
+    pub destination_amount: Option<u64>,
+    /// The max slippage of the exchange rate, as a fraction. Defaults to 0.015
+    #[serde(
+        default = "get_default_max_slippage",
+        deserialize_with = "number_or_string"
+    )]
+    pub slippage: f64,
+    /// A reference of the payment, such as the number of the invoice it pays, which is sent
+    /// to the receiver and included in its payment notifications (up to 256 bytes)
+    pub memo: Option<String>,
+    /// URL to which the payment, with its outcome, is POSTed as JSON once it is finished
+    #[serde(default)]
+    pub notification_url: Option<String>,
+}
+
+/// A payment pulled by an account (the body of `POST /accounts/:username/pulls`)
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct SpspPullRequest {
+    /// The pull pointer which the payment is pulled from
+    pub pull_pointer: String,
+    /// The amount to pull, in the units of the pull pointer
+    #[serde(deserialize_with = "number_or_string")]
+    pub amount: u64,
+}
+
+impl RuntimeSettings {
+    /// Returns these settings with the fields which are not set taken from `other`
+    pub fn or(self, other: RuntimeSettings) -> RuntimeSettings {
+        RuntimeSettings {
+            route_broadcast_interval: self
+                .route_broadcast_interval
+                .or(other.route_broadcast_interval),
+            exchange_rate_poll_interval: self
+                .exchange_rate_poll_interval
+                .or(other.exchange_rate_poll_interval),
+            max_packet_amount: self.max_packet_amount.or(other.max_packet_amount),
+            max_packet_size: self.max_packet_size.or(other.max_packet_size),
+        }
+    }
+}
+
+impl AccountSettings {
+    /// Whether the settings change any of the account's rate limits or its maximum
+    /// packet amount or size
+    pub fn modifies_limits(&self) -> bool {
+        self.packets_per_second_limit.is_some()
+            || self.packets_per_minute_limit.is_some()
+            || self.amount_per_minute_limit.is_some()
+            || self.max_packet_amount.is_some()
+            || self.max_packet_size.is_some()
+    }
+}
+
+/// Returns the JSON Schema document defining the API types
+pub fn json_schema() -> Value {
+    let mut generator = schema::generator();
+    generator.subschema_for::<AccountDetails>();
+    generator.subschema_for::<AccountSettings>();
+    generator.subschema_for::<ExchangeRates>();
+    generator.subschema_for::<RuntimeSettings>();
+    generator.subschema_for::<SpspPayRequest>();
+    generator.subschema_for::<SpspPullRequest>();
+    schema::document("Interledger node API types", generator)
+}
+
+/// Returns the TypeScript definitions of the API types
+pub fn typescript() -> String {
+    format!(
+        "// Generated from the JSON Schema of the interledger-api-types crate, do not edit\n\n{}",
+        schema::typescript(&json_schema())
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::collections::BTreeSet;
+
+    /// Returns the definition of the type
+    fn definition<T: JsonSchema>() -> Value {
+        json_schema()["definitions"][T::schema_name()].clone()
+    }
+
+    /// Returns the names of the properties of the definition
+    fn properties<T: JsonSchema>() -> BTreeSet<String> {
+        definition::<T>()["properties"]
+            .as_object()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect()
+    }
+
+    fn fields(value: impl Serialize) -> BTreeSet<String> {
+        serde_json::to_value(value)
+            .unwrap()
+            .as_object()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect()
+    }
+
+    #[test]
+    fn the_schema_has_the_serialized_fields() {
+        let details: AccountDetails = serde_json::from_str(
+            r#"{ "username": "alice", "asset_code": "XYZ", "asset_scale": 9 }"#,
+        )
+        .unwrap();
+        assert_eq!(properties::<AccountDetails>(), fields(details));
+        assert_eq!(
+            properties::<AccountSettings>(),
+            fields(AccountSettings::default())
+        );
+        let payment: SpspPayRequest = serde_json::from_value(json!({
+            "receiver": "$example.com",
+            "source_amount": "100",
+        }))
+        .unwrap();
+        assert_eq!(payment.source_amount, Some(100));
+        assert_eq!(payment.slippage, get_default_max_slippage());
+        assert_eq!(properties::<SpspPayRequest>(), fields(payment));
+        assert_eq!(
+            definition::<AccountDetails>()["required"],
+            json!(["asset_code", "asset_scale", "username"])
+        );
+    }
+
+    #[test]
+    fn the_definitions_are_up_to_date() {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/schema");
+        let files = [
+            (
+                "api-types.schema.json",
+                serde_json::to_string_pretty(&json_schema()).unwrap() + "\n",
+            ),
+            ("api-types.d.ts", typescript()),
+        ];
+        for (name, contents) in files.iter() {
+            let path = format!("{}/{}", dir, name);
+            if std::env::var_os("UPDATE_SCHEMA").is_some() {
+                std::fs::write(&path, contents).unwrap();
+            }
+            assert_eq!(
+                &std::fs::read_to_string(&path).unwrap_or_default(),
+                contents,
+                "schema/{} is out of date, regenerate it by running this test with UPDATE_SCHEMA=1",
+                name
+            );
+        }
+    }
+}
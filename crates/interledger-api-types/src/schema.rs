@@ -0,0 +1,349 @@
+//! The JSON Schema (draft-07) of the API types, and the TypeScript definitions derived from it.
+//!
+//! The types derive their schema with `schemars`, which takes their doc comments as
+//! descriptions, so that the published definitions cannot drift from the types the node
+//! (de)serializes. The fields of the types defined in other crates describe their values
+//! with the schemas of this module, as in `#[schemars(with = "schema::UsernameSchema")]`.
+
+use schemars::{
+    gen::{SchemaGenerator, SchemaSettings},
+    schema::Schema,
+    JsonSchema,
+};
+use serde_json::{json, Map, Value};
+
+/// Returns the generator of the schemas, which refers to the definitions by name
+pub fn generator() -> SchemaGenerator {
+    SchemaSettings::draft07().into_generator()
+}
+
+/// Declares the schemas of the values of the types of the other crates, which the fields
+/// of these types refer to with a `schemars(with)` attribute
+macro_rules! external_schemas {
+    ($($(#[doc = $doc:literal])* $name:ident => $schema:tt,)*) => {$(
+        $(#[doc = $doc])*
+        pub struct $name;
+
+        impl JsonSchema for $name {
+            fn schema_name() -> String {
+                stringify!($name).to_string()
+            }
+
+            fn is_referenceable() -> bool {
+                false
+            }
+
+            fn json_schema(_: &mut SchemaGenerator) -> Schema {
+                serde_json::from_value(json!($schema)).expect("the schema is valid")
+            }
+        }
+    )*};
+}
+
+external_schemas! {
+    /// A secret, which the node accepts but never returns
+    SecretStringSchema => { "type": "string", "writeOnly": true },
+    /// The username of an account
+    UsernameSchema => { "type": "string", "pattern": "^\\w{2,32}$" },
+    /// An ILP address
+    AddressSchema => {
+        "type": "string",
+        "pattern": "^(g|private|example|peer|self|test[1-3]?|local)([.][a-zA-Z0-9_~-]+)+$",
+    },
+    /// A network in CIDR notation
+    IpNetworkSchema => { "type": "string", "examples": ["203.0.113.0/24", "2001:db8::/32"] },
+    /// The dialect of ILP over HTTP spoken by a peer
+    HttpDialectSchema => { "type": "string", "enum": ["rfc", "java", "java_jwt"] },
+    /// A time of the day (in UTC)
+    TimeOfDaySchema => { "type": "string", "pattern": "^\\d{1,2}:\\d{1,2}$", "examples": ["22:30"] },
+}
+
+/// Returns the schema of an object with the properties, and its description
+fn object(description: &str, properties: Map<String, Value>, required: Vec<&str>) -> Value {
+    json!({
+        "type": "object",
+        "description": description,
+        "properties": properties,
+        "required": required,
+    })
+}
+
+/// A restriction on the packets an account may send, which is defined in the `definitions`
+/// of the schema (as `PacketFilter`)
+pub struct PacketFilterSchema;
+
+impl JsonSchema for PacketFilterSchema {
+    fn schema_name() -> String {
+        "PacketFilter".to_string()
+    }
+
+    fn json_schema(generator: &mut SchemaGenerator) -> Schema {
+        let schema_of = |schema: Schema| serde_json::to_value(schema).unwrap();
+        let strings = schema_of(generator.subschema_for::<Vec<String>>());
+        let amount = schema_of(generator.subschema_for::<u64>());
+        let time_of_day = schema_of(generator.subschema_for::<TimeOfDaySchema>());
+        let filter = |name: &str, description: &str, properties: Map<String, Value>| {
+            let mut required = vec!["type"];
+            required.extend(properties.keys().map(String::as_str));
+            required[1..].sort_unstable();
+            let mut all = Map::new();
+            all.insert("type".to_string(), json!({ "const": name }));
+            all.extend(properties.clone());
+            object(description, all, required)
+        };
+        let list = |name: &str| {
+            let mut properties = Map::new();
+            properties.insert(name.to_string(), strings.clone());
+            properties
+        };
+        let mut max_amount = Map::new();
+        max_amount.insert("amount".to_string(), amount);
+        let mut time_window = Map::new();
+        time_window.insert("start".to_string(), time_of_day.clone());
+        time_window.insert("end".to_string(), time_of_day);
+        serde_json::from_value(json!({
+            "description": "A restriction on the packets an account may send through the node",
+            "oneOf": [
+                filter("allow_addresses", "Only allows packets addressed to one of the listed addresses", list("addresses")),
+                filter("deny_addresses", "Rejects packets addressed to any of the listed addresses", list("addresses")),
+                filter("allow_prefixes", "Only allows packets addressed to destinations under one of the listed prefixes", list("prefixes")),
+                filter("deny_prefixes", "Rejects packets addressed to destinations under any of the listed prefixes", list("prefixes")),
+                filter("max_amount", "Rejects packets whose amount exceeds the provided one", max_amount),
+                filter("time_window", "Only allows packets during a daily window (in UTC), which spans midnight if it ends before it starts", time_window),
+            ],
+        }))
+        .expect("the schema is valid")
+    }
+}
+
+/// Returns the value with the keys of its objects sorted, so that it is serialized the same
+/// whether or not `serde_json` preserves the order of insertion
+fn sorted(value: Value) -> Value {
+    match value {
+        Value::Object(object) => {
+            let mut entries: Vec<(String, Value)> = object.into_iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+            Value::Object(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (key, sorted(value)))
+                    .collect(),
+            )
+        }
+        Value::Array(values) => Value::Array(values.into_iter().map(sorted).collect()),
+        value => value,
+    }
+}
+
+/// Returns the schema document with the definitions of the generator
+pub fn document(title: &str, mut generator: SchemaGenerator) -> Value {
+    sorted(json!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "title": title,
+        "definitions": generator.take_definitions(),
+    }))
+}
+
+fn doc_comment(schema: &Value, indent: &str) -> String {
+    match schema.get("description").and_then(Value::as_str) {
+        Some(description) => format!("{}/** {} */\n", indent, description),
+        None => String::new(),
+    }
+}
+
+/// Returns the TypeScript type of the values described by the schema
+fn typescript_type(schema: &Value) -> String {
+    if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+        return reference.rsplit('/').next().unwrap_or_default().to_string();
+    }
+    if let Some(constant) = schema.get("const") {
+        return constant.to_string();
+    }
+    if let Some(values) = schema.get("enum").and_then(Value::as_array) {
+        return values
+            .iter()
+            .map(Value::to_string)
+            .collect::<Vec<_>>()
+            .join(" | ");
+    }
+    for keyword in &["allOf", "anyOf", "oneOf"] {
+        if let Some(schemas) = schema.get(*keyword).and_then(Value::as_array) {
+            let separator = if *keyword == "allOf" { " & " } else { " | " };
+            return schemas
+                .iter()
+                .map(typescript_type)
+                .collect::<Vec<_>>()
+                .join(separator);
+        }
+    }
+    if let Some(types) = schema.get("type").and_then(Value::as_array) {
+        return types
+            .iter()
+            .map(|ty| {
+                let mut schema = schema.clone();
+                schema["type"] = ty.clone();
+                typescript_type(&schema)
+            })
+            .collect::<Vec<_>>()
+            .join(" | ");
+    }
+    match schema.get("type").and_then(Value::as_str) {
+        Some("string") => "string".to_string(),
+        Some("integer") | Some("number") => "number".to_string(),
+        Some("boolean") => "boolean".to_string(),
+        Some("null") => "null".to_string(),
+        Some("array") => format!("Array<{}>", typescript_type(&schema["items"])),
+        Some("object") => match schema.get("properties").and_then(Value::as_object) {
+            Some(_) => format!("{{ {} }}", typescript_properties(schema, "").join(" ")),
+            None => format!(
+                "{{ [key: string]: {} }}",
+                typescript_type(&schema["additionalProperties"])
+            ),
+        },
+        _ => "unknown".to_string(),
+    }
+}
+
+/// Returns the TypeScript declarations of the properties of an object
+fn typescript_properties(schema: &Value, indent: &str) -> Vec<String> {
+    let required: Vec<&str> = schema
+        .get("required")
+        .and_then(Value::as_array)
+        .map(|required| required.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default();
+    let mut properties: Vec<(&String, &Value)> = schema["properties"]
+        .as_object()
+        .map(|properties| properties.iter().collect())
+        .unwrap_or_default();
+    properties.sort_by_key(|(name, _)| *name);
+    properties
+        .into_iter()
+        .map(|(name, property)| {
+            let comment = if indent.is_empty() {
+                String::new()
+            } else {
+                doc_comment(property, indent)
+            };
+            let optional = if required.contains(&name.as_str()) {
+                ""
+            } else {
+                "?"
+            };
+            format!(
+                "{}{}{}{}: {};",
+                comment,
+                indent,
+                name,
+                optional,
+                typescript_type(property)
+            )
+        })
+        .collect()
+}
+
+/// Returns the TypeScript declarations of the definitions of the schema document
+pub fn typescript(document: &Value) -> String {
+    let mut declarations = Vec::new();
+    if let Some(definitions) = document["definitions"].as_object() {
+        for (name, definition) in definitions {
+            let mut declaration = doc_comment(definition, "");
+            if definition.get("properties").is_some() {
+                declaration.push_str(&format!("export interface {} {{\n", name));
+                for property in typescript_properties(definition, "  ") {
+                    declaration.push_str(&property);
+                    declaration.push('\n');
+                }
+                declaration.push_str("}\n");
+            } else {
+                declaration.push_str(&format!(
+                    "export type {} = {};\n",
+                    name,
+                    typescript_type(definition)
+                ));
+            }
+            declarations.push(declaration);
+        }
+    }
+    declarations.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema;
+    use interledger_service::Username;
+    use serde::{Deserialize, Serialize};
+
+    /// An item
+    #[derive(Debug, Default, Serialize, Deserialize, JsonSchema)]
+    pub struct Item {
+        /// The name
+        /// of the item
+        pub name: String,
+        #[serde(default)]
+        pub count: u64,
+        pub note: Option<String>,
+        #[schemars(with = "Vec<schema::UsernameSchema>")]
+        pub tags: Vec<Username>,
+    }
+
+    fn definitions() -> Value {
+        let mut generator = generator();
+        generator.subschema_for::<Item>();
+        generator.subschema_for::<PacketFilterSchema>();
+        document("Test", generator)["definitions"].clone()
+    }
+
+    #[test]
+    fn describes_the_structs() {
+        let mut generator = generator();
+        assert_eq!(
+            serde_json::to_value(generator.subschema_for::<Item>()).unwrap(),
+            json!({ "$ref": "#/definitions/Item" })
+        );
+        assert_eq!(
+            definitions()["Item"],
+            json!({
+                "type": "object",
+                "description": "An item",
+                "properties": {
+                    "name": { "type": "string", "description": "The name of the item" },
+                    "count": { "type": "integer", "format": "uint64", "minimum": 0.0, "default": 0 },
+                    "note": { "type": ["string", "null"] },
+                    "tags": { "type": "array", "items": { "type": "string", "pattern": "^\\w{2,32}$" } },
+                },
+                "required": ["name", "tags"],
+            })
+        );
+    }
+
+    #[test]
+    fn generates_typescript() {
+        let mut generator = generator();
+        generator.subschema_for::<Item>();
+        assert_eq!(
+            typescript(&document("Test", generator)),
+            "/** An item */\n\
+             export interface Item {\n  \
+             count?: number;\n  \
+             /** The name of the item */\n  \
+             name: string;\n  \
+             note?: string | null;\n  \
+             tags: Array<string>;\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn describes_the_packet_filters() {
+        assert_eq!(
+            typescript_type(&definitions()["PacketFilter"]),
+            "{ addresses: Array<string>; type: \"allow_addresses\"; } \
+             | { addresses: Array<string>; type: \"deny_addresses\"; } \
+             | { prefixes: Array<string>; type: \"allow_prefixes\"; } \
+             | { prefixes: Array<string>; type: \"deny_prefixes\"; } \
+             | { amount: number; type: \"max_amount\"; } \
+             | { end: string; start: string; type: \"time_window\"; }"
+        );
+    }
+}
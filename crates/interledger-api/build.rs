@@ -0,0 +1,4 @@
+fn main() {
+    // Generates the messages, the server and the client of the gRPC API
+    tonic_build::compile_protos("proto/admin.proto").expect("Unable to compile the gRPC API");
+}
@@ -0,0 +1,168 @@
+use async_trait::async_trait;
+use interledger_api_types::AccountDetails;
+use interledger_errors::{ApiError, NodeStoreError};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// The fields of the account details which templates may set. The others (the username,
+/// the address, and the URLs and credentials of the peer) are different for each account.
+const TEMPLATE_FIELDS: &[&str] = &[
+    "asset_code",
+    "asset_scale",
+    "routing_relation",
+    "max_packet_amount",
+    "max_packet_size",
+    "min_balance",
+    "payable_limit",
+    "receivable_limit",
+    "settle_threshold",
+    "settle_to",
+    "settlement_engine_url",
+    "amount_per_minute_limit",
+    "packets_per_second_limit",
+    "packets_per_minute_limit",
+    "round_trip_time",
+    "route_broadcast_interval",
+    "route_expiry",
+    "route_full_table",
+    "ilp_over_http_dialect",
+    "ilp_over_btp_legacy",
+    "spread",
+    "percentage_fee",
+    "fixed_fee",
+    "packet_filters",
+    "ip_allowlist",
+];
+
+/// Reusable account details, with which similar accounts (such as the peers in an asset)
+/// are created without repeating them
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AccountTemplate {
+    /// The name of the template, such as `peer-xrp`
+    pub name: String,
+    /// The account details set by the template, which the details of the created
+    /// accounts take precedence over
+    pub fields: Map<String, Value>,
+}
+
+impl AccountTemplate {
+    /// Checks that the name can be used in URLs and that the fields are valid account details
+    /// which templates may set
+    pub fn validate(&self) -> Result<(), ApiError> {
+        if self.name.is_empty()
+            || self.name.len() > 64
+            || !self
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(ApiError::bad_request().detail(format!(
+                "invalid template name {:?} (expected up to 64 letters, digits, - or _)",
+                self.name
+            )));
+        }
+        if let Some(field) = self
+            .fields
+            .keys()
+            .find(|field| !TEMPLATE_FIELDS.contains(&field.as_str()))
+        {
+            return Err(ApiError::bad_request().detail(format!(
+                "templates cannot set {} (they can set {})",
+                field,
+                TEMPLATE_FIELDS.join(", ")
+            )));
+        }
+        // The fields are checked as the details of an account, whose required fields
+        // are set if the template does not set them
+        let mut details = Map::new();
+        details.insert("username".to_string(), "template".into());
+        details.insert("asset_code".to_string(), "XYZ".into());
+        details.insert("asset_scale".to_string(), 0.into());
+        serde_json::to_vec(&self.apply(details))
+            .and_then(|json| serde_json::from_slice::<AccountDetails>(&json).map(|_| ()))
+            .map_err(|err| ApiError::bad_request().detail(format!("invalid field: {}", err)))?;
+        Ok(())
+    }
+
+    /// Returns the account details with the fields of the template which they do not set
+    pub fn apply(&self, mut details: Map<String, Value>) -> Map<String, Value> {
+        for (field, value) in &self.fields {
+            details
+                .entry(field.clone())
+                .or_insert_with(|| value.clone());
+        }
+        details
+    }
+}
+
+/// Store of the account templates of the node
+#[async_trait]
+pub trait AccountTemplateStore {
+    /// Returns the template with the name, if there is one
+    async fn get_account_template(
+        &self,
+        name: &str,
+    ) -> Result<Option<AccountTemplate>, NodeStoreError>;
+
+    /// Returns all the templates, ordered by name
+    async fn get_account_templates(&self) -> Result<Vec<AccountTemplate>, NodeStoreError>;
+
+    /// Saves the template, replacing the one with the same name
+    async fn set_account_template(&self, template: AccountTemplate) -> Result<(), NodeStoreError>;
+
+    /// Deletes the template with the name and returns it
+    async fn delete_account_template(
+        &self,
+        name: &str,
+    ) -> Result<Option<AccountTemplate>, NodeStoreError>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn template(fields: Value) -> AccountTemplate {
+        AccountTemplate {
+            name: "peer-xrp".to_string(),
+            fields: fields.as_object().unwrap().clone(),
+        }
+    }
+
+    #[test]
+    fn applies_the_fields_the_details_do_not_set() {
+        let template = template(json!({
+            "asset_code": "XRP",
+            "asset_scale": 9,
+            "routing_relation": "Peer",
+            "settle_threshold": 1000,
+        }));
+        template.validate().unwrap();
+        let details = json!({"username": "bob", "settle_threshold": 500});
+        let details = template.apply(details.as_object().unwrap().clone());
+        assert_eq!(
+            Value::Object(details),
+            json!({
+                "username": "bob",
+                "asset_code": "XRP",
+                "asset_scale": 9,
+                "routing_relation": "Peer",
+                "settle_threshold": 500,
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_templates() {
+        assert!(template(json!({"username": "bob"})).validate().is_err());
+        assert!(template(json!({"ilp_over_http_incoming_token": "secret"}))
+            .validate()
+            .is_err());
+        assert!(template(json!({"settle_threshold": "a lot"}))
+            .validate()
+            .is_err());
+        let mut invalid_name = template(json!({}));
+        invalid_name.name = "peer/xrp".to_string();
+        assert!(invalid_name.validate().is_err());
+    }
+}
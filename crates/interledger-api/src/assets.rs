@@ -0,0 +1,207 @@
+use crate::number_or_string;
+use async_trait::async_trait;
+use interledger_errors::{ApiError, NodeStoreError};
+use serde::{Deserialize, Serialize};
+
+/// Largest asset scale of the amounts of the node, which are 64-bit integers
+const MAX_ASSET_SCALE: u8 = 18;
+
+/// How the amounts of an asset are rounded to the decimals they are displayed with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Rounding {
+    /// To the nearest decimal, away from zero on ties
+    #[default]
+    HalfUp,
+    /// Towards zero
+    Down,
+    /// Away from zero
+    Up,
+}
+
+/// An asset known to the node, which the accounts may be denominated in
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Asset {
+    /// The code of the asset, such as `USD` or `XRP`
+    pub code: String,
+    /// The smallest asset scale of the accounts denominated in the asset
+    #[serde(default, deserialize_with = "number_or_string")]
+    pub min_scale: u8,
+    /// The largest asset scale of the accounts denominated in the asset
+    #[serde(deserialize_with = "number_or_string")]
+    pub max_scale: u8,
+    /// The symbol the amounts are displayed with, such as `$`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub symbol: Option<String>,
+    /// The number of decimals the amounts are displayed with
+    #[serde(deserialize_with = "number_or_string")]
+    pub display_scale: u8,
+    /// How the amounts are rounded to the displayed decimals
+    #[serde(default)]
+    pub rounding: Rounding,
+}
+
+impl Asset {
+    /// Checks that the code can be used by accounts and that the scales can be represented
+    pub fn validate(&self) -> Result<(), ApiError> {
+        if self.code.is_empty()
+            || self.code.len() > 12
+            || !self.code.chars().all(|c| c.is_ascii_alphanumeric())
+        {
+            return Err(ApiError::bad_request().detail(format!(
+                "invalid asset code {:?} (expected up to 12 letters or digits)",
+                self.code
+            )));
+        }
+        if self.min_scale > self.max_scale || self.max_scale > MAX_ASSET_SCALE {
+            return Err(ApiError::bad_request().detail(format!(
+                "invalid scale bounds {}-{} (expected a min scale no higher than the max scale, which is at most {})",
+                self.min_scale, self.max_scale, MAX_ASSET_SCALE
+            )));
+        }
+        if self.display_scale > MAX_ASSET_SCALE {
+            return Err(ApiError::bad_request().detail(format!(
+                "the display scale cannot exceed {}",
+                MAX_ASSET_SCALE
+            )));
+        }
+        Ok(())
+    }
+
+    /// Returns the amount, in units of the asset scale, as displayed to the users,
+    /// such as `$12.35` or `-0.500 XRP`
+    pub fn display(&self, amount: i128, asset_scale: u8) -> String {
+        let magnitude = if self.display_scale >= asset_scale {
+            amount
+                .abs()
+                .saturating_mul(10_i128.pow(u32::from(self.display_scale - asset_scale)))
+        } else {
+            let divisor = 10_i128.pow(u32::from(asset_scale - self.display_scale));
+            let (quotient, remainder) = (amount.abs() / divisor, amount.abs() % divisor);
+            let round_up = match self.rounding {
+                Rounding::HalfUp => remainder * 2 >= divisor,
+                Rounding::Down => false,
+                Rounding::Up => remainder > 0,
+            };
+            quotient + i128::from(round_up)
+        };
+        let unit = 10_i128.pow(u32::from(self.display_scale));
+        let mut number = (magnitude / unit).to_string();
+        if self.display_scale > 0 {
+            number = format!(
+                "{}.{:0width$}",
+                number,
+                magnitude % unit,
+                width = usize::from(self.display_scale)
+            );
+        }
+        let sign = if amount < 0 && magnitude > 0 { "-" } else { "" };
+        match self.symbol {
+            Some(ref symbol) => format!("{}{}{}", sign, symbol, number),
+            None => format!("{}{} {}", sign, number, self.code),
+        }
+    }
+}
+
+/// Checks that the accounts can be denominated in the asset code and scale. Any asset is
+/// accepted until the first one is registered.
+pub(crate) async fn check_account_asset<S>(
+    store: &S,
+    asset_code: &str,
+    asset_scale: u8,
+) -> Result<(), ApiError>
+where
+    S: AssetStore,
+{
+    let assets = store.get_assets().await?;
+    if assets.is_empty() {
+        return Ok(());
+    }
+    let asset = assets
+        .iter()
+        .find(|asset| asset.code == asset_code)
+        .ok_or_else(|| {
+            let codes: Vec<&str> = assets.iter().map(|asset| asset.code.as_str()).collect();
+            ApiError::bad_request().detail(format!(
+                "unknown asset code {} (the registered assets are {})",
+                asset_code,
+                codes.join(", ")
+            ))
+        })?;
+    if asset_scale < asset.min_scale || asset_scale > asset.max_scale {
+        return Err(ApiError::bad_request().detail(format!(
+            "the scale of {} accounts must be between {} and {}",
+            asset.code, asset.min_scale, asset.max_scale
+        )));
+    }
+    Ok(())
+}
+
+/// Store of the assets registered on the node
+#[async_trait]
+pub trait AssetStore {
+    /// Returns the asset with the code, if it is registered
+    async fn get_asset(&self, code: &str) -> Result<Option<Asset>, NodeStoreError>;
+
+    /// Returns all registered assets, ordered by code
+    async fn get_assets(&self) -> Result<Vec<Asset>, NodeStoreError>;
+
+    /// Saves the asset, replacing the one with the same code
+    async fn set_asset(&self, asset: Asset) -> Result<(), NodeStoreError>;
+
+    /// Deletes the asset with the code and returns it
+    async fn delete_asset(&self, code: &str) -> Result<Option<Asset>, NodeStoreError>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn asset(symbol: Option<&str>, display_scale: u8, rounding: Rounding) -> Asset {
+        Asset {
+            code: "USD".to_string(),
+            min_scale: 2,
+            max_scale: 9,
+            symbol: symbol.map(str::to_string),
+            display_scale,
+            rounding,
+        }
+    }
+
+    #[test]
+    fn displays_rounded_amounts() {
+        let usd = asset(Some("$"), 2, Rounding::HalfUp);
+        assert_eq!(usd.display(12_345, 3), "$12.35");
+        assert_eq!(usd.display(-12_345, 3), "-$12.35");
+        assert_eq!(usd.display(12_344, 3), "$12.34");
+        assert_eq!(usd.display(-4, 3), "$0.00");
+        assert_eq!(usd.display(5, 0), "$5.00");
+        assert_eq!(
+            asset(Some("$"), 2, Rounding::Down).display(12_349, 3),
+            "$12.34"
+        );
+        assert_eq!(
+            asset(Some("$"), 2, Rounding::Up).display(12_341, 3),
+            "$12.35"
+        );
+        assert_eq!(asset(None, 0, Rounding::HalfUp).display(1_500, 3), "2 USD");
+        assert_eq!(
+            asset(None, 3, Rounding::HalfUp).display(i64::MIN.into(), 9),
+            "-9223372036.855 USD"
+        );
+    }
+
+    #[test]
+    fn validates_codes_and_scales() {
+        assert!(asset(None, 2, Rounding::HalfUp).validate().is_ok());
+        let mut invalid = asset(None, 2, Rounding::HalfUp);
+        invalid.code = "US D".to_string();
+        assert!(invalid.validate().is_err());
+        let mut invalid = asset(None, 2, Rounding::HalfUp);
+        invalid.min_scale = 10;
+        assert!(invalid.validate().is_err());
+        let mut invalid = asset(None, 2, Rounding::HalfUp);
+        invalid.max_scale = 19;
+        assert!(invalid.validate().is_err());
+    }
+}
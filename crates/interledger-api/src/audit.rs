@@ -0,0 +1,249 @@
+use crate::jwt::{admin_actor, JwtAuth};
+use crate::optional_number_or_string;
+use async_trait::async_trait;
+use interledger_errors::{ApiError, NodeStoreError};
+use secrecy::{ExposeSecret, SecretString};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::error;
+use warp::{filters::path::FullPath, http::Method, Filter, Rejection};
+
+/// Number of entries returned by `GET /audit` if the query does not set a limit
+pub(crate) const DEFAULT_AUDIT_LIMIT: usize = 100;
+/// Largest number of entries returned by a single `GET /audit` query
+pub(crate) const MAX_AUDIT_LIMIT: usize = 1000;
+/// Value which replaces the tokens and secrets of the recorded request bodies
+const REDACTED: &str = "[redacted]";
+
+/// A mutating admin API call, recorded in the audit log once it succeeded
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// Sequence number of the entry, assigned by the store when it is appended
+    #[serde(default)]
+    pub id: u64,
+    /// When the call was made, in milliseconds since the UNIX epoch
+    pub timestamp: u64,
+    /// Who made the call: `admin` for the admin's token, or `jwt:<subject>` for a JWT
+    pub actor: String,
+    pub method: String,
+    pub path: String,
+    /// Body of the call (the requested change), with its tokens and secrets redacted
+    #[serde(default)]
+    pub request: Value,
+}
+
+/// Selects the entries of the audit log returned by `GET /audit`
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct AuditQuery {
+    /// Only returns the entries recorded at or after this time (in milliseconds since the UNIX epoch)
+    #[serde(default, deserialize_with = "optional_number_or_string")]
+    pub since: Option<u64>,
+    /// Only returns the entries recorded at or before this time (in milliseconds since the UNIX epoch)
+    #[serde(default, deserialize_with = "optional_number_or_string")]
+    pub until: Option<u64>,
+    /// Max number of entries returned
+    #[serde(default, deserialize_with = "optional_number_or_string")]
+    pub limit: Option<usize>,
+}
+
+/// Store of the append-only audit log of the mutating admin API calls
+#[async_trait]
+pub trait AuditLogStore {
+    /// Appends the entry to the audit log and returns it with its sequence number.
+    /// Entries are never modified, but the store may delete the ones which are
+    /// older than its retention period.
+    async fn append_audit_entry(&self, entry: AuditEntry) -> Result<AuditEntry, NodeStoreError>;
+
+    /// Returns the entries matching the query, newest first
+    async fn get_audit_entries(&self, query: AuditQuery)
+        -> Result<Vec<AuditEntry>, NodeStoreError>;
+}
+
+/// An API call authorized as the admin's, which is recorded in the audit log once it succeeded
+#[derive(Debug, Clone)]
+pub(crate) struct AdminCall {
+    actor: String,
+    method: Method,
+    path: String,
+    timestamp: u64,
+}
+
+impl AdminCall {
+    /// Returns the call authorized as the actor's, made now
+    pub(crate) fn new(actor: String, method: Method, path: String) -> Self {
+        AdminCall {
+            actor,
+            method,
+            path,
+            timestamp: audit_timestamp(),
+        }
+    }
+
+    /// Records the call with its request body in the audit log. The call has taken
+    /// effect already, so errors writing the entry are logged rather than returned.
+    pub(crate) async fn record<S, T>(self, store: &S, request: &T)
+    where
+        S: AuditLogStore,
+        T: Serialize,
+    {
+        let mut request = serde_json::to_value(request).unwrap_or(Value::Null);
+        redact(&mut request);
+        let entry = AuditEntry {
+            id: 0,
+            timestamp: self.timestamp,
+            actor: self.actor,
+            method: self.method.to_string(),
+            path: self.path,
+            request,
+        };
+        if let Err(err) = store.append_audit_entry(entry.clone()).await {
+            error!(
+                "Error recording admin API call in the audit log: {} (call: {:?})",
+                err, entry
+            );
+        }
+    }
+}
+
+/// Extracts the call if the request is authorized with the admin's token or with a JWT
+/// with the admin scope, or None if it is not
+pub(crate) fn optional_admin_call(
+    admin_auth_header: String,
+    jwt_auth: Option<JwtAuth>,
+) -> impl Filter<Extract = (Option<AdminCall>,), Error = Rejection> + Clone {
+    warp::header::<SecretString>("authorization")
+        .and(warp::method())
+        .and(warp::path::full())
+        .and_then(
+            move |authorization: SecretString, method: Method, path: FullPath| {
+                let admin_auth_header = admin_auth_header.clone();
+                let jwt_auth = jwt_auth.clone();
+                async move {
+                    let actor =
+                        admin_actor(authorization.expose_secret(), &admin_auth_header, &jwt_auth)
+                            .await;
+                    Ok::<_, Rejection>(
+                        actor.map(|actor| AdminCall::new(actor, method, path.as_str().to_string())),
+                    )
+                }
+            },
+        )
+}
+
+/// Extracts the call if the request is the admin's, and rejects it otherwise.
+/// The routes guard the mutating calls of the admin with it, so that they are recorded in
+/// the audit log
+pub(crate) fn admin_call(
+    admin_auth_header: String,
+    jwt_auth: Option<JwtAuth>,
+) -> impl Filter<Extract = (AdminCall,), Error = Rejection> + Clone {
+    optional_admin_call(admin_auth_header, jwt_auth).and_then(
+        |call: Option<AdminCall>| async move {
+            call.ok_or_else(|| {
+                Rejection::from(
+                    ApiError::unauthorized().detail("invalid admin auth token provided"),
+                )
+            })
+        },
+    )
+}
+
+/// Returns the limit of a query, capped to the max number of entries per query
+pub(crate) fn audit_limit(query: &AuditQuery) -> usize {
+    query
+        .limit
+        .unwrap_or(DEFAULT_AUDIT_LIMIT)
+        .min(MAX_AUDIT_LIMIT)
+}
+
+fn audit_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Replaces the values of the fields which hold tokens or secrets
+pub(crate) fn redact(value: &mut Value) {
+    match value {
+        Value::Object(fields) => {
+            for (name, value) in fields.iter_mut() {
+                if is_secret(name) && !value.is_null() {
+                    *value = Value::String(REDACTED.to_string());
+                } else {
+                    redact(value);
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(redact),
+        _ => {}
+    }
+}
+
+fn is_secret(name: &str) -> bool {
+    let name = name.to_lowercase();
+    name.ends_with("token") || name.contains("secret") || name.contains("password")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use warp::test::request;
+
+    #[test]
+    fn redacts_tokens_and_secrets() {
+        let mut body = json!({
+            "username": "alice",
+            "ilp_over_http_incoming_token": "password",
+            "ilp_over_btp_outgoing_token": null,
+            "settlement": { "client_secret": "secret" },
+            "packet_filters": [{ "type": "max_amount", "amount": 100 }],
+        });
+        redact(&mut body);
+        assert_eq!(
+            body,
+            json!({
+                "username": "alice",
+                "ilp_over_http_incoming_token": REDACTED,
+                "ilp_over_btp_outgoing_token": null,
+                "settlement": { "client_secret": REDACTED },
+                "packet_filters": [{ "type": "max_amount", "amount": 100 }],
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn extracts_admin_calls() {
+        let filter = admin_call("Bearer admin".to_string(), None);
+        let call = request()
+            .method("PUT")
+            .path("/accounts/alice")
+            .header("Authorization", "Bearer admin")
+            .filter(&filter)
+            .await
+            .unwrap();
+        assert_eq!(call.actor, "admin");
+        assert_eq!(call.method, Method::PUT);
+        assert_eq!(call.path, "/accounts/alice");
+
+        assert!(request()
+            .method("PUT")
+            .path("/accounts/alice")
+            .header("Authorization", "Bearer password")
+            .filter(&filter)
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn caps_query_limits() {
+        assert_eq!(audit_limit(&AuditQuery::default()), DEFAULT_AUDIT_LIMIT);
+        let query = AuditQuery {
+            limit: Some(MAX_AUDIT_LIMIT + 1),
+            ..AuditQuery::default()
+        };
+        assert_eq!(audit_limit(&query), MAX_AUDIT_LIMIT);
+    }
+}
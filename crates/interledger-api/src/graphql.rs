@@ -0,0 +1,824 @@
+//! The schema of the `/graphql` endpoint, over the accounts, balances, settlements, routes,
+//! rates and payments of the node.
+//!
+//! The queries are executed by [juniper](https://docs.rs/juniper/0.14.2), whose resolvers
+//! are synchronous: they run on a blocking thread, which waits there for the store. Only the
+//! selected fields are read from the store, so that a dashboard gets everything it shows in
+//! a single request. The lists are paginated with `first` and `after` (the `endCursor` of the
+//! previous page), and return their `nodes`, `totalCount` and `pageInfo`. The payments are
+//! the ones the node received since it started, up to the capacity of its
+//! [`RecentPayments`](./struct.RecentPayments.html).
+
+use crate::NodeStore;
+use interledger_ccp::CcpRoutingAccount;
+use interledger_rates::ExchangeRateStore;
+use interledger_router::RouterStore;
+use interledger_service::{Account as _, AccountStore, Username};
+use interledger_service_util::{BalanceStore, Liquidity, LiquidityStore};
+use interledger_settlement::core::types::SettlementAccount;
+use interledger_stream::{PaymentNotification, StreamNotificationsStore};
+use juniper::{
+    graphql_scalar,
+    http::GraphQLRequest,
+    parser::{ParseError, ScalarToken, Token},
+    EmptyMutation, FieldError, FieldResult, GraphQLScalarValue, InputValue, LookAheadMethods,
+    ParseScalarResult, RootNode, ScalarValue, Value,
+};
+use once_cell::sync::OnceCell;
+use serde::{de, Deserialize};
+use serde_json::json;
+use std::{
+    collections::VecDeque,
+    convert::TryFrom,
+    fmt,
+    future::Future,
+    marker::PhantomData,
+    sync::{Arc, Mutex},
+};
+use tokio::{runtime::Handle, sync::broadcast::RecvError};
+use tracing::warn;
+use uuid::Uuid;
+
+/// Number of payments the API of the node keeps for the queries
+pub const RECENT_PAYMENTS_CAPACITY: usize = 1000;
+/// Number of items of a page if `first` is not set
+const DEFAULT_PAGE_SIZE: i32 = 100;
+/// Max number of items of a page
+const MAX_PAGE_SIZE: i32 = 500;
+/// Max number of nested selection sets, lists and objects of a document, since the parser
+/// of juniper is recursive
+const MAX_DEPTH: usize = 64;
+
+#[derive(Debug, Default)]
+struct Payments {
+    capacity: usize,
+    /// Sequence number of the next payment, which is its cursor
+    next_id: u64,
+    /// The payments, oldest first
+    payments: VecDeque<(u64, PaymentNotification)>,
+}
+
+/// The latest payments received by the accounts of the node, which are kept in memory
+/// for the `payments` queries
+#[derive(Debug, Clone)]
+pub struct RecentPayments(Arc<Mutex<Payments>>);
+
+impl RecentPayments {
+    /// Keeps up to `capacity` payments, the oldest ones being dropped first
+    pub fn new(capacity: usize) -> Self {
+        RecentPayments(Arc::new(Mutex::new(Payments {
+            capacity,
+            ..Payments::default()
+        })))
+    }
+
+    pub fn push(&self, payment: PaymentNotification) {
+        let mut payments = self.0.lock().unwrap();
+        if payments.capacity == 0 {
+            return;
+        }
+        if payments.payments.len() == payments.capacity {
+            payments.payments.pop_front();
+        }
+        let id = payments.next_id;
+        payments.next_id += 1;
+        payments.payments.push_back((id, payment));
+    }
+
+    /// Keeps the payments published by the store from now on. This spawns a task,
+    /// so it must be called from a Tokio runtime.
+    pub fn record<S: StreamNotificationsStore>(&self, store: &S) {
+        let mut notifications = store.all_payment_subscription();
+        let payments = self.clone();
+        tokio::spawn(async move {
+            loop {
+                match notifications.recv().await {
+                    Ok(payment) => payments.push(payment),
+                    Err(RecvError::Lagged(skipped)) => warn!(
+                        "Skipped {} payment notifications, which are not listed by the GraphQL queries",
+                        skipped
+                    ),
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+
+    /// Returns the payments (of the account, if it is set), newest first
+    fn list(&self, username: Option<&Username>) -> Vec<(u64, PaymentNotification)> {
+        let payments = self.0.lock().unwrap();
+        payments
+            .payments
+            .iter()
+            .rev()
+            .filter(|(_, payment)| {
+                username.map_or(true, |username| payment.to_username == *username)
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the page of the payments (of the account, if it is set)
+    fn page(
+        &self,
+        username: Option<&Username>,
+        first: Option<i32>,
+        after: Option<String>,
+    ) -> FieldResult<Option<PaymentConnection>, Scalar> {
+        let payments = self
+            .list(username)
+            .into_iter()
+            .map(|(id, payment)| {
+                let id = id.to_string();
+                (id.clone(), Payment { id, payment })
+            })
+            .collect();
+        // The payments are listed newest first
+        let page = Page::new(payments, first, after, |cursor, after| {
+            match (cursor.parse::<u64>(), after.parse::<u64>()) {
+                (Ok(cursor), Ok(after)) => cursor < after,
+                _ => true,
+            }
+        })?;
+        Ok(Some(PaymentConnection(page)))
+    }
+}
+
+/// The scalar values of the schema: those of GraphQL, and the 64-bit integers of the
+/// amounts (`Long`)
+#[derive(Debug, Clone, PartialEq, GraphQLScalarValue)]
+pub enum Scalar {
+    Int(i32),
+    Long(i64),
+    Float(f64),
+    String(String),
+    Boolean(bool),
+}
+
+impl ScalarValue for Scalar {
+    type Visitor = ScalarVisitor;
+
+    fn as_int(&self) -> Option<i32> {
+        match *self {
+            Scalar::Int(int) => Some(int),
+            _ => None,
+        }
+    }
+
+    fn as_string(&self) -> Option<String> {
+        match *self {
+            Scalar::String(ref string) => Some(string.clone()),
+            _ => None,
+        }
+    }
+
+    fn as_float(&self) -> Option<f64> {
+        match *self {
+            Scalar::Int(int) => Some(f64::from(int)),
+            Scalar::Float(float) => Some(float),
+            _ => None,
+        }
+    }
+
+    fn as_boolean(&self) -> Option<bool> {
+        match *self {
+            Scalar::Boolean(boolean) => Some(boolean),
+            _ => None,
+        }
+    }
+}
+
+/// Deserializes the scalars of the variables
+#[derive(Default)]
+pub struct ScalarVisitor;
+
+impl<'de> de::Visitor<'de> for ScalarVisitor {
+    type Value = Scalar;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a valid input value")
+    }
+
+    fn visit_bool<E>(self, value: bool) -> Result<Scalar, E> {
+        Ok(Scalar::Boolean(value))
+    }
+
+    fn visit_i64<E>(self, value: i64) -> Result<Scalar, E> {
+        Ok(i32::try_from(value).map_or(Scalar::Long(value), Scalar::Int))
+    }
+
+    fn visit_u64<E>(self, value: u64) -> Result<Scalar, E> {
+        // The integers which do not fit in a `Long` are only valid as floats
+        Ok(
+            i64::try_from(value).map_or(Scalar::Float(value as f64), |value| {
+                i32::try_from(value).map_or(Scalar::Long(value), Scalar::Int)
+            }),
+        )
+    }
+
+    fn visit_f64<E>(self, value: f64) -> Result<Scalar, E> {
+        Ok(Scalar::Float(value))
+    }
+
+    fn visit_str<E>(self, value: &str) -> Result<Scalar, E> {
+        Ok(Scalar::String(value.to_string()))
+    }
+
+    fn visit_string<E>(self, value: String) -> Result<Scalar, E> {
+        Ok(Scalar::String(value))
+    }
+}
+
+graphql_scalar!(i64 as "Long" where Scalar = Scalar {
+    description: "A 64-bit integer, such as an amount"
+
+    resolve(&self) -> Value {
+        Value::scalar(*self)
+    }
+
+    from_input_value(value: &InputValue) -> Option<i64> {
+        match *value {
+            InputValue::Scalar(Scalar::Int(int)) => Some(i64::from(int)),
+            InputValue::Scalar(Scalar::Long(long)) => Some(long),
+            _ => None,
+        }
+    }
+
+    from_str<'a>(value: ScalarToken<'a>) -> ParseScalarResult<'a, Scalar> {
+        match value {
+            ScalarToken::Int(int) => int
+                .parse::<i64>()
+                .map(Scalar::from)
+                .map_err(|_| ParseError::UnexpectedToken(Token::Scalar(value))),
+            _ => Err(ParseError::UnexpectedToken(Token::Scalar(value))),
+        }
+    }
+});
+
+/// Returns an amount as a `Long`
+fn long(amount: u64) -> FieldResult<Option<i64>, Scalar> {
+    match i64::try_from(amount) {
+        Ok(amount) => Ok(Some(amount)),
+        Err(_) => Err(FieldError::new(
+            "The amount does not fit in a Long",
+            Value::null(),
+        )),
+    }
+}
+
+/// The stores whose data the queries read
+pub trait QueryStore:
+    NodeStore<Account = <Self as QueryStore>::Account>
+    + AccountStore<Account = <Self as QueryStore>::Account>
+    + BalanceStore
+    + LiquidityStore
+    + RouterStore
+    + ExchangeRateStore
+{
+    type Account: CcpRoutingAccount + SettlementAccount + Send + Sync + 'static;
+}
+
+impl<S, A> QueryStore for S
+where
+    S: NodeStore<Account = A>
+        + AccountStore<Account = A>
+        + BalanceStore
+        + LiquidityStore
+        + RouterStore
+        + ExchangeRateStore,
+    A: CcpRoutingAccount + SettlementAccount + Send + Sync + 'static,
+{
+    type Account = A;
+}
+
+/// The store and the payments the queries read
+pub struct Context<S> {
+    store: S,
+    payments: RecentPayments,
+    runtime: Handle,
+}
+
+impl<S> juniper::Context for Context<S> {}
+
+impl<S> Context<S> {
+    /// Creates the context of a query, which waits for the store on the current runtime.
+    /// The query must then be executed on a blocking thread of the runtime.
+    pub fn new(store: S, payments: RecentPayments) -> Self {
+        Context {
+            store,
+            payments,
+            runtime: Handle::current(),
+        }
+    }
+
+    fn wait<F: Future>(&self, future: F) -> F::Output {
+        self.runtime.block_on(future)
+    }
+}
+
+/// The schema of the queries (which have no mutations)
+pub type Schema<S> = RootNode<'static, Query<S>, EmptyMutation<Context<S>>, Scalar>;
+
+pub fn schema<S: QueryStore>() -> Schema<S> {
+    RootNode::new(Query(PhantomData), EmptyMutation::new())
+}
+
+/// A GraphQL request, as the body of `POST /graphql` (or the query of `GET /graphql`)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GraphqlRequest {
+    pub query: String,
+    #[serde(default)]
+    pub operation_name: Option<String>,
+    #[serde(default)]
+    pub variables: Option<InputValue<Scalar>>,
+}
+
+/// Executes the query, and returns whether it is valid, with the body of the response: the
+/// `data`, with the `errors` of the fields which could not be resolved, or only the `errors`
+/// if the query is invalid. The resolvers wait for the store, so this must run on a blocking
+/// thread of the runtime the context was created on.
+pub fn execute<S: QueryStore>(
+    schema: &Schema<S>,
+    context: &Context<S>,
+    request: GraphqlRequest,
+) -> (bool, serde_json::Value) {
+    if let Err(message) = check_depth(&request.query) {
+        return (false, json!({ "errors": [{ "message": message }] }));
+    }
+    let request = GraphQLRequest::new(request.query, request.operation_name, request.variables);
+    let response = request.execute(schema, context);
+    let body = serde_json::to_value(&response)
+        .unwrap_or_else(|err| json!({ "errors": [{ "message": err.to_string() }] }));
+    (response.is_ok(), body)
+}
+
+/// Checks that the document is not nested more than the parser supports (counting its
+/// selection sets, and the lists and objects of its values), before parsing it
+fn check_depth(query: &str) -> Result<(), String> {
+    let mut depth = 0;
+    let mut chars = query.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' | '[' | '(' => {
+                depth += 1;
+                if depth > MAX_DEPTH {
+                    return Err(format!(
+                        "Syntax error: the document is nested more than {} levels deep",
+                        MAX_DEPTH
+                    ));
+                }
+            }
+            '}' | ']' | ')' => depth = depth.saturating_sub(1),
+            '#' => {
+                chars.by_ref().find(|c| *c == '\n' || *c == '\r');
+            }
+            '"' => {
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => {
+                            chars.next();
+                        }
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// A page of a list
+struct Page<T> {
+    nodes: Vec<T>,
+    end_cursor: Option<String>,
+    total: usize,
+    has_next: bool,
+}
+
+impl<T> Page<T> {
+    /// Returns the page of the items (which are ordered by their cursors, as `is_after`
+    /// compares them) selected by the `first` and `after` arguments of the list
+    fn new(
+        items: Vec<(String, T)>,
+        first: Option<i32>,
+        after: Option<String>,
+        is_after: fn(&str, &str) -> bool,
+    ) -> FieldResult<Self, Scalar> {
+        let first = first.unwrap_or(DEFAULT_PAGE_SIZE);
+        if !(0..=MAX_PAGE_SIZE).contains(&first) {
+            return Err(FieldError::new(
+                format!(
+                    "Argument \"first\" must be an integer between 0 and {}",
+                    MAX_PAGE_SIZE
+                ),
+                Value::null(),
+            ));
+        }
+        let total = items.len();
+        let mut items: Vec<(String, T)> = items
+            .into_iter()
+            .skip_while(|(cursor, _)| {
+                after
+                    .as_ref()
+                    .map_or(false, |after| !is_after(cursor, after))
+            })
+            .collect();
+        let has_next = items.len() > first as usize;
+        items.truncate(first as usize);
+        Ok(Page {
+            end_cursor: items.last().map(|(cursor, _)| cursor.clone()),
+            nodes: items.into_iter().map(|(_, node)| node).collect(),
+            total,
+            has_next,
+        })
+    }
+
+    fn total_count(&self) -> i32 {
+        self.total as i32
+    }
+
+    fn page_info(&self) -> PageInfo {
+        PageInfo {
+            has_next_page: self.has_next,
+            end_cursor: self.end_cursor.clone(),
+        }
+    }
+}
+
+pub struct PageInfo {
+    has_next_page: bool,
+    end_cursor: Option<String>,
+}
+
+#[juniper::object(Scalar = Scalar)]
+impl PageInfo {
+    /// Whether there are items after this page
+    fn has_next_page(&self) -> bool {
+        self.has_next_page
+    }
+
+    /// The cursor of the last item of the page, which is the `after` argument of the next
+    /// page
+    fn end_cursor(&self) -> Option<&str> {
+        self.end_cursor.as_deref()
+    }
+}
+
+pub struct Query<S>(PhantomData<fn() -> S>);
+
+#[juniper::object(Context = Context<S>, Scalar = Scalar)]
+impl<S: QueryStore> Query<S> {
+    /// The accounts of the node, sorted by username
+    fn accounts(
+        context: &Context<S>,
+        first: Option<i32>,
+        after: Option<String>,
+    ) -> FieldResult<Option<AccountConnection<S>>, Scalar> {
+        let mut accounts = context.wait(context.store.get_all_accounts())?;
+        accounts.sort_by(|a, b| a.username().cmp(b.username()));
+        let accounts = accounts
+            .into_iter()
+            .map(|account| (account.username().to_string(), Account::new(account)))
+            .collect();
+        let page = Page::new(accounts, first, after, |cursor, after| cursor > after)?;
+        Ok(Some(AccountConnection(page)))
+    }
+
+    /// The account with the username
+    fn account(context: &Context<S>, username: String) -> FieldResult<Option<Account<S>>, Scalar> {
+        let username: Username = username
+            .parse()
+            .map_err(|_| FieldError::new("Invalid username", Value::null()))?;
+        let id = context.wait(context.store.get_account_id_from_username(&username))?;
+        let account = context
+            .wait(context.store.get_accounts(vec![id]))?
+            .pop()
+            .ok_or_else(|| FieldError::new("Account not found", Value::null()))?;
+        Ok(Some(Account::new(account)))
+    }
+
+    /// The routes of the node, sorted by prefix
+    fn routes(
+        executor: &Executor,
+        context: &Context<S>,
+        first: Option<i32>,
+        after: Option<String>,
+    ) -> FieldResult<Option<RouteConnection<S>>, Scalar> {
+        let table = context.store.routing_table();
+        let mut routes: Vec<(String, Uuid)> = table
+            .iter()
+            .map(|(prefix, id)| (prefix.clone(), *id))
+            .collect();
+        routes.sort();
+        let routes = routes
+            .into_iter()
+            .map(|(prefix, id)| {
+                let route = Route {
+                    prefix: prefix.clone(),
+                    id,
+                    account: None,
+                };
+                (prefix, route)
+            })
+            .collect();
+        let mut page = Page::new(routes, first, after, |cursor, after| cursor > after)?;
+        // The accounts of the page are read at once, rather than route by route
+        let selects_accounts = executor
+            .look_ahead()
+            .select_child("nodes")
+            .map_or(false, |nodes| nodes.has_child("account"));
+        if selects_accounts {
+            let ids = page.nodes.iter().map(|route| route.id).collect();
+            let accounts = context.wait(context.store.get_accounts(ids))?;
+            for (route, account) in page.nodes.iter_mut().zip(accounts) {
+                route.account = Some(account);
+            }
+        }
+        Ok(Some(RouteConnection(page)))
+    }
+
+    /// The exchange rates, sorted by asset code
+    fn rates(context: &Context<S>) -> FieldResult<Option<Vec<Rate>>, Scalar> {
+        let mut rates: Vec<Rate> = context
+            .store
+            .get_all_exchange_rates()?
+            .into_iter()
+            .map(|(asset_code, rate)| Rate { asset_code, rate })
+            .collect();
+        rates.sort_by(|a, b| a.asset_code.cmp(&b.asset_code));
+        Ok(Some(rates))
+    }
+
+    /// The payments received by the accounts of the node (or by an account) since it
+    /// started, newest first
+    fn payments(
+        context: &Context<S>,
+        first: Option<i32>,
+        after: Option<String>,
+        username: Option<String>,
+    ) -> FieldResult<Option<PaymentConnection>, Scalar> {
+        let username = match username {
+            Some(username) => Some(
+                username
+                    .parse::<Username>()
+                    .map_err(|_| FieldError::new("Invalid username", Value::null()))?,
+            ),
+            None => None,
+        };
+        context.payments.page(username.as_ref(), first, after)
+    }
+}
+
+pub struct AccountConnection<S: QueryStore>(Page<Account<S>>);
+
+#[juniper::object(Context = Context<S>, Scalar = Scalar)]
+impl<S: QueryStore> AccountConnection<S> {
+    fn nodes(&self) -> &[Account<S>] {
+        &self.0.nodes
+    }
+
+    fn total_count(&self) -> i32 {
+        self.0.total_count()
+    }
+
+    fn page_info(&self) -> PageInfo {
+        self.0.page_info()
+    }
+}
+
+pub struct Account<S: QueryStore> {
+    account: <S as QueryStore>::Account,
+    /// The liquidity is read once for all the fields of the settlement
+    liquidity: OnceCell<Liquidity>,
+}
+
+impl<S: QueryStore> Account<S> {
+    fn new(account: <S as QueryStore>::Account) -> Self {
+        Account {
+            account,
+            liquidity: OnceCell::new(),
+        }
+    }
+}
+
+#[juniper::object(Context = Context<S>, Scalar = Scalar)]
+impl<S: QueryStore> Account<S> {
+    fn id(&self) -> String {
+        self.account.id().to_string()
+    }
+
+    fn username(&self) -> String {
+        self.account.username().to_string()
+    }
+
+    fn ilp_address(&self) -> String {
+        self.account.ilp_address().to_string()
+    }
+
+    fn asset_code(&self) -> &str {
+        self.account.asset_code()
+    }
+
+    fn asset_scale(&self) -> i32 {
+        i32::from(self.account.asset_scale())
+    }
+
+    fn routing_relation(&self) -> String {
+        self.account.routing_relation().to_string()
+    }
+
+    fn balance(&self, context: &Context<S>) -> FieldResult<Option<i64>, Scalar> {
+        Ok(Some(
+            context.wait(context.store.get_balance(self.account.id()))?,
+        ))
+    }
+
+    /// The amount of the packets sent by the account which are still in flight
+    fn in_flight(&self, context: &Context<S>) -> FieldResult<Option<i64>, Scalar> {
+        long(context.wait(context.store.get_in_flight_amount(self.account.id()))?)
+    }
+
+    fn settlement(&self, context: &Context<S>) -> FieldResult<Option<Settlement>, Scalar> {
+        let liquidity = self
+            .liquidity
+            .get_or_try_init(|| context.wait(context.store.get_liquidity(self.account.id())))?;
+        Ok(Some(Settlement {
+            engine_url: self
+                .account
+                .settlement_engine_details()
+                .map(|details| details.url.to_string()),
+            liquidity: *liquidity,
+        }))
+    }
+
+    /// The payments received by the account since the node started, newest first
+    fn payments(
+        &self,
+        context: &Context<S>,
+        first: Option<i32>,
+        after: Option<String>,
+    ) -> FieldResult<Option<PaymentConnection>, Scalar> {
+        context
+            .payments
+            .page(Some(self.account.username()), first, after)
+    }
+}
+
+pub struct Settlement {
+    engine_url: Option<String>,
+    liquidity: Liquidity,
+}
+
+#[juniper::object(Scalar = Scalar)]
+impl Settlement {
+    fn engine_url(&self) -> Option<&str> {
+        self.engine_url.as_deref()
+    }
+
+    /// The amount the node owes the account
+    fn payable(&self) -> FieldResult<Option<i64>, Scalar> {
+        long(self.liquidity.payable)
+    }
+
+    /// The amount the account owes the node
+    fn receivable(&self) -> FieldResult<Option<i64>, Scalar> {
+        long(self.liquidity.receivable)
+    }
+
+    fn payable_in_flight(&self) -> FieldResult<Option<i64>, Scalar> {
+        long(self.liquidity.payable_in_flight)
+    }
+
+    fn receivable_in_flight(&self) -> FieldResult<Option<i64>, Scalar> {
+        long(self.liquidity.receivable_in_flight)
+    }
+
+    /// The max amount the node may owe the account, if it is limited
+    fn payable_limit(&self) -> FieldResult<Option<i64>, Scalar> {
+        self.liquidity.limits.payable_limit.map_or(Ok(None), long)
+    }
+
+    /// The max amount the account may owe the node, if it is limited
+    fn receivable_limit(&self) -> FieldResult<Option<i64>, Scalar> {
+        self.liquidity
+            .limits
+            .receivable_limit
+            .map_or(Ok(None), long)
+    }
+}
+
+pub struct RouteConnection<S: QueryStore>(Page<Route<S>>);
+
+#[juniper::object(Context = Context<S>, Scalar = Scalar)]
+impl<S: QueryStore> RouteConnection<S> {
+    fn nodes(&self) -> &[Route<S>] {
+        &self.0.nodes
+    }
+
+    fn total_count(&self) -> i32 {
+        self.0.total_count()
+    }
+
+    fn page_info(&self) -> PageInfo {
+        self.0.page_info()
+    }
+}
+
+pub struct Route<S: QueryStore> {
+    prefix: String,
+    id: Uuid,
+    /// The account, if the accounts of the page were read with the routes
+    account: Option<<S as QueryStore>::Account>,
+}
+
+#[juniper::object(Context = Context<S>, Scalar = Scalar)]
+impl<S: QueryStore> Route<S> {
+    fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    /// The account the packets are forwarded to
+    fn account(&self, context: &Context<S>) -> FieldResult<Option<Account<S>>, Scalar> {
+        let account = match self.account {
+            Some(ref account) => account.clone(),
+            None => context
+                .wait(context.store.get_accounts(vec![self.id]))?
+                .pop()
+                .ok_or_else(|| FieldError::new("Account not found", Value::null()))?,
+        };
+        Ok(Some(Account::new(account)))
+    }
+}
+
+pub struct Rate {
+    asset_code: String,
+    rate: f64,
+}
+
+#[juniper::object(Scalar = Scalar)]
+impl Rate {
+    fn asset_code(&self) -> &str {
+        &self.asset_code
+    }
+
+    fn rate(&self) -> f64 {
+        self.rate
+    }
+}
+
+pub struct PaymentConnection(Page<Payment>);
+
+#[juniper::object(Scalar = Scalar)]
+impl PaymentConnection {
+    fn nodes(&self) -> &[Payment] {
+        &self.0.nodes
+    }
+
+    fn total_count(&self) -> i32 {
+        self.0.total_count()
+    }
+
+    fn page_info(&self) -> PageInfo {
+        self.0.page_info()
+    }
+}
+
+pub struct Payment {
+    id: String,
+    payment: PaymentNotification,
+}
+
+#[juniper::object(Scalar = Scalar)]
+impl Payment {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn to_username(&self) -> String {
+        self.payment.to_username.to_string()
+    }
+
+    fn from_username(&self) -> String {
+        self.payment.from_username.to_string()
+    }
+
+    fn destination(&self) -> String {
+        self.payment.destination.to_string()
+    }
+
+    fn amount(&self) -> FieldResult<Option<i64>, Scalar> {
+        long(self.payment.amount)
+    }
+
+    fn timestamp(&self) -> &str {
+        &self.payment.timestamp
+    }
+
+    /// The reference the sender attached to the payment, if any
+    fn memo(&self) -> Option<&str> {
+        self.payment.memo.as_deref()
+    }
+}
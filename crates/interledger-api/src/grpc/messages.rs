@@ -0,0 +1,142 @@
+//! The messages of the gRPC API, generated from `proto/admin.proto` along with its server
+//! and client, and their conversions from and to the node's types
+
+use crate::AccountDetails;
+use interledger_ccp::CcpRoutingAccount;
+use interledger_packet::Address;
+use interledger_service::{HttpDialect, Username};
+use interledger_stream::StreamDelivery;
+use secrecy::SecretString;
+use std::{convert::TryFrom, str::FromStr};
+use tonic::Status;
+
+#[allow(clippy::all)]
+mod generated {
+    tonic::include_proto!("interledger.admin.v1");
+}
+
+pub use generated::*;
+
+impl Account {
+    pub fn from_account<A: CcpRoutingAccount>(account: &A) -> Self {
+        Account {
+            id: account.id().to_string(),
+            username: account.username().to_string(),
+            ilp_address: account.ilp_address().to_string(),
+            asset_code: account.asset_code().to_string(),
+            asset_scale: u32::from(account.asset_scale()),
+            routing_relation: account.routing_relation().to_string(),
+        }
+    }
+}
+
+/// Returns None for the empty strings, which are the strings which are not set in proto3
+fn non_empty(value: String) -> Option<String> {
+    if value.is_empty() {
+        None
+    } else {
+        Some(value)
+    }
+}
+
+fn secret(value: String) -> Option<SecretString> {
+    non_empty(value).map(SecretString::new)
+}
+
+impl TryFrom<CreateAccountRequest> for AccountDetails {
+    type Error = Status;
+
+    fn try_from(request: CreateAccountRequest) -> Result<Self, Status> {
+        let username = Username::from_str(&request.username)
+            .map_err(|_| Status::invalid_argument("Invalid username"))?;
+        let ilp_address = match non_empty(request.ilp_address) {
+            Some(address) => Some(
+                Address::from_str(&address)
+                    .map_err(|_| Status::invalid_argument("Invalid ILP address"))?,
+            ),
+            None => None,
+        };
+        let asset_scale = u8::try_from(request.asset_scale)
+            .map_err(|_| Status::invalid_argument("Invalid asset scale"))?;
+        let ilp_over_http_dialect = match non_empty(request.ilp_over_http_dialect) {
+            Some(dialect) => HttpDialect::from_str(&dialect).map_err(Status::invalid_argument)?,
+            None => HttpDialect::default(),
+        };
+        let transport_account = match non_empty(request.transport_account) {
+            Some(username) => Some(
+                Username::from_str(&username)
+                    .map_err(|_| Status::invalid_argument("Invalid transport account"))?,
+            ),
+            None => None,
+        };
+        Ok(AccountDetails {
+            ilp_address,
+            username,
+            asset_code: request.asset_code,
+            asset_scale,
+            max_packet_amount: request.max_packet_amount.unwrap_or_else(u64::max_value),
+            max_packet_size: None,
+            min_balance: None,
+            payable_limit: request.payable_limit,
+            receivable_limit: request.receivable_limit,
+            ilp_over_http_url: non_empty(request.ilp_over_http_url),
+            ilp_over_http_incoming_token: secret(request.ilp_over_http_incoming_token),
+            ilp_over_http_outgoing_token: secret(request.ilp_over_http_outgoing_token),
+            ilp_over_http_dialect,
+            ilp_over_http_signing_key: secret(request.ilp_over_http_signing_key),
+            ilp_over_http_verification_key: non_empty(request.ilp_over_http_verification_key),
+            ilp_over_btp_url: non_empty(request.ilp_over_btp_url),
+            ilp_over_btp_outgoing_token: secret(request.ilp_over_btp_outgoing_token),
+            ilp_over_btp_incoming_token: secret(request.ilp_over_btp_incoming_token),
+            ilp_over_btp_legacy: request.ilp_over_btp_legacy,
+            transport_account,
+            settle_threshold: request.settle_threshold,
+            settle_to: request.settle_to,
+            routing_relation: non_empty(request.routing_relation),
+            round_trip_time: request.round_trip_time,
+            route_broadcast_interval: request.route_broadcast_interval,
+            route_expiry: request.route_expiry,
+            route_full_table: request.route_full_table,
+            amount_per_minute_limit: None,
+            packets_per_second_limit: None,
+            packets_per_minute_limit: None,
+            settlement_engine_url: non_empty(request.settlement_engine_url),
+            liquidity_notification_url: non_empty(request.liquidity_notification_url),
+            balance_notification_url: non_empty(request.balance_notification_url),
+            spread: None,
+            percentage_fee: None,
+            fixed_fee: None,
+            packet_filters: Vec::new(),
+            ip_allowlist: Vec::new(),
+        })
+    }
+}
+
+impl From<StreamDelivery> for PaymentReceipt {
+    fn from(delivery: StreamDelivery) -> Self {
+        PaymentReceipt {
+            from: delivery.from.to_string(),
+            to: delivery.to.to_string(),
+            source_asset_code: delivery.source_asset_code,
+            source_asset_scale: u32::from(delivery.source_asset_scale),
+            source_amount: delivery.source_amount,
+            sent_amount: delivery.sent_amount,
+            delivered_amount: delivery.delivered_amount,
+            destination_asset_code: delivery.destination_asset_code,
+            destination_asset_scale: delivery.destination_asset_scale.map(u32::from),
+        }
+    }
+}
+
+impl From<interledger_stream::PaymentNotification> for PaymentNotification {
+    fn from(notification: interledger_stream::PaymentNotification) -> Self {
+        PaymentNotification {
+            to_username: notification.to_username.to_string(),
+            from_username: notification.from_username.to_string(),
+            destination: notification.destination.to_string(),
+            amount: notification.amount,
+            timestamp: notification.timestamp,
+            memo: notification.memo,
+        }
+    }
+}
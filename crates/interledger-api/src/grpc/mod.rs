@@ -0,0 +1,624 @@
+//! The administrative API of the node over gRPC, alongside the HTTP API.
+//!
+//! The service `interledger.admin.v1.Admin` is defined in `proto/admin.proto`, from which
+//! its messages, its server and a client are generated with tonic.
+//! The calls are authorized like the admin calls of the HTTP API, with the admin's token
+//! or a JWT with the admin scope in the `authorization` metadata, and the mutating calls
+//! are recorded in the same audit log.
+//!
+//! `WatchPayments` streams the payment notifications. The notifications are only read
+//! from the node as fast as the client receives them, via the flow control of HTTP/2.
+
+mod messages;
+
+pub use messages::*;
+
+use crate::assets::{check_account_asset, AssetStore};
+use crate::audit::{AdminCall, AuditLogStore};
+use crate::jwt::{admin_actor, JwtAuth};
+use crate::outgoing_payments::{
+    generate_payment_id, send_recorded_payment, OutgoingPayment, OutgoingPaymentStore,
+};
+use crate::routes::{connect_to_external_services, get_default_max_slippage, spsp_payment_error};
+use crate::{AccountDetails, NodeStore};
+use admin_server::{Admin, AdminServer};
+use async_trait::async_trait;
+use futures::{future, stream, Future, FutureExt};
+use http::{Method, StatusCode};
+use interledger_api_types::SpspPayRequest;
+use interledger_btp::{BtpAccount, BtpOutgoingService};
+use interledger_ccp::CcpRoutingAccount;
+use interledger_errors::ApiError;
+use interledger_rates::ExchangeRateStore;
+use interledger_router::RouterStore;
+use interledger_service::{
+    Account as AccountTrait, AccountStore, AddressStore, IncomingService, OutgoingService, Username,
+};
+use interledger_service_util::BalanceStore;
+use interledger_settlement::core::{types::SettlementAccount, SettlementClient};
+use interledger_spsp::{deliver, pay};
+use interledger_stream::{
+    MaxPacketAmountStore, StreamNotificationsStore, UnreachableDestinationStore, MAX_MEMO_LENGTH,
+};
+use std::{collections::HashMap, convert::TryFrom, net::SocketAddr, str::FromStr};
+use tokio::{
+    net::TcpListener,
+    sync::{broadcast::RecvError, mpsc},
+};
+use tonic::{transport::Server, Code, Request, Response, Status};
+use tracing::{error, warn};
+use uuid::Uuid;
+
+/// Full name of the service
+pub const SERVICE: &str = "interledger.admin.v1.Admin";
+/// Number of messages of a stream which are buffered before waiting for the client
+const STREAM_BUFFER: usize = 16;
+
+/// Returns the status of the errors of the HTTP API, by their HTTP status
+pub fn status_from_api_error(error: ApiError) -> Status {
+    let code = match error.status {
+        StatusCode::BAD_REQUEST => Code::InvalidArgument,
+        StatusCode::UNAUTHORIZED => Code::Unauthenticated,
+        StatusCode::FORBIDDEN => Code::PermissionDenied,
+        StatusCode::NOT_FOUND => Code::NotFound,
+        StatusCode::CONFLICT => Code::AlreadyExists,
+        StatusCode::TOO_MANY_REQUESTS => Code::ResourceExhausted,
+        StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT => {
+            Code::Unavailable
+        }
+        status if status.is_client_error() => Code::FailedPrecondition,
+        _ => Code::Internal,
+    };
+    let title = error.title;
+    Status::new(code, error.detail.unwrap_or_else(|| title.to_string()))
+}
+
+fn status<E: Into<ApiError>>(error: E) -> Status {
+    status_from_api_error(error.into())
+}
+
+/// The gRPC server of the administrative API
+pub struct GrpcApi<S, I, O, B, A: AccountTrait> {
+    store: S,
+    admin_auth_header: String,
+    jwt_auth: Option<JwtAuth>,
+    incoming_handler: I,
+    outgoing_handler: O,
+    btp: BtpOutgoingService<B, A>,
+    settlement_client: SettlementClient,
+}
+
+impl<S, I, O, B, A> GrpcApi<S, I, O, B, A>
+where
+    S: NodeStore<Account = A>
+        + AccountStore<Account = A>
+        + AddressStore
+        + BalanceStore
+        + RouterStore
+        + ExchangeRateStore
+        + MaxPacketAmountStore
+        + UnreachableDestinationStore
+        + StreamNotificationsStore<Account = A>
+        + AuditLogStore
+        + AssetStore
+        + OutgoingPaymentStore,
+    I: IncomingService<A> + Clone + Send + Sync + 'static,
+    O: OutgoingService<A> + Clone + Send + Sync + 'static,
+    B: OutgoingService<A> + Clone + Send + Sync + 'static,
+    A: BtpAccount + CcpRoutingAccount + SettlementAccount + Send + Sync + 'static,
+{
+    pub fn new(
+        admin_api_token: String,
+        jwt_auth: Option<JwtAuth>,
+        store: S,
+        incoming_handler: I,
+        outgoing_handler: O,
+        btp: BtpOutgoingService<B, A>,
+    ) -> Self {
+        GrpcApi {
+            store,
+            admin_auth_header: format!("Bearer {}", admin_api_token),
+            jwt_auth,
+            incoming_handler,
+            outgoing_handler,
+            btp,
+            settlement_client: SettlementClient::default(),
+        }
+    }
+
+    /// Sets the client used to create the accounts on the settlement engines
+    pub fn settlement_client(&mut self, client: SettlementClient) -> &mut Self {
+        self.settlement_client = client;
+        self
+    }
+
+    /// Serves the API at the provided address, over HTTP/2 without TLS
+    pub async fn bind(self, addr: SocketAddr) {
+        match TcpListener::bind(addr).await {
+            Ok(listener) => self.serve(listener).await,
+            Err(err) => error!("Error binding the gRPC API to {}: {}", addr, err),
+        }
+    }
+
+    /// Serves the API on the connections accepted by the listener
+    pub async fn serve(self, listener: TcpListener) {
+        self.serve_with_graceful_shutdown(listener, future::pending())
+            .await
+    }
+
+    /// Serves the API on the connections accepted by the listener until the signal
+    /// completes, and then waits for the calls in progress
+    pub async fn serve_with_graceful_shutdown<F>(self, listener: TcpListener, signal: F)
+    where
+        F: Future<Output = ()>,
+    {
+        let connections = stream::unfold(listener, |mut listener| async move {
+            let connection = listener.accept().await.map(|(socket, _)| socket);
+            Some((connection, listener))
+        });
+        if let Err(err) = Server::builder()
+            .add_service(AdminServer::new(self))
+            .serve_with_incoming_shutdown(connections, signal)
+            .await
+        {
+            error!("Error serving the gRPC API: {}", err);
+        }
+    }
+
+    /// Authorizes the call of the method as the admin's, and returns it to be recorded
+    async fn authorize<T>(&self, request: &Request<T>, method: &str) -> Result<AdminCall, Status> {
+        let authorization = request
+            .metadata()
+            .get("authorization")
+            .and_then(|authorization| authorization.to_str().ok())
+            .unwrap_or_default();
+        let actor = admin_actor(authorization, &self.admin_auth_header, &self.jwt_auth)
+            .await
+            .ok_or_else(|| Status::unauthenticated("invalid admin auth token provided"))?;
+        Ok(AdminCall::new(
+            actor,
+            Method::POST,
+            format!("/{}/{}", SERVICE, method),
+        ))
+    }
+
+    async fn account_id(&self, username: &str) -> Result<Uuid, Status> {
+        let username = Username::from_str(username)
+            .map_err(|_| Status::invalid_argument("Invalid username"))?;
+        self.store
+            .get_account_id_from_username(&username)
+            .await
+            .map_err(status)
+    }
+
+    async fn account(&self, username: &str) -> Result<A, Status> {
+        let id = self.account_id(username).await?;
+        let mut accounts = self.store.get_accounts(vec![id]).await.map_err(status)?;
+        accounts
+            .pop()
+            .ok_or_else(|| Status::not_found("Account not found"))
+    }
+
+    fn rates(&self) -> Result<Rates, Status> {
+        let mut rates: Vec<Rate> = self
+            .store
+            .get_all_exchange_rates()
+            .map_err(status)?
+            .into_iter()
+            .map(|(asset_code, rate)| Rate { asset_code, rate })
+            .collect();
+        rates.sort_by(|a, b| a.asset_code.cmp(&b.asset_code));
+        Ok(Rates { rates })
+    }
+}
+
+#[async_trait]
+impl<S, I, O, B, A> Admin for GrpcApi<S, I, O, B, A>
+where
+    S: NodeStore<Account = A>
+        + AccountStore<Account = A>
+        + AddressStore
+        + BalanceStore
+        + RouterStore
+        + ExchangeRateStore
+        + MaxPacketAmountStore
+        + UnreachableDestinationStore
+        + StreamNotificationsStore<Account = A>
+        + AuditLogStore
+        + AssetStore
+        + OutgoingPaymentStore,
+    I: IncomingService<A> + Clone + Send + Sync + 'static,
+    O: OutgoingService<A> + Clone + Send + Sync + 'static,
+    B: OutgoingService<A> + Clone + Send + Sync + 'static,
+    A: BtpAccount + CcpRoutingAccount + SettlementAccount + Send + Sync + 'static,
+{
+    async fn list_accounts(
+        &self,
+        request: Request<ListAccountsRequest>,
+    ) -> Result<Response<AccountList>, Status> {
+        self.authorize(&request, "ListAccounts").await?;
+        let accounts = self.store.get_all_accounts().await.map_err(status)?;
+        Ok(Response::new(AccountList {
+            accounts: accounts.iter().map(Account::from_account).collect(),
+        }))
+    }
+
+    async fn get_account(
+        &self,
+        request: Request<AccountRequest>,
+    ) -> Result<Response<Account>, Status> {
+        self.authorize(&request, "GetAccount").await?;
+        let account = self.account(&request.get_ref().username).await?;
+        Ok(Response::new(Account::from_account(&account)))
+    }
+
+    async fn create_account(
+        &self,
+        request: Request<CreateAccountRequest>,
+    ) -> Result<Response<Account>, Status> {
+        let call = self.authorize(&request, "CreateAccount").await?;
+        let details = AccountDetails::try_from(request.into_inner())?;
+        check_account_asset(&self.store, &details.asset_code, details.asset_scale)
+            .await
+            .map_err(status_from_api_error)?;
+        let account = self
+            .store
+            .insert_account(details.clone())
+            .await
+            .map_err(status)?;
+        call.record(&self.store, &details).await;
+        connect_to_external_services(
+            self.outgoing_handler.clone(),
+            account.clone(),
+            self.store.clone(),
+            self.btp.clone(),
+            self.settlement_client.clone(),
+        )
+        .await
+        .map_err(|rejection| match rejection.find::<ApiError>() {
+            Some(error) => status_from_api_error(error.clone()),
+            None => Status::internal(format!("{:?}", rejection)),
+        })?;
+        Ok(Response::new(Account::from_account(&account)))
+    }
+
+    async fn delete_account(
+        &self,
+        request: Request<AccountRequest>,
+    ) -> Result<Response<Account>, Status> {
+        let call = self.authorize(&request, "DeleteAccount").await?;
+        let id = self.account_id(&request.get_ref().username).await?;
+        let account = self.store.delete_account(id).await.map_err(status)?;
+        call.record(&self.store, &()).await;
+        self.btp.close_connection(&id);
+        Ok(Response::new(Account::from_account(&account)))
+    }
+
+    async fn get_balance(
+        &self,
+        request: Request<AccountRequest>,
+    ) -> Result<Response<Balance>, Status> {
+        self.authorize(&request, "GetBalance").await?;
+        let account = self.account(&request.get_ref().username).await?;
+        let balance = self.store.get_balance(account.id()).await.map_err(status)?;
+        let in_flight = self
+            .store
+            .get_in_flight_amount(account.id())
+            .await
+            .map_err(status)?;
+        Ok(Response::new(Balance {
+            balance,
+            in_flight,
+            asset_code: account.asset_code().to_string(),
+            asset_scale: u32::from(account.asset_scale()),
+        }))
+    }
+
+    async fn get_routes(
+        &self,
+        request: Request<GetRoutesRequest>,
+    ) -> Result<Response<RoutingTable>, Status> {
+        self.authorize(&request, "GetRoutes").await?;
+        let table = self.store.routing_table();
+        let mut routes: Vec<(String, Uuid)> = table
+            .iter()
+            .map(|(prefix, id)| (prefix.clone(), *id))
+            .collect();
+        routes.sort();
+        let accounts = self
+            .store
+            .get_accounts(routes.iter().map(|(_, id)| *id).collect())
+            .await
+            .map_err(status)?;
+        Ok(Response::new(RoutingTable {
+            routes: routes
+                .into_iter()
+                .zip(accounts)
+                .map(|((prefix, _), account)| Route {
+                    prefix,
+                    username: account.username().to_string(),
+                })
+                .collect(),
+        }))
+    }
+
+    async fn set_static_route(&self, request: Request<Route>) -> Result<Response<Route>, Status> {
+        let call = self.authorize(&request, "SetStaticRoute").await?;
+        let route = request.into_inner();
+        let id = self.account_id(&route.username).await?;
+        self.store
+            .set_static_route(route.prefix.clone(), id)
+            .await
+            .map_err(status)?;
+        let mut recorded = HashMap::new();
+        recorded.insert(route.prefix.clone(), route.username.clone());
+        call.record(&self.store, &recorded).await;
+        Ok(Response::new(route))
+    }
+
+    async fn get_rates(
+        &self,
+        request: Request<GetRatesRequest>,
+    ) -> Result<Response<Rates>, Status> {
+        self.authorize(&request, "GetRates").await?;
+        Ok(Response::new(self.rates()?))
+    }
+
+    async fn set_rates(&self, request: Request<Rates>) -> Result<Response<Rates>, Status> {
+        let call = self.authorize(&request, "SetRates").await?;
+        let rates: HashMap<String, f64> = request
+            .into_inner()
+            .rates
+            .into_iter()
+            .map(|rate| (rate.asset_code, rate.rate))
+            .collect();
+        self.store
+            .set_exchange_rates(rates.clone())
+            .map_err(status)?;
+        call.record(&self.store, &rates).await;
+        Ok(Response::new(self.rates()?))
+    }
+
+    async fn send_payment(
+        &self,
+        request: Request<SendPaymentRequest>,
+    ) -> Result<Response<PaymentReceipt>, Status> {
+        self.authorize(&request, "SendPayment").await?;
+        let request = request.into_inner();
+        if request.memo.as_ref().map_or(0, String::len) > MAX_MEMO_LENGTH {
+            return Err(Status::invalid_argument(format!(
+                "The memo may not be longer than {} bytes",
+                MAX_MEMO_LENGTH
+            )));
+        }
+        let account = self.account(&request.username).await?;
+        let slippage = request.slippage.unwrap_or_else(get_default_max_slippage);
+        let pay_request = SpspPayRequest {
+            receiver: request.receiver.clone(),
+            source_amount: Some(request.source_amount)
+                .filter(|_| request.destination_amount.is_none()),
+            destination_amount: request.destination_amount,
+            slippage,
+            memo: request.memo.clone(),
+            notification_url: None,
+        };
+        let payment = OutgoingPayment::new(
+            generate_payment_id().map_err(status)?,
+            account.username().clone(),
+            &pay_request,
+        )
+        .map_err(status)?;
+        let send = match (request.source_amount, request.destination_amount) {
+            (0, Some(destination_amount)) => deliver(
+                self.incoming_handler.clone(),
+                account,
+                self.store.clone(),
+                &request.receiver,
+                destination_amount,
+                slippage,
+                request.memo,
+            )
+            .left_future(),
+            (source_amount, None) => pay(
+                self.incoming_handler.clone(),
+                account,
+                self.store.clone(),
+                &request.receiver,
+                source_amount,
+                slippage,
+                request.memo,
+            )
+            .right_future(),
+            _ => {
+                return Err(Status::invalid_argument(
+                    "Only one of the source amount and the destination amount may be set",
+                ))
+            }
+        };
+        let delivery = send_recorded_payment(&self.store, payment, send)
+            .await
+            .map_err(status)?
+            .map_err(|err| {
+                let message = format!("Error sending SPSP payment: {}", err);
+                error!("{}", message);
+                status_from_api_error(spsp_payment_error(&err).detail(message))
+            })?;
+        Ok(Response::new(PaymentReceipt::from(delivery)))
+    }
+
+    type WatchPaymentsStream = mpsc::Receiver<Result<PaymentNotification, Status>>;
+
+    async fn watch_payments(
+        &self,
+        request: Request<WatchPaymentsRequest>,
+    ) -> Result<Response<Self::WatchPaymentsStream>, Status> {
+        self.authorize(&request, "WatchPayments").await?;
+        let username = match request.into_inner().username {
+            username if username.is_empty() => None,
+            username => Some(
+                Username::from_str(&username)
+                    .map_err(|_| Status::invalid_argument("Invalid username"))?,
+            ),
+        };
+        let mut notifications = self.store.all_payment_subscription();
+        let (mut sender, receiver) = mpsc::channel(STREAM_BUFFER);
+        tokio::spawn(async move {
+            loop {
+                let notification = match notifications.recv().await {
+                    Ok(notification) => notification,
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!(
+                            "gRPC payment notifications stream skipped {} notifications because the client is too slow",
+                            skipped
+                        );
+                        continue;
+                    }
+                    Err(RecvError::Closed) => break,
+                };
+                if username
+                    .as_ref()
+                    .map(|username| *username != notification.to_username)
+                    .unwrap_or(false)
+                {
+                    continue;
+                }
+                let message = PaymentNotification::from(notification);
+                // Waits while the client is behind, and stops once it is gone
+                if sender.send(Ok(message)).await.is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Response::new(receiver))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routes::test_helpers::{test_grpc_api, PAYMENTS};
+    use admin_client::AdminClient;
+    use interledger_packet::Address;
+    use tonic::transport::Channel;
+
+    async fn client() -> AdminClient<Channel> {
+        let addr = test_grpc_api().await;
+        AdminClient::connect(format!("http://{}", addr))
+            .await
+            .unwrap()
+    }
+
+    /// Returns the request with the token in its `authorization` metadata
+    fn authorized<T>(message: T, auth: &str) -> Request<T> {
+        let mut request = Request::new(message);
+        request
+            .metadata_mut()
+            .insert("authorization", format!("Bearer {}", auth).parse().unwrap());
+        request
+    }
+
+    #[tokio::test]
+    async fn lists_the_accounts() {
+        let accounts = client()
+            .await
+            .list_accounts(authorized(ListAccountsRequest {}, "admin"))
+            .await
+            .unwrap()
+            .into_inner()
+            .accounts;
+        assert_eq!(accounts.len(), 2);
+        assert_eq!(accounts[0].username, "alice");
+        assert_eq!(accounts[0].ilp_address, "example.alice");
+        assert_eq!(accounts[0].asset_scale, 9);
+    }
+
+    #[tokio::test]
+    async fn gets_the_balance() {
+        let request = AccountRequest {
+            username: "alice".to_string(),
+        };
+        let balance = client()
+            .await
+            .get_balance(authorized(request, "admin"))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(
+            balance,
+            Balance {
+                balance: 1,
+                in_flight: 0,
+                asset_code: "XYZ".to_string(),
+                asset_scale: 9,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn only_admin_can_call() {
+        let status = client()
+            .await
+            .list_accounts(authorized(ListAccountsRequest {}, "wrong"))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::Unauthenticated);
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_arguments() {
+        let request = AccountRequest {
+            username: "not a username".to_string(),
+        };
+        let status = client()
+            .await
+            .get_account(authorized(request, "admin"))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+    }
+
+    #[tokio::test]
+    async fn streams_the_payments_of_the_account() {
+        let watch = WatchPaymentsRequest {
+            username: "alice".to_string(),
+        };
+        // The response starts once the call subscribed to the notifications
+        let mut payments = client()
+            .await
+            .watch_payments(authorized(watch, "admin"))
+            .await
+            .unwrap()
+            .into_inner();
+        for to in &["bob", "alice"] {
+            PAYMENTS
+                .send(interledger_stream::PaymentNotification {
+                    to_username: Username::from_str(to).unwrap(),
+                    from_username: Username::from_str("carol").unwrap(),
+                    destination: Address::from_str("example.node").unwrap(),
+                    amount: 100,
+                    timestamp: "2020-09-13T12:26:40Z".to_string(),
+                    memo: None,
+                })
+                .unwrap();
+        }
+        let payment = payments.message().await.unwrap().unwrap();
+        assert_eq!(payment.to_username, "alice");
+        assert_eq!(payment.from_username, "carol");
+        assert_eq!(payment.amount, 100);
+    }
+
+    #[test]
+    fn maps_the_errors_of_the_http_api() {
+        let status = status_from_api_error(ApiError::not_found().detail("Account not found"));
+        assert_eq!(status.code(), Code::NotFound);
+        assert_eq!(status.message(), "Account not found");
+        assert_eq!(
+            status_from_api_error(ApiError::unauthorized()).code(),
+            Code::Unauthenticated
+        );
+        assert_eq!(
+            status_from_api_error(ApiError::internal_server_error()).code(),
+            Code::Internal
+        );
+    }
+}
@@ -0,0 +1,757 @@
+use interledger_errors::ApiError;
+use interledger_service::Username;
+use ring::{
+    constant_time,
+    signature::{self, RsaParameters, RsaPublicKeyComponents, UnparsedPublicKey},
+};
+use secrecy::{ExposeSecret, SecretString};
+use serde::Deserialize;
+use serde_json::Value;
+use std::{
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tracing::{debug, warn};
+use url::Url;
+use warp::{Filter, Rejection};
+
+/// Shortest time between two fetches of the JWKS, so that tokens
+/// signed with unknown keys do not make the node hammer the endpoint
+const MIN_JWKS_FETCH_INTERVAL: Duration = Duration::from_secs(10);
+/// How long to wait for the JWKS endpoint to respond
+const JWKS_FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn default_admin_scope() -> String {
+    "ilp:admin".to_string()
+}
+
+fn default_account_scope() -> String {
+    "ilp:account".to_string()
+}
+
+fn default_username_claim() -> String {
+    "sub".to_string()
+}
+
+const fn default_leeway() -> u64 {
+    60_000
+}
+
+const fn default_jwks_refresh_interval() -> u64 {
+    3_600_000
+}
+
+/// Configuration of the JWT authentication of the API, which accepts the tokens
+/// issued by an identity provider in addition to the static bearer tokens
+#[derive(Deserialize, Clone, Debug)]
+pub struct JwtConfig {
+    /// URL of the JSON Web Key Set with the keys which sign the tokens
+    pub jwks_url: Url,
+    /// Expected `iss` claim of the tokens
+    pub issuer: String,
+    /// Audience (`aud` claim) the tokens must be issued for
+    pub audience: String,
+    /// Scope which grants admin access
+    #[serde(default = "default_admin_scope")]
+    pub admin_scope: String,
+    /// Scope which grants access to the account named by the `username_claim`
+    #[serde(default = "default_account_scope")]
+    pub account_scope: String,
+    /// Claim which holds the username of the account the token grants access to
+    #[serde(default = "default_username_claim")]
+    pub username_claim: String,
+    /// Clock skew, in milliseconds, tolerated when checking the expiry of the tokens
+    #[serde(default = "default_leeway")]
+    pub leeway: u64,
+    /// Interval, in milliseconds, at which the keys are fetched again from the JWKS endpoint.
+    /// Tokens signed with unknown keys also make the node fetch them again.
+    #[serde(default = "default_jwks_refresh_interval")]
+    pub jwks_refresh_interval: u64,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+struct Jwks {
+    keys: Vec<Jwk>,
+}
+
+/// The fields of a JSON Web Key used to verify RSA and EC signatures
+#[derive(Deserialize, Clone, Debug)]
+struct Jwk {
+    kty: String,
+    kid: Option<String>,
+    #[serde(rename = "use")]
+    key_use: Option<String>,
+    alg: Option<String>,
+    // RSA keys
+    n: Option<String>,
+    e: Option<String>,
+    // EC keys
+    crv: Option<String>,
+    x: Option<String>,
+    y: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Header {
+    alg: String,
+    kid: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum OneOrMany {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl OneOrMany {
+    fn values(&self) -> Vec<&str> {
+        match self {
+            OneOrMany::One(value) => value.split(' ').collect(),
+            OneOrMany::Many(values) => values.iter().map(String::as_str).collect(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct Claims {
+    iss: Option<String>,
+    aud: Option<OneOrMany>,
+    exp: Option<u64>,
+    nbf: Option<u64>,
+    /// Space separated scopes (RFC 8693)
+    scope: Option<String>,
+    /// Scopes, as issued by some providers
+    scp: Option<OneOrMany>,
+}
+
+/// The identity granted by a valid token
+#[derive(Debug, PartialEq)]
+struct Grant {
+    scopes: Vec<String>,
+    username: Option<Username>,
+    /// The raw value of the username claim, which identifies admins in the audit log
+    subject: Option<String>,
+}
+
+enum Algorithm {
+    Rsa(&'static RsaParameters),
+    Ec(&'static signature::EcdsaVerificationAlgorithm, &'static str),
+}
+
+impl Algorithm {
+    fn from_name(alg: &str) -> Option<Self> {
+        Some(match alg {
+            "RS256" => Algorithm::Rsa(&signature::RSA_PKCS1_2048_8192_SHA256),
+            "RS384" => Algorithm::Rsa(&signature::RSA_PKCS1_2048_8192_SHA384),
+            "RS512" => Algorithm::Rsa(&signature::RSA_PKCS1_2048_8192_SHA512),
+            "PS256" => Algorithm::Rsa(&signature::RSA_PSS_2048_8192_SHA256),
+            "PS384" => Algorithm::Rsa(&signature::RSA_PSS_2048_8192_SHA384),
+            "PS512" => Algorithm::Rsa(&signature::RSA_PSS_2048_8192_SHA512),
+            "ES256" => Algorithm::Ec(&signature::ECDSA_P256_SHA256_FIXED, "P-256"),
+            "ES384" => Algorithm::Ec(&signature::ECDSA_P384_SHA384_FIXED, "P-384"),
+            // Symmetric and unsigned tokens are never accepted
+            _ => return None,
+        })
+    }
+
+    /// Returns whether the key verifies the signature of the message with this algorithm
+    fn verify(&self, key: &Jwk, message: &[u8], signature: &[u8]) -> bool {
+        match self {
+            Algorithm::Rsa(params) => {
+                if key.kty != "RSA" {
+                    return false;
+                }
+                let (n, e) = match (decode_field(&key.n), decode_field(&key.e)) {
+                    (Some(n), Some(e)) => (n, e),
+                    _ => return false,
+                };
+                let components = RsaPublicKeyComponents {
+                    n: strip_leading_zeros(&n),
+                    e: strip_leading_zeros(&e),
+                };
+                components.verify(params, message, signature).is_ok()
+            }
+            Algorithm::Ec(algorithm, curve) => {
+                if key.kty != "EC" || key.crv.as_deref() != Some(*curve) {
+                    return false;
+                }
+                let (x, y) = match (decode_field(&key.x), decode_field(&key.y)) {
+                    (Some(x), Some(y)) => (x, y),
+                    _ => return false,
+                };
+                // Uncompressed point
+                let mut point = Vec::with_capacity(1 + x.len() + y.len());
+                point.push(4);
+                point.extend_from_slice(&x);
+                point.extend_from_slice(&y);
+                UnparsedPublicKey::new(*algorithm, point)
+                    .verify(message, signature)
+                    .is_ok()
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct KeyCache {
+    keys: Vec<Jwk>,
+    /// When the keys were last fetched successfully
+    fetched_at: Option<Instant>,
+    /// When the keys were last fetched, successfully or not
+    attempted_at: Option<Instant>,
+}
+
+/// Validates the JWTs sent to the API in the `Authorization` header against the keys
+/// of the configured JWKS endpoint, which are cached and fetched again periodically
+/// (or when a token is signed with an unknown key)
+#[derive(Clone)]
+pub struct JwtAuth {
+    config: Arc<JwtConfig>,
+    client: reqwest::Client,
+    keys: Arc<Mutex<KeyCache>>,
+}
+
+impl JwtAuth {
+    pub fn new(config: JwtConfig) -> Self {
+        JwtAuth {
+            config: Arc::new(config),
+            client: reqwest::Client::new(),
+            keys: Arc::new(Mutex::new(KeyCache::default())),
+        }
+    }
+
+    /// Authorizes the token if it is a valid JWT with the admin scope.
+    /// Returns the subject the token was issued to, if it names one
+    pub(crate) async fn authorize_admin(&self, token: &str) -> Result<Option<String>, ApiError> {
+        let grant = self.validate(token).await?;
+        if grant.scopes.contains(&self.config.admin_scope) {
+            Ok(grant.subject)
+        } else {
+            Err(ApiError::unauthorized().detail("the token does not grant admin access"))
+        }
+    }
+
+    /// Authorizes the token if it is a valid JWT with the admin scope, or with the account scope
+    /// issued for the account. Returns the admin's subject if it grants admin access, so that a
+    /// request to an account's route is validated once, whoever it comes from
+    pub(crate) async fn authorize_admin_or_account(
+        &self,
+        token: &str,
+        username: &Username,
+    ) -> Result<Option<String>, ApiError> {
+        let grant = self.validate(token).await?;
+        if grant.scopes.contains(&self.config.admin_scope) {
+            Ok(Some(jwt_actor(grant.subject)))
+        } else if grant.scopes.contains(&self.config.account_scope)
+            && grant.username.as_ref() == Some(username)
+        {
+            Ok(None)
+        } else {
+            Err(ApiError::unauthorized().detail(format!(
+                "the token does not grant access to the account {}",
+                username
+            )))
+        }
+    }
+
+    /// Authorizes the token if it is a valid JWT with the account scope, issued for the account
+    pub(crate) async fn authorize_account(
+        &self,
+        token: &str,
+        username: &Username,
+    ) -> Result<(), ApiError> {
+        let grant = self.validate(token).await?;
+        if grant.scopes.contains(&self.config.account_scope)
+            && grant.username.as_ref() == Some(username)
+        {
+            Ok(())
+        } else {
+            Err(ApiError::unauthorized().detail(format!(
+                "the token does not grant access to the account {}",
+                username
+            )))
+        }
+    }
+
+    async fn validate(&self, token: &str) -> Result<Grant, ApiError> {
+        self.validate_at(token, unix_time()).await.map_err(|err| {
+            debug!("Rejected JWT: {}", err);
+            ApiError::unauthorized().detail(format!("invalid token: {}", err))
+        })
+    }
+
+    async fn validate_at(&self, token: &str, now: u64) -> Result<Grant, String> {
+        let mut parts = token.split('.');
+        let (encoded_header, payload, signature) = match (parts.next(), parts.next(), parts.next())
+        {
+            (Some(header), Some(payload), Some(signature)) if parts.next().is_none() => {
+                (header, payload, signature)
+            }
+            _ => return Err("malformed token".to_string()),
+        };
+        let header: Header = decode_json(encoded_header)?;
+        let algorithm = Algorithm::from_name(&header.alg)
+            .ok_or_else(|| format!("unsupported algorithm {}", header.alg))?;
+        let signature = decode(signature)?;
+        // The signature covers the encoded header and payload
+        let message = &token[..encoded_header.len() + 1 + payload.len()];
+
+        let keys = self.keys_for(header.kid.as_deref()).await?;
+        let verified = keys
+            .iter()
+            .filter(|key| key.alg.is_none() || key.alg.as_deref() == Some(header.alg.as_str()))
+            .filter(|key| key.key_use.is_none() || key.key_use.as_deref() == Some("sig"))
+            .any(|key| algorithm.verify(key, message.as_bytes(), &signature));
+        if !verified {
+            return Err("invalid signature".to_string());
+        }
+
+        let claims: Claims = decode_json(payload)?;
+        let leeway = self.config.leeway / 1000;
+        match claims.exp {
+            Some(exp) if exp.saturating_add(leeway) > now => {}
+            Some(_) => return Err("expired".to_string()),
+            None => return Err("missing exp claim".to_string()),
+        }
+        if let Some(nbf) = claims.nbf {
+            if nbf > now.saturating_add(leeway) {
+                return Err("not yet valid".to_string());
+            }
+        }
+        if claims.iss.as_deref() != Some(self.config.issuer.as_str()) {
+            return Err("unexpected issuer".to_string());
+        }
+        let audiences = claims.aud.as_ref().map(OneOrMany::values);
+        if !audiences.map_or(false, |audiences| {
+            audiences.contains(&self.config.audience.as_str())
+        }) {
+            return Err("unexpected audience".to_string());
+        }
+
+        let mut scopes: Vec<String> = claims
+            .scope
+            .iter()
+            .flat_map(|scope| scope.split(' '))
+            .map(String::from)
+            .collect();
+        if let Some(ref scp) = claims.scp {
+            scopes.extend(scp.values().into_iter().map(String::from));
+        }
+        let payload: Value = decode_json(payload)?;
+        let subject = payload
+            .get(&self.config.username_claim)
+            .and_then(Value::as_str)
+            .map(String::from);
+        let username = subject
+            .as_ref()
+            .and_then(|subject| Username::from_str(subject).ok());
+        Ok(Grant {
+            scopes,
+            username,
+            subject,
+        })
+    }
+
+    /// Returns the cached keys which may have signed a token with the key id,
+    /// fetching them if they are stale or if none of them has this id
+    async fn keys_for(&self, kid: Option<&str>) -> Result<Vec<Jwk>, String> {
+        let refresh_interval = Duration::from_millis(self.config.jwks_refresh_interval);
+        let (cached, fresh, may_fetch) = {
+            let cache = self.keys.lock().unwrap();
+            (
+                matching_keys(&cache.keys, kid),
+                cache
+                    .fetched_at
+                    .map_or(false, |fetched_at| fetched_at.elapsed() < refresh_interval),
+                cache.attempted_at.map_or(true, |attempted_at| {
+                    attempted_at.elapsed() >= MIN_JWKS_FETCH_INTERVAL
+                }),
+            )
+        };
+        if (!cached.is_empty() && fresh) || !may_fetch {
+            return if cached.is_empty() {
+                Err("unknown signing key".to_string())
+            } else {
+                Ok(cached)
+            };
+        }
+
+        let fetched = self.fetch_keys().await;
+        let mut cache = self.keys.lock().unwrap();
+        cache.attempted_at = Some(Instant::now());
+        match fetched {
+            Ok(keys) => {
+                cache.keys = keys;
+                cache.fetched_at = Some(Instant::now());
+            }
+            Err(err) => {
+                warn!(
+                    "Error fetching the JWKS from {}: {}",
+                    self.config.jwks_url, err
+                );
+            }
+        }
+        let keys = matching_keys(&cache.keys, kid);
+        if keys.is_empty() {
+            Err("unknown signing key".to_string())
+        } else {
+            Ok(keys)
+        }
+    }
+
+    async fn fetch_keys(&self) -> Result<Vec<Jwk>, reqwest::Error> {
+        let jwks: Jwks = self
+            .client
+            .get(self.config.jwks_url.clone())
+            .timeout(JWKS_FETCH_TIMEOUT)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(jwks.keys)
+    }
+}
+
+/// Returns whether the bearer token looks like a JWT (rather than a static token),
+/// i.e. it is made of three dot separated parts and starts with a JSON header
+pub(crate) fn is_jwt(token: &str) -> bool {
+    let mut parts = token.split('.');
+    let header = parts.next().unwrap_or_default();
+    parts.count() == 2 && decode_json::<Header>(header).is_ok()
+}
+
+/// Checks if the Authorization header is the admin's or carries a JWT with the admin scope
+pub(crate) async fn is_admin(
+    authorization: &str,
+    admin_auth_header: &str,
+    jwt_auth: &Option<JwtAuth>,
+) -> bool {
+    admin_actor(authorization, admin_auth_header, jwt_auth)
+        .await
+        .is_some()
+}
+
+/// Returns who the Authorization header authenticates as an admin: `admin` for the
+/// admin's token, or `jwt:<subject>` for a JWT with the admin scope
+pub(crate) async fn admin_actor(
+    authorization: &str,
+    admin_auth_header: &str,
+    jwt_auth: &Option<JwtAuth>,
+) -> Option<String> {
+    if is_admin_token(authorization, admin_auth_header) {
+        return Some("admin".to_string());
+    }
+    let (jwt_auth, token) = bearer_jwt(authorization, jwt_auth)?;
+    match jwt_auth.authorize_admin(token).await {
+        Ok(subject) => Some(jwt_actor(subject)),
+        Err(_) => None,
+    }
+}
+
+/// Checks if the Authorization header is the admin's, in constant time so that
+/// the timing of the comparison does not leak how much of the token matched
+pub(crate) fn is_admin_token(authorization: &str, admin_auth_header: &str) -> bool {
+    constant_time::verify_slices_are_equal(authorization.as_bytes(), admin_auth_header.as_bytes())
+        .is_ok()
+}
+
+/// Returns how the admins authenticated with a JWT are named in the audit log
+fn jwt_actor(subject: Option<String>) -> String {
+    format!("jwt:{}", subject.unwrap_or_default())
+}
+
+/// Returns a filter which only lets the requests of the admin through: those authorized
+/// with the admin's token, or with a JWT with the admin scope if JWTs are accepted
+pub fn admin_only(
+    admin_api_token: &str,
+    jwt_auth: Option<JwtAuth>,
+) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    let admin_auth_header = format!("Bearer {}", admin_api_token);
+    warp::header::<SecretString>("authorization")
+        .and_then(move |authorization: SecretString| {
+            let admin_auth_header = admin_auth_header.clone();
+            let jwt_auth = jwt_auth.clone();
+            async move {
+                if is_admin(authorization.expose_secret(), &admin_auth_header, &jwt_auth).await {
+                    Ok::<(), Rejection>(())
+                } else {
+                    Err(Rejection::from(
+                        ApiError::unauthorized().detail("invalid admin auth token provided"),
+                    ))
+                }
+            }
+        })
+        // This call makes it so we do not pass on a () value on
+        // success to the next filter, it just gets rid of it
+        .untuple_one()
+}
+
+/// Returns the JWT of the Authorization header with the validator, if JWT authentication
+/// is configured and the header carries one
+pub(crate) fn bearer_jwt<'a>(
+    authorization: &'a str,
+    jwt_auth: &'a Option<JwtAuth>,
+) -> Option<(&'a JwtAuth, &'a str)> {
+    let jwt_auth = jwt_auth.as_ref()?;
+    let token = authorization.strip_prefix("Bearer ")?;
+    if is_jwt(token) {
+        Some((jwt_auth, token))
+    } else {
+        None
+    }
+}
+
+fn matching_keys(keys: &[Jwk], kid: Option<&str>) -> Vec<Jwk> {
+    keys.iter()
+        .filter(|key| kid.is_none() || key.kid.as_deref() == kid)
+        .cloned()
+        .collect()
+}
+
+fn decode(part: &str) -> Result<Vec<u8>, String> {
+    base64::decode_config(part, base64::URL_SAFE_NO_PAD).map_err(|_| "invalid encoding".to_string())
+}
+
+fn decode_json<T: serde::de::DeserializeOwned>(part: &str) -> Result<T, String> {
+    serde_json::from_slice(&decode(part)?).map_err(|err| format!("invalid JSON: {}", err))
+}
+
+fn decode_field(field: &Option<String>) -> Option<Vec<u8>> {
+    field.as_deref().and_then(|field| decode(field).ok())
+}
+
+fn strip_leading_zeros(bytes: &[u8]) -> &[u8] {
+    let start = bytes
+        .iter()
+        .position(|byte| *byte != 0)
+        .unwrap_or(bytes.len());
+    &bytes[start..]
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::{
+        rand::SystemRandom,
+        signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING},
+    };
+    use serde_json::json;
+
+    const NOW: u64 = 1_600_000_000;
+
+    fn encode(bytes: &[u8]) -> String {
+        base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+    }
+
+    struct Issuer {
+        key_pair: EcdsaKeyPair,
+        rng: SystemRandom,
+    }
+
+    impl Issuer {
+        fn new() -> Self {
+            let rng = SystemRandom::new();
+            let pkcs8 =
+                EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+            let key_pair =
+                EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref()).unwrap();
+            Issuer { key_pair, rng }
+        }
+
+        fn jwk(&self, kid: &str) -> Jwk {
+            let point = self.key_pair.public_key().as_ref();
+            serde_json::from_value(json!({
+                "kty": "EC",
+                "kid": kid,
+                "use": "sig",
+                "crv": "P-256",
+                "x": encode(&point[1..33]),
+                "y": encode(&point[33..65]),
+            }))
+            .unwrap()
+        }
+
+        fn token(&self, kid: &str, claims: Value) -> String {
+            let header = encode(json!({ "alg": "ES256", "kid": kid }).to_string().as_bytes());
+            let payload = encode(claims.to_string().as_bytes());
+            let message = format!("{}.{}", header, payload);
+            let signature = self.key_pair.sign(&self.rng, message.as_bytes()).unwrap();
+            format!("{}.{}", message, encode(signature.as_ref()))
+        }
+    }
+
+    fn jwt_auth(keys: Vec<Jwk>) -> JwtAuth {
+        let jwt_auth = JwtAuth::new(JwtConfig {
+            jwks_url: Url::parse("http://127.0.0.1:1/jwks.json").unwrap(),
+            issuer: "https://idp.example".to_string(),
+            audience: "ilp-node".to_string(),
+            admin_scope: default_admin_scope(),
+            account_scope: default_account_scope(),
+            username_claim: default_username_claim(),
+            leeway: default_leeway(),
+            jwks_refresh_interval: default_jwks_refresh_interval(),
+        });
+        {
+            let mut cache = jwt_auth.keys.lock().unwrap();
+            cache.keys = keys;
+            cache.fetched_at = Some(Instant::now());
+            cache.attempted_at = Some(Instant::now());
+        }
+        jwt_auth
+    }
+
+    fn claims() -> Value {
+        json!({
+            "iss": "https://idp.example",
+            "aud": ["ilp-node", "other"],
+            "sub": "alice",
+            "exp": NOW + 300,
+            "scope": "openid ilp:account",
+        })
+    }
+
+    #[tokio::test]
+    async fn validates_signed_tokens() {
+        let issuer = Issuer::new();
+        let jwt_auth = jwt_auth(vec![issuer.jwk("key-1")]);
+        let token = issuer.token("key-1", claims());
+        assert!(is_jwt(&token));
+        assert_eq!(
+            jwt_auth.validate_at(&token, NOW).await.unwrap(),
+            Grant {
+                scopes: vec!["openid".to_string(), "ilp:account".to_string()],
+                username: Some(Username::from_str("alice").unwrap()),
+                subject: Some("alice".to_string()),
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_tokens() {
+        let issuer = Issuer::new();
+        let other_issuer = Issuer::new();
+        let jwt_auth = jwt_auth(vec![issuer.jwk("key-1"), other_issuer.jwk("key-2")]);
+
+        // Signed by another key than the one it names
+        let token = other_issuer.token("key-1", claims());
+        assert_eq!(
+            jwt_auth.validate_at(&token, NOW).await.unwrap_err(),
+            "invalid signature"
+        );
+        // Signed with an unknown key (which is not fetched again so soon)
+        let token = issuer.token("key-3", claims());
+        assert_eq!(
+            jwt_auth.validate_at(&token, NOW).await.unwrap_err(),
+            "unknown signing key"
+        );
+
+        let token = issuer.token("key-1", claims());
+        assert_eq!(
+            jwt_auth.validate_at(&token, NOW + 3600).await.unwrap_err(),
+            "expired"
+        );
+
+        let mut wrong_audience = claims();
+        wrong_audience["aud"] = json!("other");
+        let token = issuer.token("key-1", wrong_audience);
+        assert_eq!(
+            jwt_auth.validate_at(&token, NOW).await.unwrap_err(),
+            "unexpected audience"
+        );
+
+        let mut wrong_issuer = claims();
+        wrong_issuer["iss"] = json!("https://evil.example");
+        let token = issuer.token("key-1", wrong_issuer);
+        assert_eq!(
+            jwt_auth.validate_at(&token, NOW).await.unwrap_err(),
+            "unexpected issuer"
+        );
+
+        let unsigned = format!(
+            "{}.{}.",
+            encode(json!({ "alg": "none" }).to_string().as_bytes()),
+            encode(claims().to_string().as_bytes())
+        );
+        assert_eq!(
+            jwt_auth.validate_at(&unsigned, NOW).await.unwrap_err(),
+            "unsupported algorithm none"
+        );
+    }
+
+    /// Claims which are valid now, granting the scope
+    fn current_claims(scope: &str) -> Value {
+        let mut claims = claims();
+        claims["exp"] = json!(unix_time() + 300);
+        claims["scope"] = json!(scope);
+        claims
+    }
+
+    #[tokio::test]
+    async fn authorizes_admins_or_the_account() {
+        let issuer = Issuer::new();
+        let jwt_auth = jwt_auth(vec![issuer.jwk("key-1")]);
+        let alice = Username::from_str("alice").unwrap();
+        let bob = Username::from_str("bob").unwrap();
+
+        let admin = issuer.token("key-1", current_claims("ilp:admin"));
+        assert_eq!(
+            jwt_auth
+                .authorize_admin_or_account(&admin, &bob)
+                .await
+                .unwrap(),
+            Some("jwt:alice".to_string())
+        );
+        let account = issuer.token("key-1", current_claims("ilp:account"));
+        assert_eq!(
+            jwt_auth
+                .authorize_admin_or_account(&account, &alice)
+                .await
+                .unwrap(),
+            None
+        );
+        assert!(jwt_auth
+            .authorize_admin_or_account(&account, &bob)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn admin_only_accepts_the_admin_token_and_admin_jwts() {
+        let issuer = Issuer::new();
+        let filter = admin_only("admin", Some(jwt_auth(vec![issuer.jwk("key-1")])));
+        let authorized = |token: String| {
+            warp::test::request()
+                .header("authorization", format!("Bearer {}", token))
+                .matches(&filter)
+        };
+        assert!(authorized("admin".to_string()).await);
+        assert!(authorized(issuer.token("key-1", current_claims("ilp:admin"))).await);
+        assert!(!authorized(issuer.token("key-1", current_claims("ilp:account"))).await);
+        assert!(!authorized("wrong".to_string()).await);
+    }
+
+    #[test]
+    fn compares_the_admin_token() {
+        assert!(is_admin_token("Bearer admin", "Bearer admin"));
+        assert!(!is_admin_token("Bearer admin2", "Bearer admin"));
+        assert!(!is_admin_token("Bearer admim", "Bearer admin"));
+        assert!(!is_admin_token("", "Bearer admin"));
+    }
+
+    #[test]
+    fn tells_jwts_from_static_tokens() {
+        assert!(!is_jwt("some-static-token"));
+        assert!(!is_jwt("with.two.dots"));
+        assert!(bearer_jwt("Bearer some-static-token", &None).is_none());
+    }
+}
@@ -1,72 +1,88 @@
 #![type_length_limit = "1707074"]
+// The validations of the API return the `ApiError` which is rejected to the client as is,
+// and which is as large as it is because it is the RFC 7807 problem document itself
+#![allow(clippy::result_large_err)]
 use async_trait::async_trait;
 use bytes::Bytes;
 use interledger_btp::{BtpAccount, BtpOutgoingService};
 use interledger_ccp::CcpRoutingAccount;
 use interledger_errors::NodeStoreError;
 use interledger_http::{HttpAccount, HttpStore};
-use interledger_packet::Address;
-use interledger_rates::ExchangeRateStore;
+use interledger_rates::{ExchangeRateStore, PinnedRateStore};
 use interledger_router::RouterStore;
 use interledger_service::{
-    Account, AccountStore, AddressStore, IncomingService, OutgoingService, Username,
+    Account, AccountStore, AddressStore, IncomingService, OutgoingService, PeerBlocklist, Username,
 };
-use interledger_service_util::BalanceStore;
-use interledger_settlement::core::types::{SettlementAccount, SettlementStore};
-use interledger_stream::StreamNotificationsStore;
-use secrecy::SecretString;
-use serde::{de, Deserialize, Serialize};
-use std::{boxed::*, collections::HashMap, fmt::Display, net::SocketAddr, str::FromStr};
+use interledger_service_util::{
+    BalanceJournalStore, BalanceNotifications, BalanceStore, EchoPings, FaultInjector, FeeAccount,
+    FeePolicy, FeePolicyStore, LiquidityStore, NodeStats, PacketTraces, PeerLatencies, Scheduler,
+    SettlementReconciliation, TransferLimitStore,
+};
+use interledger_settlement::core::{
+    types::{SettlementAccount, SettlementStore},
+    SettlementClient,
+};
+use interledger_stream::{
+    MaxPacketAmountStore, ReceiptGenerator, StreamConnections, StreamNotificationsStore,
+    UnreachableDestinationStore,
+};
+use serde::{Deserialize, Serialize};
+use std::{boxed::*, collections::HashMap, net::SocketAddr, time::Instant};
 use url::Url;
 use uuid::Uuid;
 use warp::{self, Filter};
 
+mod account_templates;
+mod assets;
+mod audit;
+pub mod graphql;
+pub mod grpc;
+mod jwt;
+mod outgoing_payments;
+mod payment_pointers;
+mod payment_receipts;
+mod peering;
+mod provisioning;
+mod pull_payments;
+mod receipts;
 mod routes;
-
-// This enum and the following functions are used to allow clients to send either
-// numbers or strings and have them be properly deserialized into the appropriate
-// integer type.
-#[derive(Deserialize)]
-#[serde(untagged)]
-enum NumOrStr<T> {
-    Num(T),
-    Str(String),
-}
-
-pub fn number_or_string<'de, D, T>(deserializer: D) -> Result<T, D::Error>
-where
-    D: de::Deserializer<'de>,
-    T: FromStr + Deserialize<'de>,
-    <T as FromStr>::Err: Display,
-{
-    match NumOrStr::deserialize(deserializer)? {
-        NumOrStr::Num(n) => Ok(n),
-        NumOrStr::Str(s) => T::from_str(&s).map_err(de::Error::custom),
-    }
-}
-
-pub fn optional_number_or_string<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
-where
-    D: de::Deserializer<'de>,
-    T: FromStr + Deserialize<'de>,
-    <T as FromStr>::Err: Display,
-{
-    match NumOrStr::deserialize(deserializer)? {
-        NumOrStr::Num(n) => Ok(Some(n)),
-        NumOrStr::Str(s) => T::from_str(&s).map_err(de::Error::custom).map(Some),
-    }
-}
-
-pub fn map_of_number_or_string<'de, D>(deserializer: D) -> Result<HashMap<String, f64>, D::Error>
-where
-    D: de::Deserializer<'de>,
-{
-    #[derive(Deserialize)]
-    struct Wrapper(#[serde(deserialize_with = "number_or_string")] f64);
-
-    let v = HashMap::<String, Wrapper>::deserialize(deserializer)?;
-    Ok(v.into_iter().map(|(k, Wrapper(v))| (k, v)).collect())
-}
+mod scheduled_payments;
+mod spsp_signatures;
+mod versioning;
+
+pub use account_templates::{AccountTemplate, AccountTemplateStore};
+pub use assets::{Asset, AssetStore, Rounding};
+pub use audit::{AuditEntry, AuditLogStore, AuditQuery};
+use graphql::{RecentPayments, RECENT_PAYMENTS_CAPACITY};
+pub use grpc::GrpcApi;
+pub use interledger_api_types::{
+    map_of_number_or_string, number_or_string, optional_number_or_string, AccountDetails,
+    AccountSettings, ExchangeRates, RuntimeSettings, SpspPayRequest,
+};
+pub use jwt::{admin_only, JwtAuth, JwtConfig};
+pub use outgoing_payments::{
+    fail_interrupted_payments, OutgoingPayment, OutgoingPaymentStatus, OutgoingPaymentStore,
+};
+pub use payment_pointers::{PaymentPointer, PaymentPointerStore, WELL_KNOWN_PATH};
+pub use payment_receipts::{
+    PaymentReceipt, PaymentReceiptSigner, ReceiptPublicKey, SignedPaymentReceipt, RECEIPT_KEY_PATH,
+};
+pub use peering::{
+    PeeringApproval, PeeringProposal, PeeringRequest, PeeringRequestBody, PeeringStore,
+    PeeringTerms, MAX_PENDING_PEERING_REQUESTS, PEERING_REQUEST_TTL,
+};
+pub use provisioning::{ChildProvisioning, ProvisioningChallenge, ProvisioningRequest};
+pub use pull_payments::{PullPaymentStore, PullPointer, PullPointerTerms, PULL_POINTERS_PATH};
+pub use receipts::{ReceiptCredit, ReceiptVerifierStore};
+pub use routes::RequestLimiter;
+pub use scheduled_payments::{
+    execute_scheduled_payments, Recurrence, ScheduledPayment, ScheduledPaymentOutcome,
+    ScheduledPaymentRequest, ScheduledPaymentStatus, ScheduledPaymentStore,
+};
+pub use spsp_signatures::{
+    verify_spsp_response, SpspResponseSigner, SPSP_KEY_PATH, SPSP_SIGNATURE_HEADER,
+};
+pub use versioning::{versioned_api, CURRENT_API_VERSION};
 
 // TODO should the methods from this trait be split up and put into the
 // traits that are more specific to what they're doing?
@@ -103,10 +119,30 @@ pub trait NodeStore: Clone + Send + Sync + 'static {
         settings: AccountSettings,
     ) -> Result<Self::Account, NodeStoreError>;
 
-    // TODO limit the number of results and page through them
     /// Gets all stored accounts
     async fn get_all_accounts(&self) -> Result<Vec<Self::Account>, NodeStoreError>;
 
+    /// Gets a page of about `count` stored accounts, starting at the cursor (0 for the first
+    /// page), along with the cursor of the next page, which is 0 after the last one. As with
+    /// a Redis `SCAN`, an account may be returned twice if the accounts change while they
+    /// are paged through.
+    ///
+    /// By default, the page is sliced from `get_all_accounts`, with the cursor being the
+    /// offset of the page, so the stores which can page through their accounts should
+    /// override it.
+    async fn get_accounts_page(
+        &self,
+        cursor: u64,
+        count: usize,
+    ) -> Result<(u64, Vec<Self::Account>), NodeStoreError> {
+        let mut accounts = self.get_all_accounts().await?;
+        let start = (cursor as usize).min(accounts.len());
+        let end = start.saturating_add(count.max(1)).min(accounts.len());
+        let next = if end < accounts.len() { end as u64 } else { 0 };
+        accounts.truncate(end);
+        Ok((next, accounts.split_off(start)))
+    }
+
     /// Sets the static routes for routing
     async fn set_static_routes<R>(&self, routes: R) -> Result<(), NodeStoreError>
     where
@@ -125,6 +161,12 @@ pub trait NodeStore: Clone + Send + Sync + 'static {
     /// (acts as a catch-all route if all other routes don't match)
     async fn set_default_route(&self, account_id: Uuid) -> Result<(), NodeStoreError>;
 
+    /// Gets the static routes, which take precedence over the routes learned from peers.
+    /// The stores which do not implement it cannot export their routing table.
+    async fn get_static_routes(&self) -> Result<HashMap<String, Uuid>, NodeStoreError> {
+        Err(NodeStoreError::Unsupported("getting the static routes"))
+    }
+
     /// Sets the default settlement engines to be used for the provided asset codes
     async fn set_settlement_engines(
         &self,
@@ -138,43 +180,33 @@ pub trait NodeStore: Clone + Send + Sync + 'static {
         &self,
         asset_code: &str,
     ) -> Result<Option<Url>, NodeStoreError>;
-}
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ExchangeRates(
-    #[serde(deserialize_with = "map_of_number_or_string")] HashMap<String, f64>,
-);
+    /// Gets the default settlement engines of all the asset codes which have one
+    async fn get_settlement_engines(&self) -> Result<HashMap<String, Url>, NodeStoreError> {
+        Err(NodeStoreError::Unsupported(
+            "listing the settlement engines",
+        ))
+    }
 
-/// AccountSettings is a subset of the user parameters defined in
-/// AccountDetails. Its purpose is to allow a user to modify certain of their
-/// parameters which they may want to re-configure in the future, such as their
-/// tokens (which act as passwords), their settlement frequency preferences, or
-/// their HTTP/BTP endpoints, since they may change their network configuration.
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
-pub struct AccountSettings {
-    /// The account's incoming ILP over HTTP token.
-    pub ilp_over_http_incoming_token: Option<SecretString>,
-    /// The account's incoming ILP over BTP token.
-    pub ilp_over_btp_incoming_token: Option<SecretString>,
-    /// The account's outgoing ILP over HTTP token
-    pub ilp_over_http_outgoing_token: Option<SecretString>,
-    /// The account's outgoing ILP over BTP token.
-    /// This must match the ILP over BTP incoming token on the peer's node if exchanging
-    /// packets with that peer.
-    pub ilp_over_btp_outgoing_token: Option<SecretString>,
-    /// The account's ILP over HTTP URL (this is where packets are sent over HTTP from your node)
-    pub ilp_over_http_url: Option<String>,
-    /// The account's ILP over BTP URL (this is where packets are sent over WebSockets from your node)
-    pub ilp_over_btp_url: Option<String>,
-    /// The threshold after which the balance service will trigger a settlement
-    #[serde(default, deserialize_with = "optional_number_or_string")]
-    pub settle_threshold: Option<i64>,
-    /// The amount which the balance service will attempt to settle down to.
-    /// Note that this is intentionally an unsigned integer because users should
-    /// not be able to set the settle_to value to be negative (meaning the node
-    /// would pre-fund with the user)
-    #[serde(default, deserialize_with = "optional_number_or_string")]
-    pub settle_to: Option<u64>,
+    /// Removes the default settlement engine of the asset code, and returns it if it had one
+    async fn delete_settlement_engine(
+        &self,
+        _asset_code: &str,
+    ) -> Result<Option<Url>, NodeStoreError> {
+        Err(NodeStoreError::Unsupported("removing a settlement engine"))
+    }
+
+    /// Overwrites the runtime settings which are applied to the running node
+    /// in place of its configured values
+    async fn set_runtime_settings(&self, _settings: RuntimeSettings) -> Result<(), NodeStoreError> {
+        Err(NodeStoreError::Unsupported("changing the runtime settings"))
+    }
+
+    /// Gets the runtime settings (fields which are not set fall back to the node's configuration).
+    /// The stores which cannot save them have none, so the configured values are always used.
+    async fn get_runtime_settings(&self) -> Result<RuntimeSettings, NodeStoreError> {
+        Ok(RuntimeSettings::default())
+    }
 }
 
 /// EncryptedAccountSettings is created by encrypting the incoming and outgoing
@@ -195,71 +227,16 @@ pub struct EncryptedAccountSettings {
     #[serde(default, deserialize_with = "optional_number_or_string")]
     /// The amount which the balance service will attempt to settle down to
     pub settle_to: Option<u64>,
-}
-
-/// The Account type for the RedisStore.
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct AccountDetails {
-    /// The account's Interledger Protocol address.
-    /// If none is provided, the node should generate one
-    pub ilp_address: Option<Address>,
-    /// The account's username
-    pub username: Username,
-    /// The account's currency
-    pub asset_code: String,
-    #[serde(deserialize_with = "number_or_string")]
-    /// The account's asset scale
-    pub asset_scale: u8,
-    #[serde(default = "u64::max_value", deserialize_with = "number_or_string")]
-    /// The max amount per packet which can be routed for this account
-    pub max_packet_amount: u64,
-    /// The minimum balance this account can have (consider this as a credit/trust limit)
-    #[serde(default, deserialize_with = "optional_number_or_string")]
-    pub min_balance: Option<i64>,
-    /// The account's ILP over HTTP URL (this is where packets are sent over HTTP from your node)
-    pub ilp_over_http_url: Option<String>,
-    /// The account's API and incoming ILP over HTTP token.
-    /// This must match the ILP over HTTP outgoing token on the peer's node if receiving
-    /// packets from that peer
-    // TODO: The incoming token is used for both ILP over HTTP, and for authorizing actions from the HTTP API.
-    // Should we add 1 more token, for more granular permissioning?
-    pub ilp_over_http_incoming_token: Option<SecretString>,
-    /// The account's outgoing ILP over HTTP token
-    /// This must match the ILP over HTTP incoming token on the peer's node if sending
-    /// packets to that peer
-    pub ilp_over_http_outgoing_token: Option<SecretString>,
-    /// The account's ILP over BTP URL (this is where packets are sent over WebSockets from your node)
-    pub ilp_over_btp_url: Option<String>,
-    /// The account's outgoing ILP over BTP token.
-    /// This must match the ILP over BTP incoming token on the peer's node if exchanging
-    /// packets with that peer.
-    pub ilp_over_btp_outgoing_token: Option<SecretString>,
-    /// The account's incoming ILP over BTP token.
-    /// This must match the ILP over BTP outgoing token on the peer's node if exchanging
-    /// packets with that peer.
-    pub ilp_over_btp_incoming_token: Option<SecretString>,
-    /// The threshold after which the balance service will trigger a settlement
-    #[serde(default, deserialize_with = "optional_number_or_string")]
-    pub settle_threshold: Option<i64>,
-    /// The amount which the balance service will attempt to settle down to
     #[serde(default, deserialize_with = "optional_number_or_string")]
-    pub settle_to: Option<i64>,
-    /// The routing relation of the account
-    pub routing_relation: Option<String>,
-    /// The round trip time of the account (should be set depending on how
-    /// well the network connectivity of the account and the node is)
+    pub packets_per_second_limit: Option<u32>,
     #[serde(default, deserialize_with = "optional_number_or_string")]
-    pub round_trip_time: Option<u32>,
-    /// The maximum amount the account can send per minute
+    pub packets_per_minute_limit: Option<u32>,
     #[serde(default, deserialize_with = "optional_number_or_string")]
     pub amount_per_minute_limit: Option<u64>,
-    /// The limit of packets the account can send per minute
     #[serde(default, deserialize_with = "optional_number_or_string")]
-    pub packets_per_minute_limit: Option<u32>,
-    /// The account's settlement engine URL. If a global engine url is configured
-    /// for the account's asset code,  that will be used instead (even if the account is
-    /// configured with a specific one)
-    pub settlement_engine_url: Option<String>,
+    pub max_packet_amount: Option<u64>,
+    #[serde(default, deserialize_with = "optional_number_or_string")]
+    pub max_packet_size: Option<u64>,
 }
 
 pub struct NodeApi<S, I, O, B, A: Account> {
@@ -267,6 +244,8 @@ pub struct NodeApi<S, I, O, B, A: Account> {
     /// The admin's API token, used to make admin-only changes
     // TODO: Make this a SecretString
     admin_api_token: String,
+    /// Validates the JWTs accepted in addition to the static tokens, if configured
+    jwt_auth: Option<JwtAuth>,
     default_spsp_account: Option<Username>,
     incoming_handler: I,
     // The outgoing service is included so that the API can send outgoing
@@ -278,6 +257,41 @@ pub struct NodeApi<S, I, O, B, A: Account> {
     /// Server secret used to instantiate SPSP/Stream connections
     server_secret: Bytes,
     node_version: Option<String>,
+    /// The features enabled on the node, which are advertised by `GET /`
+    node_features: Vec<String>,
+    /// When the node started, from which `GET /` reports its uptime
+    started_at: Instant,
+    /// The node's fee policy, used to quote conversions
+    fee_policy: FeePolicy,
+    /// The pings whose echo responses are fulfilled by the node's `EchoService`
+    echo_pings: EchoPings,
+    peer_latencies: PeerLatencies,
+    /// The packet traces recorded by the node's `PacketTraceService`s
+    packet_traces: PacketTraces,
+    /// The balance notifications sent by the node's `BalanceNotifier`
+    balance_notifications: BalanceNotifications,
+    /// The faults injected by the node's `FaultInjectionService`s, if it has any
+    fault_injector: Option<FaultInjector>,
+    /// The incoming connections tracked by the node's `StreamReceiverService`
+    stream_connections: StreamConnections,
+    /// The statistics of the packets counted by the node's `StatsService`
+    node_stats: NodeStats,
+    /// The accounts quarantined by the node's `PeerBlocklistService`
+    blocklist: PeerBlocklist,
+    /// The periodic tasks of the node
+    scheduler: Scheduler,
+    /// The latest report of the node's settlement reconciliation task
+    settlement_reconciliation: SettlementReconciliation,
+    /// The client of the settlement engines, with which the accounts are created on them
+    settlement_client: SettlementClient,
+    /// Whether the receipts issued by the node's `StreamReceiverService` are verified via the API
+    receipt_verifier: bool,
+    /// Whether the node's data can be queried via `/graphql`
+    graphql: bool,
+    /// URL at which other nodes reach the API, which enables the peering requests
+    public_url: Option<Url>,
+    /// The defaults of the child accounts provisioned by wallets, if they can be
+    child_provisioning: Option<ChildProvisioning>,
 }
 
 impl<S, I, O, B, A> NodeApi<S, I, O, B, A>
@@ -287,10 +301,26 @@ where
         + AddressStore
         + HttpStore<Account = A>
         + BalanceStore
+        + BalanceJournalStore
+        + LiquidityStore
+        + TransferLimitStore
         + SettlementStore<Account = A>
         + StreamNotificationsStore<Account = A>
         + RouterStore
-        + ExchangeRateStore,
+        + ExchangeRateStore
+        + PinnedRateStore
+        + FeePolicyStore
+        + MaxPacketAmountStore
+        + UnreachableDestinationStore
+        + AuditLogStore
+        + AssetStore
+        + AccountTemplateStore
+        + PaymentPointerStore
+        + PullPaymentStore
+        + ScheduledPaymentStore
+        + OutgoingPaymentStore
+        + PeeringStore
+        + ReceiptVerifierStore,
     I: IncomingService<A> + Clone + Send + Sync + 'static,
     O: OutgoingService<A> + Clone + Send + Sync + 'static,
     B: OutgoingService<A> + Clone + Send + Sync + 'static,
@@ -298,6 +328,7 @@ where
         + CcpRoutingAccount
         + Account
         + HttpAccount
+        + FeeAccount
         + SettlementAccount
         + Serialize
         + Send
@@ -315,12 +346,31 @@ where
         NodeApi {
             store,
             admin_api_token,
+            jwt_auth: None,
             default_spsp_account: None,
             incoming_handler,
             outgoing_handler,
             btp,
             server_secret,
             node_version: None,
+            node_features: Vec::new(),
+            started_at: Instant::now(),
+            fee_policy: FeePolicy::default(),
+            echo_pings: EchoPings::default(),
+            peer_latencies: PeerLatencies::default(),
+            packet_traces: PacketTraces::default(),
+            balance_notifications: BalanceNotifications::default(),
+            fault_injector: None,
+            stream_connections: StreamConnections::default(),
+            node_stats: NodeStats::default(),
+            blocklist: PeerBlocklist::default(),
+            scheduler: Scheduler::default(),
+            settlement_reconciliation: SettlementReconciliation::default(),
+            settlement_client: SettlementClient::default(),
+            receipt_verifier: false,
+            graphql: false,
+            public_url: None,
+            child_provisioning: None,
         }
     }
 
@@ -338,23 +388,285 @@ where
         self
     }
 
-    /// Returns a Warp Filter which exposes the accounts and admin APIs
+    /// Sets the features enabled on the node (such as `btp` or `receipts`), so that the
+    /// clients of the API can adapt to them
+    pub fn node_features(&mut self, features: Vec<String>) -> &mut Self {
+        self.node_features = features;
+        self
+    }
+
+    /// Sets when the node started, if it did before the API was created
+    pub fn started_at(&mut self, started_at: Instant) -> &mut Self {
+        self.started_at = started_at;
+        self
+    }
+
+    /// Sets the node's fee policy, which the quotes fall back to
+    /// (it should match the one of the node's `ExchangeRateService`)
+    pub fn fee_policy(&mut self, fee_policy: FeePolicy) -> &mut Self {
+        self.fee_policy = fee_policy;
+        self
+    }
+
+    /// Sets the pings shared with the node's `EchoService`, which fulfills the
+    /// echo responses of the pings sent via the API
+    pub fn echo_pings(&mut self, echo_pings: EchoPings) -> &mut Self {
+        self.echo_pings = echo_pings;
+        self
+    }
+
+    /// Sets the round trip times of the probes of the peers, which are returned by
+    /// `GET /accounts/:username/stats`
+    pub fn peer_latencies(&mut self, peer_latencies: PeerLatencies) -> &mut Self {
+        self.peer_latencies = peer_latencies;
+        self
+    }
+
+    /// Sets the packet traces shared with the node's `PacketTraceService`s, which
+    /// record the packets of the accounts put in trace mode via the API
+    pub fn packet_traces(&mut self, packet_traces: PacketTraces) -> &mut Self {
+        self.packet_traces = packet_traces;
+        self
+    }
+
+    /// Sets the balance notifications sent by the node's `BalanceNotifier`, which are
+    /// relayed to the API's WebSocket subscribers
+    pub fn balance_notifications(
+        &mut self,
+        balance_notifications: BalanceNotifications,
+    ) -> &mut Self {
+        self.balance_notifications = balance_notifications;
+        self
+    }
+
+    /// Sets the connections shared with the node's `StreamReceiverService`, which
+    /// tracks the incoming connections listed and closed via the API
+    pub fn stream_connections(&mut self, stream_connections: StreamConnections) -> &mut Self {
+        self.stream_connections = stream_connections;
+        self
+    }
+
+    /// Sets the fault injector shared with the node's `FaultInjectionService`s, which
+    /// enables configuring the faults injected into the packets of the accounts via the API
+    pub fn fault_injector(&mut self, fault_injector: FaultInjector) -> &mut Self {
+        self.fault_injector = Some(fault_injector);
+        self
+    }
+
+    /// Sets the statistics shared with the node's `StatsService` and `ExchangeRateService`,
+    /// which are returned by `GET /stats/overview` and `GET /stats/conversions`
+    pub fn node_stats(&mut self, node_stats: NodeStats) -> &mut Self {
+        self.node_stats = node_stats;
+        self
+    }
+
+    /// Sets the blocklist shared with the node's `PeerBlocklistService` and servers,
+    /// which is viewed and overridden via `/blocklist`
+    pub fn blocklist(&mut self, blocklist: PeerBlocklist) -> &mut Self {
+        self.blocklist = blocklist;
+        self
+    }
+
+    /// Sets the scheduler of the node's periodic tasks, which are
+    /// listed via `GET /tasks` and triggered via `POST /tasks/:name/run`
+    pub fn scheduler(&mut self, scheduler: Scheduler) -> &mut Self {
+        self.scheduler = scheduler;
+        self
+    }
+
+    /// Sets the reconciliation of the node's settlements with the settlement engines,
+    /// whose latest report is returned by `GET /settlements/reconciliation`
+    pub fn settlement_reconciliation(
+        &mut self,
+        settlement_reconciliation: SettlementReconciliation,
+    ) -> &mut Self {
+        self.settlement_reconciliation = settlement_reconciliation;
+        self
+    }
+
+    /// Sets the client with which the accounts are created on their settlement engines
+    pub fn settlement_client(&mut self, settlement_client: SettlementClient) -> &mut Self {
+        self.settlement_client = settlement_client;
+        self
+    }
+
+    /// Makes the API also accept the JWTs issued by an identity provider, validated
+    /// against its JWKS, in addition to the admin's and the accounts' static tokens
+    pub fn jwt_auth(&mut self, jwt_auth: JwtAuth) -> &mut Self {
+        self.jwt_auth = Some(jwt_auth);
+        self
+    }
+
+    /// Enables the receipt verifier: the connections opened via the payment pointers
+    /// hosted by the node are tracked, and `POST /receipts/verify` credits the amounts
+    /// proven by their receipts to the payment pointers' balances. The node's
+    /// `StreamReceiverService` must be set up to issue receipts.
+    pub fn receipt_verifier(&mut self) -> &mut Self {
+        self.receipt_verifier = true;
+        self
+    }
+
+    /// Enables the `/graphql` endpoint, with which the admin queries the accounts, balances,
+    /// settlements, routes, rates and payments of the node in a single request. The
+    /// payments received from now on are kept in memory to be listed.
+    pub fn graphql(&mut self) -> &mut Self {
+        self.graphql = true;
+        self
+    }
+
+    /// Sets the URL at which other nodes reach the API. It enables the peering requests,
+    /// with which the accounts of two nodes are created on both sides once an admin
+    /// approves them, since the nodes send each other URLs relative to it.
+    pub fn public_url(&mut self, public_url: Url) -> &mut Self {
+        self.public_url = Some(public_url);
+        self
+    }
+
+    /// Enables the self-service provisioning of child accounts: a wallet which signs a
+    /// challenge issued by the node with its Ed25519 key gets an account under the node,
+    /// with an address derived from the key and the constrained defaults of the configuration
+    pub fn child_provisioning(&mut self, child_provisioning: ChildProvisioning) -> &mut Self {
+        self.child_provisioning = Some(child_provisioning);
+        self
+    }
+
+    /// Returns the gRPC server of the admin API, which is served alongside the HTTP API
+    /// (see the [`grpc`](./grpc/index.html) module)
+    pub fn grpc_api(&self) -> GrpcApi<S, I, O, B, A> {
+        let mut api = GrpcApi::new(
+            self.admin_api_token.clone(),
+            self.jwt_auth.clone(),
+            self.store.clone(),
+            self.incoming_handler.clone(),
+            self.outgoing_handler.clone(),
+            self.btp.clone(),
+        );
+        api.settlement_client(self.settlement_client.clone());
+        api
+    }
+
+    /// Returns a Warp Filter which exposes the accounts and admin APIs, under the prefix of
+    /// their current version and under their deprecated unversioned paths
     pub fn into_warp_filter(self) -> warp::filters::BoxedFilter<(impl warp::Reply,)> {
-        routes::accounts_api(
+        let (api, spsp) = self.into_warp_filters();
+        // The payment pointers hosted on arbitrary paths are looked up last,
+        // so that they do not take precedence over the other routes
+        versioned_api(api, None).or(spsp).boxed()
+    }
+
+    /// Returns the Warp Filters of the accounts and admin APIs, and of the (public) SPSP
+    /// endpoints which the payment pointers resolve to, so that they can be served apart.
+    /// The API is served under its unversioned paths, which `versioned_api` deprecates in
+    /// favor of the versioned ones.
+    pub fn into_warp_filters(
+        self,
+    ) -> (
+        warp::filters::BoxedFilter<(impl warp::Reply,)>,
+        warp::filters::BoxedFilter<(impl warp::Reply,)>,
+    ) {
+        let receipts = if self.receipt_verifier {
+            Some(ReceiptGenerator::new(&self.server_secret))
+        } else {
+            None
+        };
+        let payments = if self.graphql {
+            let payments = RecentPayments::new(RECENT_PAYMENTS_CAPACITY);
+            payments.record(&self.store);
+            Some(payments)
+        } else {
+            None
+        };
+        let spsp = routes::spsp_api(
+            self.server_secret.clone(),
+            receipts.clone(),
+            self.default_spsp_account,
+            self.store.clone(),
+        )
+        .boxed();
+        let peering = routes::peering_api(
+            self.admin_api_token.clone(),
+            self.jwt_auth.clone(),
+            self.public_url,
+            self.outgoing_handler.clone(),
+            self.btp.clone(),
+            self.settlement_client.clone(),
+            self.store.clone(),
+        );
+        let provisioning = routes::provisioning_api(
+            self.child_provisioning,
+            self.server_secret.clone(),
+            self.outgoing_handler.clone(),
+            self.btp.clone(),
+            self.settlement_client.clone(),
+            self.store.clone(),
+        );
+        let api = routes::accounts_api(
             self.server_secret,
             self.admin_api_token.clone(),
-            self.default_spsp_account,
-            self.incoming_handler,
+            self.jwt_auth.clone(),
+            self.incoming_handler.clone(),
             self.outgoing_handler,
             self.btp,
+            self.echo_pings,
+            self.peer_latencies,
+            self.packet_traces,
+            self.balance_notifications,
+            self.fault_injector,
+            self.stream_connections,
+            self.settlement_client.clone(),
             self.store.clone(),
         )
         .or(routes::node_settings_api(
-            self.admin_api_token,
+            self.admin_api_token.clone(),
+            self.jwt_auth.clone(),
             self.node_version,
+            self.node_features,
+            self.started_at,
+            self.fee_policy,
+            self.node_stats,
+            self.blocklist,
+            self.scheduler,
+            self.settlement_reconciliation,
+            self.settlement_client,
+            self.store.clone(),
+        ))
+        .or(routes::receipts_api(
+            receipts,
+            self.admin_api_token.clone(),
+            self.jwt_auth.clone(),
+            self.store.clone(),
+        ))
+        .or(routes::pull_payments_api(
+            self.admin_api_token.clone(),
+            self.jwt_auth.clone(),
+            self.incoming_handler,
+            self.store.clone(),
+        ))
+        .or(peering)
+        .or(provisioning)
+        .or(routes::graphql_api(
+            payments,
+            self.admin_api_token.clone(),
+            self.jwt_auth.clone(),
+            self.store.clone(),
+        ))
+        .or(routes::assets_api(
+            self.admin_api_token.clone(),
+            self.jwt_auth.clone(),
+            self.store.clone(),
+        ))
+        .or(routes::account_templates_api(
+            self.admin_api_token.clone(),
+            self.jwt_auth.clone(),
+            self.store.clone(),
+        ))
+        .or(routes::payment_pointers_api(
+            self.admin_api_token,
+            self.jwt_auth,
             self.store,
         ))
-        .boxed()
+        .boxed();
+        (api, spsp)
     }
 
     /// Serves the API at the provided address
@@ -441,4 +753,28 @@ mod tests {
         );
         assert!(settings.ilp_over_btp_url.is_none());
     }
+
+    #[test]
+    fn runtime_settings_fall_back_to_other() {
+        let overrides: RuntimeSettings = serde_json::from_value(json!({
+            "route_broadcast_interval": "5000",
+            "max_packet_amount": 100,
+        }))
+        .unwrap();
+        let configured = RuntimeSettings {
+            route_broadcast_interval: Some(30000),
+            exchange_rate_poll_interval: Some(60000),
+            max_packet_amount: None,
+            max_packet_size: Some(4096),
+        };
+        assert_eq!(
+            overrides.or(configured),
+            RuntimeSettings {
+                route_broadcast_interval: Some(5000),
+                exchange_rate_poll_interval: Some(60000),
+                max_packet_amount: Some(100),
+                max_packet_size: Some(4096),
+            }
+        );
+    }
 }
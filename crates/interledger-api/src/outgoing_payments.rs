@@ -0,0 +1,307 @@
+use crate::scheduled_payments::notify_outcome;
+use async_trait::async_trait;
+use futures::future::{select, Either};
+use interledger_api_types::SpspPayRequest;
+use interledger_errors::{ApiError, NodeStoreError};
+use interledger_service::Username;
+use interledger_spsp::Error as SpspError;
+use interledger_stream::StreamDelivery;
+use reqwest::Client;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{debug, warn};
+use url::Url;
+
+/// How often the payments which are being sent are marked as still in progress
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+/// Time, in seconds, after which a payment which was not marked as in progress is
+/// considered interrupted (because the node sending it stopped)
+const INTERRUPTED_AFTER: u64 = 60;
+/// Time, in seconds, for which the finished payments are kept in the history
+const HISTORY_RETENTION: u64 = 30 * 24 * 3600;
+
+/// Returns a random and URL-safe id of a scheduled or outgoing payment
+pub(crate) fn generate_payment_id() -> Result<String, ApiError> {
+    let mut id = [0; 18];
+    SystemRandom::new()
+        .fill(&mut id)
+        .map_err(|_| ApiError::internal_server_error().detail("could not generate a random id"))?;
+    Ok(base64::encode_config(&id, base64::URL_SAFE_NO_PAD))
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_secs()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutgoingPaymentStatus {
+    /// The payment is being sent
+    Sending,
+    /// The payment was sent (it may still have delivered less than its source amount)
+    Completed,
+    /// The payment failed, or was interrupted by the node stopping
+    Failed,
+}
+
+/// A payment sent from an account via the API, which is recorded before it is sent so
+/// that its outcome is known even if the node stops while sending it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OutgoingPayment {
+    /// Random identifier of the payment
+    pub id: String,
+    /// The account the payment is sent from
+    pub username: Username,
+    /// The payment pointer or SPSP URL of the receiver
+    pub receiver: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_amount: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub destination_amount: Option<u64>,
+    /// The max slippage of the exchange rate, as a fraction
+    pub slippage: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
+    /// URL to which the payment is POSTed as JSON once it is finished, if there is one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notification_url: Option<Url>,
+    pub status: OutgoingPaymentStatus,
+    /// When the payment was requested, as seconds since the UNIX epoch
+    pub created_at: u64,
+    /// When the payment was last updated (it is updated regularly while it is being sent),
+    /// as seconds since the UNIX epoch
+    pub updated_at: u64,
+    /// The receipt of the payment, once it was sent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delivery: Option<StreamDelivery>,
+    /// Why the payment failed, if it did
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl OutgoingPayment {
+    pub fn new(id: String, username: Username, request: &SpspPayRequest) -> Result<Self, ApiError> {
+        let notification_url = request
+            .notification_url
+            .as_ref()
+            .map(|url| Url::parse(url))
+            .transpose()
+            .map_err(|err| {
+                ApiError::bad_request().detail(format!("invalid notification URL: {}", err))
+            })?;
+        let now = now();
+        Ok(OutgoingPayment {
+            id,
+            username,
+            receiver: request.receiver.clone(),
+            source_amount: request.source_amount,
+            destination_amount: request.destination_amount,
+            slippage: request.slippage,
+            memo: request.memo.clone(),
+            notification_url,
+            status: OutgoingPaymentStatus::Sending,
+            created_at: now,
+            updated_at: now,
+            delivery: None,
+            error: None,
+        })
+    }
+
+    /// Returns whether the node sending the payment stopped before it finished, at the time
+    /// (in seconds since the UNIX epoch)
+    pub fn is_interrupted(&self, now: u64) -> bool {
+        self.status == OutgoingPaymentStatus::Sending
+            && self.updated_at.saturating_add(INTERRUPTED_AFTER) < now
+    }
+
+    /// Records the outcome of the payment
+    pub fn finish(&mut self, result: Result<StreamDelivery, String>, now: u64) {
+        self.updated_at = now;
+        match result {
+            Ok(delivery) => {
+                self.status = OutgoingPaymentStatus::Completed;
+                self.delivery = Some(delivery);
+            }
+            Err(err) => {
+                self.status = OutgoingPaymentStatus::Failed;
+                self.error = Some(err);
+            }
+        }
+    }
+}
+
+/// Store of the payments sent from the node's accounts via the API
+#[async_trait]
+pub trait OutgoingPaymentStore {
+    /// Saves the payment, replacing the one with the same id
+    async fn save_outgoing_payment(&self, payment: OutgoingPayment) -> Result<(), NodeStoreError>;
+
+    /// Returns the payment with the id, if there is one
+    async fn get_outgoing_payment(
+        &self,
+        id: &str,
+    ) -> Result<Option<OutgoingPayment>, NodeStoreError>;
+
+    /// Returns all payments, newest first
+    async fn get_outgoing_payments(&self) -> Result<Vec<OutgoingPayment>, NodeStoreError>;
+
+    /// Returns a page of about `count` payments, in no particular order, starting at the
+    /// cursor (0 for the first page), along with the cursor of the next page, which is 0
+    /// after the last one
+    async fn get_outgoing_payments_page(
+        &self,
+        cursor: u64,
+        count: usize,
+    ) -> Result<(u64, Vec<OutgoingPayment>), NodeStoreError>;
+
+    /// Deletes the payments with the ids
+    async fn delete_outgoing_payments(&self, ids: Vec<String>) -> Result<(), NodeStoreError>;
+}
+
+/// Sends the payment with `send`, after recording it in the store, and records and
+/// notifies its outcome. The payment is marked as in progress while it is being sent.
+/// Fails without sending the payment if it cannot be recorded.
+pub(crate) async fn send_recorded_payment<S, F>(
+    store: &S,
+    mut payment: OutgoingPayment,
+    send: F,
+) -> Result<Result<StreamDelivery, SpspError>, NodeStoreError>
+where
+    S: OutgoingPaymentStore,
+    F: Future<Output = Result<StreamDelivery, SpspError>>,
+{
+    store.save_outgoing_payment(payment.clone()).await?;
+    let heartbeat = {
+        let mut payment = payment.clone();
+        async move {
+            let mut interval = tokio::time::interval_at(
+                tokio::time::Instant::now() + HEARTBEAT_INTERVAL,
+                HEARTBEAT_INTERVAL,
+            );
+            loop {
+                interval.tick().await;
+                payment.updated_at = now();
+                if let Err(err) = store.save_outgoing_payment(payment.clone()).await {
+                    warn!(
+                        "Error marking payment {} as in progress: {}",
+                        payment.id, err
+                    );
+                }
+            }
+        }
+    };
+    futures::pin_mut!(send);
+    futures::pin_mut!(heartbeat);
+    let result = match select(send, heartbeat).await {
+        Either::Left((result, _)) => result,
+        Either::Right(_) => unreachable!("the heartbeat never stops"),
+    };
+
+    payment.finish(
+        result
+            .as_ref()
+            .map(Clone::clone)
+            .map_err(ToString::to_string),
+        now(),
+    );
+    store.save_outgoing_payment(payment.clone()).await?;
+    if let Some(url) = payment.notification_url.clone() {
+        notify_outcome(&Client::new(), url, &payment, &payment.id).await;
+    }
+    Ok(result)
+}
+
+/// Fails the payments whose sending node stopped before they finished, at the time (in
+/// seconds since the UNIX epoch), and notifies them. The payments finished for longer than
+/// the retention of the history are removed. Returns the number of payments failed.
+pub async fn fail_interrupted_payments<S>(store: S, now: u64) -> Result<usize, NodeStoreError>
+where
+    S: OutgoingPaymentStore,
+{
+    let payments = store.get_outgoing_payments().await?;
+    let client = Client::new();
+    let mut failed = 0;
+    let mut expired = Vec::new();
+    for mut payment in payments {
+        if payment.is_interrupted(now) {
+            payment.finish(
+                Err("the payment was interrupted by the node stopping, after delivering an unknown part of its amount".to_string()),
+                now,
+            );
+            store.save_outgoing_payment(payment.clone()).await?;
+            debug!(
+                "Failed interrupted payment {} of {}",
+                payment.id, payment.username
+            );
+            failed += 1;
+            if let Some(url) = payment.notification_url.clone() {
+                notify_outcome(&client, url, &payment, &payment.id).await;
+            }
+        } else if payment.status != OutgoingPaymentStatus::Sending
+            && payment.updated_at.saturating_add(HISTORY_RETENTION) < now
+        {
+            expired.push(payment.id);
+        }
+    }
+    if !expired.is_empty() {
+        store.delete_outgoing_payments(expired).await?;
+    }
+    Ok(failed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn payment() -> OutgoingPayment {
+        OutgoingPayment::new(
+            "id".to_string(),
+            Username::from_str("alice").unwrap(),
+            &SpspPayRequest {
+                receiver: "$example.com/bob".to_string(),
+                source_amount: Some(100),
+                destination_amount: None,
+                slippage: 0.015,
+                memo: None,
+                notification_url: Some("https://wallet.example/payments".to_string()),
+            },
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn is_interrupted_once_it_is_no_longer_updated() {
+        let mut payment = payment();
+        assert_eq!(payment.status, OutgoingPaymentStatus::Sending);
+        assert!(!payment.is_interrupted(payment.updated_at + INTERRUPTED_AFTER));
+        assert!(payment.is_interrupted(payment.updated_at + INTERRUPTED_AFTER + 1));
+
+        let now = payment.updated_at + INTERRUPTED_AFTER + 1;
+        payment.finish(Err("interrupted".to_string()), now);
+        assert_eq!(payment.status, OutgoingPaymentStatus::Failed);
+        assert_eq!(payment.error.as_deref(), Some("interrupted"));
+        assert!(!payment.is_interrupted(now + INTERRUPTED_AFTER + 1));
+    }
+
+    #[test]
+    fn rejects_invalid_notification_urls() {
+        let mut request = SpspPayRequest {
+            receiver: "$example.com/bob".to_string(),
+            source_amount: Some(100),
+            destination_amount: None,
+            slippage: 0.015,
+            memo: None,
+            notification_url: Some("not a url".to_string()),
+        };
+        let username = Username::from_str("alice").unwrap();
+        assert!(OutgoingPayment::new("id".to_string(), username.clone(), &request).is_err());
+        request.notification_url = None;
+        assert!(OutgoingPayment::new("id".to_string(), username, &request).is_ok());
+    }
+}
@@ -0,0 +1,112 @@
+use async_trait::async_trait;
+use interledger_errors::{ApiError, NodeStoreError};
+use interledger_service::Username;
+use serde::{Deserialize, Serialize};
+
+/// Path which a payment pointer without a path (such as `$example.com`) resolves to
+pub const WELL_KNOWN_PATH: &str = "/.well-known/pay";
+
+/// A payment pointer hosted by the node, which resolves to an account's STREAM receiver
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PaymentPointer {
+    /// Path of the payment pointer on the node's domain, such as `/.well-known/pay`,
+    /// `/donate` or `/invoices/123`
+    pub path: String,
+    /// The account which receives the payments
+    pub username: Username,
+    /// Segment appended to the account's ILP address, which tells the payments sent to
+    /// this payment pointer apart and selects its own receiver secret
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub destination_tag: Option<String>,
+}
+
+impl PaymentPointer {
+    /// Checks that the path and destination tag can be served and used in ILP addresses
+    pub fn validate(&self) -> Result<(), ApiError> {
+        if !is_valid_path(&self.path) {
+            return Err(ApiError::bad_request().detail(format!(
+                "invalid payment pointer path {:?} (expected a path like /donate)",
+                self.path
+            )));
+        }
+        if let Some(ref tag) = self.destination_tag {
+            let valid = !tag.is_empty()
+                && tag
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '~' || c == '-');
+            if !valid {
+                return Err(ApiError::bad_request().detail(format!(
+                    "invalid destination tag {:?} (expected a single ILP address segment)",
+                    tag
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Max length of the paths of the payment pointers
+pub const MAX_PATH_LENGTH: usize = 256;
+
+/// Returns whether the path can be the path of a payment pointer, such as `/donate`
+pub fn is_valid_path(path: &str) -> bool {
+    path.starts_with('/')
+        && path.len() >= 2
+        && path.len() <= MAX_PATH_LENGTH
+        && !path.ends_with('/')
+        && !path.contains("//")
+        && !path.contains(|c: char| c.is_whitespace() || c == '?' || c == '#')
+}
+
+/// Store of the payment pointers hosted by the node
+#[async_trait]
+pub trait PaymentPointerStore {
+    /// Returns the payment pointer served at the path, if there is one
+    async fn get_payment_pointer(
+        &self,
+        path: &str,
+    ) -> Result<Option<PaymentPointer>, NodeStoreError>;
+
+    /// Returns all payment pointers, ordered by path
+    async fn get_payment_pointers(&self) -> Result<Vec<PaymentPointer>, NodeStoreError>;
+
+    /// Saves the payment pointer, replacing the one with the same path
+    async fn set_payment_pointer(&self, pointer: PaymentPointer) -> Result<(), NodeStoreError>;
+
+    /// Deletes the payment pointer served at the path and returns it
+    async fn delete_payment_pointer(
+        &self,
+        path: &str,
+    ) -> Result<Option<PaymentPointer>, NodeStoreError>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn pointer(path: &str, destination_tag: Option<&str>) -> PaymentPointer {
+        PaymentPointer {
+            path: path.to_string(),
+            username: Username::from_str("alice").unwrap(),
+            destination_tag: destination_tag.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn validates_paths_and_tags() {
+        assert!(pointer(WELL_KNOWN_PATH, None).validate().is_ok());
+        assert!(pointer("/invoices/123", Some("invoice-123"))
+            .validate()
+            .is_ok());
+        let too_long = format!("/{}", "a".repeat(MAX_PATH_LENGTH));
+        for path in &[
+            "", "/", "donate", "/donate/", "/a//b", "/a b", "/a?b", &too_long,
+        ] {
+            assert!(pointer(path, None).validate().is_err(), "{}", path);
+        }
+        for tag in &["", "a.b", "a/b"] {
+            assert!(pointer("/donate", Some(tag)).validate().is_err(), "{}", tag);
+        }
+    }
+}
@@ -0,0 +1,243 @@
+use crate::outgoing_payments::{OutgoingPayment, OutgoingPaymentStatus};
+use interledger_packet::Address;
+use interledger_service::Username;
+use ring::{
+    hmac,
+    signature::{Ed25519KeyPair, KeyPair, UnparsedPublicKey, ED25519},
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// Path at which the public key verifying the payment receipts is published
+pub const RECEIPT_KEY_PATH: &str = "/.well-known/payment-receipt-key";
+
+static PAYMENT_RECEIPT_KEY_GENERATOR: &[u8] = b"ilp_payment_receipt_key_generator";
+
+/// What the node attests about a payment it completed, which is signed as JSON
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PaymentReceipt {
+    pub payment_id: String,
+    /// The account the payment was sent from
+    pub username: Username,
+    /// The payment pointer or SPSP URL of the receiver
+    pub receiver: String,
+    /// ILP address the payment was sent from
+    pub source_address: Address,
+    /// ILP address the payment was delivered to
+    pub destination_address: Address,
+    /// Amount sent, in source units
+    pub source_amount: u64,
+    pub source_asset_code: String,
+    pub source_asset_scale: u8,
+    /// Amount delivered to the receiver, in destination units
+    pub delivered_amount: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub destination_asset_code: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub destination_asset_scale: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
+    /// When the payment was requested, as seconds since the UNIX epoch
+    pub created_at: u64,
+    /// When the payment was completed, as seconds since the UNIX epoch
+    pub completed_at: u64,
+}
+
+impl PaymentReceipt {
+    /// Returns the receipt of the payment, if it was completed
+    pub fn new(payment: &OutgoingPayment) -> Option<Self> {
+        if payment.status != OutgoingPaymentStatus::Completed {
+            return None;
+        }
+        let delivery = payment.delivery.as_ref()?;
+        Some(PaymentReceipt {
+            payment_id: payment.id.clone(),
+            username: payment.username.clone(),
+            receiver: payment.receiver.clone(),
+            source_address: delivery.from.clone(),
+            destination_address: delivery.to.clone(),
+            source_amount: delivery.sent_amount,
+            source_asset_code: delivery.source_asset_code.clone(),
+            source_asset_scale: delivery.source_asset_scale,
+            delivered_amount: delivery.delivered_amount,
+            destination_asset_code: delivery.destination_asset_code.clone(),
+            destination_asset_scale: delivery.destination_asset_scale,
+            memo: payment.memo.clone(),
+            created_at: payment.created_at,
+            completed_at: payment.updated_at,
+        })
+    }
+}
+
+/// A payment receipt along with the node's signature of it. The signature is the Ed25519
+/// signature of the (ASCII) payload, which is the base64url-encoded JSON of the receipt, so
+/// that it is verified without having to serialize the receipt the same way.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SignedPaymentReceipt {
+    pub receipt: PaymentReceipt,
+    pub payload: String,
+    /// The base64url-encoded signature of the payload
+    pub signature: String,
+}
+
+impl SignedPaymentReceipt {
+    /// Returns whether the receipt was signed with the (raw, 32 bytes) public key and
+    /// matches its signed payload
+    pub fn verify(&self, public_key: &[u8]) -> bool {
+        let signature = match base64::decode_config(&self.signature, base64::URL_SAFE_NO_PAD) {
+            Ok(signature) => signature,
+            Err(_) => return false,
+        };
+        if UnparsedPublicKey::new(&ED25519, public_key)
+            .verify(self.payload.as_bytes(), &signature)
+            .is_err()
+        {
+            return false;
+        }
+        base64::decode_config(&self.payload, base64::URL_SAFE_NO_PAD)
+            .ok()
+            .and_then(|payload| serde_json::from_slice::<PaymentReceipt>(&payload).ok())
+            .map_or(false, |receipt| receipt == self.receipt)
+    }
+}
+
+/// The public key verifying the payment receipts, published at `RECEIPT_KEY_PATH`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReceiptPublicKey {
+    pub algorithm: String,
+    /// The base64url-encoded raw public key
+    pub public_key: String,
+}
+
+/// Signs the receipts of the payments sent via the API, with a key derived from the
+/// server secret so that it stays the same across restarts
+#[derive(Clone)]
+pub struct PaymentReceiptSigner {
+    key_pair: Arc<Ed25519KeyPair>,
+}
+
+impl PaymentReceiptSigner {
+    pub fn new(server_secret: &[u8]) -> Self {
+        let key = hmac::Key::new(hmac::HMAC_SHA256, server_secret);
+        let seed = hmac::sign(&key, PAYMENT_RECEIPT_KEY_GENERATOR);
+        let key_pair = Ed25519KeyPair::from_seed_unchecked(seed.as_ref())
+            .expect("HMAC-SHA256 outputs are valid Ed25519 seeds");
+        PaymentReceiptSigner {
+            key_pair: Arc::new(key_pair),
+        }
+    }
+
+    pub fn public_key(&self) -> ReceiptPublicKey {
+        ReceiptPublicKey {
+            algorithm: "Ed25519".to_string(),
+            public_key: base64::encode_config(
+                self.key_pair.public_key().as_ref(),
+                base64::URL_SAFE_NO_PAD,
+            ),
+        }
+    }
+
+    /// Returns the signed receipt of the payment, if it was completed
+    pub fn sign(&self, payment: &OutgoingPayment) -> Option<SignedPaymentReceipt> {
+        let receipt = PaymentReceipt::new(payment)?;
+        let json = serde_json::to_vec(&receipt).ok()?;
+        let payload = base64::encode_config(&json, base64::URL_SAFE_NO_PAD);
+        let signature = self.key_pair.sign(payload.as_bytes());
+        Some(SignedPaymentReceipt {
+            receipt,
+            payload,
+            signature: base64::encode_config(signature.as_ref(), base64::URL_SAFE_NO_PAD),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use interledger_api_types::SpspPayRequest;
+    use interledger_stream::StreamDelivery;
+    use std::str::FromStr;
+
+    fn payment() -> OutgoingPayment {
+        OutgoingPayment::new(
+            "id".to_string(),
+            Username::from_str("alice").unwrap(),
+            &SpspPayRequest {
+                receiver: "$example.com/bob".to_string(),
+                source_amount: Some(100),
+                destination_amount: None,
+                slippage: 0.015,
+                memo: Some("invoice 42".to_string()),
+                notification_url: None,
+            },
+        )
+        .unwrap()
+    }
+
+    fn delivery() -> StreamDelivery {
+        StreamDelivery {
+            from: Address::from_str("example.alice").unwrap(),
+            to: Address::from_str("example.bob.abc").unwrap(),
+            source_asset_scale: 9,
+            source_asset_code: "XYZ".to_string(),
+            source_amount: 100,
+            sent_amount: 100,
+            in_flight_amount: 0,
+            delivered_amount: 200,
+            destination_asset_scale: Some(9),
+            destination_asset_code: Some("ABC".to_string()),
+        }
+    }
+
+    #[test]
+    fn signs_receipts_of_completed_payments() {
+        let signer = PaymentReceiptSigner::new(&[0; 32]);
+        let mut payment = payment();
+        assert!(signer.sign(&payment).is_none());
+        payment.finish(Ok(delivery()), payment.created_at + 5);
+
+        let signed = signer.sign(&payment).unwrap();
+        assert_eq!(signed.receipt.delivered_amount, 200);
+        assert_eq!(signed.receipt.completed_at, payment.created_at + 5);
+        let public_key =
+            base64::decode_config(&signer.public_key().public_key, base64::URL_SAFE_NO_PAD)
+                .unwrap();
+        assert!(signed.verify(&public_key));
+
+        // The key is the same for the same server secret only
+        assert_eq!(
+            PaymentReceiptSigner::new(&[0; 32]).public_key(),
+            signer.public_key()
+        );
+        let other = PaymentReceiptSigner::new(&[1; 32]).public_key().public_key;
+        assert!(!signed.verify(&base64::decode_config(&other, base64::URL_SAFE_NO_PAD).unwrap()));
+    }
+
+    #[test]
+    fn detects_tampered_receipts() {
+        let signer = PaymentReceiptSigner::new(&[0; 32]);
+        let public_key =
+            base64::decode_config(&signer.public_key().public_key, base64::URL_SAFE_NO_PAD)
+                .unwrap();
+        let mut payment = payment();
+        payment.finish(Ok(delivery()), payment.created_at);
+        let signed = signer.sign(&payment).unwrap();
+
+        let mut tampered = signed.clone();
+        tampered.receipt.delivered_amount = 2000;
+        assert!(!tampered.verify(&public_key));
+
+        let mut receipt = signed.receipt.clone();
+        receipt.delivered_amount = 2000;
+        let mut tampered = signed;
+        tampered.payload = base64::encode_config(
+            &serde_json::to_vec(&receipt).unwrap(),
+            base64::URL_SAFE_NO_PAD,
+        );
+        tampered.receipt = receipt;
+        assert!(!tampered.verify(&public_key));
+
+        payment.finish(Err("failed".to_string()), payment.created_at);
+        assert!(signer.sign(&payment).is_none());
+    }
+}
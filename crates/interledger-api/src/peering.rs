@@ -0,0 +1,164 @@
+use crate::number_or_string;
+use async_trait::async_trait;
+use interledger_errors::{ApiError, NodeStoreError};
+use interledger_service::Username;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// Time, in milliseconds, after which the peering requests which were neither approved
+/// nor rejected are dropped (7 days)
+pub const PEERING_REQUEST_TTL: u64 = 7 * 24 * 3600 * 1000;
+/// Number of peering requests waiting for approval above which new ones are refused
+pub const MAX_PENDING_PEERING_REQUESTS: usize = 100;
+
+/// A request to peer received from another node, which waits for the admin's approval
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PeeringRequest {
+    /// Random identifier of the request, under which it is approved or rejected
+    pub id: String,
+    /// Username of the account which is created for the requester
+    pub username: Username,
+    pub asset_code: String,
+    pub asset_scale: u8,
+    /// URL to which the packets for the requester are sent over HTTP
+    pub ilp_over_http_url: String,
+    /// Token with which this node authenticates the packets it sends to the requester.
+    /// Stores keep it encrypted.
+    pub ilp_over_http_token: String,
+    /// URL to which the approval (or rejection) of the request is sent
+    pub callback_url: Url,
+    /// Token with which the approval is authenticated to the requester. Stores keep it
+    /// encrypted.
+    pub callback_token: String,
+    /// When the request was received, as milliseconds since the UNIX epoch
+    pub received_at: u64,
+}
+
+impl PeeringRequest {
+    /// Returns whether the request is too old to be approved
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.received_at.saturating_add(PEERING_REQUEST_TTL) <= now
+    }
+}
+
+/// A request to peer which this node sent to another one, until that node's admin
+/// approves or rejects it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PeeringProposal {
+    /// Random identifier of the proposal, which is part of its callback URL
+    pub id: String,
+    /// Base URL of the API of the node the request was sent to
+    pub peer_url: Url,
+    /// Username of the account which is created for the peer once it approves
+    pub username: Username,
+    pub asset_code: String,
+    pub asset_scale: u8,
+    /// Token with which the peer authenticates the packets it sends to this node. Stores
+    /// may return it hashed, as the incoming tokens of the accounts.
+    pub ilp_over_http_incoming_token: String,
+    /// Token with which the peer authenticates its response. Stores may return it hashed,
+    /// so it is checked with [`PeeringStore::verify_peering_callback_token`].
+    pub callback_token: String,
+    /// When the request was sent, as milliseconds since the UNIX epoch
+    pub sent_at: u64,
+}
+
+/// The parameters of a peering request to send, given by the admin
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PeeringTerms {
+    /// Base URL of the API of the node to peer with, such as `https://peer.example.com`
+    pub peer_url: Url,
+    /// Username of the account created on this node for the peer
+    pub username: Username,
+    /// Username proposed for this node's account on the peer. Defaults to the last
+    /// segment of this node's ILP address.
+    #[serde(default)]
+    pub peer_username: Option<Username>,
+    pub asset_code: String,
+    #[serde(deserialize_with = "number_or_string")]
+    pub asset_scale: u8,
+}
+
+/// The body of the `POST /peering/requests` call which one node sends to another
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PeeringRequestBody {
+    pub username: Username,
+    pub asset_code: String,
+    #[serde(deserialize_with = "number_or_string")]
+    pub asset_scale: u8,
+    pub ilp_over_http_url: String,
+    pub ilp_over_http_token: String,
+    pub callback_url: Url,
+    pub callback_token: String,
+}
+
+impl PeeringRequestBody {
+    /// Checks that the requested account could be created
+    pub fn validate(&self) -> Result<(), ApiError> {
+        if self.asset_code.is_empty()
+            || self.ilp_over_http_token.is_empty()
+            || self.callback_token.is_empty()
+        {
+            return Err(ApiError::bad_request()
+                .detail("a peering request needs an asset code, a token and a callback token"));
+        }
+        Url::parse(&self.ilp_over_http_url).map_err(|err| {
+            ApiError::bad_request().detail(format!("invalid ILP over HTTP URL: {}", err))
+        })?;
+        Ok(())
+    }
+}
+
+/// The body of the callback with which the approving node sends the parameters of the
+/// account it created for the requester
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PeeringApproval {
+    /// URL to which the requester sends the packets for the approving node over HTTP
+    pub ilp_over_http_url: String,
+    /// Token with which the requester authenticates the packets it sends
+    pub ilp_over_http_token: String,
+}
+
+/// Store of the peering requests received from other nodes and sent to them
+#[async_trait]
+pub trait PeeringStore {
+    /// Saves a peering request received from another node
+    async fn save_peering_request(&self, request: PeeringRequest) -> Result<(), NodeStoreError>;
+
+    /// Returns the peering requests which were neither approved nor rejected, ordered by id
+    async fn get_peering_requests(&self) -> Result<Vec<PeeringRequest>, NodeStoreError>;
+
+    /// Deletes the peering request with the id and returns it
+    async fn delete_peering_request(
+        &self,
+        id: &str,
+    ) -> Result<Option<PeeringRequest>, NodeStoreError>;
+
+    /// Saves a peering request sent to another node
+    async fn save_peering_proposal(&self, proposal: PeeringProposal) -> Result<(), NodeStoreError>;
+
+    /// Returns the peering request sent with the id, if it was not answered
+    async fn get_peering_proposal(
+        &self,
+        id: &str,
+    ) -> Result<Option<PeeringProposal>, NodeStoreError>;
+
+    /// Returns the peering requests sent to other nodes which were not answered, ordered by id
+    async fn get_peering_proposals(&self) -> Result<Vec<PeeringProposal>, NodeStoreError>;
+
+    /// Deletes the peering request sent with the id and returns it
+    async fn delete_peering_proposal(
+        &self,
+        id: &str,
+    ) -> Result<Option<PeeringProposal>, NodeStoreError>;
+
+    /// Checks the token the peer answered the proposal with against its callback token in
+    /// constant time. Stores which hash the tokens of the proposals verify the hash instead.
+    fn verify_peering_callback_token(&self, proposal: &PeeringProposal, token: &str) -> bool {
+        ring::constant_time::verify_slices_are_equal(
+            proposal.callback_token.as_bytes(),
+            token.as_bytes(),
+        )
+        .is_ok()
+    }
+}
@@ -0,0 +1,223 @@
+use interledger_errors::ApiError;
+use interledger_service::Username;
+use ring::{digest, hmac, signature};
+use secrecy::SecretString;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+fn default_username_prefix() -> String {
+    "wallet_".to_string()
+}
+
+const fn default_challenge_ttl() -> u64 {
+    300_000
+}
+
+const fn default_max_packet_amount() -> u64 {
+    1_000_000
+}
+
+const fn default_accounts_per_hour() -> u32 {
+    100
+}
+
+/// Number of bytes of the hash of a child's public key which make up its username
+const KEY_ID_LENGTH: usize = 10;
+
+/// Configuration of the self-service provisioning of child accounts, with which wallets
+/// create their own account under the node by proving control of an Ed25519 key. The
+/// accounts are created with the constrained defaults configured here.
+#[derive(Deserialize, Clone, Debug)]
+pub struct ChildProvisioning {
+    /// Asset of the provisioned accounts (it must be a registered asset, if there are any)
+    pub asset_code: String,
+    pub asset_scale: u8,
+    /// Prefix of the usernames of the provisioned accounts, which are followed by an
+    /// identifier of their key. Their ILP address is the node's address followed by it.
+    #[serde(default = "default_username_prefix")]
+    pub username_prefix: String,
+    /// Time, in milliseconds, during which a challenge can be signed
+    #[serde(default = "default_challenge_ttl")]
+    pub challenge_ttl: u64,
+    #[serde(default = "default_max_packet_amount")]
+    pub max_packet_amount: u64,
+    #[serde(default)]
+    pub amount_per_minute_limit: Option<u64>,
+    #[serde(default)]
+    pub packets_per_minute_limit: Option<u32>,
+    /// Bearer token required to create an account, so that only the wallets it is given to
+    /// (such as through the backend of a wallet) can. Anyone can if it is not set.
+    #[serde(default)]
+    pub token: Option<SecretString>,
+    /// Number of accounts which can be created per hour, in total
+    #[serde(default = "default_accounts_per_hour")]
+    pub accounts_per_hour: u32,
+}
+
+/// Challenge issued by the node, which the prospective child signs with its key
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ProvisioningChallenge {
+    pub challenge: String,
+    /// Time after which the challenge is no longer accepted, in milliseconds since the UNIX epoch
+    pub expires_at: u64,
+}
+
+/// Proof that the prospective child controls its key, which creates its account
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ProvisioningRequest {
+    /// The child's Ed25519 public key, base64url-encoded
+    pub public_key: String,
+    /// The challenge issued by the node
+    pub challenge: String,
+    /// The child's signature of the challenge, base64url-encoded
+    pub signature: String,
+}
+
+/// Issues and checks the provisioning challenges. They are authenticated with a key derived
+/// from the server secret, so that the node does not need to store them.
+#[derive(Clone)]
+pub(crate) struct ChallengeIssuer {
+    key: hmac::Key,
+}
+
+impl ChallengeIssuer {
+    pub fn new(server_secret: &[u8]) -> Self {
+        let key = hmac::sign(
+            &hmac::Key::new(hmac::HMAC_SHA256, server_secret),
+            b"ilp_child_provisioning",
+        );
+        ChallengeIssuer {
+            key: hmac::Key::new(hmac::HMAC_SHA256, key.as_ref()),
+        }
+    }
+
+    /// Returns a challenge which expires at the time (in milliseconds since the UNIX epoch)
+    pub fn issue(&self, nonce: &str, expires_at: u64) -> ProvisioningChallenge {
+        let payload = format!("{}.{}", expires_at, nonce);
+        let tag = hmac::sign(&self.key, payload.as_bytes());
+        ProvisioningChallenge {
+            challenge: format!(
+                "{}.{}",
+                payload,
+                base64::encode_config(tag.as_ref(), base64::URL_SAFE_NO_PAD)
+            ),
+            expires_at,
+        }
+    }
+
+    /// Fails if the challenge was not issued by the node, or if it expired at the time
+    pub fn check(&self, challenge: &str, now: u64) -> Result<(), ApiError> {
+        let invalid = || ApiError::unauthorized().detail("invalid provisioning challenge");
+        let mut parts = challenge.rsplitn(2, '.');
+        let tag = parts
+            .next()
+            .and_then(|tag| base64::decode_config(tag, base64::URL_SAFE_NO_PAD).ok())
+            .ok_or_else(invalid)?;
+        let payload = parts.next().ok_or_else(invalid)?;
+        hmac::verify(&self.key, payload.as_bytes(), &tag).map_err(|_| invalid())?;
+        let expires_at = payload
+            .split('.')
+            .next()
+            .and_then(|expires_at| u64::from_str(expires_at).ok())
+            .ok_or_else(invalid)?;
+        if expires_at < now {
+            return Err(ApiError::unauthorized().detail("the provisioning challenge expired"));
+        }
+        Ok(())
+    }
+}
+
+impl ProvisioningRequest {
+    /// Checks the signature of the challenge, and returns the public key
+    pub fn verify(&self) -> Result<Vec<u8>, ApiError> {
+        let public_key = base64::decode_config(&self.public_key, base64::URL_SAFE_NO_PAD)
+            .map_err(|_| ApiError::bad_request().detail("the public key is not base64url"))?;
+        let signature = base64::decode_config(&self.signature, base64::URL_SAFE_NO_PAD)
+            .map_err(|_| ApiError::bad_request().detail("the signature is not base64url"))?;
+        signature::UnparsedPublicKey::new(&signature::ED25519, &public_key)
+            .verify(self.challenge.as_bytes(), &signature)
+            .map_err(|_| {
+                ApiError::unauthorized().detail("the challenge was not signed with the public key")
+            })?;
+        Ok(public_key)
+    }
+}
+
+impl ChildProvisioning {
+    /// Returns the username of the account of the child with the public key. It is derived
+    /// from the key, so that each key has a single account.
+    pub fn username(&self, public_key: &[u8]) -> Result<Username, ApiError> {
+        let hash = digest::digest(&digest::SHA256, public_key);
+        let key_id: String = hash.as_ref()[..KEY_ID_LENGTH]
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        Username::from_str(&format!("{}{}", self.username_prefix, key_id)).map_err(|_| {
+            ApiError::internal_server_error()
+                .detail("the username prefix of the provisioned accounts is invalid")
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+
+    fn request(key_pair: &Ed25519KeyPair, challenge: &str) -> ProvisioningRequest {
+        ProvisioningRequest {
+            public_key: base64::encode_config(
+                key_pair.public_key().as_ref(),
+                base64::URL_SAFE_NO_PAD,
+            ),
+            challenge: challenge.to_string(),
+            signature: base64::encode_config(
+                key_pair.sign(challenge.as_bytes()).as_ref(),
+                base64::URL_SAFE_NO_PAD,
+            ),
+        }
+    }
+
+    #[test]
+    fn checks_the_challenges_it_issued() {
+        let issuer = ChallengeIssuer::new(&[0; 32]);
+        let challenge = issuer.issue("nonce", 1000);
+        assert!(issuer.check(&challenge.challenge, 1000).is_ok());
+        assert!(issuer.check(&challenge.challenge, 1001).is_err());
+        let forged = challenge.challenge.replacen("1000", "2000", 1);
+        assert!(issuer.check(&forged, 1000).is_err());
+        assert!(ChallengeIssuer::new(&[1; 32])
+            .check(&challenge.challenge, 1000)
+            .is_err());
+        assert!(issuer.check("not a challenge", 1000).is_err());
+    }
+
+    #[test]
+    fn verifies_the_signature_of_the_challenge() {
+        let key_pair = Ed25519KeyPair::from_seed_unchecked(&[7; 32]).unwrap();
+        let mut request = request(&key_pair, "challenge");
+        assert_eq!(request.verify().unwrap(), key_pair.public_key().as_ref());
+        request.challenge = "other challenge".to_string();
+        assert!(request.verify().is_err());
+    }
+
+    #[test]
+    fn derives_the_username_from_the_key() {
+        let provisioning = ChildProvisioning {
+            asset_code: "XYZ".to_string(),
+            asset_scale: 9,
+            username_prefix: default_username_prefix(),
+            challenge_ttl: default_challenge_ttl(),
+            max_packet_amount: default_max_packet_amount(),
+            amount_per_minute_limit: None,
+            packets_per_minute_limit: None,
+            token: None,
+            accounts_per_hour: default_accounts_per_hour(),
+        };
+        let username = provisioning.username(&[1; 32]).unwrap();
+        assert!(username.as_ref().starts_with("wallet_"));
+        assert_eq!(username.as_ref().len(), 7 + 2 * KEY_ID_LENGTH);
+        assert_eq!(provisioning.username(&[1; 32]).unwrap(), username);
+        assert_ne!(provisioning.username(&[2; 32]).unwrap(), username);
+    }
+}
@@ -0,0 +1,154 @@
+use crate::{number_or_string, optional_number_or_string};
+use async_trait::async_trait;
+use interledger_errors::{ApiError, NodeStoreError};
+use interledger_service::Username;
+use interledger_spsp::PullBalance;
+use serde::{Deserialize, Serialize};
+
+/// Path under which the pull pointers are served, followed by their ids
+pub const PULL_POINTERS_PATH: &str = "/pull";
+
+/// A pull pointer, which authorizes whoever knows it to pull payments from an account,
+/// up to the amount per interval and the cap
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PullPointer {
+    /// Random identifier of the pull pointer, which is served at `/pull/<id>`.
+    /// Knowing it is what authorizes the pulls, so it must only be shared with the payee.
+    pub id: String,
+    /// The account the pulled payments are sent from
+    pub username: Username,
+    /// The max amount which can be pulled per interval, in the account's units
+    pub amount: u64,
+    /// Length of the intervals, in seconds
+    pub interval: u64,
+    /// The max amount which can be pulled in total, if there is one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cap: Option<u64>,
+    /// When the first interval starts, as seconds since the UNIX epoch
+    pub start: u64,
+}
+
+/// The terms of a pull pointer to be created
+#[derive(Debug, Clone, Deserialize)]
+pub struct PullPointerTerms {
+    pub username: Username,
+    #[serde(deserialize_with = "number_or_string")]
+    pub amount: u64,
+    #[serde(deserialize_with = "number_or_string")]
+    pub interval: u64,
+    #[serde(default, deserialize_with = "optional_number_or_string")]
+    pub cap: Option<u64>,
+}
+
+impl PullPointerTerms {
+    /// Checks that the terms allow any pull
+    pub fn validate(&self) -> Result<(), ApiError> {
+        if self.amount == 0 || self.interval == 0 || self.cap == Some(0) {
+            return Err(ApiError::bad_request()
+                .detail("the amount, interval and cap of a pull pointer must be positive"));
+        }
+        Ok(())
+    }
+}
+
+impl PullPointer {
+    /// Returns the path the pull pointer is served at
+    pub fn path(&self) -> String {
+        format!("{}/{}", PULL_POINTERS_PATH, self.id)
+    }
+
+    /// Returns the index of the interval the time (in seconds since the UNIX epoch) falls in
+    pub fn interval_index(&self, now: u64) -> u64 {
+        now.saturating_sub(self.start) / self.interval
+    }
+
+    /// Returns the balance of the pull pointer, given the amounts pulled in total and
+    /// in the current interval
+    pub fn balance(&self, total: u64, interval: u64) -> PullBalance {
+        let mut available = self.amount.saturating_sub(interval);
+        if let Some(cap) = self.cap {
+            available = available.min(cap.saturating_sub(total));
+        }
+        PullBalance {
+            total,
+            interval,
+            available,
+        }
+    }
+}
+
+/// Store of the pull pointers created by the node's accounts, and of the amounts
+/// pulled from them
+#[async_trait]
+pub trait PullPaymentStore {
+    /// Saves a new pull pointer
+    async fn create_pull_pointer(&self, pointer: PullPointer) -> Result<(), NodeStoreError>;
+
+    /// Returns the pull pointer with the id, if there is one
+    async fn get_pull_pointer(&self, id: &str) -> Result<Option<PullPointer>, NodeStoreError>;
+
+    /// Returns all pull pointers, ordered by id
+    async fn get_pull_pointers(&self) -> Result<Vec<PullPointer>, NodeStoreError>;
+
+    /// Deletes the pull pointer with the id, and the amounts pulled from it, and returns it
+    async fn delete_pull_pointer(&self, id: &str) -> Result<Option<PullPointer>, NodeStoreError>;
+
+    /// Returns the amounts pulled from the pull pointer at the time (in seconds since
+    /// the UNIX epoch)
+    async fn get_pull_balance(
+        &self,
+        pointer: &PullPointer,
+        now: u64,
+    ) -> Result<PullBalance, NodeStoreError>;
+
+    /// Atomically adds the amount to the amounts pulled from the pull pointer, if it
+    /// does not exceed the amount available at the time. Returns the new balance, or
+    /// None if the pull is not allowed.
+    async fn reserve_pull(
+        &self,
+        pointer: &PullPointer,
+        amount: u64,
+        now: u64,
+    ) -> Result<Option<PullBalance>, NodeStoreError>;
+
+    /// Subtracts the part of a reserved pull which could not be sent from the amounts
+    /// pulled from the pull pointer (the interval it was reserved in is given by the time)
+    async fn release_pull(
+        &self,
+        pointer: &PullPointer,
+        amount: u64,
+        now: u64,
+    ) -> Result<PullBalance, NodeStoreError>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn pointer(cap: Option<u64>) -> PullPointer {
+        PullPointer {
+            id: "id".to_string(),
+            username: Username::from_str("alice").unwrap(),
+            amount: 100,
+            interval: 60,
+            cap,
+            start: 1000,
+        }
+    }
+
+    #[test]
+    fn computes_intervals_and_balances() {
+        let pointer = pointer(Some(250));
+        assert_eq!(pointer.path(), "/pull/id");
+        assert_eq!(pointer.interval_index(1000), 0);
+        assert_eq!(pointer.interval_index(1059), 0);
+        assert_eq!(pointer.interval_index(1060), 1);
+
+        assert_eq!(pointer.balance(0, 0).available, 100);
+        assert_eq!(pointer.balance(130, 30).available, 70);
+        // the cap is reached before the amount of the interval
+        assert_eq!(pointer.balance(200, 0).available, 50);
+        assert_eq!(pointer.balance(250, 0).available, 0);
+    }
+}
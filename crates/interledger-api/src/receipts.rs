@@ -0,0 +1,37 @@
+use async_trait::async_trait;
+use interledger_errors::NodeStoreError;
+use interledger_stream::Receipt;
+use serde::{Deserialize, Serialize};
+
+/// The amount a verified receipt credited to the payment pointer it was issued for
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReceiptCredit {
+    /// Path of the payment pointer the connection of the receipt was opened with
+    pub path: String,
+    /// The amount the receipt adds to the totals of the receipts verified before it
+    /// (zero if they already covered its total)
+    pub amount: u64,
+    /// The total amount credited to the payment pointer by verified receipts
+    pub balance: u64,
+}
+
+/// Store of the receipt verifier, which credits the amounts proven by the
+/// [STREAM Receipts](https://interledger.org/rfcs/0039-stream-receipts/) issued by the
+/// node to the payment pointers their connections were opened with
+#[async_trait]
+pub trait ReceiptVerifierStore {
+    /// Saves the payment pointer the connection with the receipt nonce was opened with
+    async fn save_receipt_nonce(&self, nonce: [u8; 16], path: String)
+        -> Result<(), NodeStoreError>;
+
+    /// Credits the payment pointer of the receipt's connection with the amount by which
+    /// the receipt's total exceeds the totals of the receipts of its stream verified before.
+    /// Returns None if the receipt's connection was not opened with a payment pointer.
+    async fn credit_receipt(
+        &self,
+        receipt: &Receipt,
+    ) -> Result<Option<ReceiptCredit>, NodeStoreError>;
+
+    /// Returns the total amount credited to the payment pointer by verified receipts
+    async fn get_payment_pointer_balance(&self, path: &str) -> Result<u64, NodeStoreError>;
+}
@@ -0,0 +1,150 @@
+use crate::account_templates::{AccountTemplate, AccountTemplateStore};
+use crate::audit::{admin_call, AdminCall, AuditLogStore};
+use crate::jwt::{admin_only, JwtAuth};
+use interledger_errors::*;
+use interledger_http::deserialize_json;
+use tracing::debug;
+use warp::{self, reply::Json, Filter, Rejection};
+
+pub fn account_templates_api<S>(
+    admin_api_token: String,
+    jwt_auth: Option<JwtAuth>,
+    store: S,
+) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone
+where
+    S: AccountTemplateStore + AuditLogStore + Clone + Send + Sync + 'static,
+{
+    // Helper filters
+    let admin_auth_header = format!("Bearer {}", admin_api_token);
+    let audited_admin_only = admin_call(admin_auth_header, jwt_auth.clone());
+    let admin_only = admin_only(&admin_api_token, jwt_auth.clone());
+    let with_store = warp::any().map(move || store.clone());
+
+    // GET /account-templates
+    // Response: The account templates of the node
+    let get_templates = warp::get()
+        .and(warp::path("account-templates"))
+        .and(warp::path::end())
+        .and(admin_only.clone())
+        .and(with_store.clone())
+        .and_then(|store: S| async move {
+            let templates = store.get_account_templates().await?;
+            Ok::<Json, Rejection>(warp::reply::json(&templates))
+        });
+
+    // GET /account-templates/:name
+    let get_template = warp::get()
+        .and(warp::path("account-templates"))
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(admin_only)
+        .and(with_store.clone())
+        .and_then(|name: String, store: S| async move {
+            let template = store
+                .get_account_template(&name)
+                .await?
+                .ok_or_else(|| ApiError::not_found().detail("account template not found"))?;
+            Ok::<Json, Rejection>(warp::reply::json(&template))
+        });
+
+    // PUT /account-templates
+    // Body: { "name": "peer-xrp", "fields": { "asset_code": "XRP", "asset_scale": 9,
+    //         "routing_relation": "Peer", "settle_threshold": 1000000 } }
+    // Saves the template, or replaces the one with the same name. The accounts which
+    // were created with it are not changed.
+    let put_template = warp::put()
+        .and(warp::path("account-templates"))
+        .and(warp::path::end())
+        .and(audited_admin_only.clone())
+        .and(deserialize_json())
+        .and(with_store.clone())
+        .and_then(
+            |call: AdminCall, template: AccountTemplate, store: S| async move {
+                template.validate()?;
+                store.set_account_template(template.clone()).await?;
+                call.record(&store, &template).await;
+                debug!("Set account template {}", template.name);
+                Ok::<Json, Rejection>(warp::reply::json(&template))
+            },
+        );
+
+    // DELETE /account-templates/:name
+    let delete_template = warp::delete()
+        .and(warp::path("account-templates"))
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(audited_admin_only)
+        .and(with_store)
+        .and_then(|name: String, call: AdminCall, store: S| async move {
+            let template = store
+                .delete_account_template(&name)
+                .await?
+                .ok_or_else(|| ApiError::not_found().detail("account template not found"))?;
+            call.record(&store, &()).await;
+            debug!("Deleted account template {}", template.name);
+            Ok::<Json, Rejection>(warp::reply::json(&template))
+        });
+
+    get_templates
+        .or(get_template)
+        .or(put_template)
+        .or(delete_template)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::routes::test_helpers::{api_call, test_account_templates_api};
+    use serde_json::{json, Value};
+
+    #[tokio::test]
+    async fn only_admin_can_manage_account_templates() {
+        let template = json!({
+            "name": "peer-abc",
+            "fields": {
+                "asset_code": "ABC",
+                "asset_scale": 6,
+                "routing_relation": "Peer",
+            },
+        });
+        let api = test_account_templates_api();
+        let resp = api_call(
+            &api,
+            "PUT",
+            "/account-templates",
+            "admin",
+            Some(template.clone()),
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let resp = api_call(&api, "PUT", "/account-templates", "wrong", Some(template)).await;
+        assert_eq!(resp.status().as_u16(), 401);
+
+        let resp = api_call(&api, "GET", "/account-templates", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let templates: Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(templates[0]["name"], "peer-xyz");
+        let resp = api_call(&api, "GET", "/account-templates", "wrong", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+
+        let resp = api_call(&api, "GET", "/account-templates/peer-xyz", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let resp = api_call(&api, "GET", "/account-templates/peer-abc", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 404);
+
+        let resp = api_call(&api, "DELETE", "/account-templates/peer-xyz", "wrong", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+        let resp = api_call(&api, "DELETE", "/account-templates/peer-xyz", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+    }
+
+    #[tokio::test]
+    async fn rejects_templates_setting_the_credentials() {
+        let template = json!({
+            "name": "peer-xyz",
+            "fields": { "ilp_over_http_outgoing_token": "secret" },
+        });
+        let api = test_account_templates_api();
+        let resp = api_call(&api, "PUT", "/account-templates", "admin", Some(template)).await;
+        assert_eq!(resp.status().as_u16(), 400);
+    }
+}
@@ -1,56 +1,151 @@
+use crate::account_templates::AccountTemplateStore;
+use crate::assets::{check_account_asset, AssetStore};
+use crate::audit::{admin_call, AdminCall, AuditLogStore};
+use crate::jwt::{admin_only, bearer_jwt, is_admin_token, JwtAuth};
+use crate::outgoing_payments::{
+    generate_payment_id, send_recorded_payment, OutgoingPayment, OutgoingPaymentStore,
+};
+use crate::payment_pointers::PaymentPointerStore;
+use crate::payment_receipts::PaymentReceiptSigner;
+use crate::receipts::ReceiptVerifierStore;
+use crate::routes::{streamed_json_array, LISTING_PAGE_SIZE};
+use crate::scheduled_payments::{ScheduledPayment, ScheduledPaymentRequest, ScheduledPaymentStore};
 use crate::{number_or_string, AccountDetails, AccountSettings, NodeStore};
 use bytes::Bytes;
-use futures::{Future, FutureExt, StreamExt, TryFutureExt};
+use futures::{Future, FutureExt, SinkExt, StreamExt, TryFutureExt};
+use interledger_api_types::{SpspPayRequest, SpspPullRequest};
 use interledger_btp::{connect_to_service_account, BtpAccount, BtpOutgoingService};
 use interledger_ccp::{CcpRoutingAccount, Mode, RouteControlRequest, RoutingRelation};
 use interledger_errors::*;
 use interledger_http::{deserialize_json, HttpAccount, HttpStore};
 use interledger_ildcp::IldcpRequest;
 use interledger_ildcp::IldcpResponse;
+use interledger_packet::Address;
 use interledger_rates::ExchangeRateStore;
 use interledger_router::RouterStore;
 use interledger_service::{
     Account, AccountStore, AddressStore, IncomingService, OutgoingRequest, OutgoingService,
     Username,
 };
-use interledger_service_util::BalanceStore;
+use interledger_service_util::{
+    BalanceNotifications, BalanceStore, CreditLimits, EchoPings, FaultConfig, FaultInjector,
+    LiquidityStore, PacketTraces, PeerLatencies, TransferLimitStore, TransferLimits,
+};
 use interledger_settlement::core::{types::SettlementAccount, SettlementClient};
-use interledger_spsp::{pay, SpspResponder};
-use interledger_stream::{PaymentNotification, StreamNotificationsStore};
+use interledger_spsp::{deliver, pay, pull, Error as SpspError, PullRequest, SpspResponder};
+use interledger_stream::{
+    MaxPacketAmountStore, PaymentNotification, StreamConnections, StreamNotificationsStore,
+    UnreachableDestinationStore, MAX_MEMO_LENGTH,
+};
 use secrecy::{ExposeSecret, SecretString};
 use serde::{Deserialize, Serialize};
-use serde_json::json;
+use serde_json::{json, Map, Value};
 use std::convert::TryFrom;
 use std::fmt::Debug;
+use std::str::FromStr;
+use std::time::Duration;
 use tracing::{debug, error, trace};
 use uuid::Uuid;
-use warp::{self, reply::Json, Filter, Rejection};
+use warp::{self, filters::path::FullPath, http::Method, reply::Json, Filter, Rejection};
 
 pub const BEARER_TOKEN_START: usize = 7;
 
-const fn get_default_max_slippage() -> f64 {
-    0.015
+const fn get_default_ping_timeout() -> u64 {
+    10_000
+}
+
+const fn get_default_trace_duration() -> u64 {
+    60_000
+}
+
+const fn get_default_trace_capacity() -> usize {
+    1_000
+}
+
+const fn get_default_trace_redacted() -> bool {
+    true
+}
+
+/// Longest time, in milliseconds, an account can be in trace mode
+const MAX_TRACE_DURATION: u64 = 3_600_000;
+/// Largest number of packets a trace can hold
+const MAX_TRACE_CAPACITY: usize = 100_000;
+
+#[derive(Deserialize, Debug)]
+struct PingRequest {
+    destination: String,
+    /// How long to wait for the echo response, in milliseconds
+    #[serde(
+        deserialize_with = "number_or_string",
+        default = "get_default_ping_timeout"
+    )]
+    timeout: u64,
 }
 
 #[derive(Deserialize, Debug)]
-struct SpspPayRequest {
-    receiver: String,
-    #[serde(deserialize_with = "number_or_string")]
-    source_amount: u64,
+struct CreateAccountQuery {
+    /// The name of the account template with the details which the request does not set
+    template: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct TraceRequest {
+    /// How long to record the packets of the account, in milliseconds
+    #[serde(
+        deserialize_with = "number_or_string",
+        default = "get_default_trace_duration"
+    )]
+    duration: u64,
+    /// How many packets to keep
     #[serde(
         deserialize_with = "number_or_string",
-        default = "get_default_max_slippage"
+        default = "get_default_trace_capacity"
     )]
-    slippage: f64,
+    capacity: usize,
+    /// Whether to omit the data and fulfillments of the packets
+    #[serde(default = "get_default_trace_redacted")]
+    redact: bool,
+}
+
+/// Returns the error of a failed SPSP payment: the receiver is either invalid,
+/// its SPSP server failed, or the payment itself failed
+pub(crate) fn spsp_payment_error(err: &SpspError) -> ApiError {
+    match err {
+        SpspError::InvalidPaymentPointerError(_) => ApiError::bad_request(),
+        SpspError::HttpError(_)
+        | SpspError::DnsError(_)
+        | SpspError::TlsError(_)
+        | SpspError::InvalidSpspServerResponseError(_) => {
+            ApiError::from_api_error_type(&ApiErrorType {
+                r#type: &ProblemType::Default,
+                status: http::StatusCode::BAD_GATEWAY,
+                title: "SPSP query error",
+            })
+        }
+        SpspError::PullRejectedError(_) => ApiError::from_api_error_type(&ApiErrorType {
+            r#type: &ProblemType::Default,
+            status: http::StatusCode::FORBIDDEN,
+            title: "Pull rejected",
+        }),
+        _ => ApiError::internal_server_error(),
+    }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn accounts_api<I, O, S, A, B>(
     server_secret: Bytes,
     admin_api_token: String,
-    default_spsp_account: Option<Username>,
+    jwt_auth: Option<JwtAuth>,
     incoming_handler: I,
     outgoing_handler: O,
     btp: BtpOutgoingService<B, A>,
+    echo_pings: EchoPings,
+    peer_latencies: PeerLatencies,
+    packet_traces: PacketTraces,
+    balance_notifications: BalanceNotifications,
+    fault_injector: Option<FaultInjector>,
+    stream_connections: StreamConnections,
+    settlement_client: SettlementClient,
     store: S,
 ) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone
 where
@@ -62,9 +157,20 @@ where
         + AddressStore
         + HttpStore<Account = A>
         + BalanceStore
+        + LiquidityStore
+        + TransferLimitStore
         + StreamNotificationsStore<Account = A>
         + ExchangeRateStore
-        + RouterStore,
+        + MaxPacketAmountStore
+        + UnreachableDestinationStore
+        + RouterStore
+        + AuditLogStore
+        + PaymentPointerStore
+        + ReceiptVerifierStore
+        + ScheduledPaymentStore
+        + OutgoingPaymentStore
+        + AssetStore
+        + AccountTemplateStore,
     A: BtpAccount
         + CcpRoutingAccount
         + SettlementAccount
@@ -81,22 +187,8 @@ where
 
     // Helper filters
     let admin_auth_header = format!("Bearer {}", admin_api_token);
-    let admin_auth_header_clone = admin_auth_header.clone();
-    let with_admin_auth_header = warp::any().map(move || admin_auth_header.clone());
-    let admin_only = warp::header::<SecretString>("authorization")
-        .and_then(move |authorization: SecretString| {
-            let admin_auth_header = admin_auth_header_clone.clone();
-            async move {
-                if authorization.expose_secret() == &admin_auth_header {
-                    Ok::<(), Rejection>(())
-                } else {
-                    Err(Rejection::from(ApiError::unauthorized()))
-                }
-            }
-        })
-        // This call makes it so we do not pass on a () value on
-        // success to the next filter, it just gets rid of it
-        .untuple_one();
+    let audited_admin_only = admin_call(admin_auth_header.clone(), jwt_auth.clone());
+    let admin_only = admin_only(&admin_api_token, jwt_auth.clone());
 
     // Converts an account username to an account id or errors out
     let account_username_to_id = warp::path::param::<Username>()
@@ -106,8 +198,21 @@ where
             Ok::<_, Rejection>(id)
         });
 
-    let is_authorized_user = move |store: S, path_username: Username, auth_string: SecretString| {
+    let is_authorized_user = move |store: S,
+                                   path_username: Username,
+                                   auth_string: SecretString,
+                                   jwt_auth: Option<JwtAuth>| {
         async move {
+            // The tokens of the identity provider only name the account, so it is fetched
+            if let Some((jwt_auth, token)) = bearer_jwt(auth_string.expose_secret(), &jwt_auth) {
+                jwt_auth.authorize_account(token, &path_username).await?;
+                let id = store.get_account_id_from_username(&path_username).await?;
+                let mut accounts = store.get_accounts(vec![id]).await?;
+                return accounts
+                    .pop()
+                    .ok_or_else(|| Rejection::from(ApiError::account_not_found()));
+            }
+
             if auth_string.expose_secret().len() < BEARER_TOKEN_START {
                 return Err(Rejection::from(ApiError::bad_request()));
             }
@@ -130,59 +235,128 @@ where
         }
     };
 
-    // Checks if the account is an admin or if they have provided a valid password
-    let admin_or_authorized_user_only = warp::path::param::<Username>()
+    let with_jwt_auth = warp::any().map(move || jwt_auth.clone());
+
+    // Checks if the account is an admin or if they have provided a valid password, and
+    // returns who the admin is if it is one. A JWT is validated once for both scopes.
+    let with_admin_auth_header = warp::any().map(move || admin_auth_header.clone());
+    let admin_or_authorized_user = warp::path::param::<Username>()
         .and(warp::header::<SecretString>("authorization"))
-        .and(with_store.clone())
         .and(with_admin_auth_header)
+        .and(with_store.clone())
+        .and(with_jwt_auth.clone())
         .and_then(
             move |path_username: Username,
                   auth_string: SecretString,
+                  admin_auth_header: String,
                   store: S,
-                  admin_auth_header: String| {
+                  jwt_auth: Option<JwtAuth>| {
                 async move {
-                    // If it's an admin, there's no need for more checks
-                    if auth_string.expose_secret() == &admin_auth_header {
-                        let account_id = store.get_account_id_from_username(&path_username).await?;
-                        return Ok(account_id);
-                    }
-                    let account = is_authorized_user(store, path_username, auth_string).await?;
-                    Ok::<Uuid, Rejection>(account.id())
+                    let admin = if let Some((jwt_auth, token)) =
+                        bearer_jwt(auth_string.expose_secret(), &jwt_auth)
+                    {
+                        jwt_auth
+                            .authorize_admin_or_account(token, &path_username)
+                            .await?
+                    } else if is_admin_token(auth_string.expose_secret(), &admin_auth_header) {
+                        Some("admin".to_string())
+                    } else {
+                        let account =
+                            is_authorized_user(store, path_username, auth_string, None).await?;
+                        return Ok((account.id(), None));
+                    };
+                    let account_id = store.get_account_id_from_username(&path_username).await?;
+                    Ok::<(Uuid, Option<String>), Rejection>((account_id, admin))
                 }
             },
         );
+    let admin_or_authorized_user_only = admin_or_authorized_user
+        .clone()
+        .map(|(account_id, _admin): (Uuid, Option<String>)| account_id);
 
     // Checks if the account has provided a valid password (same as admin-or-auth call, minus one call, can we refactor them together?)
     let authorized_user_only = warp::path::param::<Username>()
         .and(warp::header::<SecretString>("authorization"))
         .and(with_store.clone())
+        .and(with_jwt_auth)
         .and_then(
-            move |path_username: Username, auth_string: SecretString, store: S| async move {
-                let account = is_authorized_user(store, path_username, auth_string).await?;
+            move |path_username: Username,
+                  auth_string: SecretString,
+                  store: S,
+                  jwt_auth: Option<JwtAuth>| async move {
+                let account =
+                    is_authorized_user(store, path_username, auth_string, jwt_auth).await?;
                 Ok::<A, Rejection>(account)
             },
         );
 
-    // POST /accounts
+    // POST /accounts(?template=:name)
+    // The details which the body does not set are taken from the account template, if any
     let btp_clone = btp.clone();
     let outgoing_handler_clone = outgoing_handler.clone();
+    let settlement_client_clone = settlement_client.clone();
     let post_accounts = warp::post()
         .and(warp::path("accounts"))
         .and(warp::path::end())
-        .and(admin_only.clone())
+        .and(audited_admin_only.clone())
+        .and(warp::query::<CreateAccountQuery>())
         .and(deserialize_json()) // Why does warp::body::json not work?
         .and(with_store.clone())
-        .and_then(move |account_details: AccountDetails, store: S| {
-            let store_clone = store.clone();
-            let handler = outgoing_handler_clone.clone();
-            let btp = btp_clone.clone();
-            async move {
-                let account = store.insert_account(account_details.clone()).await?;
+        .and_then(
+            move |call: AdminCall,
+                  query: CreateAccountQuery,
+                  fields: Map<String, Value>,
+                  store: S| {
+                let store_clone = store.clone();
+                let handler = outgoing_handler_clone.clone();
+                let btp = btp_clone.clone();
+                let settlement_client = settlement_client_clone.clone();
+                async move {
+                    let fields = match query.template {
+                        Some(name) => store
+                            .get_account_template(&name)
+                            .await?
+                            .ok_or_else(|| {
+                                ApiError::bad_request()
+                                    .detail(format!("unknown account template {}", name))
+                            })?
+                            .apply(fields),
+                        None => fields,
+                    };
+                    // The details are deserialized from the JSON, since some of the fields
+                    // (such as the username) are borrowed from it
+                    let json = serde_json::to_vec(&fields)
+                        .map_err(|err| ApiError::internal_server_error().detail(err.to_string()))?;
+                    let deserializer = &mut serde_json::Deserializer::from_slice(&json);
+                    let account_details: AccountDetails =
+                        serde_path_to_error::deserialize(deserializer).map_err(|err| {
+                            warp::reject::custom(JsonDeserializeError {
+                                category: err.inner().classify(),
+                                detail: err.inner().to_string(),
+                                path: err.path().clone(),
+                            })
+                        })?;
+                    check_account_asset(
+                        &store,
+                        &account_details.asset_code,
+                        account_details.asset_scale,
+                    )
+                    .await?;
+                    let account = store.insert_account(account_details.clone()).await?;
+                    call.record(&store, &account_details).await;
 
-                connect_to_external_services(handler, account.clone(), store_clone, btp).await?;
-                Ok::<Json, Rejection>(warp::reply::json(&account))
-            }
-        });
+                    connect_to_external_services(
+                        handler,
+                        account.clone(),
+                        store_clone,
+                        btp,
+                        settlement_client,
+                    )
+                    .await?;
+                    Ok::<Json, Rejection>(warp::reply::json(&account))
+                }
+            },
+        );
 
     // GET /accounts
     let get_accounts = warp::get()
@@ -190,38 +364,59 @@ where
         .and(warp::path::end())
         .and(admin_only.clone())
         .and(with_store.clone())
-        .and_then(|store: S| async move {
-            let accounts = store.get_all_accounts().await?;
-            Ok::<Json, Rejection>(warp::reply::json(&accounts))
+        .and_then(|store: S| {
+            // The accounts are streamed page by page, rather than loaded all at once
+            streamed_json_array(move |cursor| {
+                let store = store.clone();
+                async move { store.get_accounts_page(cursor, LISTING_PAGE_SIZE).await }.boxed()
+            })
         });
 
     // PUT /accounts/:username
     let btp_clone = btp.clone();
     let outgoing_handler_clone = outgoing_handler.clone();
+    let settlement_client_clone = settlement_client.clone();
     let put_account = warp::put()
         .and(warp::path("accounts"))
         .and(account_username_to_id.clone())
         .and(warp::path::end())
-        .and(admin_only.clone())
+        .and(audited_admin_only.clone())
         .and(deserialize_json()) // warp::body::json() is not able to decode this!
         .and(with_store.clone())
-        .and_then(move |id: Uuid, account_details: AccountDetails, store: S| {
-            let outgoing_handler = outgoing_handler_clone.clone();
-            let btp = btp_clone.clone();
-            if account_details.ilp_over_btp_incoming_token.is_some() {
-                // if the BTP token was provided, assume that it's different
-                // from the existing one and drop the connection
-                // the saved websocket connection
-                // a new one will be initialized in the `connect_to_external_services` call
-                btp.close_connection(&id);
-            }
-            async move {
-                let account = store.update_account(id, account_details).await?;
-                connect_to_external_services(outgoing_handler, account.clone(), store, btp).await?;
+        .and_then(
+            move |id: Uuid, call: AdminCall, account_details: AccountDetails, store: S| {
+                let outgoing_handler = outgoing_handler_clone.clone();
+                let btp = btp_clone.clone();
+                let settlement_client = settlement_client_clone.clone();
+                if account_details.ilp_over_btp_incoming_token.is_some() {
+                    // if the BTP token was provided, assume that it's different
+                    // from the existing one and drop the connection
+                    // the saved websocket connection
+                    // a new one will be initialized in the `connect_to_external_services` call
+                    btp.close_connection(&id);
+                }
+                async move {
+                    check_account_asset(
+                        &store,
+                        &account_details.asset_code,
+                        account_details.asset_scale,
+                    )
+                    .await?;
+                    let account = store.update_account(id, account_details.clone()).await?;
+                    call.record(&store, &account_details).await;
+                    connect_to_external_services(
+                        outgoing_handler,
+                        account.clone(),
+                        store,
+                        btp,
+                        settlement_client,
+                    )
+                    .await?;
 
-                Ok::<Json, Rejection>(warp::reply::json(&account))
-            }
-        });
+                    Ok::<Json, Rejection>(warp::reply::json(&account))
+                }
+            },
+        );
 
     // GET /accounts/:username
     let get_account = warp::get()
@@ -251,29 +446,116 @@ where
                 let account = accounts.pop().unwrap();
 
                 let balance = store.get_balance(account.id()).await?;
+                let in_flight = store.get_in_flight_amount(account.id()).await?;
 
                 let asset_scale = account.asset_scale();
                 let asset_code = account.asset_code().to_owned();
-                Ok::<Json, Rejection>(warp::reply::json(&json!({
+                let mut response = json!({
                     // normalize to the base unit
                     "balance": balance as f64 / 10_u64.pow(asset_scale.into()) as f64,
+                    "in_flight": in_flight as f64 / 10_u64.pow(asset_scale.into()) as f64,
                     "asset_code": asset_code,
-                })))
+                });
+                // The amounts are also formatted with the display rules of registered assets
+                if let Some(asset) = store.get_asset(&asset_code).await? {
+                    response["display"] = json!({
+                        "balance": asset.display(balance.into(), asset_scale),
+                        "in_flight": asset.display(in_flight.into(), asset_scale),
+                    });
+                }
+                Ok::<Json, Rejection>(warp::reply::json(&response))
             }
         });
 
+    // GET /accounts/:username/liquidity
+    // Response: What the node owes the account (payable) and what the account owes
+    // the node (receivable), in the account's units, and the account's credit limits
+    let get_account_liquidity = warp::get()
+        .and(warp::path("accounts"))
+        .and(admin_or_authorized_user_only.clone())
+        .and(warp::path("liquidity"))
+        .and(warp::path::end())
+        .and(with_store.clone())
+        .and_then(|id: Uuid, store: S| async move {
+            let liquidity = store.get_liquidity(id).await?;
+            Ok::<Json, Rejection>(warp::reply::json(&liquidity))
+        });
+
+    // GET /accounts/:username/stats
+    // Response: The round trip times and jitter of the latest probes of the account, if
+    // the node probes it
+    let get_account_stats = warp::get()
+        .and(warp::path("accounts"))
+        .and(admin_or_authorized_user_only.clone())
+        .and(warp::path("stats"))
+        .and(warp::path::end())
+        .map(move |id: Uuid| warp::reply::json(&json!({ "latency": peer_latencies.stats(id) })));
+
+    // PUT /accounts/:username/credit-limits
+    // Body: The payable and receivable limits of the account (limits which are left
+    // out are removed)
+    let put_credit_limits = warp::put()
+        .and(warp::path("accounts"))
+        .and(account_username_to_id.clone())
+        .and(warp::path("credit-limits"))
+        .and(warp::path::end())
+        .and(audited_admin_only.clone())
+        .and(deserialize_json())
+        .and(with_store.clone())
+        .and_then(
+            move |id: Uuid, call: AdminCall, limits: CreditLimits, store: S| async move {
+                let liquidity = store.set_credit_limits(id, limits).await?;
+                call.record(&store, &limits).await;
+                Ok::<Json, Rejection>(warp::reply::json(&liquidity))
+            },
+        );
+
+    // GET /accounts/:username/transfer-limits
+    // Response: The daily and monthly send and receive limits of the account, the amounts
+    // counted in their windows, and when the oldest of those amounts leave them
+    let get_transfer_limits = warp::get()
+        .and(warp::path("accounts"))
+        .and(admin_or_authorized_user_only.clone())
+        .and(warp::path("transfer-limits"))
+        .and(warp::path::end())
+        .and(with_store.clone())
+        .and_then(|id: Uuid, store: S| async move {
+            let usage = store.get_transfer_usage(id).await?;
+            Ok::<Json, Rejection>(warp::reply::json(&usage))
+        });
+
+    // PUT /accounts/:username/transfer-limits
+    // Body: The daily and monthly send and receive limits of the account (limits which
+    // are left out are removed)
+    let put_transfer_limits = warp::put()
+        .and(warp::path("accounts"))
+        .and(account_username_to_id.clone())
+        .and(warp::path("transfer-limits"))
+        .and(warp::path::end())
+        .and(audited_admin_only.clone())
+        .and(deserialize_json())
+        .and(with_store.clone())
+        .and_then(
+            move |id: Uuid, call: AdminCall, limits: TransferLimits, store: S| async move {
+                let usage = store.set_transfer_limits(id, limits).await?;
+                call.record(&store, &limits).await;
+                Ok::<Json, Rejection>(warp::reply::json(&usage))
+            },
+        );
+
     // DELETE /accounts/:username
     let btp_clone = btp.clone();
     let delete_account = warp::delete()
         .and(warp::path("accounts"))
         .and(account_username_to_id.clone())
         .and(warp::path::end())
-        .and(admin_only.clone())
+        .and(audited_admin_only.clone())
         .and(with_store.clone())
-        .and_then(move |id: Uuid, store: S| {
+        .and_then(move |id: Uuid, call: AdminCall, store: S| {
             let btp = btp_clone.clone();
             async move {
                 let account = store.delete_account(id).await?;
+                call.record(&store, &()).await;
                 // close the btp connection (if any)
                 btp.close_connection(&id);
                 Ok::<Json, Rejection>(warp::reply::json(&account))
@@ -284,39 +566,61 @@ where
     let outgoing_handler_clone = outgoing_handler;
     let put_account_settings = warp::put()
         .and(warp::path("accounts"))
-        .and(admin_or_authorized_user_only.clone())
+        .and(admin_or_authorized_user.clone())
         .and(warp::path("settings"))
         .and(warp::path::end())
+        .and(warp::method())
+        .and(warp::path::full())
         .and(deserialize_json())
         .and(with_store.clone())
-        .and_then(move |id: Uuid, settings: AccountSettings, store: S| {
-            let btp = btp.clone();
-            let outgoing_handler = outgoing_handler_clone.clone();
-            async move {
-                if settings.ilp_over_btp_incoming_token.is_some() {
-                    // if the BTP token was provided, assume that it's different
-                    // from the existing one and drop the connection
-                    // the saved websocket connection
-                    btp.close_connection(&id);
+        .and_then(
+            move |(id, admin): (Uuid, Option<String>),
+                  method: Method,
+                  path: FullPath,
+                  settings: AccountSettings,
+                  store: S| {
+                let call = admin.map(|actor| AdminCall::new(actor, method, path.as_str().to_string()));
+                let btp = btp.clone();
+                let outgoing_handler = outgoing_handler_clone.clone();
+                let settlement_client = settlement_client.clone();
+                async move {
+                    // Users must not be able to lift their own rate limits
+                    if settings.modifies_limits() && call.is_none() {
+                        return Err(Rejection::from(ApiError::unauthorized().detail(
+                            "only the admin can modify the rate limits and the maximum packet amount of an account",
+                        )));
+                    }
+                    if settings.ilp_over_btp_incoming_token.is_some() {
+                        // if the BTP token was provided, assume that it's different
+                        // from the existing one and drop the connection
+                        // the saved websocket connection
+                        btp.close_connection(&id);
+                    }
+                    let modified_account =
+                        store.modify_account_settings(id, settings.clone()).await?;
+                    // Only the changes made by the admin are audited
+                    if let Some(call) = call {
+                        call.record(&store, &settings).await;
+                    }
+
+                    // Since the account was modified, we should also try to
+                    // connect to the new account:
+                    connect_to_external_services(
+                        outgoing_handler,
+                        modified_account.clone(),
+                        store,
+                        btp,
+                        settlement_client,
+                    )
+                    .await?;
+                    Ok::<Json, Rejection>(warp::reply::json(&modified_account))
                 }
-                let modified_account = store.modify_account_settings(id, settings).await?;
-
-                // Since the account was modified, we should also try to
-                // connect to the new account:
-                connect_to_external_services(
-                    outgoing_handler,
-                    modified_account.clone(),
-                    store,
-                    btp,
-                )
-                .await?;
-                Ok::<Json, Rejection>(warp::reply::json(&modified_account))
-            }
-        });
+            },
+        );
 
     // (Websocket) /accounts/:username/payments/incoming
     let incoming_payment_notifications = warp::path("accounts")
-        .and(admin_or_authorized_user_only)
+        .and(admin_or_authorized_user_only.clone())
         .and(warp::path("payments"))
         .and(warp::path("incoming"))
         .and(warp::path::end())
@@ -332,7 +636,7 @@ where
 
     // (Websocket) /payments/incoming
     let all_payment_notifications = warp::path("payments")
-        .and(admin_only)
+        .and(admin_only.clone())
         .and(warp::path("incoming"))
         .and(warp::path::end())
         .and(warp::ws())
@@ -345,135 +649,631 @@ where
             })
         });
 
+    // (Websocket) /accounts/:username/balance/notifications
+    let balance_notifications_clone = balance_notifications.clone();
+    let account_balance_notifications = warp::path("accounts")
+        .and(admin_or_authorized_user_only.clone())
+        .and(warp::path("balance"))
+        .and(warp::path("notifications"))
+        .and(warp::path::end())
+        .and(warp::ws())
+        .map(move |id: Uuid, ws: warp::ws::Ws| {
+            let notifications = balance_notifications_clone.clone();
+            ws.on_upgrade(move |ws: warp::ws::WebSocket| {
+                let (ws_tx, ws_rx) = ws.split();
+                tokio::task::spawn(notify_balances(ws_tx, notifications, Some(id)));
+                consume_msg_drain(ws_rx)
+            })
+        });
+
+    // (Websocket) /balance/notifications
+    let all_balance_notifications = warp::path("balance")
+        .and(admin_only.clone())
+        .and(warp::path("notifications"))
+        .and(warp::path::end())
+        .and(warp::ws())
+        .map(move |ws: warp::ws::Ws| {
+            let notifications = balance_notifications.clone();
+            ws.on_upgrade(move |ws: warp::ws::WebSocket| {
+                let (ws_tx, ws_rx) = ws.split();
+                tokio::task::spawn(notify_balances(ws_tx, notifications, None));
+                consume_msg_drain(ws_rx)
+            })
+        });
+
     // POST /accounts/:username/payments
+    // Body: { "receiver": "$example.com/bob", "source_amount": 100 }
+    //    or { "receiver": "$example.com/bob", "destination_amount": 100 }
+    // The payment is recorded in the account's payment history before it is sent
     let post_payments = warp::post()
         .and(warp::path("accounts"))
-        .and(authorized_user_only)
+        .and(authorized_user_only.clone())
         .and(warp::path("payments"))
         .and(warp::path::end())
         .and(deserialize_json())
-        .and(with_incoming_handler)
+        .and(with_incoming_handler.clone())
         .and(with_store.clone())
         .and_then(
             move |account: A, pay_request: SpspPayRequest, incoming_handler: I, store: S| {
                 async move {
-                    let receipt = pay(
-                        incoming_handler,
-                        account.clone(),
-                        store,
-                        &pay_request.receiver,
-                        pay_request.source_amount,
-                        pay_request.slippage,
-                    )
-                    .map_err(|err| {
+                    if pay_request.memo.as_ref().map_or(0, String::len) > MAX_MEMO_LENGTH {
+                        return Err(Rejection::from(ApiError::bad_request().detail(format!(
+                            "the memo may not be longer than {} bytes",
+                            MAX_MEMO_LENGTH
+                        ))));
+                    }
+                    let payment = OutgoingPayment::new(
+                        generate_payment_id()?,
+                        account.username().clone(),
+                        &pay_request,
+                    )?;
+                    let id = payment.id.clone();
+                    let send = match (pay_request.source_amount, pay_request.destination_amount) {
+                        (Some(source_amount), None) => pay(
+                            incoming_handler,
+                            account.clone(),
+                            store.clone(),
+                            &pay_request.receiver,
+                            source_amount,
+                            pay_request.slippage,
+                            pay_request.memo,
+                        )
+                        .left_future(),
+                        (None, Some(destination_amount)) => deliver(
+                            incoming_handler,
+                            account.clone(),
+                            store.clone(),
+                            &pay_request.receiver,
+                            destination_amount,
+                            pay_request.slippage,
+                            pay_request.memo,
+                        )
+                        .right_future(),
+                        _ => {
+                            return Err(Rejection::from(ApiError::bad_request().detail(
+                                "either the source amount or the destination amount must be set",
+                            )))
+                        }
+                    };
+                    let receipt = send_recorded_payment(&store, payment, send)
+                        .await?
+                        .map_err(|err| {
                         let msg = format!("Error sending SPSP payment: {}", err);
                         error!("{}", msg);
-                        // TODO give a different error message depending on what type of error it is
-                        Rejection::from(ApiError::internal_server_error().detail(msg))
-                    })
-                    .await?;
+                        Rejection::from(spsp_payment_error(&err).detail(msg))
+                    })?;
 
-                    debug!("Sent SPSP payment, receipt: {:?}", receipt);
-                    Ok::<Json, Rejection>(warp::reply::json(&json!(receipt)))
+                    debug!("Sent SPSP payment {}, receipt: {:?}", id, receipt);
+                    let mut response = json!(receipt);
+                    response["id"] = json!(id);
+                    Ok::<Json, Rejection>(warp::reply::json(&response))
                 }
             },
         );
 
-    // GET /accounts/:username/spsp
-    let server_secret_clone = server_secret.clone();
-    let get_spsp = warp::get()
+    // The receipts are signed with a key derived from the server secret, which the pulls use
+    let receipt_signer = PaymentReceiptSigner::new(&server_secret);
+
+    // POST /accounts/:username/pulls
+    // Body: { "pull_pointer": "$example.com/pull/<id>", "amount": 100 }
+    // Pulls the amount from the pull pointer into the account, via a new connection
+    // of the account's STREAM receiver
+    let server_secret_clone = server_secret;
+    let post_pulls = warp::post()
         .and(warp::path("accounts"))
-        .and(account_username_to_id)
-        .and(warp::path("spsp"))
+        .and(authorized_user_only.clone())
+        .and(warp::path("pulls"))
         .and(warp::path::end())
-        .and(with_store.clone())
-        .and_then(move |id: Uuid, store: S| {
-            let server_secret_clone = server_secret_clone.clone();
+        .and(deserialize_json())
+        .and_then(move |account: A, pull_request: SpspPullRequest| {
+            let server_secret = server_secret_clone.clone();
             async move {
-                let accounts = store.get_accounts(vec![id]).await?;
-                // TODO return the response without instantiating an SpspResponder (use a simple fn)
-                Ok::<_, Rejection>(
-                    SpspResponder::new(
-                        accounts[0].ilp_address().clone(),
-                        server_secret_clone.clone(),
-                    )
-                    .generate_http_response(),
+                let receiver = SpspResponder::new(account.ilp_address().clone(), server_secret)
+                    .generate_spsp_response();
+                let response = pull(
+                    &pull_request.pull_pointer,
+                    &PullRequest::new(pull_request.amount, &receiver),
                 )
+                .map_err(|err| {
+                    let msg = format!("Error pulling SPSP payment: {}", err);
+                    error!("{}", msg);
+                    Rejection::from(spsp_payment_error(&err).detail(msg))
+                })
+                .await?;
+
+                debug!("Pulled SPSP payment: {:?}", response);
+                Ok::<Json, Rejection>(warp::reply::json(&response))
             }
         });
 
-    // GET /.well-known/pay
-    // This is the endpoint a [Payment Pointer](https://github.com/interledger/rfcs/blob/master/0026-payment-pointers/0026-payment-pointers.md)
-    // with no path resolves to
-    let get_spsp_well_known = warp::get()
-        .and(warp::path(".well-known"))
-        .and(warp::path("pay"))
+    // POST /accounts/:username/payments/scheduled
+    // Body: { "receiver": "$example.com/bob", "source_amount": 100, "execute_at": 1600000000,
+    //         "recurrence": { "interval": 2592000, "count": 12 },
+    //         "notification_url": "https://wallet.example/payments" }
+    // Response: The scheduled payment, which the node's scheduler sends once it is due
+    let post_scheduled_payment = warp::post()
+        .and(warp::path("accounts"))
+        .and(authorized_user_only)
+        .and(warp::path("payments"))
+        .and(warp::path("scheduled"))
         .and(warp::path::end())
-        .and(with_store)
-        .and_then(move |store: S| {
-            let default_spsp_account = default_spsp_account.clone();
-            let server_secret_clone = server_secret.clone();
-            async move {
-                if let Some(ref username) = default_spsp_account {
-                    let id = store.get_account_id_from_username(&username).await?;
+        .and(deserialize_json())
+        .and(with_store.clone())
+        .and_then(
+            |account: A, request: ScheduledPaymentRequest, store: S| async move {
+                request.validate()?;
+                let payment = ScheduledPayment::new(
+                    generate_payment_id()?,
+                    account.username().clone(),
+                    request,
+                );
+                store.create_scheduled_payment(payment.clone()).await?;
+                debug!(
+                    "Scheduled payment {} of {} for {:?}",
+                    payment.id, payment.username, payment.next_execution
+                );
+                Ok::<Json, Rejection>(warp::reply::json(&payment))
+            },
+        );
 
-                    // TODO this shouldn't take multiple store calls
-                    let mut accounts = store.get_accounts(vec![id]).await?;
+    // GET /accounts/:username/payments/scheduled
+    // Response: The payments scheduled by the account, with the outcomes of their last executions
+    let get_scheduled_payments = warp::get()
+        .and(warp::path("accounts"))
+        .and(admin_or_authorized_user_only.clone())
+        .and(warp::path("payments"))
+        .and(warp::path("scheduled"))
+        .and(warp::path::end())
+        .and(with_store.clone())
+        .and_then(|id: Uuid, store: S| async move {
+            let account = get_account_by_id(&store, id).await?;
+            let payments: Vec<ScheduledPayment> = store
+                .get_scheduled_payments()
+                .await?
+                .into_iter()
+                .filter(|payment| &payment.username == account.username())
+                .collect();
+            Ok::<Json, Rejection>(warp::reply::json(&payments))
+        });
 
-                    let account = accounts.pop().unwrap();
-                    // TODO return the response without instantiating an SpspResponder (use a simple fn)
-                    Ok::<_, Rejection>(
-                        SpspResponder::new(
-                            account.ilp_address().clone(),
-                            server_secret_clone.clone(),
-                        )
-                        .generate_http_response(),
-                    )
-                } else {
-                    Err(Rejection::from(
-                        ApiError::not_found().detail("no default spsp account was configured"),
-                    ))
+    // GET /accounts/:username/payments
+    // Response: The payments sent by the account via the API, with their status, streamed
+    // page by page in no particular order
+    let get_payments = warp::get()
+        .and(warp::path("accounts"))
+        .and(admin_or_authorized_user_only.clone())
+        .and(warp::path("payments"))
+        .and(warp::path::end())
+        .and(with_store.clone())
+        .and_then(|id: Uuid, store: S| async move {
+            let username = get_account_by_id(&store, id).await?.username().clone();
+            streamed_json_array(move |cursor| {
+                let store = store.clone();
+                let username = username.clone();
+                async move {
+                    let (next, payments) = store
+                        .get_outgoing_payments_page(cursor, LISTING_PAGE_SIZE)
+                        .await?;
+                    let payments: Vec<OutgoingPayment> = payments
+                        .into_iter()
+                        .filter(|payment| payment.username == username)
+                        .collect();
+                    Ok((next, payments))
                 }
-            }
+                .boxed()
+            })
+            .await
         });
 
-    get_spsp
-        .or(get_spsp_well_known)
-        .or(post_accounts)
-        .or(get_accounts)
-        .or(put_account)
-        .or(delete_account)
-        .or(get_account)
-        .or(get_account_balance)
-        .or(put_account_settings)
-        .or(incoming_payment_notifications)
-        .or(all_payment_notifications)
-        .or(post_payments)
-}
-
-async fn consume_msg_drain(mut ws_rx: futures::stream::SplitStream<warp::ws::WebSocket>) {
-    while let Some(result) = ws_rx.next().await {
-        if let Err(e) = result {
-            debug!("consume msg drain read error: {}", e);
-            break;
-        }
-    }
-}
+    // GET /accounts/:username/payments/:id
+    let get_payment = warp::get()
+        .and(warp::path("accounts"))
+        .and(admin_or_authorized_user_only.clone())
+        .and(warp::path("payments"))
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(with_store.clone())
+        .and_then(|id: Uuid, payment_id: String, store: S| async move {
+            let account = get_account_by_id(&store, id).await?;
+            // The payments of the other accounts are not found either
+            let payment = store
+                .get_outgoing_payment(&payment_id)
+                .await?
+                .filter(|payment| &payment.username == account.username())
+                .ok_or_else(|| ApiError::not_found().detail("payment not found"))?;
+            Ok::<Json, Rejection>(warp::reply::json(&payment))
+        });
 
-fn notify_user(
-    ws_tx: futures::stream::SplitSink<warp::ws::WebSocket, warp::ws::Message>,
-    id: Uuid,
-    store: impl StreamNotificationsStore,
-) -> impl Future<Output = Result<(), ()>> {
-    let (tx, rx) = futures::channel::mpsc::unbounded::<PaymentNotification>();
-    // the client is now subscribed
-    store.add_payment_notification_subscription(id, tx);
+    // GET /accounts/:username/payments/:id/receipt
+    // Response: The receipt of the completed payment, signed by the node so that anyone can
+    // verify it with the public key published at `RECEIPT_KEY_PATH`
+    let get_payment_receipt = warp::get()
+        .and(warp::path("accounts"))
+        .and(admin_or_authorized_user_only.clone())
+        .and(warp::path("payments"))
+        .and(warp::path::param::<String>())
+        .and(warp::path("receipt"))
+        .and(warp::path::end())
+        .and(with_store.clone())
+        .and_then(move |id: Uuid, payment_id: String, store: S| {
+            let receipt_signer = receipt_signer.clone();
+            async move {
+                let account = get_account_by_id(&store, id).await?;
+                let payment = store
+                    .get_outgoing_payment(&payment_id)
+                    .await?
+                    .filter(|payment| &payment.username == account.username())
+                    .ok_or_else(|| ApiError::not_found().detail("payment not found"))?;
+                let receipt = receipt_signer.sign(&payment).ok_or_else(|| {
+                    ApiError::conflict().detail("the payment has not been completed")
+                })?;
+                Ok::<Json, Rejection>(warp::reply::json(&receipt))
+            }
+        });
 
-    // Anytime something is written to tx, it will reach rx
-    // and get converted to a warp::ws::Message
-    let rx = rx.map(|notification: PaymentNotification| {
-        let msg = warp::ws::Message::text(serde_json::to_string(&notification).unwrap());
-        Ok(msg)
+    // DELETE /accounts/:username/payments/scheduled/:id
+    // Cancels the scheduled payment, which is not executed again
+    let delete_scheduled_payment = warp::delete()
+        .and(warp::path("accounts"))
+        .and(admin_or_authorized_user_only.clone())
+        .and(warp::path("payments"))
+        .and(warp::path("scheduled"))
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(with_store.clone())
+        .and_then(|id: Uuid, payment_id: String, store: S| async move {
+            let account = get_account_by_id(&store, id).await?;
+            let not_found = || ApiError::not_found().detail("scheduled payment not found");
+            // The payments of the other accounts are not found either
+            store
+                .get_scheduled_payment(&payment_id)
+                .await?
+                .filter(|payment| &payment.username == account.username())
+                .ok_or_else(not_found)?;
+            let payment = store
+                .delete_scheduled_payment(&payment_id)
+                .await?
+                .ok_or_else(not_found)?;
+            debug!(
+                "Cancelled scheduled payment {} of {}",
+                payment.id, payment.username
+            );
+            Ok::<Json, Rejection>(warp::reply::json(&payment))
+        });
+
+    // POST /accounts/:username/ping
+    let post_ping = warp::post()
+        .and(warp::path("accounts"))
+        .and(admin_or_authorized_user_only.clone())
+        .and(warp::path("ping"))
+        .and(warp::path::end())
+        .and(deserialize_json())
+        .and(with_incoming_handler)
+        .and(with_store.clone())
+        .and_then(
+            move |id: Uuid, ping_request: PingRequest, incoming_handler: I, store: S| {
+                let echo_pings = echo_pings.clone();
+                async move {
+                    let destination = Address::from_str(&ping_request.destination)
+                        .map_err(|err| {
+                            Rejection::from(
+                                ApiError::bad_request()
+                                    .detail(format!("Invalid destination address: {}", err)),
+                            )
+                        })?;
+                    let mut accounts = store.get_accounts(vec![id]).await?;
+                    let account = accounts.pop().unwrap();
+
+                    let result = echo_pings
+                        .ping(
+                            incoming_handler,
+                            account,
+                            &store.get_ilp_address(),
+                            destination.clone(),
+                            Duration::from_millis(ping_request.timeout),
+                        )
+                        .await;
+                    // An unreachable destination is a valid result of a ping
+                    let response = match result {
+                        Ok(round_trip_time) => {
+                            debug!("Pinged {} in {:?}", destination, round_trip_time);
+                            json!({
+                                "destination": destination.to_string(),
+                                "reachable": true,
+                                "round_trip_time": round_trip_time.as_secs_f64() * 1000.0,
+                            })
+                        }
+                        Err(reject) => {
+                            debug!("Error pinging {}: {:?}", destination, reject);
+                            json!({
+                                "destination": destination.to_string(),
+                                "reachable": false,
+                                "reject": {
+                                    "code": reject.code().to_string(),
+                                    "message": String::from_utf8_lossy(reject.message()),
+                                    "triggered_by": reject.triggered_by().map(|address| address.to_string()),
+                                },
+                            })
+                        }
+                    };
+                    Ok::<Json, Rejection>(warp::reply::json(&response))
+                }
+            },
+        );
+
+    // PUT /accounts/:username/trace
+    let packet_traces_clone = packet_traces.clone();
+    let put_trace = warp::put()
+        .and(warp::path("accounts"))
+        .and(account_username_to_id.clone())
+        .and(warp::path("trace"))
+        .and(warp::path::end())
+        .and(audited_admin_only.clone())
+        .and(deserialize_json())
+        .and(with_store.clone())
+        .and_then(
+            move |id: Uuid, call: AdminCall, trace_request: TraceRequest, store: S| {
+                let packet_traces = packet_traces_clone.clone();
+                async move {
+                    if trace_request.duration > MAX_TRACE_DURATION {
+                        return Err(Rejection::from(ApiError::bad_request().detail(format!(
+                            "The duration of a trace cannot exceed {}ms",
+                            MAX_TRACE_DURATION
+                        ))));
+                    }
+                    if trace_request.capacity > MAX_TRACE_CAPACITY {
+                        return Err(Rejection::from(ApiError::bad_request().detail(format!(
+                            "A trace cannot hold more than {} packets",
+                            MAX_TRACE_CAPACITY
+                        ))));
+                    }
+                    let trace = packet_traces.start(
+                        id,
+                        Duration::from_millis(trace_request.duration),
+                        trace_request.capacity,
+                        trace_request.redact,
+                    );
+                    call.record(&store, &trace_request).await;
+                    debug!(
+                        "Started tracing the packets of account {} for {}ms",
+                        id, trace_request.duration
+                    );
+                    Ok::<Json, Rejection>(warp::reply::json(&trace))
+                }
+            },
+        );
+
+    // GET /accounts/:username/trace
+    let packet_traces_clone = packet_traces.clone();
+    let get_trace = warp::get()
+        .and(warp::path("accounts"))
+        .and(account_username_to_id.clone())
+        .and(warp::path("trace"))
+        .and(warp::path::end())
+        .and(admin_only.clone())
+        .and_then(move |id: Uuid| {
+            let trace = packet_traces_clone.get(id);
+            async move {
+                let trace = trace.ok_or_else(|| {
+                    ApiError::not_found().detail("the packets of this account are not traced")
+                })?;
+                Ok::<Json, Rejection>(warp::reply::json(&trace))
+            }
+        });
+
+    // DELETE /accounts/:username/trace
+    let delete_trace = warp::delete()
+        .and(warp::path("accounts"))
+        .and(account_username_to_id.clone())
+        .and(warp::path("trace"))
+        .and(warp::path::end())
+        .and(audited_admin_only.clone())
+        .and(with_store.clone())
+        .and_then(move |id: Uuid, call: AdminCall, store: S| {
+            let trace = packet_traces.stop(id);
+            async move {
+                let trace = trace.ok_or_else(|| {
+                    ApiError::not_found().detail("the packets of this account are not traced")
+                })?;
+                call.record(&store, &()).await;
+                debug!("Stopped tracing the packets of account {}", id);
+                Ok::<Json, Rejection>(warp::reply::json(&trace))
+            }
+        });
+
+    // The faults are only injected if the node has `FaultInjectionService`s
+    let fault_injection_enabled = move || {
+        let fault_injector = fault_injector.clone();
+        warp::any().and_then(move || {
+            let fault_injector = fault_injector.clone();
+            async move {
+                fault_injector.ok_or_else(|| {
+                    Rejection::from(
+                        ApiError::not_found().detail("fault injection is not enabled on this node"),
+                    )
+                })
+            }
+        })
+    };
+
+    // PUT /accounts/:username/faults
+    let put_faults = warp::put()
+        .and(warp::path("accounts"))
+        .and(account_username_to_id.clone())
+        .and(warp::path("faults"))
+        .and(warp::path::end())
+        .and(audited_admin_only.clone())
+        .and(fault_injection_enabled())
+        .and(deserialize_json())
+        .and(with_store.clone())
+        .and_then(
+            move |id: Uuid,
+                  call: AdminCall,
+                  fault_injector: FaultInjector,
+                  config: FaultConfig,
+                  store: S| {
+                async move {
+                    config
+                        .validate()
+                        .map_err(|err| Rejection::from(ApiError::bad_request().detail(err)))?;
+                    let report = fault_injector.set(id, config.clone());
+                    call.record(&store, &config).await;
+                    debug!(
+                        "Injecting faults into the packets of account {}: {:?}",
+                        id, config
+                    );
+                    Ok::<Json, Rejection>(warp::reply::json(&report))
+                }
+            },
+        );
+
+    // GET /accounts/:username/faults
+    let get_faults = warp::get()
+        .and(warp::path("accounts"))
+        .and(account_username_to_id.clone())
+        .and(warp::path("faults"))
+        .and(warp::path::end())
+        .and(admin_only.clone())
+        .and(fault_injection_enabled())
+        .and_then(move |id: Uuid, fault_injector: FaultInjector| async move {
+            let report = fault_injector.get(id).ok_or_else(|| {
+                ApiError::not_found()
+                    .detail("no faults are injected into the packets of this account")
+            })?;
+            Ok::<Json, Rejection>(warp::reply::json(&report))
+        });
+
+    // DELETE /accounts/:username/faults
+    let delete_faults = warp::delete()
+        .and(warp::path("accounts"))
+        .and(account_username_to_id)
+        .and(warp::path("faults"))
+        .and(warp::path::end())
+        .and(audited_admin_only)
+        .and(fault_injection_enabled())
+        .and(with_store)
+        .and_then(
+            move |id: Uuid, call: AdminCall, fault_injector: FaultInjector, store: S| async move {
+                let report = fault_injector.clear(id).ok_or_else(|| {
+                    ApiError::not_found()
+                        .detail("no faults are injected into the packets of this account")
+                })?;
+                call.record(&store, &()).await;
+                debug!(
+                    "Stopped injecting faults into the packets of account {}",
+                    id
+                );
+                Ok::<Json, Rejection>(warp::reply::json(&report))
+            },
+        );
+
+    // GET /accounts/:username/connections
+    // Response: The active incoming STREAM connections of the account, with their statistics
+    let stream_connections_clone = stream_connections.clone();
+    let get_connections = warp::get()
+        .and(warp::path("accounts"))
+        .and(admin_or_authorized_user_only.clone())
+        .and(warp::path("connections"))
+        .and(warp::path::end())
+        .map(move |id: Uuid| warp::reply::json(&stream_connections_clone.list(id)));
+
+    // DELETE /accounts/:username/connections/:id
+    // Closes the connection, whose packets are then rejected with a ConnectionClose frame
+    let delete_connection = warp::delete()
+        .and(warp::path("accounts"))
+        .and(admin_or_authorized_user_only.clone())
+        .and(warp::path("connections"))
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and_then(move |id: Uuid, connection_id: String| {
+            let connection = stream_connections.close(id, &connection_id);
+            async move {
+                let connection = connection
+                    .ok_or_else(|| ApiError::not_found().detail("connection not found"))?;
+                debug!(
+                    "Closed connection {} of account {}",
+                    connection.destination, id
+                );
+                Ok::<Json, Rejection>(warp::reply::json(&connection))
+            }
+        });
+
+    // The WebSockets are boxed together, to keep the type of the routes within the
+    // compiler's limits
+    let notifications = incoming_payment_notifications
+        .or(all_payment_notifications)
+        .or(account_balance_notifications)
+        .or(all_balance_notifications)
+        .boxed();
+
+    post_accounts
+        .or(get_accounts)
+        .or(put_account)
+        .or(delete_account)
+        .or(get_account)
+        .or(get_account_balance)
+        .or(get_account_liquidity)
+        .or(get_account_stats)
+        .or(put_credit_limits)
+        .or(get_transfer_limits)
+        .or(put_transfer_limits)
+        .or(put_account_settings)
+        .or(notifications)
+        .or(post_payments)
+        .or(post_pulls)
+        .or(post_scheduled_payment)
+        .or(get_scheduled_payments)
+        .or(delete_scheduled_payment)
+        .or(post_ping)
+        .or(put_trace)
+        .or(get_trace)
+        .or(delete_trace)
+        .or(put_faults)
+        .or(get_faults)
+        .or(delete_faults)
+        .or(get_connections)
+        .or(delete_connection)
+        .or(get_payments)
+        .or(get_payment)
+        .or(get_payment_receipt)
+}
+
+async fn get_account_by_id<S, A>(store: &S, id: Uuid) -> Result<A, Rejection>
+where
+    S: AccountStore<Account = A>,
+    A: Account,
+{
+    let account = store
+        .get_accounts(vec![id])
+        .await?
+        .pop()
+        .ok_or_else(ApiError::account_not_found)?;
+    Ok(account)
+}
+
+async fn consume_msg_drain(mut ws_rx: futures::stream::SplitStream<warp::ws::WebSocket>) {
+    while let Some(result) = ws_rx.next().await {
+        if let Err(e) = result {
+            debug!("consume msg drain read error: {}", e);
+            break;
+        }
+    }
+}
+
+fn notify_user(
+    ws_tx: futures::stream::SplitSink<warp::ws::WebSocket, warp::ws::Message>,
+    id: Uuid,
+    store: impl StreamNotificationsStore,
+) -> impl Future<Output = Result<(), ()>> {
+    let (tx, rx) = futures::channel::mpsc::unbounded::<PaymentNotification>();
+    // the client is now subscribed
+    store.add_payment_notification_subscription(id, tx);
+
+    // Anytime something is written to tx, it will reach rx
+    // and get converted to a warp::ws::Message
+    let rx = rx.map(|notification: PaymentNotification| {
+        let msg = warp::ws::Message::text(serde_json::to_string(&notification).unwrap());
+        Ok(msg)
     });
 
     // Then it gets forwarded to the client
@@ -509,6 +1309,35 @@ fn notify_all_payments(
         .then(futures::future::ok)
 }
 
+// Forwards the balance notifications of the account (or of all the accounts if none is
+// given) until the client disconnects. The notifications which the client fell behind on
+// are skipped.
+async fn notify_balances(
+    mut ws_tx: futures::stream::SplitSink<warp::ws::WebSocket, warp::ws::Message>,
+    notifications: BalanceNotifications,
+    account_id: Option<Uuid>,
+) {
+    let mut rx = notifications.subscribe();
+    loop {
+        let notification = match rx.recv().await {
+            Ok(notification) => notification,
+            Err(tokio::sync::broadcast::RecvError::Lagged(skipped)) => {
+                debug!("Skipped {} balance notifications", skipped);
+                continue;
+            }
+            Err(tokio::sync::broadcast::RecvError::Closed) => break,
+        };
+        if account_id.map_or(false, |id| id != notification.account_id) {
+            continue;
+        }
+        let msg = warp::ws::Message::text(serde_json::to_string(&notification).unwrap());
+        if let Err(e) = ws_tx.send(msg).await {
+            debug!("websocket send error: {}", e);
+            break;
+        }
+    }
+}
+
 async fn get_address_from_parent_and_update_routes<O, A, S>(
     mut service: O,
     parent: A,
@@ -588,18 +1417,20 @@ where
 // Helper function which gets called whenever a new account is added or
 // modified.
 // Performed actions:
-// 1. If they have a BTP uri configured: connect to their BTP socket
+// 1. If they have a BTP uri configured: connect to their BTP socket. If they share
+//    the BTP connection of another account instead, send their packets over it
 // 2. If they are a parent:
 // 2a. Perform an ILDCP Request to get the address assigned to us by them, and
 // update our store's address to that value
 // 2b. Perform a RouteControl Request to make them send us any new routes
 // 3. If they have a settlement engine endpoitn configured: Make a POST to the
 //    engine's account creation endpoint with the account's id
-async fn connect_to_external_services<O, A, S, B>(
+pub(crate) async fn connect_to_external_services<O, A, S, B>(
     service: O,
     account: A,
     store: S,
     btp: BtpOutgoingService<B, A>,
+    settlement_client: SettlementClient,
 ) -> Result<A, warp::reject::Rejection>
 where
     O: OutgoingService<A> + Clone + Send + Sync + 'static,
@@ -612,6 +1443,8 @@ where
     if account.get_ilp_over_btp_url().is_some() {
         trace!("Newly inserted account has a BTP URL configured, will try to connect");
         connect_to_service_account(account.clone(), true, btp).await?
+    } else if account.get_transport_account().is_some() {
+        btp.add_sub_account(account.clone());
     }
 
     // If we added a parent, get the address assigned to us by
@@ -634,14 +1467,13 @@ where
         .or(default_settlement_engine);
     if let Some(se_url) = settlement_engine_url {
         let id = account.id();
-        let http_client = SettlementClient::default();
         trace!(
             "Sending account {} creation request to settlement engine: {:?}",
             id,
             se_url.clone()
         );
 
-        let response = http_client
+        let response = settlement_client
             .create_engine_account(id, se_url.clone())
             .map_err(|err| {
                 Rejection::from(ApiError::internal_server_error().detail(err.to_string()))
@@ -653,11 +1485,11 @@ where
 
             // We will pre-fund our account with 0, which will return
             // the current settle_to value
-            let (_, amount_to_settle) = store.update_balances_for_fulfill(id, 0u64).await?;
+            let (_, amount_to_settle) = store.update_balances_for_settle_to(id).await?;
 
             // prefund the absolute value
             if amount_to_settle > 0 {
-                http_client
+                settlement_client
                     .send_settlement(id, se_url, amount_to_settle, account.asset_scale())
                     .map_err(|err| {
                         Rejection::from(ApiError::internal_server_error().detail(err.to_string()))
@@ -677,7 +1509,14 @@ where
 
 #[cfg(test)]
 mod tests {
+    use crate::payment_receipts::{PaymentReceiptSigner, SignedPaymentReceipt};
     use crate::routes::test_helpers::*;
+    use interledger_packet::{Address, FulfillBuilder, PrepareBuilder};
+    use interledger_service::{incoming_service_fn, IncomingRequest, IncomingService};
+    use interledger_service_util::{PacketTraceService, PacketTraces};
+    use serde_json::json;
+    use std::str::FromStr;
+    use std::time::{Duration, SystemTime};
     // TODO: Add test for GET /accounts/:username/spsp and /.well_known
 
     #[tokio::test]
@@ -690,6 +1529,62 @@ mod tests {
         assert_eq!(resp.status().as_u16(), 401);
     }
 
+    #[tokio::test]
+    async fn creates_accounts_from_templates() {
+        let api = test_accounts_api();
+        let details = json!({
+            "username": "alice",
+            "ilp_over_http_incoming_token": "password",
+        });
+        let resp = api_call(&api, "POST", "/accounts", "admin", Some(details.clone())).await;
+        assert_eq!(resp.status().as_u16(), 400);
+        let path = "/accounts?template=peer-xyz";
+        let resp = api_call(&api, "POST", path, "admin", Some(details.clone())).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let resp = api_call(
+            &api,
+            "POST",
+            "/accounts?template=other",
+            "admin",
+            Some(details),
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 400);
+
+        // The details of the request take precedence over the template
+        let details = json!({"username": "alice", "asset_scale": 2});
+        let resp = api_call(&api, "POST", path, "admin", Some(details)).await;
+        assert_eq!(resp.status().as_u16(), 400);
+    }
+
+    #[tokio::test]
+    async fn accounts_must_have_a_registered_asset() {
+        let api = test_accounts_api();
+        let mut details = DETAILS.clone().unwrap();
+        details["asset_code"] = "XZY".into();
+        let resp = api_call(&api, "POST", "/accounts", "admin", Some(details.clone())).await;
+        assert_eq!(resp.status().as_u16(), 400);
+        let resp = api_call(&api, "PUT", "/accounts/alice", "admin", Some(details)).await;
+        assert_eq!(resp.status().as_u16(), 400);
+
+        let mut details = DETAILS.clone().unwrap();
+        details["asset_scale"] = 2.into();
+        let resp = api_call(&api, "POST", "/accounts", "admin", Some(details)).await;
+        assert_eq!(resp.status().as_u16(), 400);
+    }
+
+    #[tokio::test]
+    async fn balances_are_displayed_with_the_rules_of_their_asset() {
+        let api = test_accounts_api();
+        let resp = api_call(&api, "GET", "/accounts/alice/balance", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let body: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(body["asset_code"], "XYZ");
+        // The test store's balance is 1 unit of scale 9, and XYZ amounts have 3 decimals
+        assert_eq!(body["display"]["balance"], "0.000 XYZ");
+        assert_eq!(body["display"]["in_flight"], "0.000 XYZ");
+    }
+
     #[tokio::test]
     async fn only_admin_can_delete_account() {
         let api = test_accounts_api();
@@ -748,6 +1643,115 @@ mod tests {
         assert_eq!(resp.status().as_u16(), 401);
     }
 
+    #[tokio::test]
+    async fn only_admin_or_user_can_get_accounts_stats() {
+        let api = test_accounts_api();
+        let resp = api_call(&api, "GET", "/accounts/alice/stats", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        // The test accounts are not probed
+        let stats: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert!(stats["latency"].is_null());
+
+        let resp = api_call(&api, "GET", "/accounts/alice/stats", "password", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+
+        let resp = api_call(&api, "GET", "/accounts/alice/stats", "wrong", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
+    #[tokio::test]
+    async fn only_admin_or_user_can_get_accounts_liquidity() {
+        let api = test_accounts_api();
+        let resp = api_call(&api, "GET", "/accounts/alice/liquidity", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let body: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(body["payable"], 1);
+        assert_eq!(body["receivable"], 0);
+
+        let resp = api_call(&api, "GET", "/accounts/alice/liquidity", "password", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+
+        let resp = api_call(&api, "GET", "/accounts/alice/liquidity", "wrong", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
+    #[tokio::test]
+    async fn only_admin_can_set_credit_limits() {
+        let api = test_accounts_api();
+        let limits = Some(serde_json::json!({ "payable_limit": 1000, "receivable_limit": 500 }));
+        let resp = api_call(
+            &api,
+            "PUT",
+            "/accounts/alice/credit-limits",
+            "admin",
+            limits.clone(),
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let body: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(
+            body["limits"],
+            serde_json::json!({ "payable_limit": 1000, "receivable_limit": 500 })
+        );
+
+        let resp = api_call(
+            &api,
+            "PUT",
+            "/accounts/alice/credit-limits",
+            "password",
+            limits,
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
+    #[tokio::test]
+    async fn only_admin_can_set_transfer_limits() {
+        let api = test_accounts_api();
+        let limits = Some(serde_json::json!({ "daily_send_limit": 1000 }));
+        let resp = api_call(
+            &api,
+            "PUT",
+            "/accounts/alice/transfer-limits",
+            "admin",
+            limits.clone(),
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let body: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(body["daily_send"]["limit"], 1000);
+        assert_eq!(body["monthly_send"]["limit"], serde_json::Value::Null);
+
+        let resp = api_call(
+            &api,
+            "PUT",
+            "/accounts/alice/transfer-limits",
+            "password",
+            limits,
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 401);
+
+        let resp = api_call(
+            &api,
+            "GET",
+            "/accounts/alice/transfer-limits",
+            "password",
+            None,
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let resp = api_call(
+            &api,
+            "GET",
+            "/accounts/alice/transfer-limits",
+            "wrong",
+            None,
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
     #[tokio::test]
     async fn only_admin_or_user_can_modify_accounts_settings() {
         let api = test_accounts_api();
@@ -783,6 +1787,52 @@ mod tests {
         assert_eq!(resp.status().as_u16(), 401);
     }
 
+    #[tokio::test]
+    async fn only_admin_can_modify_rate_limits() {
+        let api = test_accounts_api();
+        let limits = Some(serde_json::json!({
+            "packets_per_second_limit": 10,
+            "amount_per_minute_limit": "1000",
+        }));
+        let resp = api_call(
+            &api,
+            "PUT",
+            "/accounts/alice/settings",
+            "admin",
+            limits.clone(),
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 200);
+
+        let resp = api_call(&api, "PUT", "/accounts/alice/settings", "password", limits).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
+    #[tokio::test]
+    async fn only_admin_can_modify_max_packet_amount() {
+        let api = test_accounts_api();
+        let settings = Some(serde_json::json!({ "max_packet_amount": "10000" }));
+        let resp = api_call(
+            &api,
+            "PUT",
+            "/accounts/alice/settings",
+            "admin",
+            settings.clone(),
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 200);
+
+        let resp = api_call(
+            &api,
+            "PUT",
+            "/accounts/alice/settings",
+            "password",
+            settings,
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
     #[tokio::test]
     async fn only_admin_or_user_can_send_payment() {
         let payment: Option<serde_json::Value> = Some(serde_json::json!({
@@ -798,10 +1848,10 @@ mod tests {
             payment.clone(),
         )
         .await;
-        // This should return an internal server error since we're making an invalid payment request
+        // This should return a bad request since the receiver is not a valid payment pointer
         // We could have set up a mockito mock to set that pay is called correctly but we merely want
         // to check that authorization and paths work as expected
-        assert_eq!(resp.status().as_u16(), 500);
+        assert_eq!(resp.status().as_u16(), 400);
 
         // Note that the operator has indirect access to the user's token since they control the store
         let resp = api_call(
@@ -824,4 +1874,403 @@ mod tests {
         .await;
         assert_eq!(resp.status().as_u16(), 401);
     }
+
+    #[tokio::test]
+    async fn payments_fix_either_their_source_or_destination_amount() {
+        let api = test_accounts_api();
+        for payment in &[
+            serde_json::json!({ "receiver": "$example.com/bob" }),
+            serde_json::json!({
+                "receiver": "$example.com/bob",
+                "source_amount": 10,
+                "destination_amount": 10,
+            }),
+        ] {
+            let resp = api_call(
+                &api,
+                "POST",
+                "/accounts/alice/payments",
+                "password",
+                Some(payment.clone()),
+            )
+            .await;
+            assert_eq!(resp.status().as_u16(), 400);
+            assert!(String::from_utf8_lossy(resp.body()).contains("destination amount"));
+        }
+    }
+
+    #[tokio::test]
+    async fn only_user_can_pull_payments() {
+        let pull = Some(serde_json::json!({
+            "pull_pointer": "not a pull pointer",
+            "amount": 10,
+        }));
+        let api = test_accounts_api();
+        let resp = api_call(
+            &api,
+            "POST",
+            "/accounts/alice/pulls",
+            "password",
+            pull.clone(),
+        )
+        .await;
+        // The pull pointer is invalid, but the call was authorized
+        assert_eq!(resp.status().as_u16(), 400);
+
+        let resp = api_call(&api, "POST", "/accounts/alice/pulls", "admin", pull.clone()).await;
+        assert_eq!(resp.status().as_u16(), 401);
+        let resp = api_call(&api, "POST", "/accounts/alice/pulls", "wrong", pull).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
+    #[tokio::test]
+    async fn only_user_can_schedule_payments() {
+        let api = test_accounts_api();
+        let payment = serde_json::json!({
+            "receiver": "$example.com/bob",
+            "source_amount": 100,
+            "execute_at": 1_600_000_000,
+            "recurrence": { "interval": 60, "count": "3" },
+        });
+        let resp = api_call(
+            &api,
+            "POST",
+            "/accounts/alice/payments/scheduled",
+            "password",
+            Some(payment.clone()),
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let scheduled: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(scheduled["username"], "alice");
+        assert_eq!(scheduled["next_execution"], 1_600_000_000);
+        assert_eq!(scheduled["status"], "scheduled");
+        assert_eq!(
+            scheduled["recurrence"],
+            serde_json::json!({ "interval": 60, "count": 3 })
+        );
+
+        let resp = api_call(
+            &api,
+            "POST",
+            "/accounts/alice/payments/scheduled",
+            "password",
+            Some(serde_json::json!({ "receiver": "$example.com/bob", "source_amount": 0, "execute_at": 0 })),
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 400);
+
+        for auth in &["admin", "wrong"] {
+            let resp = api_call(
+                &api,
+                "POST",
+                "/accounts/alice/payments/scheduled",
+                auth,
+                Some(payment.clone()),
+            )
+            .await;
+            assert_eq!(resp.status().as_u16(), 401);
+        }
+    }
+
+    #[tokio::test]
+    async fn only_admin_or_user_can_get_sent_payments() {
+        let api = test_accounts_api();
+        for auth in &["admin", "password"] {
+            let resp = api_call(&api, "GET", "/accounts/alice/payments", auth, None).await;
+            assert_eq!(resp.status().as_u16(), 200);
+            let payments: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+            assert_eq!(payments[0]["id"], "sent");
+            assert_eq!(payments[0]["status"], "failed");
+
+            let resp = api_call(&api, "GET", "/accounts/alice/payments/sent", auth, None).await;
+            assert_eq!(resp.status().as_u16(), 200);
+        }
+
+        let resp = api_call(&api, "GET", "/accounts/alice/payments", "wrong", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+        let resp = api_call(&api, "GET", "/accounts/alice/payments/other", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 404);
+    }
+
+    #[tokio::test]
+    async fn signs_receipts_of_completed_payments() {
+        let api = test_accounts_api();
+        for auth in &["admin", "password"] {
+            let resp = api_call(
+                &api,
+                "GET",
+                "/accounts/alice/payments/delivered/receipt",
+                auth,
+                None,
+            )
+            .await;
+            assert_eq!(resp.status().as_u16(), 200);
+            let signed: SignedPaymentReceipt = serde_json::from_slice(resp.body()).unwrap();
+            assert_eq!(signed.receipt.payment_id, "delivered");
+            assert_eq!(signed.receipt.delivered_amount, 100);
+            let public_key = PaymentReceiptSigner::new(&[0; 32]).public_key().public_key;
+            assert!(signed
+                .verify(&base64::decode_config(&public_key, base64::URL_SAFE_NO_PAD).unwrap()));
+        }
+
+        // The failed payments have no receipt
+        let resp = api_call(
+            &api,
+            "GET",
+            "/accounts/alice/payments/sent/receipt",
+            "admin",
+            None,
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 409);
+        let resp = api_call(
+            &api,
+            "GET",
+            "/accounts/alice/payments/delivered/receipt",
+            "wrong",
+            None,
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
+    #[tokio::test]
+    async fn only_admin_or_user_can_list_and_cancel_scheduled_payments() {
+        let api = test_accounts_api();
+        for auth in &["admin", "password"] {
+            let resp = api_call(
+                &api,
+                "GET",
+                "/accounts/alice/payments/scheduled",
+                auth,
+                None,
+            )
+            .await;
+            assert_eq!(resp.status().as_u16(), 200);
+            let payments: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+            assert_eq!(payments[0]["id"], "rent");
+
+            let resp = api_call(
+                &api,
+                "DELETE",
+                "/accounts/alice/payments/scheduled/rent",
+                auth,
+                None,
+            )
+            .await;
+            assert_eq!(resp.status().as_u16(), 200);
+        }
+
+        let resp = api_call(
+            &api,
+            "GET",
+            "/accounts/alice/payments/scheduled",
+            "wrong",
+            None,
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 401);
+        let resp = api_call(
+            &api,
+            "DELETE",
+            "/accounts/alice/payments/scheduled/rent",
+            "wrong",
+            None,
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 401);
+        let resp = api_call(
+            &api,
+            "DELETE",
+            "/accounts/alice/payments/scheduled/other",
+            "admin",
+            None,
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 404);
+    }
+
+    #[tokio::test]
+    async fn only_admin_or_user_can_ping() {
+        let ping: Option<serde_json::Value> = Some(serde_json::json!({
+            "destination": "example.unreachable",
+        }));
+        let api = test_accounts_api();
+        let resp = api_call(&api, "POST", "/accounts/alice/ping", "admin", ping.clone()).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        // The test incoming handler rejects all packets
+        let body: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(body["reachable"], false);
+        assert_eq!(body["reject"]["code"], "F02");
+
+        let resp = api_call(
+            &api,
+            "POST",
+            "/accounts/alice/ping",
+            "password",
+            ping.clone(),
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 200);
+
+        let resp = api_call(&api, "POST", "/accounts/alice/ping", "wrong", ping).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
+    #[tokio::test]
+    async fn only_admin_can_trace_packets() {
+        let trace: Option<serde_json::Value> = Some(serde_json::json!({
+            "duration": 60000,
+            "capacity": 10,
+        }));
+        let packet_traces = PacketTraces::default();
+        let api = test_accounts_api_with_traces(packet_traces.clone());
+        let resp = api_call(&api, "GET", "/accounts/alice/trace", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 404);
+
+        let resp = api_call(
+            &api,
+            "PUT",
+            "/accounts/alice/trace",
+            "password",
+            trace.clone(),
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 401);
+
+        let resp = api_call(&api, "PUT", "/accounts/alice/trace", "admin", trace).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let body: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(body["active"], true);
+        assert_eq!(body["redacted"], true);
+        assert_eq!(body["capacity"], 10);
+        assert_eq!(body["packets"], json!([]));
+
+        // A packet from the account goes through the service recording the traces
+        let mut service = PacketTraceService::new(
+            packet_traces,
+            incoming_service_fn(|_| {
+                Ok(FulfillBuilder {
+                    fulfillment: &[1; 32],
+                    data: b"fulfill data",
+                }
+                .build())
+            }),
+        );
+        service
+            .handle_request(IncomingRequest {
+                from: TestAccount,
+                prepare: PrepareBuilder {
+                    destination: Address::from_str("example.bob").unwrap(),
+                    amount: 100,
+                    expires_at: SystemTime::now() + Duration::from_secs(30),
+                    execution_condition: &[0; 32],
+                    data: b"prepare data",
+                }
+                .build(),
+            })
+            .await
+            .unwrap();
+
+        let resp = api_call(&api, "GET", "/accounts/alice/trace", "password", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+        let resp = api_call(&api, "GET", "/accounts/alice/trace", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let body: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        let packets = body["packets"].as_array().unwrap();
+        assert_eq!(packets.len(), 1);
+        assert_eq!(packets[0]["direction"], "incoming");
+        assert_eq!(packets[0]["from"], "alice");
+        assert_eq!(packets[0]["prepare"]["destination"], "example.bob");
+        assert_eq!(packets[0]["prepare"]["amount"], 100);
+        assert_eq!(packets[0]["prepare"]["data_length"], 12);
+        assert_eq!(packets[0]["result"]["type"], "fulfill");
+        // The trace is redacted by default
+        assert_eq!(packets[0]["prepare"]["data"], serde_json::Value::Null);
+        assert_eq!(packets[0]["result"]["fulfillment"], serde_json::Value::Null);
+
+        let resp = api_call(&api, "DELETE", "/accounts/alice/trace", "password", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+        let resp = api_call(&api, "DELETE", "/accounts/alice/trace", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let resp = api_call(&api, "GET", "/accounts/alice/trace", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 404);
+
+        let too_long = Some(serde_json::json!({ "duration": 86400000 }));
+        let resp = api_call(&api, "PUT", "/accounts/alice/trace", "admin", too_long).await;
+        assert_eq!(resp.status().as_u16(), 400);
+    }
+    #[tokio::test]
+    async fn only_admin_or_user_can_list_and_close_connections() {
+        let api = test_accounts_api();
+        let resp = api_call(&api, "GET", "/accounts/alice/connections", "password", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        assert_eq!(resp.body(), &b"[]"[..]);
+        let resp = api_call(&api, "GET", "/accounts/alice/connections", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let resp = api_call(&api, "GET", "/accounts/alice/connections", "wrong", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+
+        let resp = api_call(
+            &api,
+            "DELETE",
+            "/accounts/alice/connections/token",
+            "password",
+            None,
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 404);
+        let resp = api_call(
+            &api,
+            "DELETE",
+            "/accounts/alice/connections/token",
+            "wrong",
+            None,
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
+    #[tokio::test]
+    async fn only_admin_can_inject_faults() {
+        let faults: Option<serde_json::Value> = Some(serde_json::json!({
+            "drop": 0.1,
+            "delay": 0.2,
+            "delay_ms": 500,
+        }));
+        let api = test_accounts_api();
+        let resp = api_call(
+            &api,
+            "PUT",
+            "/accounts/alice/faults",
+            "password",
+            faults.clone(),
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 401);
+
+        let resp = api_call(&api, "PUT", "/accounts/alice/faults", "admin", faults).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let body: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(body["config"]["drop"], 0.1);
+        assert_eq!(body["config"]["delay_ms"], 500);
+        assert_eq!(body["config"]["corrupt"], 0.0);
+        assert_eq!(body["packets"], 0);
+
+        let resp = api_call(&api, "GET", "/accounts/alice/faults", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let body: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(body["config"]["drop"], 0.1);
+        let resp = api_call(&api, "DELETE", "/accounts/alice/faults", "password", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+        let resp = api_call(&api, "DELETE", "/accounts/alice/faults", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let resp = api_call(&api, "GET", "/accounts/alice/faults", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 404);
+
+        let too_many = Some(serde_json::json!({ "drop": 0.6, "corrupt": 0.6 }));
+        let resp = api_call(&api, "PUT", "/accounts/alice/faults", "admin", too_many).await;
+        assert_eq!(resp.status().as_u16(), 400);
+    }
 }
@@ -0,0 +1,132 @@
+use crate::assets::{Asset, AssetStore};
+use crate::audit::{admin_call, AdminCall, AuditLogStore};
+use crate::jwt::{admin_only, JwtAuth};
+use interledger_errors::*;
+use interledger_http::deserialize_json;
+use tracing::debug;
+use warp::{self, reply::Json, Filter, Rejection};
+
+pub fn assets_api<S>(
+    admin_api_token: String,
+    jwt_auth: Option<JwtAuth>,
+    store: S,
+) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone
+where
+    S: AssetStore + AuditLogStore + Clone + Send + Sync + 'static,
+{
+    // Helper filters
+    let admin_auth_header = format!("Bearer {}", admin_api_token);
+    let audited_admin_only = admin_call(admin_auth_header, jwt_auth.clone());
+    let admin_only = admin_only(&admin_api_token, jwt_auth.clone());
+    let with_store = warp::any().map(move || store.clone());
+
+    // GET /assets
+    // Response: The assets registered on the node
+    let get_assets = warp::get()
+        .and(warp::path("assets"))
+        .and(warp::path::end())
+        .and(admin_only.clone())
+        .and(with_store.clone())
+        .and_then(|store: S| async move {
+            let assets = store.get_assets().await?;
+            Ok::<Json, Rejection>(warp::reply::json(&assets))
+        });
+
+    // GET /assets/:code
+    let get_asset = warp::get()
+        .and(warp::path("assets"))
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(admin_only)
+        .and(with_store.clone())
+        .and_then(|code: String, store: S| async move {
+            let asset = store
+                .get_asset(&code)
+                .await?
+                .ok_or_else(|| ApiError::not_found().detail("asset not found"))?;
+            Ok::<Json, Rejection>(warp::reply::json(&asset))
+        });
+
+    // PUT /assets
+    // Body: { "code": "USD", "min_scale": 2, "max_scale": 9, "symbol": "$",
+    //         "display_scale": 2, "rounding": "half_up" }
+    // Registers the asset, or replaces the one with the same code
+    let put_asset = warp::put()
+        .and(warp::path("assets"))
+        .and(warp::path::end())
+        .and(audited_admin_only.clone())
+        .and(deserialize_json())
+        .and(with_store.clone())
+        .and_then(|call: AdminCall, asset: Asset, store: S| async move {
+            asset.validate()?;
+            store.set_asset(asset.clone()).await?;
+            call.record(&store, &asset).await;
+            debug!("Set asset {:?}", asset);
+            Ok::<Json, Rejection>(warp::reply::json(&asset))
+        });
+
+    // DELETE /assets/:code
+    // The accounts denominated in the asset are kept, but no new ones can be created
+    let delete_asset = warp::delete()
+        .and(warp::path("assets"))
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(audited_admin_only)
+        .and(with_store)
+        .and_then(|code: String, call: AdminCall, store: S| async move {
+            let asset = store
+                .delete_asset(&code)
+                .await?
+                .ok_or_else(|| ApiError::not_found().detail("asset not found"))?;
+            call.record(&store, &()).await;
+            debug!("Deleted asset {}", asset.code);
+            Ok::<Json, Rejection>(warp::reply::json(&asset))
+        });
+
+    get_assets.or(get_asset).or(put_asset).or(delete_asset)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::routes::test_helpers::{api_call, test_assets_api};
+    use serde_json::{json, Value};
+
+    #[tokio::test]
+    async fn only_admin_can_manage_assets() {
+        let asset = json!({
+            "code": "EUR",
+            "min_scale": 2,
+            "max_scale": 9,
+            "symbol": "€",
+            "display_scale": 2,
+            "rounding": "down",
+        });
+        let api = test_assets_api();
+        let resp = api_call(&api, "PUT", "/assets", "admin", Some(asset.clone())).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        assert_eq!(serde_json::from_slice::<Value>(resp.body()).unwrap(), asset);
+
+        let invalid = json!({ "code": "EUR", "min_scale": 9, "max_scale": 2, "display_scale": 2 });
+        let resp = api_call(&api, "PUT", "/assets", "admin", Some(invalid)).await;
+        assert_eq!(resp.status().as_u16(), 400);
+
+        let resp = api_call(&api, "PUT", "/assets", "wrong", Some(asset)).await;
+        assert_eq!(resp.status().as_u16(), 401);
+
+        let resp = api_call(&api, "GET", "/assets", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let resp = api_call(&api, "GET", "/assets/XYZ", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let resp = api_call(&api, "GET", "/assets/EUR", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 404);
+        let resp = api_call(&api, "GET", "/assets", "wrong", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+
+        let resp = api_call(&api, "DELETE", "/assets/XYZ", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let resp = api_call(&api, "DELETE", "/assets/EUR", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 404);
+        let resp = api_call(&api, "DELETE", "/assets/XYZ", "wrong", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+}
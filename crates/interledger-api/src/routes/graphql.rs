@@ -0,0 +1,323 @@
+use crate::graphql::{execute, schema, Context, GraphqlRequest, QueryStore, RecentPayments};
+use crate::jwt::{admin_only, JwtAuth};
+use interledger_errors::*;
+use serde::Deserialize;
+use std::sync::Arc;
+use warp::{self, http::StatusCode, Filter, Rejection};
+
+/// Maximum size, in bytes, of the body of `POST /graphql`
+const MAX_REQUEST_SIZE: u64 = 64 * 1024;
+
+/// The query string of `GET /graphql`, whose variables are JSON-encoded
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GraphqlQuery {
+    query: String,
+    operation_name: Option<String>,
+    variables: Option<String>,
+}
+
+pub fn graphql_api<S>(
+    payments: Option<RecentPayments>,
+    admin_api_token: String,
+    jwt_auth: Option<JwtAuth>,
+    store: S,
+) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone
+where
+    S: QueryStore,
+{
+    let schema = Arc::new(schema::<S>());
+
+    // Helper filters
+    let with_payments = warp::any().and_then(move || {
+        let payments = payments.clone();
+        async move { payments.ok_or_else(warp::reject::not_found) }
+    });
+    let admin_only = admin_only(&admin_api_token, jwt_auth.clone());
+    let with_store = warp::any().map(move || store.clone());
+
+    // POST /graphql
+    // Body: { "query": "{ accounts { nodes { username balance } } }", "variables": {} }
+    // Response: { "data": { ... }, "errors": [ ... ] }
+    let post_graphql = warp::post()
+        .and(warp::path("graphql"))
+        .and(warp::path::end())
+        .and(with_payments.clone())
+        .and(admin_only.clone())
+        .and(warp::body::content_length_limit(MAX_REQUEST_SIZE))
+        .and(warp::body::json())
+        .map(|payments: RecentPayments, request: GraphqlRequest| (payments, request));
+
+    // GET /graphql?query={accounts{nodes{username}}}&variables={}
+    let get_graphql = warp::get()
+        .and(warp::path("graphql"))
+        .and(warp::path::end())
+        .and(with_payments)
+        .and(admin_only)
+        .and(warp::query::<GraphqlQuery>())
+        .and_then(|payments: RecentPayments, query: GraphqlQuery| async move {
+            let variables = match query.variables {
+                Some(variables) => Some(serde_json::from_str(&variables).map_err(|_| {
+                    ApiError::bad_request().detail("variables must be a JSON object")
+                })?),
+                None => None,
+            };
+            let request = GraphqlRequest {
+                query: query.query,
+                operation_name: query.operation_name,
+                variables,
+            };
+            Ok::<_, Rejection>((payments, request))
+        });
+
+    post_graphql
+        .or(get_graphql)
+        .unify()
+        .untuple_one()
+        .and(with_store)
+        .and_then(
+            move |payments: RecentPayments, request: GraphqlRequest, store: S| {
+                let schema = schema.clone();
+                // The context waits for the store on the runtime serving the request
+                let context = Context::new(store, payments);
+                async move {
+                    // The resolvers of juniper are synchronous, and block their thread while
+                    // they wait for the store
+                    let (valid, response) =
+                        tokio::task::spawn_blocking(move || execute(&schema, &context, request))
+                            .await
+                            .map_err(|err| {
+                                ApiError::internal_server_error().detail(err.to_string())
+                            })?;
+                    let status = if valid {
+                        StatusCode::OK
+                    } else {
+                        StatusCode::BAD_REQUEST
+                    };
+                    Ok::<_, Rejection>(warp::reply::with_status(
+                        warp::reply::json(&response),
+                        status,
+                    ))
+                }
+            },
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graphql::RecentPayments;
+    use crate::routes::test_helpers::{api_call, test_graphql_api};
+    use interledger_stream::PaymentNotification;
+    use serde_json::{json, Value};
+    use std::str::FromStr;
+
+    async fn query<F>(api: &F, query: &str) -> Value
+    where
+        F: warp::Filter + 'static,
+        F::Extract: warp::Reply,
+    {
+        let resp = api_call(
+            api,
+            "POST",
+            "/graphql",
+            "admin",
+            Some(json!({ "query": query })),
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 200);
+        serde_json::from_slice(resp.body()).unwrap()
+    }
+
+    async fn invalid_query<F>(api: &F, query: &str) -> Value
+    where
+        F: warp::Filter + 'static,
+        F::Extract: warp::Reply,
+    {
+        let resp = api_call(
+            api,
+            "POST",
+            "/graphql",
+            "admin",
+            Some(json!({ "query": query })),
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 400);
+        let resp: Value = serde_json::from_slice(resp.body()).unwrap();
+        assert!(resp.get("data").is_none());
+        resp
+    }
+
+    fn payment(to: &str, amount: u64) -> PaymentNotification {
+        PaymentNotification {
+            to_username: to.parse().unwrap(),
+            from_username: "bob".parse().unwrap(),
+            destination: interledger_packet::Address::from_str("example.alice").unwrap(),
+            amount,
+            timestamp: "2020-01-01T00:00:00Z".to_string(),
+            memo: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn selects_the_fields_of_the_accounts() {
+        let api = test_graphql_api(Some(RecentPayments::new(10)));
+        let resp = query(
+            &api,
+            "{ accounts(first: 1) { totalCount pageInfo { hasNextPage endCursor } \
+             nodes { username assetCode balance settlement { receivable payableLimit } } } \
+             rates { assetCode rate } }",
+        )
+        .await;
+        assert_eq!(
+            resp,
+            json!({ "data": {
+                "accounts": {
+                    "totalCount": 2,
+                    "pageInfo": { "hasNextPage": true, "endCursor": "alice" },
+                    "nodes": [{
+                        "username": "alice",
+                        "assetCode": "XYZ",
+                        "balance": 1,
+                        "settlement": { "receivable": 0, "payableLimit": null },
+                    }],
+                },
+                "rates": [
+                    { "assetCode": "ABC", "rate": 1.0 },
+                    { "assetCode": "XYZ", "rate": 2.0 },
+                ],
+            }})
+        );
+    }
+
+    #[tokio::test]
+    async fn paginates_the_payments() {
+        let payments = RecentPayments::new(2);
+        let api = test_graphql_api(Some(payments.clone()));
+        payments.push(payment("alice", 1));
+        payments.push(payment("alice", 2));
+        payments.push(payment("alice", 3));
+
+        let resp = query(
+            &api,
+            "{ payments(first: 1) { totalCount pageInfo { hasNextPage endCursor } nodes { amount } } }",
+        )
+        .await;
+        assert_eq!(
+            resp,
+            json!({ "data": { "payments": {
+                "totalCount": 2,
+                "pageInfo": { "hasNextPage": true, "endCursor": "2" },
+                "nodes": [{ "amount": 3 }],
+            }}})
+        );
+        let resp = query(
+            &api,
+            "{ account(username: \"alice\") { payments(after: \"2\") { nodes { id amount } } } }",
+        )
+        .await;
+        assert_eq!(
+            resp,
+            json!({ "data": { "account": { "payments": { "nodes": [{ "id": "1", "amount": 2 }] } } } })
+        );
+    }
+
+    #[tokio::test]
+    async fn reports_the_fields_which_cannot_be_resolved() {
+        let api = test_graphql_api(Some(RecentPayments::new(10)));
+        let resp = query(
+            &api,
+            "{ rates { assetCode } accounts(first: 501) { totalCount } routes { totalCount } }",
+        )
+        .await;
+        assert_eq!(
+            resp["data"],
+            json!({
+                "rates": [{ "assetCode": "ABC" }, { "assetCode": "XYZ" }],
+                "accounts": null,
+                "routes": { "totalCount": 3 },
+            })
+        );
+        assert_eq!(resp["errors"].as_array().unwrap().len(), 1);
+        assert_eq!(
+            resp["errors"][0]["message"],
+            "Argument \"first\" must be an integer between 0 and 500"
+        );
+        assert_eq!(resp["errors"][0]["path"], json!(["accounts"]));
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_queries() {
+        let api = test_graphql_api(Some(RecentPayments::new(10)));
+        let resp = invalid_query(&api, "{ rates { assetCode secret } }").await;
+        assert_eq!(
+            resp["errors"][0]["message"],
+            "Unknown field \"secret\" on type \"Rate\""
+        );
+        let resp = invalid_query(&api, "{ accounts { nodes }").await;
+        assert_eq!(resp["errors"].as_array().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn serves_the_schema() {
+        let api = test_graphql_api(Some(RecentPayments::new(10)));
+        let resp = query(
+            &api,
+            "{ __schema { queryType { name } } __type(name: \"Settlement\") { fields { type { name } } } }",
+        )
+        .await;
+        assert_eq!(resp["data"]["__schema"]["queryType"]["name"], "Query");
+        assert_eq!(resp["data"]["__type"]["fields"][1]["type"]["name"], "Long");
+    }
+
+    #[tokio::test]
+    async fn serves_get_requests() {
+        let api = test_graphql_api(Some(RecentPayments::new(10)));
+        let resp = api_call(
+            &api,
+            "GET",
+            "/graphql?query=query%20Rate(%24code%3AString%21)%7Baccount(username%3A%24code)%7Busername%7D%7D&variables=%7B%22code%22%3A%22alice%22%7D",
+            "admin",
+            None,
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 200);
+        assert_eq!(
+            serde_json::from_slice::<Value>(resp.body()).unwrap(),
+            json!({ "data": { "account": { "username": "alice" } } })
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_calls_if_disabled_or_not_admin() {
+        let api = test_graphql_api(Some(RecentPayments::new(10)));
+        let body = json!({ "query": "{ __typename }" });
+        let resp = api_call(&api, "POST", "/graphql", "wrong", Some(body.clone())).await;
+        assert_eq!(resp.status().as_u16(), 401);
+
+        let api = test_graphql_api(None);
+        let resp = api_call(&api, "POST", "/graphql", "admin", Some(body)).await;
+        assert!(resp.status().is_client_error());
+    }
+
+    #[tokio::test]
+    async fn rejects_large_and_deeply_nested_queries() {
+        let api = test_graphql_api(Some(RecentPayments::new(10)));
+        let query = format!("{}{{ __typename }}", " ".repeat(64 * 1024));
+        let resp = api_call(
+            &api,
+            "POST",
+            "/graphql",
+            "admin",
+            Some(json!({ "query": query })),
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 413);
+
+        let query = format!("{}{}", "{ a ".repeat(1000), "}".repeat(1000));
+        let response = invalid_query(&api, &query).await;
+        assert_eq!(
+            response["errors"][0]["message"],
+            "Syntax error: the document is nested more than 64 levels deep"
+        );
+    }
+}
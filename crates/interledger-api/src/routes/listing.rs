@@ -0,0 +1,156 @@
+use bytes::Bytes;
+use futures::{future::BoxFuture, stream};
+use interledger_errors::NodeStoreError;
+use serde::Serialize;
+use std::io;
+use tracing::error;
+use warp::{
+    http::{header::CONTENT_TYPE, HeaderValue},
+    reply::Response,
+    Rejection,
+};
+
+/// The number of items fetched from the store per page of a listing
+pub(crate) const LISTING_PAGE_SIZE: usize = 100;
+
+/// Where the stream of a listing is at
+enum Listing<T> {
+    /// A page to send, and the cursor of the next one
+    Page(u64, Vec<T>),
+    /// The cursor of the next page to fetch
+    Next(u64),
+    /// The array is to be closed
+    Close,
+    Done,
+}
+
+/// Streams the items of the pages returned by `fetch_page` as a JSON array, so that the
+/// memory used to send a listing does not grow with its size. `fetch_page` is called
+/// with the cursor of a page (0 for the first one) and returns the cursor of the next
+/// page, which is 0 after the last one, along with the items of the page.
+///
+/// The first page is fetched before the response is sent, so that its errors are
+/// returned as usual. An error fetching a later page ends the response before the array
+/// is closed, which clients see as an invalid body.
+pub(crate) async fn streamed_json_array<T, F>(fetch_page: F) -> Result<Response, Rejection>
+where
+    T: Serialize + Send + 'static,
+    F: Fn(u64) -> BoxFuture<'static, Result<(u64, Vec<T>), NodeStoreError>> + Send + 'static,
+{
+    let (next, items) = fetch_page(0).await?;
+    let chunks = stream::unfold(
+        (Listing::Page(next, items), false, fetch_page),
+        |(listing, started, fetch_page)| async move {
+            match listing {
+                Listing::Page(next, items) => {
+                    let mut chunk = Vec::new();
+                    let mut started = started;
+                    for item in items {
+                        chunk.push(if started { b',' } else { b'[' });
+                        started = true;
+                        if let Err(err) = serde_json::to_writer(&mut chunk, &item) {
+                            error!("Error serializing a listing: {}", err);
+                            return Some((
+                                Err(io::Error::from(err)),
+                                (Listing::Done, started, fetch_page),
+                            ));
+                        }
+                    }
+                    let listing = if next == 0 {
+                        Listing::Close
+                    } else {
+                        Listing::Next(next)
+                    };
+                    Some((Ok(Bytes::from(chunk)), (listing, started, fetch_page)))
+                }
+                Listing::Next(cursor) => match fetch_page(cursor).await {
+                    Ok((next, items)) => Some((
+                        Ok(Bytes::new()),
+                        (Listing::Page(next, items), started, fetch_page),
+                    )),
+                    Err(err) => {
+                        error!("Error fetching a page of a listing: {}", err);
+                        Some((
+                            Err(io::Error::new(io::ErrorKind::Other, err.to_string())),
+                            (Listing::Done, started, fetch_page),
+                        ))
+                    }
+                },
+                Listing::Close => {
+                    let end: &'static [u8] = if started { b"]" } else { b"[]" };
+                    Some((
+                        Ok(Bytes::from_static(end)),
+                        (Listing::Done, started, fetch_page),
+                    ))
+                }
+                Listing::Done => None,
+            }
+        },
+    );
+    let mut response = Response::new(hyper::Body::wrap_stream(chunks));
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::FutureExt;
+    use serde_json::Value;
+
+    async fn body(response: Response) -> Result<Value, String> {
+        let body = hyper::body::to_bytes(response.into_body())
+            .await
+            .map_err(|err| err.to_string())?;
+        serde_json::from_slice(&body).map_err(|err| err.to_string())
+    }
+
+    #[tokio::test]
+    async fn streams_the_pages_as_an_array() {
+        // Pages of two items, with an empty one in between
+        let response = streamed_json_array(|cursor| {
+            async move {
+                Ok(match cursor {
+                    0 => (1, vec![0, 1]),
+                    1 => (2, vec![]),
+                    _ => (0, vec![2]),
+                })
+            }
+            .boxed()
+        })
+        .await
+        .unwrap();
+        assert_eq!(response.headers()[CONTENT_TYPE], "application/json");
+        assert_eq!(body(response).await.unwrap(), serde_json::json!([0, 1, 2]));
+
+        let response = streamed_json_array(|_| async { Ok((0, Vec::<u32>::new())) }.boxed())
+            .await
+            .unwrap();
+        assert_eq!(body(response).await.unwrap(), serde_json::json!([]));
+    }
+
+    #[tokio::test]
+    async fn ends_the_array_on_errors() {
+        let response = streamed_json_array(|_| {
+            async { Err::<(u64, Vec<u32>), _>(NodeStoreError::MissingAccounts) }.boxed()
+        })
+        .await;
+        assert!(response.is_err());
+
+        let response = streamed_json_array(|cursor| {
+            async move {
+                if cursor == 0 {
+                    Ok((1, vec![0]))
+                } else {
+                    Err(NodeStoreError::MissingAccounts)
+                }
+            }
+            .boxed()
+        })
+        .await
+        .unwrap();
+        assert!(body(response).await.is_err());
+    }
+}
@@ -1,8 +1,35 @@
+mod account_templates;
 mod accounts;
+mod assets;
+mod graphql;
+mod listing;
 mod node_settings;
+mod payment_pointers;
+mod peering;
+mod provisioning;
+mod pull_payments;
+mod receipts;
+mod request_limiter;
+mod route_table;
+mod spsp;
 
+pub use account_templates::account_templates_api;
 pub use accounts::accounts_api;
+pub(crate) use accounts::{connect_to_external_services, spsp_payment_error};
+pub use assets::assets_api;
+pub use graphql::graphql_api;
+pub(crate) use interledger_api_types::get_default_max_slippage;
+pub(crate) use listing::{streamed_json_array, LISTING_PAGE_SIZE};
 pub use node_settings::node_settings_api;
+pub(crate) use payment_pointers::payment_pointer_response;
+pub use payment_pointers::payment_pointers_api;
+pub use peering::peering_api;
+pub use provisioning::provisioning_api;
+pub use pull_payments::pull_payments_api;
+pub use receipts::receipts_api;
+pub(crate) use receipts::spsp_response;
+pub use request_limiter::RequestLimiter;
+pub use spsp::spsp_api;
 
 #[cfg(test)]
 pub mod test_helpers;
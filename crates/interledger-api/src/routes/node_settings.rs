@@ -1,37 +1,167 @@
-use crate::{ExchangeRates, NodeStore};
+use super::route_table::{RouteEntry, RouteFormatQuery, RouteSource};
+use crate::audit::{admin_call, audit_limit, AdminCall, AuditLogStore, AuditQuery};
+use crate::jwt::{admin_only, JwtAuth};
+use crate::{
+    number_or_string, AssetStore, ExchangeRates, NodeStore, RuntimeSettings, CURRENT_API_VERSION,
+};
 use bytes::Bytes;
 use futures::TryFutureExt;
 use interledger_errors::*;
 use interledger_http::{deserialize_json, HttpAccount};
 use interledger_packet::Address;
-use interledger_rates::ExchangeRateStore;
+use interledger_rates::{ExchangeRateStore, PinnedRateStore};
 use interledger_router::RouterStore;
-use interledger_service::{Account, AccountStore, AddressStore, Username};
-use interledger_settlement::core::{types::SettlementAccount, SettlementClient};
-use secrecy::{ExposeSecret, SecretString};
-use serde::Serialize;
+use interledger_service::{Account, AccountStore, AddressStore, PeerBlocklist, Username};
+use interledger_service_util::{
+    resolve_fee_policy, BalanceJournalStore, FeeAccount, FeePolicy, FeePolicyStore, NodeStats,
+    QuoteRejection, Scheduler, SettlementReconciliation, StatsOverview,
+};
+use interledger_settlement::core::{
+    types::{Convert, ConvertDetails, SettlementAccount},
+    SettlementClient,
+};
+use interledger_stream::UnreachableDestinationStore;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
 use std::{
     collections::HashMap,
     iter::FromIterator,
+    net::IpAddr,
     str::{self, FromStr},
+    time::{Duration, Instant},
 };
 use tracing::{error, trace};
 use url::Url;
 use uuid::Uuid;
 use warp::{self, reply::Json, Filter, Rejection};
 
-// TODO add more to this response
+/// The versions of the API served by the node
+const API_VERSIONS: &[&str] = &[CURRENT_API_VERSION];
+
 #[derive(Clone, Serialize)]
 struct StatusResponse {
     status: String,
     ilp_address: Address,
     #[serde(skip_serializing_if = "Option::is_none")]
     version: Option<String>,
+    features: Vec<String>,
+    api_versions: &'static [&'static str],
+    /// Seconds since the API was started
+    uptime: u64,
+}
+
+#[derive(Clone, Serialize)]
+struct BalanceSnapshotResponse {
+    accounts: usize,
+}
+
+#[derive(Serialize)]
+struct StatsOverviewResponse {
+    accounts: usize,
+    #[serde(flatten)]
+    stats: StatsOverview,
+}
+
+#[derive(Deserialize)]
+struct ConvertQuery {
+    from: String,
+    to: String,
+    #[serde(deserialize_with = "number_or_string")]
+    amount: u64,
+    /// Scale of the amount, which defaults to the largest scale of the registered asset
+    #[serde(default)]
+    from_scale: Option<u8>,
+    #[serde(default)]
+    to_scale: Option<u8>,
+}
+
+#[derive(Serialize)]
+struct ConvertResponse {
+    source_amount: u64,
+    source_asset_code: String,
+    source_asset_scale: u8,
+    /// Amount of the forwarded packet, or `None` if the packet would be rejected
+    destination_amount: Option<u64>,
+    destination_asset_code: String,
+    destination_asset_scale: u8,
+    /// Exchange rate from the rate provider, before the spread
+    market_rate: f64,
+    /// Value of the amount at the market rate, in the destination units
+    market_amount: f64,
+    /// Exchange rate after the spread
+    exchange_rate: f64,
+    /// Amount converted at the exchange rate, before the fees are deducted
+    converted_amount: f64,
+    /// Fees deducted, in the destination units
+    fee: f64,
+    /// The policy which applies to packets between the two assets
+    fee_policy: FeePolicy,
+    /// Why the packet would be rejected
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rejection: Option<String>,
+}
+
+#[derive(Serialize)]
+struct RouteImportResponse {
+    /// Number of static routes set
+    imported: usize,
+    /// Number of local and learned routes, which are not imported
+    skipped: usize,
 }
 
+#[derive(Deserialize, Serialize)]
+struct QuarantineRequest {
+    /// Time, in milliseconds, for which the account is quarantined.
+    /// If it is not set, the account stays quarantined until it is released.
+    #[serde(default)]
+    duration: Option<u64>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct RatePin {
+    /// Rate of the asset, as the price of one unit in the node's reference asset
+    #[serde(deserialize_with = "number_or_string")]
+    rate: f64,
+}
+
+#[derive(Deserialize)]
+struct QuoteQuery {
+    from: Username,
+    to: Username,
+    #[serde(deserialize_with = "number_or_string")]
+    amount: u64,
+}
+
+#[derive(Serialize)]
+struct QuoteResponse {
+    source_amount: u64,
+    source_asset_code: String,
+    source_asset_scale: u8,
+    /// Amount the outgoing account would receive, after the spread and fees
+    destination_amount: u64,
+    destination_asset_code: String,
+    destination_asset_scale: u8,
+    /// Exchange rate after the spread
+    exchange_rate: f64,
+    /// Fees deducted, in the destination units
+    fee: f64,
+    /// The policy which applies to packets between the two accounts
+    fee_policy: FeePolicy,
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn node_settings_api<S, A>(
     admin_api_token: String,
+    jwt_auth: Option<JwtAuth>,
     node_version: Option<String>,
+    node_features: Vec<String>,
+    started_at: Instant,
+    fee_policy: FeePolicy,
+    node_stats: NodeStats,
+    blocklist: PeerBlocklist,
+    scheduler: Scheduler,
+    settlement_reconciliation: SettlementReconciliation,
+    settlement_client: SettlementClient,
     store: S,
 ) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone
 where
@@ -39,30 +169,24 @@ where
         + AccountStore<Account = A>
         + AddressStore
         + ExchangeRateStore
-        + RouterStore,
-    A: Account + HttpAccount + Send + Sync + SettlementAccount + Serialize + 'static,
+        + PinnedRateStore
+        + RouterStore
+        + BalanceJournalStore
+        + FeePolicyStore
+        + AuditLogStore
+        + AssetStore
+        + UnreachableDestinationStore,
+    A: Account + HttpAccount + FeeAccount + Send + Sync + SettlementAccount + Serialize + 'static,
 {
     // Helper filters
     let admin_auth_header = format!("Bearer {}", admin_api_token);
-    let admin_only = warp::header::<SecretString>("authorization")
-        .and_then(move |authorization: SecretString| {
-            let admin_auth_header = admin_auth_header.clone();
-            async move {
-                if authorization.expose_secret() == &admin_auth_header {
-                    Ok::<(), Rejection>(())
-                } else {
-                    Err(Rejection::from(
-                        ApiError::unauthorized().detail("invalid admin auth token provided"),
-                    ))
-                }
-            }
-        })
-        // This call makes it so we do not pass on a () value on
-        // success to the next filter, it just gets rid of it
-        .untuple_one();
+    let audited_admin_only = admin_call(admin_auth_header, jwt_auth.clone());
+    let admin_only = admin_only(&admin_api_token, jwt_auth.clone());
     let with_store = warp::any().map(move || store.clone());
 
     // GET /
+    // Response: The node's status, ILP address, version, enabled features, the versions of
+    // the API it serves, and its uptime in seconds
     let get_root = warp::get()
         .and(warp::path::end())
         .and(with_store.clone())
@@ -71,6 +195,9 @@ where
                 status: "Ready".to_string(),
                 ilp_address: store.get_ilp_address(),
                 version: node_version.clone(),
+                features: node_features.clone(),
+                api_versions: API_VERSIONS,
+                uptime: started_at.elapsed().as_secs(),
             })
         });
 
@@ -78,13 +205,16 @@ where
     let put_rates = warp::put()
         .and(warp::path("rates"))
         .and(warp::path::end())
-        .and(admin_only.clone())
+        .and(audited_admin_only.clone())
         .and(deserialize_json())
         .and(with_store.clone())
-        .and_then(|rates: ExchangeRates, store: S| async move {
-            store.set_exchange_rates(rates.0.clone())?;
-            Ok::<_, Rejection>(warp::reply::json(&rates))
-        });
+        .and_then(
+            |call: AdminCall, rates: ExchangeRates, store: S| async move {
+                store.set_exchange_rates(rates.0.clone())?;
+                call.record(&store, &rates).await;
+                Ok::<_, Rejection>(warp::reply::json(&rates))
+            },
+        );
 
     // GET /rates
     let get_rates = warp::get()
@@ -96,6 +226,209 @@ where
             Ok::<_, Rejection>(warp::reply::json(&rates))
         });
 
+    // PUT /rates/:asset_code/pin
+    // Body: The rate which overrides the one polled from the provider until it is unpinned
+    let put_rate_pin = warp::put()
+        .and(warp::path("rates"))
+        .and(warp::path::param::<String>())
+        .and(warp::path("pin"))
+        .and(warp::path::end())
+        .and(audited_admin_only.clone())
+        .and(deserialize_json())
+        .and(with_store.clone())
+        .and_then(
+            |asset_code: String, call: AdminCall, pin: RatePin, store: S| async move {
+                if !pin.rate.is_finite() || pin.rate <= 0.0 {
+                    return Err(Rejection::from(
+                        ApiError::bad_request().detail("the rate must be a positive number"),
+                    ));
+                }
+                store.pin_rate(&asset_code, pin.rate).await?;
+                call.record(&store, &pin).await;
+                let pinned = store.get_pinned_rates().await?;
+                Ok::<Json, Rejection>(warp::reply::json(&pinned))
+            },
+        );
+
+    // DELETE /rates/:asset_code/pin
+    // The rate of the asset last set by the provider or `PUT /rates` applies again
+    let delete_rate_pin = warp::delete()
+        .and(warp::path("rates"))
+        .and(warp::path::param::<String>())
+        .and(warp::path("pin"))
+        .and(warp::path::end())
+        .and(audited_admin_only.clone())
+        .and(with_store.clone())
+        .and_then(|asset_code: String, call: AdminCall, store: S| async move {
+            let rate = store.unpin_rate(&asset_code).await?.ok_or_else(|| {
+                Rejection::from(ApiError::not_found().detail("the rate of the asset is not pinned"))
+            })?;
+            call.record(&store, &()).await;
+            let mut unpinned = HashMap::new();
+            unpinned.insert(asset_code, rate);
+            Ok::<Json, Rejection>(warp::reply::json(&unpinned))
+        });
+
+    // GET /rates/pins
+    let get_rate_pins = warp::get()
+        .and(warp::path("rates"))
+        .and(warp::path("pins"))
+        .and(warp::path::end())
+        .and(admin_only.clone())
+        .and(with_store.clone())
+        .and_then(|store: S| async move {
+            let pinned = store.get_pinned_rates().await?;
+            Ok::<Json, Rejection>(warp::reply::json(&pinned))
+        });
+
+    // GET /rates/convert?from=<asset code>&to=<asset code>&amount=<amount>
+    // Converts the amount as a packet between accounts of the two assets would be, without
+    // the fee policies of the accounts
+    let get_rates_convert = warp::get()
+        .and(warp::path("rates"))
+        .and(warp::path("convert"))
+        .and(warp::path::end())
+        .and(admin_only.clone())
+        .and(warp::query::<ConvertQuery>())
+        .and(with_store.clone())
+        .and_then(move |query: ConvertQuery, store: S| async move {
+            let from_scale = asset_scale(&store, &query.from, query.from_scale).await?;
+            let to_scale = asset_scale(&store, &query.to, query.to_scale).await?;
+            let rate = if query.from == query.to {
+                1.0
+            } else {
+                let rates = store.get_exchange_rates(&[&query.from, &query.to])?;
+                rates[0] / rates[1]
+            };
+            let policy = store
+                .get_fee_policy(&query.from, &query.to)
+                .unwrap_or_default()
+                .or(fee_policy);
+            let details = ConvertDetails {
+                from: from_scale,
+                to: to_scale,
+            };
+            let quote = policy.quote(query.amount, rate, details).map_err(|_| {
+                Rejection::from(ApiError::bad_request().detail("amount too large to convert"))
+            })?;
+            let market_amount = (query.amount as f64 * rate)
+                .normalize_scale(ConvertDetails {
+                    from: from_scale,
+                    to: to_scale,
+                })
+                .unwrap_or(quote.converted_amount);
+            let outgoing_amount = quote.outgoing_amount();
+            let rejection = outgoing_amount.err().map(|rejection| {
+                match rejection {
+                    QuoteRejection::FeesExceedAmount => "R01: amount too small to cover the fees",
+                    QuoteRejection::AmountTooSmall => "R01: amount too small to be converted",
+                    QuoteRejection::AmountTooLarge => "F08: amount too large to be converted",
+                }
+                .to_string()
+            });
+
+            Ok::<Json, Rejection>(warp::reply::json(&ConvertResponse {
+                source_amount: query.amount,
+                source_asset_code: query.from,
+                source_asset_scale: from_scale,
+                destination_amount: outgoing_amount.ok(),
+                destination_asset_code: query.to,
+                destination_asset_scale: to_scale,
+                market_rate: rate,
+                market_amount,
+                exchange_rate: quote.rate,
+                converted_amount: quote.converted_amount,
+                fee: quote.fee,
+                fee_policy: policy,
+                rejection,
+            }))
+        });
+
+    // PUT /fees
+    // Body: Map of asset pair (FROM/TO) -> FeePolicy
+    let put_fee_policies = warp::put()
+        .and(warp::path("fees"))
+        .and(warp::path::end())
+        .and(audited_admin_only.clone())
+        .and(deserialize_json())
+        .and(with_store.clone())
+        .and_then(
+            |call: AdminCall, policies: HashMap<String, FeePolicy>, store: S| async move {
+                store.set_fee_policies(policies.clone())?;
+                call.record(&store, &policies).await;
+                let policies = store.get_all_fee_policies()?;
+                Ok::<_, Rejection>(warp::reply::json(&policies))
+            },
+        );
+
+    // GET /fees
+    let get_fee_policies = warp::get()
+        .and(warp::path("fees"))
+        .and(warp::path::end())
+        .and(admin_only.clone())
+        .and(with_store.clone())
+        .and_then(|store: S| async move {
+            let policies = store.get_all_fee_policies()?;
+            Ok::<_, Rejection>(warp::reply::json(&policies))
+        });
+
+    // GET /quote?from=<username>&to=<username>&amount=<amount>
+    // Converts the amount as if a packet was forwarded from one account to the other
+    let get_quote = warp::get()
+        .and(warp::path("quote"))
+        .and(warp::path::end())
+        .and(admin_only.clone())
+        .and(warp::query::<QuoteQuery>())
+        .and(with_store.clone())
+        .and_then(move |query: QuoteQuery, store: S| async move {
+            let from_id = store.get_account_id_from_username(&query.from).await?;
+            let to_id = store.get_account_id_from_username(&query.to).await?;
+            let mut accounts = store.get_accounts(vec![from_id, to_id]).await?;
+            let to = accounts
+                .pop()
+                .ok_or_else(|| Rejection::from(ApiError::not_found()))?;
+            let from = accounts
+                .pop()
+                .ok_or_else(|| Rejection::from(ApiError::not_found()))?;
+
+            let rate = if from.asset_code() == to.asset_code() {
+                1.0
+            } else {
+                let rates = store.get_exchange_rates(&[from.asset_code(), to.asset_code()])?;
+                rates[0] / rates[1]
+            };
+            let policy = resolve_fee_policy(&store, fee_policy, &from, &to);
+            let quote = policy
+                .quote(
+                    query.amount,
+                    rate,
+                    ConvertDetails {
+                        from: from.asset_scale(),
+                        to: to.asset_scale(),
+                    },
+                )
+                .map_err(|_| {
+                    Rejection::from(ApiError::bad_request().detail("amount too large to convert"))
+                })?;
+
+            Ok::<Json, Rejection>(warp::reply::json(&QuoteResponse {
+                source_amount: query.amount,
+                source_asset_code: from.asset_code().to_string(),
+                source_asset_scale: from.asset_scale(),
+                // Amounts which cannot be forwarded are quoted as 0
+                destination_amount: if quote.destination_amount >= 1.0 {
+                    quote.destination_amount.min(u64::MAX as f64) as u64
+                } else {
+                    0
+                },
+                destination_asset_code: to.asset_code().to_string(),
+                destination_asset_scale: to.asset_scale(),
+                exchange_rate: quote.rate,
+                fee: quote.fee,
+                fee_policy: policy,
+            }))
+        });
+
     // GET /routes
     // Response: Map of ILP Address prefix -> Username
     let get_routes = warp::get()
@@ -121,39 +454,136 @@ where
             }
         });
 
-    // PUT /routes/static
-    // Body: Map of ILP Address prefix -> Username
-    let put_static_routes = warp::put()
+    // GET /routes/export?format=<json|csv>
+    // Response: The routes, with their next hops and whether they are static, local or learned
+    let get_routes_export = warp::get()
         .and(warp::path("routes"))
-        .and(warp::path("static"))
+        .and(warp::path("export"))
         .and(warp::path::end())
         .and(admin_only.clone())
-        .and(deserialize_json())
+        .and(warp::query::<RouteFormatQuery>())
         .and(with_store.clone())
-        .and_then(move |routes: HashMap<String, String>, store: S| {
-            async move {
-                // Convert the usernames to account IDs to set the routes in the store
-                let mut usernames: Vec<Username> = Vec::new();
-                for username in routes.values() {
-                    let user = match Username::from_str(&username) {
-                        Ok(u) => u,
-                        Err(_) => return Err(Rejection::from(ApiError::bad_request())),
+        .and_then(|query: RouteFormatQuery, store: S| async move {
+            let static_routes = store.get_static_routes().await?;
+            let mut routes: Vec<(String, Uuid)> = store
+                .routing_table()
+                .iter()
+                .map(|(prefix, account_id)| (prefix.clone(), *account_id))
+                .collect();
+            routes.sort();
+            let accounts = store
+                .get_accounts(routes.iter().map(|(_, account_id)| *account_id).collect())
+                .await?;
+            let routes: Vec<RouteEntry> = routes
+                .into_iter()
+                .zip(accounts)
+                .map(|((prefix, account_id), account)| {
+                    let source = if static_routes.get(&prefix) == Some(&account_id) {
+                        RouteSource::Static
+                    } else if account.ilp_address().to_string() == prefix {
+                        RouteSource::Local
+                    } else {
+                        RouteSource::Learned
                     };
-                    usernames.push(user);
-                }
+                    RouteEntry {
+                        prefix,
+                        next_hop: account.username().to_string(),
+                        source,
+                    }
+                })
+                .collect();
+            Ok::<_, Rejection>(warp::reply::with_header(
+                query.format.write(&routes),
+                "content-type",
+                query.format.content_type(),
+            ))
+        });
 
-                let mut account_ids: Vec<Uuid> = Vec::new();
-                for username in usernames {
+    // PUT /routes/import?format=<json|csv>
+    // Body: Routes as exported, whose static routes replace the node's static routes
+    let put_routes_import = warp::put()
+        .and(warp::path("routes"))
+        .and(warp::path("import"))
+        .and(warp::path::end())
+        .and(audited_admin_only.clone())
+        .and(warp::query::<RouteFormatQuery>())
+        .and(warp::body::bytes())
+        .and(with_store.clone())
+        .and_then(
+            |call: AdminCall, query: RouteFormatQuery, body: Bytes, store: S| async move {
+                let routes = query.format.read(&body).map_err(|err| {
+                    Rejection::from(
+                        ApiError::bad_request().detail(format!("invalid routes: {}", err)),
+                    )
+                })?;
+                let (static_routes, skipped): (Vec<RouteEntry>, Vec<RouteEntry>) = routes
+                    .into_iter()
+                    .partition(|route| route.source == RouteSource::Static);
+
+                let mut account_ids = Vec::with_capacity(static_routes.len());
+                for route in &static_routes {
+                    let username = Username::from_str(&route.next_hop).map_err(|_| {
+                        Rejection::from(
+                            ApiError::bad_request()
+                                .detail(format!("invalid next hop for {}", route.prefix)),
+                        )
+                    })?;
                     account_ids.push(store.get_account_id_from_username(&username).await?);
                 }
-
-                let prefixes = routes.keys().map(|s| s.to_string());
+                let routes: HashMap<String, String> = static_routes
+                    .iter()
+                    .map(|route| (route.prefix.clone(), route.next_hop.clone()))
+                    .collect();
                 store
-                    .set_static_routes(prefixes.zip(account_ids.into_iter()))
+                    .set_static_routes(
+                        static_routes
+                            .into_iter()
+                            .map(|route| route.prefix)
+                            .zip(account_ids),
+                    )
                     .await?;
-                Ok::<Json, Rejection>(warp::reply::json(&routes))
-            }
-        });
+                call.record(&store, &routes).await;
+                Ok::<Json, Rejection>(warp::reply::json(&RouteImportResponse {
+                    imported: routes.len(),
+                    skipped: skipped.len(),
+                }))
+            },
+        );
+
+    // PUT /routes/static
+    // Body: Map of ILP Address prefix -> Username
+    let put_static_routes = warp::put()
+        .and(warp::path("routes"))
+        .and(warp::path("static"))
+        .and(warp::path::end())
+        .and(audited_admin_only.clone())
+        .and(deserialize_json())
+        .and(with_store.clone())
+        .and_then(
+            move |call: AdminCall, routes: HashMap<String, String>, store: S| {
+                async move {
+                    // Convert the usernames to account IDs to set the routes in the store
+                    let mut usernames: Vec<Username> = Vec::new();
+                    for username in routes.values() {
+                        let user = match Username::from_str(username) {
+                            Ok(u) => u,
+                            Err(_) => return Err(Rejection::from(ApiError::bad_request())),
+                        };
+                        usernames.push(user);
+                    }
+
+                    let mut account_ids: Vec<Uuid> = Vec::new();
+                    for username in usernames {
+                        account_ids.push(store.get_account_id_from_username(&username).await?);
+                    }
+
+                    let prefixes = routes.keys().map(|s| s.to_string());
+                    store.set_static_routes(prefixes.zip(account_ids)).await?;
+                    call.record(&store, &routes).await;
+                    Ok::<Json, Rejection>(warp::reply::json(&routes))
+                }
+            },
+        );
 
     // PUT /routes/static/:prefix
     // Body: Username
@@ -162,10 +592,10 @@ where
         .and(warp::path("static"))
         .and(warp::path::param::<String>())
         .and(warp::path::end())
-        .and(admin_only.clone())
+        .and(audited_admin_only.clone())
         .and(warp::body::bytes())
         .and(with_store.clone())
-        .and_then(|prefix: String, body: Bytes, store: S| {
+        .and_then(|prefix: String, call: AdminCall, body: Bytes, store: S| {
             async move {
                 let username_str =
                     str::from_utf8(&body).map_err(|_| Rejection::from(ApiError::bad_request()))?;
@@ -174,22 +604,62 @@ where
                 // Convert the username to an account ID to set it in the store
                 let account_id = store.get_account_id_from_username(&username).await?;
                 store.set_static_route(prefix, account_id).await?;
+                call.record(&store, &username).await;
                 Ok::<String, Rejection>(username.to_string())
             }
         });
 
+    // GET /settlement/engines
+    // Response: Map of asset code -> URL of the default settlement engine of the asset's accounts
+    let get_settlement_engines = warp::get()
+        .and(warp::path("settlement"))
+        .and(warp::path("engines"))
+        .and(warp::path::end())
+        .and(admin_only.clone())
+        .and(with_store.clone())
+        .and_then(|store: S| async move {
+            let engines = store.get_settlement_engines().await?;
+            Ok::<Json, Rejection>(warp::reply::json(&engines))
+        });
+
+    // DELETE /settlement/engines/:asset_code
+    // The accounts of the asset without a settlement engine of their own are no longer settled
+    let delete_settlement_engine = warp::delete()
+        .and(warp::path("settlement"))
+        .and(warp::path("engines"))
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(audited_admin_only.clone())
+        .and(with_store.clone())
+        .and_then(|asset_code: String, call: AdminCall, store: S| async move {
+            let url = store
+                .delete_settlement_engine(&asset_code)
+                .await?
+                .ok_or_else(|| {
+                    ApiError::not_found().detail("the asset has no default settlement engine")
+                })?;
+            call.record(&store, &()).await;
+            let mut engines = HashMap::new();
+            engines.insert(asset_code, url);
+            Ok::<Json, Rejection>(warp::reply::json(&engines))
+        });
+
     // PUT /settlement/engines
+    // Body: Map of asset code -> settlement engine URL. The accounts of the asset codes which
+    // have no settlement engine of their own use these, and the other asset codes are kept.
     let put_settlement_engines = warp::put()
         .and(warp::path("settlement"))
         .and(warp::path("engines"))
         .and(warp::path::end())
-        .and(admin_only)
+        .and(audited_admin_only.clone())
         .and(warp::body::json())
-        .and(with_store)
-        .and_then(move |asset_to_url_map: HashMap<String, Url>, store: S| async move {
+        .and(with_store.clone())
+        .and(warp::any().map(move || settlement_client.clone()))
+        .and_then(move |call: AdminCall, asset_to_url_map: HashMap<String, Url>, store: S, client: SettlementClient| async move {
             let asset_to_url_map_clone = asset_to_url_map.clone();
             store
                 .set_settlement_engines(asset_to_url_map.clone()).await?;
+            call.record(&store, &asset_to_url_map).await;
             // Create the accounts on the settlement engines for any
             // accounts that are using the default settlement engine URLs
             // (This is done in case we modify the globally configured settlement
@@ -201,7 +671,6 @@ where
             // (even if this isn't called often, it could crash the node at some point)
             let accounts = store.get_all_accounts().await?;
 
-            let client = SettlementClient::default();
             // Try creating the account on the settlement engine if the settlement_engine_url of the
             // account is the one we just configured as the default for the account's asset code
             for account in accounts {
@@ -221,28 +690,367 @@ where
             Ok::<Json, Rejection>(warp::reply::json(&asset_to_url_map_clone))
         });
 
+    // GET /settings
+    let get_runtime_settings = warp::get()
+        .and(warp::path("settings"))
+        .and(warp::path::end())
+        .and(admin_only.clone())
+        .and(with_store.clone())
+        .and_then(|store: S| async move {
+            let settings = store.get_runtime_settings().await?;
+            Ok::<Json, Rejection>(warp::reply::json(&settings))
+        });
+
+    // PUT /settings
+    // The running node picks up the new settings on its next poll of the store
+    let put_runtime_settings = warp::put()
+        .and(warp::path("settings"))
+        .and(warp::path::end())
+        .and(audited_admin_only.clone())
+        .and(deserialize_json())
+        .and(with_store.clone())
+        .and_then(
+            |call: AdminCall, settings: RuntimeSettings, store: S| async move {
+                if settings.route_broadcast_interval == Some(0)
+                    || settings.exchange_rate_poll_interval == Some(0)
+                {
+                    return Err(Rejection::from(
+                        ApiError::bad_request().detail("intervals must be greater than 0"),
+                    ));
+                }
+                store.set_runtime_settings(settings.clone()).await?;
+                call.record(&store, &settings).await;
+                Ok::<Json, Rejection>(warp::reply::json(&settings))
+            },
+        );
+
+    // POST /balances/snapshot
+    let post_balances_snapshot = warp::post()
+        .and(warp::path("balances"))
+        .and(warp::path("snapshot"))
+        .and(warp::path::end())
+        .and(audited_admin_only.clone())
+        .and(with_store.clone())
+        .and_then(|call: AdminCall, store: S| async move {
+            let accounts = store.snapshot_balances().await?;
+            call.record(&store, &()).await;
+            Ok::<Json, Rejection>(warp::reply::json(&BalanceSnapshotResponse { accounts }))
+        });
+
+    // GET /balances/verify
+    // Response: Balance of each account next to the one replayed from its journal
+    let get_balances_verify = warp::get()
+        .and(warp::path("balances"))
+        .and(warp::path("verify"))
+        .and(warp::path::end())
+        .and(admin_only.clone())
+        .and(with_store.clone())
+        .and_then(|store: S| async move {
+            let verifications = store.verify_balances().await?;
+            Ok::<Json, Rejection>(warp::reply::json(&verifications))
+        });
+
+    // POST /balances/:username/recover
+    // Overwrites the account's balance with the one replayed from its journal
+    let post_balance_recover = warp::post()
+        .and(warp::path("balances"))
+        .and(warp::path::param::<Username>())
+        .and(warp::path("recover"))
+        .and(warp::path::end())
+        .and(audited_admin_only.clone())
+        .and(with_store.clone())
+        .and_then(|username: Username, call: AdminCall, store: S| async move {
+            let account_id = store.get_account_id_from_username(&username).await?;
+            let verification = store.recover_balance(account_id).await?;
+            call.record(&store, &()).await;
+            Ok::<Json, Rejection>(warp::reply::json(&verification))
+        });
+
+    let stats_clone = node_stats.clone();
+    // GET /stats/overview
+    // Response: Aggregate statistics of the packets received during the last 24 hours
+    let get_stats_overview = warp::get()
+        .and(warp::path("stats"))
+        .and(warp::path("overview"))
+        .and(warp::path::end())
+        .and(admin_only.clone())
+        .and(with_store.clone())
+        .and_then(move |store: S| {
+            let stats = node_stats.overview();
+            async move {
+                let accounts = store.get_all_accounts().await?.len();
+                Ok::<Json, Rejection>(warp::reply::json(&StatsOverviewResponse {
+                    accounts,
+                    stats,
+                }))
+            }
+        });
+
+    // GET /stats/conversions
+    // Response: The rates, spread, fees and rounding applied to the packets converted during
+    // the last 24 hours, and the resulting slippage, by asset pair
+    let get_stats_conversions = warp::get()
+        .and(warp::path("stats"))
+        .and(warp::path("conversions"))
+        .and(warp::path::end())
+        .and(admin_only.clone())
+        .map(move || warp::reply::json(&stats_clone.conversions()));
+
+    // GET /audit?since=<timestamp>&until=<timestamp>&limit=<limit>
+    // Response: The entries of the audit log of the mutating admin calls, newest first
+    let get_audit_log = warp::get()
+        .and(warp::path("audit"))
+        .and(warp::path::end())
+        .and(admin_only.clone())
+        .and(warp::query::<AuditQuery>())
+        .and(with_store.clone())
+        .and_then(|query: AuditQuery, store: S| async move {
+            let query = AuditQuery {
+                limit: Some(audit_limit(&query)),
+                ..query
+            };
+            let entries = store.get_audit_entries(query).await?;
+            Ok::<Json, Rejection>(warp::reply::json(&entries))
+        });
+
+    // GET /blocklist
+    // Response: The quarantined accounts
+    let blocklist_clone = blocklist.clone();
+    let get_blocklist = warp::get()
+        .and(warp::path("blocklist"))
+        .and(warp::path::end())
+        .and(admin_only.clone())
+        .map(move || warp::reply::json(&blocklist_clone.entries()));
+
+    // PUT /blocklist/:username
+    // Body: { "duration": <milliseconds> } (optional)
+    // Quarantines the account, so that its packets and connections are refused
+    let blocklist_clone = blocklist.clone();
+    let put_blocklist_entry = warp::put()
+        .and(warp::path("blocklist"))
+        .and(warp::path::param::<Username>())
+        .and(warp::path::end())
+        .and(audited_admin_only.clone())
+        .and(warp::body::json())
+        .and(with_store.clone())
+        .and_then(
+            move |username: Username, call: AdminCall, request: QuarantineRequest, store: S| {
+                let blocklist = blocklist_clone.clone();
+                async move {
+                    let entry = blocklist
+                        .quarantine(&username, request.duration.map(Duration::from_millis));
+                    call.record(&store, &request).await;
+                    Ok::<Json, Rejection>(warp::reply::json(&entry))
+                }
+            },
+        );
+
+    // DELETE /blocklist/:username
+    // Ends the quarantine of the account and forgets its misbehavior
+    let blocklist_clone = blocklist.clone();
+    let delete_blocklist_entry = warp::delete()
+        .and(warp::path("blocklist"))
+        .and(warp::path::param::<Username>())
+        .and(warp::path::end())
+        .and(audited_admin_only.clone())
+        .and(with_store.clone())
+        .and_then(move |username: Username, call: AdminCall, store: S| {
+            let blocklist = blocklist_clone.clone();
+            async move {
+                let entry = blocklist.release(&username).ok_or_else(|| {
+                    Rejection::from(ApiError::not_found().detail("account is not quarantined"))
+                })?;
+                call.record(&store, &()).await;
+                Ok::<Json, Rejection>(warp::reply::json(&entry))
+            }
+        });
+
+    // DELETE /blocklist/addresses/:address
+    // Ends the quarantine of the remote address and forgets its misbehavior
+    let delete_blocklist_address = warp::delete()
+        .and(warp::path("blocklist"))
+        .and(warp::path("addresses"))
+        .and(warp::path::param::<IpAddr>())
+        .and(warp::path::end())
+        .and(audited_admin_only.clone())
+        .and(with_store.clone())
+        .and_then(move |address: IpAddr, call: AdminCall, store: S| {
+            let blocklist = blocklist.clone();
+            async move {
+                let entry = blocklist.release_address(address).ok_or_else(|| {
+                    Rejection::from(ApiError::not_found().detail("address is not quarantined"))
+                })?;
+                call.record(&store, &()).await;
+                Ok::<Json, Rejection>(warp::reply::json(&entry))
+            }
+        });
+
+    // GET /unreachable-destinations
+    // Response: The destinations which recently rejected payments as unreachable
+    let get_unreachable_destinations = warp::get()
+        .and(warp::path("unreachable-destinations"))
+        .and(warp::path::end())
+        .and(admin_only.clone())
+        .and(with_store.clone())
+        .and_then(|store: S| async move {
+            let destinations = store.get_unreachable_destinations().await?;
+            Ok::<Json, Rejection>(warp::reply::json(&destinations))
+        });
+
+    // DELETE /unreachable-destinations
+    // Lets the payments to all of the destinations be attempted again
+    let delete_unreachable_destinations = warp::delete()
+        .and(warp::path("unreachable-destinations"))
+        .and(warp::path::end())
+        .and(audited_admin_only.clone())
+        .and(with_store.clone())
+        .and_then(|call: AdminCall, store: S| async move {
+            let cleared = store.clear_unreachable_destinations(None).await?;
+            call.record(&store, &()).await;
+            Ok::<Json, Rejection>(warp::reply::json(&json!({ "cleared": cleared })))
+        });
+
+    // DELETE /unreachable-destinations/:prefix
+    // Lets the payments to the destination be attempted again
+    let delete_unreachable_destination = warp::delete()
+        .and(warp::path("unreachable-destinations"))
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(audited_admin_only.clone())
+        .and(with_store.clone())
+        .and_then(|prefix: String, call: AdminCall, store: S| async move {
+            let cleared = store.clear_unreachable_destinations(Some(&prefix)).await?;
+            if cleared == 0 {
+                return Err(Rejection::from(
+                    ApiError::not_found().detail("destination is not unreachable"),
+                ));
+            }
+            call.record(&store, &()).await;
+            Ok::<Json, Rejection>(warp::reply::json(&json!({ "cleared": cleared })))
+        });
+
+    // GET /settlements/reconciliation
+    // Response: The totals settled with the accounts according to the node and to their
+    // settlement engines, as of the latest reconciliation
+    let get_settlement_reconciliation = warp::get()
+        .and(warp::path("settlements"))
+        .and(warp::path("reconciliation"))
+        .and(warp::path::end())
+        .and(admin_only.clone())
+        .map(move || warp::reply::json(&settlement_reconciliation.report()));
+
+    // GET /tasks
+    // Response: The periodic tasks of the node, with their last and next runs
+    let scheduler_clone = scheduler.clone();
+    let get_tasks = warp::get()
+        .and(warp::path("tasks"))
+        .and(warp::path::end())
+        .and(admin_only.clone())
+        .map(move || warp::reply::json(&scheduler_clone.statuses()));
+
+    // POST /tasks/:name/run
+    // Runs the task right away (or as soon as its current run completes)
+    let post_task_run = warp::post()
+        .and(warp::path("tasks"))
+        .and(warp::path::param::<String>())
+        .and(warp::path("run"))
+        .and(warp::path::end())
+        .and(audited_admin_only)
+        .and(with_store)
+        .and_then(move |name: String, call: AdminCall, store: S| {
+            let scheduler = scheduler.clone();
+            async move {
+                let status = scheduler
+                    .trigger(&name)
+                    .ok_or_else(|| Rejection::from(ApiError::not_found().detail("no such task")))?;
+                call.record(&store, &()).await;
+                Ok::<Json, Rejection>(warp::reply::json(&status))
+            }
+        });
+
     get_root
         .or(put_rates)
         .or(get_rates)
+        .or(get_rates_convert)
+        .or(put_rate_pin)
+        .or(delete_rate_pin)
+        .or(get_rate_pins)
+        .or(put_fee_policies)
+        .or(get_fee_policies)
+        .or(get_quote)
         .or(get_routes)
+        .or(get_routes_export)
+        .or(put_routes_import)
         .or(put_static_routes)
         .or(put_static_route)
+        .or(get_settlement_engines)
         .or(put_settlement_engines)
+        .or(delete_settlement_engine)
+        .or(get_runtime_settings)
+        .or(put_runtime_settings)
+        .or(post_balances_snapshot)
+        .or(get_balances_verify)
+        .or(post_balance_recover)
+        .or(get_stats_overview)
+        .or(get_stats_conversions)
+        .or(get_audit_log)
+        .or(get_blocklist)
+        .or(put_blocklist_entry)
+        .or(delete_blocklist_entry)
+        .or(delete_blocklist_address)
+        .or(get_unreachable_destinations)
+        .or(delete_unreachable_destinations)
+        .or(delete_unreachable_destination)
+        .or(get_settlement_reconciliation)
+        .or(get_tasks)
+        .or(post_task_run)
+}
+
+/// Returns the scale of the amounts of the asset, which is the largest scale of the registered
+/// asset unless it is set
+async fn asset_scale<S: AssetStore>(
+    store: &S,
+    code: &str,
+    scale: Option<u8>,
+) -> Result<u8, Rejection> {
+    if let Some(scale) = scale {
+        return Ok(scale);
+    }
+    let asset = store.get_asset(code).await?.ok_or_else(|| {
+        Rejection::from(ApiError::bad_request().detail(format!(
+            "the scale of {} must be set since it is not a registered asset",
+            code
+        )))
+    })?;
+    Ok(asset.max_scale)
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::routes::test_helpers::{api_call, test_node_settings_api};
+    use crate::routes::test_helpers::{
+        api_call, test_node_settings_api, test_node_settings_api_with_blocklist,
+    };
+    use interledger_service::{BlocklistConfig, Misbehavior, PeerBlocklist};
     use serde_json::{json, Value};
+    use std::net::IpAddr;
 
     #[tokio::test]
     async fn gets_status() {
         let api = test_node_settings_api();
         let resp = api_call(&api, "GET", "/", "", None).await;
         assert_eq!(resp.status().as_u16(), 200);
+        let mut status: Value = serde_json::from_slice(resp.body()).unwrap();
+        assert!(status["uptime"].as_u64().unwrap() >= 90);
+        status.as_object_mut().unwrap().remove("uptime");
         assert_eq!(
-            resp.body(),
-            &b"{\"status\":\"Ready\",\"ilp_address\":\"example.connector\"}"[..]
+            status,
+            json!({
+                "status": "Ready",
+                "ilp_address": "example.connector",
+                "version": "1.2.3",
+                "features": ["btp", "http"],
+                "api_versions": ["v1"],
+            })
         );
     }
 
@@ -257,6 +1065,121 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn only_admin_can_manage_fee_policies() {
+        let api = test_node_settings_api();
+        let resp = api_call(&api, "GET", "/fees", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        assert_eq!(
+            serde_json::from_slice::<Value>(resp.body()).unwrap(),
+            json!({"ABC/XYZ": {"spread": 0.01}})
+        );
+        let resp = api_call(&api, "GET", "/fees", "wrong", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+
+        let policies = json!({"ABC/XYZ": {"spread": 0.01}});
+        let resp = api_call(&api, "PUT", "/fees", "admin", Some(policies.clone())).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let resp = api_call(&api, "PUT", "/fees", "wrong", Some(policies)).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
+    #[tokio::test]
+    async fn quotes_conversions() {
+        let api = test_node_settings_api();
+        let resp = api_call(
+            &api,
+            "GET",
+            "/quote?from=alice&to=alice&amount=1000",
+            "admin",
+            None,
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let quote = serde_json::from_slice::<Value>(resp.body()).unwrap();
+        assert_eq!(quote["destination_amount"], json!(1000));
+        assert_eq!(quote["exchange_rate"], json!(1.0));
+        assert_eq!(quote["fee"], json!(0.0));
+
+        let resp = api_call(
+            &api,
+            "GET",
+            "/quote?from=alice&to=alice&amount=1000",
+            "wrong",
+            None,
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
+    #[tokio::test]
+    async fn previews_conversions() {
+        let api = test_node_settings_api();
+        // XYZ is registered with a max scale of 9
+        let resp = api_call(
+            &api,
+            "GET",
+            "/rates/convert?from=ABC&to=XYZ&amount=1000&from_scale=6",
+            "admin",
+            None,
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 200);
+        assert_eq!(
+            serde_json::from_slice::<Value>(resp.body()).unwrap(),
+            json!({
+                "source_amount": 1000,
+                "source_asset_code": "ABC",
+                "source_asset_scale": 6,
+                "destination_amount": 500_000,
+                "destination_asset_code": "XYZ",
+                "destination_asset_scale": 9,
+                "market_rate": 0.5,
+                "market_amount": 500_000.0,
+                "exchange_rate": 0.5,
+                "converted_amount": 500_000.0,
+                "fee": 0.0,
+                "fee_policy": {},
+            })
+        );
+
+        let resp = api_call(
+            &api,
+            "GET",
+            "/rates/convert?from=ABC&to=XYZ&amount=1&from_scale=9",
+            "admin",
+            None,
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let conversion = serde_json::from_slice::<Value>(resp.body()).unwrap();
+        assert_eq!(conversion["destination_amount"], Value::Null);
+        assert_eq!(
+            conversion["rejection"],
+            "R01: amount too small to be converted"
+        );
+
+        // ABC is not registered
+        let resp = api_call(
+            &api,
+            "GET",
+            "/rates/convert?from=ABC&to=XYZ&amount=1000",
+            "admin",
+            None,
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 400);
+        let resp = api_call(
+            &api,
+            "GET",
+            "/rates/convert?from=XYZ&to=XYZ&amount=1000",
+            "wrong",
+            None,
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
     #[tokio::test]
     async fn gets_routes() {
         let api = test_node_settings_api();
@@ -264,6 +1187,62 @@ mod tests {
         assert_eq!(resp.status().as_u16(), 200);
     }
 
+    #[tokio::test]
+    async fn exports_routes_with_their_source() {
+        let api = test_node_settings_api();
+        let resp = api_call(&api, "GET", "/routes/export", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        assert_eq!(resp.headers()["content-type"], "application/json");
+        assert_eq!(
+            serde_json::from_slice::<Value>(resp.body()).unwrap(),
+            json!([
+                {"prefix": "example.alice", "next_hop": "alice", "source": "local"},
+                {"prefix": "example.eu", "next_hop": "alice", "source": "static"},
+                {"prefix": "g.us", "next_hop": "alice", "source": "learned"},
+            ])
+        );
+
+        let resp = api_call(&api, "GET", "/routes/export?format=csv", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        assert_eq!(resp.headers()["content-type"], "text/csv");
+        assert_eq!(
+            resp.body(),
+            "prefix,next_hop,source\nexample.alice,alice,local\nexample.eu,alice,static\ng.us,alice,learned\n"
+        );
+        let resp = api_call(&api, "GET", "/routes/export", "wrong", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
+    #[tokio::test]
+    async fn only_admin_can_import_routes() {
+        let api = test_node_settings_api();
+        let import = |auth: &'static str, format: &'static str, body: &'static str| {
+            warp::test::request()
+                .method("PUT")
+                .path(&format!("/routes/import?format={}", format))
+                .header("Authorization", format!("Bearer {}", auth))
+                .body(body)
+                .reply(&api)
+        };
+        let csv = "prefix,next_hop,source\nexample.eu,bob,static\ng.us,bob,learned\n";
+        let resp = import("admin", "csv", csv).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        assert_eq!(
+            serde_json::from_slice::<Value>(resp.body()).unwrap(),
+            json!({"imported": 1, "skipped": 1})
+        );
+        let resp = import("wrong", "csv", csv).await;
+        assert_eq!(resp.status().as_u16(), 401);
+
+        let json = r#"[{"prefix": "example.eu", "next_hop": "bob", "source": "static"}]"#;
+        let resp = import("admin", "json", json).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let resp = import("admin", "json", csv).await;
+        assert_eq!(resp.status().as_u16(), 400);
+        let resp = import("admin", "csv", "example.eu,bob").await;
+        assert_eq!(resp.status().as_u16(), 400);
+    }
+
     #[tokio::test]
     async fn only_admin_can_put_rates() {
         let api = test_node_settings_api();
@@ -275,6 +1254,41 @@ mod tests {
         assert_eq!(resp.status().as_u16(), 401);
     }
 
+    #[tokio::test]
+    async fn only_admin_can_manage_rate_pins() {
+        let api = test_node_settings_api();
+        let pin = json!({"rate": "1.5"});
+        let resp = api_call(&api, "PUT", "/rates/ABC/pin", "admin", Some(pin.clone())).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        assert_eq!(
+            serde_json::from_slice::<Value>(resp.body()).unwrap(),
+            json!({"ABC": 1.0})
+        );
+        let resp = api_call(&api, "PUT", "/rates/ABC/pin", "wrong", Some(pin)).await;
+        assert_eq!(resp.status().as_u16(), 401);
+        let resp = api_call(
+            &api,
+            "PUT",
+            "/rates/ABC/pin",
+            "admin",
+            Some(json!({"rate": -1.0})),
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 400);
+
+        let resp = api_call(&api, "GET", "/rates/pins", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let resp = api_call(&api, "GET", "/rates/pins", "wrong", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+
+        let resp = api_call(&api, "DELETE", "/rates/ABC/pin", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let resp = api_call(&api, "DELETE", "/rates/XYZ/pin", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 404);
+        let resp = api_call(&api, "DELETE", "/rates/ABC/pin", "wrong", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
     #[tokio::test]
     async fn only_admin_can_put_static_routes() {
         let api = test_node_settings_api();
@@ -326,4 +1340,276 @@ mod tests {
         let resp = api_call(&api, "PUT", "/settlement/engines", "wrong", Some(engines)).await;
         assert_eq!(resp.status().as_u16(), 401);
     }
+
+    #[tokio::test]
+    async fn only_admin_can_get_and_delete_engines() {
+        let api = test_node_settings_api();
+        let resp = api_call(&api, "GET", "/settlement/engines", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        assert_eq!(
+            serde_json::from_slice::<Value>(resp.body()).unwrap(),
+            json!({"ABC": "http://settlement.example/"})
+        );
+        let resp = api_call(&api, "GET", "/settlement/engines", "wrong", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+
+        let resp = api_call(&api, "DELETE", "/settlement/engines/ABC", "wrong", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+        let resp = api_call(&api, "DELETE", "/settlement/engines/ABC", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        assert_eq!(
+            serde_json::from_slice::<Value>(resp.body()).unwrap(),
+            json!({"ABC": "http://settlement.example/"})
+        );
+        let resp = api_call(&api, "DELETE", "/settlement/engines/XYZ", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 404);
+    }
+
+    #[tokio::test]
+    async fn only_admin_can_get_stats_overview() {
+        let api = test_node_settings_api();
+        let resp = api_call(&api, "GET", "/stats/overview", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let overview: Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(overview["accounts"], 2);
+        assert_eq!(
+            overview["packets"],
+            json!({"prepare": 0, "fulfill": 0, "reject": 0})
+        );
+        assert_eq!(overview["top_destinations"], json!([]));
+
+        let resp = api_call(&api, "GET", "/stats/overview", "wrong", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
+    #[tokio::test]
+    async fn only_admin_can_get_stats_conversions() {
+        let api = test_node_settings_api();
+        let resp = api_call(&api, "GET", "/stats/conversions", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let overview: Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(overview["pairs"], json!([]));
+
+        let resp = api_call(&api, "GET", "/stats/conversions", "wrong", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
+    #[tokio::test]
+    async fn only_admin_can_snapshot_balances() {
+        let api = test_node_settings_api();
+        let resp = api_call(&api, "POST", "/balances/snapshot", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        assert_eq!(
+            serde_json::from_slice::<Value>(resp.body()).unwrap(),
+            json!({"accounts": 1})
+        );
+
+        let resp = api_call(&api, "POST", "/balances/snapshot", "wrong", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
+    #[tokio::test]
+    async fn only_admin_can_verify_balances() {
+        let api = test_node_settings_api();
+        let resp = api_call(&api, "GET", "/balances/verify", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let verifications: Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(verifications.as_array().unwrap().len(), 1);
+
+        let resp = api_call(&api, "GET", "/balances/verify", "wrong", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
+    #[tokio::test]
+    async fn only_admin_can_recover_balance() {
+        let api = test_node_settings_api();
+        let resp = api_call(&api, "POST", "/balances/alice/recover", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+
+        let resp = api_call(&api, "POST", "/balances/alice/recover", "wrong", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
+    #[tokio::test]
+    async fn only_admin_can_get_audit_log() {
+        let api = test_node_settings_api();
+        let resp = api_call(&api, "GET", "/audit?since=0&limit=10", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        assert_eq!(
+            serde_json::from_slice::<Value>(resp.body()).unwrap(),
+            json!([{
+                "id": 1,
+                "timestamp": 1_600_000_000_000u64,
+                "actor": "admin",
+                "method": "PUT",
+                "path": "/rates",
+                "request": {"ABC": 1.0},
+            }])
+        );
+
+        let resp = api_call(&api, "GET", "/audit", "wrong", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
+    #[tokio::test]
+    async fn only_admin_can_list_and_trigger_tasks() {
+        let api = test_node_settings_api();
+        let resp = api_call(&api, "GET", "/tasks", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        assert_eq!(
+            serde_json::from_slice::<Value>(resp.body()).unwrap(),
+            json!([])
+        );
+        let resp = api_call(&api, "POST", "/tasks/route_broadcast/run", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 404);
+
+        let resp = api_call(&api, "GET", "/tasks", "wrong", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+        let resp = api_call(&api, "POST", "/tasks/route_broadcast/run", "wrong", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
+    #[tokio::test]
+    async fn only_admin_can_get_settlement_reconciliation() {
+        let api = test_node_settings_api();
+        let resp = api_call(&api, "GET", "/settlements/reconciliation", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        assert_eq!(
+            serde_json::from_slice::<Value>(resp.body()).unwrap(),
+            json!({"reconciled_at": null, "accounts": []})
+        );
+
+        let resp = api_call(&api, "GET", "/settlements/reconciliation", "wrong", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
+    #[tokio::test]
+    async fn only_admin_can_clear_unreachable_destinations() {
+        let api = test_node_settings_api();
+        let resp = api_call(&api, "GET", "/unreachable-destinations", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        assert_eq!(
+            serde_json::from_slice::<Value>(resp.body()).unwrap(),
+            json!([{
+                "destination_prefix": "example.carol",
+                "failures": 3,
+                "error": "F02 no route found",
+                "expires_in": 60000,
+            }])
+        );
+        let resp = api_call(&api, "GET", "/unreachable-destinations", "wrong", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+
+        let path = "/unreachable-destinations/example.carol";
+        let resp = api_call(&api, "DELETE", path, "wrong", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+        let resp = api_call(&api, "DELETE", path, "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        assert_eq!(
+            serde_json::from_slice::<Value>(resp.body()).unwrap(),
+            json!({ "cleared": 1 })
+        );
+        let path = "/unreachable-destinations/example.dave";
+        let resp = api_call(&api, "DELETE", path, "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 404);
+        let resp = api_call(&api, "DELETE", "/unreachable-destinations", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+    }
+
+    #[tokio::test]
+    async fn only_admin_can_override_the_blocklist() {
+        let api = test_node_settings_api();
+        let resp = api_call(
+            &api,
+            "PUT",
+            "/blocklist/alice",
+            "admin",
+            Some(json!({ "duration": 60000 })),
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let entry: Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(entry["username"], "alice");
+        assert_eq!(entry["reason"], "manual");
+
+        let resp = api_call(&api, "GET", "/blocklist", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let entries: Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(entries.as_array().unwrap().len(), 1);
+
+        let resp = api_call(&api, "DELETE", "/blocklist/alice", "wrong", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+        let resp = api_call(&api, "DELETE", "/blocklist/alice", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let resp = api_call(&api, "DELETE", "/blocklist/alice", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 404);
+
+        let resp = api_call(&api, "GET", "/blocklist", "wrong", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
+    #[tokio::test]
+    async fn only_admin_can_release_quarantined_addresses() {
+        let blocklist = PeerBlocklist::new(BlocklistConfig {
+            max_auth_failures: Some(1),
+            ..BlocklistConfig::default()
+        });
+        let address: IpAddr = [192, 0, 2, 1].into();
+        blocklist.record_address(Some(address), Misbehavior::AuthFailure);
+        let api = test_node_settings_api_with_blocklist(blocklist.clone());
+
+        let resp = api_call(&api, "GET", "/blocklist", "admin", None).await;
+        let entries: Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(entries[0]["address"], "192.0.2.1");
+        assert_eq!(entries[0]["reason"], "auth_failure");
+        assert!(entries[0].get("username").is_none());
+
+        let path = "/blocklist/addresses/192.0.2.1";
+        let resp = api_call(&api, "DELETE", path, "wrong", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+        let resp = api_call(&api, "DELETE", path, "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        assert!(!blocklist.is_address_quarantined(Some(address)));
+        let resp = api_call(&api, "DELETE", path, "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 404);
+    }
+
+    #[tokio::test]
+    async fn only_admin_can_get_runtime_settings() {
+        let api = test_node_settings_api();
+        let resp = api_call(&api, "GET", "/settings", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        assert_eq!(
+            serde_json::from_slice::<Value>(resp.body()).unwrap(),
+            json!({"route_broadcast_interval": 5000})
+        );
+
+        let resp = api_call(&api, "GET", "/settings", "wrong", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
+    #[tokio::test]
+    async fn only_admin_can_put_runtime_settings() {
+        let api = test_node_settings_api();
+        let settings = json!({"exchange_rate_poll_interval": 1000, "max_packet_amount": "500"});
+        let resp = api_call(&api, "PUT", "/settings", "admin", Some(settings.clone())).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        assert_eq!(
+            serde_json::from_slice::<Value>(resp.body()).unwrap(),
+            json!({"exchange_rate_poll_interval": 1000, "max_packet_amount": 500})
+        );
+
+        let resp = api_call(&api, "PUT", "/settings", "wrong", Some(settings)).await;
+        assert_eq!(resp.status().as_u16(), 401);
+
+        let resp = api_call(
+            &api,
+            "PUT",
+            "/settings",
+            "admin",
+            Some(json!({"route_broadcast_interval": 0})),
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 400);
+    }
 }
@@ -0,0 +1,204 @@
+use crate::audit::{admin_call, AdminCall, AuditLogStore};
+use crate::jwt::{admin_only, JwtAuth};
+use crate::payment_pointers::{PaymentPointer, PaymentPointerStore};
+use crate::receipts::ReceiptVerifierStore;
+use crate::routes::spsp_response;
+use bytes::Bytes;
+use interledger_errors::*;
+use interledger_http::deserialize_json;
+use interledger_service::{Account, AccountStore};
+use interledger_spsp::SpspResponder;
+use interledger_stream::ReceiptGenerator;
+use serde::Deserialize;
+use tracing::debug;
+use warp::{
+    self,
+    hyper::{Body, Response},
+    reply::Json,
+    Filter, Rejection,
+};
+
+#[derive(Deserialize)]
+struct PaymentPointerQuery {
+    path: String,
+}
+
+/// Returns the SPSP response of the payment pointer, with a new STREAM connection
+/// to the receiver of its account
+pub(crate) async fn payment_pointer_response<S, A>(
+    pointer: &PaymentPointer,
+    server_secret: Bytes,
+    receipts: Option<&ReceiptGenerator>,
+    store: &S,
+) -> Result<Response<Body>, Rejection>
+where
+    S: AccountStore<Account = A> + ReceiptVerifierStore,
+    A: Account,
+{
+    let id = store
+        .get_account_id_from_username(&pointer.username)
+        .await?;
+    let account = store
+        .get_accounts(vec![id])
+        .await?
+        .pop()
+        .ok_or_else(ApiError::account_not_found)?;
+    let mut responder = SpspResponder::new(account.ilp_address().clone(), server_secret);
+    if let Some(ref tag) = pointer.destination_tag {
+        responder = responder.with_destination_tag(tag).map_err(|err| {
+            ApiError::internal_server_error().detail(format!(
+                "the destination tag of payment pointer {} is invalid: {}",
+                pointer.path, err
+            ))
+        })?;
+    }
+    spsp_response(&responder, &pointer.path, receipts, store).await
+}
+
+pub fn payment_pointers_api<S, A>(
+    admin_api_token: String,
+    jwt_auth: Option<JwtAuth>,
+    store: S,
+) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone
+where
+    S: AccountStore<Account = A>
+        + PaymentPointerStore
+        + ReceiptVerifierStore
+        + AuditLogStore
+        + Clone
+        + Send
+        + Sync
+        + 'static,
+    A: Account + Send + Sync + 'static,
+{
+    // Helper filters
+    let admin_auth_header = format!("Bearer {}", admin_api_token);
+    let audited_admin_only = admin_call(admin_auth_header, jwt_auth.clone());
+    let admin_only = admin_only(&admin_api_token, jwt_auth.clone());
+    let with_store = warp::any().map(move || store.clone());
+
+    // GET /payment-pointers
+    // Response: The payment pointers hosted by the node
+    let get_payment_pointers = warp::get()
+        .and(warp::path("payment-pointers"))
+        .and(warp::path::end())
+        .and(admin_only)
+        .and(with_store.clone())
+        .and_then(|store: S| async move {
+            let pointers = store.get_payment_pointers().await?;
+            Ok::<Json, Rejection>(warp::reply::json(&pointers))
+        });
+
+    // PUT /payment-pointers
+    // Body: { "path": "/donate", "username": "alice", "destination_tag": "donations" }
+    // Adds the payment pointer, or replaces the one with the same path
+    let put_payment_pointer = warp::put()
+        .and(warp::path("payment-pointers"))
+        .and(warp::path::end())
+        .and(audited_admin_only.clone())
+        .and(deserialize_json())
+        .and(with_store.clone())
+        .and_then(
+            |call: AdminCall, pointer: PaymentPointer, store: S| async move {
+                pointer.validate()?;
+                // Fails if the payment pointer would resolve to a missing account
+                store
+                    .get_account_id_from_username(&pointer.username)
+                    .await?;
+                store.set_payment_pointer(pointer.clone()).await?;
+                call.record(&store, &pointer).await;
+                debug!("Set payment pointer {:?}", pointer);
+                Ok::<Json, Rejection>(warp::reply::json(&pointer))
+            },
+        );
+
+    // DELETE /payment-pointers?path=/donate
+    let delete_payment_pointer = warp::delete()
+        .and(warp::path("payment-pointers"))
+        .and(warp::path::end())
+        .and(audited_admin_only)
+        .and(warp::query::<PaymentPointerQuery>())
+        .and(with_store)
+        .and_then(
+            |call: AdminCall, query: PaymentPointerQuery, store: S| async move {
+                let pointer = store
+                    .delete_payment_pointer(&query.path)
+                    .await?
+                    .ok_or_else(|| {
+                        Rejection::from(ApiError::not_found().detail("payment pointer not found"))
+                    })?;
+                call.record(&store, &()).await;
+                Ok::<Json, Rejection>(warp::reply::json(&pointer))
+            },
+        );
+
+    get_payment_pointers
+        .or(put_payment_pointer)
+        .or(delete_payment_pointer)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::routes::test_helpers::{api_call, test_payment_pointers_api};
+    use serde_json::{json, Value};
+
+    #[tokio::test]
+    async fn only_admin_can_manage_payment_pointers() {
+        let pointer = json!({
+            "path": "/invoices/123",
+            "username": "alice",
+            "destination_tag": "invoice-123",
+        });
+        let api = test_payment_pointers_api();
+        let resp = api_call(
+            &api,
+            "PUT",
+            "/payment-pointers",
+            "admin",
+            Some(pointer.clone()),
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 200);
+        assert_eq!(
+            serde_json::from_slice::<Value>(resp.body()).unwrap(),
+            pointer
+        );
+
+        let resp = api_call(
+            &api,
+            "PUT",
+            "/payment-pointers",
+            "admin",
+            Some(json!({ "path": "invoices", "username": "alice" })),
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 400);
+
+        let resp = api_call(&api, "PUT", "/payment-pointers", "wrong", Some(pointer)).await;
+        assert_eq!(resp.status().as_u16(), 401);
+
+        let resp = api_call(&api, "GET", "/payment-pointers", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let resp = api_call(&api, "GET", "/payment-pointers", "wrong", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+
+        let resp = api_call(
+            &api,
+            "DELETE",
+            "/payment-pointers?path=/donate",
+            "admin",
+            None,
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let resp = api_call(
+            &api,
+            "DELETE",
+            "/payment-pointers?path=/other",
+            "admin",
+            None,
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 404);
+    }
+}
@@ -0,0 +1,802 @@
+use crate::assets::{check_account_asset, AssetStore};
+use crate::audit::{admin_call, redact, AdminCall, AuditLogStore};
+use crate::jwt::{admin_only, JwtAuth};
+use crate::peering::{
+    PeeringApproval, PeeringProposal, PeeringRequest, PeeringRequestBody, PeeringStore,
+    PeeringTerms, MAX_PENDING_PEERING_REQUESTS,
+};
+use crate::routes::{connect_to_external_services, RequestLimiter};
+use crate::{AccountDetails, NodeStore};
+use interledger_btp::{BtpAccount, BtpOutgoingService};
+use interledger_ccp::CcpRoutingAccount;
+use interledger_errors::*;
+use interledger_http::deserialize_json;
+use interledger_service::{
+    Account, AccountStore, AddressStore, HttpDialect, OutgoingService, Username,
+};
+use interledger_service_util::BalanceStore;
+use interledger_settlement::core::{types::SettlementAccount, SettlementClient};
+use reqwest::Client;
+use ring::rand::{SecureRandom, SystemRandom};
+use secrecy::{ExposeSecret, SecretString};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{debug, warn};
+use url::Url;
+use warp::{self, reply::Json, Filter, Rejection};
+
+/// Number of peering requests which other nodes can send per minute, in total
+const PEERING_REQUESTS_PER_MINUTE: u32 = 10;
+
+/// Returns the current time, in milliseconds since the UNIX epoch
+pub(super) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Returns a random and URL-safe id or token
+pub(super) fn generate_random_string() -> Result<String, ApiError> {
+    let mut bytes = [0; 18];
+    SystemRandom::new().fill(&mut bytes).map_err(|_| {
+        ApiError::internal_server_error().detail("could not generate a random token")
+    })?;
+    Ok(base64::encode_config(&bytes, base64::URL_SAFE_NO_PAD))
+}
+
+/// Returns the public URL of the node, without which it cannot peer automatically
+fn require_public_url(public_url: &Option<Url>) -> Result<Url, ApiError> {
+    public_url
+        .clone()
+        .ok_or_else(|| ApiError::not_found().detail("peering requests are not enabled"))
+}
+
+/// Returns the URL of the node at the path, relative to its public URL
+fn node_url(public_url: &Url, path: &str) -> String {
+    format!("{}{}", public_url.as_str().trim_end_matches('/'), path)
+}
+
+/// Returns the value serialized with its tokens redacted, so that listing the requests
+/// does not leak the credentials of the peers
+fn redacted<T: Serialize>(value: &T) -> Value {
+    let mut value = serde_json::to_value(value).unwrap_or(Value::Null);
+    redact(&mut value);
+    value
+}
+
+/// Returns the peering requests waiting for approval, after deleting the expired ones
+async fn pending_peering_requests<S: PeeringStore>(
+    store: &S,
+) -> Result<Vec<PeeringRequest>, NodeStoreError> {
+    let now = now();
+    let (expired, pending): (Vec<_>, Vec<_>) = store
+        .get_peering_requests()
+        .await?
+        .into_iter()
+        .partition(|request| request.is_expired(now));
+    for request in expired {
+        debug!("Peering request {} expired", request.id);
+        store.delete_peering_request(&request.id).await?;
+    }
+    Ok(pending)
+}
+
+/// Fails if the node already has too many requests waiting for approval, or one for the
+/// same username, so that the requests which require no auth cannot pile up
+fn check_pending_requests(
+    pending: &[PeeringRequest],
+    body: &PeeringRequestBody,
+) -> Result<(), ApiError> {
+    if pending.len() >= MAX_PENDING_PEERING_REQUESTS {
+        return Err(ApiError::too_many_requests()
+            .detail("too many peering requests are waiting for approval"));
+    }
+    if pending
+        .iter()
+        .any(|request| request.username == body.username)
+    {
+        return Err(ApiError::conflict().detail(format!(
+            "a peering request for the username {} is already waiting for approval",
+            body.username
+        )));
+    }
+    Ok(())
+}
+
+/// Returns the details of an account of a peer which exchanges packets over HTTP
+fn peer_account_details(
+    username: Username,
+    asset_code: String,
+    asset_scale: u8,
+    ilp_over_http_url: String,
+    outgoing_token: String,
+    incoming_token: String,
+) -> AccountDetails {
+    AccountDetails {
+        ilp_address: None,
+        username,
+        asset_code,
+        asset_scale,
+        max_packet_amount: u64::MAX,
+        max_packet_size: None,
+        min_balance: None,
+        payable_limit: None,
+        receivable_limit: None,
+        ilp_over_http_url: Some(ilp_over_http_url),
+        ilp_over_http_incoming_token: Some(SecretString::new(incoming_token)),
+        ilp_over_http_outgoing_token: Some(SecretString::new(outgoing_token)),
+        ilp_over_http_dialect: HttpDialect::Rfc,
+        ilp_over_http_signing_key: None,
+        ilp_over_http_verification_key: None,
+        ilp_over_btp_url: None,
+        ilp_over_btp_outgoing_token: None,
+        ilp_over_btp_incoming_token: None,
+        ilp_over_btp_legacy: false,
+        transport_account: None,
+        settle_threshold: None,
+        settle_to: None,
+        routing_relation: Some("Peer".to_string()),
+        round_trip_time: None,
+        route_broadcast_interval: None,
+        route_expiry: None,
+        route_full_table: false,
+        amount_per_minute_limit: None,
+        packets_per_second_limit: None,
+        packets_per_minute_limit: None,
+        settlement_engine_url: None,
+        liquidity_notification_url: None,
+        balance_notification_url: None,
+        spread: None,
+        percentage_fee: None,
+        fixed_fee: None,
+        packet_filters: Vec::new(),
+        ip_allowlist: Vec::new(),
+    }
+}
+
+/// Fails if there already is an account with the username
+pub(super) async fn check_username_is_free<S: AccountStore>(
+    store: &S,
+    username: &Username,
+) -> Result<(), ApiError> {
+    if store.get_account_id_from_username(username).await.is_ok() {
+        return Err(ApiError::conflict().detail(format!(
+            "there already is an account with the username {}",
+            username
+        )));
+    }
+    Ok(())
+}
+
+pub fn peering_api<O, S, A, B>(
+    admin_api_token: String,
+    jwt_auth: Option<JwtAuth>,
+    public_url: Option<Url>,
+    outgoing_handler: O,
+    btp: BtpOutgoingService<B, A>,
+    settlement_client: SettlementClient,
+    store: S,
+) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone
+where
+    O: OutgoingService<A> + Clone + Send + Sync + 'static,
+    B: OutgoingService<A> + Clone + Send + Sync + 'static,
+    S: NodeStore<Account = A>
+        + AccountStore<Account = A>
+        + AddressStore
+        + BalanceStore
+        + PeeringStore
+        + AuditLogStore
+        + AssetStore
+        + Clone
+        + Send
+        + Sync
+        + 'static,
+    A: BtpAccount
+        + CcpRoutingAccount
+        + SettlementAccount
+        + Account
+        + Serialize
+        + Clone
+        + Send
+        + Sync
+        + 'static,
+{
+    // Helper filters
+    let admin_auth_header = format!("Bearer {}", admin_api_token);
+    let audited_admin_only = admin_call(admin_auth_header, jwt_auth.clone());
+    let admin_only = admin_only(&admin_api_token, jwt_auth.clone());
+    let with_store = warp::any().map(move || store.clone());
+    let with_public_url = warp::any().map(move || public_url.clone());
+    let with_client = warp::any().map(Client::new);
+    let with_external_services = warp::any().map(move || {
+        (
+            outgoing_handler.clone(),
+            btp.clone(),
+            settlement_client.clone(),
+        )
+    });
+    let request_limiter = RequestLimiter::new(PEERING_REQUESTS_PER_MINUTE, Duration::from_secs(60));
+
+    // POST /peering/requests
+    // Body: { "username": "bob", "asset_code": "XRP", "asset_scale": 9, "ilp_over_http_url": "...",
+    //         "ilp_over_http_token": "...", "callback_url": "...", "callback_token": "..." }
+    // Sent by the node which requests to peer, so it requires no auth, but it is rate
+    // limited. The request is saved until the admin approves or rejects it, or it expires.
+    let post_peering_request = warp::post()
+        .and(warp::path("peering"))
+        .and(warp::path("requests"))
+        .and(warp::path::end())
+        .and(request_limiter.filter())
+        .and(deserialize_json())
+        .and(with_public_url.clone())
+        .and(with_store.clone())
+        .and_then(
+            |body: PeeringRequestBody, public_url: Option<Url>, store: S| async move {
+                require_public_url(&public_url)?;
+                body.validate()?;
+                check_username_is_free(&store, &body.username).await?;
+                check_account_asset(&store, &body.asset_code, body.asset_scale).await?;
+                check_pending_requests(&pending_peering_requests(&store).await?, &body)?;
+                let request = PeeringRequest {
+                    id: generate_random_string()?,
+                    username: body.username,
+                    asset_code: body.asset_code,
+                    asset_scale: body.asset_scale,
+                    ilp_over_http_url: body.ilp_over_http_url,
+                    ilp_over_http_token: body.ilp_over_http_token,
+                    callback_url: body.callback_url,
+                    callback_token: body.callback_token,
+                    received_at: now(),
+                };
+                store.save_peering_request(request.clone()).await?;
+                debug!(
+                    "Received peering request {} for account {}",
+                    request.id, request.username
+                );
+                Ok::<Json, Rejection>(warp::reply::json(&json!({ "id": request.id })))
+            },
+        );
+
+    // GET /peering/requests
+    // Response: The requests waiting for approval, with their tokens redacted
+    let get_peering_requests = warp::get()
+        .and(warp::path("peering"))
+        .and(warp::path("requests"))
+        .and(warp::path::end())
+        .and(admin_only.clone())
+        .and(with_store.clone())
+        .and_then(|store: S| async move {
+            let requests = pending_peering_requests(&store).await?;
+            Ok::<Json, Rejection>(warp::reply::json(&redacted(&requests)))
+        });
+
+    // POST /peering/requests/:id/approve
+    // Creates the requester's account and sends it the parameters of the account, so
+    // that it creates this node's account in turn
+    let approve_peering_request = warp::post()
+        .and(warp::path("peering"))
+        .and(warp::path("requests"))
+        .and(warp::path::param::<String>())
+        .and(warp::path("approve"))
+        .and(warp::path::end())
+        .and(audited_admin_only.clone())
+        .and(with_public_url.clone())
+        .and(with_client)
+        .and(with_external_services.clone())
+        .and(with_store.clone())
+        .and_then(
+            |id: String,
+             call: AdminCall,
+             public_url: Option<Url>,
+             client: Client,
+             (outgoing_handler, btp, settlement_client): (O, BtpOutgoingService<B, A>, SettlementClient),
+             store: S| async move {
+                let public_url = require_public_url(&public_url)?;
+                let request = store
+                    .delete_peering_request(&id)
+                    .await?
+                    .filter(|request| !request.is_expired(now()))
+                    .ok_or_else(|| ApiError::not_found().detail("peering request not found"))?;
+                let incoming_token = generate_random_string()?;
+                let details = peer_account_details(
+                    request.username.clone(),
+                    request.asset_code.clone(),
+                    request.asset_scale,
+                    request.ilp_over_http_url.clone(),
+                    request.ilp_over_http_token.clone(),
+                    incoming_token.clone(),
+                );
+                let account = store.insert_account(details.clone()).await?;
+                connect_to_external_services(
+                    outgoing_handler,
+                    account.clone(),
+                    store.clone(),
+                    btp.clone(),
+                    settlement_client,
+                )
+                .await?;
+
+                let approval = PeeringApproval {
+                    ilp_over_http_url: node_url(
+                        &public_url,
+                        &format!("/accounts/{}/ilp", request.username),
+                    ),
+                    ilp_over_http_token: incoming_token,
+                };
+                let result = client
+                    .post(request.callback_url.as_str())
+                    .bearer_auth(&request.callback_token)
+                    .json(&approval)
+                    .send()
+                    .await
+                    .and_then(|response| response.error_for_status());
+                if let Err(err) = result {
+                    // The requester does not have the account's token without the approval,
+                    // so the account is deleted and the request kept for the admin to retry
+                    store.delete_account(account.id()).await?;
+                    btp.close_connection(&account.id());
+                    store.save_peering_request(request.clone()).await?;
+                    return Err(Rejection::from(ApiError::internal_server_error().detail(
+                        format!(
+                            "could not send the approval to the requester, so account {} was not created: {}",
+                            request.username, err
+                        ),
+                    )));
+                }
+                call.record(&store, &details).await;
+                debug!(
+                    "Approved peering request {} and created account {}",
+                    id, request.username
+                );
+                Ok::<Json, Rejection>(warp::reply::json(&account))
+            },
+        );
+
+    // DELETE /peering/requests/:id
+    // Rejects the request, and lets the requester know
+    let reject_peering_request = warp::delete()
+        .and(warp::path("peering"))
+        .and(warp::path("requests"))
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(audited_admin_only.clone())
+        .and(with_client)
+        .and(with_store.clone())
+        .and_then(
+            |id: String, call: AdminCall, client: Client, store: S| async move {
+                let request = store
+                    .delete_peering_request(&id)
+                    .await?
+                    .ok_or_else(|| ApiError::not_found().detail("peering request not found"))?;
+                call.record(&store, &()).await;
+                let result = client
+                    .delete(request.callback_url.as_str())
+                    .bearer_auth(&request.callback_token)
+                    .send()
+                    .await
+                    .and_then(|response| response.error_for_status());
+                if let Err(err) = result {
+                    warn!(
+                        "Error letting the requester of peering request {} know it was rejected: {}",
+                        id, err
+                    );
+                }
+                Ok::<Json, Rejection>(warp::reply::json(&redacted(&request)))
+            },
+        );
+
+    // POST /peering/proposals
+    // Body: { "peer_url": "https://peer.example.com", "username": "alice", "asset_code": "XRP", "asset_scale": 9 }
+    // Sends a peering request to the peer's node. Once its admin approves it, the account
+    // of the peer is created on this node.
+    let post_peering_proposal = warp::post()
+        .and(warp::path("peering"))
+        .and(warp::path("proposals"))
+        .and(warp::path::end())
+        .and(audited_admin_only.clone())
+        .and(deserialize_json())
+        .and(with_public_url.clone())
+        .and(with_client)
+        .and(with_store.clone())
+        .and_then(
+            |call: AdminCall,
+             terms: PeeringTerms,
+             public_url: Option<Url>,
+             client: Client,
+             store: S| async move {
+                let public_url = require_public_url(&public_url)?;
+                check_username_is_free(&store, &terms.username).await?;
+                check_account_asset(&store, &terms.asset_code, terms.asset_scale).await?;
+                let peer_username = match terms.peer_username {
+                    Some(ref username) => username.clone(),
+                    None => {
+                        let address = store.get_ilp_address();
+                        let segment = address.segments().last().unwrap_or_default();
+                        Username::from_str(segment).map_err(|_| {
+                            ApiError::bad_request().detail(format!(
+                                "{} is not a valid username, the peer_username must be set",
+                                segment
+                            ))
+                        })?
+                    }
+                };
+                let proposal = PeeringProposal {
+                    id: generate_random_string()?,
+                    peer_url: terms.peer_url.clone(),
+                    username: terms.username.clone(),
+                    asset_code: terms.asset_code.clone(),
+                    asset_scale: terms.asset_scale,
+                    ilp_over_http_incoming_token: generate_random_string()?,
+                    callback_token: generate_random_string()?,
+                    sent_at: now(),
+                };
+                let body = PeeringRequestBody {
+                    username: peer_username,
+                    asset_code: proposal.asset_code.clone(),
+                    asset_scale: proposal.asset_scale,
+                    ilp_over_http_url: node_url(
+                        &public_url,
+                        &format!("/accounts/{}/ilp", proposal.username),
+                    ),
+                    ilp_over_http_token: proposal.ilp_over_http_incoming_token.clone(),
+                    callback_url: Url::parse(&node_url(
+                        &public_url,
+                        &format!("/peering/proposals/{}/callback", proposal.id),
+                    ))
+                    .map_err(|err| {
+                        ApiError::internal_server_error()
+                            .detail(format!("invalid callback URL: {}", err))
+                    })?,
+                    callback_token: proposal.callback_token.clone(),
+                };
+                // Saved first, so that the callback finds it even if the peer's admin
+                // approves the request right away
+                store.save_peering_proposal(proposal.clone()).await?;
+                let result = client
+                    .post(&node_url(&proposal.peer_url, "/peering/requests"))
+                    .json(&body)
+                    .send()
+                    .await
+                    .and_then(|response| response.error_for_status());
+                if let Err(err) = result {
+                    store.delete_peering_proposal(&proposal.id).await?;
+                    return Err(Rejection::from(ApiError::internal_server_error().detail(
+                        format!("could not send the peering request to the peer: {}", err),
+                    )));
+                }
+                call.record(&store, &terms).await;
+                debug!(
+                    "Sent peering request {} to {}",
+                    proposal.id, proposal.peer_url
+                );
+                Ok::<Json, Rejection>(warp::reply::json(&redacted(&proposal)))
+            },
+        );
+
+    // GET /peering/proposals
+    // Response: The requests sent to other nodes which were not answered yet
+    let get_peering_proposals = warp::get()
+        .and(warp::path("peering"))
+        .and(warp::path("proposals"))
+        .and(warp::path::end())
+        .and(admin_only)
+        .and(with_store.clone())
+        .and_then(|store: S| async move {
+            let proposals = store.get_peering_proposals().await?;
+            Ok::<Json, Rejection>(warp::reply::json(&redacted(&proposals)))
+        });
+
+    // DELETE /peering/proposals/:id
+    // Forgets a request sent to another node, whose approval is then refused
+    let delete_peering_proposal = warp::delete()
+        .and(warp::path("peering"))
+        .and(warp::path("proposals"))
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(audited_admin_only)
+        .and(with_store.clone())
+        .and_then(|id: String, call: AdminCall, store: S| async move {
+            let proposal = store
+                .delete_peering_proposal(&id)
+                .await?
+                .ok_or_else(|| ApiError::not_found().detail("peering proposal not found"))?;
+            call.record(&store, &()).await;
+            Ok::<Json, Rejection>(warp::reply::json(&redacted(&proposal)))
+        });
+
+    // Looks up the proposal of the callback, which is authorized with its callback token
+    let proposal_callback = warp::path("peering")
+        .and(warp::path("proposals"))
+        .and(warp::path::param::<String>())
+        .and(warp::path("callback"))
+        .and(warp::path::end())
+        .and(warp::header::<SecretString>("authorization"))
+        .and(with_store)
+        .and_then(
+            |id: String, authorization: SecretString, store: S| async move {
+                let proposal = store
+                    .get_peering_proposal(&id)
+                    .await?
+                    .ok_or_else(|| ApiError::not_found().detail("peering proposal not found"))?;
+                let token = authorization
+                    .expose_secret()
+                    .strip_prefix("Bearer ")
+                    .unwrap_or_default();
+                if !store.verify_peering_callback_token(&proposal, token) {
+                    return Err(Rejection::from(
+                        ApiError::unauthorized().detail("invalid callback token provided"),
+                    ));
+                }
+                Ok::<_, Rejection>((proposal, store))
+            },
+        )
+        .untuple_one();
+
+    // POST /peering/proposals/:id/callback
+    // Body: { "ilp_over_http_url": "...", "ilp_over_http_token": "..." }
+    // Sent by the peer once its admin approved the request: creates the peer's account
+    let approve_peering_proposal = warp::post()
+        .and(proposal_callback.clone())
+        .and(deserialize_json())
+        .and(with_external_services)
+        .and_then(
+            |proposal: PeeringProposal,
+             store: S,
+             approval: PeeringApproval,
+             (outgoing_handler, btp, settlement_client): (
+                O,
+                BtpOutgoingService<B, A>,
+                SettlementClient,
+            )| async move {
+                store.delete_peering_proposal(&proposal.id).await?;
+                let details = peer_account_details(
+                    proposal.username.clone(),
+                    proposal.asset_code,
+                    proposal.asset_scale,
+                    approval.ilp_over_http_url,
+                    approval.ilp_over_http_token,
+                    proposal.ilp_over_http_incoming_token,
+                );
+                let account = store.insert_account(details).await?;
+                connect_to_external_services(
+                    outgoing_handler,
+                    account,
+                    store,
+                    btp,
+                    settlement_client,
+                )
+                .await?;
+                debug!(
+                    "Peering request {} was approved, created account {}",
+                    proposal.id, proposal.username
+                );
+                Ok::<_, Rejection>(warp::reply())
+            },
+        );
+
+    // DELETE /peering/proposals/:id/callback
+    // Sent by the peer if its admin rejected the request
+    let reject_peering_proposal = warp::delete().and(proposal_callback).and_then(
+        |proposal: PeeringProposal, store: S| async move {
+            store.delete_peering_proposal(&proposal.id).await?;
+            debug!("Peering request {} was rejected", proposal.id);
+            Ok::<_, Rejection>(warp::reply())
+        },
+    );
+
+    post_peering_request
+        .or(get_peering_requests)
+        .or(approve_peering_request)
+        .or(reject_peering_request)
+        .or(post_peering_proposal)
+        .or(get_peering_proposals)
+        .or(delete_peering_proposal)
+        .or(approve_peering_proposal)
+        .or(reject_peering_proposal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::peering::PEERING_REQUEST_TTL;
+    use crate::routes::test_helpers::{
+        api_call, test_peering_api, ACCOUNT_ID, DELETED_ACCOUNTS, PEERING_REQUEST,
+        SAVED_PEERING_REQUESTS,
+    };
+
+    fn request_body(username: &str) -> Value {
+        json!({
+            "username": username,
+            "asset_code": "XYZ",
+            "asset_scale": 9,
+            "ilp_over_http_url": "http://carol.example/accounts/alice/ilp",
+            "ilp_over_http_token": "carol_token",
+            "callback_url": "http://carol.example/peering/proposals/1/callback",
+            "callback_token": "callback",
+        })
+    }
+
+    #[tokio::test]
+    async fn only_admin_can_list_and_answer_peering_requests() {
+        let api = test_peering_api(true);
+        let resp = api_call(&api, "GET", "/peering/requests", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let requests: Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(requests[0]["username"], "carol");
+        assert_eq!(requests[0]["ilp_over_http_token"], "[redacted]");
+        assert_eq!(requests[0]["callback_token"], "[redacted]");
+        let resp = api_call(&api, "GET", "/peering/requests", "wrong", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+
+        let resp = api_call(
+            &api,
+            "POST",
+            "/peering/requests/request/approve",
+            "wrong",
+            None,
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 401);
+        let resp = api_call(
+            &api,
+            "POST",
+            "/peering/requests/other/approve",
+            "admin",
+            None,
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 404);
+        let resp = api_call(&api, "DELETE", "/peering/requests/request", "wrong", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+        let resp = api_call(&api, "DELETE", "/peering/requests/other", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 404);
+
+        let resp = api_call(&api, "GET", "/peering/proposals", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let proposals: Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(proposals[0]["ilp_over_http_incoming_token"], "[redacted]");
+        let resp = api_call(&api, "GET", "/peering/proposals", "wrong", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+        let resp = api_call(&api, "DELETE", "/peering/proposals/proposal", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+    }
+
+    #[tokio::test]
+    async fn validates_peering_requests() {
+        let api = test_peering_api(true);
+        let mut invalid = request_body("carol");
+        invalid["ilp_over_http_url"] = json!("not a url");
+        let resp = api_call(&api, "POST", "/peering/requests", "", Some(invalid)).await;
+        assert_eq!(resp.status().as_u16(), 400);
+        // All the usernames are taken in the test store
+        let resp = api_call(
+            &api,
+            "POST",
+            "/peering/requests",
+            "",
+            Some(request_body("carol")),
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 409);
+
+        let api = test_peering_api(false);
+        let resp = api_call(
+            &api,
+            "POST",
+            "/peering/requests",
+            "",
+            Some(request_body("carol")),
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 404);
+    }
+
+    #[tokio::test]
+    async fn authenticates_peering_callbacks() {
+        let api = test_peering_api(true);
+        let path = "/peering/proposals/proposal/callback";
+        let resp = api_call(&api, "DELETE", path, "wrong", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+        let resp = api_call(&api, "DELETE", path, "callback", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let resp = api_call(
+            &api,
+            "DELETE",
+            "/peering/proposals/other/callback",
+            "callback",
+            None,
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 404);
+    }
+
+    #[tokio::test]
+    async fn rate_limits_peering_requests() {
+        let api = test_peering_api(true);
+        for _ in 0..PEERING_REQUESTS_PER_MINUTE {
+            let resp = api_call(
+                &api,
+                "POST",
+                "/peering/requests",
+                "",
+                Some(request_body("carol")),
+            )
+            .await;
+            assert_eq!(resp.status().as_u16(), 409);
+        }
+        let resp = api_call(
+            &api,
+            "POST",
+            "/peering/requests",
+            "",
+            Some(request_body("carol")),
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 429);
+        // The admin's calls are not limited
+        let resp = api_call(&api, "GET", "/peering/requests", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+    }
+
+    #[test]
+    fn bounds_the_pending_peering_requests() {
+        let body: PeeringRequestBody =
+            serde_json::from_str(&request_body("dave").to_string()).unwrap();
+        let pending = |count: usize| -> Vec<PeeringRequest> {
+            (0..count)
+                .map(|i| PeeringRequest {
+                    id: i.to_string(),
+                    username: Username::from_str(&format!("user{}", i)).unwrap(),
+                    ..PEERING_REQUEST.clone()
+                })
+                .collect()
+        };
+        assert!(check_pending_requests(&pending(MAX_PENDING_PEERING_REQUESTS - 1), &body).is_ok());
+        let err =
+            check_pending_requests(&pending(MAX_PENDING_PEERING_REQUESTS), &body).unwrap_err();
+        assert_eq!(err.status.as_u16(), 429);
+
+        let mut requests = pending(1);
+        requests[0].username = body.username.clone();
+        let err = check_pending_requests(&requests, &body).unwrap_err();
+        assert_eq!(err.status.as_u16(), 409);
+    }
+
+    #[test]
+    fn expires_peering_requests() {
+        let request = PeeringRequest {
+            received_at: 1000,
+            ..PEERING_REQUEST.clone()
+        };
+        assert!(!request.is_expired(1000 + PEERING_REQUEST_TTL - 1));
+        assert!(request.is_expired(1000 + PEERING_REQUEST_TTL));
+        let request = PeeringRequest {
+            received_at: u64::MAX,
+            ..PEERING_REQUEST.clone()
+        };
+        assert!(!request.is_expired(u64::MAX - 1));
+    }
+
+    #[tokio::test]
+    async fn deletes_the_account_if_the_approval_cannot_be_sent() {
+        let api = test_peering_api(true);
+        let resp = api_call(
+            &api,
+            "POST",
+            "/peering/requests/request/approve",
+            "admin",
+            None,
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 500);
+        assert!(DELETED_ACCOUNTS.lock().unwrap().contains(&*ACCOUNT_ID));
+        // The request is kept, so that the admin can approve it again
+        assert!(SAVED_PEERING_REQUESTS
+            .lock()
+            .unwrap()
+            .contains(&"request".to_string()));
+    }
+}
@@ -0,0 +1,347 @@
+use super::peering::{check_username_is_free, generate_random_string, now};
+use crate::assets::{check_account_asset, AssetStore};
+use crate::provisioning::{ChallengeIssuer, ChildProvisioning, ProvisioningRequest};
+use crate::routes::{connect_to_external_services, RequestLimiter};
+use crate::{AccountDetails, NodeStore};
+use bytes::Bytes;
+use interledger_btp::{BtpAccount, BtpOutgoingService};
+use interledger_ccp::CcpRoutingAccount;
+use interledger_errors::*;
+use interledger_http::deserialize_json;
+use interledger_service::{
+    Account, AccountStore, AddressStore, HttpDialect, OutgoingService, Username,
+};
+use interledger_service_util::BalanceStore;
+use interledger_settlement::core::{types::SettlementAccount, SettlementClient};
+use secrecy::{ExposeSecret, SecretString};
+use serde_json::json;
+use std::time::Duration;
+use tracing::debug;
+use warp::{self, reply::Json, Filter, Rejection};
+
+/// Returns the details of the account of a child provisioned with the token
+fn child_account_details(
+    provisioning: &ChildProvisioning,
+    username: Username,
+    token: &str,
+) -> AccountDetails {
+    AccountDetails {
+        ilp_address: None,
+        username,
+        asset_code: provisioning.asset_code.clone(),
+        asset_scale: provisioning.asset_scale,
+        max_packet_amount: provisioning.max_packet_amount,
+        max_packet_size: None,
+        // The child cannot owe the node anything until it prefunds its account
+        min_balance: Some(0),
+        payable_limit: None,
+        receivable_limit: None,
+        ilp_over_http_url: None,
+        ilp_over_http_incoming_token: Some(SecretString::new(token.to_string())),
+        ilp_over_http_outgoing_token: None,
+        ilp_over_http_dialect: HttpDialect::Rfc,
+        ilp_over_http_signing_key: None,
+        ilp_over_http_verification_key: None,
+        ilp_over_btp_url: None,
+        ilp_over_btp_outgoing_token: None,
+        ilp_over_btp_incoming_token: Some(SecretString::new(token.to_string())),
+        ilp_over_btp_legacy: false,
+        transport_account: None,
+        settle_threshold: None,
+        settle_to: None,
+        routing_relation: Some("Child".to_string()),
+        round_trip_time: None,
+        route_broadcast_interval: None,
+        route_expiry: None,
+        route_full_table: false,
+        amount_per_minute_limit: provisioning.amount_per_minute_limit,
+        packets_per_second_limit: None,
+        packets_per_minute_limit: provisioning.packets_per_minute_limit,
+        settlement_engine_url: None,
+        liquidity_notification_url: None,
+        balance_notification_url: None,
+        spread: None,
+        percentage_fee: None,
+        fixed_fee: None,
+        packet_filters: Vec::new(),
+        ip_allowlist: Vec::new(),
+    }
+}
+
+/// Fails unless the authorization is the provisioning token, if one is configured
+fn check_provisioning_token(
+    provisioning: &ChildProvisioning,
+    authorization: Option<&SecretString>,
+) -> Result<(), ApiError> {
+    let token = match provisioning.token {
+        Some(ref token) => token,
+        None => return Ok(()),
+    };
+    let expected = format!("Bearer {}", token.expose_secret());
+    match authorization {
+        Some(authorization)
+            if ring::constant_time::verify_slices_are_equal(
+                authorization.expose_secret().as_bytes(),
+                expected.as_bytes(),
+            )
+            .is_ok() =>
+        {
+            Ok(())
+        }
+        _ => Err(ApiError::unauthorized().detail("invalid provisioning token provided")),
+    }
+}
+
+pub fn provisioning_api<O, S, A, B>(
+    provisioning: Option<ChildProvisioning>,
+    server_secret: Bytes,
+    outgoing_handler: O,
+    btp: BtpOutgoingService<B, A>,
+    settlement_client: SettlementClient,
+    store: S,
+) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone
+where
+    O: OutgoingService<A> + Clone + Send + Sync + 'static,
+    B: OutgoingService<A> + Clone + Send + Sync + 'static,
+    S: NodeStore<Account = A>
+        + AccountStore<Account = A>
+        + AddressStore
+        + BalanceStore
+        + AssetStore
+        + Clone
+        + Send
+        + Sync
+        + 'static,
+    A: BtpAccount + CcpRoutingAccount + SettlementAccount + Account + Clone + Send + Sync + 'static,
+{
+    let issuer = ChallengeIssuer::new(&server_secret);
+    let request_limiter = RequestLimiter::new(
+        provisioning
+            .as_ref()
+            .map(|provisioning| provisioning.accounts_per_hour)
+            .unwrap_or_default(),
+        Duration::from_secs(3600),
+    );
+    // The routes are only found if the provisioning of child accounts is enabled
+    let with_provisioning = warp::any()
+        .and_then(move || {
+            let provisioning = provisioning.clone();
+            async move {
+                provisioning.ok_or_else(|| {
+                    Rejection::from(
+                        ApiError::not_found().detail("child account provisioning is not enabled"),
+                    )
+                })
+            }
+        })
+        .map(move |provisioning| (provisioning, issuer.clone()));
+    let with_store = warp::any().map(move || store.clone());
+    let with_external_services = warp::any().map(move || {
+        (
+            outgoing_handler.clone(),
+            btp.clone(),
+            settlement_client.clone(),
+        )
+    });
+
+    // POST /provisioning/challenges
+    // Response: { "challenge": "...", "expires_at": 1600000000000 }
+    // The prospective child signs the challenge with its key to create its account
+    let post_challenge = warp::post()
+        .and(warp::path("provisioning"))
+        .and(warp::path("challenges"))
+        .and(warp::path::end())
+        .and(with_provisioning.clone())
+        .and_then(
+            |(provisioning, issuer): (ChildProvisioning, ChallengeIssuer)| async move {
+                let challenge = issuer.issue(
+                    &generate_random_string()?,
+                    now().saturating_add(provisioning.challenge_ttl),
+                );
+                Ok::<Json, Rejection>(warp::reply::json(&challenge))
+            },
+        );
+
+    // POST /provisioning/accounts
+    // Body: { "public_key": "...", "challenge": "...", "signature": "..." }
+    // Creates the account of the child which signed the challenge with the Ed25519 key,
+    // under a username derived from the key. The response holds the token of the account,
+    // with which the child sends packets and uses the API. It requires the provisioning
+    // token if one is configured, and is rate limited.
+    let post_account = warp::post()
+        .and(warp::path("provisioning"))
+        .and(warp::path("accounts"))
+        .and(warp::path::end())
+        .and(with_provisioning)
+        .and(warp::header::optional::<SecretString>("authorization"))
+        .and_then(
+            |(provisioning, issuer): (ChildProvisioning, ChallengeIssuer),
+             authorization: Option<SecretString>| async move {
+                check_provisioning_token(&provisioning, authorization.as_ref())?;
+                Ok::<_, Rejection>((provisioning, issuer))
+            },
+        )
+        .and(request_limiter.filter())
+        .and(deserialize_json())
+        .and(with_external_services)
+        .and(with_store)
+        .and_then(
+            |(provisioning, issuer): (ChildProvisioning, ChallengeIssuer),
+             request: ProvisioningRequest,
+             (outgoing_handler, btp, settlement_client): (
+                O,
+                BtpOutgoingService<B, A>,
+                SettlementClient,
+            ),
+             store: S| async move {
+                issuer.check(&request.challenge, now())?;
+                let public_key = request.verify()?;
+                let username = provisioning.username(&public_key)?;
+                check_username_is_free(&store, &username).await?;
+                check_account_asset(&store, &provisioning.asset_code, provisioning.asset_scale)
+                    .await?;
+                let token = generate_random_string()?;
+                let details = child_account_details(&provisioning, username.clone(), &token);
+                let account = store.insert_account(details).await?;
+                let account = connect_to_external_services(
+                    outgoing_handler,
+                    account,
+                    store,
+                    btp,
+                    settlement_client,
+                )
+                .await?;
+                debug!(
+                    "Provisioned child account {} with address {}",
+                    username,
+                    account.ilp_address()
+                );
+                Ok::<Json, Rejection>(warp::reply::json(&json!({
+                    "username": username,
+                    "ilp_address": account.ilp_address(),
+                    "asset_code": account.asset_code(),
+                    "asset_scale": account.asset_scale(),
+                    "token": token,
+                })))
+            },
+        );
+
+    post_challenge.or(post_account)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::routes::test_helpers::{
+        api_call, test_provisioning_api, test_provisioning_api_with_token,
+    };
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+    use serde_json::{json, Value};
+
+    fn signed(key_pair: &Ed25519KeyPair, challenge: &str) -> Value {
+        json!({
+            "public_key": base64::encode_config(key_pair.public_key().as_ref(), base64::URL_SAFE_NO_PAD),
+            "challenge": challenge,
+            "signature": base64::encode_config(key_pair.sign(challenge.as_bytes()).as_ref(), base64::URL_SAFE_NO_PAD),
+        })
+    }
+
+    #[tokio::test]
+    async fn provisions_children_which_sign_a_challenge() {
+        let api = test_provisioning_api(true);
+        let resp = api_call(&api, "POST", "/provisioning/challenges", "", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let challenge: Value = serde_json::from_slice(resp.body()).unwrap();
+        let challenge = challenge["challenge"].as_str().unwrap();
+
+        let key_pair = Ed25519KeyPair::from_seed_unchecked(&[7; 32]).unwrap();
+        let other_key_pair = Ed25519KeyPair::from_seed_unchecked(&[8; 32]).unwrap();
+        let mut wrong_signature = signed(&key_pair, challenge);
+        wrong_signature["signature"] = signed(&other_key_pair, challenge)["signature"].clone();
+        let resp = api_call(
+            &api,
+            "POST",
+            "/provisioning/accounts",
+            "",
+            Some(wrong_signature),
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 401);
+        let resp = api_call(
+            &api,
+            "POST",
+            "/provisioning/accounts",
+            "",
+            Some(signed(&key_pair, "forged.challenge")),
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 401);
+        // All the usernames are taken in the test store
+        let resp = api_call(
+            &api,
+            "POST",
+            "/provisioning/accounts",
+            "",
+            Some(signed(&key_pair, challenge)),
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 409);
+    }
+
+    #[tokio::test]
+    async fn is_disabled_unless_configured() {
+        let api = test_provisioning_api(false);
+        let resp = api_call(&api, "POST", "/provisioning/challenges", "", None).await;
+        assert_eq!(resp.status().as_u16(), 404);
+    }
+
+    #[tokio::test]
+    async fn requires_the_provisioning_token_if_configured() {
+        let api = test_provisioning_api_with_token(true, Some("provisioning"));
+        let resp = api_call(&api, "POST", "/provisioning/challenges", "", None).await;
+        let challenge: Value = serde_json::from_slice(resp.body()).unwrap();
+        let key_pair = Ed25519KeyPair::from_seed_unchecked(&[7; 32]).unwrap();
+        let body = signed(&key_pair, challenge["challenge"].as_str().unwrap());
+        for token in &["", "wrong"] {
+            let resp = api_call(
+                &api,
+                "POST",
+                "/provisioning/accounts",
+                token,
+                Some(body.clone()),
+            )
+            .await;
+            assert_eq!(resp.status().as_u16(), 401);
+        }
+        // All the usernames are taken in the test store
+        let resp = api_call(
+            &api,
+            "POST",
+            "/provisioning/accounts",
+            "provisioning",
+            Some(body),
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 409);
+    }
+
+    #[tokio::test]
+    async fn rate_limits_the_provisioning_of_accounts() {
+        // The test API allows 3 accounts per hour
+        let api = test_provisioning_api(true);
+        let key_pair = Ed25519KeyPair::from_seed_unchecked(&[7; 32]).unwrap();
+        let body = signed(&key_pair, "forged.challenge");
+        for _ in 0..3 {
+            let resp = api_call(
+                &api,
+                "POST",
+                "/provisioning/accounts",
+                "",
+                Some(body.clone()),
+            )
+            .await;
+            assert_eq!(resp.status().as_u16(), 401);
+        }
+        let resp = api_call(&api, "POST", "/provisioning/accounts", "", Some(body)).await;
+        assert_eq!(resp.status().as_u16(), 429);
+    }
+}
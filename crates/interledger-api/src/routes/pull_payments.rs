@@ -0,0 +1,335 @@
+use crate::audit::{admin_call, AdminCall, AuditLogStore};
+use crate::jwt::{admin_only, JwtAuth};
+use crate::pull_payments::{PullPaymentStore, PullPointer, PullPointerTerms};
+use crate::routes::get_default_max_slippage;
+use futures::TryFutureExt;
+use interledger_errors::*;
+use interledger_http::deserialize_json;
+use interledger_rates::ExchangeRateStore;
+use interledger_service::{Account, AccountStore, IncomingService, Username};
+use interledger_spsp::{PullRequest, PullResponse, PullTerms};
+use interledger_stream::{send_money, MaxPacketAmountStore, UnreachableDestinationStore};
+use ring::rand::{SecureRandom, SystemRandom};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{debug, error};
+use warp::{self, reply::Json, Filter, Rejection};
+
+/// Pulls which exceed the amount available under the terms of the pull pointer
+const PULL_NOT_ALLOWED_TYPE: ApiErrorType = ApiErrorType {
+    r#type: &ProblemType::Default,
+    status: http::StatusCode::FORBIDDEN,
+    title: "Pull not allowed",
+};
+
+/// Returns the current time, in seconds since the UNIX epoch
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_secs()
+}
+
+/// Returns a random and URL-safe id of a pull pointer
+fn generate_pull_pointer_id() -> Result<String, ApiError> {
+    let mut id = [0; 18];
+    SystemRandom::new()
+        .fill(&mut id)
+        .map_err(|_| ApiError::internal_server_error().detail("could not generate a random id"))?;
+    Ok(base64::encode_config(&id, base64::URL_SAFE_NO_PAD))
+}
+
+async fn get_account<S, A>(store: &S, username: &Username) -> Result<A, Rejection>
+where
+    S: AccountStore<Account = A>,
+    A: Account,
+{
+    let id = store.get_account_id_from_username(username).await?;
+    let account = store
+        .get_accounts(vec![id])
+        .await?
+        .pop()
+        .ok_or_else(ApiError::account_not_found)?;
+    Ok(account)
+}
+
+pub fn pull_payments_api<I, S, A>(
+    admin_api_token: String,
+    jwt_auth: Option<JwtAuth>,
+    incoming_handler: I,
+    store: S,
+) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone
+where
+    I: IncomingService<A> + Clone + Send + Sync + 'static,
+    S: AccountStore<Account = A>
+        + PullPaymentStore
+        + ExchangeRateStore
+        + MaxPacketAmountStore
+        + UnreachableDestinationStore
+        + AuditLogStore
+        + Clone
+        + Send
+        + Sync
+        + 'static,
+    A: Account + Send + Sync + 'static,
+{
+    // Helper filters
+    let admin_auth_header = format!("Bearer {}", admin_api_token);
+    let audited_admin_only = admin_call(admin_auth_header, jwt_auth.clone());
+    let admin_only = admin_only(&admin_api_token, jwt_auth.clone());
+    let with_store = warp::any().map(move || store.clone());
+    let with_incoming_handler = warp::any().map(move || incoming_handler.clone());
+    // Looks up the pull pointer of the path, which is not found if it does not exist
+    let pull_pointer = warp::path("pull")
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(with_store.clone())
+        .and_then(|id: String, store: S| async move {
+            let pointer = store
+                .get_pull_pointer(&id)
+                .await?
+                .ok_or_else(|| ApiError::not_found().detail("pull pointer not found"))?;
+            Ok::<_, Rejection>((pointer, store))
+        })
+        .untuple_one();
+
+    // GET /pull-pointers
+    // Response: The pull pointers created by the node's accounts
+    let get_pull_pointers = warp::get()
+        .and(warp::path("pull-pointers"))
+        .and(warp::path::end())
+        .and(admin_only)
+        .and(with_store.clone())
+        .and_then(|store: S| async move {
+            let pointers = store.get_pull_pointers().await?;
+            Ok::<Json, Rejection>(warp::reply::json(&pointers))
+        });
+
+    // POST /pull-pointers
+    // Body: { "username": "alice", "amount": 1000, "interval": 2592000, "cap": 12000 }
+    // Response: The pull pointer, whose path must be shared with the payee
+    let post_pull_pointer = warp::post()
+        .and(warp::path("pull-pointers"))
+        .and(warp::path::end())
+        .and(audited_admin_only.clone())
+        .and(deserialize_json())
+        .and(with_store.clone())
+        .and_then(
+            |call: AdminCall, terms: PullPointerTerms, store: S| async move {
+                terms.validate()?;
+                // Fails if the pulls would be sent from a missing account
+                store.get_account_id_from_username(&terms.username).await?;
+                let pointer = PullPointer {
+                    id: generate_pull_pointer_id()?,
+                    username: terms.username,
+                    amount: terms.amount,
+                    interval: terms.interval,
+                    cap: terms.cap,
+                    start: now(),
+                };
+                store.create_pull_pointer(pointer.clone()).await?;
+                // The id authorizes the pulls, so it is left out of the audit log
+                call.record(&store, &()).await;
+                debug!(
+                    "Created pull pointer {} of {}",
+                    pointer.path(),
+                    pointer.username
+                );
+                Ok::<Json, Rejection>(warp::reply::json(&pointer))
+            },
+        );
+
+    // DELETE /pull-pointers/:id
+    let delete_pull_pointer = warp::delete()
+        .and(warp::path("pull-pointers"))
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(audited_admin_only)
+        .and(with_store.clone())
+        .and_then(|id: String, call: AdminCall, store: S| async move {
+            let pointer = store
+                .delete_pull_pointer(&id)
+                .await?
+                .ok_or_else(|| ApiError::not_found().detail("pull pointer not found"))?;
+            call.record(&store, &()).await;
+            Ok::<Json, Rejection>(warp::reply::json(&pointer))
+        });
+
+    // GET /pull/:id
+    // Response: The terms of the pull pointer and the amounts pulled from it so far
+    // The pull pointers are authorized by their random ids, so they require no auth.
+    let get_pull_terms = warp::get().and(pull_pointer.clone()).and_then(
+        |pointer: PullPointer, store: S| async move {
+            let account = get_account(&store, &pointer.username).await?;
+            let balance = store.get_pull_balance(&pointer, now()).await?;
+            Ok::<Json, Rejection>(warp::reply::json(&PullTerms {
+                amount: pointer.amount,
+                interval: pointer.interval,
+                cap: pointer.cap,
+                asset_code: account.asset_code().to_string(),
+                asset_scale: account.asset_scale(),
+                balance,
+            }))
+        },
+    );
+
+    // POST /pull/:id
+    // Body: { "amount": 100, "destination_account": "...", "shared_secret": "..." }
+    // Sends the amount to the puller's STREAM connection, if the terms of the pull
+    // pointer allow it
+    let post_pull = warp::post()
+        .and(pull_pointer)
+        .and(deserialize_json())
+        .and(with_incoming_handler)
+        .and_then(
+            |pointer: PullPointer, store: S, request: PullRequest, incoming_handler: I| {
+                async move {
+                    if request.amount == 0 || request.shared_secret.len() != 32 {
+                        return Err(Rejection::from(ApiError::bad_request().detail(
+                            "pulls must have a positive amount and a 32-byte shared secret",
+                        )));
+                    }
+                    let account = get_account(&store, &pointer.username).await?;
+                    // The amount is reserved before it is sent, so that concurrent
+                    // pulls cannot exceed the terms together
+                    let reserved_at = now();
+                    store
+                        .reserve_pull(&pointer, request.amount, reserved_at)
+                        .await?
+                        .ok_or_else(|| {
+                            ApiError::from_api_error_type(&PULL_NOT_ALLOWED_TYPE)
+                                .detail("the pull exceeds the amount available under its terms")
+                        })?;
+
+                    debug!(
+                        "Sending pull of {} from {} to: {}",
+                        request.amount, pointer.username, request.destination_account
+                    );
+                    let amount = request.amount;
+                    let delivery = send_money(
+                        incoming_handler,
+                        &account,
+                        store.clone(),
+                        request.destination_account,
+                        request.shared_secret,
+                        amount,
+                        get_default_max_slippage(),
+                    )
+                    .map_err(|err| {
+                        let msg = format!("Error sending pull payment: {}", err);
+                        error!("{}", msg);
+                        ApiError::internal_server_error().detail(msg)
+                    })
+                    .await?;
+
+                    // The part of the amount which the payment did not send can be pulled
+                    // again. A failed payment does not tell how much it sent, and its packets
+                    // in flight may still be fulfilled, so all of its amount stays pulled.
+                    let unsent = amount.saturating_sub(delivery.sent_amount);
+                    let balance = store.release_pull(&pointer, unsent, reserved_at).await?;
+                    debug!("Sent pull payment: {:?}", delivery);
+                    Ok::<Json, Rejection>(warp::reply::json(&PullResponse { delivery, balance }))
+                }
+            },
+        );
+
+    get_pull_pointers
+        .or(post_pull_pointer)
+        .or(delete_pull_pointer)
+        .or(get_pull_terms)
+        .or(post_pull)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::routes::test_helpers::{api_call, test_pull_payments_api, RELEASED_PULLS};
+    use serde_json::{json, Value};
+
+    #[tokio::test]
+    async fn only_admin_can_manage_pull_pointers() {
+        let terms = json!({ "username": "alice", "amount": 100, "interval": 60, "cap": 1000 });
+        let api = test_pull_payments_api();
+        let resp = api_call(&api, "POST", "/pull-pointers", "admin", Some(terms.clone())).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let pointer: Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(pointer["username"], "alice");
+        assert_eq!(pointer["amount"], 100);
+        assert_eq!(pointer["cap"], 1000);
+        assert!(pointer["id"].as_str().unwrap().len() >= 24);
+
+        let resp = api_call(
+            &api,
+            "POST",
+            "/pull-pointers",
+            "admin",
+            Some(json!({ "username": "alice", "amount": 0, "interval": 60 })),
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 400);
+
+        let resp = api_call(&api, "POST", "/pull-pointers", "wrong", Some(terms)).await;
+        assert_eq!(resp.status().as_u16(), 401);
+
+        let resp = api_call(&api, "GET", "/pull-pointers", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let resp = api_call(&api, "GET", "/pull-pointers", "wrong", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+
+        let resp = api_call(&api, "DELETE", "/pull-pointers/subscription", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let resp = api_call(&api, "DELETE", "/pull-pointers/other", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 404);
+    }
+
+    #[tokio::test]
+    async fn serves_the_terms_of_pull_pointers() {
+        let api = test_pull_payments_api();
+        let resp = api_call(&api, "GET", "/pull/subscription", "", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        assert_eq!(
+            serde_json::from_slice::<Value>(resp.body()).unwrap(),
+            json!({
+                "amount": 100,
+                "interval": 60,
+                "cap": 1000,
+                "asset_code": "XYZ",
+                "asset_scale": 9,
+                "balance": { "total": 0, "interval": 0, "available": 100 },
+            })
+        );
+
+        let resp = api_call(&api, "GET", "/pull/other", "", None).await;
+        assert_eq!(resp.status().as_u16(), 404);
+    }
+
+    #[tokio::test]
+    async fn rejects_pulls_exceeding_the_terms() {
+        let api = test_pull_payments_api();
+        let pull = |amount: u64| {
+            json!({
+                "amount": amount,
+                "destination_account": "example.bob.token",
+                "shared_secret": base64::encode(&[0; 32]),
+            })
+        };
+        let resp = api_call(&api, "POST", "/pull/subscription", "", Some(pull(101))).await;
+        assert_eq!(resp.status().as_u16(), 403);
+        let resp = api_call(&api, "POST", "/pull/subscription", "", Some(pull(0))).await;
+        assert_eq!(resp.status().as_u16(), 400);
+        let resp = api_call(&api, "POST", "/pull/other", "", Some(pull(10))).await;
+        assert_eq!(resp.status().as_u16(), 404);
+    }
+
+    #[tokio::test]
+    async fn keeps_the_amount_of_failed_pulls() {
+        let api = test_pull_payments_api();
+        let pull = json!({
+            "amount": 10,
+            "destination_account": "example.bob.token",
+            "shared_secret": base64::encode(&[0; 32]),
+        });
+        // The test API rejects all packets
+        let resp = api_call(&api, "POST", "/pull/subscription", "", Some(pull)).await;
+        assert_eq!(resp.status().as_u16(), 500);
+        assert!(RELEASED_PULLS.lock().unwrap().is_empty());
+    }
+}
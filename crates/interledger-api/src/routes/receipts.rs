@@ -0,0 +1,181 @@
+use crate::jwt::{admin_only, JwtAuth};
+use crate::receipts::ReceiptVerifierStore;
+use bytes::Bytes;
+use interledger_errors::*;
+use interledger_spsp::SpspResponder;
+use interledger_stream::ReceiptGenerator;
+use serde::Deserialize;
+use serde_json::json;
+use tracing::debug;
+use warp::{
+    self,
+    hyper::{Body, Response},
+    reply::Json,
+    Filter, Rejection,
+};
+
+#[derive(Deserialize)]
+struct BalanceQuery {
+    path: String,
+}
+
+/// Returns the SPSP response of a new connection of the responder. If the receipt
+/// verifier is enabled, the receipts of the connection are credited to the payment
+/// pointer served at the path.
+pub(crate) async fn spsp_response<S>(
+    responder: &SpspResponder,
+    path: &str,
+    receipts: Option<&ReceiptGenerator>,
+    store: &S,
+) -> Result<Response<Body>, Rejection>
+where
+    S: ReceiptVerifierStore,
+{
+    let response = responder.generate_spsp_response();
+    if let Some(receipts) = receipts {
+        let nonce = receipts.nonce(response.destination_account());
+        store.save_receipt_nonce(nonce, path.to_string()).await?;
+    }
+    Ok(response.into_http_response())
+}
+
+pub fn receipts_api<S>(
+    receipts: Option<ReceiptGenerator>,
+    admin_api_token: String,
+    jwt_auth: Option<JwtAuth>,
+    store: S,
+) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone
+where
+    S: ReceiptVerifierStore + Clone + Send + Sync + 'static,
+{
+    // Helper filters
+    let enabled = receipts.is_some();
+    let receipt_verifier = warp::any()
+        .and_then(move || async move {
+            if enabled {
+                Ok::<(), Rejection>(())
+            } else {
+                Err(warp::reject::not_found())
+            }
+        })
+        .untuple_one();
+    let admin_only = admin_only(&admin_api_token, jwt_auth.clone());
+    let with_store = warp::any().map(move || store.clone());
+
+    // POST /receipts/verify
+    // Body: A base64-encoded STREAM receipt issued by the node
+    // Response: The amount the receipt credited to its payment pointer, and the
+    // payment pointer's balance
+    // This is called by the websites paid via the payment pointers, to check the
+    // receipts their visitors claim to have paid with, so it requires no auth.
+    let post_verify = warp::post()
+        .and(warp::path("receipts"))
+        .and(warp::path("verify"))
+        .and(warp::path::end())
+        .and(receipt_verifier)
+        .and(warp::body::bytes())
+        .and(with_store.clone())
+        .and_then(move |body: Bytes, store: S| {
+            let receipts = receipts.clone();
+            async move {
+                // The filter above only lets the calls through if the verifier is enabled
+                let receipts = receipts.ok_or_else(warp::reject::not_found)?;
+                let data = base64::decode(String::from_utf8_lossy(&body).trim())
+                    .map_err(|_| ApiError::bad_request().detail("receipt is not valid base64"))?;
+                let receipt = receipts
+                    .verify(&data)
+                    .map_err(|err| ApiError::bad_request().detail(err.to_string()))?;
+                let credit = store.credit_receipt(&receipt).await?.ok_or_else(|| {
+                    ApiError::bad_request()
+                        .detail("receipt was not issued for a payment pointer of this node")
+                })?;
+                debug!("Verified receipt: {:?}", credit);
+                Ok::<Json, Rejection>(warp::reply::json(&credit))
+            }
+        });
+
+    // GET /receipts/balance?path=/donate
+    let get_balance = warp::get()
+        .and(warp::path("receipts"))
+        .and(warp::path("balance"))
+        .and(warp::path::end())
+        .and(receipt_verifier)
+        .and(admin_only)
+        .and(warp::query::<BalanceQuery>())
+        .and(with_store)
+        .and_then(|query: BalanceQuery, store: S| async move {
+            let balance = store.get_payment_pointer_balance(&query.path).await?;
+            Ok::<Json, Rejection>(warp::reply::json(&json!({
+                "path": query.path,
+                "balance": balance,
+            })))
+        });
+
+    post_verify.or(get_balance)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::routes::test_helpers::{api_call, test_receipts_api};
+    use interledger_packet::Address;
+    use interledger_stream::ReceiptGenerator;
+    use serde_json::{json, Value};
+    use std::str::FromStr;
+
+    async fn verify<F>(api: &F, receipt: &str) -> http::Response<bytes::Bytes>
+    where
+        F: warp::Filter + 'static,
+        F::Extract: warp::Reply,
+    {
+        warp::test::request()
+            .method("POST")
+            .path("/receipts/verify")
+            .body(receipt)
+            .reply(api)
+            .await
+    }
+
+    #[tokio::test]
+    async fn verifies_receipts_issued_by_the_node() {
+        let api = test_receipts_api(true);
+        let receipts = ReceiptGenerator::new(&[0; 32]);
+        let nonce = receipts.nonce(&Address::from_str("example.alice.donations.token").unwrap());
+        let receipt = base64::encode(&receipts.issue(nonce, 1, 100));
+
+        let resp = verify(&api, &receipt).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        assert_eq!(
+            serde_json::from_slice::<Value>(resp.body()).unwrap(),
+            json!({ "path": "/donate", "amount": 100, "balance": 100 })
+        );
+
+        // receipts signed by others are rejected
+        let other = ReceiptGenerator::new(&[1; 32]);
+        let resp = verify(&api, &base64::encode(&other.issue(nonce, 1, 100))).await;
+        assert_eq!(resp.status().as_u16(), 400);
+        let resp = verify(&api, "not a receipt").await;
+        assert_eq!(resp.status().as_u16(), 400);
+    }
+
+    #[tokio::test]
+    async fn only_admin_can_get_balances() {
+        let api = test_receipts_api(true);
+        let resp = api_call(&api, "GET", "/receipts/balance?path=/donate", "admin", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        assert_eq!(
+            serde_json::from_slice::<Value>(resp.body()).unwrap(),
+            json!({ "path": "/donate", "balance": 0 })
+        );
+        let resp = api_call(&api, "GET", "/receipts/balance?path=/donate", "wrong", None).await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
+    #[tokio::test]
+    async fn rejects_calls_if_disabled() {
+        let api = test_receipts_api(false);
+        let resp = verify(&api, "AQ==").await;
+        assert!(resp.status().is_client_error());
+        let resp = api_call(&api, "GET", "/receipts/balance?path=/donate", "admin", None).await;
+        assert!(resp.status().is_client_error());
+    }
+}
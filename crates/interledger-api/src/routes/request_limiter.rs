@@ -0,0 +1,148 @@
+use interledger_errors::ApiError;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use warp::{Filter, Rejection};
+
+/// Limits the requests to a number per fixed window of time, either across all the callers
+/// (such as the calls to an endpoint which requires no auth, so that they cannot make the
+/// node store data without bounds) or per key (such as the IP address of each client)
+#[derive(Clone)]
+pub struct RequestLimiter<K = ()> {
+    max_requests: u32,
+    window: Duration,
+    state: Arc<Mutex<RequestWindow<K>>>,
+}
+
+/// Start of the current window (which starts with the first request after the previous
+/// one), and the number of requests made by each key during it
+struct RequestWindow<K> {
+    start: Option<Instant>,
+    requests: HashMap<K, u32>,
+}
+
+impl RequestLimiter {
+    /// Limits the requests of all the callers together
+    pub fn new(max_requests: u32, window: Duration) -> Self {
+        RequestLimiter::per_key(max_requests, window)
+    }
+
+    /// Counts the request, and returns whether it is within the limit
+    fn try_request(&self) -> bool {
+        self.check((), Instant::now()).is_ok()
+    }
+
+    /// Rejects the requests over the limit with a 429 error
+    pub fn filter(&self) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+        let limiter = self.clone();
+        warp::any()
+            .and_then(move || {
+                let allowed = limiter.try_request();
+                async move {
+                    if allowed {
+                        Ok(())
+                    } else {
+                        Err(Rejection::from(
+                            ApiError::too_many_requests()
+                                .detail("too many requests, try again later"),
+                        ))
+                    }
+                }
+            })
+            .untuple_one()
+    }
+}
+
+impl<K: Hash + Eq> RequestLimiter<K> {
+    /// Limits the requests of each key separately
+    pub fn per_key(max_requests: u32, window: Duration) -> Self {
+        RequestLimiter {
+            max_requests,
+            window,
+            state: Arc::new(Mutex::new(RequestWindow {
+                start: None,
+                requests: HashMap::new(),
+            })),
+        }
+    }
+
+    /// Counts a request of the key, or returns how long it has to wait before the next
+    /// one if it already made its requests of the current window
+    pub fn check(&self, key: K, now: Instant) -> Result<(), Duration> {
+        let mut state = self.state.lock().unwrap();
+        let start = match state.start {
+            Some(start) if now.duration_since(start) < self.window => start,
+            _ => {
+                state.start = Some(now);
+                state.requests.clear();
+                now
+            }
+        };
+        let retry_after = self.window - now.duration_since(start);
+        let requests = state.requests.entry(key).or_insert(0);
+        if *requests < self.max_requests {
+            *requests += 1;
+            Ok(())
+        } else {
+            Err(retry_after)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::IpAddr;
+
+    #[test]
+    fn limits_the_requests_per_window() {
+        let limiter = RequestLimiter::new(2, Duration::from_millis(50));
+        assert!(limiter.try_request());
+        assert!(limiter.clone().try_request());
+        assert!(!limiter.try_request());
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(limiter.try_request());
+    }
+
+    #[test]
+    fn starts_a_new_window_once_the_current_one_is_over() {
+        let start = Instant::now();
+        let limiter = RequestLimiter::new(1, Duration::from_secs(60));
+        assert_eq!(limiter.check((), start), Ok(()));
+        assert_eq!(
+            limiter.check((), start + Duration::from_secs(15)),
+            Err(Duration::from_secs(45))
+        );
+        assert_eq!(
+            limiter.check((), start + Duration::from_secs(59)),
+            Err(Duration::from_secs(1))
+        );
+        // The next window starts with the first request after the current one
+        assert_eq!(limiter.check((), start + Duration::from_secs(60)), Ok(()));
+        assert_eq!(
+            limiter.check((), start + Duration::from_secs(90)),
+            Err(Duration::from_secs(30))
+        );
+        assert_eq!(limiter.check((), start + Duration::from_secs(200)), Ok(()));
+    }
+
+    #[test]
+    fn limits_each_key_separately() {
+        let start = Instant::now();
+        let alice: IpAddr = "10.0.0.1".parse().unwrap();
+        let bob: IpAddr = "10.0.0.2".parse().unwrap();
+        let limiter = RequestLimiter::per_key(2, Duration::from_secs(60));
+        assert!(limiter.check(alice, start).is_ok());
+        assert!(limiter.check(alice, start).is_ok());
+        assert!(limiter.check(alice, start).is_err());
+        // The requests of a client do not count against the limit of the others
+        assert!(limiter.check(bob, start).is_ok());
+        assert!(limiter.clone().check(bob, start).is_ok());
+        assert!(limiter.check(bob, start).is_err());
+        // And all of them can send their requests again in the next window
+        let next_window = start + Duration::from_secs(60);
+        assert!(limiter.check(alice, next_window).is_ok());
+        assert!(limiter.check(bob, next_window).is_ok());
+    }
+}
@@ -0,0 +1,174 @@
+//! The formats in which the routing table is exported and imported, so that operators can
+//! review their routes and keep their static routes under version control
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+
+/// The header line of the CSV files
+const CSV_HEADER: &str = "prefix,next_hop,source";
+
+/// Where a route of the routing table comes from
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RouteSource {
+    /// Configured by the operator, the only routes which are imported
+    Static,
+    /// The address of one of the node's accounts
+    Local,
+    /// Broadcast by a peer
+    Learned,
+}
+
+impl RouteSource {
+    fn as_str(self) -> &'static str {
+        match self {
+            RouteSource::Static => "static",
+            RouteSource::Local => "local",
+            RouteSource::Learned => "learned",
+        }
+    }
+}
+
+/// A route of the routing table, with the username of the account it goes through
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RouteEntry {
+    pub prefix: String,
+    pub next_hop: String,
+    pub source: RouteSource,
+}
+
+/// The formats of the exported routes
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RouteFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+#[derive(Deserialize)]
+pub struct RouteFormatQuery {
+    #[serde(default)]
+    pub format: RouteFormat,
+}
+
+impl RouteFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            RouteFormat::Json => "application/json",
+            RouteFormat::Csv => "text/csv",
+        }
+    }
+
+    /// Writes the routes in the format
+    pub fn write(self, routes: &[RouteEntry]) -> String {
+        match self {
+            RouteFormat::Json => serde_json::to_string(routes).unwrap_or_default(),
+            RouteFormat::Csv => {
+                let mut csv = format!("{}\n", CSV_HEADER);
+                for route in routes {
+                    let _ = writeln!(
+                        csv,
+                        "{},{},{}",
+                        route.prefix,
+                        route.next_hop,
+                        route.source.as_str()
+                    );
+                }
+                csv
+            }
+        }
+    }
+
+    /// Reads routes written in the format
+    pub fn read(self, body: &[u8]) -> Result<Vec<RouteEntry>, String> {
+        match self {
+            RouteFormat::Json => serde_json::from_slice(body).map_err(|err| err.to_string()),
+            RouteFormat::Csv => {
+                let body = std::str::from_utf8(body).map_err(|err| err.to_string())?;
+                body.lines()
+                    .enumerate()
+                    .filter(|(_, line)| !line.trim().is_empty() && line.trim() != CSV_HEADER)
+                    .map(|(index, line)| {
+                        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+                        if fields.len() != 3 {
+                            return Err(format!("line {}: expected 3 fields", index + 1));
+                        }
+                        let source = match fields[2] {
+                            "static" => RouteSource::Static,
+                            "local" => RouteSource::Local,
+                            "learned" => RouteSource::Learned,
+                            _ => return Err(format!("line {}: invalid source", index + 1)),
+                        };
+                        Ok(RouteEntry {
+                            prefix: fields[0].to_string(),
+                            next_hop: fields[1].to_string(),
+                            source,
+                        })
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn routes() -> Vec<RouteEntry> {
+        vec![
+            RouteEntry {
+                prefix: "example.alice".to_string(),
+                next_hop: "alice".to_string(),
+                source: RouteSource::Local,
+            },
+            RouteEntry {
+                prefix: "example.eu".to_string(),
+                next_hop: "bob".to_string(),
+                source: RouteSource::Static,
+            },
+            RouteEntry {
+                prefix: "g.us".to_string(),
+                next_hop: "bob".to_string(),
+                source: RouteSource::Learned,
+            },
+        ]
+    }
+
+    #[test]
+    fn writes_and_reads_csv() {
+        let csv = RouteFormat::Csv.write(&routes());
+        assert_eq!(
+            csv,
+            "prefix,next_hop,source\nexample.alice,alice,local\nexample.eu,bob,static\ng.us,bob,learned\n"
+        );
+        assert_eq!(RouteFormat::Csv.read(csv.as_bytes()).unwrap(), routes());
+        assert_eq!(
+            RouteFormat::Csv
+                .read(b"example.eu, bob, static\r\n\r\n")
+                .unwrap(),
+            routes()[1..2].to_vec()
+        );
+    }
+
+    #[test]
+    fn writes_and_reads_json() {
+        let json = RouteFormat::Json.write(&routes());
+        assert!(
+            json.starts_with(r#"[{"prefix":"example.alice","next_hop":"alice","source":"local"},"#)
+        );
+        assert_eq!(RouteFormat::Json.read(json.as_bytes()).unwrap(), routes());
+    }
+
+    #[test]
+    fn rejects_malformed_csv() {
+        assert_eq!(
+            RouteFormat::Csv.read(b"prefix,next_hop,source\nexample.eu,bob,configured"),
+            Err("line 2: invalid source".to_string())
+        );
+        assert_eq!(
+            RouteFormat::Csv.read(b"example.eu,bob,static,extra"),
+            Err("line 1: expected 3 fields".to_string())
+        );
+    }
+}
@@ -0,0 +1,320 @@
+use crate::payment_pointers::{is_valid_path, PaymentPointerStore, WELL_KNOWN_PATH};
+use crate::payment_receipts::PaymentReceiptSigner;
+use crate::receipts::ReceiptVerifierStore;
+use crate::routes::{payment_pointer_response, spsp_response};
+use crate::spsp_signatures::{SpspResponseSigner, SPSP_SIGNATURE_HEADER};
+use bytes::Bytes;
+use interledger_errors::*;
+use interledger_service::{Account, AccountStore, Username};
+use interledger_spsp::SpspResponder;
+use interledger_stream::ReceiptGenerator;
+use uuid::Uuid;
+use warp::{
+    self,
+    filters::path::FullPath,
+    http::{header::CONTENT_TYPE, HeaderValue, Response},
+    hyper::Body,
+    Filter, Rejection, Reply,
+};
+
+/// The SPSP endpoints, which the payment pointers of the accounts resolve to, and the
+/// public keys verifying the receipts of the payments sent via the API and the signatures
+/// of the SPSP responses.
+/// They are public, so they can be served apart from the rest of the API.
+pub fn spsp_api<S, A>(
+    server_secret: Bytes,
+    receipts: Option<ReceiptGenerator>,
+    default_spsp_account: Option<Username>,
+    store: S,
+) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone
+where
+    S: AccountStore<Account = A>
+        + PaymentPointerStore
+        + ReceiptVerifierStore
+        + Clone
+        + Send
+        + Sync
+        + 'static,
+    A: Account + Send + Sync + 'static,
+{
+    let with_store = warp::any().map(move || store.clone());
+    let account_username_to_id = warp::path::param::<Username>()
+        .and(with_store.clone())
+        .and_then(move |username: Username, store: S| async move {
+            let id = store.get_account_id_from_username(&username).await?;
+            Ok::<_, Rejection>(id)
+        });
+
+    // GET /accounts/:username/spsp
+    let server_secret_clone = server_secret.clone();
+    let get_spsp = warp::get()
+        .and(warp::path("accounts"))
+        .and(account_username_to_id)
+        .and(warp::path("spsp"))
+        .and(warp::path::end())
+        .and(with_store.clone())
+        .and_then(move |id: Uuid, store: S| {
+            let server_secret_clone = server_secret_clone.clone();
+            async move {
+                let accounts = store.get_accounts(vec![id]).await?;
+                // TODO return the response without instantiating an SpspResponder (use a simple fn)
+                Ok::<_, Rejection>(
+                    SpspResponder::new(
+                        accounts[0].ilp_address().clone(),
+                        server_secret_clone.clone(),
+                    )
+                    .generate_http_response(),
+                )
+            }
+        });
+
+    // GET /.well-known/pay
+    // This is the endpoint a [Payment Pointer](https://github.com/interledger/rfcs/blob/master/0026-payment-pointers/0026-payment-pointers.md)
+    // with no path resolves to. A payment pointer set for this path via the API
+    // takes precedence over the default SPSP account.
+    let server_secret_clone = server_secret.clone();
+    let receipts_clone = receipts.clone();
+    let get_spsp_well_known = warp::get()
+        .and(warp::path(".well-known"))
+        .and(warp::path("pay"))
+        .and(warp::path::end())
+        .and(with_store.clone())
+        .and_then(move |store: S| {
+            let default_spsp_account = default_spsp_account.clone();
+            let server_secret_clone = server_secret_clone.clone();
+            let receipts = receipts_clone.clone();
+            async move {
+                if let Some(pointer) = store.get_payment_pointer(WELL_KNOWN_PATH).await? {
+                    payment_pointer_response(
+                        &pointer,
+                        server_secret_clone,
+                        receipts.as_ref(),
+                        &store,
+                    )
+                    .await
+                } else if let Some(ref username) = default_spsp_account {
+                    let id = store.get_account_id_from_username(username).await?;
+
+                    // TODO this shouldn't take multiple store calls
+                    let mut accounts = store.get_accounts(vec![id]).await?;
+
+                    let account = accounts.pop().unwrap();
+                    let responder =
+                        SpspResponder::new(account.ilp_address().clone(), server_secret_clone);
+                    spsp_response(&responder, WELL_KNOWN_PATH, receipts.as_ref(), &store).await
+                } else {
+                    Err(Rejection::from(
+                        ApiError::not_found().detail("no default spsp account was configured"),
+                    ))
+                }
+            }
+        });
+
+    // GET /.well-known/payment-receipt-key
+    // Response: The public key with which anyone can verify the payment receipts
+    let receipt_key = PaymentReceiptSigner::new(&server_secret).public_key();
+    let get_receipt_key = warp::get()
+        .and(warp::path(".well-known"))
+        .and(warp::path("payment-receipt-key"))
+        .and(warp::path::end())
+        .map(move || warp::reply::json(&receipt_key));
+
+    // GET /.well-known/spsp-key
+    // Response: The public key with which anyone can verify the SPSP responses
+    let spsp_signer = SpspResponseSigner::new(&server_secret);
+    let spsp_key = spsp_signer.public_key();
+    let get_spsp_key = warp::get()
+        .and(warp::path(".well-known"))
+        .and(warp::path("spsp-key"))
+        .and(warp::path::end())
+        .map(move || warp::reply::json(&spsp_key));
+
+    // GET /:path
+    // This is the endpoint the payment pointers hosted by the node resolve to.
+    // Only SPSP requests for valid paths are looked up in the store, and the
+    // paths which are not payment pointers are left to the other routes.
+    let get_spsp_payment_pointer = warp::get()
+        .and(accepts_spsp())
+        .and(warp::path::full())
+        .and_then(|path: FullPath| async move {
+            if is_valid_path(path.as_str()) {
+                Ok(path)
+            } else {
+                Err(warp::reject::not_found())
+            }
+        })
+        .and(with_store)
+        .and_then(move |path: FullPath, store: S| {
+            let server_secret = server_secret.clone();
+            let receipts = receipts.clone();
+            async move {
+                let pointer = store
+                    .get_payment_pointer(path.as_str())
+                    .await?
+                    .ok_or_else(warp::reject::not_found)?;
+                payment_pointer_response(&pointer, server_secret, receipts.as_ref(), &store).await
+            }
+        });
+
+    let spsp_routes = get_spsp
+        .or(get_spsp_well_known)
+        .or(get_spsp_payment_pointer);
+    let signed_spsp_routes =
+        warp::path::full()
+            .and(spsp_routes)
+            .and_then(move |path: FullPath, reply| {
+                let spsp_signer = spsp_signer.clone();
+                async move { sign_spsp_response(&spsp_signer, path.as_str(), reply).await }
+            });
+
+    get_receipt_key.or(get_spsp_key).or(signed_spsp_routes)
+}
+
+/// Adds the node's signature of the SPSP response for the path to its headers
+async fn sign_spsp_response(
+    signer: &SpspResponseSigner,
+    path: &str,
+    reply: impl Reply,
+) -> Result<Response<Body>, Rejection> {
+    let response = reply.into_response();
+    let is_spsp = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .map_or(false, |content_type| {
+            content_type.starts_with("application/spsp")
+        });
+    if !response.status().is_success() || !is_spsp {
+        return Ok(response);
+    }
+    let (mut parts, body) = response.into_parts();
+    let body = hyper::body::to_bytes(body).await.map_err(|err| {
+        Rejection::from(ApiError::internal_server_error().detail(err.to_string()))
+    })?;
+    let signature = signer.sign(path, &body);
+    parts.headers.insert(
+        SPSP_SIGNATURE_HEADER,
+        HeaderValue::from_str(&signature).expect("base64url is a valid header value"),
+    );
+    Ok(Response::from_parts(parts, Body::from(body)))
+}
+
+/// Passes the requests which accept an SPSP response, as SPSP clients ask for one with
+/// `Accept: application/spsp4+json` (or `application/spsp+json` for older versions)
+fn accepts_spsp() -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::header::optional::<String>("accept")
+        .and_then(|accept: Option<String>| async move {
+            let accept = accept.unwrap_or_default();
+            if accept.contains("application/spsp4+json") || accept.contains("application/spsp+json")
+            {
+                Ok(())
+            } else {
+                Err(warp::reject::not_found())
+            }
+        })
+        .untuple_one()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::payment_receipts::PaymentReceiptSigner;
+    use crate::routes::test_helpers::{api_call, test_spsp_api};
+    use crate::spsp_signatures::{verify_spsp_response, SpspResponseSigner};
+    use bytes::Bytes;
+    use serde_json::Value;
+    use warp::http::Response;
+
+    async fn spsp_call<F>(api: &F, path: &str) -> Response<Bytes>
+    where
+        F: warp::Filter + 'static,
+        F::Extract: warp::Reply,
+    {
+        warp::test::request()
+            .method("GET")
+            .path(path)
+            .header("Accept", "application/spsp4+json, application/spsp+json")
+            .reply(api)
+            .await
+    }
+
+    #[tokio::test]
+    async fn resolves_hosted_payment_pointers() {
+        let api = test_spsp_api();
+        let resp = spsp_call(&api, "/donate").await;
+        assert_eq!(resp.status().as_u16(), 200);
+        assert_eq!(
+            resp.headers().get("Content-Type").unwrap(),
+            "application/spsp4+json"
+        );
+        let spsp: Value = serde_json::from_slice(resp.body()).unwrap();
+        assert!(spsp["destination_account"]
+            .as_str()
+            .unwrap()
+            .starts_with("example.alice.donations."));
+
+        // Other paths are left to the other routes, which reject them
+        let resp = spsp_call(&api, "/not-hosted").await;
+        assert!(resp.status().is_client_error());
+    }
+
+    #[tokio::test]
+    async fn only_serves_spsp_requests() {
+        let api = test_spsp_api();
+        let resp = api_call(&api, "GET", "/donate", "", None).await;
+        assert_eq!(resp.status().as_u16(), 404);
+        let resp = warp::test::request()
+            .path("/donate")
+            .header("Accept", "text/html")
+            .reply(&api)
+            .await;
+        assert_eq!(resp.status().as_u16(), 404);
+    }
+
+    #[tokio::test]
+    async fn publishes_the_payment_receipt_key() {
+        let api = test_spsp_api();
+        let resp = api_call(&api, "GET", "/.well-known/payment-receipt-key", "", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let key: Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(key["algorithm"], "Ed25519");
+        assert_eq!(
+            key["public_key"],
+            PaymentReceiptSigner::new(&[0; 32]).public_key().public_key
+        );
+    }
+
+    #[tokio::test]
+    async fn signs_the_spsp_responses() {
+        let api = test_spsp_api();
+        let resp = api_call(&api, "GET", "/.well-known/spsp-key", "", None).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let key: Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(key["algorithm"], "Ed25519");
+        let public_key = SpspResponseSigner::new(&[0; 32]).public_key().public_key;
+        assert_eq!(key["public_key"], public_key);
+        let public_key = base64::decode_config(&public_key, base64::URL_SAFE_NO_PAD).unwrap();
+
+        let resp = spsp_call(&api, "/donate").await;
+        assert_eq!(resp.status().as_u16(), 200);
+        let signature = resp.headers()["SPSP-Signature"].to_str().unwrap();
+        assert!(verify_spsp_response(
+            &public_key,
+            "/donate",
+            resp.body(),
+            signature
+        ));
+        // The signature is of the response for the path it was served for
+        assert!(!verify_spsp_response(
+            &public_key,
+            "/.well-known/pay",
+            resp.body(),
+            signature
+        ));
+
+        // The other responses are not signed
+        let resp = api_call(&api, "GET", "/.well-known/payment-receipt-key", "", None).await;
+        assert!(resp.headers().get("SPSP-Signature").is_none());
+        let resp = spsp_call(&api, "/not-hosted").await;
+        assert!(resp.headers().get("SPSP-Signature").is_none());
+    }
+}
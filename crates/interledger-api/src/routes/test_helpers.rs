@@ -1,6 +1,16 @@
 use crate::{
-    routes::{accounts_api, node_settings_api},
-    AccountDetails, AccountSettings, NodeStore,
+    graphql::RecentPayments,
+    routes::{
+        account_templates_api, accounts_api, assets_api, graphql_api, node_settings_api,
+        payment_pointers_api, peering_api, provisioning_api, pull_payments_api, receipts_api,
+        spsp_api,
+    },
+    AccountDetails, AccountSettings, AccountTemplate, AccountTemplateStore, Asset, AssetStore,
+    AuditEntry, AuditLogStore, AuditQuery, ChildProvisioning, GrpcApi, NodeStore, OutgoingPayment,
+    OutgoingPaymentStatus, OutgoingPaymentStore, PaymentPointer, PaymentPointerStore,
+    PeeringProposal, PeeringRequest, PeeringStore, PullPaymentStore, PullPointer, ReceiptCredit,
+    ReceiptVerifierStore, Rounding, RuntimeSettings, ScheduledPayment, ScheduledPaymentRequest,
+    ScheduledPaymentStore,
 };
 use async_trait::async_trait;
 use bytes::Bytes;
@@ -11,22 +21,37 @@ use interledger_ccp::{CcpRoutingAccount, RoutingRelation};
 use interledger_errors::*;
 use interledger_http::{HttpAccount, HttpStore};
 use interledger_packet::{Address, ErrorCode, FulfillBuilder, RejectBuilder};
-use interledger_rates::ExchangeRateStore;
+use interledger_rates::{ExchangeRateStore, PinnedRateStore};
 use interledger_router::RouterStore;
 use interledger_service::{
-    incoming_service_fn, outgoing_service_fn, Account, AccountStore, AddressStore, Username,
+    incoming_service_fn, outgoing_service_fn, Account, AccountStore, AddressStore, PeerBlocklist,
+    Username,
+};
+use interledger_service_util::{
+    BalanceJournalStore, BalanceNotifications, BalanceStore, BalanceVerification, CreditLimits,
+    EchoPings, Escrow, FaultInjector, FeeAccount, FeePolicy, FeePolicyStore, Liquidity,
+    LiquidityStore, NodeStats, PacketTraces, PeerLatencies, Scheduler, SettlementReconciliation,
+    TransferCharge, TransferLimitStore, TransferLimits, TransferUsage, WindowUsage,
+};
+use interledger_settlement::core::{
+    types::{SettlementAccount, SettlementEngineDetails},
+    SettlementClient,
+};
+use interledger_spsp::PullBalance;
+use interledger_stream::{
+    MaxPacketAmountStore, PaymentNotification, Receipt, ReceiptGenerator, StreamConnections,
+    StreamDelivery, StreamNotificationsStore, UnreachableDestination, UnreachableDestinationStore,
 };
-use interledger_service_util::BalanceStore;
-use interledger_settlement::core::types::{SettlementAccount, SettlementEngineDetails};
-use interledger_stream::{PaymentNotification, StreamNotificationsStore};
 use once_cell::sync::Lazy;
 use secrecy::SecretString;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
+use std::net::SocketAddr;
 use std::str::FromStr;
-use std::sync::Arc;
-use tokio::sync::broadcast;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::{net::TcpListener, sync::broadcast};
 use url::Url;
 use uuid::Uuid;
 use warp::{self, Filter};
@@ -56,10 +81,169 @@ where
 
 pub fn test_node_settings_api(
 ) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
-    node_settings_api("admin".to_owned(), None, TestStore).recover(default_rejection_handler)
+    test_node_settings_api_with_blocklist(PeerBlocklist::default())
+}
+
+/// The node settings API, overriding the quarantines of `blocklist`
+pub fn test_node_settings_api_with_blocklist(
+    blocklist: PeerBlocklist,
+) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    node_settings_api(
+        "admin".to_owned(),
+        None,
+        Some("1.2.3".to_owned()),
+        vec!["btp".to_owned(), "http".to_owned()],
+        // The node started before its API was created
+        Instant::now() - Duration::from_secs(90),
+        FeePolicy::default(),
+        NodeStats::default(),
+        blocklist,
+        Scheduler::default(),
+        SettlementReconciliation::default(),
+        SettlementClient::default(),
+        TestStore,
+    )
+    .recover(default_rejection_handler)
+}
+
+pub fn test_payment_pointers_api(
+) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    payment_pointers_api("admin".to_owned(), None, TestStore).recover(default_rejection_handler)
+}
+
+pub fn test_assets_api(
+) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    assets_api("admin".to_owned(), None, TestStore).recover(default_rejection_handler)
+}
+
+pub fn test_account_templates_api(
+) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    account_templates_api("admin".to_owned(), None, TestStore).recover(default_rejection_handler)
+}
+
+pub fn test_spsp_api(
+) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    spsp_api(Bytes::from(&[0; 32][..]), None, None, TestStore).recover(default_rejection_handler)
+}
+
+pub fn test_receipts_api(
+    enabled: bool,
+) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let receipts = if enabled {
+        Some(ReceiptGenerator::new(&[0; 32]))
+    } else {
+        None
+    };
+    receipts_api(receipts, "admin".to_owned(), None, TestStore).recover(default_rejection_handler)
+}
+
+pub fn test_graphql_api(
+    payments: Option<RecentPayments>,
+) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    graphql_api(payments, "admin".to_owned(), None, TestStore).recover(default_rejection_handler)
+}
+
+pub fn test_pull_payments_api(
+) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let incoming = incoming_service_fn(|_request| {
+        Err(RejectBuilder {
+            code: ErrorCode::F02_UNREACHABLE,
+            message: b"No other incoming handler!",
+            data: &[],
+            triggered_by: None,
+        }
+        .build())
+    });
+    pull_payments_api("admin".to_owned(), None, incoming, TestStore)
+        .recover(default_rejection_handler)
+}
+
+pub fn test_peering_api(
+    enabled: bool,
+) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let outgoing = outgoing_service_fn(move |_request| {
+        Ok(FulfillBuilder {
+            fulfillment: &[0; 32],
+            data: b"hello!",
+        }
+        .build())
+    });
+    let btp = BtpOutgoingService::new(
+        Address::from_str("example.alice").unwrap(),
+        outgoing.clone(),
+    );
+    let public_url = if enabled {
+        Some(Url::parse("http://alice.example").unwrap())
+    } else {
+        None
+    };
+    peering_api(
+        "admin".to_owned(),
+        None,
+        public_url,
+        outgoing,
+        btp,
+        SettlementClient::default(),
+        TestStore,
+    )
+    .recover(default_rejection_handler)
+}
+
+pub fn test_provisioning_api(
+    enabled: bool,
+) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    test_provisioning_api_with_token(enabled, None)
+}
+
+pub fn test_provisioning_api_with_token(
+    enabled: bool,
+    token: Option<&str>,
+) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let outgoing = outgoing_service_fn(move |_request| {
+        Ok(FulfillBuilder {
+            fulfillment: &[0; 32],
+            data: b"hello!",
+        }
+        .build())
+    });
+    let btp = BtpOutgoingService::new(
+        Address::from_str("example.alice").unwrap(),
+        outgoing.clone(),
+    );
+    let provisioning = if enabled {
+        Some(ChildProvisioning {
+            asset_code: "XYZ".to_string(),
+            asset_scale: 9,
+            username_prefix: "wallet_".to_string(),
+            challenge_ttl: 60_000,
+            max_packet_amount: 1000,
+            amount_per_minute_limit: None,
+            packets_per_minute_limit: None,
+            token: token.map(|token| SecretString::new(token.to_string())),
+            accounts_per_hour: 3,
+        })
+    } else {
+        None
+    };
+    provisioning_api(
+        provisioning,
+        Bytes::from(&[0; 32][..]),
+        outgoing,
+        btp,
+        SettlementClient::default(),
+        TestStore,
+    )
+    .recover(default_rejection_handler)
 }
 
 pub fn test_accounts_api(
+) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    test_accounts_api_with_traces(PacketTraces::default())
+}
+
+/// The accounts API, recording the packets of the traced accounts in `packet_traces`
+pub fn test_accounts_api_with_traces(
+    packet_traces: PacketTraces,
 ) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
     let incoming = incoming_service_fn(|_request| {
         Err(RejectBuilder {
@@ -83,17 +267,58 @@ pub fn test_accounts_api(
     );
     let store = TestStore;
     accounts_api(
-        Bytes::from("admin"),
+        // The pulls create STREAM receivers, whose server secret is 32 bytes
+        Bytes::from(&[0; 32][..]),
         "admin".to_owned(),
         None,
         incoming,
         outgoing,
         btp,
+        EchoPings::default(),
+        PeerLatencies::default(),
+        packet_traces,
+        BalanceNotifications::default(),
+        Some(FaultInjector::default()),
+        StreamConnections::default(),
+        SettlementClient::default(),
         store,
     )
     .recover(default_rejection_handler)
 }
 
+/// Serves the gRPC API on a random port, and returns its address
+pub async fn test_grpc_api() -> SocketAddr {
+    let outgoing = outgoing_service_fn(move |_request| {
+        Ok(FulfillBuilder {
+            fulfillment: &[0; 32],
+            data: b"hello!",
+        }
+        .build())
+    });
+    let btp = BtpOutgoingService::new(
+        Address::from_str("example.alice").unwrap(),
+        outgoing.clone(),
+    );
+    let incoming = incoming_service_fn(|_request| {
+        Err(RejectBuilder {
+            code: ErrorCode::F02_UNREACHABLE,
+            message: b"No other incoming handler!",
+            data: &[],
+            triggered_by: None,
+        }
+        .build())
+    });
+    let api = GrpcApi::new("admin".to_owned(), None, TestStore, incoming, outgoing, btp);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(api.serve(listener));
+    addr
+}
+
+/// The payments published to the subscribers of the test store
+pub static PAYMENTS: Lazy<broadcast::Sender<PaymentNotification>> =
+    Lazy::new(|| broadcast::channel(16).0);
+
 /*
  * Lots of boilerplate implementations of all necessary traits to launch
  * the crate's APIs in unit tests
@@ -104,6 +329,8 @@ struct TestStore;
 
 use serde_json::json;
 pub static USERNAME: Lazy<Username> = Lazy::new(|| Username::from_str("alice").unwrap());
+/// The id of the test account, which every username resolves to
+pub static ACCOUNT_ID: Lazy<Uuid> = Lazy::new(Uuid::new_v4);
 pub static EXAMPLE_ADDRESS: Lazy<Address> =
     Lazy::new(|| Address::from_str("example.alice").unwrap());
 pub static DETAILS: Lazy<Option<Value>> = Lazy::new(|| {
@@ -122,7 +349,7 @@ pub struct TestAccount;
 
 impl Account for TestAccount {
     fn id(&self) -> Uuid {
-        Uuid::new_v4()
+        *ACCOUNT_ID
     }
 
     fn username(&self) -> &Username {
@@ -168,6 +395,8 @@ impl SettlementAccount for TestAccount {
     }
 }
 
+impl FeeAccount for TestAccount {}
+
 impl CcpRoutingAccount for TestAccount {
     fn routing_relation(&self) -> RoutingRelation {
         RoutingRelation::NonRoutingAccount
@@ -180,9 +409,9 @@ impl AccountStore for TestStore {
 
     async fn get_accounts(
         &self,
-        _account_ids: Vec<Uuid>,
+        account_ids: Vec<Uuid>,
     ) -> Result<Vec<TestAccount>, AccountStoreError> {
-        Ok(vec![TestAccount])
+        Ok(account_ids.iter().map(|_| TestAccount).collect())
     }
 
     // stub implementation (not used in these tests)
@@ -190,7 +419,7 @@ impl AccountStore for TestStore {
         &self,
         _username: &Username,
     ) -> Result<Uuid, AccountStoreError> {
-        Ok(Uuid::new_v4())
+        Ok(*ACCOUNT_ID)
     }
 }
 
@@ -217,9 +446,59 @@ impl ExchangeRateStore for TestStore {
     }
 }
 
+#[async_trait]
+impl PinnedRateStore for TestStore {
+    async fn get_pinned_rates(&self) -> Result<HashMap<String, f64>, ExchangeRateStoreError> {
+        let mut ret = HashMap::new();
+        ret.insert("ABC".to_owned(), 1.0);
+        Ok(ret)
+    }
+
+    async fn pin_rate(&self, _asset_code: &str, _rate: f64) -> Result<(), ExchangeRateStoreError> {
+        Ok(())
+    }
+
+    async fn unpin_rate(&self, asset_code: &str) -> Result<Option<f64>, ExchangeRateStoreError> {
+        Ok(if asset_code == "ABC" { Some(1.0) } else { None })
+    }
+}
+
+impl FeePolicyStore for TestStore {
+    fn get_fee_policy(&self, _from: &str, _to: &str) -> Option<FeePolicy> {
+        None
+    }
+
+    fn get_all_fee_policies(&self) -> Result<HashMap<String, FeePolicy>, FeePolicyStoreError> {
+        let mut policies = HashMap::new();
+        policies.insert(
+            "ABC/XYZ".to_string(),
+            FeePolicy {
+                spread: Some(0.01),
+                ..Default::default()
+            },
+        );
+        Ok(policies)
+    }
+
+    fn set_fee_policies(
+        &self,
+        _policies: HashMap<String, FeePolicy>,
+    ) -> Result<(), FeePolicyStoreError> {
+        Ok(())
+    }
+}
+
 impl RouterStore for TestStore {
     fn routing_table(&self) -> Arc<HashMap<String, Uuid>> {
-        Arc::new(HashMap::new())
+        Arc::new(
+            vec![
+                ("example.alice".to_string(), Uuid::nil()),
+                ("example.eu".to_string(), Uuid::nil()),
+                ("g.us".to_string(), Uuid::nil()),
+            ]
+            .into_iter()
+            .collect(),
+        )
     }
 }
 
@@ -234,7 +513,8 @@ impl NodeStore for TestStore {
         Ok(TestAccount)
     }
 
-    async fn delete_account(&self, _id: Uuid) -> Result<Self::Account, NodeStoreError> {
+    async fn delete_account(&self, id: Uuid) -> Result<Self::Account, NodeStoreError> {
+        DELETED_ACCOUNTS.lock().unwrap().push(id);
         Ok(TestAccount)
     }
 
@@ -277,6 +557,12 @@ impl NodeStore for TestStore {
         unimplemented!()
     }
 
+    async fn get_static_routes(&self) -> Result<HashMap<String, Uuid>, NodeStoreError> {
+        Ok(vec![("example.eu".to_string(), Uuid::nil())]
+            .into_iter()
+            .collect())
+    }
+
     async fn set_settlement_engines(
         &self,
         _asset_to_url_map: impl IntoIterator<Item = (String, Url)> + Send + 'async_trait,
@@ -286,9 +572,36 @@ impl NodeStore for TestStore {
 
     async fn get_asset_settlement_engine(
         &self,
-        _asset_code: &str,
+        asset_code: &str,
     ) -> Result<Option<Url>, NodeStoreError> {
-        Ok(None)
+        Ok(self.get_settlement_engines().await?.remove(asset_code))
+    }
+
+    async fn get_settlement_engines(&self) -> Result<HashMap<String, Url>, NodeStoreError> {
+        Ok(vec![(
+            "ABC".to_string(),
+            Url::parse("http://settlement.example").unwrap(),
+        )]
+        .into_iter()
+        .collect())
+    }
+
+    async fn delete_settlement_engine(
+        &self,
+        asset_code: &str,
+    ) -> Result<Option<Url>, NodeStoreError> {
+        self.get_asset_settlement_engine(asset_code).await
+    }
+
+    async fn set_runtime_settings(&self, _settings: RuntimeSettings) -> Result<(), NodeStoreError> {
+        Ok(())
+    }
+
+    async fn get_runtime_settings(&self) -> Result<RuntimeSettings, NodeStoreError> {
+        Ok(RuntimeSettings {
+            route_broadcast_interval: Some(5000),
+            ..Default::default()
+        })
     }
 }
 
@@ -309,6 +622,73 @@ impl AddressStore for TestStore {
     }
 }
 
+#[async_trait]
+impl MaxPacketAmountStore for TestStore {
+    async fn get_max_packet_amount(
+        &self,
+        _source_asset_code: &str,
+        _source_asset_scale: u8,
+        _destination_prefix: &str,
+    ) -> Result<Option<u64>, MaxPacketAmountStoreError> {
+        Ok(None)
+    }
+
+    async fn set_max_packet_amount(
+        &self,
+        _source_asset_code: &str,
+        _source_asset_scale: u8,
+        _destination_prefix: &str,
+        _max_packet_amount: u64,
+        _ttl: u64,
+    ) -> Result<(), MaxPacketAmountStoreError> {
+        Ok(())
+    }
+}
+
+pub static UNREACHABLE_DESTINATION: Lazy<UnreachableDestination> =
+    Lazy::new(|| UnreachableDestination {
+        destination_prefix: "example.carol".to_string(),
+        failures: 3,
+        error: "F02 no route found".to_string(),
+        expires_in: 60_000,
+    });
+
+#[async_trait]
+impl UnreachableDestinationStore for TestStore {
+    async fn get_unreachable_destination(
+        &self,
+        destination_prefix: &str,
+    ) -> Result<Option<UnreachableDestination>, UnreachableDestinationStoreError> {
+        Ok(Some(UNREACHABLE_DESTINATION.clone())
+            .filter(|unreachable| unreachable.destination_prefix == destination_prefix))
+    }
+
+    async fn record_unreachable_destination(
+        &self,
+        _destination_prefix: &str,
+        _error: &str,
+        _ttl: u64,
+    ) -> Result<(), UnreachableDestinationStoreError> {
+        Ok(())
+    }
+
+    async fn get_unreachable_destinations(
+        &self,
+    ) -> Result<Vec<UnreachableDestination>, UnreachableDestinationStoreError> {
+        Ok(vec![UNREACHABLE_DESTINATION.clone()])
+    }
+
+    async fn clear_unreachable_destinations(
+        &self,
+        destination_prefix: Option<&str>,
+    ) -> Result<usize, UnreachableDestinationStoreError> {
+        Ok(match destination_prefix {
+            Some(prefix) if prefix != UNREACHABLE_DESTINATION.destination_prefix => 0,
+            _ => 1,
+        })
+    }
+}
+
 impl StreamNotificationsStore for TestStore {
     type Account = TestAccount;
 
@@ -325,7 +705,7 @@ impl StreamNotificationsStore for TestStore {
     }
 
     fn all_payment_subscription(&self) -> broadcast::Receiver<PaymentNotification> {
-        unimplemented!()
+        PAYMENTS.subscribe()
     }
 }
 
@@ -335,29 +715,524 @@ impl BalanceStore for TestStore {
         Ok(1)
     }
 
-    async fn update_balances_for_prepare(
-        &self,
-        _: Uuid,
-        _incoming_amount: u64,
-    ) -> Result<(), BalanceStoreError> {
+    async fn get_in_flight_amount(&self, _: Uuid) -> Result<u64, BalanceStoreError> {
+        Ok(0)
+    }
+
+    async fn update_balances_for_prepare(&self, _: &Escrow) -> Result<(), BalanceStoreError> {
         unimplemented!()
     }
 
     async fn update_balances_for_fulfill(
         &self,
-        _: Uuid,
-        _outgoing_amount: u64,
+        _: &Escrow,
     ) -> Result<(i64, u64), BalanceStoreError> {
         unimplemented!()
     }
 
-    async fn update_balances_for_reject(
+    async fn update_balances_for_reject(&self, _: &Escrow) -> Result<(), BalanceStoreError> {
+        unimplemented!()
+    }
+
+    async fn update_balances_for_settle_to(
         &self,
         _: Uuid,
-        _incoming_amount: u64,
-    ) -> Result<(), BalanceStoreError> {
+    ) -> Result<(i64, u64), BalanceStoreError> {
         unimplemented!()
     }
+
+    async fn release_expired_escrows(&self) -> Result<usize, BalanceStoreError> {
+        unimplemented!()
+    }
+}
+
+#[async_trait]
+impl LiquidityStore for TestStore {
+    async fn get_liquidity(&self, _: Uuid) -> Result<Liquidity, BalanceStoreError> {
+        Ok(Liquidity::new(1, 0, 0, CreditLimits::default()))
+    }
+
+    async fn set_credit_limits(
+        &self,
+        _: Uuid,
+        limits: CreditLimits,
+    ) -> Result<Liquidity, BalanceStoreError> {
+        Ok(Liquidity::new(1, 0, 0, limits))
+    }
+}
+
+#[async_trait]
+impl TransferLimitStore for TestStore {
+    async fn get_transfer_usage(&self, _: Uuid) -> Result<TransferUsage, BalanceStoreError> {
+        Ok(TransferUsage::default())
+    }
+
+    async fn set_transfer_limits(
+        &self,
+        _: Uuid,
+        limits: TransferLimits,
+    ) -> Result<TransferUsage, BalanceStoreError> {
+        let usage = |limit| WindowUsage {
+            limit,
+            ..Default::default()
+        };
+        Ok(TransferUsage {
+            daily_send: usage(limits.daily_send_limit),
+            monthly_send: usage(limits.monthly_send_limit),
+            daily_receive: usage(limits.daily_receive_limit),
+            monthly_receive: usage(limits.monthly_receive_limit),
+        })
+    }
+
+    async fn charge_transfer_limits(
+        &self,
+        charge: TransferCharge,
+    ) -> Result<TransferCharge, BalanceStoreError> {
+        Ok(charge)
+    }
+
+    async fn refund_transfer_limits(&self, _: &TransferCharge) -> Result<(), BalanceStoreError> {
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl BalanceJournalStore for TestStore {
+    async fn snapshot_balances(&self) -> Result<usize, BalanceStoreError> {
+        Ok(1)
+    }
+
+    async fn verify_balances(&self) -> Result<Vec<BalanceVerification>, BalanceStoreError> {
+        Ok(vec![BalanceVerification {
+            account_id: Uuid::new_v4(),
+            balance: 1,
+            prepaid_amount: 0,
+            expected_balance: 1,
+            expected_prepaid_amount: 0,
+            replayed_entries: 1,
+        }])
+    }
+
+    async fn recover_balance(
+        &self,
+        account_id: Uuid,
+    ) -> Result<BalanceVerification, BalanceStoreError> {
+        Ok(BalanceVerification {
+            account_id,
+            balance: 1,
+            prepaid_amount: 0,
+            expected_balance: 1,
+            expected_prepaid_amount: 0,
+            replayed_entries: 1,
+        })
+    }
+}
+
+#[async_trait]
+impl AuditLogStore for TestStore {
+    async fn append_audit_entry(&self, entry: AuditEntry) -> Result<AuditEntry, NodeStoreError> {
+        Ok(AuditEntry { id: 1, ..entry })
+    }
+
+    async fn get_audit_entries(
+        &self,
+        _query: AuditQuery,
+    ) -> Result<Vec<AuditEntry>, NodeStoreError> {
+        Ok(vec![AuditEntry {
+            id: 1,
+            timestamp: 1_600_000_000_000,
+            actor: "admin".to_string(),
+            method: "PUT".to_string(),
+            path: "/rates".to_string(),
+            request: json!({"ABC": 1.0}),
+        }])
+    }
+}
+
+/// The asset of the test accounts, which is the only one registered in the test store
+static XYZ: Lazy<Asset> = Lazy::new(|| Asset {
+    code: "XYZ".to_string(),
+    min_scale: 6,
+    max_scale: 9,
+    symbol: None,
+    display_scale: 3,
+    rounding: Rounding::HalfUp,
+});
+
+#[async_trait]
+impl AssetStore for TestStore {
+    async fn get_asset(&self, code: &str) -> Result<Option<Asset>, NodeStoreError> {
+        Ok(Some(XYZ.clone()).filter(|asset| asset.code == code))
+    }
+
+    async fn get_assets(&self) -> Result<Vec<Asset>, NodeStoreError> {
+        Ok(vec![XYZ.clone()])
+    }
+
+    async fn set_asset(&self, _asset: Asset) -> Result<(), NodeStoreError> {
+        Ok(())
+    }
+
+    async fn delete_asset(&self, code: &str) -> Result<Option<Asset>, NodeStoreError> {
+        self.get_asset(code).await
+    }
+}
+
+static PEER_XYZ: Lazy<AccountTemplate> = Lazy::new(|| AccountTemplate {
+    name: "peer-xyz".to_string(),
+    fields: json!({
+        "asset_code": "XYZ",
+        "asset_scale": 9,
+        "routing_relation": "Peer",
+    })
+    .as_object()
+    .unwrap()
+    .clone(),
+});
+
+#[async_trait]
+impl AccountTemplateStore for TestStore {
+    async fn get_account_template(
+        &self,
+        name: &str,
+    ) -> Result<Option<AccountTemplate>, NodeStoreError> {
+        Ok(Some(PEER_XYZ.clone()).filter(|template| template.name == name))
+    }
+
+    async fn get_account_templates(&self) -> Result<Vec<AccountTemplate>, NodeStoreError> {
+        Ok(vec![PEER_XYZ.clone()])
+    }
+
+    async fn set_account_template(&self, _template: AccountTemplate) -> Result<(), NodeStoreError> {
+        Ok(())
+    }
+
+    async fn delete_account_template(
+        &self,
+        name: &str,
+    ) -> Result<Option<AccountTemplate>, NodeStoreError> {
+        self.get_account_template(name).await
+    }
+}
+
+static DONATIONS: Lazy<PaymentPointer> = Lazy::new(|| PaymentPointer {
+    path: "/donate".to_string(),
+    username: USERNAME.clone(),
+    destination_tag: Some("donations".to_string()),
+});
+
+#[async_trait]
+impl PaymentPointerStore for TestStore {
+    async fn get_payment_pointer(
+        &self,
+        path: &str,
+    ) -> Result<Option<PaymentPointer>, NodeStoreError> {
+        Ok(Some(DONATIONS.clone()).filter(|pointer| pointer.path == path))
+    }
+
+    async fn get_payment_pointers(&self) -> Result<Vec<PaymentPointer>, NodeStoreError> {
+        Ok(vec![DONATIONS.clone()])
+    }
+
+    async fn set_payment_pointer(&self, _pointer: PaymentPointer) -> Result<(), NodeStoreError> {
+        Ok(())
+    }
+
+    async fn delete_payment_pointer(
+        &self,
+        path: &str,
+    ) -> Result<Option<PaymentPointer>, NodeStoreError> {
+        self.get_payment_pointer(path).await
+    }
+}
+
+pub static SUBSCRIPTION: Lazy<PullPointer> = Lazy::new(|| PullPointer {
+    id: "subscription".to_string(),
+    username: USERNAME.clone(),
+    amount: 100,
+    interval: 60,
+    cap: Some(1000),
+    start: 0,
+});
+
+#[async_trait]
+impl PullPaymentStore for TestStore {
+    async fn create_pull_pointer(&self, _pointer: PullPointer) -> Result<(), NodeStoreError> {
+        Ok(())
+    }
+
+    async fn get_pull_pointer(&self, id: &str) -> Result<Option<PullPointer>, NodeStoreError> {
+        Ok(Some(SUBSCRIPTION.clone()).filter(|pointer| pointer.id == id))
+    }
+
+    async fn get_pull_pointers(&self) -> Result<Vec<PullPointer>, NodeStoreError> {
+        Ok(vec![SUBSCRIPTION.clone()])
+    }
+
+    async fn delete_pull_pointer(&self, id: &str) -> Result<Option<PullPointer>, NodeStoreError> {
+        self.get_pull_pointer(id).await
+    }
+
+    // Nothing was pulled from the pull pointers before
+    async fn get_pull_balance(
+        &self,
+        pointer: &PullPointer,
+        _now: u64,
+    ) -> Result<PullBalance, NodeStoreError> {
+        Ok(pointer.balance(0, 0))
+    }
+
+    async fn reserve_pull(
+        &self,
+        pointer: &PullPointer,
+        amount: u64,
+        _now: u64,
+    ) -> Result<Option<PullBalance>, NodeStoreError> {
+        if amount > pointer.balance(0, 0).available {
+            return Ok(None);
+        }
+        Ok(Some(pointer.balance(amount, amount)))
+    }
+
+    async fn release_pull(
+        &self,
+        pointer: &PullPointer,
+        amount: u64,
+        _now: u64,
+    ) -> Result<PullBalance, NodeStoreError> {
+        RELEASED_PULLS.lock().unwrap().push(amount);
+        Ok(pointer.balance(0, 0))
+    }
+}
+
+/// The amounts released by `release_pull`
+pub static RELEASED_PULLS: Lazy<Mutex<Vec<u64>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+/// The ids of the accounts deleted by `delete_account`
+pub static DELETED_ACCOUNTS: Lazy<Mutex<Vec<Uuid>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+/// The ids of the requests saved by `save_peering_request`
+pub static SAVED_PEERING_REQUESTS: Lazy<Mutex<Vec<String>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+pub static RENT: Lazy<ScheduledPayment> = Lazy::new(|| {
+    ScheduledPayment::new(
+        "rent".to_string(),
+        USERNAME.clone(),
+        ScheduledPaymentRequest {
+            receiver: "$example.com/landlord".to_string(),
+            source_amount: 100,
+            slippage: 0.015,
+            execute_at: 0,
+            recurrence: None,
+            notification_url: None,
+            memo: None,
+        },
+    )
+});
+
+#[async_trait]
+impl ScheduledPaymentStore for TestStore {
+    async fn create_scheduled_payment(
+        &self,
+        _payment: ScheduledPayment,
+    ) -> Result<(), NodeStoreError> {
+        Ok(())
+    }
+
+    async fn get_scheduled_payment(
+        &self,
+        id: &str,
+    ) -> Result<Option<ScheduledPayment>, NodeStoreError> {
+        Ok(Some(RENT.clone()).filter(|payment| payment.id == id))
+    }
+
+    async fn get_scheduled_payments(&self) -> Result<Vec<ScheduledPayment>, NodeStoreError> {
+        Ok(vec![RENT.clone()])
+    }
+
+    async fn update_scheduled_payment(
+        &self,
+        payment: ScheduledPayment,
+    ) -> Result<bool, NodeStoreError> {
+        Ok(payment.id == RENT.id)
+    }
+
+    async fn delete_scheduled_payment(
+        &self,
+        id: &str,
+    ) -> Result<Option<ScheduledPayment>, NodeStoreError> {
+        self.get_scheduled_payment(id).await
+    }
+}
+
+pub static SENT_PAYMENT: Lazy<OutgoingPayment> = Lazy::new(|| OutgoingPayment {
+    id: "sent".to_string(),
+    username: USERNAME.clone(),
+    receiver: "$example.com/bob".to_string(),
+    source_amount: Some(100),
+    destination_amount: None,
+    slippage: 0.015,
+    memo: None,
+    notification_url: None,
+    status: OutgoingPaymentStatus::Failed,
+    created_at: 0,
+    updated_at: 0,
+    delivery: None,
+    error: Some("the payment was interrupted by the node stopping".to_string()),
+});
+
+pub static DELIVERED_PAYMENT: Lazy<OutgoingPayment> = Lazy::new(|| OutgoingPayment {
+    id: "delivered".to_string(),
+    username: USERNAME.clone(),
+    receiver: "$example.com/bob".to_string(),
+    source_amount: Some(100),
+    destination_amount: None,
+    slippage: 0.015,
+    memo: None,
+    notification_url: None,
+    status: OutgoingPaymentStatus::Completed,
+    created_at: 0,
+    updated_at: 1,
+    delivery: Some(StreamDelivery {
+        from: Address::from_str("example.alice").unwrap(),
+        to: Address::from_str("example.bob.abc").unwrap(),
+        source_asset_scale: 9,
+        source_asset_code: "XYZ".to_string(),
+        source_amount: 100,
+        sent_amount: 100,
+        in_flight_amount: 0,
+        delivered_amount: 100,
+        destination_asset_scale: Some(9),
+        destination_asset_code: Some("XYZ".to_string()),
+    }),
+    error: None,
+});
+
+#[async_trait]
+impl OutgoingPaymentStore for TestStore {
+    async fn save_outgoing_payment(&self, _payment: OutgoingPayment) -> Result<(), NodeStoreError> {
+        Ok(())
+    }
+
+    async fn get_outgoing_payment(
+        &self,
+        id: &str,
+    ) -> Result<Option<OutgoingPayment>, NodeStoreError> {
+        Ok(vec![SENT_PAYMENT.clone(), DELIVERED_PAYMENT.clone()]
+            .into_iter()
+            .find(|payment| payment.id == id))
+    }
+
+    async fn get_outgoing_payments(&self) -> Result<Vec<OutgoingPayment>, NodeStoreError> {
+        Ok(vec![SENT_PAYMENT.clone(), DELIVERED_PAYMENT.clone()])
+    }
+
+    async fn get_outgoing_payments_page(
+        &self,
+        _cursor: u64,
+        _count: usize,
+    ) -> Result<(u64, Vec<OutgoingPayment>), NodeStoreError> {
+        Ok((0, vec![SENT_PAYMENT.clone(), DELIVERED_PAYMENT.clone()]))
+    }
+
+    async fn delete_outgoing_payments(&self, _ids: Vec<String>) -> Result<(), NodeStoreError> {
+        Ok(())
+    }
+}
+
+pub static PEERING_REQUEST: Lazy<PeeringRequest> = Lazy::new(|| PeeringRequest {
+    id: "request".to_string(),
+    username: Username::from_str("carol").unwrap(),
+    asset_code: "XYZ".to_string(),
+    asset_scale: 9,
+    ilp_over_http_url: "http://carol.example/accounts/alice/ilp".to_string(),
+    ilp_over_http_token: "carol_token".to_string(),
+    // Nothing listens on the port, so approving the request fails
+    callback_url: Url::parse("http://127.0.0.1:1/peering/proposals/1/callback").unwrap(),
+    callback_token: "callback".to_string(),
+    received_at: super::peering::now(),
+});
+
+pub static PEERING_PROPOSAL: Lazy<PeeringProposal> = Lazy::new(|| PeeringProposal {
+    id: "proposal".to_string(),
+    peer_url: Url::parse("http://dave.example").unwrap(),
+    username: Username::from_str("dave").unwrap(),
+    asset_code: "XYZ".to_string(),
+    asset_scale: 9,
+    ilp_over_http_incoming_token: "dave_token".to_string(),
+    callback_token: "callback".to_string(),
+    sent_at: 0,
+});
+
+#[async_trait]
+impl PeeringStore for TestStore {
+    async fn save_peering_request(&self, request: PeeringRequest) -> Result<(), NodeStoreError> {
+        SAVED_PEERING_REQUESTS.lock().unwrap().push(request.id);
+        Ok(())
+    }
+
+    async fn get_peering_requests(&self) -> Result<Vec<PeeringRequest>, NodeStoreError> {
+        Ok(vec![PEERING_REQUEST.clone()])
+    }
+
+    async fn delete_peering_request(
+        &self,
+        id: &str,
+    ) -> Result<Option<PeeringRequest>, NodeStoreError> {
+        Ok(Some(PEERING_REQUEST.clone()).filter(|request| request.id == id))
+    }
+
+    async fn save_peering_proposal(
+        &self,
+        _proposal: PeeringProposal,
+    ) -> Result<(), NodeStoreError> {
+        Ok(())
+    }
+
+    async fn get_peering_proposal(
+        &self,
+        id: &str,
+    ) -> Result<Option<PeeringProposal>, NodeStoreError> {
+        Ok(Some(PEERING_PROPOSAL.clone()).filter(|proposal| proposal.id == id))
+    }
+
+    async fn get_peering_proposals(&self) -> Result<Vec<PeeringProposal>, NodeStoreError> {
+        Ok(vec![PEERING_PROPOSAL.clone()])
+    }
+
+    async fn delete_peering_proposal(
+        &self,
+        id: &str,
+    ) -> Result<Option<PeeringProposal>, NodeStoreError> {
+        self.get_peering_proposal(id).await
+    }
+}
+
+#[async_trait]
+impl ReceiptVerifierStore for TestStore {
+    async fn save_receipt_nonce(
+        &self,
+        _nonce: [u8; 16],
+        _path: String,
+    ) -> Result<(), NodeStoreError> {
+        Ok(())
+    }
+
+    // The receipts are credited to the donations as if no others were verified before
+    async fn credit_receipt(
+        &self,
+        receipt: &Receipt,
+    ) -> Result<Option<ReceiptCredit>, NodeStoreError> {
+        Ok(Some(ReceiptCredit {
+            path: DONATIONS.path.clone(),
+            amount: receipt.total_received,
+            balance: receipt.total_received,
+        }))
+    }
+
+    async fn get_payment_pointer_balance(&self, _path: &str) -> Result<u64, NodeStoreError> {
+        Ok(0)
+    }
 }
 
 #[async_trait]
@@ -0,0 +1,501 @@
+use crate::{number_or_string, optional_number_or_string};
+use async_trait::async_trait;
+use interledger_api_types::get_default_max_slippage;
+use interledger_errors::{ApiError, NodeStoreError};
+use interledger_rates::ExchangeRateStore;
+use interledger_service::{Account, AccountStore, IncomingService, Username};
+use interledger_spsp::pay;
+use interledger_stream::{
+    MaxPacketAmountStore, StreamDelivery, UnreachableDestinationStore, MAX_MEMO_LENGTH,
+};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tracing::{debug, error, warn};
+use url::Url;
+
+/// How long the node waits for the notification URLs to accept the outcomes
+const NOTIFICATION_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often a scheduled payment is repeated after its first execution
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Recurrence {
+    /// Time between two executions, in seconds
+    #[serde(deserialize_with = "number_or_string")]
+    pub interval: u64,
+    /// The max number of executions, including the first one, if there is one
+    #[serde(
+        default,
+        deserialize_with = "optional_number_or_string",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub count: Option<u64>,
+    /// The time after which the payment is no longer executed, as seconds since the
+    /// UNIX epoch, if there is one
+    #[serde(
+        default,
+        deserialize_with = "optional_number_or_string",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub until: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScheduledPaymentStatus {
+    /// The payment is waiting for its next execution
+    Scheduled,
+    /// The payment was executed as many times as its recurrence allows
+    Completed,
+}
+
+/// The outcome of an execution of a scheduled payment, which is also POSTed to the
+/// payment's notification URL
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScheduledPaymentOutcome {
+    /// The id of the scheduled payment
+    pub id: String,
+    pub username: Username,
+    /// The number of the execution, starting at 1
+    pub execution: u64,
+    /// When the execution started, as seconds since the UNIX epoch
+    pub executed_at: u64,
+    /// The receipt of the payment, if it was sent (it may still have delivered less than
+    /// its source amount)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delivery: Option<StreamDelivery>,
+    /// Why the payment could not be sent, if it failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// The memo sent with the payment, if there is one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
+}
+
+/// An SPSP payment which the node sends from an account at a later time, and possibly
+/// repeats on an interval
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScheduledPayment {
+    /// Random identifier of the scheduled payment, with which it is cancelled
+    pub id: String,
+    /// The account the payment is sent from
+    pub username: Username,
+    /// The payment pointer or SPSP URL of the receiver
+    pub receiver: String,
+    /// The amount to send at each execution, in the account's units
+    pub source_amount: u64,
+    /// The max slippage of the exchange rate, as a fraction
+    pub slippage: f64,
+    /// When the payment is executed next, as seconds since the UNIX epoch
+    /// (None once it is completed)
+    pub next_execution: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recurrence: Option<Recurrence>,
+    /// URL to which the outcome of each execution is POSTed as JSON, if there is one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notification_url: Option<Url>,
+    /// The reference sent to the receiver with each execution, if there is one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
+    pub status: ScheduledPaymentStatus,
+    /// The number of executions so far
+    pub executions: u64,
+    /// The outcome of the last execution, if there was one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_outcome: Option<ScheduledPaymentOutcome>,
+}
+
+/// A payment to be scheduled (the body of `POST /accounts/:username/payments/scheduled`)
+#[derive(Debug, Clone, Deserialize)]
+pub struct ScheduledPaymentRequest {
+    pub receiver: String,
+    #[serde(deserialize_with = "number_or_string")]
+    pub source_amount: u64,
+    #[serde(
+        default = "get_default_max_slippage",
+        deserialize_with = "number_or_string"
+    )]
+    pub slippage: f64,
+    /// When the payment is first executed, as seconds since the UNIX epoch
+    #[serde(deserialize_with = "number_or_string")]
+    pub execute_at: u64,
+    #[serde(default)]
+    pub recurrence: Option<Recurrence>,
+    #[serde(default)]
+    pub notification_url: Option<Url>,
+    #[serde(default)]
+    pub memo: Option<String>,
+}
+
+impl ScheduledPaymentRequest {
+    /// Checks that the payment would be executed at least once
+    pub fn validate(&self) -> Result<(), ApiError> {
+        if self.source_amount == 0 {
+            return Err(ApiError::bad_request()
+                .detail("the source amount of a scheduled payment must be positive"));
+        }
+        if self.memo.as_ref().map_or(0, String::len) > MAX_MEMO_LENGTH {
+            return Err(ApiError::bad_request().detail(format!(
+                "the memo may not be longer than {} bytes",
+                MAX_MEMO_LENGTH
+            )));
+        }
+        if let Some(recurrence) = &self.recurrence {
+            if recurrence.interval == 0 || recurrence.count == Some(0) {
+                return Err(ApiError::bad_request()
+                    .detail("the interval and count of a recurrence must be positive"));
+            }
+            if recurrence
+                .until
+                .map_or(false, |until| until < self.execute_at)
+            {
+                return Err(ApiError::bad_request()
+                    .detail("the recurrence ends before the first execution"));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ScheduledPayment {
+    pub fn new(id: String, username: Username, request: ScheduledPaymentRequest) -> Self {
+        ScheduledPayment {
+            id,
+            username,
+            receiver: request.receiver,
+            source_amount: request.source_amount,
+            slippage: request.slippage,
+            next_execution: Some(request.execute_at),
+            recurrence: request.recurrence,
+            notification_url: request.notification_url,
+            memo: request.memo,
+            status: ScheduledPaymentStatus::Scheduled,
+            executions: 0,
+            last_outcome: None,
+        }
+    }
+
+    /// Returns whether the payment is due at the time (in seconds since the UNIX epoch)
+    pub fn is_due(&self, now: u64) -> bool {
+        self.status == ScheduledPaymentStatus::Scheduled
+            && self.next_execution.map_or(false, |next| next <= now)
+    }
+
+    /// Starts an execution at the time, and moves the next one to the first time of
+    /// the recurrence after it (the payment is completed if there is none). The
+    /// executions missed while the node was down are skipped rather than sent all at once.
+    pub fn start_execution(&mut self, now: u64) {
+        self.executions += 1;
+        self.next_execution = match (&self.recurrence, self.next_execution) {
+            (Some(recurrence), Some(next)) => {
+                let missed = now.saturating_sub(next) / recurrence.interval;
+                // The recurrence ends if its next time is past the end of time
+                let next = recurrence
+                    .interval
+                    .checked_mul(missed + 1)
+                    .and_then(|delay| next.checked_add(delay));
+                let exhausted = recurrence
+                    .count
+                    .map_or(false, |count| self.executions >= count);
+                next.filter(|&next| {
+                    !exhausted && recurrence.until.map_or(true, |until| next <= until)
+                })
+            }
+            _ => None,
+        };
+        if self.next_execution.is_none() {
+            self.status = ScheduledPaymentStatus::Completed;
+        }
+    }
+
+    /// Records the outcome of the execution
+    pub fn finish_execution(&mut self, outcome: ScheduledPaymentOutcome) {
+        self.last_outcome = Some(outcome);
+    }
+}
+
+/// Store of the payments scheduled by the node's accounts
+#[async_trait]
+pub trait ScheduledPaymentStore {
+    /// Saves a new scheduled payment
+    async fn create_scheduled_payment(
+        &self,
+        payment: ScheduledPayment,
+    ) -> Result<(), NodeStoreError>;
+
+    /// Returns the scheduled payment with the id, if there is one
+    async fn get_scheduled_payment(
+        &self,
+        id: &str,
+    ) -> Result<Option<ScheduledPayment>, NodeStoreError>;
+
+    /// Returns all scheduled payments, ordered by id
+    async fn get_scheduled_payments(&self) -> Result<Vec<ScheduledPayment>, NodeStoreError>;
+
+    /// Overwrites the scheduled payment, unless it was cancelled in the meantime.
+    /// Returns whether it was updated.
+    async fn update_scheduled_payment(
+        &self,
+        payment: ScheduledPayment,
+    ) -> Result<bool, NodeStoreError>;
+
+    /// Cancels the scheduled payment with the id by deleting it, and returns it
+    async fn delete_scheduled_payment(
+        &self,
+        id: &str,
+    ) -> Result<Option<ScheduledPayment>, NodeStoreError>;
+}
+
+/// Sends the scheduled payments which are due at the time (in seconds since the UNIX
+/// epoch) via the node's STREAM sender, and notifies their outcomes. Each execution is
+/// recorded before the payment is sent, so that it is not sent twice if the node stops
+/// in the meantime. Returns the number of payments sent.
+pub async fn execute_scheduled_payments<I, S, A>(
+    incoming_handler: I,
+    store: S,
+    now: u64,
+) -> Result<usize, NodeStoreError>
+where
+    I: IncomingService<A> + Clone + Send + Sync + 'static,
+    S: ScheduledPaymentStore
+        + AccountStore<Account = A>
+        + ExchangeRateStore
+        + MaxPacketAmountStore
+        + UnreachableDestinationStore
+        + Clone
+        + Send
+        + Sync
+        + 'static,
+    A: Account + Send + Sync + 'static,
+{
+    let due: Vec<ScheduledPayment> = store
+        .get_scheduled_payments()
+        .await?
+        .into_iter()
+        .filter(|payment| payment.is_due(now))
+        .collect();
+    let client = Client::new();
+    let mut executed = 0;
+    for mut payment in due {
+        payment.start_execution(now);
+        if !store.update_scheduled_payment(payment.clone()).await? {
+            // It was cancelled since it was listed
+            continue;
+        }
+        executed += 1;
+
+        let result = match get_account(&store, &payment.username).await {
+            Ok(account) => pay(
+                incoming_handler.clone(),
+                account,
+                store.clone(),
+                &payment.receiver,
+                payment.source_amount,
+                payment.slippage,
+                payment.memo.clone(),
+            )
+            .await
+            .map_err(|err| err.to_string()),
+            Err(err) => Err(err.to_string()),
+        };
+        let outcome = match result {
+            Ok(delivery) => {
+                debug!(
+                    "Sent scheduled payment {} of {}: {:?}",
+                    payment.id, payment.username, delivery
+                );
+                ScheduledPaymentOutcome {
+                    id: payment.id.clone(),
+                    username: payment.username.clone(),
+                    execution: payment.executions,
+                    executed_at: now,
+                    delivery: Some(delivery),
+                    error: None,
+                    memo: payment.memo.clone(),
+                }
+            }
+            Err(err) => {
+                error!(
+                    "Error sending scheduled payment {} of {}: {}",
+                    payment.id, payment.username, err
+                );
+                ScheduledPaymentOutcome {
+                    id: payment.id.clone(),
+                    username: payment.username.clone(),
+                    execution: payment.executions,
+                    executed_at: now,
+                    delivery: None,
+                    error: Some(err),
+                    memo: payment.memo.clone(),
+                }
+            }
+        };
+
+        payment.finish_execution(outcome.clone());
+        store.update_scheduled_payment(payment.clone()).await?;
+        if let Some(url) = payment.notification_url {
+            notify_outcome(&client, url, &outcome, &outcome.id).await;
+        }
+    }
+    Ok(executed)
+}
+
+async fn get_account<S, A>(store: &S, username: &Username) -> Result<A, NodeStoreError>
+where
+    S: AccountStore<Account = A>,
+    A: Account,
+{
+    let id = store.get_account_id_from_username(username).await?;
+    store
+        .get_accounts(vec![id])
+        .await?
+        .pop()
+        .ok_or_else(|| NodeStoreError::AccountNotFound(username.to_string()))
+}
+
+/// POSTs the outcome of the payment with the id to the notification URL. The failures are
+/// only logged, since the outcome is also kept in the store.
+pub(crate) async fn notify_outcome<T: Serialize>(client: &Client, url: Url, outcome: &T, id: &str) {
+    let result = client
+        .post(url.as_ref())
+        .timeout(NOTIFICATION_TIMEOUT)
+        .json(outcome)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status());
+    if let Err(err) = result {
+        warn!(
+            "Error notifying the outcome of payment {} to {}: {}",
+            id, url, err
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn scheduled(recurrence: Option<Recurrence>) -> ScheduledPayment {
+        ScheduledPayment::new(
+            "id".to_string(),
+            Username::from_str("alice").unwrap(),
+            ScheduledPaymentRequest {
+                receiver: "$example.com/bob".to_string(),
+                source_amount: 100,
+                slippage: 0.015,
+                execute_at: 1000,
+                recurrence,
+                notification_url: None,
+                memo: None,
+            },
+        )
+    }
+
+    fn outcome(payment: &ScheduledPayment) -> ScheduledPaymentOutcome {
+        ScheduledPaymentOutcome {
+            id: payment.id.clone(),
+            username: payment.username.clone(),
+            execution: payment.executions,
+            executed_at: 0,
+            delivery: None,
+            error: None,
+            memo: None,
+        }
+    }
+
+    #[test]
+    fn executes_once_without_recurrence() {
+        let mut payment = scheduled(None);
+        assert!(!payment.is_due(999));
+        assert!(payment.is_due(1000));
+        payment.start_execution(1000);
+        assert_eq!(payment.status, ScheduledPaymentStatus::Completed);
+        assert!(!payment.is_due(1000));
+        payment.finish_execution(outcome(&payment));
+        assert_eq!(payment.last_outcome, Some(outcome(&payment)));
+        assert_eq!(payment.next_execution, None);
+        assert_eq!(payment.executions, 1);
+    }
+
+    #[test]
+    fn repeats_until_the_recurrence_ends() {
+        let mut payment = scheduled(Some(Recurrence {
+            interval: 60,
+            count: Some(3),
+            until: None,
+        }));
+        payment.start_execution(1000);
+        payment.finish_execution(outcome(&payment));
+        assert_eq!(payment.next_execution, Some(1060));
+        // The executions missed in the meantime are skipped
+        payment.start_execution(1150);
+        payment.finish_execution(outcome(&payment));
+        assert_eq!(payment.next_execution, Some(1180));
+        payment.start_execution(1180);
+        payment.finish_execution(outcome(&payment));
+        assert_eq!(payment.status, ScheduledPaymentStatus::Completed);
+        assert_eq!(payment.executions, 3);
+
+        let mut payment = scheduled(Some(Recurrence {
+            interval: 60,
+            count: None,
+            until: Some(1100),
+        }));
+        payment.start_execution(1000);
+        assert_eq!(payment.next_execution, Some(1060));
+        payment.start_execution(1060);
+        assert_eq!(payment.next_execution, None);
+    }
+
+    #[test]
+    fn ends_the_recurrence_after_the_end_of_time() {
+        let mut payment = scheduled(Some(Recurrence {
+            interval: u64::MAX - 500,
+            count: None,
+            until: None,
+        }));
+        payment.start_execution(1000);
+        assert_eq!(payment.next_execution, None);
+        assert_eq!(payment.status, ScheduledPaymentStatus::Completed);
+
+        // Or after skipping the missed executions
+        let mut payment = scheduled(Some(Recurrence {
+            interval: u64::MAX / 2,
+            count: None,
+            until: None,
+        }));
+        payment.start_execution(u64::MAX / 2 + 1500);
+        assert_eq!(payment.next_execution, None);
+    }
+
+    #[test]
+    fn validates_requests() {
+        let request = |recurrence| ScheduledPaymentRequest {
+            receiver: "$example.com/bob".to_string(),
+            source_amount: 100,
+            slippage: 0.015,
+            execute_at: 1000,
+            recurrence,
+            notification_url: None,
+            memo: None,
+        };
+        assert!(request(None).validate().is_ok());
+        assert!(ScheduledPaymentRequest {
+            memo: Some("x".repeat(MAX_MEMO_LENGTH + 1)),
+            ..request(None)
+        }
+        .validate()
+        .is_err());
+        for recurrence in &[(0, None, None), (60, Some(0), None), (60, None, Some(999))] {
+            let (interval, count, until) = *recurrence;
+            assert!(request(Some(Recurrence {
+                interval,
+                count,
+                until
+            }))
+            .validate()
+            .is_err());
+        }
+    }
+}
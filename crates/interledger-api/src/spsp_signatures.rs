@@ -0,0 +1,136 @@
+use crate::payment_receipts::ReceiptPublicKey;
+use ring::{
+    hmac,
+    signature::{Ed25519KeyPair, KeyPair, UnparsedPublicKey, ED25519},
+};
+use std::sync::Arc;
+
+/// Path at which the public key verifying the signatures of the SPSP responses is published
+pub const SPSP_KEY_PATH: &str = "/.well-known/spsp-key";
+
+/// Header of the SPSP responses with the node's signature of them
+pub const SPSP_SIGNATURE_HEADER: &str = "SPSP-Signature";
+
+static SPSP_RESPONSE_KEY_GENERATOR: &[u8] = b"ilp_spsp_response_key_generator";
+
+/// Signs the SPSP responses served by the node, so that a client which fetched them through
+/// a proxy or a CDN can check that the node served them for the path it asked for. The key
+/// is derived from the server secret, so that it stays the same across restarts, and is not
+/// the one signing the payment receipts.
+#[derive(Clone)]
+pub struct SpspResponseSigner {
+    key_pair: Arc<Ed25519KeyPair>,
+}
+
+impl SpspResponseSigner {
+    pub fn new(server_secret: &[u8]) -> Self {
+        let key = hmac::Key::new(hmac::HMAC_SHA256, server_secret);
+        let seed = hmac::sign(&key, SPSP_RESPONSE_KEY_GENERATOR);
+        let key_pair = Ed25519KeyPair::from_seed_unchecked(seed.as_ref())
+            .expect("HMAC-SHA256 outputs are valid Ed25519 seeds");
+        SpspResponseSigner {
+            key_pair: Arc::new(key_pair),
+        }
+    }
+
+    pub fn public_key(&self) -> ReceiptPublicKey {
+        ReceiptPublicKey {
+            algorithm: "Ed25519".to_string(),
+            public_key: base64::encode_config(
+                self.key_pair.public_key().as_ref(),
+                base64::URL_SAFE_NO_PAD,
+            ),
+        }
+    }
+
+    /// Returns the base64url-encoded signature of the response to a request of the path
+    pub fn sign(&self, path: &str, body: &[u8]) -> String {
+        let signature = self.key_pair.sign(&signed_message(path, body));
+        base64::encode_config(signature.as_ref(), base64::URL_SAFE_NO_PAD)
+    }
+}
+
+/// Returns whether the signature of the response to a request of the path was made with
+/// the (raw, 32 bytes) public key
+pub fn verify_spsp_response(public_key: &[u8], path: &str, body: &[u8], signature: &str) -> bool {
+    match base64::decode_config(signature, base64::URL_SAFE_NO_PAD) {
+        Ok(signature) => UnparsedPublicKey::new(&ED25519, public_key)
+            .verify(&signed_message(path, body), &signature)
+            .is_ok(),
+        Err(_) => false,
+    }
+}
+
+/// The path and the body of the response, separated by a newline (which a path cannot
+/// contain), so that a response cannot be replayed for another payment pointer
+fn signed_message(path: &str, body: &[u8]) -> Vec<u8> {
+    let mut message = Vec::with_capacity(path.len() + 1 + body.len());
+    message.extend_from_slice(path.as_bytes());
+    message.push(b'\n');
+    message.extend_from_slice(body);
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::payment_receipts::PaymentReceiptSigner;
+
+    fn public_key(signer: &SpspResponseSigner) -> Vec<u8> {
+        base64::decode_config(&signer.public_key().public_key, base64::URL_SAFE_NO_PAD).unwrap()
+    }
+
+    #[test]
+    fn verifies_the_signed_responses() {
+        let signer = SpspResponseSigner::new(&[0; 32]);
+        let body = br#"{"destination_account":"example.alice.abc","shared_secret":"AAAA"}"#;
+        let signature = signer.sign("/alice", body);
+        assert!(verify_spsp_response(
+            &public_key(&signer),
+            "/alice",
+            body,
+            &signature
+        ));
+
+        // The key is the same for the same server secret only
+        assert_eq!(
+            SpspResponseSigner::new(&[0; 32]).public_key(),
+            signer.public_key()
+        );
+        let other = SpspResponseSigner::new(&[1; 32]);
+        assert!(!verify_spsp_response(
+            &public_key(&other),
+            "/alice",
+            body,
+            &signature
+        ));
+        // And it is not the key of the payment receipts
+        assert_ne!(
+            PaymentReceiptSigner::new(&[0; 32]).public_key(),
+            signer.public_key()
+        );
+    }
+
+    #[test]
+    fn detects_tampered_or_replayed_responses() {
+        let signer = SpspResponseSigner::new(&[0; 32]);
+        let public_key = public_key(&signer);
+        let body = br#"{"destination_account":"example.alice.abc","shared_secret":"AAAA"}"#;
+        let signature = signer.sign("/alice", body);
+
+        let tampered = br#"{"destination_account":"example.mallory.abc","shared_secret":"AAAA"}"#;
+        assert!(!verify_spsp_response(
+            &public_key,
+            "/alice",
+            tampered,
+            &signature
+        ));
+        assert!(!verify_spsp_response(&public_key, "/bob", body, &signature));
+        assert!(!verify_spsp_response(
+            &public_key,
+            "/alice",
+            body,
+            "not a signature"
+        ));
+    }
+}
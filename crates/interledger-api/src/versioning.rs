@@ -0,0 +1,102 @@
+use warp::{
+    filters::BoxedFilter,
+    http::HeaderValue,
+    path::FullPath,
+    reply::{Reply, Response},
+    Filter,
+};
+
+/// The path prefix of the current version of the API
+pub const CURRENT_API_VERSION: &str = "v1";
+
+/// Serves the API under the prefix of its current version (`/v1`), and under its
+/// unversioned paths for the clients which predate the versioning. The responses of the
+/// unversioned paths (other than `GET /`, which clients call to discover the versions)
+/// have a `Deprecation` header, a `Link` to the versioned path, and a `Sunset` header with
+/// the date after which they may be removed, if it is set (as an HTTP date, such as
+/// `Sat, 31 Dec 2022 23:59:59 GMT`).
+pub fn versioned_api<R>(
+    api: BoxedFilter<(R,)>,
+    sunset: Option<HeaderValue>,
+) -> BoxedFilter<(Response,)>
+where
+    R: Reply + 'static,
+{
+    let current = warp::path(CURRENT_API_VERSION)
+        .and(api.clone())
+        .map(Reply::into_response);
+    let unversioned = warp::path::full()
+        .and(api)
+        .map(move |path: FullPath, reply: R| {
+            let mut response = reply.into_response();
+            if path.as_str() == "/" {
+                return response;
+            }
+            let headers = response.headers_mut();
+            headers.insert("deprecation", HeaderValue::from_static("true"));
+            if let Ok(link) = HeaderValue::from_str(&format!(
+                "</{}{}>; rel=\"successor-version\"",
+                CURRENT_API_VERSION,
+                path.as_str()
+            )) {
+                headers.insert("link", link);
+            }
+            if let Some(ref sunset) = sunset {
+                headers.insert("sunset", sunset.clone());
+            }
+            response
+        });
+    current.or(unversioned).unify().boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn api() -> BoxedFilter<(Response,)> {
+        let routes = warp::get()
+            .and(warp::path::end())
+            .map(|| "root")
+            .or(warp::get()
+                .and(warp::path("accounts"))
+                .and(warp::path::end())
+                .map(|| "accounts"))
+            .unify()
+            .boxed();
+        versioned_api(
+            routes,
+            Some(HeaderValue::from_static("Sat, 31 Dec 2022 23:59:59 GMT")),
+        )
+    }
+
+    #[tokio::test]
+    async fn serves_the_current_version() {
+        let resp = warp::test::request()
+            .path("/v1/accounts")
+            .reply(&api())
+            .await;
+        assert_eq!(resp.status().as_u16(), 200);
+        assert_eq!(resp.body(), "accounts");
+        assert!(resp.headers().get("deprecation").is_none());
+
+        let resp = warp::test::request().path("/v1").reply(&api()).await;
+        assert_eq!(resp.body(), "root");
+    }
+
+    #[tokio::test]
+    async fn deprecates_the_unversioned_paths() {
+        let resp = warp::test::request().path("/accounts").reply(&api()).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        assert_eq!(resp.body(), "accounts");
+        assert_eq!(resp.headers()["deprecation"], "true");
+        assert_eq!(
+            resp.headers()["link"],
+            "</v1/accounts>; rel=\"successor-version\""
+        );
+        assert_eq!(resp.headers()["sunset"], "Sat, 31 Dec 2022 23:59:59 GMT");
+
+        let resp = warp::test::request().path("/").reply(&api()).await;
+        assert_eq!(resp.body(), "root");
+        assert!(resp.headers().get("deprecation").is_none());
+    }
+}
@@ -1,3 +1,4 @@
+use super::legacy;
 use super::packet::*;
 use super::service::BtpOutgoingService;
 use super::BtpAccount;
@@ -89,8 +90,23 @@ where
     }
     let token = account
         .get_ilp_over_btp_outgoing_token()
-        .map(|s| s.to_vec())
-        .unwrap_or_default();
+        .map(|s| s.to_vec());
+    let protocol_data = if account.is_legacy_btp_peer() {
+        legacy::auth_protocol_data(&mut url, token)
+    } else {
+        vec![
+            ProtocolData {
+                protocol_name: String::from("auth"),
+                content_type: ContentType::ApplicationOctetStream,
+                data: vec![],
+            },
+            ProtocolData {
+                protocol_name: String::from("auth_token"),
+                content_type: ContentType::TextPlainUtf8,
+                data: token.unwrap_or_default(),
+            },
+        ]
+    };
     debug!("Connecting to {}", url);
 
     let (mut connection, _) = connect_async(url.clone())
@@ -114,18 +130,7 @@ where
     let auth_packet = Message::binary(
         BtpPacket::Message(BtpMessage {
             request_id: random(),
-            protocol_data: vec![
-                ProtocolData {
-                    protocol_name: String::from("auth"),
-                    content_type: ContentType::ApplicationOctetStream,
-                    data: vec![],
-                },
-                ProtocolData {
-                    protocol_name: String::from("auth_token"),
-                    content_type: ContentType::TextPlainUtf8,
-                    data: token,
-                },
-            ],
+            protocol_data,
         })
         .to_bytes(),
     );
@@ -12,6 +12,7 @@ use url::Url;
 
 mod client;
 mod errors;
+mod legacy;
 mod oer;
 mod packet;
 mod server;
@@ -19,6 +20,9 @@ mod service;
 mod wrapped_ws;
 
 pub use self::client::{connect_client, connect_to_service_account};
+pub use self::packet::{
+    BtpError, BtpMessage, BtpPacket, BtpResponse, ContentType, ProtocolData, Serializable,
+};
 pub use self::server::btp_service_as_filter; // This is consumed only by the node.
 pub use self::service::{BtpOutgoingService, BtpService};
 
@@ -31,6 +35,17 @@ pub trait BtpAccount: Account {
     /// Returns the BTP authentication token which is used when initiating a BTP connection
     /// with a peer
     fn get_ilp_over_btp_outgoing_token(&self) -> Option<&[u8]>;
+    /// Returns whether the peer speaks the BTP dialect of the legacy JavaScript plugins
+    /// (`ilp-plugin-btp` and `ilp-plugin-mini-accounts`)
+    fn is_legacy_btp_peer(&self) -> bool {
+        false
+    }
+    /// Returns the username of the account whose BTP connection this account shares, when the
+    /// peer holds an account in each of its currencies. The packets of the account are sent
+    /// on that connection, labelled with the account's asset code.
+    fn get_transport_account(&self) -> Option<&Username> {
+        None
+    }
 }
 
 /// The interface for Store implementations that can be used with the BTP Server.
@@ -47,11 +62,19 @@ pub trait BtpStore {
 
     /// Load accounts that have a ilp_over_btp_url configured
     async fn get_btp_outgoing_accounts(&self) -> Result<Vec<Self::Account>, BtpStoreError>;
+
+    /// Load the accounts whose transport account is the provided one, which hold the
+    /// other currencies of its peer
+    async fn get_sub_accounts(
+        &self,
+        account: &Self::Account,
+    ) -> Result<Vec<Self::Account>, BtpStoreError>;
 }
 
 #[cfg(test)]
 mod client_server {
     use super::*;
+    use futures::{SinkExt, StreamExt};
     use interledger_packet::{Address, ErrorCode, FulfillBuilder, PrepareBuilder, RejectBuilder};
     use interledger_service::*;
     use socket2::{Domain, Socket, Type};
@@ -91,6 +114,9 @@ mod client_server {
         pub ilp_over_btp_incoming_token: Option<String>,
         pub ilp_over_btp_outgoing_token: Option<String>,
         pub ilp_over_btp_url: Option<Url>,
+        pub legacy_btp_peer: bool,
+        pub asset_code: &'static str,
+        pub transport_account: Option<Username>,
     }
 
     impl Account for TestAccount {
@@ -107,7 +133,7 @@ mod client_server {
         }
 
         fn asset_code(&self) -> &str {
-            "XYZ"
+            self.asset_code
         }
 
         fn ilp_address(&self) -> &Address {
@@ -127,8 +153,18 @@ mod client_server {
                 None
             }
         }
+
+        fn is_legacy_btp_peer(&self) -> bool {
+            self.legacy_btp_peer
+        }
+
+        fn get_transport_account(&self) -> Option<&Username> {
+            self.transport_account.as_ref()
+        }
     }
 
+    impl IpAllowlistAccount for TestAccount {}
+
     #[derive(Clone)]
     pub struct TestStore {
         accounts: Arc<Vec<TestAccount>>,
@@ -164,6 +200,20 @@ mod client_server {
                 .cloned()
                 .collect())
         }
+
+        async fn get_sub_accounts(
+            &self,
+            account: &TestAccount,
+        ) -> Result<Vec<TestAccount>, BtpStoreError> {
+            Ok(self
+                .accounts
+                .iter()
+                .filter(|sub_account| {
+                    sub_account.transport_account.as_ref() == Some(account.username())
+                })
+                .cloned()
+                .collect())
+        }
     }
 
     // TODO should this be an integration test, since it binds to a port?
@@ -178,6 +228,9 @@ mod client_server {
                 ilp_over_btp_incoming_token: Some("test_auth_token".to_string()),
                 ilp_over_btp_outgoing_token: None,
                 ilp_over_btp_url: None,
+                legacy_btp_peer: false,
+                asset_code: "XYZ",
+                transport_account: None,
             }]),
         };
         let server_address = Address::from_str("example.server").unwrap();
@@ -203,7 +256,12 @@ mod client_server {
                 .build())
             }))
             .await;
-        let filter = btp_service_as_filter(btp_service.clone(), server_store);
+        let filter = btp_service_as_filter(
+            btp_service.clone(),
+            server_store,
+            PeerBlocklist::default(),
+            TrustedProxies::default(),
+        );
         let server = warp::serve(filter);
         // Spawn the server and listen for incoming connections
         tokio::spawn(server.bind(bind_addr));
@@ -216,6 +274,9 @@ mod client_server {
             ),
             ilp_over_btp_outgoing_token: Some("test_auth_token".to_string()),
             ilp_over_btp_incoming_token: None,
+            legacy_btp_peer: false,
+            asset_code: "XYZ",
+            transport_account: None,
         };
         let accounts = vec![account.clone()];
         let addr = Address::from_str("example.address").unwrap();
@@ -310,4 +371,315 @@ mod client_server {
 
         btp_service.close();
     }
+
+    #[tokio::test]
+    async fn sub_accounts_share_connection() {
+        let bind_addr = get_open_port();
+        let server_account = TestAccount {
+            id: Uuid::new_v4(),
+            ilp_over_btp_incoming_token: Some("test_auth_token".to_string()),
+            ilp_over_btp_outgoing_token: None,
+            ilp_over_btp_url: None,
+            legacy_btp_peer: false,
+            asset_code: "XYZ",
+            transport_account: None,
+        };
+        let server_store = TestStore {
+            accounts: Arc::new(vec![
+                server_account.clone(),
+                TestAccount {
+                    id: Uuid::new_v4(),
+                    ilp_over_btp_incoming_token: None,
+                    asset_code: "ABC",
+                    transport_account: Some(ALICE.clone()),
+                    ..server_account
+                },
+            ]),
+        };
+        let server_address = Address::from_str("example.server").unwrap();
+        let btp_service = BtpOutgoingService::new(
+            server_address.clone(),
+            outgoing_service_fn(move |_| {
+                Err(RejectBuilder {
+                    code: ErrorCode::F02_UNREACHABLE,
+                    message: b"No other outgoing handler",
+                    triggered_by: Some(&server_address),
+                    data: &[],
+                }
+                .build())
+            }),
+        );
+        // The server fulfills the packets with the asset code of the account they came from
+        btp_service
+            .clone()
+            .handle_incoming(incoming_service_fn(
+                |request: IncomingRequest<TestAccount>| {
+                    Ok(FulfillBuilder {
+                        fulfillment: &[0; 32],
+                        data: request.from.asset_code().as_bytes(),
+                    }
+                    .build())
+                },
+            ))
+            .await;
+        let filter = btp_service_as_filter(
+            btp_service.clone(),
+            server_store,
+            PeerBlocklist::default(),
+            TrustedProxies::default(),
+        );
+        tokio::spawn(warp::serve(filter).bind(bind_addr));
+
+        let account = TestAccount {
+            id: Uuid::new_v4(),
+            ilp_over_btp_url: Some(
+                Url::parse(&format!("btp+ws://{}/accounts/alice/ilp/btp", bind_addr)).unwrap(),
+            ),
+            ilp_over_btp_outgoing_token: Some("test_auth_token".to_string()),
+            ilp_over_btp_incoming_token: None,
+            legacy_btp_peer: false,
+            asset_code: "XYZ",
+            transport_account: None,
+        };
+        let sub_account = |asset_code| TestAccount {
+            id: Uuid::new_v4(),
+            ilp_over_btp_url: None,
+            ilp_over_btp_outgoing_token: None,
+            asset_code,
+            transport_account: Some(ALICE.clone()),
+            ..account.clone()
+        };
+        let addr = Address::from_str("example.address").unwrap();
+        let btp_client = connect_client(
+            addr.clone(),
+            vec![account.clone()],
+            true,
+            outgoing_service_fn(move |_| {
+                Err(RejectBuilder {
+                    code: ErrorCode::F02_UNREACHABLE,
+                    message: &[],
+                    data: &[],
+                    triggered_by: Some(&addr),
+                }
+                .build())
+            }),
+        )
+        .await
+        .unwrap();
+        let btp_client = btp_client
+            .handle_incoming(incoming_service_fn(|_| {
+                Err(RejectBuilder {
+                    code: ErrorCode::F02_UNREACHABLE,
+                    message: &[],
+                    data: &[],
+                    triggered_by: None,
+                }
+                .build())
+            }))
+            .await;
+
+        let send_to = |to: TestAccount| {
+            let mut btp_client = btp_client.clone();
+            async move {
+                btp_client
+                    .send_request(OutgoingRequest {
+                        from: to.clone(),
+                        to,
+                        original_amount: 100,
+                        prepare: PrepareBuilder {
+                            destination: Address::from_str("example.destination").unwrap(),
+                            amount: 100,
+                            execution_condition: &[0; 32],
+                            expires_at: SystemTime::now() + Duration::from_secs(30),
+                            data: &[],
+                        }
+                        .build(),
+                    })
+                    .await
+            }
+        };
+        let fulfill = send_to(account.clone()).await.unwrap();
+        assert_eq!(fulfill.data(), b"XYZ");
+        let fulfill = send_to(sub_account("ABC")).await.unwrap();
+        assert_eq!(fulfill.data(), b"ABC");
+
+        // The server has no account for the other assets of the peer
+        let reject = send_to(sub_account("DEF")).await.unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::F02_UNREACHABLE);
+
+        btp_client.close();
+        btp_service.close();
+    }
+
+    trait LegacyConnection:
+        futures::Stream<Item = Result<tungstenite::Message, tungstenite::Error>>
+        + futures::Sink<tungstenite::Message, Error = tungstenite::Error>
+        + Unpin
+    {
+    }
+
+    impl<T> LegacyConnection for T where
+        T: futures::Stream<Item = Result<tungstenite::Message, tungstenite::Error>>
+            + futures::Sink<tungstenite::Message, Error = tungstenite::Error>
+            + Unpin
+    {
+    }
+
+    /// Connects to the server like the JavaScript plugins, and returns the first binary message
+    /// it responds with to the auth message
+    async fn connect_legacy_client(
+        bind_addr: SocketAddr,
+        token: &str,
+    ) -> (impl LegacyConnection, BtpPacket) {
+        let url = format!("ws://{}/accounts/alice/ilp/btp", bind_addr);
+        let (mut connection, _) = tokio_tungstenite::connect_async(Url::parse(&url).unwrap())
+            .await
+            .unwrap();
+        let auth = BtpMessage {
+            request_id: 1,
+            protocol_data: vec![
+                ProtocolData {
+                    protocol_name: String::from("auth"),
+                    content_type: ContentType::ApplicationOctetStream,
+                    data: vec![],
+                },
+                ProtocolData {
+                    protocol_name: String::from("auth_username"),
+                    content_type: ContentType::TextPlainUtf8,
+                    data: b"alice".to_vec(),
+                },
+                ProtocolData {
+                    protocol_name: String::from("auth_token"),
+                    content_type: ContentType::TextPlainUtf8,
+                    data: token.as_bytes().to_vec(),
+                },
+            ],
+        };
+        connection
+            .send(tungstenite::Message::binary(auth.to_bytes()))
+            .await
+            .unwrap();
+        let response = next_btp_packet(&mut connection).await;
+        (connection, response)
+    }
+
+    async fn next_btp_packet(connection: &mut impl LegacyConnection) -> BtpPacket {
+        loop {
+            match connection.next().await.unwrap().unwrap() {
+                tungstenite::Message::Binary(data) => return BtpPacket::from_bytes(&data).unwrap(),
+                _ => continue,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn legacy_client_test() {
+        let bind_addr = get_open_port();
+        let server_store = TestStore {
+            accounts: Arc::new(vec![TestAccount {
+                id: Uuid::new_v4(),
+                ilp_over_btp_incoming_token: Some("test_auth_token".to_string()),
+                ilp_over_btp_outgoing_token: None,
+                ilp_over_btp_url: None,
+                legacy_btp_peer: true,
+                asset_code: "XYZ",
+                transport_account: None,
+            }]),
+        };
+        let server_address = Address::from_str("example.server").unwrap();
+        let btp_service = BtpOutgoingService::new(
+            server_address.clone(),
+            outgoing_service_fn(move |_| {
+                Err(RejectBuilder {
+                    code: ErrorCode::F02_UNREACHABLE,
+                    message: b"No other outgoing handler",
+                    triggered_by: Some(&server_address),
+                    data: &[],
+                }
+                .build())
+            }),
+        );
+        btp_service
+            .clone()
+            .handle_incoming(incoming_service_fn(|_| {
+                Ok(FulfillBuilder {
+                    fulfillment: &[0; 32],
+                    data: b"test data",
+                }
+                .build())
+            }))
+            .await;
+        let filter = btp_service_as_filter(
+            btp_service.clone(),
+            server_store,
+            PeerBlocklist::default(),
+            TrustedProxies::default(),
+        );
+        tokio::spawn(warp::serve(filter).bind(bind_addr));
+
+        // Legacy clients are sent an Error when their auth is refused
+        let (_, response) = connect_legacy_client(bind_addr, "wrong_token").await;
+        match response {
+            BtpPacket::Error(error) => {
+                assert_eq!(error.request_id, 1);
+                assert_eq!(error.code, "F00");
+            }
+            packet => panic!("Expected an error, got {:?}", packet),
+        }
+
+        let (mut connection, response) = connect_legacy_client(bind_addr, "test_auth_token").await;
+        assert!(matches!(
+            response,
+            BtpPacket::Response(BtpResponse { request_id: 1, .. })
+        ));
+
+        // Messages of the other sub-protocols are acknowledged
+        let info = BtpMessage {
+            request_id: 2,
+            protocol_data: vec![ProtocolData {
+                protocol_name: String::from("info"),
+                content_type: ContentType::ApplicationOctetStream,
+                data: vec![],
+            }],
+        };
+        connection
+            .send(tungstenite::Message::binary(info.to_bytes()))
+            .await
+            .unwrap();
+        assert!(matches!(
+            next_btp_packet(&mut connection).await,
+            BtpPacket::Response(BtpResponse { request_id: 2, .. })
+        ));
+
+        // and the responses to Prepare packets are sent in BTP Responses
+        let prepare = PrepareBuilder {
+            destination: Address::from_str("example.destination").unwrap(),
+            amount: 100,
+            execution_condition: &[0; 32],
+            expires_at: SystemTime::now() + Duration::from_secs(30),
+            data: b"test data",
+        }
+        .build();
+        let message = BtpMessage {
+            request_id: 3,
+            protocol_data: vec![ProtocolData {
+                protocol_name: String::from("ilp"),
+                content_type: ContentType::ApplicationOctetStream,
+                data: bytes::BytesMut::from(prepare).to_vec(),
+            }],
+        };
+        connection
+            .send(tungstenite::Message::binary(message.to_bytes()))
+            .await
+            .unwrap();
+        match next_btp_packet(&mut connection).await {
+            BtpPacket::Response(response) => {
+                assert_eq!(response.request_id, 3);
+                assert_eq!(response.protocol_data[0].protocol_name, "ilp");
+            }
+            packet => panic!("Expected a response, got {:?}", packet),
+        }
+
+        btp_service.close();
+    }
 }
@@ -20,14 +20,27 @@ pub trait ReadOerExt: Read + ReadBytesExt + Debug {
         let actual_length: u64 = if length & HIGH_BIT != 0 {
             let length_prefix_length = length & LOWER_SEVEN_BITS;
             // TODO check for canonical length
-            self.read_uint::<BigEndian>(length_prefix_length as usize)? as u64
+            if length_prefix_length == 0 || length_prefix_length > 8 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "invalid length prefix",
+                ));
+            }
+            self.read_uint::<BigEndian>(length_prefix_length as usize)?
         } else {
             u64::from(length)
         };
 
-        // TODO handle if the length is too long
-        let mut buf = Vec::with_capacity(actual_length as usize);
+        // The buffer grows as the bytes are read rather than being allocated upfront,
+        // because the length comes from the peer and may be far larger than the message
+        let mut buf = Vec::new();
         self.take(actual_length).read_to_end(&mut buf)?;
+        if (buf.len() as u64) < actual_length {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "octet string longer than the buffer",
+            ));
+        }
         Ok(buf)
     }
 
@@ -47,13 +47,16 @@ pub enum BtpPacket {
 
 impl Serializable<BtpPacket> for BtpPacket {
     fn from_bytes(bytes: &[u8]) -> Result<BtpPacket, ParseError> {
-        match PacketType::from(bytes[0]) {
+        let packet_type = *bytes
+            .first()
+            .ok_or_else(|| ParseError::InvalidPacket("Empty packet".to_string()))?;
+        match PacketType::from(packet_type) {
             PacketType::Message => Ok(BtpPacket::Message(BtpMessage::from_bytes(bytes)?)),
             PacketType::Response => Ok(BtpPacket::Response(BtpResponse::from_bytes(bytes)?)),
             PacketType::Error => Ok(BtpPacket::Error(BtpError::from_bytes(bytes)?)),
             PacketType::Unknown => Err(ParseError::InvalidPacket(format!(
                 "Unknown packet type: {}",
-                bytes[0]
+                packet_type
             ))),
         }
     }
@@ -355,4 +358,114 @@ mod tests {
             assert_eq!(ERROR_1.to_bytes(), *ERROR_1_SERIALIZED);
         }
     }
+
+    /// Random packets round-trip, and mutations of them are parsed or rejected without
+    /// panicking (see the `btp_packet` fuzz target for a deeper search)
+    mod properties {
+        use super::*;
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+
+        const CASES: usize = 2000;
+
+        fn bytes(rng: &mut StdRng, max_len: usize) -> Vec<u8> {
+            let len = rng.gen_range(0, max_len + 1);
+            (0..len).map(|_| rng.gen()).collect()
+        }
+
+        fn text(rng: &mut StdRng, max_len: usize) -> String {
+            let len = rng.gen_range(0, max_len + 1);
+            (0..len)
+                .map(|_| rng.gen_range(b' ', b'~') as char)
+                .collect()
+        }
+
+        fn protocol_data(rng: &mut StdRng) -> Vec<ProtocolData> {
+            (0..rng.gen_range(0, 4))
+                .map(|_| ProtocolData {
+                    protocol_name: text(rng, 20),
+                    content_type: ContentType::from(rng.gen_range(0, 2)),
+                    // Longer than 127 bytes sometimes, for the lengths with a prefix
+                    data: bytes(rng, 300),
+                })
+                .collect()
+        }
+
+        fn packet(rng: &mut StdRng) -> BtpPacket {
+            match rng.gen_range(0, 3) {
+                0 => BtpPacket::Message(BtpMessage {
+                    request_id: rng.gen(),
+                    protocol_data: protocol_data(rng),
+                }),
+                1 => BtpPacket::Response(BtpResponse {
+                    request_id: rng.gen(),
+                    protocol_data: protocol_data(rng),
+                }),
+                _ => BtpPacket::Error(BtpError {
+                    request_id: rng.gen(),
+                    code: (0..3).map(|_| rng.gen_range(b'A', b'Z') as char).collect(),
+                    name: text(rng, 30),
+                    triggered_at: Utc.timestamp_millis(rng.gen_range(0, 253_402_300_799_999)),
+                    data: text(rng, 200),
+                    protocol_data: protocol_data(rng),
+                }),
+            }
+        }
+
+        fn mutate(rng: &mut StdRng, buffer: &[u8]) -> Vec<u8> {
+            let mut buffer = buffer.to_vec();
+            for _ in 0..rng.gen_range(1, 4) {
+                let index = rng.gen_range(0, buffer.len() + 1);
+                match rng.gen_range(0, 5) {
+                    0 if index < buffer.len() => buffer[index] ^= 1 << rng.gen_range(0, 8),
+                    1 if index < buffer.len() => {
+                        buffer[index] =
+                            [0x00, 0x7f, 0x80, 0x81, 0x88, 0x89, 0xff][rng.gen_range(0, 7)]
+                    }
+                    2 => {
+                        let inserted = bytes(rng, 16);
+                        buffer.splice(index..index, inserted);
+                    }
+                    3 if index < buffer.len() => {
+                        buffer.remove(index);
+                    }
+                    _ => buffer.truncate(index),
+                }
+            }
+            buffer
+        }
+
+        #[test]
+        fn packets_round_trip() {
+            let mut rng = StdRng::seed_from_u64(0x0b7b);
+            for _ in 0..CASES {
+                let packet = packet(&mut rng);
+                assert_eq!(BtpPacket::from_bytes(&packet.to_bytes()).unwrap(), packet);
+            }
+        }
+
+        #[test]
+        fn mutated_packets_do_not_panic() {
+            let mut rng = StdRng::seed_from_u64(0x0b7b);
+            for _ in 0..CASES {
+                let valid = packet(&mut rng).to_bytes();
+                let mutated = mutate(&mut rng, &valid);
+                if let Ok(packet) = BtpPacket::from_bytes(&mutated) {
+                    let _ = packet.to_bytes();
+                }
+                let _ = BtpPacket::from_bytes(&bytes(&mut rng, 64));
+            }
+        }
+
+        #[test]
+        fn rejects_empty_packets_and_lengths() {
+            assert!(BtpPacket::from_bytes(&[]).is_err());
+            // A message whose contents have a length prefix of zero bytes
+            assert!(BtpMessage::from_bytes(&[6, 0, 0, 0, 1, 0x80]).is_err());
+            // A message whose contents are longer than the packet
+            assert!(BtpMessage::from_bytes(&[
+                6, 0, 0, 0, 1, 0x88, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff
+            ])
+            .is_err());
+        }
+    }
 }
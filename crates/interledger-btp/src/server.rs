@@ -1,13 +1,18 @@
-use super::{packet::*, BtpAccount, BtpStore};
+use super::{legacy, packet::*, BtpAccount, BtpStore};
 use super::{service::BtpOutgoingService, wrapped_ws::WsWrap};
 use futures::{FutureExt, Sink, Stream};
 use futures::{SinkExt, StreamExt, TryFutureExt};
+use interledger_packet::MAX_PACKET_SIZE;
 use interledger_service::*;
 use secrecy::{ExposeSecret, SecretString};
-use std::time::Duration;
+use std::{
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+};
 use tracing::{debug, error, warn};
 use warp::{
     self,
+    http::HeaderMap,
     ws::{Message, WebSocket, Ws},
     Filter,
 };
@@ -15,38 +20,67 @@ use warp::{
 // Close the incoming websocket connection if the auth details
 // have not been received within this timeout
 const WEBSOCKET_TIMEOUT: Duration = Duration::from_secs(10);
-const MAX_MESSAGE_SIZE: usize = 40000;
+// The BTP messages carry the packets with a few bytes of framing, so that packets of the
+// max size accepted over any transport fit in them
+const MAX_MESSAGE_SIZE: usize = MAX_PACKET_SIZE + 1024;
 
 /// Returns a Warp Filter instantiated for the provided BtpOutgoingService service.
 ///
 /// The warp filter handles the websocket upgrades and adds incoming connections
 /// to the BTP service so that it will handle each of the messages.
+/// Connections for the accounts quarantined by the blocklist are closed, and
+/// authentication failures and invalid auth messages are reported to it. The address
+/// checked against the IP allowlists of the accounts is the one passed on by the trusted
+/// proxies, if the connections come through them.
 pub fn btp_service_as_filter<O, S, A>(
     service: BtpOutgoingService<O, A>,
     store: S,
+    blocklist: PeerBlocklist,
+    trusted_proxies: TrustedProxies,
 ) -> warp::filters::BoxedFilter<(impl warp::Reply,)>
 where
     O: OutgoingService<A> + Clone + Send + Sync + 'static,
     S: BtpStore<Account = A> + Clone + Send + Sync + 'static,
-    A: BtpAccount + Send + Sync + 'static,
+    A: BtpAccount + IpAllowlistAccount + Send + Sync + 'static,
 {
     warp::path("accounts")
         .and(warp::path::param::<Username>())
         .and(warp::path("ilp"))
         .and(warp::path("btp"))
         .and(warp::path::end())
+        .and(warp::addr::remote())
+        .and(warp::header::headers_cloned())
         .and(warp::ws())
-        .map(move |username: Username, ws: Ws| {
-            // warp Websocket
-            let service_clone = service.clone();
-            let store_clone = store.clone();
-            ws.max_message_size(MAX_MESSAGE_SIZE)
-                .on_upgrade(|socket: WebSocket| {
-                    // wrapper over tungstenite Websocket
-                    add_connections(socket, username, service_clone, store_clone)
+        .map(
+            move |username: Username,
+                  remote_addr: Option<SocketAddr>,
+                  headers: HeaderMap,
+                  ws: Ws| {
+                let values = headers
+                    .get_all(trusted_proxies.header.name())
+                    .iter()
+                    .filter_map(|value| value.to_str().ok());
+                let client_ip =
+                    trusted_proxies.client_ip(remote_addr.map(|addr| addr.ip()), values);
+                // warp Websocket
+                let service_clone = service.clone();
+                let store_clone = store.clone();
+                let blocklist_clone = blocklist.clone();
+                ws.max_message_size(MAX_MESSAGE_SIZE)
+                    .on_upgrade(move |socket: WebSocket| {
+                        // wrapper over tungstenite Websocket
+                        add_connections(
+                            socket,
+                            username,
+                            client_ip,
+                            service_clone,
+                            store_clone,
+                            blocklist_clone,
+                        )
                         .map(|result| result.unwrap())
-                })
-        })
+                    })
+            },
+        )
         .boxed()
 }
 
@@ -57,31 +91,51 @@ where
 async fn add_connections<O, S, A>(
     socket: WebSocket,
     username: Username,
+    client_ip: Option<IpAddr>,
     service: BtpOutgoingService<O, A>,
     store: S,
+    blocklist: PeerBlocklist,
 ) -> Result<(), ()>
 where
     O: OutgoingService<A> + Clone + Send + Sync + 'static,
     S: BtpStore<Account = A> + Clone + Send + Sync + 'static,
-    A: BtpAccount + Send + Sync + 'static,
+    A: BtpAccount + IpAllowlistAccount + Send + Sync + 'static,
 {
+    if blocklist.is_address_quarantined(client_ip) {
+        warn!(
+            "Closing Websocket connection from {:?} because the address is quarantined",
+            client_ip
+        );
+        return Ok(());
+    }
+    if blocklist.is_quarantined(&username) {
+        warn!(
+            "Closing Websocket connection for account {} because it is quarantined",
+            username
+        );
+        return Ok(());
+    }
+
     // We ignore all the errors
     let socket = socket.filter_map(|v| async move { v.ok() });
-    let (account, connection) =
-        match tokio::time::timeout(WEBSOCKET_TIMEOUT, validate_auth(store, username, socket)).await
-        {
-            Ok(res) => match res {
-                Ok(res) => res,
-                Err(_) => {
-                    warn!("Closing Websocket connection because of invalid credentials");
-                    return Ok(());
-                }
-            },
+    let (account, connection) = match tokio::time::timeout(
+        WEBSOCKET_TIMEOUT,
+        validate_auth(store.clone(), username, client_ip, socket, blocklist),
+    )
+    .await
+    {
+        Ok(res) => match res {
+            Ok(res) => res,
             Err(_) => {
-                warn!("Closing Websocket connection because of an error");
+                warn!("Closing Websocket connection because of invalid credentials");
                 return Ok(());
             }
-        };
+        },
+        Err(_) => {
+            warn!("Closing Websocket connection because of an error");
+            return Ok(());
+        }
+    };
 
     // We need to wrap our Warp connection in order to cast the Sink type
     // to tungstenite::Message. This probably can be implemented with SinkExt::with
@@ -93,29 +147,77 @@ where
         account.id()
     );
 
+    // The accounts of the peer's other currencies share its connection
+    match store.get_sub_accounts(&account).await {
+        Ok(sub_accounts) => {
+            for sub_account in sub_accounts {
+                service.add_sub_account(sub_account);
+            }
+        }
+        Err(err) => warn!(
+            "Error loading the accounts sharing the connection of account {}: {}",
+            account.username(),
+            err
+        ),
+    }
+
     Ok(())
 }
 
 struct Auth {
     request_id: u32,
     token: SecretString,
+    /// Whether the auth message was sent by a legacy JavaScript plugin
+    legacy: bool,
+}
+
+/// Tells legacy clients that their auth was refused, since they wait for an Error
+/// rather than for the connection to close
+async fn refuse_auth(auth: &Auth, connection: &mut (impl Sink<Message> + Unpin)) {
+    if auth.legacy {
+        let error = Message::binary(legacy::auth_error(auth.request_id).to_bytes());
+        let _ = connection.send(error).await;
+    }
 }
 
 async fn validate_auth<S, A>(
     store: S,
     username: Username,
+    client_ip: Option<IpAddr>,
     connection: impl Stream<Item = Message> + Sink<Message>,
+    blocklist: PeerBlocklist,
 ) -> Result<(A, impl Stream<Item = Message> + Sink<Message>), ()>
 where
     S: BtpStore<Account = A> + 'static,
-    A: BtpAccount + 'static,
+    A: BtpAccount + IpAllowlistAccount + 'static,
 {
-    let (auth, mut connection) = get_auth(Box::pin(connection)).await?;
-    debug!("Got BTP connection for username: {}", username);
-    let account = store
-        .get_account_from_btp_auth(&username, &auth.token.expose_secret())
-        .map_err(move |_| warn!("BTP connection does not correspond to an account"))
+    let (auth, mut connection) = get_auth(Box::pin(connection))
+        .map_err(|_| {
+            blocklist.record_address(client_ip, Misbehavior::MalformedPacket);
+        })
         .await?;
+    debug!("Got BTP connection for username: {}", username);
+    let account = match store
+        .get_account_from_btp_auth(&username, auth.token.expose_secret())
+        .await
+    {
+        Ok(account) => account,
+        Err(_) => {
+            blocklist.record_address(client_ip, Misbehavior::AuthFailure);
+            warn!("BTP connection does not correspond to an account");
+            refuse_auth(&auth, &mut connection).await;
+            return Err(());
+        }
+    };
+    if !account.is_ip_allowed(client_ip) {
+        blocklist.record_address(client_ip, Misbehavior::AuthFailure);
+        warn!(
+            "BTP connection for account {} from {:?} is not in its IP allowlist",
+            username, client_ip
+        );
+        refuse_auth(&auth, &mut connection).await;
+        return Err(());
+    }
 
     let auth_response = Message::binary(
         BtpResponse {
@@ -180,6 +282,7 @@ fn parse_auth(ws_packet: Option<Message>) -> Option<Auth> {
                         return Some(Auth {
                             request_id,
                             token: SecretString::new(token),
+                            legacy: legacy::is_legacy_auth(&message),
                         });
                     } else {
                         warn!("BTP packet is missing auth token");
@@ -1,4 +1,4 @@
-use super::{packet::*, BtpAccount};
+use super::{legacy, packet::*, BtpAccount};
 use async_trait::async_trait;
 use bytes::BytesMut;
 use futures::{
@@ -10,11 +10,12 @@ use futures::{
 };
 use interledger_packet::{Address, ErrorCode, Fulfill, Packet, Prepare, Reject, RejectBuilder};
 use interledger_service::*;
+use metrics::{labels, recorder, Key};
 use once_cell::sync::Lazy;
 use parking_lot::{Mutex, RwLock};
 use rand::random;
 use std::collections::HashMap;
-use std::{convert::TryFrom, iter::IntoIterator, marker::PhantomData, sync::Arc, time::Duration};
+use std::{convert::TryFrom, marker::PhantomData, sync::Arc, time::Duration};
 use stream_cancel::{Trigger, Valve};
 use tokio::time;
 use tracing::{debug, error, trace, warn};
@@ -31,8 +32,15 @@ static PONG: Lazy<Message> = Lazy::new(|| Message::Pong(Vec::with_capacity(0)));
 // with us
 const SEND_MSG_TIMEOUT: Duration = Duration::from_secs(30);
 
+/// The name of the BTP sub-protocol labelling the packets of the accounts which share the
+/// connection of another account with the asset code of the account they belong to
+const ASSET_CODE_PROTOCOL: &str = "asset_code";
+
 type IlpResultChannel = oneshot::Sender<Result<Fulfill, Reject>>;
 type IncomingRequestBuffer<A> = UnboundedReceiver<(A, u32, Prepare)>;
+/// The accounts sharing the connection of another account, indexed by the username of that
+/// account and by their asset code
+type SubAccounts<A> = Arc<RwLock<HashMap<(String, String), A>>>;
 
 /// The BtpOutgoingService wraps all BTP/WebSocket connections that come
 /// in on the given address. It implements OutgoingService for sending
@@ -47,6 +55,10 @@ pub struct BtpOutgoingService<O, A: Account> {
     ilp_address: Address,
     /// Outgoing messages for the receiver of the websocket indexed by account uid
     connections: Arc<RwLock<HashMap<Uuid, UnboundedSender<Message>>>>,
+    /// The ids of the accounts with open connections indexed by username, so that the
+    /// accounts sharing their connections can find them
+    connection_ids: Arc<RwLock<HashMap<String, Uuid>>>,
+    sub_accounts: SubAccounts<A>,
     pending_outgoing: Arc<Mutex<HashMap<u32, IlpResultChannel>>>,
     pending_incoming: Arc<Mutex<Option<IncomingRequestBuffer<A>>>>,
     incoming_sender: UnboundedSender<(A, u32, Prepare)>,
@@ -55,6 +67,14 @@ pub struct BtpOutgoingService<O, A: Account> {
     stream_valve: Arc<Valve>,
 }
 
+/// Sets the `btp.connection` gauge of the account to 1 while its WebSocket connection is open
+fn record_connection_state(username: String, open: bool) {
+    recorder().update_gauge(
+        Key::from_name_and_labels("btp.connection", labels!("account" => username)),
+        if open { 1 } else { 0 },
+    );
+}
+
 /// Handle the packets based on whether they are an incoming request or a response to something we sent.
 ///  a. If it's a Prepare packet, it gets buffered in the incoming_sender channel which will get consumed
 ///     once an incoming handler is added
@@ -70,22 +90,56 @@ async fn handle_message<A: BtpAccount>(
     account: A,
     pending_requests: Arc<Mutex<HashMap<u32, IlpResultChannel>>>,
     incoming_sender: UnboundedSender<(A, u32, Prepare)>,
+    sub_accounts: SubAccounts<A>,
 ) {
     if message.is_binary() {
-        match parse_ilp_packet(message) {
-            // Queues up the prepare packet
-            Ok((request_id, Packet::Prepare(prepare))) => {
+        match parse_ilp_packet(&message) {
+            // Queues up the prepare packet, from the account of its asset if the peer
+            // holds several
+            Ok((request_id, Packet::Prepare(prepare), asset_code)) => {
                 trace!(
                     "Got incoming Prepare packet on request ID: {} {:?}",
                     request_id,
                     prepare
                 );
+                let from = match asset_code {
+                    Some(asset_code) if asset_code != account.asset_code() => {
+                        let key = (account.username().to_string(), asset_code);
+                        match sub_accounts.read().get(&key) {
+                            Some(sub_account) => sub_account.clone(),
+                            None => {
+                                warn!(
+                                    "Got Prepare packet in {} from account {}, which holds no account in that asset",
+                                    key.1,
+                                    account.username()
+                                );
+                                let reject = RejectBuilder {
+                                    code: ErrorCode::F02_UNREACHABLE,
+                                    message: format!("No account in {}", key.1).as_bytes(),
+                                    triggered_by: None,
+                                    data: &[],
+                                }
+                                .build();
+                                let _ = tx_clone
+                                    .unbounded_send(ilp_packet_to_ws_message(
+                                        request_id,
+                                        Packet::Reject(reject),
+                                        account.is_legacy_btp_peer(),
+                                        None,
+                                    ))
+                                    .map_err(|err| error!("Error sending Reject back: {:?}", err));
+                                return;
+                            }
+                        }
+                    }
+                    _ => account,
+                };
                 let _ = incoming_sender
-                    .unbounded_send((account, request_id, prepare))
+                    .unbounded_send((from, request_id, prepare))
                     .map_err(|err| error!("Unable to buffer incoming request: {:?}", err));
             }
             // Sends the fulfill/reject to the outgoing service
-            Ok((request_id, Packet::Fulfill(fulfill))) => {
+            Ok((request_id, Packet::Fulfill(fulfill), _)) => {
                 trace!("Got fulfill response to request id {}", request_id);
                 if let Some(channel) = (*pending_requests.lock()).remove(&request_id) {
                     let _ = channel.send(Ok(fulfill)).map_err(|fulfill| error!("Error forwarding Fulfill packet back to the Future that sent the Prepare: {:?}", fulfill));
@@ -96,7 +150,7 @@ async fn handle_message<A: BtpAccount>(
                     );
                 }
             }
-            Ok((request_id, Packet::Reject(reject))) => {
+            Ok((request_id, Packet::Reject(reject), _)) => {
                 trace!("Got reject response to request id {}", request_id);
                 if let Some(channel) = (*pending_requests.lock()).remove(&request_id) {
                     let _ = channel.send(Err(reject)).map_err(|reject| error!("Error forwarding Reject packet back to the Future that sent the Prepare: {:?}", reject));
@@ -107,6 +161,18 @@ async fn handle_message<A: BtpAccount>(
                     );
                 }
             }
+            Err(_) if account.is_legacy_btp_peer() => {
+                if let Some(response) = legacy::acknowledgement(&message.into_data()) {
+                    trace!(
+                        "Acknowledging BTP message {} without ILP packet from legacy peer {}",
+                        response.request_id,
+                        account.id()
+                    );
+                    let _ = tx_clone
+                        .unbounded_send(Message::binary(response.to_bytes()))
+                        .map_err(|err| error!("Error sending BTP response back: {:?}", err));
+                }
+            }
             Err(_) => {
                 debug!("Unable to parse ILP packet from BTP packet (if this is the first time this appears, the packet was probably the auth response)");
                 // TODO Send error back
@@ -121,6 +187,24 @@ async fn handle_message<A: BtpAccount>(
     }
 }
 
+/// Returns the connection of the account, or else the one of its transport account, along
+/// with the asset code labelling its packets when the connection is shared
+fn connection_of<A: BtpAccount>(
+    connections: &RwLock<HashMap<Uuid, UnboundedSender<Message>>>,
+    connection_ids: &RwLock<HashMap<String, Uuid>>,
+    account: &A,
+) -> Option<(UnboundedSender<Message>, Option<String>)> {
+    let connections = connections.read();
+    if let Some(connection) = connections.get(&account.id()) {
+        return Some((connection.clone(), None));
+    }
+    let transport = account.get_transport_account()?.to_string();
+    let transport_id = *connection_ids.read().get(&transport)?;
+    connections
+        .get(&transport_id)
+        .map(|connection| (connection.clone(), Some(account.asset_code().to_string())))
+}
+
 impl<O, A> BtpOutgoingService<O, A>
 where
     O: OutgoingService<A> + Clone,
@@ -132,6 +216,8 @@ where
         BtpOutgoingService {
             ilp_address,
             connections: Arc::new(RwLock::new(HashMap::new())),
+            connection_ids: Arc::new(RwLock::new(HashMap::new())),
+            sub_accounts: Arc::new(RwLock::new(HashMap::new())),
             pending_outgoing: Arc::new(Mutex::new(HashMap::new())),
             pending_incoming: Arc::new(Mutex::new(Some(incoming_receiver))),
             incoming_sender,
@@ -144,6 +230,29 @@ where
     /// Deletes the websocket associated with the provided `account_id`
     pub fn close_connection(&self, account_id: &Uuid) {
         self.connections.write().remove(account_id);
+        self.connection_ids
+            .write()
+            .retain(|_, connection_id| connection_id != account_id);
+    }
+
+    /// Registers an account which shares the connection of its transport account, so that
+    /// the packets in its asset are sent and received on that connection.
+    /// Accounts without a transport account are ignored.
+    pub fn add_sub_account(&self, account: A) {
+        if let Some(transport) = account.get_transport_account() {
+            debug!(
+                "Account {} shares the connection of account {} for {}",
+                account.username(),
+                transport,
+                account.asset_code()
+            );
+            let key = (transport.to_string(), account.asset_code().to_string());
+            self.sub_accounts.write().insert(key, account);
+        }
+    }
+
+    fn connection_of(&self, account: &A) -> Option<(UnboundedSender<Message>, Option<String>)> {
+        connection_of(&self.connections, &self.connection_ids, account)
     }
 
     /// Close all of the open WebSocket connections
@@ -164,6 +273,10 @@ where
         ws_stream: impl Stream<Item = Message> + Sink<Message> + Send + 'static,
     ) {
         let account_id = account.id();
+        let username = account.username().to_string();
+        self.connection_ids
+            .write()
+            .insert(username.clone(), account_id);
         // Set up a channel to forward outgoing packets to the WebSocket connection
         let (client_tx, client_rx) = unbounded();
         let (write, read) = ws_stream.split();
@@ -187,6 +300,7 @@ where
         // Process incoming messages depending on their type
         let pending_outgoing = self.pending_outgoing.clone();
         let incoming_sender = self.incoming_sender.clone();
+        let sub_accounts = self.sub_accounts.clone();
         let client_tx_clone = client_tx.clone();
         let handle_message_fn = move |msg: Message| {
             handle_message(
@@ -195,17 +309,20 @@ where
                 account.clone(),
                 pending_outgoing.clone(),
                 incoming_sender.clone(),
+                sub_accounts.clone(),
             )
         };
 
         // Close connections trigger
         let read = valve.wrap(read); // close when `write_to_ws` calls `drop(connection)`
         let read = self.stream_valve.wrap(read);
+        let username_clone = username.clone();
         let read_from_ws = read.for_each(handle_message_fn).then(move |_| async move {
             debug!(
                 "Finished reading from WebSocket stream for account: {}",
                 account_id
             );
+            record_connection_state(username_clone, false);
             Ok::<(), ()>(())
         });
         tokio::spawn(read_from_ws);
@@ -229,6 +346,7 @@ where
 
         // Save the sender side of the channel so we have a way to forward outgoing requests to the WebSocket
         self.connections.write().insert(account_id, client_tx);
+        record_connection_state(username, true);
     }
 
     /// Convert this BtpOutgoingService into a bidirectional BtpService by adding a handler for incoming requests.
@@ -241,7 +359,8 @@ where
         // the incoming Prepare packets they get in self.pending_incoming
         // Now that we're adding an incoming handler, this will spawn a task to read
         // all Prepare packets from the buffer, handle them, and send the responses back
-        let connections_clone = self.connections.clone();
+        let connections = self.connections.clone();
+        let connection_ids = self.connection_ids.clone();
         let mut handle_pending_incoming = self
             .pending_incoming
             .lock()
@@ -250,7 +369,10 @@ where
         let handle_pending_incoming_fut = async move {
             while let Some((account, request_id, prepare)) = handle_pending_incoming.next().await {
                 let account_id = account.id();
-                let connections_clone = connections_clone.clone();
+                let legacy = account.is_legacy_btp_peer();
+                // The responses to the accounts sharing a connection are matched by their
+                // request ids, so they need no asset code
+                let connection = connection_of(&connections, &connection_ids, &account);
                 let request = IncomingRequest {
                     from: account,
                     prepare,
@@ -267,8 +389,8 @@ where
                     Err(reject) => Packet::Reject(reject),
                 };
 
-                if let Some(connection) = connections_clone.clone().read().get(&account_id) {
-                    let message = ilp_packet_to_ws_message(request_id, packet);
+                if let Some((connection, _)) = connection {
+                    let message = ilp_packet_to_ws_message(request_id, packet, legacy, None);
                     let _ = connection.unbounded_send(message).map_err(move |err| {
                         error!(
                             "Error sending response to account: {} {:?}",
@@ -308,8 +430,7 @@ where
     /// request will be passed through to the `next` handler.
     async fn send_request(&mut self, request: OutgoingRequest<A>) -> IlpResult {
         let account_id = request.to.id();
-        let connections = self.connections.read().clone(); // have to clone here to avoid await errors
-        if let Some(connection) = connections.get(&account_id) {
+        if let Some((connection, asset_code)) = self.connection_of(&request.to) {
             let request_id = random::<u32>();
             let ilp_address = self.ilp_address.clone();
 
@@ -318,17 +439,21 @@ where
             let keep_connections_open = self.close_all_connections.clone();
 
             trace!(
-                "Sending outgoing request {} to {} ({})",
+                "Sending outgoing request {} to {} ({}) in {}",
                 request_id,
                 request.to.username(),
-                account_id
+                account_id,
+                request.to.asset_code()
             );
 
             // Connection is an unbounded sender which sends to the rx that
             // forwards to the sink which sends the data over
+            let legacy = request.to.is_legacy_btp_peer();
             match connection.unbounded_send(ilp_packet_to_ws_message(
                 request_id,
                 Packet::Prepare(request.prepare),
+                legacy,
+                asset_code,
             )) {
                 Ok(_) => {
                     let (sender, receiver) = oneshot::channel();
@@ -396,6 +521,7 @@ where
         } else {
             if request.to.get_ilp_over_btp_url().is_some()
                 || request.to.get_ilp_over_btp_outgoing_token().is_some()
+                || request.to.get_transport_account().is_some()
             {
                 trace!(
                     "No open connection for account: {}, forwarding request to the next service",
@@ -427,6 +553,11 @@ where
     pub fn close_connection(&self, account_id: &Uuid) {
         self.outgoing.close_connection(account_id);
     }
+
+    /// Registers an account which shares the connection of its transport account
+    pub fn add_sub_account(&self, account: A) {
+        self.outgoing.add_sub_account(account);
+    }
 }
 
 #[async_trait]
@@ -445,28 +576,14 @@ where
     }
 }
 
+/// Returns the ILP packet of the BTP packet, along with the asset code it is labelled with
+/// if it was sent on behalf of an account sharing the connection
 #[allow(clippy::cognitive_complexity)]
-fn parse_ilp_packet(message: Message) -> Result<(u32, Packet), ()> {
+fn parse_ilp_packet(message: &Message) -> Result<(u32, Packet, Option<String>), ()> {
     if let Message::Binary(data) = message {
-        let (request_id, ilp_data) = match BtpPacket::from_bytes(&data) {
-            Ok(BtpPacket::Message(message)) => {
-                let ilp_data = message
-                    .protocol_data
-                    .into_iter()
-                    .find(|proto| proto.protocol_name == "ilp")
-                    .ok_or(())?
-                    .data;
-                (message.request_id, ilp_data)
-            }
-            Ok(BtpPacket::Response(response)) => {
-                let ilp_data = response
-                    .protocol_data
-                    .into_iter()
-                    .find(|proto| proto.protocol_name == "ilp")
-                    .ok_or(())?
-                    .data;
-                (response.request_id, ilp_data)
-            }
+        let (request_id, protocol_data) = match BtpPacket::from_bytes(data) {
+            Ok(BtpPacket::Message(message)) => (message.request_id, message.protocol_data),
+            Ok(BtpPacket::Response(response)) => (response.request_id, response.protocol_data),
             Ok(BtpPacket::Error(error)) => {
                 error!("Got BTP error: {:?}", error);
                 return Err(());
@@ -476,8 +593,17 @@ fn parse_ilp_packet(message: Message) -> Result<(u32, Packet), ()> {
                 return Err(());
             }
         };
-        if let Ok(packet) = Packet::try_from(BytesMut::from(ilp_data)) {
-            Ok((request_id, packet))
+        let mut ilp_data = None;
+        let mut asset_code = None;
+        for proto in protocol_data {
+            match proto.protocol_name.as_str() {
+                "ilp" => ilp_data = Some(proto.data),
+                ASSET_CODE_PROTOCOL => asset_code = String::from_utf8(proto.data).ok(),
+                _ => {}
+            }
+        }
+        if let Ok(packet) = Packet::try_from(BytesMut::from(ilp_data.ok_or(())?)) {
+            Ok((request_id, packet, asset_code))
         } else {
             Err(())
         }
@@ -487,30 +613,43 @@ fn parse_ilp_packet(message: Message) -> Result<(u32, Packet), ()> {
     }
 }
 
-fn ilp_packet_to_ws_message(request_id: u32, packet: Packet) -> Message {
+/// Wraps the ILP packet in a BTP packet. Other nodes read the ILP packets from either BTP Messages
+/// or Responses, but legacy peers only read Prepare packets from Messages and Fulfill and Reject
+/// packets from Responses. The packets of accounts sharing the connection of another account are
+/// labelled with their asset code.
+fn ilp_packet_to_ws_message(
+    request_id: u32,
+    packet: Packet,
+    legacy: bool,
+    asset_code: Option<String>,
+) -> Message {
     let (data, is_response) = match packet {
         Packet::Prepare(prepare) => (BytesMut::from(prepare).to_vec(), false),
         Packet::Fulfill(fulfill) => (BytesMut::from(fulfill).to_vec(), true),
         Packet::Reject(reject) => (BytesMut::from(reject).to_vec(), true),
     };
-    let btp_packet = if is_response {
+    let mut protocol_data = vec![ProtocolData {
+        protocol_name: "ilp".to_string(),
+        content_type: ContentType::ApplicationOctetStream,
+        data,
+    }];
+    if let Some(asset_code) = asset_code {
+        protocol_data.push(ProtocolData {
+            protocol_name: ASSET_CODE_PROTOCOL.to_string(),
+            content_type: ContentType::TextPlainUtf8,
+            data: asset_code.into_bytes(),
+        });
+    }
+    let btp_packet = if is_response != legacy {
         BtpMessage {
             request_id,
-            protocol_data: vec![ProtocolData {
-                protocol_name: "ilp".to_string(),
-                content_type: ContentType::ApplicationOctetStream,
-                data,
-            }],
+            protocol_data,
         }
         .to_bytes()
     } else {
         BtpResponse {
             request_id,
-            protocol_data: vec![ProtocolData {
-                protocol_name: "ilp".to_string(),
-                content_type: ContentType::ApplicationOctetStream,
-                data,
-            }],
+            protocol_data,
         }
         .to_bytes()
     };
@@ -0,0 +1,285 @@
+use super::{CcpRoutingAccount, RoutingRelation};
+use async_trait::async_trait;
+use interledger_packet::{ErrorCode, Reject, RejectBuilder};
+use interledger_service::{
+    AddressStore, IlpResult, IncomingRequest, IncomingService, OutgoingRequest, OutgoingService,
+};
+use metrics::{labels, recorder, Key};
+use std::fmt;
+use tracing::warn;
+
+/// The schemes of the addresses which are only meant for the node and its direct peers
+/// (such as the `peer.config` of ILDCP and the `peer.route` of CCP)
+const LOCAL_SCHEMES: [&str; 2] = ["peer", "local"];
+
+/// The forwarding rules of the routing relations broken by a packet
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AddressViolation {
+    /// A Prepare packet for a `peer.` or `local.` address, which never crosses the node.
+    /// It is rejected with `F02: Unreachable`.
+    LocalDestination,
+    /// A Reject packet of a child triggered by an address outside of the child's prefix.
+    /// It keeps its code, but is attributed to the child.
+    OutsideChildPrefix,
+    /// A Reject packet of a peer or parent triggered by an address under the node's prefix.
+    /// It keeps its code, but is attributed to the peer or parent.
+    InsideNodePrefix,
+}
+
+impl fmt::Display for AddressViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            AddressViolation::LocalDestination => "local_destination",
+            AddressViolation::OutsideChildPrefix => "outside_child_prefix",
+            AddressViolation::InsideNodePrefix => "inside_node_prefix",
+        })
+    }
+}
+
+/// Whether the address is the prefix itself or one of the addresses under it
+fn has_prefix(address: &str, prefix: &str) -> bool {
+    address == prefix
+        || (address.starts_with(prefix) && address.as_bytes().get(prefix.len()) == Some(&b'.'))
+}
+
+/// Counts the packets which broke the rule, by account and rule
+fn record_violation<A: CcpRoutingAccount>(account: &A, violation: AddressViolation) {
+    recorder().increment_counter(
+        Key::from_name_and_labels(
+            "requests.address_violations",
+            labels!(
+                "account" => account.username().to_string(),
+                "violation" => violation.to_string(),
+            ),
+        ),
+        1,
+    );
+}
+
+/// # Address Range Service
+///
+/// Incoming and outgoing service which enforces the forwarding rules of the accounts'
+/// routing relations on the packets which cross the node:
+///
+/// - the Prepare packets for `peer.` and `local.` addresses are rejected instead of forwarded
+/// - the Reject packets of children may only be triggered by addresses under their prefix
+/// - the Reject packets of peers and parents may not be triggered by addresses under the node's
+///   prefix (for which the `CcpRouteManager` also ignores their routes)
+///
+/// The Reject packets which break the rules are attributed to the account which sent them,
+/// so that the senders do not blame other nodes for them. Each violation is counted in the
+/// `requests.address_violations` metric.
+///
+/// Requires a `CcpRoutingAccount` and an `AddressStore`.
+#[derive(Clone)]
+pub struct AddressRangeService<S, IO> {
+    store: S,
+    next: IO,
+}
+
+impl<S, IO> AddressRangeService<S, IO> {
+    pub fn new(store: S, next: IO) -> Self {
+        AddressRangeService { store, next }
+    }
+}
+
+impl<S, IO> AddressRangeService<S, IO>
+where
+    S: AddressStore,
+{
+    /// Returns the rule broken by the Reject packet of the account, if any
+    fn check_reject<A: CcpRoutingAccount>(
+        &self,
+        account: &A,
+        reject: &Reject,
+    ) -> Option<AddressViolation> {
+        let triggered_by = reject.triggered_by()?;
+        match account.routing_relation() {
+            RoutingRelation::Child if !has_prefix(&triggered_by, account.ilp_address()) => {
+                Some(AddressViolation::OutsideChildPrefix)
+            }
+            RoutingRelation::Peer | RoutingRelation::Parent
+                if has_prefix(&triggered_by, &self.store.get_ilp_address()) =>
+            {
+                Some(AddressViolation::InsideNodePrefix)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[async_trait]
+impl<S, I, A> IncomingService<A> for AddressRangeService<S, I>
+where
+    S: AddressStore + Send + Sync + 'static,
+    I: IncomingService<A> + Send + Sync + 'static,
+    A: CcpRoutingAccount + Sync + 'static,
+{
+    async fn handle_request(&mut self, request: IncomingRequest<A>) -> IlpResult {
+        let destination = request.prepare.destination();
+        if LOCAL_SCHEMES.contains(&destination.scheme()) {
+            warn!(
+                "Rejecting packet from account {} for {}, which is not forwarded",
+                request.from.username(),
+                destination
+            );
+            record_violation(&request.from, AddressViolation::LocalDestination);
+            return Err(RejectBuilder {
+                code: ErrorCode::F02_UNREACHABLE,
+                message: format!(
+                    "Packets for {}. addresses are not forwarded",
+                    destination.scheme()
+                )
+                .as_bytes(),
+                triggered_by: Some(&self.store.get_ilp_address()),
+                data: &[],
+            }
+            .build());
+        }
+        self.next.handle_request(request).await
+    }
+}
+
+#[async_trait]
+impl<S, O, A> OutgoingService<A> for AddressRangeService<S, O>
+where
+    S: AddressStore + Send + Sync + 'static,
+    O: OutgoingService<A> + Send + Sync + 'static,
+    A: CcpRoutingAccount + Sync + 'static,
+{
+    async fn send_request(&mut self, request: OutgoingRequest<A>) -> IlpResult {
+        let to = request.to.clone();
+        match self.next.send_request(request).await {
+            Err(reject) => match self.check_reject(&to, &reject) {
+                Some(violation) => {
+                    warn!(
+                        "Reject from account {} was triggered by {:?}, attributing it to {}",
+                        to.username(),
+                        reject.triggered_by(),
+                        to.ilp_address()
+                    );
+                    record_violation(&to, violation);
+                    Err(RejectBuilder {
+                        code: reject.code(),
+                        message: reject.message(),
+                        triggered_by: Some(to.ilp_address()),
+                        data: reject.data(),
+                    }
+                    .build())
+                }
+                None => Err(reject),
+            },
+            fulfill => fulfill,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::*;
+    use interledger_packet::{Address, FulfillBuilder, PrepareBuilder};
+    use interledger_service::{incoming_service_fn, outgoing_service_fn};
+    use std::str::FromStr;
+    use std::time::{Duration, SystemTime};
+
+    fn prepare(destination: &str) -> interledger_packet::Prepare {
+        PrepareBuilder {
+            destination: Address::from_str(destination).unwrap(),
+            amount: 100,
+            expires_at: SystemTime::now() + Duration::from_secs(30),
+            execution_condition: &[0; 32],
+            data: &[],
+        }
+        .build()
+    }
+
+    async fn reject_from(account: &TestAccount, triggered_by: &'static str) -> Reject {
+        let mut service = AddressRangeService::new(
+            TestStore::new(),
+            outgoing_service_fn(move |_| {
+                Err(RejectBuilder {
+                    code: ErrorCode::T04_INSUFFICIENT_LIQUIDITY,
+                    message: b"no liquidity",
+                    triggered_by: Some(&Address::from_str(triggered_by).unwrap()),
+                    data: b"data",
+                }
+                .build())
+            }),
+        );
+        service
+            .send_request(OutgoingRequest {
+                from: ROUTING_ACCOUNT.clone(),
+                to: account.clone(),
+                original_amount: 100,
+                prepare: prepare("example.destination"),
+            })
+            .await
+            .unwrap_err()
+    }
+
+    #[tokio::test]
+    async fn rejects_packets_for_local_addresses() {
+        let mut service = AddressRangeService::new(
+            TestStore::new(),
+            incoming_service_fn(|_| {
+                Ok(FulfillBuilder {
+                    fulfillment: &[0; 32],
+                    data: &[],
+                }
+                .build())
+            }),
+        );
+        for destination in &["peer.config", "local.host.bob"] {
+            let reject = service
+                .handle_request(IncomingRequest {
+                    from: CHILD_ACCOUNT.clone(),
+                    prepare: prepare(destination),
+                })
+                .await
+                .unwrap_err();
+            assert_eq!(reject.code(), ErrorCode::F02_UNREACHABLE);
+            assert_eq!(reject.triggered_by(), Some(EXAMPLE_CONNECTOR.clone()));
+        }
+        assert!(service
+            .handle_request(IncomingRequest {
+                from: CHILD_ACCOUNT.clone(),
+                prepare: prepare("example.peer.bob"),
+            })
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn attributes_child_rejects_from_outside_their_prefix() {
+        let reject = reject_from(&CHILD_ACCOUNT, "example.me.child.bob").await;
+        assert_eq!(
+            reject.triggered_by(),
+            Some(Address::from_str("example.me.child.bob").unwrap())
+        );
+
+        let reject = reject_from(&CHILD_ACCOUNT, "example.me.children").await;
+        assert_eq!(reject.code(), ErrorCode::T04_INSUFFICIENT_LIQUIDITY);
+        assert_eq!(
+            reject.triggered_by(),
+            Some(CHILD_ACCOUNT.ilp_address.clone())
+        );
+        assert_eq!(reject.message(), b"no liquidity");
+        assert_eq!(reject.data(), b"data");
+    }
+
+    #[tokio::test]
+    async fn attributes_peer_rejects_from_inside_our_prefix() {
+        let reject = reject_from(&ROUTING_ACCOUNT, "example.other").await;
+        assert_eq!(
+            reject.triggered_by(),
+            Some(Address::from_str("example.other").unwrap())
+        );
+
+        let reject = reject_from(&ROUTING_ACCOUNT, "example.connector.alice").await;
+        assert_eq!(
+            reject.triggered_by(),
+            Some(ROUTING_ACCOUNT.ilp_address.clone())
+        );
+    }
+}
@@ -16,6 +16,7 @@ use std::collections::HashMap;
 use std::{fmt, str::FromStr};
 use uuid::Uuid;
 
+mod address_range;
 #[cfg(test)]
 mod fixtures;
 mod packet;
@@ -24,8 +25,14 @@ mod server;
 #[cfg(test)]
 mod test_helpers;
 
-pub use packet::{Mode, RouteControlRequest};
-pub use server::{CcpRouteManager, CcpRouteManagerBuilder};
+pub use address_range::{AddressRangeService, AddressViolation};
+pub use packet::{
+    Mode, RouteControlRequest, RouteUpdateRequest, CCP_CONTROL_DESTINATION, CCP_UPDATE_DESTINATION,
+    PEER_PROTOCOL_CONDITION,
+};
+pub use server::{
+    CcpRouteManager, CcpRouteManagerBuilder, RouteLatency, DEFAULT_BROADCAST_INTERVAL,
+};
 
 use serde::{Deserialize, Serialize};
 
@@ -93,6 +100,24 @@ pub trait CcpRoutingAccount: Account {
         self.routing_relation() == RoutingRelation::Parent
             || self.routing_relation() == RoutingRelation::Peer
     }
+
+    /// Interval, in milliseconds, on which we broadcast routes to this account, if it should
+    /// be longer than the route broadcast interval of the node
+    fn route_broadcast_interval(&self) -> Option<u64> {
+        None
+    }
+
+    /// How long, in milliseconds, this account should keep the routes we broadcast to it,
+    /// if it differs from the default of 30 seconds
+    fn route_expiry(&self) -> Option<u32> {
+        None
+    }
+
+    /// Indicates whether each route update we send to this account should contain the whole
+    /// routing table, rather than the changes since the previous update
+    fn should_receive_full_table(&self) -> bool {
+        false
+    }
 }
 
 // key = Bytes, key should be Address -- TODO
@@ -127,4 +152,24 @@ pub trait CcpRoutingStore: Clone {
         &mut self,
         routes: impl IntoIterator<Item = (String, Self::Account)> + Send + 'async_trait,
     ) -> Result<(), CcpRoutingStoreError>;
+
+    /// Saves the routes learned from an account (encoded as in a Route Update Request),
+    /// replacing those saved for it before. The nodes sharing a store save the routes they
+    /// learn from the peers they are connected to, so that the node which sets the routes
+    /// considers those learned by the other nodes too. Stores which are not shared may
+    /// leave this as a no-op.
+    async fn set_learned_routes(
+        &mut self,
+        _account: Self::Account,
+        _routes: Vec<u8>,
+    ) -> Result<(), CcpRoutingStoreError> {
+        Ok(())
+    }
+
+    /// Gets the routes saved by `set_learned_routes`, along with the accounts they were learned from
+    async fn get_learned_routes(
+        &self,
+    ) -> Result<Vec<(Self::Account, Vec<u8>)>, CcpRoutingStoreError> {
+        Ok(Vec::new())
+    }
 }
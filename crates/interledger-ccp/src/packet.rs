@@ -4,13 +4,14 @@ use interledger_packet::{
     oer::{BufOerExt, MutBufOerExt},
     Address, Fulfill, FulfillBuilder, ParseError, Prepare, PrepareBuilder,
 };
+use interledger_service::clock;
 use once_cell::sync::Lazy;
 use std::{
     convert::TryFrom,
     fmt::{self, Debug},
     io::Read,
     str::{self, FromStr},
-    time::{Duration, SystemTime},
+    time::Duration,
 };
 use tracing::error;
 
@@ -92,7 +93,7 @@ impl TryFrom<&Prepare> for RouteControlRequest {
     type Error = ParseError;
 
     fn try_from(prepare: &Prepare) -> Result<Self, Self::Error> {
-        if prepare.expires_at() < SystemTime::now() {
+        if prepare.expires_at() < clock::now() {
             return Err(ParseError::InvalidPacket("Packet expired".to_string()));
         }
         RouteControlRequest::try_from_without_expiry(prepare)
@@ -123,7 +124,9 @@ impl RouteControlRequest {
         data.read_exact(&mut last_known_routing_table_id)?;
         let last_known_epoch = data.read_u32::<BigEndian>()?;
         let num_features = data.read_var_uint()?;
-        let mut features: Vec<String> = Vec::with_capacity(num_features as usize);
+        // The counts come from the peer, so the capacities are capped by the bytes left,
+        // as each of the items takes at least one
+        let mut features: Vec<String> = Vec::with_capacity((num_features as usize).min(data.len()));
         for _i in 0..num_features {
             features.push(String::from_utf8(data.read_var_octet_string()?.to_vec())?);
         }
@@ -150,7 +153,7 @@ impl RouteControlRequest {
         PrepareBuilder {
             destination: CCP_CONTROL_DESTINATION.clone(),
             amount: 0,
-            expires_at: SystemTime::now() + Duration::from_millis(PEER_PROTOCOL_EXPIRY_DURATION),
+            expires_at: clock::now() + Duration::from_millis(PEER_PROTOCOL_EXPIRY_DURATION),
             execution_condition: &PEER_PROTOCOL_CONDITION,
             data: &data[..],
         }
@@ -252,7 +255,7 @@ impl TryFrom<&mut &[u8]> for Route {
     fn try_from(data: &mut &[u8]) -> Result<Self, Self::Error> {
         let prefix = str::from_utf8(data.read_var_octet_string()?)?.to_string();
         let path_len = data.read_var_uint()? as usize;
-        let mut path: Vec<String> = Vec::with_capacity(path_len);
+        let mut path: Vec<String> = Vec::with_capacity(path_len.min(data.len()));
         for _i in 0..path_len {
             path.push(str::from_utf8(data.read_var_octet_string()?)?.to_string());
         }
@@ -260,7 +263,7 @@ impl TryFrom<&mut &[u8]> for Route {
         data.read_exact(&mut auth)?;
 
         let prop_len = data.read_var_uint()? as usize;
-        let mut props = Vec::with_capacity(prop_len);
+        let mut props = Vec::with_capacity(prop_len.min(data.len()));
         for _i in 0..prop_len {
             // For some reason we need to cast `data to `&mut &[u8]` again, otherwise
             // error[E0382]: use of moved value: `data`
@@ -329,7 +332,7 @@ impl TryFrom<&Prepare> for RouteUpdateRequest {
     type Error = ParseError;
 
     fn try_from(prepare: &Prepare) -> Result<Self, Self::Error> {
-        if prepare.expires_at() < SystemTime::now() {
+        if prepare.expires_at() < clock::now() {
             return Err(ParseError::InvalidPacket("Packet expired".to_string()));
         }
         RouteUpdateRequest::try_from_without_expiry(prepare)
@@ -362,12 +365,12 @@ impl RouteUpdateRequest {
         let hold_down_time = data.read_u32::<BigEndian>()?;
         let speaker = Address::try_from(data.read_var_octet_string()?)?;
         let new_routes_len = data.read_var_uint()? as usize;
-        let mut new_routes: Vec<Route> = Vec::with_capacity(new_routes_len);
+        let mut new_routes: Vec<Route> = Vec::with_capacity(new_routes_len.min(data.len()));
         for _i in 0..new_routes_len {
             new_routes.push(Route::try_from(&mut data)?);
         }
         let withdrawn_routes_len = data.read_var_uint()? as usize;
-        let mut withdrawn_routes = Vec::with_capacity(withdrawn_routes_len);
+        let mut withdrawn_routes = Vec::with_capacity(withdrawn_routes_len.min(data.len()));
         for _i in 0..withdrawn_routes_len {
             withdrawn_routes.push(str::from_utf8(data.read_var_octet_string()?)?.to_string());
         }
@@ -404,7 +407,7 @@ impl RouteUpdateRequest {
         PrepareBuilder {
             destination: CCP_UPDATE_DESTINATION.clone(),
             amount: 0,
-            expires_at: SystemTime::now() + Duration::from_millis(PEER_PROTOCOL_EXPIRY_DURATION),
+            expires_at: clock::now() + Duration::from_millis(PEER_PROTOCOL_EXPIRY_DURATION),
             execution_condition: &PEER_PROTOCOL_CONDITION,
             data: &data[..],
         }
@@ -472,6 +475,22 @@ mod route_control_request {
             "Invalid Packet: Packet expired"
         );
     }
+
+    #[test]
+    fn expires_after_the_peer_protocol_expiry() {
+        interledger_service::simulation::run(async {
+            let prepare = CONTROL_REQUEST.to_prepare();
+            tokio::time::delay_for(Duration::from_millis(PEER_PROTOCOL_EXPIRY_DURATION)).await;
+            assert!(RouteControlRequest::try_from(&prepare).is_ok());
+            tokio::time::delay_for(Duration::from_millis(1)).await;
+            assert_eq!(
+                RouteControlRequest::try_from(&prepare)
+                    .unwrap_err()
+                    .to_string(),
+                "Invalid Packet: Packet expired"
+            );
+        });
+    }
 }
 
 #[cfg(test)]
@@ -595,3 +614,337 @@ mod route_update_request {
         assert_eq!(route, Route::try_from(&mut &serialized[..]).unwrap());
     }
 }
+
+/// Round-trips the CCP messages of the shared test vectors (see `test-vectors/README.md`
+/// at the root of the repository), whose buffers are the data of the peer protocol
+/// Prepare packets. Set `UPDATE_TEST_VECTORS=1` to regenerate the buffers of the vectors
+/// from their fields instead of checking them.
+#[cfg(test)]
+mod vectors {
+    use super::*;
+    use serde_json::{json, Value};
+    use std::{env, fs};
+    use uuid::Uuid;
+
+    const VECTORS: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../../test-vectors/ccp-packets.json"
+    );
+
+    #[test]
+    fn ccp_packets() {
+        let mut cases: Vec<Value> =
+            serde_json::from_str(&fs::read_to_string(VECTORS).unwrap()).unwrap();
+        let update = env::var_os("UPDATE_TEST_VECTORS").is_some();
+
+        for case in cases.iter_mut() {
+            let name = case["name"].as_str().unwrap().to_string();
+            let packet = &case["packet"];
+            let prepare = match packet["type"].as_str().unwrap() {
+                "control" => control_from_json(packet).to_prepare(),
+                "update" => update_from_json(packet).to_prepare(),
+                other => panic!("unknown message type {}", other),
+            };
+            let encoded = hex::encode(prepare.data());
+            if update {
+                case["buffer"] = json!(encoded);
+                continue;
+            }
+
+            assert_eq!(encoded, case["buffer"], "encoding of {}", name);
+            let data = hex::decode(case["buffer"].as_str().unwrap()).unwrap();
+            let prepare = PrepareBuilder {
+                amount: 0,
+                expires_at: prepare.expires_at(),
+                execution_condition: &PEER_PROTOCOL_CONDITION,
+                destination: prepare.destination(),
+                data: &data,
+            }
+            .build();
+            let decoded = match packet["type"].as_str().unwrap() {
+                "control" => control_to_json(
+                    &RouteControlRequest::try_from_without_expiry(&prepare)
+                        .unwrap_or_else(|err| panic!("decoding of {}: {:?}", name, err)),
+                ),
+                _ => update_to_json(
+                    &RouteUpdateRequest::try_from_without_expiry(&prepare)
+                        .unwrap_or_else(|err| panic!("decoding of {}: {:?}", name, err)),
+                ),
+            };
+            assert_eq!(&decoded, packet, "decoding of {}", name);
+        }
+
+        if update {
+            let mut json = serde_json::to_string_pretty(&cases).unwrap();
+            json.push('\n');
+            fs::write(VECTORS, json).unwrap();
+        }
+    }
+
+    fn text(value: &Value) -> String {
+        value.as_str().unwrap().to_string()
+    }
+
+    fn texts(value: &Value) -> Vec<String> {
+        value.as_array().unwrap().iter().map(text).collect()
+    }
+
+    fn table_id(value: &Value) -> [u8; 16] {
+        *Uuid::parse_str(value.as_str().unwrap()).unwrap().as_bytes()
+    }
+
+    fn control_from_json(packet: &Value) -> RouteControlRequest {
+        RouteControlRequest {
+            mode: Mode::try_from(packet["mode"].as_u64().unwrap() as u8).unwrap(),
+            last_known_routing_table_id: table_id(&packet["lastKnownRoutingTableId"]),
+            last_known_epoch: packet["lastKnownEpoch"].as_u64().unwrap() as u32,
+            features: texts(&packet["features"]),
+        }
+    }
+
+    fn control_to_json(request: &RouteControlRequest) -> Value {
+        json!({
+            "type": "control",
+            "mode": request.mode as u8,
+            "lastKnownRoutingTableId":
+                Uuid::from_bytes(request.last_known_routing_table_id).to_string(),
+            "lastKnownEpoch": request.last_known_epoch,
+            "features": request.features,
+        })
+    }
+
+    fn update_from_json(packet: &Value) -> RouteUpdateRequest {
+        let epoch = |field: &str| packet[field].as_u64().unwrap() as u32;
+        let new_routes = packet["newRoutes"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|route| {
+                let mut auth = [0; 32];
+                auth.copy_from_slice(&hex::decode(route["auth"].as_str().unwrap()).unwrap());
+                let props = route["props"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|prop| {
+                        let flag = |field: &str| prop[field].as_bool().unwrap();
+                        RouteProp {
+                            is_optional: flag("isOptional"),
+                            is_transitive: flag("isTransitive"),
+                            is_partial: flag("isPartial"),
+                            id: prop["id"].as_u64().unwrap() as u16,
+                            is_utf8: flag("isUtf8"),
+                            value: Bytes::from(
+                                hex::decode(prop["value"].as_str().unwrap()).unwrap(),
+                            ),
+                        }
+                    })
+                    .collect();
+                Route {
+                    prefix: text(&route["prefix"]),
+                    path: texts(&route["path"]),
+                    auth,
+                    props,
+                }
+            })
+            .collect();
+        RouteUpdateRequest {
+            routing_table_id: table_id(&packet["routingTableId"]),
+            current_epoch_index: epoch("currentEpochIndex"),
+            from_epoch_index: epoch("fromEpochIndex"),
+            to_epoch_index: epoch("toEpochIndex"),
+            hold_down_time: epoch("holdDownTime"),
+            speaker: Address::from_str(packet["speaker"].as_str().unwrap()).unwrap(),
+            new_routes,
+            withdrawn_routes: texts(&packet["withdrawnRoutes"]),
+        }
+    }
+
+    fn update_to_json(request: &RouteUpdateRequest) -> Value {
+        let new_routes: Vec<Value> = request
+            .new_routes
+            .iter()
+            .map(|route| {
+                let props: Vec<Value> = route
+                    .props
+                    .iter()
+                    .map(|prop| {
+                        json!({
+                            "isOptional": prop.is_optional,
+                            "isTransitive": prop.is_transitive,
+                            "isPartial": prop.is_partial,
+                            "id": prop.id,
+                            "isUtf8": prop.is_utf8,
+                            "value": hex::encode(&prop.value),
+                        })
+                    })
+                    .collect();
+                json!({
+                    "prefix": route.prefix,
+                    "path": route.path,
+                    "auth": hex::encode(route.auth),
+                    "props": props,
+                })
+            })
+            .collect();
+        json!({
+            "type": "update",
+            "routingTableId": Uuid::from_bytes(request.routing_table_id).to_string(),
+            "currentEpochIndex": request.current_epoch_index,
+            "fromEpochIndex": request.from_epoch_index,
+            "toEpochIndex": request.to_epoch_index,
+            "holdDownTime": request.hold_down_time,
+            "speaker": request.speaker.to_string(),
+            "newRoutes": new_routes,
+            "withdrawnRoutes": request.withdrawn_routes,
+        })
+    }
+}
+
+/// Random messages round-trip, and mutations of them are parsed or rejected without
+/// panicking (see the `ccp_route_update` and `ccp_route_control` fuzz targets for a
+/// deeper search)
+#[cfg(test)]
+mod properties {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use std::time::SystemTime;
+
+    const CASES: usize = 2000;
+
+    fn bytes(rng: &mut StdRng, max_len: usize) -> Vec<u8> {
+        let len = rng.gen_range(0, max_len + 1);
+        (0..len).map(|_| rng.gen()).collect()
+    }
+
+    fn text(rng: &mut StdRng, max_len: usize) -> String {
+        let len = rng.gen_range(0, max_len + 1);
+        (0..len)
+            .map(|_| rng.gen_range(b' ', b'~') as char)
+            .collect()
+    }
+
+    fn route(rng: &mut StdRng) -> Route {
+        Route {
+            prefix: text(rng, 40),
+            path: (0..rng.gen_range(0, 4)).map(|_| text(rng, 40)).collect(),
+            auth: rng.gen(),
+            props: (0..rng.gen_range(0, 3))
+                .map(|_| RouteProp {
+                    is_optional: rng.gen(),
+                    is_transitive: rng.gen(),
+                    is_partial: rng.gen(),
+                    id: rng.gen(),
+                    is_utf8: rng.gen(),
+                    value: Bytes::from(bytes(rng, 200)),
+                })
+                .collect(),
+        }
+    }
+
+    fn control(rng: &mut StdRng) -> RouteControlRequest {
+        RouteControlRequest {
+            mode: if rng.gen() { Mode::Sync } else { Mode::Idle },
+            last_known_routing_table_id: rng.gen(),
+            last_known_epoch: rng.gen(),
+            features: (0..rng.gen_range(0, 4)).map(|_| text(rng, 20)).collect(),
+        }
+    }
+
+    fn update(rng: &mut StdRng) -> RouteUpdateRequest {
+        RouteUpdateRequest {
+            routing_table_id: rng.gen(),
+            current_epoch_index: rng.gen(),
+            from_epoch_index: rng.gen(),
+            to_epoch_index: rng.gen(),
+            hold_down_time: rng.gen(),
+            speaker: Address::from_str("example.speaker").unwrap(),
+            new_routes: (0..rng.gen_range(0, 5)).map(|_| route(rng)).collect(),
+            withdrawn_routes: (0..rng.gen_range(0, 5)).map(|_| text(rng, 40)).collect(),
+        }
+    }
+
+    fn mutate(rng: &mut StdRng, buffer: &[u8]) -> Vec<u8> {
+        let mut buffer = buffer.to_vec();
+        for _ in 0..rng.gen_range(1, 4) {
+            let index = rng.gen_range(0, buffer.len() + 1);
+            match rng.gen_range(0, 5) {
+                0 if index < buffer.len() => buffer[index] ^= 1 << rng.gen_range(0, 8),
+                1 if index < buffer.len() => {
+                    buffer[index] = [0x00, 0x7f, 0x80, 0x81, 0x88, 0x89, 0xff][rng.gen_range(0, 7)]
+                }
+                2 => {
+                    let inserted = bytes(rng, 16);
+                    buffer.splice(index..index, inserted);
+                }
+                3 if index < buffer.len() => {
+                    buffer.remove(index);
+                }
+                _ => buffer.truncate(index),
+            }
+        }
+        buffer
+    }
+
+    /// The peer protocol Prepare packet with the given data, as a peer would send it
+    fn prepare(destination: &Address, data: &[u8]) -> Prepare {
+        PrepareBuilder {
+            amount: 0,
+            expires_at: SystemTime::now() + Duration::from_secs(30),
+            execution_condition: &PEER_PROTOCOL_CONDITION,
+            destination: destination.clone(),
+            data,
+        }
+        .build()
+    }
+
+    #[test]
+    fn messages_round_trip() {
+        let mut rng = StdRng::seed_from_u64(0xcc9);
+        for _ in 0..CASES {
+            let request = control(&mut rng);
+            assert_eq!(
+                RouteControlRequest::try_from(&request.to_prepare()).unwrap(),
+                request
+            );
+            let request = update(&mut rng);
+            assert_eq!(
+                RouteUpdateRequest::try_from(&request.to_prepare()).unwrap(),
+                request
+            );
+        }
+    }
+
+    #[test]
+    fn mutated_messages_do_not_panic() {
+        let mut rng = StdRng::seed_from_u64(0xcc9);
+        for _ in 0..CASES {
+            let valid = control(&mut rng).to_prepare();
+            let mutated = mutate(&mut rng, valid.data());
+            let _ = RouteControlRequest::try_from(&prepare(&CCP_CONTROL_DESTINATION, &mutated));
+
+            let valid = update(&mut rng).to_prepare();
+            let mutated = mutate(&mut rng, valid.data());
+            if let Ok(request) =
+                RouteUpdateRequest::try_from(&prepare(&CCP_UPDATE_DESTINATION, &mutated))
+            {
+                let _ = format!("{:?}", request);
+            }
+        }
+    }
+
+    #[test]
+    fn huge_counts_are_rejected() {
+        // A control request with 2^64 - 1 features
+        let mut data = vec![1];
+        data.extend_from_slice(&[0; 20]);
+        data.extend_from_slice(&[8, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]);
+        assert!(RouteControlRequest::try_from(&prepare(&CCP_CONTROL_DESTINATION, &data)).is_err());
+
+        // An update request with 2^64 - 1 new routes
+        let mut data = vec![0; 32];
+        data.put_var_octet_string(&b"example.speaker"[..]);
+        data.extend_from_slice(&[8, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]);
+        assert!(RouteUpdateRequest::try_from(&prepare(&CCP_UPDATE_DESTINATION, &data)).is_err());
+    }
+}
@@ -1,7 +1,12 @@
 use crate::packet::{Route, RouteUpdateRequest};
+use interledger_packet::{
+    oer::{BufOerExt, MutBufOerExt},
+    ParseError,
+};
 use once_cell::sync::Lazy;
 use ring::rand::{SecureRandom, SystemRandom};
 use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::iter::FromIterator;
 use tracing::{debug, trace};
 
@@ -107,6 +112,11 @@ where
         self.prefix_map.resolve(prefix)
     }
 
+    /// The prefixes the table has routes for
+    pub(crate) fn prefixes(&self) -> impl Iterator<Item = &str> {
+        self.prefix_map.map.keys().map(|prefix| prefix.as_str())
+    }
+
     pub(crate) fn get_simplified_table(&self) -> HashMap<String, A> {
         HashMap::from_iter(
             self.prefix_map
@@ -116,6 +126,28 @@ where
         )
     }
 
+    /// Encodes the routes of the table as they are written in a Route Update Request, so
+    /// that the table of the peer it represents can be shared with the other nodes
+    pub(crate) fn routes_to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.put_var_uint(self.prefix_map.map.len() as u64);
+        for (_account, route) in self.prefix_map.map.values() {
+            route.write_to(&mut buf);
+        }
+        buf
+    }
+
+    /// Builds the table of the routes encoded by [`routes_to_bytes`](#method.routes_to_bytes),
+    /// which were learned from `account`
+    pub(crate) fn from_routes_bytes(account: A, mut data: &[u8]) -> Result<Self, ParseError> {
+        let mut table = RoutingTable::new([0; 16]);
+        let routes_len = data.read_var_uint()? as usize;
+        for _i in 0..routes_len {
+            table.add_route(account.clone(), Route::try_from(&mut data)?);
+        }
+        Ok(table)
+    }
+
     /// Handle a CCP Route Update Request from the peer this table represents
     #[allow(clippy::cognitive_complexity)]
     pub(crate) fn handle_update_request(
@@ -240,6 +272,24 @@ mod table {
     use crate::test_helpers::*;
     use uuid::Uuid;
 
+    #[test]
+    fn encodes_and_decodes_routes() {
+        let mut table = RoutingTable::new([0; 16]);
+        let mut request = UPDATE_REQUEST_COMPLEX.clone();
+        request.from_epoch_index = 0;
+        table
+            .handle_update_request(ROUTING_ACCOUNT.clone(), request)
+            .unwrap();
+
+        let decoded =
+            RoutingTable::from_routes_bytes(ROUTING_ACCOUNT.clone(), &table.routes_to_bytes())
+                .unwrap();
+        assert_eq!(decoded.prefix_map.map.len(), table.prefix_map.map.len());
+        for (prefix, (_account, route)) in table.prefix_map.map.iter() {
+            assert_eq!(&decoded.get_route(prefix).unwrap().1, route);
+        }
+    }
+
     #[test]
     fn sets_id_if_update_has_different() {
         let mut table = RoutingTable::new([0; 16]);
@@ -17,17 +17,18 @@ use interledger_service::{
 use parking_lot::{Mutex, RwLock};
 use ring::digest::{digest, SHA256};
 use std::cmp::Ordering as StdOrdering;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::{
     cmp::min,
     convert::TryFrom,
     str,
     sync::{
-        atomic::{AtomicU32, Ordering},
+        atomic::{AtomicBool, Ordering},
         Arc,
     },
     time::Duration,
 };
+use tokio::{sync::watch, time::Instant};
 use tracing::{debug, error, trace, warn};
 use uuid::Uuid;
 
@@ -43,7 +44,8 @@ use once_cell::sync::Lazy;
 // otherwise. we could make it longer and make sure the BTP server
 // comes after the expiry shortener
 const DEFAULT_ROUTE_EXPIRY_TIME: u32 = 30000;
-const DEFAULT_BROADCAST_INTERVAL: u64 = 30000;
+/// The interval, in milliseconds, on which routes are broadcast if none is configured
+pub const DEFAULT_BROADCAST_INTERVAL: u64 = 30000;
 const DUMMY_ROUTING_TABLE_ID: [u8; 16] = [0; 16];
 
 fn hash(preimage: &[u8; 32]) -> [u8; 32] {
@@ -54,6 +56,9 @@ fn hash(preimage: &[u8; 32]) -> [u8; 32] {
 
 type NewAndWithdrawnRoutes = (Vec<Route>, Vec<String>);
 
+/// Returns the round trip time of the next hop with the given account id, if it is known
+pub type RouteLatency = Arc<dyn Fn(Uuid) -> Option<Duration> + Send + Sync>;
+
 /// Builder for [CcpRouteManager](./CcpRouteManager.html)
 /// See documentation on fields for more details.
 pub struct CcpRouteManagerBuilder<I, O, S> {
@@ -68,6 +73,17 @@ pub struct CcpRouteManagerBuilder<I, O, S> {
     store: S,
     ilp_address: Address,
     broadcast_interval: u64,
+    /// If set, the broadcast interval is changed whenever a new value is sent on this channel
+    broadcast_interval_updates: Option<watch::Receiver<u64>>,
+    /// Whether the route manager currently broadcasts routes on the interval
+    broadcasting: Arc<AtomicBool>,
+    /// Whether the routes learned from the peers are shared with the other nodes using the store
+    shares_routes: bool,
+    /// Whether the route manager spawns its own broadcast interval
+    #[cfg_attr(test, allow(dead_code))]
+    broadcast_interval_spawned: bool,
+    /// If set, the ties between the best routes are broken by the latency of their next hop
+    route_latency: Option<RouteLatency>,
 }
 
 impl<I, O, S, A> CcpRouteManagerBuilder<I, O, S>
@@ -84,6 +100,11 @@ where
             outgoing,
             store,
             broadcast_interval: DEFAULT_BROADCAST_INTERVAL,
+            broadcast_interval_updates: None,
+            broadcasting: Arc::new(AtomicBool::new(true)),
+            shares_routes: false,
+            broadcast_interval_spawned: true,
+            route_latency: None,
         }
     }
 
@@ -98,6 +119,53 @@ where
         self
     }
 
+    /// Set a channel on which the broadcast interval (in milliseconds) can be changed
+    /// while the route manager is running. The current value of the channel is used
+    /// as the initial broadcast interval.
+    pub fn broadcast_interval_updates(&mut self, updates: watch::Receiver<u64>) -> &mut Self {
+        self.broadcast_interval = *updates.borrow();
+        self.broadcast_interval_updates = Some(updates);
+        self
+    }
+
+    /// Set a flag which controls whether routes are broadcast on the interval. When
+    /// multiple nodes share the same store, this lets only one of them broadcast.
+    /// Route update requests from peers are still handled while the flag is unset.
+    pub fn broadcasting(&mut self, broadcasting: Arc<AtomicBool>) -> &mut Self {
+        self.broadcasting = broadcasting;
+        self
+    }
+
+    /// Share the routes learned from the peers with the other nodes using the same store.
+    /// The routes learned by each node are saved in the store, and only the node whose
+    /// [`broadcasting`](#method.broadcasting) flag is set merges them into the routing
+    /// table, which it then writes to the store and broadcasts to the peers. The other
+    /// nodes leave the routing table to it, and do not answer Route Control Requests with
+    /// routing tables of their own.
+    pub fn share_routes(&mut self) -> &mut Self {
+        self.shares_routes = true;
+        self
+    }
+
+    /// Do not spawn the broadcast interval, because the caller requests the routes of the
+    /// peers and runs [`broadcast`](./struct.CcpRouteManager.html#method.broadcast) on a
+    /// schedule of its own
+    pub fn without_broadcast_interval(&mut self) -> &mut Self {
+        self.broadcast_interval_spawned = false;
+        self
+    }
+
+    /// Break the ties between the routes which have the same routing relation and path
+    /// length by the round trip time of their next hop, as returned by `latency`. The next
+    /// hops whose round trip time is known come before those whose is not.
+    pub fn route_latency<F>(&mut self, latency: F) -> &mut Self
+    where
+        F: Fn(Uuid) -> Option<Duration> + Send + Sync + 'static,
+    {
+        self.route_latency = Some(Arc::new(latency));
+        self
+    }
+
     pub fn to_service(&self) -> CcpRouteManager<I, O, S, A> {
         #[allow(clippy::let_and_return)]
         let service = CcpRouteManager {
@@ -107,20 +175,30 @@ where
             store: self.store.clone(),
             forwarding_table: Arc::new(RwLock::new(RoutingTable::default())),
             forwarding_table_updates: Arc::new(RwLock::new(Vec::new())),
-            last_epoch_updates_sent_for: Arc::new(AtomicU32::new(0)),
+            last_route_updates: Arc::new(Mutex::new(HashMap::new())),
             local_table: Arc::new(RwLock::new(RoutingTable::default())),
             incoming_tables: Arc::new(RwLock::new(HashMap::new())),
             unavailable_accounts: Arc::new(Mutex::new(HashMap::new())),
+            broadcasting: self.broadcasting.clone(),
+            shares_routes: self.shares_routes,
+            route_latency: self.route_latency.clone(),
         };
 
         #[cfg(not(test))]
-        {
+        if self.broadcast_interval_spawned {
             let broadcast_interval = self.broadcast_interval;
+            let broadcast_interval_updates = self.broadcast_interval_updates.clone();
             let service_clone = service.clone();
             tokio::spawn(async move {
-                service_clone
-                    .start_broadcast_interval(broadcast_interval)
-                    .await
+                if let Some(updates) = broadcast_interval_updates {
+                    service_clone
+                        .start_broadcast_interval_with_updates(broadcast_interval, updates)
+                        .await
+                } else {
+                    service_clone
+                        .start_broadcast_interval(broadcast_interval)
+                        .await
+                }
             });
         }
 
@@ -138,6 +216,14 @@ struct BackoffParams {
     skip_intervals: u8,
 }
 
+#[derive(Debug)]
+struct LastRouteUpdate {
+    /// The epoch of the forwarding table up to which the account was sent the updates
+    epoch: u32,
+    /// When routes were last broadcast to the account, whether or not it received them
+    broadcast_at: Instant,
+}
+
 /// The Routing Manager Service.
 ///
 /// This implements the Connector-to-Connector Protocol (CCP)
@@ -157,7 +243,8 @@ pub struct CcpRouteManager<I, O, S, A: Account> {
     /// This represents the routing table we will forward to our peers.
     /// It is the same as the local_table with our own address added to the path of each route.
     forwarding_table: Arc<RwLock<RoutingTable<A>>>,
-    last_epoch_updates_sent_for: Arc<AtomicU32>,
+    /// The last route update sent to each account, from which the next one continues
+    last_route_updates: Arc<Mutex<HashMap<Uuid, LastRouteUpdate>>>,
     /// These updates are stored such that index 0 is the transition from epoch 0 to epoch 1
     forwarding_table_updates: Arc<RwLock<Vec<NewAndWithdrawnRoutes>>>,
     /// This is the routing table we have compile from configuration and
@@ -175,6 +262,13 @@ pub struct CcpRouteManager<I, O, S, A: Account> {
     /// This maps the account ID to the number of route brodcast intervals
     /// we should wait before trying again
     unavailable_accounts: Arc<Mutex<HashMap<Uuid, BackoffParams>>>,
+    /// Routes are only broadcast on the interval while this is set
+    broadcasting: Arc<AtomicBool>,
+    /// Whether the routes learned from the peers are shared with the other nodes using the
+    /// store, in which case only the broadcasting node sets the routes
+    shares_routes: bool,
+    /// Breaks the ties between the best routes by the latency of their next hop
+    route_latency: Option<RouteLatency>,
 }
 
 impl<I, O, S, A> CcpRouteManager<I, O, S, A>
@@ -191,6 +285,9 @@ where
         let mut interval = tokio::time::interval(Duration::from_millis(interval));
         loop {
             interval.tick().await;
+            if !self.broadcasting.load(Ordering::Relaxed) {
+                continue;
+            }
             // ensure we have the latest ILP Address from the store
             self.update_ilp_address();
             // Do not consume the result if an error since we want to keep the loop going
@@ -198,6 +295,38 @@ where
         }
     }
 
+    /// Like [`start_broadcast_interval`](#method.start_broadcast_interval), but the interval
+    /// is replaced (and the next broadcast rescheduled) whenever a new value is received
+    /// on `updates`. `interval` and the received values are in milliseconds
+    pub async fn start_broadcast_interval_with_updates(
+        &self,
+        interval: u64,
+        mut updates: watch::Receiver<u64>,
+    ) {
+        self.request_all_routes().await;
+        let mut interval = tokio::time::interval(Duration::from_millis(interval));
+        let mut updates_open = true;
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    if self.broadcasting.load(Ordering::Relaxed) {
+                        self.update_ilp_address();
+                        let _ = self.broadcast_routes().await;
+                    }
+                }
+                update = updates.recv(), if updates_open => match update {
+                    Some(ms) => {
+                        debug!("Changing route broadcast interval to {}ms", ms);
+                        let period = Duration::from_millis(ms);
+                        interval = tokio::time::interval_at(Instant::now() + period, period);
+                    }
+                    // The sender was dropped so the interval will not change anymore
+                    None => updates_open = false,
+                },
+            }
+        }
+    }
+
     fn update_ilp_address(&self) {
         let current_ilp_address = self.ilp_address.read();
         let ilp_address = self.store.get_ilp_address();
@@ -212,6 +341,13 @@ where
         }
     }
 
+    /// Updates the ILP address from the store and broadcasts the routes to the peers,
+    /// which is what the broadcast interval does on each tick
+    pub async fn broadcast(&self) -> Result<(), CcpRoutingStoreError> {
+        self.update_ilp_address();
+        self.broadcast_routes().await
+    }
+
     pub async fn broadcast_routes(&self) -> Result<(), CcpRoutingStoreError> {
         self.update_best_routes(None).await?;
         self.send_route_updates().await
@@ -219,7 +355,7 @@ where
 
     /// Request routes from all the peers we are willing to receive routes from.
     /// This is mostly intended for when the CCP server starts up and doesn't have any routes from peers.
-    async fn request_all_routes(&self) {
+    pub async fn request_all_routes(&self) {
         let result = self.store.get_accounts_to_receive_routes_from().await;
         let accounts = result.unwrap_or_else(|_| Vec::new());
         join_all(
@@ -262,7 +398,7 @@ where
         );
 
         // TODO stop sending updates if they are in Idle mode
-        if control.mode == Mode::Sync {
+        if control.mode == Mode::Sync && !self.leaves_routes_to_other_node() {
             // Don't skip them in the route update broadcasts anymore since this
             // tells us that they are online
             // TODO what happens if they can send to us but we can't send to them?
@@ -414,14 +550,18 @@ where
                 {
                     tokio::spawn({
                         let self_clone = self.clone();
-                        async move { self_clone.update_best_routes(Some(prefixes_updated)).await }
+                        async move {
+                            self_clone
+                                .handle_learned_routes(request.from, prefixes_updated)
+                                .await
+                        }
                     });
                 }
 
                 #[cfg(test)]
                 {
                     let ilp_address = self.ilp_address.clone();
-                    self.update_best_routes(Some(prefixes_updated))
+                    self.handle_learned_routes(request.from, prefixes_updated)
                         .map_err(move |_| {
                             RejectBuilder {
                                 code: ErrorCode::T00_INTERNAL_ERROR,
@@ -513,15 +653,78 @@ where
         }
     }
 
+    /// Saves the routes learned from the account in the store, if they are shared with the
+    /// other nodes, and then checks whether there are better routes for the updated prefixes
+    async fn handle_learned_routes(
+        &self,
+        account: A,
+        prefixes_updated: Vec<String>,
+    ) -> Result<(), CcpRoutingStoreError> {
+        if self.shares_routes {
+            let routes = self
+                .incoming_tables
+                .read()
+                .get(&account.id())
+                .map(RoutingTable::routes_to_bytes)
+                .unwrap_or_default();
+            self.store
+                .clone()
+                .set_learned_routes(account, routes)
+                .await?;
+        }
+        self.update_best_routes(Some(prefixes_updated)).await
+    }
+
+    /// Whether the routes are shared with the other nodes using the store and another one
+    /// of them sets and broadcasts the routing table
+    fn leaves_routes_to_other_node(&self) -> bool {
+        self.shares_routes && !self.broadcasting.load(Ordering::Relaxed)
+    }
+
+    /// Returns the routing tables of the peers, as learned by all the nodes sharing the
+    /// store. The tables saved in the store replace those of this node, which are used
+    /// for the peers none of the nodes saved the routes of.
+    async fn get_shared_incoming_tables(
+        &self,
+    ) -> Result<HashMap<Uuid, RoutingTable<A>>, CcpRoutingStoreError> {
+        let learned_routes = self.store.get_learned_routes().await?;
+        let mut incoming_tables = self.incoming_tables.read().clone();
+        for (account, routes) in learned_routes {
+            let account_id = account.id();
+            match RoutingTable::from_routes_bytes(account, &routes) {
+                Ok(table) => {
+                    incoming_tables.insert(account_id, table);
+                }
+                Err(err) => warn!(
+                    "Ignoring the routes learned from account {} which could not be parsed: {:?}",
+                    account_id, err
+                ),
+            }
+        }
+        Ok(incoming_tables)
+    }
+
     /// Check whether the Local Routing Table currently has the best routes for the
     /// given prefixes. This is triggered when we get an incoming Route Update Request
     /// with some new or modified routes that might be better than our existing ones.
     ///
     /// If prefixes is None, this will check the best routes for all local and configured prefixes.
+    /// When the routes are shared with the other nodes, it also checks the prefixes learned by
+    /// all the nodes and those of the Local Routing Table, and it is only done by the node
+    /// which broadcasts the routes.
     async fn update_best_routes(
         &self,
         prefixes: Option<Vec<String>>,
     ) -> Result<(), CcpRoutingStoreError> {
+        if self.leaves_routes_to_other_node() {
+            trace!("Leaving the routes learned from the peers to the node which broadcasts routes");
+            return Ok(());
+        }
+        let shared_incoming_tables = if self.shares_routes {
+            Some(self.get_shared_incoming_tables().await?)
+        } else {
+            None
+        };
         let local_table = self.local_table.clone();
         let forwarding_table = self.forwarding_table.clone();
         let forwarding_table_updates = self.forwarding_table_updates.clone();
@@ -536,7 +739,10 @@ where
         let (better_routes, withdrawn_routes) = {
             // Note we only use a read lock here and later get a write lock if we need to update the table
             let local_table = local_table.read();
-            let incoming_tables = incoming_tables.read();
+            let own_incoming_tables = incoming_tables.read();
+            let incoming_tables = shared_incoming_tables
+                .as_ref()
+                .unwrap_or(&*own_incoming_tables);
 
             // Either check the given prefixes or check all of our local and configured routes
             let prefixes_to_check: Box<dyn Iterator<Item = &str>> =
@@ -544,7 +750,17 @@ where
                     Box::new(prefixes.iter().map(|prefix| prefix.as_str()))
                 } else {
                     let routes = configured_routes.iter().chain(local_routes.iter());
-                    Box::new(routes.map(|(prefix, _account)| prefix.as_str()))
+                    let prefixes = routes.map(|(prefix, _account)| prefix.as_str());
+                    if shared_incoming_tables.is_some() {
+                        // The other nodes may have learned or withdrawn routes since the last check
+                        let prefixes: HashSet<&str> = prefixes
+                            .chain(incoming_tables.values().flat_map(RoutingTable::prefixes))
+                            .chain(local_table.prefixes())
+                            .collect();
+                        Box::new(prefixes.into_iter())
+                    } else {
+                        Box::new(prefixes)
+                    }
                 };
 
             // Check all the prefixes to see which ones we have different routes for
@@ -557,8 +773,9 @@ where
                 if let Some((best_next_account, best_route)) = get_best_route_for_prefix(
                     &local_routes,
                     &configured_routes,
-                    &incoming_tables,
+                    incoming_tables,
                     prefix,
+                    self.route_latency.as_ref(),
                 ) {
                     if let Some((ref next_account, ref _route)) = local_table.get_route(prefix) {
                         if next_account.id() == best_next_account.id() {
@@ -682,34 +899,54 @@ where
             .store
             .get_accounts_to_send_routes_to(accounts_to_skip)
             .await?;
-
-        let to_epoch_index = self_clone.forwarding_table.read().epoch();
-        let from_epoch_index = self_clone
-            .last_epoch_updates_sent_for
-            .swap(to_epoch_index, Ordering::SeqCst);
-
-        let route_update_request = self_clone.create_route_update(from_epoch_index, to_epoch_index);
-
-        let prepare = route_update_request.to_prepare();
         accounts.sort_unstable_by_key(|a| a.id().to_string());
         accounts.dedup_by_key(|a| a.id());
 
+        let to_epoch_index = self_clone.forwarding_table.read().epoch();
+        let now = Instant::now();
+        // Each account is sent the epochs since the last update it received, unless it wants
+        // the whole table. The accounts with a longer interval than the node's are skipped
+        // until it has elapsed (give or take a tenth, since the ticks are not exact)
+        let accounts: Vec<(A, u32)> = {
+            let last_route_updates = self.last_route_updates.lock();
+            accounts
+                .into_iter()
+                .filter_map(|account| {
+                    let last_update = last_route_updates.get(&account.id());
+                    if let (Some(interval), Some(last_update)) =
+                        (account.route_broadcast_interval(), last_update)
+                    {
+                        let interval = Duration::from_millis(interval - interval / 10);
+                        if now.duration_since(last_update.broadcast_at) < interval {
+                            return None;
+                        }
+                    }
+                    let from_epoch_index = match last_update {
+                        Some(last_update) if !account.should_receive_full_table() => {
+                            min(last_update.epoch, to_epoch_index)
+                        }
+                        _ => 0,
+                    };
+                    Some((account, from_epoch_index))
+                })
+                .collect()
+        };
+
         let broadcasting = !accounts.is_empty();
         if broadcasting {
             trace!(
-                "Sending route update for epochs {} - {} to accounts: {:?} {}",
-                from_epoch_index,
+                "Sending route updates up to epoch {} to accounts: {}",
                 to_epoch_index,
-                route_update_request,
                 {
                     let account_list: Vec<String> = accounts
                         .iter()
-                        .map(|a| {
+                        .map(|(a, from_epoch_index)| {
                             format!(
-                                "{} (id: {}, ilp_address: {})",
+                                "{} (id: {}, ilp_address: {}, from epoch: {})",
                                 a.username(),
                                 a.id(),
-                                a.ilp_address()
+                                a.ilp_address(),
+                                from_epoch_index
                             )
                         })
                         .collect();
@@ -722,13 +959,23 @@ where
             // It is odd that the same but with `.await` instead does not work.
             let mut outgoing = self_clone.outgoing.clone();
             let mut results = Vec::new();
-            for account in accounts.into_iter() {
+            // The accounts sent the same epochs with the same expiry share the update
+            let mut prepares = HashMap::new();
+            for (account, from_epoch_index) in accounts.into_iter() {
+                let prepare = prepares
+                    .entry((from_epoch_index, account.route_expiry()))
+                    .or_insert_with(|| {
+                        self_clone
+                            .create_route_update_for(&account, from_epoch_index, to_epoch_index)
+                            .to_prepare()
+                    })
+                    .clone();
                 let res = outgoing
                     .send_request(OutgoingRequest {
                         from: account.clone(),
                         to: account.clone(),
                         original_amount: prepare.amount(),
-                        prepare: prepare.clone(),
+                        prepare,
                     })
                     .await;
                 results.push((account, res));
@@ -737,7 +984,20 @@ where
             // Handle the results of the route broadcast attempts
             trace!("Updating unavailable accounts");
             let mut unavailable_accounts = unavailable_accounts.lock();
+            let mut last_route_updates = self.last_route_updates.lock();
             for (account, result) in results.into_iter() {
+                // The accounts which did not get the update are sent its epochs again next time
+                let last_update =
+                    last_route_updates
+                        .entry(account.id())
+                        .or_insert(LastRouteUpdate {
+                            epoch: 0,
+                            broadcast_at: now,
+                        });
+                last_update.broadcast_at = now;
+                if result.is_ok() {
+                    last_update.epoch = to_epoch_index;
+                }
                 match (account.routing_relation(), result) {
                     (RoutingRelation::Child, Err(err)) => {
                         if let Some(backoff) = unavailable_accounts.get_mut(&account.id()) {
@@ -859,11 +1119,24 @@ where
         }
     }
 
+    /// Create a RouteUpdateRequest for the given range of epochs, with the route expiry
+    /// configured for the account
+    fn create_route_update_for(
+        &self,
+        account: &A,
+        from_epoch_index: u32,
+        to_epoch_index: u32,
+    ) -> RouteUpdateRequest {
+        let mut update = self.create_route_update(from_epoch_index, to_epoch_index);
+        update.hold_down_time = account.route_expiry().unwrap_or(DEFAULT_ROUTE_EXPIRY_TIME);
+        update
+    }
+
     /// Send a Route Update Request to a specific account for the given epoch range.
     /// This is used when the peer has fallen behind and has requested a specific range of updates.
     async fn send_route_update(&self, account: A, from_epoch_index: u32, to_epoch_index: u32) {
         let prepare = self
-            .create_route_update(from_epoch_index, to_epoch_index)
+            .create_route_update_for(&account, from_epoch_index, to_epoch_index)
             .to_prepare();
         let account_id = account.id();
         debug!(
@@ -881,11 +1154,22 @@ where
             })
             .await;
 
-        if let Err(err) = result {
-            error!(
+        match result {
+            Ok(_) => {
+                // The next broadcast continues from the epochs the account just received
+                let mut last_route_updates = self.last_route_updates.lock();
+                last_route_updates
+                    .entry(account_id)
+                    .or_insert(LastRouteUpdate {
+                        epoch: to_epoch_index,
+                        broadcast_at: Instant::now(),
+                    })
+                    .epoch = to_epoch_index;
+            }
+            Err(err) => error!(
                 "Error sending route update to account {}: {:?}",
                 account_id, err
-            )
+            ),
         }
     }
 }
@@ -895,6 +1179,7 @@ fn get_best_route_for_prefix<A: CcpRoutingAccount>(
     configured_routes: &HashMap<String, A>,
     incoming_tables: &HashMap<Uuid, RoutingTable<A>>,
     prefix: &str,
+    latency: Option<&RouteLatency>,
 ) -> Option<(A, Route)> {
     // Check if we have a configured route for that specific prefix
     // or any shorter prefix ("example.a.b.c" will match "example.a.b" and "example.a")
@@ -949,11 +1234,29 @@ fn get_best_route_for_prefix<A: CcpRoutingAccount>(
                             StdOrdering::Less => (best_account, best_route),
                             StdOrdering::Greater => (account, route),
                             _ => {
-                                // Finally base it on account ID
-                                if best_account.id().to_string() < account.id().to_string() {
-                                    (best_account, best_route)
-                                } else {
-                                    (account, route)
+                                // Then prioritize the next hop with the lowest latency
+                                let latencies = latency.map(|latency| {
+                                    (latency(best_account.id()), latency(account.id()))
+                                });
+                                match latencies {
+                                    Some((Some(best), Some(other))) if best != other => {
+                                        if best < other {
+                                            (best_account, best_route)
+                                        } else {
+                                            (account, route)
+                                        }
+                                    }
+                                    Some((Some(_), None)) => (best_account, best_route),
+                                    Some((None, Some(_))) => (account, route),
+                                    // Finally base it on account ID
+                                    _ => {
+                                        if best_account.id().to_string() < account.id().to_string()
+                                        {
+                                            (best_account, best_route)
+                                        } else {
+                                            (account, route)
+                                        }
+                                    }
                                 }
                             }
                         }
@@ -1057,6 +1360,15 @@ mod ranking_routes {
                 props: Vec::new(),
             },
         );
+        peer_table_1.add_route(
+            peer_1.clone(),
+            Route {
+                prefix: "example.f".to_string(),
+                path: vec!["example.one".to_string()],
+                auth: [0; 32],
+                props: Vec::new(),
+            },
+        );
         peer_table_1.add_route(
             peer_1,
             Route {
@@ -1069,6 +1381,15 @@ mod ranking_routes {
         );
         let mut peer_table_2 = RoutingTable::default();
         let peer_2 = TestAccount::new(Uuid::from_slice(&[8; 16]).unwrap(), "example.peer2");
+        peer_table_2.add_route(
+            peer_2.clone(),
+            Route {
+                prefix: "example.f".to_string(),
+                path: vec!["example.two".to_string()],
+                auth: [0; 32],
+                props: Vec::new(),
+            },
+        );
         peer_table_2.add_route(
             peer_2,
             Route {
@@ -1087,7 +1408,8 @@ mod ranking_routes {
 
     #[test]
     fn prioritizes_configured_routes() {
-        let best_route = get_best_route_for_prefix(&LOCAL, &CONFIGURED, &INCOMING, "example.a");
+        let best_route =
+            get_best_route_for_prefix(&LOCAL, &CONFIGURED, &INCOMING, "example.a", None);
         assert_eq!(
             best_route.unwrap().0.id(),
             Uuid::from_slice(&[4; 16]).unwrap()
@@ -1097,7 +1419,7 @@ mod ranking_routes {
     #[test]
     fn prioritizes_shorter_configured_routes() {
         let best_route =
-            get_best_route_for_prefix(&LOCAL, &CONFIGURED, &INCOMING, "example.a.sub-prefix");
+            get_best_route_for_prefix(&LOCAL, &CONFIGURED, &INCOMING, "example.a.sub-prefix", None);
         assert_eq!(
             best_route.unwrap().0.id(),
             Uuid::from_slice(&[4; 16]).unwrap()
@@ -1106,7 +1428,8 @@ mod ranking_routes {
 
     #[test]
     fn prioritizes_local_routes_over_broadcasted_ones() {
-        let best_route = get_best_route_for_prefix(&LOCAL, &CONFIGURED, &INCOMING, "example.c");
+        let best_route =
+            get_best_route_for_prefix(&LOCAL, &CONFIGURED, &INCOMING, "example.c", None);
         assert_eq!(
             best_route.unwrap().0.id(),
             Uuid::from_slice(&[3; 16]).unwrap()
@@ -1115,7 +1438,8 @@ mod ranking_routes {
 
     #[test]
     fn prioritizes_children_over_peers() {
-        let best_route = get_best_route_for_prefix(&LOCAL, &CONFIGURED, &INCOMING, "example.d");
+        let best_route =
+            get_best_route_for_prefix(&LOCAL, &CONFIGURED, &INCOMING, "example.d", None);
         assert_eq!(
             best_route.unwrap().0.id(),
             Uuid::from_slice(&[6; 16]).unwrap()
@@ -1124,16 +1448,45 @@ mod ranking_routes {
 
     #[test]
     fn prioritizes_shorter_paths() {
-        let best_route = get_best_route_for_prefix(&LOCAL, &CONFIGURED, &INCOMING, "example.e");
+        let best_route =
+            get_best_route_for_prefix(&LOCAL, &CONFIGURED, &INCOMING, "example.e", None);
         assert_eq!(
             best_route.unwrap().0.id(),
             Uuid::from_slice(&[7; 16]).unwrap()
         );
     }
 
+    #[test]
+    fn breaks_ties_by_latency() {
+        let peer_1 = Uuid::from_slice(&[7; 16]).unwrap();
+        let peer_2 = Uuid::from_slice(&[8; 16]).unwrap();
+        let best_route =
+            get_best_route_for_prefix(&LOCAL, &CONFIGURED, &INCOMING, "example.f", None);
+        assert_eq!(best_route.unwrap().0.id(), peer_1);
+
+        let latency: RouteLatency = Arc::new(move |id| {
+            if id == peer_1 {
+                Some(Duration::from_millis(50))
+            } else {
+                Some(Duration::from_millis(10))
+            }
+        });
+        let best_route =
+            get_best_route_for_prefix(&LOCAL, &CONFIGURED, &INCOMING, "example.f", Some(&latency));
+        assert_eq!(best_route.unwrap().0.id(), peer_2);
+
+        // The next hops whose latency is known come first
+        let latency: RouteLatency =
+            Arc::new(move |id| Some(Duration::from_millis(50)).filter(|_| id == peer_2));
+        let best_route =
+            get_best_route_for_prefix(&LOCAL, &CONFIGURED, &INCOMING, "example.f", Some(&latency));
+        assert_eq!(best_route.unwrap().0.id(), peer_2);
+    }
+
     #[test]
     fn returns_none_for_no_route() {
-        let best_route = get_best_route_for_prefix(&LOCAL, &CONFIGURED, &INCOMING, "example.z");
+        let best_route =
+            get_best_route_for_prefix(&LOCAL, &CONFIGURED, &INCOMING, "example.z", None);
         assert!(best_route.is_none());
     }
 }
@@ -1909,6 +2262,9 @@ mod send_route_updates {
                     id: id2,
                     ilp_address: Address::from_str("example.connector.other-local").unwrap(),
                     relation: RoutingRelation::Child,
+                    route_broadcast_interval: None,
+                    route_expiry: None,
+                    full_table: false,
                 },
             ),
         ]);
@@ -1997,6 +2353,9 @@ mod send_route_updates {
             id: id2,
             ilp_address: Address::from_str("example.connector.other-local").unwrap(),
             relation: RoutingRelation::Child,
+            route_broadcast_interval: None,
+            route_expiry: None,
+            full_table: false,
         };
         let local_routes = HashMap::from_iter(vec![
             (
@@ -2074,4 +2433,225 @@ mod send_route_updates {
         // When we send again, we don't skip the child because we got a request from them
         assert_eq!(outgoing_requests.lock().len(), 2);
     }
+
+    #[tokio::test]
+    async fn applies_the_broadcast_settings_of_each_account() {
+        let id1 = Uuid::from_slice(&[1; 16]).unwrap();
+        let id2 = Uuid::from_slice(&[2; 16]).unwrap();
+        let local_routes = HashMap::from_iter(vec![
+            (
+                "example.local.1".to_string(),
+                TestAccount::new(id1, "example.local.1"),
+            ),
+            (
+                "example.local.2".to_string(),
+                TestAccount {
+                    id: id2,
+                    ilp_address: Address::from_str("example.local.2").unwrap(),
+                    relation: RoutingRelation::Peer,
+                    route_broadcast_interval: Some(60000),
+                    route_expiry: Some(90000),
+                    full_table: true,
+                },
+            ),
+        ]);
+        let store = TestStore::with_routes(local_routes, HashMap::new());
+        let outgoing_requests: Arc<Mutex<Vec<OutgoingRequest<TestAccount>>>> =
+            Arc::new(Mutex::new(Vec::new()));
+        let outgoing_requests_clone = outgoing_requests.clone();
+        let outgoing = outgoing_service_fn(move |request: OutgoingRequest<TestAccount>| {
+            (*outgoing_requests_clone.lock()).push(request);
+            Ok(CCP_RESPONSE.clone())
+        });
+        let service = CcpRouteManagerBuilder::new(
+            Address::from_str("example.connector").unwrap(),
+            store,
+            outgoing,
+            incoming_service_fn(|_request| {
+                Err(RejectBuilder {
+                    code: ErrorCode::F02_UNREACHABLE,
+                    message: b"No other incoming handler!",
+                    data: &[],
+                    triggered_by: Some(&EXAMPLE_CONNECTOR),
+                }
+                .build())
+            }),
+        )
+        .to_service();
+        let sent_updates = |outgoing_requests: &Mutex<Vec<OutgoingRequest<TestAccount>>>| {
+            let updates: HashMap<Uuid, RouteUpdateRequest> = outgoing_requests
+                .lock()
+                .drain(..)
+                .map(|request| {
+                    let update = RouteUpdateRequest::try_from(&request.prepare).unwrap();
+                    (request.to.id(), update)
+                })
+                .collect();
+            updates
+        };
+
+        service.update_best_routes(None).await.unwrap();
+        service.send_route_updates().await.unwrap();
+        let updates = sent_updates(&outgoing_requests);
+        assert_eq!(updates[&id1].hold_down_time, 30000);
+        assert_eq!(updates[&id2].hold_down_time, 90000);
+        let first_epoch = updates[&id1].to_epoch_index;
+
+        let mut parent = TestAccount::new(Uuid::new_v4(), "example.parent");
+        parent.relation = RoutingRelation::Parent;
+        service
+            .handle_route_update_request(IncomingRequest {
+                from: parent,
+                prepare: RouteUpdateRequest {
+                    routing_table_id: [0; 16],
+                    current_epoch_index: 1,
+                    from_epoch_index: 0,
+                    to_epoch_index: 1,
+                    hold_down_time: 30000,
+                    speaker: Address::from_str("example.remote").unwrap(),
+                    new_routes: vec![Route {
+                        prefix: "example.remote".to_string(),
+                        path: vec!["example.parent".to_string()],
+                        auth: [0; 32],
+                        props: Vec::new(),
+                    }],
+                    withdrawn_routes: Vec::new(),
+                }
+                .to_prepare(),
+            })
+            .await
+            .unwrap();
+
+        // The account with the longer interval is skipped, the other one only gets the changes
+        service.send_route_updates().await.unwrap();
+        let updates = sent_updates(&outgoing_requests);
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[&id1].from_epoch_index, first_epoch);
+        assert_eq!(updates[&id1].new_routes.len(), 1);
+        assert_eq!(updates[&id1].new_routes[0].prefix, "example.remote");
+
+        // Once its interval elapsed, it gets the whole table
+        service
+            .last_route_updates
+            .lock()
+            .get_mut(&id2)
+            .unwrap()
+            .broadcast_at -= Duration::from_millis(60000);
+        service.send_route_updates().await.unwrap();
+        let updates = sent_updates(&outgoing_requests);
+        assert_eq!(updates.len(), 2);
+        assert_eq!(updates[&id2].from_epoch_index, 0);
+        assert_eq!(updates[&id2].new_routes.len(), 4);
+        assert_eq!(updates[&id1].new_routes.len(), 0);
+    }
+}
+
+#[cfg(test)]
+mod sharing_routes {
+    use super::*;
+    use crate::fixtures::*;
+    use crate::test_helpers::*;
+    use interledger_service::*;
+    use std::str::FromStr;
+
+    fn node(
+        store: TestStore,
+        broadcasting: bool,
+    ) -> CcpRouteManager<
+        impl IncomingService<TestAccount> + Clone,
+        impl OutgoingService<TestAccount> + Clone,
+        TestStore,
+        TestAccount,
+    > {
+        let addr = Address::from_str("example.connector").unwrap();
+        CcpRouteManagerBuilder::new(
+            addr.clone(),
+            store,
+            outgoing_service_fn(|_request| Ok(CCP_RESPONSE.clone())),
+            incoming_service_fn(|_request| {
+                Err(RejectBuilder {
+                    code: ErrorCode::F02_UNREACHABLE,
+                    message: b"No other incoming handler!",
+                    data: &[],
+                    triggered_by: Some(&EXAMPLE_CONNECTOR),
+                }
+                .build())
+            }),
+        )
+        .ilp_address(addr)
+        .broadcasting(Arc::new(AtomicBool::new(broadcasting)))
+        .share_routes()
+        .to_service()
+    }
+
+    fn update_request() -> IncomingRequest<TestAccount> {
+        let mut request = UPDATE_REQUEST_COMPLEX.clone();
+        request.to_epoch_index = 1;
+        request.from_epoch_index = 0;
+        IncomingRequest {
+            from: ROUTING_ACCOUNT.clone(),
+            prepare: request.to_prepare(),
+        }
+    }
+
+    #[tokio::test]
+    async fn other_nodes_save_learned_routes_without_setting_routes() {
+        let store = TestStore::new();
+        let mut follower = node(store.clone(), false);
+        follower.handle_request(update_request()).await.unwrap();
+
+        assert!(store.routes.lock().is_empty());
+        assert!(follower.local_table.read().prefixes().next().is_none());
+        let learned_routes = store.learned_routes.lock();
+        let (account, routes) = &learned_routes[&ROUTING_ACCOUNT.id()];
+        let table = RoutingTable::from_routes_bytes(account.clone(), routes).unwrap();
+        assert!(table.get_route("example.prefix1").is_some());
+        assert!(table.get_route("example.prefix2").is_some());
+    }
+
+    #[tokio::test]
+    async fn broadcasting_node_sets_and_advertises_routes_learned_by_other_nodes() {
+        let store = TestStore::new();
+        let mut follower = node(store.clone(), false);
+        let leader = node(store.clone(), true);
+        follower.handle_request(update_request()).await.unwrap();
+
+        leader.update_best_routes(None).await.unwrap();
+        for prefix in &["example.prefix1", "example.prefix2"] {
+            assert_eq!(
+                store.routes.lock().get(*prefix).unwrap().id(),
+                ROUTING_ACCOUNT.id()
+            );
+            let (account, route) = leader
+                .forwarding_table
+                .read()
+                .get_route(prefix)
+                .unwrap()
+                .clone();
+            assert_eq!(account.id(), ROUTING_ACCOUNT.id());
+            assert_eq!(route.path[0], "example.connector");
+        }
+
+        // The routes withdrawn from another node are withdrawn by the leader too
+        let mut request = UPDATE_REQUEST_COMPLEX.clone();
+        request.from_epoch_index = 1;
+        request.to_epoch_index = 2;
+        request.new_routes = Vec::new();
+        request.withdrawn_routes = vec!["example.prefix1".to_string()];
+        follower
+            .handle_request(IncomingRequest {
+                from: ROUTING_ACCOUNT.clone(),
+                prepare: request.to_prepare(),
+            })
+            .await
+            .unwrap();
+        leader.update_best_routes(None).await.unwrap();
+        assert!(store.routes.lock().get("example.prefix1").is_none());
+        assert!(leader
+            .forwarding_table
+            .read()
+            .get_route("example.prefix1")
+            .is_none());
+        assert!(store.routes.lock().get("example.prefix2").is_some());
+    }
 }
@@ -18,16 +18,25 @@ pub static ROUTING_ACCOUNT: Lazy<TestAccount> = Lazy::new(|| TestAccount {
     id: Uuid::new_v4(),
     ilp_address: Address::from_str("example.peer").unwrap(),
     relation: RoutingRelation::Peer,
+    route_broadcast_interval: None,
+    route_expiry: None,
+    full_table: false,
 });
 pub static NON_ROUTING_ACCOUNT: Lazy<TestAccount> = Lazy::new(|| TestAccount {
     id: Uuid::new_v4(),
     ilp_address: Address::from_str("example.me.nonroutingaccount").unwrap(),
     relation: RoutingRelation::NonRoutingAccount,
+    route_broadcast_interval: None,
+    route_expiry: None,
+    full_table: false,
 });
 pub static CHILD_ACCOUNT: Lazy<TestAccount> = Lazy::new(|| TestAccount {
     id: Uuid::new_v4(),
     ilp_address: Address::from_str("example.me.child").unwrap(),
     relation: RoutingRelation::Child,
+    route_broadcast_interval: None,
+    route_expiry: None,
+    full_table: false,
 });
 pub static EXAMPLE_CONNECTOR: Lazy<Address> =
     Lazy::new(|| Address::from_str("example.connector").unwrap());
@@ -38,6 +47,9 @@ pub struct TestAccount {
     pub id: Uuid,
     pub ilp_address: Address,
     pub relation: RoutingRelation,
+    pub route_broadcast_interval: Option<u64>,
+    pub route_expiry: Option<u32>,
+    pub full_table: bool,
 }
 
 impl TestAccount {
@@ -46,6 +58,9 @@ impl TestAccount {
             id,
             ilp_address: Address::from_str(ilp_address).unwrap(),
             relation: RoutingRelation::Peer,
+            route_broadcast_interval: None,
+            route_expiry: None,
+            full_table: false,
         }
     }
 }
@@ -76,6 +91,18 @@ impl CcpRoutingAccount for TestAccount {
     fn routing_relation(&self) -> RoutingRelation {
         self.relation
     }
+
+    fn route_broadcast_interval(&self) -> Option<u64> {
+        self.route_broadcast_interval
+    }
+
+    fn route_expiry(&self) -> Option<u32> {
+        self.route_expiry
+    }
+
+    fn should_receive_full_table(&self) -> bool {
+        self.full_table
+    }
 }
 
 #[derive(Clone)]
@@ -83,6 +110,7 @@ pub struct TestStore {
     pub local: HashMap<String, TestAccount>,
     pub configured: HashMap<String, TestAccount>,
     pub routes: Arc<Mutex<HashMap<String, TestAccount>>>,
+    pub learned_routes: Arc<Mutex<HashMap<Uuid, (TestAccount, Vec<u8>)>>>,
 }
 
 impl TestStore {
@@ -91,6 +119,7 @@ impl TestStore {
             local: HashMap::new(),
             configured: HashMap::new(),
             routes: Arc::new(Mutex::new(HashMap::new())),
+            learned_routes: Arc::new(Mutex::new(HashMap::new())),
         }
     }
 
@@ -102,6 +131,7 @@ impl TestStore {
             local,
             configured,
             routes: Arc::new(Mutex::new(HashMap::new())),
+            learned_routes: Arc::new(Mutex::new(HashMap::new())),
         }
     }
 }
@@ -175,6 +205,23 @@ impl CcpRoutingStore for TestStore {
         *self.routes.lock() = HashMap::from_iter(routes.into_iter());
         Ok(())
     }
+
+    async fn set_learned_routes(
+        &mut self,
+        account: TestAccount,
+        routes: Vec<u8>,
+    ) -> Result<(), CcpRoutingStoreError> {
+        self.learned_routes
+            .lock()
+            .insert(account.id(), (account, routes));
+        Ok(())
+    }
+
+    async fn get_learned_routes(
+        &self,
+    ) -> Result<Vec<(TestAccount, Vec<u8>)>, CcpRoutingStoreError> {
+        Ok(self.learned_routes.lock().values().cloned().collect())
+    }
 }
 
 pub fn test_service() -> CcpRouteManager<
@@ -232,6 +279,9 @@ pub fn test_service_with_routes() -> (
                 id: Uuid::from_slice(&[3; 16]).unwrap(),
                 ilp_address: Address::from_str("example.connector.other-local").unwrap(),
                 relation: RoutingRelation::NonRoutingAccount,
+                route_broadcast_interval: None,
+                route_expiry: None,
+                full_table: false,
             },
         ),
     ]);
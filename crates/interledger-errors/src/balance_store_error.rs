@@ -8,11 +8,50 @@ use thiserror::Error;
 pub enum BalanceStoreError {
     #[error("{0}")]
     Other(#[from] Box<dyn StdError + Send + 'static>),
+    #[error("account `{0}` was not found")]
+    AccountNotFound(String),
+    #[error("balance of account `{0}` changed while it was being recovered")]
+    ConcurrentModification(String),
+    #[error("{kind} limit of account `{account}` ({limit}) exceeded by {exceeded_by}")]
+    LiquidityExceeded {
+        account: String,
+        /// Which of the account's credit limits was exceeded (`payable` or `receivable`)
+        kind: &'static str,
+        limit: u64,
+        exceeded_by: u64,
+    },
+    #[error("{window} {direction} limit of account `{account}` ({limit}) exceeded, {used} was already transferred in its window")]
+    TransferLimitExceeded {
+        account: String,
+        /// Which of the account's transfer limits was exceeded (`send` or `receive`)
+        direction: &'static str,
+        /// The window of the limit (`daily` or `monthly`)
+        window: &'static str,
+        limit: u64,
+        used: u64,
+        /// When the oldest amount counted in the window leaves it, in milliseconds since the UNIX epoch
+        reset_at: u64,
+    },
+    #[error("Incoming prepare of {amount} would bring account {account} under its minimum balance. Current balance: {balance}, min balance: {min_balance}")]
+    MinimumBalanceExceeded {
+        account: String,
+        amount: u64,
+        balance: i64,
+        min_balance: i64,
+    },
 }
 
 impl From<BalanceStoreError> for ApiError {
     fn from(src: BalanceStoreError) -> Self {
-        ApiError::internal_server_error().detail(src.to_string())
+        match src {
+            BalanceStoreError::AccountNotFound(_) => {
+                ApiError::account_not_found().detail(src.to_string())
+            }
+            BalanceStoreError::ConcurrentModification(_) => {
+                ApiError::conflict().detail(src.to_string())
+            }
+            _ => ApiError::internal_server_error().detail(src.to_string()),
+        }
     }
 }
 
@@ -20,6 +20,12 @@ pub enum CreateAccountError {
     InvalidRoutingRelation(String),
     #[error("the provided value for parameter `{0}` was too large")]
     ParamTooLarge(String),
+    #[error("the provided transport account is not valid: {0}")]
+    InvalidTransportAccount(String),
+    #[error("the provided ILP over HTTP signature key is not valid: {0}")]
+    InvalidHttpSignatureKey(String),
+    #[error("the provided value for rate limit `{0}` must be greater than zero")]
+    InvalidRateLimit(String),
 }
 
 impl From<CreateAccountError> for ApiError {
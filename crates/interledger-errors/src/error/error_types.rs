@@ -54,6 +54,27 @@ pub const DEFAULT_IDEMPOTENT_CONFLICT_TYPE: ApiErrorType = ApiErrorType {
     status: StatusCode::CONFLICT,
 };
 
+/// 413 Payload Too Large HTTP Status Code
+pub const DEFAULT_PAYLOAD_TOO_LARGE_TYPE: ApiErrorType = ApiErrorType {
+    r#type: &ProblemType::Default,
+    title: "Payload Too Large",
+    status: StatusCode::PAYLOAD_TOO_LARGE,
+};
+
+/// 429 Too Many Requests HTTP Status Code
+pub const DEFAULT_TOO_MANY_REQUESTS_TYPE: ApiErrorType = ApiErrorType {
+    r#type: &ProblemType::Default,
+    title: "Too Many Requests",
+    status: StatusCode::TOO_MANY_REQUESTS,
+};
+
+/// 501 Not Implemented HTTP Status Code
+pub const DEFAULT_NOT_IMPLEMENTED_TYPE: ApiErrorType = ApiErrorType {
+    r#type: &ProblemType::Default,
+    title: "Not Implemented",
+    status: StatusCode::NOT_IMPLEMENTED,
+};
+
 // ILP over HTTP specific errors
 
 /// ILP over HTTP invalid packet error type  (400 Bad Request)
@@ -180,6 +180,16 @@ impl ApiError {
         ApiError::from_api_error_type(&DEFAULT_CONFLICT_TYPE)
     }
 
+    /// Returns a Too Many Requests [ApiError](./struct.ApiError.html)
+    pub fn too_many_requests() -> Self {
+        ApiError::from_api_error_type(&DEFAULT_TOO_MANY_REQUESTS_TYPE)
+    }
+
+    /// Returns a Not Implemented [ApiError](./struct.ApiError.html)
+    pub fn not_implemented() -> Self {
+        ApiError::from_api_error_type(&DEFAULT_NOT_IMPLEMENTED_TYPE)
+    }
+
     /// Returns an Invalid Account Id [ApiError](./struct.ApiError.html)
     pub fn invalid_account_id(invalid_account_id: Option<&str>) -> Self {
         let detail = match invalid_account_id {
@@ -348,6 +358,10 @@ pub async fn default_rejection_handler(err: warp::Rejection) -> Result<impl Repl
         Ok(api_error.clone().into_response())
     } else if let Some(json_error) = err.find::<JsonDeserializeError>() {
         Ok(json_error.clone().into_response())
+    } else if err.find::<warp::reject::PayloadTooLarge>().is_some() {
+        // Checked before the method, since the routes of the other methods also reject
+        // the request
+        Ok(ApiError::from_api_error_type(&DEFAULT_PAYLOAD_TOO_LARGE_TYPE).into_response())
     } else if err.find::<warp::reject::MethodNotAllowed>().is_some() {
         Ok(ApiError::from_api_error_type(&DEFAULT_METHOD_NOT_ALLOWED_TYPE).into_response())
     } else {
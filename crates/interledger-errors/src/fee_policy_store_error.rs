@@ -0,0 +1,29 @@
+use crate::error::ApiError;
+use std::error::Error as StdError;
+use thiserror::Error;
+
+/// Errors for the FeePolicyStore
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum FeePolicyStoreError {
+    #[error("{0}")]
+    Other(#[from] Box<dyn StdError + Send + 'static>),
+    #[error("Invalid asset pair {0}, expected FROM/TO")]
+    InvalidPair(String),
+}
+
+impl From<FeePolicyStoreError> for ApiError {
+    fn from(src: FeePolicyStoreError) -> Self {
+        match src {
+            FeePolicyStoreError::InvalidPair(_) => ApiError::bad_request().detail(src.to_string()),
+            _ => ApiError::internal_server_error().detail(src.to_string()),
+        }
+    }
+}
+
+#[cfg(feature = "warp_errors")]
+impl From<FeePolicyStoreError> for warp::Rejection {
+    fn from(src: FeePolicyStoreError) -> Self {
+        ApiError::from(src).into()
+    }
+}
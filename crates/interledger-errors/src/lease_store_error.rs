@@ -0,0 +1,19 @@
+use std::error::Error as StdError;
+use thiserror::Error;
+
+/// Errors for the LeaseStore
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum LeaseStoreError {
+    #[error("{0}")]
+    Other(#[from] Box<dyn StdError + Send>),
+}
+
+#[cfg(feature = "redis_errors")]
+use redis::RedisError;
+#[cfg(feature = "redis_errors")]
+impl From<RedisError> for LeaseStoreError {
+    fn from(src: RedisError) -> Self {
+        LeaseStoreError::Other(Box::new(src))
+    }
+}
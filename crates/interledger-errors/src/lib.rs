@@ -14,6 +14,18 @@ pub use http_store_error::HttpStoreError;
 mod btp_store_error;
 pub use btp_store_error::BtpStoreError;
 
+mod lease_store_error;
+pub use lease_store_error::LeaseStoreError;
+
+mod receipt_store_error;
+pub use receipt_store_error::ReceiptStoreError;
+
+mod max_packet_amount_store_error;
+pub use max_packet_amount_store_error::MaxPacketAmountStoreError;
+
+mod unreachable_destination_store_error;
+pub use unreachable_destination_store_error::UnreachableDestinationStoreError;
+
 mod ccprouting_store_error;
 pub use ccprouting_store_error::CcpRoutingStoreError;
 
@@ -26,6 +38,9 @@ pub use node_store_error::NodeStoreError;
 mod exchange_rate_store_error;
 pub use exchange_rate_store_error::ExchangeRateStoreError;
 
+mod fee_policy_store_error;
+pub use fee_policy_store_error::FeePolicyStoreError;
+
 mod settlement_errors;
 pub use settlement_errors::{IdempotentStoreError, LeftoversStoreError, SettlementStoreError};
 
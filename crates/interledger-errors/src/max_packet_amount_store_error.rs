@@ -0,0 +1,19 @@
+use std::error::Error as StdError;
+use thiserror::Error;
+
+/// Errors for the MaxPacketAmountStore
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum MaxPacketAmountStoreError {
+    #[error("{0}")]
+    Other(#[from] Box<dyn StdError + Send>),
+}
+
+#[cfg(feature = "redis_errors")]
+use redis::RedisError;
+#[cfg(feature = "redis_errors")]
+impl From<RedisError> for MaxPacketAmountStoreError {
+    fn from(src: RedisError) -> Self {
+        MaxPacketAmountStoreError::Other(Box::new(src))
+    }
+}
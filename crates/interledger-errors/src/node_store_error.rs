@@ -19,6 +19,8 @@ pub enum NodeStoreError {
     MissingAccounts,
     #[error("invalid account: {0}")]
     InvalidAccount(CreateAccountError),
+    #[error("{0} is not supported by this store")]
+    Unsupported(&'static str),
 }
 
 impl From<NodeStoreError> for BtpStoreError {
@@ -48,6 +50,7 @@ impl From<NodeStoreError> for ApiError {
             NodeStoreError::InvalidAccount(_) | NodeStoreError::InvalidEngineUrl(_) => {
                 ApiError::bad_request().detail(src.to_string())
             }
+            NodeStoreError::Unsupported(_) => ApiError::not_implemented().detail(src.to_string()),
             _ => ApiError::internal_server_error().detail(src.to_string()),
         }
     }
@@ -0,0 +1,19 @@
+use std::error::Error as StdError;
+use thiserror::Error;
+
+/// Errors for the StreamReceiptStore
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum ReceiptStoreError {
+    #[error("{0}")]
+    Other(#[from] Box<dyn StdError + Send>),
+}
+
+#[cfg(feature = "redis_errors")]
+use redis::RedisError;
+#[cfg(feature = "redis_errors")]
+impl From<RedisError> for ReceiptStoreError {
+    fn from(src: RedisError) -> Self {
+        ReceiptStoreError::Other(Box::new(src))
+    }
+}
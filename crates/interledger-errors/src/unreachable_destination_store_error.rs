@@ -0,0 +1,33 @@
+use crate::error::ApiError;
+use std::error::Error as StdError;
+use thiserror::Error;
+
+/// Errors for the UnreachableDestinationStore
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum UnreachableDestinationStoreError {
+    #[error("{0}")]
+    Other(#[from] Box<dyn StdError + Send>),
+}
+
+impl From<UnreachableDestinationStoreError> for ApiError {
+    fn from(src: UnreachableDestinationStoreError) -> Self {
+        ApiError::internal_server_error().detail(src.to_string())
+    }
+}
+
+#[cfg(feature = "warp_errors")]
+impl From<UnreachableDestinationStoreError> for warp::Rejection {
+    fn from(src: UnreachableDestinationStoreError) -> Self {
+        ApiError::from(src).into()
+    }
+}
+
+#[cfg(feature = "redis_errors")]
+use redis::RedisError;
+#[cfg(feature = "redis_errors")]
+impl From<RedisError> for UnreachableDestinationStoreError {
+    fn from(src: RedisError) -> Self {
+        UnreachableDestinationStoreError::Other(Box::new(src))
+    }
+}
@@ -0,0 +1,114 @@
+/*
+ * C bindings of the STREAM and SPSP implementations of Interledger.rs.
+ *
+ * Clients send payments from the wallet's account at a connector, over ILP-over-HTTP.
+ * Receivers fulfill the packets of the payments sent to the wallet, and notify it of them.
+ *
+ * The functions which can fail return ILP_OK on success and ILP_ERROR otherwise, in which
+ * case ilp_last_error returns the reason. The strings, buffers and handles returned by the
+ * bindings must be freed with the matching free functions.
+ */
+
+#ifndef INTERLEDGER_H
+#define INTERLEDGER_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define ILP_OK 0
+#define ILP_ERROR -1
+
+typedef struct IlpClient IlpClient;
+typedef struct IlpReceiver IlpReceiver;
+
+/* The destination account and shared secret of a STREAM connection */
+typedef struct {
+    /* Freed with ilp_spsp_response_free */
+    char *destination_account;
+    uint8_t shared_secret[32];
+} IlpSpspResponse;
+
+/* The amounts of a payment, in the units of the wallet's asset
+   (other than the delivered amount, which is in the receiver's) */
+typedef struct {
+    uint64_t source_amount;
+    uint64_t sent_amount;
+    uint64_t in_flight_amount;
+    uint64_t delivered_amount;
+} IlpDelivery;
+
+/* A payment received by a receiver, only valid during the call of its callback */
+typedef struct {
+    const char *destination;
+    uint64_t amount;
+    /* RFC3339 */
+    const char *timestamp;
+} IlpPayment;
+
+/* Bytes returned by the bindings, freed with ilp_buffer_free */
+typedef struct {
+    uint8_t *data;
+    size_t len;
+} IlpBuffer;
+
+typedef void (*IlpPaymentCallback)(void *context, const IlpPayment *payment);
+
+/* Errors */
+
+/* The reason the last function which failed on this thread failed, or NULL.
+   Freed with ilp_string_free. */
+char *ilp_last_error(void);
+void ilp_string_free(char *string);
+void ilp_spsp_response_free(IlpSpspResponse *response);
+void ilp_buffer_free(IlpBuffer *buffer);
+
+/* Sending */
+
+/* auth_token may be NULL. Returns NULL if the arguments are invalid. */
+IlpClient *ilp_client_new(const char *ilp_over_http_url,
+                          const char *auth_token,
+                          const char *ilp_address,
+                          const char *asset_code,
+                          uint8_t asset_scale);
+void ilp_client_free(IlpClient *client);
+/* The rates of the wallet's asset and of the receiver's must be set
+   to pay receivers in other assets */
+int ilp_client_set_exchange_rate(const IlpClient *client, const char *asset_code, double rate);
+/* Queries the SPSP server of a payment pointer (or URL) */
+int ilp_client_query(const IlpClient *client, const char *receiver, IlpSpspResponse *response);
+/* Pays a payment pointer (or URL), blocking until the payment is over.
+   The slippage is the fraction the rates may be worse than the client's (such as 0.01). */
+int ilp_client_pay(const IlpClient *client,
+                   const char *receiver,
+                   uint64_t source_amount,
+                   double slippage,
+                   IlpDelivery *delivery);
+
+/* Receiving */
+
+/* server_secret is 32 bytes. callback may be NULL; it is called from the threads which
+   call ilp_receiver_handle_packet. Returns NULL if the arguments are invalid. */
+IlpReceiver *ilp_receiver_new(const char *ilp_address,
+                              const char *asset_code,
+                              uint8_t asset_scale,
+                              const uint8_t *server_secret,
+                              IlpPaymentCallback callback,
+                              void *context);
+void ilp_receiver_free(IlpReceiver *receiver);
+/* Generates a new connection, to be sent to the payer */
+int ilp_receiver_generate(const IlpReceiver *receiver, IlpSpspResponse *response);
+/* Writes the Fulfill or Reject packet responding to a Prepare packet */
+int ilp_receiver_handle_packet(const IlpReceiver *receiver,
+                               const uint8_t *prepare,
+                               size_t len,
+                               IlpBuffer *response);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* INTERLEDGER_H */
@@ -0,0 +1,81 @@
+use interledger_http::HttpAccount;
+use interledger_packet::Address;
+use interledger_service::{Account, Username};
+use once_cell::sync::Lazy;
+use secrecy::SecretString;
+use std::str::FromStr;
+use url::Url;
+use uuid::Uuid;
+
+/// The username of the wallet's account, which only appears in the payment notifications
+static WALLET: Lazy<Username> = Lazy::new(|| Username::from_str("wallet").unwrap());
+
+/// The account of the wallet at its connector. Payments are sent from it to the connector's
+/// ILP-over-HTTP URL, and received at its address.
+#[derive(Clone, Debug)]
+pub struct FfiAccount {
+    id: Uuid,
+    ilp_address: Address,
+    asset_code: String,
+    asset_scale: u8,
+    http_url: Option<Url>,
+    http_auth_token: Option<SecretString>,
+}
+
+impl FfiAccount {
+    pub fn new(ilp_address: &str, asset_code: &str, asset_scale: u8) -> Result<Self, String> {
+        let ilp_address = Address::from_str(ilp_address)
+            .map_err(|err| format!("Invalid ILP address {}: {}", ilp_address, err))?;
+        if asset_code.is_empty() {
+            return Err("Asset code must not be empty".to_string());
+        }
+        Ok(FfiAccount {
+            id: Uuid::new_v4(),
+            ilp_address,
+            asset_code: asset_code.to_string(),
+            asset_scale,
+            http_url: None,
+            http_auth_token: None,
+        })
+    }
+
+    /// Sets the ILP-over-HTTP URL of the connector and the token the packets are sent with
+    pub fn with_http(mut self, url: &str, auth_token: Option<&str>) -> Result<Self, String> {
+        let url = Url::parse(url).map_err(|err| format!("Invalid URL {}: {}", url, err))?;
+        self.http_url = Some(url);
+        self.http_auth_token = auth_token.map(|token| SecretString::new(token.to_string()));
+        Ok(self)
+    }
+}
+
+impl Account for FfiAccount {
+    fn id(&self) -> Uuid {
+        self.id
+    }
+
+    fn username(&self) -> &Username {
+        &WALLET
+    }
+
+    fn ilp_address(&self) -> &Address {
+        &self.ilp_address
+    }
+
+    fn asset_scale(&self) -> u8 {
+        self.asset_scale
+    }
+
+    fn asset_code(&self) -> &str {
+        &self.asset_code
+    }
+}
+
+impl HttpAccount for FfiAccount {
+    fn get_http_url(&self) -> Option<&Url> {
+        self.http_url.as_ref()
+    }
+
+    fn get_http_auth_token(&self) -> Option<SecretString> {
+        self.http_auth_token.clone()
+    }
+}
@@ -0,0 +1,394 @@
+use super::account::FfiAccount;
+use super::store::SenderStore;
+use super::{ffi_call, ffi_new, handle_arg, str_arg, write_arg, IlpSpspResponse};
+use async_trait::async_trait;
+use interledger_http::HttpClientService;
+use interledger_packet::{ErrorCode, RejectBuilder};
+use interledger_rates::ExchangeRateStore;
+use interledger_service::{
+    outgoing_service_fn, Account, IlpResult, IncomingRequest, IncomingService, OutgoingRequest,
+    OutgoingService,
+};
+use interledger_stream::StreamDelivery;
+use std::future::Future;
+use std::os::raw::{c_char, c_int};
+use tokio::runtime::{Builder, Runtime};
+
+/// A client which sends payments from the wallet's account at a connector,
+/// over ILP-over-HTTP
+pub struct IlpClient {
+    runtime: Runtime,
+    account: FfiAccount,
+    store: SenderStore,
+}
+
+/// The amounts of a payment, in the units of the wallet's asset
+/// (other than the delivered amount, which is in the receiver's)
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct IlpDelivery {
+    /// The amount which was to be sent
+    pub source_amount: u64,
+    /// The amount which was sent and fulfilled
+    pub sent_amount: u64,
+    /// The amount of the packets which were neither fulfilled nor rejected
+    pub in_flight_amount: u64,
+    /// The amount the receiver got
+    pub delivered_amount: u64,
+}
+
+impl From<StreamDelivery> for IlpDelivery {
+    fn from(delivery: StreamDelivery) -> Self {
+        IlpDelivery {
+            source_amount: delivery.source_amount,
+            sent_amount: delivery.sent_amount,
+            in_flight_amount: delivery.in_flight_amount,
+            delivered_amount: delivery.delivered_amount,
+        }
+    }
+}
+
+/// Forwards the packets of the payments to the connector
+#[derive(Clone)]
+struct ConnectorService<O> {
+    connector: FfiAccount,
+    next: O,
+}
+
+#[async_trait]
+impl<O> IncomingService<FfiAccount> for ConnectorService<O>
+where
+    O: OutgoingService<FfiAccount> + Clone + Send + Sync,
+{
+    async fn handle_request(&mut self, request: IncomingRequest<FfiAccount>) -> IlpResult {
+        let request = request.into_outgoing(self.connector.clone());
+        self.next.send_request(request).await
+    }
+}
+
+impl IlpClient {
+    pub fn new(account: FfiAccount) -> Result<Self, String> {
+        let runtime = Builder::new()
+            .threaded_scheduler()
+            .enable_all()
+            .build()
+            .map_err(|err| format!("Unable to start the runtime: {}", err))?;
+        let store = SenderStore::new(account.ilp_address().clone());
+        Ok(IlpClient {
+            runtime,
+            account,
+            store,
+        })
+    }
+
+    /// Runs the future on the client's runtime, blocking the calling thread until it completes.
+    /// Several threads can wait for their payments at the same time.
+    fn block_on<F>(&self, future: F) -> Result<F::Output, String>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        futures::executor::block_on(self.runtime.handle().spawn(future))
+            .map_err(|err| format!("Task failed: {}", err))
+    }
+
+    pub fn set_exchange_rate(&self, asset_code: &str, rate: f64) -> Result<(), String> {
+        if !rate.is_finite() || rate <= 0.0 {
+            return Err(format!("Invalid exchange rate: {}", rate));
+        }
+        self.store
+            .set_exchange_rates(vec![(asset_code.to_string(), rate)].into_iter().collect())
+            .map_err(|err| err.to_string())
+    }
+
+    pub fn query(&self, receiver: &str) -> Result<IlpSpspResponse, String> {
+        let receiver = receiver.to_string();
+        let response = self
+            .block_on(async move { interledger_spsp::query(&receiver).await })?
+            .map_err(|err| err.to_string())?;
+        IlpSpspResponse::new(
+            &response.destination_account().to_string(),
+            response.shared_secret(),
+        )
+    }
+
+    pub fn pay(
+        &self,
+        receiver: &str,
+        source_amount: u64,
+        slippage: f64,
+    ) -> Result<IlpDelivery, String> {
+        if !(0.0..1.0).contains(&slippage) {
+            return Err(format!("Invalid slippage: {}", slippage));
+        }
+        let receiver = receiver.to_string();
+        let account = self.account.clone();
+        let store = self.store.clone();
+        let delivery = self.block_on(async move {
+            let ilp_address = account.ilp_address().clone();
+            let unreachable = outgoing_service_fn(move |request: OutgoingRequest<FfiAccount>| {
+                Err(RejectBuilder {
+                    code: ErrorCode::F02_UNREACHABLE,
+                    message: format!("No route to {}", request.to.ilp_address()).as_bytes(),
+                    triggered_by: Some(&ilp_address),
+                    data: &[],
+                }
+                .build())
+            });
+            let service = ConnectorService {
+                connector: account.clone(),
+                next: HttpClientService::new(store.clone(), unreachable),
+            };
+            interledger_spsp::pay(
+                service,
+                account,
+                store,
+                &receiver,
+                source_amount,
+                slippage,
+                None,
+            )
+            .await
+        })?;
+        delivery
+            .map(IlpDelivery::from)
+            .map_err(|err| err.to_string())
+    }
+}
+
+/// Creates a client which sends payments from the wallet's account at a connector, by sending
+/// the packets to the connector's ILP-over-HTTP URL with the auth token (which may be null).
+/// Returns null if the arguments are invalid. The client must be freed with `ilp_client_free`.
+///
+/// # Safety
+///
+/// The strings must be null-terminated.
+#[no_mangle]
+pub unsafe extern "C" fn ilp_client_new(
+    ilp_over_http_url: *const c_char,
+    auth_token: *const c_char,
+    ilp_address: *const c_char,
+    asset_code: *const c_char,
+    asset_scale: u8,
+) -> *mut IlpClient {
+    ffi_new(|| {
+        let auth_token = if auth_token.is_null() {
+            None
+        } else {
+            Some(str_arg(auth_token, "auth_token")?)
+        };
+        let account = FfiAccount::new(
+            str_arg(ilp_address, "ilp_address")?,
+            str_arg(asset_code, "asset_code")?,
+            asset_scale,
+        )?
+        .with_http(str_arg(ilp_over_http_url, "ilp_over_http_url")?, auth_token)?;
+        IlpClient::new(account)
+    })
+}
+
+/// Frees a client, once its payments are over.
+///
+/// # Safety
+///
+/// The client must have been returned by `ilp_client_new`, and not be freed already.
+#[no_mangle]
+pub unsafe extern "C" fn ilp_client_free(client: *mut IlpClient) {
+    if !client.is_null() {
+        drop(Box::from_raw(client));
+    }
+}
+
+/// Sets the exchange rate of an asset, relative to the others. The rates of the wallet's asset
+/// and of the receiver's must be set to pay receivers in other assets.
+///
+/// # Safety
+///
+/// The client must be valid and the asset code null-terminated.
+#[no_mangle]
+pub unsafe extern "C" fn ilp_client_set_exchange_rate(
+    client: *const IlpClient,
+    asset_code: *const c_char,
+    rate: f64,
+) -> c_int {
+    ffi_call(|| {
+        handle_arg(client, "client")?.set_exchange_rate(str_arg(asset_code, "asset_code")?, rate)
+    })
+}
+
+/// Queries the SPSP server of the payment pointer (or URL) of the receiver for the details
+/// of a new STREAM connection, which must be freed with `ilp_spsp_response_free`.
+///
+/// # Safety
+///
+/// The client must be valid, the receiver null-terminated and the response writable.
+#[no_mangle]
+pub unsafe extern "C" fn ilp_client_query(
+    client: *const IlpClient,
+    receiver: *const c_char,
+    response: *mut IlpSpspResponse,
+) -> c_int {
+    ffi_call(|| {
+        let spsp_response = handle_arg(client, "client")?.query(str_arg(receiver, "receiver")?)?;
+        write_arg(response, "response", spsp_response)
+    })
+}
+
+/// Pays the receiver's payment pointer (or URL) with STREAM, blocking until the payment is
+/// over, and writes the amounts of the payment to the delivery. The payment fails if the
+/// exchange rate it gets is worse than the rates set on the client by more than the slippage
+/// (such as 0.01 for 1%).
+///
+/// # Safety
+///
+/// The client must be valid, the receiver null-terminated and the delivery writable.
+#[no_mangle]
+pub unsafe extern "C" fn ilp_client_pay(
+    client: *const IlpClient,
+    receiver: *const c_char,
+    source_amount: u64,
+    slippage: f64,
+    delivery: *mut IlpDelivery,
+) -> c_int {
+    ffi_call(|| {
+        let client = handle_arg(client, "client")?;
+        let stream_delivery =
+            client.pay(str_arg(receiver, "receiver")?, source_amount, slippage)?;
+        write_arg(delivery, "delivery", stream_delivery)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::receiver::{IlpPayment, IlpReceiver};
+    use crate::{ilp_client_free, ilp_last_error, ilp_string_free, ILP_ERROR};
+    use serde_json::json;
+    use std::ffi::{CStr, CString};
+    use std::os::raw::c_void;
+    use std::ptr;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+    use warp::Filter;
+
+    unsafe extern "C" fn add_payment(context: *mut c_void, payment: *const IlpPayment) {
+        let received = &*(context as *const AtomicU64);
+        received.fetch_add((*payment).amount, Ordering::SeqCst);
+    }
+
+    #[test]
+    fn pays_a_receiver() {
+        let received = Arc::new(AtomicU64::new(0));
+        let receiver = unsafe {
+            let address = CString::new("example.receiver").unwrap();
+            let code = CString::new("XRP").unwrap();
+            Arc::new(IlpReceiverHandle(crate::ilp_receiver_new(
+                address.as_ptr(),
+                code.as_ptr(),
+                9,
+                [1; 32].as_ptr(),
+                Some(add_payment),
+                Arc::as_ptr(&received) as *mut c_void,
+            )))
+        };
+
+        // The connector and the receiver's SPSP server
+        let spsp_receiver = receiver.clone();
+        let spsp = warp::path("spsp").map(move || {
+            let mut response = spsp_receiver.get().generate().unwrap();
+            let body = json!({
+                "destination_account": unsafe { CStr::from_ptr(response.destination_account) }
+                    .to_str()
+                    .unwrap(),
+                "shared_secret": base64::encode(&response.shared_secret),
+            });
+            unsafe { crate::ilp_spsp_response_free(&mut response) };
+            warp::reply::json(&body)
+        });
+        let ilp_receiver = receiver.clone();
+        let ilp = warp::path("ilp").and(warp::body::bytes()).map(
+            move |body: warp::hyper::body::Bytes| {
+                ilp_receiver.get().handle_packet(&body).unwrap().to_vec()
+            },
+        );
+        let server = Builder::new()
+            .threaded_scheduler()
+            .enable_all()
+            .build()
+            .unwrap();
+        let (addr, serving) =
+            server.enter(|| warp::serve(spsp.or(ilp)).bind_ephemeral(([127, 0, 0, 1], 0)));
+        server.spawn(serving);
+
+        let account = FfiAccount::new("example.wallet", "XRP", 9)
+            .unwrap()
+            .with_http(&format!("http://{}/ilp", addr), Some("token"))
+            .unwrap();
+        let client = IlpClient::new(account).unwrap();
+        let delivery = client
+            .pay(&format!("http://{}/spsp", addr), 1000, 0.0)
+            .unwrap();
+        assert_eq!(delivery.sent_amount, 1000);
+        assert_eq!(delivery.delivered_amount, 1000);
+        assert_eq!(received.load(Ordering::SeqCst), 1000);
+
+        unsafe { crate::ilp_receiver_free(receiver.0) };
+        server.shutdown_timeout(std::time::Duration::from_secs(1));
+    }
+
+    #[test]
+    fn fails_with_invalid_arguments() {
+        let url = CString::new("http://localhost:7770/accounts/wallet/ilp").unwrap();
+        let address = CString::new("not an address").unwrap();
+        let code = CString::new("XRP").unwrap();
+        unsafe {
+            let client = ilp_client_new(
+                url.as_ptr(),
+                ptr::null(),
+                address.as_ptr(),
+                code.as_ptr(),
+                9,
+            );
+            assert!(client.is_null());
+            let error = ilp_last_error();
+            assert!(CStr::from_ptr(error)
+                .to_str()
+                .unwrap()
+                .starts_with("Invalid ILP address"));
+            ilp_string_free(error);
+
+            let address = CString::new("example.wallet").unwrap();
+            let client = ilp_client_new(
+                url.as_ptr(),
+                ptr::null(),
+                address.as_ptr(),
+                code.as_ptr(),
+                9,
+            );
+            assert!(!client.is_null());
+            let mut delivery = IlpDelivery::default();
+            let receiver = CString::new("$example.com").unwrap();
+            let result = ilp_client_pay(client, receiver.as_ptr(), 1000, 1.5, &mut delivery);
+            assert_eq!(result, ILP_ERROR);
+            let error = ilp_last_error();
+            assert_eq!(
+                CStr::from_ptr(error).to_str().unwrap(),
+                "Invalid slippage: 1.5"
+            );
+            ilp_string_free(error);
+            ilp_client_free(client);
+        }
+    }
+
+    /// The receiver handle, shared with the threads of the server
+    struct IlpReceiverHandle(*mut IlpReceiver);
+
+    unsafe impl Send for IlpReceiverHandle {}
+    unsafe impl Sync for IlpReceiverHandle {}
+
+    impl IlpReceiverHandle {
+        fn get(&self) -> &IlpReceiver {
+            unsafe { &*self.0 }
+        }
+    }
+}
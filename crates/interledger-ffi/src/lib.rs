@@ -0,0 +1,204 @@
+//! # interledger-ffi
+//!
+//! C bindings of the [STREAM](https://interledger.org/rfcs/0029-stream/) and
+//! [SPSP](https://interledger.org/rfcs/0009-simple-payment-setup-protocol/) implementations of
+//! Interledger.rs, so that wallets written in other languages (such as iOS and Android apps)
+//! can send and receive Interledger payments without porting STREAM.
+//!
+//! The functions are declared in [`include/interledger.h`](../include/interledger.h). Clients,
+//! which send payments over ILP-over-HTTP, and receivers, which fulfill the packets of the
+//! payments they are sent, are opaque handles created and freed with the functions of the
+//! bindings. The functions which can fail return `ILP_OK` on success and `ILP_ERROR` otherwise,
+//! in which case `ilp_last_error` returns the reason.
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+
+/// The account of the wallet, as which the packets are sent and received
+mod account;
+/// Sending payments with the client handles
+mod client;
+/// Receiving payments with the receiver handles
+mod receiver;
+/// The in-memory stores of the clients and receivers
+mod store;
+
+pub use client::{
+    ilp_client_free, ilp_client_new, ilp_client_pay, ilp_client_query,
+    ilp_client_set_exchange_rate, IlpClient, IlpDelivery,
+};
+pub use receiver::{
+    ilp_buffer_free, ilp_receiver_free, ilp_receiver_generate, ilp_receiver_handle_packet,
+    ilp_receiver_new, IlpBuffer, IlpPayment, IlpPaymentCallback, IlpReceiver,
+};
+
+/// Returned by the functions which succeeded
+pub const ILP_OK: c_int = 0;
+/// Returned by the functions which failed, whose reason is then returned by `ilp_last_error`
+pub const ILP_ERROR: c_int = -1;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// The destination account and shared secret of a STREAM connection,
+/// returned by SPSP queries and generated by receivers
+#[repr(C)]
+pub struct IlpSpspResponse {
+    /// The ILP address of the connection, to be freed with `ilp_spsp_response_free`
+    pub destination_account: *mut c_char,
+    /// The shared secret of the connection
+    pub shared_secret: [u8; 32],
+}
+
+impl IlpSpspResponse {
+    fn new(destination_account: &str, shared_secret: &[u8]) -> Result<Self, String> {
+        if shared_secret.len() != 32 {
+            return Err(format!(
+                "Shared secret must be 32 bytes, got {}",
+                shared_secret.len()
+            ));
+        }
+        let mut response = IlpSpspResponse {
+            destination_account: into_c_string(destination_account),
+            shared_secret: [0; 32],
+        };
+        response.shared_secret.copy_from_slice(shared_secret);
+        Ok(response)
+    }
+}
+
+/// Returns the reason the last function which failed on this thread failed, or null if none did.
+/// The string must be freed with `ilp_string_free`.
+#[no_mangle]
+pub extern "C" fn ilp_last_error() -> *mut c_char {
+    LAST_ERROR.with(|last_error| match last_error.borrow().as_ref() {
+        Some(message) => message.clone().into_raw(),
+        None => ptr::null_mut(),
+    })
+}
+
+/// Frees a string returned by the bindings.
+///
+/// # Safety
+///
+/// The string must have been returned by the bindings, and not be freed already.
+#[no_mangle]
+pub unsafe extern "C" fn ilp_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+/// Frees the destination account of an SPSP response, and sets it to null.
+///
+/// # Safety
+///
+/// The response must have been filled in by the bindings.
+#[no_mangle]
+pub unsafe extern "C" fn ilp_spsp_response_free(response: *mut IlpSpspResponse) {
+    if let Some(response) = response.as_mut() {
+        ilp_string_free(response.destination_account);
+        response.destination_account = ptr::null_mut();
+    }
+}
+
+fn set_last_error(message: String) {
+    let message = CString::new(message.replace('\0', "")).unwrap_or_default();
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = Some(message));
+}
+
+/// Runs the body of a function of the bindings, turning a panic into an error, since
+/// panics must not unwind into the caller
+fn caught<T, F: FnOnce() -> Result<T, String>>(f: F) -> Result<T, String> {
+    catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|_| Err("Panicked".to_string()))
+}
+
+/// Runs the body of a function of the bindings, and returns `ILP_OK` if it succeeded.
+/// If it failed, the reason is saved as the last error and `ILP_ERROR` is returned.
+fn ffi_call<F: FnOnce() -> Result<(), String>>(f: F) -> c_int {
+    match caught(f) {
+        Ok(()) => ILP_OK,
+        Err(message) => {
+            set_last_error(message);
+            ILP_ERROR
+        }
+    }
+}
+
+/// Like [`ffi_call`](./fn.ffi_call.html), for the functions which create a handle,
+/// which return null if they failed
+fn ffi_new<T, F: FnOnce() -> Result<T, String>>(f: F) -> *mut T {
+    match caught(f) {
+        Ok(handle) => Box::into_raw(Box::new(handle)),
+        Err(message) => {
+            set_last_error(message);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Reads a string argument, which must not be null and must be UTF-8
+unsafe fn str_arg<'a>(string: *const c_char, name: &str) -> Result<&'a str, String> {
+    if string.is_null() {
+        return Err(format!("{} must not be null", name));
+    }
+    CStr::from_ptr(string)
+        .to_str()
+        .map_err(|_| format!("{} must be UTF-8", name))
+}
+
+/// Reads a handle argument, which must not be null
+unsafe fn handle_arg<'a, T>(handle: *const T, name: &str) -> Result<&'a T, String> {
+    handle
+        .as_ref()
+        .ok_or_else(|| format!("{} must not be null", name))
+}
+
+/// Writes the value to an output argument, which must not be null
+unsafe fn write_arg<T>(output: *mut T, name: &str, value: T) -> Result<(), String> {
+    if output.is_null() {
+        return Err(format!("{} must not be null", name));
+    }
+    output.write(value);
+    Ok(())
+}
+
+fn into_c_string(string: &str) -> *mut c_char {
+    CString::new(string.replace('\0', ""))
+        .unwrap_or_default()
+        .into_raw()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saves_the_last_error() {
+        assert!(ilp_last_error().is_null());
+        assert_eq!(ffi_call(|| Err("Invalid amount".to_string())), ILP_ERROR);
+        assert_eq!(ffi_call(|| panic!("oops")), ILP_ERROR);
+        assert_eq!(ffi_call(|| Ok(())), ILP_OK);
+        unsafe {
+            let error = ilp_last_error();
+            assert_eq!(CStr::from_ptr(error).to_str().unwrap(), "Panicked");
+            ilp_string_free(error);
+        }
+    }
+
+    #[test]
+    fn reads_the_arguments() {
+        let string = CString::new("example.alice").unwrap();
+        unsafe {
+            assert_eq!(str_arg(string.as_ptr(), "address"), Ok("example.alice"));
+            assert_eq!(
+                str_arg(ptr::null(), "address"),
+                Err("address must not be null".to_string())
+            );
+        }
+    }
+}
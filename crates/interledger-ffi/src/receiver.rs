@@ -0,0 +1,331 @@
+use super::account::FfiAccount;
+use super::store::ReceiverStore;
+use super::{ffi_call, ffi_new, handle_arg, str_arg, write_arg, IlpSpspResponse};
+use bytes::{Bytes, BytesMut};
+use interledger_packet::{ErrorCode, Prepare, RejectBuilder};
+use interledger_service::{
+    outgoing_service_fn, Account, IlpResult, IncomingRequest, OutgoingRequest, OutgoingService,
+    ServiceFn,
+};
+use interledger_stream::{ConnectionGenerator, PaymentNotification, StreamReceiverService};
+use std::convert::TryFrom;
+use std::ffi::CString;
+use std::os::raw::{c_char, c_int, c_void};
+use std::{ptr, slice};
+
+/// A payment received by a receiver, passed to its callback
+#[repr(C)]
+pub struct IlpPayment {
+    /// The ILP address of the connection the payment was sent to
+    pub destination: *const c_char,
+    /// The amount received, in the units of the wallet's asset
+    pub amount: u64,
+    /// The time the payment was received, in RFC3339 format
+    pub timestamp: *const c_char,
+}
+
+/// Called with the context and each payment received. The payment is only valid during the call.
+pub type IlpPaymentCallback = Option<unsafe extern "C" fn(*mut c_void, *const IlpPayment)>;
+
+/// The callback of a receiver with its context, which the caller of `ilp_receiver_new`
+/// guarantees can be called from any thread
+#[derive(Clone, Copy)]
+struct Callback {
+    function: IlpPaymentCallback,
+    context: *mut c_void,
+}
+
+unsafe impl Send for Callback {}
+unsafe impl Sync for Callback {}
+
+impl Callback {
+    fn call(self, payment: &PaymentNotification) {
+        if let Some(function) = self.function {
+            let destination = CString::new(payment.destination.to_string()).unwrap_or_default();
+            let timestamp = CString::new(payment.timestamp.clone()).unwrap_or_default();
+            let payment = IlpPayment {
+                destination: destination.as_ptr(),
+                amount: payment.amount,
+                timestamp: timestamp.as_ptr(),
+            };
+            unsafe { function(self.context, &payment) }
+        }
+    }
+}
+
+/// A buffer of bytes returned by the bindings
+#[repr(C)]
+pub struct IlpBuffer {
+    /// The bytes, to be freed with `ilp_buffer_free`
+    pub data: *mut u8,
+    /// The number of bytes
+    pub len: usize,
+}
+
+impl From<BytesMut> for IlpBuffer {
+    fn from(bytes: BytesMut) -> Self {
+        let data = Box::into_raw(bytes.to_vec().into_boxed_slice());
+        IlpBuffer {
+            data: data as *mut u8,
+            len: bytes.len(),
+        }
+    }
+}
+
+type Unreachable = ServiceFn<fn(OutgoingRequest<FfiAccount>) -> IlpResult, FfiAccount>;
+
+/// A receiver which fulfills the packets of the STREAM payments sent to the wallet's
+/// address, and notifies the wallet of the payments
+pub struct IlpReceiver {
+    account: FfiAccount,
+    connection_generator: ConnectionGenerator,
+    service: StreamReceiverService<ReceiverStore, Unreachable, FfiAccount>,
+}
+
+/// Rejects the packets which are not for the receiver's connections
+fn unreachable(request: OutgoingRequest<FfiAccount>) -> IlpResult {
+    Err(RejectBuilder {
+        code: ErrorCode::F02_UNREACHABLE,
+        message: b"No STREAM connection for the destination",
+        triggered_by: Some(request.to.ilp_address()),
+        data: &[],
+    }
+    .build())
+}
+
+impl IlpReceiver {
+    fn new(account: FfiAccount, server_secret: &[u8; 32], callback: Callback) -> Self {
+        let server_secret = Bytes::from(&server_secret[..]);
+        let store = ReceiverStore::new(move |payment| callback.call(payment));
+        let next = outgoing_service_fn(unreachable as fn(_) -> _);
+        IlpReceiver {
+            account,
+            connection_generator: ConnectionGenerator::new(server_secret.clone()),
+            service: StreamReceiverService::new(server_secret, store, next),
+        }
+    }
+
+    /// Generates the destination account and shared secret of a new connection,
+    /// to be sent to the payer
+    pub fn generate(&self) -> Result<IlpSpspResponse, String> {
+        let (destination_account, shared_secret) = self
+            .connection_generator
+            .generate_address_and_secret(self.account.ilp_address());
+        IlpSpspResponse::new(&destination_account.to_string(), &shared_secret)
+    }
+
+    /// Returns the Fulfill or Reject packet responding to the Prepare packet
+    pub fn handle_packet(&self, prepare: &[u8]) -> Result<BytesMut, String> {
+        let prepare = Prepare::try_from(BytesMut::from(prepare))
+            .map_err(|err| format!("Invalid Prepare packet: {}", err))?;
+        let request = IncomingRequest {
+            from: self.account.clone(),
+            prepare,
+        }
+        .into_outgoing(self.account.clone());
+        let mut service = self.service.clone();
+        let response = match futures::executor::block_on(service.send_request(request)) {
+            Ok(fulfill) => BytesMut::from(fulfill),
+            Err(reject) => BytesMut::from(reject),
+        };
+        Ok(response)
+    }
+}
+
+/// Creates a receiver of the payments sent to the wallet's ILP address, whose connections'
+/// shared secrets are derived from the 32 bytes of the server secret. The callback (which may
+/// be null) is called with the context for each packet fulfilled, from the thread which
+/// passes the packet to `ilp_receiver_handle_packet`. Returns null if the arguments are
+/// invalid. The receiver must be freed with `ilp_receiver_free`.
+///
+/// # Safety
+///
+/// The strings must be null-terminated, the server secret 32 bytes long, and the callback
+/// must be callable with the context from any thread until the receiver is freed.
+#[no_mangle]
+pub unsafe extern "C" fn ilp_receiver_new(
+    ilp_address: *const c_char,
+    asset_code: *const c_char,
+    asset_scale: u8,
+    server_secret: *const u8,
+    callback: IlpPaymentCallback,
+    context: *mut c_void,
+) -> *mut IlpReceiver {
+    ffi_new(|| {
+        let account = FfiAccount::new(
+            str_arg(ilp_address, "ilp_address")?,
+            str_arg(asset_code, "asset_code")?,
+            asset_scale,
+        )?;
+        let server_secret = handle_arg(server_secret as *const [u8; 32], "server_secret")?;
+        let callback = Callback {
+            function: callback,
+            context,
+        };
+        Ok(IlpReceiver::new(account, server_secret, callback))
+    })
+}
+
+/// Frees a receiver.
+///
+/// # Safety
+///
+/// The receiver must have been returned by `ilp_receiver_new`, and not be freed already.
+#[no_mangle]
+pub unsafe extern "C" fn ilp_receiver_free(receiver: *mut IlpReceiver) {
+    if !receiver.is_null() {
+        drop(Box::from_raw(receiver));
+    }
+}
+
+/// Generates the destination account and shared secret of a new connection, to be sent to the
+/// payer (for example in the SPSP response of the wallet's payment pointer). The response must
+/// be freed with `ilp_spsp_response_free`.
+///
+/// # Safety
+///
+/// The receiver must be valid and the response writable.
+#[no_mangle]
+pub unsafe extern "C" fn ilp_receiver_generate(
+    receiver: *const IlpReceiver,
+    response: *mut IlpSpspResponse,
+) -> c_int {
+    ffi_call(|| {
+        let spsp_response = handle_arg(receiver, "receiver")?.generate()?;
+        write_arg(response, "response", spsp_response)
+    })
+}
+
+/// Writes the Fulfill or Reject packet responding to a Prepare packet received by the wallet
+/// to the response, which must be freed with `ilp_buffer_free`. Fails if the bytes are not a
+/// Prepare packet.
+///
+/// # Safety
+///
+/// The receiver must be valid, the prepare `len` bytes long and the response writable.
+#[no_mangle]
+pub unsafe extern "C" fn ilp_receiver_handle_packet(
+    receiver: *const IlpReceiver,
+    prepare: *const u8,
+    len: usize,
+    response: *mut IlpBuffer,
+) -> c_int {
+    ffi_call(|| {
+        let receiver = handle_arg(receiver, "receiver")?;
+        if prepare.is_null() {
+            return Err("prepare must not be null".to_string());
+        }
+        let packet = receiver.handle_packet(slice::from_raw_parts(prepare, len))?;
+        write_arg(response, "response", IlpBuffer::from(packet))
+    })
+}
+
+/// Frees the bytes of a buffer, and sets them to null.
+///
+/// # Safety
+///
+/// The buffer must have been filled in by the bindings.
+#[no_mangle]
+pub unsafe extern "C" fn ilp_buffer_free(buffer: *mut IlpBuffer) {
+    if let Some(buffer) = buffer.as_mut() {
+        if !buffer.data.is_null() {
+            drop(Box::from_raw(ptr::slice_from_raw_parts_mut(
+                buffer.data,
+                buffer.len,
+            )));
+        }
+        buffer.data = ptr::null_mut();
+        buffer.len = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ilp_last_error, ilp_spsp_response_free, ilp_string_free, ILP_ERROR, ILP_OK};
+    use interledger_packet::{Packet, PrepareBuilder};
+    use std::ffi::CStr;
+    use std::mem::MaybeUninit;
+    use std::time::SystemTime;
+
+    #[test]
+    fn generates_connections_under_the_address() {
+        let address = CString::new("example.wallet").unwrap();
+        let code = CString::new("XRP").unwrap();
+        unsafe {
+            let receiver = ilp_receiver_new(
+                address.as_ptr(),
+                code.as_ptr(),
+                9,
+                [0; 32].as_ptr(),
+                None,
+                ptr::null_mut(),
+            );
+            assert!(!receiver.is_null());
+            let mut response = MaybeUninit::<IlpSpspResponse>::uninit();
+            assert_eq!(
+                ilp_receiver_generate(receiver, response.as_mut_ptr()),
+                ILP_OK
+            );
+            let mut response = response.assume_init();
+            let destination = CStr::from_ptr(response.destination_account);
+            assert!(destination.to_str().unwrap().starts_with("example.wallet."));
+            ilp_spsp_response_free(&mut response);
+            assert!(response.destination_account.is_null());
+            ilp_receiver_free(receiver);
+        }
+    }
+
+    #[test]
+    fn rejects_packets_for_other_connections() {
+        let account = FfiAccount::new("example.wallet", "XRP", 9).unwrap();
+        let receiver = IlpReceiver::new(
+            account,
+            &[0; 32],
+            Callback {
+                function: None,
+                context: ptr::null_mut(),
+            },
+        );
+        let prepare = PrepareBuilder {
+            destination: "example.wallet.other".parse().unwrap(),
+            amount: 100,
+            expires_at: SystemTime::now(),
+            execution_condition: &[0; 32],
+            data: &[],
+        }
+        .build();
+        let response = receiver.handle_packet(&BytesMut::from(prepare)).unwrap();
+        match Packet::try_from(response).unwrap() {
+            Packet::Reject(reject) => assert_eq!(reject.code(), ErrorCode::F02_UNREACHABLE),
+            _ => panic!("Expected a reject"),
+        }
+    }
+
+    #[test]
+    fn fails_on_invalid_packets() {
+        let address = CString::new("example.wallet").unwrap();
+        let code = CString::new("XRP").unwrap();
+        unsafe {
+            let receiver = ilp_receiver_new(
+                address.as_ptr(),
+                code.as_ptr(),
+                9,
+                [0; 32].as_ptr(),
+                None,
+                ptr::null_mut(),
+            );
+            let mut response = MaybeUninit::<IlpBuffer>::uninit();
+            let result =
+                ilp_receiver_handle_packet(receiver, [1, 2, 3].as_ptr(), 3, response.as_mut_ptr());
+            assert_eq!(result, ILP_ERROR);
+            let error = ilp_last_error();
+            assert!(CStr::from_ptr(error)
+                .to_str()
+                .unwrap()
+                .starts_with("Invalid Prepare packet"));
+            ilp_string_free(error);
+            ilp_receiver_free(receiver);
+        }
+    }
+}
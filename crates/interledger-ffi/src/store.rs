@@ -0,0 +1,307 @@
+use super::account::FfiAccount;
+use async_trait::async_trait;
+use futures::channel::mpsc::UnboundedSender;
+use interledger_errors::{
+    AddressStoreError, ExchangeRateStoreError, HttpStoreError, MaxPacketAmountStoreError,
+    ReceiptStoreError, UnreachableDestinationStoreError,
+};
+use interledger_http::HttpStore;
+use interledger_packet::Address;
+use interledger_rates::ExchangeRateStore;
+use interledger_service::{AddressStore, Username};
+use interledger_stream::{
+    MaxPacketAmountStore, PaymentNotification, StreamNotificationsStore, StreamReceiptStore,
+    UnreachableDestination, UnreachableDestinationStore,
+};
+use parking_lot::{Mutex, RwLock};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+/// The store of a client, which keeps the exchange rates set by the wallet, and the max
+/// packet amounts and unreachable destinations discovered by its payments, in memory
+#[derive(Clone)]
+pub struct SenderStore {
+    ilp_address: Address,
+    exchange_rates: Arc<RwLock<HashMap<String, f64>>>,
+    max_packet_amounts: Arc<Mutex<HashMap<String, (u64, Instant)>>>,
+    unreachable_destinations: Arc<Mutex<HashMap<String, (UnreachableDestination, Instant)>>>,
+}
+
+impl SenderStore {
+    pub fn new(ilp_address: Address) -> Self {
+        SenderStore {
+            ilp_address,
+            exchange_rates: Arc::new(RwLock::new(HashMap::new())),
+            max_packet_amounts: Arc::new(Mutex::new(HashMap::new())),
+            unreachable_destinations: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+}
+
+impl ExchangeRateStore for SenderStore {
+    fn set_exchange_rates(
+        &self,
+        rates: HashMap<String, f64>,
+    ) -> Result<(), ExchangeRateStoreError> {
+        self.exchange_rates.write().extend(rates);
+        Ok(())
+    }
+
+    fn get_exchange_rates(&self, asset_codes: &[&str]) -> Result<Vec<f64>, ExchangeRateStoreError> {
+        let exchange_rates = self.exchange_rates.read();
+        asset_codes
+            .iter()
+            .map(|code| {
+                exchange_rates.get(*code).cloned().ok_or_else(|| {
+                    ExchangeRateStoreError::PairNotFound {
+                        from: asset_codes[0].to_string(),
+                        to: asset_codes[asset_codes.len() - 1].to_string(),
+                    }
+                })
+            })
+            .collect()
+    }
+
+    fn get_all_exchange_rates(&self) -> Result<HashMap<String, f64>, ExchangeRateStoreError> {
+        Ok(self.exchange_rates.read().clone())
+    }
+}
+
+#[async_trait]
+impl MaxPacketAmountStore for SenderStore {
+    async fn get_max_packet_amount(
+        &self,
+        source_asset_code: &str,
+        source_asset_scale: u8,
+        destination_prefix: &str,
+    ) -> Result<Option<u64>, MaxPacketAmountStoreError> {
+        let key = format!(
+            "{}:{}:{}",
+            source_asset_code, source_asset_scale, destination_prefix
+        );
+        let mut max_packet_amounts = self.max_packet_amounts.lock();
+        match max_packet_amounts.get(&key) {
+            Some((max_packet_amount, expiry)) if *expiry > Instant::now() => {
+                Ok(Some(*max_packet_amount))
+            }
+            Some(_) => {
+                max_packet_amounts.remove(&key);
+                Ok(None)
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn set_max_packet_amount(
+        &self,
+        source_asset_code: &str,
+        source_asset_scale: u8,
+        destination_prefix: &str,
+        max_packet_amount: u64,
+        ttl: u64,
+    ) -> Result<(), MaxPacketAmountStoreError> {
+        let key = format!(
+            "{}:{}:{}",
+            source_asset_code, source_asset_scale, destination_prefix
+        );
+        let expiry = Instant::now() + Duration::from_millis(ttl);
+        self.max_packet_amounts
+            .lock()
+            .insert(key, (max_packet_amount, expiry));
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl UnreachableDestinationStore for SenderStore {
+    async fn get_unreachable_destination(
+        &self,
+        destination_prefix: &str,
+    ) -> Result<Option<UnreachableDestination>, UnreachableDestinationStoreError> {
+        let now = Instant::now();
+        let mut unreachable_destinations = self.unreachable_destinations.lock();
+        match unreachable_destinations.get(destination_prefix) {
+            Some((unreachable, expiry)) if *expiry > now => Ok(Some(UnreachableDestination {
+                expires_in: (*expiry - now).as_millis() as u64,
+                ..unreachable.clone()
+            })),
+            Some(_) => {
+                unreachable_destinations.remove(destination_prefix);
+                Ok(None)
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn record_unreachable_destination(
+        &self,
+        destination_prefix: &str,
+        error: &str,
+        ttl: u64,
+    ) -> Result<(), UnreachableDestinationStoreError> {
+        let now = Instant::now();
+        let mut unreachable_destinations = self.unreachable_destinations.lock();
+        let failures = match unreachable_destinations.get(destination_prefix) {
+            Some((unreachable, expiry)) if *expiry > now => unreachable.failures,
+            _ => 0,
+        };
+        let unreachable = UnreachableDestination {
+            destination_prefix: destination_prefix.to_string(),
+            failures: failures + 1,
+            error: error.to_string(),
+            expires_in: ttl,
+        };
+        unreachable_destinations.insert(
+            destination_prefix.to_string(),
+            (unreachable, now + Duration::from_millis(ttl)),
+        );
+        Ok(())
+    }
+
+    async fn get_unreachable_destinations(
+        &self,
+    ) -> Result<Vec<UnreachableDestination>, UnreachableDestinationStoreError> {
+        let now = Instant::now();
+        let mut unreachable_destinations = self.unreachable_destinations.lock();
+        unreachable_destinations.retain(|_, (_, expiry)| *expiry > now);
+        Ok(unreachable_destinations
+            .values()
+            .map(|(unreachable, expiry)| UnreachableDestination {
+                expires_in: (*expiry - now).as_millis() as u64,
+                ..unreachable.clone()
+            })
+            .collect())
+    }
+
+    async fn clear_unreachable_destinations(
+        &self,
+        destination_prefix: Option<&str>,
+    ) -> Result<usize, UnreachableDestinationStoreError> {
+        let mut unreachable_destinations = self.unreachable_destinations.lock();
+        Ok(match destination_prefix {
+            Some(prefix) => unreachable_destinations.remove(prefix).into_iter().count(),
+            None => unreachable_destinations.drain().count(),
+        })
+    }
+}
+
+#[async_trait]
+impl AddressStore for SenderStore {
+    async fn set_ilp_address(&self, _ilp_address: Address) -> Result<(), AddressStoreError> {
+        Ok(())
+    }
+
+    async fn clear_ilp_address(&self) -> Result<(), AddressStoreError> {
+        Ok(())
+    }
+
+    fn get_ilp_address(&self) -> Address {
+        self.ilp_address.clone()
+    }
+}
+
+#[async_trait]
+impl HttpStore for SenderStore {
+    type Account = FfiAccount;
+
+    /// Clients only send ILP-over-HTTP requests, so no request is authenticated
+    async fn get_account_from_http_auth(
+        &self,
+        username: &Username,
+        _token: &str,
+    ) -> Result<FfiAccount, HttpStoreError> {
+        Err(HttpStoreError::Unauthorized(username.to_string()))
+    }
+}
+
+/// The store of a receiver, which passes its payment notifications on to the wallet
+#[derive(Clone)]
+pub struct ReceiverStore {
+    on_payment: Arc<dyn Fn(&PaymentNotification) + Send + Sync>,
+}
+
+impl ReceiverStore {
+    pub fn new<F: Fn(&PaymentNotification) + Send + Sync + 'static>(on_payment: F) -> Self {
+        ReceiverStore {
+            on_payment: Arc::new(on_payment),
+        }
+    }
+}
+
+impl StreamNotificationsStore for ReceiverStore {
+    type Account = FfiAccount;
+
+    fn add_payment_notification_subscription(
+        &self,
+        _account_id: Uuid,
+        _sender: UnboundedSender<PaymentNotification>,
+    ) {
+    }
+
+    fn publish_payment_notification(&self, payment: PaymentNotification) {
+        (self.on_payment)(&payment)
+    }
+
+    fn all_payment_subscription(&self) -> broadcast::Receiver<PaymentNotification> {
+        broadcast::channel(1).1
+    }
+}
+
+/// Receivers do not issue STREAM receipts, so the totals of the streams are not kept
+#[async_trait]
+impl StreamReceiptStore for ReceiverStore {
+    async fn add_stream_received(
+        &self,
+        _nonce: [u8; 16],
+        _stream_id: u64,
+        amount: u64,
+    ) -> Result<u64, ReceiptStoreError> {
+        Ok(amount)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[tokio::test]
+    async fn expires_the_max_packet_amounts() {
+        let store = SenderStore::new(Address::from_str("example.alice").unwrap());
+        store
+            .set_max_packet_amount("XRP", 9, "example.bob", 1000, 60_000)
+            .await
+            .unwrap();
+        store
+            .set_max_packet_amount("XRP", 9, "example.carl", 1000, 0)
+            .await
+            .unwrap();
+        assert_eq!(
+            store
+                .get_max_packet_amount("XRP", 9, "example.bob")
+                .await
+                .unwrap(),
+            Some(1000)
+        );
+        assert_eq!(
+            store
+                .get_max_packet_amount("XRP", 9, "example.carl")
+                .await
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn gets_the_exchange_rates() {
+        let store = SenderStore::new(Address::from_str("example.alice").unwrap());
+        store
+            .set_exchange_rates(vec![("XRP".to_string(), 0.25)].into_iter().collect())
+            .unwrap();
+        assert_eq!(store.get_exchange_rates(&["XRP"]).unwrap(), vec![0.25]);
+        assert!(store.get_exchange_rates(&["XRP", "EUR"]).is_err());
+    }
+}
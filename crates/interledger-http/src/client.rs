@@ -1,17 +1,30 @@
+use super::signature::{parse_signing_key, sign_request};
 use super::{HttpAccount, HttpStore};
 use async_trait::async_trait;
 use bytes::BytesMut;
 use futures::future::TryFutureExt;
 use interledger_packet::{Address, ErrorCode, Packet, RejectBuilder};
 use interledger_service::*;
+use metrics::{labels, recorder, Key};
 use reqwest::{
     header::{HeaderMap, HeaderName, HeaderValue},
     Client, ClientBuilder, Response as HttpResponse,
 };
+use ring::hmac;
 use secrecy::{ExposeSecret, SecretString};
-use std::{convert::TryFrom, iter::FromIterator, marker::PhantomData, sync::Arc, time::Duration};
+use serde_json::json;
+use std::{
+    convert::TryFrom,
+    iter::FromIterator,
+    marker::PhantomData,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 use tracing::{error, trace};
 
+/// How long the JWTs sent to the peers speaking the `java_jwt` dialect are valid for
+const JWT_LIFETIME: Duration = Duration::from_secs(60);
+
 /// The HttpClientService implements [OutgoingService](../../interledger_service/trait.OutgoingService)
 /// for sending ILP Prepare packets over to the HTTP URL associated with the provided account
 /// If no [ILP-over-HTTP](https://interledger.org/rfcs/0035-ilp-over-http) URL is specified for
@@ -76,17 +89,40 @@ where
                 request.to.id(),
                 url.as_str()
             );
-            let token = request
-                .to
-                .get_http_auth_token()
-                .unwrap_or_else(|| SecretString::new("".to_owned()));
-            let header = format!("Bearer {}", token.expose_secret());
+            let token = request.to.get_http_auth_token();
+            let signing_key = request.to.get_http_signing_key();
+            let dialect = request.to.get_http_dialect();
             let body = request.prepare.as_ref().to_owned();
-            let resp = self_clone
-                .client
-                .post(url.as_ref())
-                .header("authorization", &header)
-                .body(body)
+            let username = request.to.username().to_string();
+            let mut http_request = self_clone.client.post(url.as_ref());
+            // Signed requests only carry a bearer token if the account has one
+            if token.is_some() || signing_key.is_none() {
+                let token = token.unwrap_or_else(|| SecretString::new("".to_owned()));
+                let header = authorization(dialect, &ilp_address, token.expose_secret());
+                http_request = http_request.header("authorization", &header);
+            }
+            if let Some(signing_key) = signing_key {
+                match parse_signing_key(signing_key.expose_secret()) {
+                    Ok(key) => {
+                        let signed =
+                            sign_request(&key, &ilp_address, url.path(), &body, SystemTime::now());
+                        http_request = http_request
+                            .header("content-digest", signed.content_digest)
+                            .header("signature-input", signed.signature_input)
+                            .header("signature", signed.signature);
+                    }
+                    Err(err) => error!(
+                        "Not signing the ILP over HTTP request to account {}: {}",
+                        request.to.id(),
+                        err
+                    ),
+                }
+            }
+            if dialect != HttpDialect::Rfc {
+                http_request = http_request.header("accept", "application/octet-stream");
+            }
+            let http_request = http_request.body(body);
+            let resp = http_request
                 .send()
                 .map_err(move |err| {
                     error!("Error sending HTTP request: {:?}", err);
@@ -106,14 +142,63 @@ where
                     }
                     .build()
                 })
-                .await?;
-            parse_packet_from_response(resp, ilp_address_clone).await
+                .await;
+            // The peer is reachable if it sent back a response, even if it is an error
+            record_connection_state(username, resp.is_ok());
+            parse_packet_from_response(resp?, ilp_address_clone).await
         } else {
             self.next.send_request(request).await
         }
     }
 }
 
+/// Returns the `Authorization` header of the requests sent to a peer speaking the dialect
+fn authorization(dialect: HttpDialect, ilp_address: &Address, token: &str) -> String {
+    match dialect {
+        HttpDialect::Rfc | HttpDialect::Java => format!("Bearer {}", token),
+        HttpDialect::JavaJwt => format!(
+            "Bearer {}",
+            hs256_jwt(ilp_address, token.as_bytes(), SystemTime::now())
+        ),
+    }
+}
+
+/// Returns a JWT whose subject is the provided one, signed with HMAC-SHA256 (as verified by
+/// the `JWT_HS_256` authentication of the Java connector)
+fn hs256_jwt(subject: &str, secret: &[u8], now: SystemTime) -> String {
+    let issued_at = now
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default();
+    let header = json!({ "alg": "HS256", "typ": "JWT" });
+    let claims = json!({
+        "sub": subject,
+        "iat": issued_at,
+        "exp": issued_at + JWT_LIFETIME.as_secs(),
+    });
+    let signing_input = format!(
+        "{}.{}",
+        base64::encode_config(&header.to_string(), base64::URL_SAFE_NO_PAD),
+        base64::encode_config(&claims.to_string(), base64::URL_SAFE_NO_PAD)
+    );
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret);
+    let signature = hmac::sign(&key, signing_input.as_bytes());
+    format!(
+        "{}.{}",
+        signing_input,
+        base64::encode_config(signature.as_ref(), base64::URL_SAFE_NO_PAD)
+    )
+}
+
+/// Sets the `http.connection` gauge of the account to 1 if its ILP over HTTP peer
+/// responded to the last request, or to 0 if the request could not be sent
+fn record_connection_state(username: String, reachable: bool) {
+    recorder().update_gauge(
+        Key::from_name_and_labels("http.connection", labels!("account" => username)),
+        if reachable { 1 } else { 0 },
+    );
+}
+
 /// Parses an ILP over HTTP response.
 ///
 /// # Errors
@@ -158,7 +243,7 @@ async fn parse_packet_from_response(response: HttpResponse, ilp_address: Address
         })
         .await?;
 
-    let body = BytesMut::from_iter(body.into_iter());
+    let body = BytesMut::from_iter(body);
     match Packet::try_from(body) {
         Ok(Packet::Fulfill(fulfill)) => Ok(fulfill),
         Ok(Packet::Reject(reject)) => Err(reject),
@@ -171,3 +256,45 @@ async fn parse_packet_from_response(response: HttpResponse, ilp_address: Address
         .build()),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn signs_jwts_with_the_token() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        let jwt = hs256_jwt("example.node", b"secret", now);
+        let parts: Vec<&str> = jwt.split('.').collect();
+        assert_eq!(parts.len(), 3);
+        let claims: serde_json::Value = serde_json::from_slice(
+            &base64::decode_config(parts[1], base64::URL_SAFE_NO_PAD).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            claims,
+            json!({ "sub": "example.node", "iat": 1_600_000_000u64, "exp": 1_600_000_060u64 })
+        );
+        let signature = base64::decode_config(parts[2], base64::URL_SAFE_NO_PAD).unwrap();
+        let key = hmac::Key::new(hmac::HMAC_SHA256, b"secret");
+        let signing_input = format!("{}.{}", parts[0], parts[1]);
+        assert!(hmac::verify(&key, signing_input.as_bytes(), &signature).is_ok());
+    }
+
+    #[test]
+    fn authorizes_requests_in_the_dialect_of_the_peer() {
+        let address = Address::from_str("example.node").unwrap();
+        assert_eq!(
+            authorization(HttpDialect::Rfc, &address, "token"),
+            "Bearer token"
+        );
+        assert_eq!(
+            authorization(HttpDialect::Java, &address, "token"),
+            "Bearer token"
+        );
+        let header = authorization(HttpDialect::JavaJwt, &address, "token");
+        assert!(header.starts_with("Bearer ey"));
+        assert_eq!(header.matches('.').count(), 2);
+    }
+}
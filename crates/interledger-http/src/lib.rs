@@ -5,7 +5,7 @@
 use async_trait::async_trait;
 use bytes::Bytes;
 use interledger_errors::{ApiError, HttpStoreError, JsonDeserializeError};
-use interledger_service::{Account, Username};
+use interledger_service::{Account, HttpDialect, Username};
 use mime::Mime;
 use secrecy::SecretString;
 use serde::de::DeserializeOwned;
@@ -16,9 +16,14 @@ use warp::{self, Filter, Rejection};
 mod client;
 /// [ILP over HTTP](https://interledger.org/rfcs/0035-ilp-over-http/) API (implemented with [Warp](https://docs.rs/warp/0.2.0/warp/))
 mod server;
+/// HTTP message signatures of the ILP over HTTP requests
+mod signature;
 
 pub use self::client::HttpClientService;
 pub use self::server::HttpServer;
+pub use self::signature::{
+    parse_signing_key, parse_verification_key, SignedRequest, SIGNATURE_MAX_AGE,
+};
 
 /// Extension trait for [Account](../interledger_service/trait.Account.html) with [ILP over HTTP](https://interledger.org/rfcs/0035-ilp-over-http/) related information
 pub trait HttpAccount: Account {
@@ -26,6 +31,21 @@ pub trait HttpAccount: Account {
     fn get_http_url(&self) -> Option<&Url>;
     /// Returns the HTTP token which is sent as an HTTP header on each ILP over HTTP request
     fn get_http_auth_token(&self) -> Option<SecretString>;
+    /// Returns the dialect of ILP over HTTP spoken by the account's peer, which sets the
+    /// headers of the requests sent to it
+    fn get_http_dialect(&self) -> HttpDialect {
+        HttpDialect::Rfc
+    }
+    /// Returns the Ed25519 key (in base64) with which the requests sent to the account's peer
+    /// are signed with HTTP message signatures
+    fn get_http_signing_key(&self) -> Option<SecretString> {
+        None
+    }
+    /// Returns the Ed25519 public key (in base64) of the account's peer, with which the HTTP
+    /// message signatures of its requests are verified
+    fn get_http_verification_key(&self) -> Option<&str> {
+        None
+    }
 }
 
 /// The interface for Stores that can be used with the HttpServerService.
@@ -41,6 +61,16 @@ pub trait HttpStore: Clone + Send + Sync + 'static {
         username: &Username,
         token: &str,
     ) -> Result<Self::Account, HttpStoreError>;
+
+    /// Load account details if the HTTP message signature of the incoming request
+    /// verifies with the account's verification key.
+    async fn get_account_from_http_signature(
+        &self,
+        username: &Username,
+        _request: &SignedRequest<'_>,
+    ) -> Result<Self::Account, HttpStoreError> {
+        Err(HttpStoreError::Unauthorized(username.to_string()))
+    }
 }
 
 // TODO: Do we really need this custom deserialization function?
@@ -1,17 +1,23 @@
+use super::signature::SignedRequest;
 use super::HttpStore;
 use bytes::{Bytes, BytesMut};
 use interledger_errors::ApiError;
 use interledger_packet::Prepare;
 use interledger_service::Username;
-use interledger_service::{IncomingRequest, IncomingService};
+use interledger_service::{
+    IncomingRequest, IncomingService, IpAllowlistAccount, Misbehavior, PeerBlocklist,
+    TrustedProxies,
+};
 use secrecy::{ExposeSecret, SecretString};
-use std::convert::TryFrom;
-use std::net::SocketAddr;
-use tracing::error;
-use warp::{Filter, Rejection};
+use std::convert::{Infallible, TryFrom};
+use std::net::{IpAddr, SocketAddr};
+use std::time::SystemTime;
+use tracing::{error, warn};
+use warp::{http::HeaderMap, path::FullPath, Filter, Rejection};
 
-/// Max message size that is allowed to transfer from a request or a message.
-pub const MAX_PACKET_SIZE: u64 = 40000;
+/// Max size of the body of the ILP over HTTP requests, which is the max size of the packets
+/// accepted over any transport
+pub const MAX_PACKET_SIZE: u64 = interledger_packet::MAX_PACKET_SIZE as u64;
 /// The offset after which the bearer token should be in an ILP over HTTP request
 /// e.g. in `token = "Bearer: MyAuthToken"`, `MyAuthToken` can be taken via token[BEARER_TOKEN_START..]
 pub const BEARER_TOKEN_START: usize = 7;
@@ -24,6 +30,26 @@ pub struct HttpServer<I, S> {
     incoming: I,
     /// A store which implements [`HttpStore`](trait.HttpStore.html)
     store: S,
+    /// The peers whose requests are refused, and to which their misbehavior is reported
+    blocklist: PeerBlocklist,
+    /// The reverse proxies whose header gives the address of the clients
+    trusted_proxies: TrustedProxies,
+}
+
+/// Returns the address of the client which sent the request, as passed on by the
+/// proxy if the request comes from one of the trusted proxies
+fn client_ip(
+    trusted_proxies: TrustedProxies,
+) -> impl Filter<Extract = (Option<IpAddr>,), Error = Infallible> + Clone {
+    warp::addr::remote().and(warp::header::headers_cloned()).map(
+        move |remote: Option<SocketAddr>, headers: HeaderMap| {
+            let values = headers
+                .get_all(trusted_proxies.header.name())
+                .iter()
+                .filter_map(|value| value.to_str().ok());
+            trusted_proxies.client_ip(remote.map(|addr| addr.ip()), values)
+        },
+    )
 }
 
 #[inline]
@@ -49,6 +75,29 @@ where
         .await?)
 }
 
+/// Returns the account whose verification key validates the HTTP message signature of the
+/// request, or returns an error if the account was not found or if the signature is invalid
+async fn get_account_from_signature<S>(
+    store: S,
+    path_username: &Username,
+    path: &str,
+    headers: &HeaderMap,
+    body: &[u8],
+) -> Result<S::Account, ApiError>
+where
+    S: HttpStore,
+{
+    let request = SignedRequest {
+        path,
+        headers,
+        body,
+        now: SystemTime::now(),
+    };
+    Ok(store
+        .get_account_from_http_signature(path_username, &request)
+        .await?)
+}
+
 #[inline]
 /// Implements ILP over HTTP. If account authentication is valid
 /// and the provided packet can be parsed as a
@@ -57,21 +106,62 @@ where
 /// an Ok result if the response is a [Fulfill](../../interledger_packet/struct.Fulfill.html).
 ///
 /// # Errors
-/// 1. Unauthorized account if invalid credentials are provided
+/// 1. Too many requests if the client's address or the account is quarantined by the blocklist
+/// 1. Unauthorized account if invalid credentials or an invalid signature are provided
+/// 1. Unauthorized account if the request does not come from the account's IP allowlist
 /// 1. The provided `body` could not be parsed as a Prepare packet
 /// 1. A Reject packet was returned by the next incoming service
+#[allow(clippy::too_many_arguments)]
 async fn ilp_over_http<S, I>(
     path_username: Username,
-    password: SecretString,
+    path: FullPath,
+    headers: HeaderMap,
+    password: Option<SecretString>,
+    client_ip: Option<IpAddr>,
     body: Bytes,
     store: S,
     mut incoming: I,
+    blocklist: PeerBlocklist,
 ) -> Result<impl warp::Reply, warp::Rejection>
 where
     S: HttpStore,
+    S::Account: IpAllowlistAccount,
     I: IncomingService<S::Account> + Clone,
 {
-    let account = get_account(store, &path_username, &password).await?;
+    if blocklist.is_address_quarantined(client_ip) {
+        return Err(ApiError::too_many_requests()
+            .detail("address is quarantined")
+            .into());
+    }
+    if blocklist.is_quarantined(&path_username) {
+        return Err(ApiError::too_many_requests()
+            .detail("account is quarantined")
+            .into());
+    }
+    // Requests carrying an HTTP message signature are authenticated by it instead of a token
+    let account = if headers.contains_key("signature-input") {
+        get_account_from_signature(store, &path_username, path.as_str(), &headers, &body).await
+    } else {
+        let password = password.unwrap_or_else(|| SecretString::new(String::new()));
+        get_account(store, &path_username, &password).await
+    };
+    let account = match account {
+        Ok(account) => account,
+        Err(err) => {
+            blocklist.record_address(client_ip, Misbehavior::AuthFailure);
+            return Err(err.into());
+        }
+    };
+    if !account.is_ip_allowed(client_ip) {
+        blocklist.record_address(client_ip, Misbehavior::AuthFailure);
+        warn!(
+            "Rejecting ILP over HTTP request for account {} from {:?}, which is not in its IP allowlist",
+            path_username, client_ip
+        );
+        return Err(ApiError::unauthorized()
+            .detail("request does not come from an allowed IP address")
+            .into());
+    }
 
     let buffer = bytes::BytesMut::from(body.as_ref());
     if let Ok(prepare) = Prepare::try_from(buffer) {
@@ -94,6 +184,7 @@ where
             .unwrap())
     } else {
         error!("Body was not a valid Prepare packet");
+        blocklist.record(&path_username, Misbehavior::MalformedPacket);
         Err(Rejection::from(ApiError::invalid_ilp_packet()))
     }
 }
@@ -102,9 +193,30 @@ impl<I, S> HttpServer<I, S>
 where
     I: IncomingService<S::Account> + Clone + Send + Sync,
     S: HttpStore + Clone,
+    S::Account: IpAllowlistAccount,
 {
     pub fn new(incoming: I, store: S) -> Self {
-        HttpServer { incoming, store }
+        HttpServer {
+            incoming,
+            store,
+            blocklist: PeerBlocklist::default(),
+            trusted_proxies: TrustedProxies::default(),
+        }
+    }
+
+    /// Sets the reverse proxies in front of the node, whose header gives the address
+    /// of the clients which is checked against the IP allowlists of the accounts
+    pub fn trusted_proxies(&mut self, trusted_proxies: TrustedProxies) -> &mut Self {
+        self.trusted_proxies = trusted_proxies;
+        self
+    }
+
+    /// Sets the blocklist which refuses the requests of the quarantined accounts, and
+    /// to which the authentication failures and malformed packets of the accounts are
+    /// reported
+    pub fn blocklist(&mut self, blocklist: PeerBlocklist) -> &mut Self {
+        self.blocklist = blocklist;
+        self
     }
 
     /// Returns a Warp filter which exposes per-account endpoints for [ILP over HTTP](https://interledger.org/rfcs/0035-ilp-over-http/).
@@ -116,16 +228,22 @@ where
         let incoming = self.incoming.clone();
         let with_store = warp::any().map(move || store.clone());
         let with_incoming = warp::any().map(move || incoming.clone());
+        let blocklist = self.blocklist.clone();
+        let with_blocklist = warp::any().map(move || blocklist.clone());
         warp::post()
             .and(warp::path("accounts"))
             .and(warp::path::param::<Username>())
             .and(warp::path("ilp"))
             .and(warp::path::end())
-            .and(warp::header::<SecretString>("authorization"))
+            .and(warp::path::full())
+            .and(warp::header::headers_cloned())
+            .and(warp::header::optional::<SecretString>("authorization"))
+            .and(client_ip(self.trusted_proxies.clone()))
             .and(warp::body::content_length_limit(MAX_PACKET_SIZE))
             .and(warp::body::bytes())
             .and(with_store)
             .and(with_incoming)
+            .and(with_blocklist)
             .and_then(ilp_over_http)
     }
 
@@ -145,7 +263,7 @@ mod tests {
     use http::Response;
     use interledger_errors::{default_rejection_handler, HttpStoreError};
     use interledger_packet::{Address, ErrorCode, PrepareBuilder, RejectBuilder};
-    use interledger_service::{incoming_service_fn, Account};
+    use interledger_service::{incoming_service_fn, Account, BlocklistConfig, IpNetwork};
     use once_cell::sync::Lazy;
     use secrecy::SecretString;
     use std::convert::TryInto;
@@ -169,13 +287,32 @@ mod tests {
         .unwrap()
     });
 
+    static IP_ALLOWLIST: Lazy<Vec<IpNetwork>> =
+        Lazy::new(|| vec![IpNetwork::from_str("127.0.0.0/8").unwrap()]);
+
     const AUTH_PASSWORD: &str = "password";
+    // The first test vector of RFC 8032
+    const SIGNING_KEY: &str = "nWGxne/9WmC6hEr0kuwsxERJxWl7MmkZcDusAxyuf2A=";
+    const VERIFICATION_KEY: &str = "11qYAYKxCrfVS/7TyWQHOg7hcvPapiMlrwIaaPcHURo=";
 
     async fn api_call<F>(
         api: &F,
         endpoint: &str, // /ilp or /accounts/:username/ilp
         auth: &str,     // simple bearer or overloaded username+password
     ) -> Response<Bytes>
+    where
+        F: warp::Filter + 'static,
+        F::Extract: warp::Reply,
+    {
+        api_call_from(api, endpoint, auth, ([127, 0, 0, 1], 1234).into()).await
+    }
+
+    async fn api_call_from<F>(
+        api: &F,
+        endpoint: &str,
+        auth: &str,
+        remote_addr: SocketAddr,
+    ) -> Response<Bytes>
     where
         F: warp::Filter + 'static,
         F::Extract: warp::Reply,
@@ -183,6 +320,7 @@ mod tests {
         warp::test::request()
             .method("POST")
             .path(endpoint)
+            .remote_addr(remote_addr)
             .header("Authorization", format!("Bearer {}", auth))
             .header("Content-length", 1000)
             .body(PREPARE_BYTES.clone())
@@ -220,6 +358,167 @@ mod tests {
         assert_eq!(resp.status().as_u16(), 200);
     }
 
+    #[tokio::test]
+    async fn rejects_requests_from_outside_the_ip_allowlist() {
+        let incoming = incoming_service_fn(|_request| {
+            Err(RejectBuilder {
+                code: ErrorCode::F02_UNREACHABLE,
+                message: b"No other incoming handler!",
+                data: &[],
+                triggered_by: None,
+            }
+            .build())
+        });
+        let api = HttpServer::new(incoming, TestStore)
+            .as_filter()
+            .recover(default_rejection_handler);
+
+        let resp = api_call_from(
+            &api,
+            "/accounts/alice/ilp",
+            AUTH_PASSWORD,
+            ([198, 51, 100, 7], 1234).into(),
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 401);
+
+        // IPv4 peers of dual-stack sockets have IPv4-mapped addresses
+        let resp = api_call_from(
+            &api,
+            "/accounts/alice/ilp",
+            AUTH_PASSWORD,
+            "[::ffff:127.0.0.1]:1234".parse().unwrap(),
+        )
+        .await;
+        assert_eq!(resp.status().as_u16(), 200);
+    }
+
+    #[tokio::test]
+    async fn checks_the_client_address_passed_on_by_trusted_proxies() {
+        let incoming = incoming_service_fn(|_request| {
+            Err(RejectBuilder {
+                code: ErrorCode::F02_UNREACHABLE,
+                message: b"No other incoming handler!",
+                data: &[],
+                triggered_by: None,
+            }
+            .build())
+        });
+        let api = HttpServer::new(incoming, TestStore)
+            .trusted_proxies(TrustedProxies {
+                networks: vec![IpNetwork::from_str("10.0.0.0/8").unwrap()],
+                ..Default::default()
+            })
+            .as_filter()
+            .recover(default_rejection_handler);
+        let call = |remote_addr: SocketAddr, forwarded_for: &str| {
+            warp::test::request()
+                .method("POST")
+                .path("/accounts/alice/ilp")
+                .remote_addr(remote_addr)
+                .header("Authorization", format!("Bearer {}", AUTH_PASSWORD))
+                .header("X-Forwarded-For", forwarded_for)
+                .header("Content-length", 1000)
+                .body(PREPARE_BYTES.clone())
+                .reply(&api)
+        };
+
+        let proxy = ([10, 0, 0, 1], 1234).into();
+        assert_eq!(call(proxy, "127.0.0.1").await.status().as_u16(), 200);
+        assert_eq!(call(proxy, "198.51.100.7").await.status().as_u16(), 401);
+        // The header is ignored unless the request comes from a trusted proxy
+        let other = ([198, 51, 100, 7], 1234).into();
+        assert_eq!(call(other, "127.0.0.1").await.status().as_u16(), 401);
+    }
+
+    #[tokio::test]
+    async fn quarantines_addresses_failing_to_authenticate() {
+        let incoming = incoming_service_fn(|_request| {
+            Err(RejectBuilder {
+                code: ErrorCode::F02_UNREACHABLE,
+                message: b"No other incoming handler!",
+                data: &[],
+                triggered_by: None,
+            }
+            .build())
+        });
+        let blocklist = PeerBlocklist::new(BlocklistConfig {
+            max_auth_failures: Some(2),
+            ..BlocklistConfig::default()
+        });
+        let api = HttpServer::new(incoming, TestStore)
+            .blocklist(blocklist.clone())
+            .as_filter()
+            .recover(default_rejection_handler);
+
+        let attacker: SocketAddr = ([127, 0, 0, 2], 1234).into();
+        for _ in 0..2 {
+            let resp = api_call_from(&api, "/accounts/alice/ilp", "wrong_password", attacker).await;
+            assert_eq!(resp.status().as_u16(), 401);
+        }
+        // The account the failures claimed to be is not quarantined
+        assert!(!blocklist.is_quarantined(&USERNAME));
+        let resp = api_call(&api, "/accounts/alice/ilp", AUTH_PASSWORD).await;
+        assert_eq!(resp.status().as_u16(), 200);
+        // Even the right credentials are refused from the quarantined address
+        let resp = api_call_from(&api, "/accounts/alice/ilp", AUTH_PASSWORD, attacker).await;
+        assert_eq!(resp.status().as_u16(), 429);
+
+        blocklist.release_address(attacker.ip());
+        let resp = api_call_from(&api, "/accounts/alice/ilp", AUTH_PASSWORD, attacker).await;
+        assert_eq!(resp.status().as_u16(), 200);
+    }
+
+    #[tokio::test]
+    async fn authenticates_signed_requests() {
+        let incoming = incoming_service_fn(|_request| {
+            Err(RejectBuilder {
+                code: ErrorCode::F02_UNREACHABLE,
+                message: b"No other incoming handler!",
+                data: &[],
+                triggered_by: None,
+            }
+            .build())
+        });
+        let api = HttpServer::new(incoming, TestStore)
+            .as_filter()
+            .recover(default_rejection_handler);
+        let key = crate::parse_signing_key(SIGNING_KEY).unwrap();
+        let signed_request = |path: &str, body: &[u8]| {
+            let signed =
+                crate::signature::sign_request(&key, "example.bob", path, body, SystemTime::now());
+            warp::test::request()
+                .method("POST")
+                .path("/accounts/alice/ilp")
+                .remote_addr(([127, 0, 0, 1], 1234).into())
+                .header("Content-Digest", signed.content_digest)
+                .header("Signature-Input", signed.signature_input)
+                .header("Signature", signed.signature)
+                .body(PREPARE_BYTES.clone())
+        };
+
+        // Signed requests need no token
+        let resp = signed_request("/accounts/alice/ilp", &PREPARE_BYTES)
+            .reply(&api)
+            .await;
+        assert_eq!(resp.status().as_u16(), 200);
+
+        let resp = signed_request("/accounts/bob/ilp", &PREPARE_BYTES)
+            .reply(&api)
+            .await;
+        assert_eq!(resp.status().as_u16(), 401);
+        let resp = signed_request("/accounts/alice/ilp", b"another packet")
+            .reply(&api)
+            .await;
+        assert_eq!(resp.status().as_u16(), 401);
+        // An invalid signature is not made up for by a valid token
+        let resp = signed_request("/accounts/alice/ilp", b"another packet")
+            .header("Authorization", format!("Bearer {}", AUTH_PASSWORD))
+            .reply(&api)
+            .await;
+        assert_eq!(resp.status().as_u16(), 401);
+    }
+
     #[derive(Debug, Clone)]
     struct TestAccount;
     impl Account for TestAccount {
@@ -252,6 +551,12 @@ mod tests {
         }
     }
 
+    impl IpAllowlistAccount for TestAccount {
+        fn ip_allowlist(&self) -> &[IpNetwork] {
+            &IP_ALLOWLIST
+        }
+    }
+
     #[derive(Debug, Clone)]
     struct TestStore;
 
@@ -270,5 +575,17 @@ mod tests {
                 Err(HttpStoreError::Unauthorized(username.to_string()))
             }
         }
+
+        async fn get_account_from_http_signature(
+            &self,
+            username: &Username,
+            request: &SignedRequest<'_>,
+        ) -> Result<Self::Account, HttpStoreError> {
+            if username == &*USERNAME && request.verify(VERIFICATION_KEY) {
+                Ok(TestAccount)
+            } else {
+                Err(HttpStoreError::Unauthorized(username.to_string()))
+            }
+        }
     }
 }
@@ -0,0 +1,352 @@
+//! [HTTP message signatures](https://www.rfc-editor.org/rfc/rfc9421) of the ILP over HTTP
+//! requests, an alternative to the bearer tokens for authenticating them. The requests are
+//! signed with Ed25519 and the signature covers their method, path and `Content-Digest`
+//! (the SHA-256 of the packet), along with the time it was created at.
+use http::HeaderMap;
+use ring::{
+    digest,
+    signature::{Ed25519KeyPair, UnparsedPublicKey, ED25519},
+};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::debug;
+
+/// Label of the signatures the node adds to its requests
+const SIGNATURE_LABEL: &str = "ilp";
+/// The components of the requests covered by the signatures the node adds to them, all of
+/// which must be covered by the signatures of the incoming requests
+const COVERED_COMPONENTS: [&str; 3] = ["@method", "@path", "content-digest"];
+/// How far from the node's clock the creation time of the signature of an incoming request
+/// may be, which bounds the time during which a captured request can be replayed
+pub const SIGNATURE_MAX_AGE: Duration = Duration::from_secs(60);
+
+/// The headers which sign a request
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct SignatureHeaders {
+    pub content_digest: String,
+    pub signature_input: String,
+    pub signature: String,
+}
+
+/// Parses an Ed25519 signing key, encoded in base64 either as its 32 byte seed or as its
+/// PKCS#8 document (as output by `openssl genpkey -algorithm ed25519 -outform DER`)
+pub fn parse_signing_key(encoded: &str) -> Result<Ed25519KeyPair, &'static str> {
+    let bytes = base64::decode(encoded.trim()).map_err(|_| "signing key is not valid base64")?;
+    if bytes.len() == 32 {
+        Ed25519KeyPair::from_seed_unchecked(&bytes)
+    } else {
+        Ed25519KeyPair::from_pkcs8_maybe_unchecked(&bytes)
+    }
+    .map_err(|_| "signing key is not an Ed25519 key")
+}
+
+/// Parses an Ed25519 public key, encoded in base64
+pub fn parse_verification_key(encoded: &str) -> Result<Vec<u8>, &'static str> {
+    let bytes =
+        base64::decode(encoded.trim()).map_err(|_| "verification key is not valid base64")?;
+    if bytes.len() != 32 {
+        return Err("verification key is not a 32 byte Ed25519 public key");
+    }
+    Ok(bytes)
+}
+
+/// An incoming request carrying an HTTP message signature, which is verified against the
+/// public key of the account it claims to come from
+#[derive(Debug)]
+pub struct SignedRequest<'a> {
+    pub(crate) path: &'a str,
+    pub(crate) headers: &'a HeaderMap,
+    pub(crate) body: &'a [u8],
+    pub(crate) now: SystemTime,
+}
+
+impl SignedRequest<'_> {
+    /// Returns whether the request is signed by the key whose public key (in base64) is
+    /// the provided one
+    pub fn verify(&self, verification_key: &str) -> bool {
+        parse_verification_key(verification_key)
+            .and_then(|key| verify_request(&key, self.path, self.headers, self.body, self.now))
+            .map_err(|err| debug!("Invalid HTTP message signature: {}", err))
+            .is_ok()
+    }
+}
+
+/// Signs a POST request to the path whose body is the provided one
+pub(crate) fn sign_request(
+    key: &Ed25519KeyPair,
+    key_id: &str,
+    path: &str,
+    body: &[u8],
+    now: SystemTime,
+) -> SignatureHeaders {
+    let created = now
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default();
+    let content_digest = content_digest(body);
+    let components = COVERED_COMPONENTS
+        .iter()
+        .map(|component| format!("\"{}\"", component))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let params = format!(
+        "({});created={};keyid=\"{}\";alg=\"ed25519\"",
+        components, created, key_id
+    );
+    let base = signature_base(&COVERED_COMPONENTS, path, &content_digest, &params);
+    let signature = key.sign(base.as_bytes());
+    SignatureHeaders {
+        content_digest,
+        signature_input: format!("{}={}", SIGNATURE_LABEL, params),
+        signature: format!(
+            "{}=:{}:",
+            SIGNATURE_LABEL,
+            base64::encode(signature.as_ref())
+        ),
+    }
+}
+
+/// Verifies the signature of a POST request to the path, whose headers and body are the
+/// provided ones, with the public key of the peer
+pub(crate) fn verify_request(
+    public_key: &[u8],
+    path: &str,
+    headers: &HeaderMap,
+    body: &[u8],
+    now: SystemTime,
+) -> Result<(), &'static str> {
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .ok_or("missing signature header")
+    };
+    let (label, params) = dictionary_members(header("signature-input")?)
+        .into_iter()
+        .next()
+        .ok_or("empty signature-input header")?;
+    let signature = dictionary_members(header("signature")?)
+        .into_iter()
+        .find(|(name, _)| *name == label)
+        .map(|(_, value)| value)
+        .ok_or("no signature matches the signature-input header")?;
+    let signature = signature
+        .strip_prefix(':')
+        .and_then(|signature| signature.strip_suffix(':'))
+        .and_then(|signature| base64::decode(signature).ok())
+        .ok_or("signature is not a byte sequence")?;
+
+    let close = params.find(')').filter(|_| params.starts_with('('));
+    let close = close.ok_or("malformed signature parameters")?;
+    let components = params[1..close]
+        .split_whitespace()
+        .map(|component| component.trim_matches('"'))
+        .collect::<Vec<_>>();
+    if COVERED_COMPONENTS
+        .iter()
+        .any(|required| !components.contains(required))
+    {
+        return Err("signature does not cover the method, path and content digest");
+    }
+    if components
+        .iter()
+        .any(|component| !COVERED_COMPONENTS.contains(component))
+    {
+        return Err("signature covers unsupported components");
+    }
+    let now = now
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default();
+    let (mut created, mut expires) = (None, None);
+    for param in params[close + 1..].split(';').filter(|p| !p.is_empty()) {
+        let mut parts = param.splitn(2, '=');
+        let (name, value) = (parts.next().unwrap_or_default(), parts.next());
+        match (name, value) {
+            ("created", Some(value)) => {
+                created = Some(value.parse::<u64>().map_err(|_| "invalid created time")?)
+            }
+            ("expires", Some(value)) => {
+                expires = Some(value.parse::<u64>().map_err(|_| "invalid expiry")?)
+            }
+            ("alg", Some(value)) if value != "\"ed25519\"" => {
+                return Err("signature algorithm is not ed25519")
+            }
+            _ => {}
+        }
+    }
+    if expires.map_or(false, |expires| expires <= now) {
+        return Err("signature has expired");
+    }
+    let created = created.ok_or("signature has no creation time")?;
+    if created.abs_diff(now) > SIGNATURE_MAX_AGE.as_secs() {
+        return Err("signature was not created recently");
+    }
+
+    let content_digest = header("content-digest")?;
+    let digest = sha256(body);
+    if dictionary_members(content_digest)
+        .into_iter()
+        .find(|(algorithm, _)| *algorithm == "sha-256")
+        .map(|(_, digest)| digest)
+        != Some(digest.as_str())
+    {
+        return Err("content digest does not match the body");
+    }
+
+    let base = signature_base(&components, path, content_digest, params);
+    UnparsedPublicKey::new(&ED25519, public_key)
+        .verify(base.as_bytes(), &signature)
+        .map_err(|_| "invalid signature")
+}
+
+/// Returns the signature base of a POST request, which is what is signed
+fn signature_base(components: &[&str], path: &str, content_digest: &str, params: &str) -> String {
+    let mut base = String::new();
+    for component in components {
+        let value = match *component {
+            "@method" => "POST",
+            "@path" => path,
+            _ => content_digest,
+        };
+        base.push_str(&format!("\"{}\": {}\n", component, value));
+    }
+    base.push_str(&format!("\"@signature-params\": {}", params));
+    base
+}
+
+/// Returns the `Content-Digest` header of a request with the provided body
+fn content_digest(body: &[u8]) -> String {
+    format!("sha-256={}", sha256(body))
+}
+
+/// Returns the SHA-256 of the body, as a structured field byte sequence
+fn sha256(body: &[u8]) -> String {
+    format!(
+        ":{}:",
+        base64::encode(digest::digest(&digest::SHA256, body).as_ref())
+    )
+}
+
+/// Splits a structured field dictionary into the names and values of its members
+fn dictionary_members(header: &str) -> Vec<(&str, &str)> {
+    let mut members = Vec::new();
+    let (mut start, mut quoted, mut depth) = (0, false, 0);
+    for (index, character) in header.char_indices().chain(Some((header.len(), ','))) {
+        match character {
+            '"' => quoted = !quoted,
+            '(' if !quoted => depth += 1,
+            ')' if !quoted => depth -= 1,
+            ',' if !quoted && depth == 0 => {
+                let member = header[start..index].trim();
+                if let Some(equals) = member.find('=') {
+                    members.push((&member[..equals], &member[equals + 1..]));
+                }
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    members
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::HeaderValue;
+    use ring::signature::KeyPair;
+
+    static SEED: &str = "nWGxne/9WmC6hEr0kuwsxERJxWl7MmkZcDusAxyuf2A=";
+    static PATH: &str = "/accounts/alice/ilp";
+
+    fn verification_key(signing_key: &str) -> Result<String, &'static str> {
+        parse_signing_key(signing_key).map(|key| base64::encode(key.public_key().as_ref()))
+    }
+
+    fn signed_headers(body: &[u8], now: SystemTime) -> HeaderMap {
+        let key = parse_signing_key(SEED).unwrap();
+        let signed = sign_request(&key, "example.node", PATH, body, now);
+        let mut headers = HeaderMap::new();
+        for (name, value) in [
+            ("content-digest", signed.content_digest),
+            ("signature-input", signed.signature_input),
+            ("signature", signed.signature),
+        ]
+        .iter()
+        {
+            headers.insert(*name, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn verifies_the_signatures_of_the_requests() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        let public_key = parse_verification_key(&verification_key(SEED).unwrap()).unwrap();
+        let headers = signed_headers(b"packet", now);
+        assert_eq!(
+            headers["signature-input"],
+            "ilp=(\"@method\" \"@path\" \"content-digest\");created=1600000000;keyid=\"example.node\";alg=\"ed25519\""
+        );
+        assert!(verify_request(&public_key, PATH, &headers, b"packet", now).is_ok());
+        let later = now + Duration::from_secs(30);
+        assert!(verify_request(&public_key, PATH, &headers, b"packet", later).is_ok());
+
+        assert_eq!(
+            verify_request(&public_key, PATH, &headers, b"other packet", now),
+            Err("content digest does not match the body")
+        );
+        assert_eq!(
+            verify_request(&public_key, "/accounts/bob/ilp", &headers, b"packet", now),
+            Err("invalid signature")
+        );
+        let much_later = now + SIGNATURE_MAX_AGE + Duration::from_secs(1);
+        assert_eq!(
+            verify_request(&public_key, PATH, &headers, b"packet", much_later),
+            Err("signature was not created recently")
+        );
+        let other_key = parse_signing_key(&base64::encode(&[1; 32])).unwrap();
+        assert_eq!(
+            verify_request(
+                other_key.public_key().as_ref(),
+                PATH,
+                &headers,
+                b"packet",
+                now
+            ),
+            Err("invalid signature")
+        );
+    }
+
+    #[test]
+    fn requires_the_signature_to_cover_the_packet() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        let public_key = parse_verification_key(&verification_key(SEED).unwrap()).unwrap();
+        let mut headers = signed_headers(b"packet", now);
+        headers.insert(
+            "signature-input",
+            HeaderValue::from_static("ilp=(\"@method\" \"@path\");created=1600000000"),
+        );
+        assert_eq!(
+            verify_request(&public_key, PATH, &headers, b"packet", now),
+            Err("signature does not cover the method, path and content digest")
+        );
+        headers.remove("content-digest");
+        headers.remove("signature-input");
+        assert_eq!(
+            verify_request(&public_key, PATH, &headers, b"packet", now),
+            Err("missing signature header")
+        );
+    }
+
+    #[test]
+    fn parses_the_keys() {
+        assert!(parse_signing_key("not base64!").is_err());
+        assert!(parse_signing_key(&base64::encode(&[1; 16])).is_err());
+        assert!(parse_verification_key(&base64::encode(&[1; 16])).is_err());
+        // The first test vector of RFC 8032
+        assert_eq!(
+            verification_key(SEED).unwrap(),
+            "11qYAYKxCrfVS/7TyWQHOg7hcvPapiMlrwIaaPcHURo="
+        );
+    }
+}
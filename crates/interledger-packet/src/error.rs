@@ -28,6 +28,36 @@ impl ErrorCode {
         }
     }
 
+    /// Returns the name of the error code, if it is one of the codes defined by ILPv4
+    pub fn name(self) -> Option<&'static str> {
+        let name = match self {
+            ErrorCode::F00_BAD_REQUEST => "Bad Request",
+            ErrorCode::F01_INVALID_PACKET => "Invalid Packet",
+            ErrorCode::F02_UNREACHABLE => "Unreachable",
+            ErrorCode::F03_INVALID_AMOUNT => "Invalid Amount",
+            ErrorCode::F04_INSUFFICIENT_DESTINATION_AMOUNT => "Insufficient Destination Amount",
+            ErrorCode::F05_WRONG_CONDITION => "Wrong Condition",
+            ErrorCode::F06_UNEXPECTED_PAYMENT => "Unexpected Payment",
+            ErrorCode::F07_CANNOT_RECEIVE => "Cannot Receive",
+            ErrorCode::F08_AMOUNT_TOO_LARGE => "Amount Too Large",
+            ErrorCode::F09_INVALID_PEER_RESPONSE => "Invalid Peer Response",
+            ErrorCode::F99_APPLICATION_ERROR => "Application Error",
+            ErrorCode::T00_INTERNAL_ERROR => "Internal Error",
+            ErrorCode::T01_PEER_UNREACHABLE => "Peer Unreachable",
+            ErrorCode::T02_PEER_BUSY => "Peer Busy",
+            ErrorCode::T03_CONNECTOR_BUSY => "Connector Busy",
+            ErrorCode::T04_INSUFFICIENT_LIQUIDITY => "Insufficient Liquidity",
+            ErrorCode::T05_RATE_LIMITED => "Rate Limited",
+            ErrorCode::T99_APPLICATION_ERROR => "Application Error",
+            ErrorCode::R00_TRANSFER_TIMED_OUT => "Transfer Timed Out",
+            ErrorCode::R01_INSUFFICIENT_SOURCE_AMOUNT => "Insufficient Source Amount",
+            ErrorCode::R02_INSUFFICIENT_TIMEOUT => "Insufficient Timeout",
+            ErrorCode::R99_APPLICATION_ERROR => "Application Error",
+            _ => return None,
+        };
+        Some(name)
+    }
+
     // Error codes from: <https://github.com/interledger/rfcs/blob/master/0027-interledger-protocol-4/0027-interledger-protocol-4.md#error-codes>
 
     // Final errors:
@@ -67,34 +97,12 @@ impl From<ErrorCode> for [u8; 3] {
 
 impl fmt::Debug for ErrorCode {
     fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-        let error_str = match *self {
-            ErrorCode::F00_BAD_REQUEST => "F00 (Bad Request)",
-            ErrorCode::F01_INVALID_PACKET => "F01 (Invalid Packet)",
-            ErrorCode::F02_UNREACHABLE => "F02 (Unreachable)",
-            ErrorCode::F03_INVALID_AMOUNT => "F03 (Invalid Amount)",
-            ErrorCode::F04_INSUFFICIENT_DESTINATION_AMOUNT => {
-                "F04 (Insufficient Destination Amount)"
-            }
-            ErrorCode::F05_WRONG_CONDITION => "F05 (Wrong Condition)",
-            ErrorCode::F06_UNEXPECTED_PAYMENT => "F06 (Unexpected Payment)",
-            ErrorCode::F07_CANNOT_RECEIVE => "F07 (Cannot Receive)",
-            ErrorCode::F08_AMOUNT_TOO_LARGE => "F08 (Amount Too Large)",
-            ErrorCode::F09_INVALID_PEER_RESPONSE => "F09 (Invalid Peer Response)",
-            ErrorCode::F99_APPLICATION_ERROR => "F99 (Application Error)",
-            ErrorCode::T00_INTERNAL_ERROR => "T00 (Internal Error)",
-            ErrorCode::T01_PEER_UNREACHABLE => "T01 (Peer Unreachable)",
-            ErrorCode::T02_PEER_BUSY => "T02 (Peer Busy)",
-            ErrorCode::T03_CONNECTOR_BUSY => "T03 (Connector Busy)",
-            ErrorCode::T04_INSUFFICIENT_LIQUIDITY => "T04 (Insufficient Liquidity)",
-            ErrorCode::T05_RATE_LIMITED => "T05 (Rate Limited)",
-            ErrorCode::T99_APPLICATION_ERROR => "T99 (Application Error)",
-            ErrorCode::R00_TRANSFER_TIMED_OUT => "R00 (Transfer Timed Out)",
-            ErrorCode::R01_INSUFFICIENT_SOURCE_AMOUNT => "R01 (Insufficient Source Amount)",
-            ErrorCode::R02_INSUFFICIENT_TIMEOUT => "R02 (Insufficient Timeout)",
-            ErrorCode::R99_APPLICATION_ERROR => "R99 (Application Error)",
-            _ => str::from_utf8(&self.0[..]).map_err(|_| fmt::Error)?,
-        }
-        .to_owned();
+        let error_str = match self.name() {
+            Some(name) => format!("{} ({})", self, name),
+            None => str::from_utf8(&self.0[..])
+                .map_err(|_| fmt::Error)?
+                .to_owned(),
+        };
         formatter
             .debug_tuple("ErrorCode")
             .field(&error_str)
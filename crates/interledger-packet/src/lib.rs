@@ -15,6 +15,12 @@ pub use self::address::{Address, AddressError};
 pub use self::error::{ErrorClass, ErrorCode};
 pub use self::errors::ParseError;
 
-pub use self::packet::MaxPacketAmountDetails;
 pub use self::packet::{Fulfill, Packet, PacketType, Prepare, Reject};
 pub use self::packet::{FulfillBuilder, PrepareBuilder, RejectBuilder};
+pub use self::packet::{
+    LiquidityExceededDetails, MaxPacketAmountDetails, TransferLimitExceededDetails,
+};
+
+/// Max size, in bytes, of the ILP packets which the node accepts over any transport. The
+/// limits configured on the node and on its accounts may only lower it.
+pub const MAX_PACKET_SIZE: usize = 40000;
@@ -110,6 +110,8 @@ impl<'a> BufOerExt<'a> for &'a [u8] {
                     ErrorKind::InvalidData,
                     "length prefix too large",
                 ))
+            } else if length_prefix_length == 0 {
+                Err(Error::new(ErrorKind::InvalidData, "empty length prefix"))
             } else {
                 Ok(self.read_uint::<BigEndian>(length_prefix_length)? as usize)
             }
@@ -314,6 +316,13 @@ mod test_buf_oer_ext {
             too_big.read_var_octet_string_length().unwrap_err().kind(),
             ErrorKind::InvalidData,
         );
+
+        // The length prefix must have at least one byte.
+        let mut empty: &[u8] = &[HIGH_BIT, 0x01];
+        assert_eq!(
+            empty.read_var_octet_string_length().unwrap_err().kind(),
+            ErrorKind::InvalidData,
+        );
     }
 
     #[test]
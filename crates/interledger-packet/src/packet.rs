@@ -411,7 +411,11 @@ impl TryFrom<BytesMut> for Reject {
         let code = ErrorCode::new(code);
 
         let triggered_by_offset = content_offset + content_len - content.len();
-        Address::try_from(content.read_var_octet_string()?)?;
+        // The address is empty in the rejects built without one
+        let triggered_by = content.read_var_octet_string()?;
+        if !triggered_by.is_empty() {
+            Address::try_from(triggered_by)?;
+        }
 
         let message_offset = content_offset + content_len - content.len();
         content.skip_var_octet_string()?;
@@ -586,6 +590,101 @@ impl MaxPacketAmountDetails {
     }
 }
 
+/// Data of the T04 Insufficient Liquidity rejects of the packets which would have
+/// exceeded a credit limit of an account
+#[derive(Clone, Debug, PartialEq)]
+pub struct LiquidityExceededDetails {
+    exceeded_by: u64,
+    limit: u64,
+}
+
+impl LiquidityExceededDetails {
+    #[inline]
+    pub fn new(exceeded_by: u64, limit: u64) -> Self {
+        LiquidityExceededDetails { exceeded_by, limit }
+    }
+
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<Self, std::io::Error> {
+        let exceeded_by = bytes.read_u64::<BigEndian>()?;
+        let limit = bytes.read_u64::<BigEndian>()?;
+        Ok(LiquidityExceededDetails::new(exceeded_by, limit))
+    }
+
+    pub fn to_bytes(&self) -> [u8; 16] {
+        let mut bytes = [0x00_u8; 16];
+        let mut writer = Cursor::new(&mut bytes[..]);
+        writer.put_u64_be(self.exceeded_by);
+        writer.put_u64_be(self.limit);
+        bytes
+    }
+
+    /// The amount by which the packet would have exceeded the limit
+    #[inline]
+    pub fn exceeded_by(&self) -> u64 {
+        self.exceeded_by
+    }
+
+    /// The credit limit which the packet would have exceeded
+    #[inline]
+    pub fn limit(&self) -> u64 {
+        self.limit
+    }
+}
+
+/// Data of the rejects of the packets which would have exceeded a daily or monthly
+/// transfer limit of an account
+#[derive(Clone, Debug, PartialEq)]
+pub struct TransferLimitExceededDetails {
+    limit: u64,
+    used: u64,
+    reset_at: u64,
+}
+
+impl TransferLimitExceededDetails {
+    #[inline]
+    pub fn new(limit: u64, used: u64, reset_at: u64) -> Self {
+        TransferLimitExceededDetails {
+            limit,
+            used,
+            reset_at,
+        }
+    }
+
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<Self, std::io::Error> {
+        let limit = bytes.read_u64::<BigEndian>()?;
+        let used = bytes.read_u64::<BigEndian>()?;
+        let reset_at = bytes.read_u64::<BigEndian>()?;
+        Ok(TransferLimitExceededDetails::new(limit, used, reset_at))
+    }
+
+    pub fn to_bytes(&self) -> [u8; 24] {
+        let mut bytes = [0x00_u8; 24];
+        let mut writer = Cursor::new(&mut bytes[..]);
+        writer.put_u64_be(self.limit);
+        writer.put_u64_be(self.used);
+        writer.put_u64_be(self.reset_at);
+        bytes
+    }
+
+    /// The transfer limit which the packet would have exceeded
+    #[inline]
+    pub fn limit(&self) -> u64 {
+        self.limit
+    }
+
+    /// The amount already transferred in the window of the limit
+    #[inline]
+    pub fn used(&self) -> u64 {
+        self.used
+    }
+
+    /// When the oldest amount counted in the window leaves it, in milliseconds since the UNIX epoch
+    #[inline]
+    pub fn reset_at(&self) -> u64 {
+        self.reset_at
+    }
+}
+
 // Bytes05 compatibilty methods
 impl TryFrom<bytes05::BytesMut> for Prepare {
     type Error = ParseError;
@@ -965,3 +1064,69 @@ mod test_max_packet_amount_details {
         assert_eq!(DETAILS.max_amount(), 0x0006_0504);
     }
 }
+
+#[cfg(test)]
+mod test_liquidity_exceeded_details {
+    use super::*;
+
+    static BYTES: &[u8] = b"\
+        \x00\x00\x00\x00\x00\x00\x00\x64\
+        \x00\x00\x00\x00\x00\x00\x03\xe8\
+    ";
+
+    #[test]
+    fn test_from_bytes() {
+        let details = LiquidityExceededDetails::from_bytes(BYTES).unwrap();
+        assert_eq!(details, LiquidityExceededDetails::new(100, 1000));
+        assert_eq!(details.exceeded_by(), 100);
+        assert_eq!(details.limit(), 1000);
+        assert_eq!(
+            LiquidityExceededDetails::from_bytes(&BYTES[..8])
+                .unwrap_err()
+                .kind(),
+            std::io::ErrorKind::UnexpectedEof,
+        );
+    }
+
+    #[test]
+    fn test_to_bytes() {
+        assert_eq!(
+            &LiquidityExceededDetails::new(100, 1000).to_bytes()[..],
+            BYTES
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_transfer_limit_exceeded_details {
+    use super::*;
+
+    static BYTES: &[u8] = b"\
+        \x00\x00\x00\x00\x00\x00\x03\xe8\
+        \x00\x00\x00\x00\x00\x00\x00\x64\
+        \x00\x00\x00\x00\x00\x00\x27\x10\
+    ";
+
+    #[test]
+    fn test_from_bytes() {
+        let details = TransferLimitExceededDetails::from_bytes(BYTES).unwrap();
+        assert_eq!(details, TransferLimitExceededDetails::new(1000, 100, 10000));
+        assert_eq!(details.limit(), 1000);
+        assert_eq!(details.used(), 100);
+        assert_eq!(details.reset_at(), 10000);
+        assert_eq!(
+            TransferLimitExceededDetails::from_bytes(&BYTES[..16])
+                .unwrap_err()
+                .kind(),
+            std::io::ErrorKind::UnexpectedEof,
+        );
+    }
+
+    #[test]
+    fn test_to_bytes() {
+        assert_eq!(
+            &TransferLimitExceededDetails::new(1000, 100, 10000).to_bytes()[..],
+            BYTES
+        );
+    }
+}
@@ -0,0 +1,237 @@
+//! Property tests of the ILP packet and address parsers: the packets built from random
+//! fields round-trip, and mutations of them are either parsed or rejected, but never
+//! panic. The `fuzz` directory at the root of the repository has the corresponding
+//! fuzz targets, which explore the inputs far further than these seeded runs.
+
+use bytes::BytesMut;
+use interledger_packet::{
+    Address, ErrorCode, Fulfill, FulfillBuilder, MaxPacketAmountDetails, Packet, Prepare,
+    PrepareBuilder, Reject, RejectBuilder,
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::convert::TryFrom;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const CASES: usize = 2000;
+const SCHEMES: &[&str] = &[
+    "g", "private", "example", "peer", "self", "test", "test1", "test2", "test3", "local",
+];
+const SEGMENT_CHARACTERS: &[u8] =
+    b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789_~-";
+const ERROR_CODES: &[ErrorCode] = &[
+    ErrorCode::F00_BAD_REQUEST,
+    ErrorCode::F02_UNREACHABLE,
+    ErrorCode::F08_AMOUNT_TOO_LARGE,
+    ErrorCode::F99_APPLICATION_ERROR,
+    ErrorCode::T04_INSUFFICIENT_LIQUIDITY,
+    ErrorCode::R00_TRANSFER_TIMED_OUT,
+];
+
+fn rng() -> StdRng {
+    StdRng::seed_from_u64(0x1f2e_3d4c_5b6a_7988)
+}
+
+fn bytes(rng: &mut StdRng, max_len: usize) -> Vec<u8> {
+    let len = rng.gen_range(0, max_len + 1);
+    (0..len).map(|_| rng.gen()).collect()
+}
+
+fn address(rng: &mut StdRng) -> Address {
+    let mut address = SCHEMES[rng.gen_range(0, SCHEMES.len())].to_string();
+    for _ in 0..rng.gen_range(1, 6) {
+        address.push('.');
+        for _ in 0..rng.gen_range(1, 20) {
+            address.push(SEGMENT_CHARACTERS[rng.gen_range(0, SEGMENT_CHARACTERS.len())] as char);
+        }
+    }
+    Address::try_from(address.as_bytes()).unwrap()
+}
+
+/// A time between the years 2000 and 9999, with the millisecond precision of the packets
+fn time(rng: &mut StdRng) -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis(rng.gen_range(946_684_800_000, 253_402_300_799_999))
+}
+
+/// Flips bits, overwrites, inserts, removes or truncates bytes of a valid buffer
+fn mutate(rng: &mut StdRng, buffer: &[u8]) -> Vec<u8> {
+    let mut buffer = buffer.to_vec();
+    for _ in 0..rng.gen_range(1, 4) {
+        let index = rng.gen_range(0, buffer.len() + 1);
+        match rng.gen_range(0, 6) {
+            0 if index < buffer.len() => buffer[index] ^= 1 << rng.gen_range(0, 8),
+            // The values most likely to be misread as lengths
+            1 if index < buffer.len() => {
+                buffer[index] = [0x00, 0x7f, 0x80, 0x81, 0x88, 0x89, 0xff][rng.gen_range(0, 7)]
+            }
+            2 if index < buffer.len() => buffer[index] = rng.gen(),
+            3 => {
+                let inserted = bytes(rng, 16);
+                buffer.splice(index..index, inserted);
+            }
+            4 if index < buffer.len() => {
+                buffer.remove(index);
+            }
+            _ => buffer.truncate(index),
+        }
+    }
+    buffer
+}
+
+/// Calls all the getters of the packet, which read from the offsets found while parsing it
+fn inspect(packet: Packet) {
+    match packet {
+        Packet::Prepare(mut prepare) => {
+            let _ = (prepare.amount(), prepare.expires_at());
+            let _ = (prepare.execution_condition(), prepare.destination());
+            prepare.set_amount(1);
+            prepare.set_expires_at(SystemTime::now());
+            let _ = prepare.data().len() + prepare.into_data().len();
+        }
+        Packet::Fulfill(fulfill) => {
+            let _ = fulfill.fulfillment();
+            let _ = fulfill.data().len() + fulfill.into_data().len();
+        }
+        Packet::Reject(reject) => {
+            let _ = (reject.code(), reject.triggered_by(), reject.message());
+            let _ = reject.data().len() + reject.into_data().len();
+        }
+    }
+}
+
+#[test]
+fn prepare_round_trips() {
+    let mut rng = rng();
+    for _ in 0..CASES {
+        let condition: [u8; 32] = rng.gen();
+        let data = bytes(&mut rng, 300);
+        let builder = PrepareBuilder {
+            amount: rng.gen(),
+            expires_at: time(&mut rng),
+            execution_condition: &condition,
+            destination: address(&mut rng),
+            data: &data,
+        };
+        let prepare = Prepare::try_from(BytesMut::from(builder.build())).unwrap();
+        assert_eq!(prepare.amount(), builder.amount);
+        assert_eq!(prepare.expires_at(), builder.expires_at);
+        assert_eq!(prepare.execution_condition(), &condition[..]);
+        assert_eq!(prepare.destination(), builder.destination);
+        assert_eq!(prepare.data(), &data[..]);
+    }
+}
+
+#[test]
+fn fulfill_round_trips() {
+    let mut rng = rng();
+    for _ in 0..CASES {
+        let fulfillment: [u8; 32] = rng.gen();
+        let data = bytes(&mut rng, 300);
+        let fulfill = FulfillBuilder {
+            fulfillment: &fulfillment,
+            data: &data,
+        }
+        .build();
+        let fulfill = Fulfill::try_from(BytesMut::from(fulfill)).unwrap();
+        assert_eq!(fulfill.fulfillment(), &fulfillment[..]);
+        assert_eq!(fulfill.data(), &data[..]);
+    }
+}
+
+#[test]
+fn reject_round_trips() {
+    let mut rng = rng();
+    for _ in 0..CASES {
+        let triggered_by = address(&mut rng);
+        let message = bytes(&mut rng, 100);
+        let data = bytes(&mut rng, 300);
+        let builder = RejectBuilder {
+            code: ERROR_CODES[rng.gen_range(0, ERROR_CODES.len())],
+            message: &message,
+            triggered_by: if rng.gen() { Some(&triggered_by) } else { None },
+            data: &data,
+        };
+        let reject = Reject::try_from(BytesMut::from(builder.build())).unwrap();
+        assert_eq!(reject.code(), builder.code);
+        assert_eq!(reject.triggered_by().as_ref(), builder.triggered_by);
+        assert_eq!(reject.message(), &message[..]);
+        assert_eq!(reject.data(), &data[..]);
+    }
+}
+
+#[test]
+fn mutated_packets_do_not_panic() {
+    let mut rng = rng();
+    for _ in 0..CASES {
+        let bytes05 = |buffer: &[u8]| bytes05::BytesMut::from(buffer);
+        let condition: [u8; 32] = rng.gen();
+        let data = bytes(&mut rng, 300);
+        let triggered_by = address(&mut rng);
+        let valid = [
+            BytesMut::from(
+                PrepareBuilder {
+                    amount: rng.gen(),
+                    expires_at: time(&mut rng),
+                    execution_condition: &condition,
+                    destination: address(&mut rng),
+                    data: &data,
+                }
+                .build(),
+            ),
+            BytesMut::from(
+                FulfillBuilder {
+                    fulfillment: &condition,
+                    data: &data,
+                }
+                .build(),
+            ),
+            BytesMut::from(
+                RejectBuilder {
+                    code: ErrorCode::F99_APPLICATION_ERROR,
+                    message: &data[..data.len() / 2],
+                    triggered_by: Some(&triggered_by),
+                    data: &data,
+                }
+                .build(),
+            ),
+        ];
+        for buffer in valid.iter() {
+            let mutated = mutate(&mut rng, buffer);
+            if let Ok(packet) = Packet::try_from(bytes05(&mutated)) {
+                inspect(packet);
+            }
+            let _ = Prepare::try_from(BytesMut::from(&mutated[..]));
+            let _ = Fulfill::try_from(BytesMut::from(&mutated[..]));
+            let _ = Reject::try_from(BytesMut::from(&mutated[..]));
+        }
+    }
+}
+
+#[test]
+fn random_bytes_do_not_panic() {
+    let mut rng = rng();
+    for _ in 0..CASES {
+        let buffer = bytes(&mut rng, 64);
+        if let Ok(packet) = Packet::try_from(bytes05::BytesMut::from(&buffer[..])) {
+            inspect(packet);
+        }
+        let _ = Address::try_from(&buffer[..]);
+        let _ = MaxPacketAmountDetails::from_bytes(&buffer[..]);
+    }
+}
+
+#[test]
+fn addresses_round_trip() {
+    let mut rng = rng();
+    for _ in 0..CASES {
+        let address = address(&mut rng);
+        assert_eq!(Address::try_from(address.as_ref()).unwrap(), address);
+        let suffix = bytes(&mut rng, 10);
+        if let Ok(with_suffix) = address.with_suffix(&suffix) {
+            assert!(with_suffix.to_string().starts_with(&address.to_string()));
+        }
+        let mutated = mutate(&mut rng, address.as_ref());
+        if let Ok(mutated) = Address::try_from(&mutated[..]) {
+            let _ = (mutated.scheme(), mutated.segments().count());
+        }
+    }
+}
@@ -0,0 +1,121 @@
+//! Round-trips the ILP packets of the shared test vectors (see `test-vectors/README.md`
+//! at the root of the repository). Set `UPDATE_TEST_VECTORS=1` to regenerate the
+//! buffers of the vectors from their fields instead of checking them.
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use interledger_packet::{
+    Address, ErrorCode, FulfillBuilder, Packet, PrepareBuilder, RejectBuilder,
+};
+use serde_json::{json, Value};
+use std::{convert::TryFrom, convert::TryInto, env, fs, str, str::FromStr, time::SystemTime};
+
+const VECTORS: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/../../test-vectors/ilp-packets.json"
+);
+
+#[test]
+fn ilp_packet_vectors() {
+    let mut cases: Vec<Value> =
+        serde_json::from_str(&fs::read_to_string(VECTORS).unwrap()).unwrap();
+    let update = env::var_os("UPDATE_TEST_VECTORS").is_some();
+
+    for case in cases.iter_mut() {
+        let name = case["name"].as_str().unwrap().to_string();
+        let encoded = hex::encode(encode(&case["packet"]));
+        if update {
+            case["buffer"] = json!(encoded);
+            continue;
+        }
+
+        assert_eq!(encoded, case["buffer"], "encoding of {}", name);
+        let buffer = hex::decode(case["buffer"].as_str().unwrap()).unwrap();
+        let packet = Packet::try_from(bytes::BytesMut::from(&buffer[..]))
+            .unwrap_or_else(|err| panic!("decoding of {}: {:?}", name, err));
+        assert_eq!(to_json(&packet), case["packet"], "decoding of {}", name);
+    }
+
+    if update {
+        let mut json = serde_json::to_string_pretty(&cases).unwrap();
+        json.push('\n');
+        fs::write(VECTORS, json).unwrap();
+    }
+}
+
+fn encode(packet: &Value) -> Vec<u8> {
+    let bytes = |field: &str| hex::decode(packet[field].as_str().unwrap()).unwrap();
+    let text = |field: &str| packet[field].as_str().unwrap();
+    let data = bytes("data");
+    match text("type") {
+        "prepare" => {
+            let condition: [u8; 32] = bytes("executionCondition")[..].try_into().unwrap();
+            let expires_at: DateTime<Utc> = text("expiresAt").parse().unwrap();
+            PrepareBuilder {
+                amount: text("amount").parse().unwrap(),
+                expires_at: SystemTime::from(expires_at),
+                execution_condition: &condition,
+                destination: Address::from_str(text("destination")).unwrap(),
+                data: &data,
+            }
+            .build()
+            .as_ref()
+            .to_vec()
+        }
+        "fulfill" => {
+            let fulfillment: [u8; 32] = bytes("fulfillment")[..].try_into().unwrap();
+            FulfillBuilder {
+                fulfillment: &fulfillment,
+                data: &data,
+            }
+            .build()
+            .as_ref()
+            .to_vec()
+        }
+        "reject" => {
+            let code: [u8; 3] = text("code").as_bytes().try_into().unwrap();
+            let triggered_by = match text("triggeredBy") {
+                "" => None,
+                address => Some(Address::from_str(address).unwrap()),
+            };
+            RejectBuilder {
+                code: ErrorCode::new(code),
+                message: text("message").as_bytes(),
+                triggered_by: triggered_by.as_ref(),
+                data: &data,
+            }
+            .build()
+            .as_ref()
+            .to_vec()
+        }
+        other => panic!("unknown packet type {}", other),
+    }
+}
+
+fn to_json(packet: &Packet) -> Value {
+    match packet {
+        Packet::Prepare(prepare) => json!({
+            "type": "prepare",
+            "amount": prepare.amount().to_string(),
+            "expiresAt": DateTime::<Utc>::from(prepare.expires_at())
+                .to_rfc3339_opts(SecondsFormat::Millis, true),
+            "executionCondition": hex::encode(prepare.execution_condition()),
+            "destination": prepare.destination().to_string(),
+            "data": hex::encode(prepare.data()),
+        }),
+        Packet::Fulfill(fulfill) => json!({
+            "type": "fulfill",
+            "fulfillment": hex::encode(fulfill.fulfillment()),
+            "data": hex::encode(fulfill.data()),
+        }),
+        Packet::Reject(reject) => json!({
+            "type": "reject",
+            "code": reject.code().to_string(),
+            "triggeredBy": reject
+                .triggered_by()
+                .map(|address| address.to_string())
+                .unwrap_or_default(),
+            "message": str::from_utf8(reject.message()).unwrap(),
+            "data": hex::encode(reject.data()),
+        }),
+    }
+}
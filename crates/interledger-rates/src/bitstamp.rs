@@ -0,0 +1,74 @@
+use super::ExchangeRateProvider;
+use async_trait::async_trait;
+use futures::TryFutureExt;
+use once_cell::sync::Lazy;
+use reqwest::{Client, Url};
+use serde::Deserialize;
+use std::{collections::HashMap, str::FromStr};
+use tracing::{error, warn};
+
+static BITSTAMP_TICKERS_URL: Lazy<Url> =
+    Lazy::new(|| Url::parse("https://www.bitstamp.net/api/v2/ticker/").unwrap());
+
+#[derive(Deserialize, Debug)]
+struct Ticker {
+    /// For example "BTC/USD"
+    pair: String,
+    last: String,
+}
+
+/// Polls the [Bitstamp](https://www.bitstamp.net/api/) exchange
+/// for the last traded prices of all assets traded against USD
+#[derive(Debug, Clone)]
+pub struct Bitstamp;
+
+#[async_trait]
+impl ExchangeRateProvider for Bitstamp {
+    async fn fetch_rates(&self, client: &Client) -> Result<HashMap<String, f64>, ()> {
+        let res = client
+            .get(BITSTAMP_TICKERS_URL.clone())
+            .send()
+            .map_err(|err| {
+                error!("Error fetching exchange rates from Bitstamp: {:?}", err);
+            })
+            .await?;
+
+        let res = res.error_for_status().map_err(|err| {
+            error!("HTTP error getting exchange rates from Bitstamp: {:?}", err);
+        })?;
+
+        let tickers: Vec<Ticker> = res
+            .json()
+            .map_err(|err| {
+                error!(
+                    "Error getting exchange rate response body from Bitstamp, incorrect type: {:?}",
+                    err
+                );
+            })
+            .await?;
+
+        Ok(rates_from_tickers(tickers))
+    }
+}
+
+fn rates_from_tickers(tickers: Vec<Ticker>) -> HashMap<String, f64> {
+    tickers
+        .into_iter()
+        .filter_map(|ticker| {
+            let mut assets = ticker.pair.split('/');
+            match (assets.next(), assets.next()) {
+                (Some(base), Some("USD")) => match f64::from_str(&ticker.last) {
+                    Ok(rate) => Some((base.to_uppercase(), rate)),
+                    Err(err) => {
+                        warn!(
+                            "Unable to parse {} rate as an f64: {} {:?}",
+                            ticker.pair, ticker.last, err
+                        );
+                        None
+                    }
+                },
+                _ => None,
+            }
+        })
+        .collect()
+}
@@ -1,3 +1,5 @@
+use super::ExchangeRateProvider;
+use async_trait::async_trait;
 use futures::TryFutureExt;
 use once_cell::sync::Lazy;
 use reqwest::{Client, Url};
@@ -25,7 +27,18 @@ struct RateResponse {
     data: Vec<Rate>,
 }
 
-pub async fn query_coincap(client: &Client) -> Result<HashMap<String, f64>, ()> {
+/// Polls the [CoinCap](https://coincap.io) API
+#[derive(Debug, Clone)]
+pub struct CoinCap;
+
+#[async_trait]
+impl ExchangeRateProvider for CoinCap {
+    async fn fetch_rates(&self, client: &Client) -> Result<HashMap<String, f64>, ()> {
+        query_coincap(client).await
+    }
+}
+
+async fn query_coincap(client: &Client) -> Result<HashMap<String, f64>, ()> {
     let (assets, rates) = futures::future::join(
         query_coincap_endpoint(client, COINCAP_ASSETS_URL.clone()),
         query_coincap_endpoint(client, COINCAP_RATES_URL.clone()),
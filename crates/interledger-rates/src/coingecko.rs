@@ -0,0 +1,70 @@
+use super::ExchangeRateProvider;
+use async_trait::async_trait;
+use futures::TryFutureExt;
+use once_cell::sync::Lazy;
+use reqwest::{Client, Url};
+use serde::Deserialize;
+use std::collections::HashMap;
+use tracing::error;
+
+static COINGECKO_MARKETS_URL: Lazy<Url> = Lazy::new(|| {
+    Url::parse(
+        "https://api.coingecko.com/api/v3/coins/markets?vs_currency=usd&order=market_cap_desc&per_page=250",
+    )
+    .unwrap()
+});
+
+#[derive(Deserialize, Debug)]
+struct Market {
+    symbol: String,
+    current_price: Option<f64>,
+}
+
+/// Polls the [CoinGecko](https://www.coingecko.com/en/api) API
+/// for the USD prices of the 250 largest cryptocurrencies
+#[derive(Debug, Clone)]
+pub struct CoinGecko;
+
+#[async_trait]
+impl ExchangeRateProvider for CoinGecko {
+    async fn fetch_rates(&self, client: &Client) -> Result<HashMap<String, f64>, ()> {
+        let res = client
+            .get(COINGECKO_MARKETS_URL.clone())
+            .send()
+            .map_err(|err| {
+                error!("Error fetching exchange rates from CoinGecko: {:?}", err);
+            })
+            .await?;
+
+        let res = res.error_for_status().map_err(|err| {
+            error!(
+                "HTTP error getting exchange rates from CoinGecko: {:?}",
+                err
+            );
+        })?;
+
+        let markets: Vec<Market> = res
+            .json()
+            .map_err(|err| {
+                error!(
+                    "Error getting exchange rate response body from CoinGecko, incorrect type: {:?}",
+                    err
+                );
+            })
+            .await?;
+
+        Ok(rates_from_markets(markets))
+    }
+}
+
+fn rates_from_markets(markets: Vec<Market>) -> HashMap<String, f64> {
+    let mut rates = HashMap::with_capacity(markets.len());
+    for market in markets {
+        if let Some(price) = market.current_price {
+            // The markets are sorted by market cap, so if multiple coins
+            // use the same symbol, the rate of the largest one is used
+            rates.entry(market.symbol.to_uppercase()).or_insert(price);
+        }
+    }
+    rates
+}
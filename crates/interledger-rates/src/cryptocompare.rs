@@ -1,3 +1,5 @@
+use super::ExchangeRateProvider;
+use async_trait::async_trait;
 use futures::TryFutureExt;
 use once_cell::sync::Lazy;
 use reqwest::{Client, Url};
@@ -45,7 +47,26 @@ struct Response {
     data: Vec<Record>,
 }
 
-pub async fn query_cryptocompare(
+/// Polls the [CryptoCompare](https://cryptocompare.com) API, which requires an API key
+#[derive(Debug, Clone)]
+pub struct CryptoCompare {
+    api_key: SecretString,
+}
+
+impl CryptoCompare {
+    pub fn new(api_key: SecretString) -> Self {
+        CryptoCompare { api_key }
+    }
+}
+
+#[async_trait]
+impl ExchangeRateProvider for CryptoCompare {
+    async fn fetch_rates(&self, client: &Client) -> Result<HashMap<String, f64>, ()> {
+        query_cryptocompare(client, &self.api_key).await
+    }
+}
+
+async fn query_cryptocompare(
     client: &Client,
     api_key: &SecretString,
 ) -> Result<HashMap<String, f64>, ()> {
@@ -0,0 +1,205 @@
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+use tracing::warn;
+
+/// Sanity checks applied to the polled rates before they are stored.
+///
+/// Both guards are disabled by default.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct RateGuards {
+    /// Maximum age, in seconds, of the rate of an asset. While the provider fails, or
+    /// stops returning an asset, the last known good rate is kept until it is older
+    /// than this. Rates which are older are removed, so that packets which need them
+    /// are rejected rather than forwarded using a stale rate.
+    #[serde(default)]
+    pub max_age: Option<u64>,
+    /// Maximum change, as a fraction of the previous rate, which is applied without
+    /// operator confirmation. For example, with 0.1, a new rate which is more than 10%
+    /// above or below the previous one is rejected and the previous rate is kept.
+    /// The operator confirms the new rate by setting it via the HTTP API.
+    #[serde(default)]
+    pub max_deviation: Option<f64>,
+}
+
+/// The rates to store after applying the guards
+#[derive(Debug, Default)]
+pub(crate) struct GuardedRates {
+    pub rates: HashMap<String, f64>,
+    /// Assets whose new rate deviated too much from the previous one
+    pub rejected: Vec<String>,
+    /// Assets whose last known good rate was removed because it was too old
+    pub stale: Vec<String>,
+    /// Number of assets for which the last known good rate was kept
+    pub fallback: usize,
+}
+
+impl GuardedRates {
+    /// Whether some of the rates are not the ones the provider just returned
+    pub fn is_degraded(&self) -> bool {
+        !self.rejected.is_empty() || !self.stale.is_empty() || self.fallback > 0
+    }
+}
+
+impl RateGuards {
+    pub fn is_enabled(&self) -> bool {
+        self.max_age.is_some() || self.max_deviation.is_some()
+    }
+
+    /// Checks the `fetched` rates (None if polling the provider failed) against the
+    /// `previous` rates, and falls back to the last known good rates where needed.
+    /// `last_updated` tracks when each asset's rate was last accepted from the provider.
+    pub(crate) fn apply(
+        &self,
+        last_updated: &mut HashMap<String, Instant>,
+        fetched: Option<HashMap<String, f64>>,
+        previous: &HashMap<String, f64>,
+        now: Instant,
+    ) -> GuardedRates {
+        let mut guarded = GuardedRates::default();
+        let fetch_failed = fetched.is_none();
+
+        for (asset_code, rate) in fetched.unwrap_or_default() {
+            if let (Some(max_deviation), Some(previous_rate)) =
+                (self.max_deviation, previous.get(&asset_code))
+            {
+                if deviates(*previous_rate, rate, max_deviation) {
+                    warn!(
+                        "Not applying new {} rate of {} because it deviates by more than {}% from the previous rate of {}. Set it via the API to confirm it",
+                        asset_code, rate, max_deviation * 100.0, previous_rate
+                    );
+                    guarded.rejected.push(asset_code);
+                    continue;
+                }
+            }
+            last_updated.insert(asset_code.clone(), now);
+            guarded.rates.insert(asset_code, rate);
+        }
+
+        for (asset_code, rate) in previous {
+            if guarded.rates.contains_key(asset_code) || asset_code == "USD" {
+                continue;
+            }
+            let rejected = guarded.rejected.contains(asset_code);
+            let max_age = match self.max_age {
+                Some(max_age) => Duration::from_secs(max_age),
+                // Without a maximum age, rates are kept while polling fails and are
+                // removed when the provider stops returning them
+                None => {
+                    if rejected || fetch_failed {
+                        guarded.rates.insert(asset_code.clone(), *rate);
+                        if !rejected {
+                            guarded.fallback += 1;
+                        }
+                    }
+                    continue;
+                }
+            };
+            // Rates which were not fetched by this node (for example, set via the API)
+            // are treated as being updated when they are first seen
+            let updated = *last_updated.entry(asset_code.clone()).or_insert(now);
+            if now.duration_since(updated) > max_age {
+                warn!(
+                    "Removing {} rate of {} because it was last updated more than {}s ago",
+                    asset_code,
+                    rate,
+                    max_age.as_secs()
+                );
+                guarded.stale.push(asset_code.clone());
+            } else {
+                guarded.rates.insert(asset_code.clone(), *rate);
+                if !rejected {
+                    guarded.fallback += 1;
+                }
+            }
+        }
+
+        last_updated.retain(|asset_code, _| guarded.rates.contains_key(asset_code));
+        guarded
+    }
+}
+
+fn deviates(previous: f64, rate: f64, max_deviation: f64) -> bool {
+    previous > 0.0 && ((rate - previous) / previous).abs() > max_deviation
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rates(rates: &[(&str, f64)]) -> HashMap<String, f64> {
+        rates
+            .iter()
+            .map(|(asset_code, rate)| (asset_code.to_string(), *rate))
+            .collect()
+    }
+
+    #[test]
+    fn rejects_rates_which_deviate_too_much() {
+        let guards = RateGuards {
+            max_age: None,
+            max_deviation: Some(0.1),
+        };
+        let mut last_updated = HashMap::new();
+        let guarded = guards.apply(
+            &mut last_updated,
+            Some(rates(&[("ABC", 1.09), ("XYZ", 3.0)])),
+            &rates(&[("ABC", 1.0), ("XYZ", 2.0)]),
+            Instant::now(),
+        );
+        assert_eq!(guarded.rates, rates(&[("ABC", 1.09), ("XYZ", 2.0)]));
+        assert_eq!(guarded.rejected, vec!["XYZ".to_string()]);
+        assert!(guarded.is_degraded());
+    }
+
+    #[test]
+    fn falls_back_until_rates_are_too_old() {
+        let guards = RateGuards {
+            max_age: Some(60),
+            max_deviation: None,
+        };
+        let start = Instant::now();
+        let mut last_updated = HashMap::new();
+        let guarded = guards.apply(
+            &mut last_updated,
+            Some(rates(&[("ABC", 1.0), ("XYZ", 2.0)])),
+            &HashMap::new(),
+            start,
+        );
+        assert!(!guarded.is_degraded());
+
+        // The provider fails, so the last known good rates are kept
+        let guarded = guards.apply(
+            &mut last_updated,
+            None,
+            &guarded.rates,
+            start + Duration::from_secs(30),
+        );
+        assert_eq!(guarded.rates, rates(&[("ABC", 1.0), ("XYZ", 2.0)]));
+        assert_eq!(guarded.fallback, 2);
+
+        // The provider stops returning XYZ, whose rate eventually becomes too old
+        let guarded = guards.apply(
+            &mut last_updated,
+            Some(rates(&[("ABC", 1.5)])),
+            &guarded.rates,
+            start + Duration::from_secs(61),
+        );
+        assert_eq!(guarded.rates, rates(&[("ABC", 1.5)]));
+        assert_eq!(guarded.stale, vec!["XYZ".to_string()]);
+    }
+
+    #[test]
+    fn removes_missing_rates_without_max_age() {
+        let guarded = RateGuards::default().apply(
+            &mut HashMap::new(),
+            Some(rates(&[("ABC", 1.0)])),
+            &rates(&[("ABC", 2.0), ("XYZ", 2.0)]),
+            Instant::now(),
+        );
+        assert_eq!(guarded.rates, rates(&[("ABC", 1.0)]));
+        assert!(!guarded.is_degraded());
+    }
+}
@@ -0,0 +1,237 @@
+use super::ExchangeRateProvider;
+use async_trait::async_trait;
+use futures::TryFutureExt;
+use reqwest::Client;
+use secrecy::{ExposeSecret, SecretString};
+use serde::Deserialize;
+use serde_json::Value;
+use std::{collections::HashMap, str::FromStr};
+use tracing::{error, warn};
+
+/// Configuration of an [`HttpProvider`](./struct.HttpProvider.html).
+///
+/// The rates are located in the JSON response using [JSONPath] expressions:
+/// `records` selects the values which contain the rates. If `asset_code` and `rate`
+/// are set, each of those values is a record and the two expressions (relative to the
+/// record) select its asset code and rate. Otherwise, each of those values must be an
+/// object mapping asset codes to rates. Rates may be numbers or numeric strings.
+///
+/// [JSONPath]: https://goessner.net/articles/JsonPath/
+#[derive(Debug, Clone, Deserialize)]
+pub struct HttpProviderConfig {
+    /// URL to GET the rates from
+    pub url: String,
+    /// Headers sent with the request, for example to authenticate with an API key
+    #[serde(default)]
+    pub headers: HashMap<String, SecretString>,
+    /// JSONPath expression selecting the records in the response
+    #[serde(default = "HttpProviderConfig::default_records")]
+    pub records: String,
+    /// JSONPath expression selecting the asset code in each record
+    #[serde(default)]
+    pub asset_code: Option<String>,
+    /// JSONPath expression selecting the rate in each record
+    #[serde(default)]
+    pub rate: Option<String>,
+    /// Set this if the API returns the number of units of each asset which one USD buys,
+    /// rather than the price of one unit of the asset in USD
+    #[serde(default)]
+    pub inverse: bool,
+}
+
+impl HttpProviderConfig {
+    fn default_records() -> String {
+        "$".to_string()
+    }
+}
+
+/// Polls an arbitrary HTTP API which returns JSON
+#[derive(Debug, Clone)]
+pub struct HttpProvider {
+    config: HttpProviderConfig,
+}
+
+impl HttpProvider {
+    pub fn new(config: HttpProviderConfig) -> Self {
+        HttpProvider { config }
+    }
+
+    fn extract_rates(&self, response: &Value) -> Result<HashMap<String, f64>, ()> {
+        let records = jsonpath_lib::select(response, &self.config.records).map_err(|err| {
+            error!(
+                "Error selecting the records in the response from {}: {:?}",
+                self.config.url, err
+            );
+        })?;
+
+        let mut rates = HashMap::new();
+        for record in records {
+            match (&self.config.asset_code, &self.config.rate) {
+                (Some(asset_code_path), Some(rate_path)) => {
+                    let asset_code =
+                        select_first(record, asset_code_path).and_then(|code| match code {
+                            Value::String(code) => Some(code.to_uppercase()),
+                            _ => None,
+                        });
+                    let rate = select_first(record, rate_path).and_then(to_f64);
+                    match (asset_code, rate) {
+                        (Some(asset_code), Some(rate)) => {
+                            rates.insert(asset_code, rate);
+                        }
+                        _ => warn!(
+                            "Unable to find the asset code and rate in record from {}: {}",
+                            self.config.url, record
+                        ),
+                    }
+                }
+                (None, None) => match record {
+                    Value::Object(map) => {
+                        for (asset_code, rate) in map {
+                            match to_f64(rate) {
+                                Some(rate) => {
+                                    rates.insert(asset_code.to_uppercase(), rate);
+                                }
+                                None => {
+                                    warn!("Unable to parse {} rate as an f64: {}", asset_code, rate)
+                                }
+                            }
+                        }
+                    }
+                    _ => warn!(
+                        "Expected an object mapping asset codes to rates from {}, got: {}",
+                        self.config.url, record
+                    ),
+                },
+                _ => {
+                    error!("Both or neither of asset_code and rate must be configured for the HTTP exchange rate provider");
+                    return Err(());
+                }
+            }
+        }
+
+        if self.config.inverse {
+            rates = rates
+                .into_iter()
+                .filter(|(_, rate)| *rate != 0.0)
+                .map(|(asset_code, rate)| (asset_code, 1.0 / rate))
+                .collect();
+        }
+        Ok(rates)
+    }
+}
+
+fn select_first<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    jsonpath_lib::select(value, path)
+        .ok()
+        .and_then(|values| values.into_iter().next())
+}
+
+fn to_f64(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(number) => number.as_f64(),
+        Value::String(string) => f64::from_str(string).ok(),
+        _ => None,
+    }
+}
+
+#[async_trait]
+impl ExchangeRateProvider for HttpProvider {
+    async fn fetch_rates(&self, client: &Client) -> Result<HashMap<String, f64>, ()> {
+        let mut request = client.get(&self.config.url);
+        for (name, value) in &self.config.headers {
+            request = request.header(name.as_str(), value.expose_secret().as_str());
+        }
+
+        let res = request
+            .send()
+            .map_err(|err| {
+                error!(
+                    "Error fetching exchange rates from {}: {:?}",
+                    self.config.url, err
+                );
+            })
+            .await?;
+
+        let res = res.error_for_status().map_err(|err| {
+            error!(
+                "HTTP error getting exchange rates from {}: {:?}",
+                self.config.url, err
+            );
+        })?;
+
+        let response: Value = res
+            .json()
+            .map_err(|err| {
+                error!(
+                    "Error getting exchange rate response body from {}, invalid JSON: {:?}",
+                    self.config.url, err
+                );
+            })
+            .await?;
+
+        self.extract_rates(&response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn provider(records: &str, asset_code: Option<&str>, rate: Option<&str>) -> HttpProvider {
+        HttpProvider::new(HttpProviderConfig {
+            url: "http://example.com/rates".to_string(),
+            headers: HashMap::new(),
+            records: records.to_string(),
+            asset_code: asset_code.map(String::from),
+            rate: rate.map(String::from),
+            inverse: false,
+        })
+    }
+
+    #[test]
+    fn extracts_rates_from_records() {
+        let response = json!({
+            "data": [
+                { "symbol": "btc", "price": { "usd": "8000.5" } },
+                { "symbol": "ETH", "price": { "usd": 200 } },
+                { "symbol": "XRP" }
+            ]
+        });
+        let rates = provider("$.data[*]", Some("$.symbol"), Some("$.price.usd"))
+            .extract_rates(&response)
+            .unwrap();
+        assert_eq!(rates.len(), 2);
+        assert_eq!(rates["BTC"], 8000.5);
+        assert_eq!(rates["ETH"], 200.0);
+    }
+
+    #[test]
+    fn extracts_rates_from_object() {
+        let response = json!({ "rates": { "eur": 1.1, "JPY": "0.009" } });
+        let rates = provider("$.rates", None, None)
+            .extract_rates(&response)
+            .unwrap();
+        assert_eq!(rates.len(), 2);
+        assert_eq!(rates["EUR"], 1.1);
+        assert_eq!(rates["JPY"], 0.009);
+    }
+
+    #[test]
+    fn inverts_rates() {
+        let response = json!({ "EUR": 0.5, "ABC": 0 });
+        let mut provider = provider("$", None, None);
+        provider.config.inverse = true;
+        let rates = provider.extract_rates(&response).unwrap();
+        assert_eq!(rates.len(), 1);
+        assert_eq!(rates["EUR"], 2.0);
+    }
+
+    #[test]
+    fn requires_both_asset_code_and_rate() {
+        let response = json!([{ "symbol": "BTC", "price": 1 }]);
+        assert!(provider("$[*]", Some("$.symbol"), None)
+            .extract_rates(&response)
+            .is_err());
+    }
+}
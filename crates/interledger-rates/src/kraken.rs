@@ -0,0 +1,128 @@
+use super::ExchangeRateProvider;
+use async_trait::async_trait;
+use futures::TryFutureExt;
+use once_cell::sync::Lazy;
+use reqwest::{Client, Url};
+use serde::{de::DeserializeOwned, Deserialize};
+use std::{collections::HashMap, str::FromStr};
+use tracing::{error, warn};
+
+static KRAKEN_ASSET_PAIRS_URL: Lazy<Url> =
+    Lazy::new(|| Url::parse("https://api.kraken.com/0/public/AssetPairs").unwrap());
+static KRAKEN_TICKER_URL: Lazy<Url> =
+    Lazy::new(|| Url::parse("https://api.kraken.com/0/public/Ticker").unwrap());
+
+#[derive(Deserialize, Debug)]
+struct KrakenResponse<T> {
+    #[serde(default)]
+    error: Vec<String>,
+    result: Option<T>,
+}
+
+#[derive(Deserialize, Debug)]
+struct AssetPair {
+    /// For example "XBT/USD". Missing for the pairs which are not traded
+    wsname: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Ticker {
+    /// Last trade closed, as [price, lot volume]
+    c: Vec<String>,
+}
+
+/// Polls the [Kraken](https://www.kraken.com/features/api) exchange
+/// for the last traded prices of all assets traded against USD
+#[derive(Debug, Clone)]
+pub struct Kraken;
+
+#[async_trait]
+impl ExchangeRateProvider for Kraken {
+    async fn fetch_rates(&self, client: &Client) -> Result<HashMap<String, f64>, ()> {
+        let pairs: HashMap<String, AssetPair> =
+            query_kraken_endpoint(client, KRAKEN_ASSET_PAIRS_URL.clone()).await?;
+
+        // Maps the names of the pairs quoted in USD to their base asset
+        let usd_pairs: HashMap<String, String> = pairs
+            .into_iter()
+            .filter_map(|(name, pair)| {
+                let wsname = pair.wsname?;
+                let mut assets = wsname.split('/');
+                match (assets.next(), assets.next()) {
+                    (Some(base), Some("USD")) => Some((name, normalize_asset_code(base))),
+                    _ => None,
+                }
+            })
+            .collect();
+        if usd_pairs.is_empty() {
+            error!("Kraken did not return any asset pairs quoted in USD");
+            return Err(());
+        }
+
+        let mut url = KRAKEN_TICKER_URL.clone();
+        url.query_pairs_mut().append_pair(
+            "pair",
+            &usd_pairs.keys().cloned().collect::<Vec<_>>().join(","),
+        );
+        let tickers: HashMap<String, Ticker> = query_kraken_endpoint(client, url).await?;
+
+        Ok(tickers
+            .into_iter()
+            .filter_map(|(name, ticker)| {
+                let asset_code = usd_pairs.get(&name)?;
+                let price = ticker.c.first()?;
+                match f64::from_str(price) {
+                    Ok(rate) => Some((asset_code.clone(), rate)),
+                    Err(err) => {
+                        warn!(
+                            "Unable to parse {} rate as an f64: {} {:?}",
+                            asset_code, price, err
+                        );
+                        None
+                    }
+                }
+            })
+            .collect())
+    }
+}
+
+/// Kraken uses the ISO 4217-A3 codes XBT and XDG for Bitcoin and Dogecoin
+fn normalize_asset_code(code: &str) -> String {
+    match code {
+        "XBT" => "BTC".to_string(),
+        "XDG" => "DOGE".to_string(),
+        other => other.to_uppercase(),
+    }
+}
+
+async fn query_kraken_endpoint<T: DeserializeOwned>(client: &Client, url: Url) -> Result<T, ()> {
+    let res = client
+        .get(url)
+        .send()
+        .map_err(|err| {
+            error!("Error fetching exchange rates from Kraken: {:?}", err);
+        })
+        .await?;
+
+    let res = res.error_for_status().map_err(|err| {
+        error!("HTTP error getting exchange rates from Kraken: {:?}", err);
+    })?;
+
+    let res: KrakenResponse<T> = res
+        .json()
+        .map_err(|err| {
+            error!(
+                "Error getting exchange rate response body from Kraken, incorrect type: {:?}",
+                err
+            );
+        })
+        .await?;
+
+    match res.result {
+        Some(result) if res.error.is_empty() => Ok(result),
+        _ => {
+            error!("Error getting exchange rates from Kraken: {:?}", res.error);
+            Err(())
+        }
+    }
+}
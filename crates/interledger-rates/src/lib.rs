@@ -1,17 +1,41 @@
+use async_trait::async_trait;
 use futures::TryFutureExt;
 use interledger_errors::ExchangeRateStoreError;
+use metrics::{labels, recorder, Key};
 use reqwest::Client;
 use secrecy::SecretString;
 use serde::Deserialize;
 use std::collections::HashMap;
-use std::sync::atomic::{AtomicU32, Ordering};
-use std::sync::Arc;
-use std::time::Duration;
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{self, Duration};
+use tokio::{sync::watch, time::Instant};
 use tracing::{debug, error, trace, warn};
 
 mod cryptocompare;
+pub use cryptocompare::CryptoCompare;
 
 mod coincap;
+pub use coincap::CoinCap;
+
+mod coingecko;
+pub use coingecko::CoinGecko;
+
+mod kraken;
+pub use kraken::Kraken;
+
+mod bitstamp;
+pub use bitstamp::Bitstamp;
+
+mod http;
+pub use http::{HttpProvider, HttpProviderConfig};
+
+mod median;
+pub use median::MedianProvider;
+
+mod guards;
+pub use guards::RateGuards;
 
 pub trait ExchangeRateStore: Clone {
     // TODO we may want to make this async if/when we use pubsub to broadcast
@@ -30,9 +54,36 @@ pub trait ExchangeRateStore: Clone {
     fn get_all_exchange_rates(&self) -> Result<HashMap<String, f64>, ExchangeRateStoreError>;
 }
 
+/// Store of the rates which operators pinned, which take precedence over the rates polled
+/// from the provider or set with the API until they are unpinned. The store applies the
+/// pins when the rates are read, so that the rates set last apply again once unpinned.
+/// Pinning a rate lets operators correct the rate of an asset which the provider misprices
+/// (for example, in a thin market) right away.
+#[async_trait]
+pub trait PinnedRateStore {
+    /// Gets the pinned rates, keyed by asset code
+    async fn get_pinned_rates(&self) -> Result<HashMap<String, f64>, ExchangeRateStoreError>;
+
+    /// Pins the rate of the asset, replacing its previous pin if it had one
+    async fn pin_rate(&self, asset_code: &str, rate: f64) -> Result<(), ExchangeRateStoreError>;
+
+    /// Unpins the rate of the asset, and returns the rate it was pinned to, if it was
+    async fn unpin_rate(&self, asset_code: &str) -> Result<Option<f64>, ExchangeRateStoreError>;
+}
+
+/// A source of exchange rates, polled by the [`ExchangeRateFetcher`](./struct.ExchangeRateFetcher.html).
+///
+/// Implement this trait to poll a source which is not supported out of the box.
+#[async_trait]
+pub trait ExchangeRateProvider: Debug + Send + Sync {
+    /// Fetches the current rates, as the price of one unit of each asset in USD,
+    /// keyed by the (uppercase) asset code. Errors are logged by the provider.
+    async fn fetch_rates(&self, client: &Client) -> Result<HashMap<String, f64>, ()>;
+}
+
 /// This determines which external API service to poll for exchange rates.
 #[derive(Debug, Clone, Deserialize)]
-pub enum ExchangeRateProvider {
+pub enum ExchangeRateProviderConfig {
     /// Use the [CoinCap] API.
     ///
     /// Note that when configured with YAML, this MUST be specified as
@@ -51,25 +102,73 @@ pub enum ExchangeRateProvider {
     /// [CryptoCompare]: https://cryptocompare.com
     #[serde(alias = "cryptocompare")]
     CryptoCompare(SecretString),
+    /// Use the [CoinGecko] API.
+    ///
+    /// [CoinGecko]: https://www.coingecko.com/en/api
+    #[serde(alias = "coingecko")]
+    CoinGecko,
+    /// Use the [Kraken] exchange's prices against USD.
+    ///
+    /// [Kraken]: https://www.kraken.com/features/api
+    #[serde(alias = "kraken")]
+    Kraken,
+    /// Use the [Bitstamp] exchange's prices against USD.
+    ///
+    /// [Bitstamp]: https://www.bitstamp.net/api/
+    #[serde(alias = "bitstamp")]
+    Bitstamp,
+    /// Use an arbitrary HTTP API which returns JSON, with the rates
+    /// located in the response via JSONPath expressions.
+    #[serde(alias = "http")]
+    Http(HttpProviderConfig),
+    /// Poll all of the listed providers and use the median of the rates they return
+    /// for each asset. Assets which only some of the providers return are included,
+    /// as are the rates of the providers which responded if others failed.
+    #[serde(alias = "median")]
+    Median(Vec<ExchangeRateProviderConfig>),
+}
+
+impl ExchangeRateProviderConfig {
+    /// Creates the configured provider
+    pub fn build(&self) -> Arc<dyn ExchangeRateProvider> {
+        match self {
+            ExchangeRateProviderConfig::CoinCap => Arc::new(CoinCap),
+            ExchangeRateProviderConfig::CryptoCompare(api_key) => {
+                Arc::new(CryptoCompare::new(api_key.clone()))
+            }
+            ExchangeRateProviderConfig::CoinGecko => Arc::new(CoinGecko),
+            ExchangeRateProviderConfig::Kraken => Arc::new(Kraken),
+            ExchangeRateProviderConfig::Bitstamp => Arc::new(Bitstamp),
+            ExchangeRateProviderConfig::Http(config) => Arc::new(HttpProvider::new(config.clone())),
+            ExchangeRateProviderConfig::Median(providers) => Arc::new(MedianProvider::new(
+                providers.iter().map(|provider| provider.build()).collect(),
+            )),
+        }
+    }
 }
 
 /// Poll exchange rate providers for the current exchange rates
 #[derive(Clone)]
 pub struct ExchangeRateFetcher<S> {
-    provider: ExchangeRateProvider,
+    provider: Arc<dyn ExchangeRateProvider>,
     consecutive_failed_polls: Arc<AtomicU32>,
     failed_polls_before_invalidation: u32,
     store: S,
     client: Client,
+    /// The provider is only polled while this is set
+    polling: Arc<AtomicBool>,
+    guards: RateGuards,
+    /// When the rate of each asset was last accepted from the provider
+    last_updated: Arc<Mutex<HashMap<String, time::Instant>>>,
 }
 
 impl<S> ExchangeRateFetcher<S>
 where
-    S: ExchangeRateStore + Send + Sync + 'static,
+    S: ExchangeRateStore + PinnedRateStore + Send + Sync + 'static,
 {
     /// Simple constructor
     pub fn new(
-        provider: ExchangeRateProvider,
+        provider: Arc<dyn ExchangeRateProvider>,
         failed_polls_before_invalidation: u32,
         store: S,
     ) -> Self {
@@ -79,9 +178,26 @@ where
             failed_polls_before_invalidation,
             store,
             client: Client::new(),
+            polling: Arc::new(AtomicBool::new(true)),
+            guards: RateGuards::default(),
+            last_updated: Arc::new(Mutex::new(HashMap::new())),
         }
     }
 
+    /// Sets the sanity checks applied to the polled rates
+    pub fn guards(mut self, guards: RateGuards) -> Self {
+        self.guards = guards;
+        self
+    }
+
+    /// Sets a flag which controls whether the provider is polled on the interval. When multiple
+    /// nodes share the same store, this lets only one of them poll, since the store shares the
+    /// rates with the others.
+    pub fn polling(mut self, polling: Arc<AtomicBool>) -> Self {
+        self.polling = polling;
+        self
+    }
+
     /// Spawns a future which calls [`self.update_rates()`](./struct.ExchangeRateFetcher.html#method.update_rates) every `interval`
     pub fn spawn_interval(self, interval: Duration) {
         debug!(
@@ -92,60 +208,172 @@ where
             let mut interval = tokio::time::interval(interval);
             loop {
                 interval.tick().await;
-                // Ignore errors so that they don't cause the Interval to stop
-                let _ = self.update_rates().await;
+                if self.polling.load(Ordering::Relaxed) {
+                    // Ignore errors so that they don't cause the Interval to stop
+                    let _ = self.update_rates().await;
+                }
             }
         };
         tokio::spawn(interval);
     }
 
-    /// Calls the proper exchange rate provider
-    async fn fetch_rates(&self) -> Result<HashMap<String, f64>, ()> {
-        match self.provider {
-            ExchangeRateProvider::CryptoCompare(ref api_key) => {
-                cryptocompare::query_cryptocompare(&self.client, api_key).await
+    /// Like [`spawn_interval`](#method.spawn_interval), but the polling interval is taken from
+    /// `intervals` and is replaced (and the next poll rescheduled) whenever a new value is
+    /// sent on that channel
+    pub fn spawn_interval_with_updates(self, mut intervals: watch::Receiver<Duration>) {
+        debug!(
+            "Starting interval to poll exchange rate provider: {:?} for rates",
+            self.provider
+        );
+        let interval = async move {
+            let mut interval = tokio::time::interval(*intervals.borrow());
+            let mut intervals_open = true;
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {
+                        if self.polling.load(Ordering::Relaxed) {
+                            // Ignore errors so that they don't cause the Interval to stop
+                            let _ = self.update_rates().await;
+                        }
+                    }
+                    update = intervals.recv(), if intervals_open => match update {
+                        Some(period) => {
+                            debug!("Changing exchange rate poll interval to {:?}", period);
+                            interval = tokio::time::interval_at(Instant::now() + period, period);
+                        }
+                        // The sender was dropped so the interval will not change anymore
+                        None => intervals_open = false,
+                    },
+                }
             }
-            ExchangeRateProvider::CoinCap => coincap::query_coincap(&self.client).await,
-        }
+        };
+        tokio::spawn(interval);
     }
 
-    /// Gets the exchange rates and proceeds to update the store with the newly polled values
-    async fn update_rates(&self) -> Result<(), ()> {
+    /// Calls the exchange rate provider
+    async fn fetch_rates(&self) -> Result<HashMap<String, f64>, ()> {
+        self.provider.fetch_rates(&self.client).await
+    }
+
+    /// Gets the exchange rates and proceeds to update the store with the newly polled values.
+    /// The store overrides them with the pinned rates, whose polled values are kept out of
+    /// the guards so that they apply as they are once unpinned.
+    pub async fn update_rates(&self) -> Result<(), ()> {
+        // The rates are not updated if the pins cannot be loaded, so that the polled rates
+        // never replace the pinned ones
+        let pinned = self
+            .store
+            .get_pinned_rates()
+            .await
+            .map_err(|err| error!("Error loading pinned exchange rates: {}", err))?;
         let consecutive_failed_polls = self.consecutive_failed_polls.clone();
-        let consecutive_failed_polls_zeroer = consecutive_failed_polls.clone();
         let failed_polls_before_invalidation = self.failed_polls_before_invalidation;
         let store = self.store.clone();
-        let store_clone = self.store.clone();
-        let provider = self.provider.clone();
         #[allow(clippy::cognitive_complexity)]
-        let mut rates = self.fetch_rates()
+        let rates = self.fetch_rates()
             .map_err(move |_| {
                 // Note that a race between the read on this line and the check on the line after
                 // is quite unlikely as long as the interval between polls is reasonable.
                 let failed_polls = consecutive_failed_polls.fetch_add(1, Ordering::Relaxed);
                 if failed_polls < failed_polls_before_invalidation {
                     warn!("Failed to update exchange rates (previous consecutive failed attempts: {})", failed_polls);
+                    false
                 } else {
                     error!("Failed to update exchange rates (previous consecutive failed attempts: {}), removing old rates for safety", failed_polls);
-                    // Clear out all of the old rates
+                    // Clear out all of the old rates. The pinned ones still apply.
                     if store.set_exchange_rates(HashMap::new()).is_err() {
                         error!("Failed to clear exchange rates cache after exchange rates server became unresponsive; panicking");
                         panic!("Failed to clear exchange rates cache after exchange rates server became unresponsive");
                     }
+                    true
                 }
-            }).await?;
+            }).await;
+
+        let mut rates = match rates {
+            Ok(rates) => rates,
+            Err(invalidated) => {
+                record_degraded(true);
+                // The last known good rates are kept while polling fails, unless they
+                // were invalidated or are too old
+                if !invalidated && self.guards.max_age.is_some() {
+                    let guarded = self.apply_guards(None, &pinned);
+                    if !guarded.stale.is_empty() {
+                        let _ = self.set_rates(guarded.rates, HashMap::new());
+                    }
+                }
+                return Err(());
+            }
+        };
 
         trace!("Fetched exchange rates: {:?}", rates);
+        let polled_pins: HashMap<String, f64> = pinned
+            .keys()
+            .filter_map(|asset_code| rates.remove_entry(asset_code))
+            .collect();
         let num_rates = rates.len();
+        let guarded = self.apply_guards(Some(rates), &pinned);
+        record_degraded(guarded.is_degraded());
+        self.set_rates(guarded.rates, polled_pins)?;
+        // Reset our invalidation counter
+        self.consecutive_failed_polls.store(0, Ordering::Relaxed);
+        debug!(
+            "Updated {} exchange rates from {:?}",
+            num_rates - guarded.rejected.len(),
+            self.provider
+        );
+        Ok(())
+    }
+
+    /// Checks the polled rates against the ones currently in the store, leaving out the
+    /// pinned rates
+    fn apply_guards(
+        &self,
+        rates: Option<HashMap<String, f64>>,
+        pinned: &HashMap<String, f64>,
+    ) -> guards::GuardedRates {
+        let mut previous = self.store.get_all_exchange_rates().unwrap_or_default();
+        previous.retain(|asset_code, _| !pinned.contains_key(asset_code));
+        let guarded = self.guards.apply(
+            &mut self.last_updated.lock().unwrap(),
+            rates,
+            &previous,
+            time::Instant::now(),
+        );
+        for asset_code in &guarded.rejected {
+            record_guard_event("exchange_rates.rejected", asset_code);
+        }
+        for asset_code in &guarded.stale {
+            record_guard_event("exchange_rates.stale", asset_code);
+        }
+        guarded
+    }
+
+    /// Sets the guarded rates, with the polled rates of the pinned assets
+    fn set_rates(
+        &self,
+        mut rates: HashMap<String, f64>,
+        polled_pins: HashMap<String, f64>,
+    ) -> Result<(), ()> {
         rates.insert("USD".to_string(), 1.0);
-        if store_clone.set_exchange_rates(rates).is_ok() {
-            // Reset our invalidation counter
-            consecutive_failed_polls_zeroer.store(0, Ordering::Relaxed);
-            debug!("Updated {} exchange rates from {:?}", num_rates, provider);
-            Ok(())
-        } else {
+        rates.extend(polled_pins);
+        self.store.set_exchange_rates(rates).map_err(|_| {
             error!("Error setting exchange rates in store");
-            Err(())
-        }
+        })
     }
 }
+
+fn record_guard_event(name: &'static str, asset_code: &str) {
+    recorder().increment_counter(
+        Key::from_name_and_labels(name, labels!("asset_code" => asset_code.to_string())),
+        1,
+    );
+}
+
+/// Sets the `exchange_rates.degraded` gauge, which is 1 while some of the
+/// rates are not the ones most recently returned by the provider
+fn record_degraded(degraded: bool) {
+    recorder().update_gauge(
+        Key::from_name("exchange_rates.degraded"),
+        if degraded { 1 } else { 0 },
+    );
+}
@@ -0,0 +1,103 @@
+use super::ExchangeRateProvider;
+use async_trait::async_trait;
+use futures::future::join_all;
+use reqwest::Client;
+use std::{collections::HashMap, sync::Arc};
+use tracing::error;
+
+/// Polls multiple providers and aggregates the rates they return by taking the
+/// median for each asset. This limits the impact of a single provider returning
+/// an incorrect rate. It only fails if all of the providers fail.
+#[derive(Debug, Clone)]
+pub struct MedianProvider {
+    providers: Vec<Arc<dyn ExchangeRateProvider>>,
+}
+
+impl MedianProvider {
+    pub fn new(providers: Vec<Arc<dyn ExchangeRateProvider>>) -> Self {
+        MedianProvider { providers }
+    }
+}
+
+#[async_trait]
+impl ExchangeRateProvider for MedianProvider {
+    async fn fetch_rates(&self, client: &Client) -> Result<HashMap<String, f64>, ()> {
+        let results = join_all(
+            self.providers
+                .iter()
+                .map(|provider| provider.fetch_rates(client)),
+        )
+        .await;
+
+        let responses: Vec<HashMap<String, f64>> =
+            results.into_iter().filter_map(Result::ok).collect();
+        if responses.is_empty() {
+            error!(
+                "All {} exchange rate providers failed",
+                self.providers.len()
+            );
+            return Err(());
+        }
+        Ok(median_rates(responses))
+    }
+}
+
+fn median_rates(responses: Vec<HashMap<String, f64>>) -> HashMap<String, f64> {
+    let mut all_rates: HashMap<String, Vec<f64>> = HashMap::new();
+    for response in responses {
+        for (asset_code, rate) in response {
+            if rate.is_finite() {
+                all_rates.entry(asset_code).or_default().push(rate);
+            }
+        }
+    }
+
+    all_rates
+        .into_iter()
+        .map(|(asset_code, mut rates)| {
+            rates.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            let middle = rates.len() / 2;
+            let median = if rates.len() % 2 == 0 {
+                (rates[middle - 1] + rates[middle]) / 2.0
+            } else {
+                rates[middle]
+            };
+            (asset_code, median)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rates(rates: &[(&str, f64)]) -> HashMap<String, f64> {
+        rates
+            .iter()
+            .map(|(asset_code, rate)| (asset_code.to_string(), *rate))
+            .collect()
+    }
+
+    #[test]
+    fn takes_median_of_each_asset() {
+        let median = median_rates(vec![
+            rates(&[("BTC", 8000.0), ("ETH", 200.0), ("XRP", 0.2)]),
+            rates(&[("BTC", 9000.0), ("ETH", 210.0)]),
+            rates(&[("BTC", 100.0)]),
+        ]);
+        assert_eq!(median.len(), 3);
+        assert_eq!(median["BTC"], 8000.0);
+        assert_eq!(median["ETH"], 205.0);
+        assert_eq!(median["XRP"], 0.2);
+    }
+
+    #[test]
+    fn ignores_non_finite_rates() {
+        let median = median_rates(vec![
+            rates(&[("BTC", f64::INFINITY), ("ETH", 200.0)]),
+            rates(&[("BTC", f64::NAN)]),
+        ]);
+        assert_eq!(median.len(), 1);
+        assert_eq!(median["ETH"], 200.0);
+    }
+}
@@ -0,0 +1,157 @@
+//! Benchmark routing Prepare packets through a large routing table.
+
+use async_trait::async_trait;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use interledger_errors::{AccountStoreError, AddressStoreError};
+use interledger_packet::{Address, FulfillBuilder, Prepare, PrepareBuilder};
+use interledger_router::{Router, RouterStore};
+use interledger_service::{
+    outgoing_service_fn, Account, AccountStore, AddressStore, IncomingRequest, IncomingService,
+    Username,
+};
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+use uuid::Uuid;
+
+/// Sizes of the routing tables the lookups are benchmarked with
+static TABLE_SIZES: &[usize] = &[10, 1_000, 100_000];
+
+static ALICE: Lazy<Username> = Lazy::new(|| Username::from_str("alice").unwrap());
+static EXAMPLE_ADDRESS: Lazy<Address> = Lazy::new(|| Address::from_str("example.alice").unwrap());
+
+#[derive(Debug, Clone)]
+struct BenchAccount(Uuid);
+
+impl Account for BenchAccount {
+    fn id(&self) -> Uuid {
+        self.0
+    }
+
+    fn username(&self) -> &Username {
+        &ALICE
+    }
+
+    fn asset_scale(&self) -> u8 {
+        9
+    }
+
+    fn asset_code(&self) -> &str {
+        "XYZ"
+    }
+
+    fn ilp_address(&self) -> &Address {
+        &EXAMPLE_ADDRESS
+    }
+}
+
+#[derive(Clone)]
+struct BenchStore {
+    routes: Arc<HashMap<String, Uuid>>,
+}
+
+impl BenchStore {
+    /// Routes `example.peer<n>` to a different account for each of the `size` peers
+    fn new(size: usize) -> Self {
+        let routes = (0..size)
+            .map(|n| (format!("example.peer{}", n), Uuid::new_v4()))
+            .collect();
+        BenchStore {
+            routes: Arc::new(routes),
+        }
+    }
+}
+
+#[async_trait]
+impl AccountStore for BenchStore {
+    type Account = BenchAccount;
+
+    async fn get_accounts(
+        &self,
+        account_ids: Vec<Uuid>,
+    ) -> Result<Vec<BenchAccount>, AccountStoreError> {
+        Ok(account_ids.into_iter().map(BenchAccount).collect())
+    }
+
+    async fn get_account_id_from_username(
+        &self,
+        _username: &Username,
+    ) -> Result<Uuid, AccountStoreError> {
+        Ok(Uuid::new_v4())
+    }
+}
+
+#[async_trait]
+impl AddressStore for BenchStore {
+    async fn set_ilp_address(&self, _ilp_address: Address) -> Result<(), AddressStoreError> {
+        Ok(())
+    }
+
+    async fn clear_ilp_address(&self) -> Result<(), AddressStoreError> {
+        Ok(())
+    }
+
+    fn get_ilp_address(&self) -> Address {
+        Address::from_str("example.connector").unwrap()
+    }
+}
+
+impl RouterStore for BenchStore {
+    fn routing_table(&self) -> Arc<HashMap<String, Uuid>> {
+        self.routes.clone()
+    }
+}
+
+fn prepare(destination: &str) -> Prepare {
+    PrepareBuilder {
+        destination: Address::from_str(destination).unwrap(),
+        amount: 100,
+        execution_condition: &[1; 32],
+        expires_at: UNIX_EPOCH,
+        data: &[],
+    }
+    .build()
+}
+
+fn benchmark_lookup(c: &mut Criterion, name: &str, destination: &str) {
+    let prepare = prepare(destination);
+    let mut runtime = tokio::runtime::Builder::new()
+        .basic_scheduler()
+        .build()
+        .unwrap();
+    let mut group = c.benchmark_group(name);
+    for size in TABLE_SIZES {
+        let mut router = Router::new(
+            BenchStore::new(*size),
+            outgoing_service_fn(|_| {
+                Ok(FulfillBuilder {
+                    fulfillment: &[0; 32],
+                    data: &[],
+                }
+                .build())
+            }),
+        );
+        group.bench_with_input(BenchmarkId::from_parameter(size), size, |b, _| {
+            b.iter(|| {
+                let request = IncomingRequest {
+                    from: BenchAccount(Uuid::nil()),
+                    prepare: prepare.clone(),
+                };
+                assert!(runtime.block_on(router.handle_request(request)).is_ok());
+            })
+        });
+    }
+    group.finish();
+}
+
+fn benchmark_router(c: &mut Criterion) {
+    // The destination is a key of the routing table
+    benchmark_lookup(c, "Router (exact match)", "example.peer5");
+    // The destination is under one of the prefixes, so the whole table is scanned
+    benchmark_lookup(c, "Router (prefix match)", "example.peer5.alice.stream");
+}
+
+criterion_group!(benches, benchmark_router);
+criterion_main!(benches);
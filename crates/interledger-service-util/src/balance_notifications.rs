@@ -0,0 +1,520 @@
+use crate::balance_service::{Liquidity, LiquidityStore};
+use interledger_errors::BalanceStoreError;
+use interledger_packet::Address;
+use interledger_service::Username;
+use interledger_settlement::core::types::SettlementAccount;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::{broadcast, mpsc::UnboundedReceiver};
+use tracing::{debug, warn};
+use url::Url;
+use uuid::Uuid;
+
+/// Number of the notifications kept for the subscribers which fall behind
+const NOTIFICATION_CAPACITY: usize = 256;
+/// Time after which a balance notification URL which did not answer is given up on, so
+/// that a slow endpoint does not hold the notifications of the other accounts
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+
+fn default_debounce() -> u64 {
+    10_000
+}
+
+/// Configuration of the notifications of the accounts' balances crossing thresholds
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct BalanceNotificationConfig {
+    /// Interval, in milliseconds, at which the balances of the accounts which changed are
+    /// checked against the thresholds, so that the bursts of packets of an account only
+    /// check its balance once. It must be greater than 0. Defaults to 10000ms (10 seconds).
+    #[serde(default = "default_debounce")]
+    pub debounce: u64,
+    /// The thresholds whose crossing is notified
+    #[serde(default)]
+    pub thresholds: Vec<BalanceThreshold>,
+}
+
+/// A threshold of the balances of the accounts
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct BalanceThreshold {
+    #[serde(flatten)]
+    pub condition: BalanceCondition,
+    /// Usernames of the accounts the threshold applies to. Defaults to all accounts.
+    #[serde(default)]
+    pub accounts: Vec<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BalanceCondition {
+    /// What the account owes the node reached the `share` (between 0 and 1) of its
+    /// receivable limit, or what the node owes the account reached the share of its
+    /// payable limit. It is notified again when it falls back below the share.
+    CreditLimit { share: f64 },
+    /// The balance changed by more than `amount` within `window` milliseconds
+    Change { amount: u64, window: u64 },
+}
+
+/// A balance of an account which crossed a threshold. It is POSTed as JSON to the
+/// account's balance notification URL, and sent to the API's WebSocket subscribers.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct BalanceNotification {
+    #[serde(skip)]
+    pub account_id: Uuid,
+    /// The username of the account
+    pub account: String,
+    /// The ILP address of the notifying node
+    pub node: String,
+    pub asset_code: String,
+    pub asset_scale: u8,
+    /// The balance of the account, including its prepaid amount
+    pub balance: i64,
+    #[serde(flatten)]
+    pub event: BalanceEvent,
+    /// When the balance was checked, in milliseconds since the UNIX epoch
+    pub at: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BalanceEvent {
+    /// The balance crossed the `share` of the credit limit, `above` or `below` it
+    CreditLimit {
+        share: f64,
+        /// Which credit limit of the account applies to the balance (`payable` or `receivable`)
+        kind: &'static str,
+        limit: Option<u64>,
+        /// The share of the limit which the balance uses
+        used: f64,
+        crossed: &'static str,
+    },
+    /// The balance changed by `change`, which is more than the `amount`, within the window
+    Change {
+        amount: u64,
+        window: u64,
+        change: i64,
+    },
+}
+
+/// An account whose balance was changed by a packet, which the balance service
+/// sends to the notifier
+#[derive(Clone, Debug, PartialEq)]
+pub struct BalanceChange {
+    pub account_id: Uuid,
+    pub username: Username,
+    pub asset_code: String,
+    pub asset_scale: u8,
+    /// Where the account's balance notifications are POSTed
+    pub notification_url: Option<Url>,
+}
+
+impl BalanceChange {
+    pub(crate) fn new<A: SettlementAccount>(account: &A) -> Self {
+        BalanceChange {
+            account_id: account.id(),
+            username: account.username().clone(),
+            asset_code: account.asset_code().to_string(),
+            asset_scale: account.asset_scale(),
+            notification_url: account.balance_notification_url(),
+        }
+    }
+}
+
+/// The balance notifications of the node, which the API's WebSocket clients subscribe to
+#[derive(Clone)]
+pub struct BalanceNotifications(broadcast::Sender<BalanceNotification>);
+
+impl Default for BalanceNotifications {
+    fn default() -> Self {
+        BalanceNotifications(broadcast::channel(NOTIFICATION_CAPACITY).0)
+    }
+}
+
+impl BalanceNotifications {
+    /// Returns a receiver of the notifications sent from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<BalanceNotification> {
+        self.0.subscribe()
+    }
+
+    fn publish(&self, notification: BalanceNotification) {
+        // There may be no subscribers
+        let _ = self.0.send(notification);
+    }
+}
+
+/// What is known of the balance of an account since it was first checked
+#[derive(Default)]
+struct AccountBalances {
+    /// The balances of the checks within the longest window, with their times
+    samples: VecDeque<(u64, i64)>,
+    /// The credit limit thresholds whose share the balance is above
+    above: HashSet<usize>,
+    /// When the change thresholds were last notified
+    notified: HashMap<usize, u64>,
+}
+
+/// Checks the balances of the accounts changed by the packets against the thresholds, at
+/// most once per debounce interval, and notifies the ones which crossed a threshold
+pub struct BalanceNotifier<S> {
+    node: Address,
+    store: S,
+    config: BalanceNotificationConfig,
+    client: Client,
+    notifications: BalanceNotifications,
+    changed: HashMap<Uuid, BalanceChange>,
+    accounts: HashMap<Uuid, AccountBalances>,
+}
+
+impl<S> BalanceNotifier<S>
+where
+    S: LiquidityStore + Send + Sync + 'static,
+{
+    pub fn new(
+        node: Address,
+        store: S,
+        config: BalanceNotificationConfig,
+        notifications: BalanceNotifications,
+    ) -> Self {
+        BalanceNotifier {
+            node,
+            store,
+            config,
+            client: Client::builder().timeout(WEBHOOK_TIMEOUT).build().unwrap(),
+            notifications,
+            changed: HashMap::new(),
+            accounts: HashMap::new(),
+        }
+    }
+
+    /// Returns the notifications of the thresholds which the balance of the account crossed
+    fn evaluate(
+        &mut self,
+        change: &BalanceChange,
+        liquidity: Liquidity,
+        now: u64,
+    ) -> Vec<BalanceNotification> {
+        let balance = liquidity.payable as i64 - liquidity.receivable as i64;
+        let longest_window = self
+            .config
+            .thresholds
+            .iter()
+            .filter_map(|threshold| match threshold.condition {
+                BalanceCondition::Change { window, .. } => Some(window),
+                _ => None,
+            })
+            .max()
+            .unwrap_or(0);
+        let account = self.accounts.entry(change.account_id).or_default();
+        account.samples.push_back((now, balance));
+        while let Some(&(time, _)) = account.samples.front() {
+            if time >= now.saturating_sub(longest_window) {
+                break;
+            }
+            account.samples.pop_front();
+        }
+
+        let mut events = Vec::new();
+        for (index, threshold) in self.config.thresholds.iter().enumerate() {
+            if !threshold.accounts.is_empty()
+                && !threshold
+                    .accounts
+                    .iter()
+                    .any(|username| username.as_str() == change.username.as_ref())
+            {
+                continue;
+            }
+            match threshold.condition {
+                BalanceCondition::CreditLimit { share } => {
+                    let (kind, limit, owed) = if liquidity.receivable > 0 {
+                        (
+                            "receivable",
+                            liquidity.limits.receivable_limit,
+                            liquidity.receivable,
+                        )
+                    } else {
+                        ("payable", liquidity.limits.payable_limit, liquidity.payable)
+                    };
+                    let used = match limit {
+                        Some(limit) if limit > 0 => owed as f64 / limit as f64,
+                        _ => 0.0,
+                    };
+                    let above = limit.is_some() && used >= share;
+                    if above != account.above.contains(&index) {
+                        if above {
+                            account.above.insert(index);
+                        } else {
+                            account.above.remove(&index);
+                        }
+                        events.push(BalanceEvent::CreditLimit {
+                            share,
+                            kind,
+                            limit,
+                            used,
+                            crossed: if above { "above" } else { "below" },
+                        });
+                    }
+                }
+                BalanceCondition::Change { amount, window } => {
+                    // The change is measured from the last notification of the threshold,
+                    // so that it is only notified once
+                    let since = now
+                        .saturating_sub(window)
+                        .max(account.notified.get(&index).copied().unwrap_or(0));
+                    let earlier = account
+                        .samples
+                        .iter()
+                        .find(|(time, _)| *time >= since)
+                        .map(|(_, balance)| *balance)
+                        .unwrap_or(balance);
+                    let difference = balance - earlier;
+                    if difference.unsigned_abs() > amount {
+                        account.notified.insert(index, now);
+                        events.push(BalanceEvent::Change {
+                            amount,
+                            window,
+                            change: difference,
+                        });
+                    }
+                }
+            }
+        }
+
+        events
+            .into_iter()
+            .map(|event| BalanceNotification {
+                account_id: change.account_id,
+                account: change.username.to_string(),
+                node: self.node.to_string(),
+                asset_code: change.asset_code.clone(),
+                asset_scale: change.asset_scale,
+                balance,
+                event,
+                at: now,
+            })
+            .collect()
+    }
+
+    /// Checks the balances of the accounts which changed since the previous check
+    async fn check(&mut self) {
+        let changed: Vec<BalanceChange> = self.changed.drain().map(|(_, change)| change).collect();
+        for change in changed {
+            let liquidity = match self.store.get_liquidity(change.account_id).await {
+                Ok(liquidity) => liquidity,
+                Err(BalanceStoreError::AccountNotFound(_)) => {
+                    self.accounts.remove(&change.account_id);
+                    continue;
+                }
+                Err(err) => {
+                    warn!(
+                        "Error getting the balance of account {} to notify: {}",
+                        change.username, err
+                    );
+                    continue;
+                }
+            };
+            for notification in self.evaluate(&change, liquidity, now()) {
+                if let Some(ref url) = change.notification_url {
+                    tokio::spawn(notify(
+                        self.client.clone(),
+                        url.clone(),
+                        notification.clone(),
+                    ));
+                }
+                self.notifications.publish(notification);
+            }
+        }
+    }
+
+    /// Notifies the balances which crossed the thresholds, checking the accounts which are
+    /// received from the channel until it is closed
+    pub fn spawn(mut self, mut changes: UnboundedReceiver<BalanceChange>) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_millis(self.config.debounce));
+            loop {
+                tokio::select! {
+                    change = changes.recv() => match change {
+                        Some(change) => {
+                            self.changed.insert(change.account_id, change);
+                        }
+                        None => break,
+                    },
+                    _ = interval.tick() => self.check().await,
+                }
+            }
+        });
+    }
+}
+
+async fn notify(client: Client, url: Url, notification: BalanceNotification) {
+    debug!(
+        "Notifying the balance {} of account {}",
+        notification.balance, notification.account
+    );
+    let result = client
+        .post(url.as_str())
+        .json(&notification)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status());
+    if let Err(err) = result {
+        warn!(
+            "Error sending the balance notification of account {} to {}: {}",
+            notification.account, url, err
+        );
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::balance_service::CreditLimits;
+    use async_trait::async_trait;
+    use std::str::FromStr;
+
+    #[derive(Clone)]
+    struct TestStore;
+
+    #[async_trait]
+    impl LiquidityStore for TestStore {
+        async fn get_liquidity(&self, _: Uuid) -> Result<Liquidity, BalanceStoreError> {
+            unreachable!()
+        }
+
+        async fn set_credit_limits(
+            &self,
+            _: Uuid,
+            _: CreditLimits,
+        ) -> Result<Liquidity, BalanceStoreError> {
+            unreachable!()
+        }
+    }
+
+    fn notifier(thresholds: Vec<BalanceCondition>) -> BalanceNotifier<TestStore> {
+        BalanceNotifier::new(
+            Address::from_str("example.node").unwrap(),
+            TestStore,
+            BalanceNotificationConfig {
+                debounce: 1000,
+                thresholds: thresholds
+                    .into_iter()
+                    .map(|condition| BalanceThreshold {
+                        condition,
+                        accounts: Vec::new(),
+                    })
+                    .collect(),
+            },
+            BalanceNotifications::default(),
+        )
+    }
+
+    fn change() -> BalanceChange {
+        BalanceChange {
+            account_id: Uuid::nil(),
+            username: Username::from_str("alice").unwrap(),
+            asset_code: "XYZ".to_string(),
+            asset_scale: 9,
+            notification_url: None,
+        }
+    }
+
+    fn liquidity(balance: i64) -> Liquidity {
+        Liquidity::new(
+            balance,
+            0,
+            0,
+            CreditLimits {
+                payable_limit: None,
+                receivable_limit: Some(1000),
+            },
+        )
+    }
+
+    fn events(notifications: Vec<BalanceNotification>) -> Vec<BalanceEvent> {
+        notifications
+            .into_iter()
+            .map(|notification| notification.event)
+            .collect()
+    }
+
+    #[test]
+    fn notifies_the_crossings_of_the_credit_limits() {
+        let mut notifier = notifier(vec![BalanceCondition::CreditLimit { share: 0.8 }]);
+        assert!(notifier
+            .evaluate(&change(), liquidity(-500), 1000)
+            .is_empty());
+        let notifications = notifier.evaluate(&change(), liquidity(-900), 2000);
+        assert_eq!(notifications[0].balance, -900);
+        assert_eq!(
+            events(notifications),
+            vec![BalanceEvent::CreditLimit {
+                share: 0.8,
+                kind: "receivable",
+                limit: Some(1000),
+                used: 0.9,
+                crossed: "above",
+            }]
+        );
+        // Until the balance falls back below the share
+        assert!(notifier
+            .evaluate(&change(), liquidity(-950), 3000)
+            .is_empty());
+        assert_eq!(
+            events(notifier.evaluate(&change(), liquidity(100), 4000)),
+            vec![BalanceEvent::CreditLimit {
+                share: 0.8,
+                kind: "payable",
+                limit: None,
+                used: 0.0,
+                crossed: "below",
+            }]
+        );
+    }
+
+    #[test]
+    fn notifies_the_changes_within_the_window() {
+        let mut notifier = notifier(vec![BalanceCondition::Change {
+            amount: 100,
+            window: 10_000,
+        }]);
+        assert!(notifier.evaluate(&change(), liquidity(0), 1000).is_empty());
+        assert!(notifier
+            .evaluate(&change(), liquidity(-60), 2000)
+            .is_empty());
+        assert_eq!(
+            events(notifier.evaluate(&change(), liquidity(-120), 3000)),
+            vec![BalanceEvent::Change {
+                amount: 100,
+                window: 10_000,
+                change: -120,
+            }]
+        );
+        // The next change is measured from the notified balance
+        assert!(notifier
+            .evaluate(&change(), liquidity(-200), 4000)
+            .is_empty());
+        // And the balances out of the window are forgotten
+        assert!(notifier
+            .evaluate(&change(), liquidity(-200), 20_000)
+            .is_empty());
+        assert!(notifier
+            .evaluate(&change(), liquidity(-250), 21_000)
+            .is_empty());
+    }
+
+    #[test]
+    fn only_checks_the_accounts_of_the_thresholds() {
+        let mut notifier = notifier(vec![BalanceCondition::CreditLimit { share: 0.5 }]);
+        notifier.config.thresholds[0].accounts = vec!["bob".to_string()];
+        assert!(notifier
+            .evaluate(&change(), liquidity(-900), 1000)
+            .is_empty());
+    }
+}
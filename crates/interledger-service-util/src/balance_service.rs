@@ -1,16 +1,68 @@
+use crate::balance_notifications::BalanceChange;
 use async_trait::async_trait;
 use futures::TryFutureExt;
 use interledger_errors::BalanceStoreError;
-use interledger_packet::{ErrorCode, RejectBuilder};
+use interledger_packet::{ErrorCode, LiquidityExceededDetails, RejectBuilder};
 use interledger_service::*;
 use interledger_settlement::core::{
     types::{SettlementAccount, SettlementStore},
     SettlementClient,
 };
-use std::marker::PhantomData;
+use metrics::{labels, recorder, Key};
+use serde::{Deserialize, Serialize};
+use std::{
+    convert::TryFrom,
+    marker::PhantomData,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::mpsc::UnboundedSender;
 use tracing::{debug, error};
+use url::Url;
 use uuid::Uuid;
 
+/// The incoming amount of a Prepare packet which is held in escrow while the packet
+/// is in flight. The escrowed amount is deducted from the sending account's balance
+/// when the Prepare is forwarded, and it is committed when the packet is fulfilled
+/// or released back to the sending account when the packet is rejected or expires.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Escrow {
+    /// Unique identifier of the escrow
+    pub id: Uuid,
+    /// The account which sent the Prepare packet
+    pub from_account_id: Uuid,
+    /// The amount of the Prepare packet which was received from the sending account
+    pub incoming_amount: u64,
+    /// The account to which the Prepare packet is forwarded
+    pub to_account_id: Uuid,
+    /// The amount of the Prepare packet which is forwarded to the receiving account
+    pub outgoing_amount: u64,
+    /// The expiry of the Prepare packet, in milliseconds since the UNIX epoch
+    pub expires_at: u64,
+}
+
+impl Escrow {
+    /// Creates the escrow of a Prepare packet, with a random identifier
+    pub fn new(
+        from_account_id: Uuid,
+        incoming_amount: u64,
+        to_account_id: Uuid,
+        outgoing_amount: u64,
+        expires_at: SystemTime,
+    ) -> Self {
+        Escrow {
+            id: Uuid::new_v4(),
+            from_account_id,
+            incoming_amount,
+            to_account_id,
+            outgoing_amount,
+            expires_at: expires_at
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_millis() as u64)
+                .unwrap_or_default(),
+        }
+    }
+}
+
 // TODO: Remove AccountStore dependency, use `AccountId: ToString` as associated type
 /// Trait responsible for managing an account's balance in the store
 /// as ILP Packets get routed
@@ -19,26 +71,173 @@ pub trait BalanceStore {
     /// Fetch the current balance for the given account id.
     async fn get_balance(&self, account_id: Uuid) -> Result<i64, BalanceStoreError>;
 
-    /// Decreases the sending account's balance before forwarding out a prepare packet
-    async fn update_balances_for_prepare(
-        &self,
-        from_account_id: Uuid,
-        incoming_amount: u64,
-    ) -> Result<(), BalanceStoreError>;
+    /// Fetch the amount of the given account which is held in escrow by the
+    /// packets it sent that are still in flight. This amount is not included in the balance.
+    async fn get_in_flight_amount(&self, account_id: Uuid) -> Result<u64, BalanceStoreError>;
 
-    /// Increases the receiving account's balance, and returns the updated balance
-    /// along with the amount which should be settled
+    /// Moves the incoming amount from the sending account's balance into escrow
+    /// before forwarding out a prepare packet
+    async fn update_balances_for_prepare(&self, escrow: &Escrow) -> Result<(), BalanceStoreError>;
+
+    /// Commits the escrowed amount and increases the receiving account's balance,
+    /// and returns the updated balance along with the amount which should be settled.
+    /// If the escrow was already released, the sending account is debited again
     async fn update_balances_for_fulfill(
         &self,
-        to_account_id: Uuid,
-        outgoing_amount: u64,
+        escrow: &Escrow,
     ) -> Result<(i64, u64), BalanceStoreError>;
 
-    async fn update_balances_for_reject(
+    /// Releases the escrowed amount back to the sending account's balance
+    async fn update_balances_for_reject(&self, escrow: &Escrow) -> Result<(), BalanceStoreError>;
+
+    /// Moves the account's balance down to its `settle_to` if it reached its `settle_threshold`
+    /// (for example when it is created with a negative `settle_to`), without any packet being
+    /// fulfilled. Returns the updated balance along with the amount which should be settled.
+    async fn update_balances_for_settle_to(
         &self,
-        from_account_id: Uuid,
-        incoming_amount: u64,
-    ) -> Result<(), BalanceStoreError>;
+        account_id: Uuid,
+    ) -> Result<(i64, u64), BalanceStoreError>;
+
+    /// Releases the escrows of the packets which expired without their Fulfill or Reject
+    /// being accounted for (for example because the node crashed while they were in flight).
+    /// Returns the number of escrows which were released.
+    async fn release_expired_escrows(&self) -> Result<usize, BalanceStoreError>;
+}
+
+/// The credit limits of an account, which bound what the node and the account may owe
+/// each other. The packets which would exceed them are rejected with T04 errors.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct CreditLimits {
+    /// The max amount the node may owe the account, including the packets forwarded
+    /// to it which are still in flight. There is no limit if it is not set.
+    #[serde(default)]
+    pub payable_limit: Option<u64>,
+    /// The max amount the account may owe the node, including the packets it sent which
+    /// are still in flight. If it is not set, the account's `min_balance` applies.
+    #[serde(default)]
+    pub receivable_limit: Option<u64>,
+}
+
+/// What the node owes an account and what the account owes the node, separately
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Liquidity {
+    /// The amount the node owes the account (its positive balance, including the
+    /// amount it prepaid)
+    pub payable: u64,
+    /// The amount the account owes the node (its negative balance)
+    pub receivable: u64,
+    /// The amount of the packets forwarded to the account which are still in flight,
+    /// which will be owed to the account if they are fulfilled
+    pub payable_in_flight: u64,
+    /// The amount of the packets sent by the account which are still in flight. This
+    /// amount is already included in `receivable` (or deducted from `payable`).
+    pub receivable_in_flight: u64,
+    /// The credit limits of the account
+    pub limits: CreditLimits,
+}
+
+impl Liquidity {
+    /// Splits the balance (including the prepaid amount) of an account into what the
+    /// node owes it and what it owes the node
+    pub fn new(
+        balance: i64,
+        payable_in_flight: u64,
+        receivable_in_flight: u64,
+        limits: CreditLimits,
+    ) -> Self {
+        Liquidity {
+            payable: balance.max(0) as u64,
+            receivable: balance.min(0).wrapping_neg() as u64,
+            payable_in_flight,
+            receivable_in_flight,
+            limits,
+        }
+    }
+}
+
+/// A packet which was rejected with a T04 error because it would have exceeded a credit
+/// limit of an account (or brought it under its minimum balance)
+#[derive(Debug, Clone, PartialEq)]
+pub struct LiquidityBlock {
+    /// The account whose limit would have been exceeded: the sending account for its
+    /// receivable limit, and the receiving account for its payable limit
+    pub account_id: Uuid,
+    pub username: Username,
+    pub asset_code: String,
+    pub asset_scale: u8,
+    /// Which of the account's credit limits would have been exceeded (`payable` or `receivable`)
+    pub kind: &'static str,
+    /// The amount of the packet, in the units of the account
+    pub amount: u64,
+    /// The credit limit which would have been exceeded
+    pub limit: u64,
+    /// The amount by which the packet would have exceeded the limit, which is what the
+    /// account must settle for the packet to go through
+    pub exceeded_by: u64,
+    /// Where the account's peer wants to be notified of the packets it sent which were blocked
+    pub notification_url: Option<Url>,
+}
+
+/// Trait responsible for the credit limits of the accounts, which can be adjusted at runtime
+#[async_trait]
+pub trait LiquidityStore {
+    /// Returns what the node and the account owe each other, and the account's credit limits
+    async fn get_liquidity(&self, account_id: Uuid) -> Result<Liquidity, BalanceStoreError>;
+
+    /// Replaces the credit limits of the account, and returns its updated liquidity.
+    /// The new limits only apply to the packets which are sent after the update.
+    async fn set_credit_limits(
+        &self,
+        account_id: Uuid,
+        limits: CreditLimits,
+    ) -> Result<Liquidity, BalanceStoreError>;
+}
+
+/// The result of replaying an account's balance journal and comparing
+/// the replayed balance with the one currently held in the store
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BalanceVerification {
+    /// The account whose balance was verified
+    pub account_id: Uuid,
+    /// The balance currently held in the store
+    pub balance: i64,
+    /// The prepaid amount currently held in the store
+    pub prepaid_amount: i64,
+    /// The balance obtained by replaying the journal (starting from the latest snapshot, if any)
+    pub expected_balance: i64,
+    /// The prepaid amount obtained by replaying the journal (starting from the latest snapshot, if any)
+    pub expected_prepaid_amount: i64,
+    /// The number of journal entries which were replayed on top of the snapshot
+    pub replayed_entries: u64,
+}
+
+impl BalanceVerification {
+    /// Returns true if the balances in the store match the ones replayed from the journal
+    pub fn is_consistent(&self) -> bool {
+        self.balance == self.expected_balance && self.prepaid_amount == self.expected_prepaid_amount
+    }
+}
+
+/// Trait responsible for the append-only journal of balance-affecting operations
+/// (prepares, fulfills, rejects and settlements) that the store keeps alongside
+/// the balances, so that operators can prove the balances are correct after incidents.
+#[async_trait]
+pub trait BalanceJournalStore {
+    /// Snapshots the balances of all accounts along with the position of each
+    /// account's journal, so that verifications only need to replay the entries
+    /// which were written after the snapshot. Returns the number of accounts snapshotted.
+    async fn snapshot_balances(&self) -> Result<usize, BalanceStoreError>;
+
+    /// Replays the journal of every account on top of its latest snapshot
+    /// and compares the result with the balances currently held in the store
+    async fn verify_balances(&self) -> Result<Vec<BalanceVerification>, BalanceStoreError>;
+
+    /// Overwrites the balance of the provided account with the one replayed from
+    /// its journal. Returns the verification which was used for the recovery.
+    async fn recover_balance(
+        &self,
+        account_id: Uuid,
+    ) -> Result<BalanceVerification, BalanceStoreError>;
 }
 
 /// # Balance Service
@@ -51,6 +250,10 @@ pub struct BalanceService<S, O, A> {
     store: S,
     next: O,
     settlement_client: SettlementClient,
+    settlement_failures: Option<UnboundedSender<Uuid>>,
+    settlement_queue: Option<UnboundedSender<(Uuid, u64)>>,
+    liquidity_blocks: Option<UnboundedSender<LiquidityBlock>>,
+    balance_changes: Option<UnboundedSender<BalanceChange>>,
     account_type: PhantomData<A>,
 }
 
@@ -65,9 +268,51 @@ where
             store,
             next,
             settlement_client: SettlementClient::default(),
+            settlement_failures: None,
+            settlement_queue: None,
+            liquidity_blocks: None,
+            balance_changes: None,
             account_type: PhantomData,
         }
     }
+
+    /// Sets the client used to send the outgoing settlements to the settlement engines
+    pub fn settlement_client(&mut self, client: SettlementClient) -> &mut Self {
+        self.settlement_client = client;
+        self
+    }
+
+    /// Sends the id of the account to the given channel whenever its
+    /// settlement engine fails to accept an outgoing settlement
+    pub fn notify_settlement_failures(&mut self, sender: UnboundedSender<Uuid>) -> &mut Self {
+        self.settlement_failures = Some(sender);
+        self
+    }
+
+    /// Sends the outgoing settlements (the account's id and the amount) to the given channel
+    /// instead of sending them to the settlement engines, so that they can be handed over to
+    /// another node. The amounts are already deducted from the accounts' balances.
+    pub fn queue_settlements(&mut self, sender: UnboundedSender<(Uuid, u64)>) -> &mut Self {
+        self.settlement_queue = Some(sender);
+        self
+    }
+
+    /// Sends the packets which are rejected because they would exceed a credit limit of an
+    /// account to the given channel, so that the account's peer can be notified
+    pub fn notify_liquidity_blocks(
+        &mut self,
+        sender: UnboundedSender<LiquidityBlock>,
+    ) -> &mut Self {
+        self.liquidity_blocks = Some(sender);
+        self
+    }
+
+    /// Sends the accounts whose balances are changed by the fulfilled packets to the given
+    /// channel, so that their balances can be checked against the notification thresholds
+    pub fn notify_balance_changes(&mut self, sender: UnboundedSender<BalanceChange>) -> &mut Self {
+        self.balance_changes = Some(sender);
+        self
+    }
 }
 
 #[async_trait]
@@ -78,12 +323,12 @@ where
     A: SettlementAccount + Send + Sync + 'static,
 {
     /// On send message:
-    /// 1. Calls `store.update_balances_for_prepare` with the prepare.
+    /// 1. Calls `store.update_balances_for_prepare` with the prepare, which holds its incoming amount in escrow.
     /// If it fails, it replies with a reject
     /// 1. Tries to forward the request:
-    ///     - If it returns a fullfil, calls `store.update_balances_for_fulfill` and replies with the fulfill
+    ///     - If it returns a fullfil, calls `store.update_balances_for_fulfill` (committing the escrow) and replies with the fulfill
     ///       INDEPENDENTLY of if the call suceeds or fails. This makes a `sendMoney` call if the fulfill puts the account's balance over the `settle_threshold`
-    ///     - if it returns an reject calls `store.update_balances_for_reject` and replies with the fulfill
+    ///     - if it returns an reject calls `store.update_balances_for_reject` (releasing the escrow) and replies with the fulfill
     ///       INDEPENDENTLY of if the call suceeds or fails
     async fn send_request(&mut self, request: OutgoingRequest<A>) -> IlpResult {
         // Don't bother touching the store for zero-amount packets.
@@ -101,11 +346,23 @@ where
         let from_id = from.id();
         let to = request.to.clone();
         let to_clone = to.clone();
+        let (from_blocked, to_blocked) = (from.clone(), to.clone());
         let to_id = to.id();
         let incoming_amount = request.original_amount;
         let outgoing_amount = request.prepare.amount();
+        let escrow = Escrow::new(
+            from_id,
+            incoming_amount,
+            to_id,
+            outgoing_amount,
+            request.prepare.expires_at(),
+        );
         let ilp_address = self.store.get_ilp_address();
         let settlement_client = self.settlement_client.clone();
+        let settlement_failures = self.settlement_failures.clone();
+        let settlement_queue = self.settlement_queue.clone();
+        let liquidity_blocks = self.liquidity_blocks.clone();
+        let balance_changes = self.balance_changes.clone();
 
         // Update the balance _before_ sending the settlement so that we don't accidentally send
         // multiple settlements for the same balance. While there will be a small moment of time (the delta
@@ -118,14 +375,69 @@ where
         // operate as-if the settlement engine has completed. Finally, if the request to the settlement-engine
         // fails, this amount will be re-added back to balance.
         self.store
-            .update_balances_for_prepare(from.id(), incoming_amount)
-            .map_err(move |_| {
-                debug!("Rejecting packet because it would exceed a balance limit");
+            .update_balances_for_prepare(&escrow)
+            .map_err(move |err| {
+                debug!(
+                    "Rejecting packet because it would exceed a balance limit: {}",
+                    err
+                );
+                // Tell the sender which limit was exceeded, and by how much. Going under
+                // the minimum balance is reported as exceeding the receivable limit
+                let exceeded = match err {
+                    BalanceStoreError::LiquidityExceeded {
+                        kind,
+                        limit,
+                        exceeded_by,
+                        ..
+                    } => Some((kind, limit, exceeded_by)),
+                    BalanceStoreError::MinimumBalanceExceeded {
+                        amount,
+                        balance,
+                        min_balance,
+                        ..
+                    } => Some((
+                        "receivable",
+                        min_balance.min(0).unsigned_abs(),
+                        min_balance_exceeded_by(amount, balance, min_balance),
+                    )),
+                    _ => None,
+                };
+                let (message, data) = match exceeded {
+                    Some((kind, limit, exceeded_by)) => {
+                        // The payable limit is the receiving account's, in its units
+                        let (account, amount) = if kind == "payable" {
+                            (&to_blocked, outgoing_amount)
+                        } else {
+                            (&from_blocked, incoming_amount)
+                        };
+                        record_liquidity_block(account, kind, amount);
+                        if let Some(ref liquidity_blocks) = liquidity_blocks {
+                            let _ = liquidity_blocks.send(LiquidityBlock {
+                                account_id: account.id(),
+                                username: account.username().clone(),
+                                asset_code: account.asset_code().to_string(),
+                                asset_scale: account.asset_scale(),
+                                kind,
+                                amount,
+                                limit,
+                                exceeded_by,
+                                notification_url: account.liquidity_notification_url(),
+                            });
+                        }
+                        (
+                            format!("{} limit exceeded by {}", kind, exceeded_by),
+                            LiquidityExceededDetails::new(exceeded_by, limit)
+                                .to_bytes()
+                                .to_vec(),
+                        )
+                    }
+                    None => (String::new(), Vec::new()),
+                };
                 RejectBuilder {
                     code: ErrorCode::T04_INSUFFICIENT_LIQUIDITY,
-                    message: &[],
+                    message: message.as_bytes(),
                     triggered_by: Some(&ilp_address),
-                    data: &[],
+                    data: &data,
                 }
                 .build()
             })
@@ -133,7 +445,9 @@ where
 
         match next.send_request(request).await {
             Ok(fulfill) => {
-                if outgoing_amount > 0 {
+                // The escrow is committed even if nothing is owed to the receiving account,
+                // so that the sweeper does not release it back to the sending account
+                if incoming_amount > 0 || outgoing_amount > 0 {
                     // We will spawn a task to update the balances in the database
                     // so that we DO NOT wait for the database before sending the
                     // Fulfill packet back to our peer. Due to how the flow of ILP
@@ -144,38 +458,42 @@ where
                     // relay the fulfillment _even if saving to the DB fails._
                     tokio::spawn(async move {
                         let (balance, amount_to_settle) = store
-                            .update_balances_for_fulfill(to.id(), outgoing_amount)
+                            .update_balances_for_fulfill(&escrow)
                             .map_err(|err| error!("Error applying balance changes for fulfill from account: {} to account: {}. Incoming amount was: {}, outgoing amount was: {}. Error: {}", from_id, to_id, incoming_amount, outgoing_amount, err))
                             .await?;
                         debug!(
                             "Account balance after fulfill: {}. Amount that needs to be settled: {}",
                             balance, amount_to_settle
                         );
-                        if amount_to_settle > 0 {
-                            if let Some(engine_details) = to.settlement_engine_details() {
-                                let engine_url = engine_details.url;
-                                // Note that if this program crashes after changing the balance (in the PROCESS_FULFILL script)
-                                // and the send_settlement fails but the program isn't alive to hear that, the balance will be incorrect.
-                                // No other instance will know that it was trying to send an outgoing settlement. We could
-                                // make this more robust by saving something to the DB about the outgoing settlement when we change the balance
-                                // but then we would also need to prevent a situation where every connector instance is polling the
-                                // settlement engine for the status of each
-                                // outgoing settlement and putting unnecessary
-                                // load on the settlement engine.
-                                if settlement_client
-                                    .send_settlement(
-                                        to.id(),
-                                        engine_url,
-                                        amount_to_settle,
-                                        to.asset_scale(),
-                                    )
-                                    .await
-                                    .is_err()
-                                {
-                                    store
-                                        .refund_settlement(to_id, amount_to_settle)
-                                        .map_err(|_| ())
-                                        .await?;
+                        if let Some(ref balance_changes) = balance_changes {
+                            let _ = balance_changes.send(BalanceChange::new(&from));
+                            let _ = balance_changes.send(BalanceChange::new(&to));
+                        }
+                        if amount_to_settle > 0 && to.settlement_engine_details().is_some() {
+                            // The settlement is sent by another node if it is queued
+                            let queued = settlement_queue
+                                .map(|queue| queue.send((to_id, amount_to_settle)).is_ok())
+                                .unwrap_or(false);
+                            // Note that if this program crashes after changing the balance (in the PROCESS_FULFILL script)
+                            // and the send_settlement fails but the program isn't alive to hear that, the balance will be incorrect.
+                            // No other instance will know that it was trying to send an outgoing settlement. We could
+                            // make this more robust by saving something to the DB about the outgoing settlement when we change the balance
+                            // but then we would also need to prevent a situation where every connector instance is polling the
+                            // settlement engine for the status of each
+                            // outgoing settlement and putting unnecessary
+                            // load on the settlement engine.
+                            if !queued
+                                && send_settlement(
+                                    &store,
+                                    &settlement_client,
+                                    &to,
+                                    amount_to_settle,
+                                )
+                                .await
+                                .is_err()
+                            {
+                                if let Some(ref settlement_failures) = settlement_failures {
+                                    let _ = settlement_failures.send(to_id);
                                 }
                             }
                         }
@@ -198,10 +516,7 @@ where
                 tokio::spawn({
                     let store_clone = self.store.clone();
                     async move {
-                        store_clone.update_balances_for_reject(
-                            from_clone.id(),
-                            incoming_amount,
-                        ).map_err(move |_| error!("Error rolling back balance change for accounts: {} and {}. Incoming amount was: {}, outgoing amount was: {}", from_clone.id(), to_clone.id(), incoming_amount, outgoing_amount)).await
+                        store_clone.update_balances_for_reject(&escrow).map_err(move |_| error!("Error rolling back balance change for accounts: {} and {}. Incoming amount was: {}, outgoing amount was: {}", from_clone.id(), to_clone.id(), incoming_amount, outgoing_amount)).await
                     }
                 });
 
@@ -211,6 +526,87 @@ where
     }
 }
 
+/// Sends a settlement of the amount, which must already be deducted from the account's
+/// balance, to the account's settlement engine. If the engine does not accept it, the
+/// amount is refunded to the account's balance and an error is returned.
+pub async fn send_settlement<S, A>(
+    store: &S,
+    settlement_client: &SettlementClient,
+    account: &A,
+    amount: u64,
+) -> Result<(), ()>
+where
+    S: SettlementStore<Account = A>,
+    A: SettlementAccount,
+{
+    let engine_url = match account.settlement_engine_details() {
+        Some(engine_details) => engine_details.url,
+        None => return Ok(()),
+    };
+    let result = settlement_client
+        .send_settlement(account.id(), engine_url, amount, account.asset_scale())
+        .await;
+    record_outgoing_settlement(account, result.is_ok());
+    if result.is_err() {
+        if let Err(err) = store.refund_settlement(account.id(), amount).await {
+            error!(
+                "Error refunding the settlement of {} to account {}: {}",
+                amount,
+                account.id(),
+                err
+            );
+        }
+        return Err(());
+    }
+    Ok(())
+}
+
+/// Counts the packets rejected because they would exceed a credit limit of the account in
+/// `liquidity.blocked.packets`, and their amount in `liquidity.blocked`
+fn record_liquidity_block<A: Account>(account: &A, kind: &'static str, amount: u64) {
+    let labels = labels!(
+        "account" => account.username().to_string(),
+        "asset_code" => account.asset_code().to_string(),
+        "limit" => kind,
+    );
+    recorder().increment_counter(
+        Key::from_name_and_labels("liquidity.blocked.packets", labels.clone()),
+        1,
+    );
+    recorder().increment_counter(
+        Key::from_name_and_labels("liquidity.blocked", labels),
+        amount,
+    );
+}
+
+/// Counts the settlements sent to the account's engine in `settlements.outgoing`,
+/// and the ones the engine failed to accept in `settlements.outgoing.failed`
+fn record_outgoing_settlement<A: Account>(account: &A, sent: bool) {
+    let name = if sent {
+        "settlements.outgoing"
+    } else {
+        "settlements.outgoing.failed"
+    };
+    recorder().increment_counter(
+        Key::from_name_and_labels(
+            name,
+            labels!(
+                "account" => account.username().to_string(),
+                "asset_code" => account.asset_code().to_string(),
+            ),
+        ),
+        1,
+    );
+}
+
+/// Returns by how much the balance would go under the minimum balance if the amount
+/// were subtracted from it. The amounts may be anywhere in their ranges, so this is
+/// computed on 128 bits and capped at `u64::MAX`.
+fn min_balance_exceeded_by(amount: u64, balance: i64, min_balance: i64) -> u64 {
+    let exceeded_by = i128::from(min_balance) - (i128::from(balance) - i128::from(amount));
+    u64::try_from(exceeded_by.max(0)).unwrap_or(u64::MAX)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -224,6 +620,19 @@ mod tests {
     use std::time::Duration;
     use url::Url;
 
+    #[test]
+    fn computes_how_much_the_min_balance_is_exceeded_by() {
+        assert_eq!(min_balance_exceeded_by(100, 50, 0), 50);
+        assert_eq!(min_balance_exceeded_by(100, 0, -1000), 0);
+        assert_eq!(min_balance_exceeded_by(u64::MAX, 0, 0), u64::MAX);
+        assert_eq!(
+            min_balance_exceeded_by(u64::MAX, i64::MIN, i64::MAX),
+            u64::MAX
+        );
+        assert_eq!(min_balance_exceeded_by(0, i64::MAX, i64::MIN), 0);
+        assert_eq!(min_balance_exceeded_by(1, i64::MIN, i64::MIN), 1);
+    }
+
     #[tokio::test]
     async fn executes_settlement() {
         let mock = mockito::mock("POST", mockito::Matcher::Any).create();
@@ -281,14 +690,48 @@ mod tests {
             .build())
         });
         let store = TestStore::new(1);
+        let (sender, mut settlement_failures) = tokio::sync::mpsc::unbounded_channel();
         let mut service = BalanceService::new(store.clone(), next);
+        service.notify_settlement_failures(sender);
         let fulfill = service.send_request(TEST_REQUEST.clone()).await.unwrap();
         assert_eq!(fulfill.data(), b"test data");
 
         tokio::time::delay_for(Duration::from_millis(100u64)).await;
         mock.assert();
-        assert_eq!(*store.refunded_settlement.read(), true);
-        assert_eq!(*store.rejected_message.read(), false);
+        assert!(*store.refunded_settlement.read());
+        assert!(!(*store.rejected_message.read()));
+        assert_eq!(
+            settlement_failures.try_recv().unwrap(),
+            TEST_REQUEST.to.id()
+        );
+    }
+
+    #[tokio::test]
+    async fn queues_settlements() {
+        let mock = mockito::mock("POST", mockito::Matcher::Any)
+            .create()
+            .expect(0);
+        let next = outgoing_service_fn(move |_| {
+            Ok(FulfillBuilder {
+                fulfillment: &[0; 32],
+                data: b"test data",
+            }
+            .build())
+        });
+        let store = TestStore::new(1);
+        let (sender, mut settlement_queue) = tokio::sync::mpsc::unbounded_channel();
+        let mut service = BalanceService::new(store.clone(), next);
+        service.queue_settlements(sender);
+        let fulfill = service.send_request(TEST_REQUEST.clone()).await.unwrap();
+        assert_eq!(fulfill.data(), b"test data");
+
+        tokio::time::delay_for(Duration::from_millis(100u64)).await;
+        mock.assert();
+        assert!(!(*store.refunded_settlement.read()));
+        assert_eq!(
+            settlement_queue.try_recv().unwrap(),
+            (TEST_REQUEST.to.id(), 1)
+        );
     }
 
     #[tokio::test]
@@ -318,6 +761,73 @@ mod tests {
         assert_eq!(*store.rejected_message.read(), true);
     }
 
+    #[tokio::test]
+    async fn settles_the_escrow_of_the_prepare() {
+        let next = outgoing_service_fn(move |_| {
+            Ok(FulfillBuilder {
+                fulfillment: &[0; 32],
+                data: b"test data",
+            }
+            .build())
+        });
+        let store = TestStore::new(0);
+        let mut service = BalanceService::new(store.clone(), next);
+        service.send_request(TEST_REQUEST.clone()).await.unwrap();
+
+        tokio::time::delay_for(Duration::from_millis(100u64)).await;
+        let prepared = (*store.prepared_escrow.read()).unwrap();
+        assert_eq!(prepared.incoming_amount, 100);
+        assert_eq!(prepared.outgoing_amount, 100);
+        assert_eq!(
+            prepared.expires_at,
+            TEST_REQUEST
+                .prepare
+                .expires_at()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_millis() as u64
+        );
+        // The Fulfill commits the same escrow which was created for the Prepare
+        assert_eq!(*store.settled_escrow.read(), Some(prepared));
+    }
+
+    #[tokio::test]
+    async fn rejects_packets_exceeding_credit_limits() {
+        let next = outgoing_service_fn(move |_| -> IlpResult { unreachable!() });
+        let mut store = TestStore::new(0);
+        store.liquidity_exceeded = true;
+        let mut service = BalanceService::new(store, next);
+        let reject = service
+            .send_request(TEST_REQUEST.clone())
+            .await
+            .unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::T04_INSUFFICIENT_LIQUIDITY);
+        assert_eq!(reject.message(), b"receivable limit exceeded by 50");
+        let details = LiquidityExceededDetails::from_bytes(reject.data()).unwrap();
+        assert_eq!(details.exceeded_by(), 50);
+        assert_eq!(details.limit(), 1000);
+    }
+
+    #[tokio::test]
+    async fn notifies_the_packets_blocked_by_credit_limits() {
+        let next = outgoing_service_fn(move |_| -> IlpResult { unreachable!() });
+        let mut store = TestStore::new(0);
+        store.liquidity_exceeded = true;
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        let mut service = BalanceService::new(store, next);
+        service.notify_liquidity_blocks(sender);
+        service
+            .send_request(TEST_REQUEST.clone())
+            .await
+            .unwrap_err();
+        let block = receiver.recv().await.unwrap();
+        assert_eq!(block.kind, "receivable");
+        assert_eq!(block.username, *ALICE);
+        assert_eq!(block.amount, 100);
+        assert_eq!(block.exceeded_by, 50);
+        assert_eq!(block.limit, 1000);
+    }
+
     #[derive(Debug, Clone)]
     struct TestAccount {
         pub engine_url: Url,
@@ -329,7 +839,7 @@ mod tests {
 
     impl Account for TestAccount {
         fn id(&self) -> Uuid {
-            Uuid::new_v4()
+            Uuid::nil()
         }
 
         fn username(&self) -> &Username {
@@ -363,6 +873,9 @@ mod tests {
         amount_to_settle: u64,
         rejected_message: Arc<RwLock<bool>>,
         refunded_settlement: Arc<RwLock<bool>>,
+        prepared_escrow: Arc<RwLock<Option<Escrow>>>,
+        settled_escrow: Arc<RwLock<Option<Escrow>>>,
+        liquidity_exceeded: bool,
     }
 
     impl TestStore {
@@ -371,6 +884,9 @@ mod tests {
                 amount_to_settle,
                 rejected_message: Arc::new(RwLock::new(false)),
                 refunded_settlement: Arc::new(RwLock::new(false)),
+                prepared_escrow: Arc::new(RwLock::new(None)),
+                settled_escrow: Arc::new(RwLock::new(None)),
+                liquidity_exceeded: false,
             }
         }
     }
@@ -396,30 +912,53 @@ mod tests {
             unimplemented!()
         }
 
+        async fn get_in_flight_amount(&self, _: Uuid) -> Result<u64, BalanceStoreError> {
+            unimplemented!()
+        }
+
         async fn update_balances_for_prepare(
             &self,
-            _: Uuid,
-            _: u64,
+            escrow: &Escrow,
         ) -> Result<(), BalanceStoreError> {
+            if self.liquidity_exceeded {
+                return Err(BalanceStoreError::LiquidityExceeded {
+                    account: escrow.from_account_id.to_string(),
+                    kind: "receivable",
+                    limit: 1000,
+                    exceeded_by: 50,
+                });
+            }
+            *self.prepared_escrow.write() = Some(*escrow);
             Ok(())
         }
 
         async fn update_balances_for_fulfill(
             &self,
-            _: Uuid,
-            _: u64,
+            escrow: &Escrow,
         ) -> Result<(i64, u64), BalanceStoreError> {
+            *self.settled_escrow.write() = Some(*escrow);
             Ok((0, self.amount_to_settle))
         }
 
         async fn update_balances_for_reject(
             &self,
-            _: Uuid,
-            _: u64,
+            escrow: &Escrow,
         ) -> Result<(), BalanceStoreError> {
+            *self.settled_escrow.write() = Some(*escrow);
             *self.rejected_message.write() = true;
             Ok(())
         }
+
+        async fn update_balances_for_settle_to(
+            &self,
+            _: Uuid,
+        ) -> Result<(i64, u64), BalanceStoreError> {
+            unimplemented!()
+        }
+
+        async fn release_expired_escrows(&self) -> Result<usize, BalanceStoreError> {
+            unimplemented!()
+        }
     }
 
     #[async_trait]
@@ -0,0 +1,528 @@
+use async_trait::async_trait;
+use interledger_packet::Prepare;
+use interledger_service::{Account, IlpResult, OutgoingRequest, OutgoingService};
+use ring::digest::{Context, SHA256};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use tokio::sync::watch;
+use tracing::{debug, trace};
+
+/// Approximate memory, in bytes, taken by each remembered prepare packet,
+/// on top of the data of its response
+const ENTRY_OVERHEAD: usize = 160;
+
+type Fingerprint = [u8; 32];
+
+/// # Deduplication Service
+///
+/// Remembers the prepare packets recently forwarded, fingerprinted by their condition and
+/// destination, and answers the duplicates of a packet with the response to the first one
+/// instead of forwarding them again. Duplicates of a packet which is still in flight wait
+/// for its response. This keeps routing loops (which bring a packet back from another
+/// peer) and peers retrying the same packet from delivering (and paying for) a packet more
+/// than once.
+///
+/// The hops a packet loops through convert its amount and shorten its expiry, so these
+/// are not part of the fingerprint. The expiry only bounds how long a packet is
+/// remembered: a packet expiring after the remembered one cannot have looped back, so it
+/// is forwarded (and remembered in its place).
+///
+/// The packets of the peer protocols (addressed to `peer.*`, such as ILDCP requests)
+/// are only exchanged with the adjacent peer, so they are never deduplicated.
+///
+/// The packets are remembered until their expiry or until the cache reaches its max
+/// memory, in which case the packets forwarded first are forgotten first.
+/// Requires _no store_
+#[derive(Clone)]
+pub struct DeduplicationService<O> {
+    next: O,
+    cache: Arc<Mutex<DeduplicationCache>>,
+}
+
+impl<O> DeduplicationService<O> {
+    /// Constructs a service which remembers as many packets as fit in the max memory (in
+    /// bytes). No packets are deduplicated if it is zero.
+    pub fn new(max_memory: usize, next: O) -> Self {
+        DeduplicationService {
+            next,
+            cache: Arc::new(Mutex::new(DeduplicationCache {
+                entries: HashMap::new(),
+                order: VecDeque::new(),
+                memory: 0,
+                max_memory,
+                next_id: 0,
+            })),
+        }
+    }
+}
+
+#[async_trait]
+impl<O, A> OutgoingService<A> for DeduplicationService<O>
+where
+    O: OutgoingService<A> + Send + Sync + 'static,
+    A: Account + Send + Sync + 'static,
+{
+    async fn send_request(&mut self, request: OutgoingRequest<A>) -> IlpResult {
+        if request.prepare.destination().scheme() == "peer" {
+            return self.next.send_request(request).await;
+        }
+        let fingerprint = fingerprint(&request.prepare);
+        let lookup = self.cache.lock().unwrap().lookup(
+            fingerprint,
+            request.prepare.expires_at(),
+            SystemTime::now(),
+        );
+
+        match lookup {
+            Lookup::Duplicate(mut previous) => {
+                // The value is None until the first packet gets its response
+                loop {
+                    match previous.recv().await {
+                        Some(Some(result)) => {
+                            debug!(
+                                "Answering duplicate prepare packet to {} with the previous response",
+                                request.prepare.destination()
+                            );
+                            return result;
+                        }
+                        Some(None) => continue,
+                        None => break,
+                    }
+                }
+                // The first packet was dropped before it got a response, so this one is
+                // forwarded in its place
+                self.next.send_request(request).await
+            }
+            Lookup::First { id, sender } => {
+                let result = self.next.send_request(request).await;
+                self.cache
+                    .lock()
+                    .unwrap()
+                    .complete(&fingerprint, id, response_size(&result));
+                let _ = sender.broadcast(Some(result.clone()));
+                result
+            }
+            Lookup::Untracked => self.next.send_request(request).await,
+        }
+    }
+}
+
+/// Fingerprints the prepare packet by its condition and destination. The accounts it is
+/// sent from and to, its amount and its expiry are left out, so that the packet is
+/// recognized when a routing loop brings it back from another peer.
+fn fingerprint(prepare: &Prepare) -> Fingerprint {
+    let mut context = Context::new(&SHA256);
+    context.update(prepare.execution_condition());
+    context.update(prepare.destination().as_ref());
+    let mut fingerprint = [0; 32];
+    fingerprint.copy_from_slice(context.finish().as_ref());
+    fingerprint
+}
+
+/// Returns the memory taken by the data of the response
+fn response_size(result: &IlpResult) -> usize {
+    match result {
+        Ok(fulfill) => fulfill.data().len(),
+        Err(reject) => reject.message().len() + reject.data().len(),
+    }
+}
+
+enum Lookup {
+    /// The packet was seen before and its response is (or will be) shared via the receiver
+    Duplicate(watch::Receiver<Option<IlpResult>>),
+    /// The packet is seen for the first time and its response must be shared via the sender
+    First {
+        id: u64,
+        sender: watch::Sender<Option<IlpResult>>,
+    },
+    /// The packet is not remembered (since the cache is disabled)
+    Untracked,
+}
+
+struct Entry {
+    /// Tells the entry apart from the entries of the same packet which were evicted before
+    id: u64,
+    expires_at: SystemTime,
+    size: usize,
+    result: watch::Receiver<Option<IlpResult>>,
+}
+
+struct DeduplicationCache {
+    entries: HashMap<Fingerprint, Entry>,
+    /// The entries in the order they were added, which is the order they are evicted in
+    order: VecDeque<(Fingerprint, u64)>,
+    /// Approximate memory taken by the entries
+    memory: usize,
+    max_memory: usize,
+    next_id: u64,
+}
+
+impl DeduplicationCache {
+    fn lookup(
+        &mut self,
+        fingerprint: Fingerprint,
+        expires_at: SystemTime,
+        now: SystemTime,
+    ) -> Lookup {
+        if self.max_memory == 0 {
+            return Lookup::Untracked;
+        }
+        // Only the expired entries are evicted before looking the packet up, so that the
+        // entry of a duplicate is not evicted to make room for the duplicate itself
+        self.evict(now, 0);
+        if let Some(entry) = self.entries.get(&fingerprint) {
+            if expires_at <= entry.expires_at {
+                return Lookup::Duplicate(entry.result.clone());
+            }
+            // The remembered packet is stale, since a duplicate would not outlive it
+            let entry = self.entries.remove(&fingerprint).unwrap();
+            self.memory -= entry.size;
+        }
+        self.evict(now, ENTRY_OVERHEAD);
+        if self.memory + ENTRY_OVERHEAD > self.max_memory {
+            return Lookup::Untracked;
+        }
+
+        let id = self.next_id;
+        self.next_id += 1;
+        let (sender, result) = watch::channel(None);
+        self.entries.insert(
+            fingerprint,
+            Entry {
+                id,
+                expires_at,
+                size: ENTRY_OVERHEAD,
+                result,
+            },
+        );
+        self.order.push_back((fingerprint, id));
+        self.memory += ENTRY_OVERHEAD;
+        Lookup::First { id, sender }
+    }
+
+    /// Accounts for the memory taken by the response to the packet
+    fn complete(&mut self, fingerprint: &Fingerprint, id: u64, size: usize) {
+        if let Some(entry) = self.entries.get_mut(fingerprint) {
+            if entry.id == id {
+                entry.size += size;
+                self.memory += size;
+            }
+        }
+        self.evict(SystemTime::now(), 0);
+    }
+
+    /// Evicts the expired entries at the front of the queue, and as many entries as
+    /// required to fit the additional memory
+    fn evict(&mut self, now: SystemTime, additional_memory: usize) {
+        while let Some(&(fingerprint, id)) = self.order.front() {
+            let entry = match self.entries.get(&fingerprint) {
+                Some(entry) if entry.id == id => entry,
+                // The entry of the packet is a newer one
+                _ => {
+                    self.order.pop_front();
+                    continue;
+                }
+            };
+            if entry.expires_at > now && self.memory + additional_memory <= self.max_memory {
+                break;
+            }
+            trace!(
+                "Forgetting forwarded prepare packet {}",
+                hex::encode(fingerprint)
+            );
+            self.memory -= entry.size;
+            self.entries.remove(&fingerprint);
+            self.order.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use interledger_packet::{Address, ErrorCode, FulfillBuilder, PrepareBuilder, RejectBuilder};
+    use interledger_service::{outgoing_service_fn, Username};
+    use once_cell::sync::Lazy;
+    use std::str::FromStr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+    use uuid::Uuid;
+
+    pub static ALICE: Lazy<Username> = Lazy::new(|| Username::from_str("alice").unwrap());
+    pub static EXAMPLE_ADDRESS: Lazy<Address> =
+        Lazy::new(|| Address::from_str("example.alice").unwrap());
+    static ALICE_ID: Lazy<Uuid> = Lazy::new(Uuid::new_v4);
+    static BOB_ID: Lazy<Uuid> = Lazy::new(Uuid::new_v4);
+
+    #[derive(Clone, Debug)]
+    struct TestAccount(Uuid);
+    impl Account for TestAccount {
+        fn id(&self) -> Uuid {
+            self.0
+        }
+
+        fn username(&self) -> &Username {
+            &ALICE
+        }
+
+        fn asset_code(&self) -> &str {
+            "XYZ"
+        }
+
+        fn asset_scale(&self) -> u8 {
+            9
+        }
+
+        fn ilp_address(&self) -> &Address {
+            &EXAMPLE_ADDRESS
+        }
+    }
+
+    fn request(amount: u64, expires_at: SystemTime) -> OutgoingRequest<TestAccount> {
+        request_from(*ALICE_ID, amount, expires_at)
+    }
+
+    /// Returns the request of another packet than the ones of `request`, which it is told
+    /// apart from by its condition
+    fn other_request(
+        condition: u8,
+        amount: u64,
+        expires_at: SystemTime,
+    ) -> OutgoingRequest<TestAccount> {
+        let mut request = request(amount, expires_at);
+        request.prepare = PrepareBuilder {
+            destination: request.prepare.destination(),
+            amount,
+            expires_at,
+            data: &[],
+            execution_condition: &[condition; 32],
+        }
+        .build();
+        request
+    }
+
+    fn request_from(
+        from: Uuid,
+        amount: u64,
+        expires_at: SystemTime,
+    ) -> OutgoingRequest<TestAccount> {
+        OutgoingRequest {
+            from: TestAccount(from),
+            to: TestAccount(*BOB_ID),
+            prepare: PrepareBuilder {
+                destination: Address::from_str("example.destination").unwrap(),
+                amount,
+                expires_at,
+                data: &[],
+                execution_condition: &[0; 32],
+            }
+            .build(),
+            original_amount: amount,
+        }
+    }
+
+    /// Returns a service which counts the packets it is sent and fulfills them
+    /// with a fulfillment telling which packet they were
+    fn counting_service(
+        max_memory: usize,
+    ) -> (
+        DeduplicationService<impl OutgoingService<TestAccount> + Clone>,
+        Arc<AtomicUsize>,
+    ) {
+        let count = Arc::new(AtomicUsize::new(0));
+        let count_clone = count.clone();
+        let next = outgoing_service_fn(move |_request| {
+            let count = count_clone.fetch_add(1, Ordering::SeqCst) + 1;
+            Ok(FulfillBuilder {
+                fulfillment: &[count as u8; 32],
+                data: &[],
+            }
+            .build())
+        });
+        (DeduplicationService::new(max_memory, next), count)
+    }
+
+    #[tokio::test]
+    async fn answers_duplicates_with_the_previous_response() {
+        let (mut service, count) = counting_service(10_000);
+        let expires_at = SystemTime::now() + Duration::from_secs(30);
+        let first = service.send_request(request(100, expires_at)).await;
+        let duplicate = service.send_request(request(100, expires_at)).await;
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+        assert_eq!(duplicate, first);
+
+        // The duplicates converted and shortened by other hops are recognized too
+        let converted = service
+            .send_request(request(99, expires_at - Duration::from_millis(1)))
+            .await;
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+        assert_eq!(converted, first);
+
+        // Packets with another condition are forwarded
+        service
+            .send_request(other_request(1, 100, expires_at))
+            .await
+            .unwrap();
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+        // A packet expiring after the remembered one is not a duplicate of it, and is
+        // remembered in its place
+        let later = expires_at + Duration::from_millis(1);
+        service.send_request(request(100, later)).await.unwrap();
+        service.send_request(request(100, later)).await.unwrap();
+        assert_eq!(count.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn cuts_off_routing_loops() {
+        #[derive(Clone)]
+        struct LoopingService {
+            count: Arc<AtomicUsize>,
+            node: Arc<Mutex<Option<DeduplicationService<LoopingService>>>>,
+        }
+
+        #[async_trait]
+        impl OutgoingService<TestAccount> for LoopingService {
+            async fn send_request(&mut self, request: OutgoingRequest<TestAccount>) -> IlpResult {
+                self.count.fetch_add(1, Ordering::SeqCst);
+                // The peer routes the packet back to the node, which gets it from another
+                // account, converted and with a shorter expiry like after any other hop
+                let mut node = self.node.lock().unwrap().clone().unwrap();
+                let mut prepare = request.prepare;
+                prepare.set_amount(prepare.amount() * 99 / 100);
+                prepare.set_expires_at(prepare.expires_at() - Duration::from_secs(1));
+                let looped = OutgoingRequest {
+                    from: TestAccount(Uuid::new_v4()),
+                    prepare,
+                    ..request
+                };
+                let _ = tokio::time::timeout(Duration::from_millis(50), node.send_request(looped))
+                    .await;
+                Err(RejectBuilder {
+                    code: ErrorCode::R00_TRANSFER_TIMED_OUT,
+                    message: &[],
+                    triggered_by: None,
+                    data: &[],
+                }
+                .build())
+            }
+        }
+
+        let count = Arc::new(AtomicUsize::new(0));
+        let next = LoopingService {
+            count: count.clone(),
+            node: Arc::new(Mutex::new(None)),
+        };
+        let mut service = DeduplicationService::new(10_000, next.clone());
+        *next.node.lock().unwrap() = Some(service.clone());
+
+        let expires_at = SystemTime::now() + Duration::from_secs(30);
+        let reject = service
+            .send_request(request(100, expires_at))
+            .await
+            .unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::R00_TRANSFER_TIMED_OUT);
+        // The packet which came back was not forwarded again
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+
+        // Retries of the packet from other senders are answered with its response
+        let retry = service
+            .send_request(request_from(Uuid::new_v4(), 100, expires_at))
+            .await;
+        assert_eq!(retry.unwrap_err().code(), ErrorCode::R00_TRANSFER_TIMED_OUT);
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn forwards_all_peer_protocol_packets() {
+        let (mut service, count) = counting_service(10_000);
+        let expires_at = SystemTime::now() + Duration::from_secs(30);
+        for _ in 0..2 {
+            let mut request = request(0, expires_at);
+            request.prepare = PrepareBuilder {
+                destination: Address::from_str("peer.config").unwrap(),
+                amount: 0,
+                expires_at,
+                data: &[],
+                execution_condition: &[0; 32],
+            }
+            .build();
+            service.send_request(request).await.unwrap();
+        }
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn duplicates_of_packets_in_flight_wait_for_their_response() {
+        #[derive(Clone)]
+        struct SlowService(Arc<AtomicUsize>);
+
+        #[async_trait]
+        impl OutgoingService<TestAccount> for SlowService {
+            async fn send_request(&mut self, _request: OutgoingRequest<TestAccount>) -> IlpResult {
+                self.0.fetch_add(1, Ordering::SeqCst);
+                tokio::time::delay_for(Duration::from_millis(50)).await;
+                Ok(FulfillBuilder {
+                    fulfillment: &[0; 32],
+                    data: b"slow",
+                }
+                .build())
+            }
+        }
+
+        let count = Arc::new(AtomicUsize::new(0));
+        let service = DeduplicationService::new(10_000, SlowService(count.clone()));
+        let expires_at = SystemTime::now() + Duration::from_secs(30);
+        let (first, duplicate) = futures::future::join(
+            service.clone().send_request(request(100, expires_at)),
+            service.clone().send_request(request(100, expires_at)),
+        )
+        .await;
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+        assert_eq!(first.unwrap().data(), b"slow");
+        assert_eq!(duplicate.unwrap().data(), b"slow");
+    }
+
+    #[tokio::test]
+    async fn forgets_packets_beyond_max_memory() {
+        // Only a single packet fits
+        let (mut service, count) = counting_service(ENTRY_OVERHEAD);
+        let expires_at = SystemTime::now() + Duration::from_secs(30);
+        service
+            .send_request(other_request(1, 1, expires_at))
+            .await
+            .unwrap();
+        service
+            .send_request(other_request(2, 1, expires_at))
+            .await
+            .unwrap();
+        service
+            .send_request(other_request(1, 1, expires_at))
+            .await
+            .unwrap();
+        assert_eq!(count.load(Ordering::SeqCst), 3);
+        service
+            .send_request(other_request(1, 1, expires_at))
+            .await
+            .unwrap();
+        assert_eq!(count.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn forgets_expired_packets() {
+        let (mut service, count) = counting_service(10_000);
+        let expires_at = SystemTime::now() - Duration::from_secs(1);
+        service.send_request(request(1, expires_at)).await.unwrap();
+        service.send_request(request(1, expires_at)).await.unwrap();
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn forwards_all_packets_if_disabled() {
+        let (mut service, count) = counting_service(0);
+        let expires_at = SystemTime::now() + Duration::from_secs(30);
+        service.send_request(request(1, expires_at)).await.unwrap();
+        service.send_request(request(1, expires_at)).await.unwrap();
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+    }
+}
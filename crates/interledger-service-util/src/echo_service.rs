@@ -3,13 +3,18 @@ use byteorder::ReadBytesExt;
 use bytes::{BufMut, BytesMut};
 use core::borrow::Borrow;
 use interledger_packet::{
-    oer::BufOerExt, Address, ErrorCode, Prepare, PrepareBuilder, RejectBuilder,
+    oer::{self, BufOerExt, MutBufOerExt},
+    Address, ErrorCode, FulfillBuilder, Prepare, PrepareBuilder, Reject, RejectBuilder,
 };
 use interledger_service::*;
+use ring::digest::{digest, SHA256};
+use ring::rand::{SecureRandom, SystemRandom};
+use std::collections::HashMap;
 use std::convert::TryFrom;
 use std::marker::PhantomData;
 use std::str;
-use std::time::SystemTime;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
 use tracing::debug;
 
 /// The prefix that echo packets should have in its data section
@@ -30,9 +35,104 @@ enum EchoPacketType {
 pub struct EchoService<I, S, A> {
     store: S,
     next: I,
+    pings: EchoPings,
     account_type: PhantomData<A>,
 }
 
+/// The pings sent by this node which are waiting for their echo response,
+/// mapping the condition of each echo request to its fulfillment
+#[derive(Clone, Default)]
+pub struct EchoPings(Arc<Mutex<HashMap<[u8; 32], [u8; 32]>>>);
+
+impl EchoPings {
+    /// Pings the `destination` by sending an echo request on behalf of the `from` account
+    /// through the `service`, and returns the round trip time once the echo response came
+    /// back. The `source_address` must be the address of the node whose `EchoService`
+    /// shares these pings, so that it can fulfill the response.
+    pub async fn ping<I, A>(
+        &self,
+        mut service: I,
+        from: A,
+        source_address: &Address,
+        destination: Address,
+        timeout: Duration,
+    ) -> Result<Duration, Reject>
+    where
+        I: IncomingService<A>,
+        A: Account,
+    {
+        let (prepare, condition) = self.echo_request(source_address, &destination, timeout);
+        let sent_at = Instant::now();
+        let result = service
+            .handle_request(IncomingRequest { from, prepare })
+            .await;
+        self.0.lock().unwrap().remove(&condition);
+        result.map(|_| sent_at.elapsed())
+    }
+
+    /// Pings the account by sending an echo request to its ILP address straight through the
+    /// outgoing `service` (without routing it), on behalf of the account itself, and returns
+    /// the round trip time once the echo response came back
+    pub async fn ping_account<O, A>(
+        &self,
+        mut service: O,
+        account: A,
+        source_address: &Address,
+        timeout: Duration,
+    ) -> Result<Duration, Reject>
+    where
+        O: OutgoingService<A>,
+        A: Account,
+    {
+        let (prepare, condition) =
+            self.echo_request(source_address, account.ilp_address(), timeout);
+        let sent_at = Instant::now();
+        let result = service
+            .send_request(OutgoingRequest {
+                from: account.clone(),
+                to: account,
+                original_amount: 0,
+                prepare,
+            })
+            .await;
+        self.0.lock().unwrap().remove(&condition);
+        result.map(|_| sent_at.elapsed())
+    }
+
+    /// Builds an echo request of the ping with a new condition, whose fulfillment is kept
+    /// until the ping is over
+    fn echo_request(
+        &self,
+        source_address: &Address,
+        destination: &Address,
+        timeout: Duration,
+    ) -> (Prepare, [u8; 32]) {
+        let mut fulfillment = [0; 32];
+        SystemRandom::new()
+            .fill(&mut fulfillment)
+            .expect("Failed to securely generate random fulfillment!");
+        let mut condition = [0; 32];
+        condition.copy_from_slice(digest(&SHA256, &fulfillment).as_ref());
+
+        let prepare = EchoRequestBuilder {
+            amount: 0,
+            expires_at: SystemTime::now() + timeout,
+            execution_condition: &condition,
+            destination,
+            source_address,
+        }
+        .build();
+        self.0.lock().unwrap().insert(condition, fulfillment);
+        (prepare, condition)
+    }
+
+    /// Returns the fulfillment of the pending ping with the given condition
+    fn fulfillment(&self, condition: &[u8]) -> Option<[u8; 32]> {
+        let condition = <[u8; 32]>::try_from(condition).ok()?;
+        self.0.lock().unwrap().get(&condition).cloned()
+    }
+}
+
 impl<I, S, A> EchoService<I, S, A>
 where
     S: AddressStore,
@@ -41,12 +141,29 @@ where
 {
     /// Simple Constructor
     pub fn new(store: S, next: I) -> Self {
+        Self::with_pings(store, EchoPings::default(), next)
+    }
+
+    /// Constructs a service which fulfills the echo responses of the provided pings
+    pub fn with_pings(store: S, pings: EchoPings, next: I) -> Self {
         EchoService {
             store,
             next,
+            pings,
             account_type: PhantomData,
         }
     }
+
+    /// Fulfills the echo response if it belongs to one of our pings
+    fn fulfill_response(&self, prepare: &Prepare) -> Option<IlpResult> {
+        let fulfillment = self.pings.fulfillment(prepare.execution_condition())?;
+        debug!("Received the echo response of a ping: {:?}", prepare);
+        Some(Ok(FulfillBuilder {
+            fulfillment: &fulfillment,
+            data: &[],
+        }
+        .build()))
+    }
 }
 
 #[async_trait]
@@ -87,6 +204,9 @@ where
             }
         };
         if echo_packet_type == EchoPacketType::Response as u8 {
+            if let Some(result) = self.fulfill_response(&request.prepare) {
+                return result;
+            }
             // if the echo packet type is Response, just pass it to the next service
             // so that the initiator could handle this packet
             return self.next.handle_request(request).await;
@@ -147,14 +267,16 @@ where
         }
         .build();
 
+        // The node pinged itself
+        if source_address == ilp_address {
+            if let Some(result) = self.fulfill_response(&request.prepare) {
+                return result;
+            }
+        }
         self.next.handle_request(request).await
     }
 }
 
-#[cfg(test)]
-use interledger_packet::{oer, oer::MutBufOerExt};
-// This should be exported when we have a use for it outside of the tests
-#[cfg(test)]
 pub struct EchoRequestBuilder<'a> {
     pub amount: u64,
     pub expires_at: SystemTime,
@@ -165,7 +287,6 @@ pub struct EchoRequestBuilder<'a> {
     pub source_address: &'a Address,
 }
 
-#[cfg(test)]
 impl<'a> EchoRequestBuilder<'a> {
     pub fn build(&self) -> Prepare {
         use bytes04::BufMut as BufMut04;
@@ -217,10 +338,7 @@ mod echo_tests {
     use interledger_packet::{FulfillBuilder, PrepareBuilder};
     use interledger_service::incoming_service_fn;
     use once_cell::sync::Lazy;
-    use ring::digest::{digest, SHA256};
-    use ring::rand::{SecureRandom, SystemRandom};
     use std::str::FromStr;
-    use std::time::{Duration, SystemTime};
     use uuid::Uuid;
 
     pub static ALICE: Lazy<Username> = Lazy::new(|| Username::from_str("alice").unwrap());
@@ -494,6 +612,74 @@ mod echo_tests {
         assert!(result.is_err());
     }
 
+    /// The echo response of a ping sent by the node itself is fulfilled by the service.
+    #[tokio::test]
+    async fn test_ping_self() {
+        let node_address = Address::from_str("example.node").unwrap();
+        let pings = EchoPings::default();
+        let handler = incoming_service_fn(|_| {
+            Err(RejectBuilder {
+                code: ErrorCode::F02_UNREACHABLE,
+                message: &[],
+                triggered_by: None,
+                data: &[],
+            }
+            .build())
+        });
+        let echo_service =
+            EchoService::with_pings(TestStore(node_address.clone()), pings.clone(), handler);
+        let from = TestAccount(Uuid::new_v4());
+
+        let result = pings
+            .ping(
+                echo_service,
+                from,
+                &node_address,
+                node_address.clone(),
+                Duration::from_secs(30),
+            )
+            .await;
+        assert!(result.is_ok());
+        assert!(pings.0.lock().unwrap().is_empty());
+    }
+
+    /// Echo responses which don't belong to a ping of the node are passed to the next service,
+    /// and pings whose destination is unreachable return the reject.
+    #[tokio::test]
+    async fn test_ping_unreachable() {
+        let node_address = Address::from_str("example.node").unwrap();
+        let destination = Address::from_str("example.unreachable").unwrap();
+        let pings = EchoPings::default();
+        let dest = destination.clone();
+        let handler = incoming_service_fn(move |request| {
+            assert_eq!(request.prepare.destination(), dest);
+            assert_eq!(request.prepare.amount(), 0);
+            Err(RejectBuilder {
+                code: ErrorCode::F02_UNREACHABLE,
+                message: &[],
+                triggered_by: None,
+                data: &[],
+            }
+            .build())
+        });
+        let echo_service =
+            EchoService::with_pings(TestStore(node_address.clone()), pings.clone(), handler);
+        let from = TestAccount(Uuid::new_v4());
+
+        let reject = pings
+            .ping(
+                echo_service,
+                from,
+                &node_address,
+                destination,
+                Duration::from_secs(30),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::F02_UNREACHABLE);
+        assert!(pings.0.lock().unwrap().is_empty());
+    }
+
     fn get_random_fulfillment() -> [u8; 32] {
         let mut bytes: [u8; 32] = [0; 32];
         SystemRandom::new().fill(&mut bytes).unwrap();
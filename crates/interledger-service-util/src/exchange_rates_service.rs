@@ -1,18 +1,25 @@
+use super::fee_policy::{
+    resolve_fee_policy, FeeAccount, FeePolicy, FeePolicyStore, QuoteRejection,
+};
+use super::stats_service::{Conversion, NodeStats};
 use async_trait::async_trait;
-use interledger_packet::{ErrorCode, RejectBuilder};
+use interledger_packet::{ErrorCode, MaxPacketAmountDetails, Reject, RejectBuilder};
 use interledger_rates::ExchangeRateStore;
 use interledger_service::*;
 use interledger_settlement::core::types::{Convert, ConvertDetails};
 use std::marker::PhantomData;
-use tracing::{error, trace, warn};
+use tracing::{error, trace};
 
 /// # Exchange Rates Service
 ///
-/// Responsible for getting the exchange rates for the two assets in the outgoing request (`request.from.asset_code`, `request.to.asset_code`).
-/// Requires a `ExchangeRateStore`
+/// Responsible for getting the exchange rates for the two assets in the outgoing request (`request.from.asset_code`, `request.to.asset_code`),
+/// and for charging the spread and fees of the [`FeePolicy`](./struct.FeePolicy.html) which applies to the packet.
+/// Requires a `ExchangeRateStore` and a `FeePolicyStore`
 #[derive(Clone)]
 pub struct ExchangeRateService<S, O, A> {
-    spread: f64,
+    fee_policy: FeePolicy,
+    /// The stats the conversions of the forwarded packets are counted in
+    stats: Option<NodeStats>,
     store: S,
     next: O,
     account_type: PhantomData<A>,
@@ -20,36 +27,64 @@ pub struct ExchangeRateService<S, O, A> {
 
 impl<S, O, A> ExchangeRateService<S, O, A>
 where
-    S: AddressStore + ExchangeRateStore,
+    S: AddressStore + ExchangeRateStore + FeePolicyStore,
     O: OutgoingService<A>,
-    A: Account,
+    A: FeeAccount,
 {
     pub fn new(spread: f64, store: S, next: O) -> Self {
+        Self::with_fee_policy(
+            FeePolicy {
+                spread: Some(spread),
+                ..Default::default()
+            },
+            store,
+            next,
+        )
+    }
+
+    /// Creates the service with the node's fee policy, which applies to the packets for
+    /// which neither the incoming account nor the asset pair have one
+    pub fn with_fee_policy(fee_policy: FeePolicy, store: S, next: O) -> Self {
         ExchangeRateService {
-            spread,
+            fee_policy,
+            stats: None,
             store,
             next,
             account_type: PhantomData,
         }
     }
+
+    /// Counts the rate, spread, fees and rounding applied to each forwarded packet in the
+    /// stats, which the operators can compare to the margin they intend to make
+    pub fn with_stats(mut self, stats: NodeStats) -> Self {
+        self.stats = Some(stats);
+        self
+    }
 }
 
 #[async_trait]
 impl<S, O, A> OutgoingService<A> for ExchangeRateService<S, O, A>
 where
     // TODO can we make these non-'static?
-    S: AddressStore + ExchangeRateStore + Clone + Send + Sync + 'static,
+    S: AddressStore + ExchangeRateStore + FeePolicyStore + Clone + Send + Sync + 'static,
     O: OutgoingService<A> + Send + Sync + Clone + 'static,
-    A: Account + Send + Sync + 'static,
+    A: FeeAccount + Send + Sync + 'static,
 {
     /// On send request:
     /// 1. If the prepare packet's amount is 0, it just forwards
     /// 1. Retrieves the exchange rate from the store (the store independently is responsible for polling the rates)
     ///     - return reject if the call to the store fails
     /// 1. Calculates the exchange rate AND scales it up/down depending on how many decimals each asset requires
+    /// 1. Applies the spread and deducts the fees of the fee policy
+    ///     - return reject if the fees exceed the amount
     /// 1. Updates the amount in the prepare packet and forwards it
+    /// 1. Converts the amounts of the `F08: Amount Too Large` rejects back to the incoming asset,
+    ///    so that the sender can size its packets with them
+    /// 1. Counts the conversion in the stats, if any
     async fn send_request(&mut self, mut request: OutgoingRequest<A>) -> IlpResult {
         let ilp_address = self.store.get_ilp_address();
+        let incoming_amount = request.prepare.amount();
+        let mut conversion = None;
         if request.prepare.amount() > 0 {
             let rate: f64 = if request.from.asset_code() == request.to.asset_code() {
                 1f64
@@ -82,65 +117,85 @@ where
                 .build());
             };
 
-            // Apply spread
+            // Apply spread and fees
             // TODO should this be applied differently for "local" or same-currency packets?
-            let rate = rate * (1.0 - self.spread);
-            let rate = if rate.is_finite() && rate.is_sign_positive() {
-                rate
-            } else {
-                warn!(
-                    "Exchange rate would have been {} based on rate and spread, using 0.0 instead",
-                    rate
-                );
-                0.0
-            };
-
-            // Can we overflow here?
-            let outgoing_amount = (request.prepare.amount() as f64) * rate;
-            let outgoing_amount = outgoing_amount.normalize_scale(ConvertDetails {
-                from: request.from.asset_scale(),
-                to: request.to.asset_scale(),
-            });
-
-            match outgoing_amount {
-                Ok(outgoing_amount) => {
+            let fee_policy =
+                resolve_fee_policy(&self.store, self.fee_policy, &request.from, &request.to);
+            let quote = fee_policy.quote(
+                request.prepare.amount(),
+                rate,
+                ConvertDetails {
+                    from: request.from.asset_scale(),
+                    to: request.to.asset_scale(),
+                },
+            );
+
+            match quote {
+                Ok(quote) => {
                     // Valid outgoing amount must be representable by a non-zero u64 once converted from f64.
-                    // FIXME: f64 > u64::MAX as f64 isn't very reliable for comparisons for
-                    // extremely small values; this should ideally be handled better.
-                    if outgoing_amount != 0.0
-                        && (outgoing_amount > u64::MAX as f64 || outgoing_amount < 1.0)
-                    {
-                        let (code, message) = if outgoing_amount < 1.0 {
-                            // Amount was too small to be converted to a non-zero u64, i.e. smaller
-                            // than 1.0.
-                            (
-                                ErrorCode::R01_INSUFFICIENT_SOURCE_AMOUNT,
-                                format!(
-                                    "Could not cast to f64, amount too small: {}",
-                                    outgoing_amount
+                    let outgoing_amount = match quote.outgoing_amount() {
+                        Ok(amount) => amount,
+                        Err(rejection) => {
+                            let (code, message) = match rejection {
+                                QuoteRejection::FeesExceedAmount => (
+                                    ErrorCode::R01_INSUFFICIENT_SOURCE_AMOUNT,
+                                    format!(
+                                        "Amount too small to cover fees: {} (fees: {})",
+                                        quote.converted_amount, quote.fee
+                                    ),
                                 ),
-                            )
-                        } else {
-                            // Amount was too large to be converted to u64 from f64, i.e. greater
-                            // than u64::MAX as f64.
-                            (
-                                ErrorCode::F08_AMOUNT_TOO_LARGE,
-                                format!(
-                                    "Could not cast to f64, amount too large: {}",
-                                    outgoing_amount
+                                // Amount was too small to be converted to a non-zero u64, i.e. smaller
+                                // than 1.0.
+                                QuoteRejection::AmountTooSmall => (
+                                    ErrorCode::R01_INSUFFICIENT_SOURCE_AMOUNT,
+                                    format!(
+                                        "Could not cast to f64, amount too small: {}",
+                                        quote.destination_amount
+                                    ),
                                 ),
-                            )
-                        };
-
-                        return Err(RejectBuilder {
-                            code,
-                            message: message.as_bytes(),
-                            triggered_by: Some(&ilp_address),
-                            data: &[],
+                                // Amount was too large to be converted to u64 from f64, i.e. greater
+                                // than u64::MAX as f64.
+                                QuoteRejection::AmountTooLarge => (
+                                    ErrorCode::F08_AMOUNT_TOO_LARGE,
+                                    format!(
+                                        "Could not cast to f64, amount too large: {}",
+                                        quote.destination_amount
+                                    ),
+                                ),
+                            };
+                            return Err(RejectBuilder {
+                                code,
+                                message: message.as_bytes(),
+                                triggered_by: Some(&ilp_address),
+                                data: &[],
+                            }
+                            .build());
                         }
-                        .build());
+                    };
+                    request.prepare.set_amount(outgoing_amount);
+                    if self.stats.is_some() {
+                        // The value of the packet at the market rate, without the spread
+                        let market_amount = (incoming_amount as f64 * rate)
+                            .normalize_scale(ConvertDetails {
+                                from: request.from.asset_scale(),
+                                to: request.to.asset_scale(),
+                            })
+                            .unwrap_or(quote.converted_amount);
+                        conversion = Some(Conversion {
+                            source_asset_code: request.from.asset_code().to_string(),
+                            source_asset_scale: request.from.asset_scale(),
+                            destination_asset_code: request.to.asset_code().to_string(),
+                            destination_asset_scale: request.to.asset_scale(),
+                            source_amount: incoming_amount,
+                            market_rate: rate,
+                            rate: quote.rate,
+                            market_amount,
+                            converted_amount: quote.converted_amount,
+                            fee: quote.fee,
+                            destination_amount: outgoing_amount,
+                            fulfilled: false,
+                        });
                     }
-                    request.prepare.set_amount(outgoing_amount as u64);
                     trace!("Converted incoming amount of: {} {} (scale {}) from account {} to outgoing amount of: {} {} (scale {}) for account {}",
                         request.original_amount, request.from.asset_code(), request.from.asset_scale(), request.from.id(),
                         outgoing_amount, request.to.asset_code(), request.to.asset_scale(), request.to.id());
@@ -169,14 +224,58 @@ where
             }
         }
 
-        self.next.send_request(request).await
+        let outgoing_amount = request.prepare.amount();
+        let result = self.next.send_request(request).await;
+        if let (Some(stats), Some(mut conversion)) = (&self.stats, conversion) {
+            conversion.fulfilled = result.is_ok();
+            stats.record_conversion(&conversion);
+        }
+        match result {
+            Err(reject)
+                if reject.code() == ErrorCode::F08_AMOUNT_TOO_LARGE
+                    && incoming_amount != outgoing_amount =>
+            {
+                Err(convert_max_packet_amount(
+                    reject,
+                    incoming_amount,
+                    outgoing_amount,
+                ))
+            }
+            result => result,
+        }
+    }
+}
+
+/// The details of an F08 reject are denominated in the asset of the node which rejected the
+/// packet. Scales them by the ratio of the amounts of the packet before and after conversion,
+/// so that the amount received is the one of the incoming packet and the maximum is
+/// expressed in the same asset.
+fn convert_max_packet_amount(reject: Reject, incoming_amount: u64, outgoing_amount: u64) -> Reject {
+    let details = match MaxPacketAmountDetails::from_bytes(reject.data()) {
+        Ok(details) if outgoing_amount > 0 => details,
+        _ => return reject,
+    };
+    let max_amount = u128::from(details.max_amount()) * u128::from(incoming_amount)
+        / u128::from(outgoing_amount);
+    let max_amount = if max_amount > u128::from(u64::MAX) {
+        u64::MAX
+    } else {
+        max_amount as u64
+    };
+    let data = MaxPacketAmountDetails::new(incoming_amount, max_amount).to_bytes();
+    RejectBuilder {
+        code: reject.code(),
+        message: reject.message(),
+        triggered_by: reject.triggered_by().as_ref(),
+        data: &data[..],
     }
+    .build()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use interledger_errors::{AddressStoreError, ExchangeRateStoreError};
+    use interledger_errors::{AddressStoreError, ExchangeRateStoreError, FeePolicyStoreError};
     use interledger_packet::{Address, Fulfill, FulfillBuilder, PrepareBuilder, Reject};
     use interledger_service::{outgoing_service_fn, Account};
     use once_cell::sync::Lazy;
@@ -226,6 +325,89 @@ mod tests {
         assert!(reject.message().starts_with(b"Could not convert"));
     }
 
+    #[tokio::test]
+    async fn converts_max_packet_amount_details() {
+        // The next node accepts at most 100 units of the outgoing asset, which is worth
+        // twice the incoming one
+        let outgoing = outgoing_service_fn(move |request: OutgoingRequest<TestAccount>| {
+            Err(RejectBuilder {
+                code: ErrorCode::F08_AMOUNT_TOO_LARGE,
+                message: &[],
+                triggered_by: Some(&Address::from_str("example.bob").unwrap()),
+                data: &MaxPacketAmountDetails::new(request.prepare.amount(), 100).to_bytes(),
+            }
+            .build())
+        });
+        let mut service = test_service(1.0, 2.0, 0.0, outgoing);
+        let reject = service
+            .send_request(OutgoingRequest {
+                from: TestAccount::new("ABC".to_owned(), 1),
+                to: TestAccount::new("XYZ".to_owned(), 1),
+                original_amount: 500,
+                prepare: PrepareBuilder {
+                    destination: Address::from_str("example.destination").unwrap(),
+                    amount: 500,
+                    expires_at: SystemTime::now(),
+                    execution_condition: &[1; 32],
+                    data: b"hello",
+                }
+                .build(),
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::F08_AMOUNT_TOO_LARGE);
+        assert_eq!(
+            reject.triggered_by(),
+            Some(Address::from_str("example.bob").unwrap())
+        );
+        let details = MaxPacketAmountDetails::from_bytes(reject.data()).unwrap();
+        assert_eq!(details.amount_received(), 500);
+        assert_eq!(details.max_amount(), 200);
+    }
+
+    #[tokio::test]
+    async fn counts_conversions_in_stats() {
+        let stats = NodeStats::default();
+        let outgoing = outgoing_service_fn(|_| {
+            Ok(FulfillBuilder {
+                fulfillment: &[0; 32],
+                data: &[],
+            }
+            .build())
+        });
+        // The outgoing asset is worth twice the incoming one, and the spread is 1%
+        let mut service = test_service(1.0, 2.0, 0.01, outgoing).with_stats(stats.clone());
+        service
+            .send_request(OutgoingRequest {
+                from: TestAccount::new("ABC".to_owned(), 1),
+                to: TestAccount::new("XYZ".to_owned(), 1),
+                original_amount: 100,
+                prepare: PrepareBuilder {
+                    destination: Address::from_str("example.destination").unwrap(),
+                    amount: 100,
+                    expires_at: SystemTime::now(),
+                    execution_condition: &[1; 32],
+                    data: b"hello",
+                }
+                .build(),
+            })
+            .await
+            .unwrap();
+
+        let overview = stats.conversions();
+        let pair = &overview.pairs[0];
+        assert_eq!(pair.source_asset_code, "ABC");
+        assert_eq!(pair.destination_asset_code, "XYZ");
+        assert_eq!(pair.fulfilled, 1);
+        assert_eq!(pair.destination_amount, 49);
+        assert!((pair.market_rate - 0.5).abs() < 1e-9);
+        assert!((pair.rate - 0.495).abs() < 1e-9);
+        // 50 units at the market rate, 49.5 after the spread, 49 after rounding
+        assert!((pair.spread - 0.5).abs() < 1e-9);
+        assert!((pair.rounding - 0.5).abs() < 1e-9);
+        assert!((pair.slippage - 0.02).abs() < 1e-9);
+    }
+
     #[tokio::test]
     async fn applies_spread() {
         let ret = exchange_rate(100, 1, 1.0, 1, 2.0, 0.01).await;
@@ -249,6 +431,52 @@ mod tests {
         assert_eq!(ret.1[0].prepare.amount(), 0);
     }
 
+    #[tokio::test]
+    async fn deducts_fees() {
+        let node_policy = FeePolicy {
+            spread: Some(0.5),
+            percentage_fee: Some(0.1),
+            fixed_fee: None,
+        };
+        let ret = exchange_rate_with_policies(1000, node_policy, None, FeePolicy::default()).await;
+        assert_eq!(ret.1[0].prepare.amount(), 900);
+
+        // The asset pair's policy takes precedence over the node's
+        let pair_policy = FeePolicy {
+            fixed_fee: Some(25),
+            percentage_fee: Some(0.0),
+            ..Default::default()
+        };
+        let ret =
+            exchange_rate_with_policies(1000, node_policy, Some(pair_policy), FeePolicy::default())
+                .await;
+        assert_eq!(ret.1[0].prepare.amount(), 975);
+
+        // The incoming account's policy takes precedence over the asset pair's
+        let account_policy = FeePolicy {
+            spread: Some(0.0),
+            ..Default::default()
+        };
+        let ret =
+            exchange_rate_with_policies(1000, node_policy, Some(pair_policy), account_policy).await;
+        assert_eq!(ret.1[0].prepare.amount(), 1975);
+    }
+
+    #[tokio::test]
+    async fn rejects_if_fees_exceed_amount() {
+        let node_policy = FeePolicy {
+            fixed_fee: Some(10),
+            ..Default::default()
+        };
+        let ret = exchange_rate_with_policies(4, node_policy, None, FeePolicy::default()).await;
+        let reject = ret.0.unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::R01_INSUFFICIENT_SOURCE_AMOUNT);
+        assert!(reject
+            .message()
+            .starts_with(b"Amount too small to cover fees"));
+        assert!(ret.1.is_empty());
+    }
+
     // Instantiates an exchange rate service and returns the fulfill/reject
     // packet and the outgoing request after performing an asset conversion
     async fn exchange_rate(
@@ -290,11 +518,55 @@ mod tests {
         (result, reqs.clone())
     }
 
+    // Converts from an asset worth 1 to an asset worth 2 (with the same scale),
+    // applying the given policies
+    async fn exchange_rate_with_policies(
+        amount: u64,
+        node_policy: FeePolicy,
+        pair_policy: Option<FeePolicy>,
+        account_policy: FeePolicy,
+    ) -> (Result<Fulfill, Reject>, Vec<OutgoingRequest<TestAccount>>) {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let requests_clone = requests.clone();
+        let outgoing = outgoing_service_fn(move |request| {
+            requests_clone.lock().unwrap().push(request);
+            Ok(FulfillBuilder {
+                fulfillment: &[0; 32],
+                data: b"hello!",
+            }
+            .build())
+        });
+        let mut store = test_store(2.0, 1.0);
+        store.fee_policy = pair_policy;
+        let mut service = ExchangeRateService::with_fee_policy(node_policy, store, outgoing);
+        let mut from = TestAccount::new("ABC".to_owned(), 2);
+        from.fee_policy = account_policy;
+        let result = service
+            .send_request(OutgoingRequest {
+                from,
+                to: TestAccount::new("XYZ".to_owned(), 2),
+                original_amount: amount,
+                prepare: PrepareBuilder {
+                    destination: Address::from_str("example.destination").unwrap(),
+                    amount,
+                    expires_at: SystemTime::now(),
+                    execution_condition: &[1; 32],
+                    data: b"hello",
+                }
+                .build(),
+            })
+            .await;
+
+        let reqs = requests.lock().unwrap();
+        (result, reqs.clone())
+    }
+
     #[derive(Debug, Clone)]
     struct TestAccount {
         ilp_address: Address,
         asset_code: String,
         asset_scale: u8,
+        fee_policy: FeePolicy,
     }
     impl TestAccount {
         fn new(asset_code: String, asset_scale: u8) -> Self {
@@ -302,10 +574,17 @@ mod tests {
                 ilp_address: Address::from_str("example.alice").unwrap(),
                 asset_code,
                 asset_scale,
+                fee_policy: FeePolicy::default(),
             }
         }
     }
 
+    impl FeeAccount for TestAccount {
+        fn fee_policy(&self) -> FeePolicy {
+            self.fee_policy
+        }
+    }
+
     #[async_trait]
     impl AddressStore for TestStore {
         /// Saves the ILP Address in the store's memory and database
@@ -348,6 +627,24 @@ mod tests {
     #[derive(Debug, Clone)]
     struct TestStore {
         rates: HashMap<Vec<String>, (f64, f64)>,
+        fee_policy: Option<FeePolicy>,
+    }
+
+    impl FeePolicyStore for TestStore {
+        fn get_fee_policy(&self, _from: &str, _to: &str) -> Option<FeePolicy> {
+            self.fee_policy
+        }
+
+        fn get_all_fee_policies(&self) -> Result<HashMap<String, FeePolicy>, FeePolicyStoreError> {
+            unimplemented!()
+        }
+
+        fn set_fee_policies(
+            &self,
+            _policies: HashMap<String, FeePolicy>,
+        ) -> Result<(), FeePolicyStoreError> {
+            unimplemented!()
+        }
     }
 
     impl ExchangeRateStore for TestStore {
@@ -385,7 +682,10 @@ mod tests {
     fn test_store(rate1: f64, rate2: f64) -> TestStore {
         let mut rates = HashMap::new();
         rates.insert(vec!["ABC".to_owned(), "XYZ".to_owned()], (rate1, rate2));
-        TestStore { rates }
+        TestStore {
+            rates,
+            fee_policy: None,
+        }
     }
 
     fn test_service(
@@ -1,6 +1,6 @@
 use async_trait::async_trait;
 use chrono::{DateTime, Duration, Utc};
-use interledger_service::{Account, IlpResult, OutgoingRequest, OutgoingService};
+use interledger_service::{clock, Account, IlpResult, OutgoingRequest, OutgoingService};
 use tracing::trace;
 
 pub const DEFAULT_ROUND_TRIP_TIME: u32 = 500;
@@ -27,6 +27,7 @@ pub trait RoundTripTimeAccount: Account {
 pub struct ExpiryShortenerService<O> {
     next: O,
     max_expiry_duration: u32,
+    hop_reduction: u32,
 }
 
 impl<O> ExpiryShortenerService<O> {
@@ -34,15 +35,22 @@ impl<O> ExpiryShortenerService<O> {
         ExpiryShortenerService {
             next,
             max_expiry_duration: DEFAULT_MAX_EXPIRY_DURATION,
+            hop_reduction: 0,
         }
     }
 
-    // TODO: This isn't used anywhere, should we remove it?
     /// Sets the service's max expiry duration
     pub fn max_expiry_duration(&mut self, milliseconds: u32) -> &mut Self {
         self.max_expiry_duration = milliseconds;
         self
     }
+
+    /// Sets the time, in milliseconds, which is subtracted from the expiry of each
+    /// packet on top of the round trip times of the accounts
+    pub fn hop_reduction(&mut self, milliseconds: u32) -> &mut Self {
+        self.hop_reduction = milliseconds;
+        self
+    }
 }
 
 #[async_trait]
@@ -53,17 +61,18 @@ where
 {
     /// On send request:
     /// 1. Get the sender and receiver's roundtrip time (default 1000ms)
-    /// 2. Reduce the packet's expiry by that amount
+    /// 2. Reduce the packet's expiry by that amount, plus the hop reduction
     /// 3. Ensure that the packet expiry does not exceed the maximum expiry duration
     /// 4. Forward the request
     async fn send_request(&mut self, mut request: OutgoingRequest<A>) -> IlpResult {
-        let time_to_subtract =
-            i64::from(request.from.round_trip_time() + request.to.round_trip_time());
+        let time_to_subtract = i64::from(request.from.round_trip_time())
+            + i64::from(request.to.round_trip_time())
+            + i64::from(self.hop_reduction);
         let new_expiry = DateTime::<Utc>::from(request.prepare.expires_at())
             - Duration::milliseconds(time_to_subtract);
 
-        let latest_allowable_expiry =
-            Utc::now() + Duration::milliseconds(i64::from(self.max_expiry_duration));
+        let latest_allowable_expiry = DateTime::<Utc>::from(clock::now())
+            + Duration::milliseconds(i64::from(self.max_expiry_duration));
         let new_expiry = if new_expiry > latest_allowable_expiry {
             trace!(
                 "Shortening packet expiry duration to {}ms in the future",
@@ -164,6 +173,47 @@ mod tests {
             .expect("Should have shortened expiry");
     }
 
+    #[tokio::test]
+    async fn shortens_expiry_by_hop_reduction() {
+        let original_expiry = Utc::now() + Duration::milliseconds(30000);
+        let mut service = ExpiryShortenerService::new(outgoing_service_fn(move |request| {
+            if DateTime::<Utc>::from(request.prepare.expires_at())
+                == original_expiry - Duration::milliseconds(1500)
+            {
+                Ok(FulfillBuilder {
+                    fulfillment: &[0; 32],
+                    data: &[],
+                }
+                .build())
+            } else {
+                Err(RejectBuilder {
+                    code: ErrorCode::F00_BAD_REQUEST,
+                    message: &[],
+                    data: &[],
+                    triggered_by: None,
+                }
+                .build())
+            }
+        }));
+        service.hop_reduction(200);
+        service
+            .send_request(OutgoingRequest {
+                from: TestAccount(Uuid::new_v4(), 600),
+                to: TestAccount(Uuid::new_v4(), 700),
+                prepare: PrepareBuilder {
+                    destination: Address::from_str("example.destination").unwrap(),
+                    amount: 10,
+                    expires_at: original_expiry.into(),
+                    data: &[],
+                    execution_condition: &[0; 32],
+                }
+                .build(),
+                original_amount: 10,
+            })
+            .await
+            .expect("Should have shortened expiry");
+    }
+
     #[tokio::test]
     async fn reduces_expiry_to_max_duration() {
         let mut service = ExpiryShortenerService::new(outgoing_service_fn(move |request| {
@@ -0,0 +1,509 @@
+use async_trait::async_trait;
+use interledger_packet::{ErrorCode, RejectBuilder};
+use interledger_service::{Account, AddressStore, IlpResult, IncomingRequest, IncomingService};
+use serde::Deserialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use tokio::sync::oneshot;
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+/// Virtual time by which an account with a weight of 1 advances each time one of its
+/// packets is dequeued. Accounts with higher weights advance proportionally less.
+const STRIDE: u64 = 1 << 20;
+
+fn default_queue_capacity() -> usize {
+    100
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+/// Configuration of the queuing of the incoming packets, which shares the processing of
+/// the node between the accounts when more packets arrive than it processes at once
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct FairQueueConfig {
+    /// Max number of incoming packets processed at the same time. The packets received
+    /// above it wait in the queue of their account. Defaults to 0, which disables the queues.
+    #[serde(default)]
+    pub max_concurrent: usize,
+    /// Max number of packets waiting in the queue of each account. The packets received
+    /// when the queue of their account is full are rejected with `T03: Connector Busy`.
+    /// Defaults to 100.
+    #[serde(default = "default_queue_capacity")]
+    pub queue_capacity: usize,
+    /// Share of the processing given to the accounts without a weight of their own.
+    /// Defaults to 1.
+    #[serde(default = "default_weight")]
+    pub default_weight: u32,
+    /// Weights of the accounts, by username. An account with a weight of 2 gets its queued
+    /// packets processed twice as often as one with a weight of 1.
+    #[serde(default)]
+    pub weights: HashMap<String, u32>,
+}
+
+impl Default for FairQueueConfig {
+    fn default() -> Self {
+        FairQueueConfig {
+            max_concurrent: 0,
+            queue_capacity: default_queue_capacity(),
+            default_weight: default_weight(),
+            weights: HashMap::new(),
+        }
+    }
+}
+
+/// # Fair Queue Service
+///
+/// Limits the number of incoming packets processed at the same time, and makes the packets
+/// received above that limit wait in per-account queues. The queues are served by weighted
+/// fair queuing (stride scheduling), so that a peer sending many packets cannot starve the
+/// other peers: each account gets a share of the processing proportional to its weight,
+/// however many packets it sends. Packets which would overflow the queue of their account
+/// are rejected with `T03: Connector Busy`.
+///
+/// Nothing is queued if `max_concurrent` is zero.
+/// Requires an `AddressStore`
+#[derive(Clone)]
+pub struct FairQueueService<S, I> {
+    store: S,
+    next: I,
+    queue: Option<Arc<Mutex<FairQueue>>>,
+}
+
+impl<S, I> FairQueueService<S, I> {
+    pub fn new(config: FairQueueConfig, store: S, next: I) -> Self {
+        let queue = if config.max_concurrent > 0 {
+            Some(Arc::new(Mutex::new(FairQueue::new(config))))
+        } else {
+            None
+        };
+        FairQueueService { store, next, queue }
+    }
+}
+
+#[async_trait]
+impl<S, I, A> IncomingService<A> for FairQueueService<S, I>
+where
+    S: AddressStore + Send + Sync + 'static,
+    I: IncomingService<A> + Send + Sync + 'static,
+    A: Account + Send + Sync + 'static,
+{
+    /// On receive request:
+    /// 1. If fewer than `max_concurrent` packets are being processed and none is waiting,
+    ///    forwards the request
+    /// 1. Otherwise, rejects it with T03 if the queue of its account is full, or waits
+    ///    in that queue until its turn comes
+    async fn handle_request(&mut self, request: IncomingRequest<A>) -> IlpResult {
+        let queue = match self.queue {
+            Some(ref queue) => queue.clone(),
+            None => return self.next.handle_request(request).await,
+        };
+        let admission = queue
+            .lock()
+            .unwrap()
+            .admit(request.from.id(), request.from.username().as_ref());
+        let _permit = match admission {
+            Admission::Now => Permit(queue),
+            Admission::Queued(turn) => {
+                let mut waiting = Waiting {
+                    turn: Some(turn),
+                    queue: queue.clone(),
+                };
+                if let Some(turn) = waiting.turn.as_mut() {
+                    // The sender is only dropped once the slot was handed to the packet,
+                    // so the error cannot happen while the queue exists
+                    let _ = turn.await;
+                }
+                waiting.turn = None;
+                Permit(queue)
+            }
+            Admission::Full => {
+                debug!(
+                    "Rejecting packet from account {} because its queue is full",
+                    request.from.id()
+                );
+                return Err(RejectBuilder {
+                    code: ErrorCode::T03_CONNECTOR_BUSY,
+                    message: b"Too many packets queued for the account",
+                    triggered_by: Some(&self.store.get_ilp_address()),
+                    data: &[],
+                }
+                .build());
+            }
+        };
+        self.next.handle_request(request).await
+    }
+}
+
+/// Slot of a packet being processed, which is handed to the next queued packet once the
+/// packet's response is returned (or its future is dropped)
+struct Permit(Arc<Mutex<FairQueue>>);
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        if let Ok(mut queue) = self.0.lock() {
+            queue.release();
+        } else {
+            warn!("Fair queue lock poisoned, the slot of a packet was not released");
+        }
+    }
+}
+
+/// Packet waiting in the queue of its account. If its future is dropped right after it was
+/// handed a slot, the slot is handed to the next packet instead of being lost.
+struct Waiting {
+    /// The receiver of the turn, until the packet received it
+    turn: Option<oneshot::Receiver<()>>,
+    queue: Arc<Mutex<FairQueue>>,
+}
+
+impl Drop for Waiting {
+    fn drop(&mut self) {
+        if let Some(mut turn) = self.turn.take() {
+            turn.close();
+            if turn.try_recv().is_ok() {
+                if let Ok(mut queue) = self.queue.lock() {
+                    queue.release();
+                }
+            }
+        }
+    }
+}
+
+enum Admission {
+    Now,
+    Queued(oneshot::Receiver<()>),
+    Full,
+}
+
+struct Waiter {
+    /// Order of arrival, which breaks the ties between the accounts
+    sequence: u64,
+    turn: oneshot::Sender<()>,
+}
+
+struct AccountQueue {
+    /// Virtual time of the account, which is the next one to be served if it is the lowest
+    pass: u64,
+    stride: u64,
+    waiters: VecDeque<Waiter>,
+}
+
+struct FairQueue {
+    config: FairQueueConfig,
+    /// Weights by lowercased username, since usernames are case insensitive
+    weights: HashMap<String, u32>,
+    in_flight: usize,
+    /// Virtual time of the last packet dequeued. Accounts which start queuing again
+    /// start from it, so that they cannot claim the turns they did not use while idle.
+    virtual_time: u64,
+    sequence: u64,
+    queues: HashMap<Uuid, AccountQueue>,
+}
+
+impl FairQueue {
+    fn new(config: FairQueueConfig) -> Self {
+        let weights = config
+            .weights
+            .iter()
+            .map(|(username, weight)| (username.to_lowercase(), *weight))
+            .collect();
+        FairQueue {
+            config,
+            weights,
+            in_flight: 0,
+            virtual_time: 0,
+            sequence: 0,
+            queues: HashMap::new(),
+        }
+    }
+
+    fn admit(&mut self, account_id: Uuid, username: &str) -> Admission {
+        if self.in_flight < self.config.max_concurrent && self.queues.is_empty() {
+            self.in_flight += 1;
+            return Admission::Now;
+        }
+
+        let weight = self
+            .weights
+            .get(&username.to_lowercase())
+            .copied()
+            .unwrap_or(self.config.default_weight)
+            .max(1);
+        let virtual_time = self.virtual_time;
+        let capacity = self.config.queue_capacity;
+        let queue = self
+            .queues
+            .entry(account_id)
+            .or_insert_with(|| AccountQueue {
+                pass: virtual_time,
+                stride: STRIDE / u64::from(weight),
+                waiters: VecDeque::new(),
+            });
+        if queue.waiters.len() >= capacity {
+            return Admission::Full;
+        }
+        let (turn, receiver) = oneshot::channel();
+        queue.waiters.push_back(Waiter {
+            sequence: self.sequence,
+            turn,
+        });
+        self.sequence += 1;
+        Admission::Queued(receiver)
+    }
+
+    /// Hands the slot of a packet which was processed to the next queued packet, from the
+    /// account with the lowest virtual time
+    fn release(&mut self) {
+        loop {
+            let next = self
+                .queues
+                .iter()
+                .filter_map(|(id, queue)| {
+                    queue
+                        .waiters
+                        .front()
+                        .map(|waiter| (queue.pass, waiter.sequence, *id))
+                })
+                .min();
+            let account_id = match next {
+                Some((_, _, account_id)) => account_id,
+                None => {
+                    self.in_flight = self.in_flight.saturating_sub(1);
+                    return;
+                }
+            };
+
+            let queue = self.queues.get_mut(&account_id).unwrap();
+            let waiter = queue.waiters.pop_front().unwrap();
+            self.virtual_time = queue.pass;
+            queue.pass += queue.stride;
+            if queue.waiters.is_empty() {
+                self.queues.remove(&account_id);
+            }
+            // Packets whose futures were dropped while they waited are skipped
+            if waiter.turn.send(()).is_ok() {
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use interledger_errors::AddressStoreError;
+    use interledger_packet::{Address, FulfillBuilder, PrepareBuilder};
+    use interledger_service::Username;
+    use std::str::FromStr;
+    use std::time::{Duration, SystemTime};
+    use tokio::sync::Semaphore;
+
+    #[tokio::test]
+    async fn forwards_when_disabled() {
+        let (service, log, gate) = test_service(FairQueueConfig::default());
+        gate.add_permits(1);
+        let result = service.clone().handle_request(request("alice", 1)).await;
+        assert!(result.is_ok());
+        assert_eq!(*log.lock().unwrap(), vec!["alice"]);
+    }
+
+    #[tokio::test]
+    async fn rejects_when_queue_is_full() {
+        let (service, log, gate) = test_service(FairQueueConfig {
+            max_concurrent: 1,
+            queue_capacity: 1,
+            ..Default::default()
+        });
+        let first = spawn(&service, "alice", 1).await;
+        let queued = spawn(&service, "alice", 2).await;
+
+        let reject = service
+            .clone()
+            .handle_request(request("alice", 3))
+            .await
+            .unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::T03_CONNECTOR_BUSY);
+        // Other accounts still have room in their queues
+        let other = spawn(&service, "bob", 1).await;
+
+        gate.add_permits(3);
+        for handle in [first, queued, other] {
+            assert!(handle.await.unwrap().is_ok());
+        }
+        assert_eq!(*log.lock().unwrap(), vec!["alice", "alice", "bob"]);
+    }
+
+    #[tokio::test]
+    async fn shares_processing_between_accounts() {
+        let (service, log, gate) = test_service(FairQueueConfig {
+            max_concurrent: 1,
+            ..Default::default()
+        });
+        // Alice floods the node before Bob sends anything
+        let mut handles = Vec::new();
+        for amount in 0..5 {
+            handles.push(spawn(&service, "alice", amount).await);
+        }
+        for amount in 0..2 {
+            handles.push(spawn(&service, "bob", amount).await);
+        }
+
+        gate.add_permits(handles.len());
+        for handle in handles {
+            assert!(handle.await.unwrap().is_ok());
+        }
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec!["alice", "alice", "bob", "alice", "bob", "alice", "alice"]
+        );
+    }
+
+    #[tokio::test]
+    async fn applies_weights() {
+        let mut weights = HashMap::new();
+        weights.insert("Bob".to_owned(), 2);
+        let (service, log, gate) = test_service(FairQueueConfig {
+            max_concurrent: 1,
+            weights,
+            ..Default::default()
+        });
+        let mut handles = vec![spawn(&service, "carl", 0).await];
+        for amount in 0..4 {
+            handles.push(spawn(&service, "alice", amount).await);
+        }
+        for amount in 0..4 {
+            handles.push(spawn(&service, "bob", amount).await);
+        }
+
+        gate.add_permits(handles.len());
+        for handle in handles {
+            assert!(handle.await.unwrap().is_ok());
+        }
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec!["carl", "alice", "bob", "bob", "alice", "bob", "bob", "alice", "alice"]
+        );
+    }
+
+    type Log = Arc<Mutex<Vec<String>>>;
+
+    #[derive(Clone)]
+    struct GatedService {
+        log: Log,
+        gate: Arc<Semaphore>,
+    }
+
+    #[async_trait]
+    impl IncomingService<TestAccount> for GatedService {
+        async fn handle_request(&mut self, request: IncomingRequest<TestAccount>) -> IlpResult {
+            self.log
+                .lock()
+                .unwrap()
+                .push(request.from.username.to_string());
+            self.gate.acquire().await.forget();
+            Ok(FulfillBuilder {
+                fulfillment: &[0; 32],
+                data: &[],
+            }
+            .build())
+        }
+    }
+
+    fn test_service(
+        config: FairQueueConfig,
+    ) -> (
+        FairQueueService<TestStore, GatedService>,
+        Log,
+        Arc<Semaphore>,
+    ) {
+        let log = Log::default();
+        let gate = Arc::new(Semaphore::new(0));
+        let next = GatedService {
+            log: log.clone(),
+            gate: gate.clone(),
+        };
+        (FairQueueService::new(config, TestStore, next), log, gate)
+    }
+
+    /// Sends the request in the background, once the previous ones were admitted or queued
+    async fn spawn(
+        service: &FairQueueService<TestStore, GatedService>,
+        username: &str,
+        amount: u64,
+    ) -> tokio::task::JoinHandle<IlpResult> {
+        let mut service = service.clone();
+        let request = request(username, amount);
+        let handle = tokio::spawn(async move { service.handle_request(request).await });
+        tokio::time::delay_for(Duration::from_millis(5)).await;
+        handle
+    }
+
+    fn request(username: &str, amount: u64) -> IncomingRequest<TestAccount> {
+        // The accounts are told apart by their ids, which are derived from their usernames
+        let mut id = [0; 16];
+        id[..username.len()].copy_from_slice(username.as_bytes());
+        IncomingRequest {
+            from: TestAccount {
+                id: Uuid::from_bytes(id),
+                username: Username::from_str(username).unwrap(),
+            },
+            prepare: PrepareBuilder {
+                destination: Address::from_str("example.destination").unwrap(),
+                amount,
+                expires_at: SystemTime::now() + Duration::from_secs(30),
+                execution_condition: &[0; 32],
+                data: &[],
+            }
+            .build(),
+        }
+    }
+
+    #[derive(Clone, Debug)]
+    struct TestAccount {
+        id: Uuid,
+        username: Username,
+    }
+
+    impl Account for TestAccount {
+        fn id(&self) -> Uuid {
+            self.id
+        }
+
+        fn username(&self) -> &Username {
+            &self.username
+        }
+
+        fn asset_code(&self) -> &str {
+            "XYZ"
+        }
+
+        fn asset_scale(&self) -> u8 {
+            9
+        }
+
+        fn ilp_address(&self) -> &Address {
+            unimplemented!()
+        }
+    }
+
+    #[derive(Clone)]
+    struct TestStore;
+
+    #[async_trait]
+    impl AddressStore for TestStore {
+        async fn set_ilp_address(&self, _: Address) -> Result<(), AddressStoreError> {
+            unimplemented!()
+        }
+
+        async fn clear_ilp_address(&self) -> Result<(), AddressStoreError> {
+            unimplemented!()
+        }
+
+        fn get_ilp_address(&self) -> Address {
+            Address::from_str("example.connector").unwrap()
+        }
+    }
+}
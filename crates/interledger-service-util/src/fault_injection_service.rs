@@ -0,0 +1,561 @@
+use async_trait::async_trait;
+use interledger_packet::{ErrorCode, Prepare, PrepareBuilder, RejectBuilder};
+use interledger_service::*;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tokio::time::delay_for;
+use tracing::debug;
+use uuid::Uuid;
+
+fn default_delay() -> u64 {
+    1000
+}
+
+/// Fractions of the packets of an account which are dropped, delayed, duplicated
+/// or corrupted. At most one fault is injected into each packet.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct FaultConfig {
+    /// Fraction of the packets which are never forwarded. They are rejected with
+    /// `R00: Transfer Timed Out` once they expire, as if they were lost.
+    #[serde(default)]
+    pub drop: f64,
+    /// Fraction of the packets which are forwarded after `delay_ms`
+    #[serde(default)]
+    pub delay: f64,
+    /// How long the delayed packets are held, in milliseconds. Defaults to 1000.
+    #[serde(default = "default_delay")]
+    pub delay_ms: u64,
+    /// Fraction of the packets which are forwarded twice. The response to the copy is discarded.
+    #[serde(default)]
+    pub duplicate: f64,
+    /// Fraction of the packets which are forwarded with a byte of their data flipped
+    /// (or of their condition, if they have no data)
+    #[serde(default)]
+    pub corrupt: f64,
+}
+
+impl Default for FaultConfig {
+    fn default() -> Self {
+        FaultConfig {
+            drop: 0.0,
+            delay: 0.0,
+            delay_ms: default_delay(),
+            duplicate: 0.0,
+            corrupt: 0.0,
+        }
+    }
+}
+
+impl FaultConfig {
+    /// Checks that the fractions are between 0 and 1, and that they add up to at most 1
+    pub fn validate(&self) -> Result<(), String> {
+        let fractions = [
+            ("drop", self.drop),
+            ("delay", self.delay),
+            ("duplicate", self.duplicate),
+            ("corrupt", self.corrupt),
+        ];
+        for (name, fraction) in fractions.iter() {
+            if !(0.0..=1.0).contains(fraction) {
+                return Err(format!("{} must be between 0 and 1", name));
+            }
+        }
+        if fractions.iter().map(|(_, fraction)| fraction).sum::<f64>() > 1.0 {
+            return Err("the fractions cannot add up to more than 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// The faults configured for an account, along with the number of packets
+/// they were injected into
+#[derive(Clone, Debug, Serialize)]
+pub struct FaultReport {
+    pub config: FaultConfig,
+    /// Number of packets of the account which went through the fault injection
+    pub packets: u64,
+    pub dropped: u64,
+    pub delayed: u64,
+    pub duplicated: u64,
+    pub corrupted: u64,
+}
+
+impl FaultReport {
+    fn new(config: FaultConfig) -> Self {
+        FaultReport {
+            config,
+            packets: 0,
+            dropped: 0,
+            delayed: 0,
+            duplicated: 0,
+            corrupted: 0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Fault {
+    Drop,
+    Delay(Duration),
+    Duplicate,
+    Corrupt,
+}
+
+/// The faults injected into the packets of the accounts by the node's
+/// `FaultInjectionService`s. The packets of the accounts without faults are untouched.
+#[derive(Clone, Default)]
+pub struct FaultInjector(Arc<Mutex<HashMap<Uuid, FaultReport>>>);
+
+impl FaultInjector {
+    /// Injects the configured faults into the packets of the account from now on,
+    /// replacing its previous configuration and resetting its counts
+    pub fn set(&self, account_id: Uuid, config: FaultConfig) -> FaultReport {
+        let report = FaultReport::new(config);
+        self.0.lock().unwrap().insert(account_id, report.clone());
+        report
+    }
+
+    /// Returns the faults of the account, if any were configured
+    pub fn get(&self, account_id: Uuid) -> Option<FaultReport> {
+        self.0.lock().unwrap().get(&account_id).cloned()
+    }
+
+    /// Stops injecting faults into the packets of the account and returns its last report
+    pub fn clear(&self, account_id: Uuid) -> Option<FaultReport> {
+        self.0.lock().unwrap().remove(&account_id)
+    }
+
+    /// Picks the fault, if any, to inject into the next packet of the account
+    fn pick(&self, account_id: Uuid) -> Option<Fault> {
+        let mut faults = self.0.lock().unwrap();
+        let report = faults.get_mut(&account_id)?;
+        report.packets += 1;
+        let config = &report.config;
+        let draw = random_fraction();
+        let fault = if draw < config.drop {
+            report.dropped += 1;
+            Fault::Drop
+        } else if draw < config.drop + config.delay {
+            report.delayed += 1;
+            Fault::Delay(Duration::from_millis(config.delay_ms))
+        } else if draw < config.drop + config.delay + config.duplicate {
+            report.duplicated += 1;
+            Fault::Duplicate
+        } else if draw < config.drop + config.delay + config.duplicate + config.corrupt {
+            report.corrupted += 1;
+            Fault::Corrupt
+        } else {
+            return None;
+        };
+        debug!(
+            "Injecting {:?} into a packet of account {}",
+            fault, account_id
+        );
+        Some(fault)
+    }
+}
+
+fn random_u32() -> u32 {
+    let mut bytes = [0; 4];
+    // The system's randomness only fails if it is unavailable, in which case the
+    // packets are left alone
+    if SystemRandom::new().fill(&mut bytes).is_err() {
+        return u32::MAX;
+    }
+    u32::from_be_bytes(bytes)
+}
+
+/// Returns a random number in [0, 1)
+fn random_fraction() -> f64 {
+    f64::from(random_u32()) / (f64::from(u32::MAX) + 1.0)
+}
+
+/// Returns a copy of the packet with a random byte of its data (or of its condition,
+/// if it has no data) flipped
+fn corrupt(prepare: &Prepare) -> Prepare {
+    let mut data = prepare.data().to_vec();
+    let mut condition = [0; 32];
+    condition.copy_from_slice(prepare.execution_condition());
+    let bytes: &mut [u8] = if data.is_empty() {
+        &mut condition
+    } else {
+        &mut data
+    };
+    let index = random_u32() as usize % bytes.len();
+    bytes[index] ^= 0xff;
+    PrepareBuilder {
+        amount: prepare.amount(),
+        expires_at: prepare.expires_at(),
+        execution_condition: &condition,
+        destination: prepare.destination(),
+        data: &data,
+    }
+    .build()
+}
+
+/// # Fault Injection Service
+///
+/// Incoming or Outgoing Service which drops, delays, duplicates or corrupts a fraction
+/// of the packets received from (incoming) or sent to (outgoing) the accounts, as
+/// configured in the `FaultInjector`, to test how the senders cope with an unreliable
+/// network. Forwards everything else.
+///
+/// It is only meant for test networks.
+/// Requires an `AddressStore`
+#[derive(Clone)]
+pub struct FaultInjectionService<IO, S, A> {
+    injector: FaultInjector,
+    store: S,
+    next: IO,
+    account_type: PhantomData<A>,
+}
+
+impl<IO, S, A> FaultInjectionService<IO, S, A>
+where
+    S: AddressStore,
+    A: Account,
+{
+    /// Constructs a service which injects the faults configured in `injector`
+    pub fn new(injector: FaultInjector, store: S, next: IO) -> Self {
+        FaultInjectionService {
+            injector,
+            store,
+            next,
+            account_type: PhantomData,
+        }
+    }
+
+    /// Waits until the packet expires and rejects it, as the node would if it was lost
+    async fn time_out(&self, prepare: &Prepare) -> IlpResult {
+        let time_left = prepare
+            .expires_at()
+            .duration_since(SystemTime::now())
+            .unwrap_or_default();
+        delay_for(time_left).await;
+        Err(RejectBuilder {
+            code: ErrorCode::R00_TRANSFER_TIMED_OUT,
+            message: b"Packet dropped by fault injection",
+            triggered_by: Some(&self.store.get_ilp_address()),
+            data: &[],
+        }
+        .build())
+    }
+}
+
+#[async_trait]
+impl<I, S, A> IncomingService<A> for FaultInjectionService<I, S, A>
+where
+    I: IncomingService<A> + Clone + Send + Sync + 'static,
+    S: AddressStore + Send + Sync + 'static,
+    A: Account + Send + Sync + 'static,
+{
+    async fn handle_request(&mut self, mut request: IncomingRequest<A>) -> IlpResult {
+        match self.injector.pick(request.from.id()) {
+            None => {}
+            Some(Fault::Drop) => return self.time_out(&request.prepare).await,
+            Some(Fault::Delay(delay)) => delay_for(delay).await,
+            Some(Fault::Duplicate) => {
+                let mut next = self.next.clone();
+                let duplicate = request.clone();
+                tokio::spawn(async move { next.handle_request(duplicate).await });
+            }
+            Some(Fault::Corrupt) => {
+                request.prepare = corrupt(&request.prepare);
+            }
+        }
+        self.next.handle_request(request).await
+    }
+}
+
+#[async_trait]
+impl<O, S, A> OutgoingService<A> for FaultInjectionService<O, S, A>
+where
+    O: OutgoingService<A> + Clone + Send + Sync + 'static,
+    S: AddressStore + Send + Sync + 'static,
+    A: Account + Send + Sync + 'static,
+{
+    async fn send_request(&mut self, mut request: OutgoingRequest<A>) -> IlpResult {
+        match self.injector.pick(request.to.id()) {
+            None => {}
+            Some(Fault::Drop) => return self.time_out(&request.prepare).await,
+            Some(Fault::Delay(delay)) => delay_for(delay).await,
+            Some(Fault::Duplicate) => {
+                let mut next = self.next.clone();
+                let duplicate = request.clone();
+                tokio::spawn(async move { next.send_request(duplicate).await });
+            }
+            Some(Fault::Corrupt) => {
+                request.prepare = corrupt(&request.prepare);
+            }
+        }
+        self.next.send_request(request).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use interledger_errors::AddressStoreError;
+    use interledger_packet::{Address, FulfillBuilder};
+    use once_cell::sync::Lazy;
+    use std::str::FromStr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Instant;
+
+    #[derive(Clone, Debug)]
+    struct TestAccount(Uuid);
+
+    static ALICE: Lazy<Username> = Lazy::new(|| Username::from_str("alice").unwrap());
+    static EXAMPLE_ADDRESS: Lazy<Address> =
+        Lazy::new(|| Address::from_str("example.alice").unwrap());
+
+    impl Account for TestAccount {
+        fn id(&self) -> Uuid {
+            self.0
+        }
+
+        fn username(&self) -> &Username {
+            &ALICE
+        }
+
+        fn asset_scale(&self) -> u8 {
+            9
+        }
+
+        fn asset_code(&self) -> &str {
+            "XYZ"
+        }
+
+        fn ilp_address(&self) -> &Address {
+            &EXAMPLE_ADDRESS
+        }
+    }
+
+    #[derive(Clone)]
+    struct TestStore;
+
+    #[async_trait]
+    impl AddressStore for TestStore {
+        async fn set_ilp_address(&self, _ilp_address: Address) -> Result<(), AddressStoreError> {
+            unimplemented!()
+        }
+
+        async fn clear_ilp_address(&self) -> Result<(), AddressStoreError> {
+            unimplemented!()
+        }
+
+        fn get_ilp_address(&self) -> Address {
+            Address::from_str("example.connector").unwrap()
+        }
+    }
+
+    fn request(account: TestAccount, expires_in: Duration) -> IncomingRequest<TestAccount> {
+        IncomingRequest {
+            from: account,
+            prepare: PrepareBuilder {
+                destination: Address::from_str("example.destination").unwrap(),
+                amount: 100,
+                expires_at: SystemTime::now() + expires_in,
+                execution_condition: &[0; 32],
+                data: b"data",
+            }
+            .build(),
+        }
+    }
+
+    fn test_service(
+        injector: FaultInjector,
+        forwarded: Arc<Mutex<Vec<Vec<u8>>>>,
+    ) -> FaultInjectionService<impl IncomingService<TestAccount> + Clone, TestStore, TestAccount>
+    {
+        FaultInjectionService::new(
+            injector,
+            TestStore,
+            incoming_service_fn(move |request| {
+                forwarded
+                    .lock()
+                    .unwrap()
+                    .push(request.prepare.data().to_vec());
+                Ok(FulfillBuilder {
+                    fulfillment: &[0; 32],
+                    data: &[],
+                }
+                .build())
+            }),
+        )
+    }
+
+    #[test]
+    fn validates_fractions() {
+        assert!(FaultConfig::default().validate().is_ok());
+        let config = FaultConfig {
+            drop: 0.5,
+            corrupt: 0.5,
+            ..FaultConfig::default()
+        };
+        assert!(config.validate().is_ok());
+        let config = FaultConfig {
+            delay: 1.5,
+            ..FaultConfig::default()
+        };
+        assert_eq!(
+            config.validate().unwrap_err(),
+            "delay must be between 0 and 1"
+        );
+        let config = FaultConfig {
+            drop: 0.6,
+            duplicate: 0.6,
+            ..FaultConfig::default()
+        };
+        assert!(config.validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn forwards_packets_of_accounts_without_faults() {
+        let injector = FaultInjector::default();
+        let account = TestAccount(Uuid::new_v4());
+        injector.set(
+            Uuid::new_v4(),
+            FaultConfig {
+                drop: 1.0,
+                ..FaultConfig::default()
+            },
+        );
+        let forwarded = Arc::new(Mutex::new(Vec::new()));
+        let mut service = test_service(injector, forwarded.clone());
+
+        service
+            .handle_request(request(account, Duration::from_secs(30)))
+            .await
+            .unwrap();
+        assert_eq!(*forwarded.lock().unwrap(), vec![b"data".to_vec()]);
+    }
+
+    #[tokio::test]
+    async fn drops_packets_until_they_expire() {
+        let injector = FaultInjector::default();
+        let account = TestAccount(Uuid::new_v4());
+        injector.set(
+            account.id(),
+            FaultConfig {
+                drop: 1.0,
+                ..FaultConfig::default()
+            },
+        );
+        let forwarded = Arc::new(Mutex::new(Vec::new()));
+        let mut service = test_service(injector.clone(), forwarded.clone());
+
+        let started_at = Instant::now();
+        let reject = service
+            .handle_request(request(account.clone(), Duration::from_millis(100)))
+            .await
+            .unwrap_err();
+        assert!(started_at.elapsed() >= Duration::from_millis(90));
+        assert_eq!(reject.code(), ErrorCode::R00_TRANSFER_TIMED_OUT);
+        assert_eq!(
+            reject.triggered_by().unwrap(),
+            Address::from_str("example.connector").unwrap()
+        );
+        assert!(forwarded.lock().unwrap().is_empty());
+
+        let report = injector.get(account.id()).unwrap();
+        assert_eq!(report.packets, 1);
+        assert_eq!(report.dropped, 1);
+    }
+
+    #[tokio::test]
+    async fn delays_packets() {
+        let injector = FaultInjector::default();
+        let account = TestAccount(Uuid::new_v4());
+        injector.set(
+            account.id(),
+            FaultConfig {
+                delay: 1.0,
+                delay_ms: 50,
+                ..FaultConfig::default()
+            },
+        );
+        let forwarded = Arc::new(Mutex::new(Vec::new()));
+        let mut service = test_service(injector.clone(), forwarded.clone());
+
+        let started_at = Instant::now();
+        service
+            .handle_request(request(account.clone(), Duration::from_secs(30)))
+            .await
+            .unwrap();
+        assert!(started_at.elapsed() >= Duration::from_millis(50));
+        assert_eq!(forwarded.lock().unwrap().len(), 1);
+        assert_eq!(injector.get(account.id()).unwrap().delayed, 1);
+    }
+
+    #[tokio::test]
+    async fn duplicates_packets() {
+        let injector = FaultInjector::default();
+        let account = TestAccount(Uuid::new_v4());
+        injector.set(
+            account.id(),
+            FaultConfig {
+                duplicate: 1.0,
+                ..FaultConfig::default()
+            },
+        );
+        let count = Arc::new(AtomicUsize::new(0));
+        let count_clone = count.clone();
+        let mut service = FaultInjectionService::new(
+            injector,
+            TestStore,
+            incoming_service_fn(move |_| {
+                count_clone.fetch_add(1, Ordering::SeqCst);
+                Ok(FulfillBuilder {
+                    fulfillment: &[0; 32],
+                    data: &[],
+                }
+                .build())
+            }),
+        );
+
+        service
+            .handle_request(request(account, Duration::from_secs(30)))
+            .await
+            .unwrap();
+        // Lets the spawned copy run
+        delay_for(Duration::from_millis(10)).await;
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn corrupts_packets() {
+        let injector = FaultInjector::default();
+        let account = TestAccount(Uuid::new_v4());
+        injector.set(
+            account.id(),
+            FaultConfig {
+                corrupt: 1.0,
+                ..FaultConfig::default()
+            },
+        );
+        let forwarded = Arc::new(Mutex::new(Vec::new()));
+        let mut service = test_service(injector.clone(), forwarded.clone());
+
+        service
+            .handle_request(request(account.clone(), Duration::from_secs(30)))
+            .await
+            .unwrap();
+        let forwarded = forwarded.lock().unwrap();
+        assert_eq!(forwarded[0].len(), 4);
+        let flipped = forwarded[0]
+            .iter()
+            .zip(b"data".iter())
+            .filter(|(a, b)| a != b)
+            .count();
+        assert_eq!(flipped, 1);
+
+        let report = injector.clear(account.id()).unwrap();
+        assert_eq!(report.corrupted, 1);
+        assert!(injector.get(account.id()).is_none());
+    }
+}
@@ -0,0 +1,222 @@
+use interledger_errors::FeePolicyStoreError;
+use interledger_service::Account;
+use interledger_settlement::core::types::{Convert, ConvertDetails};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tracing::warn;
+
+/// Spread and fees charged on the packets converted by the
+/// [`ExchangeRateService`](./struct.ExchangeRateService.html).
+///
+/// Policies are set for the node, for asset pairs and for accounts. Fields which are not
+/// set in a policy fall back to the next, less specific one: the incoming account's policy
+/// takes precedence over the policy of the asset pair, which takes precedence over the node's.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct FeePolicy {
+    /// Spread, as a fraction, subtracted from the exchange rate
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spread: Option<f64>,
+    /// Fee, as a fraction of the converted amount
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub percentage_fee: Option<f64>,
+    /// Fee charged on each packet, in the outgoing account's units (at its asset scale)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fixed_fee: Option<u64>,
+}
+
+impl FeePolicy {
+    /// Returns this policy with the fields which are not set taken from `other`
+    pub fn or(self, other: FeePolicy) -> FeePolicy {
+        FeePolicy {
+            spread: self.spread.or(other.spread),
+            percentage_fee: self.percentage_fee.or(other.percentage_fee),
+            fixed_fee: self.fixed_fee.or(other.fixed_fee),
+        }
+    }
+
+    /// Converts `amount` using the `rate` (the price of the incoming asset in units of
+    /// the outgoing asset) and deducts the fees. Fails if the converted amount overflows.
+    #[allow(clippy::result_unit_err)]
+    pub fn quote(&self, amount: u64, rate: f64, details: ConvertDetails) -> Result<Quote, ()> {
+        let rate = rate * (1.0 - self.spread.unwrap_or(0.0));
+        let rate = if rate.is_finite() && rate.is_sign_positive() {
+            rate
+        } else {
+            warn!(
+                "Exchange rate would have been {} based on rate and spread, using 0.0 instead",
+                rate
+            );
+            0.0
+        };
+
+        // Can we overflow here?
+        let converted_amount = (amount as f64 * rate).normalize_scale(details)?;
+        let fee = converted_amount * self.percentage_fee.unwrap_or(0.0)
+            + self.fixed_fee.unwrap_or(0) as f64;
+        Ok(Quote {
+            rate,
+            converted_amount,
+            fee,
+            destination_amount: converted_amount - fee,
+        })
+    }
+}
+
+/// The result of converting an amount with a [`FeePolicy`](./struct.FeePolicy.html)
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Quote {
+    /// Exchange rate, after the spread
+    pub rate: f64,
+    /// Converted amount, before the fees are deducted (in the outgoing units)
+    pub converted_amount: f64,
+    /// Fees deducted from the converted amount (in the outgoing units)
+    pub fee: f64,
+    /// Amount which is forwarded (in the outgoing units). This is not rounded
+    /// and may be negative if the fees exceed the converted amount
+    pub destination_amount: f64,
+}
+
+/// Why a converted packet is rejected instead of being forwarded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuoteRejection {
+    /// The fees are larger than the converted amount
+    FeesExceedAmount,
+    /// The converted amount rounds down to zero
+    AmountTooSmall,
+    /// The converted amount cannot be represented by a packet
+    AmountTooLarge,
+}
+
+impl Quote {
+    /// Returns the amount of the forwarded packet, which is the destination amount rounded
+    /// down, or why the packet is rejected instead
+    pub fn outgoing_amount(&self) -> Result<u64, QuoteRejection> {
+        let amount = self.destination_amount;
+        if self.fee > 0.0 && amount < 1.0 {
+            Err(QuoteRejection::FeesExceedAmount)
+        // FIXME: f64 > u64::MAX as f64 isn't very reliable for comparisons for
+        // extremely small values; this should ideally be handled better.
+        } else if amount != 0.0 && amount > u64::MAX as f64 {
+            Err(QuoteRejection::AmountTooLarge)
+        } else if amount != 0.0 && amount < 1.0 {
+            Err(QuoteRejection::AmountTooSmall)
+        } else {
+            Ok(amount as u64)
+        }
+    }
+}
+
+/// Extension trait for [`Account`](../interledger_service/trait.Account.html) with the account's fee policy
+pub trait FeeAccount: Account {
+    /// The policy applied to the packets sent by this account
+    fn fee_policy(&self) -> FeePolicy {
+        FeePolicy::default()
+    }
+}
+
+/// Store for the fee policies of asset pairs
+pub trait FeePolicyStore {
+    /// Gets the policy for packets converted from the `from` asset to the `to` asset, if there is one
+    fn get_fee_policy(&self, from: &str, to: &str) -> Option<FeePolicy>;
+
+    /// Gets the policies of all asset pairs, keyed by `FROM/TO`
+    fn get_all_fee_policies(&self) -> Result<HashMap<String, FeePolicy>, FeePolicyStoreError>;
+
+    /// Replaces the policies of all asset pairs (keyed by `FROM/TO`)
+    fn set_fee_policies(
+        &self,
+        policies: HashMap<String, FeePolicy>,
+    ) -> Result<(), FeePolicyStoreError>;
+}
+
+/// Formats the key of an asset pair's fee policy
+pub fn fee_policy_pair(from: &str, to: &str) -> String {
+    format!("{}/{}", from, to)
+}
+
+/// Returns the policy which applies to packets sent by the `from` account
+/// to the `to` account, with `node_policy` as the fallback
+pub fn resolve_fee_policy<S, A>(store: &S, node_policy: FeePolicy, from: &A, to: &A) -> FeePolicy
+where
+    S: FeePolicyStore,
+    A: FeeAccount,
+{
+    let pair_policy = store
+        .get_fee_policy(from.asset_code(), to.asset_code())
+        .unwrap_or_default();
+    from.fee_policy().or(pair_policy).or(node_policy)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAME_SCALE: ConvertDetails = ConvertDetails { from: 2, to: 2 };
+
+    #[test]
+    fn more_specific_policies_take_precedence() {
+        let account = FeePolicy {
+            fixed_fee: Some(1),
+            ..Default::default()
+        };
+        let pair = FeePolicy {
+            spread: Some(0.02),
+            fixed_fee: Some(5),
+            ..Default::default()
+        };
+        let node = FeePolicy {
+            spread: Some(0.01),
+            percentage_fee: Some(0.003),
+            fixed_fee: None,
+        };
+        assert_eq!(
+            account.or(pair).or(node),
+            FeePolicy {
+                spread: Some(0.02),
+                percentage_fee: Some(0.003),
+                fixed_fee: Some(1),
+            }
+        );
+    }
+
+    #[test]
+    fn deducts_fees_from_converted_amount() {
+        let policy = FeePolicy {
+            spread: Some(0.5),
+            percentage_fee: Some(0.25),
+            fixed_fee: Some(9),
+        };
+        let quote = policy.quote(1000, 2.0, SAME_SCALE).unwrap();
+        assert_eq!(quote.rate, 1.0);
+        assert_eq!(quote.converted_amount, 1000.0);
+        assert_eq!(quote.fee, 259.0);
+        assert_eq!(quote.destination_amount, 741.0);
+    }
+
+    #[test]
+    fn fees_may_exceed_amount() {
+        let policy = FeePolicy {
+            fixed_fee: Some(10),
+            ..Default::default()
+        };
+        let quote = policy.quote(5, 1.0, SAME_SCALE).unwrap();
+        assert_eq!(quote.destination_amount, -5.0);
+        assert_eq!(
+            quote.outgoing_amount(),
+            Err(QuoteRejection::FeesExceedAmount)
+        );
+    }
+
+    #[test]
+    fn rounds_down_outgoing_amounts() {
+        let policy = FeePolicy::default();
+        let quote = policy.quote(1000, 0.0015, SAME_SCALE).unwrap();
+        assert_eq!(quote.outgoing_amount(), Ok(1));
+        let quote = policy.quote(1000, 0.0005, SAME_SCALE).unwrap();
+        assert_eq!(quote.outgoing_amount(), Err(QuoteRejection::AmountTooSmall));
+        let quote = policy.quote(0, 2.0, SAME_SCALE).unwrap();
+        assert_eq!(quote.outgoing_amount(), Ok(0));
+        let quote = policy.quote(u64::MAX, 2.0, SAME_SCALE).unwrap();
+        assert_eq!(quote.outgoing_amount(), Err(QuoteRejection::AmountTooLarge));
+    }
+}
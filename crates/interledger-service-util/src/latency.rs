@@ -0,0 +1,300 @@
+use crate::echo_service::EchoPings;
+use futures::future::join_all;
+use interledger_packet::Address;
+use interledger_service::{clock, Account, OutgoingService};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::{Arc, RwLock},
+    time::{Duration, UNIX_EPOCH},
+};
+use tracing::debug;
+use uuid::Uuid;
+
+/// Number of the latest probes of each account which its statistics are computed over
+const PROBE_WINDOW: usize = 20;
+
+fn default_probe_interval() -> u64 {
+    30000
+}
+
+fn default_probe_timeout() -> u64 {
+    5000
+}
+
+/// Configuration of the probes which measure the latency of the peers
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct LatencyProbeConfig {
+    /// Interval, in milliseconds, between the probes of each peer. Defaults to 30000ms
+    /// (30 seconds).
+    #[serde(default = "default_probe_interval")]
+    pub interval: u64,
+    /// Time, in milliseconds, after which a probe which was not answered fails. Defaults
+    /// to 5000ms (5 seconds).
+    #[serde(default = "default_probe_timeout")]
+    pub timeout: u64,
+    /// Whether the routes which are otherwise equal (with the same routing relation and
+    /// path length) are chosen by the average round trip time of their next hop.
+    /// Defaults to false.
+    #[serde(default)]
+    pub route_tiebreaker: bool,
+}
+
+/// Statistics of the latest probes of an account. The times are in milliseconds.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct LatencyStats {
+    /// Number of probes the statistics are computed over (the latest ones)
+    pub probes: usize,
+    /// How many of them were rejected or not answered in time
+    pub failures: usize,
+    /// Round trip time of the last answered probe
+    pub last_rtt: Option<f64>,
+    pub min_rtt: Option<f64>,
+    pub avg_rtt: Option<f64>,
+    pub max_rtt: Option<f64>,
+    /// Average difference between the round trip times of consecutive answered probes
+    pub jitter: Option<f64>,
+    /// When the last probe was sent, in milliseconds since the UNIX epoch
+    pub last_probe: u64,
+}
+
+#[derive(Default)]
+struct Probes {
+    /// The round trip times of the latest probes, or `None` for those which failed
+    round_trip_times: VecDeque<Option<Duration>>,
+    last_probe: u64,
+}
+
+impl Probes {
+    fn stats(&self) -> LatencyStats {
+        let rtts: Vec<f64> = self
+            .round_trip_times
+            .iter()
+            .flatten()
+            .map(|rtt| rtt.as_secs_f64() * 1000.0)
+            .collect();
+        let average = |values: &[f64]| {
+            if values.is_empty() {
+                None
+            } else {
+                Some(values.iter().sum::<f64>() / values.len() as f64)
+            }
+        };
+        let differences: Vec<f64> = rtts
+            .windows(2)
+            .map(|pair| (pair[1] - pair[0]).abs())
+            .collect();
+        LatencyStats {
+            probes: self.round_trip_times.len(),
+            failures: self
+                .round_trip_times
+                .iter()
+                .filter(|rtt| rtt.is_none())
+                .count(),
+            last_rtt: rtts.last().cloned(),
+            min_rtt: rtts.iter().cloned().fold(None, |min, rtt| {
+                Some(min.map_or(rtt, |min: f64| min.min(rtt)))
+            }),
+            avg_rtt: average(&rtts),
+            max_rtt: rtts.iter().cloned().fold(None, |max, rtt| {
+                Some(max.map_or(rtt, |max: f64| max.max(rtt)))
+            }),
+            jitter: average(&differences),
+            last_probe: self.last_probe,
+        }
+    }
+}
+
+/// The round trip times of the probes sent to the peers, by account
+#[derive(Clone, Default)]
+pub struct PeerLatencies(Arc<RwLock<HashMap<Uuid, Probes>>>);
+
+impl PeerLatencies {
+    /// Records the outcome of a probe of the account: its round trip time, or `None` if
+    /// it failed
+    pub fn record(&self, account_id: Uuid, round_trip_time: Option<Duration>) {
+        let now = clock::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        let mut accounts = self.0.write().unwrap();
+        let probes = accounts.entry(account_id).or_default();
+        if probes.round_trip_times.len() == PROBE_WINDOW {
+            probes.round_trip_times.pop_front();
+        }
+        probes.round_trip_times.push_back(round_trip_time);
+        probes.last_probe = now;
+    }
+
+    /// Returns the statistics of the latest probes of the account, if it was probed
+    pub fn stats(&self, account_id: Uuid) -> Option<LatencyStats> {
+        self.0.read().unwrap().get(&account_id).map(Probes::stats)
+    }
+
+    /// Returns the average round trip time of the latest answered probes of the account
+    pub fn round_trip_time(&self, account_id: Uuid) -> Option<Duration> {
+        self.stats(account_id)?
+            .avg_rtt
+            .map(|rtt| Duration::from_secs_f64(rtt / 1000.0))
+    }
+
+    /// Probes each of the accounts with an echo request to its ILP address, sent through
+    /// the outgoing `service` on behalf of the account itself, and records the round trip
+    /// times. The echo responses are fulfilled by the `EchoService` sharing the `pings`,
+    /// at the node's `source_address`. The statistics of the accounts which are no longer
+    /// probed are dropped. Returns the number of probes which were answered.
+    pub async fn probe<O, A>(
+        &self,
+        pings: &EchoPings,
+        service: O,
+        accounts: Vec<A>,
+        source_address: &Address,
+        timeout: Duration,
+    ) -> usize
+    where
+        O: OutgoingService<A> + Clone,
+        A: Account,
+    {
+        let probed: HashSet<Uuid> = accounts.iter().map(Account::id).collect();
+        self.0
+            .write()
+            .unwrap()
+            .retain(|account_id, _| probed.contains(account_id));
+
+        let results = join_all(accounts.into_iter().map(|account| {
+            let service = service.clone();
+            async move {
+                let result = pings
+                    .ping_account(service, account.clone(), source_address, timeout)
+                    .await;
+                (account, result)
+            }
+        }))
+        .await;
+        let mut answered = 0;
+        for (account, result) in results {
+            match result {
+                Ok(round_trip_time) => {
+                    answered += 1;
+                    self.record(account.id(), Some(round_trip_time));
+                }
+                Err(reject) => {
+                    debug!(
+                        "Probe of account {} failed: {:?}",
+                        account.username(),
+                        reject
+                    );
+                    self.record(account.id(), None);
+                }
+            }
+        }
+        answered
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use interledger_packet::{ErrorCode, FulfillBuilder, RejectBuilder};
+    use interledger_service::{outgoing_service_fn, OutgoingRequest, Username};
+    use once_cell::sync::Lazy;
+    use std::str::FromStr;
+
+    static ALICE: Lazy<Username> = Lazy::new(|| Username::from_str("alice").unwrap());
+    static ALICE_ADDRESS: Lazy<Address> = Lazy::new(|| Address::from_str("example.alice").unwrap());
+    static BOB_ADDRESS: Lazy<Address> = Lazy::new(|| Address::from_str("example.bob").unwrap());
+
+    #[derive(Clone, Debug)]
+    struct TestAccount(Uuid, &'static Address);
+
+    impl Account for TestAccount {
+        fn id(&self) -> Uuid {
+            self.0
+        }
+
+        fn username(&self) -> &Username {
+            &ALICE
+        }
+
+        fn asset_code(&self) -> &str {
+            "XYZ"
+        }
+
+        fn asset_scale(&self) -> u8 {
+            9
+        }
+
+        fn ilp_address(&self) -> &Address {
+            self.1
+        }
+    }
+
+    #[test]
+    fn computes_the_statistics_of_the_latest_probes() {
+        let latencies = PeerLatencies::default();
+        let id = Uuid::new_v4();
+        assert_eq!(latencies.stats(id), None);
+
+        // The first probes fall out of the window
+        for _ in 0..PROBE_WINDOW {
+            latencies.record(id, None);
+        }
+        for rtt in &[Some(10), None, Some(30), Some(20)] {
+            latencies.record(id, rtt.map(Duration::from_millis));
+        }
+        let stats = latencies.stats(id).unwrap();
+        assert_eq!(stats.probes, PROBE_WINDOW);
+        assert_eq!(stats.failures, PROBE_WINDOW - 3);
+        assert_eq!(stats.last_rtt, Some(20.0));
+        assert_eq!(stats.min_rtt, Some(10.0));
+        assert_eq!(stats.avg_rtt, Some(20.0));
+        assert_eq!(stats.max_rtt, Some(30.0));
+        assert_eq!(stats.jitter, Some(15.0));
+        assert_eq!(
+            latencies.round_trip_time(id),
+            Some(Duration::from_millis(20))
+        );
+    }
+
+    #[tokio::test]
+    async fn probes_the_accounts() {
+        let latencies = PeerLatencies::default();
+        let gone = Uuid::new_v4();
+        latencies.record(gone, Some(Duration::from_millis(1)));
+
+        let service = outgoing_service_fn(|request: OutgoingRequest<TestAccount>| {
+            assert_eq!(request.from.id(), request.to.id());
+            assert_eq!(&request.prepare.destination(), request.to.ilp_address());
+            if request.to.ilp_address() == &*ALICE_ADDRESS {
+                Ok(FulfillBuilder {
+                    fulfillment: &[0; 32],
+                    data: &[],
+                }
+                .build())
+            } else {
+                Err(RejectBuilder {
+                    code: ErrorCode::R00_TRANSFER_TIMED_OUT,
+                    message: &[],
+                    triggered_by: None,
+                    data: &[],
+                }
+                .build())
+            }
+        });
+        let alice = TestAccount(Uuid::new_v4(), &ALICE_ADDRESS);
+        let bob = TestAccount(Uuid::new_v4(), &BOB_ADDRESS);
+        let answered = latencies
+            .probe(
+                &EchoPings::default(),
+                service,
+                vec![alice.clone(), bob.clone()],
+                &Address::from_str("example.node").unwrap(),
+                Duration::from_secs(5),
+            )
+            .await;
+        assert_eq!(answered, 1);
+        assert!(latencies.stats(alice.id()).unwrap().last_rtt.is_some());
+        assert_eq!(latencies.stats(bob.id()).unwrap().failures, 1);
+        assert_eq!(latencies.stats(gone), None);
+    }
+}
@@ -2,8 +2,13 @@
 //!
 //! Miscellaneous, small Interledger Services.
 
+/// Notifications of the balances of the accounts crossing the thresholds of the node
+mod balance_notifications;
 /// Balance tracking service
 mod balance_service;
+/// Service which answers duplicate prepare packets with the response to the first one,
+/// to defend against routing loops and packets being delivered more than once
+mod deduplication_service;
 /// Service which implements the echo protocol
 mod echo_service;
 /// Service responsible for setting and fetching dollar denominated exchange rates
@@ -11,22 +16,90 @@ mod exchange_rates_service;
 /// Service responsible for shortening the expiry time of packets,
 /// to take into account for network latency
 mod expiry_shortener_service;
+/// Service which queues the incoming packets per account and processes them
+/// by weighted fair queuing when the node is busy
+mod fair_queue_service;
+/// Service which drops, delays, duplicates or corrupts a fraction of the packets
+/// of the accounts, to test the senders' resilience
+mod fault_injection_service;
+/// Spread and fees charged when converting packets between assets
+mod fee_policy;
+/// Round trip times of the peers, measured by probing them with echo requests
+mod latency;
+/// Notifications of the peers whose packets are blocked by their accounts' receivable limits
+mod liquidity_notifications;
 /// Service responsible for capping the amount an account can send in a packet
 mod max_packet_amount_service;
+/// Service responsible for capping the size of the packets the accounts send and receive
+mod packet_size_service;
+/// Service which records the packets of the accounts in trace mode
+mod packet_trace_service;
 /// Service responsible for capping the amount of packets and amount in packets an account can send
 mod rate_limit_service;
+/// Service which sanitizes the rejects sent back to the senders of packets, so that they do not
+/// leak internal details
+mod reject_redaction_service;
+/// Scheduler which runs the periodic tasks of the node and tracks their runs
+mod scheduler;
+/// Reconciliation of the settlements recorded by the node with the totals of the settlement engines
+mod settlement_reconciliation;
+/// Service which counts the packets received by the node for the stats API
+mod stats_service;
+/// Service which caps the amounts the accounts send and receive per day and per month
+mod transfer_limit_service;
 /// Service responsible for checking that packets are not expired and that prepare packets' fulfillment conditions
 /// match the fulfillment inside the incoming fulfills
 mod validator_service;
 
-pub use self::balance_service::{BalanceService, BalanceStore};
-pub use self::echo_service::EchoService;
+pub use self::balance_notifications::{
+    BalanceChange, BalanceCondition, BalanceEvent, BalanceNotification, BalanceNotificationConfig,
+    BalanceNotifications, BalanceNotifier, BalanceThreshold,
+};
+pub use self::balance_service::{
+    send_settlement, BalanceJournalStore, BalanceService, BalanceStore, BalanceVerification,
+    CreditLimits, Escrow, Liquidity, LiquidityBlock, LiquidityStore,
+};
+pub use self::deduplication_service::DeduplicationService;
+pub use self::echo_service::{EchoPings, EchoRequestBuilder, EchoResponseBuilder, EchoService};
 pub use self::exchange_rates_service::ExchangeRateService;
 pub use self::expiry_shortener_service::{
-    ExpiryShortenerService, RoundTripTimeAccount, DEFAULT_ROUND_TRIP_TIME,
+    ExpiryShortenerService, RoundTripTimeAccount, DEFAULT_MAX_EXPIRY_DURATION,
+    DEFAULT_ROUND_TRIP_TIME,
+};
+pub use self::fair_queue_service::{FairQueueConfig, FairQueueService};
+pub use self::fault_injection_service::{
+    FaultConfig, FaultInjectionService, FaultInjector, FaultReport,
 };
+pub use self::fee_policy::{
+    fee_policy_pair, resolve_fee_policy, FeeAccount, FeePolicy, FeePolicyStore, Quote,
+    QuoteRejection,
+};
+pub use self::latency::{LatencyProbeConfig, LatencyStats, PeerLatencies};
+pub use self::liquidity_notifications::{LiquidityNotification, LiquidityNotifier};
 pub use self::max_packet_amount_service::{MaxPacketAmountAccount, MaxPacketAmountService};
+pub use self::packet_size_service::{MaxPacketSizeAccount, PacketSizeService};
+pub use self::packet_trace_service::{
+    PacketTrace, PacketTraceService, PacketTraces, TraceDirection, TracedPacket, TracedPrepare,
+    TracedResult,
+};
 pub use self::rate_limit_service::{
     RateLimitAccount, RateLimitError, RateLimitService, RateLimitStore,
 };
+pub use self::reject_redaction_service::{
+    RedactionPolicy, RejectRedactionConfig, RejectRedactionService,
+};
+pub use self::scheduler::{
+    Scheduler, SchedulerConfig, Task, TaskConfig, TaskStatus, DEFAULT_JITTER,
+};
+pub use self::settlement_reconciliation::{
+    AccountReconciliation, ReconciliationReport, SettlementReconciliation,
+};
+pub use self::stats_service::{
+    AssetVolume, Conversion, ConversionOverview, ConversionStats, DestinationCount, NodeStats,
+    PacketCounts, StatsOverview, StatsService,
+};
+pub use self::transfer_limit_service::{
+    TransferCharge, TransferLimitService, TransferLimitStore, TransferLimits, TransferUsage,
+    TransferWindow, WindowUsage,
+};
 pub use self::validator_service::ValidatorService;
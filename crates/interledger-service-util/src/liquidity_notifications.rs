@@ -0,0 +1,202 @@
+use crate::balance_service::LiquidityBlock;
+use interledger_packet::Address;
+use reqwest::Client;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc::UnboundedReceiver;
+use tracing::{debug, warn};
+use url::Url;
+use uuid::Uuid;
+
+/// Summary of the packets from an account which were rejected because the account owes the
+/// node more than its receivable limit. It is POSTed as JSON to the account's liquidity
+/// notification URL, so that its peer can settle to unblock its packets.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct LiquidityNotification {
+    /// The username of the account on the notifying node
+    pub account: String,
+    /// The ILP address of the notifying node
+    pub node: String,
+    pub asset_code: String,
+    pub asset_scale: u8,
+    /// The account's receivable limit
+    pub receivable_limit: u64,
+    /// Number of the account's packets which were rejected during the period
+    pub blocked_packets: u64,
+    /// Total amount of the rejected packets
+    pub blocked_amount: u64,
+    /// The amount the account must settle for the largest rejected packet to go through
+    pub settlement_needed: u64,
+    /// Start of the period, in milliseconds since the UNIX epoch
+    pub since: u64,
+    /// End of the period, in milliseconds since the UNIX epoch
+    pub until: u64,
+}
+
+/// Aggregates the packets blocked by the receivable limits of the accounts, and notifies
+/// the peers of the accounts with a liquidity notification URL, at most once per interval
+pub struct LiquidityNotifier {
+    node: Address,
+    interval: Duration,
+    client: Client,
+    pending: HashMap<Uuid, (Url, LiquidityNotification)>,
+}
+
+impl LiquidityNotifier {
+    pub fn new(node: Address, interval: Duration) -> Self {
+        LiquidityNotifier {
+            node,
+            interval,
+            client: Client::new(),
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Adds the blocked packet to the next notification of its account, if its peer
+    /// is to be notified
+    fn record(&mut self, block: LiquidityBlock, now: u64) {
+        let url = match block.notification_url {
+            Some(ref url) if block.kind == "receivable" => url.clone(),
+            _ => return,
+        };
+        let node = &self.node;
+        let (_, notification) = self.pending.entry(block.account_id).or_insert_with(|| {
+            (
+                url,
+                LiquidityNotification {
+                    account: block.username.to_string(),
+                    node: node.to_string(),
+                    asset_code: block.asset_code.clone(),
+                    asset_scale: block.asset_scale,
+                    receivable_limit: block.limit,
+                    blocked_packets: 0,
+                    blocked_amount: 0,
+                    settlement_needed: 0,
+                    since: now,
+                    until: now,
+                },
+            )
+        });
+        notification.receivable_limit = block.limit;
+        notification.blocked_packets += 1;
+        notification.blocked_amount = notification.blocked_amount.saturating_add(block.amount);
+        notification.settlement_needed = notification.settlement_needed.max(block.exceeded_by);
+    }
+
+    /// Returns the notifications of the packets blocked since the previous ones
+    fn take_notifications(&mut self, now: u64) -> Vec<(Url, LiquidityNotification)> {
+        self.pending
+            .drain()
+            .map(|(_, (url, mut notification))| {
+                notification.until = now;
+                (url, notification)
+            })
+            .collect()
+    }
+
+    /// Notifies the peers of the packets blocked by their accounts' receivable limits,
+    /// which are received from the channel until it is closed
+    pub fn spawn(mut self, mut blocks: UnboundedReceiver<LiquidityBlock>) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(self.interval);
+            loop {
+                tokio::select! {
+                    block = blocks.recv() => match block {
+                        Some(block) => self.record(block, now()),
+                        None => break,
+                    },
+                    _ = interval.tick() => {
+                        for (url, notification) in self.take_notifications(now()) {
+                            tokio::spawn(notify(self.client.clone(), url, notification));
+                        }
+                    }
+                }
+            }
+        });
+    }
+}
+
+async fn notify(client: Client, url: Url, notification: LiquidityNotification) {
+    debug!(
+        "Notifying the peer of account {} of {} packets blocked by its receivable limit",
+        notification.account, notification.blocked_packets
+    );
+    let result = client
+        .post(url.as_str())
+        .json(&notification)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status());
+    if let Err(err) = result {
+        warn!(
+            "Error sending the liquidity notification of account {} to {}: {}",
+            notification.account, url, err
+        );
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use interledger_service::Username;
+    use std::str::FromStr;
+
+    fn block(kind: &'static str, amount: u64, exceeded_by: u64) -> LiquidityBlock {
+        LiquidityBlock {
+            account_id: Uuid::nil(),
+            username: Username::from_str("alice").unwrap(),
+            asset_code: "XYZ".to_string(),
+            asset_scale: 9,
+            kind,
+            amount,
+            limit: 1000,
+            exceeded_by,
+            notification_url: Some(Url::parse("http://peer.example/liquidity").unwrap()),
+        }
+    }
+
+    #[test]
+    fn summarizes_the_packets_blocked_by_receivable_limits() {
+        let mut notifier = LiquidityNotifier::new(
+            Address::from_str("example.node").unwrap(),
+            Duration::from_secs(60),
+        );
+        notifier.record(block("receivable", 100, 50), 1000);
+        notifier.record(block("receivable", 300, 250), 2000);
+        // The node owing the account too much is not for its peer to settle
+        notifier.record(block("payable", 500, 500), 2000);
+        let mut without_url = block("receivable", 100, 50);
+        without_url.account_id = Uuid::new_v4();
+        without_url.notification_url = None;
+        notifier.record(without_url, 2000);
+
+        let notifications = notifier.take_notifications(3000);
+        assert_eq!(
+            notifications,
+            vec![(
+                Url::parse("http://peer.example/liquidity").unwrap(),
+                LiquidityNotification {
+                    account: "alice".to_string(),
+                    node: "example.node".to_string(),
+                    asset_code: "XYZ".to_string(),
+                    asset_scale: 9,
+                    receivable_limit: 1000,
+                    blocked_packets: 2,
+                    blocked_amount: 400,
+                    settlement_needed: 250,
+                    since: 1000,
+                    until: 3000,
+                }
+            )]
+        );
+        assert!(notifier.take_notifications(4000).is_empty());
+    }
+}
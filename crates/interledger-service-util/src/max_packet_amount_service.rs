@@ -1,6 +1,10 @@
 use async_trait::async_trait;
 use interledger_packet::{ErrorCode, MaxPacketAmountDetails, RejectBuilder};
 use interledger_service::*;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
 use tracing::debug;
 
 /// Extension trait for [`Account`](../interledger_service/trait.Account.html) with the max packet amount
@@ -15,18 +19,31 @@ pub trait MaxPacketAmountAccount: Account {
 /// Nodes may limit the packet amount for a variety of reasons:
 /// - Liquidity: a node operator may not way to allow a single high-value packet to tie up a large portion of its liquidity at once (especially because they do not know whether the packet will be fulfilled or rejected)
 /// - Security: each packet carries some risk, due to the possibility that a node's failure to pass back the fulfillment within the available time window would cause that node to lose money. Keeping the value of each individual packet low may help reduce the impact of such a failure
-/// Signaling: nodes SHOULD set the maximum packet amount _lower_ than the maximum amount in flight (also known as the payment or money bandwidth). `T04: Insufficient Liquidity` errors do not communicate to the sender how much they can send, largely because the "available liquidity" may be time based or based on the rate of other payments going through and thus difficult to communicate effectively. In contrast, the `F08: Amount Too Large` error conveys the maximum back to the sender, because this limit is assumed to be a static value, and alllows sender-side software like STREAM implementations to respond accordingly. Therefore, setting the maximum packet amount lower than the total money bandwidth allows client implementations to quickly adjust their packet amounts to appropriate levels.
+///   Signaling: nodes SHOULD set the maximum packet amount _lower_ than the maximum amount in flight (also known as the payment or money bandwidth). `T04: Insufficient Liquidity` errors do not communicate to the sender how much they can send, largely because the "available liquidity" may be time based or based on the rate of other payments going through and thus difficult to communicate effectively. In contrast, the `F08: Amount Too Large` error conveys the maximum back to the sender, because this limit is assumed to be a static value, and alllows sender-side software like STREAM implementations to respond accordingly. Therefore, setting the maximum packet amount lower than the total money bandwidth allows client implementations to quickly adjust their packet amounts to appropriate levels.
+///
+/// In addition to the per-account limit, a node-wide limit can be applied to the packets of all
+/// accounts. It is shared behind an atomic so that it can be changed while the node is running.
 /// Requires a `MaxPacketAmountAccount` and _no store_.
 #[derive(Clone)]
 pub struct MaxPacketAmountService<I, S> {
     next: I,
     store: S,
+    node_limit: Arc<AtomicU64>,
 }
 
 impl<I, S> MaxPacketAmountService<I, S> {
     /// Simple constructor
     pub fn new(store: S, next: I) -> Self {
-        MaxPacketAmountService { store, next }
+        Self::with_node_limit(store, Arc::new(AtomicU64::new(u64::MAX)), next)
+    }
+
+    /// Constructor which also caps the packets of all accounts at the value of `node_limit`
+    pub fn with_node_limit(store: S, node_limit: Arc<AtomicU64>, next: I) -> Self {
+        MaxPacketAmountService {
+            store,
+            next,
+            node_limit,
+        }
     }
 }
 
@@ -38,10 +55,13 @@ where
     A: MaxPacketAmountAccount + Send + Sync + 'static,
 {
     /// On receive request:
-    /// 1. if request.prepare.amount <= request.from.max_packet_amount forward the request, else error
+    /// 1. if request.prepare.amount <= min(request.from.max_packet_amount, node limit) forward the request, else error
     async fn handle_request(&mut self, request: IncomingRequest<A>) -> IlpResult {
         let ilp_address = self.store.get_ilp_address();
-        let max_packet_amount = request.from.max_packet_amount();
+        let max_packet_amount = request
+            .from
+            .max_packet_amount()
+            .min(self.node_limit.load(Ordering::Relaxed));
         if request.prepare.amount() <= max_packet_amount {
             self.next.handle_request(request).await
         } else {
@@ -137,6 +157,53 @@ mod tests {
         assert_eq!(reject.code(), ErrorCode::F08_AMOUNT_TOO_LARGE);
     }
 
+    #[tokio::test]
+    async fn above_node_limit() {
+        let next = incoming_service_fn(move |_| {
+            Ok(FulfillBuilder {
+                fulfillment: &[0; 32],
+                data: b"test data",
+            }
+            .build())
+        });
+        let store = TestStore;
+        let prepare = || {
+            PrepareBuilder {
+                destination: Address::from_str("example.destination").unwrap(),
+                amount: 100,
+                expires_at: std::time::SystemTime::now() + std::time::Duration::from_secs(30),
+                execution_condition: &[0; 32],
+                data: b"test data",
+            }
+            .build()
+        };
+
+        let node_limit = Arc::new(AtomicU64::new(99));
+        let mut service =
+            MaxPacketAmountService::with_node_limit(store.clone(), node_limit.clone(), next);
+        let reject = service
+            .handle_request(IncomingRequest {
+                from: TestAccount(101),
+                prepare: prepare(),
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::F08_AMOUNT_TOO_LARGE);
+        let details = MaxPacketAmountDetails::from_bytes(reject.data()).unwrap();
+        assert_eq!(details.max_amount(), 99);
+
+        // Raising the limit applies to the next packet
+        node_limit.store(100, Ordering::Relaxed);
+        let fulfill = service
+            .handle_request(IncomingRequest {
+                from: TestAccount(101),
+                prepare: prepare(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(fulfill.data(), b"test data");
+    }
+
     #[derive(Clone)]
     struct TestStore;
 
@@ -0,0 +1,291 @@
+use async_trait::async_trait;
+use interledger_packet::{ErrorCode, Prepare, Reject, RejectBuilder};
+use interledger_service::*;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+use tracing::debug;
+
+/// Extension trait for [`Account`](../interledger_service/trait.Account.html) with the max size
+/// of the ILP packets exchanged with this account
+pub trait MaxPacketSizeAccount: Account {
+    /// The max size, in bytes, of the prepare packets sent to or by the account
+    fn max_packet_size(&self) -> Option<u64> {
+        None
+    }
+}
+
+/// # Packet Size Service
+///
+/// Rejects the prepare packets which are larger than the max packet size of the account which
+/// sent them (or of the node), and those which are larger than the max packet size of the
+/// account they are sent to, with `F01: Invalid Packet` errors. Since the packets received
+/// over BTP and over HTTP go through the same services, the same limits apply to both.
+///
+/// The data of the rejects is the size of the packet followed by the maximum, as two 64-bit
+/// big-endian integers (like the data of `F08: Amount Too Large` errors), so that senders
+/// can split the data they send over more packets.
+///
+/// The node-wide limit is shared behind an atomic so that it can be changed while the node is
+/// running. Requires a `MaxPacketSizeAccount` and _no store_.
+#[derive(Clone)]
+pub struct PacketSizeService<I, S> {
+    next: I,
+    store: S,
+    node_limit: Arc<AtomicU64>,
+}
+
+impl<I, S> PacketSizeService<I, S> {
+    /// Simple constructor
+    pub fn new(store: S, next: I) -> Self {
+        Self::with_node_limit(store, Arc::new(AtomicU64::new(u64::MAX)), next)
+    }
+
+    /// Constructor which also caps the packets of all accounts at the value of `node_limit`
+    pub fn with_node_limit(store: S, node_limit: Arc<AtomicU64>, next: I) -> Self {
+        PacketSizeService {
+            store,
+            next,
+            node_limit,
+        }
+    }
+}
+
+impl<I, S> PacketSizeService<I, S>
+where
+    S: AddressStore,
+{
+    /// Returns the reject of the prepare if it is larger than the limit
+    fn check(&self, prepare: &Prepare, max_packet_size: u64) -> Result<(), Reject> {
+        let size = prepare.as_ref().len() as u64;
+        if size <= max_packet_size {
+            return Ok(());
+        }
+        debug!(
+            "Prepare size: {} exceeds max_packet_size: {}",
+            size, max_packet_size
+        );
+        let mut details = [0; 16];
+        details[..8].copy_from_slice(&size.to_be_bytes());
+        details[8..].copy_from_slice(&max_packet_size.to_be_bytes());
+        let message = format!(
+            "packet size {} exceeds the maximum of {} bytes",
+            size, max_packet_size
+        );
+        Err(RejectBuilder {
+            code: ErrorCode::F01_INVALID_PACKET,
+            message: message.as_bytes(),
+            triggered_by: Some(&self.store.get_ilp_address()),
+            data: &details,
+        }
+        .build())
+    }
+}
+
+#[async_trait]
+impl<I, S, A> IncomingService<A> for PacketSizeService<I, S>
+where
+    I: IncomingService<A> + Send + Sync + 'static,
+    S: AddressStore + Send + Sync + 'static,
+    A: MaxPacketSizeAccount + Send + Sync + 'static,
+{
+    /// On receive request:
+    /// 1. if the size of request.prepare <= min(request.from.max_packet_size, node limit) forward the request, else error
+    async fn handle_request(&mut self, request: IncomingRequest<A>) -> IlpResult {
+        let node_limit = self.node_limit.load(Ordering::Relaxed);
+        let max_packet_size = request
+            .from
+            .max_packet_size()
+            .map_or(node_limit, |limit| limit.min(node_limit));
+        self.check(&request.prepare, max_packet_size)?;
+        self.next.handle_request(request).await
+    }
+}
+
+#[async_trait]
+impl<O, S, A> OutgoingService<A> for PacketSizeService<O, S>
+where
+    O: OutgoingService<A> + Send + Sync + 'static,
+    S: AddressStore + Send + Sync + 'static,
+    A: MaxPacketSizeAccount + Send + Sync + 'static,
+{
+    /// On send request:
+    /// 1. if the size of request.prepare <= request.to.max_packet_size forward the request, else error
+    async fn send_request(&mut self, request: OutgoingRequest<A>) -> IlpResult {
+        if let Some(max_packet_size) = request.to.max_packet_size() {
+            self.check(&request.prepare, max_packet_size)?;
+        }
+        self.next.send_request(request).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use interledger_errors::AddressStoreError;
+    use interledger_packet::{Address, FulfillBuilder, PrepareBuilder};
+    use once_cell::sync::Lazy;
+    use std::convert::TryInto;
+    use std::str::FromStr;
+    use uuid::Uuid;
+
+    #[derive(Debug, Clone)]
+    struct TestAccount(Option<u64>);
+
+    impl MaxPacketSizeAccount for TestAccount {
+        fn max_packet_size(&self) -> Option<u64> {
+            self.0
+        }
+    }
+
+    fn prepare(data: &[u8]) -> Prepare {
+        PrepareBuilder {
+            destination: Address::from_str("example.destination").unwrap(),
+            amount: 100,
+            expires_at: std::time::SystemTime::now() + std::time::Duration::from_secs(30),
+            execution_condition: &[0; 32],
+            data,
+        }
+        .build()
+    }
+
+    fn details(reject: &Reject) -> (u64, u64) {
+        let data = reject.data();
+        (
+            u64::from_be_bytes(data[..8].try_into().unwrap()),
+            u64::from_be_bytes(data[8..].try_into().unwrap()),
+        )
+    }
+
+    #[tokio::test]
+    async fn rejects_packets_above_the_account_limit() {
+        let next = incoming_service_fn(move |_| {
+            Ok(FulfillBuilder {
+                fulfillment: &[0; 32],
+                data: b"test data",
+            }
+            .build())
+        });
+        let mut service = PacketSizeService::new(TestStore, next);
+        let size = prepare(&[0; 100]).as_ref().len() as u64;
+
+        let fulfill = service
+            .handle_request(IncomingRequest {
+                from: TestAccount(Some(size)),
+                prepare: prepare(&[0; 100]),
+            })
+            .await
+            .unwrap();
+        assert_eq!(fulfill.data(), b"test data");
+
+        let reject = service
+            .handle_request(IncomingRequest {
+                from: TestAccount(Some(size - 1)),
+                prepare: prepare(&[0; 100]),
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::F01_INVALID_PACKET);
+        assert_eq!(details(&reject), (size, size - 1));
+    }
+
+    #[tokio::test]
+    async fn rejects_packets_above_the_node_limit() {
+        let next = incoming_service_fn(move |_| {
+            Ok(FulfillBuilder {
+                fulfillment: &[0; 32],
+                data: b"test data",
+            }
+            .build())
+        });
+        let size = prepare(&[0; 100]).as_ref().len() as u64;
+        let node_limit = Arc::new(AtomicU64::new(size - 1));
+        let mut service = PacketSizeService::with_node_limit(TestStore, node_limit.clone(), next);
+        let reject = service
+            .handle_request(IncomingRequest {
+                from: TestAccount(None),
+                prepare: prepare(&[0; 100]),
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(details(&reject), (size, size - 1));
+
+        // Raising the limit applies to the next packet
+        node_limit.store(size, Ordering::Relaxed);
+        assert!(service
+            .handle_request(IncomingRequest {
+                from: TestAccount(None),
+                prepare: prepare(&[0; 100]),
+            })
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn rejects_packets_above_the_limit_of_the_receiving_account() {
+        let next = outgoing_service_fn(move |_| {
+            Ok(FulfillBuilder {
+                fulfillment: &[0; 32],
+                data: b"test data",
+            }
+            .build())
+        });
+        let mut service = PacketSizeService::new(TestStore, next);
+        let reject = service
+            .send_request(OutgoingRequest {
+                from: TestAccount(None),
+                to: TestAccount(Some(100)),
+                original_amount: 100,
+                prepare: prepare(&[0; 100]),
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::F01_INVALID_PACKET);
+        assert_eq!(details(&reject).1, 100);
+    }
+
+    #[derive(Clone)]
+    struct TestStore;
+
+    #[async_trait]
+    impl AddressStore for TestStore {
+        async fn set_ilp_address(&self, _: Address) -> Result<(), AddressStoreError> {
+            unimplemented!()
+        }
+
+        async fn clear_ilp_address(&self) -> Result<(), AddressStoreError> {
+            unimplemented!()
+        }
+
+        fn get_ilp_address(&self) -> Address {
+            Address::from_str("example.connector").unwrap()
+        }
+    }
+
+    impl Account for TestAccount {
+        fn id(&self) -> Uuid {
+            Uuid::new_v4()
+        }
+
+        fn username(&self) -> &Username {
+            &ALICE
+        }
+
+        fn asset_code(&self) -> &str {
+            "XYZ"
+        }
+
+        fn asset_scale(&self) -> u8 {
+            9
+        }
+
+        fn ilp_address(&self) -> &Address {
+            &EXAMPLE_ADDRESS
+        }
+    }
+
+    static ALICE: Lazy<Username> = Lazy::new(|| Username::from_str("alice").unwrap());
+    static EXAMPLE_ADDRESS: Lazy<Address> =
+        Lazy::new(|| Address::from_str("example.alice").unwrap());
+}
@@ -0,0 +1,476 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use interledger_packet::Prepare;
+use interledger_service::*;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// Whether a traced packet was received from or sent to the traced account
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TraceDirection {
+    Incoming,
+    Outgoing,
+}
+
+/// A Prepare packet recorded in a packet trace
+#[derive(Clone, Debug, Serialize)]
+pub struct TracedPrepare {
+    pub destination: String,
+    pub amount: u64,
+    pub expires_at: String,
+    pub execution_condition: String,
+    /// The hex-encoded data, unless the trace is redacted
+    pub data: Option<String>,
+    pub data_length: usize,
+}
+
+/// The Fulfill or Reject packet which answered a traced Prepare packet
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TracedResult {
+    Fulfill {
+        /// The hex-encoded fulfillment, unless the trace is redacted
+        fulfillment: Option<String>,
+        data: Option<String>,
+        data_length: usize,
+    },
+    Reject {
+        code: String,
+        message: String,
+        triggered_by: Option<String>,
+        data: Option<String>,
+        data_length: usize,
+    },
+}
+
+/// A packet exchanged with a traced account, along with its response
+#[derive(Clone, Debug, Serialize)]
+pub struct TracedPacket {
+    /// When the Prepare packet was received or sent
+    pub timestamp: String,
+    pub direction: TraceDirection,
+    /// Username of the account which sent the Prepare packet
+    pub from: String,
+    /// Username of the account the Prepare packet was forwarded to (for outgoing packets)
+    pub to: Option<String>,
+    pub prepare: TracedPrepare,
+    pub result: TracedResult,
+    /// Time, in milliseconds, until the response came back
+    pub duration: f64,
+}
+
+/// The packets recorded while an account is in trace mode
+#[derive(Clone, Debug, Serialize)]
+pub struct PacketTrace {
+    pub started_at: String,
+    pub expires_at: String,
+    /// Whether the packets are still being recorded
+    pub active: bool,
+    /// Whether the data and fulfillments of the packets are omitted
+    pub redacted: bool,
+    /// Maximum number of packets kept. Once it is reached, the oldest packets are dropped.
+    pub capacity: usize,
+    /// Number of packets which were dropped to make room for newer ones
+    pub dropped: u64,
+    pub packets: VecDeque<TracedPacket>,
+}
+
+struct ActiveTrace {
+    expires_at: Instant,
+    trace: PacketTrace,
+}
+
+impl ActiveTrace {
+    fn is_active(&self) -> bool {
+        Instant::now() < self.expires_at
+    }
+
+    fn snapshot(&self) -> PacketTrace {
+        let mut trace = self.trace.clone();
+        trace.active = self.is_active();
+        trace
+    }
+}
+
+/// The packet traces of the accounts in trace mode. The packets of an account are
+/// recorded into a ring buffer until its trace expires, and the trace is kept (so that it
+/// can be retrieved) until it is stopped or restarted.
+#[derive(Clone, Default)]
+pub struct PacketTraces(Arc<Mutex<HashMap<Uuid, ActiveTrace>>>);
+
+impl PacketTraces {
+    /// Starts recording up to `capacity` packets of the account for the given duration,
+    /// replacing its previous trace
+    pub fn start(
+        &self,
+        account_id: Uuid,
+        duration: Duration,
+        capacity: usize,
+        redacted: bool,
+    ) -> PacketTrace {
+        let now = Utc::now();
+        let expires_at =
+            now + chrono::Duration::from_std(duration).unwrap_or_else(|_| chrono::Duration::zero());
+        let trace = ActiveTrace {
+            expires_at: Instant::now() + duration,
+            trace: PacketTrace {
+                started_at: now.to_rfc3339(),
+                expires_at: expires_at.to_rfc3339(),
+                active: true,
+                redacted,
+                capacity,
+                dropped: 0,
+                packets: VecDeque::with_capacity(capacity.min(1024)),
+            },
+        };
+        let snapshot = trace.snapshot();
+        self.0.lock().unwrap().insert(account_id, trace);
+        snapshot
+    }
+
+    /// Returns the trace of the account, if one was started
+    pub fn get(&self, account_id: Uuid) -> Option<PacketTrace> {
+        self.0
+            .lock()
+            .unwrap()
+            .get(&account_id)
+            .map(ActiveTrace::snapshot)
+    }
+
+    /// Stops recording the packets of the account and returns its trace
+    pub fn stop(&self, account_id: Uuid) -> Option<PacketTrace> {
+        self.0.lock().unwrap().remove(&account_id).map(|active| {
+            let mut trace = active.trace;
+            trace.active = false;
+            trace
+        })
+    }
+
+    /// Returns whether the packets of the account are being recorded, and if so, whether
+    /// the trace is redacted
+    fn tracing(&self, account_id: Uuid) -> Option<bool> {
+        self.0
+            .lock()
+            .unwrap()
+            .get(&account_id)
+            .filter(|active| active.is_active())
+            .map(|active| active.trace.redacted)
+    }
+
+    fn record(&self, account_id: Uuid, packet: TracedPacket) {
+        if let Some(active) = self.0.lock().unwrap().get_mut(&account_id) {
+            if !active.is_active() {
+                return;
+            }
+            let trace = &mut active.trace;
+            if trace.capacity == 0 {
+                trace.dropped += 1;
+                return;
+            }
+            if trace.packets.len() >= trace.capacity {
+                trace.packets.pop_front();
+                trace.dropped += 1;
+            }
+            trace.packets.push_back(packet);
+        }
+    }
+}
+
+fn hex_data(data: &[u8], redacted: bool) -> Option<String> {
+    if redacted {
+        None
+    } else {
+        Some(hex::encode(data))
+    }
+}
+
+fn trace_prepare(prepare: &Prepare, redacted: bool) -> TracedPrepare {
+    TracedPrepare {
+        destination: prepare.destination().to_string(),
+        amount: prepare.amount(),
+        expires_at: DateTime::<Utc>::from(prepare.expires_at()).to_rfc3339(),
+        execution_condition: hex::encode(prepare.execution_condition()),
+        data: hex_data(prepare.data(), redacted),
+        data_length: prepare.data().len(),
+    }
+}
+
+fn trace_result(result: &IlpResult, redacted: bool) -> TracedResult {
+    match result {
+        Ok(fulfill) => TracedResult::Fulfill {
+            fulfillment: hex_data(fulfill.fulfillment(), redacted),
+            data: hex_data(fulfill.data(), redacted),
+            data_length: fulfill.data().len(),
+        },
+        Err(reject) => TracedResult::Reject {
+            code: reject.code().to_string(),
+            message: String::from_utf8_lossy(reject.message()).to_string(),
+            triggered_by: reject.triggered_by().map(|address| address.to_string()),
+            data: hex_data(reject.data(), redacted),
+            data_length: reject.data().len(),
+        },
+    }
+}
+
+/// # Packet Trace Service
+///
+/// Incoming or Outgoing Service which records the packets received from (incoming) or
+/// sent to (outgoing) the accounts in trace mode, along with their responses, into the
+/// accounts' `PacketTraces`. Forwards everything.
+#[derive(Clone)]
+pub struct PacketTraceService<IO, A> {
+    traces: PacketTraces,
+    next: IO,
+    account_type: PhantomData<A>,
+}
+
+impl<IO, A> PacketTraceService<IO, A>
+where
+    A: Account,
+{
+    /// Constructs a service which records the packets of the accounts traced in `traces`
+    pub fn new(traces: PacketTraces, next: IO) -> Self {
+        PacketTraceService {
+            traces,
+            next,
+            account_type: PhantomData,
+        }
+    }
+}
+
+#[async_trait]
+impl<I, A> IncomingService<A> for PacketTraceService<I, A>
+where
+    I: IncomingService<A> + Send + Sync + 'static,
+    A: Account + Send + Sync + 'static,
+{
+    async fn handle_request(&mut self, request: IncomingRequest<A>) -> IlpResult {
+        let account_id = request.from.id();
+        let redacted = match self.traces.tracing(account_id) {
+            Some(redacted) => redacted,
+            None => return self.next.handle_request(request).await,
+        };
+
+        let timestamp = Utc::now().to_rfc3339();
+        let from = request.from.username().to_string();
+        let prepare = trace_prepare(&request.prepare, redacted);
+        let sent_at = Instant::now();
+        let result = self.next.handle_request(request).await;
+        self.traces.record(
+            account_id,
+            TracedPacket {
+                timestamp,
+                direction: TraceDirection::Incoming,
+                from,
+                to: None,
+                prepare,
+                result: trace_result(&result, redacted),
+                duration: sent_at.elapsed().as_secs_f64() * 1000.0,
+            },
+        );
+        result
+    }
+}
+
+#[async_trait]
+impl<O, A> OutgoingService<A> for PacketTraceService<O, A>
+where
+    O: OutgoingService<A> + Send + Sync + 'static,
+    A: Account + Send + Sync + 'static,
+{
+    async fn send_request(&mut self, request: OutgoingRequest<A>) -> IlpResult {
+        let account_id = request.to.id();
+        let redacted = match self.traces.tracing(account_id) {
+            Some(redacted) => redacted,
+            None => return self.next.send_request(request).await,
+        };
+
+        let timestamp = Utc::now().to_rfc3339();
+        let from = request.from.username().to_string();
+        let to = request.to.username().to_string();
+        let prepare = trace_prepare(&request.prepare, redacted);
+        let sent_at = Instant::now();
+        let result = self.next.send_request(request).await;
+        self.traces.record(
+            account_id,
+            TracedPacket {
+                timestamp,
+                direction: TraceDirection::Outgoing,
+                from,
+                to: Some(to),
+                prepare,
+                result: trace_result(&result, redacted),
+                duration: sent_at.elapsed().as_secs_f64() * 1000.0,
+            },
+        );
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use interledger_packet::{Address, ErrorCode, FulfillBuilder, PrepareBuilder, RejectBuilder};
+    use once_cell::sync::Lazy;
+    use std::str::FromStr;
+    use std::time::SystemTime;
+
+    #[derive(Clone, Debug)]
+    struct TestAccount(Uuid);
+
+    static ALICE: Lazy<Username> = Lazy::new(|| Username::from_str("alice").unwrap());
+    static EXAMPLE_ADDRESS: Lazy<Address> =
+        Lazy::new(|| Address::from_str("example.alice").unwrap());
+
+    impl Account for TestAccount {
+        fn id(&self) -> Uuid {
+            self.0
+        }
+
+        fn username(&self) -> &Username {
+            &ALICE
+        }
+
+        fn asset_scale(&self) -> u8 {
+            9
+        }
+
+        fn asset_code(&self) -> &str {
+            "XYZ"
+        }
+
+        fn ilp_address(&self) -> &Address {
+            &EXAMPLE_ADDRESS
+        }
+    }
+
+    fn request(account: TestAccount) -> IncomingRequest<TestAccount> {
+        IncomingRequest {
+            from: account,
+            prepare: PrepareBuilder {
+                destination: Address::from_str("example.destination").unwrap(),
+                amount: 100,
+                expires_at: SystemTime::now() + Duration::from_secs(30),
+                execution_condition: &[0; 32],
+                data: b"secret",
+            }
+            .build(),
+        }
+    }
+
+    #[tokio::test]
+    async fn records_packets_of_traced_accounts() {
+        let traced = TestAccount(Uuid::new_v4());
+        let other = TestAccount(Uuid::new_v4());
+        let traces = PacketTraces::default();
+        traces.start(traced.id(), Duration::from_secs(60), 10, false);
+        let mut service = PacketTraceService::new(
+            traces.clone(),
+            incoming_service_fn(|_| {
+                Ok(FulfillBuilder {
+                    fulfillment: &[1; 32],
+                    data: b"response",
+                }
+                .build())
+            }),
+        );
+
+        service
+            .handle_request(request(traced.clone()))
+            .await
+            .unwrap();
+        service
+            .handle_request(request(other.clone()))
+            .await
+            .unwrap();
+
+        let trace = traces.get(traced.id()).unwrap();
+        assert!(trace.active);
+        assert_eq!(trace.packets.len(), 1);
+        let packet = &trace.packets[0];
+        assert_eq!(packet.direction, TraceDirection::Incoming);
+        assert_eq!(packet.prepare.amount, 100);
+        assert_eq!(packet.prepare.data, Some(hex::encode(b"secret")));
+        match packet.result {
+            TracedResult::Fulfill { ref data, .. } => {
+                assert_eq!(data, &Some(hex::encode(b"response")))
+            }
+            _ => panic!("Expected a fulfill"),
+        }
+        assert!(traces.get(other.id()).is_none());
+    }
+
+    #[tokio::test]
+    async fn redacts_and_drops_oldest_packets() {
+        let traced = TestAccount(Uuid::new_v4());
+        let traces = PacketTraces::default();
+        traces.start(traced.id(), Duration::from_secs(60), 2, true);
+        let mut service = PacketTraceService::new(
+            traces.clone(),
+            incoming_service_fn(|_| {
+                Err(RejectBuilder {
+                    code: ErrorCode::F02_UNREACHABLE,
+                    message: b"unreachable",
+                    triggered_by: None,
+                    data: b"data",
+                }
+                .build())
+            }),
+        );
+
+        for _ in 0..3 {
+            service
+                .handle_request(request(traced.clone()))
+                .await
+                .unwrap_err();
+        }
+
+        let trace = traces.stop(traced.id()).unwrap();
+        assert!(!trace.active);
+        assert_eq!(trace.packets.len(), 2);
+        assert_eq!(trace.dropped, 1);
+        assert_eq!(trace.packets[0].prepare.data, None);
+        assert_eq!(trace.packets[0].prepare.data_length, 6);
+        match trace.packets[0].result {
+            TracedResult::Reject {
+                ref code, ref data, ..
+            } => {
+                assert_eq!(code, "F02");
+                assert_eq!(data, &None);
+            }
+            _ => panic!("Expected a reject"),
+        }
+        assert!(traces.get(traced.id()).is_none());
+    }
+
+    #[tokio::test]
+    async fn stops_recording_when_expired() {
+        let traced = TestAccount(Uuid::new_v4());
+        let traces = PacketTraces::default();
+        traces.start(traced.id(), Duration::from_millis(0), 10, false);
+        let mut service = PacketTraceService::new(
+            traces.clone(),
+            incoming_service_fn(|_| {
+                Ok(FulfillBuilder {
+                    fulfillment: &[1; 32],
+                    data: &[],
+                }
+                .build())
+            }),
+        );
+
+        service
+            .handle_request(request(traced.clone()))
+            .await
+            .unwrap();
+
+        let trace = traces.get(traced.id()).unwrap();
+        assert!(!trace.active);
+        assert!(trace.packets.is_empty());
+    }
+}
@@ -1,12 +1,18 @@
 use async_trait::async_trait;
 use interledger_packet::{ErrorCode, RejectBuilder};
 use interledger_service::{Account, AddressStore, IlpResult, IncomingRequest, IncomingService};
+use metrics::{labels, recorder, Key};
 use std::fmt::Debug;
 use std::marker::PhantomData;
 use tracing::{error, warn};
 
 /// Extension trait for [`Account`](../interledger_service/trait.Account.html) with rate limiting related information
 pub trait RateLimitAccount: Account {
+    /// The maximum packets per second allowed for this account
+    fn packets_per_second_limit(&self) -> Option<u32> {
+        None
+    }
+
     /// The maximum packets per minute allowed for this account
     fn packets_per_minute_limit(&self) -> Option<u32> {
         None
@@ -21,7 +27,7 @@ pub trait RateLimitAccount: Account {
 /// Rate limiting related errors
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum RateLimitError {
-    /// Account exceeded their packet limit
+    /// Account exceeded their packets per second or packets per minute limit
     PacketLimitExceeded,
     /// Account exceeded their amount limit
     ThroughputLimitExceeded,
@@ -35,8 +41,8 @@ pub trait RateLimitStore {
     /// The provided account must implement [`RateLimitAccount`](./trait.RateLimitAccount.html)
     type Account: RateLimitAccount;
 
-    /// Apply rate limits based on the packets per minute and amount of per minute
-    /// limits set on the provided account
+    /// Apply rate limits based on the packets per second, packets per minute and
+    /// amount per minute limits set on the provided account
     async fn apply_rate_limits(
         &self,
         account: Self::Account,
@@ -61,6 +67,10 @@ pub trait RateLimitStore {
 /// Talks with the associated Store in order to figure out
 /// and set the rate limits per account.
 /// This service does packet based limiting and amount based limiting.
+/// Packets which exceed a packet limit are rejected with `T05: Rate Limited`,
+/// and those which exceed the amount limit with `T04: Insufficient Liquidity`.
+/// Each rejected packet increments the `requests.incoming.throttled` counter,
+/// labelled with the account's username and the `limit` which was hit.
 ///
 /// Forwards everything else.
 /// Requires a `RateLimitAccount` and a `RateLimitStore`.
@@ -134,15 +144,20 @@ where
             Err(err) => {
                 let code = match err {
                     RateLimitError::PacketLimitExceeded => {
-                        if let Some(limit) = account.packets_per_minute_limit() {
-                            warn!("Account {} was rate limited for sending too many packets. Limit is: {} per minute", account.id(), limit);
-                        }
+                        warn!(
+                            "Account {} was rate limited for sending too many packets. Limits are: {:?} per second, {:?} per minute",
+                            account.id(),
+                            account.packets_per_second_limit(),
+                            account.packets_per_minute_limit()
+                        );
+                        record_throttled(&account, "packets");
                         ErrorCode::T05_RATE_LIMITED
                     }
                     RateLimitError::ThroughputLimitExceeded => {
                         if let Some(limit) = account.amount_per_minute_limit() {
                             warn!("Account {} was throughput limited for trying to send too much money. Limit is: {} per minute", account.id(), limit);
                         }
+                        record_throttled(&account, "amount");
                         ErrorCode::T04_INSUFFICIENT_LIQUIDITY
                     }
                     RateLimitError::StoreError => ErrorCode::T00_INTERNAL_ERROR,
@@ -162,6 +177,19 @@ where
     }
 }
 
+fn record_throttled<A: Account>(account: &A, limit: &'static str) {
+    recorder().increment_counter(
+        Key::from_name_and_labels(
+            "requests.incoming.throttled",
+            labels!(
+                "account" => account.username().to_string(),
+                "limit" => limit,
+            ),
+        ),
+        1,
+    );
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
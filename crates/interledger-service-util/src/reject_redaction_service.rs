@@ -0,0 +1,302 @@
+use async_trait::async_trait;
+use interledger_packet::{ErrorClass, RejectBuilder};
+use interledger_service::{Account, AddressStore, IlpResult, IncomingRequest, IncomingService};
+use serde::Deserialize;
+use std::sync::Arc;
+use tracing::info;
+
+/// How the rejects of an error class are sanitized before they are sent back to the sender
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+pub struct RedactionPolicy {
+    /// Message sent instead of the rejects' own. Defaults to the name of their error code.
+    #[serde(default)]
+    pub message: Option<String>,
+    /// Whether the address which triggered the rejects is kept. By default, it is replaced
+    /// with the node's address, so that the peers behind the node are not revealed.
+    #[serde(default)]
+    pub keep_triggered_by: bool,
+    /// Whether the data of the rejects is removed. It is kept by default, since senders
+    /// rely on it (for example STREAM, or the max packet amount of `F08` rejects).
+    #[serde(default)]
+    pub strip_data: bool,
+}
+
+/// Configuration of the redaction of the rejects sent back to the senders of packets, per
+/// error class. The rejects of the classes without a policy are sent as is.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+pub struct RejectRedactionConfig {
+    /// Policy of the `F` (final) errors
+    #[serde(default)]
+    pub final_errors: Option<RedactionPolicy>,
+    /// Policy of the `T` (temporary) errors
+    #[serde(default)]
+    pub temporary_errors: Option<RedactionPolicy>,
+    /// Policy of the `R` (relative) errors
+    #[serde(default)]
+    pub relative_errors: Option<RedactionPolicy>,
+}
+
+impl RejectRedactionConfig {
+    fn policy(&self, class: ErrorClass) -> Option<&RedactionPolicy> {
+        match class {
+            ErrorClass::Final => self.final_errors.as_ref(),
+            ErrorClass::Temporary => self.temporary_errors.as_ref(),
+            ErrorClass::Relative => self.relative_errors.as_ref(),
+            ErrorClass::Unknown => None,
+        }
+    }
+}
+
+/// # Reject Redaction Service
+///
+/// Replaces the message, and optionally the triggering address and the data, of the rejects
+/// sent back to the senders of incoming packets with the policy of their error class, so that
+/// internal details such as store errors and the addresses of peers are not leaked. The
+/// original rejects are logged at the info level.
+/// Requires an `AddressStore`
+#[derive(Clone)]
+pub struct RejectRedactionService<S, I> {
+    config: Arc<RejectRedactionConfig>,
+    store: S,
+    next: I,
+}
+
+impl<S, I> RejectRedactionService<S, I> {
+    pub fn new(config: RejectRedactionConfig, store: S, next: I) -> Self {
+        RejectRedactionService {
+            config: Arc::new(config),
+            store,
+            next,
+        }
+    }
+}
+
+#[async_trait]
+impl<S, I, A> IncomingService<A> for RejectRedactionService<S, I>
+where
+    S: AddressStore + Send + Sync + 'static,
+    I: IncomingService<A> + Send + Sync + 'static,
+    A: Account + Send + Sync + 'static,
+{
+    /// On receive request:
+    /// 1. Forwards the request
+    /// 1. If it is rejected with an error of a class with a policy, logs the reject and
+    ///    returns it redacted with the policy
+    async fn handle_request(&mut self, request: IncomingRequest<A>) -> IlpResult {
+        let username = request.from.username().clone();
+        let reject = match self.next.handle_request(request).await {
+            Ok(fulfill) => return Ok(fulfill),
+            Err(reject) => reject,
+        };
+        let policy = match self.config.policy(reject.code().class()) {
+            Some(policy) => policy,
+            None => return Err(reject),
+        };
+        info!(
+            "Redacting reject of packet from {}: code: {}, message: {}, triggered by: {:?}, data length: {}",
+            username,
+            reject.code(),
+            String::from_utf8_lossy(reject.message()),
+            reject.triggered_by(),
+            reject.data().len()
+        );
+        let message = match policy.message {
+            Some(ref message) => message.clone(),
+            None => reject.code().name().unwrap_or_default().to_string(),
+        };
+        let triggered_by = if policy.keep_triggered_by {
+            reject.triggered_by()
+        } else {
+            Some(self.store.get_ilp_address())
+        };
+        let data = if policy.strip_data {
+            &[][..]
+        } else {
+            reject.data()
+        };
+        Err(RejectBuilder {
+            code: reject.code(),
+            message: message.as_bytes(),
+            triggered_by: triggered_by.as_ref(),
+            data,
+        }
+        .build())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use interledger_packet::{Address, ErrorCode, FulfillBuilder, PrepareBuilder};
+    use interledger_service::{incoming_service_fn, Username};
+    use once_cell::sync::Lazy;
+    use std::str::FromStr;
+    use std::time::SystemTime;
+    use uuid::Uuid;
+
+    static NODE: Lazy<Address> = Lazy::new(|| Address::from_str("example.node").unwrap());
+
+    #[derive(Clone, Debug)]
+    struct TestAccount;
+
+    impl Account for TestAccount {
+        fn id(&self) -> Uuid {
+            Uuid::nil()
+        }
+
+        fn username(&self) -> &Username {
+            static USERNAME: Lazy<Username> = Lazy::new(|| Username::from_str("alice").unwrap());
+            &USERNAME
+        }
+
+        fn asset_scale(&self) -> u8 {
+            9
+        }
+
+        fn asset_code(&self) -> &str {
+            "XYZ"
+        }
+
+        fn ilp_address(&self) -> &Address {
+            &NODE
+        }
+    }
+
+    #[derive(Clone)]
+    struct TestStore;
+
+    #[async_trait]
+    impl AddressStore for TestStore {
+        async fn set_ilp_address(
+            &self,
+            _: Address,
+        ) -> Result<(), interledger_errors::AddressStoreError> {
+            unimplemented!()
+        }
+
+        async fn clear_ilp_address(&self) -> Result<(), interledger_errors::AddressStoreError> {
+            unimplemented!()
+        }
+
+        fn get_ilp_address(&self) -> Address {
+            NODE.clone()
+        }
+    }
+
+    async fn reject(config: RejectRedactionConfig, code: ErrorCode) -> interledger_packet::Reject {
+        let next = incoming_service_fn(move |_request| {
+            Err(RejectBuilder {
+                code,
+                message: b"Error getting the balance of account 3fa8...: connection refused",
+                triggered_by: Some(&Address::from_str("example.node.bob").unwrap()),
+                data: b"details",
+            }
+            .build())
+        });
+        let mut service = RejectRedactionService::new(config, TestStore, next);
+        service.handle_request(request()).await.unwrap_err()
+    }
+
+    fn request() -> IncomingRequest<TestAccount> {
+        IncomingRequest {
+            from: TestAccount,
+            prepare: PrepareBuilder {
+                destination: Address::from_str("example.destination").unwrap(),
+                amount: 100,
+                expires_at: SystemTime::now(),
+                execution_condition: &[0; 32],
+                data: &[],
+            }
+            .build(),
+        }
+    }
+
+    fn redact_everything() -> RejectRedactionConfig {
+        let policy = RedactionPolicy {
+            message: None,
+            keep_triggered_by: false,
+            strip_data: true,
+        };
+        RejectRedactionConfig {
+            final_errors: Some(policy.clone()),
+            temporary_errors: Some(policy.clone()),
+            relative_errors: Some(policy),
+        }
+    }
+
+    #[tokio::test]
+    async fn redacts_the_rejects_of_the_configured_classes() {
+        let config = RejectRedactionConfig {
+            temporary_errors: Some(RedactionPolicy::default()),
+            relative_errors: Some(RedactionPolicy {
+                message: Some("Try again".to_string()),
+                keep_triggered_by: true,
+                strip_data: true,
+            }),
+            ..Default::default()
+        };
+
+        let redacted = reject(config.clone(), ErrorCode::T00_INTERNAL_ERROR).await;
+        assert_eq!(redacted.code(), ErrorCode::T00_INTERNAL_ERROR);
+        assert_eq!(redacted.message(), b"Internal Error");
+        assert_eq!(redacted.triggered_by(), Some(NODE.clone()));
+        assert_eq!(redacted.data(), b"details");
+
+        let redacted = reject(config.clone(), ErrorCode::R00_TRANSFER_TIMED_OUT).await;
+        assert_eq!(redacted.message(), b"Try again");
+        assert_eq!(
+            redacted.triggered_by(),
+            Some(Address::from_str("example.node.bob").unwrap())
+        );
+        assert!(redacted.data().is_empty());
+
+        let kept = reject(config, ErrorCode::F02_UNREACHABLE).await;
+        assert_eq!(
+            kept.message(),
+            &b"Error getting the balance of account 3fa8...: connection refused"[..]
+        );
+        assert_eq!(
+            kept.triggered_by(),
+            Some(Address::from_str("example.node.bob").unwrap())
+        );
+    }
+
+    #[tokio::test]
+    async fn strips_the_data_of_f08_rejects() {
+        let redacted = reject(redact_everything(), ErrorCode::F08_AMOUNT_TOO_LARGE).await;
+        assert_eq!(redacted.code(), ErrorCode::F08_AMOUNT_TOO_LARGE);
+        assert_eq!(redacted.message(), b"Amount Too Large");
+        assert_eq!(redacted.triggered_by(), Some(NODE.clone()));
+        assert!(redacted.data().is_empty());
+    }
+
+    #[tokio::test]
+    async fn passes_the_rejects_of_unknown_classes_through() {
+        let kept = reject(redact_everything(), ErrorCode::new(*b"X01")).await;
+        assert_eq!(kept.code(), ErrorCode::new(*b"X01"));
+        assert_eq!(
+            kept.message(),
+            &b"Error getting the balance of account 3fa8...: connection refused"[..]
+        );
+        assert_eq!(
+            kept.triggered_by(),
+            Some(Address::from_str("example.node.bob").unwrap())
+        );
+        assert_eq!(kept.data(), b"details");
+    }
+
+    #[tokio::test]
+    async fn passes_the_fulfills_through() {
+        let next = incoming_service_fn(|_request| {
+            Ok(FulfillBuilder {
+                fulfillment: &[1; 32],
+                data: b"receipt",
+            }
+            .build())
+        });
+        let mut service = RejectRedactionService::new(redact_everything(), TestStore, next);
+        let fulfill = service.handle_request(request()).await.unwrap();
+        assert_eq!(fulfill.fulfillment(), &[1; 32]);
+        assert_eq!(fulfill.data(), b"receipt");
+    }
+}
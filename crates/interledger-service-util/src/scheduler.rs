@@ -0,0 +1,463 @@
+use futures::future::{BoxFuture, FutureExt};
+use interledger_service::clock;
+use metrics::{labels, recorder, Key};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    future::Future,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::{
+    sync::{watch, Notify},
+    time::{delay_for, Instant},
+};
+use tracing::{debug, trace, warn};
+
+/// Fraction of the interval by which the runs of the tasks are moved if none is configured
+pub const DEFAULT_JITTER: f64 = 0.1;
+
+fn default_jitter() -> f64 {
+    DEFAULT_JITTER
+}
+
+/// Configuration of the scheduling of a task
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+pub struct TaskConfig {
+    /// Fraction of the interval by which each run is randomly moved earlier or later.
+    /// If it is not set, the scheduler's default applies.
+    #[serde(default)]
+    pub jitter: Option<f64>,
+    /// Whether the task only runs when it is triggered via the API. Defaults to false.
+    #[serde(default)]
+    pub manual_only: bool,
+}
+
+/// Configuration of the scheduler of the periodic tasks
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct SchedulerConfig {
+    /// Fraction of the interval by which each run of the tasks is randomly moved earlier or
+    /// later, so that the nodes started together do not all run their tasks (and call the
+    /// same peers and providers) at the same time. Defaults to 0.1.
+    #[serde(default = "default_jitter")]
+    pub jitter: f64,
+    /// Configuration of the individual tasks, by name
+    #[serde(default)]
+    pub tasks: HashMap<String, TaskConfig>,
+}
+
+impl Default for SchedulerConfig {
+    fn default() -> Self {
+        SchedulerConfig {
+            jitter: DEFAULT_JITTER,
+            tasks: HashMap::new(),
+        }
+    }
+}
+
+/// The state of a task, as returned by the tasks API
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct TaskStatus {
+    pub name: String,
+    /// Interval between the runs, in milliseconds
+    pub interval: u64,
+    /// Fraction of the interval by which the runs are randomly moved
+    pub jitter: f64,
+    /// Whether the task only runs when it is triggered
+    pub manual_only: bool,
+    pub running: bool,
+    pub runs: u64,
+    pub failures: u64,
+    /// When the last run started, in milliseconds since the UNIX epoch
+    pub last_run: Option<u64>,
+    /// How long the last run took, in milliseconds
+    pub last_duration: Option<u64>,
+    /// The error of the last run, if it failed
+    pub last_error: Option<String>,
+    /// When the next run is due, in milliseconds since the UNIX epoch
+    pub next_run: Option<u64>,
+}
+
+struct ScheduledTask {
+    status: Mutex<TaskStatus>,
+    trigger: Notify,
+}
+
+/// Runs the periodic tasks of the node (such as the route broadcasts, the polling of the
+/// exchange rates and the maintenance of the store), each on its own interval with some
+/// jitter, and keeps track of their runs so that they can be inspected and triggered via
+/// the API. Clones share the same tasks.
+#[derive(Clone, Default)]
+pub struct Scheduler {
+    config: Arc<SchedulerConfig>,
+    tasks: Arc<Mutex<BTreeMap<String, Arc<ScheduledTask>>>>,
+}
+
+impl Scheduler {
+    pub fn new(config: SchedulerConfig) -> Self {
+        Scheduler {
+            config: Arc::new(config),
+            tasks: Arc::new(Mutex::new(BTreeMap::new())),
+        }
+    }
+
+    /// Returns the builder of a task which runs every `interval`
+    pub fn task(&self, name: &str, interval: Duration) -> Task {
+        Task {
+            scheduler: self.clone(),
+            name: name.to_string(),
+            interval,
+            interval_updates: None,
+            delay_first_run: false,
+            active: None,
+        }
+    }
+
+    /// Returns the state of all the tasks, ordered by name
+    pub fn statuses(&self) -> Vec<TaskStatus> {
+        self.tasks
+            .lock()
+            .unwrap()
+            .values()
+            .map(|task| task.status.lock().unwrap().clone())
+            .collect()
+    }
+
+    /// Runs the task as soon as it is not running anymore (or right away if it is idle),
+    /// regardless of its interval. Returns its state, or None if there is no such task.
+    pub fn trigger(&self, name: &str) -> Option<TaskStatus> {
+        let task = self.tasks.lock().unwrap().get(name).cloned()?;
+        debug!("Triggering task {}", name);
+        task.trigger.notify();
+        let status = task.status.lock().unwrap().clone();
+        Some(status)
+    }
+}
+
+/// Builder of a task added to the [`Scheduler`](./struct.Scheduler.html)
+pub struct Task {
+    scheduler: Scheduler,
+    name: String,
+    interval: Duration,
+    interval_updates: Option<watch::Receiver<Duration>>,
+    delay_first_run: bool,
+    active: Option<Arc<AtomicBool>>,
+}
+
+impl Task {
+    /// Replaces the interval (and reschedules the next run) whenever a new value is
+    /// received on `updates`. The current value of the channel is the initial interval.
+    pub fn interval_updates(mut self, updates: watch::Receiver<Duration>) -> Self {
+        self.interval = *updates.borrow();
+        self.interval_updates = Some(updates);
+        self
+    }
+
+    /// Waits for an interval before the first run, rather than running the task right away
+    pub fn delay_first_run(mut self) -> Self {
+        self.delay_first_run = true;
+        self
+    }
+
+    /// Only runs the task on its interval while the flag is set. When multiple nodes
+    /// share the same store, this lets only one of them run it. Triggered runs ignore it.
+    pub fn only_while(mut self, active: Arc<AtomicBool>) -> Self {
+        self.active = Some(active);
+        self
+    }
+
+    /// Spawns the loop which calls `run` on the interval and whenever the task is triggered
+    pub fn spawn<F, Fut>(self, run: F)
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), String>> + Send + 'static,
+    {
+        let config = self
+            .scheduler
+            .config
+            .tasks
+            .get(&self.name)
+            .cloned()
+            .unwrap_or_default();
+        let jitter = config
+            .jitter
+            .unwrap_or(self.scheduler.config.jitter)
+            .clamp(0.0, 1.0);
+        let task = Arc::new(ScheduledTask {
+            status: Mutex::new(TaskStatus {
+                name: self.name.clone(),
+                interval: self.interval.as_millis() as u64,
+                jitter,
+                manual_only: config.manual_only,
+                ..TaskStatus::default()
+            }),
+            trigger: Notify::new(),
+        });
+        self.scheduler
+            .tasks
+            .lock()
+            .unwrap()
+            .insert(self.name.clone(), task.clone());
+
+        let run: Arc<dyn Fn() -> BoxFuture<'static, Result<(), String>> + Send + Sync> =
+            Arc::new(move || run().boxed());
+        let name = self.name;
+        let mut interval = self.interval;
+        let mut updates = self.interval_updates;
+        let active = self.active;
+        let mut first_run = !self.delay_first_run;
+        debug!("Scheduling task {} every {:?}", name, interval);
+        tokio::spawn(async move {
+            loop {
+                let delay = if first_run {
+                    Duration::from_millis(0)
+                } else {
+                    jittered(interval, jitter)
+                };
+                task.status.lock().unwrap().next_run = if config.manual_only {
+                    None
+                } else {
+                    Some(millis_since_epoch(clock::now() + delay))
+                };
+
+                let triggered = tokio::select! {
+                    _ = delay_for(delay), if !config.manual_only => false,
+                    _ = task.trigger.notified() => true,
+                    update = receive(&mut updates), if updates.is_some() => {
+                        match update {
+                            Some(new_interval) if new_interval != interval => {
+                                debug!("Changing the interval of task {} to {:?}", name, new_interval);
+                                interval = new_interval;
+                                task.status.lock().unwrap().interval =
+                                    new_interval.as_millis() as u64;
+                            }
+                            Some(_) => {}
+                            // The sender was dropped so the interval will not change anymore
+                            None => updates = None,
+                        }
+                        continue;
+                    }
+                };
+                first_run = false;
+
+                if !triggered {
+                    if let Some(ref active) = active {
+                        if !active.load(Ordering::Relaxed) {
+                            trace!("Skipping task {}, which only runs on the leader", name);
+                            continue;
+                        }
+                    }
+                }
+                run_task(&name, &task, run.as_ref()).await;
+            }
+        });
+    }
+}
+
+async fn receive(updates: &mut Option<watch::Receiver<Duration>>) -> Option<Duration> {
+    match updates {
+        Some(updates) => updates.recv().await,
+        None => None,
+    }
+}
+
+async fn run_task(
+    name: &str,
+    task: &ScheduledTask,
+    run: &(dyn Fn() -> BoxFuture<'static, Result<(), String>> + Send + Sync),
+) {
+    {
+        let mut status = task.status.lock().unwrap();
+        status.running = true;
+        status.next_run = None;
+        status.last_run = Some(millis_since_epoch(clock::now()));
+    }
+    trace!("Running task {}", name);
+    let started = Instant::now();
+    let result = run().await;
+    let duration = started.elapsed();
+
+    let mut status = task.status.lock().unwrap();
+    status.running = false;
+    status.runs += 1;
+    status.last_duration = Some(duration.as_millis() as u64);
+    record_task_run(name, duration, result.is_ok());
+    match result {
+        Ok(()) => {
+            trace!("Task {} completed in {:?}", name, duration);
+            status.last_error = None;
+        }
+        Err(err) => {
+            warn!("Task {} failed after {:?}: {}", name, duration, err);
+            status.failures += 1;
+            status.last_error = Some(err);
+        }
+    }
+}
+
+/// Counts the runs of the task in `tasks.runs` and the failed ones in `tasks.failures`,
+/// and records their duration, in milliseconds, in `tasks.duration`
+fn record_task_run(name: &str, duration: Duration, succeeded: bool) {
+    let labels = labels!("task" => name.to_string());
+    recorder().increment_counter(Key::from_name_and_labels("tasks.runs", labels.clone()), 1);
+    if !succeeded {
+        recorder().increment_counter(
+            Key::from_name_and_labels("tasks.failures", labels.clone()),
+            1,
+        );
+    }
+    recorder().record_histogram(
+        Key::from_name_and_labels("tasks.duration", labels),
+        duration.as_millis() as u64,
+    );
+}
+
+/// Moves the interval randomly by up to the `jitter` fraction of it, earlier or later
+fn jittered(interval: Duration, jitter: f64) -> Duration {
+    if jitter <= 0.0 {
+        return interval;
+    }
+    let mut bytes = [0; 4];
+    if SystemRandom::new().fill(&mut bytes).is_err() {
+        return interval;
+    }
+    // Uniformly distributed in [-1, 1]
+    let random = f64::from(u32::from_be_bytes(bytes)) / f64::from(u32::MAX) * 2.0 - 1.0;
+    interval.mul_f64(1.0 + jitter * random)
+}
+
+fn millis_since_epoch(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    fn counting_task(scheduler: &Scheduler, name: &str, interval: Duration) -> Arc<AtomicUsize> {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let runs_clone = runs.clone();
+        scheduler.task(name, interval).spawn(move || {
+            let runs = runs_clone.clone();
+            async move {
+                runs.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            }
+        });
+        runs
+    }
+
+    #[test]
+    fn jitter_stays_within_bounds() {
+        let interval = Duration::from_millis(1000);
+        assert_eq!(jittered(interval, 0.0), interval);
+        for _ in 0..100 {
+            let delay = jittered(interval, 0.2);
+            assert!(delay >= Duration::from_millis(800));
+            assert!(delay <= Duration::from_millis(1200));
+        }
+    }
+
+    #[tokio::test]
+    async fn runs_on_the_interval() {
+        let scheduler = Scheduler::new(SchedulerConfig {
+            jitter: 0.0,
+            tasks: HashMap::new(),
+        });
+        let runs = counting_task(&scheduler, "count", Duration::from_millis(20));
+        delay_for(Duration::from_millis(110)).await;
+        assert!(runs.load(Ordering::SeqCst) >= 3);
+
+        let statuses = scheduler.statuses();
+        assert_eq!(statuses.len(), 1);
+        assert_eq!(statuses[0].name, "count");
+        assert_eq!(statuses[0].interval, 20);
+        assert!(statuses[0].runs >= 3);
+        assert_eq!(statuses[0].failures, 0);
+        assert!(statuses[0].last_run.is_some());
+    }
+
+    #[tokio::test]
+    async fn runs_manual_only_tasks_when_triggered() {
+        let mut tasks = HashMap::new();
+        tasks.insert(
+            "count".to_string(),
+            TaskConfig {
+                jitter: None,
+                manual_only: true,
+            },
+        );
+        let scheduler = Scheduler::new(SchedulerConfig { jitter: 0.0, tasks });
+        let runs = counting_task(&scheduler, "count", Duration::from_millis(10));
+        delay_for(Duration::from_millis(50)).await;
+        assert_eq!(runs.load(Ordering::SeqCst), 0);
+        assert_eq!(scheduler.statuses()[0].next_run, None);
+
+        assert!(scheduler.trigger("count").is_some());
+        delay_for(Duration::from_millis(20)).await;
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+        assert!(scheduler.trigger("unknown").is_none());
+    }
+
+    #[tokio::test]
+    async fn records_failures() {
+        let scheduler = Scheduler::default();
+        scheduler
+            .task("fail", Duration::from_secs(60))
+            .spawn(|| async { Err("unreachable".to_string()) });
+        delay_for(Duration::from_millis(20)).await;
+        let status = &scheduler.statuses()[0];
+        assert_eq!(status.runs, 1);
+        assert_eq!(status.failures, 1);
+        assert_eq!(status.last_error, Some("unreachable".to_string()));
+        assert!(status.next_run.is_some());
+    }
+
+    #[tokio::test]
+    async fn skips_inactive_tasks_unless_triggered() {
+        let scheduler = Scheduler::default();
+        let runs = Arc::new(AtomicUsize::new(0));
+        let runs_clone = runs.clone();
+        scheduler
+            .task("leader", Duration::from_millis(10))
+            .only_while(Arc::new(AtomicBool::new(false)))
+            .spawn(move || {
+                let runs = runs_clone.clone();
+                async move {
+                    runs.fetch_add(1, Ordering::SeqCst);
+                    Ok(())
+                }
+            });
+        delay_for(Duration::from_millis(50)).await;
+        assert_eq!(runs.load(Ordering::SeqCst), 0);
+        scheduler.trigger("leader");
+        delay_for(Duration::from_millis(20)).await;
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn runs_on_the_virtual_clock_of_simulations() {
+        interledger_service::simulation::run(async {
+            let scheduler = Scheduler::new(SchedulerConfig {
+                jitter: 0.0,
+                tasks: HashMap::new(),
+            });
+            let start = millis_since_epoch(clock::now());
+            let runs = counting_task(&scheduler, "hourly", Duration::from_secs(3600));
+            // A day passes without waiting for it
+            delay_for(Duration::from_secs(24 * 3600 + 1)).await;
+            assert_eq!(runs.load(Ordering::SeqCst), 25);
+
+            let status = &scheduler.statuses()[0];
+            assert_eq!(status.last_run, Some(start + 24 * 3600 * 1000));
+            assert_eq!(status.next_run, Some(start + 25 * 3600 * 1000));
+        });
+    }
+}
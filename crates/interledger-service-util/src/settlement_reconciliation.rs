@@ -0,0 +1,370 @@
+use futures::future::join_all;
+use interledger_service::{clock, Account};
+use interledger_settlement::core::{
+    types::{SettlementAccount, SettlementTotals, SettlementTotalsStore},
+    SettlementClient,
+};
+use serde::Serialize;
+use std::{
+    convert::TryFrom,
+    sync::{Arc, RwLock},
+    time::UNIX_EPOCH,
+};
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+/// The totals settled with an account, as recorded by the node and by the account's
+/// settlement engine
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct AccountReconciliation {
+    pub account_id: Uuid,
+    pub username: String,
+    /// Asset scale of the amounts, which is the smaller of the account's and the engine's
+    /// so that the amounts lost to precision do not count as discrepancies
+    pub asset_scale: u8,
+    /// Total of the outgoing settlements which the node deducted from the balance
+    pub node_sent: u64,
+    /// Total of the incoming settlements which the engine notified the node of
+    pub node_received: u64,
+    /// Total of the outgoing settlements which the engine executed
+    pub engine_sent: Option<u64>,
+    /// Total of the incoming settlements which the engine received
+    pub engine_received: Option<u64>,
+    /// When the totals were first found to differ, in milliseconds since the UNIX epoch,
+    /// if they still do
+    pub discrepancy_since: Option<u64>,
+    /// Why the totals could not be compared
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// The results of the latest reconciliation of the accounts which have a settlement engine
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct ReconciliationReport {
+    /// When the reconciliation ran, in milliseconds since the UNIX epoch, if it did
+    pub reconciled_at: Option<u64>,
+    pub accounts: Vec<AccountReconciliation>,
+}
+
+/// The latest report of the reconciliation between the settlements recorded by the node
+/// and the totals of the settlement engines, which catches the settlements the engines
+/// received but failed to notify the node of (or the other way around)
+#[derive(Clone, Default)]
+pub struct SettlementReconciliation(Arc<RwLock<ReconciliationReport>>);
+
+impl SettlementReconciliation {
+    /// Returns the report of the latest reconciliation
+    pub fn report(&self) -> ReconciliationReport {
+        self.0.read().unwrap().clone()
+    }
+
+    /// Returns the reconciliation of the account if its totals differed the last time
+    pub fn discrepancy(&self, account_id: Uuid) -> Option<AccountReconciliation> {
+        self.0
+            .read()
+            .unwrap()
+            .accounts
+            .iter()
+            .find(|account| account.account_id == account_id)
+            .filter(|account| account.discrepancy_since.is_some())
+            .cloned()
+    }
+
+    /// Compares the totals settled with each of the accounts which have a settlement engine
+    /// with the totals of the engine, and replaces the report with the results. A discrepancy
+    /// keeps the time it was first found for as long as the totals differ. Returns the
+    /// number of accounts whose totals differ.
+    pub async fn reconcile<S, A>(
+        &self,
+        store: &S,
+        client: &SettlementClient,
+        accounts: Vec<A>,
+    ) -> usize
+    where
+        S: SettlementTotalsStore,
+        A: SettlementAccount,
+    {
+        let now = clock::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        let reconciliations = join_all(
+            accounts
+                .iter()
+                .filter_map(|account| Some((account, account.settlement_engine_details()?.url)))
+                .map(|(account, engine_url)| async move {
+                    let engine_totals = client
+                        .get_settlement_totals(account.id(), engine_url)
+                        .await
+                        .map_err(|err| format!("Error loading the totals of the engine: {}", err));
+                    let node_totals = store
+                        .get_settlement_totals(account.id())
+                        .await
+                        .map_err(|err| format!("Error loading the totals of the node: {}", err));
+                    reconcile_account(account, node_totals, engine_totals)
+                }),
+        )
+        .await;
+
+        let mut report = self.0.write().unwrap();
+        let mut accounts = Vec::with_capacity(reconciliations.len());
+        for mut reconciliation in reconciliations {
+            if reconciliation.discrepancy_since.is_some() {
+                let since = report
+                    .accounts
+                    .iter()
+                    .find(|previous| previous.account_id == reconciliation.account_id)
+                    .and_then(|previous| previous.discrepancy_since);
+                if since.is_none() {
+                    warn!(
+                        "Settlement totals of account {} differ from the engine's: the node sent {} and received {}, the engine sent {} and received {} (scale {})",
+                        reconciliation.username,
+                        reconciliation.node_sent,
+                        reconciliation.node_received,
+                        reconciliation.engine_sent.unwrap_or_default(),
+                        reconciliation.engine_received.unwrap_or_default(),
+                        reconciliation.asset_scale,
+                    );
+                }
+                reconciliation.discrepancy_since = Some(since.unwrap_or(now));
+            } else if let Some(ref error) = reconciliation.error {
+                debug!(
+                    "Could not reconcile the settlements of account {}: {}",
+                    reconciliation.username, error
+                );
+            }
+            accounts.push(reconciliation);
+        }
+        let discrepancies = accounts
+            .iter()
+            .filter(|account| account.discrepancy_since.is_some())
+            .count();
+        *report = ReconciliationReport {
+            reconciled_at: Some(now),
+            accounts,
+        };
+        discrepancies
+    }
+}
+
+/// Compares the totals in the smaller of the two asset scales. The time of the discrepancy
+/// is only a marker here, which the caller replaces.
+fn reconcile_account<A: Account>(
+    account: &A,
+    node_totals: Result<(u64, u64), String>,
+    engine_totals: Result<SettlementTotals, String>,
+) -> AccountReconciliation {
+    let mut reconciliation = AccountReconciliation {
+        account_id: account.id(),
+        username: account.username().to_string(),
+        asset_scale: account.asset_scale(),
+        node_sent: 0,
+        node_received: 0,
+        engine_sent: None,
+        engine_received: None,
+        discrepancy_since: None,
+        error: None,
+    };
+    let result = node_totals.and_then(|(node_sent, node_received)| {
+        let engine_totals = engine_totals?;
+        let scale = account
+            .asset_scale()
+            .min(engine_totals.sent.scale)
+            .min(engine_totals.received.scale);
+        reconciliation.asset_scale = scale;
+        let from_node = |amount: u64| scale_down(amount.into(), account.asset_scale(), scale);
+        reconciliation.node_sent = from_node(node_sent)?;
+        reconciliation.node_received = from_node(node_received)?;
+        let from_engine = |amount: &str, engine_scale: u8| {
+            let amount = amount
+                .parse::<u128>()
+                .map_err(|_| format!("Invalid amount from the engine: {}", amount))?;
+            scale_down(amount, engine_scale, scale)
+        };
+        reconciliation.engine_sent = Some(from_engine(
+            &engine_totals.sent.amount,
+            engine_totals.sent.scale,
+        )?);
+        reconciliation.engine_received = Some(from_engine(
+            &engine_totals.received.amount,
+            engine_totals.received.scale,
+        )?);
+        Ok(())
+    });
+    match result {
+        Ok(()) => {
+            if reconciliation.engine_sent != Some(reconciliation.node_sent)
+                || reconciliation.engine_received != Some(reconciliation.node_received)
+            {
+                reconciliation.discrepancy_since = Some(0);
+            }
+        }
+        Err(error) => reconciliation.error = Some(error),
+    }
+    reconciliation
+}
+
+/// Scales the amount down to the smaller scale, dropping the remainder
+fn scale_down(amount: u128, from: u8, to: u8) -> Result<u64, String> {
+    let scaled = 10u128
+        .checked_pow((from - to).into())
+        .map_or(0, |divisor| amount / divisor);
+    u64::try_from(scaled).map_err(|_| format!("Settled amount too large: {}", scaled))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use interledger_errors::SettlementStoreError;
+    use interledger_packet::Address;
+    use interledger_service::Username;
+    use interledger_settlement::core::types::SettlementEngineDetails;
+    use mockito::mock;
+    use once_cell::sync::Lazy;
+    use std::str::FromStr;
+
+    #[derive(Clone, Debug)]
+    struct TestAccount(Uuid);
+
+    impl Account for TestAccount {
+        fn id(&self) -> Uuid {
+            self.0
+        }
+
+        fn username(&self) -> &Username {
+            &ALICE
+        }
+
+        fn asset_code(&self) -> &str {
+            "XYZ"
+        }
+
+        fn asset_scale(&self) -> u8 {
+            6
+        }
+
+        fn ilp_address(&self) -> &Address {
+            &EXAMPLE_ADDRESS
+        }
+    }
+
+    impl SettlementAccount for TestAccount {
+        fn settlement_engine_details(&self) -> Option<SettlementEngineDetails> {
+            Some(SettlementEngineDetails {
+                url: mockito::server_url().parse().unwrap(),
+            })
+        }
+    }
+
+    struct TestStore(u64, u64);
+
+    #[async_trait]
+    impl SettlementTotalsStore for TestStore {
+        async fn get_settlement_totals(
+            &self,
+            _account_id: Uuid,
+        ) -> Result<(u64, u64), SettlementStoreError> {
+            Ok((self.0, self.1))
+        }
+    }
+
+    fn mock_totals(id: Uuid, body: &str) -> mockito::Mock {
+        mock("GET", format!("/accounts/{}/settlements", id).as_str())
+            .with_status(200)
+            .with_body(body)
+            .create()
+    }
+
+    #[tokio::test]
+    async fn matches_totals_in_the_smaller_scale() {
+        let account = TestAccount(Uuid::new_v4());
+        // The engine works at scale 9, with amounts the node could not be credited
+        let _m = mock_totals(
+            account.id(),
+            r#"{"sent":{"amount":"1000000999","scale":9},"received":{"amount":"50000","scale":9}}"#,
+        );
+        let reconciliation = SettlementReconciliation::default();
+        let discrepancies = reconciliation
+            .reconcile(
+                &TestStore(1_000_000, 50),
+                &SettlementClient::default(),
+                vec![account.clone()],
+            )
+            .await;
+        assert_eq!(discrepancies, 0);
+        let report = reconciliation.report();
+        assert!(report.reconciled_at.is_some());
+        assert_eq!(report.accounts[0].asset_scale, 6);
+        assert_eq!(report.accounts[0].engine_sent, Some(1_000_000));
+        assert_eq!(report.accounts[0].engine_received, Some(50));
+        assert!(reconciliation.discrepancy(account.id()).is_none());
+    }
+
+    #[tokio::test]
+    async fn flags_lost_settlement_notifications() {
+        let account = TestAccount(Uuid::new_v4());
+        let _m = mock_totals(
+            account.id(),
+            r#"{"sent":{"amount":"0","scale":6},"received":{"amount":"300","scale":6}}"#,
+        );
+        let reconciliation = SettlementReconciliation::default();
+        let client = SettlementClient::default();
+        // The node was only notified of 100 of the 300 the engine received
+        let store = TestStore(0, 100);
+        assert_eq!(
+            reconciliation
+                .reconcile(&store, &client, vec![account.clone()])
+                .await,
+            1
+        );
+        let discrepancy = reconciliation.discrepancy(account.id()).unwrap();
+        assert_eq!(discrepancy.node_received, 100);
+        assert_eq!(discrepancy.engine_received, Some(300));
+
+        // The discrepancy keeps the time it was found at
+        reconciliation
+            .reconcile(&store, &client, vec![account.clone()])
+            .await;
+        assert_eq!(
+            reconciliation
+                .discrepancy(account.id())
+                .unwrap()
+                .discrepancy_since,
+            discrepancy.discrepancy_since
+        );
+
+        // And is cleared once the totals match
+        reconciliation
+            .reconcile(&TestStore(0, 300), &client, vec![account.clone()])
+            .await;
+        assert!(reconciliation.discrepancy(account.id()).is_none());
+    }
+
+    #[tokio::test]
+    async fn reports_engines_which_cannot_be_queried() {
+        let account = TestAccount(Uuid::new_v4());
+        let _m = mock(
+            "GET",
+            format!("/accounts/{}/settlements", account.id()).as_str(),
+        )
+        .with_status(501)
+        .create();
+        let reconciliation = SettlementReconciliation::default();
+        let discrepancies = reconciliation
+            .reconcile(
+                &TestStore(0, 0),
+                &SettlementClient::default(),
+                vec![account.clone()],
+            )
+            .await;
+        assert_eq!(discrepancies, 0);
+        let report = reconciliation.report();
+        assert!(report.accounts[0].error.is_some());
+        assert_eq!(report.accounts[0].engine_sent, None);
+    }
+
+    static ALICE: Lazy<Username> = Lazy::new(|| Username::from_str("alice").unwrap());
+    static EXAMPLE_ADDRESS: Lazy<Address> =
+        Lazy::new(|| Address::from_str("example.alice").unwrap());
+}
@@ -0,0 +1,649 @@
+use async_trait::async_trait;
+use chrono::{DateTime, TimeZone, Utc};
+use interledger_packet::Address;
+use interledger_service::*;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex};
+
+/// Length, in seconds, of the periods the packets are counted in
+const BUCKET_SECONDS: i64 = 3600;
+/// Number of periods the overview covers (24 hours)
+const BUCKETS: usize = 24;
+/// Number of segments of the destination addresses the packets are grouped by,
+/// so that the connection tags of STREAM receivers do not create a new destination
+/// for every connection
+const DESTINATION_SEGMENTS: usize = 3;
+/// Largest number of destinations counted per period
+const MAX_DESTINATIONS: usize = 1000;
+/// Number of destinations listed in the overview
+const TOP_DESTINATIONS: usize = 10;
+/// Largest number of asset pairs whose conversions are counted per period
+const MAX_ASSET_PAIRS: usize = 1000;
+
+/// Number of Prepare packets received, and of the ones which were fulfilled or rejected
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct PacketCounts {
+    pub prepare: u64,
+    pub fulfill: u64,
+    pub reject: u64,
+}
+
+/// Amount of the fulfilled packets received in an asset
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct AssetVolume {
+    pub asset_code: String,
+    pub asset_scale: u8,
+    pub amount: u64,
+}
+
+/// Number of Prepare packets sent to a destination
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct DestinationCount {
+    pub destination: String,
+    pub packets: u64,
+}
+
+/// Aggregate statistics of the packets received by the node during the last 24 hours
+#[derive(Clone, Debug, Serialize)]
+pub struct StatsOverview {
+    /// Start of the period covered by the statistics
+    pub since: String,
+    pub packets: PacketCounts,
+    pub volume: Vec<AssetVolume>,
+    pub top_destinations: Vec<DestinationCount>,
+    /// Number of Reject packets by error code
+    pub errors: BTreeMap<String, u64>,
+}
+
+/// The conversion applied by the `ExchangeRateService` to a packet it forwarded
+#[derive(Clone, Debug, PartialEq)]
+pub struct Conversion {
+    pub source_asset_code: String,
+    pub source_asset_scale: u8,
+    pub destination_asset_code: String,
+    pub destination_asset_scale: u8,
+    /// Amount of the incoming packet
+    pub source_amount: u64,
+    /// Exchange rate of the two assets, before the spread
+    pub market_rate: f64,
+    /// Exchange rate applied to the packet, after the spread
+    pub rate: f64,
+    /// Amount the packet is worth at the market rate (in the outgoing units)
+    pub market_amount: f64,
+    /// Amount converted at the rate applied, before the fees (in the outgoing units)
+    pub converted_amount: f64,
+    /// Fees deducted from the converted amount (in the outgoing units)
+    pub fee: f64,
+    /// Amount of the outgoing packet, which is rounded down
+    pub destination_amount: u64,
+    /// Whether the packet was fulfilled
+    pub fulfilled: bool,
+}
+
+/// Conversions of the packets forwarded from one asset to another
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ConversionStats {
+    pub source_asset_code: String,
+    pub source_asset_scale: u8,
+    pub destination_asset_code: String,
+    pub destination_asset_scale: u8,
+    /// Number of packets forwarded, and of the ones which were fulfilled. The other
+    /// figures only count the fulfilled packets, on which the margin is earned.
+    pub packets: u64,
+    pub fulfilled: u64,
+    pub source_amount: u64,
+    pub destination_amount: u64,
+    /// Average of the market rates, weighted by the amounts of the packets
+    pub market_rate: f64,
+    /// Average of the rates applied, weighted by the amounts of the packets
+    pub rate: f64,
+    /// Value kept by the spread (in the outgoing units)
+    pub spread: f64,
+    /// Fees deducted (in the outgoing units)
+    pub fees: f64,
+    /// Fractions of units lost by rounding down the outgoing amounts (in the outgoing units)
+    pub rounding: f64,
+    /// Share of the value of the packets at the market rate which was not forwarded,
+    /// which is the margin made on the pair
+    pub slippage: f64,
+}
+
+/// Aggregate statistics of the conversions applied by the node during the last 24 hours
+#[derive(Clone, Debug, Serialize)]
+pub struct ConversionOverview {
+    /// Start of the period covered by the statistics
+    pub since: String,
+    pub pairs: Vec<ConversionStats>,
+}
+
+/// The source and destination assets of a conversion, with their scales
+type AssetPair = (String, u8, String, u8);
+
+#[derive(Clone, Default)]
+struct ConversionTotals {
+    packets: u64,
+    fulfilled: u64,
+    source_amount: u64,
+    destination_amount: u64,
+    /// Sums of the rates multiplied by the amounts of the packets
+    weighted_market_rate: f64,
+    weighted_rate: f64,
+    market_amount: f64,
+    spread: f64,
+    fees: f64,
+    rounding: f64,
+}
+
+impl ConversionTotals {
+    fn add(&mut self, other: &ConversionTotals) {
+        self.packets += other.packets;
+        self.fulfilled += other.fulfilled;
+        self.source_amount = self.source_amount.saturating_add(other.source_amount);
+        self.destination_amount = self
+            .destination_amount
+            .saturating_add(other.destination_amount);
+        self.weighted_market_rate += other.weighted_market_rate;
+        self.weighted_rate += other.weighted_rate;
+        self.market_amount += other.market_amount;
+        self.spread += other.spread;
+        self.fees += other.fees;
+        self.rounding += other.rounding;
+    }
+}
+
+#[derive(Default)]
+struct StatsBucket {
+    /// Index of the hour (since the UNIX epoch) which is counted in this bucket
+    hour: i64,
+    packets: PacketCounts,
+    volume: HashMap<(String, u8), u64>,
+    destinations: HashMap<String, u64>,
+    errors: HashMap<String, u64>,
+    conversions: HashMap<AssetPair, ConversionTotals>,
+}
+
+/// Statistics of the packets received by the node, counted by the `StatsService`
+/// in a bucket per hour for the last 24 hours
+#[derive(Clone, Default)]
+pub struct NodeStats(Arc<Mutex<Vec<StatsBucket>>>);
+
+impl NodeStats {
+    /// Returns the statistics of the last 24 hours
+    pub fn overview(&self) -> StatsOverview {
+        self.overview_at(Utc::now())
+    }
+
+    fn overview_at(&self, now: DateTime<Utc>) -> StatsOverview {
+        let current_hour = now.timestamp() / BUCKET_SECONDS;
+        let first_hour = current_hour - BUCKETS as i64 + 1;
+        let mut packets = PacketCounts::default();
+        let mut volume: HashMap<(String, u8), u64> = HashMap::new();
+        let mut destinations: HashMap<String, u64> = HashMap::new();
+        let mut errors = BTreeMap::new();
+
+        let buckets = self.0.lock().unwrap();
+        for bucket in buckets
+            .iter()
+            .filter(|bucket| bucket.hour >= first_hour && bucket.hour <= current_hour)
+        {
+            packets.prepare += bucket.packets.prepare;
+            packets.fulfill += bucket.packets.fulfill;
+            packets.reject += bucket.packets.reject;
+            for (asset, amount) in bucket.volume.iter() {
+                let total = volume.entry(asset.clone()).or_default();
+                *total = total.saturating_add(*amount);
+            }
+            for (destination, count) in bucket.destinations.iter() {
+                *destinations.entry(destination.clone()).or_default() += count;
+            }
+            for (code, count) in bucket.errors.iter() {
+                *errors.entry(code.clone()).or_default() += count;
+            }
+        }
+        drop(buckets);
+
+        let mut volume: Vec<AssetVolume> = volume
+            .into_iter()
+            .map(|((asset_code, asset_scale), amount)| AssetVolume {
+                asset_code,
+                asset_scale,
+                amount,
+            })
+            .collect();
+        volume.sort_by(|a, b| (&a.asset_code, a.asset_scale).cmp(&(&b.asset_code, b.asset_scale)));
+        let mut top_destinations: Vec<DestinationCount> = destinations
+            .into_iter()
+            .map(|(destination, packets)| DestinationCount {
+                destination,
+                packets,
+            })
+            .collect();
+        top_destinations.sort_by(|a, b| {
+            b.packets
+                .cmp(&a.packets)
+                .then_with(|| a.destination.cmp(&b.destination))
+        });
+        top_destinations.truncate(TOP_DESTINATIONS);
+
+        StatsOverview {
+            since: Utc.timestamp(first_hour * BUCKET_SECONDS, 0).to_rfc3339(),
+            packets,
+            volume,
+            top_destinations,
+            errors,
+        }
+    }
+
+    /// Returns the statistics of the conversions of the last 24 hours, by asset pair
+    pub fn conversions(&self) -> ConversionOverview {
+        self.conversions_at(Utc::now())
+    }
+
+    fn conversions_at(&self, now: DateTime<Utc>) -> ConversionOverview {
+        let current_hour = now.timestamp() / BUCKET_SECONDS;
+        let first_hour = current_hour - BUCKETS as i64 + 1;
+        let mut totals: HashMap<AssetPair, ConversionTotals> = HashMap::new();
+
+        let buckets = self.0.lock().unwrap();
+        for bucket in buckets
+            .iter()
+            .filter(|bucket| bucket.hour >= first_hour && bucket.hour <= current_hour)
+        {
+            for (pair, conversions) in bucket.conversions.iter() {
+                totals.entry(pair.clone()).or_default().add(conversions);
+            }
+        }
+        drop(buckets);
+
+        let mut pairs: Vec<ConversionStats> = totals
+            .into_iter()
+            .map(|(pair, totals)| {
+                let ratio = |value: f64, total: f64| if total > 0.0 { value / total } else { 0.0 };
+                let source_amount = totals.source_amount as f64;
+                ConversionStats {
+                    source_asset_code: pair.0,
+                    source_asset_scale: pair.1,
+                    destination_asset_code: pair.2,
+                    destination_asset_scale: pair.3,
+                    packets: totals.packets,
+                    fulfilled: totals.fulfilled,
+                    source_amount: totals.source_amount,
+                    destination_amount: totals.destination_amount,
+                    market_rate: ratio(totals.weighted_market_rate, source_amount),
+                    rate: ratio(totals.weighted_rate, source_amount),
+                    spread: totals.spread,
+                    fees: totals.fees,
+                    rounding: totals.rounding,
+                    slippage: ratio(
+                        totals.market_amount - totals.destination_amount as f64,
+                        totals.market_amount,
+                    ),
+                }
+            })
+            .collect();
+        pairs.sort_by(|a, b| {
+            (&a.source_asset_code, a.source_asset_scale)
+                .cmp(&(&b.source_asset_code, b.source_asset_scale))
+                .then_with(|| {
+                    (&a.destination_asset_code, a.destination_asset_scale)
+                        .cmp(&(&b.destination_asset_code, b.destination_asset_scale))
+                })
+        });
+
+        ConversionOverview {
+            since: Utc.timestamp(first_hour * BUCKET_SECONDS, 0).to_rfc3339(),
+            pairs,
+        }
+    }
+
+    /// Counts the conversion of a packet forwarded by the `ExchangeRateService`
+    pub fn record_conversion(&self, conversion: &Conversion) {
+        self.record_conversion_at(Utc::now(), conversion)
+    }
+
+    fn record_conversion_at(&self, now: DateTime<Utc>, conversion: &Conversion) {
+        let pair = (
+            conversion.source_asset_code.clone(),
+            conversion.source_asset_scale,
+            conversion.destination_asset_code.clone(),
+            conversion.destination_asset_scale,
+        );
+        let mut buckets = self.0.lock().unwrap();
+        let bucket = current_bucket(&mut buckets, now);
+        if bucket.conversions.len() >= MAX_ASSET_PAIRS && !bucket.conversions.contains_key(&pair) {
+            return;
+        }
+        let totals = bucket.conversions.entry(pair).or_default();
+        totals.packets += 1;
+        if conversion.fulfilled {
+            let source_amount = conversion.source_amount as f64;
+            totals.fulfilled += 1;
+            totals.source_amount = totals
+                .source_amount
+                .saturating_add(conversion.source_amount);
+            totals.destination_amount = totals
+                .destination_amount
+                .saturating_add(conversion.destination_amount);
+            totals.weighted_market_rate += conversion.market_rate * source_amount;
+            totals.weighted_rate += conversion.rate * source_amount;
+            totals.market_amount += conversion.market_amount;
+            totals.spread += conversion.market_amount - conversion.converted_amount;
+            totals.fees += conversion.fee;
+            totals.rounding +=
+                conversion.converted_amount - conversion.fee - conversion.destination_amount as f64;
+        }
+    }
+
+    fn record<A: Account>(
+        &self,
+        now: DateTime<Utc>,
+        from: &A,
+        destination: &Address,
+        amount: u64,
+        result: &IlpResult,
+    ) {
+        let mut buckets = self.0.lock().unwrap();
+        let bucket = current_bucket(&mut buckets, now);
+        bucket.packets.prepare += 1;
+        let destination = destination_prefix(destination);
+        if bucket.destinations.len() < MAX_DESTINATIONS
+            || bucket.destinations.contains_key(&destination)
+        {
+            *bucket.destinations.entry(destination).or_default() += 1;
+        }
+        match result {
+            Ok(_) => {
+                bucket.packets.fulfill += 1;
+                let volume = bucket
+                    .volume
+                    .entry((from.asset_code().to_string(), from.asset_scale()))
+                    .or_default();
+                *volume = volume.saturating_add(amount);
+            }
+            Err(reject) => {
+                bucket.packets.reject += 1;
+                *bucket.errors.entry(reject.code().to_string()).or_default() += 1;
+            }
+        }
+    }
+}
+
+/// Returns the bucket of the hour, which is reset if it still counts the packets of a
+/// previous day
+fn current_bucket(buckets: &mut Vec<StatsBucket>, now: DateTime<Utc>) -> &mut StatsBucket {
+    let hour = now.timestamp() / BUCKET_SECONDS;
+    if buckets.is_empty() {
+        buckets.resize_with(BUCKETS, StatsBucket::default);
+    }
+    let bucket = &mut buckets[hour.rem_euclid(BUCKETS as i64) as usize];
+    if bucket.hour != hour {
+        *bucket = StatsBucket {
+            hour,
+            ..StatsBucket::default()
+        };
+    }
+    bucket
+}
+
+fn destination_prefix(destination: &Address) -> String {
+    destination
+        .segments()
+        .take(DESTINATION_SEGMENTS)
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// # Stats Service
+///
+/// Incoming Service which counts the packets received by the node, their outcome, the
+/// volume fulfilled per asset and their destinations into the shared `NodeStats`.
+/// Forwards everything.
+#[derive(Clone)]
+pub struct StatsService<I, A> {
+    stats: NodeStats,
+    next: I,
+    account_type: PhantomData<A>,
+}
+
+impl<I, A> StatsService<I, A>
+where
+    I: IncomingService<A>,
+    A: Account,
+{
+    /// Constructs a service which counts the packets into the provided stats
+    pub fn new(stats: NodeStats, next: I) -> Self {
+        StatsService {
+            stats,
+            next,
+            account_type: PhantomData,
+        }
+    }
+}
+
+#[async_trait]
+impl<I, A> IncomingService<A> for StatsService<I, A>
+where
+    I: IncomingService<A> + Send + Sync + 'static,
+    A: Account + Send + Sync + 'static,
+{
+    async fn handle_request(&mut self, request: IncomingRequest<A>) -> IlpResult {
+        let from = request.from.clone();
+        let destination = request.prepare.destination();
+        let amount = request.prepare.amount();
+        let result = self.next.handle_request(request).await;
+        self.stats
+            .record(Utc::now(), &from, &destination, amount, &result);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use interledger_packet::{ErrorCode, FulfillBuilder, RejectBuilder};
+    use once_cell::sync::Lazy;
+    use std::str::FromStr;
+    use uuid::Uuid;
+
+    #[derive(Clone, Debug)]
+    struct TestAccount;
+
+    static ALICE: Lazy<Username> = Lazy::new(|| Username::from_str("alice").unwrap());
+    static EXAMPLE_ADDRESS: Lazy<Address> =
+        Lazy::new(|| Address::from_str("example.alice").unwrap());
+
+    impl Account for TestAccount {
+        fn id(&self) -> Uuid {
+            Uuid::nil()
+        }
+
+        fn username(&self) -> &Username {
+            &ALICE
+        }
+
+        fn asset_scale(&self) -> u8 {
+            9
+        }
+
+        fn asset_code(&self) -> &str {
+            "XYZ"
+        }
+
+        fn ilp_address(&self) -> &Address {
+            &EXAMPLE_ADDRESS
+        }
+    }
+
+    fn fulfill() -> IlpResult {
+        Ok(FulfillBuilder {
+            fulfillment: &[0; 32],
+            data: &[],
+        }
+        .build())
+    }
+
+    fn reject(code: ErrorCode) -> IlpResult {
+        Err(RejectBuilder {
+            code,
+            message: &[],
+            triggered_by: None,
+            data: &[],
+        }
+        .build())
+    }
+
+    fn address(address: &str) -> Address {
+        Address::from_str(address).unwrap()
+    }
+
+    #[test]
+    fn aggregates_last_day() {
+        let stats = NodeStats::default();
+        let now = Utc.timestamp(1_600_000_000, 0);
+        let hour_ago = now - chrono::Duration::hours(1);
+        let stream_receiver = address("example.bob.receiver.connection-tag");
+        stats.record(now, &TestAccount, &stream_receiver, 100, &fulfill());
+        stats.record(hour_ago, &TestAccount, &stream_receiver, 50, &fulfill());
+        stats.record(
+            now,
+            &TestAccount,
+            &address("example.charlie"),
+            10,
+            &reject(ErrorCode::F02_UNREACHABLE),
+        );
+
+        let overview = stats.overview_at(now);
+        assert_eq!(
+            overview.packets,
+            PacketCounts {
+                prepare: 3,
+                fulfill: 2,
+                reject: 1,
+            }
+        );
+        assert_eq!(
+            overview.volume,
+            vec![AssetVolume {
+                asset_code: "XYZ".to_string(),
+                asset_scale: 9,
+                amount: 150,
+            }]
+        );
+        assert_eq!(
+            overview.top_destinations,
+            vec![
+                DestinationCount {
+                    destination: "example.bob.receiver".to_string(),
+                    packets: 2,
+                },
+                DestinationCount {
+                    destination: "example.charlie".to_string(),
+                    packets: 1,
+                },
+            ]
+        );
+        assert_eq!(overview.errors.get("F02"), Some(&1));
+    }
+
+    #[test]
+    fn forgets_packets_older_than_a_day() {
+        let stats = NodeStats::default();
+        let now = Utc.timestamp(1_600_000_000, 0);
+        let day_ago = now - chrono::Duration::hours(24);
+        stats.record(
+            day_ago,
+            &TestAccount,
+            &address("example.bob"),
+            100,
+            &fulfill(),
+        );
+        assert_eq!(stats.overview_at(day_ago).packets.prepare, 1);
+
+        stats.record(now, &TestAccount, &address("example.bob"), 100, &fulfill());
+        let overview = stats.overview_at(now);
+        assert_eq!(overview.packets.prepare, 1);
+        assert_eq!(overview.volume[0].amount, 100);
+    }
+
+    fn conversion(source_amount: u64, destination_amount: u64, fulfilled: bool) -> Conversion {
+        // 1 XYZ is worth 2 ABC, with a spread of 1% and a fee of 1 unit
+        Conversion {
+            source_asset_code: "XYZ".to_string(),
+            source_asset_scale: 9,
+            destination_asset_code: "ABC".to_string(),
+            destination_asset_scale: 9,
+            source_amount,
+            market_rate: 2.0,
+            rate: 1.98,
+            market_amount: source_amount as f64 * 2.0,
+            converted_amount: source_amount as f64 * 1.98,
+            fee: 1.0,
+            destination_amount,
+            fulfilled,
+        }
+    }
+
+    #[test]
+    fn aggregates_conversions_by_pair() {
+        let stats = NodeStats::default();
+        let now = Utc.timestamp(1_600_000_000, 0);
+        let hour_ago = now - chrono::Duration::hours(1);
+        // 1000 XYZ are converted to 1980 ABC, 1979 after the fee
+        stats.record_conversion_at(now, &conversion(1000, 1979, true));
+        // 250 XYZ are converted to 494.5 ABC, 493.5 after the fee, which is rounded down
+        let mut rounded = conversion(250, 493, true);
+        rounded.converted_amount = 494.5;
+        stats.record_conversion_at(hour_ago, &rounded);
+        stats.record_conversion_at(now, &conversion(100, 197, false));
+
+        let overview = stats.conversions_at(now);
+        assert_eq!(overview.pairs.len(), 1);
+        let pair = &overview.pairs[0];
+        assert_eq!(pair.source_asset_code, "XYZ");
+        assert_eq!(pair.destination_asset_code, "ABC");
+        assert_eq!(pair.packets, 3);
+        assert_eq!(pair.fulfilled, 2);
+        assert_eq!(pair.source_amount, 1250);
+        assert_eq!(pair.destination_amount, 2472);
+        assert!((pair.market_rate - 2.0).abs() < 1e-9);
+        assert!((pair.rate - 1.98).abs() < 1e-9);
+        assert!((pair.spread - 25.5).abs() < 1e-9);
+        assert!((pair.fees - 2.0).abs() < 1e-9);
+        assert!((pair.rounding - 0.5).abs() < 1e-9);
+        // 28 of the 2500 units the packets were worth were kept
+        assert!((pair.slippage - 0.0112).abs() < 1e-9);
+
+        let day_later = now + chrono::Duration::hours(24);
+        assert!(stats.conversions_at(day_later).pairs.is_empty());
+    }
+
+    #[tokio::test]
+    async fn counts_incoming_packets() {
+        let stats = NodeStats::default();
+        let mut service = StatsService::new(
+            stats.clone(),
+            incoming_service_fn(|_| reject(ErrorCode::T04_INSUFFICIENT_LIQUIDITY)),
+        );
+        service
+            .handle_request(IncomingRequest {
+                from: TestAccount,
+                prepare: interledger_packet::PrepareBuilder {
+                    destination: address("example.bob"),
+                    amount: 100,
+                    expires_at: std::time::SystemTime::now(),
+                    execution_condition: &[0; 32],
+                    data: &[],
+                }
+                .build(),
+            })
+            .await
+            .unwrap_err();
+
+        let overview = stats.overview();
+        assert_eq!(overview.packets.reject, 1);
+        assert_eq!(overview.errors.get("T04"), Some(&1));
+        assert!(overview.volume.is_empty());
+    }
+}
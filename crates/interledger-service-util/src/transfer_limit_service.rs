@@ -0,0 +1,540 @@
+use async_trait::async_trait;
+use futures::TryFutureExt;
+use interledger_errors::BalanceStoreError;
+use interledger_packet::{ErrorCode, RejectBuilder, TransferLimitExceededDetails};
+use interledger_service::*;
+use metrics::{labels, recorder, Key};
+use serde::{Deserialize, Serialize};
+use std::{
+    marker::PhantomData,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tracing::{debug, error};
+use uuid::Uuid;
+
+const HOUR: u64 = 3_600_000;
+const DAY: u64 = 24 * HOUR;
+
+/// The caps on the amounts an account sends and receives over rolling windows of time
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct TransferLimits {
+    /// The max amount the account may send over the last 24 hours
+    #[serde(default)]
+    pub daily_send_limit: Option<u64>,
+    /// The max amount the account may send over the last 30 days
+    #[serde(default)]
+    pub monthly_send_limit: Option<u64>,
+    /// The max amount the account may receive over the last 24 hours
+    #[serde(default)]
+    pub daily_receive_limit: Option<u64>,
+    /// The max amount the account may receive over the last 30 days
+    #[serde(default)]
+    pub monthly_receive_limit: Option<u64>,
+}
+
+/// A rolling window over which the amounts of an account are capped. The amounts are
+/// counted in buckets (of one hour for the daily window and of one day for the monthly
+/// window), so an amount leaves the window once its whole bucket is out of it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferWindow {
+    Daily,
+    Monthly,
+}
+
+impl TransferWindow {
+    /// Length of the buckets of the window, in milliseconds
+    pub fn bucket_length(self) -> u64 {
+        match self {
+            TransferWindow::Daily => HOUR,
+            TransferWindow::Monthly => DAY,
+        }
+    }
+
+    /// Number of buckets in the window
+    pub fn buckets(self) -> u64 {
+        match self {
+            TransferWindow::Daily => 24,
+            TransferWindow::Monthly => 30,
+        }
+    }
+
+    /// Index of the bucket of the time, in milliseconds since the UNIX epoch
+    pub fn bucket(self, at: u64) -> u64 {
+        at / self.bucket_length()
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TransferWindow::Daily => "daily",
+            TransferWindow::Monthly => "monthly",
+        }
+    }
+}
+
+/// The amount counted in a window of an account, and its limit
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct WindowUsage {
+    pub limit: Option<u64>,
+    pub used: u64,
+    /// When the oldest amount counted in the window leaves it, in milliseconds since
+    /// the UNIX epoch. It is not set if nothing is counted in the window.
+    pub reset_at: Option<u64>,
+}
+
+impl WindowUsage {
+    /// Sums the amounts of the buckets (as bucket indexes and amounts) which are in the
+    /// window at the time, in milliseconds since the UNIX epoch
+    pub fn new(
+        window: TransferWindow,
+        limit: Option<u64>,
+        buckets: &[(u64, u64)],
+        now: u64,
+    ) -> Self {
+        let current = window.bucket(now);
+        let in_window = buckets
+            .iter()
+            .filter(|(index, amount)| index + window.buckets() > current && *amount > 0);
+        WindowUsage {
+            limit,
+            used: in_window
+                .clone()
+                .fold(0u64, |used, (_, amount)| used.saturating_add(*amount)),
+            reset_at: in_window
+                .map(|(index, _)| *index)
+                .min()
+                .map(|oldest| (oldest + window.buckets()) * window.bucket_length()),
+        }
+    }
+}
+
+/// The amounts sent and received by an account in its windows. They are only counted
+/// while the account has a limit in the direction.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct TransferUsage {
+    pub daily_send: WindowUsage,
+    pub monthly_send: WindowUsage,
+    pub daily_receive: WindowUsage,
+    pub monthly_receive: WindowUsage,
+}
+
+/// The amounts of a Prepare packet counted in the windows of the sending and receiving
+/// accounts, which are uncounted if the packet is rejected
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TransferCharge {
+    /// The account which sent the Prepare packet
+    pub from_account_id: Uuid,
+    /// The amount of the Prepare packet which was received from the sending account
+    pub incoming_amount: u64,
+    /// The account to which the Prepare packet is forwarded
+    pub to_account_id: Uuid,
+    /// The amount of the Prepare packet which is forwarded to the receiving account
+    pub outgoing_amount: u64,
+    /// When the packet was charged, in milliseconds since the UNIX epoch. The amounts are
+    /// counted in the buckets of that time.
+    pub charged_at: u64,
+    /// Whether the incoming amount was counted, because the sending account has a send limit
+    pub sent: bool,
+    /// Whether the outgoing amount was counted, because the receiving account has a receive limit
+    pub received: bool,
+}
+
+impl TransferCharge {
+    pub fn new(
+        from_account_id: Uuid,
+        incoming_amount: u64,
+        to_account_id: Uuid,
+        outgoing_amount: u64,
+    ) -> Self {
+        TransferCharge {
+            from_account_id,
+            incoming_amount,
+            to_account_id,
+            outgoing_amount,
+            charged_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_millis() as u64)
+                .unwrap_or_default(),
+            sent: false,
+            received: false,
+        }
+    }
+}
+
+/// Trait responsible for the transfer limits of the accounts and the windowed counters
+/// of the amounts they send and receive
+#[async_trait]
+pub trait TransferLimitStore {
+    /// Returns the transfer limits of the account and the amounts counted in its windows
+    async fn get_transfer_usage(
+        &self,
+        account_id: Uuid,
+    ) -> Result<TransferUsage, BalanceStoreError>;
+
+    /// Replaces the transfer limits of the account, and returns its updated usage
+    async fn set_transfer_limits(
+        &self,
+        account_id: Uuid,
+        limits: TransferLimits,
+    ) -> Result<TransferUsage, BalanceStoreError>;
+
+    /// Counts the amounts of the packet in the windows of the sending account's send limits
+    /// and of the receiving account's receive limits, unless it would exceed one of them.
+    /// Returns the charge with the amounts which were counted.
+    async fn charge_transfer_limits(
+        &self,
+        charge: TransferCharge,
+    ) -> Result<TransferCharge, BalanceStoreError>;
+
+    /// Uncounts the amounts of a rejected packet
+    async fn refund_transfer_limits(
+        &self,
+        charge: &TransferCharge,
+    ) -> Result<(), BalanceStoreError>;
+}
+
+/// # Transfer Limit Service
+///
+/// Caps the amounts the accounts send and receive over rolling daily and monthly windows.
+/// The packets which would exceed a limit are rejected with `T99: Application Error`, with
+/// `TransferLimitExceededDetails` as data, which hold the limit, the amount already counted
+/// in its window, and when the oldest of that amount leaves the window. Each rejected
+/// packet increments the `transfer_limits.exceeded` counter, labelled with the account and
+/// the limit. The amounts of the rejected packets are uncounted.
+///
+/// Requires a `TransferLimitStore`
+#[derive(Clone)]
+pub struct TransferLimitService<S, O, A> {
+    store: S,
+    next: O,
+    account_type: PhantomData<A>,
+}
+
+impl<S, O, A> TransferLimitService<S, O, A>
+where
+    S: AddressStore + TransferLimitStore,
+    O: OutgoingService<A>,
+    A: Account,
+{
+    pub fn new(store: S, next: O) -> Self {
+        TransferLimitService {
+            store,
+            next,
+            account_type: PhantomData,
+        }
+    }
+}
+
+#[async_trait]
+impl<S, O, A> OutgoingService<A> for TransferLimitService<S, O, A>
+where
+    S: AddressStore + TransferLimitStore + Clone + Send + Sync + 'static,
+    O: OutgoingService<A> + Send + Sync + 'static,
+    A: Account + Send + Sync + 'static,
+{
+    /// On send message:
+    /// 1. Counts the amounts of the packet in the windows of the accounts with limits, and
+    ///    rejects it if it would exceed one of them
+    /// 1. Forwards the request, and uncounts the amounts if it is rejected
+    async fn send_request(&mut self, request: OutgoingRequest<A>) -> IlpResult {
+        if request.prepare.amount() == 0 && request.original_amount == 0 {
+            return self.next.send_request(request).await;
+        }
+
+        let ilp_address = self.store.get_ilp_address();
+        let charge = TransferCharge::new(
+            request.from.id(),
+            request.original_amount,
+            request.to.id(),
+            request.prepare.amount(),
+        );
+        let charge = match self.store.charge_transfer_limits(charge).await {
+            Ok(charge) => charge,
+            Err(err) => {
+                let (message, data) = match err {
+                    BalanceStoreError::TransferLimitExceeded {
+                        direction,
+                        window,
+                        limit,
+                        used,
+                        reset_at,
+                        ..
+                    } => {
+                        let account = if direction == "send" {
+                            &request.from
+                        } else {
+                            &request.to
+                        };
+                        debug!(
+                            "Rejecting packet because it would exceed the {} {} limit of account {}",
+                            window,
+                            direction,
+                            account.username()
+                        );
+                        record_exceeded(account, direction, window);
+                        (
+                            format!(
+                                "{} {} limit exceeded, resets at {}",
+                                window, direction, reset_at
+                            ),
+                            TransferLimitExceededDetails::new(limit, used, reset_at)
+                                .to_bytes()
+                                .to_vec(),
+                        )
+                    }
+                    err => {
+                        error!("Error applying the transfer limits: {}", err);
+                        return Err(RejectBuilder {
+                            code: ErrorCode::T00_INTERNAL_ERROR,
+                            message: &[],
+                            triggered_by: Some(&ilp_address),
+                            data: &[],
+                        }
+                        .build());
+                    }
+                };
+                return Err(RejectBuilder {
+                    code: ErrorCode::T99_APPLICATION_ERROR,
+                    message: message.as_bytes(),
+                    triggered_by: Some(&ilp_address),
+                    data: &data,
+                }
+                .build());
+            }
+        };
+        if !charge.sent && !charge.received {
+            return self.next.send_request(request).await;
+        }
+
+        let result = self.next.send_request(request).await;
+        if result.is_err() {
+            // As with the balances, the reject is relayed without waiting for the store
+            let store = self.store.clone();
+            tokio::spawn(async move {
+                store
+                    .refund_transfer_limits(&charge)
+                    .map_err(|err| {
+                        error!(
+                            "Error uncounting the amounts of a rejected packet from the transfer limits of accounts {} and {}: {}",
+                            charge.from_account_id, charge.to_account_id, err
+                        )
+                    })
+                    .await
+            });
+        }
+        result
+    }
+}
+
+fn record_exceeded<A: Account>(account: &A, direction: &'static str, window: &'static str) {
+    recorder().increment_counter(
+        Key::from_name_and_labels(
+            "transfer_limits.exceeded",
+            labels!(
+                "account" => account.username().to_string(),
+                "limit" => format!("{}_{}", window, direction),
+            ),
+        ),
+        1,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use interledger_errors::AddressStoreError;
+    use interledger_packet::{Address, FulfillBuilder, PrepareBuilder};
+    use interledger_service::{outgoing_service_fn, Username};
+    use once_cell::sync::Lazy;
+    use parking_lot::Mutex;
+    use std::str::FromStr;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    static NODE: Lazy<Address> = Lazy::new(|| Address::from_str("example.node").unwrap());
+
+    #[derive(Clone, Debug)]
+    struct TestAccount;
+
+    impl Account for TestAccount {
+        fn id(&self) -> Uuid {
+            Uuid::nil()
+        }
+
+        fn username(&self) -> &Username {
+            static USERNAME: Lazy<Username> = Lazy::new(|| Username::from_str("alice").unwrap());
+            &USERNAME
+        }
+
+        fn asset_scale(&self) -> u8 {
+            9
+        }
+
+        fn asset_code(&self) -> &str {
+            "XYZ"
+        }
+
+        fn ilp_address(&self) -> &Address {
+            &NODE
+        }
+    }
+
+    /// Store with a daily send limit of 100, which records the refunds
+    #[derive(Clone, Default)]
+    struct TestStore {
+        used: Arc<Mutex<u64>>,
+        refunds: Arc<Mutex<Vec<TransferCharge>>>,
+    }
+
+    #[async_trait]
+    impl AddressStore for TestStore {
+        async fn set_ilp_address(&self, _: Address) -> Result<(), AddressStoreError> {
+            unimplemented!()
+        }
+
+        async fn clear_ilp_address(&self) -> Result<(), AddressStoreError> {
+            unimplemented!()
+        }
+
+        fn get_ilp_address(&self) -> Address {
+            NODE.clone()
+        }
+    }
+
+    #[async_trait]
+    impl TransferLimitStore for TestStore {
+        async fn get_transfer_usage(&self, _: Uuid) -> Result<TransferUsage, BalanceStoreError> {
+            unimplemented!()
+        }
+
+        async fn set_transfer_limits(
+            &self,
+            _: Uuid,
+            _: TransferLimits,
+        ) -> Result<TransferUsage, BalanceStoreError> {
+            unimplemented!()
+        }
+
+        async fn charge_transfer_limits(
+            &self,
+            charge: TransferCharge,
+        ) -> Result<TransferCharge, BalanceStoreError> {
+            let mut used = self.used.lock();
+            if *used + charge.incoming_amount > 100 {
+                return Err(BalanceStoreError::TransferLimitExceeded {
+                    account: charge.from_account_id.to_string(),
+                    direction: "send",
+                    window: "daily",
+                    limit: 100,
+                    used: *used,
+                    reset_at: 1000,
+                });
+            }
+            *used += charge.incoming_amount;
+            Ok(TransferCharge {
+                sent: true,
+                ..charge
+            })
+        }
+
+        async fn refund_transfer_limits(
+            &self,
+            charge: &TransferCharge,
+        ) -> Result<(), BalanceStoreError> {
+            *self.used.lock() -= charge.incoming_amount;
+            self.refunds.lock().push(*charge);
+            Ok(())
+        }
+    }
+
+    fn request(amount: u64) -> OutgoingRequest<TestAccount> {
+        OutgoingRequest {
+            from: TestAccount,
+            to: TestAccount,
+            original_amount: amount,
+            prepare: PrepareBuilder {
+                destination: Address::from_str("example.destination").unwrap(),
+                amount,
+                expires_at: SystemTime::now() + Duration::from_secs(30),
+                execution_condition: &[0; 32],
+                data: &[],
+            }
+            .build(),
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_the_packets_exceeding_a_limit() {
+        let store = TestStore::default();
+        let next = outgoing_service_fn(|_| {
+            Ok(FulfillBuilder {
+                fulfillment: &[0; 32],
+                data: &[],
+            }
+            .build())
+        });
+        let mut service = TransferLimitService::new(store.clone(), next);
+        service.send_request(request(60)).await.unwrap();
+        let reject = service.send_request(request(50)).await.unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::T99_APPLICATION_ERROR);
+        assert_eq!(
+            reject.message(),
+            &b"daily send limit exceeded, resets at 1000"[..]
+        );
+        assert_eq!(
+            TransferLimitExceededDetails::from_bytes(reject.data()).unwrap(),
+            TransferLimitExceededDetails::new(100, 60, 1000)
+        );
+        assert!(store.refunds.lock().is_empty());
+    }
+
+    #[tokio::test]
+    async fn refunds_the_rejected_packets() {
+        let store = TestStore::default();
+        let next = outgoing_service_fn(|_| {
+            Err(RejectBuilder {
+                code: ErrorCode::F02_UNREACHABLE,
+                message: &[],
+                triggered_by: None,
+                data: &[],
+            }
+            .build())
+        });
+        let mut service = TransferLimitService::new(store.clone(), next);
+        service.send_request(request(60)).await.unwrap_err();
+        tokio::time::delay_for(Duration::from_millis(50)).await;
+        let refunds = store.refunds.lock();
+        assert_eq!(refunds.len(), 1);
+        assert_eq!(refunds[0].incoming_amount, 60);
+        assert_eq!(*store.used.lock(), 0);
+    }
+
+    #[test]
+    fn sums_the_buckets_in_the_window() {
+        let now = 100 * DAY + 5 * HOUR;
+        let hour = TransferWindow::Daily.bucket(now);
+        let buckets = [(hour - 24, 1000), (hour - 23, 10), (hour - 1, 0), (hour, 5)];
+        assert_eq!(
+            WindowUsage::new(TransferWindow::Daily, Some(100), &buckets, now),
+            WindowUsage {
+                limit: Some(100),
+                used: 15,
+                reset_at: Some((hour + 1) * HOUR),
+            }
+        );
+
+        let day = TransferWindow::Monthly.bucket(now);
+        let buckets = [(day - 30, 1000), (day - 29, 10), (day, 5)];
+        assert_eq!(
+            WindowUsage::new(TransferWindow::Monthly, None, &buckets, now),
+            WindowUsage {
+                limit: None,
+                used: 15,
+                reset_at: Some((day + 1) * DAY),
+            }
+        );
+        assert_eq!(
+            WindowUsage::new(TransferWindow::Daily, None, &[], now),
+            WindowUsage::default()
+        );
+    }
+}
@@ -12,11 +12,35 @@ use tracing::error;
 /// Incoming or Outgoing Service responsible for rejecting timed out
 /// requests and checking that fulfillments received match the `execution_condition` from the original `Prepare` packets.
 /// Forwards everything else.
+///
+/// Incoming packets can be required to have a minimum time left before they expire, and the
+/// expiry check can tolerate a difference between the clocks of the node and its peers.
 #[derive(Clone)]
 pub struct ValidatorService<IO, S, A> {
     store: S,
     next: IO,
     account_type: PhantomData<A>,
+    /// Minimum time, in milliseconds, an incoming packet must have left before it expires
+    min_expiry_window: u32,
+    /// Time, in milliseconds, by which an incoming packet may appear to have expired
+    /// because the clock of the peer is behind ours
+    max_clock_skew: u32,
+}
+
+impl<IO, S, A> ValidatorService<IO, S, A> {
+    /// Sets the minimum time, in milliseconds, an incoming packet must have left before it
+    /// expires. Packets expiring sooner are rejected with `R02: Insufficient Timeout`.
+    pub fn min_expiry_window(&mut self, milliseconds: u32) -> &mut Self {
+        self.min_expiry_window = milliseconds;
+        self
+    }
+
+    /// Sets the maximum difference, in milliseconds, between the clocks of the node and
+    /// its peers which is tolerated when checking whether incoming packets expired
+    pub fn max_clock_skew(&mut self, milliseconds: u32) -> &mut Self {
+        self.max_clock_skew = milliseconds;
+        self
+    }
 }
 
 impl<I, S, A> ValidatorService<I, S, A>
@@ -32,6 +56,8 @@ where
             store,
             next,
             account_type: PhantomData,
+            min_expiry_window: 0,
+            max_clock_skew: 0,
         }
     }
 }
@@ -50,6 +76,8 @@ where
             store,
             next,
             account_type: PhantomData,
+            min_expiry_window: 0,
+            max_clock_skew: 0,
         }
     }
 }
@@ -62,27 +90,66 @@ where
     A: Account + Send + Sync,
 {
     /// On receiving a request:
-    /// 1. If the prepare packet in the request is not expired, forward it, otherwise return a reject
+    /// 1. If the prepare packet in the request is expired (allowing for the max clock skew), return a reject
+    /// 1. If the prepare packet expires sooner than the minimum expiry window, return a reject
+    /// 1. Otherwise forward it
+    ///
+    /// The data of the rejects holds the expiry of the packet, the time of the node (both in
+    /// milliseconds since the UNIX epoch) and the max clock skew or minimum expiry window
+    /// (in milliseconds) which was applied, each as a big-endian u64.
     async fn handle_request(&mut self, request: IncomingRequest<A>) -> IlpResult {
         let expires_at = DateTime::<Utc>::from(request.prepare.expires_at());
-        let now = Utc::now();
-        if expires_at >= now {
-            self.next.handle_request(request).await
-        } else {
+        let now = DateTime::<Utc>::from(clock::now());
+        let time_left = expires_at.signed_duration_since(now)
+            + Duration::milliseconds(i64::from(self.max_clock_skew));
+        let (code, message, tolerance) = if time_left < Duration::zero() {
             error!(
                 "Incoming packet expired {}ms ago at {:?} (time now: {:?})",
                 now.signed_duration_since(expires_at).num_milliseconds(),
                 expires_at.to_rfc3339(),
-                expires_at.to_rfc3339(),
+                now.to_rfc3339(),
             );
-            Err(RejectBuilder {
-                code: ErrorCode::R00_TRANSFER_TIMED_OUT,
-                message: &[],
-                triggered_by: Some(&self.store.get_ilp_address()),
-                data: &[],
-            }
-            .build())
+            (
+                ErrorCode::R00_TRANSFER_TIMED_OUT,
+                format!(
+                    "Packet expired at {} (time now: {}, max clock skew: {}ms)",
+                    expires_at.to_rfc3339(),
+                    now.to_rfc3339(),
+                    self.max_clock_skew
+                ),
+                self.max_clock_skew,
+            )
+        } else if time_left < Duration::milliseconds(i64::from(self.min_expiry_window)) {
+            error!(
+                "Incoming packet expires in {}ms, which is less than the minimum of {}ms",
+                time_left.num_milliseconds(),
+                self.min_expiry_window,
+            );
+            (
+                ErrorCode::R02_INSUFFICIENT_TIMEOUT,
+                format!(
+                    "Packet expires at {} (time now: {}), at least {}ms are required",
+                    expires_at.to_rfc3339(),
+                    now.to_rfc3339(),
+                    self.min_expiry_window
+                ),
+                self.min_expiry_window,
+            )
+        } else {
+            return self.next.handle_request(request).await;
+        };
+
+        let mut data = [0; 24];
+        data[..8].copy_from_slice(&(expires_at.timestamp_millis().max(0) as u64).to_be_bytes());
+        data[8..16].copy_from_slice(&(now.timestamp_millis().max(0) as u64).to_be_bytes());
+        data[16..].copy_from_slice(&u64::from(tolerance).to_be_bytes());
+        Err(RejectBuilder {
+            code,
+            message: message.as_bytes(),
+            triggered_by: Some(&self.store.get_ilp_address()),
+            data: &data,
         }
+        .build())
     }
 }
 
@@ -106,7 +173,7 @@ where
         condition[..].copy_from_slice(request.prepare.execution_condition()); // why?
 
         let expires_at = DateTime::<Utc>::from(request.prepare.expires_at());
-        let now = Utc::now();
+        let now = DateTime::<Utc>::from(clock::now());
         let time_left = expires_at - now;
         let ilp_address = self.store.get_ilp_address();
         if time_left > Duration::zero() {
@@ -239,6 +306,8 @@ mod incoming {
     use interledger_packet::*;
     use interledger_service::incoming_service_fn;
     use std::{
+        convert::TryInto,
+        str,
         sync::{Arc, Mutex},
         time::{Duration, SystemTime},
     };
@@ -318,6 +387,59 @@ mod incoming {
             ErrorCode::R00_TRANSFER_TIMED_OUT
         );
     }
+
+    #[tokio::test]
+    async fn applies_min_expiry_window_and_max_clock_skew() {
+        let mut validator = ValidatorService::incoming(
+            TestStore,
+            incoming_service_fn(|_| {
+                Ok(FulfillBuilder {
+                    fulfillment: &[0; 32],
+                    data: b"test data",
+                }
+                .build())
+            }),
+        );
+        validator.min_expiry_window(2000).max_clock_skew(1000);
+        let request = |expires_in: i64| IncomingRequest {
+            from: TestAccount(Uuid::new_v4()),
+            prepare: PrepareBuilder {
+                destination: Address::from_str("example.destination").unwrap(),
+                amount: 100,
+                expires_at: if expires_in > 0 {
+                    SystemTime::now() + Duration::from_millis(expires_in as u64)
+                } else {
+                    SystemTime::now() - Duration::from_millis(-expires_in as u64)
+                },
+                execution_condition: &[0; 32],
+                data: b"test data",
+            }
+            .build(),
+        };
+
+        // 1.5s left, plus 1s of tolerated clock skew
+        assert!(validator.handle_request(request(1500)).await.is_ok());
+
+        let reject = validator.handle_request(request(500)).await.unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::R02_INSUFFICIENT_TIMEOUT);
+        assert!(str::from_utf8(reject.message())
+            .unwrap()
+            .contains("at least 2000ms are required"));
+        let data = reject.data();
+        assert_eq!(data.len(), 24);
+        let expires_at = u64::from_be_bytes(data[..8].try_into().unwrap());
+        let now = u64::from_be_bytes(data[8..16].try_into().unwrap());
+        assert!((450..=500).contains(&(expires_at - now)));
+        assert_eq!(u64::from_be_bytes(data[16..].try_into().unwrap()), 2000);
+
+        let reject = validator.handle_request(request(-1500)).await.unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::R00_TRANSFER_TIMED_OUT);
+        let data = reject.data();
+        let expires_at = u64::from_be_bytes(data[..8].try_into().unwrap());
+        let now = u64::from_be_bytes(data[8..16].try_into().unwrap());
+        assert!((1500..=1550).contains(&(now - expires_at)));
+        assert_eq!(u64::from_be_bytes(data[16..].try_into().unwrap()), 1000);
+    }
 }
 
 #[cfg(test)]
@@ -409,4 +531,42 @@ mod outgoing {
             ErrorCode::F09_INVALID_PEER_RESPONSE
         );
     }
+
+    #[derive(Clone)]
+    struct NeverResponds;
+
+    #[async_trait]
+    impl OutgoingService<TestAccount> for NeverResponds {
+        async fn send_request(&mut self, _: OutgoingRequest<TestAccount>) -> IlpResult {
+            futures::future::pending().await
+        }
+    }
+
+    #[test]
+    fn times_out_when_the_packet_expires() {
+        interledger_service::simulation::run(async {
+            let start = clock::now();
+            let result = ValidatorService::outgoing(TestStore, NeverResponds)
+                .send_request(OutgoingRequest {
+                    from: TestAccount(Uuid::new_v4()),
+                    to: TestAccount(Uuid::new_v4()),
+                    original_amount: 100,
+                    prepare: PrepareBuilder {
+                        destination: Address::from_str("example.destination").unwrap(),
+                        amount: 100,
+                        expires_at: start + Duration::from_secs(30),
+                        execution_condition: &[0; 32],
+                        data: b"test data",
+                    }
+                    .build(),
+                })
+                .await;
+
+            assert_eq!(
+                result.unwrap_err().code(),
+                ErrorCode::R00_TRANSFER_TIMED_OUT
+            );
+            assert_eq!(clock::now(), start + Duration::from_secs(30));
+        });
+    }
 }
@@ -0,0 +1,705 @@
+use super::{Account, AddressStore, IlpResult, IncomingRequest, IncomingService, Username};
+use async_trait::async_trait;
+use interledger_packet::{Address, ErrorCode, Reject, RejectBuilder};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    hash::{Hash, Hasher},
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use unicase::UniCase;
+
+/// Window, in milliseconds, in which the misbehavior of a peer is counted if none is configured
+pub const DEFAULT_MISBEHAVIOR_WINDOW: u64 = 60_000;
+/// Time, in milliseconds, for which a peer is quarantined if none is configured
+pub const DEFAULT_QUARANTINE_DURATION: u64 = 600_000;
+/// Max number of peers whose misbehavior is counted, and of peers quarantined automatically.
+/// Above it, the peers whose window or quarantine ends first are forgotten.
+const MAX_TRACKED_PEERS: usize = 10_000;
+
+/// The kinds of misbehavior which are counted against a peer
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Misbehavior {
+    /// The peer sent data which could not be parsed as a Prepare packet
+    MalformedPacket,
+    /// The peer presented invalid credentials
+    AuthFailure,
+    /// A Prepare packet of the peer was rejected by the node
+    Reject,
+    /// The peer was quarantined via the API
+    Manual,
+}
+
+/// Thresholds of the misbehavior after which a peer is quarantined. Each threshold is the
+/// number of occurrences within the `window` which quarantine the peer. Peers are never
+/// quarantined automatically for the kinds of misbehavior whose threshold is not set.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+pub struct BlocklistConfig {
+    /// Window, in milliseconds, in which the misbehavior is counted. Defaults to 60000ms (1 minute).
+    #[serde(default = "default_window")]
+    pub window: u64,
+    /// Time, in milliseconds, for which a misbehaving peer is quarantined.
+    /// Defaults to 600000ms (10 minutes).
+    #[serde(default = "default_quarantine_duration")]
+    pub quarantine_duration: u64,
+    #[serde(default)]
+    pub max_malformed_packets: Option<u32>,
+    #[serde(default)]
+    pub max_auth_failures: Option<u32>,
+    #[serde(default)]
+    pub max_rejects: Option<u32>,
+}
+
+fn default_window() -> u64 {
+    DEFAULT_MISBEHAVIOR_WINDOW
+}
+
+fn default_quarantine_duration() -> u64 {
+    DEFAULT_QUARANTINE_DURATION
+}
+
+impl Default for BlocklistConfig {
+    fn default() -> Self {
+        BlocklistConfig {
+            window: DEFAULT_MISBEHAVIOR_WINDOW,
+            quarantine_duration: DEFAULT_QUARANTINE_DURATION,
+            max_malformed_packets: None,
+            max_auth_failures: None,
+            max_rejects: None,
+        }
+    }
+}
+
+impl BlocklistConfig {
+    fn threshold(&self, misbehavior: Misbehavior) -> Option<u32> {
+        match misbehavior {
+            Misbehavior::MalformedPacket => self.max_malformed_packets,
+            Misbehavior::AuthFailure => self.max_auth_failures,
+            Misbehavior::Reject => self.max_rejects,
+            Misbehavior::Manual => None,
+        }
+    }
+}
+
+/// A quarantined peer, as returned by the blocklist API
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct BlocklistEntry {
+    /// The account, if the peer was quarantined for the misbehavior of its account
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<Username>,
+    /// The remote address, if the peer was quarantined for misbehaving before it authenticated
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address: Option<IpAddr>,
+    /// The misbehavior the peer was quarantined for
+    pub reason: Misbehavior,
+    /// When the quarantine started, in milliseconds since the UNIX epoch
+    pub since: u64,
+    /// When the quarantine ends, in milliseconds since the UNIX epoch,
+    /// or None if the peer stays quarantined until it is released via the API
+    pub until: Option<u64>,
+}
+
+struct Quarantine {
+    entry: BlocklistEntry,
+    expires_at: Option<Instant>,
+}
+
+impl Quarantine {
+    fn is_active(&self, now: Instant) -> bool {
+        self.expires_at.map_or(true, |expires_at| now < expires_at)
+    }
+}
+
+/// The misbehavior of a peer counted in the current window
+struct Counts {
+    window_start: Instant,
+    malformed_packets: u32,
+    auth_failures: u32,
+    rejects: u32,
+}
+
+impl Counts {
+    fn new(now: Instant) -> Self {
+        Counts {
+            window_start: now,
+            malformed_packets: 0,
+            auth_failures: 0,
+            rejects: 0,
+        }
+    }
+
+    /// Counts the misbehavior in the current window and returns its number of occurrences
+    fn increment(&mut self, misbehavior: Misbehavior) -> u32 {
+        let count = match misbehavior {
+            Misbehavior::MalformedPacket => &mut self.malformed_packets,
+            Misbehavior::AuthFailure => &mut self.auth_failures,
+            Misbehavior::Reject => &mut self.rejects,
+            Misbehavior::Manual => return 0,
+        };
+        *count = count.saturating_add(1);
+        *count
+    }
+}
+
+/// Peers are identified by their accounts once they authenticated, and by their
+/// remote addresses before, so that nobody can get an account quarantined by
+/// failing to authenticate as it
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Peer {
+    Account(Username),
+    Address(IpAddr),
+}
+
+/// Usernames are compared case insensitively, but do not implement `Hash`
+impl Hash for Peer {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self {
+            Peer::Account(username) => UniCase::new(username).hash(state),
+            Peer::Address(address) => address.hash(state),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Peers {
+    counts: HashMap<Peer, Counts>,
+    quarantines: HashMap<Peer, Quarantine>,
+}
+
+/// Tracks the misbehavior of the peers (malformed packets, authentication failures and
+/// rejected packets) and quarantines the peers which exceed the configured thresholds,
+/// so that their packets and connections are refused until the quarantine ends.
+///
+/// The misbehavior of authenticated peers is counted against their accounts, and
+/// the misbehavior before they authenticated against their remote addresses.
+/// The blocklist is kept in memory, so each node sharing a store tracks
+/// (and quarantines) the peers which connect to it.
+#[derive(Clone, Default)]
+pub struct PeerBlocklist {
+    config: BlocklistConfig,
+    peers: Arc<Mutex<Peers>>,
+}
+
+impl PeerBlocklist {
+    /// Creates a blocklist which quarantines the peers according to the configured thresholds
+    pub fn new(config: BlocklistConfig) -> Self {
+        PeerBlocklist {
+            config,
+            peers: Arc::new(Mutex::new(Peers::default())),
+        }
+    }
+
+    /// Counts the misbehavior against the authenticated peer, and quarantines it if that
+    /// exceeds the threshold. Returns whether the peer was quarantined because of this misbehavior.
+    pub fn record(&self, username: &Username, misbehavior: Misbehavior) -> bool {
+        self.record_peer(Peer::Account(username.clone()), misbehavior)
+    }
+
+    /// Counts the misbehavior of a peer which did not authenticate (such as an authentication
+    /// failure) against its remote address, and quarantines the address if that exceeds the
+    /// threshold. Nothing is counted if the address is not known.
+    pub fn record_address(&self, address: Option<IpAddr>, misbehavior: Misbehavior) -> bool {
+        address.map_or(false, |address| {
+            self.record_peer(Peer::Address(address), misbehavior)
+        })
+    }
+
+    fn record_peer(&self, peer: Peer, misbehavior: Misbehavior) -> bool {
+        let threshold = match self.config.threshold(misbehavior) {
+            Some(threshold) => threshold,
+            None => return false,
+        };
+        let now = Instant::now();
+        let window = Duration::from_millis(self.config.window);
+        let mut peers = self.peers.lock().unwrap();
+        if is_quarantined(&mut peers, &peer, now) {
+            return false;
+        }
+
+        if !peers.counts.contains_key(&peer) && peers.counts.len() >= MAX_TRACKED_PEERS {
+            evict_counts(&mut peers, window, now);
+        }
+        let counts = peers
+            .counts
+            .entry(peer.clone())
+            .or_insert_with(|| Counts::new(now));
+        if now.duration_since(counts.window_start) >= window {
+            *counts = Counts::new(now);
+        }
+        if counts.increment(misbehavior) < threshold {
+            return false;
+        }
+
+        peers.counts.remove(&peer);
+        if peers.quarantines.len() >= MAX_TRACKED_PEERS {
+            evict_quarantines(&mut peers, now);
+        }
+        let duration = Duration::from_millis(self.config.quarantine_duration);
+        insert_quarantine(&mut peers, peer, misbehavior, Some(duration), now);
+        true
+    }
+
+    /// Returns whether the peer is currently quarantined
+    pub fn is_quarantined(&self, username: &Username) -> bool {
+        let mut peers = self.peers.lock().unwrap();
+        is_quarantined(&mut peers, &Peer::Account(username.clone()), Instant::now())
+    }
+
+    /// Returns whether the remote address is currently quarantined
+    pub fn is_address_quarantined(&self, address: Option<IpAddr>) -> bool {
+        address.map_or(false, |address| {
+            let mut peers = self.peers.lock().unwrap();
+            is_quarantined(&mut peers, &Peer::Address(address), Instant::now())
+        })
+    }
+
+    /// Quarantines the peer for the given duration, or until it is released if there is none,
+    /// replacing its current quarantine
+    pub fn quarantine(&self, username: &Username, duration: Option<Duration>) -> BlocklistEntry {
+        let mut peers = self.peers.lock().unwrap();
+        insert_quarantine(
+            &mut peers,
+            Peer::Account(username.clone()),
+            Misbehavior::Manual,
+            duration,
+            Instant::now(),
+        )
+    }
+
+    /// Ends the quarantine of the peer and forgets its misbehavior.
+    /// Returns the quarantine which was ended, if the peer was quarantined.
+    pub fn release(&self, username: &Username) -> Option<BlocklistEntry> {
+        self.release_peer(Peer::Account(username.clone()))
+    }
+
+    /// Ends the quarantine of the remote address and forgets its misbehavior.
+    /// Returns the quarantine which was ended, if the address was quarantined.
+    pub fn release_address(&self, address: IpAddr) -> Option<BlocklistEntry> {
+        self.release_peer(Peer::Address(address))
+    }
+
+    fn release_peer(&self, peer: Peer) -> Option<BlocklistEntry> {
+        let mut peers = self.peers.lock().unwrap();
+        peers.counts.remove(&peer);
+        peers
+            .quarantines
+            .remove(&peer)
+            .filter(|quarantine| quarantine.is_active(Instant::now()))
+            .map(|quarantine| quarantine.entry)
+    }
+
+    /// Returns the peers which are currently quarantined
+    pub fn entries(&self) -> Vec<BlocklistEntry> {
+        let now = Instant::now();
+        let mut peers = self.peers.lock().unwrap();
+        peers
+            .quarantines
+            .retain(|_, quarantine| quarantine.is_active(now));
+        let mut entries: Vec<BlocklistEntry> = peers
+            .quarantines
+            .values()
+            .map(|quarantine| quarantine.entry.clone())
+            .collect();
+        // The accounts come first, since None is less than any address
+        entries.sort_by(|a, b| (&a.address, &a.username).cmp(&(&b.address, &b.username)));
+        entries
+    }
+}
+
+fn is_quarantined(peers: &mut Peers, peer: &Peer, now: Instant) -> bool {
+    match peers.quarantines.get(peer) {
+        Some(quarantine) if quarantine.is_active(now) => true,
+        Some(_) => {
+            peers.quarantines.remove(peer);
+            false
+        }
+        None => false,
+    }
+}
+
+/// Forgets the counts whose window ended, and the tenth whose windows started first
+/// if that does not make room for more peers
+fn evict_counts(peers: &mut Peers, window: Duration, now: Instant) {
+    peers
+        .counts
+        .retain(|_, counts| now.duration_since(counts.window_start) < window);
+    if peers.counts.len() >= MAX_TRACKED_PEERS {
+        let mut oldest: Vec<(Instant, Peer)> = peers
+            .counts
+            .iter()
+            .map(|(peer, counts)| (counts.window_start, peer.clone()))
+            .collect();
+        oldest.sort_unstable_by_key(|(window_start, _)| *window_start);
+        for (_, peer) in oldest.into_iter().take(MAX_TRACKED_PEERS / 10) {
+            peers.counts.remove(&peer);
+        }
+    }
+}
+
+/// Forgets the quarantines which ended, and the tenth of the automatic quarantines which
+/// end first if that does not make room for more. Quarantines without an end, which are
+/// only set via the API, are kept until they are released.
+fn evict_quarantines(peers: &mut Peers, now: Instant) {
+    peers
+        .quarantines
+        .retain(|_, quarantine| quarantine.is_active(now));
+    if peers.quarantines.len() >= MAX_TRACKED_PEERS {
+        let mut first_to_end: Vec<(Instant, Peer)> = peers
+            .quarantines
+            .iter()
+            .filter_map(|(peer, quarantine)| {
+                quarantine
+                    .expires_at
+                    .map(|expires_at| (expires_at, peer.clone()))
+            })
+            .collect();
+        first_to_end.sort_unstable_by_key(|(expires_at, _)| *expires_at);
+        for (_, peer) in first_to_end.into_iter().take(MAX_TRACKED_PEERS / 10) {
+            peers.quarantines.remove(&peer);
+        }
+    }
+}
+
+fn insert_quarantine(
+    peers: &mut Peers,
+    peer: Peer,
+    reason: Misbehavior,
+    duration: Option<Duration>,
+    now: Instant,
+) -> BlocklistEntry {
+    let since = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_millis() as u64)
+        .unwrap_or_default();
+    let (username, address) = match &peer {
+        Peer::Account(username) => (Some(username.clone()), None),
+        Peer::Address(address) => (None, Some(*address)),
+    };
+    let entry = BlocklistEntry {
+        username,
+        address,
+        reason,
+        since,
+        until: duration.map(|duration| since + duration.as_millis() as u64),
+    };
+    peers.quarantines.insert(
+        peer,
+        Quarantine {
+            entry: entry.clone(),
+            expires_at: duration.map(|duration| now + duration),
+        },
+    );
+    entry
+}
+
+/// # Peer Blocklist Service
+///
+/// Incoming Service which rejects the packets of the quarantined peers with
+/// `T05: Rate Limited`, and counts the packets of the other peers which the node
+/// rejected against them in the `PeerBlocklist`. Forwards everything else.
+///
+/// The rejects triggered by other nodes, and those the node triggers for reasons
+/// which do not depend on the sender (internal errors, unreachable peers, missing
+/// liquidity and timeouts), are not counted.
+///
+/// Requires an `AddressStore`.
+#[derive(Clone)]
+pub struct PeerBlocklistService<S, I> {
+    store: S,
+    blocklist: PeerBlocklist,
+    next: I,
+}
+
+impl<S, I> PeerBlocklistService<S, I> {
+    pub fn new(store: S, blocklist: PeerBlocklist, next: I) -> Self {
+        PeerBlocklistService {
+            store,
+            blocklist,
+            next,
+        }
+    }
+}
+
+#[async_trait]
+impl<S, I, A> IncomingService<A> for PeerBlocklistService<S, I>
+where
+    S: AddressStore + Send + Sync + 'static,
+    I: IncomingService<A> + Send + Sync + 'static,
+    A: Account + Sync + 'static,
+{
+    async fn handle_request(&mut self, request: IncomingRequest<A>) -> IlpResult {
+        let username = request.from.username().clone();
+        if self.blocklist.is_quarantined(&username) {
+            return Err(RejectBuilder {
+                code: ErrorCode::T05_RATE_LIMITED,
+                message: b"account is quarantined",
+                triggered_by: Some(&self.store.get_ilp_address()),
+                data: &[],
+            }
+            .build());
+        }
+
+        let result = self.next.handle_request(request).await;
+        if let Err(ref reject) = result {
+            if is_charged_to_sender(reject, &self.store.get_ilp_address()) {
+                self.blocklist.record(&username, Misbehavior::Reject);
+            }
+        }
+        result
+    }
+}
+
+fn is_charged_to_sender(reject: &Reject, ilp_address: &Address) -> bool {
+    let triggered_here = reject
+        .triggered_by()
+        .map_or(false, |triggered_by| &triggered_by == ilp_address);
+    let senders_fault = !matches!(
+        reject.code(),
+        ErrorCode::T00_INTERNAL_ERROR
+            | ErrorCode::T01_PEER_UNREACHABLE
+            | ErrorCode::T04_INSUFFICIENT_LIQUIDITY
+            | ErrorCode::R00_TRANSFER_TIMED_OUT
+    );
+    triggered_here && senders_fault
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::incoming_service_fn;
+    use interledger_errors::AddressStoreError;
+    use interledger_packet::{Address, FulfillBuilder, PrepareBuilder};
+    use once_cell::sync::Lazy;
+    use std::str::FromStr;
+    use uuid::Uuid;
+
+    static ALICE: Lazy<Username> = Lazy::new(|| Username::from_str("alice").unwrap());
+
+    fn blocklist(max_rejects: u32) -> PeerBlocklist {
+        PeerBlocklist::new(BlocklistConfig {
+            max_rejects: Some(max_rejects),
+            ..BlocklistConfig::default()
+        })
+    }
+
+    #[test]
+    fn quarantines_peers_exceeding_thresholds() {
+        let blocklist = blocklist(2);
+        assert!(!blocklist.record(&ALICE, Misbehavior::Reject));
+        assert!(!blocklist.is_quarantined(&ALICE));
+        // Misbehavior without a threshold is not counted
+        assert!(!blocklist.record(&ALICE, Misbehavior::AuthFailure));
+        assert!(blocklist.record(&ALICE, Misbehavior::Reject));
+        assert!(blocklist.is_quarantined(&Username::from_str("ALICE").unwrap()));
+
+        let entries = blocklist.entries();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].username, Some(ALICE.clone()));
+        assert_eq!(entries[0].address, None);
+        assert_eq!(entries[0].reason, Misbehavior::Reject);
+        assert_eq!(
+            entries[0].until,
+            Some(entries[0].since + DEFAULT_QUARANTINE_DURATION)
+        );
+    }
+
+    #[test]
+    fn counts_misbehavior_before_authentication_against_addresses() {
+        let blocklist = PeerBlocklist::new(BlocklistConfig {
+            max_auth_failures: Some(2),
+            ..BlocklistConfig::default()
+        });
+        let address: IpAddr = [192, 0, 2, 1].into();
+        assert!(!blocklist.record_address(Some(address), Misbehavior::AuthFailure));
+        assert!(!blocklist.record_address(None, Misbehavior::AuthFailure));
+        assert!(!blocklist.record_address(None, Misbehavior::AuthFailure));
+        assert!(!blocklist.is_address_quarantined(None));
+        assert!(blocklist.record_address(Some(address), Misbehavior::AuthFailure));
+        assert!(blocklist.is_address_quarantined(Some(address)));
+        assert!(!blocklist.is_address_quarantined(Some([192, 0, 2, 2].into())));
+        // The account the failures claimed to be is not quarantined
+        assert!(!blocklist.is_quarantined(&ALICE));
+
+        let entries = blocklist.entries();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].username, None);
+        assert_eq!(entries[0].address, Some(address));
+        assert_eq!(entries[0].reason, Misbehavior::AuthFailure);
+
+        assert_eq!(blocklist.release_address(address), Some(entries[0].clone()));
+        assert!(!blocklist.is_address_quarantined(Some(address)));
+    }
+
+    #[test]
+    fn bounds_the_tracked_peers() {
+        let address = |i: usize| IpAddr::from((i as u32).to_be_bytes());
+        let blocklist = PeerBlocklist::new(BlocklistConfig {
+            max_auth_failures: Some(2),
+            ..BlocklistConfig::default()
+        });
+        for i in 0..MAX_TRACKED_PEERS * 2 {
+            blocklist.record_address(Some(address(i)), Misbehavior::AuthFailure);
+        }
+        assert!(blocklist.peers.lock().unwrap().counts.len() <= MAX_TRACKED_PEERS);
+        // The latest peers are still counted
+        let last = address(MAX_TRACKED_PEERS * 2 - 1);
+        assert!(blocklist.record_address(Some(last), Misbehavior::AuthFailure));
+
+        let blocklist = PeerBlocklist::new(BlocklistConfig {
+            max_auth_failures: Some(1),
+            ..BlocklistConfig::default()
+        });
+        blocklist.quarantine(&ALICE, None);
+        for i in 0..MAX_TRACKED_PEERS * 2 {
+            assert!(blocklist.record_address(Some(address(i)), Misbehavior::AuthFailure));
+        }
+        assert!(blocklist.peers.lock().unwrap().quarantines.len() <= MAX_TRACKED_PEERS);
+        assert!(blocklist.is_address_quarantined(Some(last)));
+        // The quarantines set via the API are kept
+        assert!(blocklist.is_quarantined(&ALICE));
+    }
+
+    #[test]
+    fn quarantines_and_releases_peers_manually() {
+        let blocklist = PeerBlocklist::default();
+        let entry = blocklist.quarantine(&ALICE, None);
+        assert_eq!(entry.reason, Misbehavior::Manual);
+        assert_eq!(entry.until, None);
+        assert!(blocklist.is_quarantined(&ALICE));
+
+        assert_eq!(blocklist.release(&ALICE), Some(entry));
+        assert!(!blocklist.is_quarantined(&ALICE));
+        assert_eq!(blocklist.release(&ALICE), None);
+        assert!(blocklist.entries().is_empty());
+    }
+
+    #[test]
+    fn quarantines_expire() {
+        let blocklist = PeerBlocklist::default();
+        blocklist.quarantine(&ALICE, Some(Duration::from_millis(0)));
+        assert!(!blocklist.is_quarantined(&ALICE));
+        assert!(blocklist.entries().is_empty());
+    }
+
+    #[tokio::test]
+    async fn rejects_packets_of_quarantined_peers() {
+        let blocklist = blocklist(1);
+        let next = incoming_service_fn(|request: IncomingRequest<TestAccount>| {
+            if request.prepare.amount() > 0 {
+                Ok(FulfillBuilder {
+                    fulfillment: &[0; 32],
+                    data: &[],
+                }
+                .build())
+            } else {
+                Err(RejectBuilder {
+                    code: ErrorCode::F02_UNREACHABLE,
+                    message: &[],
+                    triggered_by: Some(&TestStore.get_ilp_address()),
+                    data: &[],
+                }
+                .build())
+            }
+        });
+        let mut service = PeerBlocklistService::new(TestStore, blocklist.clone(), next);
+
+        assert!(service.handle_request(request(100)).await.is_ok());
+        assert!(service.handle_request(request(0)).await.is_err());
+        assert!(blocklist.is_quarantined(&ALICE));
+        let reject = service.handle_request(request(100)).await.unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::T05_RATE_LIMITED);
+        assert_eq!(reject.message(), b"account is quarantined");
+    }
+
+    #[tokio::test]
+    async fn does_not_count_rejects_which_are_not_the_senders_fault() {
+        let blocklist = blocklist(1);
+        let next = incoming_service_fn(|request: IncomingRequest<TestAccount>| {
+            let (code, triggered_by) = match request.prepare.amount() {
+                // Rejected by another node
+                0 => (ErrorCode::F99_APPLICATION_ERROR, "example.receiver"),
+                1 => (ErrorCode::F02_UNREACHABLE, ""),
+                // Rejected by the node for reasons which do not depend on the sender
+                2 => (ErrorCode::T00_INTERNAL_ERROR, "example.connector"),
+                3 => (ErrorCode::T01_PEER_UNREACHABLE, "example.connector"),
+                4 => (ErrorCode::T04_INSUFFICIENT_LIQUIDITY, "example.connector"),
+                _ => (ErrorCode::R00_TRANSFER_TIMED_OUT, "example.connector"),
+            };
+            let triggered_by = Address::from_str(triggered_by).ok();
+            Err(RejectBuilder {
+                code,
+                message: &[],
+                triggered_by: triggered_by.as_ref(),
+                data: &[],
+            }
+            .build())
+        });
+        let mut service = PeerBlocklistService::new(TestStore, blocklist.clone(), next);
+
+        for amount in 0..6 {
+            let reject = service.handle_request(request(amount)).await.unwrap_err();
+            assert_ne!(reject.code(), ErrorCode::T05_RATE_LIMITED);
+        }
+        assert!(!blocklist.is_quarantined(&ALICE));
+    }
+
+    fn request(amount: u64) -> IncomingRequest<TestAccount> {
+        IncomingRequest {
+            from: TestAccount,
+            prepare: PrepareBuilder {
+                destination: Address::from_str("example.destination").unwrap(),
+                amount,
+                expires_at: SystemTime::now() + Duration::from_secs(30),
+                execution_condition: &[0; 32],
+                data: &[],
+            }
+            .build(),
+        }
+    }
+
+    #[derive(Clone, Debug)]
+    struct TestAccount;
+
+    impl Account for TestAccount {
+        fn id(&self) -> Uuid {
+            Uuid::nil()
+        }
+
+        fn username(&self) -> &Username {
+            &ALICE
+        }
+
+        fn asset_scale(&self) -> u8 {
+            9
+        }
+
+        fn asset_code(&self) -> &str {
+            "XYZ"
+        }
+
+        fn ilp_address(&self) -> &Address {
+            unimplemented!()
+        }
+    }
+
+    #[derive(Clone)]
+    struct TestStore;
+
+    #[async_trait]
+    impl AddressStore for TestStore {
+        async fn set_ilp_address(&self, _ilp_address: Address) -> Result<(), AddressStoreError> {
+            unimplemented!()
+        }
+
+        async fn clear_ilp_address(&self) -> Result<(), AddressStoreError> {
+            unimplemented!()
+        }
+
+        fn get_ilp_address(&self) -> Address {
+            Address::from_str("example.connector").unwrap()
+        }
+    }
+}
@@ -0,0 +1,22 @@
+//! The clock the services read the current time from.
+//!
+//! The services use [`now`](./fn.now.html) rather than `SystemTime::now()` to check and set
+//! the expiry of packets, so that they can be run on the virtual clock of a
+//! [`simulation`](../simulation/index.html) (with the `simulation` feature), in which time
+//! only passes when all the tasks are idle.
+
+use std::time::SystemTime;
+
+/// Returns the current time: the time of the system, or the virtual time if it is called
+/// from a simulation
+#[cfg(not(feature = "simulation"))]
+pub fn now() -> SystemTime {
+    SystemTime::now()
+}
+
+/// Returns the current time: the time of the system, or the virtual time if it is called
+/// from a simulation
+#[cfg(feature = "simulation")]
+pub fn now() -> SystemTime {
+    crate::simulation::now().unwrap_or_else(SystemTime::now)
+}
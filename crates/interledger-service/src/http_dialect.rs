@@ -0,0 +1,66 @@
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+
+/// The dialect of ILP-over-HTTP spoken by the peer of an account, which sets the headers of
+/// the requests sent to the peer. Interledger.rs and the JavaScript connector speak the
+/// dialect of the RFC, while the Java connector expects different headers and authentication.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HttpDialect {
+    /// As specified by the [RFC](https://interledger.org/rfcs/0035-ilp-over-http/): the
+    /// outgoing token is sent as a bearer token
+    #[default]
+    Rfc,
+    /// The Java connector with the `SIMPLE` authentication: the requests must also accept
+    /// `application/octet-stream` responses, which the Java connector requires
+    Java,
+    /// The Java connector with the `JWT_HS_256` authentication: the outgoing token is the
+    /// secret with which a short-lived JWT, whose subject is the node's ILP address, is signed
+    JavaJwt,
+}
+
+impl FromStr for HttpDialect {
+    type Err = String;
+
+    fn from_str(dialect: &str) -> Result<Self, Self::Err> {
+        match dialect {
+            "rfc" => Ok(HttpDialect::Rfc),
+            "java" => Ok(HttpDialect::Java),
+            "java_jwt" => Ok(HttpDialect::JavaJwt),
+            _ => Err(format!(
+                "Invalid ILP-over-HTTP dialect (expected rfc, java or java_jwt): {}",
+                dialect
+            )),
+        }
+    }
+}
+
+impl fmt::Display for HttpDialect {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            HttpDialect::Rfc => "rfc",
+            HttpDialect::Java => "java",
+            HttpDialect::JavaJwt => "java_jwt",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_its_display() {
+        for dialect in &[HttpDialect::Rfc, HttpDialect::Java, HttpDialect::JavaJwt] {
+            assert_eq!(
+                HttpDialect::from_str(&dialect.to_string()).unwrap(),
+                *dialect
+            );
+            assert_eq!(
+                serde_json::to_string(dialect).unwrap(),
+                format!("\"{}\"", dialect)
+            );
+        }
+        assert!(HttpDialect::from_str("RFC").is_err());
+    }
+}
@@ -0,0 +1,284 @@
+use super::Account;
+use ipnet::IpNet;
+use serde::{Deserialize, Serialize};
+use std::{convert::TryFrom, fmt, net::IpAddr, str::FromStr};
+
+/// A range of IP addresses in CIDR notation (such as `203.0.113.0/24` or `2001:db8::/32`).
+/// A single address without a prefix length is the network of only that address
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct IpNetwork(IpNet);
+
+impl IpNetwork {
+    /// Whether the address is in this network
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        self.0.contains(&normalize(*ip))
+    }
+}
+
+/// Turns IPv4-mapped IPv6 addresses (`::ffff:a.b.c.d`), which dual-stack
+/// sockets report for IPv4 peers, back into IPv4 addresses
+fn normalize(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(ipv6) => match ipv6.segments() {
+            [0, 0, 0, 0, 0, 0xffff, ..] => ipv6.to_ipv4().map(IpAddr::V4).unwrap_or(ip),
+            _ => ip,
+        },
+        ip => ip,
+    }
+}
+
+impl FromStr for IpNetwork {
+    type Err = String;
+
+    fn from_str(network: &str) -> Result<Self, Self::Err> {
+        network
+            .parse::<IpNet>()
+            .or_else(|_| network.parse::<IpAddr>().map(IpNet::from))
+            .map(|network| IpNetwork(network.trunc()))
+            .map_err(|_| format!("Invalid IP network (expected CIDR notation): {}", network))
+    }
+}
+
+impl TryFrom<String> for IpNetwork {
+    type Error = String;
+
+    fn try_from(network: String) -> Result<Self, Self::Error> {
+        IpNetwork::from_str(&network)
+    }
+}
+
+impl From<IpNetwork> for String {
+    fn from(network: IpNetwork) -> Self {
+        network.to_string()
+    }
+}
+
+impl fmt::Display for IpNetwork {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// Accounts which may restrict the IP addresses their incoming
+/// ILP-over-HTTP requests and BTP connections come from
+pub trait IpAllowlistAccount: Account {
+    /// The networks the account may connect from. Any address is allowed if it is empty
+    fn ip_allowlist(&self) -> &[IpNetwork] {
+        &[]
+    }
+
+    /// Whether the account may connect from the provided address. An unknown
+    /// address is only allowed if the account does not restrict its addresses
+    fn is_ip_allowed(&self, ip: Option<IpAddr>) -> bool {
+        let allowlist = self.ip_allowlist();
+        allowlist.is_empty()
+            || ip.map_or(false, |ip| {
+                allowlist.iter().any(|network| network.contains(&ip))
+            })
+    }
+}
+
+/// The header with which the reverse proxies in front of the node pass on the client's address
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ForwardedHeader {
+    /// `X-Forwarded-For: <client>, <proxy1>, ...`
+    #[default]
+    XForwardedFor,
+    /// `Forwarded: for=<client>, for=<proxy1>, ...` ([RFC 7239](https://tools.ietf.org/html/rfc7239))
+    Forwarded,
+}
+
+impl ForwardedHeader {
+    /// The name of the header
+    pub fn name(self) -> &'static str {
+        match self {
+            ForwardedHeader::XForwardedFor => "x-forwarded-for",
+            ForwardedHeader::Forwarded => "forwarded",
+        }
+    }
+
+    /// Returns the addresses listed by the header, from the client to the last proxy
+    fn addresses<'a>(self, value: &'a str) -> impl Iterator<Item = Option<IpAddr>> + 'a {
+        value.split(',').map(move |element| match self {
+            ForwardedHeader::XForwardedFor => parse_node(element),
+            ForwardedHeader::Forwarded => element
+                .split(';')
+                .filter_map(|pair| {
+                    let mut parts = pair.splitn(2, '=');
+                    match (parts.next(), parts.next()) {
+                        (Some(name), Some(node)) if name.trim().eq_ignore_ascii_case("for") => {
+                            Some(node)
+                        }
+                        _ => None,
+                    }
+                })
+                .next()
+                .and_then(parse_node),
+        })
+    }
+}
+
+/// Parses an address of a forwarding header, which may be quoted, bracketed (IPv6) and
+/// followed by a port. Obfuscated identifiers (such as `unknown` or `_hidden`) are `None`.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim().trim_matches('"');
+    if let Ok(ip) = node.parse::<IpAddr>() {
+        return Some(ip);
+    }
+    if let Some(rest) = node.strip_prefix('[') {
+        return rest.split(']').next()?.parse().ok();
+    }
+    node.rsplitn(2, ':').last()?.parse().ok()
+}
+
+/// The reverse proxies in front of the node, which pass on the address of the clients
+/// in a header. The header is only honored in the requests which come from them, since
+/// anyone else could set it to an allowed address.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct TrustedProxies {
+    /// The networks of the proxies
+    #[serde(default)]
+    pub networks: Vec<IpNetwork>,
+    /// The header which the proxies set
+    #[serde(default)]
+    pub header: ForwardedHeader,
+}
+
+impl TrustedProxies {
+    fn is_trusted(&self, ip: &IpAddr) -> bool {
+        self.networks.iter().any(|network| network.contains(ip))
+    }
+
+    /// Returns the address of the client which sent the request through the peer, given the
+    /// values of the header. The addresses of the header are read from the last one (set by
+    /// the proxy closest to the node) for as long as they are trusted proxies, so that a
+    /// client cannot pass off the addresses it prepends as its own. The peer's address is
+    /// returned if it is not a trusted proxy or the header names no other address.
+    pub fn client_ip<'a>(
+        &self,
+        peer: Option<IpAddr>,
+        header_values: impl IntoIterator<Item = &'a str>,
+    ) -> Option<IpAddr> {
+        let mut client = peer;
+        if !peer.map_or(false, |peer| self.is_trusted(&peer)) {
+            return client;
+        }
+        let addresses: Vec<Option<IpAddr>> = header_values
+            .into_iter()
+            .flat_map(|value| self.header.addresses(value))
+            .collect();
+        for address in addresses.into_iter().rev() {
+            match address {
+                Some(ip) => {
+                    client = Some(ip);
+                    if !self.is_trusted(&ip) {
+                        break;
+                    }
+                }
+                // The client hid its address behind this proxy
+                None => return None,
+            }
+        }
+        client
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(ip: &str) -> IpAddr {
+        IpAddr::from_str(ip).unwrap()
+    }
+
+    #[test]
+    fn parses_networks_and_addresses() {
+        let network = IpNetwork::from_str("203.0.113.7/24").unwrap();
+        assert_eq!(network.to_string(), "203.0.113.0/24");
+        assert!(network.contains(&ip("203.0.113.200")));
+        assert!(!network.contains(&ip("203.0.114.1")));
+
+        let address = IpNetwork::from_str("2001:db8::1").unwrap();
+        assert_eq!(address.to_string(), "2001:db8::1/128");
+        assert!(address.contains(&ip("2001:db8::1")));
+        assert!(!address.contains(&ip("2001:db8::2")));
+
+        assert!(IpNetwork::from_str("203.0.113.0/33").is_err());
+        assert!(IpNetwork::from_str("example.com").is_err());
+    }
+
+    #[test]
+    fn matches_ipv4_mapped_addresses() {
+        let network = IpNetwork::from_str("127.0.0.0/8").unwrap();
+        assert!(network.contains(&ip("::ffff:127.0.0.1")));
+        assert!(!network.contains(&ip("::1")));
+    }
+
+    #[test]
+    fn serializes_as_strings() {
+        let networks: Vec<IpNetwork> =
+            serde_json::from_str(r#"["10.0.0.0/8","192.168.1.1"]"#).unwrap();
+        assert_eq!(
+            serde_json::to_string(&networks).unwrap(),
+            r#"["10.0.0.0/8","192.168.1.1/32"]"#
+        );
+        assert!(serde_json::from_str::<Vec<IpNetwork>>(r#"["10.0.0.0/"]"#).is_err());
+    }
+
+    #[test]
+    fn reads_the_client_address_from_trusted_proxies() {
+        let proxies = TrustedProxies {
+            networks: vec![IpNetwork::from_str("10.0.0.0/8").unwrap()],
+            header: ForwardedHeader::XForwardedFor,
+        };
+        let proxy = Some(ip("10.0.0.1"));
+        assert_eq!(
+            proxies.client_ip(proxy, vec!["203.0.113.7"]),
+            Some(ip("203.0.113.7"))
+        );
+        // The addresses prepended by the client are skipped
+        assert_eq!(
+            proxies.client_ip(proxy, vec!["192.0.2.1, 203.0.113.7, 10.0.0.2"]),
+            Some(ip("203.0.113.7"))
+        );
+        assert_eq!(
+            proxies.client_ip(proxy, vec!["192.0.2.1", "203.0.113.7"]),
+            Some(ip("203.0.113.7"))
+        );
+        // The header of the other peers is ignored
+        assert_eq!(
+            proxies.client_ip(Some(ip("203.0.113.7")), vec!["10.0.0.3"]),
+            Some(ip("203.0.113.7"))
+        );
+        assert_eq!(proxies.client_ip(proxy, Vec::new()), proxy);
+        assert_eq!(proxies.client_ip(proxy, vec!["unknown"]), None);
+        assert_eq!(
+            TrustedProxies::default().client_ip(proxy, vec!["203.0.113.7"]),
+            proxy
+        );
+    }
+
+    #[test]
+    fn parses_the_forwarded_header() {
+        let proxies = TrustedProxies {
+            networks: vec![IpNetwork::from_str("10.0.0.0/8").unwrap()],
+            header: ForwardedHeader::Forwarded,
+        };
+        let proxy = Some(ip("10.0.0.1"));
+        assert_eq!(
+            proxies.client_ip(proxy, vec!["for=192.0.2.60;proto=http;by=10.0.0.1"]),
+            Some(ip("192.0.2.60"))
+        );
+        assert_eq!(
+            proxies.client_ip(proxy, vec![r#"For="[2001:db8:cafe::17]:4711""#]),
+            Some(ip("2001:db8:cafe::17"))
+        );
+        assert_eq!(
+            proxies.client_ip(proxy, vec!["for=192.0.2.43:8080, for=10.0.0.2"]),
+            Some(ip("192.0.2.43"))
+        );
+        assert_eq!(proxies.client_ip(proxy, vec!["for=_hidden"]), None);
+    }
+}
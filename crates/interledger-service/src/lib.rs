@@ -27,7 +27,7 @@
 //! HttpServerService --> ValidatorService --> StreamReceiverService
 
 use async_trait::async_trait;
-use interledger_errors::{AccountStoreError, AddressStoreError};
+use interledger_errors::{AccountStoreError, AddressStoreError, LeaseStoreError};
 use interledger_packet::{Address, Fulfill, Prepare, Reject};
 use std::{
     fmt::{self, Debug},
@@ -37,6 +37,20 @@ use std::{
 };
 use uuid::Uuid;
 
+mod blocklist;
+pub mod clock;
+#[cfg(feature = "simulation")]
+pub mod simulation;
+pub use blocklist::{
+    BlocklistConfig, BlocklistEntry, Misbehavior, PeerBlocklist, PeerBlocklistService,
+    DEFAULT_MISBEHAVIOR_WINDOW, DEFAULT_QUARANTINE_DURATION,
+};
+mod http_dialect;
+pub use http_dialect::HttpDialect;
+mod ip_allowlist;
+pub use ip_allowlist::{ForwardedHeader, IpAllowlistAccount, IpNetwork, TrustedProxies};
+mod packet_filter;
+pub use packet_filter::{PacketFilter, PacketFilterAccount, PacketFilterService, TimeOfDay};
 mod username;
 pub use username::Username;
 #[cfg(feature = "trace")]
@@ -347,6 +361,27 @@ pub trait AddressStore {
     fn get_ilp_address(&self) -> Address;
 }
 
+/// A store which hands out time-limited, exclusive leases. When multiple nodes share
+/// the same store, leases are used to elect the node which runs a singleton task
+/// (such as broadcasting routes) or which owns a resource (such as a BTP connection).
+#[async_trait]
+pub trait LeaseStore {
+    /// Acquires the named lease for the holder, or extends it if the holder already
+    /// owns it. Returns whether the holder owns the lease for the next `ttl` milliseconds.
+    async fn acquire_lease(
+        &self,
+        // The name of the task or resource the lease is for
+        name: &str,
+        // A unique identifier of the node requesting the lease
+        holder: &str,
+        ttl: u64,
+    ) -> Result<bool, LeaseStoreError>;
+
+    /// Releases the named lease if it is owned by the holder, so that
+    /// another node can acquire it without waiting for it to expire
+    async fn release_lease(&self, name: &str, holder: &str) -> Result<(), LeaseStoreError>;
+}
+
 // Even though we wrap the types _a lot_ of times in multiple configurations
 // the tests still build nearly instantly. The trick is to make the wrapping function
 // take a trait object instead of the concrete type
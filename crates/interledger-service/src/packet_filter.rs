@@ -0,0 +1,384 @@
+use super::{Account, AddressStore, IlpResult, IncomingRequest, IncomingService};
+use async_trait::async_trait;
+use interledger_packet::{
+    Address, ErrorCode, MaxPacketAmountDetails, Prepare, Reject, RejectBuilder,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    convert::TryFrom,
+    fmt,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const MINUTES_PER_DAY: u16 = 24 * 60;
+
+/// A time of the day in UTC, with a precision of minutes. It is (de)serialized as `HH:MM`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct TimeOfDay(u16);
+
+impl TimeOfDay {
+    /// Returns None if the hours or minutes are out of range
+    pub fn new(hours: u16, minutes: u16) -> Option<Self> {
+        if hours < 24 && minutes < 60 {
+            Some(TimeOfDay(hours * 60 + minutes))
+        } else {
+            None
+        }
+    }
+
+    /// The time of the day (in UTC) of the provided instant
+    pub fn at(time: SystemTime) -> Self {
+        let seconds = time
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default();
+        TimeOfDay(((seconds / 60) % u64::from(MINUTES_PER_DAY)) as u16)
+    }
+}
+
+impl TryFrom<String> for TimeOfDay {
+    type Error = String;
+
+    fn try_from(time: String) -> Result<Self, Self::Error> {
+        let mut parts = time.splitn(2, ':');
+        let hours = parts.next().and_then(|hours| hours.parse().ok());
+        let minutes = parts.next().and_then(|minutes| minutes.parse().ok());
+        match (hours, minutes) {
+            (Some(hours), Some(minutes)) => TimeOfDay::new(hours, minutes),
+            _ => None,
+        }
+        .ok_or_else(|| format!("Invalid time of the day (expected HH:MM): {}", time))
+    }
+}
+
+impl From<TimeOfDay> for String {
+    fn from(time: TimeOfDay) -> Self {
+        time.to_string()
+    }
+}
+
+impl fmt::Display for TimeOfDay {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:02}:{:02}", self.0 / 60, self.0 % 60)
+    }
+}
+
+/// A restriction on the packets an account may send through the node.
+/// The filters of an account are applied in order, and the first one which
+/// does not allow a packet rejects it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PacketFilter {
+    /// Only allows packets addressed to one of the listed addresses
+    AllowAddresses { addresses: Vec<String> },
+    /// Rejects packets addressed to any of the listed addresses
+    DenyAddresses { addresses: Vec<String> },
+    /// Only allows packets addressed to destinations under one of the listed prefixes
+    AllowPrefixes { prefixes: Vec<String> },
+    /// Rejects packets addressed to destinations under any of the listed prefixes
+    DenyPrefixes { prefixes: Vec<String> },
+    /// Rejects packets whose amount exceeds the provided one
+    MaxAmount { amount: u64 },
+    /// Only allows packets during a daily window (in UTC). The window
+    /// spans midnight if it ends before it starts
+    TimeWindow { start: TimeOfDay, end: TimeOfDay },
+}
+
+/// Whether the destination is the prefix itself or one of the addresses under it
+fn has_prefix(destination: &str, prefix: &str) -> bool {
+    destination == prefix
+        || (destination.starts_with(prefix)
+            && destination.as_bytes().get(prefix.len()) == Some(&b'.'))
+}
+
+impl PacketFilter {
+    /// Returns the Reject for the prepare if this filter does not allow it at the provided time
+    pub fn apply(
+        &self,
+        prepare: &Prepare,
+        now: SystemTime,
+        ilp_address: &Address,
+    ) -> Result<(), Reject> {
+        let destination = prepare.destination();
+        let reject = |code: ErrorCode, message: String, data: &[u8]| {
+            Err(RejectBuilder {
+                code,
+                message: message.as_bytes(),
+                triggered_by: Some(ilp_address),
+                data,
+            }
+            .build())
+        };
+        match self {
+            PacketFilter::AllowAddresses { addresses }
+                if !addresses.iter().any(|address| **address == *destination) =>
+            {
+                reject(
+                    ErrorCode::F02_UNREACHABLE,
+                    format!(
+                        "Destination {} is not in the account's allow list",
+                        destination
+                    ),
+                    &[],
+                )
+            }
+            PacketFilter::DenyAddresses { addresses }
+                if addresses.iter().any(|address| **address == *destination) =>
+            {
+                reject(
+                    ErrorCode::F02_UNREACHABLE,
+                    format!("Destination {} is in the account's deny list", destination),
+                    &[],
+                )
+            }
+            PacketFilter::AllowPrefixes { prefixes }
+                if !prefixes
+                    .iter()
+                    .any(|prefix| has_prefix(&destination, prefix)) =>
+            {
+                reject(
+                    ErrorCode::F02_UNREACHABLE,
+                    format!(
+                        "Destination {} is not under any of the account's allowed prefixes",
+                        destination
+                    ),
+                    &[],
+                )
+            }
+            PacketFilter::DenyPrefixes { prefixes }
+                if prefixes
+                    .iter()
+                    .any(|prefix| has_prefix(&destination, prefix)) =>
+            {
+                reject(
+                    ErrorCode::F02_UNREACHABLE,
+                    format!(
+                        "Destination {} is under one of the account's denied prefixes",
+                        destination
+                    ),
+                    &[],
+                )
+            }
+            PacketFilter::MaxAmount { amount } if prepare.amount() > *amount => reject(
+                ErrorCode::F08_AMOUNT_TOO_LARGE,
+                String::new(),
+                &MaxPacketAmountDetails::new(prepare.amount(), *amount).to_bytes(),
+            ),
+            PacketFilter::TimeWindow { start, end } => {
+                let time = TimeOfDay::at(now);
+                let within = if start <= end {
+                    *start <= time && time < *end
+                } else {
+                    *start <= time || time < *end
+                };
+                if within {
+                    Ok(())
+                } else {
+                    reject(
+                        ErrorCode::F00_BAD_REQUEST,
+                        format!(
+                            "The account may only send packets between {} and {} UTC",
+                            start, end
+                        ),
+                        &[],
+                    )
+                }
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Extension trait for [`Account`](./trait.Account.html) with the filters
+/// applied to the packets sent by the account
+pub trait PacketFilterAccount: Account {
+    fn packet_filters(&self) -> &[PacketFilter] {
+        &[]
+    }
+}
+
+/// # Packet Filter Service
+///
+/// Incoming service which applies the packet filters of the sending account
+/// to each Prepare packet before it is forwarded, and rejects the packets
+/// which any of the filters does not allow.
+///
+/// Requires a `PacketFilterAccount` and an `AddressStore`.
+#[derive(Clone)]
+pub struct PacketFilterService<S, I> {
+    store: S,
+    next: I,
+}
+
+impl<S, I> PacketFilterService<S, I> {
+    pub fn new(store: S, next: I) -> Self {
+        PacketFilterService { store, next }
+    }
+}
+
+#[async_trait]
+impl<S, I, A> IncomingService<A> for PacketFilterService<S, I>
+where
+    S: AddressStore + Send + Sync + 'static,
+    I: IncomingService<A> + Send + Sync + 'static,
+    A: PacketFilterAccount + Sync + 'static,
+{
+    async fn handle_request(&mut self, request: IncomingRequest<A>) -> IlpResult {
+        let filters = request.from.packet_filters();
+        if !filters.is_empty() {
+            let now = SystemTime::now();
+            let ilp_address = self.store.get_ilp_address();
+            for filter in filters {
+                filter.apply(&request.prepare, now, &ilp_address)?;
+            }
+        }
+        self.next.handle_request(request).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use interledger_packet::PrepareBuilder;
+    use once_cell::sync::Lazy;
+    use std::str::FromStr;
+    use std::time::Duration;
+
+    static NODE_ADDRESS: Lazy<Address> =
+        Lazy::new(|| Address::from_str("example.connector").unwrap());
+
+    fn prepare(destination: &str, amount: u64) -> Prepare {
+        PrepareBuilder {
+            destination: Address::from_str(destination).unwrap(),
+            amount,
+            expires_at: SystemTime::now() + Duration::from_secs(30),
+            execution_condition: &[0; 32],
+            data: &[],
+        }
+        .build()
+    }
+
+    fn time(hours: u64, minutes: u64) -> SystemTime {
+        // An arbitrary day, at the provided time of the day (UTC)
+        UNIX_EPOCH + Duration::from_secs(86400 * 18000 + hours * 3600 + minutes * 60)
+    }
+
+    fn rejection(filter: &PacketFilter, prepare: &Prepare, now: SystemTime) -> Option<ErrorCode> {
+        filter
+            .apply(prepare, now, &NODE_ADDRESS)
+            .err()
+            .map(|reject| reject.code())
+    }
+
+    #[test]
+    fn applies_address_lists() {
+        let allow = PacketFilter::AllowAddresses {
+            addresses: vec!["example.bob".to_string()],
+        };
+        let deny = PacketFilter::DenyAddresses {
+            addresses: vec!["example.bob".to_string()],
+        };
+        let now = SystemTime::now();
+        assert_eq!(rejection(&allow, &prepare("example.bob", 1), now), None);
+        assert_eq!(
+            rejection(&allow, &prepare("example.bob.x", 1), now),
+            Some(ErrorCode::F02_UNREACHABLE)
+        );
+        assert_eq!(
+            rejection(&deny, &prepare("example.bob", 1), now),
+            Some(ErrorCode::F02_UNREACHABLE)
+        );
+        assert_eq!(rejection(&deny, &prepare("example.bob.x", 1), now), None);
+    }
+
+    #[test]
+    fn applies_prefixes_on_segment_boundaries() {
+        let allow = PacketFilter::AllowPrefixes {
+            prefixes: vec!["example.bob".to_string()],
+        };
+        let deny = PacketFilter::DenyPrefixes {
+            prefixes: vec!["example.bob".to_string()],
+        };
+        let now = SystemTime::now();
+        assert_eq!(rejection(&allow, &prepare("example.bob", 1), now), None);
+        assert_eq!(rejection(&allow, &prepare("example.bob.x", 1), now), None);
+        assert_eq!(
+            rejection(&allow, &prepare("example.bobby", 1), now),
+            Some(ErrorCode::F02_UNREACHABLE)
+        );
+        assert_eq!(
+            rejection(&deny, &prepare("example.bob.x", 1), now),
+            Some(ErrorCode::F02_UNREACHABLE)
+        );
+        assert_eq!(rejection(&deny, &prepare("example.bobby", 1), now), None);
+    }
+
+    #[test]
+    fn caps_amounts() {
+        let filter = PacketFilter::MaxAmount { amount: 100 };
+        let now = SystemTime::now();
+        assert_eq!(rejection(&filter, &prepare("example.bob", 100), now), None);
+        let reject = filter
+            .apply(&prepare("example.bob", 101), now, &NODE_ADDRESS)
+            .unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::F08_AMOUNT_TOO_LARGE);
+        let details = MaxPacketAmountDetails::from_bytes(reject.data()).unwrap();
+        assert_eq!(details.max_amount(), 100);
+    }
+
+    #[test]
+    fn applies_time_windows() {
+        let office_hours = PacketFilter::TimeWindow {
+            start: TimeOfDay::new(9, 0).unwrap(),
+            end: TimeOfDay::new(17, 30).unwrap(),
+        };
+        let nights = PacketFilter::TimeWindow {
+            start: TimeOfDay::new(22, 0).unwrap(),
+            end: TimeOfDay::new(6, 0).unwrap(),
+        };
+        let packet = prepare("example.bob", 1);
+        assert_eq!(rejection(&office_hours, &packet, time(9, 0)), None);
+        assert_eq!(rejection(&office_hours, &packet, time(17, 29)), None);
+        assert_eq!(
+            rejection(&office_hours, &packet, time(17, 30)),
+            Some(ErrorCode::F00_BAD_REQUEST)
+        );
+        assert_eq!(rejection(&nights, &packet, time(23, 15)), None);
+        assert_eq!(rejection(&nights, &packet, time(5, 59)), None);
+        assert_eq!(
+            rejection(&nights, &packet, time(12, 0)),
+            Some(ErrorCode::F00_BAD_REQUEST)
+        );
+    }
+
+    #[test]
+    fn deserializes_filters() {
+        let filters: Vec<PacketFilter> = serde_json::from_str(
+            r#"[
+                {"type": "deny_prefixes", "prefixes": ["example.bob"]},
+                {"type": "max_amount", "amount": 1000},
+                {"type": "time_window", "start": "09:00", "end": "17:30"}
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            filters,
+            vec![
+                PacketFilter::DenyPrefixes {
+                    prefixes: vec!["example.bob".to_string()]
+                },
+                PacketFilter::MaxAmount { amount: 1000 },
+                PacketFilter::TimeWindow {
+                    start: TimeOfDay::new(9, 0).unwrap(),
+                    end: TimeOfDay::new(17, 30).unwrap(),
+                },
+            ]
+        );
+        assert_eq!(
+            serde_json::to_value(&filters[2]).unwrap(),
+            serde_json::json!({"type": "time_window", "start": "09:00", "end": "17:30"})
+        );
+        assert!(serde_json::from_str::<TimeOfDay>(r#""24:00""#).is_err());
+    }
+}
@@ -0,0 +1,131 @@
+//! Deterministic simulation of services on a virtual clock (with the `simulation` feature).
+//!
+//! [`run`](./fn.run.html) runs a future, and the tasks it spawns, on a single-threaded
+//! executor whose clock is paused. Time only passes when all the tasks are waiting: the
+//! clock then jumps to the next timer, so that packet expiries, scheduled tasks and route
+//! broadcasts which would take seconds or minutes complete instantly, and always in the same
+//! order. [`clock::now`](../clock/fn.now.html) returns the virtual time, starting at
+//! [`EPOCH_SECS`](./constant.EPOCH_SECS.html), and the services which read it behave as if
+//! that time had passed.
+//!
+//! ```
+//! use interledger_service::{clock, simulation};
+//! use std::time::Duration;
+//!
+//! simulation::run(async {
+//!     let start = clock::now();
+//!     tokio::time::delay_for(Duration::from_secs(3600)).await;
+//!     assert_eq!(clock::now(), start + Duration::from_secs(3600));
+//! });
+//! ```
+
+use std::{
+    cell::Cell,
+    future::Future,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::{runtime::Builder, time::Instant};
+
+/// The time, in seconds since the Unix epoch, at which the virtual clock of the simulations
+/// starts (2020-09-13T12:26:40Z)
+pub const EPOCH_SECS: u64 = 1_600_000_000;
+
+thread_local! {
+    /// The instant at which the simulation running on this thread started
+    static STARTED_AT: Cell<Option<Instant>> = const { Cell::new(None) };
+}
+
+/// Clears the start of the simulation when it ends, even if it panics
+struct Started;
+
+impl Drop for Started {
+    fn drop(&mut self) {
+        STARTED_AT.with(|started_at| started_at.set(None));
+    }
+}
+
+/// Returns the time at which the virtual clock of the simulations starts
+pub fn epoch() -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(EPOCH_SECS)
+}
+
+/// Runs the future to completion on a single-threaded executor with a paused clock, which
+/// advances to the next timer whenever all the tasks are idle, and returns its output.
+///
+/// # Panics
+///
+/// Panics if it is called from a Tokio runtime.
+pub fn run<F: Future>(future: F) -> F::Output {
+    let mut runtime = Builder::new()
+        .basic_scheduler()
+        .enable_time()
+        .build()
+        .expect("Could not build the simulation runtime");
+    runtime.block_on(async {
+        tokio::time::pause();
+        STARTED_AT.with(|started_at| started_at.set(Some(Instant::now())));
+        let _started = Started;
+        future.await
+    })
+}
+
+/// Advances the virtual clock by the duration, firing the timers which expire in the
+/// meantime, without waiting for the tasks to be idle
+///
+/// # Panics
+///
+/// Panics if it is not called from a simulation.
+pub async fn advance(duration: Duration) {
+    tokio::time::advance(duration).await
+}
+
+/// Returns the virtual time if it is called from a simulation. It is truncated to the
+/// millisecond, which is the precision of both the timers and the packet expiries, since
+/// the timers fire on the milliseconds counted from the creation of the runtime rather than
+/// from the start of the simulation.
+pub(crate) fn now() -> Option<SystemTime> {
+    STARTED_AT
+        .with(Cell::get)
+        .map(|started_at| epoch() + Duration::from_millis(started_at.elapsed().as_millis() as u64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock;
+    use tokio::time::delay_for;
+
+    #[test]
+    fn time_passes_when_the_tasks_are_idle() {
+        let started = std::time::Instant::now();
+        let times = run(async {
+            let first = clock::now();
+            let task = tokio::spawn(async {
+                delay_for(Duration::from_secs(7200)).await;
+                clock::now()
+            });
+            delay_for(Duration::from_secs(3600)).await;
+            (first, clock::now(), task.await.unwrap())
+        });
+        assert_eq!(times.0, epoch());
+        assert_eq!(times.1, epoch() + Duration::from_secs(3600));
+        assert_eq!(times.2, epoch() + Duration::from_secs(7200));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn advances_the_clock() {
+        run(async {
+            advance(Duration::from_millis(1500)).await;
+            assert_eq!(clock::now(), epoch() + Duration::from_millis(1500));
+        });
+    }
+
+    #[test]
+    fn uses_the_system_time_outside_of_simulations() {
+        run(async {});
+        let now = clock::now();
+        assert!(now > epoch() + Duration::from_secs(3600));
+        assert!(SystemTime::now().duration_since(now).unwrap() < Duration::from_secs(5));
+    }
+}
@@ -1,6 +1,7 @@
 use crate::core::{
     get_hash_of,
     idempotency::*,
+    replay_protection::{replay_protection, NonceStore, ReplayProtection},
     scale_with_precision_loss,
     types::{
         ApiResponse, ApiResult, LeftoversStore, Quantity, SettlementAccount, SettlementStore,
@@ -14,6 +15,7 @@ use hyper::{Response, StatusCode};
 use interledger_errors::*;
 use interledger_packet::PrepareBuilder;
 use interledger_service::{Account, AccountStore, OutgoingRequest, OutgoingService};
+use metrics::{labels, recorder, Key};
 use num_bigint::BigUint;
 use num_traits::cast::ToPrimitive;
 use std::{
@@ -114,14 +116,20 @@ where
 /// 1. receives messages about incoming settlements from the engine
 /// 1. sends messages from the connector's engine to the peer's
 ///    message service which are sent to the peer's engine
+///
+/// If replay protection is provided, calls which are not signed by the engine of their
+/// account, are stale or reuse a nonce are rejected
+/// (see [`replay_protection`](../core/replay_protection/fn.replay_protection.html)).
 pub fn create_settlements_filter<S, O, A>(
     store: S,
     outgoing_handler: O,
+    protection: Option<ReplayProtection>,
 ) -> warp::filters::BoxedFilter<(impl warp::Reply,)>
 where
     S: LeftoversStore<AccountId = Uuid, AssetType = BigUint>
         + SettlementStore<Account = A>
         + IdempotentStore
+        + NonceStore
         + AccountStore<Account = A>
         + Clone
         + Send
@@ -130,6 +138,8 @@ where
     O: OutgoingService<A> + Clone + Send + Sync + 'static,
     A: SettlementAccount + Account + Send + Sync + 'static,
 {
+    // The body of the calls, once the replay protection accepted them
+    let body = replay_protection(store.clone(), protection);
     let with_store = warp::any().map(move || store.clone());
     let idempotency = warp::header::optional::<String>("idempotency-key");
     let account_id_filter = warp::path("accounts").and(warp::path::param::<String>()); // account_id
@@ -141,7 +151,13 @@ where
         .and(settlement_endpoint)
         .and(warp::path::end())
         .and(idempotency)
-        .and(warp::body::json())
+        .and(body.clone().and_then(|body: Bytes| async move {
+            serde_json::from_slice::<Quantity>(&body).map_err(|err| {
+                Rejection::from(
+                    ApiError::bad_request().detail(format!("Invalid settlement: {}", err)),
+                )
+            })
+        }))
         .and(with_store.clone())
         .and_then(receive_settlement);
 
@@ -153,7 +169,7 @@ where
         .and(messages_endpoint)
         .and(warp::path::end())
         .and(idempotency)
-        .and(warp::body::bytes())
+        .and(body)
         .and(with_store)
         .and(with_outgoing_handler)
         .and_then(send_message);
@@ -286,6 +302,16 @@ where
         return Err(ApiError::from_api_error_type(&error_type).detail(error_msg));
     }
 
+    recorder().increment_counter(
+        Key::from_name_and_labels(
+            "settlements.incoming",
+            labels!(
+                "account" => account.username().to_string(),
+                "asset_code" => account.asset_code().to_string(),
+            ),
+        ),
+        1,
+    );
     Ok(ApiResponse::Default)
 }
 
@@ -496,6 +522,50 @@ mod tests {
             assert_eq!(cached_data.body, &bytes::Bytes::from("RECEIVED"));
         }
 
+        #[tokio::test]
+        async fn rejects_replayed_and_forged_settlements() {
+            use crate::core::replay_protection::{
+                sign_call, ReplayProtection, NONCE_HEADER, SIGNATURE_HEADER, TIMESTAMP_HEADER,
+            };
+
+            let id = TEST_ACCOUNT_0.clone().id.to_string();
+            let store = test_store(false, true);
+            let protection = ReplayProtection::new(
+                Duration::from_secs(60),
+                vec![(TEST_ACCOUNT_0.url.clone(), "secret")],
+            );
+            let api = test_api_with_replay_protection(store.clone(), false, Some(protection));
+            let path = format!("/accounts/{}/settlements", id);
+            let settlement = |secret: &[u8], nonce: &str| {
+                let timestamp = SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap()
+                    .as_millis() as u64;
+                let body = json!(Quantity::new("100", OUR_SCALE)).to_string();
+                warp::test::request()
+                    .method("POST")
+                    .path(&path)
+                    .header(TIMESTAMP_HEADER, timestamp.to_string())
+                    .header(NONCE_HEADER, nonce)
+                    .header(
+                        SIGNATURE_HEADER,
+                        sign_call(secret, timestamp, nonce, "POST", &path, body.as_bytes()),
+                    )
+                    .body(body)
+            };
+
+            let response = settlement_call(&api, &id, 100, OUR_SCALE, None).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+            let response = settlement(b"forged", "nonce").reply(&api).await;
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+            let response = settlement(b"secret", "nonce").reply(&api).await;
+            assert_eq!(response.status(), StatusCode::CREATED);
+            let response = settlement(b"secret", "nonce").reply(&api).await;
+            assert_eq!(response.status(), StatusCode::CONFLICT);
+            assert_eq!(store.get_balance(TEST_ACCOUNT_0.id), 1);
+        }
+
         #[tokio::test]
         // The connector must save the difference each time there's precision
         // loss and try to add it the amount it's being notified to settle for the next time.
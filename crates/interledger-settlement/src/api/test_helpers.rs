@@ -1,6 +1,7 @@
 use super::*;
 use crate::core::{
     idempotency::*,
+    replay_protection::{NonceStore, ReplayProtection},
     scale_with_precision_loss,
     types::{
         Convert, ConvertDetails, LeftoversStore, SettlementAccount, SettlementEngineDetails,
@@ -23,9 +24,10 @@ use interledger_errors::*;
 use once_cell::sync::Lazy;
 use parking_lot::RwLock;
 use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
 use url::Url;
 
 #[derive(Debug, Clone)]
@@ -79,6 +81,7 @@ pub struct TestStore {
     pub should_fail: bool,
     pub cache: Arc<RwLock<HashMap<String, IdempotentData>>>,
     pub cache_hits: Arc<RwLock<u64>>,
+    pub nonces: Arc<RwLock<HashSet<String>>>,
     pub uncredited_settlement_amount: Arc<RwLock<HashMap<Uuid, (BigUint, u8)>>>,
 }
 
@@ -150,6 +153,17 @@ impl IdempotentStore for TestStore {
     }
 }
 
+#[async_trait]
+impl NonceStore for TestStore {
+    async fn save_nonce(
+        &self,
+        nonce: String,
+        _ttl: Duration,
+    ) -> Result<bool, IdempotentStoreError> {
+        Ok(self.nonces.write().insert(nonce))
+    }
+}
+
 #[async_trait]
 impl AccountStore for TestStore {
     type Account = TestAccount;
@@ -288,6 +302,7 @@ impl TestStore {
             should_fail,
             cache: Arc::new(RwLock::new(HashMap::new())),
             cache_hits: Arc::new(RwLock::new(0)),
+            nonces: Arc::new(RwLock::new(HashSet::new())),
             uncredited_settlement_amount: Arc::new(RwLock::new(HashMap::new())),
         }
     }
@@ -348,6 +363,14 @@ pub fn test_store(store_fails: bool, account_has_engine: bool) -> TestStore {
 pub fn test_api(
     test_store: TestStore,
     should_fulfill: bool,
+) -> warp::filters::BoxedFilter<(impl warp::Reply,)> {
+    test_api_with_replay_protection(test_store, should_fulfill, None)
+}
+
+pub fn test_api_with_replay_protection(
+    test_store: TestStore,
+    should_fulfill: bool,
+    replay_protection: Option<ReplayProtection>,
 ) -> warp::filters::BoxedFilter<(impl warp::Reply,)> {
     let outgoing = outgoing_service_fn(move |_| {
         if should_fulfill {
@@ -366,5 +389,5 @@ pub fn test_api(
             .build())
         }
     });
-    create_settlements_filter(test_store, outgoing)
+    create_settlements_filter(test_store, outgoing, replay_protection)
 }
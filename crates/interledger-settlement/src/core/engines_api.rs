@@ -143,6 +143,19 @@ where
         .unwrap())
 }
 
+/// Calls [`engine.get_settlement_totals`](../types/trait.SettlementEngine.html#method.get_settlement_totals)
+/// Returns Status Code `200` with the totals
+async fn engine_get_settlement_totals<E>(
+    id: String,
+    engine: E,
+) -> Result<impl warp::Reply, warp::Rejection>
+where
+    E: SettlementEngine + Clone + Send + Sync,
+{
+    let totals = engine.get_settlement_totals(id).await?;
+    Ok(warp::reply::json(&totals))
+}
+
 /// Returns a Settlement Engine filter which exposes a Warp-compatible
 /// idempotent API which forwards calls to the provided settlement engine which
 /// uses the underlying store for idempotency.
@@ -191,6 +204,14 @@ where
         .and(with_store.clone())
         .and_then(engine_send_money);
 
+    // GET /accounts/:account_id/settlements
+    // Responds with the totals sent and received, as Quantity objects
+    let settlement_totals = warp::get()
+        .and(settlement_endpoint)
+        .and(warp::path::end())
+        .and(with_engine.clone())
+        .and_then(engine_get_settlement_totals);
+
     // POST /accounts/:account_id/messages (optional idempotency-key header)
     // Body is a Vec<u8> object
     let messages_endpoint = account_id.and(warp::path("messages"));
@@ -206,6 +227,7 @@ where
     accounts
         .or(del_account)
         .or(settlements)
+        .or(settlement_totals)
         .or(messages)
         .recover(default_rejection_handler)
 }
@@ -214,7 +236,7 @@ where
 mod tests {
     use super::*;
     use crate::core::idempotency::IdempotentData;
-    use crate::core::types::{ApiResponse, ApiResult};
+    use crate::core::types::{ApiResponse, ApiResult, SettlementTotals};
     use async_trait::async_trait;
     use bytes::Bytes;
     use http::StatusCode;
@@ -302,6 +324,20 @@ mod tests {
         async fn delete_account(&self, _account_id: String) -> ApiResult {
             Ok(ApiResponse::Default)
         }
+
+        async fn get_settlement_totals(
+            &self,
+            account_id: String,
+        ) -> Result<SettlementTotals, ApiError> {
+            if account_id == "1" {
+                Ok(SettlementTotals {
+                    sent: Quantity::new(100, 6),
+                    received: Quantity::new(42, 6),
+                })
+            } else {
+                Err(ApiError::account_not_found())
+            }
+        }
     }
 
     #[tokio::test]
@@ -466,4 +502,26 @@ mod tests {
         assert_eq!(cached_data.status, 204);
         assert_eq!(cached_data.body, "DELETED".to_string());
     }
+
+    #[tokio::test]
+    async fn gets_settlement_totals() {
+        let api = create_settlement_engine_filter(TestEngine, test_store());
+
+        let ret = warp::test::request()
+            .method("GET")
+            .path("/accounts/1/settlements")
+            .reply(&api)
+            .await;
+        assert_eq!(ret.status(), StatusCode::OK);
+        let totals: SettlementTotals = serde_json::from_slice(ret.body()).unwrap();
+        assert_eq!(totals.sent, Quantity::new(100, 6));
+        assert_eq!(totals.received, Quantity::new(42, 6));
+
+        let ret = warp::test::request()
+            .method("GET")
+            .path("/accounts/42/settlements")
+            .reply(&api)
+            .await;
+        assert_eq!(ret.status(), StatusCode::NOT_FOUND);
+    }
 }
@@ -10,9 +10,16 @@ pub mod engines_api;
 mod settlement_client;
 pub use settlement_client::SettlementClient;
 
+/// Mutual TLS between the node and its settlement engines
+mod tls;
+pub use tls::SettlementTlsConfig;
+
 /// Expose useful utilities for implementing idempotent functionalities
 pub mod idempotency;
 
+/// Rejection of the stale and replayed calls of the settlement engines
+pub mod replay_protection;
+
 /// Expose useful traits
 pub mod types;
 
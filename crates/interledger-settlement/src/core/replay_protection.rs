@@ -0,0 +1,545 @@
+use crate::core::types::SettlementAccount;
+use async_trait::async_trait;
+use bytes::Bytes;
+use http::Method;
+use interledger_errors::{ApiError, IdempotentStoreError};
+use interledger_service::AccountStore;
+use metrics::{labels, recorder, Key};
+use ring::{constant_time, digest, hmac};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{error, warn};
+use url::Url;
+use uuid::Uuid;
+use warp::{filters::path::FullPath, Filter, Rejection};
+
+/// Header with the time at which the engine made the call, in milliseconds since the UNIX epoch
+pub const TIMESTAMP_HEADER: &str = "request-timestamp";
+/// Header with a value which is unique to each call of the engine (such as a UUID)
+pub const NONCE_HEADER: &str = "request-nonce";
+/// Header with the signature of the call by the engine (see [`sign_call`](./fn.sign_call.html))
+pub const SIGNATURE_HEADER: &str = "request-signature";
+
+/// Store trait which should be implemented for the replay protection of the settlement API
+#[async_trait]
+pub trait NonceStore {
+    /// Saves the nonce for the provided time, unless it was saved already.
+    /// Returns true if the nonce was saved and false if it had been used before.
+    async fn save_nonce(&self, nonce: String, ttl: Duration) -> Result<bool, IdempotentStoreError>;
+}
+
+/// Settings of the replay protection of the settlement API
+#[derive(Clone, Debug)]
+pub struct ReplayProtection {
+    window: Duration,
+    /// Keys of the signatures of the engines, by the URL of the engine
+    keys: Arc<HashMap<Url, hmac::Key>>,
+}
+
+impl ReplayProtection {
+    /// Rejects the calls whose timestamp is more than `window` away from the node's clock,
+    /// and the calls which are not signed with the secret of the engine of their account
+    pub fn new<I, T>(window: Duration, secrets: I) -> Self
+    where
+        I: IntoIterator<Item = (Url, T)>,
+        T: AsRef<[u8]>,
+    {
+        let keys = secrets
+            .into_iter()
+            .map(|(url, secret)| (url, hmac::Key::new(hmac::HMAC_SHA256, secret.as_ref())))
+            .collect();
+        ReplayProtection {
+            window,
+            keys: Arc::new(keys),
+        }
+    }
+}
+
+/// Returns the signature of a call of an engine, which is sent in the `Request-Signature`
+/// header: the lowercase hex of the HMAC-SHA256, under the secret of the engine, of the
+/// timestamp, the nonce, the method, the path and the hex of the SHA-256 digest of the
+/// body, each followed by a newline.
+pub fn sign_call(
+    secret: &[u8],
+    timestamp: u64,
+    nonce: &str,
+    method: &str,
+    path: &str,
+    body: &[u8],
+) -> String {
+    sign(
+        &hmac::Key::new(hmac::HMAC_SHA256, secret),
+        timestamp,
+        nonce,
+        method,
+        path,
+        body,
+    )
+}
+
+fn sign(
+    key: &hmac::Key,
+    timestamp: u64,
+    nonce: &str,
+    method: &str,
+    path: &str,
+    body: &[u8],
+) -> String {
+    let message = format!(
+        "{}\n{}\n{}\n{}\n{}\n",
+        timestamp,
+        nonce,
+        method,
+        path,
+        to_hex(digest::digest(&digest::SHA256, body).as_ref())
+    );
+    to_hex(hmac::sign(key, message.as_bytes()).as_ref())
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// A call of an engine, with the headers of its replay protection
+struct EngineCall {
+    method: Method,
+    path: String,
+    timestamp: Option<String>,
+    nonce: Option<String>,
+    signature: Option<String>,
+    body: Bytes,
+}
+
+/// Returns a filter which extracts the body of the calls of the engines, rejecting the
+/// calls which are forged, stale or replayed if replay protection is configured. Every
+/// call must then have a `Request-Timestamp` header which is at most `window` old (or
+/// ahead of the node's clock), a `Request-Nonce` header which was not used within the
+/// window, and a `Request-Signature` header which signs them with the method, path and
+/// body of the call (see [`sign_call`](./fn.sign_call.html)) under the secret of the
+/// engine of the account. Unlike idempotency keys, which make the retries of a call
+/// return its first response, nonces must not be reused by retries.
+pub fn replay_protection<S, A>(
+    store: S,
+    protection: Option<ReplayProtection>,
+) -> impl Filter<Extract = (Bytes,), Error = Rejection> + Clone
+where
+    S: NonceStore + AccountStore<Account = A> + Clone + Send + Sync + 'static,
+    A: SettlementAccount + Send + Sync + 'static,
+{
+    warp::method()
+        .and(warp::path::full())
+        .and(warp::header::optional::<String>(TIMESTAMP_HEADER))
+        .and(warp::header::optional::<String>(NONCE_HEADER))
+        .and(warp::header::optional::<String>(SIGNATURE_HEADER))
+        .and(warp::body::bytes())
+        .and_then(
+            move |method: Method,
+                  path: FullPath,
+                  timestamp: Option<String>,
+                  nonce: Option<String>,
+                  signature: Option<String>,
+                  body: Bytes| {
+                let store = store.clone();
+                let protection = protection.clone();
+                async move {
+                    if let Some(protection) = protection {
+                        let call = EngineCall {
+                            method,
+                            path: path.as_str().to_string(),
+                            timestamp,
+                            nonce,
+                            signature,
+                            body,
+                        };
+                        check_replay(store, &protection, &call)
+                            .await
+                            .map_err(Rejection::from)?;
+                        return Ok::<_, Rejection>(call.body);
+                    }
+                    Ok::<_, Rejection>(body)
+                }
+            },
+        )
+}
+
+/// Returns the key of the engine of the account the call is for. The paths of the
+/// settlement API all start with `/accounts/:account_id`.
+async fn engine_key<S, A>(store: &S, protection: &ReplayProtection, path: &str) -> Option<hmac::Key>
+where
+    S: AccountStore<Account = A>,
+    A: SettlementAccount,
+{
+    let account_id = path.strip_prefix("/accounts/")?.split('/').next()?;
+    let account_id = Uuid::from_str(account_id).ok()?;
+    let accounts = store.get_accounts(vec![account_id]).await.ok()?;
+    let engine = accounts.first()?.settlement_engine_details()?;
+    protection.keys.get(&engine.url).cloned()
+}
+
+async fn check_replay<S, A>(
+    store: S,
+    protection: &ReplayProtection,
+    call: &EngineCall,
+) -> Result<(), ApiError>
+where
+    S: NonceStore + AccountStore<Account = A>,
+    A: SettlementAccount,
+{
+    let (timestamp, nonce, signature) =
+        match (&call.timestamp, &call.nonce, &call.signature) {
+            (Some(timestamp), Some(nonce), Some(signature)) if !nonce.is_empty() => {
+                (timestamp, nonce, signature)
+            }
+            _ => return Err(rejected_replay(
+                "missing",
+                ApiError::bad_request().detail(
+                    "Request-Timestamp, Request-Nonce and Request-Signature headers are required"
+                        .to_string(),
+                ),
+            )),
+        };
+    let timestamp = timestamp.parse::<u64>().map_err(|_| {
+        rejected_replay(
+            "invalid",
+            ApiError::bad_request().detail(format!("Invalid Request-Timestamp: {}", timestamp)),
+        )
+    })?;
+
+    // The signature is checked first, so that forged calls cannot use up nonces
+    let signed = match engine_key(&store, protection, &call.path).await {
+        Some(key) => {
+            let expected = sign(
+                &key,
+                timestamp,
+                nonce,
+                call.method.as_str(),
+                &call.path,
+                &call.body,
+            );
+            constant_time::verify_slices_are_equal(expected.as_bytes(), signature.as_bytes())
+                .is_ok()
+        }
+        None => false,
+    };
+    if !signed {
+        warn!(
+            "Rejecting settlement API call to {} with an invalid signature",
+            call.path
+        );
+        return Err(rejected_replay(
+            "signature",
+            ApiError::unauthorized().detail(
+                "Request-Signature is not the signature of the call by the engine of the account"
+                    .to_string(),
+            ),
+        ));
+    }
+
+    let window = protection.window;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64;
+    let age = now.abs_diff(timestamp);
+    if age > window.as_millis() as u64 {
+        warn!(
+            "Rejecting stale settlement API call with timestamp {} ({}ms away from now)",
+            timestamp, age
+        );
+        return Err(rejected_replay(
+            "stale",
+            ApiError::unauthorized().detail(format!(
+                "Request-Timestamp is outside of the acceptance window of {}ms",
+                window.as_millis()
+            )),
+        ));
+    }
+
+    // The timestamps of the accepted calls are at most one window ahead of the clock,
+    // so their nonces must be remembered until one window after that
+    let saved = store
+        .save_nonce(nonce.clone(), window * 2)
+        .await
+        .map_err(|err| {
+            error!("Error saving nonce of settlement API call: {}", err);
+            ApiError::internal_server_error().detail("Error saving nonce".to_string())
+        })?;
+    if !saved {
+        warn!(
+            "Rejecting replayed settlement API call with nonce {}",
+            nonce
+        );
+        return Err(rejected_replay(
+            "duplicate",
+            ApiError::conflict().detail(format!("Request-Nonce {} was already used", nonce)),
+        ));
+    }
+    Ok(())
+}
+
+fn rejected_replay(reason: &'static str, error: ApiError) -> ApiError {
+    recorder().increment_counter(
+        Key::from_name_and_labels("settlements.replays_rejected", labels!("reason" => reason)),
+        1,
+    );
+    error
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::types::SettlementEngineDetails;
+    use interledger_errors::AccountStoreError;
+    use interledger_packet::Address;
+    use interledger_service::{Account, Username};
+    use once_cell::sync::Lazy;
+    use parking_lot::Mutex;
+    use std::collections::HashSet;
+    use warp::test::{request, RequestBuilder};
+
+    static USERNAME: Lazy<Username> = Lazy::new(|| Username::from_str("alice").unwrap());
+    static ADDRESS: Lazy<Address> = Lazy::new(|| Address::from_str("example.alice").unwrap());
+    static ENGINE: Lazy<Url> = Lazy::new(|| Url::parse("http://localhost:3000").unwrap());
+    static OTHER_ENGINE: Lazy<Url> = Lazy::new(|| Url::parse("http://localhost:3001").unwrap());
+    const ACCOUNT_ID: Uuid = Uuid::from_u128(1);
+    const OTHER_ACCOUNT_ID: Uuid = Uuid::from_u128(2);
+
+    #[derive(Clone, Debug)]
+    struct TestAccount {
+        id: Uuid,
+        engine: Url,
+    }
+
+    impl Account for TestAccount {
+        fn id(&self) -> Uuid {
+            self.id
+        }
+        fn username(&self) -> &Username {
+            &USERNAME
+        }
+        fn ilp_address(&self) -> &Address {
+            &ADDRESS
+        }
+        fn asset_scale(&self) -> u8 {
+            9
+        }
+        fn asset_code(&self) -> &str {
+            "XRP"
+        }
+    }
+
+    impl SettlementAccount for TestAccount {
+        fn settlement_engine_details(&self) -> Option<SettlementEngineDetails> {
+            Some(SettlementEngineDetails {
+                url: self.engine.clone(),
+            })
+        }
+    }
+
+    #[derive(Clone, Default)]
+    struct TestStore {
+        nonces: Arc<Mutex<HashSet<String>>>,
+    }
+
+    #[async_trait]
+    impl NonceStore for TestStore {
+        async fn save_nonce(
+            &self,
+            nonce: String,
+            _ttl: Duration,
+        ) -> Result<bool, IdempotentStoreError> {
+            Ok(self.nonces.lock().insert(nonce))
+        }
+    }
+
+    #[async_trait]
+    impl AccountStore for TestStore {
+        type Account = TestAccount;
+
+        async fn get_accounts(
+            &self,
+            account_ids: Vec<Uuid>,
+        ) -> Result<Vec<TestAccount>, AccountStoreError> {
+            let engine = |id| {
+                if id == ACCOUNT_ID {
+                    Some(ENGINE.clone())
+                } else if id == OTHER_ACCOUNT_ID {
+                    Some(OTHER_ENGINE.clone())
+                } else {
+                    None
+                }
+            };
+            account_ids
+                .into_iter()
+                .map(|id| {
+                    engine(id)
+                        .map(|engine| TestAccount { id, engine })
+                        .ok_or_else(|| AccountStoreError::AccountNotFound(id.to_string()))
+                })
+                .collect()
+        }
+
+        async fn get_account_id_from_username(
+            &self,
+            username: &Username,
+        ) -> Result<Uuid, AccountStoreError> {
+            Err(AccountStoreError::AccountNotFound(username.to_string()))
+        }
+    }
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as u64
+    }
+
+    fn protection() -> ReplayProtection {
+        ReplayProtection::new(
+            Duration::from_secs(60),
+            vec![
+                (ENGINE.clone(), b"secret".to_vec()),
+                (OTHER_ENGINE.clone(), b"other secret".to_vec()),
+            ],
+        )
+    }
+
+    fn path(account_id: Uuid) -> String {
+        format!("/accounts/{}/settlements", account_id)
+    }
+
+    /// A call signed with the secret, whose body is `{}`
+    fn call(secret: &[u8], timestamp: u64, nonce: &str, path: &str) -> RequestBuilder {
+        request()
+            .method("POST")
+            .path(path)
+            .header(TIMESTAMP_HEADER, timestamp.to_string())
+            .header(NONCE_HEADER, nonce)
+            .header(
+                SIGNATURE_HEADER,
+                sign_call(secret, timestamp, nonce, "POST", path, b"{}"),
+            )
+            .body("{}")
+    }
+
+    #[tokio::test]
+    async fn accepts_any_call_without_protection() {
+        let filter = replay_protection(TestStore::default(), None);
+        let body = request().body("{}").filter(&filter).await.unwrap();
+        assert_eq!(body, Bytes::from("{}"));
+    }
+
+    #[tokio::test]
+    async fn rejects_stale_and_replayed_calls() {
+        let filter = replay_protection(TestStore::default(), Some(protection()));
+        let path = path(ACCOUNT_ID);
+
+        assert!(request().path(&path).filter(&filter).await.is_err());
+        let body = call(b"secret", now(), "a", &path)
+            .filter(&filter)
+            .await
+            .unwrap();
+        assert_eq!(body, Bytes::from("{}"));
+        // the same nonce cannot be used twice
+        assert!(call(b"secret", now(), "a", &path)
+            .filter(&filter)
+            .await
+            .is_err());
+        assert!(call(b"secret", now(), "b", &path)
+            .filter(&filter)
+            .await
+            .is_ok());
+        // stale and future timestamps are rejected
+        assert!(call(b"secret", now() - 61_000, "c", &path)
+            .filter(&filter)
+            .await
+            .is_err());
+        assert!(call(b"secret", now() + 61_000, "d", &path)
+            .filter(&filter)
+            .await
+            .is_err());
+        assert!(request()
+            .path(&path)
+            .header(TIMESTAMP_HEADER, "yesterday")
+            .header(NONCE_HEADER, "e")
+            .header(SIGNATURE_HEADER, "00")
+            .filter(&filter)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn rejects_forged_calls_before_using_their_nonce() {
+        let store = TestStore::default();
+        let filter = replay_protection(store.clone(), Some(protection()));
+        let path = path(ACCOUNT_ID);
+
+        // signed with the secret of another engine
+        assert!(call(b"other secret", now(), "a", &path)
+            .filter(&filter)
+            .await
+            .is_err());
+        // the timestamp, body and path are signed
+        let timestamp = now();
+        let signature = sign_call(b"secret", timestamp, "a", "POST", &path, b"{}");
+        let forged = |timestamp: u64, path: &str, body: &str| {
+            request()
+                .method("POST")
+                .path(path)
+                .header(TIMESTAMP_HEADER, timestamp.to_string())
+                .header(NONCE_HEADER, "a")
+                .header(SIGNATURE_HEADER, signature.as_str())
+                .body(body)
+        };
+        assert!(forged(timestamp, &path, "{}").filter(&filter).await.is_ok());
+        let other_path = self::path(OTHER_ACCOUNT_ID);
+        assert!(forged(timestamp + 1, &path, "{}")
+            .filter(&filter)
+            .await
+            .is_err());
+        assert!(forged(timestamp, &path, r#"{"amount":"1"}"#)
+            .filter(&filter)
+            .await
+            .is_err());
+        assert!(forged(timestamp, &other_path, "{}")
+            .filter(&filter)
+            .await
+            .is_err());
+        // the accounts without a configured engine cannot be called
+        assert!(call(b"secret", now(), "b", &self::path(Uuid::from_u128(3)))
+            .filter(&filter)
+            .await
+            .is_err());
+        assert!(call(b"secret", now(), "c", "/settlements")
+            .filter(&filter)
+            .await
+            .is_err());
+        assert_eq!(store.nonces.lock().len(), 1);
+
+        // the nonces of the rejected calls can still be used
+        assert!(call(b"secret", now(), "b", &path)
+            .filter(&filter)
+            .await
+            .is_ok());
+        assert!(call(b"other secret", now(), "c", &other_path)
+            .filter(&filter)
+            .await
+            .is_ok());
+    }
+
+    #[test]
+    fn signs_the_call() {
+        // The SHA-256 digest of an empty body is e3b0c442...b855
+        let signature = sign_call(b"key", 1, "nonce", "POST", "/accounts/a/messages", b"");
+        let message = "1\nnonce\nPOST\n/accounts/a/messages\n\
+            e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855\n";
+        let expected = hmac::sign(
+            &hmac::Key::new(hmac::HMAC_SHA256, b"key"),
+            message.as_bytes(),
+        );
+        assert_eq!(signature, to_hex(expected.as_ref()));
+        assert_eq!(signature.len(), 64);
+    }
+}
@@ -1,8 +1,11 @@
-use crate::core::types::Quantity;
+use crate::core::{
+    types::{Quantity, SettlementTotals},
+    SettlementTlsConfig,
+};
 use futures_retry::{ErrorHandler, FutureRetry, RetryPolicy};
 use reqwest::Client;
 use serde_json::json;
-use std::time::Duration;
+use std::{io, time::Duration};
 use tracing::{debug, trace};
 use url::Url;
 use uuid::Uuid;
@@ -31,6 +34,21 @@ impl SettlementClient {
         }
     }
 
+    /// Returns a client which authenticates with the node's certificate and only trusts
+    /// the engines whose certificate is signed by the configured CA. It fails if the
+    /// certificates cannot be loaded.
+    pub fn with_tls(config: &SettlementTlsConfig) -> io::Result<Self> {
+        let client = Client::builder()
+            .timeout(DEFAULT_HTTP_TIMEOUT)
+            .use_preconfigured_tls(config.client_config()?)
+            .build()
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+        Ok(SettlementClient {
+            client,
+            max_retries: MAX_RETRIES,
+        })
+    }
+
     /// Sends an idempotent account creation request to the engine (will retry if it fails)
     /// This is done by sending a POST to /accounts with the provided `id` as the request's body
     pub async fn create_engine_account(&self, id: Uuid, engine_url: Url) -> Response {
@@ -91,6 +109,36 @@ impl SettlementClient {
         .await
     }
 
+    /// Requests the total amounts settled with the account from the engine (without retrying,
+    /// since the totals are requested periodically). This is done by sending a GET to
+    /// /accounts/:id/settlements
+    pub async fn get_settlement_totals(
+        &self,
+        id: Uuid,
+        engine_url: Url,
+    ) -> Result<SettlementTotals, reqwest::Error> {
+        let mut settlement_engine_url = engine_url;
+        // $URL/accounts/:account_id/settlements
+        settlement_engine_url
+            .path_segments_mut()
+            .expect("Invalid settlement engine URL")
+            .push(ACCOUNTS_ENDPOINT)
+            .push(&id.to_string())
+            .push("settlements");
+        trace!(
+            "Requesting the settlement totals of account {} from settlement engine: {}",
+            id,
+            settlement_engine_url
+        );
+        self.client
+            .get(settlement_engine_url.as_ref())
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+    }
+
     async fn create_engine_account_once(&self, id: Uuid, engine_url: Url) -> Response {
         let mut se_url = engine_url;
         // $URL/accounts
@@ -256,4 +304,24 @@ mod tests {
         m.assert();
         assert!(ret.is_err());
     }
+
+    #[tokio::test]
+    async fn gets_settlement_totals() {
+        let m = mock("GET", SETTLEMENT_API.clone())
+            .with_status(200)
+            .with_body(
+                r#"{"sent":{"amount":"100","scale":6},"received":{"amount":"42","scale":6}}"#,
+            )
+            .create();
+        let client = SettlementClient::default();
+
+        let totals = client
+            .get_settlement_totals(Uuid::new_v4(), "http://localhost:1234".parse().unwrap())
+            .await
+            .unwrap();
+
+        m.assert();
+        assert_eq!(totals.sent, Quantity::new(100, 6));
+        assert_eq!(totals.received, Quantity::new(42, 6));
+    }
 }
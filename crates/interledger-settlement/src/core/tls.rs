@@ -0,0 +1,115 @@
+use rustls::{
+    internal::pemfile, AllowAnyAuthenticatedClient, Certificate, ClientConfig, PrivateKey,
+    RootCertStore, ServerConfig,
+};
+use serde::Deserialize;
+use std::{
+    fs::File,
+    io::{self, BufReader},
+};
+
+/// Mutual TLS between the node and its settlement engines. The node authenticates with
+/// its certificate when it calls the engines, and requires the engines to present a
+/// certificate when they call its settlement API. In both directions, only the
+/// certificates signed by the configured CA are trusted.
+#[derive(Deserialize, Clone, Debug)]
+pub struct SettlementTlsConfig {
+    /// Path of the PEM file with the certificates of the CAs which are trusted to sign the
+    /// certificates of the settlement engines (instead of the system's root certificates)
+    pub ca_cert: String,
+    /// Path of the PEM file with the certificate chain of the node
+    pub cert: String,
+    /// Path of the PEM file with the private key (PKCS#8 or RSA) of the node's certificate
+    pub key: String,
+}
+
+impl SettlementTlsConfig {
+    /// Returns the TLS configuration of the node's calls to the settlement engines
+    pub fn client_config(&self) -> io::Result<ClientConfig> {
+        let mut config = ClientConfig::new();
+        config.root_store = self.ca_store()?;
+        config
+            .set_single_client_cert(self.certs()?, self.private_key()?)
+            .map_err(|err| invalid_data(format!("Invalid client certificate: {}", err)))?;
+        Ok(config)
+    }
+
+    /// Returns the TLS configuration of the settlement API, which
+    /// requires the engines to authenticate with a client certificate
+    pub fn server_config(&self) -> io::Result<ServerConfig> {
+        let mut config = ServerConfig::new(AllowAnyAuthenticatedClient::new(self.ca_store()?));
+        config
+            .set_single_cert(self.certs()?, self.private_key()?)
+            .map_err(|err| invalid_data(format!("Invalid server certificate: {}", err)))?;
+        Ok(config)
+    }
+
+    fn ca_store(&self) -> io::Result<RootCertStore> {
+        let mut store = RootCertStore::empty();
+        let (added, _) = store
+            .add_pem_file(&mut open(&self.ca_cert)?)
+            .map_err(|_| invalid_data(format!("Invalid CA certificate in {}", self.ca_cert)))?;
+        if added == 0 {
+            return Err(invalid_data(format!(
+                "No CA certificate found in {}",
+                self.ca_cert
+            )));
+        }
+        Ok(store)
+    }
+
+    fn certs(&self) -> io::Result<Vec<Certificate>> {
+        let certs = pemfile::certs(&mut open(&self.cert)?)
+            .map_err(|_| invalid_data(format!("Invalid certificate in {}", self.cert)))?;
+        if certs.is_empty() {
+            return Err(invalid_data(format!(
+                "No certificate found in {}",
+                self.cert
+            )));
+        }
+        Ok(certs)
+    }
+
+    fn private_key(&self) -> io::Result<PrivateKey> {
+        let invalid_key = || invalid_data(format!("Invalid private key in {}", self.key));
+        let mut keys =
+            pemfile::pkcs8_private_keys(&mut open(&self.key)?).map_err(|_| invalid_key())?;
+        if keys.is_empty() {
+            keys = pemfile::rsa_private_keys(&mut open(&self.key)?).map_err(|_| invalid_key())?;
+        }
+        keys.into_iter()
+            .next()
+            .ok_or_else(|| invalid_data(format!("No private key found in {}", self.key)))
+    }
+}
+
+fn open(path: &str) -> io::Result<BufReader<File>> {
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|err| io::Error::new(err.kind(), format!("Error opening {}: {}", path, err)))
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fails_to_load_missing_files() {
+        let config = SettlementTlsConfig {
+            ca_cert: "/nonexistent/ca.pem".to_string(),
+            cert: "/nonexistent/node.pem".to_string(),
+            key: "/nonexistent/node-key.pem".to_string(),
+        };
+        let err = match config.client_config() {
+            Ok(_) => panic!("Loaded a TLS client config from missing files"),
+            Err(err) => err,
+        };
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert!(err.to_string().contains("/nonexistent/ca.pem"));
+        assert!(config.server_config().is_err());
+    }
+}
@@ -20,6 +20,13 @@ pub const NO_ENGINE_CONFIGURED_ERROR_TYPE: ApiErrorType = ApiErrorType {
     status: StatusCode::NOT_FOUND,
 };
 
+/// Settlement Totals Not Supported error type (501 Not Implemented)
+pub const TOTALS_NOT_SUPPORTED_ERROR_TYPE: ApiErrorType = ApiErrorType {
+    r#type: &ProblemType::Default,
+    title: "Settlement totals not supported",
+    status: StatusCode::NOT_IMPLEMENTED,
+};
+
 /// Number Conversion error type (404 Not Found)
 pub const CONVERSION_ERROR_TYPE: ApiErrorType = ApiErrorType {
     r#type: &ProblemType::Default,
@@ -53,6 +60,16 @@ impl Quantity {
     }
 }
 
+/// The total amounts which a settlement engine sent to and received from the peer of an
+/// account, since the account was created on the engine
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+pub struct SettlementTotals {
+    /// Total of the outgoing settlements the engine executed
+    pub sent: Quantity,
+    /// Total of the incoming settlements the engine received
+    pub received: Quantity,
+}
+
 /// Helper enum allowing API responses to not specify any data and let the consumer
 /// of the call decide what to do with the success value
 // TODO: could this maybe be omitted and replaced with Option<Bytes> in Responses?
@@ -89,6 +106,21 @@ pub trait SettlementEngine {
     /// The connector sends this request when it receives an incoming settlement message
     /// from the peer, and returns the response message back to the peer.
     async fn receive_message(&self, account_id: String, message: Vec<u8>) -> ApiResult;
+
+    /// Returns the total amounts settled with the account, which the connector reconciles
+    /// with its own records. Engines which do not track them keep this default, which
+    /// responds with a `501 Not Implemented` error.
+    async fn get_settlement_totals(
+        &self,
+        account_id: String,
+    ) -> Result<SettlementTotals, ApiError> {
+        Err(
+            ApiError::from_api_error_type(&TOTALS_NOT_SUPPORTED_ERROR_TYPE).detail(format!(
+                "the engine does not track the settlements of account {}",
+                account_id
+            )),
+        )
+    }
 }
 
 // TODO: Since we still haven't finalized all the settlement details, we might
@@ -107,6 +139,18 @@ pub trait SettlementAccount: Account {
     fn settlement_engine_details(&self) -> Option<SettlementEngineDetails> {
         None
     }
+
+    /// URL to which the account's peer is notified of the packets rejected because it owes
+    /// the node too much, so that it can settle to unblock them
+    fn liquidity_notification_url(&self) -> Option<Url> {
+        None
+    }
+
+    /// URL to which the notifications of the account's balance crossing the thresholds
+    /// configured on the node are POSTed
+    fn balance_notification_url(&self) -> Option<Url> {
+        None
+    }
 }
 
 #[async_trait]
@@ -136,6 +180,36 @@ pub trait SettlementStore {
     ) -> Result<(), SettlementStoreError>;
 }
 
+#[async_trait]
+/// Trait used by the connector to load the amounts it accounted for as settled with each
+/// account, which are reconciled with the totals of the settlement engines
+pub trait SettlementTotalsStore {
+    /// Returns the total amounts of the outgoing settlements deducted from the account's
+    /// balance (minus the ones which were refunded) and of the incoming settlements credited
+    /// to it, in this order and in the account's asset scale
+    async fn get_settlement_totals(
+        &self,
+        account_id: Uuid,
+    ) -> Result<(u64, u64), SettlementStoreError>;
+}
+
+#[async_trait]
+/// Trait used by the nodes sharing a store to hand the outgoing settlements triggered
+/// by the packets they forward over to the node which sends them to the settlement engines
+pub trait SettlementQueueStore {
+    /// Queues a settlement of the provided amount to the account. The amount must
+    /// already be deducted from the account's balance.
+    async fn queue_settlement(
+        &self,
+        account_id: Uuid,
+        settle_amount: u64,
+    ) -> Result<(), SettlementStoreError>;
+
+    /// Removes the settlement which was queued first from the queue and returns it,
+    /// along with the account it is owed to
+    async fn take_queued_settlement(&self) -> Result<Option<(Uuid, u64)>, SettlementStoreError>;
+}
+
 /// Trait used by the connector and engine to track amounts which should have been
 /// settled but were not due to precision loss
 #[async_trait]
@@ -1,34 +1,210 @@
-use super::{Error, SpspResponse};
+use super::payment_pointer::{check_secure, payment_pointer_to_url};
+use super::{Error, PullRequest, PullResponse, PullTerms, SpspResponse};
 use futures::TryFutureExt;
 use interledger_rates::ExchangeRateStore;
 use interledger_service::{Account, IncomingService};
-use interledger_stream::{send_money, StreamDelivery};
-use reqwest::Client;
+use interledger_stream::{
+    deliver_money, send_money_with_memo, MaxPacketAmountStore, StreamDelivery,
+    UnreachableDestinationStore,
+};
+use once_cell::sync::Lazy;
+use reqwest::{header::HeaderMap, redirect, Client};
+use serde::de::DeserializeOwned;
+use std::{
+    collections::HashMap,
+    error::Error as StdError,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
 use tracing::{debug, error, trace};
+use url::Url;
+
+/// Max number of redirects followed when querying an SPSP server
+const MAX_REDIRECTS: usize = 5;
+/// Max number of SPSP responses kept in the cache of a client
+const MAX_CACHED_RESPONSES: usize = 1000;
+
+/// The client used by [`query`](./fn.query.html) and [`pay`](./fn.pay.html), so that
+/// they share its cache of responses
+static DEFAULT_CLIENT: Lazy<SpspClient> = Lazy::new(SpspClient::new);
+
+/// A client querying SPSP servers, which caches their responses for as long as
+/// their `Cache-Control` header allows.
+///
+/// Payment pointers must resolve to HTTPS URLs. Plain HTTP is only accepted for
+/// URLs of the local host (such as `http://localhost:7770/accounts/alice/spsp`),
+/// and redirects are followed as long as they do not downgrade to HTTP.
+#[derive(Clone)]
+pub struct SpspClient {
+    http: Client,
+    cache: Arc<Mutex<HashMap<Url, CachedResponse>>>,
+}
+
+struct CachedResponse {
+    response: SpspResponse,
+    expires_at: Instant,
+}
+
+impl Default for SpspClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SpspClient {
+    /// Constructs a client with an empty cache
+    pub fn new() -> Self {
+        let redirect_policy = redirect::Policy::custom(|attempt| {
+            if attempt.previous().len() >= MAX_REDIRECTS {
+                attempt.error(format!("more than {} redirects", MAX_REDIRECTS))
+            } else if let Err(err) = check_secure(attempt.url()) {
+                attempt.error(err.to_string())
+            } else {
+                attempt.follow()
+            }
+        });
+        SpspClient {
+            http: Client::builder()
+                .redirect(redirect_policy)
+                .build()
+                .expect("Could not build HTTP client"),
+            cache: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Get an ILP Address and shared secret by the receiver of this payment for this
+    /// connection. The receiver is either a payment pointer (such as `$example.com/alice`)
+    /// or the URL of an SPSP server.
+    pub async fn query(&self, receiver: &str) -> Result<SpspResponse, Error> {
+        let url = payment_pointer_to_url(receiver)?;
+        if let Some(response) = self.cached_response(&url) {
+            trace!("Using cached SPSP response of receiver: {}", url);
+            return Ok(response);
+        }
+        trace!("Querying receiver: {}", url);
+
+        let res = self
+            .http
+            .get(url.clone())
+            .header("Accept", "application/spsp4+json")
+            .send()
+            .map_err(query_error)
+            .await?;
+        let res = res.error_for_status().map_err(query_error)?;
+        let max_age = cache_max_age(res.headers());
+
+        let body = res.bytes().map_err(query_error).await?;
+        let response: SpspResponse = parse_response(&body)?;
+        if response.shared_secret.len() != 32 {
+            return Err(Error::InvalidSpspServerResponseError(format!(
+                "the shared secret must be 32 bytes long, got {} bytes",
+                response.shared_secret.len()
+            )));
+        }
+
+        if let Some(max_age) = max_age {
+            self.cache_response(url, response.clone(), max_age);
+        }
+        Ok(response)
+    }
+
+    /// Get the terms of a pull pointer and the amounts pulled from it so far.
+    /// These are never cached, since every pull changes them.
+    pub async fn query_pull(&self, pull_pointer: &str) -> Result<PullTerms, Error> {
+        let url = payment_pointer_to_url(pull_pointer)?;
+        trace!("Querying pull pointer: {}", url);
+
+        let res = self
+            .http
+            .get(url)
+            .header("Accept", "application/spsp4+json")
+            .send()
+            .map_err(query_error)
+            .await?;
+        let res = res.error_for_status().map_err(query_error)?;
+        let body = res.bytes().map_err(query_error).await?;
+        parse_response(&body)
+    }
+
+    /// Pull the amount of the request from a pull pointer. Its server pays the amount to
+    /// the STREAM connection of the request, if the terms of the pull pointer allow it.
+    pub async fn pull(
+        &self,
+        pull_pointer: &str,
+        request: &PullRequest,
+    ) -> Result<PullResponse, Error> {
+        let url = payment_pointer_to_url(pull_pointer)?;
+        trace!("Pulling {} from pull pointer: {}", request.amount, url);
+
+        let res = self
+            .http
+            .post(url)
+            .json(request)
+            .send()
+            .map_err(query_error)
+            .await?;
+        let status = res.status();
+        let body = res.bytes().map_err(query_error).await?;
+        if status.is_client_error() {
+            return Err(Error::PullRejectedError(format!(
+                "{} (response: {})",
+                status,
+                truncated(&body)
+            )));
+        } else if !status.is_success() {
+            return Err(Error::HttpError(format!(
+                "{} (response: {})",
+                status,
+                truncated(&body)
+            )));
+        }
+        parse_response(&body)
+    }
+
+    fn cached_response(&self, url: &Url) -> Option<SpspResponse> {
+        let cache = self.cache.lock().unwrap();
+        cache
+            .get(url)
+            .filter(|cached| cached.expires_at > Instant::now())
+            .map(|cached| cached.response.clone())
+    }
+
+    fn cache_response(&self, url: Url, response: SpspResponse, max_age: Duration) {
+        let mut cache = self.cache.lock().unwrap();
+        let now = Instant::now();
+        cache.retain(|_, cached| cached.expires_at > now);
+        if cache.len() < MAX_CACHED_RESPONSES {
+            cache.insert(
+                url,
+                CachedResponse {
+                    response,
+                    expires_at: now + max_age,
+                },
+            );
+        }
+    }
+}
 
 /// Get an ILP Address and shared secret by the receiver of this payment for this connection
 pub async fn query(server: &str) -> Result<SpspResponse, Error> {
-    let server = payment_pointer_to_url(server);
-    trace!("Querying receiver: {}", server);
-
-    let client = Client::new();
-    let res = client
-        .get(&server)
-        .header("Accept", "application/spsp4+json")
-        .send()
-        .map_err(|err| Error::HttpError(format!("Error querying SPSP receiver: {:?}", err)))
-        .await?;
-
-    let res = res
-        .error_for_status()
-        .map_err(|err| Error::HttpError(format!("Error querying SPSP receiver: {:?}", err)))?;
-
-    res.json::<SpspResponse>()
-        .map_err(|err| Error::InvalidSpspServerResponseError(format!("{:?}", err)))
-        .await
+    DEFAULT_CLIENT.query(server).await
+}
+
+/// Get the terms of a pull pointer and the amounts pulled from it so far
+pub async fn query_pull(pull_pointer: &str) -> Result<PullTerms, Error> {
+    DEFAULT_CLIENT.query_pull(pull_pointer).await
+}
+
+/// Pull the amount of the request from a pull pointer, whose server pays it to the
+/// STREAM connection of the request.
+///
+/// This returns the payment sent by the server and the amounts pulled from the pull pointer.
+pub async fn pull(pull_pointer: &str, request: &PullRequest) -> Result<PullResponse, Error> {
+    DEFAULT_CLIENT.pull(pull_pointer, request).await
 }
 
 /// Query the details of the given Payment Pointer and send a payment using the STREAM protocol.
+/// The memo, if there is one, is sent to the receiver along with the money.
 ///
 /// This returns the amount delivered, as reported by the receiver and in the receiver's asset's units.
 pub async fn pay<I, A, S>(
@@ -38,18 +214,24 @@ pub async fn pay<I, A, S>(
     receiver: &str,
     source_amount: u64,
     slippage: f64,
+    memo: Option<String>,
 ) -> Result<StreamDelivery, Error>
 where
     I: IncomingService<A> + Clone + Send + Sync + 'static,
     A: Account + Send + Sync + 'static,
-    S: ExchangeRateStore + Send + Sync + 'static,
+    S: ExchangeRateStore
+        + MaxPacketAmountStore
+        + UnreachableDestinationStore
+        + Send
+        + Sync
+        + 'static,
 {
     let spsp = query(receiver).await?;
     let shared_secret = spsp.shared_secret;
     let addr = spsp.destination_account;
     debug!("Sending SPSP payment to address: {}", addr);
 
-    let receipt = send_money(
+    let receipt = send_money_with_memo(
         service,
         &from_account,
         store,
@@ -57,6 +239,7 @@ where
         shared_secret,
         source_amount,
         slippage,
+        memo,
     )
     .map_err(move |err| {
         error!("Error sending payment: {:?}", err);
@@ -68,39 +251,137 @@ where
     Ok(receipt)
 }
 
-fn payment_pointer_to_url(payment_pointer: &str) -> String {
-    let mut url: String = if payment_pointer.starts_with('$') {
-        let mut url = "https://".to_string();
-        url.push_str(&payment_pointer[1..]);
-        url
-    } else {
-        payment_pointer.to_string()
+/// Query the details of the given Payment Pointer and deliver exactly the destination amount
+/// to it using the STREAM protocol, or fail. The source amount is whatever the destination
+/// amount costs at the exchange rate of the path, as long as it is within the slippage.
+///
+/// This returns the amounts sent and delivered.
+pub async fn deliver<I, A, S>(
+    service: I,
+    from_account: A,
+    store: S,
+    receiver: &str,
+    destination_amount: u64,
+    slippage: f64,
+    memo: Option<String>,
+) -> Result<StreamDelivery, Error>
+where
+    I: IncomingService<A> + Clone + Send + Sync + 'static,
+    A: Account + Send + Sync + 'static,
+    S: ExchangeRateStore
+        + MaxPacketAmountStore
+        + UnreachableDestinationStore
+        + Send
+        + Sync
+        + 'static,
+{
+    let spsp = query(receiver).await?;
+    let shared_secret = spsp.shared_secret;
+    let addr = spsp.destination_account;
+    debug!("Delivering SPSP payment to address: {}", addr);
+
+    let receipt = deliver_money(
+        service,
+        &from_account,
+        store,
+        addr,
+        shared_secret,
+        destination_amount,
+        slippage,
+        memo,
+    )
+    .map_err(move |err| {
+        error!("Error delivering payment: {:?}", err);
+        Error::SendMoneyError(destination_amount)
+    })
+    .await?;
+
+    debug!("Delivered SPSP payment. StreamDelivery: {:?}", receipt);
+    Ok(receipt)
+}
+
+/// Parses the JSON body of a response of an SPSP server
+fn parse_response<T: DeserializeOwned>(body: &[u8]) -> Result<T, Error> {
+    serde_json::from_slice(body).map_err(|err| {
+        Error::InvalidSpspServerResponseError(format!("{} (response: {})", err, truncated(body)))
+    })
+}
+
+/// Returns the beginning of the body of a response, to be included in errors
+fn truncated(body: &[u8]) -> String {
+    String::from_utf8_lossy(&body[..body.len().min(200)]).to_string()
+}
+
+/// Returns for how long the response may be cached, according to its `Cache-Control` header
+fn cache_max_age(headers: &HeaderMap) -> Option<Duration> {
+    let cache_control = headers.get("cache-control")?.to_str().ok()?;
+    let mut max_age = None;
+    for directive in cache_control.split(',').map(str::trim) {
+        let directive = directive.to_lowercase();
+        if directive == "no-store" || directive == "no-cache" {
+            return None;
+        } else if let Some(seconds) = directive.strip_prefix("max-age=") {
+            max_age = seconds.parse().ok().map(Duration::from_secs);
+        }
+    }
+    max_age.filter(|max_age| *max_age > Duration::from_secs(0))
+}
+
+/// Tells the failures to resolve the server's name and to establish a TLS connection
+/// apart from the other HTTP errors
+fn query_error(err: reqwest::Error) -> Error {
+    // reqwest does not expose the kind of connection errors, so they are told apart
+    // by the messages of the errors which caused them
+    let mut causes = Vec::new();
+    let mut source: Option<&(dyn StdError + 'static)> = err.source();
+    while let Some(cause) = source {
+        causes.push(cause.to_string().to_lowercase());
+        source = cause.source();
+    }
+    let is_cause = |patterns: &[&str]| {
+        causes
+            .iter()
+            .any(|cause| patterns.iter().any(|pattern| cause.contains(pattern)))
     };
 
-    let num_slashes = url.matches('/').count();
-    if num_slashes == 2 {
-        url.push_str("/.well-known/pay");
-    } else if num_slashes == 1 && url.ends_with('/') {
-        url.push_str(".well-known/pay");
+    let url = err
+        .url()
+        .map(|url| url.to_string())
+        .unwrap_or_else(|| "unknown URL".to_string());
+    if is_cause(&["dns error", "failed to lookup address"]) {
+        Error::DnsError(format!("{}: {}", url, err))
+    } else if is_cause(&["certificate", "handshake", "tls", "ssl"]) {
+        Error::TlsError(format!("{}: {}", url, err))
+    } else if err.is_decode() || err.is_body() {
+        Error::InvalidSpspServerResponseError(format!("{}: {}", url, err))
+    } else {
+        Error::HttpError(format!("{}: {}", url, err))
     }
-    trace!(
-        "Converted payment pointer: {} to URL: {}",
-        payment_pointer,
-        url
-    );
-    url
 }
 
 #[cfg(test)]
-mod payment_pointer {
+mod cache_headers {
     use super::*;
+    use reqwest::header::HeaderValue;
 
     #[test]
-    fn converts_pointer() {
-        let pointer = "$subdomain.domain.example";
+    fn reads_cache_headers() {
+        let headers = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert("cache-control", HeaderValue::from_str(value).unwrap());
+            headers
+        };
+        assert_eq!(cache_max_age(&HeaderMap::new()), None);
+        assert_eq!(
+            cache_max_age(&headers("max-age=60")),
+            Some(Duration::from_secs(60))
+        );
         assert_eq!(
-            payment_pointer_to_url(pointer),
-            "https://subdomain.domain.example/.well-known/pay"
+            cache_max_age(&headers("public, Max-Age=30")),
+            Some(Duration::from_secs(30))
         );
+        assert_eq!(cache_max_age(&headers("max-age=0")), None);
+        assert_eq!(cache_max_age(&headers("no-store, max-age=60")), None);
+        assert_eq!(cache_max_age(&headers("no-cache")), None);
     }
 }
@@ -4,23 +4,42 @@
 //!
 //! This uses a simple HTTPS request to establish a shared key between the sender and receiver that is used to
 //! authenticate ILP packets sent between them. SPSP uses the STREAM transport protocol for sending money and data over ILP.
+//!
+//! When built for `wasm32`, only the types of SPSP and the resolution of payment pointers are
+//! available, since the client and the server depend on reqwest and hyper.
 
 use interledger_packet::Address;
 use interledger_stream::Error as StreamError;
 use serde::{Deserialize, Serialize};
 
-/// An SPSP client which can query an SPSP Server's payment pointer and initiate a STREAM payment
+/// An SPSP client which can query an SPSP Server's payment pointer and initiate a STREAM payment.
+/// Responses are cached as allowed by their `Cache-Control` header.
+#[cfg(not(target_arch = "wasm32"))]
 mod client;
+/// Resolution of the payment pointers to the URLs of their SPSP servers
+mod payment_pointer;
+/// Types of the pull payments, whose payees pull the payments from the pull pointers
+/// the payers created for them, within the terms of the pull pointers
+mod pull;
 /// An SPSP Server implementing an HTTP Service which generates ILP Addresses and Shared Secrets
+#[cfg(not(target_arch = "wasm32"))]
 mod server;
 
-pub use client::{pay, query};
+#[cfg(not(target_arch = "wasm32"))]
+pub use client::{deliver, pay, pull, query, query_pull, SpspClient};
+pub use payment_pointer::payment_pointer_to_url;
+pub use pull::{PullBalance, PullRequest, PullResponse, PullTerms};
+#[cfg(not(target_arch = "wasm32"))]
 pub use server::SpspResponder;
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("Unable to query SPSP server: {0}")]
     HttpError(String),
+    #[error("Unable to resolve the host of SPSP server: {0}")]
+    DnsError(String),
+    #[error("Unable to establish a TLS connection to SPSP server: {0}")]
+    TlsError(String),
     #[error("Got invalid SPSP response from server: {0}")]
     InvalidSpspServerResponseError(String),
     #[error("STREAM error: {0}")]
@@ -31,10 +50,12 @@ pub enum Error {
     ListenError(String),
     #[error("Invalid Payment Pointer: {0}")]
     InvalidPaymentPointerError(String),
+    #[error("Pull was rejected by the server of the pull pointer: {0}")]
+    PullRejectedError(String),
 }
 
 /// An SPSP Response returned by the SPSP server
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct SpspResponse {
     /// The generated ILP Address for this SPSP connection
     destination_account: Address,
@@ -44,6 +65,18 @@ pub struct SpspResponse {
     shared_secret: Vec<u8>,
 }
 
+impl SpspResponse {
+    /// The ILP Address generated for this SPSP connection
+    pub fn destination_account(&self) -> &Address {
+        &self.destination_account
+    }
+
+    /// The shared secret of this SPSP connection
+    pub fn shared_secret(&self) -> &[u8] {
+        &self.shared_secret
+    }
+}
+
 // From https://github.com/serde-rs/json/issues/360#issuecomment-330095360
 #[doc(hidden)]
 mod serde_base64 {
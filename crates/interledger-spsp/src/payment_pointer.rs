@@ -0,0 +1,118 @@
+use super::Error;
+use std::net::IpAddr;
+use tracing::trace;
+use url::{Host, Url};
+
+/// Path a payment pointer without a path resolves to
+const WELL_KNOWN_PATH: &str = "/.well-known/pay";
+
+/// Resolves a payment pointer to the URL of its SPSP server as defined in the
+/// [RFC](https://interledger.org/rfcs/0026-payment-pointers/). URLs of SPSP servers
+/// are also accepted, as long as they are HTTPS URLs or the local host's.
+pub fn payment_pointer_to_url(payment_pointer: &str) -> Result<Url, Error> {
+    let payment_pointer = payment_pointer.trim();
+    let invalid = |reason: &str| {
+        Error::InvalidPaymentPointerError(format!("{} ({})", payment_pointer, reason))
+    };
+
+    let url = if let Some(pointer) = payment_pointer.strip_prefix('$') {
+        let mut url =
+            Url::parse(&format!("https://{}", pointer)).map_err(|err| invalid(&err.to_string()))?;
+        if !url.username().is_empty() || url.password().is_some() {
+            return Err(invalid("payment pointers cannot contain credentials"));
+        }
+        if url.query().is_some() || url.fragment().is_some() {
+            return Err(invalid(
+                "payment pointers cannot contain a query or a fragment",
+            ));
+        }
+        if url.path() == "/" {
+            url.set_path(WELL_KNOWN_PATH);
+        }
+        url
+    } else {
+        let mut url = Url::parse(payment_pointer).map_err(|err| invalid(&err.to_string()))?;
+        url.set_fragment(None);
+        check_secure(&url)?;
+        url
+    };
+    if url.host_str().map_or(true, str::is_empty) {
+        return Err(invalid("missing host"));
+    }
+
+    trace!(
+        "Converted payment pointer: {} to URL: {}",
+        payment_pointer,
+        url
+    );
+    Ok(url)
+}
+
+/// Only HTTPS URLs are accepted, except for the local host's ones
+pub(crate) fn check_secure(url: &Url) -> Result<(), Error> {
+    let is_local = match url.host() {
+        Some(Host::Domain(domain)) => domain.eq_ignore_ascii_case("localhost"),
+        Some(Host::Ipv4(ip)) => IpAddr::V4(ip).is_loopback(),
+        Some(Host::Ipv6(ip)) => IpAddr::V6(ip).is_loopback(),
+        None => false,
+    };
+    match url.scheme() {
+        "https" => Ok(()),
+        "http" if is_local => Ok(()),
+        "http" => Err(Error::InvalidPaymentPointerError(format!(
+            "{} (SPSP servers must be queried over HTTPS)",
+            url
+        ))),
+        scheme => Err(Error::InvalidPaymentPointerError(format!(
+            "{} (unsupported scheme {})",
+            url, scheme
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(payment_pointer: &str) -> String {
+        payment_pointer_to_url(payment_pointer).unwrap().to_string()
+    }
+
+    #[test]
+    fn converts_pointer() {
+        let pointer = "$subdomain.domain.example";
+        assert_eq!(
+            url(pointer),
+            "https://subdomain.domain.example/.well-known/pay"
+        );
+        assert_eq!(url("$Example.COM/"), "https://example.com/.well-known/pay");
+        assert_eq!(url(" $example.com/alice "), "https://example.com/alice");
+        assert_eq!(
+            url("$example.com:8443/invoices/123"),
+            "https://example.com:8443/invoices/123"
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_pointers() {
+        for pointer in &[
+            "$",
+            "$user:password@example.com",
+            "$example.com/alice?query",
+            "$example.com/alice#fragment",
+            "example.com/alice",
+            "ftp://example.com/alice",
+        ] {
+            assert!(payment_pointer_to_url(pointer).is_err(), "{}", pointer);
+        }
+    }
+
+    #[test]
+    fn requires_https_except_for_local_host() {
+        assert!(payment_pointer_to_url("https://example.com/alice").is_ok());
+        assert!(payment_pointer_to_url("http://localhost:7770/accounts/alice/spsp").is_ok());
+        assert!(payment_pointer_to_url("http://127.0.0.1:7770/accounts/alice/spsp").is_ok());
+        assert!(payment_pointer_to_url("http://[::1]:7770/accounts/alice/spsp").is_ok());
+        assert!(payment_pointer_to_url("http://example.com/alice").is_err());
+    }
+}
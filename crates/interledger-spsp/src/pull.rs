@@ -0,0 +1,95 @@
+use super::{serde_base64, SpspResponse};
+use interledger_packet::Address;
+use interledger_stream::StreamDelivery;
+use serde::{Deserialize, Serialize};
+
+/// The terms of a pull pointer, which the pulls from it are authorized against
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct PullTerms {
+    /// The max amount which can be pulled per interval, in the units of the asset
+    pub amount: u64,
+    /// Length of the intervals, in seconds
+    pub interval: u64,
+    /// The max amount which can be pulled in total, if there is one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cap: Option<u64>,
+    /// Code of the asset the amounts are denominated in
+    pub asset_code: String,
+    /// Scale of the asset the amounts are denominated in
+    pub asset_scale: u8,
+    /// The amounts pulled so far
+    pub balance: PullBalance,
+}
+
+/// The amounts pulled from a pull pointer
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+pub struct PullBalance {
+    /// Total amount pulled
+    pub total: u64,
+    /// Amount pulled in the current interval
+    pub interval: u64,
+    /// Amount which can still be pulled in the current interval
+    pub available: u64,
+}
+
+/// Request of a puller to be paid the amount from a pull pointer, via the STREAM
+/// connection of its SPSP receiver
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct PullRequest {
+    /// The amount to pull, in the units of the pull pointer's asset
+    pub amount: u64,
+    /// The ILP Address of the puller's STREAM connection
+    pub destination_account: Address,
+    /// Base-64 encoded shared secret of the puller's STREAM connection
+    #[serde(with = "serde_base64")]
+    pub shared_secret: Vec<u8>,
+}
+
+impl PullRequest {
+    /// Requests the amount to be paid to the connection of the SPSP response
+    pub fn new(amount: u64, receiver: &SpspResponse) -> Self {
+        PullRequest {
+            amount,
+            destination_account: receiver.destination_account.clone(),
+            shared_secret: receiver.shared_secret.clone(),
+        }
+    }
+}
+
+/// Response of the server of a pull pointer to an executed pull
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct PullResponse {
+    /// The payment sent to the puller
+    pub delivery: StreamDelivery,
+    /// The amounts pulled from the pull pointer, including this pull
+    pub balance: PullBalance,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::str::FromStr;
+
+    #[test]
+    fn serializes_pull_requests() {
+        let request = PullRequest {
+            amount: 100,
+            destination_account: Address::from_str("example.bob.token").unwrap(),
+            shared_secret: vec![0; 32],
+        };
+        let serialized = serde_json::to_value(&request).unwrap();
+        assert_eq!(
+            serialized,
+            json!({
+                "amount": 100,
+                "destination_account": "example.bob.token",
+                "shared_secret": base64::encode(&[0; 32]),
+            })
+        );
+        assert_eq!(
+            serde_json::from_str::<PullRequest>(&serialized.to_string()).unwrap(),
+            request
+        );
+    }
+}
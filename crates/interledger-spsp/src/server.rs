@@ -1,7 +1,7 @@
 use super::SpspResponse;
 use bytes::Bytes;
 use hyper::{service::Service as HttpService, Body, Error, Request, Response};
-use interledger_packet::Address;
+use interledger_packet::{Address, AddressError};
 use interledger_stream::ConnectionGenerator;
 use std::error::Error as StdError;
 use std::{
@@ -29,10 +29,24 @@ impl SpspResponder {
         }
     }
 
-    /// Returns an HTTP Response containing the destination account
-    /// and shared secret for this connection
+    /// Makes the responder generate the connections of the receiver hosted under the
+    /// destination tag, whose addresses are `<ILP Address>.<tag>.<token>` and whose
+    /// shared secrets are derived from a secret of its own
+    pub fn with_destination_tag(mut self, tag: &str) -> Result<Self, AddressError> {
+        if tag.is_empty() || tag.contains('.') {
+            return Err(AddressError::InvalidFormat);
+        }
+        self.ilp_address = self
+            .ilp_address
+            .with_suffix(tag.as_bytes())
+            .map_err(|_| AddressError::InvalidFormat)?;
+        self.connection_generator = self.connection_generator.with_tag(tag);
+        Ok(self)
+    }
+
+    /// Returns the destination account and shared secret of a new connection
     /// These fields are generated via [Stream's `ConnectionGenerator`](../interledger_stream/struct.ConnectionGenerator.html#method.generate_address_and_secret)
-    pub fn generate_http_response(&self) -> Response<Body> {
+    pub fn generate_spsp_response(&self) -> SpspResponse {
         let (destination_account, shared_secret) = self
             .connection_generator
             .generate_address_and_secret(&self.ilp_address);
@@ -40,16 +54,27 @@ impl SpspResponder {
             "Generated address and secret for: {:?}",
             destination_account
         );
-        let response = SpspResponse {
+        SpspResponse {
             destination_account,
             shared_secret: shared_secret.to_vec(),
-        };
+        }
+    }
 
+    /// Returns an HTTP Response containing the destination account
+    /// and shared secret for this connection
+    pub fn generate_http_response(&self) -> Response<Body> {
+        self.generate_spsp_response().into_http_response()
+    }
+}
+
+impl SpspResponse {
+    /// Returns the HTTP Response an SPSP server answers a query with
+    pub fn into_http_response(self) -> Response<Body> {
         Response::builder()
             .header("Content-Type", "application/spsp4+json")
             .header("Cache-Control", "max-age=60")
             .status(200)
-            .body(Body::from(serde_json::to_string(&response).unwrap()))
+            .body(Body::from(serde_json::to_string(&self).unwrap()))
             .unwrap()
     }
 }
@@ -113,4 +138,22 @@ mod spsp_server_test {
             "max-age=60"
         );
     }
+
+    #[tokio::test]
+    async fn generates_tagged_destination_accounts() {
+        let addr = Address::from_str("example.receiver").unwrap();
+        let responder = SpspResponder::new(addr.clone(), Bytes::from(&[0; 32][..]));
+        assert!(responder.clone().with_destination_tag("a.b").is_err());
+
+        let response = responder
+            .with_destination_tag("donate")
+            .unwrap()
+            .generate_http_response();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let response: SpspResponse = serde_json::from_slice(&body).unwrap();
+        assert!(response
+            .destination_account
+            .to_string()
+            .starts_with("example.receiver.donate."));
+    }
 }
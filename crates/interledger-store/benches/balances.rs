@@ -0,0 +1,129 @@
+//! Benchmark the Redis scripts which update the balances of the accounts for each packet.
+//!
+//! A `redis-server` is started for the benchmarks, which are skipped if there is none on the PATH.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use interledger_api::{AccountDetails, NodeStore};
+use interledger_packet::Address;
+use interledger_service::Account;
+use interledger_service_util::{BalanceStore, Escrow};
+use interledger_store::redis::RedisStoreBuilder;
+use redis_crate::{Client, ConnectionAddr, ConnectionInfo};
+use serde_json::json;
+use std::net::TcpListener;
+use std::process::{Child, Command, Stdio};
+use std::str::FromStr;
+use std::thread::sleep;
+use std::time::{Duration, SystemTime};
+
+/// A `redis-server` process which is killed when dropped
+struct RedisServer(Child);
+
+impl RedisServer {
+    /// Starts a server without persistence on a free port, or returns None if it cannot be started
+    fn start() -> Option<(RedisServer, ConnectionInfo)> {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| listener.local_addr())
+            .ok()?
+            .port();
+        let child = Command::new("redis-server")
+            .args(["--port", &port.to_string(), "--bind", "127.0.0.1"])
+            .args(["--save", "", "--appendonly", "no"])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .ok()?;
+        let server = RedisServer(child);
+        let info = ConnectionInfo {
+            addr: Box::new(ConnectionAddr::Tcp("127.0.0.1".to_string(), port)),
+            db: 0,
+            passwd: None,
+        };
+
+        let client = Client::open(info.clone()).ok()?;
+        for _ in 0..1000 {
+            if client.get_connection().is_ok() {
+                return Some((server, info));
+            }
+            sleep(Duration::from_millis(5));
+        }
+        None
+    }
+}
+
+impl Drop for RedisServer {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+fn account_details(username: &str) -> AccountDetails {
+    serde_json::from_value(json!({
+        "username": username,
+        "asset_code": "XYZ",
+        "asset_scale": 9,
+        "ilp_address": format!("example.{}", username),
+    }))
+    .unwrap()
+}
+
+fn benchmark_balances(c: &mut Criterion) {
+    let (_server, connection_info) = match RedisServer::start() {
+        Some(server) => server,
+        None => {
+            eprintln!("Skipping the balance benchmarks because redis-server could not be started");
+            return;
+        }
+    };
+    let mut runtime = tokio::runtime::Builder::new()
+        .basic_scheduler()
+        .enable_all()
+        .build()
+        .unwrap();
+    let (store, alice, bob) = runtime.block_on(async {
+        let store = RedisStoreBuilder::new(connection_info, [0; 32])
+            .node_ilp_address(Address::from_str("example.node").unwrap())
+            .connect()
+            .await
+            .unwrap();
+        let alice = store
+            .insert_account(account_details("alice"))
+            .await
+            .unwrap();
+        let bob = store.insert_account(account_details("bob")).await.unwrap();
+        (store, alice.id(), bob.id())
+    });
+    let escrow = || {
+        Escrow::new(
+            alice,
+            100,
+            bob,
+            100,
+            SystemTime::now() + Duration::from_secs(30),
+        )
+    };
+
+    c.bench_function("Balances (prepare and fulfill)", |b| {
+        b.iter(|| {
+            runtime.block_on(async {
+                let escrow = escrow();
+                store.update_balances_for_prepare(&escrow).await.unwrap();
+                store.update_balances_for_fulfill(&escrow).await.unwrap();
+            })
+        })
+    });
+
+    c.bench_function("Balances (prepare and reject)", |b| {
+        b.iter(|| {
+            runtime.block_on(async {
+                let escrow = escrow();
+                store.update_balances_for_prepare(&escrow).await.unwrap();
+                store.update_balances_for_reject(&escrow).await.unwrap();
+            })
+        })
+    });
+}
+
+criterion_group!(benches, benchmark_balances);
+criterion_main!(benches);
@@ -1,16 +1,22 @@
-use super::crypto::{decrypt_token, encrypt_token};
+#[cfg(feature = "redis")]
+use super::crypto::verify_token;
+use super::crypto::{decrypt_token, encrypt_token, hash_token, is_hashed_token};
 use interledger_api::AccountDetails;
 use interledger_btp::BtpAccount;
 use interledger_ccp::{CcpRoutingAccount, RoutingRelation};
 use interledger_errors::CreateAccountError;
-use interledger_http::HttpAccount;
+use interledger_http::{parse_signing_key, parse_verification_key, HttpAccount};
 use interledger_packet::Address;
-use interledger_service::{Account as AccountTrait, Username};
+use interledger_service::{
+    Account as AccountTrait, HttpDialect, IpAllowlistAccount, IpNetwork, PacketFilter,
+    PacketFilterAccount, Username,
+};
 use interledger_service_util::{
-    MaxPacketAmountAccount, RateLimitAccount, RoundTripTimeAccount, DEFAULT_ROUND_TRIP_TIME,
+    FeeAccount, FeePolicy, MaxPacketAmountAccount, MaxPacketSizeAccount, RateLimitAccount,
+    RoundTripTimeAccount, DEFAULT_ROUND_TRIP_TIME,
 };
 use interledger_settlement::core::types::{SettlementAccount, SettlementEngineDetails};
-use ring::aead;
+use ring::{aead, hmac};
 use secrecy::{ExposeSecret, SecretBytesMut, SecretString};
 use serde::Serializer;
 use serde::{Deserialize, Serialize};
@@ -38,8 +44,14 @@ pub struct Account {
     pub(crate) asset_scale: u8,
     /// The max amount per packet which can be routed for this account
     pub(crate) max_packet_amount: u64,
+    /// The max size, in bytes, of the packets sent to or by this account
+    pub(crate) max_packet_size: Option<u64>,
     /// The minimum balance this account can have (consider this as a credit/trust limit)
     pub(crate) min_balance: Option<i64>,
+    /// The max amount the node may owe this account
+    pub(crate) payable_limit: Option<u64>,
+    /// The max amount this account may owe the node (takes precedence over the min balance)
+    pub(crate) receivable_limit: Option<u64>,
     /// The account's ILP over HTTP URL (this is where packets are sent over HTTP from your node)
     pub(crate) ilp_over_http_url: Option<Url>,
     #[serde(serialize_with = "optional_secret_bytes_to_utf8")]
@@ -54,6 +66,13 @@ pub struct Account {
     /// This must match the ILP over HTTP incoming token on the peer's node if sending
     /// packets to that peer
     pub(crate) ilp_over_http_outgoing_token: Option<SecretBytesMut>,
+    /// The dialect of ILP over HTTP spoken by the account's peer
+    pub(crate) ilp_over_http_dialect: HttpDialect,
+    #[serde(serialize_with = "optional_secret_bytes_to_utf8")]
+    /// The Ed25519 key with which the ILP over HTTP requests sent to the peer are signed
+    pub(crate) ilp_over_http_signing_key: Option<SecretBytesMut>,
+    /// The peer's Ed25519 public key, with which the signatures of its requests are verified
+    pub(crate) ilp_over_http_verification_key: Option<String>,
     /// The account's ILP over BTP URL (this is where packets are sent over WebSockets from your node)
     pub(crate) ilp_over_btp_url: Option<Url>,
     #[serde(serialize_with = "optional_secret_bytes_to_utf8")]
@@ -66,6 +85,10 @@ pub struct Account {
     /// This must match the ILP over BTP incoming token on the peer's node if exchanging
     /// packets with that peer
     pub(crate) ilp_over_btp_outgoing_token: Option<SecretBytesMut>,
+    /// Whether the peer speaks the BTP dialect of the legacy JavaScript plugins
+    pub(crate) ilp_over_btp_legacy: bool,
+    /// The account of the same peer whose BTP connection this account shares
+    pub(crate) transport_account: Option<Username>,
     /// The threshold after which the balance service will trigger a settlement
     pub(crate) settle_threshold: Option<i64>,
     /// The amount which the balance service will attempt to settle down to
@@ -75,6 +98,15 @@ pub struct Account {
     /// The round trip time of the account (should be set depending on how
     /// well the network connectivity of the account and the node is)
     pub(crate) round_trip_time: u32,
+    /// Interval, in milliseconds, on which routes are broadcast to the account, if it should
+    /// be longer than the node's
+    pub(crate) route_broadcast_interval: Option<u64>,
+    /// How long, in milliseconds, the account should keep the routes broadcast to it
+    pub(crate) route_expiry: Option<u32>,
+    /// Whether each route broadcast to the account carries the whole routing table
+    pub(crate) route_full_table: bool,
+    /// The limit of packets the account can send per second
+    pub(crate) packets_per_second_limit: Option<u32>,
     /// The limit of packets the account can send per minute
     pub(crate) packets_per_minute_limit: Option<u32>,
     /// The maximum amount the account can send per minute
@@ -83,6 +115,20 @@ pub struct Account {
     /// for the account's asset code,  that will be used instead (even if the account is
     /// configured with a specific one)
     pub(crate) settlement_engine_url: Option<Url>,
+    /// URL to which the account's peer is notified of the packets blocked by its receivable limit
+    pub(crate) liquidity_notification_url: Option<Url>,
+    /// URL to which the account's balance notifications are POSTed
+    pub(crate) balance_notification_url: Option<Url>,
+    /// Spread, as a fraction, subtracted from the exchange rate of the packets sent by the account
+    pub(crate) spread: Option<f64>,
+    /// Fee, as a fraction of the converted amount, charged on the packets sent by the account
+    pub(crate) percentage_fee: Option<f64>,
+    /// Fee, in the outgoing account's units, charged on each packet sent by the account
+    pub(crate) fixed_fee: Option<u64>,
+    /// Filters applied, in order, to the packets sent by the account before they are forwarded
+    pub(crate) packet_filters: Vec<PacketFilter>,
+    /// Networks the account's incoming ILP-over-HTTP requests and BTP connections must come from
+    pub(crate) ip_allowlist: Vec<IpNetwork>,
 }
 
 fn address_to_string<S>(address: &Address, serializer: S) -> Result<S::Ok, S::Error>
@@ -131,12 +177,54 @@ impl Account {
             None
         };
 
+        if let Some(ref key) = details.ilp_over_http_signing_key {
+            parse_signing_key(key.expose_secret())
+                .map_err(|err| CreateAccountError::InvalidHttpSignatureKey(err.to_string()))?;
+        }
+        if let Some(ref key) = details.ilp_over_http_verification_key {
+            parse_verification_key(key)
+                .map_err(|err| CreateAccountError::InvalidHttpSignatureKey(err.to_string()))?;
+        }
+
+        // The rate limits are the capacities of token buckets, which cannot be empty
+        if details.packets_per_second_limit == Some(0) {
+            return Err(CreateAccountError::InvalidRateLimit(
+                "packets_per_second_limit".to_owned(),
+            ));
+        }
+        if details.packets_per_minute_limit == Some(0) {
+            return Err(CreateAccountError::InvalidRateLimit(
+                "packets_per_minute_limit".to_owned(),
+            ));
+        }
+        if details.amount_per_minute_limit == Some(0) {
+            return Err(CreateAccountError::InvalidRateLimit(
+                "amount_per_minute_limit".to_owned(),
+            ));
+        }
+
+        if details.transport_account.as_ref() == Some(&details.username) {
+            return Err(CreateAccountError::InvalidTransportAccount(
+                details.username.to_string(),
+            ));
+        }
+
         let routing_relation = if let Some(ref relation) = details.routing_relation {
             RoutingRelation::from_str(relation)
                 .map_err(|_| CreateAccountError::InvalidRoutingRelation(relation.to_string()))?
         } else {
             RoutingRelation::NonRoutingAccount
         };
+        let liquidity_notification_url = if let Some(ref url) = details.liquidity_notification_url {
+            Some(Url::parse(url).map_err(CreateAccountError::InvalidHttpUrl)?)
+        } else {
+            None
+        };
+        let balance_notification_url = if let Some(ref url) = details.balance_notification_url {
+            Some(Url::parse(url).map_err(CreateAccountError::InvalidHttpUrl)?)
+        } else {
+            None
+        };
         let settlement_engine_url =
             if let Some(settlement_engine_url) = details.settlement_engine_url {
                 Url::parse(&settlement_engine_url).ok()
@@ -151,7 +239,10 @@ impl Account {
             asset_code: details.asset_code.to_uppercase(),
             asset_scale: details.asset_scale,
             max_packet_amount: details.max_packet_amount,
+            max_packet_size: details.max_packet_size,
             min_balance: details.min_balance,
+            payable_limit: details.payable_limit,
+            receivable_limit: details.receivable_limit,
             ilp_over_http_url,
             ilp_over_http_incoming_token: details
                 .ilp_over_http_incoming_token
@@ -159,6 +250,11 @@ impl Account {
             ilp_over_http_outgoing_token: details
                 .ilp_over_http_outgoing_token
                 .map(|token| SecretBytesMut::new(token.expose_secret().as_str())),
+            ilp_over_http_dialect: details.ilp_over_http_dialect,
+            ilp_over_http_signing_key: details
+                .ilp_over_http_signing_key
+                .map(|key| SecretBytesMut::new(key.expose_secret().as_str())),
+            ilp_over_http_verification_key: details.ilp_over_http_verification_key,
             ilp_over_btp_url,
             ilp_over_btp_incoming_token: details
                 .ilp_over_btp_incoming_token
@@ -166,20 +262,36 @@ impl Account {
             ilp_over_btp_outgoing_token: details
                 .ilp_over_btp_outgoing_token
                 .map(|token| SecretBytesMut::new(token.expose_secret().as_str())),
+            ilp_over_btp_legacy: details.ilp_over_btp_legacy,
+            transport_account: details.transport_account,
             settle_to: details.settle_to,
             settle_threshold: details.settle_threshold,
             routing_relation,
             round_trip_time: details.round_trip_time.unwrap_or(DEFAULT_ROUND_TRIP_TIME),
+            route_broadcast_interval: details.route_broadcast_interval,
+            route_expiry: details.route_expiry,
+            route_full_table: details.route_full_table,
+            packets_per_second_limit: details.packets_per_second_limit,
             packets_per_minute_limit: details.packets_per_minute_limit,
             amount_per_minute_limit: details.amount_per_minute_limit,
             settlement_engine_url,
+            liquidity_notification_url,
+            balance_notification_url,
+            spread: details.spread,
+            percentage_fee: details.percentage_fee,
+            fixed_fee: details.fixed_fee,
+            packet_filters: details.packet_filters,
+            ip_allowlist: details.ip_allowlist,
         })
     }
 
-    /// Encrypts the account's incoming/outgoing BTP and HTTP keys with the provided encryption key
+    /// Encrypts the account's outgoing BTP and HTTP keys with the provided encryption key,
+    /// and hashes its incoming ones with the provided token hashing key (the node only
+    /// needs to verify them, so they are never stored in a recoverable form)
     pub fn encrypt_tokens(
         mut self,
         encryption_key: &aead::LessSafeKey,
+        token_hashing_key: &hmac::Key,
     ) -> AccountWithEncryptedTokens {
         if let Some(ref token) = self.ilp_over_btp_outgoing_token {
             self.ilp_over_btp_outgoing_token = Some(SecretBytesMut::from(encrypt_token(
@@ -193,20 +305,50 @@ impl Account {
                 &token.expose_secret(),
             )));
         }
-        if let Some(ref token) = self.ilp_over_btp_incoming_token {
-            self.ilp_over_btp_incoming_token = Some(SecretBytesMut::from(encrypt_token(
+        if let Some(ref key) = self.ilp_over_http_signing_key {
+            self.ilp_over_http_signing_key = Some(SecretBytesMut::from(encrypt_token(
                 encryption_key,
-                &token.expose_secret(),
+                key.expose_secret(),
             )));
         }
+        // Incoming tokens which are hashed already (such as the one of a peering proposal,
+        // whose account is created once the peer approves it) are kept as they are
+        if let Some(ref token) = self.ilp_over_btp_incoming_token {
+            if !is_hashed_token(token.expose_secret()) {
+                self.ilp_over_btp_incoming_token = Some(SecretBytesMut::from(hash_token(
+                    token_hashing_key,
+                    token.expose_secret(),
+                )));
+            }
+        }
         if let Some(ref token) = self.ilp_over_http_incoming_token {
-            self.ilp_over_http_incoming_token = Some(SecretBytesMut::from(encrypt_token(
-                encryption_key,
-                &token.expose_secret(),
-            )));
+            if !is_hashed_token(token.expose_secret()) {
+                self.ilp_over_http_incoming_token = Some(SecretBytesMut::from(hash_token(
+                    token_hashing_key,
+                    token.expose_secret(),
+                )));
+            }
         }
         AccountWithEncryptedTokens { account: self }
     }
+
+    /// Checks the token against the account's incoming ILP over HTTP token in constant time
+    #[cfg(feature = "redis")]
+    pub(crate) fn verify_http_incoming_token(&self, hashing_key: &hmac::Key, token: &str) -> bool {
+        self.ilp_over_http_incoming_token
+            .as_ref()
+            .map(|stored| verify_token(hashing_key, stored.expose_secret(), token.as_bytes()))
+            .unwrap_or(false)
+    }
+
+    /// Checks the token against the account's incoming ILP over BTP token in constant time
+    #[cfg(feature = "redis")]
+    pub(crate) fn verify_btp_incoming_token(&self, hashing_key: &hmac::Key, token: &str) -> bool {
+        self.ilp_over_btp_incoming_token
+            .as_ref()
+            .map(|stored| verify_token(hashing_key, stored.expose_secret(), token.as_bytes()))
+            .unwrap_or(false)
+    }
 }
 
 /// A wrapper over the [`Account`](./struct.Account.html) which contains their encrypt tokens.
@@ -216,7 +358,9 @@ pub struct AccountWithEncryptedTokens {
 }
 
 impl AccountWithEncryptedTokens {
-    /// Decrypts the account's incoming/outgoing BTP and HTTP keys with the provided decryption key
+    /// Decrypts the account's outgoing BTP and HTTP keys with the provided decryption key.
+    /// The incoming ones remain hashed, unless they were stored encrypted before the
+    /// incoming tokens were hashed, in which case they are decrypted too.
     pub fn decrypt_tokens(mut self, decryption_key: &aead::LessSafeKey) -> Account {
         if let Some(ref encrypted) = self.account.ilp_over_btp_outgoing_token {
             self.account.ilp_over_btp_outgoing_token =
@@ -240,7 +384,23 @@ impl AccountWithEncryptedTokens {
                     })
                     .ok();
         }
-        if let Some(ref encrypted) = self.account.ilp_over_btp_incoming_token {
+        if let Some(ref encrypted) = self.account.ilp_over_http_signing_key {
+            self.account.ilp_over_http_signing_key =
+                decrypt_token(decryption_key, encrypted.expose_secret())
+                    .map_err(|_| {
+                        error!(
+                            "Unable to decrypt ilp_over_http_signing_key for account {}",
+                            self.account.id
+                        )
+                    })
+                    .ok();
+        }
+        if let Some(encrypted) = self
+            .account
+            .ilp_over_btp_incoming_token
+            .as_ref()
+            .filter(|token| !is_hashed_token(token.expose_secret()))
+        {
             self.account.ilp_over_btp_incoming_token =
                 decrypt_token(decryption_key, &encrypted.expose_secret())
                     .map_err(|_| {
@@ -251,7 +411,12 @@ impl AccountWithEncryptedTokens {
                     })
                     .ok();
         }
-        if let Some(ref encrypted) = self.account.ilp_over_http_incoming_token {
+        if let Some(encrypted) = self
+            .account
+            .ilp_over_http_incoming_token
+            .as_ref()
+            .filter(|token| !is_hashed_token(token.expose_secret()))
+        {
             self.account.ilp_over_http_incoming_token =
                 decrypt_token(decryption_key, &encrypted.expose_secret())
                     .map_err(|_| {
@@ -305,6 +470,24 @@ impl HttpAccount for Account {
             )
         })
     }
+
+    fn get_http_dialect(&self) -> HttpDialect {
+        self.ilp_over_http_dialect
+    }
+
+    fn get_http_signing_key(&self) -> Option<SecretString> {
+        self.ilp_over_http_signing_key.as_ref().map(|s| {
+            SecretString::new(
+                str::from_utf8(s.expose_secret().as_ref())
+                    .unwrap_or_default()
+                    .to_string(),
+            )
+        })
+    }
+
+    fn get_http_verification_key(&self) -> Option<&str> {
+        self.ilp_over_http_verification_key.as_deref()
+    }
 }
 
 impl BtpAccount for Account {
@@ -319,6 +502,14 @@ impl BtpAccount for Account {
             None
         }
     }
+
+    fn is_legacy_btp_peer(&self) -> bool {
+        self.ilp_over_btp_legacy
+    }
+
+    fn get_transport_account(&self) -> Option<&Username> {
+        self.transport_account.as_ref()
+    }
 }
 
 impl MaxPacketAmountAccount for Account {
@@ -327,10 +518,28 @@ impl MaxPacketAmountAccount for Account {
     }
 }
 
+impl MaxPacketSizeAccount for Account {
+    fn max_packet_size(&self) -> Option<u64> {
+        self.max_packet_size
+    }
+}
+
 impl CcpRoutingAccount for Account {
     fn routing_relation(&self) -> RoutingRelation {
         self.routing_relation
     }
+
+    fn route_broadcast_interval(&self) -> Option<u64> {
+        self.route_broadcast_interval
+    }
+
+    fn route_expiry(&self) -> Option<u32> {
+        self.route_expiry
+    }
+
+    fn should_receive_full_table(&self) -> bool {
+        self.route_full_table
+    }
 }
 
 impl RoundTripTimeAccount for Account {
@@ -344,11 +553,37 @@ impl RateLimitAccount for Account {
         self.amount_per_minute_limit
     }
 
+    fn packets_per_second_limit(&self) -> Option<u32> {
+        self.packets_per_second_limit
+    }
+
     fn packets_per_minute_limit(&self) -> Option<u32> {
         self.packets_per_minute_limit
     }
 }
 
+impl FeeAccount for Account {
+    fn fee_policy(&self) -> FeePolicy {
+        FeePolicy {
+            spread: self.spread,
+            percentage_fee: self.percentage_fee,
+            fixed_fee: self.fixed_fee,
+        }
+    }
+}
+
+impl PacketFilterAccount for Account {
+    fn packet_filters(&self) -> &[PacketFilter] {
+        &self.packet_filters
+    }
+}
+
+impl IpAllowlistAccount for Account {
+    fn ip_allowlist(&self) -> &[IpNetwork] {
+        &self.ip_allowlist
+    }
+}
+
 impl SettlementAccount for Account {
     fn settlement_engine_details(&self) -> Option<SettlementEngineDetails> {
         match &self.settlement_engine_url {
@@ -356,6 +591,14 @@ impl SettlementAccount for Account {
             _ => None,
         }
     }
+
+    fn liquidity_notification_url(&self) -> Option<Url> {
+        self.liquidity_notification_url.clone()
+    }
+
+    fn balance_notification_url(&self) -> Option<Url> {
+        self.balance_notification_url.clone()
+    }
 }
 
 #[cfg(test)]
@@ -370,21 +613,40 @@ mod test {
         asset_scale: 6,
         asset_code: "XYZ".to_string(),
         max_packet_amount: 1000,
+        max_packet_size: None,
         min_balance: Some(-1000),
+        payable_limit: None,
+        receivable_limit: None,
         // we are Bob and we're using this account to peer with Alice
         ilp_over_http_url: Some("http://example.com/accounts/bob/ilp".to_string()),
         ilp_over_http_incoming_token: Some(SecretString::new("incoming_auth_token".to_string())),
         ilp_over_http_outgoing_token: Some(SecretString::new("outgoing_auth_token".to_string())),
+        ilp_over_http_dialect: HttpDialect::Rfc,
+        ilp_over_http_signing_key: None,
+        ilp_over_http_verification_key: None,
         ilp_over_btp_url: Some("btp+ws://example.com/accounts/bob/ilp/btp".to_string()),
         ilp_over_btp_incoming_token: Some(SecretString::new("incoming_btp_token".to_string())),
         ilp_over_btp_outgoing_token: Some(SecretString::new("outgoing_btp_token".to_string())),
+        ilp_over_btp_legacy: false,
+        transport_account: None,
         settle_threshold: Some(0),
         settle_to: Some(-1000),
         routing_relation: Some("Peer".to_string()),
         round_trip_time: Some(600),
+        route_broadcast_interval: None,
+        route_expiry: None,
+        route_full_table: false,
         amount_per_minute_limit: None,
+        packets_per_second_limit: None,
         packets_per_minute_limit: None,
         settlement_engine_url: None,
+        liquidity_notification_url: None,
+        balance_notification_url: None,
+        spread: None,
+        percentage_fee: None,
+        fixed_fee: None,
+        packet_filters: Vec::new(),
+        ip_allowlist: Vec::new(),
     });
 
     #[test]
@@ -416,4 +678,20 @@ mod test {
         );
         assert_eq!(account.routing_relation(), RoutingRelation::Peer);
     }
+
+    #[test]
+    fn rejects_zero_rate_limits() {
+        let mut details = ACCOUNT_DETAILS.clone();
+        details.packets_per_minute_limit = Some(0);
+        let err = Account::try_from(
+            Uuid::new_v4(),
+            details,
+            Address::from_str("example.account").unwrap(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "the provided value for rate limit `packets_per_minute_limit` must be greater than zero"
+        );
+    }
 }
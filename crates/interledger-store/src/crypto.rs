@@ -6,6 +6,10 @@ use ring::{
 
 const NONCE_LENGTH: usize = 12;
 static ENCRYPTION_KEY_GENERATION_STRING: &[u8] = b"ilp_store_redis_encryption_key";
+static TOKEN_HASHING_KEY_GENERATION_STRING: &[u8] = b"ilp_store_redis_token_hashing_key";
+/// Prefix of the hashed tokens, which tells them apart from the encrypted ones
+static TOKEN_HASH_PREFIX: &[u8] = b"hmac-sha256$";
+const SALT_LENGTH: usize = 16;
 
 use core::sync::atomic;
 use secrecy::{DebugSecret, Secret, SecretBytesMut};
@@ -21,9 +25,13 @@ pub struct DecryptionKey(pub(crate) aead::LessSafeKey);
 #[derive(Debug)]
 pub struct GenerationKey(pub(crate) hmac::Key);
 
+#[derive(Debug)]
+pub struct TokenHashingKey(pub(crate) hmac::Key);
+
 impl DebugSecret for EncryptionKey {}
 impl DebugSecret for DecryptionKey {}
 impl DebugSecret for GenerationKey {}
+impl DebugSecret for TokenHashingKey {}
 
 impl Zeroize for EncryptionKey {
     fn zeroize(&mut self) {
@@ -77,6 +85,22 @@ impl Drop for GenerationKey {
     }
 }
 
+impl Zeroize for TokenHashingKey {
+    fn zeroize(&mut self) {
+        // Instead of clearing the memory, we overwrite the key with a
+        // slice filled with zeros
+        let empty_key = TokenHashingKey(hmac::Key::new(hmac::HMAC_SHA256, &[0; 32]));
+        volatile_write(self, empty_key);
+        atomic_fence();
+    }
+}
+
+impl Drop for TokenHashingKey {
+    fn drop(&mut self) {
+        self.zeroize()
+    }
+}
+
 // this logic is taken from [here](https://github.com/iqlusioninc/crates/blob/develop/zeroize/src/lib.rs#L388-L400)
 // Perform a [volatile
 // write](https://doc.rust-lang.org/beta/std/ptr/fn.write_volatile.html) to the
@@ -117,6 +141,90 @@ pub fn generate_keys(server_secret: &[u8]) -> (Secret<EncryptionKey>, Secret<Dec
     (encryption_key, decryption_key)
 }
 
+/// Generates the key the incoming tokens are hashed with. It is derived from the server
+/// secret (rather than the data encryption key), so that the hashes stay valid when the
+/// data key is rotated, and the nodes sharing the store compute the same hashes.
+pub fn generate_token_hashing_key(server_secret: &[u8]) -> Secret<TokenHashingKey> {
+    let generation_key = GenerationKey(hmac::Key::new(hmac::HMAC_SHA256, server_secret));
+    Secret::new(TokenHashingKey(hmac::Key::new(
+        hmac::HMAC_SHA256,
+        hmac::sign(&generation_key.0, TOKEN_HASHING_KEY_GENERATION_STRING).as_ref(),
+    )))
+}
+
+/// Hashes an incoming token with a random salt, so that the store never holds the
+/// tokens the peers authenticate with. The hash is `hmac-sha256$<salt>$<mac>`
+/// (hex encoded), where the MAC is keyed with the token hashing key.
+pub fn hash_token(hashing_key: &hmac::Key, token: &[u8]) -> BytesMut {
+    let mut salt: [u8; SALT_LENGTH] = [0; SALT_LENGTH];
+    SystemRandom::new()
+        .fill(&mut salt)
+        .expect("Unable to get sufficient entropy for salt");
+    let tag = hmac::sign(hashing_key, &salted(&salt, token));
+
+    let mut hashed = BytesMut::from(TOKEN_HASH_PREFIX);
+    hashed.extend_from_slice(hex::encode(salt).as_bytes());
+    hashed.extend_from_slice(b"$");
+    hashed.extend_from_slice(hex::encode(tag.as_ref()).as_bytes());
+    hashed
+}
+
+/// Returns whether the stored token is hashed (rather than encrypted or in plaintext,
+/// as the tokens stored before they were hashed)
+pub fn is_hashed_token(stored: &[u8]) -> bool {
+    stored.starts_with(TOKEN_HASH_PREFIX)
+}
+
+/// Checks the token against the stored one in constant time. Tokens stored before they
+/// were hashed are compared directly, until they are migrated.
+pub fn verify_token(hashing_key: &hmac::Key, stored: &[u8], token: &[u8]) -> bool {
+    if !is_hashed_token(stored) {
+        return ring::constant_time::verify_slices_are_equal(stored, token).is_ok();
+    }
+    let mut parts = stored[TOKEN_HASH_PREFIX.len()..].splitn(2, |byte| *byte == b'$');
+    let (salt, tag) = match (parts.next(), parts.next()) {
+        (Some(salt), Some(tag)) => match (hex::decode(salt), hex::decode(tag)) {
+            (Ok(salt), Ok(tag)) => (salt, tag),
+            _ => return false,
+        },
+        _ => return false,
+    };
+    // hmac::verify compares the tags in constant time
+    hmac::verify(hashing_key, &salted(&salt, token), &tag).is_ok()
+}
+
+fn salted(salt: &[u8], token: &[u8]) -> Vec<u8> {
+    let mut message = Vec::with_capacity(salt.len() + token.len());
+    message.extend_from_slice(salt);
+    message.extend_from_slice(token);
+    message
+}
+
+/// Generates a random 256-bit data encryption key. Account secrets are encrypted with
+/// it directly, while the key itself is stored wrapped by a [`KeyManager`](../kms/trait.KeyManager.html)
+pub fn generate_data_key() -> SecretBytesMut {
+    let mut data_key: [u8; 32] = [0; 32];
+    SystemRandom::new()
+        .fill(&mut data_key)
+        .expect("Unable to get sufficient entropy for data key");
+    let secret = SecretBytesMut::new(&data_key[..]);
+    data_key.zeroize();
+    secret
+}
+
+/// Creates the encryption and decryption keys from a (unwrapped) data encryption key
+#[allow(clippy::result_unit_err)]
+pub fn keys_from_data_key(
+    data_key: &[u8],
+) -> Result<(Secret<EncryptionKey>, Secret<DecryptionKey>), ()> {
+    let encryption_key = aead::UnboundKey::new(&aead::AES_256_GCM, data_key).map_err(|_| ())?;
+    let decryption_key = aead::UnboundKey::new(&aead::AES_256_GCM, data_key).map_err(|_| ())?;
+    Ok((
+        Secret::new(EncryptionKey(aead::LessSafeKey::new(encryption_key))),
+        Secret::new(DecryptionKey(aead::LessSafeKey::new(decryption_key))),
+    ))
+}
+
 pub fn encrypt_token(encryption_key: &aead::LessSafeKey, token: &[u8]) -> BytesMut {
     let mut token = token.to_vec();
 
@@ -172,4 +280,51 @@ mod encryption {
             "test test"
         );
     }
+
+    #[test]
+    fn encrypts_and_decrypts_with_data_key() {
+        let data_key = generate_data_key();
+        let (encryption_key, decryption_key) =
+            keys_from_data_key(data_key.expose_secret().as_ref()).unwrap();
+        let encrypted = encrypt_token(&encryption_key.expose_secret().0, b"test test");
+        let decrypted = decrypt_token(&decryption_key.expose_secret().0, encrypted.as_ref());
+        assert_eq!(
+            str::from_utf8(decrypted.unwrap().expose_secret().as_ref()).unwrap(),
+            "test test"
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_data_key() {
+        assert!(keys_from_data_key(&[0; 16]).is_err());
+    }
+
+    #[test]
+    fn hashes_and_verifies_tokens() {
+        let hashing_key = generate_token_hashing_key(&[9; 32]);
+        let key = &hashing_key.expose_secret().0;
+        let hashed = hash_token(key, b"test test");
+        assert!(is_hashed_token(&hashed));
+        assert!(!hashed.windows(9).any(|window| window == b"test test"));
+        assert!(verify_token(key, &hashed, b"test test"));
+        assert!(!verify_token(key, &hashed, b"test tesu"));
+        // The salt makes every hash of a token different
+        assert_ne!(hashed, hash_token(key, b"test test"));
+
+        let other_key = generate_token_hashing_key(&[8; 32]);
+        assert!(!verify_token(
+            &other_key.expose_secret().0,
+            &hashed,
+            b"test test"
+        ));
+    }
+
+    #[test]
+    fn verifies_tokens_stored_before_hashing() {
+        let hashing_key = generate_token_hashing_key(&[9; 32]);
+        let key = &hashing_key.expose_secret().0;
+        assert!(!is_hashed_token(b"test test"));
+        assert!(verify_token(key, b"test test", b"test test"));
+        assert!(!verify_token(key, b"test test", b"test"));
+    }
 }
@@ -0,0 +1,115 @@
+use metrics::{labels, recorder, Key};
+use std::future::Future;
+use std::time::Instant;
+
+/// Runs a store operation, recording how long it took in the `store.operation.duration`
+/// histogram (in nanoseconds) and counting failed operations in `store.operation.error`.
+/// Both are labelled with the backend and the name of the operation.
+pub(crate) async fn instrument<F, T, E>(
+    backend: &'static str,
+    operation: &'static str,
+    future: F,
+) -> Result<T, E>
+where
+    F: Future<Output = Result<T, E>>,
+{
+    let start_time = Instant::now();
+    let result = future.await;
+    let labels = labels!("backend" => backend, "operation" => operation);
+
+    if result.is_err() {
+        recorder().increment_counter(
+            Key::from_name_and_labels("store.operation.error", labels.clone()),
+            1,
+        );
+    }
+    recorder().record_histogram(
+        Key::from_name_and_labels("store.operation.duration", labels),
+        (Instant::now() - start_time).as_nanos() as u64,
+    );
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use metrics::Recorder;
+    use once_cell::sync::Lazy;
+    use std::sync::{Mutex, Once};
+
+    /// The name and the labels of a recorded metric
+    type Recorded = (String, Vec<(String, String)>);
+
+    /// The metrics recorded by all the tests
+    static RECORDED: Lazy<Mutex<Vec<Recorded>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+    struct TestRecorder;
+
+    impl TestRecorder {
+        fn record(&self, key: Key) {
+            let (name, labels) = key.into_parts();
+            let labels = labels
+                .into_iter()
+                .map(|label| (label.key().to_owned(), label.value().to_owned()))
+                .collect();
+            RECORDED.lock().unwrap().push((name.to_string(), labels));
+        }
+    }
+
+    impl Recorder for TestRecorder {
+        fn increment_counter(&self, key: Key, _: u64) {
+            self.record(key)
+        }
+
+        fn update_gauge(&self, key: Key, _: i64) {
+            self.record(key)
+        }
+
+        fn record_histogram(&self, key: Key, _: u64) {
+            self.record(key)
+        }
+    }
+
+    static RECORDER: TestRecorder = TestRecorder;
+    static INSTALL_RECORDER: Once = Once::new();
+
+    /// Returns the names of the metrics recorded for the operation (the tests use
+    /// different operations, since they share the recorder)
+    fn recorded_for(operation: &str) -> Vec<String> {
+        RECORDED
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, labels)| {
+                labels.contains(&("backend".to_owned(), "test".to_owned()))
+                    && labels.contains(&("operation".to_owned(), operation.to_owned()))
+            })
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn records_the_duration_of_successful_operations() {
+        INSTALL_RECORDER.call_once(|| metrics::set_recorder(&RECORDER).unwrap());
+        let result = instrument("test", "get_thing", async { Ok::<_, ()>(1) }).await;
+        assert_eq!(result, Ok(1));
+        assert_eq!(
+            recorded_for("get_thing"),
+            vec!["store.operation.duration".to_string()]
+        );
+    }
+
+    #[tokio::test]
+    async fn counts_the_failed_operations() {
+        INSTALL_RECORDER.call_once(|| metrics::set_recorder(&RECORDER).unwrap());
+        let result = instrument("test", "update_thing", async { Err::<(), _>("failed") }).await;
+        assert_eq!(result, Err("failed"));
+        assert_eq!(
+            recorded_for("update_thing"),
+            vec![
+                "store.operation.error".to_string(),
+                "store.operation.duration".to_string()
+            ]
+        );
+    }
+}
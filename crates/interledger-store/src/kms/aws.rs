@@ -0,0 +1,312 @@
+use super::{KeyManagementError, KeyManager};
+use async_trait::async_trait;
+use chrono::Utc;
+use reqwest::Client;
+use ring::{digest, hmac};
+use secrecy::{ExposeSecret, SecretBytesMut, SecretString};
+use serde::{Deserialize, Serialize};
+use url::Url;
+use zeroize::Zeroize;
+
+/// Content type of the KMS JSON API
+const CONTENT_TYPE: &str = "application/x-amz-json-1.1";
+
+/// Key manager which wraps the data key with a customer master key in
+/// [AWS KMS](https://docs.aws.amazon.com/kms/latest/APIReference/Welcome.html).
+///
+/// Requests are signed with [Signature Version 4](https://docs.aws.amazon.com/general/latest/gr/signature-version-4.html).
+pub struct AwsKmsKeyManager {
+    client: Client,
+    endpoint: Url,
+    host: String,
+    key_id: String,
+    region: String,
+    access_key_id: String,
+    secret_access_key: SecretString,
+    session_token: Option<SecretString>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct EncryptRequest<'a> {
+    key_id: &'a str,
+    plaintext: &'a str,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct EncryptResponse {
+    ciphertext_blob: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct DecryptRequest<'a> {
+    key_id: &'a str,
+    ciphertext_blob: &'a str,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct DecryptResponse {
+    plaintext: String,
+}
+
+impl AwsKmsKeyManager {
+    pub fn new(
+        key_id: String,
+        region: String,
+        access_key_id: String,
+        secret_access_key: SecretString,
+        session_token: Option<SecretString>,
+        endpoint: Option<Url>,
+    ) -> Result<Self, KeyManagementError> {
+        let endpoint = match endpoint {
+            Some(endpoint) => endpoint,
+            None => Url::parse(&format!("https://kms.{}.amazonaws.com/", region))
+                .map_err(|err| KeyManagementError::InvalidKey(err.to_string()))?,
+        };
+        let host = match (endpoint.host_str(), endpoint.port()) {
+            (Some(host), Some(port)) => format!("{}:{}", host, port),
+            (Some(host), None) => host.to_string(),
+            (None, _) => {
+                return Err(KeyManagementError::InvalidKey(format!(
+                    "invalid AWS KMS endpoint: {}",
+                    endpoint
+                )))
+            }
+        };
+        Ok(AwsKmsKeyManager {
+            client: Client::new(),
+            endpoint,
+            host,
+            key_id,
+            region,
+            access_key_id,
+            secret_access_key,
+            session_token,
+        })
+    }
+
+    /// Calls the provided KMS action (for example `Encrypt`), signing the request
+    async fn call<B: Serialize, T: for<'de> Deserialize<'de>>(
+        &self,
+        action: &str,
+        body: &B,
+    ) -> Result<T, KeyManagementError> {
+        let body =
+            serde_json::to_vec(body).map_err(|err| KeyManagementError::Service(err.to_string()))?;
+        let target = format!("TrentService.{}", action);
+        let now = Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+
+        let mut request = self
+            .client
+            .post(self.endpoint.clone())
+            .header("Content-Type", CONTENT_TYPE)
+            .header("X-Amz-Date", amz_date.as_str())
+            .header("X-Amz-Target", target.as_str());
+        if let Some(ref session_token) = self.session_token {
+            request = request.header(
+                "X-Amz-Security-Token",
+                session_token.expose_secret().as_str(),
+            );
+        }
+        let authorization = self.authorization(&amz_date, &date, &target, &body);
+        let response = request
+            .header("Authorization", authorization)
+            .body(body)
+            .send()
+            .await
+            .map_err(|err| KeyManagementError::Service(err.to_string()))?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let text = response.text().await.unwrap_or_default();
+            return Err(KeyManagementError::Service(format!(
+                "AWS KMS responded to {} with {}: {}",
+                action, status, text
+            )));
+        }
+        response
+            .json()
+            .await
+            .map_err(|err| KeyManagementError::Service(err.to_string()))
+    }
+
+    /// Creates the Signature Version 4 `Authorization` header of a request
+    fn authorization(&self, amz_date: &str, date: &str, target: &str, body: &[u8]) -> String {
+        // The signed headers must be sorted by their (lowercase) names
+        let mut canonical_headers = format!(
+            "content-type:{}\nhost:{}\nx-amz-date:{}\n",
+            CONTENT_TYPE, self.host, amz_date
+        );
+        let mut signed_headers = "content-type;host;x-amz-date".to_string();
+        if let Some(ref session_token) = self.session_token {
+            canonical_headers.push_str(&format!(
+                "x-amz-security-token:{}\n",
+                session_token.expose_secret()
+            ));
+            signed_headers.push_str(";x-amz-security-token");
+        }
+        canonical_headers.push_str(&format!("x-amz-target:{}\n", target));
+        signed_headers.push_str(";x-amz-target");
+
+        let canonical_request = format!(
+            "POST\n{}\n\n{}\n{}\n{}",
+            self.endpoint.path(),
+            canonical_headers,
+            signed_headers,
+            hex_digest(body)
+        );
+        let credential_scope = format!("{}/{}/kms/aws4_request", date, self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            credential_scope,
+            hex_digest(canonical_request.as_bytes())
+        );
+
+        let mut secret = format!("AWS4{}", self.secret_access_key.expose_secret());
+        let signing_key = [date, self.region.as_str(), "kms", "aws4_request"]
+            .iter()
+            .fold(secret.as_bytes().to_vec(), |key, data| {
+                hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, &key), data.as_bytes())
+                    .as_ref()
+                    .to_vec()
+            });
+        secret.zeroize();
+        let signature = hmac::sign(
+            &hmac::Key::new(hmac::HMAC_SHA256, &signing_key),
+            string_to_sign.as_bytes(),
+        );
+
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.access_key_id,
+            credential_scope,
+            signed_headers,
+            to_hex(signature.as_ref())
+        )
+    }
+}
+
+fn hex_digest(data: &[u8]) -> String {
+    to_hex(digest::digest(&digest::SHA256, data).as_ref())
+}
+
+fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[async_trait]
+impl KeyManager for AwsKmsKeyManager {
+    fn key_id(&self) -> String {
+        format!("aws-kms:{}", self.key_id)
+    }
+
+    async fn wrap_key(&self, data_key: &[u8]) -> Result<Vec<u8>, KeyManagementError> {
+        let mut plaintext = base64::encode(data_key);
+        let response: Result<EncryptResponse, _> = self
+            .call(
+                "Encrypt",
+                &EncryptRequest {
+                    key_id: &self.key_id,
+                    plaintext: &plaintext,
+                },
+            )
+            .await;
+        plaintext.zeroize();
+        base64::decode(&response?.ciphertext_blob)
+            .map_err(|err| KeyManagementError::Service(err.to_string()))
+    }
+
+    async fn unwrap_key(&self, wrapped_key: &[u8]) -> Result<SecretBytesMut, KeyManagementError> {
+        let ciphertext_blob = base64::encode(wrapped_key);
+        let mut response: DecryptResponse = self
+            .call(
+                "Decrypt",
+                &DecryptRequest {
+                    key_id: &self.key_id,
+                    ciphertext_blob: &ciphertext_blob,
+                },
+            )
+            .await?;
+        let data_key = base64::decode(&response.plaintext);
+        response.plaintext.zeroize();
+        let mut data_key = data_key.map_err(|err| KeyManagementError::Unwrap(err.to_string()))?;
+        let secret = SecretBytesMut::new(&data_key[..]);
+        data_key.zeroize();
+        Ok(secret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signs_requests() {
+        let key_manager = AwsKmsKeyManager::new(
+            "alias/ilp".to_string(),
+            "us-east-1".to_string(),
+            "AKIDEXAMPLE".to_string(),
+            SecretString::new("wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".to_string()),
+            None,
+            None,
+        )
+        .unwrap();
+        let authorization = key_manager.authorization(
+            "20200101T000000Z",
+            "20200101",
+            "TrentService.Encrypt",
+            b"{}",
+        );
+        assert_eq!(
+            authorization,
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20200101/us-east-1/kms/aws4_request, \
+             SignedHeaders=content-type;host;x-amz-date;x-amz-target, \
+             Signature=efd3cac0df092b650cac455612ec3255dd9a8a3887ac42cb4b30827308356b46"
+        );
+    }
+
+    fn key_manager() -> AwsKmsKeyManager {
+        AwsKmsKeyManager::new(
+            "alias/ilp".to_string(),
+            "us-east-1".to_string(),
+            "AKIDEXAMPLE".to_string(),
+            SecretString::new("secret".to_string()),
+            None,
+            Some(mockito::server_url().parse().unwrap()),
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn wraps_and_unwraps_key_with_kms() {
+        let encrypt = mockito::mock("POST", "/")
+            .match_header("x-amz-target", "TrentService.Encrypt")
+            .match_body(mockito::Matcher::Json(serde_json::json!({
+                "KeyId": "alias/ilp",
+                "Plaintext": base64::encode(&[7; 32]),
+            })))
+            .with_body(r#"{"CiphertextBlob":"d3JhcHBlZA=="}"#)
+            .create();
+        let decrypt = mockito::mock("POST", "/")
+            .match_header("x-amz-target", "TrentService.Decrypt")
+            .match_body(mockito::Matcher::Json(serde_json::json!({
+                "KeyId": "alias/ilp",
+                "CiphertextBlob": "d3JhcHBlZA==",
+            })))
+            .with_body(format!(r#"{{"Plaintext":"{}"}}"#, base64::encode(&[7; 32])))
+            .create();
+
+        let key_manager = key_manager();
+        let wrapped = key_manager.wrap_key(&[7; 32]).await.unwrap();
+        assert_eq!(wrapped, b"wrapped");
+        let unwrapped = key_manager.unwrap_key(&wrapped).await.unwrap();
+        assert_eq!(unwrapped.expose_secret().as_ref(), &[7; 32][..]);
+        encrypt.assert();
+        decrypt.assert();
+    }
+}
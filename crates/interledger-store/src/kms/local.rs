@@ -0,0 +1,103 @@
+use super::{KeyManagementError, KeyManager};
+use crate::crypto::{decrypt_token, encrypt_token};
+use async_trait::async_trait;
+use hex::FromHex;
+use ring::{aead, digest};
+use secrecy::SecretBytesMut;
+use std::{fs, path::Path};
+use zeroize::Zeroize;
+
+/// Key manager which wraps the data key with a key encryption key read from a local file
+pub struct LocalKeyManager {
+    key: aead::LessSafeKey,
+    key_id: String,
+}
+
+impl LocalKeyManager {
+    /// Creates a key manager from a 256-bit key
+    pub fn new(key: &[u8; 32]) -> Self {
+        // The key ID is derived from the key itself, so that a wrong key file
+        // can be detected without revealing anything about the key
+        let fingerprint = digest::digest(&digest::SHA256, key);
+        let key_id = fingerprint.as_ref()[..8]
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<String>();
+        LocalKeyManager {
+            key: aead::LessSafeKey::new(
+                aead::UnboundKey::new(&aead::AES_256_GCM, key).expect("key is 256 bits long"),
+            ),
+            key_id: format!("local:{}", key_id),
+        }
+    }
+
+    /// Reads the hex-encoded 256-bit key from the provided file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, KeyManagementError> {
+        let mut contents = fs::read_to_string(path)?;
+        let key = <[u8; 32]>::from_hex(contents.trim());
+        contents.zeroize();
+        let mut key = key.map_err(|err| {
+            KeyManagementError::InvalidKey(format!(
+                "key file must contain 32 hex-encoded bytes: {}",
+                err
+            ))
+        })?;
+        let key_manager = LocalKeyManager::new(&key);
+        key.zeroize();
+        Ok(key_manager)
+    }
+}
+
+#[async_trait]
+impl KeyManager for LocalKeyManager {
+    fn key_id(&self) -> String {
+        self.key_id.clone()
+    }
+
+    async fn wrap_key(&self, data_key: &[u8]) -> Result<Vec<u8>, KeyManagementError> {
+        Ok(encrypt_token(&self.key, data_key).to_vec())
+    }
+
+    async fn unwrap_key(&self, wrapped_key: &[u8]) -> Result<SecretBytesMut, KeyManagementError> {
+        decrypt_token(&self.key, wrapped_key).map_err(|_| {
+            KeyManagementError::Unwrap(format!("data key was not wrapped with key {}", self.key_id))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use secrecy::ExposeSecret;
+
+    #[tokio::test]
+    async fn wraps_and_unwraps_key() {
+        let key_manager = LocalKeyManager::new(&[1; 32]);
+        let wrapped = key_manager.wrap_key(&[7; 32]).await.unwrap();
+        assert_ne!(&wrapped[..32], &[7; 32][..]);
+        let unwrapped = key_manager.unwrap_key(&wrapped).await.unwrap();
+        assert_eq!(unwrapped.expose_secret().as_ref(), &[7; 32][..]);
+    }
+
+    #[tokio::test]
+    async fn cannot_unwrap_with_other_key() {
+        let wrapped = LocalKeyManager::new(&[1; 32])
+            .wrap_key(&[7; 32])
+            .await
+            .unwrap();
+        let other = LocalKeyManager::new(&[2; 32]);
+        assert!(other.unwrap_key(&wrapped).await.is_err());
+    }
+
+    #[test]
+    fn key_id_depends_on_key() {
+        assert_eq!(
+            LocalKeyManager::new(&[1; 32]).key_id(),
+            LocalKeyManager::new(&[1; 32]).key_id()
+        );
+        assert_ne!(
+            LocalKeyManager::new(&[1; 32]).key_id(),
+            LocalKeyManager::new(&[2; 32]).key_id()
+        );
+    }
+}
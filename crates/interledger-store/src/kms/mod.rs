@@ -0,0 +1,137 @@
+//! Pluggable key management for the envelope encryption of account secrets.
+//!
+//! The account secrets (the ILP over HTTP and BTP tokens) are encrypted with a random
+//! data encryption key. That key is only ever stored wrapped (encrypted) by a key
+//! encryption key which lives in a [`KeyManager`](./trait.KeyManager.html): a local key
+//! file, [AWS KMS](https://aws.amazon.com/kms/) or the transit secrets engine of
+//! [HashiCorp Vault](https://www.vaultproject.io/docs/secrets/transit). This means that
+//! a copy of the database alone is not enough to recover the secrets.
+
+mod local;
+pub use local::LocalKeyManager;
+
+#[cfg(feature = "aws-kms")]
+mod aws;
+#[cfg(feature = "aws-kms")]
+pub use aws::AwsKmsKeyManager;
+
+#[cfg(feature = "vault")]
+mod vault;
+#[cfg(feature = "vault")]
+pub use vault::VaultKeyManager;
+
+use async_trait::async_trait;
+use secrecy::SecretBytesMut;
+#[cfg(any(feature = "aws-kms", feature = "vault"))]
+use secrecy::SecretString;
+use serde::Deserialize;
+use std::{path::PathBuf, sync::Arc};
+use thiserror::Error;
+#[cfg(any(feature = "aws-kms", feature = "vault"))]
+use url::Url;
+
+/// Errors for the key managers
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum KeyManagementError {
+    #[error("invalid key encryption key: {0}")]
+    InvalidKey(String),
+    #[error("could not unwrap the data key: {0}")]
+    Unwrap(String),
+    #[error("key management service responded with an error: {0}")]
+    Service(String),
+    #[error("error reading the key file: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// A service which holds the key encryption key, used to wrap and unwrap the data key
+/// that encrypts the account secrets
+#[async_trait]
+pub trait KeyManager: Send + Sync {
+    /// Identifies the key encryption key. It is stored next to the wrapped data key
+    /// so that it is possible to tell which key was used to wrap it.
+    fn key_id(&self) -> String;
+
+    /// Wraps (encrypts) the provided data key with the key encryption key
+    async fn wrap_key(&self, data_key: &[u8]) -> Result<Vec<u8>, KeyManagementError>;
+
+    /// Unwraps (decrypts) a data key which was wrapped by this key manager
+    async fn unwrap_key(&self, wrapped_key: &[u8]) -> Result<SecretBytesMut, KeyManagementError>;
+}
+
+/// Configuration of the key manager which protects the data encryption key
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "provider", rename_all = "snake_case")]
+pub enum KeyManagerConfig {
+    /// A 256-bit key encryption key stored hex-encoded in a local file
+    /// (for example, generated by running `openssl rand -hex 32 > key.hex`)
+    Local { key_file: PathBuf },
+    /// A symmetric customer master key in [AWS KMS](https://aws.amazon.com/kms/)
+    #[cfg(feature = "aws-kms")]
+    AwsKms {
+        /// ID, ARN or alias of the key
+        key_id: String,
+        region: String,
+        access_key_id: String,
+        secret_access_key: SecretString,
+        #[serde(default)]
+        session_token: Option<SecretString>,
+        /// Overrides the default `https://kms.<region>.amazonaws.com` endpoint
+        #[serde(default)]
+        endpoint: Option<Url>,
+    },
+    /// A key of the transit secrets engine of [HashiCorp Vault](https://www.vaultproject.io)
+    #[cfg(feature = "vault")]
+    Vault {
+        url: Url,
+        token: SecretString,
+        key_name: String,
+        /// Path the transit secrets engine is mounted at. Defaults to `transit`
+        #[serde(default = "default_vault_mount")]
+        mount: String,
+    },
+}
+
+#[cfg(feature = "vault")]
+fn default_vault_mount() -> String {
+    "transit".to_string()
+}
+
+impl KeyManagerConfig {
+    /// Creates the key manager described by this configuration
+    pub fn build(&self) -> Result<Arc<dyn KeyManager>, KeyManagementError> {
+        match self {
+            KeyManagerConfig::Local { key_file } => {
+                Ok(Arc::new(LocalKeyManager::from_file(key_file)?))
+            }
+            #[cfg(feature = "aws-kms")]
+            KeyManagerConfig::AwsKms {
+                key_id,
+                region,
+                access_key_id,
+                secret_access_key,
+                session_token,
+                endpoint,
+            } => Ok(Arc::new(AwsKmsKeyManager::new(
+                key_id.clone(),
+                region.clone(),
+                access_key_id.clone(),
+                secret_access_key.clone(),
+                session_token.clone(),
+                endpoint.clone(),
+            )?)),
+            #[cfg(feature = "vault")]
+            KeyManagerConfig::Vault {
+                url,
+                token,
+                key_name,
+                mount,
+            } => Ok(Arc::new(VaultKeyManager::new(
+                url.clone(),
+                token.clone(),
+                mount.clone(),
+                key_name.clone(),
+            ))),
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use super::{KeyManagementError, KeyManager};
+use async_trait::async_trait;
+use reqwest::Client;
+use secrecy::{ExposeSecret, SecretBytesMut, SecretString};
+use serde::{Deserialize, Serialize};
+use url::Url;
+use zeroize::Zeroize;
+
+/// Key manager which wraps the data key using the
+/// [transit secrets engine](https://www.vaultproject.io/docs/secrets/transit) of HashiCorp Vault
+pub struct VaultKeyManager {
+    client: Client,
+    url: Url,
+    token: SecretString,
+    mount: String,
+    key_name: String,
+}
+
+#[derive(Serialize)]
+struct EncryptRequest<'a> {
+    plaintext: &'a str,
+}
+
+#[derive(Serialize)]
+struct DecryptRequest<'a> {
+    ciphertext: &'a str,
+}
+
+#[derive(Deserialize)]
+struct VaultResponse<T> {
+    data: T,
+}
+
+#[derive(Deserialize)]
+struct EncryptResponse {
+    ciphertext: String,
+}
+
+#[derive(Deserialize)]
+struct DecryptResponse {
+    plaintext: String,
+}
+
+impl VaultKeyManager {
+    pub fn new(url: Url, token: SecretString, mount: String, key_name: String) -> Self {
+        VaultKeyManager {
+            client: Client::new(),
+            url,
+            token,
+            mount,
+            key_name,
+        }
+    }
+
+    async fn transit<B: Serialize, T: for<'de> Deserialize<'de>>(
+        &self,
+        operation: &str,
+        body: &B,
+    ) -> Result<T, KeyManagementError> {
+        let url = self
+            .url
+            .join(&format!(
+                "v1/{}/{}/{}",
+                self.mount, operation, self.key_name
+            ))
+            .map_err(|err| KeyManagementError::Service(err.to_string()))?;
+        let response = self
+            .client
+            .post(url)
+            .header("X-Vault-Token", self.token.expose_secret().as_str())
+            .json(body)
+            .send()
+            .await
+            .map_err(|err| KeyManagementError::Service(err.to_string()))?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let text = response.text().await.unwrap_or_default();
+            return Err(KeyManagementError::Service(format!(
+                "Vault responded to {} with {}: {}",
+                operation, status, text
+            )));
+        }
+        let response: VaultResponse<T> = response
+            .json()
+            .await
+            .map_err(|err| KeyManagementError::Service(err.to_string()))?;
+        Ok(response.data)
+    }
+}
+
+#[async_trait]
+impl KeyManager for VaultKeyManager {
+    fn key_id(&self) -> String {
+        format!("vault:{}/{}", self.mount, self.key_name)
+    }
+
+    async fn wrap_key(&self, data_key: &[u8]) -> Result<Vec<u8>, KeyManagementError> {
+        let mut plaintext = base64::encode(data_key);
+        let response: Result<EncryptResponse, _> = self
+            .transit(
+                "encrypt",
+                &EncryptRequest {
+                    plaintext: &plaintext,
+                },
+            )
+            .await;
+        plaintext.zeroize();
+        // Vault ciphertexts are strings in the form `vault:v<key version>:<base64>`
+        Ok(response?.ciphertext.into_bytes())
+    }
+
+    async fn unwrap_key(&self, wrapped_key: &[u8]) -> Result<SecretBytesMut, KeyManagementError> {
+        let ciphertext = std::str::from_utf8(wrapped_key)
+            .map_err(|_| KeyManagementError::Unwrap("invalid Vault ciphertext".to_string()))?;
+        let mut response: DecryptResponse = self
+            .transit("decrypt", &DecryptRequest { ciphertext })
+            .await?;
+        let data_key = base64::decode(&response.plaintext);
+        response.plaintext.zeroize();
+        let mut data_key = data_key.map_err(|err| KeyManagementError::Unwrap(err.to_string()))?;
+        let secret = SecretBytesMut::new(&data_key[..]);
+        data_key.zeroize();
+        Ok(secret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::{mock, Matcher};
+    use serde_json::json;
+
+    fn key_manager() -> VaultKeyManager {
+        VaultKeyManager::new(
+            mockito::server_url().parse().unwrap(),
+            SecretString::new("root".to_string()),
+            "transit".to_string(),
+            "ilp".to_string(),
+        )
+    }
+
+    #[tokio::test]
+    async fn wraps_and_unwraps_key_with_transit() {
+        let encrypt = mock("POST", "/v1/transit/encrypt/ilp")
+            .match_header("x-vault-token", "root")
+            .match_body(Matcher::Json(
+                json!({ "plaintext": base64::encode(&[7; 32]) }),
+            ))
+            .with_body(r#"{"data":{"ciphertext":"vault:v1:abcd"}}"#)
+            .create();
+        let decrypt = mock("POST", "/v1/transit/decrypt/ilp")
+            .match_header("x-vault-token", "root")
+            .match_body(Matcher::Json(json!({ "ciphertext": "vault:v1:abcd" })))
+            .with_body(format!(
+                r#"{{"data":{{"plaintext":"{}"}}}}"#,
+                base64::encode(&[7; 32])
+            ))
+            .create();
+
+        let key_manager = key_manager();
+        assert_eq!(key_manager.key_id(), "vault:transit/ilp");
+        let wrapped = key_manager.wrap_key(&[7; 32]).await.unwrap();
+        assert_eq!(wrapped, b"vault:v1:abcd");
+        let unwrapped = key_manager.unwrap_key(&wrapped).await.unwrap();
+        assert_eq!(unwrapped.expose_secret().as_ref(), &[7; 32][..]);
+        encrypt.assert();
+        decrypt.assert();
+    }
+
+    #[tokio::test]
+    async fn reports_errors_of_vault() {
+        let _m = mock("POST", "/v1/transit/decrypt/ilp")
+            .with_status(403)
+            .with_body("permission denied")
+            .create();
+        let err = key_manager()
+            .unwrap_key(b"vault:v1:abcd")
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("permission denied"));
+    }
+}
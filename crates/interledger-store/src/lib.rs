@@ -6,6 +6,11 @@
 pub mod account;
 /// Cryptographic utilities for encrypting/decrypting data as well as clearing data from memory
 pub mod crypto;
+/// Metrics about the latency and errors of the store operations
+#[cfg(feature = "redis")]
+mod instrumentation;
+/// Key managers which protect the key used to encrypt account secrets
+pub mod kms;
 /// A redis backend using [redis-rs](https://github.com/mitsuhiko/redis-rs/)
 #[cfg(feature = "redis")]
 pub mod redis;
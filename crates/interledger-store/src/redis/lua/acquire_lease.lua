@@ -0,0 +1,13 @@
+local lease_key = KEYS[1]
+local holder = ARGV[1]
+local ttl = ARGV[2]
+
+-- Take the lease if nobody holds it, or extend it if we already do
+if redis.call('SET', lease_key, holder, 'NX', 'PX', ttl) then
+    return 1
+elseif redis.call('GET', lease_key) == holder then
+    redis.call('PEXPIRE', lease_key, ttl)
+    return 1
+else
+    return 0
+end
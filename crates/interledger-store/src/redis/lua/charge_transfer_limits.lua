@@ -0,0 +1,73 @@
+-- Counts the amounts of a packet in the rolling windows of the transfer limits of the
+-- sending and receiving accounts. The amounts are counted in hourly buckets for the daily
+-- limits (fields `h:<hour>`) and in daily buckets for the monthly limits (fields `d:<day>`),
+-- in a hash per account and direction. Only the accounts with a limit in the direction are counted.
+local hour = tonumber(ARGV[3])
+local day = tonumber(ARGV[4])
+
+-- Returns the limit of the direction which the amount would exceed, as
+-- {direction, window, limit, used, reset_at}, and whether the account has a limit in it
+local function check_transfer_limits(account_key, usage_key, direction, amount)
+    if amount == 0 then
+        return nil, false
+    end
+    local daily_limit, monthly_limit = unpack(redis.call('HMGET', account_key, 'daily_' .. direction .. '_limit', 'monthly_' .. direction .. '_limit'))
+    if not daily_limit and not monthly_limit then
+        return nil, false
+    end
+
+    local windows = {
+        h = {name = 'daily', limit = tonumber(daily_limit), current = hour, buckets = 24, length = 3600000, used = 0},
+        d = {name = 'monthly', limit = tonumber(monthly_limit), current = day, buckets = 30, length = 86400000, used = 0},
+    }
+    local usage = redis.call('HGETALL', usage_key)
+    for i = 1, #usage, 2 do
+        local tag, index = string.match(usage[i], '^(%a):(%d+)$')
+        local window = windows[tag]
+        if window then
+            index = tonumber(index)
+            if index + window.buckets > window.current then
+                window.used = window.used + tonumber(usage[i + 1])
+                if not window.oldest or index < window.oldest then
+                    window.oldest = index
+                end
+            else
+                -- The bucket left the window
+                redis.call('HDEL', usage_key, usage[i])
+            end
+        end
+    end
+
+    for _, tag in ipairs({'h', 'd'}) do
+        local window = windows[tag]
+        if window.limit and window.used + amount > window.limit then
+            local oldest = window.oldest or window.current
+            return {direction, window.name, window.limit, window.used, (oldest + window.buckets) * window.length}, true
+        end
+    end
+    return nil, true
+end
+
+local function count(usage_key, amount)
+    redis.call('HINCRBY', usage_key, 'h:' .. hour, amount)
+    redis.call('HINCRBY', usage_key, 'd:' .. day, amount)
+    -- The usage of the accounts whose limits were removed eventually goes away
+    redis.call('EXPIRE', usage_key, 31 * 86400)
+end
+
+local exceeded, sent = check_transfer_limits(KEYS[1], KEYS[2], 'send', tonumber(ARGV[1]))
+if exceeded then
+    return exceeded
+end
+local exceeded, received = check_transfer_limits(KEYS[3], KEYS[4], 'receive', tonumber(ARGV[2]))
+if exceeded then
+    return exceeded
+end
+
+if sent then
+    count(KEYS[2], ARGV[1])
+end
+if received then
+    count(KEYS[4], ARGV[2])
+end
+return {'ok', '', sent and 1 or 0, received and 1 or 0, 0}
@@ -0,0 +1,56 @@
+-- Helpers for the scripts which debit an account for a packet. This file is prepended to
+-- them, so that the packets forwarded and the ones fulfilled late are charged the same way.
+
+-- Checks the credit limits of moving an amount out of the sending account and into the
+-- receiving account. Returns the limit which would be exceeded as {'receivable', exceeded_by, limit},
+-- {'min_balance', balance, min_balance} or {'payable', exceeded_by, limit}, if any.
+local function check_credit_limits(from_account, from_amount, to_account, to_amount)
+    local min_balance, receivable_limit, balance, prepaid_amount = unpack(redis.call('HMGET', from_account, 'min_balance', 'receivable_limit', 'balance', 'prepaid_amount'))
+    local total_balance = (tonumber(balance) or 0) + (tonumber(prepaid_amount) or 0)
+
+    -- Check that the account wouldn't owe more than its receivable limit
+    -- (or go under its minimum balance, which the receivable limit takes precedence over)
+    if receivable_limit then
+        receivable_limit = tonumber(receivable_limit)
+        if total_balance - from_amount < 0 - receivable_limit then
+            return {'receivable', from_amount - receivable_limit - total_balance, receivable_limit}
+        end
+    elseif min_balance then
+        min_balance = tonumber(min_balance)
+        if total_balance - from_amount < min_balance then
+            return {'min_balance', total_balance, min_balance}
+        end
+    end
+
+    -- Check that the node wouldn't owe the receiving account more than its payable limit
+    -- if the amount and the packets forwarded to it which are still in flight were fulfilled
+    local payable_limit, to_balance, to_prepaid_amount, payable_in_flight = unpack(redis.call('HMGET', to_account, 'payable_limit', 'balance', 'prepaid_amount', 'payable_in_flight'))
+    if payable_limit then
+        payable_limit = tonumber(payable_limit)
+        local payable = (tonumber(to_balance) or 0) + (tonumber(to_prepaid_amount) or 0) + (tonumber(payable_in_flight) or 0) + to_amount
+        if payable > payable_limit then
+            return {'payable', payable - payable_limit, payable_limit}
+        end
+    end
+
+    return nil
+end
+
+-- Deducts the amount from the account's prepaid amount and, once that runs out, from its balance.
+-- Returns the account's updated balance and prepaid amount
+local function deduct_from_account(account, amount)
+    local balance, prepaid_amount = unpack(redis.call('HMGET', account, 'balance', 'prepaid_amount'))
+    balance = tonumber(balance)
+    prepaid_amount = tonumber(prepaid_amount)
+    if prepaid_amount >= amount then
+        prepaid_amount = redis.call('HINCRBY', account, 'prepaid_amount', 0 - amount)
+    elseif prepaid_amount > 0 then
+        local sub_from_balance = amount - prepaid_amount
+        prepaid_amount = 0
+        redis.call('HSET', account, 'prepaid_amount', 0)
+        balance = redis.call('HINCRBY', account, 'balance', 0 - sub_from_balance)
+    else
+        balance = redis.call('HINCRBY', account, 'balance', 0 - amount)
+    end
+    return balance, prepaid_amount
+end
@@ -0,0 +1,25 @@
+local nonce_key = KEYS[1]
+local totals_key = KEYS[2]
+local balances_key = KEYS[3]
+local stream_id = ARGV[1]
+local total_received = tonumber(ARGV[2])
+local ttl = tonumber(ARGV[3])
+
+-- The path of the payment pointer the connection was opened with
+local path = redis.call('GET', nonce_key)
+if not path then
+    return nil
+end
+
+-- Only the part of the total which the receipts verified before do not cover is credited,
+-- so replayed receipts credit nothing
+local previous_total = tonumber(redis.call('HGET', totals_key, stream_id) or 0)
+local amount = 0
+if total_received > previous_total then
+    amount = total_received - previous_total
+    redis.call('HSET', totals_key, stream_id, ARGV[2])
+    redis.call('EXPIRE', totals_key, ttl)
+end
+
+local balance = redis.call('HINCRBY', balances_key, path, amount)
+return {path, amount, balance}
@@ -0,0 +1,12 @@
+-- Appends an entry to the account's balance journal.
+-- This file is prepended to every script which changes an account's balance,
+-- so that the balance change and its journal entry are written atomically.
+-- The journal can be replayed (on top of the latest snapshot) to verify the balances.
+-- The snapshots remove the entries written before them.
+local function append_journal_entry(account_id, op, amount, balance_change, prepaid_change, balance, prepaid_amount, timestamp)
+    local entry = string.format(
+        '{"op":"%s","amount":%d,"balance_change":%d,"prepaid_change":%d,"balance":%d,"prepaid_amount":%d,"timestamp":%d}',
+        op, amount, balance_change, prepaid_change, balance, prepaid_amount, timestamp)
+    redis.call('RPUSH', 'balance_journal:' .. account_id, entry)
+end
+
@@ -1,8 +1,37 @@
+local to_id = ARGV[1]
 local to_account = 'accounts:' .. ARGV[1]
 local to_amount = tonumber(ARGV[2])
+local timestamp = tonumber(ARGV[3])
+local escrow_id = ARGV[4]
+local from_id = ARGV[5]
+local from_account = 'accounts:' .. ARGV[5]
+local from_amount = tonumber(ARGV[6])
+local escrow_key = 'escrow:' .. escrow_id
+
+-- Commit the amount which was held in escrow since the prepare
+if redis.call('EXISTS', escrow_key) == 1 then
+    local escrow_from_amount, escrow_to_amount = unpack(redis.call('HMGET', escrow_key, 'from_amount', 'to_amount'))
+    if redis.call('EXISTS', from_account) == 1 then
+        redis.call('HINCRBY', from_account, 'in_flight', 0 - tonumber(escrow_from_amount))
+    end
+    if redis.call('EXISTS', to_account) == 1 then
+        redis.call('HINCRBY', to_account, 'payable_in_flight', 0 - tonumber(escrow_to_amount))
+    end
+    redis.call('DEL', escrow_key)
+    redis.call('ZREM', 'escrow_expiries', escrow_id)
+elseif from_amount > 0 and redis.call('EXISTS', from_account) == 1 then
+    -- The escrow already expired and was released back to the sending account, so the
+    -- amount is deducted from it again, starting with its prepaid amount like a prepare
+    local balance_before, prepaid_amount_before = unpack(redis.call('HMGET', from_account, 'balance', 'prepaid_amount'))
+    local from_balance, from_prepaid_amount = deduct_from_account(from_account, from_amount)
+    append_journal_entry(from_id, 'late_fulfill', from_amount, from_balance - tonumber(balance_before), from_prepaid_amount - tonumber(prepaid_amount_before), from_balance, from_prepaid_amount, timestamp)
+end
 
 local balance = redis.call('HINCRBY', to_account, 'balance', to_amount)
 local prepaid_amount, settle_threshold, settle_to = unpack(redis.call('HMGET', to_account, 'prepaid_amount', 'settle_threshold', 'settle_to'))
+prepaid_amount = tonumber(prepaid_amount)
+
+append_journal_entry(to_id, 'fulfill', to_amount, to_amount, 0, balance, prepaid_amount, timestamp)
 
 -- The logic for trigerring settlement is as follows:
 --  1. settle_threshold must be non-nil (if it's nil, then settlement was perhaps disabled on the account).
@@ -15,8 +44,12 @@ if (settle_threshold and settle_to) and (balance >= tonumber(settle_threshold))
     -- Update the balance _before_ sending the settlement so that we don't accidentally send
     -- multiple settlements for the same balance. If the settlement fails we'll roll back
     -- the balance change by re-adding the amount back to the balance
-    balance = settle_to
+    balance = tonumber(settle_to)
     redis.call('HSET', to_account, 'balance', balance)
+    -- The total which was settled, reconciled with the settlement engine's records
+    redis.call('HINCRBY', to_account, 'settled_out', settle_amount)
+
+    append_journal_entry(to_id, 'outgoing_settlement', settle_amount, 0 - settle_amount, 0, balance, prepaid_amount, timestamp)
 end
 
 return {balance + prepaid_amount, settle_amount}
@@ -1,6 +1,9 @@
+local id = ARGV[1]
 local account = 'accounts:' .. ARGV[1]
 local amount = tonumber(ARGV[2])
 local idempotency_key = ARGV[3]
+local timestamp = tonumber(ARGV[4])
+local idempotency_ttl = tonumber(ARGV[5])
 
 local balance, prepaid_amount = unpack(redis.call('HMGET', account, 'balance', 'prepaid_amount'))
 
@@ -9,12 +12,16 @@ if redis.call('EXISTS', idempotency_key) == 1 then
     return balance + prepaid_amount
 end
 
--- Otherwise, set it to true and make it expire after 24h (86400 sec)
-redis.call('SET', idempotency_key, 'true', 'EX', 86400)
+-- Otherwise, set it to true and make it expire after the configured TTL (in seconds)
+redis.call('SET', idempotency_key, 'true', 'EX', idempotency_ttl)
+
+balance = tonumber(balance)
+prepaid_amount = tonumber(prepaid_amount)
+local balance_before, prepaid_amount_before = balance, prepaid_amount
 
 -- Credit the incoming settlement to the balance and/or prepaid amount,
 -- depending on whether that account currently owes money or not
-if tonumber(balance) >= 0 then
+if balance >= 0 then
     prepaid_amount = redis.call('HINCRBY', account, 'prepaid_amount', amount)
 elseif math.abs(balance) >= amount then
     balance = redis.call('HINCRBY', account, 'balance', amount)
@@ -24,4 +31,8 @@ else
     redis.call('HSET', account, 'balance', 0)
 end
 
+redis.call('HINCRBY', account, 'settled_in', amount)
+
+append_journal_entry(id, 'incoming_settlement', amount, balance - balance_before, prepaid_amount - prepaid_amount_before, balance, prepaid_amount, timestamp)
+
 return balance + prepaid_amount
@@ -1,28 +1,32 @@
 local from_id = ARGV[1]
 local from_account = 'accounts:' .. ARGV[1]
 local from_amount = tonumber(ARGV[2])
-local min_balance, balance, prepaid_amount = unpack(redis.call('HMGET', from_account, 'min_balance', 'balance', 'prepaid_amount'))
-balance = tonumber(balance)
-prepaid_amount = tonumber(prepaid_amount)
+local timestamp = tonumber(ARGV[3])
+local escrow_id = ARGV[4]
+local to_id = ARGV[5]
+local to_amount = tonumber(ARGV[6])
+local expires_at = tonumber(ARGV[7])
+local to_account = 'accounts:' .. to_id
+local balance_before, prepaid_amount_before = unpack(redis.call('HMGET', from_account, 'balance', 'prepaid_amount'))
+balance_before = tonumber(balance_before)
+prepaid_amount_before = tonumber(prepaid_amount_before)
 
--- Check that the prepare wouldn't go under the account's minimum balance
-if min_balance then
-    min_balance = tonumber(min_balance)
-    if balance + prepaid_amount - from_amount < min_balance then
-        error('Incoming prepare of ' .. from_amount .. ' would bring account ' .. from_id .. ' under its minimum balance. Current balance: ' .. balance .. ', min balance: ' .. min_balance)
-    end
+local exceeded_limit = check_credit_limits(from_account, from_amount, to_account, to_amount)
+if exceeded_limit then
+    return exceeded_limit
 end
 
--- Deduct the from_amount from the prepaid_amount and/or the balance
-if prepaid_amount >= from_amount then
-    prepaid_amount = redis.call('HINCRBY', from_account, 'prepaid_amount', 0 - from_amount)
-elseif prepaid_amount > 0 then
-    local sub_from_balance = from_amount - prepaid_amount
-    prepaid_amount = 0
-    redis.call('HSET', from_account, 'prepaid_amount', 0)
-    balance = redis.call('HINCRBY', from_account, 'balance', 0 - sub_from_balance)
-else
-    balance = redis.call('HINCRBY', from_account, 'balance', 0 - from_amount)
+local balance, prepaid_amount = deduct_from_account(from_account, from_amount)
+
+-- Hold the deducted amount in escrow until the packet is fulfilled, rejected or expires
+redis.call('HINCRBY', from_account, 'in_flight', from_amount)
+redis.call('HMSET', 'escrow:' .. escrow_id, 'from_id', from_id, 'from_amount', from_amount, 'to_id', to_id, 'to_amount', to_amount, 'expires_at', expires_at)
+redis.call('ZADD', 'escrow_expiries', expires_at, escrow_id)
+if redis.call('EXISTS', to_account) == 1 then
+    redis.call('HINCRBY', to_account, 'payable_in_flight', to_amount)
 end
 
-return balance + prepaid_amount
+append_journal_entry(from_id, 'prepare', from_amount, balance - balance_before, prepaid_amount - prepaid_amount_before, balance, prepaid_amount, timestamp)
+
+-- The first element is the limit which would have been exceeded, if any
+return {'', balance + prepaid_amount, 0}
@@ -1,6 +1,26 @@
+local from_id = ARGV[1]
 local from_account = 'accounts:' .. ARGV[1]
 local from_amount = tonumber(ARGV[2])
+local timestamp = tonumber(ARGV[3])
+local escrow_key = 'escrow:' .. ARGV[4]
 
-local prepaid_amount = redis.call('HGET', from_account, 'prepaid_amount')
+local prepaid_amount = tonumber(redis.call('HGET', from_account, 'prepaid_amount'))
+
+-- Nothing to release if the escrow already expired and was released by the sweeper
+if redis.call('EXISTS', escrow_key) == 0 then
+    return tonumber(redis.call('HGET', from_account, 'balance')) + prepaid_amount
+end
+local to_id, to_amount = unpack(redis.call('HMGET', escrow_key, 'to_id', 'to_amount'))
+local to_account = 'accounts:' .. tostring(to_id)
+if to_id and redis.call('EXISTS', to_account) == 1 then
+    redis.call('HINCRBY', to_account, 'payable_in_flight', 0 - tonumber(to_amount))
+end
+redis.call('DEL', escrow_key)
+redis.call('ZREM', 'escrow_expiries', ARGV[4])
+
+redis.call('HINCRBY', from_account, 'in_flight', 0 - from_amount)
 local balance = redis.call('HINCRBY', from_account, 'balance', from_amount)
+
+append_journal_entry(from_id, 'reject', from_amount, from_amount, 0, balance, prepaid_amount, timestamp)
+
 return balance + prepaid_amount
@@ -0,0 +1,54 @@
+-- Takes tokens from the buckets of the rate limits of an account, which are shared by all
+-- the nodes using the store. Each bucket (KEYS[i]) is a hash of its `tokens` and of the
+-- time they were counted at (`at`, in milliseconds), and refills continuously at `capacity`
+-- tokens per `period` milliseconds, up to `capacity`. ARGV[1] is the number of tokens to
+-- lease, which are taken in addition to the cost from the buckets which lease, as far as
+-- all of them have these tokens. The rest of ARGV holds the capacity, period, cost and
+-- whether it leases (1 or 0) of each bucket. The cost is only taken if all the buckets
+-- have enough tokens, and negative costs refund the buckets.
+-- Returns the index (starting at 1) of the first bucket without enough tokens, or 0 if the
+-- cost was taken, the milliseconds after which the bucket has enough tokens, and the
+-- number of tokens leased.
+
+-- The time of the store is used, so that the clocks of the nodes do not need to agree
+if redis.replicate_commands then
+    redis.replicate_commands()
+end
+local time = redis.call('TIME')
+local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+
+local lease = tonumber(ARGV[1])
+local leasing = false
+local buckets = {}
+for i, key in ipairs(KEYS) do
+    local capacity = tonumber(ARGV[i * 4 - 2])
+    local period = tonumber(ARGV[i * 4 - 1])
+    local cost = tonumber(ARGV[i * 4])
+    local leases = ARGV[i * 4 + 1] == '1'
+    local tokens, at = unpack(redis.call('HMGET', key, 'tokens', 'at'))
+    tokens = tonumber(tokens) or capacity
+    at = tonumber(at) or now
+    tokens = math.min(capacity, tokens + math.max(0, now - at) * capacity / period)
+    if cost > tokens then
+        return {i, math.ceil((cost - tokens) * period / capacity), 0}
+    end
+    if leases then
+        leasing = true
+        lease = math.max(0, math.min(lease, math.floor(tokens - cost)))
+    end
+    buckets[i] = {key = key, period = period, tokens = math.min(capacity, tokens - cost), leases = leases}
+end
+if not leasing then
+    lease = 0
+end
+
+for _, bucket in ipairs(buckets) do
+    local tokens = bucket.tokens
+    if bucket.leases then
+        tokens = tokens - lease
+    end
+    redis.call('HMSET', bucket.key, 'tokens', string.format('%.17g', tokens), 'at', now)
+    -- A bucket left alone for a period is full, which is the same as not having one
+    redis.call('PEXPIRE', bucket.key, bucket.period)
+end
+return {0, 0, lease}
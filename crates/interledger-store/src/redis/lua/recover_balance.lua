@@ -0,0 +1,27 @@
+-- Overwrites the account's balance with the one replayed from its journal.
+-- Returns 0 on success, 1 if the account does not exist and 2 if the
+-- journal was appended to since it was replayed (in which case nothing is changed)
+local id = ARGV[1]
+local account = 'accounts:' .. ARGV[1]
+local balance = tonumber(ARGV[2])
+local prepaid_amount = tonumber(ARGV[3])
+local journal_length = tonumber(ARGV[4])
+local timestamp = tonumber(ARGV[5])
+
+if redis.call('EXISTS', account) == 0 then
+    return 1
+end
+
+-- The position of the journal counts the entries trimmed by the snapshots
+local trimmed = tonumber(redis.call('HGET', 'balance_snapshot:' .. id, 'journal_trimmed')) or 0
+if trimmed + redis.call('LLEN', 'balance_journal:' .. id) ~= journal_length then
+    return 2
+end
+
+redis.call('HMSET', account, 'balance', balance, 'prepaid_amount', prepaid_amount)
+
+-- The journal is the source of truth, so the recovery itself does not change
+-- the replayed balance. It is only recorded to leave an audit trail
+append_journal_entry(id, 'recovery', 0, 0, 0, balance, prepaid_amount, timestamp)
+
+return 0
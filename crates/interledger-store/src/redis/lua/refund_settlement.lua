@@ -1,5 +1,12 @@
+local id = ARGV[1]
 local account = 'accounts:' .. ARGV[1]
 local settle_amount = tonumber(ARGV[2])
+local timestamp = tonumber(ARGV[3])
 
 local balance = redis.call('HINCRBY', account, 'balance', settle_amount)
+local prepaid_amount = tonumber(redis.call('HGET', account, 'prepaid_amount'))
+redis.call('HINCRBY', account, 'settled_out', 0 - settle_amount)
+
+append_journal_entry(id, 'settlement_refund', settle_amount, settle_amount, 0, balance, prepaid_amount, timestamp)
+
 return balance
@@ -0,0 +1,20 @@
+-- Uncounts the amounts of a rejected packet from the buckets of the transfer limits of the
+-- accounts it was counted in (an amount of 0 is not counted)
+local buckets = {'h:' .. ARGV[3], 'd:' .. ARGV[4]}
+
+local function uncount(usage_key, amount)
+    if amount == 0 then
+        return
+    end
+    for _, bucket in ipairs(buckets) do
+        local counted = tonumber(redis.call('HGET', usage_key, bucket) or 0)
+        if counted <= amount then
+            redis.call('HDEL', usage_key, bucket)
+        else
+            redis.call('HINCRBY', usage_key, bucket, 0 - amount)
+        end
+    end
+end
+
+uncount(KEYS[1], tonumber(ARGV[1]))
+uncount(KEYS[2], tonumber(ARGV[2]))
@@ -0,0 +1,29 @@
+-- Releases the escrows of the packets which expired before the provided time back to
+-- their sending accounts, as if the packets had been rejected. At most `limit` escrows
+-- are released per call. Returns the number of escrows which were released.
+local expired_before = tonumber(ARGV[1])
+local limit = tonumber(ARGV[2])
+local timestamp = tonumber(ARGV[3])
+
+local escrow_ids = redis.call('ZRANGEBYSCORE', 'escrow_expiries', '-inf', expired_before, 'LIMIT', 0, limit)
+for _, escrow_id in ipairs(escrow_ids) do
+    local escrow_key = 'escrow:' .. escrow_id
+    local from_id, from_amount, to_id, to_amount = unpack(redis.call('HMGET', escrow_key, 'from_id', 'from_amount', 'to_id', 'to_amount'))
+    local from_account = 'accounts:' .. tostring(from_id)
+    local to_account = 'accounts:' .. tostring(to_id)
+    if to_id and redis.call('EXISTS', to_account) == 1 then
+        redis.call('HINCRBY', to_account, 'payable_in_flight', 0 - tonumber(to_amount))
+    end
+    -- The escrows of deleted accounts are dropped
+    if from_id and redis.call('EXISTS', from_account) == 1 then
+        from_amount = tonumber(from_amount)
+        redis.call('HINCRBY', from_account, 'in_flight', 0 - from_amount)
+        local balance = redis.call('HINCRBY', from_account, 'balance', from_amount)
+        local prepaid_amount = tonumber(redis.call('HGET', from_account, 'prepaid_amount'))
+        append_journal_entry(from_id, 'expire', from_amount, from_amount, 0, balance, prepaid_amount, timestamp)
+    end
+    redis.call('DEL', escrow_key)
+    redis.call('ZREM', 'escrow_expiries', escrow_id)
+end
+
+return #escrow_ids
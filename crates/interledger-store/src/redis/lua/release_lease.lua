@@ -0,0 +1,9 @@
+local lease_key = KEYS[1]
+local holder = ARGV[1]
+
+-- Only the holder may release the lease
+if redis.call('GET', lease_key) == holder then
+    return redis.call('DEL', lease_key)
+else
+    return 0
+end
@@ -0,0 +1,14 @@
+local balance_key = KEYS[1]
+local amount = tonumber(ARGV[1])
+local interval_index = ARGV[2]
+
+local total = tonumber(redis.call('HGET', balance_key, 'total') or 0)
+local released = math.min(amount, total)
+total = redis.call('HINCRBY', balance_key, 'total', -released)
+
+-- The amount is only given back to the interval it was reserved in
+local interval = tonumber(redis.call('HGET', balance_key, 'interval') or 0)
+if redis.call('HGET', balance_key, 'interval_index') == interval_index then
+    interval = redis.call('HINCRBY', balance_key, 'interval', -math.min(released, interval))
+end
+return {total, interval}
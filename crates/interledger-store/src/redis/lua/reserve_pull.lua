@@ -0,0 +1,21 @@
+local balance_key = KEYS[1]
+local amount = tonumber(ARGV[1])
+local interval_index = ARGV[2]
+local interval_amount = tonumber(ARGV[3])
+-- Empty if the pull pointer has no cap
+local cap = tonumber(ARGV[4])
+
+-- The amount pulled in an interval starts over in the next one
+if redis.call('HGET', balance_key, 'interval_index') ~= interval_index then
+    redis.call('HMSET', balance_key, 'interval_index', interval_index, 'interval', 0)
+end
+local total = tonumber(redis.call('HGET', balance_key, 'total') or 0)
+local interval = tonumber(redis.call('HGET', balance_key, 'interval'))
+
+if interval + amount > interval_amount or (cap and total + amount > cap) then
+    return {0, total, interval}
+end
+
+total = redis.call('HINCRBY', balance_key, 'total', ARGV[1])
+interval = redis.call('HINCRBY', balance_key, 'interval', ARGV[1])
+return {1, total, interval}
@@ -0,0 +1,29 @@
+-- Snapshots the balance of each of the given accounts, along with the position
+-- of the account's balance journal at the time the snapshot was taken.
+-- Verifications only need to replay the journal entries written after it, so
+-- the entries before it are removed, which keeps the journal from growing without
+-- bound. The positions in the journal count the removed entries (`journal_trimmed`),
+-- so that they keep increasing as the journal is trimmed.
+local timestamp = ARGV[1]
+local snapshotted = 0
+
+for i = 2, #ARGV do
+    local id = ARGV[i]
+    local balance, prepaid_amount = unpack(redis.call('HMGET', 'accounts:' .. id, 'balance', 'prepaid_amount'))
+    if balance and prepaid_amount then
+        local snapshot = 'balance_snapshot:' .. id
+        local journal = 'balance_journal:' .. id
+        local journal_length = (tonumber(redis.call('HGET', snapshot, 'journal_trimmed')) or 0)
+            + redis.call('LLEN', journal)
+        redis.call('DEL', journal)
+        redis.call('HMSET', snapshot,
+            'balance', balance,
+            'prepaid_amount', prepaid_amount,
+            'journal_length', journal_length,
+            'journal_trimmed', journal_length,
+            'timestamp', timestamp)
+        snapshotted = snapshotted + 1
+    end
+end
+
+return snapshotted
@@ -0,0 +1,9 @@
+local payments_key = KEYS[1]
+local id = ARGV[1]
+
+-- The payments cancelled in the meantime are not recreated
+if redis.call('HEXISTS', payments_key, id) == 0 then
+    return 0
+end
+redis.call('HSET', payments_key, id, ARGV[2])
+return 1
@@ -2,10 +2,26 @@
 //   send_routes_to         set         used for CCP routing
 //   receive_routes_from    set         used for CCP routing
 //   next_account_id        string      unique ID for each new account
-//   rates:current          hash        exchange rates
+//   rates:current          hash        exchange rates, which the pinned rates override
+//   rates:pinned           hash        exchange rates pinned by the operators
+//   fee_policies           hash        spread and fees of asset pairs (JSON), keyed by FROM/TO
 //   routes:current         hash        dynamic routing table
 //   routes:static          hash        static routing table
 //   accounts:<id>          hash        information for each account
+//   balance_journal:<id>   list        journal of the balance changes since the latest snapshot
+//   balance_snapshot:<id>  hash        latest balance snapshot, journal position and number of trimmed entries
+//   escrow:<id>            hash        incoming amount of a packet in flight, held until it is fulfilled, rejected or expires
+//   escrow_expiries        sorted set  ids of the escrows, scored by the expiry of their packet
+//   idempotency-key:<key>  hash        cached settlement API responses (expire after a TTL)
+//   uncredited-amount:<id> list        settlement leftovers which could not be credited yet
+//   encryption_data_key    hash        data key encrypting account secrets, wrapped by a key manager
+//   runtime_settings       hash        node settings which are applied without a restart
+//   replica_heartbeat:<id> string      timestamp used to measure the lag of read replicas
+//   lease:<name>           string      id of the node holding a lease (expires after its TTL)
+//   audit_log              sorted set  append-only log of the mutating admin API calls (JSON), scored by their timestamp
+//   audit_log:next_id      string      sequence number of the last audit log entry
+//   max_packet_amount:<asset_code>:<asset_scale>:<prefix>
+//                          string      max packet amount discovered on the path to a prefix (expires after a TTL)
 //   btp_outgoing
 // For interactive exploration of the store,
 // use the redis-cli tool included with your redis install.
@@ -15,32 +31,60 @@
 //    get <key>             get the value of a key
 //    hgetall <key>         the flattened list of every key/value entry within a hash
 mod reconnect;
+mod replicas;
 use reconnect::RedisReconnect;
+use replicas::ReadReplicas;
 
 use super::account::{Account, AccountWithEncryptedTokens};
-use super::crypto::{encrypt_token, generate_keys, DecryptionKey, EncryptionKey};
+use super::crypto::{
+    decrypt_token, encrypt_token, generate_data_key, generate_keys, generate_token_hashing_key,
+    hash_token, is_hashed_token, keys_from_data_key, verify_token, DecryptionKey, EncryptionKey,
+    TokenHashingKey,
+};
+use super::instrumentation::instrument;
+use super::kms::KeyManager;
 use async_trait::async_trait;
 use bytes::{Bytes, BytesMut};
 use futures::channel::mpsc::UnboundedSender;
 use http::StatusCode;
-use interledger_api::{AccountDetails, AccountSettings, EncryptedAccountSettings, NodeStore};
+use interledger_api::{
+    AccountDetails, AccountSettings, AccountTemplate, AccountTemplateStore, Asset, AssetStore,
+    AuditEntry, AuditLogStore, AuditQuery, EncryptedAccountSettings, NodeStore, OutgoingPayment,
+    OutgoingPaymentStore, PaymentPointer, PaymentPointerStore, PeeringProposal, PeeringRequest,
+    PeeringStore, PullPaymentStore, PullPointer, ReceiptCredit, ReceiptVerifierStore,
+    RuntimeSettings, ScheduledPayment, ScheduledPaymentStore,
+};
 use interledger_btp::BtpStore;
 use interledger_ccp::{CcpRoutingAccount, CcpRoutingStore, RoutingRelation};
 use interledger_errors::*;
-use interledger_http::HttpStore;
+use interledger_http::{HttpStore, SignedRequest};
 use interledger_packet::Address;
-use interledger_rates::ExchangeRateStore;
+use interledger_rates::{ExchangeRateStore, PinnedRateStore};
 use interledger_router::RouterStore;
-use interledger_service::{Account as AccountTrait, AccountStore, AddressStore, Username};
+use interledger_service::{
+    Account as AccountTrait, AccountStore, AddressStore, HttpDialect, LeaseStore, Username,
+};
 use interledger_service_util::{
-    BalanceStore, RateLimitError, RateLimitStore, DEFAULT_ROUND_TRIP_TIME,
+    fee_policy_pair, BalanceJournalStore, BalanceStore, BalanceVerification, CreditLimits, Escrow,
+    FeePolicy, FeePolicyStore, Liquidity, LiquidityStore, RateLimitError, RateLimitStore,
+    TransferCharge, TransferLimitStore, TransferLimits, TransferUsage, TransferWindow, WindowUsage,
+    DEFAULT_ROUND_TRIP_TIME,
 };
 use interledger_settlement::core::{
     idempotency::{IdempotentData, IdempotentStore},
+    replay_protection::NonceStore,
     scale_with_precision_loss,
-    types::{Convert, ConvertDetails, LeftoversStore, SettlementStore},
+    types::{
+        Convert, ConvertDetails, LeftoversStore, SettlementQueueStore, SettlementStore,
+        SettlementTotalsStore,
+    },
+};
+use interledger_spsp::PullBalance;
+use interledger_stream::{
+    MaxPacketAmountStore, PaymentNotification, Receipt, StreamNotificationsStore,
+    StreamReceiptStore, UnreachableDestination, UnreachableDestinationStore,
 };
-use interledger_stream::{PaymentNotification, StreamNotificationsStore};
+use metrics::{labels, recorder, Key};
 use num_bigint::BigUint;
 use once_cell::sync::Lazy;
 use parking_lot::{Mutex, RwLock};
@@ -49,8 +93,9 @@ use redis_crate::{
     self, cmd, from_redis_value, Client, ConnectionInfo, ControlFlow, ErrorKind, FromRedisValue,
     PubSubCommands, RedisError, RedisWrite, Script, ToRedisArgs, Value,
 };
+use ring::{aead, hmac};
 use secrecy::{ExposeSecret, Secret, SecretBytesMut};
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::{
     collections::{HashMap, HashSet},
     fmt::Display,
@@ -60,39 +105,201 @@ use std::{
     str,
     str::FromStr,
     sync::Arc,
-    time::Duration,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 use tokio::sync::broadcast;
-use tracing::{debug, error, trace, warn};
+use tracing::{debug, error, info, trace, warn};
 use url::Url;
 use uuid::Uuid;
 use zeroize::Zeroize;
 
+/// Label of the metrics recorded for the store operations
+const BACKEND: &str = "redis";
 const DEFAULT_POLL_INTERVAL: u64 = 30000; // 30 seconds
-const ACCOUNT_DETAILS_FIELDS: usize = 21;
+const DEFAULT_IDEMPOTENCY_TTL: u64 = 86400; // 24 hours
+const DEFAULT_MAX_REPLICA_STALENESS: u64 = 1000; // 1 second
+/// Number of keys requested from Redis on each SCAN iteration while compacting
+const COMPACTION_SCAN_COUNT: u64 = 500;
+const ACCOUNT_DETAILS_FIELDS: usize = 38;
+/// Interval (in milliseconds) at which the escrows of expired packets are released if none is set
+pub const DEFAULT_ESCROW_SWEEP_INTERVAL: u64 = 10000; // 10 seconds
+/// Time (in milliseconds) after the expiry of a packet after which its escrow is released,
+/// which leaves time for the Fulfill or Reject of packets expiring right now to be accounted for
+const ESCROW_RELEASE_GRACE_PERIOD: u64 = 5000;
+/// Max number of escrows released by each run of the script releasing expired escrows
+const ESCROW_RELEASE_BATCH_SIZE: usize = 1000;
+/// Time (in milliseconds) for which the packet tokens leased from the rate limits of an
+/// account may be used, and whose worth of tokens is leased at once
+const RATE_LIMIT_LEASE_PERIOD: u64 = 100;
 
 static PARENT_ILP_KEY: &str = "parent_node_account_address";
 static ROUTES_KEY: &str = "routes:current";
 static STATIC_ROUTES_KEY: &str = "routes:static";
+/// Hash of the routes learned from each account by the nodes sharing the store
+static LEARNED_ROUTES_KEY: &str = "routes:learned";
 static DEFAULT_ROUTE_KEY: &str = "routes:default";
+static RATES_KEY: &str = "rates:current";
+static PINNED_RATES_KEY: &str = "rates:pinned";
+/// Pub/sub channel on which exchange rate updates are shared between the nodes using the store
+static EXCHANGE_RATES_CHANNEL: &str = "exchange_rates";
+/// Pub/sub channel on which the pinned rates are shared between the nodes using the store
+static PINNED_RATES_CHANNEL: &str = "pinned_rates";
+static FEE_POLICIES_KEY: &str = "fee_policies";
+/// Pub/sub channel on which fee policy updates are shared between the nodes using the store
+static FEE_POLICIES_CHANNEL: &str = "fee_policies";
 static STREAM_NOTIFICATIONS_PREFIX: &str = "stream_notifications:";
 static SETTLEMENT_ENGINES_KEY: &str = "settlement_engines";
+static RUNTIME_SETTINGS_KEY: &str = "runtime_settings";
+static DATA_KEY_KEY: &str = "encryption_data_key";
+static AUDIT_LOG_KEY: &str = "audit_log";
+static AUDIT_LOG_ID_KEY: &str = "audit_log:next_id";
+static ASSETS_KEY: &str = "assets";
+static ACCOUNT_TEMPLATES_KEY: &str = "account_templates";
+static PAYMENT_POINTERS_KEY: &str = "payment_pointers";
+static PAYMENT_POINTER_BALANCES_KEY: &str = "payment_pointer_balances";
+static PULL_POINTERS_KEY: &str = "pull_pointers";
+static SCHEDULED_PAYMENTS_KEY: &str = "scheduled_payments";
+static OUTGOING_PAYMENTS_KEY: &str = "outgoing_payments";
+static PEERING_REQUESTS_KEY: &str = "peering_requests";
+static PEERING_PROPOSALS_KEY: &str = "peering_proposals";
+static SETTLEMENT_QUEUE_KEY: &str = "settlement_queue";
+/// How long, in seconds, the receipts of a connection are tracked after it was
+/// opened or after its last receipt
+const RECEIPT_TTL: u64 = 86_400;
+/// The fields of the account hashes which are encrypted (the incoming tokens
+/// only if they were stored before the incoming tokens were hashed)
+static ENCRYPTED_ACCOUNT_FIELDS: [&str; 5] = [
+    "ilp_over_btp_outgoing_token",
+    "ilp_over_http_outgoing_token",
+    "ilp_over_http_signing_key",
+    "ilp_over_btp_incoming_token",
+    "ilp_over_http_incoming_token",
+];
+/// The fields of the account hashes which hold the (hashed) incoming tokens
+static INCOMING_TOKEN_FIELDS: [&str; 2] = [
+    "ilp_over_btp_incoming_token",
+    "ilp_over_http_incoming_token",
+];
 
 /// Domain separator for leftover amounts
 fn uncredited_amount_key(account_id: impl ToString) -> String {
     format!("uncredited-amount:{}", account_id.to_string())
 }
 
+/// Domain separator for leases
+fn lease_key(name: &str) -> String {
+    format!("lease:{}", name)
+}
+
+/// Domain separator for the max packet amounts discovered on the paths to destinations
+fn max_packet_amount_key(asset_code: &str, asset_scale: u8, destination_prefix: &str) -> String {
+    format!(
+        "max_packet_amount:{}:{}:{}",
+        asset_code, asset_scale, destination_prefix
+    )
+}
+
+/// Domain separator for the destinations which recently rejected packets as unreachable
+fn unreachable_destination_key(destination_prefix: &str) -> String {
+    format!("unreachable_destination:{}", destination_prefix)
+}
+
 /// Domain separator for idempotency keys
 fn prefixed_idempotency_key(idempotency_key: &str) -> String {
     format!("idempotency-key:{}", idempotency_key)
 }
 
+/// Domain separator for the nonces of the settlement API
+fn prefixed_nonce_key(nonce: &str) -> String {
+    format!("settlement-nonce:{}", nonce)
+}
+
+/// Domain separator for the totals received on the streams of a connection
+fn stream_receipt_key(nonce: &[u8; 16]) -> String {
+    format!("stream-receipts:{}", hex::encode(nonce))
+}
+
+/// Domain separator for the payment pointer of a connection with receipts
+fn receipt_nonce_key(nonce: &[u8; 16]) -> String {
+    format!("receipt-nonce:{}", hex::encode(nonce))
+}
+
+/// Domain separator for the totals of the verified receipts of a connection
+fn verified_receipts_key(nonce: &[u8; 16]) -> String {
+    format!("verified-receipts:{}", hex::encode(nonce))
+}
+
+/// Domain separator for the amounts pulled from a pull pointer
+fn pull_balance_key(id: &str) -> String {
+    format!("pull-balance:{}", id)
+}
+
 /// Domain separator for accounts
 fn accounts_key(account_id: Uuid) -> String {
     format!("accounts:{}", account_id)
 }
 
+/// Domain separator for balance journals
+fn balance_journal_key(account_id: Uuid) -> String {
+    format!("balance_journal:{}", account_id)
+}
+
+/// Domain separator for balance snapshots
+fn balance_snapshot_key(account_id: Uuid) -> String {
+    format!("balance_snapshot:{}", account_id)
+}
+
+/// Domain separator for the token bucket of a rate limit of an account
+fn rate_limit_key(account_id: Uuid, limit: &str) -> String {
+    format!("rate_limit:{}:{}", account_id, limit)
+}
+
+/// Domain separator for the amounts counted in the windows of the transfer limits of an
+/// account, in a direction (`send` or `receive`)
+fn transfer_usage_key(account_id: Uuid, direction: &str) -> String {
+    format!("transfer_usage:{}:{}", account_id, direction)
+}
+
+/// Milliseconds since the UNIX epoch, used to timestamp journal entries and snapshots
+fn journal_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Turns the limit which the script debiting the sending account of the escrow reports as
+/// exceeded into its error. For a credit limit, the value is by how much it would have been
+/// exceeded. For the minimum balance, the value is the current balance.
+fn check_credit_limits(
+    escrow: &Escrow,
+    exceeded_limit: &str,
+    value: i64,
+    limit: i64,
+) -> Result<(), BalanceStoreError> {
+    match exceeded_limit {
+        "receivable" => Err(BalanceStoreError::LiquidityExceeded {
+            account: escrow.from_account_id.to_string(),
+            kind: "receivable",
+            limit: limit as u64,
+            exceeded_by: value as u64,
+        }),
+        "min_balance" => Err(BalanceStoreError::MinimumBalanceExceeded {
+            account: escrow.from_account_id.to_string(),
+            amount: escrow.incoming_amount,
+            balance: value,
+            min_balance: limit,
+        }),
+        "payable" => Err(BalanceStoreError::LiquidityExceeded {
+            account: escrow.to_account_id.to_string(),
+            kind: "payable",
+            limit: limit as u64,
+            exceeded_by: value as u64,
+        }),
+        _ => Ok(()),
+    }
+}
+
 // TODO: Add descriptive errors inside the lua scripts!
 
 // The following are Lua scripts that are used to atomically execute the given logic
@@ -109,6 +316,28 @@ static DEFAULT_ILP_ADDRESS: Lazy<Address> = Lazy::new(|| Address::from_str("loca
 static ACCOUNT_FROM_USERNAME: Lazy<Script> =
     Lazy::new(|| Script::new(include_str!("lua/account_from_username.lua")));
 
+/// Lua script which acquires or extends a lease for its holder
+static ACQUIRE_LEASE: Lazy<Script> =
+    Lazy::new(|| Script::new(include_str!("lua/acquire_lease.lua")));
+
+/// Lua script which releases a lease if it is held by the provided holder
+static RELEASE_LEASE: Lazy<Script> =
+    Lazy::new(|| Script::new(include_str!("lua/release_lease.lua")));
+
+/// Lua script which credits the amount proven by a receipt to its payment pointer
+static CREDIT_RECEIPT: Lazy<Script> =
+    Lazy::new(|| Script::new(include_str!("lua/credit_receipt.lua")));
+
+/// Lua script which adds a pull to the amounts pulled from a pull pointer, if its terms allow it
+static RESERVE_PULL: Lazy<Script> = Lazy::new(|| Script::new(include_str!("lua/reserve_pull.lua")));
+
+/// Lua script which subtracts the unsent part of a pull from the amounts pulled from a pull pointer
+static RELEASE_PULL: Lazy<Script> = Lazy::new(|| Script::new(include_str!("lua/release_pull.lua")));
+
+/// Lua script which overwrites a scheduled payment, unless it was cancelled
+static UPDATE_SCHEDULED_PAYMENT: Lazy<Script> =
+    Lazy::new(|| Script::new(include_str!("lua/update_scheduled_payment.lua")));
+
 /// Lua script which loads a list of accounts
 /// If an account does not have a settlement_engine_url set
 /// but there is one configured for that account's currency,
@@ -116,25 +345,86 @@ static ACCOUNT_FROM_USERNAME: Lazy<Script> =
 static LOAD_ACCOUNTS: Lazy<Script> =
     Lazy::new(|| Script::new(include_str!("lua/load_accounts.lua")));
 
-/// Lua script which reduces the provided account's balance before sending a Prepare packet
-static PROCESS_PREPARE: Lazy<Script> =
-    Lazy::new(|| Script::new(include_str!("lua/process_prepare.lua")));
-
-/// Lua script which increases the provided account's balance after receiving a Fulfill packet
-static PROCESS_FULFILL: Lazy<Script> =
-    Lazy::new(|| Script::new(include_str!("lua/process_fulfill.lua")));
-
-/// Lua script which increases the provided account's balance after receiving a Reject packet
-static PROCESS_REJECT: Lazy<Script> =
-    Lazy::new(|| Script::new(include_str!("lua/process_reject.lua")));
+// All of the scripts which change balances are prepended with the journal helpers
+// so that every balance change is recorded in the balance journal in the same script
+
+/// Lua script which moves the incoming amount of a Prepare packet from the provided
+/// account's balance into escrow before sending the packet
+static PROCESS_PREPARE: Lazy<Script> = Lazy::new(|| {
+    Script::new(concat!(
+        include_str!("lua/journal.lua"),
+        include_str!("lua/credit_limits.lua"),
+        include_str!("lua/process_prepare.lua")
+    ))
+});
+
+/// Lua script which commits the escrow of a packet and increases the provided
+/// account's balance after receiving a Fulfill packet
+static PROCESS_FULFILL: Lazy<Script> = Lazy::new(|| {
+    Script::new(concat!(
+        include_str!("lua/journal.lua"),
+        include_str!("lua/credit_limits.lua"),
+        include_str!("lua/process_fulfill.lua")
+    ))
+});
+
+/// Lua script which releases the escrow of a packet back to the provided
+/// account's balance after receiving a Reject packet
+static PROCESS_REJECT: Lazy<Script> = Lazy::new(|| {
+    Script::new(concat!(
+        include_str!("lua/journal.lua"),
+        include_str!("lua/process_reject.lua")
+    ))
+});
+
+/// Lua script which releases the escrows of expired packets back to their sending accounts
+static RELEASE_EXPIRED_ESCROWS: Lazy<Script> = Lazy::new(|| {
+    Script::new(concat!(
+        include_str!("lua/journal.lua"),
+        include_str!("lua/release_expired_escrows.lua")
+    ))
+});
 
 /// Lua script which increases the provided account's balance after a settlement attempt failed
-static REFUND_SETTLEMENT: Lazy<Script> =
-    Lazy::new(|| Script::new(include_str!("lua/refund_settlement.lua")));
+static REFUND_SETTLEMENT: Lazy<Script> = Lazy::new(|| {
+    Script::new(concat!(
+        include_str!("lua/journal.lua"),
+        include_str!("lua/refund_settlement.lua")
+    ))
+});
 
 /// Lua script which increases the provided account's balance after an incoming settlement succeeded
-static PROCESS_INCOMING_SETTLEMENT: Lazy<Script> =
-    Lazy::new(|| Script::new(include_str!("lua/process_incoming_settlement.lua")));
+static PROCESS_INCOMING_SETTLEMENT: Lazy<Script> = Lazy::new(|| {
+    Script::new(concat!(
+        include_str!("lua/journal.lua"),
+        include_str!("lua/process_incoming_settlement.lua")
+    ))
+});
+
+/// Lua script which overwrites the provided account's balance with the one replayed from its journal
+static RECOVER_BALANCE: Lazy<Script> = Lazy::new(|| {
+    Script::new(concat!(
+        include_str!("lua/journal.lua"),
+        include_str!("lua/recover_balance.lua")
+    ))
+});
+
+/// Lua script which snapshots the balances of the provided accounts
+static SNAPSHOT_BALANCES: Lazy<Script> =
+    Lazy::new(|| Script::new(include_str!("lua/snapshot_balances.lua")));
+
+/// Lua script which counts the amounts of a packet in the windows of the transfer limits
+/// of the sending and receiving accounts, unless it would exceed one of them
+static CHARGE_TRANSFER_LIMITS: Lazy<Script> =
+    Lazy::new(|| Script::new(include_str!("lua/charge_transfer_limits.lua")));
+
+/// Lua script which uncounts the amounts of a rejected packet from the transfer limits
+static REFUND_TRANSFER_LIMITS: Lazy<Script> =
+    Lazy::new(|| Script::new(include_str!("lua/refund_transfer_limits.lua")));
+
+/// Lua script which takes the cost of a packet from the token buckets of the rate limits
+/// of an account, unless one of them does not have enough tokens
+static RATE_LIMIT: Lazy<Script> = Lazy::new(|| Script::new(include_str!("lua/rate_limit.lua")));
 
 /// Builder for the Redis Store
 pub struct RedisStoreBuilder {
@@ -143,6 +433,29 @@ pub struct RedisStoreBuilder {
     poll_interval: u64,
     /// Connector's ILP Address. Used to insert `Child` accounts as
     node_ilp_address: Address,
+    /// Interval at which the store will snapshot the balances of all accounts
+    balance_snapshot_interval: Option<u64>,
+    /// Interval at which the store will release the escrows of expired packets
+    escrow_sweep_interval: u64,
+    /// TTL (in seconds) of idempotency records
+    idempotency_ttl: u64,
+    /// TTL (in seconds) of uncredited settlement amounts
+    uncredited_settlement_ttl: Option<u64>,
+    /// Time (in seconds) for which the entries of the audit log are kept
+    audit_log_retention: Option<u64>,
+    /// Interval at which the store will run the compaction task
+    compaction_interval: Option<u64>,
+    /// Key manager which wraps the key encrypting the account secrets.
+    /// If it is not set, the secrets are encrypted with a key derived from the secret
+    key_manager: Option<Arc<dyn KeyManager>>,
+    /// Key manager which wrapped the current data key, used when rotating it
+    previous_key_manager: Option<Arc<dyn KeyManager>>,
+    /// Read-only replicas which serve account lookups and route table loads
+    read_replicas: Vec<ConnectionInfo>,
+    /// Max lag (in milliseconds) of a replica behind the primary for it to be read from
+    max_replica_staleness: u64,
+    /// Whether the store spawns the intervals of its maintenance tasks
+    maintenance_intervals: bool,
 }
 
 impl RedisStoreBuilder {
@@ -153,6 +466,17 @@ impl RedisStoreBuilder {
             secret,
             poll_interval: DEFAULT_POLL_INTERVAL,
             node_ilp_address: DEFAULT_ILP_ADDRESS.clone(),
+            balance_snapshot_interval: None,
+            escrow_sweep_interval: DEFAULT_ESCROW_SWEEP_INTERVAL,
+            idempotency_ttl: DEFAULT_IDEMPOTENCY_TTL,
+            uncredited_settlement_ttl: None,
+            audit_log_retention: None,
+            compaction_interval: None,
+            key_manager: None,
+            previous_key_manager: None,
+            read_replicas: Vec::new(),
+            max_replica_staleness: DEFAULT_MAX_REPLICA_STALENESS,
+            maintenance_intervals: true,
         }
     }
 
@@ -168,17 +492,154 @@ impl RedisStoreBuilder {
         self
     }
 
+    /// Sets the interval (in milliseconds) at which the store will snapshot the
+    /// balances of all accounts. If it is not set, snapshots are only taken on demand
+    pub fn balance_snapshot_interval(&mut self, balance_snapshot_interval: u64) -> &mut Self {
+        self.balance_snapshot_interval = Some(balance_snapshot_interval);
+        self
+    }
+
+    /// Sets the interval (in milliseconds) at which the store will release the escrows
+    /// of the packets which expired without their Fulfill or Reject being accounted for
+    /// back to the sending accounts. Defaults to 10000ms (10 seconds)
+    pub fn escrow_sweep_interval(&mut self, escrow_sweep_interval: u64) -> &mut Self {
+        self.escrow_sweep_interval = escrow_sweep_interval;
+        self
+    }
+
+    /// Sets the time (in seconds) after which idempotency records of the settlement
+    /// API expire, which must be greater than 0. Defaults to 86400 seconds (24 hours)
+    pub fn idempotency_ttl(&mut self, idempotency_ttl: u64) -> &mut Self {
+        self.idempotency_ttl = idempotency_ttl;
+        self
+    }
+
+    /// Sets the time (in seconds) after the last change to an account's uncredited
+    /// settlement amount after which it expires, which must be greater than 0. If it is
+    /// not set, uncredited amounts are kept until they are credited or the account is deleted
+    pub fn uncredited_settlement_ttl(&mut self, uncredited_settlement_ttl: u64) -> &mut Self {
+        self.uncredited_settlement_ttl = Some(uncredited_settlement_ttl);
+        self
+    }
+
+    /// Sets the time (in seconds) for which the entries of the audit log of the admin API
+    /// are kept. Older entries are deleted when new ones are appended and when the store
+    /// is compacted. If it is not set, the entries are kept forever
+    pub fn audit_log_retention(&mut self, audit_log_retention: u64) -> &mut Self {
+        self.audit_log_retention = Some(audit_log_retention);
+        self
+    }
+
+    /// Sets the interval (in milliseconds) at which the store will compact its data,
+    /// by applying the configured TTLs to records written without one and deleting
+    /// the uncredited settlement amounts of deleted accounts
+    pub fn compaction_interval(&mut self, compaction_interval: u64) -> &mut Self {
+        self.compaction_interval = Some(compaction_interval);
+        self
+    }
+
+    /// Do not spawn the intervals of the balance snapshots, escrow sweeps and compaction,
+    /// because the caller runs `snapshot_balances`, `release_expired_escrows` and `compact`
+    /// on a schedule of its own. The intervals configured on the builder are then ignored
+    pub fn without_maintenance_intervals(&mut self) -> &mut Self {
+        self.maintenance_intervals = false;
+        self
+    }
+
+    /// Sets the key manager used for the envelope encryption of the account secrets.
+    /// The secrets are encrypted with a random data key, which is stored wrapped by the
+    /// key manager. When it is first set, the secrets which were encrypted with the key
+    /// derived from the store's secret are re-encrypted with a new data key.
+    pub fn key_manager(&mut self, key_manager: Arc<dyn KeyManager>) -> &mut Self {
+        self.key_manager = Some(key_manager);
+        self
+    }
+
+    /// Sets the key manager which wrapped the current data key. This is only used by
+    /// [`rotate_encryption_key`](#method.rotate_encryption_key), to switch between key managers.
+    pub fn previous_key_manager(&mut self, previous_key_manager: Arc<dyn KeyManager>) -> &mut Self {
+        self.previous_key_manager = Some(previous_key_manager);
+        self
+    }
+
+    /// Sets read-only replicas of the Redis database. Account lookups and route table loads
+    /// are spread over the replicas which lag behind the primary by less than the
+    /// [`max_replica_staleness`](#method.max_replica_staleness), while all writes (including
+    /// the balance scripts) go to the primary. Lookups of accounts which a replica does not
+    /// know (yet) fall back to the primary.
+    pub fn read_replicas(&mut self, read_replicas: Vec<ConnectionInfo>) -> &mut Self {
+        self.read_replicas = read_replicas;
+        self
+    }
+
+    /// Sets the max lag (in milliseconds) of a replica behind the primary for it to
+    /// be used for reads. Defaults to 1000ms (1 second)
+    pub fn max_replica_staleness(&mut self, max_replica_staleness: u64) -> &mut Self {
+        self.max_replica_staleness = max_replica_staleness;
+        self
+    }
+
+    /// Re-encrypts the secrets of all accounts with a newly generated data key, wrapped
+    /// by the configured key manager. The current data key is unwrapped with the previous
+    /// key manager, if one was set, or the configured one otherwise.
+    /// Returns the number of accounts whose secrets were re-encrypted.
+    ///
+    /// No node may use the store while the key is being rotated.
+    pub async fn rotate_encryption_key(&mut self) -> Result<usize, ()> {
+        let key_manager = self.key_manager.clone().ok_or_else(|| {
+            error!("A key manager must be configured to rotate the encryption key")
+        })?;
+        let previous_key_manager = self
+            .previous_key_manager
+            .clone()
+            .unwrap_or_else(|| key_manager.clone());
+        let (_, legacy_decryption_key) = generate_keys(&self.secret[..]);
+        self.secret.zeroize();
+
+        let mut connection = RedisReconnect::connect(self.redis_url.clone())
+            .map_err(|_| ())
+            .await?;
+        let decryption_key =
+            match unwrap_stored_data_key(&mut connection, previous_key_manager.as_ref()).await? {
+                Some((_, decryption_key)) => decryption_key,
+                None => legacy_decryption_key,
+            };
+        let (_, reencrypted) = replace_data_key(
+            &mut connection,
+            key_manager.as_ref(),
+            &decryption_key.expose_secret().0,
+        )
+        .await?;
+        info!(
+            "Rotated the data encryption key and re-encrypted the secrets of {} accounts",
+            reencrypted
+        );
+        Ok(reencrypted)
+    }
+
     /// Connects to the Redis Store
     ///
     /// Specifically
     /// 1. Generates encryption and decryption keys
     /// 1. Connects to the redis store (ensuring that it reconnects in case of drop)
+    /// 1. Unwraps the data encryption key (if a key manager was configured)
+    /// 1. Hashes the incoming tokens which were stored encrypted by earlier versions
+    /// 1. Connects to the read replicas (if any were configured) and starts checking their lag
     /// 1. Gets the Node address assigned to us by our parent (if it exists)
     /// 1. Starts polling for routing table updates
+    /// 1. Starts snapshotting the balances (if a snapshot interval was configured)
+    /// 1. Starts releasing the escrows of expired packets
+    /// 1. Starts compacting the store (if a compaction interval was configured)
     /// 1. Spawns a thread to notify incoming payments over WebSockets
     pub async fn connect(&mut self) -> Result<RedisStore, ()> {
+        // Redis rejects the expiries of 0 (and EXPIRE 0 deletes the key right away)
+        if self.idempotency_ttl == 0 || self.uncredited_settlement_ttl == Some(0) {
+            error!("The idempotency and uncredited settlement TTLs must be greater than 0");
+            return Err(());
+        }
         let redis_info = self.redis_url.clone();
         let (encryption_key, decryption_key) = generate_keys(&self.secret[..]);
+        let token_hashing_key = generate_token_hashing_key(&self.secret[..]);
         self.secret.zeroize(); // clear the secret after it has been used for key generation
         let poll_interval = self.poll_interval;
         let ilp_address = self.node_ilp_address.clone();
@@ -189,6 +650,41 @@ impl RedisStoreBuilder {
         let mut connection = RedisReconnect::connect(redis_info.clone())
             .map_err(|_| ())
             .await?;
+        let (encryption_key, decryption_key) = if let Some(ref key_manager) = self.key_manager {
+            match unwrap_stored_data_key(&mut connection, key_manager.as_ref()).await? {
+                Some(keys) => keys,
+                None => {
+                    // The secrets were encrypted with the key derived from the secret
+                    // before the key manager was configured
+                    warn!(
+                        "No data encryption key found. Re-encrypting the account secrets with a new data key wrapped by {}",
+                        key_manager.key_id()
+                    );
+                    let (keys, _) = replace_data_key(
+                        &mut connection,
+                        key_manager.as_ref(),
+                        &decryption_key.expose_secret().0,
+                    )
+                    .await?;
+                    keys
+                }
+            }
+        } else {
+            (encryption_key, decryption_key)
+        };
+        hash_legacy_incoming_tokens(
+            &mut connection,
+            &decryption_key.expose_secret().0,
+            &token_hashing_key.expose_secret().0,
+        )
+        .await?;
+        let replicas = if self.read_replicas.is_empty() {
+            ReadReplicas::none()
+        } else {
+            ReadReplicas::connect(self.read_replicas.clone(), self.max_replica_staleness)
+                .map_err(|err| error!("Error connecting to Redis read replica: {:?}", err))
+                .await?
+        };
         let mut sub_connection = client
             .get_connection()
             .map_err(|err| error!("Error connecting subscription client to Redis: {:?}", err))?;
@@ -210,6 +706,30 @@ impl RedisStoreBuilder {
         } else {
             ilp_address
         };
+        // Start with the exchange rates which were last set by any node using the store
+        let exchange_rates: HashMap<String, f64> = connection
+            .hgetall(RATES_KEY)
+            .map_err(|err| error!("Error loading exchange rates: {:?}", err))
+            .await?;
+        // The pins apply from the start, whether or not the node polls a rate provider
+        let pinned_rates: HashMap<String, f64> = connection
+            .hgetall(PINNED_RATES_KEY)
+            .map_err(|err| error!("Error loading pinned exchange rates: {:?}", err))
+            .await?;
+        let fee_policies: HashMap<String, String> = connection
+            .hgetall(FEE_POLICIES_KEY)
+            .map_err(|err| error!("Error loading fee policies: {:?}", err))
+            .await?;
+        let fee_policies: HashMap<String, FeePolicy> = fee_policies
+            .into_iter()
+            .filter_map(|(pair, policy)| match serde_json::from_str(&policy) {
+                Ok(policy) => Some((pair, policy)),
+                Err(err) => {
+                    warn!("Ignoring invalid fee policy for {}: {}", pair, err);
+                    None
+                }
+            })
+            .collect();
 
         let (all_payment_publisher, _) = broadcast::channel::<PaymentNotification>(256);
 
@@ -218,17 +738,53 @@ impl RedisStoreBuilder {
             connection,
             subscriptions: Arc::new(Mutex::new(HashMap::new())),
             payment_publisher: all_payment_publisher,
-            exchange_rates: Arc::new(RwLock::new(HashMap::new())),
+            exchange_rates: Arc::new(RwLock::new(exchange_rates)),
+            pinned_rates: Arc::new(RwLock::new(pinned_rates)),
+            fee_policies: Arc::new(RwLock::new(fee_policies)),
             routes: Arc::new(RwLock::new(Arc::new(HashMap::new()))),
             encryption_key: Arc::new(encryption_key),
             decryption_key: Arc::new(decryption_key),
+            token_hashing_key: Arc::new(token_hashing_key),
+            idempotency_ttl: self.idempotency_ttl,
+            uncredited_settlement_ttl: self.uncredited_settlement_ttl,
+            audit_log_retention: self.audit_log_retention,
+            replicas,
+            throttled: Arc::new(RwLock::new(HashMap::new())),
+            packet_leases: Arc::new(RwLock::new(HashMap::new())),
         };
 
+        // Check how far the read replicas lag behind, so that
+        // reads only go to those within the staleness bound
+        if !store.replicas.is_empty() {
+            let replicas = store.replicas.clone();
+            let connection_clone = Arc::downgrade(&store.connection.conn);
+            let redis_info = store.connection.redis_info.clone();
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(replicas.check_interval());
+                loop {
+                    interval.tick().await;
+                    if let Some(conn) = connection_clone.upgrade() {
+                        let mut primary = RedisReconnect {
+                            conn,
+                            redis_info: redis_info.clone(),
+                        };
+                        if let Err(err) = replicas.check_staleness(&mut primary).await {
+                            error!("Error checking the lag of the read replicas: {}", err);
+                        }
+                    } else {
+                        debug!("Not checking read replicas anymore because connection was closed");
+                        break;
+                    }
+                }
+            });
+        }
+
         // Poll for routing table updates
         // Note: if this behavior changes, make sure to update the Drop implementation
         let connection_clone = Arc::downgrade(&store.connection.conn);
         let redis_info = store.connection.redis_info.clone();
         let routing_table = store.routes.clone();
+        let replicas = store.replicas.clone();
 
         let poll_routes = async move {
             let mut interval = tokio::time::interval(Duration::from_millis(poll_interval));
@@ -236,15 +792,13 @@ impl RedisStoreBuilder {
             loop {
                 interval.tick().await;
                 if let Some(conn) = connection_clone.upgrade() {
-                    let _ = update_routes(
-                        RedisReconnect {
-                            conn,
-                            redis_info: redis_info.clone(),
-                        },
-                        routing_table.clone(),
-                    )
-                    .map_err(|err| error!("{}", err))
-                    .await;
+                    let connection = replicas.connection().unwrap_or_else(|| RedisReconnect {
+                        conn,
+                        redis_info: redis_info.clone(),
+                    });
+                    let _ = update_routes(connection, routing_table.clone())
+                        .map_err(|err| error!("{}", err))
+                        .await;
                 } else {
                     debug!("Not polling routes anymore because connection was closed");
                     break;
@@ -254,12 +808,95 @@ impl RedisStoreBuilder {
         };
         tokio::spawn(poll_routes);
 
+        // Periodically snapshot the balances so that verifying them does not
+        // require replaying the full balance journal of every account
+        if let (true, Some(balance_snapshot_interval)) =
+            (self.maintenance_intervals, self.balance_snapshot_interval)
+        {
+            let store_clone = store.clone();
+            let connection_clone = Arc::downgrade(&store.connection.conn);
+            tokio::spawn(async move {
+                let mut interval =
+                    tokio::time::interval(Duration::from_millis(balance_snapshot_interval));
+                loop {
+                    interval.tick().await;
+                    if connection_clone.upgrade().is_none() {
+                        debug!("Not snapshotting balances anymore because connection was closed");
+                        break;
+                    }
+                    match store_clone.snapshot_balances().await {
+                        Ok(snapshotted) => {
+                            trace!("Snapshotted balances of {} accounts", snapshotted)
+                        }
+                        Err(err) => error!("Error snapshotting balances: {}", err),
+                    }
+                }
+            });
+        }
+
+        // Periodically release the escrows of packets whose Fulfill or Reject was never
+        // accounted for, for example because the node which forwarded them crashed.
+        // The first sweep waits for an interval so that packets which were in flight
+        // while the node restarted can still be fulfilled or rejected
+        let escrow_sweep_interval = Duration::from_millis(self.escrow_sweep_interval.max(1));
+        let store_clone = store.clone();
+        let connection_clone = Arc::downgrade(&store.connection.conn);
+        let escrow_sweeps = async move {
+            let mut interval = tokio::time::interval_at(
+                tokio::time::Instant::now() + escrow_sweep_interval,
+                escrow_sweep_interval,
+            );
+            loop {
+                interval.tick().await;
+                if connection_clone.upgrade().is_none() {
+                    debug!("Not releasing expired escrows anymore because connection was closed");
+                    break;
+                }
+                match store_clone.release_expired_escrows().await {
+                    Ok(0) => {}
+                    Ok(released) => warn!(
+                        "Released the escrows of {} packets which expired without being fulfilled or rejected",
+                        released
+                    ),
+                    Err(err) => error!("Error releasing expired escrows: {}", err),
+                }
+            }
+        };
+        if self.maintenance_intervals {
+            tokio::spawn(escrow_sweeps);
+        }
+
+        // Periodically reclaim the records which would otherwise never expire
+        if let (true, Some(compaction_interval)) =
+            (self.maintenance_intervals, self.compaction_interval)
+        {
+            let store_clone = store.clone();
+            let connection_clone = Arc::downgrade(&store.connection.conn);
+            tokio::spawn(async move {
+                let mut interval =
+                    tokio::time::interval(Duration::from_millis(compaction_interval));
+                loop {
+                    interval.tick().await;
+                    if connection_clone.upgrade().is_none() {
+                        debug!("Not compacting the store anymore because connection was closed");
+                        break;
+                    }
+                    if let Err(err) = store_clone.compact().await {
+                        error!("Error compacting the store: {}", err);
+                    }
+                }
+            });
+        }
+
         // Here we spawn a worker thread to listen for incoming messages on Redis pub/sub,
         // running a callback for each message received.
         // This currently must be a thread rather than a task due to the redis-rs driver
         // not yet supporting asynchronous subscriptions (see https://github.com/mitsuhiko/redis-rs/issues/183).
         let subscriptions_clone = store.subscriptions.clone();
         let payment_publisher = store.payment_publisher.clone();
+        let exchange_rates = store.exchange_rates.clone();
+        let pinned_rates = store.pinned_rates.clone();
+        let fee_policies = store.fee_policies.clone();
         std::thread::spawn(move || {
             #[allow(clippy::cognitive_complexity)]
             let sub_status =
@@ -296,6 +933,30 @@ impl RedisStoreBuilder {
                         } else {
                             error!("Invalid Uuid in channel name: {}", channel_name);
                         }
+                    } else if channel_name == EXCHANGE_RATES_CHANNEL {
+                        match serde_json::from_slice::<HashMap<String, f64>>(msg.get_payload_bytes()) {
+                            Ok(rates) => {
+                                trace!("Received exchange rates from Redis subscription: {:?}", rates);
+                                *exchange_rates.write() = rates;
+                            }
+                            Err(e) => error!("Failed to parse exchange rates from subscription: {}", e),
+                        }
+                    } else if channel_name == PINNED_RATES_CHANNEL {
+                        match serde_json::from_slice::<HashMap<String, f64>>(msg.get_payload_bytes()) {
+                            Ok(rates) => {
+                                trace!("Received pinned rates from Redis subscription: {:?}", rates);
+                                *pinned_rates.write() = rates;
+                            }
+                            Err(e) => error!("Failed to parse pinned rates from subscription: {}", e),
+                        }
+                    } else if channel_name == FEE_POLICIES_CHANNEL {
+                        match serde_json::from_slice::<HashMap<String, FeePolicy>>(msg.get_payload_bytes()) {
+                            Ok(policies) => {
+                                trace!("Received fee policies from Redis subscription: {:?}", policies);
+                                *fee_policies.write() = policies;
+                            }
+                            Err(e) => error!("Failed to parse fee policies from subscription: {}", e),
+                        }
                     } else {
                         warn!("Ignoring unexpected message from Redis subscription for channel: {}", channel_name);
                     }
@@ -327,7 +988,14 @@ pub struct RedisStore {
     subscriptions: Arc<Mutex<HashMap<Uuid, Vec<UnboundedSender<PaymentNotification>>>>>,
     /// A subscriber to all payment notifications, exposed via a WebSocket
     payment_publisher: broadcast::Sender<PaymentNotification>,
+    /// The exchange rates last set by the rate provider or the API
     exchange_rates: Arc<RwLock<HashMap<String, f64>>>,
+    /// The rates pinned by the operators, which override the exchange rates until they
+    /// are unpinned
+    pinned_rates: Arc<RwLock<HashMap<String, f64>>>,
+    /// Fee policies of the asset pairs, kept in memory so that they can
+    /// be returned synchronously while packets are converted
+    fee_policies: Arc<RwLock<HashMap<String, FeePolicy>>>,
     /// The store keeps the routing table in memory so that it can be returned
     /// synchronously while the Router is processing packets.
     /// The outer `Arc<RwLock>` is used so that we can update the stored routing
@@ -339,9 +1007,177 @@ pub struct RedisStore {
     encryption_key: Arc<Secret<EncryptionKey>>,
     /// Decryption Key to provide cleartext data to users
     decryption_key: Arc<Secret<DecryptionKey>>,
+    /// Key the incoming tokens are hashed with, since they only need to be verified
+    token_hashing_key: Arc<Secret<TokenHashingKey>>,
+    /// TTL (in seconds) of idempotency records
+    idempotency_ttl: u64,
+    /// TTL (in seconds) of uncredited settlement amounts
+    uncredited_settlement_ttl: Option<u64>,
+    /// Time (in seconds) for which the entries of the audit log are kept
+    audit_log_retention: Option<u64>,
+    /// Read-only replicas used for lookups which tolerate slightly stale data
+    replicas: ReadReplicas,
+    /// Accounts whose packet rate limits are exhausted, with when their buckets have
+    /// tokens again
+    throttled: Arc<RwLock<HashMap<Uuid, Instant>>>,
+    /// Packet tokens taken from the rate limits of accounts in advance, with until when
+    /// they may be used
+    packet_leases: Arc<RwLock<HashMap<Uuid, (u64, Instant)>>>,
+}
+
+/// Number of keys reclaimed by a single compaction run
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct CompactionSummary {
+    /// Keys which had no TTL and were set to expire
+    pub expiring: u64,
+    /// Keys which were deleted
+    pub deleted: u64,
 }
 
 impl RedisStore {
+    /// Compacts the store, so that its memory usage does not grow unbounded:
+    ///
+    /// 1. Idempotency records without a TTL are set to expire after the configured TTL
+    /// 1. Uncredited settlement amounts of accounts which no longer exist are deleted
+    /// 1. Uncredited settlement amounts without a TTL are set to expire (if a TTL was configured)
+    /// 1. Audit log entries older than the retention period are deleted (if one was configured)
+    ///
+    /// The number of reclaimed keys is recorded in the `store.compaction.expiring` and
+    /// `store.compaction.deleted` metrics.
+    pub async fn compact(&self) -> Result<CompactionSummary, RedisError> {
+        let mut summary = CompactionSummary::default();
+        let mut connection = self.connection.clone();
+
+        let mut cursor = 0u64;
+        loop {
+            let (next_cursor, keys): (u64, Vec<String>) = cmd("SCAN")
+                .arg(cursor)
+                .arg("MATCH")
+                .arg(prefixed_idempotency_key("*"))
+                .arg("COUNT")
+                .arg(COMPACTION_SCAN_COUNT)
+                .query_async(&mut connection)
+                .await?;
+            let expiring = self
+                .expire_keys_without_ttl(&keys, self.idempotency_ttl)
+                .await?;
+            record_compaction("idempotency", expiring, 0);
+            summary.expiring += expiring;
+
+            cursor = next_cursor;
+            if cursor == 0 {
+                break;
+            }
+        }
+
+        let mut cursor = 0u64;
+        loop {
+            let (next_cursor, keys): (u64, Vec<String>) = cmd("SCAN")
+                .arg(cursor)
+                .arg("MATCH")
+                .arg(uncredited_amount_key("*"))
+                .arg("COUNT")
+                .arg(COMPACTION_SCAN_COUNT)
+                .query_async(&mut connection)
+                .await?;
+
+            let (expiring, deleted) = self.compact_uncredited_amounts(keys).await?;
+            record_compaction("uncredited_settlement", expiring, deleted);
+            summary.expiring += expiring;
+            summary.deleted += deleted;
+
+            cursor = next_cursor;
+            if cursor == 0 {
+                break;
+            }
+        }
+
+        let trimmed = self.trim_audit_log().await?;
+        record_compaction("audit_log", 0, trimmed);
+
+        debug!(
+            "Compacted the store: {} keys set to expire, {} keys deleted, {} audit log entries deleted",
+            summary.expiring, summary.deleted, trimmed
+        );
+        Ok(summary)
+    }
+
+    /// Deletes the provided uncredited settlement amounts if their account does not exist
+    /// anymore (since they can never be credited) and sets the rest to expire (if a TTL
+    /// was configured). Returns the number of keys which were set to expire and deleted.
+    async fn compact_uncredited_amounts(
+        &self,
+        keys: Vec<String>,
+    ) -> Result<(u64, u64), RedisError> {
+        if keys.is_empty() {
+            return Ok((0, 0));
+        }
+        let mut connection = self.connection.clone();
+        let mut pipe = redis_crate::pipe();
+        for key in keys.iter() {
+            let account_id = &key[uncredited_amount_key("").len()..];
+            pipe.exists(format!("accounts:{}", account_id));
+        }
+        let exists: Vec<bool> = pipe.query_async(&mut connection).await?;
+        let (keys, orphaned): (Vec<_>, Vec<_>) = keys
+            .into_iter()
+            .zip(exists)
+            .partition(|(_, exists)| *exists);
+
+        let deleted = if orphaned.is_empty() {
+            0
+        } else {
+            let orphaned: Vec<String> = orphaned.into_iter().map(|(key, _)| key).collect();
+            connection.del(orphaned).await?
+        };
+        let expiring = if let Some(ttl) = self.uncredited_settlement_ttl {
+            let keys: Vec<String> = keys.into_iter().map(|(key, _)| key).collect();
+            self.expire_keys_without_ttl(&keys, ttl).await?
+        } else {
+            0
+        };
+        Ok((expiring, deleted))
+    }
+
+    /// Deletes the audit log entries older than the retention period, if one was
+    /// configured. Returns the number of deleted entries.
+    async fn trim_audit_log(&self) -> Result<u64, RedisError> {
+        match audit_log_cutoff(self.audit_log_retention) {
+            Some(cutoff) => {
+                self.connection
+                    .clone()
+                    .zrembyscore(AUDIT_LOG_KEY, "-inf", format!("({}", cutoff))
+                    .await
+            }
+            None => Ok(0),
+        }
+    }
+
+    /// Sets the provided keys to expire after the TTL (in seconds), unless they already
+    /// have one. Returns the number of keys which were set to expire.
+    async fn expire_keys_without_ttl(&self, keys: &[String], ttl: u64) -> Result<u64, RedisError> {
+        if keys.is_empty() {
+            return Ok(0);
+        }
+        let mut connection = self.connection.clone();
+        let mut pipe = redis_crate::pipe();
+        for key in keys {
+            pipe.ttl(key);
+        }
+        // A TTL of -1 means that the key exists but has no expiry
+        let ttls: Vec<i64> = pipe.query_async(&mut connection).await?;
+        let mut pipe = redis_crate::pipe();
+        let mut expiring = 0;
+        for (key, _) in keys.iter().zip(ttls).filter(|(_, ttl)| *ttl == -1) {
+            pipe.expire(key, ttl as usize).ignore();
+            expiring += 1;
+        }
+        if expiring > 0 {
+            pipe.query_async::<_, ()>(&mut connection).await?;
+        }
+        Ok(expiring)
+    }
+
     /// Gets all the account ids from Redis
     async fn get_all_accounts_ids(&self) -> Result<Vec<Uuid>, NodeStoreError> {
         let mut connection = self.connection.clone();
@@ -391,8 +1227,11 @@ impl RedisStore {
         .ignore();
 
         // Set balance-related details
-        pipe.hset_multiple(&id, &[("balance", 0), ("prepaid_amount", 0)])
-            .ignore();
+        pipe.hset_multiple(
+            &id,
+            &[("balance", 0), ("prepaid_amount", 0), ("in_flight", 0)],
+        )
+        .ignore();
 
         if account.should_send_routes() {
             pipe.sadd("send_routes_to", RedisAccountId(account.id))
@@ -565,7 +1404,51 @@ impl RedisStore {
             pipe.hset(accounts_key(id), "settle_to", settle_to);
         }
 
-        pipe.query_async(&mut self.connection.clone()).await?;
+        for (name, limit) in &[
+            (
+                "packets_per_second_limit",
+                settings.packets_per_second_limit.map(u64::from),
+            ),
+            (
+                "packets_per_minute_limit",
+                settings.packets_per_minute_limit.map(u64::from),
+            ),
+            ("amount_per_minute_limit", settings.amount_per_minute_limit),
+        ] {
+            if *limit == Some(0) {
+                return Err(NodeStoreError::InvalidAccount(
+                    CreateAccountError::InvalidRateLimit((*name).to_owned()),
+                ));
+            }
+        }
+
+        if let Some(limit) = settings.packets_per_second_limit {
+            pipe.hset(accounts_key(id), "packets_per_second_limit", limit);
+        }
+
+        if let Some(limit) = settings.packets_per_minute_limit {
+            pipe.hset(accounts_key(id), "packets_per_minute_limit", limit);
+        }
+
+        if let Some(limit) = settings.amount_per_minute_limit {
+            if limit > i64::MAX as u64 {
+                return Err(NodeStoreError::InvalidAccount(
+                    CreateAccountError::ParamTooLarge("amount_per_minute_limit".to_owned()),
+                ));
+            }
+            pipe.hset(accounts_key(id), "amount_per_minute_limit", limit);
+        }
+
+        if let Some(max_packet_amount) = settings.max_packet_amount {
+            pipe.hset(accounts_key(id), "max_packet_amount", max_packet_amount);
+        }
+
+        if let Some(max_packet_size) = settings.max_packet_size {
+            pipe.hset(accounts_key(id), "max_packet_size", max_packet_size);
+        }
+
+        pipe.query_async::<_, ()>(&mut self.connection.clone())
+            .await?;
 
         // return the updated account
         self.redis_get_account(id).await
@@ -618,8 +1501,12 @@ impl RedisStore {
 
         pipe.hdel(ROUTES_KEY, account.ilp_address.to_bytes().to_vec())
             .ignore();
+        pipe.hdel(LEARNED_ROUTES_KEY, RedisAccountId(account.id))
+            .ignore();
 
         pipe.del(uncredited_amount_key(id));
+        pipe.del(transfer_usage_key(id, "send"));
+        pipe.del(transfer_usage_key(id, "receive"));
 
         let mut connection = self.connection.clone();
         pipe.query_async(&mut connection).await?;
@@ -627,6 +1514,38 @@ impl RedisStore {
         debug!("Deleted account {}", account.id);
         Ok(encrypted)
     }
+
+    /// Loads the account with the provided username from a read replica, or from the primary
+    /// if no replica is usable or the replica's copy is missing or is rejected by
+    /// `is_current` (e.g. because its credentials were changed after it was replicated)
+    async fn load_account_from_username(
+        &self,
+        username: &Username,
+        is_current: impl Fn(&Account) -> bool,
+    ) -> Result<Option<Account>, RedisError> {
+        if let Some(mut replica) = self.replicas.connection() {
+            let account: Option<AccountWithEncryptedTokens> = ACCOUNT_FROM_USERNAME
+                .arg(username.as_ref())
+                .invoke_async(&mut replica)
+                .await
+                .unwrap_or_else(|err| {
+                    warn!("Error loading account from read replica: {}", err);
+                    None
+                });
+            if let Some(account) = account {
+                let account = account.decrypt_tokens(&self.decryption_key.expose_secret().0);
+                if is_current(&account) {
+                    return Ok(Some(account));
+                }
+            }
+        }
+
+        let account: Option<AccountWithEncryptedTokens> = ACCOUNT_FROM_USERNAME
+            .arg(username.as_ref())
+            .invoke_async(&mut self.connection.clone())
+            .await?;
+        Ok(account.map(|account| account.decrypt_tokens(&self.decryption_key.expose_secret().0)))
+    }
 }
 
 #[async_trait]
@@ -638,49 +1557,78 @@ impl AccountStore for RedisStore {
         &self,
         account_ids: Vec<Uuid>,
     ) -> Result<Vec<Account>, AccountStoreError> {
-        let num_accounts = account_ids.len();
-        let mut script = LOAD_ACCOUNTS.prepare_invoke();
-        for id in account_ids.iter() {
-            script.arg(id.to_string());
-        }
+        instrument(BACKEND, "get_accounts", async move {
+            let num_accounts = account_ids.len();
+            let mut script = LOAD_ACCOUNTS.prepare_invoke();
+            for id in account_ids.iter() {
+                script.arg(id.to_string());
+            }
 
-        // Need to clone the connection here to avoid lifetime errors
-        let accounts: Vec<AccountWithEncryptedTokens> =
-            script.invoke_async(&mut self.connection.clone()).await?;
+            // Read from a replica if one is within the staleness bound, falling back to
+            // the primary if the replica does not have all of the accounts (yet)
+            let mut accounts: Option<Vec<AccountWithEncryptedTokens>> = None;
+            if let Some(mut replica) = self.replicas.connection() {
+                match script.invoke_async(&mut replica).await {
+                    Ok(loaded) => accounts = Some(loaded),
+                    Err(err) => warn!("Error loading accounts from read replica: {}", err),
+                }
+            }
+            let accounts = match accounts {
+                Some(accounts) if accounts.len() == num_accounts => accounts,
+                // Need to clone the connection here to avoid lifetime errors
+                _ => script.invoke_async(&mut self.connection.clone()).await?,
+            };
 
-        // Decrypt the accounts. TODO: This functionality should be
-        // decoupled from redis so that it gets reused by the other backends
-        if accounts.len() == num_accounts {
-            let accounts = accounts
-                .into_iter()
-                .map(|account| account.decrypt_tokens(&self.decryption_key.expose_secret().0))
-                .collect();
-            Ok(accounts)
-        } else {
-            Err(AccountStoreError::WrongLength {
-                expected: num_accounts,
-                actual: accounts.len(),
-            })
-        }
+            // Decrypt the accounts. TODO: This functionality should be
+            // decoupled from redis so that it gets reused by the other backends
+            if accounts.len() == num_accounts {
+                let accounts = accounts
+                    .into_iter()
+                    .map(|account| account.decrypt_tokens(&self.decryption_key.expose_secret().0))
+                    .collect();
+                Ok(accounts)
+            } else {
+                Err(AccountStoreError::WrongLength {
+                    expected: num_accounts,
+                    actual: accounts.len(),
+                })
+            }
+        })
+        .await
     }
 
     async fn get_account_id_from_username(
         &self,
         username: &Username,
     ) -> Result<Uuid, AccountStoreError> {
-        let username = username.clone();
-        let id: Option<RedisAccountId> = self
-            .connection
-            .clone()
-            .hget("usernames", username.as_ref())
-            .await?;
-        match id {
-            Some(rid) => Ok(rid.0),
-            None => {
-                debug!("Username not found: {}", username);
-                Err(AccountStoreError::AccountNotFound(username.to_string()))
+        instrument(BACKEND, "get_account_id_from_username", async move {
+            let username = username.clone();
+            let mut id: Option<RedisAccountId> = None;
+            if let Some(mut replica) = self.replicas.connection() {
+                id = replica
+                    .hget("usernames", username.as_ref())
+                    .await
+                    .unwrap_or_else(|err| {
+                        warn!("Error loading account id from read replica: {}", err);
+                        None
+                    });
             }
-        }
+            if id.is_none() {
+                id = self
+                    .connection
+                    .clone()
+                    .hget("usernames", username.as_ref())
+                    .await?;
+            }
+            match id {
+                Some(rid) => Ok(rid.0),
+                None => {
+                    debug!("Username not found: {}", username);
+                    Err(AccountStoreError::AccountNotFound(username.to_string()))
+                }
+            }
+        })
+        .await
     }
 }
 
@@ -743,90 +1691,592 @@ impl BalanceStore for RedisStore {
     /// Returns the balance **from the account holder's perspective**, meaning the sum of
     /// the Payable Balance and Pending Outgoing minus the Receivable Balance and the Pending Incoming.
     async fn get_balance(&self, account_id: Uuid) -> Result<i64, BalanceStoreError> {
-        let values: Vec<i64> = self
-            .connection
-            .clone()
-            .hget(accounts_key(account_id), &["balance", "prepaid_amount"])
-            .await?;
-
-        let balance = values[0];
-        let prepaid_amount = values[1];
-        Ok(balance + prepaid_amount)
+        instrument(BACKEND, "get_balance", async move {
+            let values: Vec<i64> = self
+                .connection
+                .clone()
+                .hget(accounts_key(account_id), &["balance", "prepaid_amount"])
+                .await?;
+
+            let balance = values[0];
+            let prepaid_amount = values[1];
+            Ok(balance + prepaid_amount)
+        })
+        .await
     }
 
-    async fn update_balances_for_prepare(
-        &self,
-        from_account_id: Uuid,
-        incoming_amount: u64,
-    ) -> Result<(), BalanceStoreError> {
-        // Don't do anything if the amount was 0
-        if incoming_amount == 0 {
-            return Ok(());
-        }
+    async fn get_in_flight_amount(&self, account_id: Uuid) -> Result<u64, BalanceStoreError> {
+        instrument(BACKEND, "get_in_flight_amount", async move {
+            let in_flight: Option<u64> = self
+                .connection
+                .clone()
+                .hget(accounts_key(account_id), "in_flight")
+                .await?;
+            Ok(in_flight.unwrap_or_default())
+        })
+        .await
+    }
 
-        let balance: i64 = PROCESS_PREPARE
-            .arg(RedisAccountId(from_account_id))
-            .arg(incoming_amount)
-            .invoke_async(&mut self.connection.clone())
-            .await?;
+    async fn update_balances_for_prepare(&self, escrow: &Escrow) -> Result<(), BalanceStoreError> {
+        let escrow = *escrow;
+        instrument(BACKEND, "update_balances_for_prepare", async move {
+            // Don't do anything if the amount was 0
+            if escrow.incoming_amount == 0 {
+                return Ok(());
+            }
 
-        trace!(
-            "Processed prepare with incoming amount: {}. Account {} has balance (including prepaid amount): {} ",
-            incoming_amount, from_account_id, balance
-        );
-        Ok(())
+            let (exceeded_limit, balance, limit): (String, i64, i64) = PROCESS_PREPARE
+                .arg(RedisAccountId(escrow.from_account_id))
+                .arg(escrow.incoming_amount)
+                .arg(journal_timestamp())
+                .arg(escrow.id.to_string())
+                .arg(RedisAccountId(escrow.to_account_id))
+                .arg(escrow.outgoing_amount)
+                .arg(escrow.expires_at)
+                .invoke_async(&mut self.connection.clone())
+                .await?;
+            // If a limit would have been exceeded, the other values describe it
+            check_credit_limits(&escrow, &exceeded_limit, balance, limit)?;
+
+            trace!(
+                "Processed prepare with incoming amount: {}. Account {} has balance (including prepaid amount): {} ",
+                escrow.incoming_amount, escrow.from_account_id, balance
+            );
+            Ok(())
+        })
+        .await
     }
 
     async fn update_balances_for_fulfill(
         &self,
-        to_account_id: Uuid,
-        outgoing_amount: u64,
+        escrow: &Escrow,
     ) -> Result<(i64, u64), BalanceStoreError> {
-        let (balance, amount_to_settle): (i64, u64) = PROCESS_FULFILL
-            .arg(RedisAccountId(to_account_id))
-            .arg(outgoing_amount)
-            .invoke_async(&mut self.connection.clone())
-            .await?;
+        let escrow = *escrow;
+        instrument(BACKEND, "update_balances_for_fulfill", async move {
+            let (balance, amount_to_settle): (i64, u64) = PROCESS_FULFILL
+                .arg(RedisAccountId(escrow.to_account_id))
+                .arg(escrow.outgoing_amount)
+                .arg(journal_timestamp())
+                .arg(escrow.id.to_string())
+                .arg(RedisAccountId(escrow.from_account_id))
+                .arg(escrow.incoming_amount)
+                .invoke_async(&mut self.connection.clone())
+                .await?;
 
-        trace!(
-            "Processed fulfill for account {} for outgoing amount {}. Fulfill call result: {} {}",
-            to_account_id,
-            outgoing_amount,
-            balance,
-            amount_to_settle,
-        );
-        Ok((balance, amount_to_settle))
+            trace!(
+                "Processed fulfill for account {} for outgoing amount {}. Fulfill call result: {} {}",
+                escrow.to_account_id,
+                escrow.outgoing_amount,
+                balance,
+                amount_to_settle,
+            );
+            Ok((balance, amount_to_settle))
+        })
+        .await
+    }
+
+    async fn update_balances_for_reject(&self, escrow: &Escrow) -> Result<(), BalanceStoreError> {
+        let escrow = *escrow;
+        instrument(BACKEND, "update_balances_for_reject", async move {
+            if escrow.incoming_amount == 0 {
+                return Ok(());
+            }
+
+            let balance: i64 = PROCESS_REJECT
+                .arg(RedisAccountId(escrow.from_account_id))
+                .arg(escrow.incoming_amount)
+                .arg(journal_timestamp())
+                .arg(escrow.id.to_string())
+                .invoke_async(&mut self.connection.clone())
+                .await?;
+
+            trace!(
+                "Processed reject for incoming amount: {}. Account {} has balance (including prepaid amount): {}",
+                escrow.incoming_amount, escrow.from_account_id, balance
+            );
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn update_balances_for_settle_to(
+        &self,
+        account_id: Uuid,
+    ) -> Result<(i64, u64), BalanceStoreError> {
+        instrument(BACKEND, "update_balances_for_settle_to", async move {
+            // The same as a fulfill of 0 to the account, without an escrow to commit
+            let (balance, amount_to_settle): (i64, u64) = PROCESS_FULFILL
+                .arg(RedisAccountId(account_id))
+                .arg(0)
+                .arg(journal_timestamp())
+                .arg("")
+                .arg(RedisAccountId(account_id))
+                .arg(0)
+                .invoke_async(&mut self.connection.clone())
+                .await?;
+
+            trace!(
+                "Moved the balance of account {} to its settle_to: {} (amount to settle: {})",
+                account_id,
+                balance,
+                amount_to_settle,
+            );
+            Ok((balance, amount_to_settle))
+        })
+        .await
+    }
+
+    async fn release_expired_escrows(&self) -> Result<usize, BalanceStoreError> {
+        instrument(BACKEND, "release_expired_escrows", async move {
+            let expired_before = journal_timestamp().saturating_sub(ESCROW_RELEASE_GRACE_PERIOD);
+            let mut released = 0;
+            loop {
+                let batch: usize = RELEASE_EXPIRED_ESCROWS
+                    .arg(expired_before)
+                    .arg(ESCROW_RELEASE_BATCH_SIZE)
+                    .arg(journal_timestamp())
+                    .invoke_async(&mut self.connection.clone())
+                    .await?;
+                released += batch;
+                if batch < ESCROW_RELEASE_BATCH_SIZE {
+                    break;
+                }
+            }
+            Ok(released)
+        })
+        .await
+    }
+}
+
+/// A single entry of an account's balance journal, as written by the Lua scripts
+#[derive(Debug, Deserialize)]
+struct JournalEntry {
+    balance_change: i64,
+    prepaid_change: i64,
+}
+
+impl RedisStore {
+    /// Replays the balance journal of the provided account on top of its latest
+    /// snapshot. Also returns the position of the journal which was replayed up to,
+    /// which counts the entries trimmed by the snapshots.
+    async fn replay_balance_journal(
+        &self,
+        account_id: Uuid,
+    ) -> Result<(BalanceVerification, usize), BalanceStoreError> {
+        instrument(BACKEND, "replay_balance_journal", async move {
+            // Load the balances, the snapshot and the journal atomically, so
+            // that no balance change or snapshot can happen in between
+            let mut pipe = redis_crate::pipe();
+            pipe.atomic()
+                .exists(accounts_key(account_id))
+                .hget(accounts_key(account_id), &["balance", "prepaid_amount"])
+                .hgetall(balance_snapshot_key(account_id))
+                .lrange(balance_journal_key(account_id), 0, -1);
+            let (exists, balances, snapshot, journal): (
+                bool,
+                Vec<Option<i64>>,
+                HashMap<String, i64>,
+                Vec<String>,
+            ) = pipe.query_async(&mut self.connection.clone()).await?;
+            if !exists {
+                return Err(BalanceStoreError::AccountNotFound(account_id.to_string()));
+            }
+            let expected_balance = snapshot.get("balance").cloned().unwrap_or(0);
+            let expected_prepaid_amount = snapshot.get("prepaid_amount").cloned().unwrap_or(0);
+            let trimmed = snapshot.get("journal_trimmed").cloned().unwrap_or(0) as usize;
+            let offset = snapshot.get("journal_length").cloned().unwrap_or(0) as usize;
+            // Only the entries written after the snapshot are replayed (the snapshots
+            // taken before the journal was trimmed left the entries before them)
+            let entries = journal.get(offset.saturating_sub(trimmed)..).unwrap_or(&[]);
+
+            let mut verification = BalanceVerification {
+                account_id,
+                balance: balances.first().cloned().flatten().unwrap_or(0),
+                prepaid_amount: balances.get(1).cloned().flatten().unwrap_or(0),
+                expected_balance,
+                expected_prepaid_amount,
+                replayed_entries: entries.len() as u64,
+            };
+            for entry in entries.iter() {
+                let entry: JournalEntry = serde_json::from_str(entry).map_err(|err| {
+                    error!(
+                        "Invalid entry in the balance journal of account {}: {} ({})",
+                        account_id, entry, err
+                    );
+                    BalanceStoreError::Other(Box::new(err))
+                })?;
+                verification.expected_balance += entry.balance_change;
+                verification.expected_prepaid_amount += entry.prepaid_change;
+            }
+
+            Ok((verification, trimmed + journal.len()))
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl LiquidityStore for RedisStore {
+    async fn get_liquidity(&self, account_id: Uuid) -> Result<Liquidity, BalanceStoreError> {
+        instrument(BACKEND, "get_liquidity", async move {
+            let (exists, values): (bool, Vec<Option<i64>>) = redis_crate::pipe()
+                .exists(accounts_key(account_id))
+                .hget(
+                    accounts_key(account_id),
+                    &[
+                        "balance",
+                        "prepaid_amount",
+                        "payable_in_flight",
+                        "in_flight",
+                        "payable_limit",
+                        "receivable_limit",
+                    ],
+                )
+                .query_async(&mut self.connection.clone())
+                .await?;
+            if !exists {
+                return Err(BalanceStoreError::AccountNotFound(account_id.to_string()));
+            }
+
+            let value = |index: usize| values[index].unwrap_or_default();
+            let limit = |index: usize| values[index].map(|limit| limit as u64);
+            Ok(Liquidity::new(
+                value(0) + value(1),
+                value(2) as u64,
+                value(3) as u64,
+                CreditLimits {
+                    payable_limit: limit(4),
+                    receivable_limit: limit(5),
+                },
+            ))
+        })
+        .await
+    }
+
+    async fn set_credit_limits(
+        &self,
+        account_id: Uuid,
+        limits: CreditLimits,
+    ) -> Result<Liquidity, BalanceStoreError> {
+        instrument(BACKEND, "set_credit_limits", async move {
+            let mut connection = self.connection.clone();
+            let exists: bool = connection.exists(accounts_key(account_id)).await?;
+            if !exists {
+                return Err(BalanceStoreError::AccountNotFound(account_id.to_string()));
+            }
+
+            // The limits which are not set are removed
+            let mut pipe = redis_crate::pipe();
+            pipe.atomic();
+            for (field, limit) in &[
+                ("payable_limit", limits.payable_limit),
+                ("receivable_limit", limits.receivable_limit),
+            ] {
+                match limit {
+                    Some(limit) => pipe.hset(accounts_key(account_id), *field, *limit).ignore(),
+                    None => pipe.hdel(accounts_key(account_id), *field).ignore(),
+                };
+            }
+            pipe.query_async::<_, ()>(&mut connection).await?;
+            debug!("Set credit limits of account {}: {:?}", account_id, limits);
+
+            self.get_liquidity(account_id).await
+        })
+        .await
+    }
+}
+
+/// Parses the buckets (`h:<hour>` or `d:<day>` fields) of a window from a usage hash
+fn window_buckets(usage: &HashMap<String, u64>, tag: &str) -> Vec<(u64, u64)> {
+    usage
+        .iter()
+        .filter_map(|(field, amount)| {
+            let mut parts = field.splitn(2, ':');
+            if parts.next() != Some(tag) {
+                return None;
+            }
+            parts
+                .next()
+                .and_then(|index| u64::from_str(index).ok())
+                .map(|index| (index, *amount))
+        })
+        .collect()
+}
+
+#[async_trait]
+impl TransferLimitStore for RedisStore {
+    async fn get_transfer_usage(
+        &self,
+        account_id: Uuid,
+    ) -> Result<TransferUsage, BalanceStoreError> {
+        instrument(BACKEND, "get_transfer_usage", async move {
+            #[allow(clippy::type_complexity)]
+            let (exists, limits, send, receive): (
+                bool,
+                Vec<Option<u64>>,
+                HashMap<String, u64>,
+                HashMap<String, u64>,
+            ) = redis_crate::pipe()
+                .exists(accounts_key(account_id))
+                .hget(
+                    accounts_key(account_id),
+                    &[
+                        "daily_send_limit",
+                        "monthly_send_limit",
+                        "daily_receive_limit",
+                        "monthly_receive_limit",
+                    ],
+                )
+                .hgetall(transfer_usage_key(account_id, "send"))
+                .hgetall(transfer_usage_key(account_id, "receive"))
+                .query_async(&mut self.connection.clone())
+                .await?;
+            if !exists {
+                return Err(BalanceStoreError::AccountNotFound(account_id.to_string()));
+            }
+
+            let now = journal_timestamp();
+            let daily = |usage: &HashMap<String, u64>, limit| {
+                WindowUsage::new(
+                    TransferWindow::Daily,
+                    limit,
+                    &window_buckets(usage, "h"),
+                    now,
+                )
+            };
+            let monthly = |usage: &HashMap<String, u64>, limit| {
+                WindowUsage::new(
+                    TransferWindow::Monthly,
+                    limit,
+                    &window_buckets(usage, "d"),
+                    now,
+                )
+            };
+            Ok(TransferUsage {
+                daily_send: daily(&send, limits[0]),
+                monthly_send: monthly(&send, limits[1]),
+                daily_receive: daily(&receive, limits[2]),
+                monthly_receive: monthly(&receive, limits[3]),
+            })
+        })
+        .await
+    }
+
+    async fn set_transfer_limits(
+        &self,
+        account_id: Uuid,
+        limits: TransferLimits,
+    ) -> Result<TransferUsage, BalanceStoreError> {
+        instrument(BACKEND, "set_transfer_limits", async move {
+            let mut connection = self.connection.clone();
+            let exists: bool = connection.exists(accounts_key(account_id)).await?;
+            if !exists {
+                return Err(BalanceStoreError::AccountNotFound(account_id.to_string()));
+            }
+
+            // The limits which are not set are removed
+            let mut pipe = redis_crate::pipe();
+            pipe.atomic();
+            for (field, limit) in &[
+                ("daily_send_limit", limits.daily_send_limit),
+                ("monthly_send_limit", limits.monthly_send_limit),
+                ("daily_receive_limit", limits.daily_receive_limit),
+                ("monthly_receive_limit", limits.monthly_receive_limit),
+            ] {
+                match limit {
+                    Some(limit) => pipe.hset(accounts_key(account_id), *field, *limit).ignore(),
+                    None => pipe.hdel(accounts_key(account_id), *field).ignore(),
+                };
+            }
+            pipe.query_async::<_, ()>(&mut connection).await?;
+            debug!(
+                "Set transfer limits of account {}: {:?}",
+                account_id, limits
+            );
+
+            self.get_transfer_usage(account_id).await
+        })
+        .await
+    }
+
+    async fn charge_transfer_limits(
+        &self,
+        charge: TransferCharge,
+    ) -> Result<TransferCharge, BalanceStoreError> {
+        instrument(BACKEND, "charge_transfer_limits", async move {
+            let (direction, window, limit, used, reset_at): (String, String, u64, u64, u64) =
+                CHARGE_TRANSFER_LIMITS
+                    .key(accounts_key(charge.from_account_id))
+                    .key(transfer_usage_key(charge.from_account_id, "send"))
+                    .key(accounts_key(charge.to_account_id))
+                    .key(transfer_usage_key(charge.to_account_id, "receive"))
+                    .arg(charge.incoming_amount)
+                    .arg(charge.outgoing_amount)
+                    .arg(TransferWindow::Daily.bucket(charge.charged_at))
+                    .arg(TransferWindow::Monthly.bucket(charge.charged_at))
+                    .invoke_async(&mut self.connection.clone())
+                    .await?;
+            if direction == "ok" {
+                // The flags of the counted directions are returned in place of the limit details
+                trace!(
+                    "Charged transfer limits of accounts {} and {}",
+                    charge.from_account_id,
+                    charge.to_account_id
+                );
+                return Ok(TransferCharge {
+                    sent: limit == 1,
+                    received: used == 1,
+                    ..charge
+                });
+            }
+
+            let (account_id, direction) = if direction == "send" {
+                (charge.from_account_id, "send")
+            } else {
+                (charge.to_account_id, "receive")
+            };
+            Err(BalanceStoreError::TransferLimitExceeded {
+                account: account_id.to_string(),
+                direction,
+                window: if window == "daily" {
+                    TransferWindow::Daily.as_str()
+                } else {
+                    TransferWindow::Monthly.as_str()
+                },
+                limit,
+                used,
+                reset_at,
+            })
+        })
+        .await
     }
 
-    async fn update_balances_for_reject(
+    async fn refund_transfer_limits(
         &self,
-        from_account_id: Uuid,
-        incoming_amount: u64,
+        charge: &TransferCharge,
     ) -> Result<(), BalanceStoreError> {
-        if incoming_amount == 0 {
-            return Ok(());
-        }
+        instrument(BACKEND, "refund_transfer_limits", async move {
+            REFUND_TRANSFER_LIMITS
+                .key(transfer_usage_key(charge.from_account_id, "send"))
+                .key(transfer_usage_key(charge.to_account_id, "receive"))
+                .arg(if charge.sent {
+                    charge.incoming_amount
+                } else {
+                    0
+                })
+                .arg(if charge.received {
+                    charge.outgoing_amount
+                } else {
+                    0
+                })
+                .arg(TransferWindow::Daily.bucket(charge.charged_at))
+                .arg(TransferWindow::Monthly.bucket(charge.charged_at))
+                .invoke_async::<_, ()>(&mut self.connection.clone())
+                .await?;
+            Ok(())
+        })
+        .await
+    }
+}
 
-        let balance: i64 = PROCESS_REJECT
-            .arg(RedisAccountId(from_account_id))
-            .arg(incoming_amount)
-            .invoke_async(&mut self.connection.clone())
-            .await?;
+#[async_trait]
+impl BalanceJournalStore for RedisStore {
+    async fn snapshot_balances(&self) -> Result<usize, BalanceStoreError> {
+        instrument(BACKEND, "snapshot_balances", async move {
+            let account_ids = self
+                .get_all_accounts_ids()
+                .await
+                .map_err(|err| BalanceStoreError::Other(Box::new(err)))?;
+            if account_ids.is_empty() {
+                return Ok(0);
+            }
 
-        trace!(
-            "Processed reject for incoming amount: {}. Account {} has balance (including prepaid amount): {}",
-            incoming_amount, from_account_id, balance
-        );
+            let mut script = SNAPSHOT_BALANCES.prepare_invoke();
+            script.arg(journal_timestamp());
+            for id in account_ids.iter() {
+                script.arg(RedisAccountId(*id));
+            }
+            let snapshotted: usize = script.invoke_async(&mut self.connection.clone()).await?;
+            debug!("Snapshotted balances of {} accounts", snapshotted);
+            Ok(snapshotted)
+        })
+        .await
+    }
 
-        Ok(())
+    async fn verify_balances(&self) -> Result<Vec<BalanceVerification>, BalanceStoreError> {
+        instrument(BACKEND, "verify_balances", async move {
+            let account_ids = self
+                .get_all_accounts_ids()
+                .await
+                .map_err(|err| BalanceStoreError::Other(Box::new(err)))?;
+
+            let mut verifications = Vec::with_capacity(account_ids.len());
+            for account_id in account_ids {
+                match self.replay_balance_journal(account_id).await {
+                    Ok((verification, _)) => {
+                        if !verification.is_consistent() {
+                            warn!(
+                                "Balance of account {} does not match its journal: {:?}",
+                                account_id, verification
+                            );
+                        }
+                        verifications.push(verification);
+                    }
+                    // The account may have been deleted since we loaded the account ids
+                    Err(BalanceStoreError::AccountNotFound(_)) => continue,
+                    Err(err) => return Err(err),
+                }
+            }
+            Ok(verifications)
+        })
+        .await
+    }
+
+    async fn recover_balance(
+        &self,
+        account_id: Uuid,
+    ) -> Result<BalanceVerification, BalanceStoreError> {
+        instrument(BACKEND, "recover_balance", async move {
+            let (verification, journal_length) = self.replay_balance_journal(account_id).await?;
+            let status: u8 = RECOVER_BALANCE
+                .arg(RedisAccountId(account_id))
+                .arg(verification.expected_balance)
+                .arg(verification.expected_prepaid_amount)
+                .arg(journal_length)
+                .arg(journal_timestamp())
+                .invoke_async(&mut self.connection.clone())
+                .await?;
+            match status {
+                0 => {
+                    warn!(
+                        "Recovered balance of account {} from its journal: {:?}",
+                        account_id, verification
+                    );
+                    Ok(verification)
+                }
+                1 => Err(BalanceStoreError::AccountNotFound(account_id.to_string())),
+                _ => Err(BalanceStoreError::ConcurrentModification(
+                    account_id.to_string(),
+                )),
+            }
+        })
+        .await
     }
 }
 
 impl ExchangeRateStore for RedisStore {
     fn get_exchange_rates(&self, asset_codes: &[&str]) -> Result<Vec<f64>, ExchangeRateStoreError> {
+        let exchange_rates = self.exchange_rates.read();
+        let pinned_rates = self.pinned_rates.read();
         let rates: Vec<f64> = asset_codes
             .iter()
-            .filter_map(|code| (*self.exchange_rates.read()).get(*code).cloned())
+            .filter_map(|code| {
+                pinned_rates
+                    .get(*code)
+                    .or_else(|| exchange_rates.get(*code))
+                    .cloned()
+            })
             .collect();
         if rates.len() == asset_codes.len() {
             Ok(rates)
@@ -840,19 +2290,165 @@ impl ExchangeRateStore for RedisStore {
     }
 
     fn get_all_exchange_rates(&self) -> Result<HashMap<String, f64>, ExchangeRateStoreError> {
-        Ok((*self.exchange_rates.read()).clone())
+        let mut rates = (*self.exchange_rates.read()).clone();
+        rates.extend(self.pinned_rates.read().clone());
+        Ok(rates)
     }
 
+    /// Sets the rates, which the pinned rates keep overriding. The rates set for the pinned
+    /// assets apply again once they are unpinned.
     fn set_exchange_rates(
         &self,
         rates: HashMap<String, f64>,
     ) -> Result<(), ExchangeRateStoreError> {
-        // TODO publish rate updates through a pubsub mechanism to support horizontally scaling nodes
+        // Save the rates for nodes which start later and publish them to the running ones
+        let mut pipe = redis_crate::pipe();
+        pipe.atomic().del(RATES_KEY).ignore();
+        if !rates.is_empty() {
+            let entries: Vec<(&String, f64)> =
+                rates.iter().map(|(code, rate)| (code, *rate)).collect();
+            pipe.hset_multiple(RATES_KEY, &entries).ignore();
+        }
+        let payload = serde_json::to_string(&rates)
+            .map_err(|err| ExchangeRateStoreError::Other(Box::new(err)))?;
+        pipe.publish(EXCHANGE_RATES_CHANNEL, payload).ignore();
+        let mut connection = self.connection.clone();
+        tokio::spawn(async move {
+            pipe.query_async::<_, ()>(&mut connection)
+                .map_err(|err| error!("Error saving exchange rates: {:?}", err))
+                .await
+        });
+
         (*self.exchange_rates.write()) = rates;
         Ok(())
     }
 }
 
+impl RedisStore {
+    /// Publishes the pinned rates to the running nodes, and applies them to this one right
+    /// away rather than when the message comes back
+    async fn publish_pinned_rates(
+        &self,
+        pinned_rates: HashMap<String, f64>,
+    ) -> Result<(), ExchangeRateStoreError> {
+        let payload = serde_json::to_string(&pinned_rates)
+            .map_err(|err| ExchangeRateStoreError::Other(Box::new(err)))?;
+        self.connection
+            .clone()
+            .publish::<_, _, ()>(PINNED_RATES_CHANNEL, payload)
+            .map_err(|err| ExchangeRateStoreError::Other(Box::new(err)))
+            .await?;
+        (*self.pinned_rates.write()) = pinned_rates;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl PinnedRateStore for RedisStore {
+    async fn get_pinned_rates(&self) -> Result<HashMap<String, f64>, ExchangeRateStoreError> {
+        instrument(BACKEND, "get_pinned_rates", async move {
+            self.connection
+                .clone()
+                .hgetall(PINNED_RATES_KEY)
+                .map_err(|err| ExchangeRateStoreError::Other(Box::new(err)))
+                .await
+        })
+        .await
+    }
+
+    async fn pin_rate(&self, asset_code: &str, rate: f64) -> Result<(), ExchangeRateStoreError> {
+        instrument(BACKEND, "pin_rate", async move {
+            let (pinned_rates,): (HashMap<String, f64>,) = redis_crate::pipe()
+                .atomic()
+                .hset(PINNED_RATES_KEY, asset_code, rate)
+                .ignore()
+                .hgetall(PINNED_RATES_KEY)
+                .query_async(&mut self.connection.clone())
+                .map_err(|err| ExchangeRateStoreError::Other(Box::new(err)))
+                .await?;
+            // The pinned rate applies right away, rather than on the next poll
+            self.publish_pinned_rates(pinned_rates).await?;
+            debug!("Pinned the rate of {} to {}", asset_code, rate);
+            Ok(())
+        })
+        .await
+    }
+
+    async fn unpin_rate(&self, asset_code: &str) -> Result<Option<f64>, ExchangeRateStoreError> {
+        instrument(BACKEND, "unpin_rate", async move {
+            let (rate, pinned_rates): (Option<f64>, HashMap<String, f64>) = redis_crate::pipe()
+                .atomic()
+                .hget(PINNED_RATES_KEY, asset_code)
+                .hdel(PINNED_RATES_KEY, asset_code)
+                .ignore()
+                .hgetall(PINNED_RATES_KEY)
+                .query_async(&mut self.connection.clone())
+                .map_err(|err| ExchangeRateStoreError::Other(Box::new(err)))
+                .await?;
+            if rate.is_some() {
+                // The rate last set for the asset applies again right away
+                self.publish_pinned_rates(pinned_rates).await?;
+                debug!("Unpinned the rate of {}", asset_code);
+            }
+            Ok(rate)
+        })
+        .await
+    }
+}
+
+impl FeePolicyStore for RedisStore {
+    fn get_fee_policy(&self, from: &str, to: &str) -> Option<FeePolicy> {
+        self.fee_policies
+            .read()
+            .get(&fee_policy_pair(from, to))
+            .cloned()
+    }
+
+    fn get_all_fee_policies(&self) -> Result<HashMap<String, FeePolicy>, FeePolicyStoreError> {
+        Ok(self.fee_policies.read().clone())
+    }
+
+    fn set_fee_policies(
+        &self,
+        policies: HashMap<String, FeePolicy>,
+    ) -> Result<(), FeePolicyStoreError> {
+        let mut normalized = HashMap::with_capacity(policies.len());
+        for (pair, policy) in policies {
+            let mut assets = pair.split('/');
+            match (assets.next(), assets.next(), assets.next()) {
+                (Some(from), Some(to), None) if !from.is_empty() && !to.is_empty() => {
+                    normalized.insert(
+                        fee_policy_pair(&from.to_uppercase(), &to.to_uppercase()),
+                        policy,
+                    );
+                }
+                _ => return Err(FeePolicyStoreError::InvalidPair(pair)),
+            }
+        }
+
+        // Save the policies for nodes which start later and publish them to the running ones
+        let mut pipe = redis_crate::pipe();
+        pipe.atomic().del(FEE_POLICIES_KEY).ignore();
+        for (pair, policy) in normalized.iter() {
+            let policy = serde_json::to_string(policy)
+                .map_err(|err| FeePolicyStoreError::Other(Box::new(err)))?;
+            pipe.hset(FEE_POLICIES_KEY, pair, policy).ignore();
+        }
+        let payload = serde_json::to_string(&normalized)
+            .map_err(|err| FeePolicyStoreError::Other(Box::new(err)))?;
+        pipe.publish(FEE_POLICIES_CHANNEL, payload).ignore();
+        let mut connection = self.connection.clone();
+        tokio::spawn(async move {
+            pipe.query_async::<_, ()>(&mut connection)
+                .map_err(|err| error!("Error saving fee policies: {:?}", err))
+                .await
+        });
+
+        (*self.fee_policies.write()) = normalized;
+        Ok(())
+    }
+}
+
 #[async_trait]
 impl BtpStore for RedisStore {
     type Account = Account;
@@ -862,51 +2458,73 @@ impl BtpStore for RedisStore {
         username: &Username,
         token: &str,
     ) -> Result<Self::Account, BtpStoreError> {
-        // TODO make sure it can't do script injection!
-        // TODO cache the result so we don't hit redis for every packet (is that
-        // necessary if redis is often used as a cache?)
-        let account: Option<AccountWithEncryptedTokens> = ACCOUNT_FROM_USERNAME
-            .arg(username.as_ref())
-            .invoke_async(&mut self.connection.clone())
-            .await?;
+        instrument(BACKEND, "get_account_from_btp_auth", async move {
+            // TODO make sure it can't do script injection!
+            // TODO cache the result so we don't hit redis for every packet (is that
+            // necessary if redis is often used as a cache?)
+            let hashing_key = &self.token_hashing_key.expose_secret().0;
+            let account = self
+                .load_account_from_username(username, |account| {
+                    account.verify_btp_incoming_token(hashing_key, token)
+                })
+                .await?;
 
-        if let Some(account) = account {
-            let account = account.decrypt_tokens(&self.decryption_key.expose_secret().0);
-            if let Some(ref t) = account.ilp_over_btp_incoming_token {
-                let t = t.expose_secret();
-                if t.as_ref() == token.as_bytes() {
-                    Ok(account)
+            if let Some(account) = account {
+                if account.ilp_over_btp_incoming_token.is_some() {
+                    if account.verify_btp_incoming_token(hashing_key, token) {
+                        Ok(account)
+                    } else {
+                        debug!(
+                            "Found account {} but BTP auth token was wrong",
+                            account.username
+                        );
+                        Err(BtpStoreError::Unauthorized(username.to_string()))
+                    }
                 } else {
                     debug!(
-                        "Found account {} but BTP auth token was wrong",
+                        "Account {} does not have an incoming btp token configured",
                         account.username
                     );
                     Err(BtpStoreError::Unauthorized(username.to_string()))
                 }
             } else {
-                debug!(
-                    "Account {} does not have an incoming btp token configured",
-                    account.username
-                );
-                Err(BtpStoreError::Unauthorized(username.to_string()))
+                warn!("No account found with BTP token");
+                Err(BtpStoreError::AccountNotFound(username.to_string()))
             }
-        } else {
-            warn!("No account found with BTP token");
-            Err(BtpStoreError::AccountNotFound(username.to_string()))
-        }
+        })
+        .await
     }
 
     async fn get_btp_outgoing_accounts(&self) -> Result<Vec<Self::Account>, BtpStoreError> {
-        let account_ids: Vec<RedisAccountId> =
-            self.connection.clone().smembers("btp_outgoing").await?;
-        let account_ids: Vec<Uuid> = account_ids.into_iter().map(|id| id.0).collect();
+        instrument(BACKEND, "get_btp_outgoing_accounts", async move {
+            let account_ids: Vec<RedisAccountId> =
+                self.connection.clone().smembers("btp_outgoing").await?;
+            let account_ids: Vec<Uuid> = account_ids.into_iter().map(|id| id.0).collect();
 
-        if account_ids.is_empty() {
-            return Ok(Vec::new());
-        }
+            if account_ids.is_empty() {
+                return Ok(Vec::new());
+            }
+
+            let accounts = self.get_accounts(account_ids).await?;
+            Ok(accounts)
+        })
+        .await
+    }
 
-        let accounts = self.get_accounts(account_ids).await?;
-        Ok(accounts)
+    async fn get_sub_accounts(
+        &self,
+        account: &Self::Account,
+    ) -> Result<Vec<Self::Account>, BtpStoreError> {
+        instrument(BACKEND, "get_sub_accounts", async move {
+            let accounts = self.get_all_accounts().await?;
+            Ok(accounts
+                .into_iter()
+                .filter(|sub_account| {
+                    sub_account.transport_account.as_ref() == Some(&account.username)
+                })
+                .collect())
+        })
+        .await
     }
 }
 
@@ -921,28 +2539,57 @@ impl HttpStore for RedisStore {
         username: &Username,
         token: &str,
     ) -> Result<Self::Account, HttpStoreError> {
-        // TODO make sure it can't do script injection!
-        let account: Option<AccountWithEncryptedTokens> = ACCOUNT_FROM_USERNAME
-            .arg(username.as_ref())
-            .invoke_async(&mut self.connection.clone())
-            .await?;
+        instrument(BACKEND, "get_account_from_http_auth", async move {
+            // TODO make sure it can't do script injection!
+            let hashing_key = &self.token_hashing_key.expose_secret().0;
+            let account = self
+                .load_account_from_username(username, |account| {
+                    account.verify_http_incoming_token(hashing_key, token)
+                })
+                .await?;
 
-        if let Some(account) = account {
-            let account = account.decrypt_tokens(&self.decryption_key.expose_secret().0);
-            if let Some(ref t) = account.ilp_over_http_incoming_token {
-                let t = t.expose_secret();
-                if t.as_ref() == token.as_bytes() {
-                    Ok(account)
+            if let Some(account) = account {
+                if account.ilp_over_http_incoming_token.is_some() {
+                    if account.verify_http_incoming_token(hashing_key, token) {
+                        Ok(account)
+                    } else {
+                        Err(HttpStoreError::Unauthorized(username.to_string()))
+                    }
                 } else {
                     Err(HttpStoreError::Unauthorized(username.to_string()))
                 }
             } else {
-                Err(HttpStoreError::Unauthorized(username.to_string()))
+                warn!("No account found with given HTTP auth");
+                Err(HttpStoreError::AccountNotFound(username.to_string()))
             }
-        } else {
-            warn!("No account found with given HTTP auth");
-            Err(HttpStoreError::AccountNotFound(username.to_string()))
-        }
+        })
+        .await
+    }
+
+    /// Checks if the HTTP message signature of the request verifies with the stored
+    /// verification key of the account, and if so, returns the account
+    async fn get_account_from_http_signature(
+        &self,
+        username: &Username,
+        request: &SignedRequest<'_>,
+    ) -> Result<Self::Account, HttpStoreError> {
+        instrument(BACKEND, "get_account_from_http_signature", async move {
+            let verifies = |account: &Account| {
+                account
+                    .ilp_over_http_verification_key
+                    .as_deref()
+                    .map_or(false, |key| request.verify(key))
+            };
+            match self.load_account_from_username(username, verifies).await? {
+                Some(account) if verifies(&account) => Ok(account),
+                Some(_) => Err(HttpStoreError::Unauthorized(username.to_string())),
+                None => {
+                    warn!("No account found with given HTTP signature");
+                    Err(HttpStoreError::AccountNotFound(username.to_string()))
+                }
+            }
+        })
+        .await
     }
 }
 
@@ -960,24 +2607,31 @@ impl NodeStore for RedisStore {
         &self,
         account: AccountDetails,
     ) -> Result<Self::Account, NodeStoreError> {
-        let id = Uuid::new_v4();
-        let account = Account::try_from(id, account, self.get_ilp_address())
-            .map_err(NodeStoreError::InvalidAccount)?;
-        debug!(
-            "Generated account id for {}: {}",
-            account.username, account.id
-        );
-        let encrypted = account
-            .clone()
-            .encrypt_tokens(&self.encryption_key.expose_secret().0);
+        instrument(BACKEND, "insert_account", async move {
+            let id = Uuid::new_v4();
+            let account = Account::try_from(id, account, self.get_ilp_address())
+                .map_err(NodeStoreError::InvalidAccount)?;
+            debug!(
+                "Generated account id for {}: {}",
+                account.username, account.id
+            );
+            let encrypted = account.clone().encrypt_tokens(
+                &self.encryption_key.expose_secret().0,
+                &self.token_hashing_key.expose_secret().0,
+            );
 
-        self.redis_insert_account(&encrypted).await?;
-        Ok(account)
+            self.redis_insert_account(&encrypted).await?;
+            Ok(account)
+        })
+        .await
     }
 
     async fn delete_account(&self, id: Uuid) -> Result<Account, NodeStoreError> {
-        let account = self.redis_delete_account(id).await?;
-        Ok(account.decrypt_tokens(&self.decryption_key.expose_secret().0))
+        instrument(BACKEND, "delete_account", async move {
+            let account = self.redis_delete_account(id).await?;
+            Ok(account.decrypt_tokens(&self.decryption_key.expose_secret().0))
+        })
+        .await
     }
 
     async fn update_account(
@@ -985,19 +2639,23 @@ impl NodeStore for RedisStore {
         id: Uuid,
         account: AccountDetails,
     ) -> Result<Self::Account, NodeStoreError> {
-        let account = Account::try_from(id, account, self.get_ilp_address())
-            .map_err(NodeStoreError::InvalidAccount)?;
+        instrument(BACKEND, "update_account", async move {
+            let account = Account::try_from(id, account, self.get_ilp_address())
+                .map_err(NodeStoreError::InvalidAccount)?;
 
-        debug!(
-            "Generated account id for {}: {}",
-            account.username, account.id
-        );
-        let encrypted = account
-            .clone()
-            .encrypt_tokens(&self.encryption_key.expose_secret().0);
+            debug!(
+                "Generated account id for {}: {}",
+                account.username, account.id
+            );
+            let encrypted = account.clone().encrypt_tokens(
+                &self.encryption_key.expose_secret().0,
+                &self.token_hashing_key.expose_secret().0,
+            );
 
-        self.redis_update_account(&encrypted).await?;
-        Ok(account)
+            self.redis_update_account(&encrypted).await?;
+            Ok(account)
+        })
+        .await
     }
 
     async fn modify_account_settings(
@@ -1005,105 +2663,154 @@ impl NodeStore for RedisStore {
         id: Uuid,
         settings: AccountSettings,
     ) -> Result<Self::Account, NodeStoreError> {
-        let settings = EncryptedAccountSettings {
-            settle_to: settings.settle_to,
-            settle_threshold: settings.settle_threshold,
-            ilp_over_btp_url: settings.ilp_over_btp_url,
-            ilp_over_http_url: settings.ilp_over_http_url,
-            ilp_over_btp_incoming_token: settings.ilp_over_btp_incoming_token.map(|token| {
-                encrypt_token(
-                    &self.encryption_key.expose_secret().0,
-                    token.expose_secret().as_bytes(),
-                )
-                .freeze()
-            }),
-            ilp_over_http_incoming_token: settings.ilp_over_http_incoming_token.map(|token| {
-                encrypt_token(
-                    &self.encryption_key.expose_secret().0,
-                    token.expose_secret().as_bytes(),
-                )
-                .freeze()
-            }),
-            ilp_over_btp_outgoing_token: settings.ilp_over_btp_outgoing_token.map(|token| {
-                encrypt_token(
-                    &self.encryption_key.expose_secret().0,
-                    token.expose_secret().as_bytes(),
-                )
-                .freeze()
-            }),
-            ilp_over_http_outgoing_token: settings.ilp_over_http_outgoing_token.map(|token| {
-                encrypt_token(
-                    &self.encryption_key.expose_secret().0,
-                    token.expose_secret().as_bytes(),
-                )
-                .freeze()
-            }),
-        };
+        instrument(BACKEND, "modify_account_settings", async move {
+            let settings = EncryptedAccountSettings {
+                settle_to: settings.settle_to,
+                settle_threshold: settings.settle_threshold,
+                packets_per_second_limit: settings.packets_per_second_limit,
+                packets_per_minute_limit: settings.packets_per_minute_limit,
+                amount_per_minute_limit: settings.amount_per_minute_limit,
+                max_packet_amount: settings.max_packet_amount,
+                max_packet_size: settings.max_packet_size,
+                ilp_over_btp_url: settings.ilp_over_btp_url,
+                ilp_over_http_url: settings.ilp_over_http_url,
+                ilp_over_btp_incoming_token: settings.ilp_over_btp_incoming_token.map(|token| {
+                    hash_token(
+                        &self.token_hashing_key.expose_secret().0,
+                        token.expose_secret().as_bytes(),
+                    )
+                    .freeze()
+                }),
+                ilp_over_http_incoming_token: settings.ilp_over_http_incoming_token.map(|token| {
+                    hash_token(
+                        &self.token_hashing_key.expose_secret().0,
+                        token.expose_secret().as_bytes(),
+                    )
+                    .freeze()
+                }),
+                ilp_over_btp_outgoing_token: settings.ilp_over_btp_outgoing_token.map(|token| {
+                    encrypt_token(
+                        &self.encryption_key.expose_secret().0,
+                        token.expose_secret().as_bytes(),
+                    )
+                    .freeze()
+                }),
+                ilp_over_http_outgoing_token: settings.ilp_over_http_outgoing_token.map(|token| {
+                    encrypt_token(
+                        &self.encryption_key.expose_secret().0,
+                        token.expose_secret().as_bytes(),
+                    )
+                    .freeze()
+                }),
+            };
 
-        let account = self.redis_modify_account(id, settings).await?;
-        Ok(account.decrypt_tokens(&self.decryption_key.expose_secret().0))
+            let account = self.redis_modify_account(id, settings).await?;
+            Ok(account.decrypt_tokens(&self.decryption_key.expose_secret().0))
+        })
+        .await
     }
 
     // TODO limit the number of results and page through them
     async fn get_all_accounts(&self) -> Result<Vec<Self::Account>, NodeStoreError> {
-        let mut connection = self.connection.clone();
+        instrument(BACKEND, "get_all_accounts", async move {
+            let mut connection = self.connection.clone();
 
-        let account_ids = self.get_all_accounts_ids().await?;
+            let account_ids = self.get_all_accounts_ids().await?;
 
-        let mut script = LOAD_ACCOUNTS.prepare_invoke();
-        for id in account_ids.iter() {
-            script.arg(id.to_string());
-        }
+            let mut script = LOAD_ACCOUNTS.prepare_invoke();
+            for id in account_ids.iter() {
+                script.arg(id.to_string());
+            }
 
-        let accounts: Vec<AccountWithEncryptedTokens> =
-            script.invoke_async(&mut connection).await?;
+            let accounts: Vec<AccountWithEncryptedTokens> =
+                script.invoke_async(&mut connection).await?;
 
-        // TODO this should be refactored so that it gets reused in multiple backends
-        let accounts: Vec<Account> = accounts
-            .into_iter()
-            .map(|account| account.decrypt_tokens(&self.decryption_key.expose_secret().0))
-            .collect();
+            // TODO this should be refactored so that it gets reused in multiple backends
+            let accounts: Vec<Account> = accounts
+                .into_iter()
+                .map(|account| account.decrypt_tokens(&self.decryption_key.expose_secret().0))
+                .collect();
+
+            Ok(accounts)
+        })
+        .await
+    }
+
+    async fn get_accounts_page(
+        &self,
+        cursor: u64,
+        count: usize,
+    ) -> Result<(u64, Vec<Self::Account>), NodeStoreError> {
+        instrument(BACKEND, "get_accounts_page", async move {
+            let mut connection = self.connection.clone();
+
+            let (next_cursor, account_ids): (u64, Vec<RedisAccountId>) = cmd("SSCAN")
+                .arg("accounts")
+                .arg(cursor)
+                .arg("COUNT")
+                .arg(count)
+                .query_async(&mut connection)
+                .await?;
+            if account_ids.is_empty() {
+                return Ok((next_cursor, Vec::new()));
+            }
+
+            let mut script = LOAD_ACCOUNTS.prepare_invoke();
+            for id in account_ids.iter() {
+                script.arg(id.0.to_string());
+            }
+            let accounts: Vec<AccountWithEncryptedTokens> =
+                script.invoke_async(&mut connection).await?;
+            let accounts: Vec<Account> = accounts
+                .into_iter()
+                .map(|account| account.decrypt_tokens(&self.decryption_key.expose_secret().0))
+                .collect();
 
-        Ok(accounts)
+            Ok((next_cursor, accounts))
+        })
+        .await
     }
 
     async fn set_static_routes<R>(&self, routes: R) -> Result<(), NodeStoreError>
     where
         R: IntoIterator<Item = (String, Uuid)> + Send + 'async_trait,
     {
-        let mut connection = self.connection.clone();
-        let routes: Vec<(String, RedisAccountId)> = routes
-            .into_iter()
-            .map(|(s, id)| (s, RedisAccountId(id)))
-            .collect();
-        let accounts: HashSet<_> =
-            HashSet::from_iter(routes.iter().map(|(_prefix, account_id)| account_id));
-        let mut pipe = redis_crate::pipe();
-        for account_id in accounts {
-            pipe.exists(accounts_key((*account_id).0));
-        }
+        instrument(BACKEND, "set_static_routes", async move {
+            let mut connection = self.connection.clone();
+            let routes: Vec<(String, RedisAccountId)> = routes
+                .into_iter()
+                .map(|(s, id)| (s, RedisAccountId(id)))
+                .collect();
+            let accounts: HashSet<_> =
+                HashSet::from_iter(routes.iter().map(|(_prefix, account_id)| account_id));
+            let mut pipe = redis_crate::pipe();
+            for account_id in accounts {
+                pipe.exists(accounts_key(account_id.0));
+            }
 
-        let routing_table = self.routes.clone();
+            let routing_table = self.routes.clone();
 
-        let accounts_exist: Vec<bool> = pipe.query_async(&mut connection).await?;
+            let accounts_exist: Vec<bool> = pipe.query_async(&mut connection).await?;
 
-        if !accounts_exist.iter().all(|a| *a) {
-            error!("Error setting static routes because not all of the given accounts exist");
-            // TODO add proper error variant for "not all accoutns were found"
-            return Err(NodeStoreError::MissingAccounts);
-        }
+            if !accounts_exist.iter().all(|a| *a) {
+                error!("Error setting static routes because not all of the given accounts exist");
+                // TODO add proper error variant for "not all accoutns were found"
+                return Err(NodeStoreError::MissingAccounts);
+            }
 
-        let mut pipe = redis_crate::pipe();
-        pipe.atomic()
-            .del(STATIC_ROUTES_KEY)
-            .ignore()
-            .hset_multiple(STATIC_ROUTES_KEY, &routes)
-            .ignore();
+            let mut pipe = redis_crate::pipe();
+            pipe.atomic()
+                .del(STATIC_ROUTES_KEY)
+                .ignore()
+                .hset_multiple(STATIC_ROUTES_KEY, &routes)
+                .ignore();
 
-        pipe.query_async(&mut connection).await?;
+            pipe.query_async::<_, ()>(&mut connection).await?;
 
-        update_routes(connection, routing_table).await?;
-        Ok(())
+            update_routes(connection, routing_table).await?;
+            Ok(())
+        })
+        .await
     }
 
     async fn set_static_route(
@@ -1111,282 +2818,1546 @@ impl NodeStore for RedisStore {
         prefix: String,
         account_id: Uuid,
     ) -> Result<(), NodeStoreError> {
-        let routing_table = self.routes.clone();
-        let mut connection = self.connection.clone();
+        instrument(BACKEND, "set_static_route", async move {
+            let routing_table = self.routes.clone();
+            let mut connection = self.connection.clone();
 
-        let exists: bool = connection.exists(accounts_key(account_id)).await?;
-        if !exists {
-            error!(
-                "Cannot set static route for prefix: {} because account {} does not exist",
-                prefix, account_id
-            );
-            return Err(NodeStoreError::AccountNotFound(account_id.to_string()));
-        }
+            let exists: bool = connection.exists(accounts_key(account_id)).await?;
+            if !exists {
+                error!(
+                    "Cannot set static route for prefix: {} because account {} does not exist",
+                    prefix, account_id
+                );
+                return Err(NodeStoreError::AccountNotFound(account_id.to_string()));
+            }
 
-        connection
-            .hset(STATIC_ROUTES_KEY, prefix, RedisAccountId(account_id))
-            .await?;
+            connection
+                .hset::<_, _, _, ()>(STATIC_ROUTES_KEY, prefix, RedisAccountId(account_id))
+                .await?;
 
-        update_routes(connection, routing_table).await?;
+            update_routes(connection, routing_table).await?;
 
-        Ok(())
+            Ok(())
+        })
+        .await
     }
 
     async fn set_default_route(&self, account_id: Uuid) -> Result<(), NodeStoreError> {
-        let routing_table = self.routes.clone();
-        // TODO replace this with a lua script to do both calls at once
-        let mut connection = self.connection.clone();
-        let exists: bool = connection.exists(accounts_key(account_id)).await?;
-        if !exists {
-            error!(
-                "Cannot set default route because account {} does not exist",
-                account_id
-            );
-            return Err(NodeStoreError::AccountNotFound(account_id.to_string()));
-        }
+        instrument(BACKEND, "set_default_route", async move {
+            let routing_table = self.routes.clone();
+            // TODO replace this with a lua script to do both calls at once
+            let mut connection = self.connection.clone();
+            let exists: bool = connection.exists(accounts_key(account_id)).await?;
+            if !exists {
+                error!(
+                    "Cannot set default route because account {} does not exist",
+                    account_id
+                );
+                return Err(NodeStoreError::AccountNotFound(account_id.to_string()));
+            }
 
-        connection
-            .set(DEFAULT_ROUTE_KEY, RedisAccountId(account_id))
-            .await?;
-        debug!("Set default route to account id: {}", account_id);
-        update_routes(connection, routing_table).await?;
-        Ok(())
+            connection
+                .set::<_, _, ()>(DEFAULT_ROUTE_KEY, RedisAccountId(account_id))
+                .await?;
+            debug!("Set default route to account id: {}", account_id);
+            update_routes(connection, routing_table).await?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_static_routes(&self) -> Result<HashMap<String, Uuid>, NodeStoreError> {
+        instrument(BACKEND, "get_static_routes", async move {
+            let static_routes: Vec<(String, RedisAccountId)> =
+                self.connection.clone().hgetall(STATIC_ROUTES_KEY).await?;
+            Ok(static_routes
+                .into_iter()
+                .map(|(prefix, account_id)| (prefix, account_id.0))
+                .collect())
+        })
+        .await
     }
 
     async fn set_settlement_engines(
         &self,
         asset_to_url_map: impl IntoIterator<Item = (String, Url)> + Send + 'async_trait,
     ) -> Result<(), NodeStoreError> {
-        let mut connection = self.connection.clone();
-        let asset_to_url_map: Vec<(String, String)> = asset_to_url_map
-            .into_iter()
-            .map(|(asset_code, url)| (asset_code, url.to_string()))
-            .collect();
-        debug!("Setting settlement engines to {:?}", asset_to_url_map);
-        connection
-            .hset_multiple(SETTLEMENT_ENGINES_KEY, &asset_to_url_map)
-            .await?;
-        Ok(())
+        instrument(BACKEND, "set_settlement_engines", async move {
+            let mut connection = self.connection.clone();
+            let asset_to_url_map: Vec<(String, String)> = asset_to_url_map
+                .into_iter()
+                .map(|(asset_code, url)| (asset_code, url.to_string()))
+                .collect();
+            debug!("Setting settlement engines to {:?}", asset_to_url_map);
+            connection
+                .hset_multiple::<_, _, _, ()>(SETTLEMENT_ENGINES_KEY, &asset_to_url_map)
+                .await?;
+            Ok(())
+        })
+        .await
     }
 
     async fn get_asset_settlement_engine(
         &self,
         asset_code: &str,
     ) -> Result<Option<Url>, NodeStoreError> {
-        let url: Option<String> = self
-            .connection
-            .clone()
-            .hget(SETTLEMENT_ENGINES_KEY, asset_code)
-            .await?;
-        if let Some(url) = url {
-            match Url::parse(url.as_str()) {
-                Ok(url) => Ok(Some(url)),
-                Err(err) => {
-                    error!(
-                        "Settlement engine URL loaded from Redis was not a valid URL: {:?}",
-                        err
-                    );
-                    Err(NodeStoreError::InvalidEngineUrl(err.to_string()))
+        instrument(BACKEND, "get_asset_settlement_engine", async move {
+            let url: Option<String> = self
+                .connection
+                .clone()
+                .hget(SETTLEMENT_ENGINES_KEY, asset_code)
+                .await?;
+            if let Some(url) = url {
+                match Url::parse(url.as_str()) {
+                    Ok(url) => Ok(Some(url)),
+                    Err(err) => {
+                        error!(
+                            "Settlement engine URL loaded from Redis was not a valid URL: {:?}",
+                            err
+                        );
+                        Err(NodeStoreError::InvalidEngineUrl(err.to_string()))
+                    }
                 }
+            } else {
+                Ok(None)
             }
-        } else {
-            Ok(None)
-        }
+        })
+        .await
     }
-}
 
-#[async_trait]
-impl AddressStore for RedisStore {
-    // Updates the ILP address of the store & iterates over all children and
-    // updates their ILP Address to match the new address.
-    async fn set_ilp_address(&self, ilp_address: Address) -> Result<(), AddressStoreError> {
-        debug!("Setting ILP address to: {}", ilp_address);
-        let routing_table = self.routes.clone();
-        let mut connection = self.connection.clone();
-
-        // Set the ILP address we have in memory
-        (*self.ilp_address.write()) = ilp_address.clone();
-
-        // Save it to Redis
-        connection
-            .set(PARENT_ILP_KEY, ilp_address.as_bytes())
-            .await?;
-
-        let accounts = self.get_all_accounts().await?;
-        // TODO: This can be an expensive operation if this function
-        // gets called often. This currently only gets called when
-        // inserting a new parent account in the API. It'd be nice
-        // if we could generate a child's ILP address on the fly,
-        // instead of having to store the username appended to the
-        // node's ilp address. Currently this is not possible, as
-        // account.ilp_address() cannot access any state that exists
-        // on the store.
-        let first_segment = ilp_address
-            .segments()
-            .rev()
-            .next()
-            .expect("address did not have a first segment, this should be impossible");
-        let mut pipe = redis_crate::pipe();
-        for account in &accounts {
-            // Update the address and routes of all children and non-routing accounts.
-            if account.routing_relation() != RoutingRelation::Parent
-                && account.routing_relation() != RoutingRelation::Peer
-            {
-                // remove the old route
-                pipe.hdel(ROUTES_KEY, account.ilp_address.as_bytes())
-                    .ignore();
-
-                // if the username of the account ends with the
-                // node's address, we're already configured so no
-                // need to append anything.
-                let new_ilp_address = if first_segment == account.username().to_string() {
-                    ilp_address.clone()
-                } else {
-                    ilp_address
-                        .with_suffix(account.username().as_bytes())
-                        .unwrap()
-                };
-                pipe.hset(
-                    accounts_key(account.id()),
-                    "ilp_address",
-                    new_ilp_address.as_bytes(),
-                )
-                .ignore();
+    async fn get_settlement_engines(&self) -> Result<HashMap<String, Url>, NodeStoreError> {
+        instrument(BACKEND, "get_settlement_engines", async move {
+            let engines: HashMap<String, String> = self
+                .connection
+                .clone()
+                .hgetall(SETTLEMENT_ENGINES_KEY)
+                .await?;
+            engines
+                .into_iter()
+                .map(|(asset_code, url)| {
+                    Url::parse(&url)
+                        .map(|url| (asset_code, url))
+                        .map_err(|err| NodeStoreError::InvalidEngineUrl(err.to_string()))
+                })
+                .collect()
+        })
+        .await
+    }
 
-                pipe.hset(
-                    ROUTES_KEY,
-                    new_ilp_address.as_bytes(),
-                    RedisAccountId(account.id()),
-                )
-                .ignore();
+    async fn delete_settlement_engine(
+        &self,
+        asset_code: &str,
+    ) -> Result<Option<Url>, NodeStoreError> {
+        instrument(BACKEND, "delete_settlement_engine", async move {
+            let url = self.get_asset_settlement_engine(asset_code).await?;
+            if url.is_some() {
+                self.connection
+                    .clone()
+                    .hdel::<_, _, ()>(SETTLEMENT_ENGINES_KEY, asset_code)
+                    .await?;
+                debug!("Removed the default settlement engine of {}", asset_code);
             }
-        }
-
-        pipe.query_async(&mut connection.clone()).await?;
-        update_routes(connection, routing_table).await?;
-        Ok(())
+            Ok(url)
+        })
+        .await
     }
 
-    async fn clear_ilp_address(&self) -> Result<(), AddressStoreError> {
-        self.connection
-            .clone()
-            .del(PARENT_ILP_KEY)
-            .map_err(|err| AddressStoreError::Other(Box::new(err)))
-            .await?;
+    async fn set_runtime_settings(&self, settings: RuntimeSettings) -> Result<(), NodeStoreError> {
+        instrument(BACKEND, "set_runtime_settings", async move {
+            let fields: Vec<(&str, u64)> = vec![
+                (
+                    "route_broadcast_interval",
+                    settings.route_broadcast_interval,
+                ),
+                (
+                    "exchange_rate_poll_interval",
+                    settings.exchange_rate_poll_interval,
+                ),
+                ("max_packet_amount", settings.max_packet_amount),
+                ("max_packet_size", settings.max_packet_size),
+            ]
+            .into_iter()
+            .filter_map(|(field, value)| value.map(|value| (field, value)))
+            .collect();
+            debug!("Setting runtime settings to {:?}", fields);
 
-        // overwrite the ilp address with the default value
-        *(self.ilp_address.write()) = DEFAULT_ILP_ADDRESS.clone();
-        Ok(())
+            let mut pipe = redis_crate::pipe();
+            pipe.atomic().del(RUNTIME_SETTINGS_KEY).ignore();
+            if !fields.is_empty() {
+                pipe.hset_multiple(RUNTIME_SETTINGS_KEY, &fields).ignore();
+            }
+            pipe.query_async::<_, ()>(&mut self.connection.clone())
+                .await?;
+            Ok(())
+        })
+        .await
     }
 
-    fn get_ilp_address(&self) -> Address {
-        // read consumes the Arc<RwLock<T>> so we cannot return a reference
-        self.ilp_address.read().clone()
+    async fn get_runtime_settings(&self) -> Result<RuntimeSettings, NodeStoreError> {
+        instrument(BACKEND, "get_runtime_settings", async move {
+            let fields: HashMap<String, u64> = self
+                .connection
+                .clone()
+                .hgetall(RUNTIME_SETTINGS_KEY)
+                .await?;
+            Ok(RuntimeSettings {
+                route_broadcast_interval: fields.get("route_broadcast_interval").cloned(),
+                exchange_rate_poll_interval: fields.get("exchange_rate_poll_interval").cloned(),
+                max_packet_amount: fields.get("max_packet_amount").cloned(),
+                max_packet_size: fields.get("max_packet_size").cloned(),
+            })
+        })
+        .await
     }
 }
 
-type RoutingTable<A> = HashMap<String, A>;
+fn parse_audit_entries(serialized: &[String]) -> Result<Vec<AuditEntry>, NodeStoreError> {
+    serialized
+        .iter()
+        .map(|entry| serde_json::from_str(entry))
+        .collect::<Result<Vec<AuditEntry>, _>>()
+        .map_err(|err| NodeStoreError::Other(Box::new(err)))
+}
 
 #[async_trait]
-impl CcpRoutingStore for RedisStore {
-    type Account = Account;
+impl AuditLogStore for RedisStore {
+    async fn append_audit_entry(&self, entry: AuditEntry) -> Result<AuditEntry, NodeStoreError> {
+        instrument(BACKEND, "append_audit_entry", async move {
+            let mut connection = self.connection.clone();
+            let id: u64 = connection.incr(AUDIT_LOG_ID_KEY, 1).await?;
+            let entry = AuditEntry { id, ..entry };
+            let serialized = serde_json::to_string(&entry)
+                .map_err(|err| NodeStoreError::Other(Box::new(err)))?;
 
-    async fn get_accounts_to_send_routes_to(
-        &self,
-        ignore_accounts: Vec<Uuid>,
-    ) -> Result<Vec<Account>, CcpRoutingStoreError> {
-        let account_ids: Vec<RedisAccountId> =
-            self.connection.clone().smembers("send_routes_to").await?;
-        let account_ids: Vec<Uuid> = account_ids
-            .into_iter()
-            .map(|id| id.0)
-            .filter(|id| !ignore_accounts.contains(&id))
-            .collect();
-        if account_ids.is_empty() {
-            return Ok(Vec::new());
-        }
+            let mut pipe = redis_crate::pipe();
+            pipe.atomic();
+            pipe.zadd(AUDIT_LOG_KEY, serialized, entry.timestamp)
+                .ignore();
+            if let Some(cutoff) = audit_log_cutoff(self.audit_log_retention) {
+                pipe.zrembyscore(AUDIT_LOG_KEY, "-inf", format!("({}", cutoff))
+                    .ignore();
+            }
+            pipe.query_async::<_, ()>(&mut connection).await?;
+            Ok(entry)
+        })
+        .await
+    }
 
-        let accounts = self.get_accounts(account_ids).await?;
-        Ok(accounts)
+    async fn get_audit_entries(
+        &self,
+        query: AuditQuery,
+    ) -> Result<Vec<AuditEntry>, NodeStoreError> {
+        instrument(BACKEND, "get_audit_entries", async move {
+            let max = query
+                .until
+                .map_or_else(|| "+inf".to_string(), |until| until.to_string());
+            let min = query
+                .since
+                .map_or_else(|| "-inf".to_string(), |since| since.to_string());
+            let mut connection = self.connection.clone();
+            let limit = query.limit.map_or(-1, |limit| limit as isize);
+            let serialized: Vec<String> = connection
+                .zrevrangebyscore_limit(AUDIT_LOG_KEY, max, min, 0, limit)
+                .await?;
+            let mut entries = parse_audit_entries(&serialized)?;
+            // Redis orders the entries recorded in the same millisecond by their serialization,
+            // so the limit may have cut the ones of the last millisecond in the wrong order.
+            // All of them are fetched to be sorted by their sequence number before the limit.
+            if let (Some(limit), Some(last)) = (query.limit, entries.last()) {
+                if entries.len() == limit {
+                    let timestamp = last.timestamp;
+                    let serialized: Vec<String> = connection
+                        .zrangebyscore(AUDIT_LOG_KEY, timestamp, timestamp)
+                        .await?;
+                    entries.retain(|entry| entry.timestamp != timestamp);
+                    entries.extend(parse_audit_entries(&serialized)?);
+                }
+            }
+            entries.sort_by_key(|b| std::cmp::Reverse((b.timestamp, b.id)));
+            if let Some(limit) = query.limit {
+                entries.truncate(limit);
+            }
+            Ok(entries)
+        })
+        .await
     }
+}
 
-    async fn get_accounts_to_receive_routes_from(
+#[async_trait]
+impl PaymentPointerStore for RedisStore {
+    async fn get_payment_pointer(
         &self,
-    ) -> Result<Vec<Account>, CcpRoutingStoreError> {
-        let account_ids: Vec<RedisAccountId> = self
-            .connection
-            .clone()
-            .smembers("receive_routes_from")
-            .await?;
-        let account_ids: Vec<Uuid> = account_ids.into_iter().map(|id| id.0).collect();
+        path: &str,
+    ) -> Result<Option<PaymentPointer>, NodeStoreError> {
+        instrument(BACKEND, "get_payment_pointer", async move {
+            let serialized: Option<String> = self
+                .connection
+                .clone()
+                .hget(PAYMENT_POINTERS_KEY, path)
+                .await?;
+            serialized
+                .map(|pointer| serde_json::from_str(&pointer))
+                .transpose()
+                .map_err(|err| NodeStoreError::Other(Box::new(err)))
+        })
+        .await
+    }
 
-        if account_ids.is_empty() {
-            return Ok(Vec::new());
-        }
+    async fn get_payment_pointers(&self) -> Result<Vec<PaymentPointer>, NodeStoreError> {
+        instrument(BACKEND, "get_payment_pointers", async move {
+            let serialized: HashMap<String, String> = self
+                .connection
+                .clone()
+                .hgetall(PAYMENT_POINTERS_KEY)
+                .await?;
+            let mut pointers = serialized
+                .values()
+                .map(|pointer| serde_json::from_str(pointer))
+                .collect::<Result<Vec<PaymentPointer>, _>>()
+                .map_err(|err| NodeStoreError::Other(Box::new(err)))?;
+            pointers.sort_by(|a, b| a.path.cmp(&b.path));
+            Ok(pointers)
+        })
+        .await
+    }
 
-        let accounts = self.get_accounts(account_ids).await?;
-        Ok(accounts)
+    async fn set_payment_pointer(&self, pointer: PaymentPointer) -> Result<(), NodeStoreError> {
+        instrument(BACKEND, "set_payment_pointer", async move {
+            let serialized = serde_json::to_string(&pointer)
+                .map_err(|err| NodeStoreError::Other(Box::new(err)))?;
+            self.connection
+                .clone()
+                .hset::<_, _, _, ()>(PAYMENT_POINTERS_KEY, &pointer.path, serialized)
+                .await?;
+            Ok(())
+        })
+        .await
     }
 
-    async fn get_local_and_configured_routes(
+    async fn delete_payment_pointer(
         &self,
-    ) -> Result<(RoutingTable<Account>, RoutingTable<Account>), CcpRoutingStoreError> {
-        let static_routes: Vec<(String, RedisAccountId)> =
-            self.connection.clone().hgetall(STATIC_ROUTES_KEY).await?;
+        path: &str,
+    ) -> Result<Option<PaymentPointer>, NodeStoreError> {
+        instrument(BACKEND, "delete_payment_pointer", async move {
+            let pointer = self.get_payment_pointer(path).await?;
+            if pointer.is_some() {
+                self.connection
+                    .clone()
+                    .hdel::<_, _, ()>(PAYMENT_POINTERS_KEY, path)
+                    .await?;
+            }
+            Ok(pointer)
+        })
+        .await
+    }
+}
 
-        let accounts = self.get_all_accounts().await?;
+#[async_trait]
+impl AssetStore for RedisStore {
+    async fn get_asset(&self, code: &str) -> Result<Option<Asset>, NodeStoreError> {
+        instrument(BACKEND, "get_asset", async move {
+            let serialized: Option<String> = self.connection.clone().hget(ASSETS_KEY, code).await?;
+            serialized
+                .map(|asset| serde_json::from_str(&asset))
+                .transpose()
+                .map_err(|err| NodeStoreError::Other(Box::new(err)))
+        })
+        .await
+    }
 
-        let local_table = HashMap::from_iter(
-            accounts
-                .iter()
-                .map(|account| (account.ilp_address.to_string(), account.clone())),
-        );
+    async fn get_assets(&self) -> Result<Vec<Asset>, NodeStoreError> {
+        instrument(BACKEND, "get_assets", async move {
+            let serialized: HashMap<String, String> =
+                self.connection.clone().hgetall(ASSETS_KEY).await?;
+            let mut assets = serialized
+                .values()
+                .map(|asset| serde_json::from_str(asset))
+                .collect::<Result<Vec<Asset>, _>>()
+                .map_err(|err| NodeStoreError::Other(Box::new(err)))?;
+            assets.sort_by(|a, b| a.code.cmp(&b.code));
+            Ok(assets)
+        })
+        .await
+    }
 
-        let account_map: HashMap<Uuid, &Account> =
-            HashMap::from_iter(accounts.iter().map(|account| (account.id, account)));
-        let configured_table: HashMap<String, Account> = HashMap::from_iter(
-            static_routes
-                .into_iter()
-                .filter_map(|(prefix, account_id)| {
-                    if let Some(account) = account_map.get(&account_id.0) {
-                        Some((prefix, (*account).clone()))
-                    } else {
-                        warn!(
-                            "No account for ID: {}, ignoring configured route for prefix: {}",
-                            account_id, prefix
+    async fn set_asset(&self, asset: Asset) -> Result<(), NodeStoreError> {
+        instrument(BACKEND, "set_asset", async move {
+            let serialized = serde_json::to_string(&asset)
+                .map_err(|err| NodeStoreError::Other(Box::new(err)))?;
+            self.connection
+                .clone()
+                .hset::<_, _, _, ()>(ASSETS_KEY, &asset.code, serialized)
+                .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn delete_asset(&self, code: &str) -> Result<Option<Asset>, NodeStoreError> {
+        instrument(BACKEND, "delete_asset", async move {
+            let asset = self.get_asset(code).await?;
+            if asset.is_some() {
+                self.connection
+                    .clone()
+                    .hdel::<_, _, ()>(ASSETS_KEY, code)
+                    .await?;
+            }
+            Ok(asset)
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl AccountTemplateStore for RedisStore {
+    async fn get_account_template(
+        &self,
+        name: &str,
+    ) -> Result<Option<AccountTemplate>, NodeStoreError> {
+        instrument(BACKEND, "get_account_template", async move {
+            let serialized: Option<String> = self
+                .connection
+                .clone()
+                .hget(ACCOUNT_TEMPLATES_KEY, name)
+                .await?;
+            serialized
+                .map(|template| serde_json::from_str(&template))
+                .transpose()
+                .map_err(|err| NodeStoreError::Other(Box::new(err)))
+        })
+        .await
+    }
+
+    async fn get_account_templates(&self) -> Result<Vec<AccountTemplate>, NodeStoreError> {
+        instrument(BACKEND, "get_account_templates", async move {
+            let serialized: HashMap<String, String> = self
+                .connection
+                .clone()
+                .hgetall(ACCOUNT_TEMPLATES_KEY)
+                .await?;
+            let mut templates = serialized
+                .values()
+                .map(|template| serde_json::from_str(template))
+                .collect::<Result<Vec<AccountTemplate>, _>>()
+                .map_err(|err| NodeStoreError::Other(Box::new(err)))?;
+            templates.sort_by(|a, b| a.name.cmp(&b.name));
+            Ok(templates)
+        })
+        .await
+    }
+
+    async fn set_account_template(&self, template: AccountTemplate) -> Result<(), NodeStoreError> {
+        instrument(BACKEND, "set_account_template", async move {
+            let serialized = serde_json::to_string(&template)
+                .map_err(|err| NodeStoreError::Other(Box::new(err)))?;
+            self.connection
+                .clone()
+                .hset::<_, _, _, ()>(ACCOUNT_TEMPLATES_KEY, &template.name, serialized)
+                .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn delete_account_template(
+        &self,
+        name: &str,
+    ) -> Result<Option<AccountTemplate>, NodeStoreError> {
+        instrument(BACKEND, "delete_account_template", async move {
+            let template = self.get_account_template(name).await?;
+            if template.is_some() {
+                self.connection
+                    .clone()
+                    .hdel::<_, _, ()>(ACCOUNT_TEMPLATES_KEY, name)
+                    .await?;
+            }
+            Ok(template)
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl PullPaymentStore for RedisStore {
+    async fn create_pull_pointer(&self, pointer: PullPointer) -> Result<(), NodeStoreError> {
+        instrument(BACKEND, "create_pull_pointer", async move {
+            let serialized = serde_json::to_string(&pointer)
+                .map_err(|err| NodeStoreError::Other(Box::new(err)))?;
+            self.connection
+                .clone()
+                .hset::<_, _, _, ()>(PULL_POINTERS_KEY, &pointer.id, serialized)
+                .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_pull_pointer(&self, id: &str) -> Result<Option<PullPointer>, NodeStoreError> {
+        instrument(BACKEND, "get_pull_pointer", async move {
+            let serialized: Option<String> =
+                self.connection.clone().hget(PULL_POINTERS_KEY, id).await?;
+            serialized
+                .map(|pointer| serde_json::from_str(&pointer))
+                .transpose()
+                .map_err(|err| NodeStoreError::Other(Box::new(err)))
+        })
+        .await
+    }
+
+    async fn get_pull_pointers(&self) -> Result<Vec<PullPointer>, NodeStoreError> {
+        instrument(BACKEND, "get_pull_pointers", async move {
+            let serialized: HashMap<String, String> =
+                self.connection.clone().hgetall(PULL_POINTERS_KEY).await?;
+            let mut pointers = serialized
+                .values()
+                .map(|pointer| serde_json::from_str(pointer))
+                .collect::<Result<Vec<PullPointer>, _>>()
+                .map_err(|err| NodeStoreError::Other(Box::new(err)))?;
+            pointers.sort_by(|a, b| a.id.cmp(&b.id));
+            Ok(pointers)
+        })
+        .await
+    }
+
+    async fn delete_pull_pointer(&self, id: &str) -> Result<Option<PullPointer>, NodeStoreError> {
+        instrument(BACKEND, "delete_pull_pointer", async move {
+            let pointer = self.get_pull_pointer(id).await?;
+            if pointer.is_some() {
+                redis_crate::pipe()
+                    .atomic()
+                    .hdel(PULL_POINTERS_KEY, id)
+                    .ignore()
+                    .del(pull_balance_key(id))
+                    .ignore()
+                    .query_async::<_, ()>(&mut self.connection.clone())
+                    .await?;
+            }
+            Ok(pointer)
+        })
+        .await
+    }
+
+    async fn get_pull_balance(
+        &self,
+        pointer: &PullPointer,
+        now: u64,
+    ) -> Result<PullBalance, NodeStoreError> {
+        instrument(BACKEND, "get_pull_balance", async move {
+            let (total, interval_index, interval): (Option<u64>, Option<u64>, Option<u64>) = self
+                .connection
+                .clone()
+                .hget(
+                    pull_balance_key(&pointer.id),
+                    &["total", "interval_index", "interval"],
+                )
+                .await?;
+            // Nothing was pulled yet if the last pull was in a previous interval
+            let interval = if interval_index == Some(pointer.interval_index(now)) {
+                interval.unwrap_or_default()
+            } else {
+                0
+            };
+            Ok(pointer.balance(total.unwrap_or_default(), interval))
+        })
+        .await
+    }
+
+    async fn reserve_pull(
+        &self,
+        pointer: &PullPointer,
+        amount: u64,
+        now: u64,
+    ) -> Result<Option<PullBalance>, NodeStoreError> {
+        instrument(BACKEND, "reserve_pull", async move {
+            let cap = pointer.cap.map(|cap| cap.to_string()).unwrap_or_default();
+            let (reserved, total, interval): (bool, u64, u64) = RESERVE_PULL
+                .key(pull_balance_key(&pointer.id))
+                .arg(amount)
+                .arg(pointer.interval_index(now))
+                .arg(pointer.amount)
+                .arg(cap)
+                .invoke_async(&mut self.connection.clone())
+                .await?;
+            if reserved {
+                Ok(Some(pointer.balance(total, interval)))
+            } else {
+                Ok(None)
+            }
+        })
+        .await
+    }
+
+    async fn release_pull(
+        &self,
+        pointer: &PullPointer,
+        amount: u64,
+        now: u64,
+    ) -> Result<PullBalance, NodeStoreError> {
+        instrument(BACKEND, "release_pull", async move {
+            let (total, interval): (u64, u64) = RELEASE_PULL
+                .key(pull_balance_key(&pointer.id))
+                .arg(amount)
+                .arg(pointer.interval_index(now))
+                .invoke_async(&mut self.connection.clone())
+                .await?;
+            Ok(pointer.balance(total, interval))
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl ScheduledPaymentStore for RedisStore {
+    async fn create_scheduled_payment(
+        &self,
+        payment: ScheduledPayment,
+    ) -> Result<(), NodeStoreError> {
+        instrument(BACKEND, "create_scheduled_payment", async move {
+            let serialized = serde_json::to_string(&payment)
+                .map_err(|err| NodeStoreError::Other(Box::new(err)))?;
+            self.connection
+                .clone()
+                .hset::<_, _, _, ()>(SCHEDULED_PAYMENTS_KEY, &payment.id, serialized)
+                .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_scheduled_payment(
+        &self,
+        id: &str,
+    ) -> Result<Option<ScheduledPayment>, NodeStoreError> {
+        instrument(BACKEND, "get_scheduled_payment", async move {
+            let serialized: Option<String> = self
+                .connection
+                .clone()
+                .hget(SCHEDULED_PAYMENTS_KEY, id)
+                .await?;
+            serialized
+                .map(|payment| serde_json::from_str(&payment))
+                .transpose()
+                .map_err(|err| NodeStoreError::Other(Box::new(err)))
+        })
+        .await
+    }
+
+    async fn get_scheduled_payments(&self) -> Result<Vec<ScheduledPayment>, NodeStoreError> {
+        instrument(BACKEND, "get_scheduled_payments", async move {
+            let serialized: HashMap<String, String> = self
+                .connection
+                .clone()
+                .hgetall(SCHEDULED_PAYMENTS_KEY)
+                .await?;
+            let mut payments = serialized
+                .values()
+                .map(|payment| serde_json::from_str(payment))
+                .collect::<Result<Vec<ScheduledPayment>, _>>()
+                .map_err(|err| NodeStoreError::Other(Box::new(err)))?;
+            payments.sort_by(|a, b| a.id.cmp(&b.id));
+            Ok(payments)
+        })
+        .await
+    }
+
+    async fn update_scheduled_payment(
+        &self,
+        payment: ScheduledPayment,
+    ) -> Result<bool, NodeStoreError> {
+        instrument(BACKEND, "update_scheduled_payment", async move {
+            let serialized = serde_json::to_string(&payment)
+                .map_err(|err| NodeStoreError::Other(Box::new(err)))?;
+            let updated: bool = UPDATE_SCHEDULED_PAYMENT
+                .key(SCHEDULED_PAYMENTS_KEY)
+                .arg(&payment.id)
+                .arg(serialized)
+                .invoke_async(&mut self.connection.clone())
+                .await?;
+            Ok(updated)
+        })
+        .await
+    }
+
+    async fn delete_scheduled_payment(
+        &self,
+        id: &str,
+    ) -> Result<Option<ScheduledPayment>, NodeStoreError> {
+        instrument(BACKEND, "delete_scheduled_payment", async move {
+            let payment = self.get_scheduled_payment(id).await?;
+            if payment.is_some() {
+                self.connection
+                    .clone()
+                    .hdel::<_, _, ()>(SCHEDULED_PAYMENTS_KEY, id)
+                    .await?;
+            }
+            Ok(payment)
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl OutgoingPaymentStore for RedisStore {
+    async fn save_outgoing_payment(&self, payment: OutgoingPayment) -> Result<(), NodeStoreError> {
+        instrument(BACKEND, "save_outgoing_payment", async move {
+            let serialized = serde_json::to_string(&payment)
+                .map_err(|err| NodeStoreError::Other(Box::new(err)))?;
+            self.connection
+                .clone()
+                .hset::<_, _, _, ()>(OUTGOING_PAYMENTS_KEY, &payment.id, serialized)
+                .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_outgoing_payment(
+        &self,
+        id: &str,
+    ) -> Result<Option<OutgoingPayment>, NodeStoreError> {
+        instrument(BACKEND, "get_outgoing_payment", async move {
+            let serialized: Option<String> = self
+                .connection
+                .clone()
+                .hget(OUTGOING_PAYMENTS_KEY, id)
+                .await?;
+            serialized
+                .map(|payment| serde_json::from_str(&payment))
+                .transpose()
+                .map_err(|err| NodeStoreError::Other(Box::new(err)))
+        })
+        .await
+    }
+
+    async fn get_outgoing_payments(&self) -> Result<Vec<OutgoingPayment>, NodeStoreError> {
+        instrument(BACKEND, "get_outgoing_payments", async move {
+            let serialized: HashMap<String, String> = self
+                .connection
+                .clone()
+                .hgetall(OUTGOING_PAYMENTS_KEY)
+                .await?;
+            let mut payments = serialized
+                .values()
+                .map(|payment| serde_json::from_str(payment))
+                .collect::<Result<Vec<OutgoingPayment>, _>>()
+                .map_err(|err| NodeStoreError::Other(Box::new(err)))?;
+            payments.sort_by(|a, b| b.created_at.cmp(&a.created_at).then(a.id.cmp(&b.id)));
+            Ok(payments)
+        })
+        .await
+    }
+
+    async fn get_outgoing_payments_page(
+        &self,
+        cursor: u64,
+        count: usize,
+    ) -> Result<(u64, Vec<OutgoingPayment>), NodeStoreError> {
+        instrument(BACKEND, "get_outgoing_payments_page", async move {
+            let (next_cursor, serialized): (u64, Vec<(String, String)>) = cmd("HSCAN")
+                .arg(OUTGOING_PAYMENTS_KEY)
+                .arg(cursor)
+                .arg("COUNT")
+                .arg(count)
+                .query_async(&mut self.connection.clone())
+                .await?;
+            let payments = serialized
+                .iter()
+                .map(|(_, payment)| serde_json::from_str(payment))
+                .collect::<Result<Vec<OutgoingPayment>, _>>()
+                .map_err(|err| NodeStoreError::Other(Box::new(err)))?;
+            Ok((next_cursor, payments))
+        })
+        .await
+    }
+
+    async fn delete_outgoing_payments(&self, ids: Vec<String>) -> Result<(), NodeStoreError> {
+        instrument(BACKEND, "delete_outgoing_payments", async move {
+            if !ids.is_empty() {
+                self.connection
+                    .clone()
+                    .hdel::<_, _, ()>(OUTGOING_PAYMENTS_KEY, ids)
+                    .await?;
+            }
+            Ok(())
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl PeeringStore for RedisStore {
+    async fn save_peering_request(&self, request: PeeringRequest) -> Result<(), NodeStoreError> {
+        instrument(BACKEND, "save_peering_request", async move {
+            let request = encrypt_peering_request(&self.encryption_key.expose_secret().0, request);
+            save_json(
+                &self.connection,
+                PEERING_REQUESTS_KEY,
+                &request.id,
+                &request,
+            )
+            .await
+        })
+        .await
+    }
+
+    async fn get_peering_requests(&self) -> Result<Vec<PeeringRequest>, NodeStoreError> {
+        instrument(BACKEND, "get_peering_requests", async move {
+            let stored: Vec<PeeringRequest> =
+                get_all_json(&self.connection, PEERING_REQUESTS_KEY).await?;
+            let mut requests = Vec::with_capacity(stored.len());
+            let mut undecryptable = Vec::new();
+            for request in stored {
+                let id = request.id.clone();
+                match decrypt_peering_request(&self.decryption_key.expose_secret().0, request) {
+                    Ok(request) => requests.push(request),
+                    Err(_) => {
+                        // It could never be answered, so it is dropped
+                        error!(
+                            "Unable to decrypt the tokens of peering request {}, dropping it",
+                            id
                         );
-                        None
+                        undecryptable.push(id);
                     }
-                }),
-        );
+                }
+            }
+            if !undecryptable.is_empty() {
+                self.connection
+                    .clone()
+                    .hdel::<_, _, ()>(PEERING_REQUESTS_KEY, undecryptable)
+                    .await?;
+            }
+            requests.sort_by(|a, b| a.id.cmp(&b.id));
+            Ok(requests)
+        })
+        .await
+    }
+
+    async fn delete_peering_request(
+        &self,
+        id: &str,
+    ) -> Result<Option<PeeringRequest>, NodeStoreError> {
+        instrument(BACKEND, "delete_peering_request", async move {
+            let request: Option<PeeringRequest> =
+                take_json(&self.connection, PEERING_REQUESTS_KEY, id).await?;
+            // A request which cannot be decrypted cannot be answered either
+            Ok(request.and_then(|request| {
+                decrypt_peering_request(&self.decryption_key.expose_secret().0, request)
+                    .map_err(|_| error!("Unable to decrypt the tokens of peering request {}", id))
+                    .ok()
+            }))
+        })
+        .await
+    }
+
+    async fn save_peering_proposal(&self, proposal: PeeringProposal) -> Result<(), NodeStoreError> {
+        instrument(BACKEND, "save_peering_proposal", async move {
+            let hashing_key = &self.token_hashing_key.expose_secret().0;
+            // The peer only authenticates with these tokens, so they are only verified
+            let proposal = PeeringProposal {
+                ilp_over_http_incoming_token: hashed_string(
+                    hashing_key,
+                    &proposal.ilp_over_http_incoming_token,
+                ),
+                callback_token: hashed_string(hashing_key, &proposal.callback_token),
+                ..proposal
+            };
+            save_json(
+                &self.connection,
+                PEERING_PROPOSALS_KEY,
+                &proposal.id,
+                &proposal,
+            )
+            .await
+        })
+        .await
+    }
+
+    async fn get_peering_proposal(
+        &self,
+        id: &str,
+    ) -> Result<Option<PeeringProposal>, NodeStoreError> {
+        instrument(BACKEND, "get_peering_proposal", async move {
+            let serialized: Option<String> = self
+                .connection
+                .clone()
+                .hget(PEERING_PROPOSALS_KEY, id)
+                .await?;
+            serialized
+                .map(|proposal| serde_json::from_str(&proposal))
+                .transpose()
+                .map_err(|err| NodeStoreError::Other(Box::new(err)))
+        })
+        .await
+    }
+
+    async fn get_peering_proposals(&self) -> Result<Vec<PeeringProposal>, NodeStoreError> {
+        instrument(BACKEND, "get_peering_proposals", async move {
+            let mut proposals: Vec<PeeringProposal> =
+                get_all_json(&self.connection, PEERING_PROPOSALS_KEY).await?;
+            proposals.sort_by(|a, b| a.id.cmp(&b.id));
+            Ok(proposals)
+        })
+        .await
+    }
+
+    async fn delete_peering_proposal(
+        &self,
+        id: &str,
+    ) -> Result<Option<PeeringProposal>, NodeStoreError> {
+        instrument(BACKEND, "delete_peering_proposal", async move {
+            take_json(&self.connection, PEERING_PROPOSALS_KEY, id).await
+        })
+        .await
+    }
+
+    fn verify_peering_callback_token(&self, proposal: &PeeringProposal, token: &str) -> bool {
+        verify_token(
+            &self.token_hashing_key.expose_secret().0,
+            proposal.callback_token.as_bytes(),
+            token.as_bytes(),
+        )
+    }
+}
+
+/// Encrypts the tokens of a peering request, which this node sends to the requester,
+/// and hex encodes them so that the request can be stored as JSON
+fn encrypt_peering_request(
+    encryption_key: &aead::LessSafeKey,
+    request: PeeringRequest,
+) -> PeeringRequest {
+    let encrypt = |token: &str| hex::encode(encrypt_token(encryption_key, token.as_bytes()));
+    PeeringRequest {
+        ilp_over_http_token: encrypt(&request.ilp_over_http_token),
+        callback_token: encrypt(&request.callback_token),
+        ..request
+    }
+}
+
+/// Decrypts the tokens of a stored peering request
+fn decrypt_peering_request(
+    decryption_key: &aead::LessSafeKey,
+    request: PeeringRequest,
+) -> Result<PeeringRequest, ()> {
+    let decrypt = |token: &str| -> Result<String, ()> {
+        let encrypted = hex::decode(token).map_err(|_| ())?;
+        let decrypted = decrypt_token(decryption_key, &encrypted)?;
+        String::from_utf8(decrypted.expose_secret().to_vec()).map_err(|_| ())
+    };
+    Ok(PeeringRequest {
+        ilp_over_http_token: decrypt(&request.ilp_over_http_token)?,
+        callback_token: decrypt(&request.callback_token)?,
+        ..request
+    })
+}
+
+/// Hashes a token which is stored as part of a JSON value
+fn hashed_string(hashing_key: &hmac::Key, token: &str) -> String {
+    // The hash is hex encoded, with an ASCII prefix
+    String::from_utf8_lossy(&hash_token(hashing_key, token.as_bytes())).into_owned()
+}
+
+/// Saves the value serialized as JSON in the field of the hash
+async fn save_json<T: Serialize>(
+    connection: &RedisReconnect,
+    key: &str,
+    field: &str,
+    value: &T,
+) -> Result<(), NodeStoreError> {
+    let serialized =
+        serde_json::to_string(value).map_err(|err| NodeStoreError::Other(Box::new(err)))?;
+    connection
+        .clone()
+        .hset::<_, _, _, ()>(key, field, serialized)
+        .await?;
+    Ok(())
+}
+
+/// Returns all the values serialized as JSON in the hash
+async fn get_all_json<T: DeserializeOwned>(
+    connection: &RedisReconnect,
+    key: &str,
+) -> Result<Vec<T>, NodeStoreError> {
+    let serialized: HashMap<String, String> = connection.clone().hgetall(key).await?;
+    serialized
+        .values()
+        .map(|value| serde_json::from_str(value))
+        .collect::<Result<Vec<T>, _>>()
+        .map_err(|err| NodeStoreError::Other(Box::new(err)))
+}
+
+/// Atomically removes the value serialized as JSON in the field of the hash and returns
+/// it, so that only one of concurrent callers gets it
+async fn take_json<T: DeserializeOwned>(
+    connection: &RedisReconnect,
+    key: &str,
+    field: &str,
+) -> Result<Option<T>, NodeStoreError> {
+    let (serialized,): (Option<String>,) = redis_crate::pipe()
+        .atomic()
+        .hget(key, field)
+        .hdel(key, field)
+        .ignore()
+        .query_async(&mut connection.clone())
+        .await?;
+    serialized
+        .map(|value| serde_json::from_str(&value))
+        .transpose()
+        .map_err(|err| NodeStoreError::Other(Box::new(err)))
+}
+
+#[async_trait]
+impl StreamReceiptStore for RedisStore {
+    async fn add_stream_received(
+        &self,
+        nonce: [u8; 16],
+        stream_id: u64,
+        amount: u64,
+    ) -> Result<u64, ReceiptStoreError> {
+        instrument(BACKEND, "add_stream_received", async move {
+            let key = stream_receipt_key(&nonce);
+            let (total_received,): (u64,) = redis_crate::pipe()
+                .atomic()
+                .hincr(&key, stream_id, amount)
+                .expire(&key, RECEIPT_TTL as usize)
+                .ignore()
+                .query_async(&mut self.connection.clone())
+                .await?;
+            Ok(total_received)
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl ReceiptVerifierStore for RedisStore {
+    async fn save_receipt_nonce(
+        &self,
+        nonce: [u8; 16],
+        path: String,
+    ) -> Result<(), NodeStoreError> {
+        instrument(BACKEND, "save_receipt_nonce", async move {
+            self.connection
+                .clone()
+                .set_ex::<_, _, ()>(receipt_nonce_key(&nonce), path, RECEIPT_TTL as usize)
+                .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn credit_receipt(
+        &self,
+        receipt: &Receipt,
+    ) -> Result<Option<ReceiptCredit>, NodeStoreError> {
+        instrument(BACKEND, "credit_receipt", async move {
+            let credit: Option<(String, u64, u64)> = CREDIT_RECEIPT
+                .key(receipt_nonce_key(&receipt.nonce))
+                .key(verified_receipts_key(&receipt.nonce))
+                .key(PAYMENT_POINTER_BALANCES_KEY)
+                .arg(receipt.stream_id)
+                .arg(receipt.total_received)
+                .arg(RECEIPT_TTL)
+                .invoke_async(&mut self.connection.clone())
+                .await?;
+            Ok(credit.map(|(path, amount, balance)| ReceiptCredit {
+                path,
+                amount,
+                balance,
+            }))
+        })
+        .await
+    }
+
+    async fn get_payment_pointer_balance(&self, path: &str) -> Result<u64, NodeStoreError> {
+        instrument(BACKEND, "get_payment_pointer_balance", async move {
+            let balance: Option<u64> = self
+                .connection
+                .clone()
+                .hget(PAYMENT_POINTER_BALANCES_KEY, path)
+                .await?;
+            Ok(balance.unwrap_or_default())
+        })
+        .await
+    }
+}
+
+/// Returns the timestamp (in milliseconds) before which the audit log
+/// entries are deleted, if the audit log has a retention period
+fn audit_log_cutoff(audit_log_retention: Option<u64>) -> Option<u64> {
+    audit_log_retention.map(|retention| journal_timestamp().saturating_sub(retention * 1000))
+}
+
+#[async_trait]
+impl AddressStore for RedisStore {
+    // Updates the ILP address of the store & iterates over all children and
+    // updates their ILP Address to match the new address.
+    async fn set_ilp_address(&self, ilp_address: Address) -> Result<(), AddressStoreError> {
+        instrument(BACKEND, "set_ilp_address", async move {
+            debug!("Setting ILP address to: {}", ilp_address);
+            let routing_table = self.routes.clone();
+            let mut connection = self.connection.clone();
+
+            // Set the ILP address we have in memory
+            (*self.ilp_address.write()) = ilp_address.clone();
+
+            // Save it to Redis
+            connection
+                .set::<_, _, ()>(PARENT_ILP_KEY, ilp_address.as_bytes())
+                .await?;
+
+            let accounts = self.get_all_accounts().await?;
+            // TODO: This can be an expensive operation if this function
+            // gets called often. This currently only gets called when
+            // inserting a new parent account in the API. It'd be nice
+            // if we could generate a child's ILP address on the fly,
+            // instead of having to store the username appended to the
+            // node's ilp address. Currently this is not possible, as
+            // account.ilp_address() cannot access any state that exists
+            // on the store.
+            let first_segment = ilp_address
+                .segments()
+                .next_back()
+                .expect("address did not have a first segment, this should be impossible");
+            let mut pipe = redis_crate::pipe();
+            for account in &accounts {
+                // Update the address and routes of all children and non-routing accounts.
+                if account.routing_relation() != RoutingRelation::Parent
+                    && account.routing_relation() != RoutingRelation::Peer
+                {
+                    // remove the old route
+                    pipe.hdel(ROUTES_KEY, account.ilp_address.as_bytes())
+                        .ignore();
+
+                    // if the username of the account ends with the
+                    // node's address, we're already configured so no
+                    // need to append anything.
+                    let new_ilp_address = if first_segment == account.username().to_string() {
+                        ilp_address.clone()
+                    } else {
+                        ilp_address
+                            .with_suffix(account.username().as_bytes())
+                            .unwrap()
+                    };
+                    pipe.hset(
+                        accounts_key(account.id()),
+                        "ilp_address",
+                        new_ilp_address.as_bytes(),
+                    )
+                    .ignore();
 
-        Ok((local_table, configured_table))
+                    pipe.hset(
+                        ROUTES_KEY,
+                        new_ilp_address.as_bytes(),
+                        RedisAccountId(account.id()),
+                    )
+                    .ignore();
+                }
+            }
+
+            pipe.query_async::<_, ()>(&mut connection.clone()).await?;
+            update_routes(connection, routing_table).await?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn clear_ilp_address(&self) -> Result<(), AddressStoreError> {
+        instrument(BACKEND, "clear_ilp_address", async move {
+            self.connection
+                .clone()
+                .del::<_, ()>(PARENT_ILP_KEY)
+                .map_err(|err| AddressStoreError::Other(Box::new(err)))
+                .await?;
+
+            // overwrite the ilp address with the default value
+            *(self.ilp_address.write()) = DEFAULT_ILP_ADDRESS.clone();
+            Ok(())
+        })
+        .await
+    }
+
+    fn get_ilp_address(&self) -> Address {
+        // read consumes the Arc<RwLock<T>> so we cannot return a reference
+        self.ilp_address.read().clone()
+    }
+}
+
+type RoutingTable<A> = HashMap<String, A>;
+
+#[async_trait]
+impl LeaseStore for RedisStore {
+    async fn acquire_lease(
+        &self,
+        name: &str,
+        holder: &str,
+        ttl: u64,
+    ) -> Result<bool, LeaseStoreError> {
+        instrument(BACKEND, "acquire_lease", async move {
+            let acquired: bool = ACQUIRE_LEASE
+                .key(lease_key(name))
+                .arg(holder)
+                .arg(ttl)
+                .invoke_async(&mut self.connection.clone())
+                .await?;
+            Ok(acquired)
+        })
+        .await
+    }
+
+    async fn release_lease(&self, name: &str, holder: &str) -> Result<(), LeaseStoreError> {
+        instrument(BACKEND, "release_lease", async move {
+            let _: u64 = RELEASE_LEASE
+                .key(lease_key(name))
+                .arg(holder)
+                .invoke_async(&mut self.connection.clone())
+                .await?;
+            debug!("Released lease {}", name);
+            Ok(())
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl MaxPacketAmountStore for RedisStore {
+    async fn get_max_packet_amount(
+        &self,
+        source_asset_code: &str,
+        source_asset_scale: u8,
+        destination_prefix: &str,
+    ) -> Result<Option<u64>, MaxPacketAmountStoreError> {
+        instrument(BACKEND, "get_max_packet_amount", async move {
+            let max_packet_amount: Option<u64> = self
+                .connection
+                .clone()
+                .get(max_packet_amount_key(
+                    source_asset_code,
+                    source_asset_scale,
+                    destination_prefix,
+                ))
+                .await?;
+            Ok(max_packet_amount)
+        })
+        .await
+    }
+
+    async fn set_max_packet_amount(
+        &self,
+        source_asset_code: &str,
+        source_asset_scale: u8,
+        destination_prefix: &str,
+        max_packet_amount: u64,
+        ttl: u64,
+    ) -> Result<(), MaxPacketAmountStoreError> {
+        instrument(BACKEND, "set_max_packet_amount", async move {
+            cmd("SET")
+                .arg(max_packet_amount_key(
+                    source_asset_code,
+                    source_asset_scale,
+                    destination_prefix,
+                ))
+                .arg(max_packet_amount)
+                .arg("PX")
+                .arg(ttl)
+                .query_async::<_, ()>(&mut self.connection.clone())
+                .await?;
+            trace!(
+                "Cached max packet amount of {} {} for {}",
+                max_packet_amount,
+                source_asset_code,
+                destination_prefix
+            );
+            Ok(())
+        })
+        .await
+    }
+}
+
+impl RedisStore {
+    /// Loads the rejects of the destination prefix, which are gone once their key expired
+    async fn load_unreachable_destination(
+        &self,
+        destination_prefix: &str,
+    ) -> Result<Option<UnreachableDestination>, RedisError> {
+        let key = unreachable_destination_key(destination_prefix);
+        let ((failures, error), expires_in): ((Option<u32>, Option<String>), i64) =
+            redis_crate::pipe()
+                .cmd("HMGET")
+                .arg(&key)
+                .arg("failures")
+                .arg("error")
+                .cmd("PTTL")
+                .arg(&key)
+                .query_async(&mut self.connection.clone())
+                .await?;
+        Ok(failures.map(|failures| UnreachableDestination {
+            destination_prefix: destination_prefix.to_string(),
+            failures,
+            error: error.unwrap_or_default(),
+            expires_in: expires_in.max(0) as u64,
+        }))
+    }
+}
+
+#[async_trait]
+impl UnreachableDestinationStore for RedisStore {
+    async fn get_unreachable_destination(
+        &self,
+        destination_prefix: &str,
+    ) -> Result<Option<UnreachableDestination>, UnreachableDestinationStoreError> {
+        instrument(BACKEND, "get_unreachable_destination", async move {
+            Ok(self
+                .load_unreachable_destination(destination_prefix)
+                .await?)
+        })
+        .await
+    }
+
+    async fn record_unreachable_destination(
+        &self,
+        destination_prefix: &str,
+        error: &str,
+        ttl: u64,
+    ) -> Result<(), UnreachableDestinationStoreError> {
+        instrument(BACKEND, "record_unreachable_destination", async move {
+            let key = unreachable_destination_key(destination_prefix);
+            redis_crate::pipe()
+                .atomic()
+                .cmd("HINCRBY")
+                .arg(&key)
+                .arg("failures")
+                .arg(1)
+                .ignore()
+                .cmd("HSET")
+                .arg(&key)
+                .arg("error")
+                .arg(error)
+                .ignore()
+                .cmd("PEXPIRE")
+                .arg(&key)
+                .arg(ttl)
+                .ignore()
+                .query_async::<_, ()>(&mut self.connection.clone())
+                .await?;
+            trace!(
+                "Recorded reject of {} as unreachable: {}",
+                destination_prefix,
+                error
+            );
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_unreachable_destinations(
+        &self,
+    ) -> Result<Vec<UnreachableDestination>, UnreachableDestinationStoreError> {
+        instrument(BACKEND, "get_unreachable_destinations", async move {
+            let mut connection = self.connection.clone();
+            let key_prefix = unreachable_destination_key("");
+            let mut destinations = Vec::new();
+            let mut cursor = 0u64;
+            loop {
+                let (next_cursor, keys): (u64, Vec<String>) = cmd("SCAN")
+                    .arg(cursor)
+                    .arg("MATCH")
+                    .arg(unreachable_destination_key("*"))
+                    .arg("COUNT")
+                    .arg(COMPACTION_SCAN_COUNT)
+                    .query_async(&mut connection)
+                    .await?;
+                for key in keys {
+                    // The key may have expired since it was scanned
+                    if let Some(destination) = self
+                        .load_unreachable_destination(&key[key_prefix.len()..])
+                        .await?
+                    {
+                        destinations.push(destination);
+                    }
+                }
+                cursor = next_cursor;
+                if cursor == 0 {
+                    break;
+                }
+            }
+            destinations.sort_by(|a, b| a.destination_prefix.cmp(&b.destination_prefix));
+            destinations.dedup_by(|a, b| a.destination_prefix == b.destination_prefix);
+            Ok(destinations)
+        })
+        .await
+    }
+
+    async fn clear_unreachable_destinations(
+        &self,
+        destination_prefix: Option<&str>,
+    ) -> Result<usize, UnreachableDestinationStoreError> {
+        instrument(BACKEND, "clear_unreachable_destinations", async move {
+            let keys: Vec<String> = match destination_prefix {
+                Some(prefix) => vec![unreachable_destination_key(prefix)],
+                None => self
+                    .get_unreachable_destinations()
+                    .await?
+                    .iter()
+                    .map(|destination| unreachable_destination_key(&destination.destination_prefix))
+                    .collect(),
+            };
+            if keys.is_empty() {
+                return Ok(0);
+            }
+            let cleared: usize = self.connection.clone().del(keys).await?;
+            debug!("Cleared {} unreachable destinations", cleared);
+            Ok(cleared)
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl CcpRoutingStore for RedisStore {
+    type Account = Account;
+
+    async fn get_accounts_to_send_routes_to(
+        &self,
+        ignore_accounts: Vec<Uuid>,
+    ) -> Result<Vec<Account>, CcpRoutingStoreError> {
+        instrument(BACKEND, "get_accounts_to_send_routes_to", async move {
+            let account_ids: Vec<RedisAccountId> =
+                self.connection.clone().smembers("send_routes_to").await?;
+            let account_ids: Vec<Uuid> = account_ids
+                .into_iter()
+                .map(|id| id.0)
+                .filter(|id| !ignore_accounts.contains(id))
+                .collect();
+            if account_ids.is_empty() {
+                return Ok(Vec::new());
+            }
+
+            let accounts = self.get_accounts(account_ids).await?;
+            Ok(accounts)
+        })
+        .await
+    }
+
+    async fn get_accounts_to_receive_routes_from(
+        &self,
+    ) -> Result<Vec<Account>, CcpRoutingStoreError> {
+        instrument(BACKEND, "get_accounts_to_receive_routes_from", async move {
+            let account_ids: Vec<RedisAccountId> = self
+                .connection
+                .clone()
+                .smembers("receive_routes_from")
+                .await?;
+            let account_ids: Vec<Uuid> = account_ids.into_iter().map(|id| id.0).collect();
+
+            if account_ids.is_empty() {
+                return Ok(Vec::new());
+            }
+
+            let accounts = self.get_accounts(account_ids).await?;
+            Ok(accounts)
+        })
+        .await
+    }
+
+    async fn get_local_and_configured_routes(
+        &self,
+    ) -> Result<(RoutingTable<Account>, RoutingTable<Account>), CcpRoutingStoreError> {
+        instrument(BACKEND, "get_local_and_configured_routes", async move {
+            let static_routes: Vec<(String, RedisAccountId)> =
+                self.connection.clone().hgetall(STATIC_ROUTES_KEY).await?;
+
+            let accounts = self.get_all_accounts().await?;
+
+            let local_table = HashMap::from_iter(
+                accounts
+                    .iter()
+                    .map(|account| (account.ilp_address.to_string(), account.clone())),
+            );
+
+            let account_map: HashMap<Uuid, &Account> =
+                HashMap::from_iter(accounts.iter().map(|account| (account.id, account)));
+            let configured_table: HashMap<String, Account> = HashMap::from_iter(
+                static_routes
+                    .into_iter()
+                    .filter_map(|(prefix, account_id)| {
+                        if let Some(account) = account_map.get(&account_id.0) {
+                            Some((prefix, (*account).clone()))
+                        } else {
+                            warn!(
+                                "No account for ID: {}, ignoring configured route for prefix: {}",
+                                account_id, prefix
+                            );
+                            None
+                        }
+                    }),
+            );
+
+            Ok((local_table, configured_table))
+        })
+        .await
     }
 
     async fn set_routes(
         &mut self,
         routes: impl IntoIterator<Item = (String, Account)> + Send + 'async_trait,
     ) -> Result<(), CcpRoutingStoreError> {
-        let routes: Vec<(String, RedisAccountId)> = routes
-            .into_iter()
-            .map(|(prefix, account)| (prefix, RedisAccountId(account.id)))
-            .collect();
-        let num_routes = routes.len();
-        let mut connection = self.connection.clone();
+        instrument(BACKEND, "set_routes", async move {
+            let routes: Vec<(String, RedisAccountId)> = routes
+                .into_iter()
+                .map(|(prefix, account)| (prefix, RedisAccountId(account.id)))
+                .collect();
+            let num_routes = routes.len();
+            let mut connection = self.connection.clone();
 
-        // Save routes to Redis
-        let mut pipe = redis_crate::pipe();
-        pipe.atomic()
-            .del(ROUTES_KEY)
-            .ignore()
-            .hset_multiple(ROUTES_KEY, &routes)
-            .ignore();
+            // Save routes to Redis
+            let mut pipe = redis_crate::pipe();
+            pipe.atomic()
+                .del(ROUTES_KEY)
+                .ignore()
+                .hset_multiple(ROUTES_KEY, &routes)
+                .ignore();
 
-        pipe.query_async(&mut connection).await?;
-        trace!("Saved {} routes to Redis", num_routes);
+            pipe.query_async::<_, ()>(&mut connection).await?;
+            trace!("Saved {} routes to Redis", num_routes);
+
+            update_routes(connection, self.routes.clone()).await?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn set_learned_routes(
+        &mut self,
+        account: Account,
+        routes: Vec<u8>,
+    ) -> Result<(), CcpRoutingStoreError> {
+        instrument(BACKEND, "set_learned_routes", async move {
+            // Each account's routes replace only its own, so the nodes sharing the
+            // store do not overwrite the routes learned by each other
+            self.connection
+                .clone()
+                .hset::<_, _, _, ()>(LEARNED_ROUTES_KEY, RedisAccountId(account.id), routes)
+                .await?;
+            Ok(())
+        })
+        .await
+    }
 
-        update_routes(connection, self.routes.clone()).await?;
-        Ok(())
+    async fn get_learned_routes(&self) -> Result<Vec<(Account, Vec<u8>)>, CcpRoutingStoreError> {
+        instrument(BACKEND, "get_learned_routes", async move {
+            let learned_routes: Vec<(RedisAccountId, Vec<u8>)> =
+                self.connection.clone().hgetall(LEARNED_ROUTES_KEY).await?;
+            if learned_routes.is_empty() {
+                return Ok(Vec::new());
+            }
+            let (account_ids, routes): (Vec<Uuid>, Vec<Vec<u8>>) = learned_routes
+                .into_iter()
+                .map(|(account_id, routes)| (account_id.0, routes))
+                .unzip();
+            let accounts = self.get_accounts(account_ids).await?;
+            Ok(accounts.into_iter().zip(routes).collect())
+        })
+        .await
     }
 }
 
@@ -1394,68 +4365,149 @@ impl CcpRoutingStore for RedisStore {
 impl RateLimitStore for RedisStore {
     type Account = Account;
 
-    /// Apply rate limits for number of packets per minute and amount of money per minute
+    /// Apply rate limits for number of packets per second and per minute and amount of money per minute
+    ///
+    /// The limits are token buckets kept in the store, so that they are shared by all the
+    /// nodes using it. The accounts whose packet limits are exhausted are remembered until
+    /// their buckets refill, so that their packets are rejected without querying the store.
     ///
-    /// This uses https://github.com/brandur/redis-cell so the redis-cell module MUST be loaded into redis before this is run
+    /// So that the store is not queried for each packet of the accounts which only have
+    /// packet limits, the tokens of `RATE_LIMIT_LEASE_PERIOD` are leased along with the
+    /// token of a packet, and used for the next packets of the account until the period
+    /// ends. The tokens leased by a node cannot be used by the others, and the ones which
+    /// are not used in time are lost. The packets of the accounts with an amount limit still
+    /// query the store each, for their amounts.
     async fn apply_rate_limits(
         &self,
         account: Account,
         prepare_amount: u64,
     ) -> Result<(), RateLimitError> {
-        if account.amount_per_minute_limit.is_some() || account.packets_per_minute_limit.is_some() {
-            let mut pipe = redis_crate::pipe();
-            let packet_limit = account.packets_per_minute_limit.is_some();
-            let amount_limit = account.amount_per_minute_limit.is_some();
-
-            if let Some(limit) = account.packets_per_minute_limit {
-                let limit = limit - 1;
-                let packets_limit = format!("limit:packets:{}", account.id);
-                pipe.cmd("CL.THROTTLE")
-                    .arg(packets_limit)
-                    .arg(limit)
-                    .arg(limit)
-                    .arg(60)
-                    .arg(1);
+        instrument(BACKEND, "apply_rate_limits", async move {
+            if let Some(until) = self.throttled.read().get(&account.id) {
+                if *until > Instant::now() {
+                    return Err(RateLimitError::PacketLimitExceeded);
+                }
+            }
+
+            // The script divides by the capacity of the buckets, so the limits of 0, which
+            // are only found on accounts stored before the limits were validated, are
+            // exceeded without calling it
+            if account.packets_per_second_limit == Some(0)
+                || account.packets_per_minute_limit == Some(0)
+            {
+                return Err(RateLimitError::PacketLimitExceeded);
+            }
+            if account.amount_per_minute_limit == Some(0) {
+                return Err(RateLimitError::ThroughputLimitExceeded);
+            }
+
+            // The token of the packet is taken from the lease of the account if it has one
+            let leased = match self.packet_leases.write().get_mut(&account.id) {
+                Some((tokens, until)) if *tokens > 0 && *until > Instant::now() => {
+                    *tokens -= 1;
+                    true
+                }
+                _ => false,
+            };
+
+            let packet_buckets: [(Option<u32>, &str, u64); 2] = [
+                (account.packets_per_second_limit, "packets_per_second", 1000),
+                (account.packets_per_minute_limit, "packets", 60_000),
+            ];
+            // The tokens of the lease period of the smallest bucket, which is none if the
+            // packet token is leased already
+            let lease = if leased {
+                0
+            } else {
+                packet_buckets
+                    .iter()
+                    .filter_map(|&(limit, _, period)| {
+                        limit.map(|limit| u64::from(limit) * RATE_LIMIT_LEASE_PERIOD / period)
+                    })
+                    .min()
+                    .unwrap_or(0)
+            };
+
+            let mut script = RATE_LIMIT.prepare_invoke();
+            script.arg(lease);
+            // The error returned if the corresponding bucket does not have enough tokens
+            let mut limits = Vec::with_capacity(3);
+
+            if !leased {
+                for &(limit, name, period) in packet_buckets.iter() {
+                    if let Some(limit) = limit {
+                        script
+                            .key(rate_limit_key(account.id, name))
+                            .arg(limit)
+                            .arg(period)
+                            .arg(1)
+                            .arg(1);
+                        limits.push(RateLimitError::PacketLimitExceeded);
+                    }
+                }
             }
 
             if let Some(limit) = account.amount_per_minute_limit {
-                let limit = limit - 1;
-                let throughput_limit = format!("limit:throughput:{}", account.id);
-                pipe.cmd("CL.THROTTLE")
-                    .arg(throughput_limit)
-                    // TODO allow separate configuration for burst limit
+                script
+                    .key(rate_limit_key(account.id, "throughput"))
                     .arg(limit)
-                    .arg(limit)
-                    .arg(60)
-                    .arg(prepare_amount);
+                    .arg(60_000)
+                    .arg(prepare_amount)
+                    .arg(0);
+                limits.push(RateLimitError::ThroughputLimitExceeded);
             }
 
-            let results: Vec<Vec<i64>> = pipe
-                .query_async(&mut self.connection.clone())
+            if limits.is_empty() {
+                return Ok(());
+            }
+
+            let (exceeded, retry_after, leased_tokens): (usize, u64, u64) = script
+                .invoke_async(&mut self.connection.clone())
                 .map_err(|err| {
                     error!("Error applying rate limits: {:?}", err);
                     RateLimitError::StoreError
                 })
                 .await?;
 
-            if packet_limit && amount_limit {
-                if results[0][0] == 1 {
+            match exceeded.checked_sub(1).map(|index| limits[index].clone()) {
+                Some(RateLimitError::PacketLimitExceeded) => {
+                    let now = Instant::now();
+                    let mut throttled = self.throttled.write();
+                    // The accounts whose buckets refilled are forgotten, so that the
+                    // accounts throttled once are not kept forever
+                    throttled.retain(|_, until| *until > now);
+                    throttled.insert(account.id, now + Duration::from_millis(retry_after));
                     Err(RateLimitError::PacketLimitExceeded)
-                } else if results[1][0] == 1 {
-                    Err(RateLimitError::ThroughputLimitExceeded)
-                } else {
+                }
+                Some(err) => {
+                    // The packet is rejected, so its leased token is given back
+                    if leased {
+                        if let Some((tokens, _)) = self.packet_leases.write().get_mut(&account.id) {
+                            *tokens += 1;
+                        }
+                    }
+                    Err(err)
+                }
+                None => {
+                    self.throttled.write().remove(&account.id);
+                    if leased_tokens > 0 {
+                        let now = Instant::now();
+                        let mut packet_leases = self.packet_leases.write();
+                        // The leases which ended are forgotten, as the throttled accounts are
+                        packet_leases.retain(|_, (_, until)| *until > now);
+                        packet_leases.insert(
+                            account.id,
+                            (
+                                leased_tokens,
+                                now + Duration::from_millis(RATE_LIMIT_LEASE_PERIOD),
+                            ),
+                        );
+                    }
                     Ok(())
                 }
-            } else if packet_limit && results[0][0] == 1 {
-                Err(RateLimitError::PacketLimitExceeded)
-            } else if amount_limit && results[0][0] == 1 {
-                Err(RateLimitError::ThroughputLimitExceeded)
-            } else {
-                Ok(())
             }
-        } else {
-            Ok(())
-        }
+        })
+        .await
     }
 
     async fn refund_throughput_limit(
@@ -1463,22 +4515,24 @@ impl RateLimitStore for RedisStore {
         account: Account,
         prepare_amount: u64,
     ) -> Result<(), RateLimitError> {
-        if let Some(limit) = account.amount_per_minute_limit {
-            let limit = limit - 1;
-            let throughput_limit = format!("limit:throughput:{}", account.id);
-            cmd("CL.THROTTLE")
-                .arg(throughput_limit)
-                .arg(limit)
-                .arg(limit)
-                .arg(60)
-                // TODO make sure this doesn't overflow
-                .arg(0i64 - (prepare_amount as i64))
-                .query_async(&mut self.connection.clone())
-                .map_err(|_| RateLimitError::StoreError)
-                .await?;
-        }
+        instrument(BACKEND, "refund_throughput_limit", async move {
+            if let Some(limit) = account.amount_per_minute_limit.filter(|limit| *limit > 0) {
+                let refund = -(prepare_amount.min(i64::MAX as u64) as i64);
+                RATE_LIMIT
+                    .arg(0)
+                    .key(rate_limit_key(account.id, "throughput"))
+                    .arg(limit)
+                    .arg(60_000)
+                    .arg(refund)
+                    .arg(0)
+                    .invoke_async::<_, (usize, u64, u64)>(&mut self.connection.clone())
+                    .map_err(|_| RateLimitError::StoreError)
+                    .await?;
+            }
 
-        Ok(())
+            Ok(())
+        })
+        .await
     }
 }
 
@@ -1488,27 +4542,30 @@ impl IdempotentStore for RedisStore {
         &self,
         idempotency_key: String,
     ) -> Result<Option<IdempotentData>, IdempotentStoreError> {
-        let mut connection = self.connection.clone();
-        let ret: HashMap<String, String> = connection
-            .hgetall(prefixed_idempotency_key(&idempotency_key))
-            .await?;
+        instrument(BACKEND, "load_idempotent_data", async move {
+            let mut connection = self.connection.clone();
+            let ret: HashMap<String, String> = connection
+                .hgetall(prefixed_idempotency_key(&idempotency_key))
+                .await?;
 
-        if let (Some(status_code), Some(data), Some(input_hash_slice)) = (
-            ret.get("status_code"),
-            ret.get("data"),
-            ret.get("input_hash"),
-        ) {
-            trace!("Loaded idempotency key {:?} - {:?}", idempotency_key, ret);
-            let mut input_hash: [u8; 32] = Default::default();
-            input_hash.copy_from_slice(input_hash_slice.as_ref());
-            Ok(Some(IdempotentData::new(
-                StatusCode::from_str(status_code).unwrap(),
-                Bytes::from(data.to_owned()),
-                input_hash,
-            )))
-        } else {
-            Ok(None)
-        }
+            if let (Some(status_code), Some(data), Some(input_hash_slice)) = (
+                ret.get("status_code"),
+                ret.get("data"),
+                ret.get("input_hash"),
+            ) {
+                trace!("Loaded idempotency key {:?} - {:?}", idempotency_key, ret);
+                let mut input_hash: [u8; 32] = Default::default();
+                input_hash.copy_from_slice(input_hash_slice.as_ref());
+                Ok(Some(IdempotentData::new(
+                    StatusCode::from_str(status_code).unwrap(),
+                    Bytes::from(data.to_owned()),
+                    input_hash,
+                )))
+            } else {
+                Ok(None)
+            }
+        })
+        .await
     }
 
     async fn save_idempotent_data(
@@ -1518,29 +4575,54 @@ impl IdempotentStore for RedisStore {
         status_code: StatusCode,
         data: Bytes,
     ) -> Result<(), IdempotentStoreError> {
-        let mut pipe = redis_crate::pipe();
-        let mut connection = self.connection.clone();
-        pipe.atomic()
-            .cmd("HMSET") // cannot use hset_multiple since data and status_code have different types
-            .arg(&prefixed_idempotency_key(&idempotency_key))
-            .arg("status_code")
-            .arg(status_code.as_u16())
-            .arg("data")
-            .arg(data.as_ref())
-            .arg("input_hash")
-            .arg(&input_hash)
-            .ignore()
-            .expire(&prefixed_idempotency_key(&idempotency_key), 86400)
-            .ignore();
-        pipe.query_async(&mut connection).await?;
+        instrument(BACKEND, "save_idempotent_data", async move {
+            let mut pipe = redis_crate::pipe();
+            let mut connection = self.connection.clone();
+            pipe.atomic()
+                .cmd("HMSET") // cannot use hset_multiple since data and status_code have different types
+                .arg(prefixed_idempotency_key(&idempotency_key))
+                .arg("status_code")
+                .arg(status_code.as_u16())
+                .arg("data")
+                .arg(data.as_ref())
+                .arg("input_hash")
+                .arg(&input_hash)
+                .ignore()
+                .expire(
+                    prefixed_idempotency_key(&idempotency_key),
+                    self.idempotency_ttl as usize,
+                )
+                .ignore();
+            pipe.query_async::<_, ()>(&mut connection).await?;
 
-        trace!(
-            "Cached {:?}: {:?}, {:?}",
-            idempotency_key,
-            status_code,
-            data,
-        );
-        Ok(())
+            trace!(
+                "Cached {:?}: {:?}, {:?}",
+                idempotency_key,
+                status_code,
+                data,
+            );
+            Ok(())
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl NonceStore for RedisStore {
+    async fn save_nonce(&self, nonce: String, ttl: Duration) -> Result<bool, IdempotentStoreError> {
+        instrument(BACKEND, "save_nonce", async move {
+            // SET NX only replies OK if the nonce was not saved yet
+            let saved: Option<String> = cmd("SET")
+                .arg(prefixed_nonce_key(&nonce))
+                .arg(1)
+                .arg("NX")
+                .arg("PX")
+                .arg(ttl.as_millis() as u64)
+                .query_async(&mut self.connection.clone())
+                .await?;
+            Ok(saved.is_some())
+        })
+        .await
     }
 }
 
@@ -1554,20 +4636,29 @@ impl SettlementStore for RedisStore {
         amount: u64,
         idempotency_key: Option<String>,
     ) -> Result<(), SettlementStoreError> {
-        let idempotency_key = idempotency_key.unwrap();
-        let balance: i64 = PROCESS_INCOMING_SETTLEMENT
-            .arg(RedisAccountId(account_id))
-            .arg(amount)
-            .arg(idempotency_key)
-            .invoke_async(&mut self.connection.clone())
-            .await?;
-        trace!(
-            "Processed incoming settlement from account: {} for amount: {}. Balance is now: {}",
-            account_id,
-            amount,
-            balance
-        );
-        Ok(())
+        instrument(
+            BACKEND,
+            "update_balance_for_incoming_settlement",
+            async move {
+                let idempotency_key = idempotency_key.unwrap();
+                let balance: i64 = PROCESS_INCOMING_SETTLEMENT
+                    .arg(RedisAccountId(account_id))
+                    .arg(amount)
+                    .arg(idempotency_key)
+                    .arg(journal_timestamp())
+                    .arg(self.idempotency_ttl)
+                    .invoke_async(&mut self.connection.clone())
+                    .await?;
+                trace!(
+                    "Processed incoming settlement from account: {} for amount: {}. Balance is now: {}",
+                    account_id,
+                    amount,
+                    balance
+                );
+                Ok(())
+            },
+        )
+        .await
     }
 
     async fn refund_settlement(
@@ -1575,24 +4666,99 @@ impl SettlementStore for RedisStore {
         account_id: Uuid,
         settle_amount: u64,
     ) -> Result<(), SettlementStoreError> {
-        trace!(
-            "Refunding settlement for account: {} of amount: {}",
-            account_id,
-            settle_amount
-        );
-        let balance: i64 = REFUND_SETTLEMENT
-            .arg(RedisAccountId(account_id))
-            .arg(settle_amount)
-            .invoke_async(&mut self.connection.clone())
-            .await?;
+        instrument(BACKEND, "refund_settlement", async move {
+            trace!(
+                "Refunding settlement for account: {} of amount: {}",
+                account_id,
+                settle_amount
+            );
+            let balance: i64 = REFUND_SETTLEMENT
+                .arg(RedisAccountId(account_id))
+                .arg(settle_amount)
+                .arg(journal_timestamp())
+                .invoke_async(&mut self.connection.clone())
+                .await?;
 
-        trace!(
-            "Refunded settlement for account: {} of amount: {}. Balance is now: {}",
-            account_id,
-            settle_amount,
-            balance
-        );
-        Ok(())
+            trace!(
+                "Refunded settlement for account: {} of amount: {}. Balance is now: {}",
+                account_id,
+                settle_amount,
+                balance
+            );
+            Ok(())
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl SettlementTotalsStore for RedisStore {
+    async fn get_settlement_totals(
+        &self,
+        account_id: Uuid,
+    ) -> Result<(u64, u64), SettlementStoreError> {
+        instrument(BACKEND, "get_settlement_totals", async move {
+            // The totals are missing until the first settlement in each direction
+            let values: Vec<Option<i64>> = self
+                .connection
+                .clone()
+                .hget(accounts_key(account_id), &["settled_out", "settled_in"])
+                .await?;
+            let total = |value: Option<i64>| value.unwrap_or_default().max(0) as u64;
+            Ok((total(values[0]), total(values[1])))
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl SettlementQueueStore for RedisStore {
+    async fn queue_settlement(
+        &self,
+        account_id: Uuid,
+        settle_amount: u64,
+    ) -> Result<(), SettlementStoreError> {
+        instrument(BACKEND, "queue_settlement", async move {
+            let _: u64 = self
+                .connection
+                .clone()
+                .rpush(
+                    SETTLEMENT_QUEUE_KEY,
+                    format!("{}:{}", account_id, settle_amount),
+                )
+                .await?;
+            trace!(
+                "Queued settlement for account: {} of amount: {}",
+                account_id,
+                settle_amount
+            );
+            Ok(())
+        })
+        .await
+    }
+
+    async fn take_queued_settlement(&self) -> Result<Option<(Uuid, u64)>, SettlementStoreError> {
+        instrument(BACKEND, "take_queued_settlement", async move {
+            let settlement: Option<String> =
+                self.connection.clone().lpop(SETTLEMENT_QUEUE_KEY).await?;
+            let settlement = match settlement {
+                Some(settlement) => settlement,
+                None => return Ok(None),
+            };
+            let mut parts = settlement.splitn(2, ':');
+            let account_id = parts.next().and_then(|id| Uuid::from_str(id).ok());
+            let settle_amount = parts.next().and_then(|amount| amount.parse().ok());
+            match (account_id, settle_amount) {
+                (Some(account_id), Some(settle_amount)) => Ok(Some((account_id, settle_amount))),
+                _ => Err(RedisError::from((
+                    ErrorKind::TypeError,
+                    "Invalid queued settlement",
+                    settlement,
+                ))
+                .into()),
+            }
+        })
+        .await
     }
 }
 
@@ -1694,18 +4860,22 @@ impl LeftoversStore for RedisStore {
         &self,
         account_id: Uuid,
     ) -> Result<(Self::AssetType, u8), LeftoversStoreError> {
-        let mut pipe = redis_crate::pipe();
-        pipe.atomic();
-        // get the amounts and instantly delete them
-        pipe.lrange(uncredited_amount_key(account_id.to_string()), 0, -1);
-        pipe.del(uncredited_amount_key(account_id.to_string()))
-            .ignore();
+        instrument(BACKEND, "get_uncredited_settlement_amount", async move {
+            let mut pipe = redis_crate::pipe();
+            pipe.atomic();
+            // get the amounts and instantly delete them
+            pipe.lrange(uncredited_amount_key(account_id.to_string()), 0, -1);
+            pipe.del(uncredited_amount_key(account_id.to_string()))
+                .ignore();
 
-        let amounts: Vec<AmountWithScale> = pipe.query_async(&mut self.connection.clone()).await?;
+            let amounts: Vec<AmountWithScale> =
+                pipe.query_async(&mut self.connection.clone()).await?;
 
-        // this call will only return 1 element
-        let amount = amounts[0].to_owned();
-        Ok((amount.num, amount.scale))
+            // this call will only return 1 element
+            let amount = amounts[0].to_owned();
+            Ok((amount.num, amount.scale))
+        })
+        .await
     }
 
     async fn save_uncredited_settlement_amount(
@@ -1713,27 +4883,36 @@ impl LeftoversStore for RedisStore {
         account_id: Uuid,
         uncredited_settlement_amount: (Self::AssetType, u8),
     ) -> Result<(), LeftoversStoreError> {
-        trace!(
-            "Saving uncredited_settlement_amount {:?} {:?}",
-            account_id,
-            uncredited_settlement_amount
-        );
-        // We store these amounts as lists of strings
-        // because we cannot do BigNumber arithmetic in the store
-        // When loading the amounts, we convert them to the appropriate data
-        // type and sum them up.
-        let mut connection = self.connection.clone();
-        connection
-            .rpush(
-                uncredited_amount_key(account_id),
-                AmountWithScale {
-                    num: uncredited_settlement_amount.0,
-                    scale: uncredited_settlement_amount.1,
-                },
-            )
-            .await?;
+        instrument(BACKEND, "save_uncredited_settlement_amount", async move {
+            trace!(
+                "Saving uncredited_settlement_amount {:?} {:?}",
+                account_id,
+                uncredited_settlement_amount
+            );
+            // We store these amounts as lists of strings
+            // because we cannot do BigNumber arithmetic in the store
+            // When loading the amounts, we convert them to the appropriate data
+            // type and sum them up.
+            let mut pipe = redis_crate::pipe();
+            pipe.atomic()
+                .rpush(
+                    uncredited_amount_key(account_id),
+                    AmountWithScale {
+                        num: uncredited_settlement_amount.0,
+                        scale: uncredited_settlement_amount.1,
+                    },
+                )
+                .ignore();
+            if let Some(ttl) = self.uncredited_settlement_ttl {
+                pipe.expire(uncredited_amount_key(account_id), ttl as usize)
+                    .ignore();
+            }
+            pipe.query_async::<_, ()>(&mut self.connection.clone())
+                .await?;
 
-        Ok(())
+            Ok(())
+        })
+        .await
     }
 
     async fn load_uncredited_settlement_amount(
@@ -1741,39 +4920,238 @@ impl LeftoversStore for RedisStore {
         account_id: Uuid,
         local_scale: u8,
     ) -> Result<Self::AssetType, LeftoversStoreError> {
-        trace!("Loading uncredited_settlement_amount {:?}", account_id);
-        let amount = self.get_uncredited_settlement_amount(account_id).await?;
-        // scale the amount from the max scale to the local scale, and then
-        // save any potential leftovers to the store
-        let (scaled_amount, precision_loss) =
-            scale_with_precision_loss(amount.0, local_scale, amount.1);
-
-        if precision_loss > BigUint::from(0u32) {
-            self.connection
-                .clone()
-                .rpush(
-                    uncredited_amount_key(account_id),
-                    AmountWithScale {
-                        num: precision_loss,
-                        scale: std::cmp::max(local_scale, amount.1),
-                    },
-                )
-                .await?;
-        }
+        instrument(BACKEND, "load_uncredited_settlement_amount", async move {
+            trace!("Loading uncredited_settlement_amount {:?}", account_id);
+            let amount = self.get_uncredited_settlement_amount(account_id).await?;
+            // scale the amount from the max scale to the local scale, and then
+            // save any potential leftovers to the store
+            let (scaled_amount, precision_loss) =
+                scale_with_precision_loss(amount.0, local_scale, amount.1);
+
+            if precision_loss > BigUint::from(0u32) {
+                let mut pipe = redis_crate::pipe();
+                pipe.atomic()
+                    .rpush(
+                        uncredited_amount_key(account_id),
+                        AmountWithScale {
+                            num: precision_loss,
+                            scale: std::cmp::max(local_scale, amount.1),
+                        },
+                    )
+                    .ignore();
+                if let Some(ttl) = self.uncredited_settlement_ttl {
+                    pipe.expire(uncredited_amount_key(account_id), ttl as usize)
+                        .ignore();
+                }
+                pipe.query_async::<_, ()>(&mut self.connection.clone())
+                    .await?;
+            }
 
-        Ok(scaled_amount)
+            Ok(scaled_amount)
+        })
+        .await
     }
 
     async fn clear_uncredited_settlement_amount(
         &self,
         account_id: Uuid,
     ) -> Result<(), LeftoversStoreError> {
-        trace!("Clearing uncredited_settlement_amount {:?}", account_id);
-        self.connection
-            .clone()
-            .del(uncredited_amount_key(account_id))
+        instrument(BACKEND, "clear_uncredited_settlement_amount", async move {
+            trace!("Clearing uncredited_settlement_amount {:?}", account_id);
+            self.connection
+                .clone()
+                .del::<_, ()>(uncredited_amount_key(account_id))
+                .await?;
+            Ok(())
+        })
+        .await
+    }
+}
+
+type EncryptionKeys = (Secret<EncryptionKey>, Secret<DecryptionKey>);
+
+/// Loads the data encryption key and unwraps it with the provided key manager.
+/// Returns `None` if no data key was stored yet.
+async fn unwrap_stored_data_key(
+    connection: &mut RedisReconnect,
+    key_manager: &dyn KeyManager,
+) -> Result<Option<EncryptionKeys>, ()> {
+    let (key_id, wrapped_key): (Option<String>, Option<Vec<u8>>) = connection
+        .hget(DATA_KEY_KEY, &["key_id", "wrapped_key"])
+        .map_err(|err| error!("Error loading the data encryption key: {:?}", err))
+        .await?;
+    let (key_id, wrapped_key) = match (key_id, wrapped_key) {
+        (Some(key_id), Some(wrapped_key)) => (key_id, wrapped_key),
+        _ => return Ok(None),
+    };
+    if key_id != key_manager.key_id() {
+        warn!(
+            "Data encryption key was wrapped by {} but the key manager is {}",
+            key_id,
+            key_manager.key_id()
+        );
+    }
+    let data_key = key_manager
+        .unwrap_key(&wrapped_key)
+        .map_err(|err| error!("Error unwrapping the data encryption key: {}", err))
+        .await?;
+    let keys = keys_from_data_key(data_key.expose_secret().as_ref())
+        .map_err(|_| error!("Unwrapped data encryption key is invalid"))?;
+    Ok(Some(keys))
+}
+
+/// Generates a new data encryption key wrapped by the provided key manager, and
+/// re-encrypts the secrets of every account with it. The secrets and the wrapped key
+/// are written in a single transaction. Returns the new keys and the number of
+/// accounts whose secrets were re-encrypted.
+async fn replace_data_key(
+    connection: &mut RedisReconnect,
+    key_manager: &dyn KeyManager,
+    decryption_key: &aead::LessSafeKey,
+) -> Result<(EncryptionKeys, usize), ()> {
+    let data_key = generate_data_key();
+    let wrapped_key = key_manager
+        .wrap_key(data_key.expose_secret().as_ref())
+        .map_err(|err| error!("Error wrapping the data encryption key: {}", err))
+        .await?;
+    let (encryption_key, new_decryption_key) =
+        keys_from_data_key(data_key.expose_secret().as_ref())?;
+
+    let account_ids: Vec<RedisAccountId> = connection
+        .smembers("accounts")
+        .map_err(|err| error!("Error loading account ids: {:?}", err))
+        .await?;
+    let mut pipe = redis_crate::pipe();
+    pipe.atomic();
+    for account_id in account_ids.iter() {
+        let id = accounts_key(account_id.0);
+        let secrets: Vec<Option<Vec<u8>>> = connection
+            .hget(&id, &ENCRYPTED_ACCOUNT_FIELDS[..])
+            .map_err(|err| {
+                error!(
+                    "Error loading the secrets of account {}: {:?}",
+                    account_id, err
+                )
+            })
             .await?;
-        Ok(())
+        for (field, secret) in ENCRYPTED_ACCOUNT_FIELDS.iter().zip(secrets) {
+            // The hashed incoming tokens do not depend on the data key
+            if let Some(secret) = secret.filter(|secret| !is_hashed_token(secret)) {
+                let decrypted = decrypt_token(decryption_key, &secret).map_err(|_| {
+                    error!("Unable to decrypt {} for account {}", field, account_id)
+                })?;
+                let encrypted =
+                    encrypt_token(&encryption_key.expose_secret().0, decrypted.expose_secret());
+                pipe.hset(&id, *field, encrypted.as_ref()).ignore();
+            }
+        }
+    }
+    // The tokens of the pending peering requests are encrypted with the data key too
+    let requests: HashMap<String, String> = connection
+        .hgetall(PEERING_REQUESTS_KEY)
+        .map_err(|err| error!("Error loading the peering requests: {:?}", err))
+        .await?;
+    for (id, serialized) in requests {
+        let request = match serde_json::from_str(&serialized)
+            .map_err(|_| ())
+            .and_then(|request| decrypt_peering_request(decryption_key, request))
+        {
+            Ok(request) => request,
+            Err(_) => {
+                // The nodes drop the requests they cannot decrypt when listing them
+                warn!("Unable to decrypt the tokens of peering request {}", id);
+                continue;
+            }
+        };
+        let request = encrypt_peering_request(&encryption_key.expose_secret().0, request);
+        let serialized = serde_json::to_string(&request)
+            .map_err(|err| error!("Error serializing peering request {}: {}", id, err))?;
+        pipe.hset(PEERING_REQUESTS_KEY, &id, serialized).ignore();
+    }
+    pipe.hset(DATA_KEY_KEY, "key_id", key_manager.key_id())
+        .ignore()
+        .hset(DATA_KEY_KEY, "wrapped_key", wrapped_key)
+        .ignore();
+    pipe.query_async::<_, ()>(connection)
+        .map_err(|err| error!("Error storing the re-encrypted secrets: {:?}", err))
+        .await?;
+
+    Ok(((encryption_key, new_decryption_key), account_ids.len()))
+}
+
+/// Hashes the incoming tokens which were stored encrypted, before the incoming tokens
+/// were hashed, so that the store does not hold them in a recoverable form anymore.
+/// Tokens which cannot be decrypted are left as they are. Returns the number of
+/// tokens which were hashed.
+async fn hash_legacy_incoming_tokens(
+    connection: &mut RedisReconnect,
+    decryption_key: &aead::LessSafeKey,
+    hashing_key: &hmac::Key,
+) -> Result<usize, ()> {
+    let account_ids: Vec<RedisAccountId> = connection
+        .smembers("accounts")
+        .map_err(|err| error!("Error loading account ids: {:?}", err))
+        .await?;
+    if account_ids.is_empty() {
+        return Ok(0);
+    }
+    let mut load_tokens = redis_crate::pipe();
+    for account_id in account_ids.iter() {
+        load_tokens.hget(accounts_key(account_id.0), &INCOMING_TOKEN_FIELDS[..]);
+    }
+    let tokens: Vec<Vec<Option<Vec<u8>>>> = load_tokens
+        .query_async(connection)
+        .map_err(|err| error!("Error loading the incoming tokens: {:?}", err))
+        .await?;
+
+    let mut pipe = redis_crate::pipe();
+    pipe.atomic();
+    let mut hashed = 0;
+    for (account_id, tokens) in account_ids.iter().zip(tokens) {
+        for (field, token) in INCOMING_TOKEN_FIELDS.iter().zip(tokens) {
+            let token = match token {
+                Some(token) if !is_hashed_token(&token) => token,
+                _ => continue,
+            };
+            match decrypt_token(decryption_key, &token) {
+                Ok(decrypted) => {
+                    let hashed_token = hash_token(hashing_key, decrypted.expose_secret());
+                    pipe.hset(accounts_key(account_id.0), *field, hashed_token.as_ref())
+                        .ignore();
+                    hashed += 1;
+                }
+                Err(_) => warn!(
+                    "Unable to decrypt {} for account {}, not hashing it",
+                    field, account_id
+                ),
+            }
+        }
+    }
+    if hashed > 0 {
+        pipe.query_async::<_, ()>(connection)
+            .map_err(|err| error!("Error storing the hashed incoming tokens: {:?}", err))
+            .await?;
+        info!(
+            "Hashed {} incoming tokens stored before they were hashed",
+            hashed
+        );
+    }
+    Ok(hashed)
+}
+
+/// Records the number of keys reclaimed while compacting the store
+fn record_compaction(key_type: &'static str, expiring: u64, deleted: u64) {
+    if expiring > 0 {
+        recorder().increment_counter(
+            Key::from_name_and_labels("store.compaction.expiring", labels!("key_type" => key_type)),
+            expiring,
+        );
+    }
+    if deleted > 0 {
+        recorder().increment_counter(
+            Key::from_name_and_labels("store.compaction.deleted", labels!("key_type" => key_type)),
+            deleted,
+        );
     }
 }
 
@@ -1882,6 +5260,10 @@ impl ToRedisArgs for &AccountWithEncryptedTokens {
         account.asset_scale.write_redis_args(&mut rv);
         "max_packet_amount".write_redis_args(&mut rv);
         account.max_packet_amount.write_redis_args(&mut rv);
+        if let Some(max_packet_size) = account.max_packet_size {
+            "max_packet_size".write_redis_args(&mut rv);
+            max_packet_size.write_redis_args(&mut rv);
+        }
         "routing_relation".write_redis_args(&mut rv);
         account
             .routing_relation
@@ -1889,6 +5271,38 @@ impl ToRedisArgs for &AccountWithEncryptedTokens {
             .write_redis_args(&mut rv);
         "round_trip_time".write_redis_args(&mut rv);
         account.round_trip_time.write_redis_args(&mut rv);
+        "route_full_table".write_redis_args(&mut rv);
+        account
+            .route_full_table
+            .to_string()
+            .write_redis_args(&mut rv);
+        "ilp_over_http_dialect".write_redis_args(&mut rv);
+        account
+            .ilp_over_http_dialect
+            .to_string()
+            .write_redis_args(&mut rv);
+        "ilp_over_btp_legacy".write_redis_args(&mut rv);
+        account
+            .ilp_over_btp_legacy
+            .to_string()
+            .write_redis_args(&mut rv);
+        // Always written (empty if there is none) so that updating an account can remove it
+        "transport_account".write_redis_args(&mut rv);
+        account
+            .transport_account
+            .as_ref()
+            .map(|username| username.to_string())
+            .unwrap_or_default()
+            .write_redis_args(&mut rv);
+        // Always written so that updating an account can remove its filters and allowlist
+        "packet_filters".write_redis_args(&mut rv);
+        serde_json::to_string(&account.packet_filters)
+            .unwrap_or_else(|_| "[]".to_string())
+            .write_redis_args(&mut rv);
+        "ip_allowlist".write_redis_args(&mut rv);
+        serde_json::to_string(&account.ip_allowlist)
+            .unwrap_or_else(|_| "[]".to_string())
+            .write_redis_args(&mut rv);
 
         // Write optional fields
         if let Some(ilp_over_http_url) = account.ilp_over_http_url.as_ref() {
@@ -1909,6 +5323,17 @@ impl ToRedisArgs for &AccountWithEncryptedTokens {
                 .as_ref()
                 .write_redis_args(&mut rv);
         }
+        if let Some(signing_key) = account.ilp_over_http_signing_key.as_ref() {
+            "ilp_over_http_signing_key".write_redis_args(&mut rv);
+            signing_key
+                .expose_secret()
+                .as_ref()
+                .write_redis_args(&mut rv);
+        }
+        if let Some(verification_key) = account.ilp_over_http_verification_key.as_ref() {
+            "ilp_over_http_verification_key".write_redis_args(&mut rv);
+            verification_key.write_redis_args(&mut rv);
+        }
         if let Some(ilp_over_btp_url) = account.ilp_over_btp_url.as_ref() {
             "ilp_over_btp_url".write_redis_args(&mut rv);
             ilp_over_btp_url.as_str().write_redis_args(&mut rv);
@@ -1935,6 +5360,18 @@ impl ToRedisArgs for &AccountWithEncryptedTokens {
             "settle_to".write_redis_args(&mut rv);
             settle_to.write_redis_args(&mut rv);
         }
+        if let Some(interval) = account.route_broadcast_interval {
+            "route_broadcast_interval".write_redis_args(&mut rv);
+            interval.write_redis_args(&mut rv);
+        }
+        if let Some(expiry) = account.route_expiry {
+            "route_expiry".write_redis_args(&mut rv);
+            expiry.write_redis_args(&mut rv);
+        }
+        if let Some(limit) = account.packets_per_second_limit {
+            "packets_per_second_limit".write_redis_args(&mut rv);
+            limit.write_redis_args(&mut rv);
+        }
         if let Some(limit) = account.packets_per_minute_limit {
             "packets_per_minute_limit".write_redis_args(&mut rv);
             limit.write_redis_args(&mut rv);
@@ -1947,10 +5384,38 @@ impl ToRedisArgs for &AccountWithEncryptedTokens {
             "min_balance".write_redis_args(&mut rv);
             min_balance.write_redis_args(&mut rv);
         }
+        if let Some(payable_limit) = account.payable_limit {
+            "payable_limit".write_redis_args(&mut rv);
+            payable_limit.write_redis_args(&mut rv);
+        }
+        if let Some(receivable_limit) = account.receivable_limit {
+            "receivable_limit".write_redis_args(&mut rv);
+            receivable_limit.write_redis_args(&mut rv);
+        }
         if let Some(settlement_engine_url) = &account.settlement_engine_url {
             "settlement_engine_url".write_redis_args(&mut rv);
             settlement_engine_url.as_str().write_redis_args(&mut rv);
         }
+        if let Some(url) = &account.liquidity_notification_url {
+            "liquidity_notification_url".write_redis_args(&mut rv);
+            url.as_str().write_redis_args(&mut rv);
+        }
+        if let Some(url) = &account.balance_notification_url {
+            "balance_notification_url".write_redis_args(&mut rv);
+            url.as_str().write_redis_args(&mut rv);
+        }
+        if let Some(spread) = account.spread {
+            "spread".write_redis_args(&mut rv);
+            spread.write_redis_args(&mut rv);
+        }
+        if let Some(percentage_fee) = account.percentage_fee {
+            "percentage_fee".write_redis_args(&mut rv);
+            percentage_fee.write_redis_args(&mut rv);
+        }
+        if let Some(fixed_fee) = account.fixed_fee {
+            "fixed_fee".write_redis_args(&mut rv);
+            fixed_fee.write_redis_args(&mut rv);
+        }
 
         debug_assert!(rv.len() <= ACCOUNT_DETAILS_FIELDS * 2);
         debug_assert!((rv.len() % 2) == 0);
@@ -1977,6 +5442,38 @@ impl FromRedisValue for AccountWithEncryptedTokens {
         };
         let round_trip_time: Option<u32> = get_value_option("round_trip_time", &hash)?;
         let round_trip_time: u32 = round_trip_time.unwrap_or(DEFAULT_ROUND_TRIP_TIME);
+        let route_full_table: Option<String> = get_value_option("route_full_table", &hash)?;
+        let route_full_table = route_full_table.as_deref() == Some("true");
+        let ilp_over_http_dialect: Option<String> =
+            get_value_option("ilp_over_http_dialect", &hash)?;
+        let ilp_over_http_dialect = match ilp_over_http_dialect {
+            Some(dialect) => HttpDialect::from_str(&dialect).map_err(|_| {
+                RedisError::from((ErrorKind::TypeError, "Invalid ILP over HTTP dialect"))
+            })?,
+            None => HttpDialect::default(),
+        };
+        let ilp_over_btp_legacy: Option<String> = get_value_option("ilp_over_btp_legacy", &hash)?;
+        let ilp_over_btp_legacy = ilp_over_btp_legacy.as_deref() == Some("true");
+        let transport_account: Option<String> = get_value_option("transport_account", &hash)?;
+        let transport_account = match transport_account.as_deref() {
+            None | Some("") => None,
+            Some(username) => Some(Username::from_str(username).map_err(|_| {
+                RedisError::from((ErrorKind::TypeError, "Invalid transport account"))
+            })?),
+        };
+
+        let packet_filters: Option<String> = get_value_option("packet_filters", &hash)?;
+        let packet_filters = match packet_filters {
+            Some(packet_filters) => serde_json::from_str(&packet_filters)
+                .map_err(|_| RedisError::from((ErrorKind::TypeError, "Invalid packet filters")))?,
+            None => Vec::new(),
+        };
+        let ip_allowlist: Option<String> = get_value_option("ip_allowlist", &hash)?;
+        let ip_allowlist = match ip_allowlist {
+            Some(ip_allowlist) => serde_json::from_str(&ip_allowlist)
+                .map_err(|_| RedisError::from((ErrorKind::TypeError, "Invalid IP allowlist")))?,
+            None => Vec::new(),
+        };
 
         let rid: RedisAccountId = get_value("id", &hash)?;
 
@@ -1998,6 +5495,13 @@ impl FromRedisValue for AccountWithEncryptedTokens {
                     &hash,
                 )?
                 .map(SecretBytesMut::from),
+                ilp_over_http_dialect,
+                ilp_over_http_signing_key: get_bytes_option("ilp_over_http_signing_key", &hash)?
+                    .map(SecretBytesMut::from),
+                ilp_over_http_verification_key: get_value_option(
+                    "ilp_over_http_verification_key",
+                    &hash,
+                )?,
                 ilp_over_btp_url: get_url_option("ilp_over_btp_url", &hash)?,
                 ilp_over_btp_incoming_token: get_bytes_option(
                     "ilp_over_btp_incoming_token",
@@ -2009,15 +5513,31 @@ impl FromRedisValue for AccountWithEncryptedTokens {
                     &hash,
                 )?
                 .map(SecretBytesMut::from),
+                ilp_over_btp_legacy,
+                transport_account,
                 max_packet_amount: get_value("max_packet_amount", &hash)?,
+                max_packet_size: get_value_option("max_packet_size", &hash)?,
                 min_balance: get_value_option("min_balance", &hash)?,
+                payable_limit: get_value_option("payable_limit", &hash)?,
+                receivable_limit: get_value_option("receivable_limit", &hash)?,
                 settle_threshold: get_value_option("settle_threshold", &hash)?,
                 settle_to: get_value_option("settle_to", &hash)?,
                 routing_relation,
                 round_trip_time,
+                route_broadcast_interval: get_value_option("route_broadcast_interval", &hash)?,
+                route_expiry: get_value_option("route_expiry", &hash)?,
+                route_full_table,
+                packets_per_second_limit: get_value_option("packets_per_second_limit", &hash)?,
                 packets_per_minute_limit: get_value_option("packets_per_minute_limit", &hash)?,
                 amount_per_minute_limit: get_value_option("amount_per_minute_limit", &hash)?,
                 settlement_engine_url: get_url_option("settlement_engine_url", &hash)?,
+                liquidity_notification_url: get_url_option("liquidity_notification_url", &hash)?,
+                balance_notification_url: get_url_option("balance_notification_url", &hash)?,
+                spread: get_value_option("spread", &hash)?,
+                percentage_fee: get_value_option("percentage_fee", &hash)?,
+                fixed_fee: get_value_option("fixed_fee", &hash)?,
+                packet_filters,
+                ip_allowlist,
             },
         })
     }
@@ -0,0 +1,158 @@
+use super::reconnect::RedisReconnect;
+use futures::future::{join_all, TryFutureExt};
+use redis_crate::{self, AsyncCommands, ConnectionInfo, RedisError};
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+static REPLICA_HEARTBEAT_PREFIX: &str = "replica_heartbeat:";
+
+struct Replica {
+    connection: RedisReconnect,
+    /// Whether the replica lagged behind the primary by less than the
+    /// staleness bound when it was last checked
+    fresh: AtomicBool,
+}
+
+/// Read-only replicas of the primary Redis instance, which serve reads
+/// that can tolerate data which is at most `max_staleness` milliseconds old.
+///
+/// The lag of each replica is measured by writing a timestamp to the primary and
+/// reading it back from the replica. Replicas which lag too far behind (or cannot
+/// be reached) are skipped until they catch up again.
+#[derive(Clone)]
+pub(crate) struct ReadReplicas {
+    replicas: Arc<Vec<Replica>>,
+    /// Index of the replica to try first for the next read
+    next: Arc<AtomicUsize>,
+    /// Each store writes its own heartbeat so that the clocks of different nodes
+    /// sharing the database do not affect the measured lag
+    heartbeat_key: Arc<String>,
+    max_staleness: u64,
+}
+
+impl ReadReplicas {
+    /// No replicas, all reads go to the primary
+    pub fn none() -> Self {
+        ReadReplicas {
+            replicas: Arc::new(Vec::new()),
+            next: Arc::new(AtomicUsize::new(0)),
+            heartbeat_key: Arc::new(String::new()),
+            max_staleness: 0,
+        }
+    }
+
+    /// Connects to the replicas. They are not used for reads until
+    /// their lag has been checked for the first time.
+    pub async fn connect(
+        replica_urls: Vec<ConnectionInfo>,
+        max_staleness: u64,
+    ) -> Result<Self, RedisError> {
+        let mut replicas = Vec::with_capacity(replica_urls.len());
+        for replica_url in replica_urls {
+            let connection = RedisReconnect::connect(replica_url).await?;
+            replicas.push(Replica {
+                connection,
+                fresh: AtomicBool::new(false),
+            });
+        }
+        Ok(ReadReplicas {
+            replicas: Arc::new(replicas),
+            next: Arc::new(AtomicUsize::new(0)),
+            heartbeat_key: Arc::new(format!("{}{}", REPLICA_HEARTBEAT_PREFIX, Uuid::new_v4())),
+            max_staleness: max_staleness.max(1),
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.replicas.is_empty()
+    }
+
+    /// Interval on which the lag of the replicas must be checked. The lag of a replica
+    /// may grow by up to this interval between two checks, which is accounted for
+    /// when deciding whether it is within the staleness bound.
+    pub fn check_interval(&self) -> Duration {
+        Duration::from_millis((self.max_staleness / 4).max(1))
+    }
+
+    /// Returns a connection to one of the replicas within the staleness bound
+    /// (in round-robin order), or None if there is no such replica
+    pub fn connection(&self) -> Option<RedisReconnect> {
+        let len = self.replicas.len();
+        if len == 0 {
+            return None;
+        }
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        (0..len)
+            .map(|i| &self.replicas[(start + i) % len])
+            .find(|replica| replica.fresh.load(Ordering::Relaxed))
+            .map(|replica| replica.connection.clone())
+    }
+
+    /// Measures the lag of every replica and marks those within the staleness bound
+    /// as usable for reads
+    pub async fn check_staleness(&self, primary: &mut RedisReconnect) -> Result<(), RedisError> {
+        redis_crate::cmd("SET")
+            .arg(self.heartbeat_key.as_str())
+            .arg(timestamp_ms())
+            .arg("PX")
+            .arg(self.max_staleness * 2)
+            .query_async::<_, ()>(primary)
+            .await?;
+
+        let check_interval = self.check_interval().as_millis() as u64;
+        join_all(self.replicas.iter().map(|replica| {
+            let heartbeat_key = self.heartbeat_key.clone();
+            let max_staleness = self.max_staleness;
+            async move {
+                let heartbeat: Option<u64> = replica
+                    .connection
+                    .clone()
+                    .get(heartbeat_key.as_str())
+                    .map_err(|err| {
+                        debug!(
+                            "Error reading heartbeat from replica {:?}: {}",
+                            replica.connection.redis_info.addr, err
+                        )
+                    })
+                    .await
+                    .unwrap_or(None);
+                // A missing heartbeat means the replica is more than twice the
+                // staleness bound behind, or it is not replicating the primary
+                let lag = heartbeat.map(|heartbeat| timestamp_ms().saturating_sub(heartbeat));
+                let fresh = lag
+                    .map(|lag| lag + check_interval <= max_staleness)
+                    .unwrap_or(false);
+                if replica.fresh.swap(fresh, Ordering::Relaxed) != fresh {
+                    if fresh {
+                        debug!(
+                            "Using read replica {:?} (lag: {}ms)",
+                            replica.connection.redis_info.addr,
+                            lag.unwrap_or_default()
+                        );
+                    } else {
+                        warn!(
+                            "Not using read replica {:?} because it lags behind the primary by more than {}ms",
+                            replica.connection.redis_info.addr, max_staleness
+                        );
+                    }
+                }
+            }
+        }))
+        .await;
+        Ok(())
+    }
+}
+
+fn timestamp_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
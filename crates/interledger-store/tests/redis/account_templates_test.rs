@@ -0,0 +1,55 @@
+use super::store_helpers::*;
+
+use interledger_api::{AccountTemplate, AccountTemplateStore};
+use serde_json::json;
+
+fn template(name: &str, asset_code: &str) -> AccountTemplate {
+    AccountTemplate {
+        name: name.to_string(),
+        fields: json!({
+            "asset_code": asset_code,
+            "asset_scale": 9,
+            "routing_relation": "Peer",
+        })
+        .as_object()
+        .unwrap()
+        .clone(),
+    }
+}
+
+#[tokio::test]
+async fn saves_gets_and_deletes_account_templates() {
+    let (store, _context, _) = test_store().await.unwrap();
+    let xrp = template("peer-xrp", "XRP");
+    let eth = template("peer-eth", "ETH");
+    store.set_account_template(xrp.clone()).await.unwrap();
+    store.set_account_template(eth.clone()).await.unwrap();
+
+    assert_eq!(
+        store.get_account_template("peer-xrp").await.unwrap(),
+        Some(xrp.clone())
+    );
+    assert_eq!(store.get_account_template("peer-usd").await.unwrap(), None);
+    assert_eq!(
+        store.get_account_templates().await.unwrap(),
+        vec![eth.clone(), xrp]
+    );
+
+    // Setting a template with the same name replaces it
+    let replaced = template("peer-xrp", "XRP2");
+    store.set_account_template(replaced.clone()).await.unwrap();
+    assert_eq!(
+        store.get_account_template("peer-xrp").await.unwrap(),
+        Some(replaced.clone())
+    );
+
+    assert_eq!(
+        store.delete_account_template("peer-xrp").await.unwrap(),
+        Some(replaced)
+    );
+    assert_eq!(
+        store.delete_account_template("peer-xrp").await.unwrap(),
+        None
+    );
+    assert_eq!(store.get_account_templates().await.unwrap(), vec![eth]);
+}
@@ -1,18 +1,25 @@
 use super::{fixtures::*, redis_helpers::*, store_helpers::*};
 use interledger_api::{AccountSettings, NodeStore};
-use interledger_btp::BtpAccount;
+use interledger_btp::{BtpAccount, BtpStore};
 use interledger_ccp::{CcpRoutingAccount, RoutingRelation};
 use interledger_http::HttpAccount;
 use interledger_packet::Address;
 use interledger_service::Account as AccountTrait;
-use interledger_service::{AccountStore, AddressStore, Username};
-use interledger_service_util::BalanceStore;
+use interledger_service::{
+    AccountStore, AddressStore, HttpDialect, IpAllowlistAccount, IpNetwork, PacketFilter,
+    PacketFilterAccount, TimeOfDay, Username,
+};
+use interledger_service_util::{
+    BalanceStore, MaxPacketAmountAccount, MaxPacketSizeAccount, RateLimitAccount,
+};
+use interledger_settlement::core::types::SettlementAccount;
 use interledger_store::redis::RedisStoreBuilder;
 use redis_crate::Client;
 use secrecy::ExposeSecret;
 use secrecy::SecretString;
 use std::default::Default;
 use std::str::FromStr;
+use url::Url;
 use uuid::Uuid;
 
 #[tokio::test]
@@ -196,6 +203,174 @@ async fn update_accounts() {
     assert_eq!(err.to_string(), format!("account `{}` was not found", id));
 }
 
+#[tokio::test]
+async fn stores_packet_filters() {
+    let (store, _context, accounts) = test_store().await.unwrap();
+    let id = accounts[0].id();
+    let filters = vec![
+        PacketFilter::DenyPrefixes {
+            prefixes: vec!["example.mallory".to_string()],
+        },
+        PacketFilter::TimeWindow {
+            start: TimeOfDay::new(8, 0).unwrap(),
+            end: TimeOfDay::new(20, 0).unwrap(),
+        },
+    ];
+    let mut details = ACCOUNT_DETAILS_0.clone();
+    details.packet_filters = filters.clone();
+    store.update_account(id, details.clone()).await.unwrap();
+    let account = store.get_accounts(vec![id]).await.unwrap().pop().unwrap();
+    assert_eq!(account.packet_filters(), &filters[..]);
+
+    // Updating the account without filters removes them
+    details.packet_filters = Vec::new();
+    store.update_account(id, details).await.unwrap();
+    let account = store.get_accounts(vec![id]).await.unwrap().pop().unwrap();
+    assert!(account.packet_filters().is_empty());
+}
+
+#[tokio::test]
+async fn stores_ip_allowlist() {
+    let (store, _context, accounts) = test_store().await.unwrap();
+    let id = accounts[0].id();
+    let allowlist = vec![
+        IpNetwork::from_str("203.0.113.0/24").unwrap(),
+        IpNetwork::from_str("2001:db8::1").unwrap(),
+    ];
+    let mut details = ACCOUNT_DETAILS_0.clone();
+    details.ip_allowlist = allowlist.clone();
+    store.update_account(id, details.clone()).await.unwrap();
+    let account = store.get_accounts(vec![id]).await.unwrap().pop().unwrap();
+    assert_eq!(account.ip_allowlist(), &allowlist[..]);
+    assert!(account.is_ip_allowed(Some("203.0.113.7".parse().unwrap())));
+    assert!(!account.is_ip_allowed(Some("198.51.100.7".parse().unwrap())));
+
+    details.ip_allowlist = Vec::new();
+    store.update_account(id, details).await.unwrap();
+    let account = store.get_accounts(vec![id]).await.unwrap().pop().unwrap();
+    assert!(account.ip_allowlist().is_empty());
+}
+
+#[tokio::test]
+async fn stores_ilp_over_http_dialect() {
+    let (store, _context, accounts) = test_store().await.unwrap();
+    let id = accounts[0].id();
+    assert_eq!(accounts[0].get_http_dialect(), HttpDialect::Rfc);
+    let mut details = ACCOUNT_DETAILS_0.clone();
+    details.ilp_over_http_dialect = HttpDialect::JavaJwt;
+    store.update_account(id, details).await.unwrap();
+    let account = store.get_accounts(vec![id]).await.unwrap().pop().unwrap();
+    assert_eq!(account.get_http_dialect(), HttpDialect::JavaJwt);
+}
+
+#[tokio::test]
+async fn stores_ilp_over_http_signature_keys() {
+    let (store, _context, accounts) = test_store().await.unwrap();
+    let id = accounts[0].id();
+    assert!(accounts[0].get_http_signing_key().is_none());
+    let mut details = ACCOUNT_DETAILS_0.clone();
+    details.ilp_over_http_signing_key = Some(SecretString::new(
+        "nWGxne/9WmC6hEr0kuwsxERJxWl7MmkZcDusAxyuf2A=".to_string(),
+    ));
+    details.ilp_over_http_verification_key =
+        Some("11qYAYKxCrfVS/7TyWQHOg7hcvPapiMlrwIaaPcHURo=".to_string());
+    store.update_account(id, details.clone()).await.unwrap();
+    let account = store.get_accounts(vec![id]).await.unwrap().pop().unwrap();
+    assert_eq!(
+        account.get_http_signing_key().unwrap().expose_secret(),
+        "nWGxne/9WmC6hEr0kuwsxERJxWl7MmkZcDusAxyuf2A="
+    );
+    assert_eq!(
+        account.get_http_verification_key(),
+        Some("11qYAYKxCrfVS/7TyWQHOg7hcvPapiMlrwIaaPcHURo=")
+    );
+
+    details.ilp_over_http_verification_key = Some("not a key".to_string());
+    assert!(store.update_account(id, details).await.is_err());
+}
+
+#[tokio::test]
+async fn stores_ilp_over_btp_legacy() {
+    let (store, _context, accounts) = test_store().await.unwrap();
+    let id = accounts[0].id();
+    assert!(!accounts[0].is_legacy_btp_peer());
+    let mut details = ACCOUNT_DETAILS_0.clone();
+    details.ilp_over_btp_legacy = true;
+    store.update_account(id, details).await.unwrap();
+    let account = store.get_accounts(vec![id]).await.unwrap().pop().unwrap();
+    assert!(account.is_legacy_btp_peer());
+}
+
+#[tokio::test]
+async fn stores_transport_account() {
+    let (store, _context, accounts) = test_store().await.unwrap();
+    let id = accounts[1].id();
+    assert_eq!(accounts[1].get_transport_account(), None);
+    let mut details = ACCOUNT_DETAILS_1.clone();
+    details.transport_account = Some(accounts[0].username().clone());
+    store.update_account(id, details).await.unwrap();
+    let account = store.get_accounts(vec![id]).await.unwrap().pop().unwrap();
+    assert_eq!(
+        account.get_transport_account(),
+        Some(accounts[0].username())
+    );
+    let sub_accounts = store.get_sub_accounts(&accounts[0]).await.unwrap();
+    assert_eq!(sub_accounts.len(), 1);
+    assert_eq!(sub_accounts[0].id(), id);
+
+    // Updating the account without a transport account removes it
+    store
+        .update_account(id, ACCOUNT_DETAILS_1.clone())
+        .await
+        .unwrap();
+    assert!(store
+        .get_sub_accounts(&accounts[0])
+        .await
+        .unwrap()
+        .is_empty());
+
+    // An account cannot share its own connection
+    let mut details = ACCOUNT_DETAILS_1.clone();
+    details.transport_account = Some(details.username.clone());
+    assert!(store.update_account(id, details).await.is_err());
+}
+
+#[tokio::test]
+async fn stores_route_broadcast_settings() {
+    let (store, _context, accounts) = test_store().await.unwrap();
+    let id = accounts[0].id();
+    assert_eq!(accounts[0].route_broadcast_interval(), None);
+    assert!(!accounts[0].should_receive_full_table());
+    let mut details = ACCOUNT_DETAILS_0.clone();
+    details.route_broadcast_interval = Some(120_000);
+    details.route_expiry = Some(300_000);
+    details.route_full_table = true;
+    store.update_account(id, details).await.unwrap();
+    let account = store.get_accounts(vec![id]).await.unwrap().pop().unwrap();
+    assert_eq!(account.route_broadcast_interval(), Some(120_000));
+    assert_eq!(account.route_expiry(), Some(300_000));
+    assert!(account.should_receive_full_table());
+}
+
+#[tokio::test]
+async fn stores_liquidity_notification_url() {
+    let (store, _context, accounts) = test_store().await.unwrap();
+    let id = accounts[0].id();
+    assert_eq!(accounts[0].liquidity_notification_url(), None);
+    let mut details = ACCOUNT_DETAILS_0.clone();
+    details.liquidity_notification_url = Some("https://peer.example/liquidity".to_string());
+    store.update_account(id, details).await.unwrap();
+    let account = store.get_accounts(vec![id]).await.unwrap().pop().unwrap();
+    assert_eq!(
+        account.liquidity_notification_url(),
+        Some(Url::parse("https://peer.example/liquidity").unwrap())
+    );
+
+    let mut details = ACCOUNT_DETAILS_0.clone();
+    details.liquidity_notification_url = Some("not a url".to_string());
+    assert!(store.update_account(id, details).await.is_err());
+}
+
 #[tokio::test]
 async fn modify_account_settings_settle_to_overflow() {
     let (store, _context, accounts) = test_store().await.unwrap();
@@ -214,6 +389,24 @@ async fn modify_account_settings_settle_to_overflow() {
     );
 }
 
+#[tokio::test]
+async fn modify_account_settings_zero_rate_limit() {
+    let (store, _context, accounts) = test_store().await.unwrap();
+    let settings = AccountSettings {
+        packets_per_second_limit: Some(0),
+        ..Default::default()
+    };
+    let id = accounts[0].id();
+    let err = store
+        .modify_account_settings(id, settings)
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "invalid account: the provided value for rate limit `packets_per_second_limit` must be greater than zero"
+    );
+}
+
 #[tokio::test]
 async fn modify_account_settings_unchanged() {
     let (store, _context, accounts) = test_store().await.unwrap();
@@ -245,6 +438,11 @@ async fn modify_account_settings() {
         ilp_over_btp_url: Some("http://example.com/accounts/dylan/ilp/btp".to_owned()),
         settle_threshold: Some(-50),
         settle_to: Some(100),
+        packets_per_second_limit: Some(5),
+        packets_per_minute_limit: Some(100),
+        amount_per_minute_limit: Some(5000),
+        max_packet_amount: Some(2000),
+        max_packet_size: Some(4096),
     };
     let account = accounts[0].clone();
 
@@ -261,6 +459,11 @@ async fn modify_account_settings() {
         ret.get_ilp_over_btp_outgoing_token().unwrap(),
         &b"dylan:test"[..],
     );
+    assert_eq!(ret.packets_per_second_limit(), Some(5));
+    assert_eq!(ret.packets_per_minute_limit(), Some(100));
+    assert_eq!(ret.amount_per_minute_limit(), Some(5000));
+    assert_eq!(ret.max_packet_amount(), 2000);
+    assert_eq!(ret.max_packet_size(), Some(4096));
 
     let id = Uuid::new_v4();
     let err = store
@@ -300,6 +503,25 @@ async fn get_all_accounts() {
     assert_eq!(accounts.len(), 2);
 }
 
+#[tokio::test]
+async fn pages_through_accounts() {
+    let (store, _context, accs) = test_store().await.unwrap();
+    let mut ids = Vec::new();
+    let mut cursor = 0;
+    loop {
+        let (next, accounts) = store.get_accounts_page(cursor, 1).await.unwrap();
+        ids.extend(accounts.iter().map(|account| account.id()));
+        if next == 0 {
+            break;
+        }
+        cursor = next;
+    }
+    ids.sort();
+    let mut expected: Vec<Uuid> = accs.iter().map(|account| account.id()).collect();
+    expected.sort();
+    assert_eq!(ids, expected);
+}
+
 #[tokio::test]
 async fn gets_single_account() {
     let (store, _context, accs) = test_store().await.unwrap();
@@ -0,0 +1,42 @@
+use super::store_helpers::*;
+
+use interledger_api::{Asset, AssetStore, Rounding};
+
+fn asset(code: &str, symbol: Option<&str>) -> Asset {
+    Asset {
+        code: code.to_string(),
+        min_scale: 2,
+        max_scale: 9,
+        symbol: symbol.map(str::to_string),
+        display_scale: 2,
+        rounding: Rounding::HalfUp,
+    }
+}
+
+#[tokio::test]
+async fn saves_gets_and_deletes_assets() {
+    let (store, _context, _) = test_store().await.unwrap();
+    let usd = asset("USD", Some("$"));
+    let eur = asset("EUR", Some("€"));
+    store.set_asset(usd.clone()).await.unwrap();
+    store.set_asset(eur.clone()).await.unwrap();
+
+    assert_eq!(store.get_asset("USD").await.unwrap(), Some(usd.clone()));
+    assert_eq!(store.get_asset("XRP").await.unwrap(), None);
+    assert_eq!(
+        store.get_assets().await.unwrap(),
+        vec![eur.clone(), usd.clone()]
+    );
+
+    // Setting an asset with the same code replaces it
+    let replaced = asset("USD", None);
+    store.set_asset(replaced.clone()).await.unwrap();
+    assert_eq!(
+        store.get_asset("USD").await.unwrap(),
+        Some(replaced.clone())
+    );
+
+    assert_eq!(store.delete_asset("USD").await.unwrap(), Some(replaced));
+    assert_eq!(store.delete_asset("USD").await.unwrap(), None);
+    assert_eq!(store.get_assets().await.unwrap(), vec![eur]);
+}
@@ -0,0 +1,117 @@
+use super::{redis_helpers::*, store_helpers::*};
+
+use interledger_api::{AuditEntry, AuditLogStore, AuditQuery};
+use interledger_store::redis::RedisStoreBuilder;
+use serde_json::json;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
+
+fn entry(timestamp: u64, path: &str) -> AuditEntry {
+    AuditEntry {
+        id: 0,
+        timestamp,
+        actor: "admin".to_string(),
+        method: "PUT".to_string(),
+        path: path.to_string(),
+        request: json!({ "ABC": 1.0 }),
+    }
+}
+
+#[tokio::test]
+async fn appends_and_queries_audit_entries() {
+    let (store, _context, _) = test_store().await.unwrap();
+    let now = now();
+    let first = store
+        .append_audit_entry(entry(now - 2000, "/rates"))
+        .await
+        .unwrap();
+    let second = store
+        .append_audit_entry(entry(now - 1000, "/settings"))
+        .await
+        .unwrap();
+    let third = store
+        .append_audit_entry(entry(now - 1000, "/routes/static"))
+        .await
+        .unwrap();
+    assert_eq!((first.id, second.id, third.id), (1, 2, 3));
+
+    // Newest first, with the entries of the same millisecond in the order they were appended
+    let entries = store
+        .get_audit_entries(AuditQuery::default())
+        .await
+        .unwrap();
+    assert_eq!(entries, vec![third.clone(), second.clone(), first.clone()]);
+
+    let entries = store
+        .get_audit_entries(AuditQuery {
+            until: Some(now - 1500),
+            ..AuditQuery::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(entries, vec![first]);
+
+    let entries = store
+        .get_audit_entries(AuditQuery {
+            since: Some(now - 1500),
+            limit: Some(1),
+            ..AuditQuery::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(entries, vec![third]);
+}
+
+#[tokio::test]
+async fn sorts_audit_entries_before_applying_the_limit() {
+    let (store, _context, _) = test_store().await.unwrap();
+    let now = now();
+    let mut appended = Vec::new();
+    // The entries 9 and 10 would be swapped if they were sorted by their serialization
+    for _ in 0..10 {
+        appended.push(
+            store
+                .append_audit_entry(entry(now, "/rates"))
+                .await
+                .unwrap(),
+        );
+    }
+    let entries = store
+        .get_audit_entries(AuditQuery {
+            limit: Some(2),
+            ..AuditQuery::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(entries, vec![appended[9].clone(), appended[8].clone()]);
+}
+
+#[tokio::test]
+async fn deletes_audit_entries_older_than_retention() {
+    let context = TestContext::new();
+    let store = RedisStoreBuilder::new(context.get_client_connection_info(), [0; 32])
+        .audit_log_retention(60)
+        .connect()
+        .await
+        .unwrap();
+    let now = now();
+    store
+        .append_audit_entry(entry(now - 120_000, "/rates"))
+        .await
+        .unwrap();
+    let recent = store
+        .append_audit_entry(entry(now, "/settings"))
+        .await
+        .unwrap();
+    let entries = store
+        .get_audit_entries(AuditQuery::default())
+        .await
+        .unwrap();
+    assert_eq!(entries, vec![recent]);
+}
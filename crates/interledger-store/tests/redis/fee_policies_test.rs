@@ -0,0 +1,44 @@
+use super::store_helpers::*;
+
+use interledger_service_util::{FeePolicy, FeePolicyStore};
+use interledger_store::redis::RedisStoreBuilder;
+use std::collections::HashMap;
+
+#[tokio::test]
+async fn set_fee_policies() {
+    let (store, context, _) = test_store().await.unwrap();
+    assert!(store.get_fee_policy("ABC", "XYZ").is_none());
+    let policy = FeePolicy {
+        spread: Some(0.01),
+        percentage_fee: None,
+        fixed_fee: Some(10),
+    };
+    let mut policies = HashMap::new();
+    policies.insert("abc/XYZ".to_string(), policy);
+    store.set_fee_policies(policies).unwrap();
+
+    // Asset codes are stored in uppercase
+    assert_eq!(store.get_fee_policy("ABC", "XYZ"), Some(policy));
+    assert!(store.get_fee_policy("XYZ", "ABC").is_none());
+    let all = store.get_all_fee_policies().unwrap();
+    assert_eq!(all.len(), 1);
+    assert_eq!(all["ABC/XYZ"], policy);
+
+    // Stores which start later load the last policies that were set
+    tokio::time::delay_for(std::time::Duration::from_millis(100)).await;
+    let new_store = RedisStoreBuilder::new(context.get_client_connection_info(), [0; 32])
+        .connect()
+        .await
+        .unwrap();
+    assert_eq!(new_store.get_fee_policy("ABC", "XYZ"), Some(policy));
+}
+
+#[tokio::test]
+async fn rejects_invalid_pairs() {
+    let (store, _context, _) = test_store().await.unwrap();
+    for pair in &["ABC", "ABC/", "ABC/XYZ/DEF"] {
+        let mut policies = HashMap::new();
+        policies.insert(pair.to_string(), FeePolicy::default());
+        assert!(store.set_fee_policies(policies).is_err());
+    }
+}
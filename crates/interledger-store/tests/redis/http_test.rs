@@ -6,6 +6,8 @@ use interledger_btp::BtpAccount;
 use interledger_http::{HttpAccount, HttpStore};
 use interledger_packet::Address;
 use interledger_service::{Account, Username};
+use interledger_store::redis::RedisStoreBuilder;
+use redis_crate::AsyncCommands;
 use secrecy::{ExposeSecret, SecretString};
 use std::str::FromStr;
 
@@ -84,3 +86,51 @@ async fn duplicate_http_incoming_auth_works() {
     assert_eq!(accs[0].id(), original_id);
     assert_eq!(accs[1].id(), duplicate_id);
 }
+
+#[tokio::test]
+async fn stores_incoming_tokens_hashed() {
+    let (_store, context, accs) = test_store().await.unwrap();
+    let mut connection = context.async_connection().await.unwrap();
+    let key = format!("accounts:{}", accs[0].id());
+    let http_token: Vec<u8> = connection
+        .hget(&key, "ilp_over_http_incoming_token")
+        .await
+        .unwrap();
+    let btp_token: Vec<u8> = connection
+        .hget(&key, "ilp_over_btp_incoming_token")
+        .await
+        .unwrap();
+    assert!(http_token.starts_with(b"hmac-sha256$"));
+    assert!(btp_token.starts_with(b"hmac-sha256$"));
+}
+
+#[tokio::test]
+async fn hashes_incoming_tokens_stored_encrypted() {
+    let (_store, context, accs) = test_store().await.unwrap();
+    let mut connection = context.async_connection().await.unwrap();
+    let key = format!("accounts:{}", accs[0].id());
+    // Incoming tokens used to be encrypted like the outgoing ones are
+    let encrypted: Vec<u8> = connection
+        .hget(&key, "ilp_over_http_outgoing_token")
+        .await
+        .unwrap();
+    connection
+        .hset::<_, _, _, ()>(&key, "ilp_over_http_incoming_token", encrypted)
+        .await
+        .unwrap();
+
+    let store = RedisStoreBuilder::new(context.get_client_connection_info(), [0; 32])
+        .connect()
+        .await
+        .unwrap();
+    let stored: Vec<u8> = connection
+        .hget(&key, "ilp_over_http_incoming_token")
+        .await
+        .unwrap();
+    assert!(stored.starts_with(b"hmac-sha256$"));
+    let account = store
+        .get_account_from_http_auth(&Username::from_str("alice").unwrap(), "outgoing_auth_token")
+        .await
+        .unwrap();
+    assert_eq!(account.id(), accs[0].id());
+}
@@ -0,0 +1,130 @@
+use super::store_helpers::*;
+use interledger_http::{HttpAccount, HttpStore};
+use interledger_service::{Account as AccountTrait, Username};
+use interledger_store::{
+    kms::{KeyManager, LocalKeyManager},
+    redis::RedisStoreBuilder,
+};
+use redis_crate::AsyncCommands;
+use secrecy::ExposeSecret;
+use std::str::FromStr;
+use std::sync::Arc;
+
+#[tokio::test]
+async fn encrypts_existing_secrets_with_data_key() {
+    let (_store, context, accs) = test_store().await.unwrap();
+
+    let store = RedisStoreBuilder::new(context.get_client_connection_info(), [0; 32])
+        .key_manager(Arc::new(LocalKeyManager::new(&[1; 32])))
+        .connect()
+        .await
+        .unwrap();
+    let account = store
+        .get_account_from_http_auth(&Username::from_str("alice").unwrap(), "incoming_auth_token")
+        .await
+        .unwrap();
+    assert_eq!(account.username().as_ref(), "alice");
+    // The outgoing token was re-encrypted with the data key and still decrypts to its value
+    let original = accs
+        .iter()
+        .find(|acc| acc.username() == account.username())
+        .unwrap();
+    assert_eq!(
+        account.get_http_auth_token().unwrap().expose_secret(),
+        original.get_http_auth_token().unwrap().expose_secret()
+    );
+
+    let mut connection = context.async_connection().await.unwrap();
+    let key_id: String = connection
+        .hget("encryption_data_key", "key_id")
+        .await
+        .unwrap();
+    assert_eq!(key_id, LocalKeyManager::new(&[1; 32]).key_id());
+
+    // The secrets can no longer be decrypted with the key derived from the secret
+    // (the incoming tokens are hashed, so they do not depend on the data key)
+    let legacy_store = RedisStoreBuilder::new(context.get_client_connection_info(), [0; 32])
+        .connect()
+        .await
+        .unwrap();
+    let account = legacy_store
+        .get_account_from_http_auth(&Username::from_str("alice").unwrap(), "incoming_auth_token")
+        .await
+        .unwrap();
+    assert!(account.get_http_auth_token().is_none());
+}
+
+#[tokio::test]
+async fn cannot_connect_with_other_key_manager() {
+    let (_store, context, _accs) = test_store().await.unwrap();
+    RedisStoreBuilder::new(context.get_client_connection_info(), [0; 32])
+        .key_manager(Arc::new(LocalKeyManager::new(&[1; 32])))
+        .connect()
+        .await
+        .unwrap();
+
+    let result = RedisStoreBuilder::new(context.get_client_connection_info(), [0; 32])
+        .key_manager(Arc::new(LocalKeyManager::new(&[2; 32])))
+        .connect()
+        .await;
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn rotates_encryption_key() {
+    let (_store, context, accs) = test_store().await.unwrap();
+    let mut connection = context.async_connection().await.unwrap();
+
+    let reencrypted = RedisStoreBuilder::new(context.get_client_connection_info(), [0; 32])
+        .key_manager(Arc::new(LocalKeyManager::new(&[1; 32])))
+        .rotate_encryption_key()
+        .await
+        .unwrap();
+    assert_eq!(reencrypted, accs.len());
+    let wrapped_key: Vec<u8> = connection
+        .hget("encryption_data_key", "wrapped_key")
+        .await
+        .unwrap();
+
+    // Rotate again, switching to another key encryption key
+    let reencrypted = RedisStoreBuilder::new(context.get_client_connection_info(), [0; 32])
+        .key_manager(Arc::new(LocalKeyManager::new(&[2; 32])))
+        .previous_key_manager(Arc::new(LocalKeyManager::new(&[1; 32])))
+        .rotate_encryption_key()
+        .await
+        .unwrap();
+    assert_eq!(reencrypted, accs.len());
+    let rotated_wrapped_key: Vec<u8> = connection
+        .hget("encryption_data_key", "wrapped_key")
+        .await
+        .unwrap();
+    assert_ne!(wrapped_key, rotated_wrapped_key);
+
+    let store = RedisStoreBuilder::new(context.get_client_connection_info(), [0; 32])
+        .key_manager(Arc::new(LocalKeyManager::new(&[2; 32])))
+        .connect()
+        .await
+        .unwrap();
+    let account = store
+        .get_account_from_http_auth(&Username::from_str("bob").unwrap(), "incoming_auth_token")
+        .await
+        .unwrap();
+    assert_eq!(account.username().as_ref(), "bob");
+    let original = accs
+        .iter()
+        .find(|acc| acc.username() == account.username())
+        .unwrap();
+    assert_eq!(account.id(), original.id());
+    // The secrets survive both rotations
+    assert_eq!(
+        account.get_http_auth_token().unwrap().expose_secret(),
+        original.get_http_auth_token().unwrap().expose_secret()
+    );
+
+    // The previous key encryption key can no longer unwrap the data key
+    let result = RedisStoreBuilder::new(context.get_client_connection_info(), [0; 32])
+        .key_manager(Arc::new(LocalKeyManager::new(&[1; 32])))
+        .connect()
+        .await;
+    assert!(result.is_err());
+}
@@ -0,0 +1,47 @@
+use super::store_helpers::*;
+
+use interledger_service::LeaseStore;
+
+#[tokio::test]
+async fn lease_is_exclusive_until_released() {
+    let (store, _context, _) = test_store().await.unwrap();
+    assert!(store
+        .acquire_lease("leader", "node-a", 10_000)
+        .await
+        .unwrap());
+    assert!(!store
+        .acquire_lease("leader", "node-b", 10_000)
+        .await
+        .unwrap());
+    // The holder can renew its lease
+    assert!(store
+        .acquire_lease("leader", "node-a", 10_000)
+        .await
+        .unwrap());
+    // Leases are independent of each other
+    assert!(store
+        .acquire_lease("other", "node-b", 10_000)
+        .await
+        .unwrap());
+
+    // Only the holder can release the lease
+    store.release_lease("leader", "node-b").await.unwrap();
+    assert!(!store
+        .acquire_lease("leader", "node-b", 10_000)
+        .await
+        .unwrap());
+    store.release_lease("leader", "node-a").await.unwrap();
+    assert!(store
+        .acquire_lease("leader", "node-b", 10_000)
+        .await
+        .unwrap());
+}
+
+#[tokio::test]
+async fn lease_expires_if_not_renewed() {
+    let (store, _context, _) = test_store().await.unwrap();
+    assert!(store.acquire_lease("leader", "node-a", 50).await.unwrap());
+    assert!(!store.acquire_lease("leader", "node-b", 50).await.unwrap());
+    tokio::time::delay_for(std::time::Duration::from_millis(100)).await;
+    assert!(store.acquire_lease("leader", "node-b", 50).await.unwrap());
+}
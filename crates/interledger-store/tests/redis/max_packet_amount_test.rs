@@ -0,0 +1,51 @@
+use super::store_helpers::*;
+
+use interledger_stream::MaxPacketAmountStore;
+
+#[tokio::test]
+async fn caches_max_packet_amounts() {
+    let (store, _context, _) = test_store().await.unwrap();
+    assert_eq!(
+        store
+            .get_max_packet_amount("ABC", 9, "example.receiver")
+            .await
+            .unwrap(),
+        None
+    );
+    store
+        .set_max_packet_amount("ABC", 9, "example.receiver", 1000, 10_000)
+        .await
+        .unwrap();
+    assert_eq!(
+        store
+            .get_max_packet_amount("ABC", 9, "example.receiver")
+            .await
+            .unwrap(),
+        Some(1000)
+    );
+    // The amounts are in the units of the source asset
+    assert_eq!(
+        store
+            .get_max_packet_amount("ABC", 6, "example.receiver")
+            .await
+            .unwrap(),
+        None
+    );
+}
+
+#[tokio::test]
+async fn max_packet_amounts_expire() {
+    let (store, _context, _) = test_store().await.unwrap();
+    store
+        .set_max_packet_amount("ABC", 9, "example.receiver", 1000, 50)
+        .await
+        .unwrap();
+    tokio::time::delay_for(std::time::Duration::from_millis(100)).await;
+    assert_eq!(
+        store
+            .get_max_packet_amount("ABC", 9, "example.receiver")
+            .await
+            .unwrap(),
+        None
+    );
+}
@@ -0,0 +1,123 @@
+use super::store_helpers::*;
+
+use interledger_api::{
+    fail_interrupted_payments, OutgoingPayment, OutgoingPaymentStatus, OutgoingPaymentStore,
+    SpspPayRequest,
+};
+use interledger_service::Username;
+use std::str::FromStr;
+
+fn outgoing_payment(id: &str, created_at: u64) -> OutgoingPayment {
+    let mut payment = OutgoingPayment::new(
+        id.to_string(),
+        Username::from_str("alice").unwrap(),
+        &SpspPayRequest {
+            receiver: "$example.com/bob".to_string(),
+            source_amount: Some(100),
+            destination_amount: None,
+            slippage: 0.015,
+            memo: None,
+            notification_url: None,
+        },
+    )
+    .unwrap();
+    payment.created_at = created_at;
+    payment.updated_at = created_at;
+    payment
+}
+
+#[tokio::test]
+async fn saves_outgoing_payments_newest_first() {
+    let (store, _context, _) = test_store().await.unwrap();
+    let first = outgoing_payment("first", 1000);
+    store.save_outgoing_payment(first.clone()).await.unwrap();
+    store
+        .save_outgoing_payment(outgoing_payment("second", 2000))
+        .await
+        .unwrap();
+
+    assert_eq!(
+        store.get_outgoing_payment("first").await.unwrap(),
+        Some(first.clone())
+    );
+    assert_eq!(store.get_outgoing_payment("other").await.unwrap(), None);
+    let ids: Vec<String> = store
+        .get_outgoing_payments()
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|payment| payment.id)
+        .collect();
+    assert_eq!(ids, vec!["second", "first"]);
+
+    store
+        .delete_outgoing_payments(vec!["first".to_string()])
+        .await
+        .unwrap();
+    assert_eq!(store.get_outgoing_payment("first").await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn pages_through_outgoing_payments() {
+    let (store, _context, _) = test_store().await.unwrap();
+    for (id, created_at) in &[("first", 1000), ("second", 2000)] {
+        store
+            .save_outgoing_payment(outgoing_payment(id, *created_at))
+            .await
+            .unwrap();
+    }
+
+    let (next, payments) = store.get_outgoing_payments_page(0, 10).await.unwrap();
+    assert_eq!(next, 0);
+    let mut ids: Vec<String> = payments.into_iter().map(|payment| payment.id).collect();
+    ids.sort();
+    assert_eq!(ids, vec!["first", "second"]);
+}
+
+#[tokio::test]
+async fn fails_interrupted_payments_and_removes_old_ones() {
+    let (store, _context, _) = test_store().await.unwrap();
+    let now = 100 * 24 * 3600;
+    // Still being sent
+    store
+        .save_outgoing_payment(outgoing_payment("sending", now - 10))
+        .await
+        .unwrap();
+    // Not updated since its node stopped
+    store
+        .save_outgoing_payment(outgoing_payment("interrupted", now - 3600))
+        .await
+        .unwrap();
+    // Finished long ago
+    let mut old = outgoing_payment("old", 0);
+    old.finish(Err("rejected".to_string()), 0);
+    store.save_outgoing_payment(old).await.unwrap();
+
+    assert_eq!(
+        fail_interrupted_payments(store.clone(), now).await.unwrap(),
+        1
+    );
+    let sending = store
+        .get_outgoing_payment("sending")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(sending.status, OutgoingPaymentStatus::Sending);
+    let interrupted = store
+        .get_outgoing_payment("interrupted")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(interrupted.status, OutgoingPaymentStatus::Failed);
+    assert_eq!(interrupted.updated_at, now);
+    assert!(interrupted.error.unwrap().contains("interrupted"));
+    assert_eq!(store.get_outgoing_payment("old").await.unwrap(), None);
+
+    // The failed payments are not failed again
+    assert_eq!(
+        fail_interrupted_payments(store.clone(), now + 3600)
+            .await
+            .unwrap(),
+        0
+    );
+}
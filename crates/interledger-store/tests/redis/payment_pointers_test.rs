@@ -0,0 +1,47 @@
+use super::store_helpers::*;
+
+use interledger_api::{PaymentPointer, PaymentPointerStore};
+use interledger_service::Username;
+use std::str::FromStr;
+
+fn pointer(path: &str, destination_tag: Option<&str>) -> PaymentPointer {
+    PaymentPointer {
+        path: path.to_string(),
+        username: Username::from_str("alice").unwrap(),
+        destination_tag: destination_tag.map(str::to_string),
+    }
+}
+
+#[tokio::test]
+async fn saves_gets_and_deletes_payment_pointers() {
+    let (store, _context, _) = test_store().await.unwrap();
+    let donate = pointer("/donate", Some("donations"));
+    let invoice = pointer("/invoices/123", Some("invoice-123"));
+    store.set_payment_pointer(invoice.clone()).await.unwrap();
+    store.set_payment_pointer(donate.clone()).await.unwrap();
+
+    assert_eq!(
+        store.get_payment_pointer("/donate").await.unwrap(),
+        Some(donate.clone())
+    );
+    assert_eq!(store.get_payment_pointer("/other").await.unwrap(), None);
+    assert_eq!(
+        store.get_payment_pointers().await.unwrap(),
+        vec![donate.clone(), invoice.clone()]
+    );
+
+    // Setting a pointer with the same path replaces it
+    let replaced = pointer("/donate", None);
+    store.set_payment_pointer(replaced.clone()).await.unwrap();
+    assert_eq!(
+        store.get_payment_pointer("/donate").await.unwrap(),
+        Some(replaced.clone())
+    );
+
+    assert_eq!(
+        store.delete_payment_pointer("/donate").await.unwrap(),
+        Some(replaced)
+    );
+    assert_eq!(store.delete_payment_pointer("/donate").await.unwrap(), None);
+    assert_eq!(store.get_payment_pointers().await.unwrap(), vec![invoice]);
+}
@@ -0,0 +1,149 @@
+use super::fixtures::*;
+use super::store_helpers::*;
+
+use interledger_api::{NodeStore, PeeringProposal, PeeringRequest, PeeringStore};
+use interledger_http::HttpStore;
+use interledger_service::Username;
+use redis_crate::AsyncCommands;
+use secrecy::SecretString;
+use std::collections::HashMap;
+use std::str::FromStr;
+use url::Url;
+
+fn peering_request(id: &str) -> PeeringRequest {
+    PeeringRequest {
+        id: id.to_string(),
+        username: Username::from_str("carol").unwrap(),
+        asset_code: "XYZ".to_string(),
+        asset_scale: 9,
+        ilp_over_http_url: "http://carol.example/accounts/alice/ilp".to_string(),
+        ilp_over_http_token: "carol_token".to_string(),
+        callback_url: Url::parse("http://carol.example/peering/proposals/1/callback").unwrap(),
+        callback_token: "callback_token".to_string(),
+        received_at: 1000,
+    }
+}
+
+fn peering_proposal(id: &str) -> PeeringProposal {
+    PeeringProposal {
+        id: id.to_string(),
+        peer_url: Url::parse("http://dave.example").unwrap(),
+        username: Username::from_str("dave").unwrap(),
+        asset_code: "XYZ".to_string(),
+        asset_scale: 9,
+        ilp_over_http_incoming_token: "dave_token".to_string(),
+        callback_token: "callback_token".to_string(),
+        sent_at: 1000,
+    }
+}
+
+#[tokio::test]
+async fn saves_and_takes_peering_requests() {
+    let (store, _context, _) = test_store().await.unwrap();
+    store
+        .save_peering_request(peering_request("second"))
+        .await
+        .unwrap();
+    store
+        .save_peering_request(peering_request("first"))
+        .await
+        .unwrap();
+    let ids: Vec<String> = store
+        .get_peering_requests()
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|request| request.id)
+        .collect();
+    assert_eq!(ids, vec!["first", "second"]);
+
+    assert_eq!(
+        store.delete_peering_request("first").await.unwrap(),
+        Some(peering_request("first"))
+    );
+    // Only one of the callers approving or rejecting the request gets it
+    assert_eq!(store.delete_peering_request("first").await.unwrap(), None);
+    assert_eq!(store.get_peering_requests().await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn saves_and_takes_peering_proposals() {
+    let (store, _context, _) = test_store().await.unwrap();
+    store
+        .save_peering_proposal(peering_proposal("proposal"))
+        .await
+        .unwrap();
+    let proposal = store
+        .get_peering_proposal("proposal")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(proposal.id, "proposal");
+    assert_eq!(proposal.username, peering_proposal("proposal").username);
+    assert!(store.verify_peering_callback_token(&proposal, "callback_token"));
+    assert!(!store.verify_peering_callback_token(&proposal, "other_token"));
+    assert_eq!(store.get_peering_proposal("other").await.unwrap(), None);
+    assert_eq!(store.get_peering_proposals().await.unwrap().len(), 1);
+
+    assert_eq!(
+        store.delete_peering_proposal("proposal").await.unwrap(),
+        Some(proposal)
+    );
+    assert_eq!(
+        store.delete_peering_proposal("proposal").await.unwrap(),
+        None
+    );
+    assert!(store.get_peering_proposals().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn does_not_store_the_peering_tokens_in_plaintext() {
+    let (store, context, _) = test_store().await.unwrap();
+    store
+        .save_peering_request(peering_request("request"))
+        .await
+        .unwrap();
+    store
+        .save_peering_proposal(peering_proposal("proposal"))
+        .await
+        .unwrap();
+    let mut connection = context.async_connection().await.unwrap();
+    for key in &["peering_requests", "peering_proposals"] {
+        let stored: HashMap<String, String> = connection.hgetall(*key).await.unwrap();
+        assert_eq!(stored.len(), 1);
+        for value in stored.values() {
+            for token in &["carol_token", "dave_token", "callback_token"] {
+                assert!(!value.contains(token), "{} is stored in {}", token, key);
+            }
+        }
+    }
+    // The tokens the requester is sent are decrypted
+    assert_eq!(
+        store.get_peering_requests().await.unwrap(),
+        vec![peering_request("request")]
+    );
+}
+
+#[tokio::test]
+async fn creates_the_account_of_a_proposal_with_its_hashed_token() {
+    let (store, _context, _) = test_store().await.unwrap();
+    store
+        .save_peering_proposal(peering_proposal("proposal"))
+        .await
+        .unwrap();
+    let proposal = store
+        .delete_peering_proposal("proposal")
+        .await
+        .unwrap()
+        .unwrap();
+    let mut details = ACCOUNT_DETAILS_2.clone();
+    details.username = proposal.username.clone();
+    details.ilp_over_http_incoming_token =
+        Some(SecretString::new(proposal.ilp_over_http_incoming_token));
+    store.insert_account(details).await.unwrap();
+    // The peer authenticates with the token it was sent, which is not hashed twice
+    store
+        .get_account_from_http_auth(&proposal.username, "dave_token")
+        .await
+        .unwrap();
+}
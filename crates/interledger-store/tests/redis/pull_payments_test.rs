@@ -0,0 +1,131 @@
+use super::store_helpers::*;
+
+use interledger_api::{PullPaymentStore, PullPointer};
+use interledger_service::Username;
+use interledger_spsp::PullBalance;
+use std::str::FromStr;
+
+fn pull_pointer(id: &str, cap: Option<u64>) -> PullPointer {
+    PullPointer {
+        id: id.to_string(),
+        username: Username::from_str("alice").unwrap(),
+        amount: 100,
+        interval: 60,
+        cap,
+        start: 1000,
+    }
+}
+
+#[tokio::test]
+async fn saves_and_deletes_pull_pointers() {
+    let (store, _context, _) = test_store().await.unwrap();
+    let subscription = pull_pointer("subscription", Some(1000));
+    store
+        .create_pull_pointer(subscription.clone())
+        .await
+        .unwrap();
+    store
+        .create_pull_pointer(pull_pointer("allowance", None))
+        .await
+        .unwrap();
+
+    assert_eq!(
+        store.get_pull_pointer("subscription").await.unwrap(),
+        Some(subscription.clone())
+    );
+    let ids: Vec<String> = store
+        .get_pull_pointers()
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|pointer| pointer.id)
+        .collect();
+    assert_eq!(ids, vec!["allowance", "subscription"]);
+
+    store.reserve_pull(&subscription, 10, 1000).await.unwrap();
+    assert_eq!(
+        store.delete_pull_pointer("subscription").await.unwrap(),
+        Some(subscription.clone())
+    );
+    assert_eq!(store.get_pull_pointer("subscription").await.unwrap(), None);
+    assert_eq!(
+        store.delete_pull_pointer("subscription").await.unwrap(),
+        None
+    );
+    // The amounts pulled are deleted along with the pull pointer
+    assert_eq!(
+        store.get_pull_balance(&subscription, 1000).await.unwrap(),
+        subscription.balance(0, 0)
+    );
+}
+
+#[tokio::test]
+async fn reserves_pulls_within_terms() {
+    let (store, _context, _) = test_store().await.unwrap();
+    let pointer = pull_pointer("subscription", Some(250));
+
+    assert_eq!(
+        store.reserve_pull(&pointer, 60, 1000).await.unwrap(),
+        Some(PullBalance {
+            total: 60,
+            interval: 60,
+            available: 40,
+        })
+    );
+    // The amount of the interval cannot be exceeded
+    assert_eq!(store.reserve_pull(&pointer, 50, 1030).await.unwrap(), None);
+    assert_eq!(
+        store.reserve_pull(&pointer, 40, 1030).await.unwrap(),
+        Some(PullBalance {
+            total: 100,
+            interval: 100,
+            available: 0,
+        })
+    );
+
+    // The next interval starts over, but the cap still applies
+    assert_eq!(
+        store.get_pull_balance(&pointer, 1060).await.unwrap(),
+        PullBalance {
+            total: 100,
+            interval: 0,
+            available: 100,
+        }
+    );
+    store.reserve_pull(&pointer, 100, 1060).await.unwrap();
+    assert_eq!(store.reserve_pull(&pointer, 60, 1120).await.unwrap(), None);
+    assert_eq!(
+        store.reserve_pull(&pointer, 50, 1120).await.unwrap(),
+        Some(PullBalance {
+            total: 250,
+            interval: 50,
+            available: 0,
+        })
+    );
+}
+
+#[tokio::test]
+async fn releases_unsent_pulls() {
+    let (store, _context, _) = test_store().await.unwrap();
+    let pointer = pull_pointer("subscription", None);
+    store.reserve_pull(&pointer, 80, 1000).await.unwrap();
+    assert_eq!(
+        store.release_pull(&pointer, 30, 1000).await.unwrap(),
+        PullBalance {
+            total: 50,
+            interval: 50,
+            available: 50,
+        }
+    );
+
+    // Releasing a pull reserved in a previous interval leaves the current one untouched
+    store.reserve_pull(&pointer, 20, 1060).await.unwrap();
+    assert_eq!(
+        store.release_pull(&pointer, 50, 1000).await.unwrap(),
+        PullBalance {
+            total: 20,
+            interval: 20,
+            available: 80,
+        }
+    );
+}
@@ -1,8 +1,9 @@
 use super::{fixtures::*, store_helpers::*};
 use futures::future::join_all;
-use interledger_service::AddressStore;
+use interledger_service::{Account as AccountTrait, AccountStore, AddressStore};
 use interledger_service_util::{RateLimitError, RateLimitStore};
-use interledger_store::account::Account;
+use interledger_store::{account::Account, redis::RedisStoreBuilder};
+use redis_crate::AsyncCommands;
 use uuid::Uuid;
 
 #[tokio::test]
@@ -28,6 +29,35 @@ async fn rate_limits_number_of_packets() {
     );
 }
 
+#[tokio::test]
+async fn shares_the_limits_between_the_stores() {
+    let (store, context, _) = test_store().await.unwrap();
+    let other_store = RedisStoreBuilder::new(context.get_client_connection_info(), [0; 32])
+        .connect()
+        .await
+        .unwrap();
+    let account = Account::try_from(
+        Uuid::new_v4(),
+        ACCOUNT_DETAILS_0.clone(),
+        store.get_ilp_address(),
+    )
+    .unwrap();
+    store.apply_rate_limits(account.clone(), 10).await.unwrap();
+    other_store
+        .apply_rate_limits(account.clone(), 10)
+        .await
+        .unwrap();
+    // The account is only allowed 2 packets per minute, whichever node they go through
+    assert_eq!(
+        store.apply_rate_limits(account.clone(), 10).await,
+        Err(RateLimitError::PacketLimitExceeded)
+    );
+    assert_eq!(
+        other_store.apply_rate_limits(account, 10).await,
+        Err(RateLimitError::PacketLimitExceeded)
+    );
+}
+
 #[tokio::test]
 async fn limits_amount_throughput() {
     let (store, _context, _) = test_store().await.unwrap();
@@ -78,3 +108,97 @@ async fn refunds_throughput_limit_for_rejected_packets() {
     let result = store.apply_rate_limits(account.clone(), 1).await;
     assert_eq!(result.unwrap_err(), RateLimitError::ThroughputLimitExceeded);
 }
+
+#[tokio::test]
+async fn rate_limits_packets_per_second() {
+    let (store, _context, _) = test_store().await.unwrap();
+    let mut details = ACCOUNT_DETAILS_0.clone();
+    details.packets_per_second_limit = Some(2);
+    details.packets_per_minute_limit = None;
+    let account = Account::try_from(Uuid::new_v4(), details, store.get_ilp_address()).unwrap();
+    let results = join_all(vec![
+        store.clone().apply_rate_limits(account.clone(), 10),
+        store.clone().apply_rate_limits(account.clone(), 10),
+        store.clone().apply_rate_limits(account.clone(), 10),
+    ])
+    .await;
+    // The account is only allowed 2 packets per second
+    assert_eq!(
+        results,
+        vec![Ok(()), Ok(()), Err(RateLimitError::PacketLimitExceeded)]
+    );
+
+    // The limit is replenished after a second
+    tokio::time::delay_for(std::time::Duration::from_millis(1100)).await;
+    store.apply_rate_limits(account, 10).await.unwrap();
+}
+
+#[tokio::test]
+async fn exceeds_the_limits_of_zero_without_dividing_by_them() {
+    let (store, context, accounts) = test_store().await.unwrap();
+    // The limits of 0 are rejected when accounts are created or updated, but may be found
+    // on the accounts stored before they were
+    let mut connection = context.async_connection().await.unwrap();
+    let _: redis_crate::Value = connection
+        .hset(
+            format!("accounts:{}", accounts[0].id()),
+            "packets_per_minute_limit",
+            0,
+        )
+        .await
+        .unwrap();
+    let _: redis_crate::Value = connection
+        .hset(
+            format!("accounts:{}", accounts[1].id()),
+            "amount_per_minute_limit",
+            0,
+        )
+        .await
+        .unwrap();
+    let accounts = store
+        .get_accounts(vec![accounts[0].id(), accounts[1].id()])
+        .await
+        .unwrap();
+
+    assert_eq!(
+        store.apply_rate_limits(accounts[0].clone(), 10).await,
+        Err(RateLimitError::PacketLimitExceeded)
+    );
+    assert_eq!(
+        store.apply_rate_limits(accounts[1].clone(), 10).await,
+        Err(RateLimitError::ThroughputLimitExceeded)
+    );
+    store
+        .refund_throughput_limit(accounts[1].clone(), 10)
+        .await
+        .unwrap();
+    let buckets: Vec<String> = connection.keys("rate_limit:*").await.unwrap();
+    assert!(buckets.is_empty());
+}
+
+#[tokio::test]
+async fn leases_the_packet_tokens_of_a_tenth_of_a_second() {
+    let (store, context, _) = test_store().await.unwrap();
+    let mut details = ACCOUNT_DETAILS_0.clone();
+    details.packets_per_second_limit = None;
+    // 2 tokens are leased, which are the tokens of 100 milliseconds
+    details.packets_per_minute_limit = Some(1200);
+    let account = Account::try_from(Uuid::new_v4(), details, store.get_ilp_address()).unwrap();
+    let mut connection = context.async_connection().await.unwrap();
+    let key = format!("rate_limit:{}:packets", account.id());
+
+    store.apply_rate_limits(account.clone(), 10).await.unwrap();
+    let tokens: f64 = connection.hget(&key, "tokens").await.unwrap();
+    assert!(tokens < 1198.0);
+
+    // The next 2 packets use the leased tokens, without taking any from the store
+    store.apply_rate_limits(account.clone(), 10).await.unwrap();
+    store.apply_rate_limits(account.clone(), 10).await.unwrap();
+    let leased_tokens: f64 = connection.hget(&key, "tokens").await.unwrap();
+    assert!(leased_tokens >= tokens);
+
+    // The packet after them leases tokens again
+    store.apply_rate_limits(account, 10).await.unwrap();
+    let tokens: f64 = connection.hget(&key, "tokens").await.unwrap();
+    assert!(tokens < leased_tokens - 2.0);
+}
@@ -1,6 +1,7 @@
 use super::store_helpers::*;
 
-use interledger_rates::ExchangeRateStore;
+use interledger_rates::{ExchangeRateStore, PinnedRateStore};
+use interledger_store::redis::RedisStoreBuilder;
 
 #[tokio::test]
 async fn set_rates() {
@@ -20,3 +21,118 @@ async fn set_rates() {
     assert_eq!(rates[0].to_string(), "0.005");
     assert_eq!(rates[1].to_string(), "500");
 }
+
+#[tokio::test]
+async fn rates_are_shared_between_stores() {
+    let (store, context, _) = test_store().await.unwrap();
+    let other_store = RedisStoreBuilder::new(context.get_client_connection_info(), [0; 32])
+        .connect()
+        .await
+        .unwrap();
+    store
+        .set_exchange_rates(
+            [("ABC".to_string(), 500.0), ("XYZ".to_string(), 0.005)]
+                .iter()
+                .cloned()
+                .collect(),
+        )
+        .unwrap();
+    tokio::time::delay_for(std::time::Duration::from_millis(100)).await;
+
+    // Stores which are running receive the rates via pub/sub
+    let rates = other_store.get_exchange_rates(&["XYZ", "ABC"]).unwrap();
+    assert_eq!(rates[0].to_string(), "0.005");
+    assert_eq!(rates[1].to_string(), "500");
+
+    // Stores which start later load the last rates that were set
+    let new_store = RedisStoreBuilder::new(context.get_client_connection_info(), [0; 32])
+        .connect()
+        .await
+        .unwrap();
+    let rates = new_store.get_exchange_rates(&["XYZ", "ABC"]).unwrap();
+    assert_eq!(rates[0].to_string(), "0.005");
+    assert_eq!(rates[1].to_string(), "500");
+}
+
+#[tokio::test]
+async fn pins_rates() {
+    let (store, _context, _) = test_store().await.unwrap();
+    store
+        .set_exchange_rates(
+            [("ABC".to_string(), 500.0), ("XYZ".to_string(), 0.005)]
+                .iter()
+                .cloned()
+                .collect(),
+        )
+        .unwrap();
+    store.pin_rate("XYZ", 0.004).await.unwrap();
+
+    // The pin applies to the current rates right away
+    let rates = store.get_exchange_rates(&["XYZ", "ABC"]).unwrap();
+    assert_eq!(rates[0].to_string(), "0.004");
+    assert_eq!(rates[1].to_string(), "500");
+    let pinned = store.get_pinned_rates().await.unwrap();
+    assert_eq!(pinned.len(), 1);
+    assert_eq!(pinned["XYZ"].to_string(), "0.004");
+
+    // Setting the rates does not replace the pin
+    store
+        .set_exchange_rates(
+            [("ABC".to_string(), 400.0), ("XYZ".to_string(), 0.006)]
+                .iter()
+                .cloned()
+                .collect(),
+        )
+        .unwrap();
+    let rates = store.get_exchange_rates(&["XYZ", "ABC"]).unwrap();
+    assert_eq!(rates[0].to_string(), "0.004");
+    assert_eq!(rates[1].to_string(), "400");
+    assert_eq!(
+        store.get_all_exchange_rates().unwrap()["XYZ"].to_string(),
+        "0.004"
+    );
+
+    // The rate which was set last applies again once unpinned
+    assert_eq!(store.unpin_rate("XYZ").await.unwrap(), Some(0.004));
+    assert_eq!(store.unpin_rate("XYZ").await.unwrap(), None);
+    assert!(store.get_pinned_rates().await.unwrap().is_empty());
+    let rates = store.get_exchange_rates(&["XYZ", "ABC"]).unwrap();
+    assert_eq!(rates[0].to_string(), "0.006");
+}
+
+#[tokio::test]
+async fn pins_are_shared_between_stores() {
+    let (store, context, _) = test_store().await.unwrap();
+    let other_store = RedisStoreBuilder::new(context.get_client_connection_info(), [0; 32])
+        .connect()
+        .await
+        .unwrap();
+    store
+        .set_exchange_rates(
+            [("ABC".to_string(), 500.0), ("XYZ".to_string(), 0.005)]
+                .iter()
+                .cloned()
+                .collect(),
+        )
+        .unwrap();
+    store.pin_rate("XYZ", 0.004).await.unwrap();
+    tokio::time::delay_for(std::time::Duration::from_millis(100)).await;
+
+    // Stores which are running receive the pins via pub/sub
+    let rates = other_store.get_exchange_rates(&["XYZ", "ABC"]).unwrap();
+    assert_eq!(rates[0].to_string(), "0.004");
+
+    // Stores which start later apply the pins, even if no provider sets the rates again
+    let new_store = RedisStoreBuilder::new(context.get_client_connection_info(), [0; 32])
+        .connect()
+        .await
+        .unwrap();
+    let rates = new_store.get_exchange_rates(&["XYZ", "ABC"]).unwrap();
+    assert_eq!(rates[0].to_string(), "0.004");
+    assert_eq!(rates[1].to_string(), "500");
+
+    other_store.unpin_rate("XYZ").await.unwrap();
+    tokio::time::delay_for(std::time::Duration::from_millis(100)).await;
+    let rates = store.get_exchange_rates(&["XYZ", "ABC"]).unwrap();
+    assert_eq!(rates[0].to_string(), "0.005");
+}
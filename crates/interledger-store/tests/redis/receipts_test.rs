@@ -0,0 +1,103 @@
+use super::store_helpers::*;
+
+use interledger_api::{ReceiptCredit, ReceiptVerifierStore};
+use interledger_stream::{Receipt, StreamReceiptStore};
+
+fn receipt(nonce: [u8; 16], stream_id: u64, total_received: u64) -> Receipt {
+    Receipt {
+        nonce,
+        stream_id,
+        total_received,
+    }
+}
+
+#[tokio::test]
+async fn adds_up_amounts_received_on_each_stream() {
+    let (store, _context, _) = test_store().await.unwrap();
+    assert_eq!(
+        store.add_stream_received([1; 16], 1, 100).await.unwrap(),
+        100
+    );
+    assert_eq!(
+        store.add_stream_received([1; 16], 1, 50).await.unwrap(),
+        150
+    );
+    assert_eq!(store.add_stream_received([1; 16], 3, 10).await.unwrap(), 10);
+    assert_eq!(store.add_stream_received([2; 16], 1, 10).await.unwrap(), 10);
+}
+
+#[tokio::test]
+async fn credits_receipts_to_payment_pointers() {
+    let (store, _context, _) = test_store().await.unwrap();
+    store
+        .save_receipt_nonce([1; 16], "/donate".to_string())
+        .await
+        .unwrap();
+    store
+        .save_receipt_nonce([2; 16], "/donate".to_string())
+        .await
+        .unwrap();
+
+    assert_eq!(
+        store
+            .credit_receipt(&receipt([1; 16], 1, 100))
+            .await
+            .unwrap(),
+        Some(ReceiptCredit {
+            path: "/donate".to_string(),
+            amount: 100,
+            balance: 100,
+        })
+    );
+    // Only the increase of the total is credited, so replays credit nothing
+    assert_eq!(
+        store
+            .credit_receipt(&receipt([1; 16], 1, 150))
+            .await
+            .unwrap(),
+        Some(ReceiptCredit {
+            path: "/donate".to_string(),
+            amount: 50,
+            balance: 150,
+        })
+    );
+    assert_eq!(
+        store
+            .credit_receipt(&receipt([1; 16], 1, 150))
+            .await
+            .unwrap()
+            .unwrap()
+            .amount,
+        0
+    );
+    // Each stream and connection has its own total
+    assert_eq!(
+        store
+            .credit_receipt(&receipt([2; 16], 1, 30))
+            .await
+            .unwrap()
+            .unwrap()
+            .balance,
+        180
+    );
+    assert_eq!(
+        store.get_payment_pointer_balance("/donate").await.unwrap(),
+        180
+    );
+    assert_eq!(
+        store.get_payment_pointer_balance("/other").await.unwrap(),
+        0
+    );
+}
+
+#[tokio::test]
+async fn does_not_credit_receipts_of_unknown_connections() {
+    let (store, _context, _) = test_store().await.unwrap();
+    assert_eq!(
+        store
+            .credit_receipt(&receipt([3; 16], 1, 100))
+            .await
+            .unwrap(),
+        None
+    );
+}
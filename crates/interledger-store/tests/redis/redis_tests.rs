@@ -1,17 +1,34 @@
+mod account_templates_test;
 mod accounts_test;
+mod assets_test;
+mod audit_log_test;
 mod balances_test;
 mod btp_test;
+mod fee_policies_test;
 mod http_test;
+mod kms_test;
+mod lease_test;
+mod max_packet_amount_test;
+mod outgoing_payments_test;
+mod payment_pointers_test;
+mod peering_test;
+mod pull_payments_test;
 mod rate_limiting_test;
 mod rates_test;
+mod receipts_test;
+mod replicas_test;
 mod routing_test;
+mod scheduled_payments_test;
+mod settings_test;
 mod settlement_test;
+mod transfer_limits_test;
+mod unreachable_destinations_test;
 
 mod fixtures {
 
     use interledger_api::AccountDetails;
     use interledger_packet::Address;
-    use interledger_service::Username;
+    use interledger_service::{HttpDialect, Username};
     use once_cell::sync::Lazy;
     use secrecy::SecretString;
     use std::str::FromStr;
@@ -23,20 +40,39 @@ mod fixtures {
         asset_scale: 6,
         asset_code: "XYZ".to_string(),
         max_packet_amount: 1000,
+        max_packet_size: None,
         min_balance: Some(-1000),
+        payable_limit: None,
+        receivable_limit: None,
         ilp_over_http_url: Some("http://example.com/accounts/dylan/ilp".to_string()),
         ilp_over_http_incoming_token: Some(SecretString::new("incoming_auth_token".to_string())),
         ilp_over_http_outgoing_token: Some(SecretString::new("outgoing_auth_token".to_string())),
+        ilp_over_http_dialect: HttpDialect::Rfc,
+        ilp_over_http_signing_key: None,
+        ilp_over_http_verification_key: None,
         ilp_over_btp_url: Some("btp+ws://example.com/accounts/dylan/ilp/btp".to_string()),
         ilp_over_btp_incoming_token: Some(SecretString::new("btp_token".to_string())),
         ilp_over_btp_outgoing_token: Some(SecretString::new("btp_token".to_string())),
+        ilp_over_btp_legacy: false,
+        transport_account: None,
         settle_threshold: Some(0),
         settle_to: Some(-1000),
         routing_relation: Some("Parent".to_owned()),
         round_trip_time: None,
+        route_broadcast_interval: None,
+        route_expiry: None,
+        route_full_table: false,
         amount_per_minute_limit: Some(1000),
+        packets_per_second_limit: None,
         packets_per_minute_limit: Some(2),
         settlement_engine_url: Some("http://settlement.example".to_string()),
+        liquidity_notification_url: None,
+        balance_notification_url: None,
+        spread: None,
+        percentage_fee: None,
+        fixed_fee: None,
+        packet_filters: Vec::new(),
+        ip_allowlist: Vec::new(),
     });
     pub static ACCOUNT_DETAILS_1: Lazy<AccountDetails> = Lazy::new(|| AccountDetails {
         ilp_address: None,
@@ -44,21 +80,40 @@ mod fixtures {
         asset_scale: 9,
         asset_code: "ABC".to_string(),
         max_packet_amount: 1_000_000,
+        max_packet_size: None,
         min_balance: Some(0),
+        payable_limit: None,
+        receivable_limit: None,
         ilp_over_http_url: Some("http://example.com/accounts/dylan/ilp".to_string()),
         // incoming token has is the account's username concatenated wiht the password
         ilp_over_http_incoming_token: Some(SecretString::new("incoming_auth_token".to_string())),
         ilp_over_http_outgoing_token: Some(SecretString::new("outgoing_auth_token".to_string())),
+        ilp_over_http_dialect: HttpDialect::Rfc,
+        ilp_over_http_signing_key: None,
+        ilp_over_http_verification_key: None,
         ilp_over_btp_url: Some("btp+ws://example.com/accounts/dylan/ilp/btp".to_string()),
         ilp_over_btp_incoming_token: Some(SecretString::new("other_btp_token".to_string())),
         ilp_over_btp_outgoing_token: Some(SecretString::new("btp_token".to_string())),
+        ilp_over_btp_legacy: false,
+        transport_account: None,
         settle_threshold: Some(0),
         settle_to: Some(-1000),
         routing_relation: Some("Child".to_owned()),
         round_trip_time: None,
+        route_broadcast_interval: None,
+        route_expiry: None,
+        route_full_table: false,
         amount_per_minute_limit: Some(1000),
+        packets_per_second_limit: None,
         packets_per_minute_limit: Some(20),
         settlement_engine_url: None,
+        liquidity_notification_url: None,
+        balance_notification_url: None,
+        spread: None,
+        percentage_fee: None,
+        fixed_fee: None,
+        packet_filters: Vec::new(),
+        ip_allowlist: Vec::new(),
     });
     pub static ACCOUNT_DETAILS_2: Lazy<AccountDetails> = Lazy::new(|| AccountDetails {
         ilp_address: None,
@@ -66,20 +121,39 @@ mod fixtures {
         asset_scale: 9,
         asset_code: "XRP".to_string(),
         max_packet_amount: 1000,
+        max_packet_size: None,
         min_balance: Some(0),
+        payable_limit: None,
+        receivable_limit: None,
         ilp_over_http_url: None,
         ilp_over_http_incoming_token: None,
         ilp_over_http_outgoing_token: None,
+        ilp_over_http_dialect: HttpDialect::Rfc,
+        ilp_over_http_signing_key: None,
+        ilp_over_http_verification_key: None,
         ilp_over_btp_url: None,
         ilp_over_btp_incoming_token: None,
         ilp_over_btp_outgoing_token: None,
+        ilp_over_btp_legacy: false,
+        transport_account: None,
         settle_threshold: Some(0),
         settle_to: None,
         routing_relation: None,
         round_trip_time: None,
+        route_broadcast_interval: None,
+        route_expiry: None,
+        route_full_table: false,
         amount_per_minute_limit: None,
+        packets_per_second_limit: None,
         packets_per_minute_limit: None,
         settlement_engine_url: None,
+        liquidity_notification_url: None,
+        balance_notification_url: None,
+        spread: None,
+        percentage_fee: None,
+        fixed_fee: None,
+        packet_filters: Vec::new(),
+        ip_allowlist: Vec::new(),
     });
 }
 
@@ -129,18 +203,6 @@ mod redis_helpers {
             let server_type = ServerType::get_intended();
             let mut cmd = process::Command::new("redis-server");
 
-            let fname = if os_type::current_platform().os_type == os_type::OSType::OSX {
-                "libredis_cell.dylib"
-            } else {
-                "libredis_cell.so"
-            };
-
-            // Load redis_cell
-            let mut cell_module: PathBuf = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-            cell_module.push("external");
-            cell_module.push(fname);
-            cmd.arg("--loadmodule").arg(cell_module.as_os_str());
-
             cmd.stdout(process::Stdio::null())
                 .stderr(process::Stdio::null());
 
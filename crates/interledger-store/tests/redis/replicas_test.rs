@@ -0,0 +1,136 @@
+use super::fixtures::*;
+use super::redis_helpers::*;
+
+use interledger_api::NodeStore;
+use interledger_http::HttpStore;
+use interledger_packet::Address;
+use interledger_service::{Account, AccountStore, Username};
+use interledger_store::redis::{RedisStore, RedisStoreBuilder};
+use redis_crate::{AsyncCommands, ConnectionInfo};
+use std::str::FromStr;
+use std::time::Duration;
+use uuid::Uuid;
+
+async fn store_with_replica(context: &TestContext, replica: ConnectionInfo) -> RedisStore {
+    RedisStoreBuilder::new(context.get_client_connection_info(), [0; 32])
+        .node_ilp_address(Address::from_str("example.node").unwrap())
+        .read_replicas(vec![replica])
+        .max_replica_staleness(100)
+        .connect()
+        .await
+        .unwrap()
+}
+
+/// Connects to another database of the test server, which stands in for a replica. It is
+/// seeded with a different account id for alice, so that the ids returned by the store
+/// show whether a lookup was served by the replica or by the primary.
+async fn seeded_replica(
+    context: &TestContext,
+) -> (ConnectionInfo, redis_crate::aio::Connection, Uuid) {
+    let mut replica = context.get_client_connection_info();
+    replica.db = 1;
+    let mut connection = redis_crate::Client::open(replica.clone())
+        .unwrap()
+        .get_async_connection()
+        .await
+        .unwrap();
+    let replica_id = Uuid::new_v4();
+    connection
+        .hset::<_, _, _, ()>("usernames", "alice", replica_id.to_string())
+        .await
+        .unwrap();
+    (replica, connection, replica_id)
+}
+
+#[tokio::test]
+async fn reads_from_replica_within_staleness_bound() {
+    let context = TestContext::new();
+    let (replica, mut replica_connection, replica_id) = seeded_replica(&context).await;
+    // Replicate the heartbeats (and only them) from the primary, so that the
+    // replica is within the bound but still has its own copy of the usernames
+    let mut primary = context.async_connection().await.unwrap();
+    tokio::spawn(async move {
+        loop {
+            let keys: Vec<String> = primary.keys("replica_heartbeat:*").await.unwrap();
+            for key in keys {
+                let heartbeat: Option<String> = primary.get(&key).await.unwrap();
+                if let Some(heartbeat) = heartbeat {
+                    replica_connection
+                        .set::<_, _, ()>(&key, heartbeat)
+                        .await
+                        .unwrap();
+                }
+            }
+            tokio::time::delay_for(Duration::from_millis(5)).await;
+        }
+    });
+    let store = store_with_replica(&context, replica).await;
+    let account = store
+        .insert_account(ACCOUNT_DETAILS_0.clone())
+        .await
+        .unwrap();
+    tokio::time::delay_for(Duration::from_millis(200)).await;
+
+    let id = store
+        .get_account_id_from_username(&Username::from_str("alice").unwrap())
+        .await
+        .unwrap();
+    assert_eq!(id, replica_id);
+    assert_ne!(id, account.id());
+    // The replica does not have the account itself, so it is loaded from the primary
+    let accounts = store.get_accounts(vec![account.id()]).await.unwrap();
+    assert_eq!(accounts[0].id(), account.id());
+    assert_eq!(accounts[0].username(), account.username());
+}
+
+#[tokio::test]
+async fn reads_from_primary_as_replica() {
+    let context = TestContext::new();
+    // The primary itself is a replica which never lags behind
+    let store = store_with_replica(&context, context.get_client_connection_info()).await;
+    let account = store
+        .insert_account(ACCOUNT_DETAILS_0.clone())
+        .await
+        .unwrap();
+    tokio::time::delay_for(Duration::from_millis(50)).await;
+
+    let id = store
+        .get_account_id_from_username(&Username::from_str("alice").unwrap())
+        .await
+        .unwrap();
+    assert_eq!(id, account.id());
+    let accounts = store.get_accounts(vec![id]).await.unwrap();
+    assert_eq!(accounts[0].username(), account.username());
+    let account = store
+        .get_account_from_http_auth(&Username::from_str("alice").unwrap(), "incoming_auth_token")
+        .await
+        .unwrap();
+    assert_eq!(account.id(), id);
+}
+
+#[tokio::test]
+async fn falls_back_to_primary_if_replica_is_stale() {
+    let context = TestContext::new();
+    // The replica never receives the heartbeat, so it is never within the bound
+    let (replica, _replica_connection, replica_id) = seeded_replica(&context).await;
+    let store = store_with_replica(&context, replica).await;
+    let account = store
+        .insert_account(ACCOUNT_DETAILS_0.clone())
+        .await
+        .unwrap();
+    tokio::time::delay_for(Duration::from_millis(200)).await;
+
+    let id = store
+        .get_account_id_from_username(&Username::from_str("alice").unwrap())
+        .await
+        .unwrap();
+    assert_eq!(id, account.id());
+    assert_ne!(id, replica_id);
+    let accounts = store.get_accounts(vec![id]).await.unwrap();
+    assert_eq!(accounts[0].username(), account.username());
+    let account = store
+        .get_account_from_http_auth(&Username::from_str("alice").unwrap(), "incoming_auth_token")
+        .await
+        .unwrap();
+    assert_eq!(account.id(), id);
+}
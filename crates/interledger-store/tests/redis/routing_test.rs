@@ -4,7 +4,7 @@ use interledger_api::{AccountDetails, NodeStore};
 use interledger_ccp::CcpRoutingStore;
 use interledger_packet::Address;
 use interledger_router::RouterStore;
-use interledger_service::{Account as AccountTrait, AddressStore, Username};
+use interledger_service::{Account as AccountTrait, AddressStore, HttpDialect, Username};
 use interledger_store::{account::Account, redis::RedisStoreBuilder};
 use std::str::FromStr;
 use std::{collections::HashMap, time::Duration};
@@ -38,20 +38,39 @@ async fn polls_for_route_updates() {
             asset_scale: 6,
             asset_code: "XYZ".to_string(),
             max_packet_amount: 1000,
+            max_packet_size: None,
             min_balance: Some(-1000),
+            payable_limit: None,
+            receivable_limit: None,
             ilp_over_http_url: None,
             ilp_over_http_incoming_token: None,
             ilp_over_http_outgoing_token: None,
+            ilp_over_http_dialect: HttpDialect::Rfc,
+            ilp_over_http_signing_key: None,
+            ilp_over_http_verification_key: None,
             ilp_over_btp_url: None,
             ilp_over_btp_outgoing_token: None,
             ilp_over_btp_incoming_token: None,
+            ilp_over_btp_legacy: false,
+            transport_account: None,
             settle_threshold: None,
             settle_to: None,
             routing_relation: Some("Peer".to_owned()),
             round_trip_time: None,
+            route_broadcast_interval: None,
+            route_expiry: None,
+            route_full_table: false,
             amount_per_minute_limit: None,
+            packets_per_second_limit: None,
             packets_per_minute_limit: None,
             settlement_engine_url: None,
+            liquidity_notification_url: None,
+            balance_notification_url: None,
+            spread: None,
+            percentage_fee: None,
+            fixed_fee: None,
+            packet_filters: Vec::new(),
+            ip_allowlist: Vec::new(),
         })
         .await
         .unwrap();
@@ -194,6 +213,28 @@ async fn adds_static_routes_to_redis() {
     assert_eq!(routes.len(), 3);
 }
 
+#[tokio::test]
+async fn gets_static_routes() {
+    let (store, _context, accs) = test_store().await.unwrap();
+    assert!(store.get_static_routes().await.unwrap().is_empty());
+    store
+        .set_static_routes(vec![
+            ("example.a".to_string(), accs[0].id()),
+            ("example.b".to_string(), accs[1].id()),
+        ])
+        .await
+        .unwrap();
+    store
+        .set_static_route("example.c".to_string(), accs[1].id())
+        .await
+        .unwrap();
+    let routes = store.get_static_routes().await.unwrap();
+    assert_eq!(routes["example.a"], accs[0].id());
+    assert_eq!(routes["example.b"], accs[1].id());
+    assert_eq!(routes["example.c"], accs[1].id());
+    assert_eq!(routes.len(), 3);
+}
+
 #[tokio::test]
 async fn static_routes_override_others() {
     let (store, _context, accs) = test_store().await.unwrap();
@@ -271,3 +312,42 @@ async fn returns_configured_routes_for_route_manager() {
     assert_eq!(configured["example.a"].id(), accs[0].id());
     assert_eq!(configured["example.b"].id(), accs[1].id());
 }
+
+#[tokio::test]
+async fn saves_the_routes_learned_from_each_account() {
+    let (store, _context, accs) = test_store().await.unwrap();
+    store
+        .clone()
+        .set_learned_routes(accs[0].clone(), vec![1, 2, 3])
+        .await
+        .unwrap();
+    // Another node saves the routes it learned from another account
+    store
+        .clone()
+        .set_learned_routes(accs[1].clone(), vec![4, 5])
+        .await
+        .unwrap();
+    store
+        .clone()
+        .set_learned_routes(accs[0].clone(), vec![6])
+        .await
+        .unwrap();
+
+    let mut learned_routes: Vec<(Uuid, Vec<u8>)> = store
+        .get_learned_routes()
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|(account, routes)| (account.id(), routes))
+        .collect();
+    learned_routes.sort();
+    let mut expected = vec![(accs[0].id(), vec![6]), (accs[1].id(), vec![4, 5])];
+    expected.sort();
+    assert_eq!(learned_routes, expected);
+
+    // The routes learned from deleted accounts are removed
+    store.delete_account(accs[1].id()).await.unwrap();
+    let learned_routes = store.get_learned_routes().await.unwrap();
+    assert_eq!(learned_routes.len(), 1);
+    assert_eq!(learned_routes[0].0.id(), accs[0].id());
+}
@@ -0,0 +1,65 @@
+use super::store_helpers::*;
+
+use interledger_api::{ScheduledPayment, ScheduledPaymentRequest, ScheduledPaymentStore};
+use interledger_service::Username;
+use std::str::FromStr;
+
+fn scheduled_payment(id: &str) -> ScheduledPayment {
+    ScheduledPayment::new(
+        id.to_string(),
+        Username::from_str("alice").unwrap(),
+        ScheduledPaymentRequest {
+            receiver: "$example.com/bob".to_string(),
+            source_amount: 100,
+            slippage: 0.015,
+            execute_at: 1000,
+            recurrence: None,
+            notification_url: None,
+            memo: None,
+        },
+    )
+}
+
+#[tokio::test]
+async fn saves_updates_and_cancels_scheduled_payments() {
+    let (store, _context, _) = test_store().await.unwrap();
+    let rent = scheduled_payment("rent");
+    store.create_scheduled_payment(rent.clone()).await.unwrap();
+    store
+        .create_scheduled_payment(scheduled_payment("allowance"))
+        .await
+        .unwrap();
+
+    assert_eq!(
+        store.get_scheduled_payment("rent").await.unwrap(),
+        Some(rent.clone())
+    );
+    let ids: Vec<String> = store
+        .get_scheduled_payments()
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|payment| payment.id)
+        .collect();
+    assert_eq!(ids, vec!["allowance", "rent"]);
+
+    let mut executed = rent.clone();
+    executed.start_execution(1000);
+    assert!(store
+        .update_scheduled_payment(executed.clone())
+        .await
+        .unwrap());
+    assert_eq!(
+        store.get_scheduled_payment("rent").await.unwrap(),
+        Some(executed.clone())
+    );
+
+    assert_eq!(
+        store.delete_scheduled_payment("rent").await.unwrap(),
+        Some(executed.clone())
+    );
+    // The cancelled payments are not recreated by the executions in progress
+    assert!(!store.update_scheduled_payment(executed).await.unwrap());
+    assert_eq!(store.get_scheduled_payment("rent").await.unwrap(), None);
+    assert_eq!(store.delete_scheduled_payment("rent").await.unwrap(), None);
+}
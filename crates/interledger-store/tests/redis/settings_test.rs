@@ -0,0 +1,49 @@
+use super::store_helpers::*;
+
+use interledger_api::{NodeStore, RuntimeSettings};
+
+#[tokio::test]
+async fn runtime_settings_default_to_unset() {
+    let (store, _context, _) = test_store().await.unwrap();
+    let settings = store.get_runtime_settings().await.unwrap();
+    assert_eq!(settings, RuntimeSettings::default());
+}
+
+#[tokio::test]
+async fn set_runtime_settings_overwrites_previous_settings() {
+    let (store, _context, _) = test_store().await.unwrap();
+    store
+        .set_runtime_settings(RuntimeSettings {
+            route_broadcast_interval: Some(5000),
+            exchange_rate_poll_interval: Some(1000),
+            max_packet_amount: None,
+            max_packet_size: Some(4096),
+        })
+        .await
+        .unwrap();
+    let settings = store.get_runtime_settings().await.unwrap();
+    assert_eq!(settings.route_broadcast_interval, Some(5000));
+    assert_eq!(settings.exchange_rate_poll_interval, Some(1000));
+    assert_eq!(settings.max_packet_amount, None);
+    assert_eq!(settings.max_packet_size, Some(4096));
+
+    // Fields which are left out are removed from the store
+    let new_settings = RuntimeSettings {
+        max_packet_amount: Some(100),
+        ..Default::default()
+    };
+    store
+        .set_runtime_settings(new_settings.clone())
+        .await
+        .unwrap();
+    assert_eq!(store.get_runtime_settings().await.unwrap(), new_settings);
+
+    store
+        .set_runtime_settings(RuntimeSettings::default())
+        .await
+        .unwrap();
+    assert_eq!(
+        store.get_runtime_settings().await.unwrap(),
+        RuntimeSettings::default()
+    );
+}
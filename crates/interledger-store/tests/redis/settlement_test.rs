@@ -4,15 +4,21 @@ use bytes::Bytes;
 use http::StatusCode;
 use interledger_api::NodeStore;
 use interledger_service::{Account, AccountStore};
-use interledger_service_util::BalanceStore;
+use interledger_service_util::{BalanceStore, Escrow};
 use interledger_settlement::core::{
     idempotency::{IdempotentData, IdempotentStore},
-    types::{LeftoversStore, SettlementAccount, SettlementStore},
+    replay_protection::NonceStore,
+    types::{
+        LeftoversStore, SettlementAccount, SettlementQueueStore, SettlementStore,
+        SettlementTotalsStore,
+    },
 };
+use interledger_store::redis::RedisStoreBuilder;
 use num_bigint::BigUint;
 use once_cell::sync::Lazy;
 use redis_crate::cmd;
 use redis_crate::AsyncCommands;
+use std::time::{Duration, SystemTime};
 use url::Url;
 use uuid::Uuid;
 
@@ -50,6 +56,83 @@ async fn saves_gets_clears_uncredited_settlement_amount_properly() {
     assert_eq!(ret, (BigUint::from(0u32), 0));
 }
 
+#[tokio::test]
+async fn uncredited_settlement_amount_expires_after_its_ttl() {
+    let (_store, context, _accs) = test_store().await.unwrap();
+    let store = RedisStoreBuilder::new(context.get_client_connection_info(), [0; 32])
+        .uncredited_settlement_ttl(60)
+        .connect()
+        .await
+        .unwrap();
+    let mut connection = context.async_connection().await.unwrap();
+    let acc = Uuid::new_v4();
+    let key = format!("uncredited-amount:{}", acc);
+
+    store
+        .save_uncredited_settlement_amount(acc, (BigUint::from(15u32), 10))
+        .await
+        .unwrap();
+    let ttl: i64 = connection.ttl(&key).await.unwrap();
+    assert!(ttl > 0 && ttl <= 60);
+
+    // Saving the leftovers of a load pushes the expiry back
+    let _: () = connection.expire(&key, 10).await.unwrap();
+    let amount = store
+        .load_uncredited_settlement_amount(acc, 9u8)
+        .await
+        .unwrap();
+    assert_eq!(amount, BigUint::from(1u32));
+    let ttl: i64 = connection.ttl(&key).await.unwrap();
+    assert!(ttl > 10 && ttl <= 60);
+
+    // Without leftovers, the amount is credited entirely and nothing is left to expire
+    store
+        .save_uncredited_settlement_amount(acc, (BigUint::from(1u32), 9))
+        .await
+        .unwrap();
+    store
+        .load_uncredited_settlement_amount(acc, 9u8)
+        .await
+        .unwrap();
+    let exists: bool = connection.exists(&key).await.unwrap();
+    assert!(!exists);
+}
+
+#[tokio::test]
+async fn uncredited_settlement_amount_has_no_ttl_by_default() {
+    let (store, context, _accs) = test_store().await.unwrap();
+    let mut connection = context.async_connection().await.unwrap();
+    let acc = Uuid::new_v4();
+    store
+        .save_uncredited_settlement_amount(acc, (BigUint::from(15u32), 10))
+        .await
+        .unwrap();
+    store
+        .load_uncredited_settlement_amount(acc, 9u8)
+        .await
+        .unwrap();
+    let ttl: i64 = connection
+        .ttl(format!("uncredited-amount:{}", acc))
+        .await
+        .unwrap();
+    assert_eq!(ttl, -1);
+}
+
+#[tokio::test]
+async fn rejects_ttls_of_0() {
+    let (_store, context, _accs) = test_store().await.unwrap();
+    let result = RedisStoreBuilder::new(context.get_client_connection_info(), [0; 32])
+        .idempotency_ttl(0)
+        .connect()
+        .await;
+    assert!(result.is_err());
+    let result = RedisStoreBuilder::new(context.get_client_connection_info(), [0; 32])
+        .uncredited_settlement_ttl(0)
+        .connect()
+        .await;
+    assert!(result.is_err());
+}
+
 #[tokio::test]
 async fn saves_and_loads_idempotency_key_data_properly() {
     let (store, _context, _) = test_store().await.unwrap();
@@ -105,6 +188,46 @@ async fn idempotent_settlement_calls() {
     assert_eq!(balance, 100);
 }
 
+#[tokio::test]
+async fn tracks_settlement_totals() {
+    let (store, context, accs) = test_store().await.unwrap();
+    let id = accs[0].id();
+    assert_eq!(store.get_settlement_totals(id).await.unwrap(), (0, 0));
+
+    // A fulfill which brings the balance over the threshold settles down to `settle_to`
+    let mut connection = context.shared_async_connection().await.unwrap();
+    let _: redis_crate::Value = connection
+        .hset_multiple(
+            format!("accounts:{}", id),
+            &[("balance", 0), ("settle_threshold", 50), ("settle_to", 10)],
+        )
+        .await
+        .unwrap();
+    let escrow = Escrow::new(
+        Uuid::new_v4(),
+        0,
+        id,
+        100,
+        SystemTime::now() + Duration::from_secs(30),
+    );
+    let (_, settle_amount) = store.update_balances_for_fulfill(&escrow).await.unwrap();
+    assert_eq!(settle_amount, 90);
+    // Refunds are subtracted, since the engine did not accept the settlement
+    store.refund_settlement(id, 40).await.unwrap();
+
+    store
+        .update_balance_for_incoming_settlement(id, 100, Some(IDEMPOTENCY_KEY.clone()))
+        .await
+        .unwrap();
+    // Repeated notifications are only counted once
+    store
+        .update_balance_for_incoming_settlement(id, 100, Some(IDEMPOTENCY_KEY.clone()))
+        .await
+        .unwrap();
+
+    assert_eq!(store.get_settlement_totals(id).await.unwrap(), (50, 100));
+}
+
 #[tokio::test]
 async fn credits_prepaid_amount() {
     let (store, context, accs) = test_store().await.unwrap();
@@ -236,3 +359,123 @@ async fn loads_globally_configured_settlement_engine_url() {
         "http://settle-abc.example/"
     );
 }
+
+#[tokio::test]
+async fn compaction_reclaims_records_without_ttl() {
+    let (store, context, accs) = test_store().await.unwrap();
+    let mut connection = context.async_connection().await.unwrap();
+    // Records written before TTLs were applied to them
+    let _: redis_crate::Value = connection
+        .hset("idempotency-key:legacy", "status_code", 200)
+        .await
+        .unwrap();
+    let _: redis_crate::Value = connection
+        .rpush(format!("uncredited-amount:{}", Uuid::new_v4()), "1:10")
+        .await
+        .unwrap();
+    let _: redis_crate::Value = connection
+        .rpush(format!("uncredited-amount:{}", accs[0].id()), "1:10")
+        .await
+        .unwrap();
+
+    let summary = store.compact().await.unwrap();
+    assert_eq!(summary.expiring, 1);
+    assert_eq!(summary.deleted, 1);
+
+    let ttl: i64 = connection.ttl("idempotency-key:legacy").await.unwrap();
+    assert!(ttl > 0 && ttl <= 86400);
+    // The account still exists so its uncredited amount is kept
+    let ttl: i64 = connection
+        .ttl(format!("uncredited-amount:{}", accs[0].id()))
+        .await
+        .unwrap();
+    assert_eq!(ttl, -1);
+
+    // Running it again does not reclaim anything else
+    let summary = store.compact().await.unwrap();
+    assert_eq!(summary, Default::default());
+}
+
+#[tokio::test]
+async fn takes_queued_settlements_in_order() {
+    let (store, _context, accs) = test_store().await.unwrap();
+    store.queue_settlement(accs[0].id(), 100).await.unwrap();
+    store.queue_settlement(accs[1].id(), 200).await.unwrap();
+    assert_eq!(
+        store.take_queued_settlement().await.unwrap(),
+        Some((accs[0].id(), 100))
+    );
+    assert_eq!(
+        store.take_queued_settlement().await.unwrap(),
+        Some((accs[1].id(), 200))
+    );
+    assert_eq!(store.take_queued_settlement().await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn saves_each_nonce_once() {
+    let (store, _context, _) = test_store().await.unwrap();
+    let ttl = Duration::from_millis(100);
+    assert!(store.save_nonce("nonce".to_string(), ttl).await.unwrap());
+    assert!(!store.save_nonce("nonce".to_string(), ttl).await.unwrap());
+    assert!(store.save_nonce("other".to_string(), ttl).await.unwrap());
+
+    // nonces can be used again once they expired
+    tokio::time::delay_for(Duration::from_millis(150)).await;
+    assert!(store.save_nonce("nonce".to_string(), ttl).await.unwrap());
+}
+
+#[tokio::test]
+async fn gets_and_deletes_globally_configured_settlement_engine_urls() {
+    let (store, _context, accs) = test_store().await.unwrap();
+    store
+        .set_settlement_engines(vec![(
+            "ABC".to_string(),
+            Url::parse("http://settle-abc.example").unwrap(),
+        )])
+        .await
+        .unwrap();
+    store
+        .set_settlement_engines(vec![(
+            "XYZ".to_string(),
+            Url::parse("http://settle-xyz.example").unwrap(),
+        )])
+        .await
+        .unwrap();
+    let engines = store.get_settlement_engines().await.unwrap();
+    assert_eq!(engines.len(), 2);
+    assert_eq!(engines["ABC"].as_str(), "http://settle-abc.example/");
+
+    assert_eq!(
+        store
+            .delete_settlement_engine("ABC")
+            .await
+            .unwrap()
+            .unwrap()
+            .as_str(),
+        "http://settle-abc.example/"
+    );
+    assert!(store
+        .delete_settlement_engine("ABC")
+        .await
+        .unwrap()
+        .is_none());
+    assert_eq!(
+        store
+            .get_settlement_engines()
+            .await
+            .unwrap()
+            .keys()
+            .collect::<Vec<_>>(),
+        vec!["XYZ"]
+    );
+
+    // The account which used the default settlement engine of its asset no longer has one,
+    // while the one with its own is unchanged
+    let accounts = store
+        .get_accounts(vec![accs[0].id(), accs[1].id()])
+        .await
+        .unwrap();
+    assert!(accounts[0].settlement_engine_details().is_some());
+    assert!(accounts[1].settlement_engine_details().is_none());
+}
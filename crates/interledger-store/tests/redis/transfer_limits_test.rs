@@ -0,0 +1,134 @@
+use super::store_helpers::*;
+
+use interledger_errors::BalanceStoreError;
+use interledger_service::Account as AccountTrait;
+use interledger_service_util::{
+    TransferCharge, TransferLimitStore, TransferLimits, TransferUsage, WindowUsage,
+};
+
+#[tokio::test]
+async fn sets_transfer_limits() {
+    let (store, _context, accs) = test_store().await.unwrap();
+    let id = accs[0].id();
+    assert_eq!(
+        store.get_transfer_usage(id).await.unwrap(),
+        TransferUsage::default()
+    );
+
+    let limits = TransferLimits {
+        daily_send_limit: Some(100),
+        monthly_receive_limit: Some(1000),
+        ..Default::default()
+    };
+    let usage = store.set_transfer_limits(id, limits).await.unwrap();
+    assert_eq!(usage.daily_send.limit, Some(100));
+    assert_eq!(usage.monthly_send.limit, None);
+    assert_eq!(usage.monthly_receive.limit, Some(1000));
+
+    // The limits which are not set are removed
+    let usage = store
+        .set_transfer_limits(id, TransferLimits::default())
+        .await
+        .unwrap();
+    assert_eq!(usage, TransferUsage::default());
+}
+
+#[tokio::test]
+async fn charges_and_refunds_the_accounts_with_limits() {
+    let (store, _context, accs) = test_store().await.unwrap();
+    let (from, to) = (accs[0].id(), accs[1].id());
+    store
+        .set_transfer_limits(
+            from,
+            TransferLimits {
+                daily_send_limit: Some(100),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+
+    let first = store
+        .charge_transfer_limits(TransferCharge::new(from, 60, to, 60))
+        .await
+        .unwrap();
+    // The receiving account has no limit, so nothing is counted for it
+    assert!(first.sent && !first.received);
+    let usage = store.get_transfer_usage(from).await.unwrap();
+    assert_eq!(usage.daily_send.used, 60);
+    assert_eq!(usage.monthly_send.used, 60);
+    assert!(usage.daily_send.reset_at.unwrap() > first.charged_at);
+    assert_eq!(
+        store.get_transfer_usage(to).await.unwrap().daily_receive,
+        WindowUsage::default()
+    );
+
+    match store
+        .charge_transfer_limits(TransferCharge::new(from, 50, to, 50))
+        .await
+    {
+        Err(BalanceStoreError::TransferLimitExceeded {
+            direction,
+            window,
+            limit,
+            used,
+            reset_at,
+            ..
+        }) => {
+            assert_eq!(direction, "send");
+            assert_eq!(window, "daily");
+            assert_eq!(limit, 100);
+            assert_eq!(used, 60);
+            assert_eq!(Some(reset_at), usage.daily_send.reset_at);
+        }
+        result => panic!("unexpected result: {:?}", result),
+    }
+
+    store.refund_transfer_limits(&first).await.unwrap();
+    assert_eq!(
+        store.get_transfer_usage(from).await.unwrap().daily_send,
+        WindowUsage {
+            limit: Some(100),
+            used: 0,
+            reset_at: None,
+        }
+    );
+    store
+        .charge_transfer_limits(TransferCharge::new(from, 50, to, 50))
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn enforces_receive_limits() {
+    let (store, _context, accs) = test_store().await.unwrap();
+    let (from, to) = (accs[0].id(), accs[1].id());
+    store
+        .set_transfer_limits(
+            to,
+            TransferLimits {
+                monthly_receive_limit: Some(10),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    match store
+        .charge_transfer_limits(TransferCharge::new(from, 20, to, 11))
+        .await
+    {
+        Err(BalanceStoreError::TransferLimitExceeded {
+            account,
+            direction,
+            window,
+            used,
+            ..
+        }) => {
+            assert_eq!(account, to.to_string());
+            assert_eq!(direction, "receive");
+            assert_eq!(window, "monthly");
+            assert_eq!(used, 0);
+        }
+        result => panic!("unexpected result: {:?}", result),
+    }
+}
@@ -0,0 +1,80 @@
+use super::store_helpers::*;
+
+use interledger_stream::UnreachableDestinationStore;
+
+#[tokio::test]
+async fn counts_unreachable_rejects() {
+    let (store, _context, _) = test_store().await.unwrap();
+    assert_eq!(
+        store
+            .get_unreachable_destination("example.receiver")
+            .await
+            .unwrap(),
+        None
+    );
+    for _ in 0..2 {
+        store
+            .record_unreachable_destination("example.receiver", "F02 no route found", 10_000)
+            .await
+            .unwrap();
+    }
+    store
+        .record_unreachable_destination("example.other", "F02 peer is down", 10_000)
+        .await
+        .unwrap();
+
+    let unreachable = store
+        .get_unreachable_destination("example.receiver")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(unreachable.failures, 2);
+    assert_eq!(unreachable.error, "F02 no route found");
+    assert!(unreachable.expires_in > 0 && unreachable.expires_in <= 10_000);
+    let destinations = store.get_unreachable_destinations().await.unwrap();
+    assert_eq!(
+        destinations
+            .iter()
+            .map(|destination| destination.destination_prefix.as_str())
+            .collect::<Vec<_>>(),
+        vec!["example.other", "example.receiver"]
+    );
+
+    assert_eq!(
+        store
+            .clear_unreachable_destinations(Some("example.receiver"))
+            .await
+            .unwrap(),
+        1
+    );
+    assert_eq!(
+        store
+            .clear_unreachable_destinations(Some("example.receiver"))
+            .await
+            .unwrap(),
+        0
+    );
+    assert_eq!(store.clear_unreachable_destinations(None).await.unwrap(), 1);
+    assert!(store
+        .get_unreachable_destinations()
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test]
+async fn unreachable_destinations_expire() {
+    let (store, _context, _) = test_store().await.unwrap();
+    store
+        .record_unreachable_destination("example.receiver", "F02 no route found", 50)
+        .await
+        .unwrap();
+    tokio::time::delay_for(std::time::Duration::from_millis(100)).await;
+    assert_eq!(
+        store
+            .get_unreachable_destination("example.receiver")
+            .await
+            .unwrap(),
+        None
+    );
+}
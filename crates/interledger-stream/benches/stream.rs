@@ -0,0 +1,185 @@
+//! Benchmark STREAM packet encryption, decryption and the receiver fulfilling Prepare packets.
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use criterion::{criterion_group, criterion_main, Criterion};
+use futures::channel::mpsc::UnboundedSender;
+use interledger_errors::ReceiptStoreError;
+use interledger_packet::{Address, PacketType, Prepare, PrepareBuilder};
+use interledger_service::{
+    outgoing_service_fn, Account, IlpResult, OutgoingRequest, OutgoingService, Username,
+};
+use interledger_stream::{
+    ConnectionGenerator, Frame, PaymentNotification, StreamDataFrame, StreamMaxMoneyFrame,
+    StreamMoneyFrame, StreamNotificationsStore, StreamPacket, StreamPacketBuilder,
+    StreamReceiptStore, StreamReceiverService,
+};
+use once_cell::sync::Lazy;
+use ring::{digest, hmac};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+static SHARED_SECRET: [u8; 32] = [7; 32];
+static RECEIVER: Lazy<Address> = Lazy::new(|| Address::from_str("example.receiver").unwrap());
+static USERNAME: Lazy<Username> = Lazy::new(|| Username::from_str("bench").unwrap());
+
+#[derive(Debug, Clone)]
+struct BenchAccount {
+    id: Uuid,
+    ilp_address: Address,
+}
+
+impl Account for BenchAccount {
+    fn id(&self) -> Uuid {
+        self.id
+    }
+
+    fn username(&self) -> &Username {
+        &USERNAME
+    }
+
+    fn asset_code(&self) -> &str {
+        "XYZ"
+    }
+
+    fn asset_scale(&self) -> u8 {
+        9
+    }
+
+    fn ilp_address(&self) -> &Address {
+        &self.ilp_address
+    }
+}
+
+#[derive(Clone)]
+struct BenchStore;
+
+impl StreamNotificationsStore for BenchStore {
+    type Account = BenchAccount;
+
+    fn add_payment_notification_subscription(
+        &self,
+        _account_id: Uuid,
+        _sender: UnboundedSender<PaymentNotification>,
+    ) {
+    }
+
+    fn publish_payment_notification(&self, _payment: PaymentNotification) {}
+
+    fn all_payment_subscription(&self) -> broadcast::Receiver<PaymentNotification> {
+        broadcast::channel(1).1
+    }
+}
+
+#[async_trait]
+impl StreamReceiptStore for BenchStore {
+    async fn add_stream_received(
+        &self,
+        _nonce: [u8; 16],
+        _stream_id: u64,
+        amount: u64,
+    ) -> Result<u64, ReceiptStoreError> {
+        Ok(amount)
+    }
+}
+
+/// The condition of a STREAM packet, as the receiver derives it from the shared secret
+fn condition(shared_secret: &[u8], data: &[u8]) -> [u8; 32] {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, shared_secret);
+    let fulfillment_key = hmac::sign(&key, b"ilp_stream_fulfillment");
+    let key = hmac::Key::new(hmac::HMAC_SHA256, fulfillment_key.as_ref());
+    let fulfillment = hmac::sign(&key, data);
+    let mut condition = [0; 32];
+    condition.copy_from_slice(digest::digest(&digest::SHA256, fulfillment.as_ref()).as_ref());
+    condition
+}
+
+fn stream_packet(data: &[u8]) -> StreamPacket {
+    StreamPacketBuilder {
+        sequence: 1,
+        ilp_packet_type: PacketType::Prepare,
+        prepare_amount: 99,
+        frames: &[
+            Frame::StreamMoney(StreamMoneyFrame {
+                stream_id: 1,
+                shares: 1,
+            }),
+            Frame::StreamMaxMoney(StreamMaxMoneyFrame {
+                stream_id: 1,
+                receive_max: 1000,
+                total_received: 500,
+            }),
+            Frame::StreamData(StreamDataFrame {
+                stream_id: 1,
+                offset: 0,
+                data,
+            }),
+        ],
+    }
+    .build()
+}
+
+fn prepare(destination: Address, shared_secret: &[u8]) -> Prepare {
+    let data = stream_packet(b"hello").into_encrypted(shared_secret);
+    PrepareBuilder {
+        amount: 100,
+        expires_at: SystemTime::now() + Duration::from_secs(3600),
+        execution_condition: &condition(shared_secret, &data),
+        destination,
+        data: &data,
+    }
+    .build()
+}
+
+fn benchmark_packets(c: &mut Criterion) {
+    c.bench_function("STREAM packet (encrypt)", |b| {
+        b.iter(|| stream_packet(b"hello").into_encrypted(&SHARED_SECRET))
+    });
+
+    let ciphertext = stream_packet(b"hello").into_encrypted(&SHARED_SECRET);
+    c.bench_function("STREAM packet (decrypt)", move |b| {
+        b.iter(|| {
+            let packet = StreamPacket::from_encrypted(&SHARED_SECRET, ciphertext.clone()).unwrap();
+            assert_eq!(packet.frames().count(), 3);
+        })
+    });
+}
+
+fn benchmark_receiver(c: &mut Criterion) {
+    let server_secret = Bytes::from(&[1; 32][..]);
+    let (destination, shared_secret) =
+        ConnectionGenerator::new(server_secret.clone()).generate_address_and_secret(&RECEIVER);
+    let prepare = prepare(destination, &shared_secret);
+    let account = BenchAccount {
+        id: Uuid::new_v4(),
+        ilp_address: RECEIVER.clone(),
+    };
+    let mut service = StreamReceiverService::new(
+        server_secret,
+        BenchStore,
+        outgoing_service_fn(|_: OutgoingRequest<BenchAccount>| -> IlpResult {
+            panic!("The packets are all for the receiver")
+        }),
+    );
+    let mut runtime = tokio::runtime::Builder::new()
+        .basic_scheduler()
+        .build()
+        .unwrap();
+
+    c.bench_function("STREAM receiver (fulfill)", move |b| {
+        b.iter(|| {
+            let request = OutgoingRequest {
+                from: account.clone(),
+                to: account.clone(),
+                original_amount: prepare.amount(),
+                prepare: prepare.clone(),
+            };
+            assert!(runtime.block_on(service.send_request(request)).is_ok());
+        })
+    });
+}
+
+criterion_group!(benches, benchmark_packets, benchmark_receiver);
+criterion_main!(benches);
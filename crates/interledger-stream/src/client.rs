@@ -2,9 +2,14 @@ use super::congestion::CongestionController;
 use super::crypto::*;
 use super::error::Error;
 use super::packet::*;
+use super::runtime::Runtime;
+use async_trait::async_trait;
 use bytes::Bytes;
 use bytes::BytesMut;
+use futures::future::{select, Either};
+use futures::lock::Mutex;
 use futures::stream::{FuturesUnordered, StreamExt};
+use interledger_errors::{MaxPacketAmountStoreError, UnreachableDestinationStoreError};
 use interledger_packet::{
     Address, ErrorClass, ErrorCode as IlpErrorCode, PacketType as IlpPacketType, PrepareBuilder,
     Reject,
@@ -18,16 +23,13 @@ use num::traits::ops::checked::CheckedDiv;
 use num::traits::pow::pow;
 use num::BigInt;
 use serde::{Deserialize, Serialize};
-use tokio::sync::Mutex;
-use tokio::time::timeout_at;
-use tokio::time::{Duration, Instant};
 use tracing::{debug, error, warn};
 
 use std::cmp::{max, min};
 use std::marker::{Send, Sync};
 use std::str;
 use std::sync::Arc;
-use std::time::SystemTime;
+use std::time::Duration;
 
 /// Maximum time we should wait since last fulfill before we error out to avoid
 /// getting into an infinite loop of sending packets and effectively DoSing ourselves
@@ -39,6 +41,116 @@ const FAIL_FAST_MINIMUM_PACKET_ATTEMPTS: u64 = 200;
 /// Minimum rate of rejected packets in order to terminate the payment
 const FAIL_FAST_MINIMUM_FAILURE_RATE: f64 = 0.99;
 
+/// Time for which the max packet amount discovered on the path to a destination
+/// is shared with subsequent payments. After it expires, the path is probed again,
+/// in case its max packet amount has been raised.
+const MAX_PACKET_AMOUNT_CACHE_TTL: Duration = Duration::from_secs(600);
+
+/// Time for which the `F02: Unreachable` rejects of a destination are remembered. While the
+/// destination keeps being unreachable, the payments to it fail without sending any packet.
+const UNREACHABLE_DESTINATION_TTL: Duration = Duration::from_secs(60);
+
+/// Number of `F02: Unreachable` rejects from a destination, each less than the TTL after
+/// the previous one, after which the payments to it fail fast
+const UNREACHABLE_DESTINATION_FAILURES: u32 = 3;
+
+/// Longest memo, in bytes, which can be attached to a payment
+pub const MAX_MEMO_LENGTH: usize = 256;
+
+/// Max number of unfulfillable packets sent to discover the exchange rate of the path
+/// before a payment of a fixed destination amount
+const MAX_PROBE_PACKETS: usize = 10;
+
+/// Store trait for sharing the max packet amounts discovered via `F08: Amount Too Large`
+/// rejects between payments, so that each payment starts with the right packet size
+/// instead of probing the path again
+#[async_trait]
+pub trait MaxPacketAmountStore {
+    /// Returns the cached max packet amount of the path to the destination prefix,
+    /// in the units of the given source asset
+    async fn get_max_packet_amount(
+        &self,
+        source_asset_code: &str,
+        source_asset_scale: u8,
+        destination_prefix: &str,
+    ) -> Result<Option<u64>, MaxPacketAmountStoreError>;
+
+    /// Caches the max packet amount of the path to the destination prefix (in the
+    /// units of the given source asset) for `ttl` milliseconds
+    async fn set_max_packet_amount(
+        &self,
+        source_asset_code: &str,
+        source_asset_scale: u8,
+        destination_prefix: &str,
+        max_packet_amount: u64,
+        ttl: u64,
+    ) -> Result<(), MaxPacketAmountStoreError>;
+}
+
+/// A destination whose packets were recently rejected with `F02: Unreachable`
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+pub struct UnreachableDestination {
+    /// Prefix of the destination addresses, without their connection tokens
+    pub destination_prefix: String,
+    /// Number of `F02: Unreachable` rejects in a row
+    pub failures: u32,
+    /// The error of the last reject
+    pub error: String,
+    /// Milliseconds until the rejects are forgotten, unless the destination is unreachable again
+    pub expires_in: u64,
+}
+
+impl UnreachableDestination {
+    /// Whether the destination was unreachable often enough for the payments to it to fail fast
+    pub fn fails_fast(&self) -> bool {
+        self.failures >= UNREACHABLE_DESTINATION_FAILURES
+    }
+}
+
+/// Store trait for remembering the destinations which reject packets with `F02: Unreachable`,
+/// so that the payments to a destination which is repeatedly unreachable fail right away
+/// instead of sending packets which are bound to be rejected
+#[async_trait]
+pub trait UnreachableDestinationStore {
+    /// Returns the rejects of the destination prefix which have not expired
+    async fn get_unreachable_destination(
+        &self,
+        destination_prefix: &str,
+    ) -> Result<Option<UnreachableDestination>, UnreachableDestinationStoreError>;
+
+    /// Counts one more reject of the destination prefix with the provided error, and
+    /// remembers its rejects for `ttl` milliseconds
+    async fn record_unreachable_destination(
+        &self,
+        destination_prefix: &str,
+        error: &str,
+        ttl: u64,
+    ) -> Result<(), UnreachableDestinationStoreError>;
+
+    /// Returns all the destinations whose rejects have not expired
+    async fn get_unreachable_destinations(
+        &self,
+    ) -> Result<Vec<UnreachableDestination>, UnreachableDestinationStoreError>;
+
+    /// Forgets the rejects of the destination prefix, or of all the destinations if it is
+    /// `None`, and returns the number of destinations forgotten
+    async fn clear_unreachable_destinations(
+        &self,
+        destination_prefix: Option<&str>,
+    ) -> Result<usize, UnreachableDestinationStoreError>;
+}
+
+/// Returns the prefix of a STREAM destination address which is shared by all of the
+/// receiver's connections, i.e. the address without the connection token
+fn destination_prefix(destination: &Address) -> String {
+    let segments: Vec<&str> = destination.segments().collect();
+    if segments.len() > 2 {
+        segments[..segments.len() - 1].join(".")
+    } else {
+        segments.join(".")
+    }
+}
+
 /// Receipt for STREAM payment to account for how much and what assets were sent & delivered
 #[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
 pub struct StreamDelivery {
@@ -83,6 +195,16 @@ impl StreamDelivery {
     }
 }
 
+/// The amount to deliver of a payment of a fixed destination amount
+struct FixedDestination {
+    /// Destination amount the payment must deliver
+    amount: u64,
+    /// Exchange rate of the path discovered by probing it, in destination units per source unit
+    rate: BigRational,
+    /// Destination amount expected from the packets in-flight, at the probed rate
+    in_flight_amount: u64,
+}
+
 /// Stream payment mutable state: amounts & assets sent and received, sequence, packet counts, and flow control parameters
 struct StreamPayment {
     /// The [congestion controller](./../congestion/struct.CongestionController.html) to adjust flow control and the in-flight amount
@@ -99,8 +221,11 @@ struct StreamPayment {
     rejected_packets: u64,
     /// Number of rejected packets applied to the fail-fast threshold
     fail_fast_rejects: u64,
-    /// Timestamp when a packet was last fulfilled for this payment
-    last_fulfill_time: Instant,
+    /// Time of the runtime when a packet was last fulfilled for this payment
+    last_fulfill_time: Duration,
+    /// The amount to deliver, if the destination amount of the payment is fixed rather than
+    /// its source amount
+    fixed_destination: Option<FixedDestination>,
 }
 
 impl StreamPayment {
@@ -174,20 +299,40 @@ impl StreamPayment {
         // (1) Amount available to send, subtracting fulfilled and in-fligth amounts
         source_amount = min(source_amount, self.get_amount_available_to_send());
 
+        // (0) Amount which delivers what is left of a fixed destination amount at the probed rate
+        let destination_amount_available = self.get_destination_amount_available();
+        if let Some((fixed, available)) = self
+            .fixed_destination
+            .as_mut()
+            .zip(destination_amount_available)
+        {
+            source_amount = min(source_amount, source_amount_for(available, &fixed.rate));
+            fixed.in_flight_amount = fixed
+                .in_flight_amount
+                .saturating_add(convert(source_amount, fixed.rate.clone()).unwrap_or(0));
+        }
+
         // Account for the prepare
         self.congestion_controller.prepare(source_amount);
         self.receipt.sent_amount = self.receipt.sent_amount.saturating_add(source_amount);
         self.receipt.in_flight_amount = self.receipt.in_flight_amount.saturating_add(source_amount);
 
-        // Compute the minimum destination amount using the same rate
+        // Compute the minimum destination amount using the same rate. The last packets of a
+        // fixed destination amount may not require more than what is left to deliver, which
+        // the rounding of their source amount would otherwise exceed.
         let min_destination_amount = convert(source_amount, rate).unwrap_or(0);
+        let min_destination_amount = match destination_amount_available {
+            Some(available) => min(min_destination_amount, available),
+            None => min_destination_amount,
+        };
         (source_amount, min_destination_amount)
     }
 
     /// Account for a fulfilled packet and update flow control
     #[inline]
-    fn apply_fulfill(&mut self, source_amount: u64, destination_amount: u64) {
+    fn apply_fulfill(&mut self, source_amount: u64, destination_amount: u64, now: Duration) {
         self.congestion_controller.fulfill(source_amount);
+        self.settle_fixed_destination(source_amount);
 
         self.receipt.in_flight_amount = self.receipt.in_flight_amount.saturating_sub(source_amount);
         self.receipt.delivered_amount = self
@@ -195,7 +340,7 @@ impl StreamPayment {
             .delivered_amount
             .saturating_add(destination_amount);
 
-        self.last_fulfill_time = Instant::now();
+        self.last_fulfill_time = now;
         self.fulfilled_packets += 1;
     }
 
@@ -203,6 +348,7 @@ impl StreamPayment {
     #[inline]
     fn apply_reject(&mut self, amount: u64, reject: &Reject) {
         self.congestion_controller.reject(amount, reject);
+        self.settle_fixed_destination(amount);
 
         self.receipt.sent_amount = self.receipt.sent_amount.saturating_sub(amount);
         self.receipt.in_flight_amount = self.receipt.in_flight_amount.saturating_sub(amount);
@@ -222,6 +368,33 @@ impl StreamPayment {
         }
     }
 
+    /// Remove the destination amount expected from a packet which is no longer in-flight
+    #[inline]
+    fn settle_fixed_destination(&mut self, source_amount: u64) {
+        if let Some(fixed) = self.fixed_destination.as_mut() {
+            let expected_amount = convert(source_amount, fixed.rate.clone()).unwrap_or(0);
+            fixed.in_flight_amount = fixed.in_flight_amount.saturating_sub(expected_amount);
+        }
+    }
+
+    /// Fix the destination amount of the payment, which is delivered at the probed rate
+    /// by sending at most the given source amount
+    fn set_fixed_destination(&mut self, amount: u64, rate: BigRational, max_source_amount: u64) {
+        let max_packet_amount = self.congestion_controller.get_max_packet_amount();
+        self.congestion_controller =
+            CongestionController::new(max_source_amount, max_source_amount / 10, 2.0);
+        if max_packet_amount < u64::MAX {
+            self.congestion_controller
+                .set_max_packet_amount(max_packet_amount);
+        }
+        self.receipt.source_amount = max_source_amount;
+        self.fixed_destination = Some(FixedDestination {
+            amount,
+            rate,
+            in_flight_amount: 0,
+        });
+    }
+
     /// Save the recipient's destination asset details for calculating minimum exchange rates
     #[inline]
     fn set_destination_asset_details(&mut self, asset_code: String, asset_scale: u8) {
@@ -229,6 +402,66 @@ impl StreamPayment {
         self.receipt.destination_asset_scale = Some(asset_scale);
     }
 
+    /// Reads the STREAM packet of the reply to the packet with the sequence, and returns
+    /// the amount the recipient claims they received (zero if the packet is invalid)
+    fn read_reply(&mut self, shared_secret: &[u8], sequence: u64, reply: &IlpResult) -> u64 {
+        let (packet_type, reply_data) = match reply {
+            Ok(fulfill) => (IlpPacketType::Fulfill, fulfill.data()),
+            Err(reject) => (IlpPacketType::Reject, reject.data()),
+        };
+
+        let stream_reply_packet =
+            StreamPacket::from_encrypted(shared_secret, BytesMut::from(reply_data));
+
+        match stream_reply_packet {
+            Ok(stream_reply_packet) => {
+                if stream_reply_packet.sequence() != sequence {
+                    warn!(
+                        "Discarding replayed STREAM packet (expected sequence {}, but received {})",
+                        sequence,
+                        stream_reply_packet.sequence()
+                    );
+                    0
+                } else if stream_reply_packet.ilp_packet_type() == IlpPacketType::Reject
+                    && packet_type == IlpPacketType::Fulfill
+                {
+                    // If receiver claimed they sent a Reject but we got a Fulfill, they lied!
+                    // If receiver said they sent a Fulfill but we got a Reject, that's possible
+                    warn!("Discarding STREAM packet (received Fulfill, but recipient said they sent a Reject)");
+                    0
+                } else {
+                    // Since we decrypted the response, the recipient read the request packet and knows our account
+                    self.should_send_source_account = false;
+
+                    // Update the destination asset scale & code
+                    // https://github.com/interledger/rfcs/pull/551 ensures that this won't change
+                    if self.receipt.destination_asset_scale.is_none() {
+                        for frame in stream_reply_packet.frames() {
+                            if let Frame::ConnectionAssetDetails(frame) = frame {
+                                let asset_code = frame.source_asset_code.to_string();
+                                let asset_scale = frame.source_asset_scale;
+                                debug!(
+                                    "Setting remote asset details ({} with scale {})",
+                                    asset_code, asset_scale
+                                );
+                                self.set_destination_asset_details(asset_code, asset_scale);
+                            }
+                        }
+                    }
+
+                    stream_reply_packet.prepare_amount()
+                }
+            }
+            Err(_) => {
+                warn!(
+                    "Unable to parse STREAM packet from response data for sequence {}",
+                    sequence
+                );
+                0
+            }
+        }
+    }
+
     /// Return the current sequence number and increment the value for subsequent packets
     #[inline]
     fn next_sequence(&mut self) -> u64 {
@@ -253,10 +486,34 @@ impl StreamPayment {
             .saturating_sub(self.get_fulfilled_amount())
     }
 
-    /// Has the entire intended source amount been fulfilled by the recipient?
+    /// Has the entire intended source amount (or the fixed destination amount) been fulfilled by the recipient?
     #[inline]
     fn is_complete(&self) -> bool {
-        self.get_remaining_amount() == 0
+        match &self.fixed_destination {
+            Some(fixed) => self.receipt.delivered_amount >= fixed.amount,
+            None => self.get_remaining_amount() == 0,
+        }
+    }
+
+    /// Has the payment run out of source amount to send, with nothing left in-flight,
+    /// before it was complete? (Only payments of a fixed destination amount can)
+    #[inline]
+    fn is_exhausted(&self) -> bool {
+        !self.is_complete()
+            && self.receipt.in_flight_amount == 0
+            && self.get_amount_available_to_send() == 0
+    }
+
+    /// Return the destination amount left to deliver of a fixed destination amount,
+    /// minus the amount expected from the packets in-flight
+    #[inline]
+    fn get_destination_amount_available(&self) -> Option<u64> {
+        self.fixed_destination.as_ref().map(|fixed| {
+            fixed
+                .amount
+                .saturating_sub(self.receipt.delivered_amount)
+                .saturating_sub(fixed.in_flight_amount)
+        })
     }
 
     /// Return the amount of money available to be sent in the payment (amount remaining minus in-flight)
@@ -275,6 +532,7 @@ impl StreamPayment {
     fn is_max_in_flight(&self) -> bool {
         self.congestion_controller.get_amount_left_in_window() == 0
             || self.get_amount_available_to_send() == 0
+            || self.get_destination_amount_available() == Some(0)
     }
 
     /// Given we've attempted sending enough packets, does the rate of rejects
@@ -289,6 +547,7 @@ impl StreamPayment {
 
 /// Send the given source amount with packetized Interledger payments using the STREAM transport protocol
 /// Returns the receipt with sent & delivered amounts, asset & account details
+#[cfg(not(target_arch = "wasm32"))]
 pub async fn send_money<I, A, S>(
     service: I,
     from_account: &A,
@@ -301,8 +560,186 @@ pub async fn send_money<I, A, S>(
 where
     I: IncomingService<A> + Clone + Send + Sync + 'static,
     A: Account + Send + Sync + 'static,
-    S: ExchangeRateStore + Send + Sync + 'static,
+    S: ExchangeRateStore
+        + MaxPacketAmountStore
+        + UnreachableDestinationStore
+        + Send
+        + Sync
+        + 'static,
 {
+    send_money_with_memo(
+        service,
+        from_account,
+        store,
+        destination_account,
+        shared_secret,
+        source_amount,
+        slippage,
+        None,
+    )
+    .await
+}
+
+/// Like [`send_money`](./fn.send_money.html), with a memo (such as the reference of an invoice)
+/// which is sent to the receiver in a `StreamData` frame of each packet. Receivers built on
+/// the `StreamReceiverService` include it in their payment notifications.
+#[cfg(not(target_arch = "wasm32"))]
+#[allow(clippy::too_many_arguments)]
+pub async fn send_money_with_memo<I, A, S>(
+    service: I,
+    from_account: &A,
+    store: S,
+    destination_account: Address,
+    shared_secret: Vec<u8>,
+    source_amount: u64,
+    slippage: f64,
+    memo: Option<String>,
+) -> Result<StreamDelivery, Error>
+where
+    I: IncomingService<A> + Clone + Send + Sync + 'static,
+    A: Account + Send + Sync + 'static,
+    S: ExchangeRateStore
+        + MaxPacketAmountStore
+        + UnreachableDestinationStore
+        + Send
+        + Sync
+        + 'static,
+{
+    send_money_with_runtime(
+        super::runtime::TokioRuntime::default(),
+        service,
+        from_account,
+        store,
+        destination_account,
+        shared_secret,
+        source_amount,
+        slippage,
+        memo,
+    )
+    .await
+}
+
+/// Deliver the given destination amount with packetized Interledger payments using the STREAM
+/// transport protocol, or fail. The exchange rate of the path is probed with unfulfillable packets
+/// first, and the payment fails without sending any money if it is below the rate of the store
+/// minus the slippage. The packets are then sized to deliver what is left at the probed rate, so
+/// the delivered amount only exceeds the destination amount by the rounding of the last packets.
+///
+/// The `source_amount` of the returned receipt is the most the payment could send, i.e. the
+/// destination amount at the minimum rate, and its `sent_amount` is what it actually sent.
+#[cfg(not(target_arch = "wasm32"))]
+#[allow(clippy::too_many_arguments)]
+pub async fn deliver_money<I, A, S>(
+    service: I,
+    from_account: &A,
+    store: S,
+    destination_account: Address,
+    shared_secret: Vec<u8>,
+    destination_amount: u64,
+    slippage: f64,
+    memo: Option<String>,
+) -> Result<StreamDelivery, Error>
+where
+    I: IncomingService<A> + Clone + Send + Sync + 'static,
+    A: Account + Send + Sync + 'static,
+    S: ExchangeRateStore
+        + MaxPacketAmountStore
+        + UnreachableDestinationStore
+        + Send
+        + Sync
+        + 'static,
+{
+    stream_money(
+        super::runtime::TokioRuntime::default(),
+        service,
+        from_account,
+        store,
+        destination_account,
+        shared_secret,
+        PaymentAmount::Destination(destination_amount),
+        slippage,
+        memo,
+    )
+    .await
+}
+
+/// Like [`send_money_with_memo`](./fn.send_money_with_memo.html), with the clocks, timers
+/// and tasks of the runtime
+#[allow(clippy::too_many_arguments)]
+pub async fn send_money_with_runtime<R, I, A, S>(
+    runtime: R,
+    service: I,
+    from_account: &A,
+    store: S,
+    destination_account: Address,
+    shared_secret: Vec<u8>,
+    source_amount: u64,
+    slippage: f64,
+    memo: Option<String>,
+) -> Result<StreamDelivery, Error>
+where
+    R: Runtime,
+    I: IncomingService<A> + Clone + Send + Sync + 'static,
+    A: Account + Send + Sync + 'static,
+    S: ExchangeRateStore
+        + MaxPacketAmountStore
+        + UnreachableDestinationStore
+        + Send
+        + Sync
+        + 'static,
+{
+    stream_money(
+        runtime,
+        service,
+        from_account,
+        store,
+        destination_account,
+        shared_secret,
+        PaymentAmount::Source(source_amount),
+        slippage,
+        memo,
+    )
+    .await
+}
+
+/// The amount which is fixed by a payment
+#[derive(Clone, Copy, Debug)]
+enum PaymentAmount {
+    /// Send this source amount, and deliver whatever it is worth
+    Source(u64),
+    /// Deliver this destination amount, and send whatever it takes
+    Destination(u64),
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn stream_money<R, I, A, S>(
+    runtime: R,
+    service: I,
+    from_account: &A,
+    store: S,
+    destination_account: Address,
+    shared_secret: Vec<u8>,
+    amount: PaymentAmount,
+    slippage: f64,
+    memo: Option<String>,
+) -> Result<StreamDelivery, Error>
+where
+    R: Runtime,
+    I: IncomingService<A> + Clone + Send + Sync + 'static,
+    A: Account + Send + Sync + 'static,
+    S: ExchangeRateStore
+        + MaxPacketAmountStore
+        + UnreachableDestinationStore
+        + Send
+        + Sync
+        + 'static,
+{
+    if memo.as_ref().map_or(0, String::len) > MAX_MEMO_LENGTH {
+        return Err(Error::SendMoneyError(format!(
+            "Memo is longer than {} bytes",
+            MAX_MEMO_LENGTH
+        )));
+    }
     let shared_secret = Bytes::from(shared_secret);
 
     let from = from_account.ilp_address();
@@ -314,29 +751,109 @@ where
         );
     }
 
+    // The source amount of a fixed destination amount is only known once the path is probed
+    let source_amount = match amount {
+        PaymentAmount::Source(source_amount) => source_amount,
+        PaymentAmount::Destination(_) => 0,
+    };
+
+    // TODO Make configurable to get money flowing ASAP vs as much as possible per-packet
+    let mut congestion_controller =
+        CongestionController::new(source_amount, source_amount / 10, 2.0);
+    // Start with the max packet amount which previous payments discovered on the path
+    let destination_prefix = destination_prefix(&destination_account);
+    match store
+        .get_max_packet_amount(
+            from_account.asset_code(),
+            from_account.asset_scale(),
+            &destination_prefix,
+        )
+        .await
+    {
+        Ok(Some(max_packet_amount)) => {
+            debug!(
+                "Using cached max packet amount of {} for destination {}",
+                max_packet_amount, destination_prefix
+            );
+            congestion_controller.set_max_packet_amount(max_packet_amount);
+        }
+        Ok(None) => {}
+        Err(err) => warn!("Error loading cached max packet amount: {}", err),
+    }
+    // Fail right away if the destination was unreachable too many times in a row
+    let was_unreachable = match store.get_unreachable_destination(&destination_prefix).await {
+        Ok(Some(unreachable)) if unreachable.fails_fast() => {
+            return Err(Error::SendMoneyError(format!(
+                "Destination {} is unreachable ({} rejects in a row): {}",
+                destination_prefix, unreachable.failures, unreachable.error
+            )));
+        }
+        Ok(unreachable) => unreachable.is_some(),
+        Err(err) => {
+            warn!("Error loading unreachable destination: {}", err);
+            false
+        }
+    };
+
     let mut sender = StreamSender {
+        runtime: runtime.clone(),
         next: service,
         from_account: from_account.clone(),
         shared_secret,
         store,
         slippage,
+        memo: memo.map(Bytes::from),
+        destination_prefix: Arc::new(destination_prefix),
         payment: Arc::new(Mutex::new(StreamPayment {
-            // TODO Make configurable to get money flowing ASAP vs as much as possible per-packet
-            congestion_controller: CongestionController::new(
-                source_amount,
-                source_amount / 10,
-                2.0,
-            ),
+            congestion_controller,
             receipt: StreamDelivery::new(from_account, destination_account, source_amount),
             should_send_source_account: true,
             sequence: 1,
             fulfilled_packets: 0,
             rejected_packets: 0,
             fail_fast_rejects: 0,
-            last_fulfill_time: Instant::now(),
+            last_fulfill_time: runtime.now(),
+            fixed_destination: None,
         })),
     };
 
+    if let PaymentAmount::Destination(destination_amount) = amount {
+        let rate = sender.probe_exchange_rate().await?;
+        let mut payment = sender.payment.lock().await;
+        let min_rate = get_rate(
+            &sender.store,
+            payment.receipt.source_asset_scale,
+            &payment.receipt.source_asset_code,
+            payment.receipt.destination_asset_scale,
+            payment.receipt.destination_asset_code.as_deref(),
+            slippage,
+        )
+        .filter(|min_rate| !min_rate.is_zero())
+        .ok_or_else(|| {
+            Error::SendMoneyError(format!(
+                "No exchange rate from {} to {}",
+                payment.receipt.source_asset_code,
+                payment
+                    .receipt
+                    .destination_asset_code
+                    .as_deref()
+                    .unwrap_or("the receiver's asset")
+            ))
+        })?;
+        if rate < min_rate {
+            return Err(Error::SendMoneyError(format!(
+                "The exchange rate of the path ({}) is below the minimum rate ({})",
+                rate, min_rate
+            )));
+        }
+        let max_source_amount = source_amount_for(destination_amount, &min_rate);
+        debug!(
+            "Delivering {} at the probed rate of {}, sending at most {}",
+            destination_amount, rate, max_source_amount
+        );
+        payment.set_fixed_destination(destination_amount, rate, max_source_amount);
+    }
+
     let mut pending_requests = FuturesUnordered::new();
 
     /// Actions corresponding to the state of the payment
@@ -344,25 +861,29 @@ where
         /// Send more money: send a packet with the given source amount and minimum destination amount
         SendMoney((u64, u64)),
         /// Congestion controller limited in-flight amount: wait for pending requests until given deadline
-        MaxInFlight(Instant),
+        MaxInFlight(Duration),
         /// Sent full source amount: close the connection and return success
         CloseConnection,
         /// Maximum timeout since last fulfill has elapsed: terminate the payment
         Timeout,
         /// Too many packets are rejected, such as if the exchange rate is too low: terminate the payment
         FailFast,
+        /// Sent the max source amount without delivering the fixed destination amount: terminate the payment
+        Exhausted,
     }
 
     loop {
         let event = {
             let mut payment = sender.payment.lock().await;
 
-            if payment.last_fulfill_time.elapsed() >= MAX_TIME_SINCE_LAST_FULFILL {
+            if runtime.now() >= payment.last_fulfill_time + MAX_TIME_SINCE_LAST_FULFILL {
                 PaymentEvent::Timeout
             } else if payment.is_failing() {
                 PaymentEvent::FailFast
             } else if payment.is_complete() {
                 PaymentEvent::CloseConnection
+            } else if payment.is_exhausted() {
+                PaymentEvent::Exhausted
             } else if payment.is_max_in_flight() {
                 let deadline = payment
                     .last_fulfill_time
@@ -377,16 +898,17 @@ where
         match event {
             PaymentEvent::SendMoney((source_amount, dest_amount)) => {
                 let mut sender = sender.clone();
-                pending_requests.push(tokio::spawn(async move {
+                pending_requests.push(runtime.spawn(Box::pin(async move {
                     sender.send_money_packet(source_amount, dest_amount).await
-                }));
+                })));
             }
             PaymentEvent::MaxInFlight(deadline) => {
                 // Wait for any request to complete, or if after reach deadline since last fulfill,
                 // run loop again, which should timeout the payment
-                let result = timeout_at(deadline, pending_requests.select_next_some()).await;
+                let timeout = runtime.sleep(deadline.saturating_sub(runtime.now()));
+                let result = select(pending_requests.select_next_some(), timeout).await;
 
-                if let Ok(Ok(Err(error))) = result {
+                if let Either::Left((Err(error), _)) = result {
                     error!("Send money stopped because of error: {:?}", error);
                     return Err(error);
                 }
@@ -400,6 +922,9 @@ where
 
                 // Return final receipt
                 let payment = sender.payment.lock().await;
+                if was_unreachable && payment.fulfilled_packets > 0 {
+                    sender.forget_unreachable_destination().await;
+                }
                 debug!(
                     "Send money future finished. Delivered: {} ({} packets fulfilled, {} packets rejected)",
                     payment.receipt.delivered_amount,
@@ -414,6 +939,19 @@ where
                     "Time since last fulfill exceeded the maximum time limit".to_string(),
                 ));
             }
+            PaymentEvent::Exhausted => {
+                sender.try_send_connection_close().await;
+                let payment = sender.payment.lock().await;
+                return Err(Error::SendMoneyError(format!(
+                    "Delivered only {} of {} with the max source amount of {}",
+                    payment.receipt.delivered_amount,
+                    payment
+                        .fixed_destination
+                        .as_ref()
+                        .map_or(0, |fixed| fixed.amount),
+                    payment.receipt.source_amount,
+                )));
+            }
             PaymentEvent::FailFast => {
                 let payment = sender.payment.lock().await;
                 return Err(Error::SendMoneyError(
@@ -428,7 +966,9 @@ where
 
 /// Sends and handles all ILP & STREAM packets, encapsulating all payment state
 #[derive(Clone)]
-struct StreamSender<I, A, S> {
+struct StreamSender<R, I, A, S> {
+    /// Runtime the payment is sent on
+    runtime: R,
     /// Next service to send and forward Interledger packets to the network
     next: I,
     /// The account sending the STREAM payment
@@ -439,15 +979,20 @@ struct StreamSender<I, A, S> {
     store: S,
     /// Maximum acceptable slippage percentage below calculated minimum exchange rate
     slippage: f64,
+    /// Memo sent to the receiver with each packet, if there is one
+    memo: Option<Bytes>,
+    /// Prefix of the destination address under which discovered max packet amounts are cached
+    destination_prefix: Arc<String>,
     /// Mutable payment state
     payment: Arc<Mutex<StreamPayment>>,
 }
 
-impl<I, A, S> StreamSender<I, A, S>
+impl<R, I, A, S> StreamSender<R, I, A, S>
 where
+    R: Runtime,
     I: IncomingService<A>,
     A: Account,
-    S: ExchangeRateStore,
+    S: ExchangeRateStore + MaxPacketAmountStore + UnreachableDestinationStore,
 {
     /// Send a Prepare for the given source amount and apply the resulting Fulfill or Reject
     #[inline]
@@ -470,6 +1015,15 @@ where
                     source_account: payment.receipt.from.clone(),
                 }));
             }
+            // Every packet carries the whole memo, since the receiver does not keep
+            // the state of the connections to reassemble it
+            if let Some(memo) = &self.memo {
+                frames.push(Frame::StreamData(StreamDataFrame {
+                    stream_id: 1,
+                    offset: 0,
+                    data: &memo[..],
+                }));
+            }
             let stream_request_packet = StreamPacketBuilder {
                 ilp_packet_type: IlpPacketType::Prepare,
                 prepare_amount: min_destination_amount,
@@ -498,7 +1052,7 @@ where
                 destination: payment.receipt.to.clone(),
                 amount: source_amount,
                 execution_condition: &execution_condition,
-                expires_at: SystemTime::now() + Duration::from_secs(30),
+                expires_at: self.runtime.system_time() + Duration::from_secs(30),
                 // TODO Don't copy the data
                 data: &prepare_data[..],
             }
@@ -516,64 +1070,10 @@ where
             })
             .await;
 
-        let (packet_type, reply_data) = match &reply {
-            Ok(fulfill) => (IlpPacketType::Fulfill, fulfill.data()),
-            Err(reject) => (IlpPacketType::Reject, reject.data()),
-        };
-
-        let stream_reply_packet =
-            StreamPacket::from_encrypted(&self.shared_secret, BytesMut::from(reply_data));
-
         let mut payment = self.payment.lock().await;
 
         // Parse the stream packet and determine the amount the recipient claims they received
-        let claimed_amount: u64 = match stream_reply_packet {
-            Ok(stream_reply_packet) => {
-                if stream_reply_packet.sequence() != sequence {
-                    warn!(
-                        "Discarding replayed STREAM packet (expected sequence {}, but received {})",
-                        sequence,
-                        stream_reply_packet.sequence()
-                    );
-                    0
-                } else if stream_reply_packet.ilp_packet_type() == IlpPacketType::Reject
-                    && packet_type == IlpPacketType::Fulfill
-                {
-                    // If receiver claimed they sent a Reject but we got a Fulfill, they lied!
-                    // If receiver said they sent a Fulfill but we got a Reject, that's possible
-                    warn!("Discarding STREAM packet (received Fulfill, but recipient said they sent a Reject)");
-                    0
-                } else {
-                    // Since we decrypted the response, the recipient read the request packet and knows our account
-                    payment.should_send_source_account = false;
-
-                    // Update the destination asset scale & code
-                    // https://github.com/interledger/rfcs/pull/551 ensures that this won't change
-                    if payment.receipt.destination_asset_scale.is_none() {
-                        for frame in stream_reply_packet.frames() {
-                            if let Frame::ConnectionAssetDetails(frame) = frame {
-                                let asset_code = frame.source_asset_code.to_string();
-                                let asset_scale = frame.source_asset_scale;
-                                debug!(
-                                    "Setting remote asset details ({} with scale {})",
-                                    asset_code, asset_scale
-                                );
-                                payment.set_destination_asset_details(asset_code, asset_scale);
-                            }
-                        }
-                    }
-
-                    stream_reply_packet.prepare_amount()
-                }
-            }
-            Err(_) => {
-                warn!(
-                    "Unable to parse STREAM packet from response data for sequence {}",
-                    sequence
-                );
-                0
-            }
-        };
+        let claimed_amount = payment.read_reply(&self.shared_secret, sequence, &reply);
 
         match reply {
             // Handle ILP Fulfill
@@ -582,7 +1082,7 @@ where
                 // Even if the data was invalid, since it was fulfilled, we must assume they got at least the minimum
                 let delivered_amount = max(min_destination_amount, claimed_amount);
 
-                payment.apply_fulfill(source_amount, delivered_amount);
+                payment.apply_fulfill(source_amount, delivered_amount, self.runtime.now());
 
                 debug!(
                     "Prepare {} with amount {} was fulfilled ({} left to send)",
@@ -605,6 +1105,18 @@ where
                     payment.get_remaining_amount()
                 );
 
+                if reject.code() == IlpErrorCode::F08_AMOUNT_TOO_LARGE {
+                    let max_packet_amount = payment.congestion_controller.get_max_packet_amount();
+                    // Do not block the other packets while the store is updated
+                    drop(payment);
+                    if max_packet_amount < u64::MAX {
+                        self.cache_max_packet_amount(max_packet_amount).await;
+                    }
+                } else if reject.code() == IlpErrorCode::F02_UNREACHABLE {
+                    drop(payment);
+                    self.record_unreachable_destination(&reject).await;
+                }
+
                 match (reject.code().class(), reject.code()) {
                     (ErrorClass::Temporary, _) => Ok(()),
                     (_, IlpErrorCode::F08_AMOUNT_TOO_LARGE) => Ok(()),
@@ -623,6 +1135,163 @@ where
         }
     }
 
+    /// Send unfulfillable packets until the receiver tells how much one of them was worth,
+    /// and return the exchange rate of the path in destination units per source unit. The
+    /// probes start at one unit of the source asset (or the max packet amount, if it is
+    /// lower) and grow tenfold while they are worth nothing.
+    async fn probe_exchange_rate(&mut self) -> Result<BigRational, Error> {
+        let mut probe_amount = {
+            let payment = self.payment.lock().await;
+            min(
+                10u64.saturating_pow(u32::from(payment.receipt.source_asset_scale)),
+                payment.congestion_controller.get_max_packet_amount(),
+            )
+        };
+        for _ in 0..MAX_PROBE_PACKETS {
+            let (prepare, sequence) = {
+                let mut payment = self.payment.lock().await;
+                let sequence = payment.next_sequence();
+                let stream_packet = StreamPacketBuilder {
+                    ilp_packet_type: IlpPacketType::Prepare,
+                    prepare_amount: 0,
+                    sequence,
+                    frames: &[
+                        Frame::StreamMoney(StreamMoneyFrame {
+                            stream_id: 1,
+                            shares: 1,
+                        }),
+                        Frame::ConnectionNewAddress(ConnectionNewAddressFrame {
+                            source_account: payment.receipt.from.clone(),
+                        }),
+                    ],
+                }
+                .build();
+                let data = stream_packet.into_encrypted(&self.shared_secret);
+                let prepare = PrepareBuilder {
+                    destination: payment.receipt.to.clone(),
+                    amount: probe_amount,
+                    execution_condition: &random_condition(),
+                    expires_at: self.runtime.system_time() + Duration::from_secs(30),
+                    data: &data[..],
+                }
+                .build();
+                (prepare, sequence)
+            };
+
+            debug!("Probing the exchange rate with {}", probe_amount);
+            let reply = self
+                .next
+                .handle_request(IncomingRequest {
+                    from: self.from_account.clone(),
+                    prepare,
+                })
+                .await;
+
+            let mut payment = self.payment.lock().await;
+            let claimed_amount = payment.read_reply(&self.shared_secret, sequence, &reply);
+            let reject = match reply {
+                // The condition is random, so a Fulfill can only come from a broken receiver
+                Ok(_) => {
+                    return Err(Error::SendMoneyError(
+                        "Unfulfillable probe was fulfilled".to_string(),
+                    ))
+                }
+                Err(reject) => reject,
+            };
+            match reject.code() {
+                IlpErrorCode::F99_APPLICATION_ERROR if claimed_amount > 0 => {
+                    return Ok(BigRational::new(
+                        BigInt::from(claimed_amount),
+                        BigInt::from(probe_amount),
+                    ));
+                }
+                IlpErrorCode::F08_AMOUNT_TOO_LARGE => {
+                    payment.congestion_controller.reject(probe_amount, &reject);
+                    probe_amount = min(
+                        probe_amount,
+                        payment.congestion_controller.get_max_packet_amount(),
+                    );
+                }
+                // The probe was worth nothing at the receiver or at a connector
+                IlpErrorCode::F99_APPLICATION_ERROR
+                | IlpErrorCode::R01_INSUFFICIENT_SOURCE_AMOUNT => {
+                    let next_amount = min(
+                        probe_amount.saturating_mul(10),
+                        payment.congestion_controller.get_max_packet_amount(),
+                    );
+                    if next_amount == probe_amount {
+                        break;
+                    }
+                    probe_amount = next_amount;
+                }
+                code if code.class() == ErrorClass::Temporary => {}
+                code => {
+                    drop(payment);
+                    if code == IlpErrorCode::F02_UNREACHABLE {
+                        self.record_unreachable_destination(&reject).await;
+                    }
+                    return Err(Error::SendMoneyError(format!(
+                        "Probe was rejected with error: {} {}",
+                        code,
+                        str::from_utf8(reject.message()).unwrap_or_default(),
+                    )));
+                }
+            }
+        }
+        Err(Error::SendMoneyError(
+            "Could not probe the exchange rate of the path to the receiver".to_string(),
+        ))
+    }
+
+    /// Share the max packet amount discovered on the path with subsequent payments
+    async fn cache_max_packet_amount(&self, max_packet_amount: u64) {
+        if let Err(err) = self
+            .store
+            .set_max_packet_amount(
+                self.from_account.asset_code(),
+                self.from_account.asset_scale(),
+                &self.destination_prefix,
+                max_packet_amount,
+                MAX_PACKET_AMOUNT_CACHE_TTL.as_millis() as u64,
+            )
+            .await
+        {
+            warn!("Error caching max packet amount: {}", err);
+        }
+    }
+
+    /// Count the `F02: Unreachable` reject against the destination, so that the payments to
+    /// it fail fast if it keeps being unreachable
+    async fn record_unreachable_destination(&self, reject: &Reject) {
+        let error = format!(
+            "{} {}",
+            reject.code(),
+            str::from_utf8(reject.message()).unwrap_or_default()
+        );
+        if let Err(err) = self
+            .store
+            .record_unreachable_destination(
+                &self.destination_prefix,
+                error.trim_end(),
+                UNREACHABLE_DESTINATION_TTL.as_millis() as u64,
+            )
+            .await
+        {
+            warn!("Error recording unreachable destination: {}", err);
+        }
+    }
+
+    /// Forget the rejects of the destination once a packet reached it
+    async fn forget_unreachable_destination(&self) {
+        if let Err(err) = self
+            .store
+            .clear_unreachable_destinations(Some(&self.destination_prefix))
+            .await
+        {
+            warn!("Error clearing unreachable destination: {}", err);
+        }
+    }
+
     /// Send an unfulfillable Prepare with a ConnectionClose frame to the peer
     /// There's no ACK from the recipient, so we can't confirm it closed
     #[inline]
@@ -648,7 +1317,7 @@ where
                 destination: payment.receipt.to.clone(),
                 amount: 0,
                 execution_condition: &random_condition(),
-                expires_at: SystemTime::now() + Duration::from_secs(30),
+                expires_at: self.runtime.system_time() + Duration::from_secs(30),
                 data: &data[..],
             }
             .build()
@@ -707,6 +1376,16 @@ fn get_rate<S: ExchangeRateStore>(
     Some(rate)
 }
 
+/// Compute the source amount which converts into at least the given destination amount
+/// at the rate. Round up for safety.
+#[inline]
+fn source_amount_for(destination_amount: u64, rate: &BigRational) -> u64 {
+    BigRational::from_u64(destination_amount)
+        .and_then(|destination_amount| destination_amount.checked_div(rate))
+        .and_then(|source_amount| source_amount.ceil().to_integer().to_u64())
+        .unwrap_or(u64::MAX)
+}
+
 /// Convert the given source amount into a destination amount
 /// using the provided rate. Round up for safety.
 #[inline]
@@ -726,7 +1405,7 @@ mod send_money_tests {
     use super::*;
     use crate::test_helpers::{TestAccount, TestStore, EXAMPLE_CONNECTOR};
     use async_trait::async_trait;
-    use interledger_packet::{ErrorCode as IlpErrorCode, RejectBuilder};
+    use interledger_packet::{ErrorCode as IlpErrorCode, MaxPacketAmountDetails, RejectBuilder};
     use interledger_service::incoming_service_fn;
     use interledger_service_util::MaxPacketAmountService;
     use parking_lot::Mutex;
@@ -763,6 +1442,8 @@ mod send_money_tests {
                 route: None,
                 price_1: None,
                 price_2: None,
+                max_packet_amounts: Default::default(),
+                unreachable_destinations: Default::default(),
             },
             Address::from_str("example.destination").unwrap(),
             vec![0; 32],
@@ -774,6 +1455,75 @@ mod send_money_tests {
         assert_eq!(requests.lock().len(), 1);
     }
 
+    #[tokio::test]
+    async fn fails_fast_to_unreachable_destinations() {
+        let account = TestAccount {
+            id: Uuid::new_v4(),
+            asset_code: "XYZ".to_string(),
+            asset_scale: 9,
+            ilp_address: Address::from_str("example.sender").unwrap(),
+            max_packet_amount: None,
+        };
+        let store = TestStore {
+            route: None,
+            price_1: None,
+            price_2: None,
+            max_packet_amounts: Default::default(),
+            unreachable_destinations: Default::default(),
+        };
+        let requests = Arc::new(AtomicUsize::new(0));
+        let requests_clone = requests.clone();
+        let service = incoming_service_fn(move |_| {
+            requests_clone.fetch_add(1, Ordering::Relaxed);
+            Err(RejectBuilder {
+                code: IlpErrorCode::F02_UNREACHABLE,
+                message: b"no route found",
+                triggered_by: Some(&EXAMPLE_CONNECTOR),
+                data: &[],
+            }
+            .build())
+        });
+        let pay = |destination: &str| {
+            send_money(
+                service.clone(),
+                &account,
+                store.clone(),
+                Address::from_str(destination).unwrap(),
+                vec![0; 32],
+                100,
+                0.0,
+            )
+        };
+
+        for _ in 0..UNREACHABLE_DESTINATION_FAILURES {
+            assert!(pay("example.receiver.connection").await.is_err());
+        }
+        assert_eq!(
+            requests.load(Ordering::Relaxed),
+            UNREACHABLE_DESTINATION_FAILURES as usize
+        );
+        // Other connections to the receiver fail without sending any packet
+        let err = pay("example.receiver.other_connection").await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Error polling: Destination example.receiver is unreachable (3 rejects in a row): F02 no route found"
+        );
+        assert_eq!(
+            requests.load(Ordering::Relaxed),
+            UNREACHABLE_DESTINATION_FAILURES as usize
+        );
+
+        store
+            .clear_unreachable_destinations(Some("example.receiver"))
+            .await
+            .unwrap();
+        assert!(pay("example.receiver.connection").await.is_err());
+        assert_eq!(
+            requests.load(Ordering::Relaxed),
+            UNREACHABLE_DESTINATION_FAILURES as usize + 1
+        );
+    }
+
     #[tokio::test]
     async fn perserveres_past_liquidity_errors() {
         let destination_address = Address::from_str("example.receiver").unwrap();
@@ -788,6 +1538,8 @@ mod send_money_tests {
             route: None,
             price_1: None,
             price_2: None,
+            max_packet_amounts: Default::default(),
+            unreachable_destinations: Default::default(),
         };
 
         let start_time = std::time::Instant::now();
@@ -846,6 +1598,8 @@ mod send_money_tests {
             route: Some((destination_address.to_string(), account)),
             price_1: None,
             price_2: None,
+            max_packet_amounts: Default::default(),
+            unreachable_destinations: Default::default(),
         };
 
         #[derive(Clone)]
@@ -903,6 +1657,8 @@ mod send_money_tests {
                 route: None,
                 price_1: None,
                 price_2: None,
+                max_packet_amounts: Default::default(),
+                unreachable_destinations: Default::default(),
             },
             destination_address.clone(),
             vec![0; 32],
@@ -915,6 +1671,87 @@ mod send_money_tests {
         assert_eq!(num_requests_in_flight.load(Ordering::Relaxed), 5);
     }
 
+    #[tokio::test]
+    async fn shares_discovered_max_packet_amount() {
+        let destination_address = Address::from_str("example.receiver.connection").unwrap();
+        let account = TestAccount {
+            id: Uuid::new_v4(),
+            asset_code: "XYZ".to_string(),
+            asset_scale: 9,
+            ilp_address: Address::from_str("example.sender").unwrap(),
+            max_packet_amount: None,
+        };
+        let store = TestStore {
+            route: None,
+            price_1: None,
+            price_2: None,
+            max_packet_amounts: Default::default(),
+            unreachable_destinations: Default::default(),
+        };
+        let amounts = Arc::new(Mutex::new(Vec::new()));
+        let amounts_clone = amounts.clone();
+        // Rejects packets above 10 with F08, and the others with a final error
+        let service = incoming_service_fn(move |request| {
+            let amount = request.prepare.amount();
+            amounts_clone.lock().push(amount);
+            if amount > 10 {
+                Err(RejectBuilder {
+                    code: IlpErrorCode::F08_AMOUNT_TOO_LARGE,
+                    message: &[],
+                    triggered_by: Some(&EXAMPLE_CONNECTOR),
+                    data: &MaxPacketAmountDetails::new(amount, 10).to_bytes(),
+                }
+                .build())
+            } else {
+                Err(RejectBuilder {
+                    code: IlpErrorCode::F00_BAD_REQUEST,
+                    message: b"just some final error",
+                    triggered_by: Some(&EXAMPLE_CONNECTOR),
+                    data: &[],
+                }
+                .build())
+            }
+        });
+
+        let result = send_money(
+            service.clone(),
+            &account,
+            store.clone(),
+            destination_address,
+            vec![0; 32],
+            100,
+            0.0,
+        )
+        .await;
+        assert!(result.is_err());
+        // Packets may be sent concurrently before the final error arrives
+        assert_eq!(amounts.lock()[0], 100);
+        assert!(amounts.lock()[1..].iter().all(|amount| *amount == 10));
+        assert_eq!(
+            store
+                .get_max_packet_amount("XYZ", 9, "example.receiver")
+                .await
+                .unwrap(),
+            Some(10)
+        );
+
+        // A payment to another connection of the same receiver starts with the discovered amount
+        amounts.lock().clear();
+        let result = send_money(
+            service,
+            &account,
+            store,
+            Address::from_str("example.receiver.other_connection").unwrap(),
+            vec![0; 32],
+            100,
+            0.0,
+        )
+        .await;
+        assert!(result.is_err());
+        assert!(!amounts.lock().is_empty());
+        assert!(amounts.lock().iter().all(|amount| *amount == 10));
+    }
+
     #[tokio::test]
     async fn computes_min_destination_amount() {
         struct TestData<'a> {
@@ -1084,6 +1921,8 @@ mod send_money_tests {
                     route: None,
                     price_1: t.price_1,
                     price_2: t.price_2,
+                    max_packet_amounts: Default::default(),
+                    unreachable_destinations: Default::default(),
                 },
                 t.source_scale,
                 t.source_code,
@@ -159,8 +159,8 @@ impl CongestionController {
         }
     }
 
-    #[cfg(test)]
-    fn set_max_packet_amount(&mut self, max_packet_amount: u64) {
+    /// Sets the max packet amount, for example to one discovered by a previous payment
+    pub fn set_max_packet_amount(&mut self, max_packet_amount: u64) {
         self.max_packet_amount = Some(max_packet_amount)
     }
 
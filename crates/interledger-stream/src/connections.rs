@@ -0,0 +1,254 @@
+use chrono::{DateTime, Utc};
+use interledger_packet::Address;
+use interledger_service::Username;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// Time after its last packet at which a connection is no longer active
+const CONNECTION_IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Time after its last packet for which a closed connection keeps being refused
+const CLOSED_CONNECTION_TTL: Duration = Duration::from_secs(3600);
+
+/// Max number of connections tracked at once, so that senders opening connections
+/// cannot exhaust the node's memory. The connections opened beyond it are not tracked.
+const MAX_TRACKED_CONNECTIONS: usize = 10_000;
+
+/// The statistics of an incoming STREAM connection of an account
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StreamConnection {
+    /// The token of the connection (the last segment of its destination address)
+    pub id: String,
+    /// The destination address of the connection's packets
+    pub destination: Address,
+    /// The address of the sender, if it told it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_account: Option<Address>,
+    /// The account the last packet of the connection was routed from
+    pub from_username: Username,
+    /// When the first packet of the connection was received, in RFC3339 format
+    pub opened_at: String,
+    /// When the last packet of the connection was received, in RFC3339 format
+    pub last_packet_at: String,
+    /// Time since the first packet of the connection, in seconds
+    pub age: u64,
+    /// Total amount of the fulfilled packets, in the units of the account
+    pub amount_received: u64,
+    pub fulfilled_packets: u64,
+    pub rejected_packets: u64,
+    /// Average number of packets per second since the connection was opened
+    pub packets_per_second: f64,
+}
+
+struct TrackedConnection {
+    account_id: Uuid,
+    connection: StreamConnection,
+    opened: Instant,
+    last_packet: Instant,
+    closed: bool,
+}
+
+impl TrackedConnection {
+    fn is_expired(&self, now: Instant) -> bool {
+        let ttl = if self.closed {
+            CLOSED_CONNECTION_TTL
+        } else {
+            CONNECTION_IDLE_TIMEOUT
+        };
+        now.duration_since(self.last_packet) > ttl
+    }
+
+    fn snapshot(&self, now: Instant) -> StreamConnection {
+        let age = now.duration_since(self.opened);
+        let packets = self.connection.fulfilled_packets + self.connection.rejected_packets;
+        StreamConnection {
+            age: age.as_secs(),
+            packets_per_second: packets as f64 / age.as_secs_f64().max(1.0),
+            ..self.connection.clone()
+        }
+    }
+}
+
+/// The incoming STREAM connections of the accounts, as seen by the
+/// [`StreamReceiverService`](./struct.StreamReceiverService.html) they are given to.
+///
+/// The connections are only tracked in the memory of the node, and are no longer listed once
+/// they are idle for 5 minutes. A connection which is closed refuses the packets of its sender
+/// with a `ConnectionClose` frame for an hour after its last packet (the sender may still open
+/// another connection via the account's SPSP server).
+#[derive(Clone, Default)]
+pub struct StreamConnections(Arc<Mutex<HashMap<String, TrackedConnection>>>);
+
+impl StreamConnections {
+    /// Returns the active connections of the account, ordered by when they were opened
+    pub fn list(&self, account_id: Uuid) -> Vec<StreamConnection> {
+        let now = Instant::now();
+        let mut connections = self.0.lock();
+        connections.retain(|_, tracked| !tracked.is_expired(now));
+        let mut list: Vec<(Instant, StreamConnection)> = connections
+            .values()
+            .filter(|tracked| tracked.account_id == account_id && !tracked.closed)
+            .map(|tracked| (tracked.opened, tracked.snapshot(now)))
+            .collect();
+        list.sort_by_key(|(opened, _)| *opened);
+        list.into_iter().map(|(_, connection)| connection).collect()
+    }
+
+    /// Closes the active connection of the account with the id, and returns its last statistics
+    pub fn close(&self, account_id: Uuid, id: &str) -> Option<StreamConnection> {
+        let now = Instant::now();
+        self.0
+            .lock()
+            .values_mut()
+            .find(|tracked| {
+                tracked.account_id == account_id
+                    && tracked.connection.id == id
+                    && !tracked.closed
+                    && !tracked.is_expired(now)
+            })
+            .map(|tracked| {
+                tracked.closed = true;
+                tracked.snapshot(now)
+            })
+    }
+
+    /// Returns whether the connection of the destination address was closed
+    pub(crate) fn is_closed(&self, destination: &Address) -> bool {
+        let now = Instant::now();
+        self.0
+            .lock()
+            .get_mut(&destination.to_string())
+            .filter(|tracked| tracked.closed && !tracked.is_expired(now))
+            .map(|tracked| tracked.last_packet = now)
+            .is_some()
+    }
+
+    /// Adds a packet of the connection of the destination address to its statistics
+    pub(crate) fn record(
+        &self,
+        account_id: Uuid,
+        destination: &Address,
+        from_username: &Username,
+        source_account: Option<Address>,
+        fulfilled_amount: Option<u64>,
+    ) {
+        let now = Instant::now();
+        let timestamp = DateTime::<Utc>::from(std::time::SystemTime::now()).to_rfc3339();
+        let mut connections = self.0.lock();
+        let key = destination.to_string();
+        if !connections.contains_key(&key) {
+            if connections.len() >= MAX_TRACKED_CONNECTIONS {
+                connections.retain(|_, tracked| !tracked.is_expired(now));
+                if connections.len() >= MAX_TRACKED_CONNECTIONS {
+                    return;
+                }
+            }
+            let id = destination
+                .segments()
+                .next_back()
+                .unwrap_or_default()
+                .to_string();
+            connections.insert(
+                key.clone(),
+                TrackedConnection {
+                    account_id,
+                    connection: StreamConnection {
+                        id,
+                        destination: destination.clone(),
+                        source_account: None,
+                        from_username: from_username.clone(),
+                        opened_at: timestamp.clone(),
+                        last_packet_at: timestamp.clone(),
+                        age: 0,
+                        amount_received: 0,
+                        fulfilled_packets: 0,
+                        rejected_packets: 0,
+                        packets_per_second: 0.0,
+                    },
+                    opened: now,
+                    last_packet: now,
+                    closed: false,
+                },
+            );
+        }
+        // The entry was inserted above if it was missing
+        if let Some(tracked) = connections.get_mut(&key) {
+            let connection = &mut tracked.connection;
+            tracked.last_packet = now;
+            connection.last_packet_at = timestamp;
+            connection.from_username = from_username.clone();
+            if source_account.is_some() {
+                connection.source_account = source_account;
+            }
+            match fulfilled_amount {
+                Some(amount) => {
+                    connection.amount_received = connection.amount_received.saturating_add(amount);
+                    connection.fulfilled_packets += 1;
+                }
+                None => connection.rejected_packets += 1,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn tracks_the_connections_of_each_account() {
+        let connections = StreamConnections::default();
+        let alice = Uuid::new_v4();
+        let bob = Uuid::new_v4();
+        let peer = Username::from_str("peer").unwrap();
+        let first = Address::from_str("example.alice.first").unwrap();
+        let second = Address::from_str("example.alice.second").unwrap();
+        let sender = Address::from_str("example.sender").unwrap();
+
+        connections.record(alice, &first, &peer, Some(sender.clone()), None);
+        connections.record(alice, &first, &peer, None, Some(100));
+        connections.record(alice, &first, &peer, None, Some(50));
+        connections.record(alice, &second, &peer, None, Some(10));
+        connections.record(
+            bob,
+            &Address::from_str("example.bob.third").unwrap(),
+            &peer,
+            None,
+            None,
+        );
+
+        let list = connections.list(alice);
+        assert_eq!(list.len(), 2);
+        assert_eq!(list[0].id, "first");
+        assert_eq!(list[0].source_account, Some(sender));
+        assert_eq!(list[0].amount_received, 150);
+        assert_eq!(list[0].fulfilled_packets, 2);
+        assert_eq!(list[0].rejected_packets, 1);
+        assert_eq!(list[1].id, "second");
+        assert_eq!(connections.list(bob).len(), 1);
+    }
+
+    #[test]
+    fn closes_connections() {
+        let connections = StreamConnections::default();
+        let alice = Uuid::new_v4();
+        let peer = Username::from_str("peer").unwrap();
+        let destination = Address::from_str("example.alice.token").unwrap();
+        connections.record(alice, &destination, &peer, None, Some(100));
+
+        assert!(!connections.is_closed(&destination));
+        assert!(connections.close(Uuid::new_v4(), "token").is_none());
+        assert_eq!(
+            connections.close(alice, "token").unwrap().amount_received,
+            100
+        );
+        assert!(connections.is_closed(&destination));
+        assert!(connections.list(alice).is_empty());
+        assert!(connections.close(alice, "token").is_none());
+    }
+}
@@ -112,13 +112,13 @@ fn encrypt_with_nonce(
     });
 
     // Rearrange the bytes so that the tag goes first (should have put it last in the JS implementation, but oh well)
+    // The format is `nonce, auth tag, data`, in that order. The bytes are copied rather than
+    // unsplit, since unsplitting small (inline) buffers offsets a dangling pointer
     let auth_tag_position = plaintext.len() - AUTH_TAG_LENGTH;
-    let mut tag_data = plaintext.split_off(auth_tag_position);
-    tag_data.unsplit(plaintext);
-
-    // The format is `nonce, auth tag, data`, in that order
-    let mut nonce_tag_data = BytesMut::from(&nonce[..]);
-    nonce_tag_data.unsplit(tag_data);
+    let mut nonce_tag_data = BytesMut::with_capacity(NONCE_LENGTH + plaintext.len());
+    nonce_tag_data.extend_from_slice(&nonce[..]);
+    nonce_tag_data.extend_from_slice(&plaintext[auth_tag_position..]);
+    nonce_tag_data.extend_from_slice(&plaintext[..auth_tag_position]);
 
     nonce_tag_data
 }
@@ -150,7 +150,7 @@ pub fn decrypt(shared_secret: &[u8], mut ciphertext: BytesMut) -> Result<BytesMu
     let additional_data: &[u8] = &[];
 
     // Ring expects the tag to come after the data
-    ciphertext.unsplit(auth_tag);
+    ciphertext.extend_from_slice(&auth_tag);
 
     let length = key
         .open_in_place(
@@ -232,4 +232,23 @@ mod encrypt_decrypt_test {
         let decrypted = decrypt(SHARED_SECRET, ciphertext);
         assert_eq!(&decrypted.unwrap()[..], PLAINTEXT);
     }
+
+    #[test]
+    fn it_encrypts_and_decrypts_small_buffers() {
+        for length in 0..=64 {
+            let plaintext: Vec<u8> = (0..length as u8).collect();
+            let ciphertext =
+                encrypt_with_nonce(SHARED_SECRET, BytesMut::from(&plaintext[..]), NONCE);
+            assert_eq!(ciphertext.len(), NONCE_LENGTH + AUTH_TAG_LENGTH + length);
+            assert_eq!(&ciphertext[..NONCE_LENGTH], &NONCE[..]);
+
+            // The ciphertext may be a view into a larger buffer, as when it is split off a packet
+            let mut packet = BytesMut::from(&[1, 2, 3][..]);
+            packet.extend_from_slice(&ciphertext);
+            let ciphertext = packet.split_off(3);
+            let decrypted = decrypt(SHARED_SECRET, ciphertext);
+            assert_eq!(&decrypted.unwrap()[..], &plaintext[..]);
+            assert_eq!(&packet[..], &[1, 2, 3]);
+        }
+    }
 }
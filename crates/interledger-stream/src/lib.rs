@@ -8,17 +8,37 @@
 mod client;
 /// Congestion controller consumed by the [stream client](./client/fn.send_money.html)
 mod congestion;
+/// Statistics of the incoming connections of the stream server
+mod connections;
 /// Cryptographic utilities for generating fulfillments and encrypting/decrypting STREAM packets
 mod crypto;
 /// Stream errors
 mod error;
 /// Stream Packet implementation, [as specified in the RFC](https://interledger.org/rfcs/0029-stream/#5-packet-and-frame-specification)
 mod packet;
+/// [STREAM Receipts](https://interledger.org/rfcs/0039-stream-receipts/) of the amounts received by the stream server
+mod receipt;
+/// The clocks, timers and tasks of the environment payments are sent from
+mod runtime;
 /// A stream server implementing an [Outgoing Service](../interledger_service/trait.OutgoingService.html) for receiving STREAM payments from peers
 mod server;
 
-pub use client::{send_money, StreamDelivery};
+#[cfg(not(target_arch = "wasm32"))]
+pub use client::{deliver_money, send_money, send_money_with_memo};
+pub use client::{
+    send_money_with_runtime, MaxPacketAmountStore, StreamDelivery, UnreachableDestination,
+    UnreachableDestinationStore, MAX_MEMO_LENGTH,
+};
+pub use connections::{StreamConnection, StreamConnections};
 pub use error::Error;
+pub use packet::{
+    Frame, StreamDataFrame, StreamMaxMoneyFrame, StreamMoneyFrame, StreamPacket,
+    StreamPacketBuilder,
+};
+pub use receipt::{Receipt, ReceiptError, ReceiptGenerator, StreamReceiptStore};
+pub use runtime::Runtime;
+#[cfg(not(target_arch = "wasm32"))]
+pub use runtime::TokioRuntime;
 pub use server::{
     ConnectionGenerator, PaymentNotification, StreamNotificationsStore, StreamReceiverService,
 };
@@ -28,13 +48,17 @@ pub mod test_helpers {
     use super::*;
     use async_trait::async_trait;
     use futures::channel::mpsc::UnboundedSender;
-    use interledger_errors::{AccountStoreError, AddressStoreError, ExchangeRateStoreError};
+    use interledger_errors::{
+        AccountStoreError, AddressStoreError, ExchangeRateStoreError, FeePolicyStoreError,
+        MaxPacketAmountStoreError, ReceiptStoreError, UnreachableDestinationStoreError,
+    };
     use interledger_packet::Address;
     use interledger_rates::ExchangeRateStore;
     use interledger_router::RouterStore;
     use interledger_service::{Account, AccountStore, AddressStore, Username};
-    use interledger_service_util::MaxPacketAmountAccount;
+    use interledger_service_util::{FeeAccount, FeePolicy, FeePolicyStore, MaxPacketAmountAccount};
     use once_cell::sync::Lazy;
+    use parking_lot::Mutex;
     use std::collections::HashMap;
     use std::iter::FromIterator;
     use std::str::FromStr;
@@ -85,6 +109,8 @@ pub mod test_helpers {
         }
     }
 
+    impl FeeAccount for TestAccount {}
+
     #[derive(Clone)]
     pub struct DummyStore;
 
@@ -105,11 +131,25 @@ pub mod test_helpers {
         }
     }
 
+    #[async_trait]
+    impl super::StreamReceiptStore for DummyStore {
+        async fn add_stream_received(
+            &self,
+            _nonce: [u8; 16],
+            _stream_id: u64,
+            amount: u64,
+        ) -> Result<u64, ReceiptStoreError> {
+            Ok(amount)
+        }
+    }
+
     #[derive(Clone)]
     pub struct TestStore {
         pub route: Option<(String, TestAccount)>,
         pub price_1: Option<f64>,
         pub price_2: Option<f64>,
+        pub max_packet_amounts: Arc<Mutex<HashMap<String, u64>>>,
+        pub unreachable_destinations: Arc<Mutex<HashMap<String, UnreachableDestination>>>,
     }
 
     #[async_trait]
@@ -184,6 +224,113 @@ pub mod test_helpers {
             unimplemented!("Cannot get all exchange rates")
         }
     }
+
+    #[async_trait]
+    impl MaxPacketAmountStore for TestStore {
+        async fn get_max_packet_amount(
+            &self,
+            source_asset_code: &str,
+            source_asset_scale: u8,
+            destination_prefix: &str,
+        ) -> Result<Option<u64>, MaxPacketAmountStoreError> {
+            let key = format!(
+                "{}:{}:{}",
+                source_asset_code, source_asset_scale, destination_prefix
+            );
+            Ok(self.max_packet_amounts.lock().get(&key).cloned())
+        }
+
+        async fn set_max_packet_amount(
+            &self,
+            source_asset_code: &str,
+            source_asset_scale: u8,
+            destination_prefix: &str,
+            max_packet_amount: u64,
+            _ttl: u64,
+        ) -> Result<(), MaxPacketAmountStoreError> {
+            let key = format!(
+                "{}:{}:{}",
+                source_asset_code, source_asset_scale, destination_prefix
+            );
+            self.max_packet_amounts
+                .lock()
+                .insert(key, max_packet_amount);
+            Ok(())
+        }
+    }
+
+    #[async_trait]
+    impl UnreachableDestinationStore for TestStore {
+        async fn get_unreachable_destination(
+            &self,
+            destination_prefix: &str,
+        ) -> Result<Option<UnreachableDestination>, UnreachableDestinationStoreError> {
+            Ok(self
+                .unreachable_destinations
+                .lock()
+                .get(destination_prefix)
+                .cloned())
+        }
+
+        async fn record_unreachable_destination(
+            &self,
+            destination_prefix: &str,
+            error: &str,
+            ttl: u64,
+        ) -> Result<(), UnreachableDestinationStoreError> {
+            let mut unreachable_destinations = self.unreachable_destinations.lock();
+            let unreachable = unreachable_destinations
+                .entry(destination_prefix.to_string())
+                .or_insert_with(|| UnreachableDestination {
+                    destination_prefix: destination_prefix.to_string(),
+                    failures: 0,
+                    error: String::new(),
+                    expires_in: ttl,
+                });
+            unreachable.failures += 1;
+            unreachable.error = error.to_string();
+            Ok(())
+        }
+
+        async fn get_unreachable_destinations(
+            &self,
+        ) -> Result<Vec<UnreachableDestination>, UnreachableDestinationStoreError> {
+            Ok(self
+                .unreachable_destinations
+                .lock()
+                .values()
+                .cloned()
+                .collect())
+        }
+
+        async fn clear_unreachable_destinations(
+            &self,
+            destination_prefix: Option<&str>,
+        ) -> Result<usize, UnreachableDestinationStoreError> {
+            let mut unreachable_destinations = self.unreachable_destinations.lock();
+            Ok(match destination_prefix {
+                Some(prefix) => unreachable_destinations.remove(prefix).into_iter().count(),
+                None => unreachable_destinations.drain().count(),
+            })
+        }
+    }
+
+    impl FeePolicyStore for TestStore {
+        fn get_fee_policy(&self, _from: &str, _to: &str) -> Option<FeePolicy> {
+            None
+        }
+
+        fn get_all_fee_policies(&self) -> Result<HashMap<String, FeePolicy>, FeePolicyStoreError> {
+            unimplemented!("Cannot get all fee policies")
+        }
+
+        fn set_fee_policies(
+            &self,
+            _policies: HashMap<String, FeePolicy>,
+        ) -> Result<(), FeePolicyStoreError> {
+            unimplemented!("Cannot set fee policies")
+        }
+    }
 }
 
 #[cfg(test)]
@@ -214,6 +361,8 @@ mod send_money_to_receiver {
             route: Some((destination_address.to_string(), account)),
             price_1: None,
             price_2: None,
+            max_packet_amounts: Default::default(),
+            unreachable_destinations: Default::default(),
         };
         let connection_generator = ConnectionGenerator::new(server_secret.clone());
         let server = StreamReceiverService::new(
@@ -248,6 +397,8 @@ mod send_money_to_receiver {
                 route: None,
                 price_1: None,
                 price_2: None,
+                max_packet_amounts: Default::default(),
+                unreachable_destinations: Default::default(),
             },
             destination_account,
             shared_secret.to_vec(),
@@ -286,6 +437,8 @@ mod send_money_to_receiver {
             route: Some((destination_address.to_string(), recipient_account)),
             price_1: Some(1.0),
             price_2: Some(1.0),
+            max_packet_amounts: Default::default(),
+            unreachable_destinations: Default::default(),
         };
 
         let connection_generator = ConnectionGenerator::new(server_secret.clone());
@@ -326,4 +479,91 @@ mod send_money_to_receiver {
             _ => panic!("Payment should fail fast due to poor exchange rates"),
         }
     }
+
+    /// Delivers the destination amount to a receiver of another asset via a connector taking
+    /// the spread, or fails if the spread exceeds the slippage
+    async fn deliver_through_spread(
+        spread: f64,
+        destination_amount: u64,
+        slippage: f64,
+    ) -> Result<StreamDelivery, Error> {
+        let server_secret = Bytes::from(&[0; 32][..]);
+        let destination_address = Address::from_str("example.receiver").unwrap();
+        let sender_account = TestAccount {
+            id: Uuid::new_v4(),
+            ilp_address: Address::from_str("example.sender").unwrap(),
+            asset_code: "XYZ".to_string(),
+            asset_scale: 6,
+            max_packet_amount: None,
+        };
+        let recipient_account = TestAccount {
+            id: Uuid::new_v4(),
+            ilp_address: destination_address.clone(),
+            asset_code: "ABC".to_string(),
+            asset_scale: 9,
+            max_packet_amount: None,
+        };
+        let store = TestStore {
+            route: Some((destination_address.to_string(), recipient_account)),
+            price_1: Some(1.0),
+            price_2: Some(1.0),
+            max_packet_amounts: Default::default(),
+            unreachable_destinations: Default::default(),
+        };
+        let connection_generator = ConnectionGenerator::new(server_secret.clone());
+        let server = StreamReceiverService::new(
+            server_secret,
+            DummyStore,
+            outgoing_service_fn(|_| {
+                Err(RejectBuilder {
+                    code: ErrorCode::F02_UNREACHABLE,
+                    message: b"No other outgoing handler",
+                    triggered_by: Some(&EXAMPLE_RECEIVER),
+                    data: &[],
+                }
+                .build())
+            }),
+        );
+        let server = ExchangeRateService::new(spread, store.clone(), server);
+        let server = Router::new(store.clone(), server);
+
+        let (destination_account, shared_secret) =
+            connection_generator.generate_address_and_secret(&destination_address);
+        deliver_money(
+            server,
+            &sender_account,
+            store,
+            destination_account,
+            shared_secret.to_vec(),
+            destination_amount,
+            slippage,
+            None,
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn delivers_the_destination_amount() {
+        // The connector converts each unit of the sender into 990 units of the receiver
+        let receipt = deliver_through_spread(0.01, 990_000_000, 0.015)
+            .await
+            .unwrap();
+        assert_eq!(receipt.delivered_amount, 990_000_000);
+        assert_eq!(receipt.sent_amount, 1_000_000);
+        assert_eq!(receipt.destination_asset_code, Some("ABC".to_string()));
+        // It was willing to send up to the destination amount at the minimum rate
+        assert_eq!(receipt.source_amount, 1_005_077);
+    }
+
+    #[tokio::test]
+    async fn delivery_fails_if_large_spread() {
+        // Connector takes 2% spread, but we're only willing to tolerate 1.4%
+        match deliver_through_spread(0.02, 1_000_000, 0.014).await {
+            Err(Error::SendMoneyError(message)) => assert!(message.contains("below the minimum")),
+            result => panic!(
+                "Delivery should fail before sending money, got {:?}",
+                result
+            ),
+        }
+    }
 }
@@ -98,6 +98,10 @@ impl<'a> StreamPacketBuilder<'a> {
                     buffer_unencrypted.put_u8(FrameType::StreamDataBlocked as u8);
                     frame.put_contents(&mut contents);
                 }
+                Frame::StreamReceipt(ref frame) => {
+                    buffer_unencrypted.put_u8(FrameType::StreamReceipt as u8);
+                    frame.put_contents(&mut contents);
+                }
                 Frame::Unknown => continue,
             }
             buffer_unencrypted.put_var_octet_string(contents);
@@ -150,7 +154,7 @@ impl StreamPacket {
     /// # Errors
     /// 1. If the version of Stream Protocol doesn't match the hardcoded [stream version](constant.STREAM_VERSION.html)
     /// 1. If the decrypted bytes cannot be parsed to an unencrypted [Stream Packet](./struct.StreamPacket.html)
-    fn from_bytes_unencrypted(buffer_unencrypted: BytesMut) -> Result<Self, ParseError> {
+    pub fn from_bytes_unencrypted(buffer_unencrypted: BytesMut) -> Result<Self, ParseError> {
         // TODO don't copy the whole packet again
         let mut reader = &buffer_unencrypted[..];
         let version = reader.read_u8()?;
@@ -283,6 +287,9 @@ impl<'a> FrameIterator<'a> {
             FrameType::StreamDataBlocked => {
                 Frame::StreamDataBlocked(StreamDataBlockedFrame::read_contents(&contents)?)
             }
+            FrameType::StreamReceipt => {
+                Frame::StreamReceipt(StreamReceiptFrame::read_contents(contents)?)
+            }
             FrameType::Unknown => {
                 warn!(
                     "Ignoring unknown frame of type {}: {:x?}",
@@ -345,6 +352,7 @@ pub enum Frame<'a> {
     StreamData(StreamDataFrame<'a>),
     StreamMaxData(StreamMaxDataFrame),
     StreamDataBlocked(StreamDataBlockedFrame),
+    StreamReceipt(StreamReceiptFrame<'a>),
     Unknown,
 }
 
@@ -365,6 +373,7 @@ impl<'a> fmt::Debug for Frame<'a> {
             Frame::StreamData(frame) => write!(f, "{:?}", frame),
             Frame::StreamMaxData(frame) => write!(f, "{:?}", frame),
             Frame::StreamDataBlocked(frame) => write!(f, "{:?}", frame),
+            Frame::StreamReceipt(frame) => write!(f, "{:?}", frame),
             Frame::Unknown => write!(f, "UnknownFrame"),
         }
     }
@@ -388,6 +397,7 @@ pub enum FrameType {
     StreamData = 0x14,
     StreamMaxData = 0x15,
     StreamDataBlocked = 0x16,
+    StreamReceipt = 0x17,
     Unknown,
 }
 
@@ -408,6 +418,7 @@ impl From<u8> for FrameType {
             0x14 => FrameType::StreamData,
             0x15 => FrameType::StreamMaxData,
             0x16 => FrameType::StreamDataBlocked,
+            0x17 => FrameType::StreamReceipt,
             _ => FrameType::Unknown,
         }
     }
@@ -850,6 +861,31 @@ impl<'a> SerializableFrame<'a> for StreamDataBlockedFrame {
     }
 }
 
+/// Receipt proving the total amount received on a stream, which the receiver signed with
+/// the receipt secret of the connection
+/// ([STREAM Receipts](https://interledger.org/rfcs/0039-stream-receipts/))
+#[derive(Debug, PartialEq, Clone)]
+pub struct StreamReceiptFrame<'a> {
+    /// Identifier of the stream this frame refers to.
+    pub stream_id: u64,
+    /// The serialized receipt
+    pub receipt: &'a [u8],
+}
+
+impl<'a> SerializableFrame<'a> for StreamReceiptFrame<'a> {
+    fn read_contents(mut reader: &'a [u8]) -> Result<Self, ParseError> {
+        let stream_id = reader.read_var_uint()?;
+        let receipt = reader.read_var_octet_string()?;
+
+        Ok(StreamReceiptFrame { stream_id, receipt })
+    }
+
+    fn put_contents(&self, buf: &mut impl MutBufOerExt) {
+        buf.put_var_uint(self.stream_id);
+        buf.put_var_octet_string(self.receipt);
+    }
+}
+
 /// See: https://github.com/interledger/rfcs/blob/master/0029-stream/0029-stream.md#514-maximum-varuint-size
 fn saturating_read_var_uint<'a>(reader: &mut impl BufOerExt<'a>) -> Result<u64, ParseError> {
     if reader.peek_var_octet_string()?.len() > 8 {
@@ -997,4 +1033,422 @@ mod serialization {
         let frame = StreamMoneyBlockedFrame::read_contents(&buffer).unwrap();
         assert_eq!(frame.send_max, u64::MAX);
     }
+
+    #[test]
+    fn it_serializes_receipt_frames() {
+        let receipt = [7; 58];
+        let packet = StreamPacketBuilder {
+            sequence: 1,
+            ilp_packet_type: IlpPacketType::Fulfill,
+            prepare_amount: 10,
+            frames: &[Frame::StreamReceipt(StreamReceiptFrame {
+                stream_id: 1,
+                receipt: &receipt[..],
+            })],
+        }
+        .build();
+        let packet = StreamPacket::from_bytes_unencrypted(packet.buffer_unencrypted).unwrap();
+        assert_eq!(
+            packet.frames().next().unwrap(),
+            Frame::StreamReceipt(StreamReceiptFrame {
+                stream_id: 1,
+                receipt: &receipt[..],
+            })
+        );
+    }
+}
+
+/// Round-trips the STREAM packets of the shared test vectors (see `test-vectors/README.md`
+/// at the root of the repository). Set `UPDATE_TEST_VECTORS=1` to regenerate the buffers
+/// of the vectors from their fields instead of checking them.
+#[cfg(test)]
+mod vectors {
+    use super::*;
+    use serde_json::{json, Value};
+    use std::{env, fs, str::FromStr};
+
+    const VECTORS: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../../test-vectors/stream-packets.json"
+    );
+
+    #[test]
+    fn stream_packets() {
+        let mut cases: Vec<Value> =
+            serde_json::from_str(&fs::read_to_string(VECTORS).unwrap()).unwrap();
+        let update = env::var_os("UPDATE_TEST_VECTORS").is_some();
+
+        for case in cases.iter_mut() {
+            let name = case["name"].as_str().unwrap().to_string();
+            let packet = &case["packet"];
+            let frames: Vec<Frame> = packet["frames"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(frame_from_json)
+                .collect();
+            let encoded = hex::encode(
+                StreamPacketBuilder {
+                    sequence: number(&packet["sequence"]),
+                    ilp_packet_type: IlpPacketType::try_from(
+                        packet["packetType"].as_u64().unwrap() as u8,
+                    )
+                    .unwrap(),
+                    prepare_amount: number(&packet["amount"]),
+                    frames: &frames,
+                }
+                .build()
+                .buffer_unencrypted,
+            );
+            if update {
+                case["buffer"] = json!(encoded);
+                continue;
+            }
+
+            assert_eq!(encoded, case["buffer"], "encoding of {}", name);
+            let buffer = hex::decode(case["buffer"].as_str().unwrap()).unwrap();
+            let decoded = StreamPacket::from_bytes_unencrypted(BytesMut::from(&buffer[..]))
+                .unwrap_or_else(|err| panic!("decoding of {}: {:?}", name, err));
+            let decoded = json!({
+                "sequence": decoded.sequence().to_string(),
+                "packetType": decoded.ilp_packet_type() as u8,
+                "amount": decoded.prepare_amount().to_string(),
+                "frames": decoded.frames().map(|frame| frame_to_json(&frame)).collect::<Vec<_>>(),
+            });
+            assert_eq!(decoded, case["packet"], "decoding of {}", name);
+        }
+
+        if update {
+            let mut json = serde_json::to_string_pretty(&cases).unwrap();
+            json.push('\n');
+            fs::write(VECTORS, json).unwrap();
+        }
+    }
+
+    /// Reads the numbers which are encoded as strings, since they may not fit in a double
+    fn number(value: &Value) -> u64 {
+        value.as_str().unwrap().parse().unwrap()
+    }
+
+    fn frame_from_json(frame: &Value) -> Frame<'_> {
+        let text = |field: &str| frame[field].as_str().unwrap();
+        let stream_id = || number(&frame["streamId"]);
+        let max_offset = || number(&frame["maxOffset"]);
+        let max_stream_id = || number(&frame["maxStreamId"]);
+        let code = || ErrorCode::from(frame["errorCode"].as_u64().unwrap() as u8);
+        // The frames borrow their bytes, which only live as long as the test
+        let bytes = |field: &str| -> &'static [u8] {
+            Box::leak(hex::decode(text(field)).unwrap().into_boxed_slice())
+        };
+        match text("type") {
+            "ConnectionClose" => Frame::ConnectionClose(ConnectionCloseFrame {
+                code: code(),
+                message: text("errorMessage"),
+            }),
+            "ConnectionNewAddress" => Frame::ConnectionNewAddress(ConnectionNewAddressFrame {
+                source_account: Address::from_str(text("sourceAccount")).unwrap(),
+            }),
+            "ConnectionAssetDetails" => {
+                Frame::ConnectionAssetDetails(ConnectionAssetDetailsFrame {
+                    source_asset_code: text("sourceAssetCode"),
+                    source_asset_scale: frame["sourceAssetScale"].as_u64().unwrap() as u8,
+                })
+            }
+            "ConnectionMaxData" => Frame::ConnectionMaxData(ConnectionMaxDataFrame {
+                max_offset: max_offset(),
+            }),
+            "ConnectionDataBlocked" => Frame::ConnectionDataBlocked(ConnectionDataBlockedFrame {
+                max_offset: max_offset(),
+            }),
+            "ConnectionMaxStreamId" => Frame::ConnectionMaxStreamId(ConnectionMaxStreamIdFrame {
+                max_stream_id: max_stream_id(),
+            }),
+            "ConnectionStreamIdBlocked" => {
+                Frame::ConnectionStreamIdBlocked(ConnectionStreamIdBlockedFrame {
+                    max_stream_id: max_stream_id(),
+                })
+            }
+            "StreamClose" => Frame::StreamClose(StreamCloseFrame {
+                stream_id: stream_id(),
+                code: code(),
+                message: text("errorMessage"),
+            }),
+            "StreamMoney" => Frame::StreamMoney(StreamMoneyFrame {
+                stream_id: stream_id(),
+                shares: number(&frame["shares"]),
+            }),
+            "StreamMaxMoney" => Frame::StreamMaxMoney(StreamMaxMoneyFrame {
+                stream_id: stream_id(),
+                receive_max: number(&frame["receiveMax"]),
+                total_received: number(&frame["totalReceived"]),
+            }),
+            "StreamMoneyBlocked" => Frame::StreamMoneyBlocked(StreamMoneyBlockedFrame {
+                stream_id: stream_id(),
+                send_max: number(&frame["sendMax"]),
+                total_sent: number(&frame["totalSent"]),
+            }),
+            "StreamData" => Frame::StreamData(StreamDataFrame {
+                stream_id: stream_id(),
+                offset: number(&frame["offset"]),
+                data: bytes("data"),
+            }),
+            "StreamMaxData" => Frame::StreamMaxData(StreamMaxDataFrame {
+                stream_id: stream_id(),
+                max_offset: max_offset(),
+            }),
+            "StreamDataBlocked" => Frame::StreamDataBlocked(StreamDataBlockedFrame {
+                stream_id: stream_id(),
+                max_offset: max_offset(),
+            }),
+            "StreamReceipt" => Frame::StreamReceipt(StreamReceiptFrame {
+                stream_id: stream_id(),
+                receipt: bytes("receipt"),
+            }),
+            other => panic!("unknown frame type {}", other),
+        }
+    }
+
+    fn frame_to_json(frame: &Frame) -> Value {
+        match frame {
+            Frame::ConnectionClose(frame) => json!({
+                "type": "ConnectionClose",
+                "errorCode": frame.code as u8,
+                "errorMessage": frame.message,
+            }),
+            Frame::ConnectionNewAddress(frame) => json!({
+                "type": "ConnectionNewAddress",
+                "sourceAccount": frame.source_account.to_string(),
+            }),
+            Frame::ConnectionAssetDetails(frame) => json!({
+                "type": "ConnectionAssetDetails",
+                "sourceAssetCode": frame.source_asset_code,
+                "sourceAssetScale": frame.source_asset_scale,
+            }),
+            Frame::ConnectionMaxData(frame) => json!({
+                "type": "ConnectionMaxData",
+                "maxOffset": frame.max_offset.to_string(),
+            }),
+            Frame::ConnectionDataBlocked(frame) => json!({
+                "type": "ConnectionDataBlocked",
+                "maxOffset": frame.max_offset.to_string(),
+            }),
+            Frame::ConnectionMaxStreamId(frame) => json!({
+                "type": "ConnectionMaxStreamId",
+                "maxStreamId": frame.max_stream_id.to_string(),
+            }),
+            Frame::ConnectionStreamIdBlocked(frame) => json!({
+                "type": "ConnectionStreamIdBlocked",
+                "maxStreamId": frame.max_stream_id.to_string(),
+            }),
+            Frame::StreamClose(frame) => json!({
+                "type": "StreamClose",
+                "streamId": frame.stream_id.to_string(),
+                "errorCode": frame.code as u8,
+                "errorMessage": frame.message,
+            }),
+            Frame::StreamMoney(frame) => json!({
+                "type": "StreamMoney",
+                "streamId": frame.stream_id.to_string(),
+                "shares": frame.shares.to_string(),
+            }),
+            Frame::StreamMaxMoney(frame) => json!({
+                "type": "StreamMaxMoney",
+                "streamId": frame.stream_id.to_string(),
+                "receiveMax": frame.receive_max.to_string(),
+                "totalReceived": frame.total_received.to_string(),
+            }),
+            Frame::StreamMoneyBlocked(frame) => json!({
+                "type": "StreamMoneyBlocked",
+                "streamId": frame.stream_id.to_string(),
+                "sendMax": frame.send_max.to_string(),
+                "totalSent": frame.total_sent.to_string(),
+            }),
+            Frame::StreamData(frame) => json!({
+                "type": "StreamData",
+                "streamId": frame.stream_id.to_string(),
+                "offset": frame.offset.to_string(),
+                "data": hex::encode(frame.data),
+            }),
+            Frame::StreamMaxData(frame) => json!({
+                "type": "StreamMaxData",
+                "streamId": frame.stream_id.to_string(),
+                "maxOffset": frame.max_offset.to_string(),
+            }),
+            Frame::StreamDataBlocked(frame) => json!({
+                "type": "StreamDataBlocked",
+                "streamId": frame.stream_id.to_string(),
+                "maxOffset": frame.max_offset.to_string(),
+            }),
+            Frame::StreamReceipt(frame) => json!({
+                "type": "StreamReceipt",
+                "streamId": frame.stream_id.to_string(),
+                "receipt": hex::encode(frame.receipt),
+            }),
+            Frame::Unknown => json!({ "type": "Unknown" }),
+        }
+    }
+}
+
+/// Random packets round-trip, and mutations of them are parsed or rejected without
+/// panicking (see the `stream_packet` fuzz target for a deeper search)
+#[cfg(test)]
+mod properties {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    const CASES: usize = 2000;
+
+    fn bytes(rng: &mut StdRng, max_len: usize) -> Vec<u8> {
+        let len = rng.gen_range(0, max_len + 1);
+        (0..len).map(|_| rng.gen()).collect()
+    }
+
+    fn text(rng: &mut StdRng, max_len: usize) -> String {
+        let len = rng.gen_range(0, max_len + 1);
+        (0..len)
+            .map(|_| rng.gen_range(b' ', b'~') as char)
+            .collect()
+    }
+
+    /// The variable length fields of the frames, which the frames borrow
+    struct Contents {
+        text: String,
+        data: Vec<u8>,
+    }
+
+    fn frame<'a>(rng: &mut StdRng, contents: &'a Contents) -> Frame<'a> {
+        let code = ErrorCode::from(rng.gen_range(1, 10));
+        match rng.gen_range(0, 15) {
+            0 => Frame::ConnectionClose(ConnectionCloseFrame {
+                code,
+                message: &contents.text,
+            }),
+            1 => Frame::ConnectionNewAddress(ConnectionNewAddressFrame {
+                source_account: Address::try_from(&b"example.sender"[..]).unwrap(),
+            }),
+            2 => Frame::ConnectionAssetDetails(ConnectionAssetDetailsFrame {
+                source_asset_code: &contents.text,
+                source_asset_scale: rng.gen(),
+            }),
+            3 => Frame::ConnectionMaxData(ConnectionMaxDataFrame {
+                max_offset: rng.gen(),
+            }),
+            4 => Frame::ConnectionDataBlocked(ConnectionDataBlockedFrame {
+                max_offset: rng.gen(),
+            }),
+            5 => Frame::ConnectionMaxStreamId(ConnectionMaxStreamIdFrame {
+                max_stream_id: rng.gen(),
+            }),
+            6 => Frame::ConnectionStreamIdBlocked(ConnectionStreamIdBlockedFrame {
+                max_stream_id: rng.gen(),
+            }),
+            7 => Frame::StreamClose(StreamCloseFrame {
+                stream_id: rng.gen(),
+                code,
+                message: &contents.text,
+            }),
+            8 => Frame::StreamMoney(StreamMoneyFrame {
+                stream_id: rng.gen(),
+                shares: rng.gen(),
+            }),
+            9 => Frame::StreamMaxMoney(StreamMaxMoneyFrame {
+                stream_id: rng.gen(),
+                receive_max: rng.gen(),
+                total_received: rng.gen(),
+            }),
+            10 => Frame::StreamMoneyBlocked(StreamMoneyBlockedFrame {
+                stream_id: rng.gen(),
+                send_max: rng.gen(),
+                total_sent: rng.gen(),
+            }),
+            11 => Frame::StreamData(StreamDataFrame {
+                stream_id: rng.gen(),
+                offset: rng.gen(),
+                data: &contents.data,
+            }),
+            12 => Frame::StreamMaxData(StreamMaxDataFrame {
+                stream_id: rng.gen(),
+                max_offset: rng.gen(),
+            }),
+            13 => Frame::StreamDataBlocked(StreamDataBlockedFrame {
+                stream_id: rng.gen(),
+                max_offset: rng.gen(),
+            }),
+            _ => Frame::StreamReceipt(StreamReceiptFrame {
+                stream_id: rng.gen(),
+                receipt: &contents.data,
+            }),
+        }
+    }
+
+    fn packet(rng: &mut StdRng) -> (StreamPacket, Vec<String>) {
+        let contents: Vec<Contents> = (0..rng.gen_range(0, 6))
+            .map(|_| Contents {
+                text: text(rng, 40),
+                // Longer than 127 bytes sometimes, for the lengths with a prefix
+                data: bytes(rng, 300),
+            })
+            .collect();
+        let frames: Vec<Frame> = contents.iter().map(|c| frame(rng, c)).collect();
+        let packet = StreamPacketBuilder {
+            sequence: rng.gen(),
+            ilp_packet_type: IlpPacketType::try_from(rng.gen_range(12, 15)).unwrap(),
+            prepare_amount: rng.gen(),
+            frames: &frames,
+        }
+        .build();
+        let expected = frames.iter().map(|frame| format!("{:?}", frame)).collect();
+        (packet, expected)
+    }
+
+    fn mutate(rng: &mut StdRng, buffer: &[u8]) -> Vec<u8> {
+        let mut buffer = buffer.to_vec();
+        for _ in 0..rng.gen_range(1, 4) {
+            let index = rng.gen_range(0, buffer.len() + 1);
+            match rng.gen_range(0, 5) {
+                0 if index < buffer.len() => buffer[index] ^= 1 << rng.gen_range(0, 8),
+                1 if index < buffer.len() => {
+                    buffer[index] = [0x00, 0x7f, 0x80, 0x81, 0x88, 0x89, 0xff][rng.gen_range(0, 7)]
+                }
+                2 => {
+                    let inserted = bytes(rng, 16);
+                    buffer.splice(index..index, inserted);
+                }
+                3 if index < buffer.len() => {
+                    buffer.remove(index);
+                }
+                _ => buffer.truncate(index),
+            }
+        }
+        buffer
+    }
+
+    #[test]
+    fn packets_round_trip() {
+        let mut rng = StdRng::seed_from_u64(0x5743);
+        for _ in 0..CASES {
+            let (packet, frames) = packet(&mut rng);
+            let parsed =
+                StreamPacket::from_bytes_unencrypted(packet.buffer_unencrypted.clone()).unwrap();
+            assert_eq!(parsed, packet);
+            let parsed_frames: Vec<String> = parsed
+                .frames()
+                .map(|frame| format!("{:?}", frame))
+                .collect();
+            assert_eq!(parsed_frames, frames);
+        }
+    }
+
+    #[test]
+    fn mutated_packets_do_not_panic() {
+        let mut rng = StdRng::seed_from_u64(0x5743);
+        for _ in 0..CASES {
+            let (packet, _) = packet(&mut rng);
+            let mutated = mutate(&mut rng, &packet.buffer_unencrypted);
+            if let Ok(packet) = StreamPacket::from_bytes_unencrypted(BytesMut::from(mutated)) {
+                let _ = (packet.sequence(), packet.ilp_packet_type());
+                let _ = (packet.prepare_amount(), format!("{:?}", packet));
+            }
+        }
+    }
 }
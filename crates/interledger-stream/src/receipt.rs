@@ -0,0 +1,203 @@
+use super::crypto::hmac_sha256;
+use async_trait::async_trait;
+use byteorder::{BigEndian, ReadBytesExt};
+use bytes::{BufMut, Bytes};
+use interledger_errors::ReceiptStoreError;
+use interledger_packet::{
+    oer::{BufOerExt, MutBufOerExt},
+    Address,
+};
+use ring::constant_time::verify_slices_are_equal;
+
+/// The version of the [STREAM Receipts](https://interledger.org/rfcs/0039-stream-receipts/)
+const RECEIPT_VERSION: u8 = 1;
+const RECEIPT_NONCE_GENERATOR: &[u8] = b"ilp_stream_receipt_nonce";
+const RECEIPT_SECRET_GENERATOR: &[u8] = b"ilp_stream_receipt_secret";
+const HMAC_LENGTH: usize = 32;
+
+/// A [STREAM Receipt](https://interledger.org/rfcs/0039-stream-receipts/), which proves
+/// the total amount the receiver received on a stream of a connection
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Receipt {
+    /// The nonce of the connection, which tells its receipts apart from the receipts of
+    /// other connections
+    pub nonce: [u8; 16],
+    /// Identifier of the stream the receipt refers to
+    pub stream_id: u64,
+    /// Total amount received on the stream, denominated in the units of the receiver
+    pub total_received: u64,
+}
+
+/// Errors of the receipts which cannot be verified
+#[derive(Debug, PartialEq, thiserror::Error)]
+pub enum ReceiptError {
+    #[error("receipt is malformed")]
+    Malformed,
+    #[error("unsupported receipt version: {0}")]
+    UnsupportedVersion(u8),
+    #[error("receipt signature is invalid")]
+    InvalidSignature,
+}
+
+impl Receipt {
+    /// Serializes the receipt and appends its HMAC with the receipt secret
+    pub fn sign(&self, secret: &[u8]) -> Bytes {
+        let mut buffer = Vec::with_capacity(27 + HMAC_LENGTH);
+        buffer.put_u8(RECEIPT_VERSION);
+        buffer.put_slice(&self.nonce[..]);
+        buffer.put_var_uint(self.stream_id);
+        buffer.put_u64_be(self.total_received);
+        let hmac = hmac_sha256(secret, &buffer);
+        buffer.put_slice(&hmac[..]);
+        Bytes::from(buffer)
+    }
+
+    /// Parses the receipt, without verifying its HMAC
+    pub fn decode(mut data: &[u8]) -> Result<Self, ReceiptError> {
+        let version = data.read_u8().map_err(|_| ReceiptError::Malformed)?;
+        if version != RECEIPT_VERSION {
+            return Err(ReceiptError::UnsupportedVersion(version));
+        }
+        if data.len() < 16 {
+            return Err(ReceiptError::Malformed);
+        }
+        let mut nonce = [0; 16];
+        nonce.copy_from_slice(&data[..16]);
+        data = &data[16..];
+        let stream_id = data.read_var_uint().map_err(|_| ReceiptError::Malformed)?;
+        let total_received = data
+            .read_u64::<BigEndian>()
+            .map_err(|_| ReceiptError::Malformed)?;
+        if data.len() != HMAC_LENGTH {
+            return Err(ReceiptError::Malformed);
+        }
+        Ok(Receipt {
+            nonce,
+            stream_id,
+            total_received,
+        })
+    }
+
+    /// Parses the receipt and checks that it was signed with the receipt secret
+    pub fn verify(data: &[u8], secret: &[u8]) -> Result<Self, ReceiptError> {
+        let receipt = Receipt::decode(data)?;
+        let (message, hmac) = data.split_at(data.len() - HMAC_LENGTH);
+        verify_slices_are_equal(&hmac_sha256(secret, message)[..], hmac)
+            .map_err(|_| ReceiptError::InvalidSignature)?;
+        Ok(receipt)
+    }
+}
+
+/// Generator of the receipt nonces and secrets of the connections of a receiver,
+/// based on a single root secret.
+///
+/// Both are derived from the connection token of the `destination_account`, so the
+/// receiver can issue receipts and the node can verify them without saving any secret.
+#[derive(Clone)]
+pub struct ReceiptGenerator {
+    nonce_generator: Bytes,
+    secret_generator: Bytes,
+}
+
+impl ReceiptGenerator {
+    pub fn new(server_secret: &[u8]) -> Self {
+        assert_eq!(server_secret.len(), 32, "Server secret must be 32 bytes");
+        ReceiptGenerator {
+            nonce_generator: Bytes::from(&hmac_sha256(server_secret, RECEIPT_NONCE_GENERATOR)[..]),
+            secret_generator: Bytes::from(
+                &hmac_sha256(server_secret, RECEIPT_SECRET_GENERATOR)[..],
+            ),
+        }
+    }
+
+    /// Returns the receipt nonce of the connection of the `destination_account`
+    pub fn nonce(&self, destination_account: &Address) -> [u8; 16] {
+        let token = destination_account.segments().next_back().unwrap();
+        let mut nonce = [0; 16];
+        nonce.copy_from_slice(&hmac_sha256(&self.nonce_generator[..], token.as_bytes())[..16]);
+        nonce
+    }
+
+    /// Returns the secret the receipts of the connection with the nonce are signed with
+    pub fn secret(&self, nonce: &[u8; 16]) -> [u8; 32] {
+        hmac_sha256(&self.secret_generator[..], &nonce[..])
+    }
+
+    /// Returns a signed receipt of the total amount received on the stream
+    pub fn issue(&self, nonce: [u8; 16], stream_id: u64, total_received: u64) -> Bytes {
+        let secret = self.secret(&nonce);
+        Receipt {
+            nonce,
+            stream_id,
+            total_received,
+        }
+        .sign(&secret[..])
+    }
+
+    /// Parses the receipt and checks that it was issued by this receiver
+    pub fn verify(&self, data: &[u8]) -> Result<Receipt, ReceiptError> {
+        let receipt = Receipt::decode(data)?;
+        Receipt::verify(data, &self.secret(&receipt.nonce)[..])
+    }
+}
+
+/// Store trait which keeps the totals received on the streams of the connections
+/// the receiver issues receipts for
+#[async_trait]
+pub trait StreamReceiptStore {
+    /// Adds the amount to the total received on the stream of the connection with the
+    /// receipt nonce and returns the new total
+    async fn add_stream_received(
+        &self,
+        nonce: [u8; 16],
+        stream_id: u64,
+        amount: u64,
+    ) -> Result<u64, ReceiptStoreError>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn issues_and_verifies_receipts() {
+        let generator = ReceiptGenerator::new(&[1; 32]);
+        let destination = Address::from_str("example.alice.donations.token").unwrap();
+        let nonce = generator.nonce(&destination);
+        assert_eq!(nonce, generator.nonce(&destination));
+
+        let receipt = generator.issue(nonce, 1, 500);
+        assert_eq!(
+            generator.verify(&receipt).unwrap(),
+            Receipt {
+                nonce,
+                stream_id: 1,
+                total_received: 500,
+            }
+        );
+
+        // receipts of other receivers and tampered receipts are rejected
+        let other = ReceiptGenerator::new(&[2; 32]);
+        assert_eq!(
+            other.verify(&receipt).unwrap_err(),
+            ReceiptError::InvalidSignature
+        );
+        let mut tampered = receipt.to_vec();
+        tampered[20] ^= 1;
+        assert_eq!(
+            generator.verify(&tampered).unwrap_err(),
+            ReceiptError::InvalidSignature
+        );
+        assert_eq!(
+            generator.verify(&receipt[..30]).unwrap_err(),
+            ReceiptError::Malformed
+        );
+        let mut unsupported = receipt.to_vec();
+        unsupported[0] = 2;
+        assert_eq!(
+            generator.verify(&unsupported).unwrap_err(),
+            ReceiptError::UnsupportedVersion(2)
+        );
+    }
+}
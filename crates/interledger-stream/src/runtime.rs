@@ -0,0 +1,71 @@
+use super::error::Error;
+use futures::future::BoxFuture;
+use std::time::{Duration, SystemTime};
+#[cfg(not(target_arch = "wasm32"))]
+use tracing::error;
+
+/// The clocks, timers and tasks of the environment the [stream client](./fn.send_money_with_runtime.html)
+/// runs in, so that payments can be sent from environments without tokio, such as browsers
+pub trait Runtime: Clone + Send + Sync + 'static {
+    /// Returns the time elapsed on a monotonic clock since an arbitrary point
+    fn now(&self) -> Duration;
+
+    /// Returns the current time, from which the expiries of the packets are set
+    fn system_time(&self) -> SystemTime;
+
+    /// Returns a future which completes after the duration
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()>;
+
+    /// Runs the sending of a packet in the background, and returns a future of its result.
+    /// The packets of a payment are sent concurrently, so runtimes with several threads may
+    /// send them in parallel.
+    fn spawn(
+        &self,
+        future: BoxFuture<'static, Result<(), Error>>,
+    ) -> BoxFuture<'static, Result<(), Error>>;
+}
+
+/// The runtime of the payments sent from a tokio runtime
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Clone, Debug)]
+pub struct TokioRuntime {
+    start: std::time::Instant,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Default for TokioRuntime {
+    fn default() -> Self {
+        TokioRuntime {
+            start: std::time::Instant::now(),
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Runtime for TokioRuntime {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    fn system_time(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        Box::pin(tokio::time::delay_for(duration))
+    }
+
+    fn spawn(
+        &self,
+        future: BoxFuture<'static, Result<(), Error>>,
+    ) -> BoxFuture<'static, Result<(), Error>> {
+        let handle = tokio::spawn(future);
+        Box::pin(async move {
+            // A packet whose task failed is left out of the payment, like a packet still in flight
+            handle.await.unwrap_or_else(|err| {
+                error!("Error sending packet: {}", err);
+                Ok(())
+            })
+        })
+    }
+}
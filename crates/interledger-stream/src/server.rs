@@ -1,5 +1,7 @@
+use super::connections::StreamConnections;
 use super::crypto::*;
-use super::packet::*;
+use super::packet::{ErrorCode as StreamErrorCode, *};
+use super::receipt::{ReceiptGenerator, StreamReceiptStore};
 use async_trait::async_trait;
 use bytes::{Bytes, BytesMut};
 use chrono::{DateTime, Utc};
@@ -13,7 +15,7 @@ use serde::{Deserialize, Serialize};
 use std::marker::PhantomData;
 use std::time::SystemTime;
 use tokio::sync::broadcast;
-use tracing::debug;
+use tracing::{debug, error};
 use uuid::Uuid;
 
 // Note we are using the same magic bytes as the Javascript
@@ -62,6 +64,20 @@ impl ConnectionGenerator {
         (destination_account, shared_secret)
     }
 
+    /// Returns the generator of the connections of a receiver hosted under a destination tag.
+    ///
+    /// The tag is a segment between the base address and the connection token (the
+    /// `destination_account`s of the tagged receiver are `<base address>.<tag>.<token>`),
+    /// and selects a secret derived from the server secret, so that each tagged receiver
+    /// has its own shared secrets.
+    pub fn with_tag(&self, tag: &str) -> Self {
+        ConnectionGenerator {
+            secret_generator: Bytes::from(
+                &hmac_sha256(&self.secret_generator[..], tag.as_bytes())[..],
+            ),
+        }
+    }
+
     /// Rederive the `shared_secret` from a `destination_account`.
     ///
     /// Although it is not strictly necessary, this uses the same logic as the Javascript
@@ -97,6 +113,9 @@ pub struct PaymentNotification {
     pub amount: u64,
     /// The time this payment notification was fired in RFC3339 format
     pub timestamp: String,
+    /// The memo the sender attached to the payment, if there is one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
 }
 
 /// A trait representing the Publish side of a pub/sub store
@@ -127,6 +146,9 @@ pub trait StreamNotificationsStore {
 #[derive(Clone)]
 pub struct StreamReceiverService<S, O: OutgoingService<A>, A: Account> {
     connection_generator: ConnectionGenerator,
+    receipt_generator: ReceiptGenerator,
+    issue_receipts: bool,
+    connections: Option<StreamConnections>,
     next: O,
     account_type: PhantomData<A>,
     store: S,
@@ -139,20 +161,43 @@ where
     A: Account,
 {
     pub fn new(server_secret: Bytes, store: S, next: O) -> Self {
+        let receipt_generator = ReceiptGenerator::new(&server_secret[..]);
         let connection_generator = ConnectionGenerator::new(server_secret);
         StreamReceiverService {
             connection_generator,
+            receipt_generator,
+            issue_receipts: false,
+            connections: None,
             next,
             account_type: PhantomData,
             store,
         }
     }
+
+    /// Makes the service include a [STREAM Receipt](https://interledger.org/rfcs/0039-stream-receipts/)
+    /// of the total received on each stream in the packets it fulfills.
+    ///
+    /// The totals are kept in the store, and the receipts are signed with secrets derived from
+    /// the server secret, so they can be verified with a [`ReceiptGenerator`](./struct.ReceiptGenerator.html)
+    /// created from the same secret.
+    pub fn with_receipts(mut self) -> Self {
+        self.issue_receipts = true;
+        self
+    }
+
+    /// Makes the service keep the statistics of the incoming connections of the accounts in
+    /// the [`StreamConnections`](./struct.StreamConnections.html), and refuse the packets of
+    /// the connections closed through them.
+    pub fn with_connections(mut self, connections: StreamConnections) -> Self {
+        self.connections = Some(connections);
+        self
+    }
 }
 
 #[async_trait]
 impl<S, O, A> OutgoingService<A> for StreamReceiverService<S, O, A>
 where
-    S: StreamNotificationsStore + Send + Sync + 'static + Clone,
+    S: StreamNotificationsStore + StreamReceiptStore + Send + Sync + 'static + Clone,
     O: OutgoingService<A> + Send + Sync + Clone,
     A: Account + Send + Sync + Clone,
 {
@@ -169,36 +214,73 @@ where
 
         // The case where the request is bound for this server
         if dest.starts_with(to_address.as_ref()) {
-            if let Ok(shared_secret) = self.connection_generator.rederive_secret(&destination) {
-                let response = receive_money(
-                    &shared_secret,
-                    &to_address,
-                    request.to.asset_code(),
-                    request.to.asset_scale(),
-                    &request.prepare,
-                );
-                match response {
-                    Ok(ref _fulfill) => {
-                        self.store
-                            .publish_payment_notification(PaymentNotification {
-                                to_username,
-                                from_username,
-                                amount,
-                                destination,
-                                timestamp: DateTime::<Utc>::from(SystemTime::now()).to_rfc3339(),
-                            })
-                    }
-                    Err(ref reject) => {
-                        if reject.code() == ErrorCode::F06_UNEXPECTED_PAYMENT {
-                            // Assume the packet isn't for us if the decryption step fails.
-                            // Note this means that if the packet data is modified in any way,
-                            // the sender will likely see an error like F02: Unavailable (this is
-                            // a bit confusing but the packet data should not be modified at all
-                            // under normal circumstances).
-                            return self.next.send_request(request).await;
-                        }
+            let connection_generator = match destination_tag(&dest[to_address.len()..]) {
+                Some(tag) => self.connection_generator.with_tag(tag),
+                None => self.connection_generator.clone(),
+            };
+            if let Ok(shared_secret) = connection_generator.rederive_secret(&destination) {
+                let stream_packet =
+                    match decrypt_stream_packet(&shared_secret, to_address, &request.prepare) {
+                        Ok(stream_packet) => stream_packet,
+                        // Assume the packet isn't for us if the decryption step fails.
+                        // Note this means that if the packet data is modified in any way,
+                        // the sender will likely see an error like F02: Unavailable (this is
+                        // a bit confusing but the packet data should not be modified at all
+                        // under normal circumstances).
+                        Err(_) => return self.next.send_request(request).await,
+                    };
+                if let Some(ref connections) = self.connections {
+                    if connections.is_closed(&destination) {
+                        debug!("Rejecting packet for closed connection {}", destination);
+                        return Err(close_connection(
+                            &shared_secret,
+                            to_address,
+                            &request.prepare,
+                            &stream_packet,
+                        ));
                     }
+                }
+                let response = if self.issue_receipts {
+                    self.receive_money_with_receipts(
+                        &shared_secret,
+                        to_address,
+                        request.to.asset_code(),
+                        request.to.asset_scale(),
+                        &request.prepare,
+                        &stream_packet,
+                    )
+                    .await
+                } else {
+                    respond_to_stream_packet(
+                        &shared_secret,
+                        to_address,
+                        request.to.asset_code(),
+                        request.to.asset_scale(),
+                        &request.prepare,
+                        &stream_packet,
+                        &[],
+                    )
                 };
+                if let Some(ref connections) = self.connections {
+                    connections.record(
+                        request.to.id(),
+                        &destination,
+                        &from_username,
+                        connection_source(&stream_packet),
+                        response.as_ref().ok().map(|_| amount),
+                    );
+                }
+                if response.is_ok() {
+                    self.store
+                        .publish_payment_notification(PaymentNotification {
+                            to_username,
+                            from_username,
+                            amount,
+                            destination,
+                            timestamp: DateTime::<Utc>::from(SystemTime::now()).to_rfc3339(),
+                            memo: stream_memo(&stream_packet),
+                        })
+                }
                 return response;
             }
         }
@@ -206,26 +288,89 @@ where
     }
 }
 
-// TODO send asset code and scale back to sender also
-#[allow(clippy::cognitive_complexity)]
-fn receive_money(
+impl<S, O, A> StreamReceiverService<S, O, A>
+where
+    S: StreamReceiptStore,
+    O: OutgoingService<A>,
+    A: Account,
+{
+    /// Responds to the packet, but adds the amounts of the streams to their totals
+    /// and signs receipts of the new totals if the packet is fulfilled
+    async fn receive_money_with_receipts(
+        &self,
+        shared_secret: &[u8; 32],
+        ilp_address: &Address,
+        asset_code: &str,
+        asset_scale: u8,
+        prepare: &Prepare,
+        stream_packet: &StreamPacket,
+    ) -> Result<Fulfill, Reject> {
+        let mut receipts = Vec::new();
+        if is_fulfillable(shared_secret, prepare, stream_packet) {
+            let nonce = self.receipt_generator.nonce(&prepare.destination());
+            for (stream_id, amount) in stream_amounts(prepare.amount(), stream_packet) {
+                match self
+                    .store
+                    .add_stream_received(nonce, stream_id, amount)
+                    .await
+                {
+                    Ok(total_received) => receipts.push((
+                        stream_id,
+                        self.receipt_generator
+                            .issue(nonce, stream_id, total_received),
+                    )),
+                    // The money is received anyway, only the receipt is left out
+                    Err(err) => error!("Error adding amount received on stream: {}", err),
+                }
+            }
+        }
+        respond_to_stream_packet(
+            shared_secret,
+            ilp_address,
+            asset_code,
+            asset_scale,
+            prepare,
+            stream_packet,
+            &receipts,
+        )
+    }
+}
+
+/// Returns the destination tag of an address local to the account (the part of the
+/// destination after the account's address), if it has the form `.<tag>.<token>`
+fn destination_tag(local_part: &[u8]) -> Option<&str> {
+    let local_part = std::str::from_utf8(local_part).ok()?;
+    let mut segments = local_part.strip_prefix('.')?.split('.');
+    match (segments.next(), segments.next(), segments.next()) {
+        (Some(tag), Some(_token), None) => Some(tag),
+        _ => None,
+    }
+}
+
+/// Returns the address the sender told in the packet, if there is one
+fn connection_source(stream_packet: &StreamPacket) -> Option<Address> {
+    stream_packet.frames().find_map(|frame| match frame {
+        Frame::ConnectionNewAddress(frame) => Some(frame.source_account),
+        _ => None,
+    })
+}
+
+/// Returns the memo the sender attached to the packet, if there is one
+/// (see [`send_money_with_memo`](./fn.send_money_with_memo.html))
+fn stream_memo(stream_packet: &StreamPacket) -> Option<String> {
+    stream_packet.frames().find_map(|frame| match frame {
+        Frame::StreamData(frame) if frame.stream_id == 1 && frame.offset == 0 => {
+            std::str::from_utf8(frame.data).ok().map(String::from)
+        }
+        _ => None,
+    })
+}
+
+fn decrypt_stream_packet(
     shared_secret: &[u8; 32],
-    // Our node's ILP Address ( we are the receiver, so we should return that
-    // plus any other relevant information in our prepare packet's frames)
     ilp_address: &Address,
-    asset_code: &str,
-    asset_scale: u8,
     prepare: &Prepare,
-) -> Result<Fulfill, Reject> {
-    // Generate fulfillment
-    let fulfillment = generate_fulfillment(&shared_secret[..], prepare.data());
-    let condition = hash_sha256(&fulfillment);
-    let is_fulfillable = condition == prepare.execution_condition();
-
-    // Parse STREAM packet
-    // TODO avoid copying data
-    let prepare_amount = prepare.amount();
-
+) -> Result<StreamPacket, Reject> {
     // Note that we are copying the Prepare packet data. This is a bad idea
     // in cases where STREAM is used to send a significant amount of data.
     // This implementation doesn't currently support handling the STREAM data
@@ -236,7 +381,7 @@ fn receive_money(
     // the request on to the next service.
     let copied_data = BytesMut::from(prepare.data());
 
-    let stream_packet = StreamPacket::from_encrypted(shared_secret, copied_data).map_err(|_| {
+    StreamPacket::from_encrypted(shared_secret, copied_data).map_err(|_| {
         debug!("Unable to parse data, rejecting Prepare packet");
         RejectBuilder {
             code: ErrorCode::F06_UNEXPECTED_PAYMENT,
@@ -245,7 +390,63 @@ fn receive_money(
             data: &[],
         }
         .build()
-    })?;
+    })
+}
+
+/// Returns true if the packet can be fulfilled and carries at least the amount
+/// the sender wants to be received
+fn is_fulfillable(
+    shared_secret: &[u8; 32],
+    prepare: &Prepare,
+    stream_packet: &StreamPacket,
+) -> bool {
+    let fulfillment = generate_fulfillment(&shared_secret[..], prepare.data());
+    hash_sha256(&fulfillment) == prepare.execution_condition()
+        && prepare.amount() >= stream_packet.prepare_amount()
+}
+
+/// Splits the amount of the Prepare between the streams of its StreamMoney frames,
+/// in proportion to their shares. The remainder goes to the first stream.
+fn stream_amounts(amount: u64, stream_packet: &StreamPacket) -> Vec<(u64, u64)> {
+    let shares: Vec<(u64, u64)> = stream_packet
+        .frames()
+        .filter_map(|frame| match frame {
+            Frame::StreamMoney(frame) => Some((frame.stream_id, frame.shares)),
+            _ => None,
+        })
+        .collect();
+    let total_shares: u128 = shares.iter().map(|(_, shares)| u128::from(*shares)).sum();
+    if total_shares == 0 {
+        return Vec::new();
+    }
+    let mut amounts: Vec<(u64, u64)> = shares
+        .into_iter()
+        .map(|(stream_id, shares)| {
+            let share = u128::from(amount) * u128::from(shares) / total_shares;
+            (stream_id, share as u64)
+        })
+        .collect();
+    let allocated: u64 = amounts.iter().map(|(_, amount)| amount).sum();
+    amounts[0].1 += amount - allocated;
+    amounts
+}
+
+#[allow(clippy::cognitive_complexity)]
+fn respond_to_stream_packet(
+    shared_secret: &[u8; 32],
+    ilp_address: &Address,
+    asset_code: &str,
+    asset_scale: u8,
+    prepare: &Prepare,
+    stream_packet: &StreamPacket,
+    receipts: &[(u64, Bytes)],
+) -> Result<Fulfill, Reject> {
+    // Generate fulfillment
+    let fulfillment = generate_fulfillment(&shared_secret[..], prepare.data());
+    let condition = hash_sha256(&fulfillment);
+    let is_fulfillable = condition == prepare.execution_condition();
+
+    let prepare_amount = prepare.amount();
 
     let mut response_frames: Vec<Frame> = Vec::new();
 
@@ -274,6 +475,13 @@ fn receive_money(
         }
     }
 
+    for (stream_id, receipt) in receipts {
+        response_frames.push(Frame::StreamReceipt(StreamReceiptFrame {
+            stream_id: *stream_id,
+            receipt: &receipt[..],
+        }));
+    }
+
     // Return Fulfill or Reject Packet
     if is_fulfillable && prepare_amount >= stream_packet.prepare_amount() {
         let response_packet = StreamPacketBuilder {
@@ -329,6 +537,34 @@ fn receive_money(
     }
 }
 
+/// Returns the Reject of a packet of a connection closed by the receiver, which tells the sender
+/// to stop sending packets on the connection
+fn close_connection(
+    shared_secret: &[u8; 32],
+    ilp_address: &Address,
+    prepare: &Prepare,
+    stream_packet: &StreamPacket,
+) -> Reject {
+    let response_packet = StreamPacketBuilder {
+        sequence: stream_packet.sequence(),
+        ilp_packet_type: IlpPacketType::Reject,
+        prepare_amount: prepare.amount(),
+        frames: &[Frame::ConnectionClose(ConnectionCloseFrame {
+            code: StreamErrorCode::ApplicationError,
+            message: "Connection closed by the receiver",
+        })],
+    }
+    .build();
+    let encrypted_response = response_packet.into_encrypted(shared_secret);
+    RejectBuilder {
+        code: ErrorCode::F99_APPLICATION_ERROR,
+        message: b"Connection closed by the receiver",
+        triggered_by: Some(ilp_address),
+        data: &encrypted_response[..],
+    }
+    .build()
+}
+
 #[cfg(test)]
 mod connection_generator {
     use super::*;
@@ -353,6 +589,35 @@ mod connection_generator {
             shared_secret
         );
     }
+
+    #[test]
+    fn generates_tagged_secrets() {
+        let connection_generator = ConnectionGenerator::new(Bytes::from(&[9; 32][..]));
+        let receiver_address = Address::from_str("example.receiver.donate").unwrap();
+        let (destination_account, shared_secret) = connection_generator
+            .with_tag("donate")
+            .generate_address_and_secret(&receiver_address);
+
+        assert_eq!(
+            destination_tag(&destination_account.to_bytes()["example.receiver".len()..]),
+            Some("donate")
+        );
+        assert_eq!(
+            connection_generator
+                .with_tag("donate")
+                .rederive_secret(&destination_account)
+                .unwrap(),
+            shared_secret
+        );
+        assert_ne!(
+            connection_generator
+                .rederive_secret(&destination_account)
+                .unwrap(),
+            shared_secret
+        );
+        assert_eq!(destination_tag(b".token"), None);
+        assert_eq!(destination_tag(b".a.b.token"), None);
+    }
 }
 
 #[cfg(test)]
@@ -377,6 +642,30 @@ mod receiving_money {
 
     use std::str::FromStr;
     use std::time::UNIX_EPOCH;
+
+    /// Fulfills the packet if it is valid, and returns the memo of its sender
+    fn receive_money(
+        shared_secret: &[u8; 32],
+        // Our node's ILP Address ( we are the receiver, so we should return that
+        // plus any other relevant information in our prepare packet's frames)
+        ilp_address: &Address,
+        asset_code: &str,
+        asset_scale: u8,
+        prepare: &Prepare,
+    ) -> Result<(Fulfill, Option<String>), Reject> {
+        let stream_packet = decrypt_stream_packet(shared_secret, ilp_address, prepare)?;
+        let fulfill = respond_to_stream_packet(
+            shared_secret,
+            ilp_address,
+            asset_code,
+            asset_scale,
+            prepare,
+            &stream_packet,
+            &[],
+        )?;
+        Ok((fulfill, stream_memo(&stream_packet)))
+    }
+
     #[test]
     fn fulfills_valid_packet() {
         let ilp_address = Address::from_str("example.destination").unwrap();
@@ -431,6 +720,46 @@ mod receiving_money {
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn returns_the_memo_of_the_sender() {
+        let ilp_address = Address::from_str("example.destination").unwrap();
+        let server_secret = Bytes::from(&[1; 32][..]);
+        let connection_generator = ConnectionGenerator::new(server_secret);
+        let (destination_account, shared_secret) =
+            connection_generator.generate_address_and_secret(&ilp_address);
+        let stream_packet = StreamPacketBuilder {
+            ilp_packet_type: IlpPacketType::Prepare,
+            prepare_amount: 0,
+            sequence: 1,
+            frames: &[
+                Frame::StreamMoney(StreamMoneyFrame {
+                    stream_id: 1,
+                    shares: 1,
+                }),
+                Frame::StreamData(StreamDataFrame {
+                    stream_id: 1,
+                    offset: 0,
+                    data: b"invoice 42",
+                }),
+            ],
+        }
+        .build();
+        let data = stream_packet.into_encrypted(&shared_secret[..]);
+        let execution_condition = generate_condition(&shared_secret[..], &data);
+
+        let prepare = PrepareBuilder {
+            destination: destination_account,
+            amount: 100,
+            expires_at: UNIX_EPOCH,
+            data: &data[..],
+            execution_condition: &execution_condition,
+        }
+        .build();
+
+        let (_, memo) = receive_money(&shared_secret, &ilp_address, "ABC", 9, &prepare).unwrap();
+        assert_eq!(memo, Some("invoice 42".to_string()));
+    }
+
     #[test]
     fn rejects_modified_data() {
         let ilp_address = Address::from_str("example.destination").unwrap();
@@ -515,8 +844,9 @@ mod receiving_money {
                 .as_ref() as &[u8],
             "did not regenerate the same shared secret",
         );
-        let fulfill = receive_money(&shared_secret, &ilp_address, "ABC", 9, &prepare)
+        let (fulfill, memo) = receive_money(&shared_secret, &ilp_address, "ABC", 9, &prepare)
             .expect("Receiver should be able to generate the fulfillment");
+        assert_eq!(memo, None);
         assert_eq!(
             &hash_sha256(fulfill.fulfillment())[..],
             &condition[..],
@@ -709,4 +1039,183 @@ mod stream_receiver_service {
             Address::from_str("example.other-receiver").unwrap(),
         );
     }
+
+    #[tokio::test]
+    async fn tracks_and_closes_connections() {
+        let ilp_address = Address::from_str("example.destination").unwrap();
+        let server_secret = Bytes::from(&[1; 32][..]);
+        let connection_generator = ConnectionGenerator::new(server_secret.clone());
+        let (destination_account, shared_secret) =
+            connection_generator.generate_address_and_secret(&ilp_address);
+        let account = TestAccount {
+            id: Uuid::new_v4(),
+            ilp_address: ilp_address.clone(),
+            asset_code: "XYZ".to_string(),
+            asset_scale: 9,
+            max_packet_amount: None,
+        };
+        let connections = StreamConnections::default();
+        let mut service = StreamReceiverService::new(
+            server_secret,
+            DummyStore,
+            outgoing_service_fn(|_: OutgoingRequest<TestAccount>| -> IlpResult {
+                panic!("shouldn't get here")
+            }),
+        )
+        .with_connections(connections.clone());
+        let data = test_stream_packet().into_encrypted(&shared_secret[..]);
+        let execution_condition = generate_condition(&shared_secret[..], &data);
+        let prepare = PrepareBuilder {
+            destination: destination_account.clone(),
+            amount: 100,
+            expires_at: UNIX_EPOCH,
+            data: &data[..],
+            execution_condition: &execution_condition,
+        }
+        .build();
+        let request = OutgoingRequest {
+            from: account.clone(),
+            to: account.clone(),
+            original_amount: prepare.amount(),
+            prepare,
+        };
+
+        assert!(service.send_request(request.clone()).await.is_ok());
+        let listed = connections.list(account.id);
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].destination, destination_account);
+        assert_eq!(listed[0].amount_received, 100);
+        assert_eq!(listed[0].fulfilled_packets, 1);
+
+        assert!(connections.close(account.id, &listed[0].id).is_some());
+        let reject = service.send_request(request).await.unwrap_err();
+        assert_eq!(reject.code(), ErrorCode::F99_APPLICATION_ERROR);
+        let response =
+            StreamPacket::from_encrypted(&shared_secret[..], BytesMut::from(reject.data()))
+                .unwrap();
+        assert!(response
+            .frames()
+            .any(|frame| matches!(frame, Frame::ConnectionClose(_))));
+    }
+
+    #[derive(Clone, Default)]
+    struct ReceiptTotals {
+        totals: std::sync::Arc<parking_lot::Mutex<std::collections::HashMap<u64, u64>>>,
+    }
+
+    impl StreamNotificationsStore for ReceiptTotals {
+        type Account = TestAccount;
+
+        fn add_payment_notification_subscription(
+            &self,
+            _account_id: Uuid,
+            _sender: UnboundedSender<PaymentNotification>,
+        ) {
+        }
+
+        fn publish_payment_notification(&self, _payment: PaymentNotification) {}
+
+        fn all_payment_subscription(&self) -> broadcast::Receiver<PaymentNotification> {
+            broadcast::channel(1).1
+        }
+    }
+
+    #[async_trait]
+    impl StreamReceiptStore for ReceiptTotals {
+        async fn add_stream_received(
+            &self,
+            _nonce: [u8; 16],
+            stream_id: u64,
+            amount: u64,
+        ) -> Result<u64, interledger_errors::ReceiptStoreError> {
+            let mut totals = self.totals.lock();
+            let total = totals.entry(stream_id).or_insert(0);
+            *total += amount;
+            Ok(*total)
+        }
+    }
+
+    #[tokio::test]
+    async fn issues_receipts_of_stream_totals() {
+        let ilp_address = Address::from_str("example.destination").unwrap();
+        let server_secret = Bytes::from(&[1; 32][..]);
+        let connection_generator = ConnectionGenerator::new(server_secret.clone());
+        let receipt_generator = ReceiptGenerator::new(&server_secret[..]);
+        let (destination_account, shared_secret) =
+            connection_generator.generate_address_and_secret(&ilp_address);
+        let account = TestAccount {
+            id: Uuid::new_v4(),
+            ilp_address: ilp_address.clone(),
+            asset_code: "XYZ".to_string(),
+            asset_scale: 9,
+            max_packet_amount: None,
+        };
+
+        let mut service = StreamReceiverService::new(
+            server_secret,
+            ReceiptTotals::default(),
+            outgoing_service_fn(|_: OutgoingRequest<TestAccount>| -> IlpResult {
+                panic!("shouldn't get here")
+            }),
+        )
+        .with_receipts();
+
+        for expected_total in &[100, 200] {
+            let data = test_stream_packet().into_encrypted(&shared_secret[..]);
+            let execution_condition = generate_condition(&shared_secret[..], &data);
+            let prepare = PrepareBuilder {
+                destination: destination_account.clone(),
+                amount: 100,
+                expires_at: UNIX_EPOCH,
+                data: &data[..],
+                execution_condition: &execution_condition,
+            }
+            .build();
+            let fulfill = service
+                .send_request(OutgoingRequest {
+                    from: account.clone(),
+                    to: account.clone(),
+                    original_amount: prepare.amount(),
+                    prepare,
+                })
+                .await
+                .unwrap();
+
+            let response =
+                StreamPacket::from_encrypted(&shared_secret[..], BytesMut::from(fulfill.data()))
+                    .unwrap();
+            let receipt = response
+                .frames()
+                .find_map(|frame| match frame {
+                    Frame::StreamReceipt(frame) => Some(frame.receipt.to_vec()),
+                    _ => None,
+                })
+                .expect("the fulfill should include a receipt");
+            let receipt = receipt_generator.verify(&receipt).unwrap();
+            assert_eq!(receipt.nonce, receipt_generator.nonce(&destination_account));
+            assert_eq!(receipt.stream_id, 1);
+            assert_eq!(receipt.total_received, *expected_total);
+        }
+    }
+
+    #[test]
+    fn splits_amounts_between_streams() {
+        let stream_packet = StreamPacketBuilder {
+            ilp_packet_type: IlpPacketType::Prepare,
+            prepare_amount: 0,
+            sequence: 1,
+            frames: &[
+                Frame::StreamMoney(StreamMoneyFrame {
+                    stream_id: 1,
+                    shares: 1,
+                }),
+                Frame::StreamMoney(StreamMoneyFrame {
+                    stream_id: 3,
+                    shares: 2,
+                }),
+            ],
+        }
+        .build();
+        assert_eq!(stream_amounts(100, &stream_packet), vec![(1, 34), (3, 66)]);
+    }
 }
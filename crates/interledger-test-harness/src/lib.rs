@@ -0,0 +1,71 @@
+//! # interledger-test-harness
+//!
+//! Builds networks of Interledger.rs nodes running in the test's process, so that
+//! end-to-end tests describe a topology in code instead of scripting the nodes from a shell.
+//!
+//! A [`Topology`](./struct.Topology.html) lists the nodes, the links between them (peerings
+//! and parent/child relations, over HTTP or BTP), their exchange rates and static routes.
+//! [`start`](./struct.Topology.html#method.start) runs the nodes on the chosen store, creates
+//! the accounts of the links (and one user account per node, named after the node), and
+//! waits until the routes between the nodes are known. The returned
+//! [`Network`](./struct.Network.html) sends payments between the users of the nodes and
+//! reads the balances of their accounts.
+//!
+//! ```no_run
+//! # async fn example() -> Result<(), interledger_test_harness::HarnessError> {
+//! use interledger_test_harness::{Link, NodeSpec, Topology, Transport};
+//!
+//! let network = Topology::new()
+//!     .node(NodeSpec::new("alice", "XYZ", 9))
+//!     .node(NodeSpec::new("bob", "ABC", 6))
+//!     .link(Link::peers("alice", "bob").over(Transport::Btp))
+//!     .rate("XYZ", 2.0)
+//!     .rate("ABC", 1.0)
+//!     .start()
+//!     .await?;
+//! let receipt = network.pay("alice", "bob", 1_000_000).await?;
+//! assert_eq!(receipt.delivered_amount, 2000);
+//! assert_eq!(network.balance("bob", "alice").await?, -0.002);
+//! network.shut_down().await;
+//! # Ok(())
+//! # }
+//! ```
+
+/// The network of running nodes
+mod network;
+/// The stores the nodes run on
+mod store;
+/// The description of the nodes and the links between them
+mod topology;
+
+pub use network::{Network, TestNode};
+pub use store::StoreKind;
+pub use topology::{Link, NodeSpec, Relation, Topology, Transport};
+
+pub use interledger::stream::StreamDelivery;
+use thiserror::Error;
+
+/// Errors of the set up of a network and of the calls to its nodes
+#[derive(Debug, Error)]
+pub enum HarnessError {
+    #[error("the topology is invalid: {0}")]
+    InvalidTopology(String),
+    #[error("error starting the store: {0}")]
+    Store(String),
+    #[error("error starting node `{0}`")]
+    NodeStart(String),
+    #[error("error calling the API of node `{node}`: {source}")]
+    Http {
+        node: String,
+        #[source]
+        source: reqwest::Error,
+    },
+    #[error("node `{node}` answered {status}: {body}")]
+    Api {
+        node: String,
+        status: u16,
+        body: String,
+    },
+    #[error("timed out waiting for {0}")]
+    Timeout(String),
+}
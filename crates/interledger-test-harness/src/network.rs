@@ -0,0 +1,288 @@
+use crate::store::Store;
+use crate::topology::ADMIN_TOKEN;
+use crate::HarnessError;
+use ilp_node::Shutdown;
+use interledger::{packet::Address, stream::StreamDelivery};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::json;
+use std::{collections::HashMap, net::SocketAddr, str::FromStr, time::Duration};
+
+/// Time the nodes have to complete their in-flight requests when the network shuts down
+const SHUTDOWN_DEADLINE: Duration = Duration::from_secs(5);
+/// Slippage the payments between the users of the nodes allow
+const PAYMENT_SLIPPAGE: f64 = 0.025;
+
+/// A running network, whose nodes are looked up by their names
+pub struct Network {
+    nodes: Vec<TestNode>,
+    /// The store of the nodes, which is stopped when the network is dropped
+    _store: Store,
+}
+
+impl Network {
+    pub(crate) fn new(nodes: Vec<TestNode>, store: Store) -> Self {
+        Network {
+            nodes,
+            _store: store,
+        }
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = &TestNode> {
+        self.nodes.iter()
+    }
+
+    /// Returns the node with the name.
+    ///
+    /// # Panics
+    ///
+    /// If the network has no node with the name
+    pub fn node(&self, name: &str) -> &TestNode {
+        self.nodes
+            .iter()
+            .find(|node| node.name == name)
+            .unwrap_or_else(|| panic!("the network has no node named `{}`", name))
+    }
+
+    pub(crate) fn set_ilp_address(&mut self, name: &str, address: Address) {
+        if let Some(node) = self.nodes.iter_mut().find(|node| node.name == name) {
+            node.ilp_address = Some(address);
+        }
+    }
+
+    /// Sends a STREAM payment of the amount (in the asset of the sender) from the user
+    /// of a node to the user of another one, and returns its receipt
+    pub async fn pay(
+        &self,
+        from: &str,
+        to: &str,
+        source_amount: u64,
+    ) -> Result<StreamDelivery, HarnessError> {
+        let to = self.node(to);
+        self.node(from)
+            .pay(
+                &format!("{}/accounts/{}/spsp", to.url(), to.name),
+                source_amount,
+            )
+            .await
+    }
+
+    /// Returns the balance of the account with the username on the node, in units of
+    /// its asset (rather than of its scale)
+    pub async fn balance(&self, node: &str, username: &str) -> Result<f64, HarnessError> {
+        self.node(node).balance(username).await
+    }
+
+    /// Stops all the nodes, waiting for their in-flight requests to complete
+    pub async fn shut_down(self) {
+        for node in self.nodes.iter() {
+            node.shutdown.shut_down(SHUTDOWN_DEADLINE).await;
+        }
+    }
+}
+
+/// A node of the network, whose API is called as its admin
+pub struct TestNode {
+    name: String,
+    http_address: SocketAddr,
+    ilp_address: Option<Address>,
+    asset_code: String,
+    asset_scale: u8,
+    client: reqwest::Client,
+    shutdown: Shutdown,
+}
+
+#[derive(Deserialize)]
+struct NodeStatus {
+    ilp_address: String,
+}
+
+#[derive(Deserialize)]
+struct Balance {
+    balance: f64,
+}
+
+impl TestNode {
+    pub(crate) fn new(
+        name: String,
+        http_address: SocketAddr,
+        ilp_address: Option<Address>,
+        asset_code: String,
+        asset_scale: u8,
+        shutdown: Shutdown,
+    ) -> Self {
+        TestNode {
+            name,
+            http_address,
+            ilp_address,
+            asset_code,
+            asset_scale,
+            client: reqwest::Client::new(),
+            shutdown,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The address the node's API and its ILP over HTTP and BTP endpoints listen on
+    pub fn http_address(&self) -> SocketAddr {
+        self.http_address
+    }
+
+    /// The base URL of the node's API
+    pub fn url(&self) -> String {
+        format!("http://{}", self.http_address)
+    }
+
+    /// The node's address, which nodes with a parent only have once their parent
+    /// account was created
+    pub fn ilp_address(&self) -> Option<&Address> {
+        self.ilp_address.as_ref()
+    }
+
+    pub fn asset_code(&self) -> &str {
+        &self.asset_code
+    }
+
+    pub fn asset_scale(&self) -> u8 {
+        self.asset_scale
+    }
+
+    /// The address of the node's user account
+    pub fn user_address(&self) -> String {
+        match self.ilp_address {
+            Some(ref address) => format!("{}.{}", address, self.name),
+            None => self.name.clone(),
+        }
+    }
+
+    /// The token with which the node's user account is authenticated
+    pub fn user_token(&self) -> String {
+        format!("{}_token", self.name)
+    }
+
+    /// Creates an account with the details, in the format of the `POST /accounts` body
+    pub async fn create_account<T: Serialize>(&self, details: T) -> Result<(), HarnessError> {
+        self.call::<_, serde_json::Value>(reqwest::Method::POST, "/accounts", Some(&details))
+            .await
+            .map(|_| ())
+    }
+
+    /// Sets the exchange rates of the node
+    pub async fn set_rates(&self, rates: &HashMap<String, f64>) -> Result<(), HarnessError> {
+        self.call::<_, serde_json::Value>(reqwest::Method::PUT, "/rates", Some(rates))
+            .await
+            .map(|_| ())
+    }
+
+    /// Routes the packets of the node for the prefix to the account with the username
+    pub async fn set_static_route(&self, prefix: &str, username: &str) -> Result<(), HarnessError> {
+        let response = self
+            .client
+            .put(&format!("{}/routes/static/{}", self.url(), prefix))
+            .bearer_auth(ADMIN_TOKEN)
+            .body(username.to_string())
+            .send()
+            .await
+            .map_err(|source| self.http_error(source))?;
+        self.check(response).await.map(|_| ())
+    }
+
+    /// Returns the node's routing table, as a map of the prefixes to the usernames
+    pub async fn routes(&self) -> Result<HashMap<String, String>, HarnessError> {
+        self.call::<(), _>(reqwest::Method::GET, "/routes", None)
+            .await
+    }
+
+    /// Returns the balance of the account with the username, in units of its asset
+    pub async fn balance(&self, username: &str) -> Result<f64, HarnessError> {
+        let balance: Balance = self
+            .call::<(), _>(
+                reqwest::Method::GET,
+                &format!("/accounts/{}/balance", username),
+                None,
+            )
+            .await?;
+        Ok(balance.balance)
+    }
+
+    /// Sends a STREAM payment of the amount from the node's user to the SPSP receiver
+    pub async fn pay(
+        &self,
+        receiver: &str,
+        source_amount: u64,
+    ) -> Result<StreamDelivery, HarnessError> {
+        let response = self
+            .client
+            .post(&format!("{}/accounts/{}/payments", self.url(), self.name))
+            .bearer_auth(self.user_token())
+            .json(&json!({
+                "receiver": receiver,
+                "source_amount": source_amount,
+                "slippage": PAYMENT_SLIPPAGE,
+            }))
+            .send()
+            .await
+            .map_err(|source| self.http_error(source))?;
+        let response = self.check(response).await?;
+        response
+            .json()
+            .await
+            .map_err(|source| self.http_error(source))
+    }
+
+    /// Asks the node for its address, which it got from its parent
+    pub(crate) async fn fetch_ilp_address(&self) -> Result<Address, HarnessError> {
+        let status: NodeStatus = self.call::<(), _>(reqwest::Method::GET, "/", None).await?;
+        Address::from_str(&status.ilp_address).map_err(|_| HarnessError::Api {
+            node: self.name.clone(),
+            status: 200,
+            body: format!("invalid address: {}", status.ilp_address),
+        })
+    }
+
+    async fn call<B: Serialize + ?Sized, R: DeserializeOwned>(
+        &self,
+        method: reqwest::Method,
+        path: &str,
+        body: Option<&B>,
+    ) -> Result<R, HarnessError> {
+        let mut request = self
+            .client
+            .request(method, &format!("{}{}", self.url(), path))
+            .bearer_auth(ADMIN_TOKEN);
+        if let Some(body) = body {
+            request = request.json(body);
+        }
+        let response = request
+            .send()
+            .await
+            .map_err(|source| self.http_error(source))?;
+        let response = self.check(response).await?;
+        response
+            .json()
+            .await
+            .map_err(|source| self.http_error(source))
+    }
+
+    async fn check(&self, response: reqwest::Response) -> Result<reqwest::Response, HarnessError> {
+        let status = response.status();
+        if status.is_success() {
+            Ok(response)
+        } else {
+            Err(HarnessError::Api {
+                node: self.name.clone(),
+                status: status.as_u16(),
+                body: response.text().await.unwrap_or_default(),
+            })
+        }
+    }
+
+    fn http_error(&self, source: reqwest::Error) -> HarnessError {
+        HarnessError::Http {
+            node: self.name.clone(),
+            source,
+        }
+    }
+}
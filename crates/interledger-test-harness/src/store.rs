@@ -0,0 +1,118 @@
+use crate::HarnessError;
+use ring::rand::{SecureRandom, SystemRandom};
+use std::{
+    path::PathBuf,
+    process::{Child, Command, Stdio},
+    time::{Duration, Instant},
+};
+
+/// Number of databases of a Redis server with the default configuration, which bounds
+/// the number of nodes of a network sharing a server
+const REDIS_DATABASES: usize = 16;
+/// Time within which a spawned Redis server must accept connections
+const REDIS_START_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The store the nodes of a network run on. Each node gets its own database, so a
+/// network has at most 16 nodes.
+#[derive(Clone, Debug, Default)]
+pub enum StoreKind {
+    /// A `redis-server` spawned for the network (it must be installed), listening on a
+    /// Unix socket and stopped along with the network
+    #[default]
+    Redis,
+    /// An existing Redis server, such as `redis://127.0.0.1:6379`. The nodes use its
+    /// databases 1 to 15, which should be empty.
+    RedisUrl(String),
+}
+
+/// The store of a running network, which hands out the URLs of the nodes' databases
+pub(crate) struct Store {
+    url: StoreUrl,
+    /// The spawned server, which is stopped when the network is dropped
+    _server: Option<RedisServer>,
+}
+
+enum StoreUrl {
+    Unix(PathBuf),
+    Tcp(String),
+}
+
+impl Store {
+    pub(crate) fn start(kind: &StoreKind, nodes: usize) -> Result<Self, HarnessError> {
+        if nodes >= REDIS_DATABASES {
+            return Err(HarnessError::InvalidTopology(format!(
+                "a network has at most {} nodes",
+                REDIS_DATABASES - 1
+            )));
+        }
+        match kind {
+            StoreKind::Redis => {
+                let server = RedisServer::spawn()?;
+                Ok(Store {
+                    url: StoreUrl::Unix(server.socket.clone()),
+                    _server: Some(server),
+                })
+            }
+            StoreKind::RedisUrl(url) => Ok(Store {
+                url: StoreUrl::Tcp(url.trim_end_matches('/').to_string()),
+                _server: None,
+            }),
+        }
+    }
+
+    /// URL of the database of the node with the index
+    pub(crate) fn database_url(&self, index: usize) -> String {
+        // Database 0 is left alone, so that the network does not clear the default one
+        let db = index + 1;
+        match self.url {
+            StoreUrl::Unix(ref path) => format!("redis+unix:{}?db={}", path.display(), db),
+            StoreUrl::Tcp(ref url) => format!("{}/{}", url, db),
+        }
+    }
+}
+
+struct RedisServer {
+    process: Child,
+    socket: PathBuf,
+}
+
+impl RedisServer {
+    fn spawn() -> Result<Self, HarnessError> {
+        let mut suffix = [0; 8];
+        SystemRandom::new()
+            .fill(&mut suffix)
+            .map_err(|_| HarnessError::Store("could not generate the socket path".to_string()))?;
+        let socket = std::env::temp_dir().join(format!(
+            "interledger-test-harness-{}.sock",
+            hex::encode(suffix)
+        ));
+        let process = Command::new("redis-server")
+            .arg("--port")
+            .arg("0")
+            .arg("--unixsocket")
+            .arg(&socket)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|err| HarnessError::Store(format!("could not spawn redis-server: {}", err)))?;
+        let server = RedisServer { process, socket };
+
+        // The socket is created once the server accepts connections
+        let started = Instant::now();
+        while !server.socket.exists() {
+            if started.elapsed() > REDIS_START_TIMEOUT {
+                return Err(HarnessError::Timeout("redis-server to start".to_string()));
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        Ok(server)
+    }
+}
+
+impl Drop for RedisServer {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+        let _ = std::fs::remove_file(&self.socket);
+    }
+}
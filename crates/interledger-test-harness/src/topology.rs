@@ -0,0 +1,507 @@
+use crate::network::{Network, TestNode};
+use crate::store::{Store, StoreKind};
+use crate::HarnessError;
+use ilp_node::InterledgerNode;
+use interledger::{packet::Address, service::Username};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde_json::{json, Map, Value};
+use std::{
+    collections::{HashMap, HashSet},
+    net::{SocketAddr, TcpListener},
+    str::FromStr,
+    time::{Duration, Instant},
+};
+use tokio::time::delay_for;
+use tracing::debug;
+
+/// Token with which the admin of every node is authenticated
+pub(crate) const ADMIN_TOKEN: &str = "admin";
+/// Interval at which the nodes broadcast their routes, short so that the network
+/// converges quickly
+const ROUTE_BROADCAST_INTERVAL: u64 = 200;
+/// Time within which every node must have a route to the users of all the other ones
+const CONVERGENCE_TIMEOUT: Duration = Duration::from_secs(10);
+const CONVERGENCE_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A node of the network, and the asset of its user account
+#[derive(Clone, Debug)]
+pub struct NodeSpec {
+    name: String,
+    asset_code: String,
+    asset_scale: u8,
+    spread: f64,
+    rates: HashMap<String, f64>,
+}
+
+impl NodeSpec {
+    /// A node with the name, which is also the username of its user account and the
+    /// username of the accounts the other nodes have for it. The asset is the one of the
+    /// user account, and the default one of the node's links.
+    pub fn new(name: impl Into<String>, asset_code: impl Into<String>, asset_scale: u8) -> Self {
+        NodeSpec {
+            name: name.into(),
+            asset_code: asset_code.into(),
+            asset_scale,
+            spread: 0.0,
+            rates: HashMap::new(),
+        }
+    }
+
+    /// Sets the spread the node takes on the packets it converts
+    pub fn spread(mut self, spread: f64) -> Self {
+        self.spread = spread;
+        self
+    }
+
+    /// Sets the rate of the asset on this node, instead of the one of the topology
+    pub fn rate(mut self, asset_code: impl Into<String>, rate: f64) -> Self {
+        self.rates.insert(asset_code.into(), rate);
+        self
+    }
+}
+
+/// How the packets are sent over a link
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Transport {
+    /// ILP over HTTP, in both directions
+    Http,
+    /// BTP, over the WebSocket the child (or the second peer) opens to the parent
+    /// (or the first peer)
+    Btp,
+}
+
+/// The relation between the nodes of a link
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Relation {
+    Peers,
+    /// The first node of the link is the parent, which assigns the address of the second one
+    Parent,
+}
+
+/// A link between two nodes, each of which gets an account for the other one
+#[derive(Clone, Debug)]
+pub struct Link {
+    a: String,
+    b: String,
+    relation: Relation,
+    transport: Transport,
+    asset: Option<(String, u8)>,
+    min_balance: Option<i64>,
+}
+
+impl Link {
+    /// Peers the nodes
+    pub fn peers(a: impl Into<String>, b: impl Into<String>) -> Self {
+        Link::new(a.into(), b.into(), Relation::Peers)
+    }
+
+    /// Makes the first node the parent of the second one, which gets its address from it
+    pub fn parent(parent: impl Into<String>, child: impl Into<String>) -> Self {
+        Link::new(parent.into(), child.into(), Relation::Parent)
+    }
+
+    fn new(a: String, b: String, relation: Relation) -> Self {
+        Link {
+            a,
+            b,
+            relation,
+            transport: Transport::Http,
+            asset: None,
+            min_balance: None,
+        }
+    }
+
+    /// Sets how the packets are sent over the link (HTTP by default)
+    pub fn over(mut self, transport: Transport) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    /// Sets the asset of the accounts of the link, which defaults to the asset of the
+    /// first node
+    pub fn asset(mut self, asset_code: impl Into<String>, asset_scale: u8) -> Self {
+        self.asset = Some((asset_code.into(), asset_scale));
+        self
+    }
+
+    /// Sets the minimum balance of the accounts of the link (they have none by default)
+    pub fn min_balance(mut self, min_balance: i64) -> Self {
+        self.min_balance = Some(min_balance);
+        self
+    }
+}
+
+/// The description of a network: its nodes, the links between them, their rates and
+/// their static routes
+#[derive(Clone, Debug)]
+pub struct Topology {
+    address_prefix: String,
+    store: StoreKind,
+    nodes: Vec<NodeSpec>,
+    links: Vec<Link>,
+    rates: HashMap<String, f64>,
+    static_routes: Vec<(String, String, String)>,
+}
+
+impl Default for Topology {
+    fn default() -> Self {
+        Topology {
+            address_prefix: "example".to_string(),
+            store: StoreKind::default(),
+            nodes: Vec::new(),
+            links: Vec::new(),
+            rates: HashMap::new(),
+            static_routes: Vec::new(),
+        }
+    }
+}
+
+impl Topology {
+    pub fn new() -> Self {
+        Topology::default()
+    }
+
+    /// Sets the prefix of the addresses of the nodes without a parent, which are
+    /// `{prefix}.{name}` (`example` by default)
+    pub fn address_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.address_prefix = prefix.into();
+        self
+    }
+
+    /// Sets the store the nodes run on (a Redis server spawned for the network by default)
+    pub fn store(mut self, store: StoreKind) -> Self {
+        self.store = store;
+        self
+    }
+
+    pub fn node(mut self, node: NodeSpec) -> Self {
+        self.nodes.push(node);
+        self
+    }
+
+    pub fn link(mut self, link: Link) -> Self {
+        self.links.push(link);
+        self
+    }
+
+    /// Sets the rate of the asset on all the nodes, unless a node sets its own
+    pub fn rate(mut self, asset_code: impl Into<String>, rate: f64) -> Self {
+        self.rates.insert(asset_code.into(), rate);
+        self
+    }
+
+    /// Routes the packets of the node for the prefix to the neighbour `via`
+    pub fn static_route(
+        mut self,
+        node: impl Into<String>,
+        prefix: impl Into<String>,
+        via: impl Into<String>,
+    ) -> Self {
+        self.static_routes
+            .push((node.into(), prefix.into(), via.into()));
+        self
+    }
+
+    /// Starts the nodes and creates their accounts, and returns the network once every
+    /// node has a route to the users of all the other ones
+    pub async fn start(self) -> Result<Network, HarnessError> {
+        self.validate()?;
+        let store = Store::start(&self.store, self.nodes.len())?;
+
+        let mut nodes = Vec::with_capacity(self.nodes.len());
+        for (index, spec) in self.nodes.iter().enumerate() {
+            let has_parent = self
+                .links
+                .iter()
+                .any(|link| link.relation == Relation::Parent && link.b == spec.name);
+            let ilp_address = if has_parent {
+                None
+            } else {
+                Some(
+                    Address::from_str(&format!("{}.{}", self.address_prefix, spec.name)).map_err(
+                        |_| {
+                            HarnessError::InvalidTopology(format!(
+                                "invalid address prefix: {}",
+                                self.address_prefix
+                            ))
+                        },
+                    )?,
+                )
+            };
+            let start_error = || HarnessError::NodeStart(spec.name.clone());
+            let http_address = open_address().ok_or_else(start_error)?;
+            let secret_seed = random_secret().ok_or_else(start_error)?;
+            let mut node = InterledgerNode::new(secret_seed, ADMIN_TOKEN);
+            node.ilp_address = ilp_address.clone();
+            node.database_url = store.database_url(index);
+            node.http_bind_address = http_address;
+            node.settlement_api_bind_address = open_address().ok_or_else(start_error)?;
+            node.route_broadcast_interval = Some(ROUTE_BROADCAST_INTERVAL);
+            node.exchange_rate.spread = spec.spread;
+            let shutdown = node.run().await.map_err(|_| start_error())?;
+            debug!("Started node {} on {}", spec.name, http_address);
+            nodes.push(TestNode::new(
+                spec.name.clone(),
+                http_address,
+                ilp_address,
+                spec.asset_code.clone(),
+                spec.asset_scale,
+                shutdown,
+            ));
+        }
+        let mut network = Network::new(nodes, store);
+
+        self.create_link_accounts(&mut network).await?;
+        for spec in self.nodes.iter() {
+            let node = network.node(&spec.name);
+            node.create_account(json!({
+                "username": spec.name,
+                "asset_code": spec.asset_code,
+                "asset_scale": spec.asset_scale,
+                "ilp_over_http_incoming_token": node.user_token(),
+            }))
+            .await?;
+
+            let mut rates = self.rates.clone();
+            rates.extend(spec.rates.clone());
+            if !rates.is_empty() {
+                node.set_rates(&rates).await?;
+            }
+        }
+        for (node, prefix, via) in self.static_routes.iter() {
+            network.node(node).set_static_route(prefix, via).await?;
+        }
+
+        self.wait_for_routes(&network).await?;
+        Ok(network)
+    }
+
+    fn validate(&self) -> Result<(), HarnessError> {
+        let invalid = |message: String| Err(HarnessError::InvalidTopology(message));
+        let mut names = HashSet::new();
+        for spec in self.nodes.iter() {
+            if Username::from_str(&spec.name).is_err() {
+                return invalid(format!("`{}` is not a valid username", spec.name));
+            }
+            if !names.insert(spec.name.as_str()) {
+                return invalid(format!("there are two nodes named `{}`", spec.name));
+            }
+        }
+        let mut parents = HashSet::new();
+        for link in self.links.iter() {
+            if !names.contains(link.a.as_str()) || !names.contains(link.b.as_str()) {
+                return invalid(format!(
+                    "the link {}-{} has an unknown node",
+                    link.a, link.b
+                ));
+            }
+            if link.a == link.b {
+                return invalid(format!("the node `{}` is linked to itself", link.a));
+            }
+            if link.relation == Relation::Parent && !parents.insert(link.b.as_str()) {
+                return invalid(format!("the node `{}` has two parents", link.b));
+            }
+        }
+        for (node, _, via) in self.static_routes.iter() {
+            if !self.neighbours(node).contains(via.as_str()) {
+                return invalid(format!("`{}` is not a neighbour of `{}`", via, node));
+            }
+        }
+
+        // The network only converges if every node is reachable from all the other ones
+        if let Some(first) = self.nodes.first() {
+            let mut reached = HashSet::new();
+            let mut queue = vec![first.name.as_str()];
+            while let Some(name) = queue.pop() {
+                if reached.insert(name) {
+                    queue.extend(self.neighbours(name));
+                }
+            }
+            if reached.len() < self.nodes.len() {
+                return invalid("the links do not connect all the nodes".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    fn neighbours<'a>(&'a self, name: &str) -> HashSet<&'a str> {
+        self.links
+            .iter()
+            .filter_map(|link| {
+                if link.a == name {
+                    Some(link.b.as_str())
+                } else if link.b == name {
+                    Some(link.a.as_str())
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Creates the accounts of the links. A link is created once the addresses it needs
+    /// are known, since the children only learn theirs when their parent account is created.
+    async fn create_link_accounts(&self, network: &mut Network) -> Result<(), HarnessError> {
+        let mut pending: Vec<&Link> = self.links.iter().collect();
+        while !pending.is_empty() {
+            let (ready, waiting): (Vec<&Link>, Vec<&Link>) =
+                pending.into_iter().partition(|link| {
+                    let a_known = network.node(&link.a).ilp_address().is_some();
+                    let b_known = network.node(&link.b).ilp_address().is_some();
+                    match link.relation {
+                        Relation::Peers => a_known && b_known,
+                        Relation::Parent => a_known,
+                    }
+                });
+            if ready.is_empty() {
+                return Err(HarnessError::InvalidTopology(
+                    "the parents of some nodes never get an address".to_string(),
+                ));
+            }
+            for link in ready {
+                self.create_link(network, link).await?;
+            }
+            pending = waiting;
+        }
+        Ok(())
+    }
+
+    async fn create_link(&self, network: &mut Network, link: &Link) -> Result<(), HarnessError> {
+        let (asset_code, asset_scale) = link.asset.clone().unwrap_or_else(|| {
+            let a = network.node(&link.a);
+            (a.asset_code().to_string(), a.asset_scale())
+        });
+        let (a_relation, b_relation) = match link.relation {
+            Relation::Peers => ("Peer", "Peer"),
+            Relation::Parent => ("Child", "Parent"),
+        };
+
+        // The first node's account is created first, so that it accepts the BTP
+        // connection and the ILDCP request of the second one
+        for (from, to, relation) in [
+            (&link.a, &link.b, a_relation),
+            (&link.b, &link.a, b_relation),
+        ]
+        .iter()
+        {
+            let from_node = network.node(from);
+            let to_node = network.node(to);
+            let mut account = Map::new();
+            account.insert("username".to_string(), json!(to));
+            account.insert("asset_code".to_string(), json!(asset_code));
+            account.insert("asset_scale".to_string(), json!(asset_scale));
+            account.insert("routing_relation".to_string(), json!(relation));
+            // The parent assigns the address of its children
+            if *relation != "Child" {
+                if let Some(address) = to_node.ilp_address() {
+                    account.insert("ilp_address".to_string(), json!(address.to_string()));
+                }
+            }
+            if let Some(min_balance) = link.min_balance {
+                account.insert("min_balance".to_string(), json!(min_balance));
+            }
+            match link.transport {
+                Transport::Http => {
+                    account.insert(
+                        "ilp_over_http_url".to_string(),
+                        json!(format!("{}/accounts/{}/ilp", to_node.url(), from)),
+                    );
+                    account.insert(
+                        "ilp_over_http_outgoing_token".to_string(),
+                        json!(token(from, to)),
+                    );
+                    account.insert(
+                        "ilp_over_http_incoming_token".to_string(),
+                        json!(token(to, from)),
+                    );
+                }
+                // The second node is the client, which authenticates with the same token
+                // in both directions
+                Transport::Btp if *from == &link.b => {
+                    account.insert(
+                        "ilp_over_btp_url".to_string(),
+                        json!(format!(
+                            "btp+ws://{}/accounts/{}/ilp/btp",
+                            to_node.http_address(),
+                            from
+                        )),
+                    );
+                    account.insert(
+                        "ilp_over_btp_outgoing_token".to_string(),
+                        json!(token(from, to)),
+                    );
+                }
+                Transport::Btp => {
+                    account.insert(
+                        "ilp_over_btp_incoming_token".to_string(),
+                        json!(token(to, from)),
+                    );
+                }
+            }
+            from_node.create_account(Value::Object(account)).await?;
+        }
+
+        if link.relation == Relation::Parent {
+            let address = network.node(&link.b).fetch_ilp_address().await?;
+            debug!(
+                "Node {} got the address {} from its parent",
+                link.b, address
+            );
+            network.set_ilp_address(&link.b, address);
+        }
+        Ok(())
+    }
+
+    async fn wait_for_routes(&self, network: &Network) -> Result<(), HarnessError> {
+        let started = Instant::now();
+        for node in network.nodes() {
+            for other in network.nodes() {
+                if node.name() == other.name() {
+                    continue;
+                }
+                let destination = other.user_address();
+                loop {
+                    let routes = node.routes().await?;
+                    if routes
+                        .keys()
+                        .any(|prefix| is_prefix_of(prefix, &destination))
+                    {
+                        break;
+                    }
+                    if started.elapsed() > CONVERGENCE_TIMEOUT {
+                        return Err(HarnessError::Timeout(format!(
+                            "a route from {} to {}",
+                            node.name(),
+                            destination
+                        )));
+                    }
+                    delay_for(CONVERGENCE_POLL_INTERVAL).await;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Token with which the node `from` authenticates the packets it sends to `to`
+fn token(from: &str, to: &str) -> String {
+    format!("{}_{}", from, to)
+}
+
+/// Whether the routing table prefix (where the empty one is the default route) covers the address
+fn is_prefix_of(prefix: &str, address: &str) -> bool {
+    prefix.is_empty()
+        || address == prefix
+        || (address.starts_with(prefix) && address[prefix.len()..].starts_with('.'))
+}
+
+fn random_secret() -> Option<[u8; 32]> {
+    let mut secret = [0; 32];
+    SystemRandom::new().fill(&mut secret).ok()?;
+    Some(secret)
+}
+
+/// Returns a local address on which nothing listens
+fn open_address() -> Option<SocketAddr> {
+    TcpListener::bind("127.0.0.1:0").ok()?.local_addr().ok()
+}
@@ -0,0 +1,69 @@
+use interledger_test_harness::{Link, NodeSpec, Topology, Transport};
+
+#[tokio::test]
+async fn three_nodes() {
+    // Alice and Bob are peers, Bob is the parent of Charlie
+    let network = Topology::new()
+        .node(NodeSpec::new("alice", "XYZ", 9).rate("XYZ", 2.01))
+        .node(NodeSpec::new("bob", "XYZ", 9).spread(0.02))
+        .node(NodeSpec::new("charlie", "ABC", 6))
+        .link(Link::peers("alice", "bob").min_balance(-1_000_000_000))
+        .link(
+            Link::parent("bob", "charlie")
+                .over(Transport::Btp)
+                .asset("ABC", 6)
+                .min_balance(-1_000_000_000),
+        )
+        .rate("ABC", 1.0)
+        .rate("XYZ", 2.0)
+        .start()
+        .await
+        .unwrap();
+
+    // Alice sends 1,000,000 at scale 9, which is 1000 at Charlie's scale of 6. Bob's rate
+    // is 2:1 minus a 2% spread, so Charlie gets 1960, which is within the 2.5% slippage
+    // of the 2010 Alice expects with her rate of 2.01:1.
+    let receipt = network.pay("alice", "charlie", 1_000_000).await.unwrap();
+    assert_eq!(receipt.from.to_string(), "example.alice.alice");
+    assert!(receipt
+        .to
+        .to_string()
+        .starts_with("example.bob.charlie.charlie."));
+    assert_eq!(receipt.source_asset_code, "XYZ");
+    assert_eq!(receipt.source_asset_scale, 9);
+    assert_eq!(receipt.sent_amount, 1_000_000);
+    assert_eq!(receipt.in_flight_amount, 0);
+    assert_eq!(receipt.delivered_amount, 1960);
+    assert_eq!(receipt.destination_asset_code.unwrap(), "ABC");
+    assert_eq!(receipt.destination_asset_scale.unwrap(), 6);
+
+    assert_eq!(
+        network.balance("alice", "alice").await.unwrap(),
+        -1_000_000.0 / 1e9
+    );
+    assert_eq!(
+        network.balance("bob", "charlie").await.unwrap(),
+        1960.0 / 1e6
+    );
+    assert_eq!(
+        network.balance("charlie", "charlie").await.unwrap(),
+        1960.0 / 1e6
+    );
+
+    // Charlie sends 1000 back, which Bob converts at 1:2 minus the spread
+    let receipt = network.pay("charlie", "alice", 1000).await.unwrap();
+    assert_eq!(receipt.from.to_string(), "example.bob.charlie.charlie");
+    assert!(receipt.to.to_string().starts_with("example.alice.alice."));
+    assert_eq!(receipt.delivered_amount, 490_000);
+
+    assert_eq!(
+        network.balance("alice", "alice").await.unwrap(),
+        (490_000.0 - 1_000_000.0) / 1e9
+    );
+    assert_eq!(
+        network.balance("charlie", "charlie").await.unwrap(),
+        (1960.0 - 1000.0) / 1e6
+    );
+
+    network.shut_down().await;
+}
@@ -0,0 +1,56 @@
+use interledger_packet::Address;
+use interledger_service::{Account, Username};
+use once_cell::sync::Lazy;
+use std::str::FromStr;
+use uuid::Uuid;
+use wasm_bindgen::JsValue;
+
+/// The username of the sender's account, which is not sent to the connector
+static SENDER: Lazy<Username> = Lazy::new(|| Username::from_str("sender").unwrap());
+
+/// The account of the sender at its connector
+#[derive(Clone, Debug)]
+pub struct WasmAccount {
+    ilp_address: Address,
+    asset_code: String,
+    asset_scale: u8,
+}
+
+impl WasmAccount {
+    pub fn new(ilp_address: &str, asset_code: &str, asset_scale: u8) -> Result<Self, JsValue> {
+        let ilp_address = Address::from_str(ilp_address).map_err(|err| {
+            JsValue::from(format!("Invalid ILP address {}: {}", ilp_address, err))
+        })?;
+        if asset_code.is_empty() {
+            return Err(JsValue::from("Asset code must not be empty"));
+        }
+        Ok(WasmAccount {
+            ilp_address,
+            asset_code: asset_code.to_string(),
+            asset_scale,
+        })
+    }
+}
+
+impl Account for WasmAccount {
+    /// The sender has a single account, so its id is not used
+    fn id(&self) -> Uuid {
+        Uuid::nil()
+    }
+
+    fn username(&self) -> &Username {
+        &SENDER
+    }
+
+    fn ilp_address(&self) -> &Address {
+        &self.ilp_address
+    }
+
+    fn asset_scale(&self) -> u8 {
+        self.asset_scale
+    }
+
+    fn asset_code(&self) -> &str {
+        &self.asset_code
+    }
+}
@@ -0,0 +1,110 @@
+//! # interledger-wasm
+//!
+//! WebAssembly bindings for sending [STREAM](https://interledger.org/rfcs/0029-stream/) payments
+//! from browsers. The packets of the payments are sent to the ILP-over-HTTP URL of the sender's
+//! account at a connector with the browser's `fetch`, and the payments are timed with its
+//! `setTimeout`.
+//!
+//! The bindings only compile to `wasm32-unknown-unknown`, for example with
+//! `wasm-pack build --target web crates/interledger-wasm`. On other targets the crate is empty.
+#![cfg(target_arch = "wasm32")]
+
+mod account;
+mod runtime;
+mod service;
+mod store;
+
+use account::WasmAccount;
+use interledger_rates::ExchangeRateStore;
+use interledger_stream::send_money_with_runtime;
+use js_sys::Promise;
+use runtime::BrowserRuntime;
+use service::{query, IlpOverHttpService};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use store::SenderStore;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::future_to_promise;
+
+/// Wraps the values of the browser's APIs, which are not `Send`, so they can be used by the
+/// services and the runtime of the STREAM sender. Browsers run the WebAssembly module on a
+/// single thread, so the values are never sent to another thread.
+pub(crate) struct SingleThreaded<T>(pub T);
+
+unsafe impl<T> Send for SingleThreaded<T> {}
+unsafe impl<T> Sync for SingleThreaded<T> {}
+
+impl<F: Future> Future for SingleThreaded<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        unsafe { self.map_unchecked_mut(|this| &mut this.0) }.poll(cx)
+    }
+}
+
+/// Sends STREAM payments from an account at a connector, through the account's
+/// ILP-over-HTTP URL
+#[wasm_bindgen]
+pub struct StreamSender {
+    account: WasmAccount,
+    service: IlpOverHttpService,
+    store: SenderStore,
+}
+
+#[wasm_bindgen]
+impl StreamSender {
+    /// Creates a sender of the payments of the account with the ILP address and asset,
+    /// whose packets are sent to the URL with the token (if any)
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        ilp_over_http_url: String,
+        auth_token: Option<String>,
+        ilp_address: &str,
+        asset_code: &str,
+        asset_scale: u8,
+    ) -> Result<StreamSender, JsValue> {
+        let account = WasmAccount::new(ilp_address, asset_code, asset_scale)?;
+        Ok(StreamSender {
+            store: SenderStore::new(),
+            service: IlpOverHttpService::new(ilp_over_http_url, auth_token),
+            account,
+        })
+    }
+
+    /// Sets the rate of the asset, in the units of a common base asset. The rates of the
+    /// account's asset and the receivers' assets must be set to pay receivers in other assets.
+    #[wasm_bindgen(js_name = setExchangeRate)]
+    pub fn set_exchange_rate(&self, asset_code: String, rate: f64) -> Result<(), JsValue> {
+        self.store
+            .set_exchange_rates(std::iter::once((asset_code, rate)).collect())
+            .map_err(|err| JsValue::from(err.to_string()))
+    }
+
+    /// Resolves the payment pointer (or SPSP URL) and pays the amount to it, allowing the
+    /// slippage in the exchange rate. Returns a promise of the amounts sent and delivered.
+    pub fn pay(&self, receiver: String, source_amount: u64, slippage: f64) -> Promise {
+        let account = self.account.clone();
+        let service = self.service.clone();
+        let store = self.store.clone();
+        future_to_promise(async move {
+            let spsp = query(&receiver).await?;
+            let delivery = send_money_with_runtime(
+                BrowserRuntime,
+                service,
+                &account,
+                store,
+                spsp.destination_account().clone(),
+                spsp.shared_secret().to_vec(),
+                source_amount,
+                slippage,
+                None,
+            )
+            .await
+            .map_err(|err| JsValue::from(format!("Error sending payment: {}", err)))?;
+            let delivery =
+                serde_json::to_string(&delivery).map_err(|err| JsValue::from(err.to_string()))?;
+            js_sys::JSON::parse(&delivery)
+        })
+    }
+}
@@ -0,0 +1,39 @@
+use super::SingleThreaded;
+use futures::future::{BoxFuture, FutureExt};
+use interledger_stream::{Error, Runtime};
+use js_sys::{Date, Promise};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use wasm_bindgen_futures::JsFuture;
+
+/// The runtime of the payments sent from a browser, whose packets are sent concurrently
+/// on the browser's single thread
+#[derive(Clone, Debug)]
+pub struct BrowserRuntime;
+
+impl Runtime for BrowserRuntime {
+    fn now(&self) -> Duration {
+        Duration::from_secs_f64(Date::now() / 1000.0)
+    }
+
+    fn system_time(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(Date::now() as u64)
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        let timeout = duration.as_millis().min(i32::MAX as u128) as i32;
+        let promise = Promise::new(&mut |resolve, _reject| {
+            if let Some(window) = web_sys::window() {
+                let _ =
+                    window.set_timeout_with_callback_and_timeout_and_arguments_0(&resolve, timeout);
+            }
+        });
+        SingleThreaded(JsFuture::from(promise)).map(|_| ()).boxed()
+    }
+
+    fn spawn(
+        &self,
+        future: BoxFuture<'static, Result<(), Error>>,
+    ) -> BoxFuture<'static, Result<(), Error>> {
+        future
+    }
+}
@@ -0,0 +1,121 @@
+use super::account::WasmAccount;
+use super::SingleThreaded;
+use async_trait::async_trait;
+use bytes::BytesMut;
+use interledger_packet::{ErrorCode, Packet, RejectBuilder};
+use interledger_service::{Account, IlpResult, IncomingRequest, IncomingService};
+use interledger_spsp::{payment_pointer_to_url, SpspResponse};
+use js_sys::Uint8Array;
+use std::convert::TryFrom;
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{Request, RequestInit, Response};
+
+/// Sends the packets of the sender's account to its ILP-over-HTTP URL with the browser's `fetch`
+#[derive(Clone)]
+pub struct IlpOverHttpService {
+    url: String,
+    auth_token: Option<String>,
+}
+
+impl IlpOverHttpService {
+    pub fn new(url: String, auth_token: Option<String>) -> Self {
+        IlpOverHttpService { url, auth_token }
+    }
+}
+
+#[async_trait]
+impl IncomingService<WasmAccount> for IlpOverHttpService {
+    async fn handle_request(&mut self, request: IncomingRequest<WasmAccount>) -> IlpResult {
+        let ilp_address = request.from.ilp_address().clone();
+        let reject = |code, message: &str| {
+            RejectBuilder {
+                code,
+                message: message.as_bytes(),
+                triggered_by: Some(&ilp_address),
+                data: &[],
+            }
+            .build()
+        };
+
+        let body = BytesMut::from(request.prepare).to_vec();
+        let response = SingleThreaded(fetch(
+            "POST",
+            &self.url,
+            &[("Content-Type", "application/octet-stream")],
+            self.auth_token.as_deref(),
+            Some(body),
+        ))
+        .await
+        .map_err(|err| reject(ErrorCode::T01_PEER_UNREACHABLE, &err))?;
+
+        match Packet::try_from(BytesMut::from(response)) {
+            Ok(Packet::Fulfill(fulfill)) => Ok(fulfill),
+            Ok(Packet::Reject(reject)) => Err(reject),
+            _ => Err(reject(
+                ErrorCode::T00_INTERNAL_ERROR,
+                "Unexpected response from the connector",
+            )),
+        }
+    }
+}
+
+/// Resolves the payment pointer (or SPSP URL) to the connection details of its receiver
+pub async fn query(receiver: &str) -> Result<SpspResponse, JsValue> {
+    let url = payment_pointer_to_url(receiver).map_err(|err| JsValue::from(err.to_string()))?;
+    let body = fetch(
+        "GET",
+        url.as_str(),
+        &[("Accept", "application/spsp4+json, application/spsp+json")],
+        None,
+        None,
+    )
+    .await?;
+    serde_json::from_slice(&body)
+        .map_err(|err| JsValue::from(format!("Invalid SPSP response from {}: {}", url, err)))
+}
+
+/// Sends the HTTP request and returns the body of its successful response
+async fn fetch(
+    method: &str,
+    url: &str,
+    headers: &[(&str, &str)],
+    auth_token: Option<&str>,
+    body: Option<Vec<u8>>,
+) -> Result<Vec<u8>, String> {
+    let js_error = |err: JsValue| format!("Error sending request to {}: {:?}", url, err);
+
+    let mut init = RequestInit::new();
+    init.method(method);
+    if let Some(body) = body {
+        init.body(Some(&Uint8Array::from(&body[..]).into()));
+    }
+    let request = Request::new_with_str_and_init(url, &init).map_err(js_error)?;
+    for (name, value) in headers {
+        request.headers().set(name, value).map_err(js_error)?;
+    }
+    if let Some(auth_token) = auth_token {
+        request
+            .headers()
+            .set("Authorization", &format!("Bearer {}", auth_token))
+            .map_err(js_error)?;
+    }
+
+    let window = web_sys::window().ok_or_else(|| "No window to send requests from".to_string())?;
+    let response: Response = JsFuture::from(window.fetch_with_request(&request))
+        .await
+        .map_err(js_error)?
+        .dyn_into()
+        .map_err(js_error)?;
+    if !response.ok() {
+        return Err(format!(
+            "Error sending request to {}: status {}",
+            url,
+            response.status()
+        ));
+    }
+    let body = JsFuture::from(response.array_buffer().map_err(js_error)?)
+        .await
+        .map_err(js_error)?;
+    Ok(Uint8Array::new(&body).to_vec())
+}
@@ -0,0 +1,180 @@
+use async_trait::async_trait;
+use interledger_errors::{
+    ExchangeRateStoreError, MaxPacketAmountStoreError, UnreachableDestinationStoreError,
+};
+use interledger_rates::ExchangeRateStore;
+use interledger_stream::{
+    MaxPacketAmountStore, UnreachableDestination, UnreachableDestinationStore,
+};
+use js_sys::Date;
+use parking_lot::{Mutex, RwLock};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// The store of a sender, which keeps the exchange rates set by the application, and the max
+/// packet amounts and unreachable destinations discovered by its payments, in memory
+#[derive(Clone, Default)]
+pub struct SenderStore {
+    exchange_rates: Arc<RwLock<HashMap<String, f64>>>,
+    /// The max packet amounts with their expiries, in milliseconds since the UNIX epoch
+    max_packet_amounts: Arc<Mutex<HashMap<String, (u64, f64)>>>,
+    /// The unreachable destinations with their expiries, in milliseconds since the UNIX epoch
+    unreachable_destinations: Arc<Mutex<HashMap<String, (UnreachableDestination, f64)>>>,
+}
+
+impl SenderStore {
+    pub fn new() -> Self {
+        SenderStore {
+            exchange_rates: Arc::new(RwLock::new(HashMap::new())),
+            max_packet_amounts: Arc::new(Mutex::new(HashMap::new())),
+            unreachable_destinations: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+}
+
+impl ExchangeRateStore for SenderStore {
+    fn set_exchange_rates(
+        &self,
+        rates: HashMap<String, f64>,
+    ) -> Result<(), ExchangeRateStoreError> {
+        self.exchange_rates.write().extend(rates);
+        Ok(())
+    }
+
+    fn get_exchange_rates(&self, asset_codes: &[&str]) -> Result<Vec<f64>, ExchangeRateStoreError> {
+        let exchange_rates = self.exchange_rates.read();
+        asset_codes
+            .iter()
+            .map(|code| {
+                exchange_rates.get(*code).cloned().ok_or_else(|| {
+                    ExchangeRateStoreError::PairNotFound {
+                        from: asset_codes[0].to_string(),
+                        to: asset_codes[asset_codes.len() - 1].to_string(),
+                    }
+                })
+            })
+            .collect()
+    }
+
+    fn get_all_exchange_rates(&self) -> Result<HashMap<String, f64>, ExchangeRateStoreError> {
+        Ok(self.exchange_rates.read().clone())
+    }
+}
+
+#[async_trait]
+impl MaxPacketAmountStore for SenderStore {
+    async fn get_max_packet_amount(
+        &self,
+        source_asset_code: &str,
+        source_asset_scale: u8,
+        destination_prefix: &str,
+    ) -> Result<Option<u64>, MaxPacketAmountStoreError> {
+        let key = format!(
+            "{}:{}:{}",
+            source_asset_code, source_asset_scale, destination_prefix
+        );
+        let mut max_packet_amounts = self.max_packet_amounts.lock();
+        match max_packet_amounts.get(&key) {
+            Some((max_packet_amount, expiry)) if *expiry > Date::now() => {
+                Ok(Some(*max_packet_amount))
+            }
+            Some(_) => {
+                max_packet_amounts.remove(&key);
+                Ok(None)
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn set_max_packet_amount(
+        &self,
+        source_asset_code: &str,
+        source_asset_scale: u8,
+        destination_prefix: &str,
+        max_packet_amount: u64,
+        ttl: u64,
+    ) -> Result<(), MaxPacketAmountStoreError> {
+        let key = format!(
+            "{}:{}:{}",
+            source_asset_code, source_asset_scale, destination_prefix
+        );
+        let expiry = Date::now() + ttl as f64;
+        self.max_packet_amounts
+            .lock()
+            .insert(key, (max_packet_amount, expiry));
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl UnreachableDestinationStore for SenderStore {
+    async fn get_unreachable_destination(
+        &self,
+        destination_prefix: &str,
+    ) -> Result<Option<UnreachableDestination>, UnreachableDestinationStoreError> {
+        let now = Date::now();
+        let mut unreachable_destinations = self.unreachable_destinations.lock();
+        match unreachable_destinations.get(destination_prefix) {
+            Some((unreachable, expiry)) if *expiry > now => Ok(Some(UnreachableDestination {
+                expires_in: (*expiry - now) as u64,
+                ..unreachable.clone()
+            })),
+            Some(_) => {
+                unreachable_destinations.remove(destination_prefix);
+                Ok(None)
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn record_unreachable_destination(
+        &self,
+        destination_prefix: &str,
+        error: &str,
+        ttl: u64,
+    ) -> Result<(), UnreachableDestinationStoreError> {
+        let now = Date::now();
+        let mut unreachable_destinations = self.unreachable_destinations.lock();
+        let failures = match unreachable_destinations.get(destination_prefix) {
+            Some((unreachable, expiry)) if *expiry > now => unreachable.failures,
+            _ => 0,
+        };
+        let unreachable = UnreachableDestination {
+            destination_prefix: destination_prefix.to_string(),
+            failures: failures + 1,
+            error: error.to_string(),
+            expires_in: ttl,
+        };
+        unreachable_destinations.insert(
+            destination_prefix.to_string(),
+            (unreachable, now + ttl as f64),
+        );
+        Ok(())
+    }
+
+    async fn get_unreachable_destinations(
+        &self,
+    ) -> Result<Vec<UnreachableDestination>, UnreachableDestinationStoreError> {
+        let now = Date::now();
+        let mut unreachable_destinations = self.unreachable_destinations.lock();
+        unreachable_destinations.retain(|_, (_, expiry)| *expiry > now);
+        Ok(unreachable_destinations
+            .values()
+            .map(|(unreachable, expiry)| UnreachableDestination {
+                expires_in: (*expiry - now) as u64,
+                ..unreachable.clone()
+            })
+            .collect())
+    }
+
+    async fn clear_unreachable_destinations(
+        &self,
+        destination_prefix: Option<&str>,
+    ) -> Result<usize, UnreachableDestinationStoreError> {
+        let mut unreachable_destinations = self.unreachable_destinations.lock();
+        Ok(match destination_prefix {
+            Some(prefix) => unreachable_destinations.remove(prefix).into_iter().count(),
+            None => unreachable_destinations.drain().count(),
+        })
+    }
+}
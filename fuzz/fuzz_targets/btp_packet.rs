@@ -0,0 +1,12 @@
+#![no_main]
+use interledger_btp::{BtpError, BtpMessage, BtpPacket, BtpResponse, Serializable};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(packet) = BtpPacket::from_bytes(data) {
+        let _ = packet.to_bytes();
+    }
+    let _ = BtpMessage::from_bytes(data);
+    let _ = BtpResponse::from_bytes(data);
+    let _ = BtpError::from_bytes(data);
+});
@@ -0,0 +1,21 @@
+#![no_main]
+use interledger_ccp::{RouteControlRequest, CCP_CONTROL_DESTINATION, PEER_PROTOCOL_CONDITION};
+use interledger_packet::PrepareBuilder;
+use libfuzzer_sys::fuzz_target;
+use std::convert::TryFrom;
+use std::time::{Duration, SystemTime};
+
+fuzz_target!(|data: &[u8]| {
+    // The CCP messages are the data of Prepare packets sent to the peer
+    let prepare = PrepareBuilder {
+        destination: CCP_CONTROL_DESTINATION.clone(),
+        amount: 0,
+        expires_at: SystemTime::now() + Duration::from_secs(60),
+        execution_condition: &PEER_PROTOCOL_CONDITION,
+        data,
+    }
+    .build();
+    if let Ok(request) = RouteControlRequest::try_from(&prepare) {
+        let _ = request.to_prepare();
+    }
+});
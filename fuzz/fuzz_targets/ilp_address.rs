@@ -0,0 +1,11 @@
+#![no_main]
+use interledger_packet::Address;
+use libfuzzer_sys::fuzz_target;
+use std::convert::TryFrom;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(address) = Address::try_from(data) {
+        let _ = (address.scheme(), address.segments().count());
+        assert_eq!(Address::try_from(address.as_ref()).unwrap(), address);
+    }
+});
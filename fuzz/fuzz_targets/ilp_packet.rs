@@ -0,0 +1,29 @@
+#![no_main]
+use bytes::BytesMut;
+use interledger_packet::{Fulfill, MaxPacketAmountDetails, Packet, Prepare, Reject};
+use libfuzzer_sys::fuzz_target;
+use std::convert::TryFrom;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(packet) = Packet::try_from(bytes05::BytesMut::from(data)) {
+        // The getters read from the offsets found while parsing
+        match packet {
+            Packet::Prepare(prepare) => {
+                let _ = (prepare.amount(), prepare.expires_at());
+                let _ = (prepare.execution_condition(), prepare.destination());
+                let _ = prepare.data();
+            }
+            Packet::Fulfill(fulfill) => {
+                let _ = (fulfill.fulfillment(), fulfill.data());
+            }
+            Packet::Reject(reject) => {
+                let _ = (reject.code(), reject.triggered_by(), reject.message());
+                let _ = reject.data();
+            }
+        }
+    }
+    let _ = Prepare::try_from(BytesMut::from(data));
+    let _ = Fulfill::try_from(BytesMut::from(data));
+    let _ = Reject::try_from(BytesMut::from(data));
+    let _ = MaxPacketAmountDetails::from_bytes(data);
+});
@@ -0,0 +1,15 @@
+#![no_main]
+use bytes::BytesMut;
+use interledger_stream::StreamPacket;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    // The cleartext, because random ciphertexts would almost never pass the authentication
+    if let Ok(packet) = StreamPacket::from_bytes_unencrypted(BytesMut::from(data)) {
+        let _ = (packet.sequence(), packet.ilp_packet_type());
+        let _ = packet.prepare_amount();
+        for frame in packet.frames() {
+            let _ = format!("{:?}", frame);
+        }
+    }
+});
@@ -0,0 +1,65 @@
+#!/usr/bin/env bash
+
+# Runs the criterion benchmarks of the hot paths (packet parsing, STREAM, routing and
+# the balance scripts of the Redis store) and keeps track of their baselines.
+#
+# $ ./scripts/bench.sh save [baseline]       # saves the results as a baseline (default: master)
+# $ ./scripts/bench.sh compare [baseline]    # compares the results with a saved baseline
+# $ ./scripts/bench.sh diff [git-ref]        # saves a baseline of the git ref (default: origin/master)
+#                                            # and compares the working tree with it
+#
+# Any further arguments are passed to criterion, e.g. a filter: `./scripts/bench.sh compare master STREAM`
+# The reports are written to target/criterion, target/criterion/report/index.html being the summary.
+# The balance benchmarks need a `redis-server` on the PATH and are skipped without one.
+
+set -euo pipefail
+
+BASE_DIR=$(cd "$(dirname "$0")/.." && pwd)
+export CARGO_TARGET_DIR=${CARGO_TARGET_DIR:-$BASE_DIR/target}
+
+function run_benches() {
+    # `--bench '*'` only selects the criterion targets, as the libtest harness would
+    # reject the criterion arguments
+    cargo bench \
+        -p interledger-packet \
+        -p interledger-router \
+        -p interledger-stream \
+        -p interledger-store --features interledger-store/redis \
+        --bench '*' -- "$@"
+}
+
+command=${1:-}
+shift || true
+case "$command" in
+    save)
+        baseline=${1:-master}
+        shift || true
+        cd "$BASE_DIR" && run_benches --save-baseline "$baseline" "$@"
+        ;;
+    compare)
+        baseline=${1:-master}
+        shift || true
+        cd "$BASE_DIR" && run_benches --baseline "$baseline" "$@"
+        ;;
+    diff)
+        git_ref=${1:-origin/master}
+        shift || true
+        worktree=$(mktemp -d)
+        trap 'git -C "$BASE_DIR" worktree remove --force "$worktree"' EXIT
+        git -C "$BASE_DIR" worktree add --detach "$worktree" "$git_ref"
+        # Cargo.lock is not checked in, so both runs build with the same dependencies
+        if [ -f "$BASE_DIR/Cargo.lock" ]; then
+            cp "$BASE_DIR/Cargo.lock" "$worktree/"
+        fi
+        # Both runs share the target directory, in which criterion keeps the baselines.
+        # Saving over the baseline compares with it like `--baseline` does, except that the
+        # benchmarks which do not exist at the git ref yet do not fail.
+        (cd "$worktree" && run_benches --save-baseline "diff" "$@")
+        cd "$BASE_DIR" && run_benches --save-baseline "diff" "$@"
+        ;;
+    *)
+        echo "Usage: $0 save|compare [baseline] [criterion args...]"
+        echo "       $0 diff [git-ref] [criterion args...]"
+        exit 1
+        ;;
+esac